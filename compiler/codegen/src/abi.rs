@@ -0,0 +1,31 @@
+use firefly_llvm::{self as llvm, Align, GlobalValue, Linkage, PointerType, Value, Visibility};
+use firefly_session::Options;
+
+pub use firefly_rt::abi::{ABI_SECTION, ABI_VERSION, ABI_VERSION_SYMBOL};
+
+/// Stamps the given module with the ABI version it was compiled against.
+///
+/// This emits a global in the ABI section containing the address of the runtime's ABI version
+/// symbol, see `firefly_rt::abi` for details. When linked against a runtime with a different ABI
+/// version, the symbol will be undefined, and linking will fail.
+pub fn stamp_module(options: &Options, module: llvm::Module, name: &str) {
+    let context = module.context();
+    let i8_type = context.get_i8_type();
+    let version = module.get_or_add_global(i8_type, ABI_VERSION_SYMBOL, None);
+    version.set_linkage(Linkage::External);
+
+    let ptr_type = PointerType::new(i8_type, 0);
+    let stamp_name = format!("__firefly_abi_stamp.{}", name);
+    let stamp = module.add_global(ptr_type, stamp_name.as_str(), Some(version.base()));
+    stamp.set_constant(true);
+    // The stamp must be externally visible so that it isn't stripped by the optimizer, but
+    // nothing outside of the executable ever needs to reference it
+    stamp.set_linkage(Linkage::External);
+    stamp.set_visibility(Visibility::Hidden);
+    stamp.set_alignment(options.target.pointer_width / 8);
+    if options.target.options.is_like_osx {
+        stamp.set_section(format!("__DATA,{}", ABI_SECTION).as_str());
+    } else {
+        stamp.set_section(ABI_SECTION);
+    }
+}
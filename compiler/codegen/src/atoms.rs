@@ -0,0 +1,89 @@
+use log::debug;
+
+use firefly_llvm::{
+    self as llvm, ComdatSelectionKind, GlobalObject, GlobalValue, Linkage, Value, ValueBase,
+};
+use firefly_rt::term::atoms::BUILTIN_ATOMS;
+use firefly_session::Options;
+
+/// The name of the section in which `AtomData` records are placed on ELF/COFF/Wasm targets
+///
+/// On Mach-O targets, the section is named `__DATA,__atoms`.
+pub const ATOMS_SECTION: &'static str = "__atoms";
+
+/// The prefix used for the symbol names of `AtomData` records
+pub const ATOM_SYMBOL_PREFIX: &'static str = "atom_";
+
+/// Deduplicates the `AtomData` records defined in the given module.
+///
+/// Records for atoms which are defined by the runtime itself are turned into external references to
+/// the runtime's definition. Every other record is placed, along with the string it refers to, in
+/// its own COMDAT group keyed by the record's symbol name.
+///
+/// Every module emits a `linkonce_odr` record in the atoms section for each atom it uses, and
+/// the runtime builds its atom table from the contents of that section at startup. Without
+/// COMDATs, `linkonce_odr` symbols are only deduplicated for the purposes of symbol resolution;
+/// the data of every copy still ends up in the output section. Grouping them ensures the linker
+/// discards all but one copy, so the merged section contains exactly one record per atom, and that
+/// record is the one referenced by generated code.
+///
+/// Mach-O has no COMDATs, but `ld64` already coalesces weak definitions, so no grouping is done
+/// there.
+///
+/// Returns the number of atom records which were deduplicated.
+pub fn dedup_atoms(options: &Options, module: llvm::Module) -> usize {
+    let section = if options.target.options.is_like_osx {
+        format!("__DATA,{}", ATOMS_SECTION)
+    } else {
+        ATOMS_SECTION.to_string()
+    };
+    let atoms = module
+        .globals()
+        .filter(|gv| gv.linkage() == Linkage::LinkOnceODR && gv.section().eq(section.as_str()))
+        .collect::<Vec<_>>();
+
+    let mut grouped = 0;
+    for atom in atoms.iter() {
+        let name: String = atom.name().into();
+
+        // The runtime always provides its own atoms, so we must refer to those definitions,
+        // otherwise the table could end up with two records for the same atom
+        let is_builtin = name
+            .strip_prefix(ATOM_SYMBOL_PREFIX)
+            .map(|value| BUILTIN_ATOMS.contains(&value))
+            .unwrap_or(false);
+        if is_builtin {
+            atom.set_initializer(ValueBase::null());
+            atom.set_linkage(Linkage::External);
+            atom.set_section("");
+            continue;
+        }
+
+        if options.target.options.is_like_osx {
+            continue;
+        }
+        let comdat = module.get_or_add_comdat(name.as_str());
+        comdat.set_kind(ComdatSelectionKind::Any);
+        atom.set_comdat(comdat);
+
+        // The string data for an atom is named after the atom value (or its hash), i.e. the
+        // symbol name without the prefix, and must be discarded along with the record itself
+        if let Some(value) = name
+            .strip_prefix(ATOM_SYMBOL_PREFIX)
+            .and_then(|value| module.get_global(value))
+        {
+            if value.linkage() == Linkage::LinkOnceODR {
+                value.set_comdat(comdat);
+            }
+        }
+        grouped += 1;
+    }
+
+    debug!(
+        "deduplicated {} atom records, {} grouped into comdats",
+        atoms.len(),
+        grouped
+    );
+
+    atoms.len()
+}
@@ -0,0 +1,55 @@
+use firefly_intern::Symbol;
+use firefly_llvm::{self as llvm, GlobalValue, Linkage, Value};
+use firefly_session::Options;
+
+pub use firefly_rt::boot::{BootScript, RestartType, BOOT_MANIFEST_SYMBOL};
+
+/// Applications which are part of the runtime itself, and are always started first
+const BUILTIN_APPS: &'static [&'static str] = &["kernel", "stdlib"];
+
+/// Builds the boot script for the executable described by `options`
+///
+/// The builtin applications are always started first. The application being built is started next
+/// if it has an application callback module, followed by any applications requested with
+/// `--boot-app`. Configuration is bundled if `--config` was given.
+pub fn boot_script(options: &Options) -> BootScript {
+    let mut script = BootScript {
+        applications: BUILTIN_APPS
+            .iter()
+            .map(|name| (name.to_string(), RestartType::Permanent))
+            .collect(),
+        config: options.sys_config.is_some(),
+    };
+
+    let mut push = |name: Symbol, restart_type: RestartType| {
+        let name = name.as_str().get();
+        if script.applications.iter().all(|(app, _)| app != name) {
+            script.applications.push((name.to_string(), restart_type));
+        }
+    };
+    if options.app.otp_module.is_some() {
+        push(options.app.name, RestartType::Permanent);
+    }
+    for (name, restart_type) in options.boot_apps.iter() {
+        let restart_type = restart_type
+            .as_deref()
+            .and_then(|ty| ty.parse().ok())
+            .unwrap_or_default();
+        push(*name, restart_type);
+    }
+
+    script
+}
+
+/// Emits the boot manifest for the executable described by `options` into the given module
+///
+/// Only one module in an executable may contain the manifest, see `firefly_rt::boot` for its
+/// format.
+pub fn emit_boot_manifest(options: &Options, module: llvm::Module) {
+    let context = module.context();
+    let manifest = boot_script(options).to_string();
+    let data = context.const_string(manifest.as_str());
+    let global = module.add_global(data.get_type(), BOOT_MANIFEST_SYMBOL, Some(data.base()));
+    global.set_constant(true);
+    global.set_linkage(Linkage::External);
+}
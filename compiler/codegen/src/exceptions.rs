@@ -0,0 +1,36 @@
+use log::debug;
+
+use firefly_llvm::{self as llvm, ExceptionModel};
+use firefly_session::Options;
+
+/// Lowers exception handling in the given module to the native unwinding mechanism of the target.
+///
+/// Exceptions are raised by unwinding, so a call which returns normally costs nothing more than it
+/// would without a handler. A raise with no handler in the current function unwinds to the nearest
+/// try or catch on the stack, running the landing pad of each frame in between, which is how the
+/// `after` block of a try runs when leaving it via an exception. Exceptions unwinding out of
+/// generated code into the runtime are caught where the runtime called in, and returned as `Err`,
+/// see `firefly_rt::error::unwind`.
+///
+/// Natives return their exceptions as `Err` rather than unwinding, so the result of every call is
+/// still checked. Targets without native unwinding, i.e. WebAssembly, keep propagating exceptions
+/// that way alone.
+pub fn lower_exceptions(options: &Options, module: llvm::Module) -> llvm::ExceptionStats {
+    let target = &options.target.options;
+    if target.is_like_wasm {
+        return llvm::ExceptionStats::default();
+    }
+    let model = if target.is_like_msvc {
+        ExceptionModel::Seh
+    } else {
+        ExceptionModel::Itanium
+    };
+    let stats = module.lower_exceptions(model);
+
+    debug!(
+        "exceptions: {} handlers reached by {} invokes, {} raises",
+        stats.handlers, stats.invokes, stats.raises
+    );
+
+    stats
+}
@@ -5,9 +5,18 @@
 #![feature(let_else)]
 #![feature(once_cell)]
 
+pub mod abi;
+pub mod atoms;
+pub mod boot;
+pub mod exceptions;
 pub mod linker;
+pub mod literals;
 pub mod meta;
 pub mod passes;
+pub mod preemption;
+pub mod spans;
+pub mod stackmaps;
+pub mod tailcalls;
 
 use firefly_llvm as llvm;
 use firefly_mlir as mlir;
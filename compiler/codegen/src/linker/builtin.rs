@@ -1,5 +1,5 @@
 use std::ffi::CString;
-use std::io;
+use std::fs::File;
 use std::os;
 use std::os::raw::{c_char, c_int};
 
@@ -25,16 +25,14 @@ extern "C" {
 
 /// Invoke the statically linked `lld` linker with the given arguments.
 ///
+/// The output of the linker is written to `stdout` and `stderr`, rather than those of
+/// the compiler, so that it can be reported like the output of an external linker.
+///
 /// NOTE: Assumes that the first value of the argument vector contains the
 /// program name which informs lld which flavor of linker is being run.
-pub fn link(argv: &[CString]) -> Result<(), ()> {
-    // Acquire exclusive access to stdout/stderr for the linker
-    let stdout = io::stdout();
-    let stderr = io::stderr();
-    let stdout_lock = stdout.lock();
-    let stderr_lock = stderr.lock();
-    let stdout_fd = fs::get_file_descriptor(&stdout_lock);
-    let stderr_fd = fs::get_file_descriptor(&stderr_lock);
+pub fn link(argv: &[CString], stdout: &File, stderr: &File) -> Result<(), ()> {
+    let stdout_fd = fs::get_file_descriptor(stdout);
+    let stderr_fd = fs::get_file_descriptor(stderr);
 
     let argc = argv.len();
     let mut c_argv = Vec::with_capacity(argc);
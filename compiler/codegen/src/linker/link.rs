@@ -2,7 +2,7 @@ use std::ascii;
 use std::cell::OnceCell;
 use std::char;
 use std::env;
-use std::ffi::OsString;
+use std::ffi::{CString, OsString};
 use std::fmt;
 use std::fs;
 use std::io;
@@ -28,6 +28,7 @@ use firefly_target::{
 use firefly_util::diagnostics::DiagnosticsHandler;
 use firefly_util::fs::{fix_windows_verbatim_for_gcc, NativeLibraryKind};
 
+use crate::linker::builtin;
 use crate::linker::command::Command;
 use crate::linker::rpath::{self, RPathConfig};
 use crate::linker::Linker;
@@ -35,6 +36,12 @@ use crate::meta::CodegenResults;
 
 use super::archive::{ArchiveBuilder, LlvmArchiveBuilder};
 
+/// The symbol by which the runtime starts the application, exported by every runtime
+const ENTRY_SYMBOL_NAME: &str = "firefly_entry";
+
+/// The mangled symbol of `std::rt::lang_start_internal`, through which the runtime is started
+const LANG_START_SYMBOL_NAME: &str = env!("LANG_START_SYMBOL_NAME");
+
 /// Performs the linkage portion of the compilation phase. This will generate all
 /// of the requested outputs for this compilation session.
 pub fn link_binary(
@@ -51,6 +58,18 @@ pub fn link_binary(
         ));
     }
 
+    // The runtime is linked from the sysroot, so it must have been built for the target
+    if options.is_cross_compiling() && !options.codegen_opts.no_std.unwrap_or(false) {
+        let lib_dir = options.target_filesearch(PathKind::Native).get_lib_path();
+        if !lib_dir.is_dir() {
+            return Err(anyhow!(
+                "the runtime for target `{}` is not installed, expected it in {}",
+                options.target.triple(),
+                lib_dir.display()
+            ));
+        }
+    }
+
     if options.codegen_opts.no_codegen {
         return Ok(());
     }
@@ -125,6 +144,11 @@ pub fn link_binary(
         }
     }
 
+    if options.save_temps {
+        let kept = tmpdir.into_path();
+        diagnostics.note(format!("Linker temporaries kept in {}", kept.as_display()));
+    }
+
     if options.debugging_opts.print_artifact_sizes {
         let file_size = fs::metadata(&output_file).map(|m| m.len()).unwrap_or(0);
         diagnostics.note(format!(
@@ -139,7 +163,11 @@ pub fn link_binary(
     }
 
     // Remove the temporary object file and metadata
-    let (preserve_objects, preserve_dwarf_objects) = preserve_objects_for_their_debuginfo(options);
+    let (preserve_objects, preserve_dwarf_objects) = if options.save_temps {
+        (true, true)
+    } else {
+        preserve_objects_for_their_debuginfo(options)
+    };
     for module in codegen_results.modules.iter() {
         if !preserve_objects {
             if let Some(obj) = module.object() {
@@ -224,7 +252,7 @@ fn link_natively(
     info!("preparing {:?} to {:?}", project_type, output_file);
     let (linker_path, flavor) = linker_and_flavor(options);
 
-    let (is_ld, mut cmd) = linker_with_args(
+    let mut cmd = linker_with_args(
         &linker_path,
         flavor,
         options,
@@ -249,33 +277,31 @@ fn link_natively(
     // May have not found libraries in the right formats.
     diagnostics.abort_if_errors();
 
-    if is_ld {
-        todo!("invoke firefly-lld");
+    if let Some(program) = builtin_linker(&linker_path, flavor) {
+        match exec_builtin_linker(&cmd, program, tmpdir) {
+            Ok((true, _)) => (),
+            Ok((false, output)) => report_link_failure(
+                diagnostics,
+                &linker_path,
+                "lld reported errors",
+                &cmd,
+                &output,
+            ),
+            Err(e) => {
+                let mut err = diagnostics.diagnostic(Severity::Error);
+                err.with_message("could not invoke the built-in linker");
+                err.with_note(e.to_string());
+                err.emit();
+            }
+        }
+        diagnostics.abort_if_errors();
     } else {
         match exec_linker(options, &mut cmd, output_file, tmpdir) {
             Ok(prog) => {
                 if !prog.status.success() {
                     let mut output = prog.stderr.clone();
                     output.extend_from_slice(&prog.stdout);
-                    let escaped_output = escape_stdout_stderr_string(&output);
-                    let mut err = diagnostics.diagnostic(Severity::Error);
-                    err.with_message(format!(
-                        "linking with `{}` failed: {}",
-                        linker_path.display(),
-                        prog.status
-                    ));
-                    err.with_note(format!("{:?}", &cmd));
-                    if escaped_output.contains("undefined reference to") {
-                        err.with_note(escaped_output);
-                        err.with_note(
-                            "some `extern` functions couldn't be found; some native libraries may \
-                         need to be installed or have their path specified",
-                        );
-                        err.with_note("use the `-l` flag to specify native libraries to link");
-                    } else {
-                        err.with_note(escaped_output);
-                    }
-                    err.emit();
+                    report_link_failure(diagnostics, &linker_path, prog.status, &cmd, &output);
                 }
                 diagnostics.abort_if_errors();
             }
@@ -569,6 +595,17 @@ pub fn linker_and_flavor(options: &Options) -> (PathBuf, LinkerFlavor) {
         return ret;
     }
 
+    // The host's `cc` cannot link for another target, so unless the target specification
+    // names a linker, look for a toolchain which can
+    if options.is_cross_compiling()
+        && options.target.options.linker.is_none()
+        && options.target.options.linker_flavor == LinkerFlavor::Gcc
+    {
+        if let Some(ret) = find_cross_linker(options) {
+            return ret;
+        }
+    }
+
     if let Some(ret) = infer_from(
         options,
         options.target.options.linker.as_deref().map(PathBuf::from),
@@ -580,6 +617,45 @@ pub fn linker_and_flavor(options: &Options) -> (PathBuf, LinkerFlavor) {
     panic!("Not enough information provided to determine how to invoke the linker")
 }
 
+/// Searches for a linker able to link for the target when cross-compiling
+///
+/// A GCC cross toolchain is preferred, e.g. `aarch64-linux-musl-gcc`, then `clang`, which is
+/// given the target by `add_cross_target_args`, and finally the lld built in to the compiler.
+fn find_cross_linker(options: &Options) -> Option<(PathBuf, LinkerFlavor)> {
+    let triple = options.target.triple();
+    let exe_suffix = &options.host.options.exe_suffix;
+    // Cross toolchains are commonly named after the triple without its vendor
+    let mut prefixes = vec![triple.to_string()];
+    let parts = triple.split('-').collect::<Vec<_>>();
+    if parts.len() == 4 {
+        prefixes.push(format!("{}-{}-{}", parts[0], parts[2], parts[3]));
+    }
+    for prefix in prefixes.iter() {
+        for driver in ["gcc", "cc", "clang"] {
+            let name = format!("{}-{}{}", prefix, driver, exe_suffix);
+            if let Some(path) = find_program(&name) {
+                return Some((path, LinkerFlavor::Gcc));
+            }
+        }
+    }
+    if let Some(path) = find_program(&format!("clang{}", exe_suffix)) {
+        return Some((path, LinkerFlavor::Gcc));
+    }
+    // The built-in lld has no driver for `link.exe`-style targets
+    match options.target.options.lld_flavor {
+        LldFlavor::Link => None,
+        flavor => Some((PathBuf::from("firefly-lld"), LinkerFlavor::Lld(flavor))),
+    }
+}
+
+/// Returns the path of the given program if it is found in `PATH`
+fn find_program(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
 /// Returns a pair of boolean indicating whether we should preserve the object and
 /// dwarf object files on the filesystem for their debug information. This is often
 /// useful with split-dwarf like schemes.
@@ -641,6 +717,110 @@ fn get_object_file_path(options: &Options, name: &str, self_contained: bool) ->
     PathBuf::from(name)
 }
 
+/// Returns the program name selecting the driver of the lld linker built in to the compiler,
+/// when it should be used in place of an external linker
+///
+/// The built-in linker stands in for `lld` and `firefly-lld`, a linker given by path is always
+/// run as an external program.
+fn builtin_linker(linker: &Path, flavor: LinkerFlavor) -> Option<&'static str> {
+    if linker != Path::new("lld") && linker != Path::new("firefly-lld") {
+        return None;
+    }
+    match flavor {
+        LinkerFlavor::Lld(LldFlavor::Ld) => Some("ld.lld"),
+        LinkerFlavor::Lld(LldFlavor::Ld64) => Some("ld64.lld"),
+        LinkerFlavor::Lld(LldFlavor::Wasm) => Some("wasm-ld"),
+        _ => None,
+    }
+}
+
+/// Invokes the lld linker built in to the compiler with the arguments of `cmd`
+///
+/// Returns whether linking succeeded, along with the output of the linker. The output is
+/// captured in `tmpdir`, so that it can be reported like that of an external linker.
+fn exec_builtin_linker(cmd: &Command, program: &str, tmpdir: &Path) -> io::Result<(bool, Vec<u8>)> {
+    let mut argv = Vec::with_capacity(cmd.get_args().len() + 1);
+    argv.push(CString::new(program)?);
+    for arg in cmd.get_args() {
+        let arg = arg.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("linker argument is not valid unicode: {:?}", arg),
+            )
+        })?;
+        argv.push(CString::new(arg)?);
+    }
+    info!("invoking built-in linker {:?}", argv);
+
+    let stdout_file = tmpdir.join("linker-stdout");
+    let stderr_file = tmpdir.join("linker-stderr");
+    let is_ok = builtin::link(
+        argv.as_slice(),
+        &fs::File::create(&stdout_file)?,
+        &fs::File::create(&stderr_file)?,
+    )
+    .is_ok();
+
+    let mut output = fs::read(&stderr_file)?;
+    output.extend(fs::read(&stdout_file)?);
+    Ok((is_ok, output))
+}
+
+/// Reports that the linker failed, along with its output
+///
+/// Undefined references to the entry points of the runtime are explained separately, as they
+/// mean that the runtime itself was not linked, rather than some native library.
+fn report_link_failure<S: fmt::Display>(
+    diagnostics: &DiagnosticsHandler,
+    linker_path: &Path,
+    status: S,
+    cmd: &Command,
+    output: &[u8],
+) {
+    let escaped_output = escape_stdout_stderr_string(output);
+    let mut err = diagnostics.diagnostic(Severity::Error);
+    err.with_message(format!(
+        "linking with `{}` failed: {}",
+        linker_path.display(),
+        status
+    ));
+    err.with_note(format!("{:?}", cmd));
+    // The wording differs between GNU ld, lld and ld64 respectively
+    let has_undefined = escaped_output.contains("undefined reference to")
+        || escaped_output.contains("undefined symbol")
+        || escaped_output.contains("Undefined symbols");
+    if !has_undefined {
+        err.with_note(escaped_output);
+        err.emit();
+        return;
+    }
+    let missing_entry = escaped_output.contains(ENTRY_SYMBOL_NAME);
+    let missing_lang_start = escaped_output.contains(LANG_START_SYMBOL_NAME)
+        || escaped_output.contains("std::rt::lang_start_internal");
+    err.with_note(escaped_output);
+    if missing_entry {
+        err.with_note(format!(
+            "`{}` is the entry point of the Firefly runtime, which was not linked; \
+             make sure the runtime for this target is installed in the sysroot",
+            ENTRY_SYMBOL_NAME
+        ));
+    }
+    if missing_lang_start {
+        err.with_note(
+            "the Firefly runtime requires the Rust standard library, which was not linked; \
+             make sure the standard library for this target is installed in the sysroot",
+        );
+    }
+    if !missing_entry && !missing_lang_start {
+        err.with_note(
+            "some `extern` functions couldn't be found; some native libraries may \
+             need to be installed or have their path specified",
+        );
+        err.with_note("use the `-l` flag to specify native libraries to link");
+    }
+    err.emit();
+}
+
 fn exec_linker(
     options: &Options,
     cmd: &mut Command,
@@ -968,6 +1148,9 @@ fn add_link_script(
 /// Add arbitrary "user defined" args defined from command line.
 /// FIXME: Determine where exactly these args need to be inserted.
 fn add_user_defined_link_args(cmd: &mut dyn Linker, options: &Options) {
+    for arg in options.codegen_opts.linker_arg.iter() {
+        cmd.arg(arg);
+    }
     if let Some(args) = options.codegen_opts.linker_args.as_ref() {
         for arg in args {
             cmd.arg(arg);
@@ -1084,7 +1267,7 @@ fn linker_with_args(
     tmpdir: &Path,
     out_filename: &Path,
     codegen_results: &CodegenResults,
-) -> (bool, Command) {
+) -> Command {
     let crt_objects_fallback = crt_objects_fallback(options, project_type);
     let cmd = &mut *super::get_linker(
         options,
@@ -1234,9 +1417,7 @@ fn linker_with_args(
     // to it and remove the option.
     add_post_link_args(cmd, options, flavor);
 
-    let is_ld = cmd.is_ld();
-    let command = cmd.take_cmd();
-    (is_ld, command)
+    cmd.take_cmd()
 }
 
 fn add_order_independent_options(
@@ -1253,6 +1434,8 @@ fn add_order_independent_options(
 ) {
     add_gcc_ld_path(cmd, options, diagnostics, flavor);
 
+    add_cross_target_args(cmd, options, flavor);
+
     add_apple_sdk(cmd, options, diagnostics, flavor);
 
     add_link_script(cmd, options, diagnostics, tmpdir, project_type);
@@ -1412,9 +1595,9 @@ fn add_local_native_libraries(
 
         match lib.kind {
             NativeLibraryKind::Dylib { as_needed } => {
-                cmd.link_dylib(name, verbatim, as_needed.unwrap_or(true))
+                link_native_dylib(cmd, options, name, verbatim, as_needed.unwrap_or(true))
             }
-            NativeLibraryKind::Unspecified => cmd.link_dylib(name, verbatim, true),
+            NativeLibraryKind::Unspecified => link_native_dylib(cmd, options, name, verbatim, true),
             NativeLibraryKind::Framework { as_needed } => {
                 cmd.link_framework(name, as_needed.unwrap_or(true))
             }
@@ -1450,6 +1633,23 @@ fn add_local_native_libraries(
     }
 }
 
+/// Links a native library which is not explicitly static
+///
+/// With `--static`, these are linked statically too, so that no shared libraries are loaded.
+fn link_native_dylib(
+    cmd: &mut dyn Linker,
+    options: &Options,
+    name: &str,
+    verbatim: bool,
+    as_needed: bool,
+) {
+    if options.static_link {
+        cmd.link_staticlib(name, verbatim)
+    } else {
+        cmd.link_dylib(name, verbatim, as_needed)
+    }
+}
+
 fn add_upstream_erlang_libraries(
     cmd: &mut dyn Linker,
     options: &Options,
@@ -1487,9 +1687,9 @@ fn add_upstream_native_libraries(
         let verbatim = lib.verbatim.unwrap_or(false);
         match lib.kind {
             NativeLibraryKind::Dylib { as_needed } => {
-                cmd.link_dylib(name, verbatim, as_needed.unwrap_or(true))
+                link_native_dylib(cmd, options, name, verbatim, as_needed.unwrap_or(true))
             }
-            NativeLibraryKind::Unspecified => cmd.link_dylib(name, verbatim, true),
+            NativeLibraryKind::Unspecified => link_native_dylib(cmd, options, name, verbatim, true),
             NativeLibraryKind::Framework { as_needed } => {
                 cmd.link_framework(name, as_needed.unwrap_or(true))
             }
@@ -1665,6 +1865,38 @@ fn add_gcc_ld_path(
     }
 }
 
+/// Points the linker at the target's sysroot and, if necessary, at the target itself
+fn add_cross_target_args(cmd: &mut dyn Linker, options: &Options, flavor: LinkerFlavor) {
+    if let Some(sysroot) = options.codegen_opts.target_sysroot.as_ref() {
+        match flavor {
+            LinkerFlavor::Gcc | LinkerFlavor::Ld | LinkerFlavor::Lld(LldFlavor::Ld) => {
+                cmd.cmd().arg({
+                    let mut arg = OsString::from("--sysroot=");
+                    arg.push(sysroot);
+                    arg
+                });
+            }
+            _ => (),
+        }
+    }
+
+    if !options.is_cross_compiling() || flavor != LinkerFlavor::Gcc {
+        return;
+    }
+    // Unlike a cross toolchain, which is named after its target, clang links for the host
+    // unless told otherwise
+    let (linker, _) = linker_and_flavor(options);
+    let is_clang = linker
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem == "clang" || stem.starts_with("clang-"))
+        .unwrap_or(false);
+    if is_clang {
+        cmd.cmd()
+            .arg(format!("--target={}", options.target.triple()));
+    }
+}
+
 /// Checks if target supports project_type as output
 fn invalid_output_for_target(options: &Options) -> bool {
     let project_type = options.app_type;
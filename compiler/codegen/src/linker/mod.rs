@@ -1,4 +1,5 @@
 pub(crate) mod archive;
+mod builtin;
 mod command;
 pub(crate) mod link;
 mod rpath;
@@ -671,6 +672,21 @@ impl<'a> Linker for GccLinker<'a> {
     }
 
     fn export_symbols(&mut self, tmpdir: &Path, project_type: ProjectType, symbols: &[String]) {
+        // NIF libraries loaded via `erlang:load_nif/2`, and drivers loaded via `erl_ddll`, resolve
+        // the `enif_*` and `driver_*` functions against the executable, so they must be present in
+        // its dynamic symbol table on ELF targets
+        let target_options = &self.options.target.options;
+        let is_elf = !target_options.is_like_osx
+            && !target_options.is_like_windows
+            && !target_options.is_like_wasm;
+        if project_type == ProjectType::Executable && target_options.dynamic_linking && is_elf {
+            self.linker_arg("--export-dynamic-symbol=enif_*");
+            self.linker_arg("--export-dynamic-symbol=driver_*");
+            self.linker_arg("--export-dynamic-symbol=erl_errno_id");
+            self.linker_arg("--export-dynamic-symbol=set_port_control_flags");
+            self.linker_arg("--export-dynamic-symbol=set_busy_port");
+        }
+
         // Symbol visibility in object files typically takes care of this.
         if project_type == ProjectType::Executable
             && self
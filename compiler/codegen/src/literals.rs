@@ -0,0 +1,56 @@
+use log::debug;
+
+use firefly_llvm::{self as llvm, ComdatSelectionKind, GlobalObject, GlobalValue, Linkage, Value};
+use firefly_session::Options;
+
+/// The prefixes used for the symbol names of constant data emitted for literals
+///
+/// Each is followed by a hash of the contents of the constant, so identical constants emitted by
+/// different modules have the same symbol name:
+///
+/// * `binary_`: the `BinaryData` of a constant binary
+/// * `bigint_`: the digits of a big integer constant
+/// * `literal_`: a constant tuple, or the cons cells of a constant list
+pub const LITERAL_SYMBOL_PREFIXES: &[&'static str] = &["binary_", "bigint_", "literal_"];
+
+/// Deduplicates the constant data emitted for literals in the given module.
+///
+/// Every module emits a `linkonce_odr` definition for each constant binary, big integer, tuple or
+/// list it uses, named by a hash of its contents. As with atoms, see `atoms::dedup_atoms`, without
+/// COMDATs those definitions are only deduplicated for the purposes of symbol resolution, and every
+/// copy of the data ends up in the output. Each definition is placed in its own COMDAT group keyed
+/// by its symbol name, so the linker keeps exactly one read-only instance of each constant, no
+/// matter how many modules use it.
+///
+/// Mach-O has no COMDATs, but `ld64` already coalesces weak definitions, so no grouping is done
+/// there.
+///
+/// Returns the number of constants which were deduplicated.
+pub fn dedup_literals(options: &Options, module: llvm::Module) -> usize {
+    if options.target.options.is_like_osx {
+        return 0;
+    }
+
+    let literals = module
+        .globals()
+        .filter(|&gv| {
+            let name: String = gv.name().into();
+            gv.linkage() == Linkage::LinkOnceODR
+                && gv.is_constant()
+                && LITERAL_SYMBOL_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+        })
+        .collect::<Vec<_>>();
+
+    for literal in literals.iter() {
+        let name: String = literal.name().into();
+        let comdat = module.get_or_add_comdat(name.as_str());
+        comdat.set_kind(ComdatSelectionKind::Any);
+        literal.set_comdat(comdat);
+    }
+
+    debug!("deduplicated {} literal constants", literals.len());
+
+    literals.len()
+}
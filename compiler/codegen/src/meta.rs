@@ -74,7 +74,17 @@ impl ProjectInfo {
                     source: Some(fireflylib_dir.join(&format!("{}panic_unwind.rlib", prefix))),
                 },
             });
-        if options.target.options.is_like_wasm {
+        if options.target.options.is_like_wasm && options.target.options.os == "wasi" {
+            // The WASI runtime includes its own copy of the tiny runtime
+            info.used_libraries.push(NativeLibrary {
+                kind: NativeLibraryKind::Static {
+                    bundle: None,
+                    whole_archive: Some(true),
+                },
+                name: Some("firefly_rt_wasi".to_string()),
+                verbatim: None,
+            });
+        } else if options.target.options.is_like_wasm {
             info.used_libraries.push(NativeLibrary {
                 kind: NativeLibraryKind::Static {
                     bundle: None,
@@ -1,3 +1,5 @@
+mod ssa_to_beam;
 mod ssa_to_mlir;
 
+pub use self::ssa_to_beam::*;
 pub use self::ssa_to_mlir::*;
@@ -0,0 +1,1422 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail};
+
+use firefly_beam::beam::compact::{opcodes, Operand, Operation};
+use firefly_beam::serialization::etf;
+use firefly_binary::{BitVec, Bitstring};
+use firefly_intern::{symbols, Symbol};
+use firefly_number::Integer;
+use firefly_syntax_base::{Lit, Signature, TermType, Type};
+use firefly_syntax_ssa::{self as syntax_ssa, ir::instructions::*, DataFlowGraph};
+use firefly_syntax_ssa::{Block, Constant, ConstantItem, Immediate, ImmediateTerm, Value};
+
+use super::*;
+
+/// The number of words reserved on the heap for a fun, excluding its free variables
+///
+/// This is an upper bound on the size of a fun across the supported versions of the VM.
+const FUN_HEAP_SIZE: u32 = 10;
+
+const NIL: Operand = Operand::Atom(0);
+
+const BINARIES_UNSUPPORTED: &str = "binary syntax is not yet supported by the beam backend";
+
+/// What a call transfers control to
+#[derive(Debug, Copy, Clone)]
+enum Callee {
+    /// A function defined in this module, entered through its label
+    Local { label: u32, arity: u32 },
+    /// A function in the import table
+    External { import: u32, arity: u32 },
+    /// The fun passed after the arguments, i.e. a closure, or an indirect call
+    Fun { arity: u32 },
+    /// `erlang:raw_raise/3`, which is an instruction rather than a function
+    RawRaise,
+}
+
+/// Calls to builtins which are translated to dedicated instructions
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Intrinsic {
+    TupleSize,
+    MapEmpty,
+    MapFetch,
+    BuildStacktrace,
+    RemoveMessage,
+    RecvNext,
+    RecvPeekMessage,
+    RecvWaitTimeout,
+    NifStart,
+}
+impl Intrinsic {
+    fn get(signature: &Signature) -> Option<Self> {
+        match (signature.module, signature.name) {
+            (symbols::Empty, symbols::NifTupleSize) => Some(Self::TupleSize),
+            (symbols::Empty, symbols::NifMapEmpty) => Some(Self::MapEmpty),
+            (symbols::Empty, symbols::NifMapFetch) => Some(Self::MapFetch),
+            (symbols::Empty, symbols::NifBuildStacktrace)
+            | (symbols::Erlang, symbols::BuildStacktrace) => Some(Self::BuildStacktrace),
+            (symbols::Erlang, symbols::RemoveMessage) => Some(Self::RemoveMessage),
+            (symbols::Erlang, symbols::RecvNext) => Some(Self::RecvNext),
+            (symbols::Erlang, symbols::RecvPeekMessage) => Some(Self::RecvPeekMessage),
+            (symbols::Erlang, symbols::RecvWaitTimeout) => Some(Self::RecvWaitTimeout),
+            (symbols::Erlang, symbols::NifStart) => Some(Self::NifStart),
+            _ => None,
+        }
+    }
+}
+
+/// This builder translates a single function to BEAM instructions
+///
+/// Every value which is computed at runtime is stored in its own `y` register, and `x`
+/// registers are only used to pass arguments and results within a single instruction.
+/// This means nothing needs to be saved across calls, or garbage collections, at the cost
+/// of a large stack frame and a lot of moves.
+///
+/// The exceptions raised by a call are only caught when the function handles them, i.e.
+/// when the call's `is_err` result is used for something other than returning it to the
+/// caller. The exception value is then a tuple of class, reason and raw stacktrace, from
+/// which the exception is re-raised with `raw_raise`.
+pub(super) struct FunctionBuilder<'b, 'm> {
+    module: &'b mut ModuleBuilder<'m>,
+    function: &'m syntax_ssa::Function,
+    dfg: &'m DataFlowGraph,
+    definition: Definition,
+    // The label at the start of each block
+    blocks: HashMap<Block, u32>,
+    // The operand holding each value, either a `y` register or a constant
+    //
+    // Values which are never used do not have an operand
+    values: HashMap<Value, Operand>,
+    // Values which are the same as another value, e.g. the result of a cast
+    aliases: HashMap<Value, Value>,
+    uses: HashMap<Value, usize>,
+    // The `y` registers holding the free variables of a closure
+    env: Vec<Operand>,
+    // The calls whose exceptions must be caught
+    guarded: HashSet<Inst>,
+    // Tuples which are allocated, but not yet built, as their elements are still being set
+    //
+    // Setting the elements of a tuple in place is only safe if no garbage collection happens
+    // in between, so tuples are built in one go when they are first used instead
+    pending: HashMap<Value, Vec<Operand>>,
+    frame_size: u32,
+    code: Vec<Operation>,
+    // Code placed after the last block, i.e. argument moves for conditional branches
+    deferred: Vec<Operation>,
+    // The operands which hold the size of the stack frame, which is only known at the end
+    frame_refs: Vec<(usize, usize)>,
+}
+impl<'b, 'm> FunctionBuilder<'b, 'm> {
+    pub fn new(module: &'b mut ModuleBuilder<'m>, function: &'m syntax_ssa::Function) -> Self {
+        let definition = module
+            .definition(function.id)
+            .expect("function was not declared");
+        Self {
+            module,
+            function,
+            dfg: &function.dfg,
+            definition,
+            blocks: HashMap::new(),
+            values: HashMap::new(),
+            aliases: HashMap::new(),
+            uses: HashMap::new(),
+            env: vec![],
+            guarded: HashSet::new(),
+            pending: HashMap::new(),
+            frame_size: 0,
+            code: vec![],
+            deferred: vec![],
+            frame_refs: vec![],
+        }
+    }
+
+    /// Builds the function, appending its code to the module
+    pub fn build(mut self) -> anyhow::Result<()> {
+        let dfg = self.dfg;
+        for (block, _) in dfg.blocks() {
+            let label = self.module.next_label();
+            self.blocks.insert(block, label);
+        }
+        for _ in 0..self.definition.num_free.unwrap_or(0) {
+            let slot = self.allocate_slot();
+            self.env.push(Operand::Y(slot));
+        }
+        self.count_uses();
+        self.assign_operands()?;
+
+        for (block, _) in dfg.blocks() {
+            self.build_block(block)?;
+        }
+
+        let prologue = self.prologue()?;
+        for (index, operand) in self.frame_refs.iter().copied() {
+            self.code[index].operands[operand] = Operand::unsigned(self.frame_size);
+        }
+        self.module.code.extend(prologue);
+        self.module.code.append(&mut self.code);
+        self.module.code.append(&mut self.deferred);
+        Ok(())
+    }
+
+    fn count_uses(&mut self) {
+        let dfg = self.dfg;
+        for (block, _) in dfg.blocks() {
+            for inst in dfg.block_insts(block) {
+                let data = &**dfg.insts[inst];
+                // The environment of a closure is passed in place of the closure itself, so
+                // unpacking it does not require the closure
+                if let InstData::BinaryOpImm(BinaryOpImm {
+                    op: Opcode::UnpackEnv,
+                    ..
+                }) = data
+                {
+                    continue;
+                }
+                for value in self.inst_uses(inst) {
+                    *self.uses.entry(value).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns the values used by the given instruction, including branch arguments
+    fn inst_uses(&self, inst: Inst) -> Vec<Value> {
+        let dfg = self.dfg;
+        let data = &**dfg.insts[inst];
+        let mut uses = data.arguments(&dfg.value_lists).to_vec();
+        match data {
+            InstData::CallIndirect(CallIndirect { callee, .. }) => uses.push(*callee),
+            InstData::CondBr(CondBr {
+                then_dest,
+                else_dest,
+                ..
+            }) => {
+                uses.extend_from_slice(then_dest.1.as_slice(&dfg.value_lists));
+                uses.extend_from_slice(else_dest.1.as_slice(&dfg.value_lists));
+            }
+            _ => (),
+        }
+        uses
+    }
+
+    fn is_used(&self, value: Value) -> bool {
+        self.uses.get(&value).copied().unwrap_or(0) > 0
+    }
+
+    fn allocate_slot(&mut self) -> u32 {
+        let slot = self.frame_size;
+        self.frame_size += 1;
+        slot
+    }
+
+    /// Assigns a `y` register to the given value, if it is used
+    fn allocate(&mut self, value: Value) {
+        if self.is_used(value) {
+            let slot = self.allocate_slot();
+            self.values.insert(value, Operand::Y(slot));
+        }
+    }
+
+    /// Assigns an operand to every value up front, as blocks are not necessarily laid out
+    /// in an order where definitions come before uses
+    fn assign_operands(&mut self) -> anyhow::Result<()> {
+        let dfg = self.dfg;
+        for (block, _) in dfg.blocks() {
+            for param in dfg.block_params(block) {
+                self.allocate(*param);
+            }
+            for inst in dfg.block_insts(block) {
+                let results = dfg.inst_results(inst);
+                match &**dfg.insts[inst] {
+                    InstData::UnaryOpImm(UnaryOpImm { op, imm }) if *op != Opcode::Tuple => {
+                        let operand = self.immediate(*imm)?;
+                        self.values.insert(results[0], operand);
+                    }
+                    InstData::UnaryOpConst(UnaryOpConst { imm, .. }) => {
+                        let operand = self.constant(*imm)?;
+                        self.values.insert(results[0], operand);
+                    }
+                    InstData::UnaryOp(UnaryOp {
+                        op: Opcode::Cast | Opcode::Trunc | Opcode::Zext,
+                        arg,
+                    }) => {
+                        self.aliases.insert(results[0], *arg);
+                    }
+                    InstData::SetElement(SetElement {
+                        op: Opcode::SetElementMut,
+                        args,
+                        ..
+                    }) => {
+                        self.aliases.insert(results[0], args[0]);
+                    }
+                    InstData::SetElementImm(SetElementImm {
+                        op: Opcode::SetElementMut,
+                        arg,
+                        ..
+                    }) => {
+                        self.aliases.insert(results[0], *arg);
+                    }
+                    InstData::BinaryOpImm(BinaryOpImm {
+                        op: Opcode::UnpackEnv,
+                        arg,
+                        imm,
+                    }) => {
+                        let closure = dfg.block_params(self.entry_block()).last().copied();
+                        if self.definition.num_free.is_none() || closure != Some(*arg) {
+                            bail!("unpacking the environment of another closure is not supported by the beam backend");
+                        }
+                        let index = imm.as_i64().unwrap() as usize;
+                        let operand = self.env.get(index).cloned().ok_or_else(|| {
+                            anyhow!("invalid closure environment index {}", index)
+                        })?;
+                        self.values.insert(results[0], operand);
+                    }
+                    InstData::MakeFun(_) => {
+                        let ok = self.boolean(false);
+                        self.values.insert(results[0], ok);
+                        self.allocate(results[1]);
+                    }
+                    InstData::Call(Call { callee, .. }) => {
+                        let intrinsic = Intrinsic::get(&dfg.callee_signature(*callee));
+                        match intrinsic {
+                            Some(Intrinsic::MapEmpty) => {
+                                let map = self.module.literal(etf::Map::from(vec![]).into())?;
+                                self.values.insert(results[0], Operand::Literal(map));
+                            }
+                            Some(Intrinsic::RecvWaitTimeout) => {
+                                let ok = self.boolean(false);
+                                self.values.insert(results[0], ok);
+                                self.allocate(results[1]);
+                            }
+                            Some(_) => {
+                                for result in results {
+                                    self.allocate(*result);
+                                }
+                            }
+                            None => self.assign_call_results(block, inst),
+                        }
+                    }
+                    InstData::CallIndirect(_) => self.assign_call_results(block, inst),
+                    _ => {
+                        for result in results {
+                            self.allocate(*result);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn assign_call_results(&mut self, block: Block, inst: Inst) {
+        let dfg = self.dfg;
+        let results = dfg.inst_results(inst);
+        if results.len() == 2 {
+            if !self.is_guarded(block, inst) {
+                // The call either succeeds, or its exception unwinds to the caller
+                let ok = self.boolean(false);
+                self.values.insert(results[0], ok);
+                self.allocate(results[1]);
+                return;
+            }
+            self.guarded.insert(inst);
+        }
+        for result in results {
+            self.allocate(*result);
+        }
+    }
+
+    /// Returns true if the exceptions raised by the given call must be caught
+    ///
+    /// Calls which cannot fail are not guarded, nor are calls whose failure is only returned
+    /// to the caller, as it is equivalent to let the exception unwind. Exception ops always
+    /// fail, their result is the exception, so they are guarded unless it is returned.
+    fn is_guarded(&self, block: Block, inst: Inst) -> bool {
+        let dfg = self.dfg;
+        let results = dfg.inst_results(inst);
+        let (is_err, value) = (results[0], results[1]);
+        match self.uses.get(&is_err).copied().unwrap_or(0) {
+            0 => match &**dfg.insts[inst] {
+                InstData::Call(Call { callee, .. }) => {
+                    dfg.callee_signature(*callee).mfa().is_exception_op()
+                        && !self.propagates(block, inst, is_err, value)
+                }
+                _ => false,
+            },
+            1 => !self.propagates(block, inst, is_err, value),
+            _ => true,
+        }
+    }
+
+    /// Returns true if the instruction following `inst` returns its result to the caller
+    /// when `is_err` is set, e.g. `br_if is_err, failure_block, [value]`
+    fn propagates(&self, block: Block, inst: Inst, is_err: Value, value: Value) -> bool {
+        let dfg = self.dfg;
+        let Some(next) = dfg.block_insts(block).skip_while(|i| *i != inst).nth(1) else { return false; };
+        match &**dfg.insts[next] {
+            InstData::Ret(Ret { args, .. }) => args[0] == is_err && args[1] == value,
+            InstData::Br(Br {
+                op: Opcode::BrIf,
+                destination,
+                args,
+            }) => {
+                args.as_slice(&dfg.value_lists) == [is_err, value]
+                    && self.is_failure_block(*destination)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the given block raises the exception it receives
+    fn is_failure_block(&self, block: Block) -> bool {
+        let dfg = self.dfg;
+        let params = dfg.block_params(block);
+        let Some(first) = dfg.block_insts(block).next() else { return false; };
+        match &**dfg.insts[first] {
+            InstData::RetImm(RetImm { imm, arg, .. }) => {
+                params == [*arg] && imm.as_bool() == Some(true)
+            }
+            _ => false,
+        }
+    }
+
+    fn entry_block(&self) -> Block {
+        self.dfg
+            .blocks()
+            .next()
+            .map(|(block, _)| block)
+            .expect("function has no blocks")
+    }
+
+    /// Builds the code which sets up the stack frame and moves the arguments into it
+    fn prologue(&mut self) -> anyhow::Result<Vec<Operation>> {
+        let module = self.module.module.name();
+        let module = self.module.atom(module);
+        let name = self.module.atom(self.definition.name);
+        let arity = self.definition.arity;
+        let mut code = vec![
+            op(
+                opcodes::LABEL,
+                vec![Operand::unsigned(self.definition.info)],
+            ),
+            op(
+                opcodes::FUNC_INFO,
+                vec![
+                    Operand::Atom(module),
+                    Operand::Atom(name),
+                    Operand::unsigned(arity),
+                ],
+            ),
+            op(
+                opcodes::LABEL,
+                vec![Operand::unsigned(self.definition.entry)],
+            ),
+            op(
+                opcodes::ALLOCATE,
+                vec![Operand::unsigned(self.frame_size), Operand::unsigned(arity)],
+            ),
+        ];
+        if self.frame_size > 0 {
+            let slots = (0..self.frame_size).map(Operand::Y).collect();
+            code.push(op(opcodes::INIT_YREGS, vec![Operand::List(slots)]));
+        }
+
+        let dfg = self.dfg;
+        let params = dfg.block_params(self.entry_block());
+        let (args, closure) = match self.definition.num_free {
+            Some(_) => (&params[..params.len() - 1], params.last().copied()),
+            None => (params, None),
+        };
+        for (i, arg) in args.iter().enumerate() {
+            if let Some(dst) = self.dest(*arg) {
+                code.push(op(opcodes::MOVE, vec![Operand::X(i as u32), dst]));
+            }
+        }
+        for (j, slot) in self.env.iter().enumerate() {
+            let src = Operand::X((args.len() + j) as u32);
+            code.push(op(opcodes::MOVE, vec![src, slot.clone()]));
+        }
+        // The closure itself is not passed, so it must be rebuilt if it is used
+        if let Some(dst) = closure.and_then(|closure| self.dest(closure)) {
+            let index = self.module.lambda(self.function.id)?;
+            let need = FUN_HEAP_SIZE + self.env.len() as u32;
+            code.push(op(
+                opcodes::TEST_HEAP,
+                vec![Operand::unsigned(need), Operand::unsigned(0)],
+            ));
+            code.push(op(
+                opcodes::MAKE_FUN3,
+                vec![
+                    Operand::unsigned(index),
+                    dst,
+                    Operand::List(self.env.clone()),
+                ],
+            ));
+        }
+        Ok(code)
+    }
+
+    fn build_block(&mut self, block: Block) -> anyhow::Result<()> {
+        let dfg = self.dfg;
+        let label = self.blocks[&block];
+        self.emit(opcodes::LABEL, vec![Operand::unsigned(label)]);
+        for inst in dfg.block_insts(block) {
+            self.build_pending_tuples(inst)?;
+            self.build_inst(inst)?;
+        }
+        Ok(())
+    }
+
+    fn build_inst(&mut self, inst: Inst) -> anyhow::Result<()> {
+        let dfg = self.dfg;
+        let pool = &dfg.value_lists;
+        let results = dfg.inst_results(inst);
+        match &**dfg.insts[inst] {
+            InstData::UnaryOpImm(UnaryOpImm {
+                op: Opcode::Tuple,
+                imm,
+            }) => {
+                let arity = imm.as_i64().unwrap() as usize;
+                self.pending.insert(results[0], vec![NIL; arity]);
+            }
+            // Constants are assigned their operands up front
+            InstData::UnaryOpImm(_) | InstData::UnaryOpConst(_) => (),
+            InstData::UnaryOp(UnaryOp { op, arg }) => match op {
+                Opcode::Cast | Opcode::Trunc | Opcode::Zext => (),
+                Opcode::Head | Opcode::Tail => {
+                    if let Some(dst) = self.dest(results[0]) {
+                        let list = self.register(*arg, 0)?;
+                        let opcode = if *op == Opcode::Head {
+                            opcodes::GET_HD
+                        } else {
+                            opcodes::GET_TL
+                        };
+                        self.emit(opcode, vec![list, dst]);
+                    }
+                }
+                Opcode::Neg | Opcode::Not | Opcode::Bnot => {
+                    let arg = self.operand(*arg)?;
+                    let name = operator(*op).unwrap();
+                    self.call_operator(name, vec![arg], results[0]);
+                }
+                other => bail!("{} is not supported by the beam backend", other),
+            },
+            InstData::BinaryOp(BinaryOp { op, args }) => {
+                let lhs = self.operand(args[0])?;
+                let rhs = self.operand(args[1])?;
+                self.build_binary_op(*op, lhs, rhs, results[0])?;
+            }
+            InstData::BinaryOpImm(BinaryOpImm { op, arg, imm }) => match op {
+                // The environment of a closure is assigned its operands up front
+                Opcode::UnpackEnv => (),
+                Opcode::GetElement => {
+                    if let Some(dst) = self.dest(results[0]) {
+                        let tuple = self.register(*arg, 0)?;
+                        let index = imm.as_i64().unwrap() as u32;
+                        self.emit(
+                            opcodes::GET_TUPLE_ELEMENT,
+                            vec![tuple, Operand::unsigned(index), dst],
+                        );
+                    }
+                }
+                _ => {
+                    let lhs = self.operand(*arg)?;
+                    let rhs = self.immediate(*imm)?;
+                    self.build_binary_op(*op, lhs, rhs, results[0])?;
+                }
+            },
+            InstData::Call(Call { op, callee, args }) => {
+                let args = args.as_slice(pool);
+                self.build_call(inst, *callee, args, *op == Opcode::Enter)?;
+            }
+            InstData::CallIndirect(CallIndirect { op, callee, args }) => {
+                let mut operands = self.operands(args.as_slice(pool))?;
+                let arity = operands.len() as u32;
+                operands.push(self.operand(*callee)?);
+                let tail = *op == Opcode::EnterIndirect;
+                self.call(inst, Callee::Fun { arity }, operands, tail);
+            }
+            InstData::MakeFun(MakeFun { callee, env }) => {
+                if let Some(dst) = self.dest(results[1]) {
+                    let env = self.operands(env.as_slice(pool))?;
+                    let num_free = self.module.definition(*callee).and_then(|d| d.num_free);
+                    if num_free != Some(env.len() as u32) {
+                        bail!("closures with differently sized environments are not supported by the beam backend");
+                    }
+                    let index = self.module.lambda(*callee)?;
+                    let need = FUN_HEAP_SIZE + env.len() as u32;
+                    self.emit(
+                        opcodes::TEST_HEAP,
+                        vec![Operand::unsigned(need), Operand::unsigned(0)],
+                    );
+                    self.emit(
+                        opcodes::MAKE_FUN3,
+                        vec![Operand::unsigned(index), dst, Operand::List(env)],
+                    );
+                }
+            }
+            InstData::Br(Br {
+                op,
+                destination,
+                args,
+            }) => {
+                let args = args.as_slice(pool);
+                match op {
+                    Opcode::Br => {
+                        let jump = self.jump(*destination, args)?;
+                        self.code.extend(jump);
+                    }
+                    _ => {
+                        let cond = self.operand(args[0])?;
+                        let jump = self.jump(*destination, &args[1..])?;
+                        self.branch_if(cond, *op == Opcode::BrIf, jump);
+                    }
+                }
+            }
+            InstData::CondBr(CondBr {
+                cond,
+                then_dest,
+                else_dest,
+            }) => {
+                let cond = self.operand(*cond)?;
+                let then_jump = self.jump(then_dest.0, then_dest.1.as_slice(pool))?;
+                let else_jump = self.jump(else_dest.0, else_dest.1.as_slice(pool))?;
+                self.branch_if(cond, false, else_jump);
+                self.code.extend(then_jump);
+            }
+            InstData::Switch(Switch {
+                arg, arms, default, ..
+            }) => {
+                let arg = self.register(*arg, 0)?;
+                let mut arms = arms.clone();
+                arms.sort_by_key(|(value, _)| *value);
+                let mut choices = Vec::with_capacity(arms.len() * 2);
+                for (value, block) in arms {
+                    choices.push(Operand::integer(value as i64));
+                    choices.push(Operand::Label(self.blocks[&block]));
+                }
+                let default = Operand::Label(self.blocks[default]);
+                self.emit(
+                    opcodes::SELECT_VAL,
+                    vec![arg, default, Operand::List(choices)],
+                );
+            }
+            InstData::Ret(Ret { args, .. }) => {
+                let is_err = self.operand(args[0])?;
+                if is_err == self.boolean(true) {
+                    self.raise(args[1])?;
+                } else if is_err == self.boolean(false) {
+                    self.ret(args[1])?;
+                } else {
+                    let ok = self.module.next_label();
+                    let t = self.boolean(true);
+                    self.emit(opcodes::IS_EQ_EXACT, vec![Operand::Label(ok), is_err, t]);
+                    self.raise(args[1])?;
+                    self.emit(opcodes::LABEL, vec![Operand::unsigned(ok)]);
+                    self.ret(args[1])?;
+                }
+            }
+            InstData::RetImm(RetImm { imm, arg, .. }) => {
+                if imm.as_bool() == Some(true) {
+                    self.raise(*arg)?;
+                } else {
+                    self.ret(*arg)?;
+                }
+            }
+            InstData::PrimOp(PrimOp { op, args }) => {
+                let args = args.as_slice(pool);
+                match op {
+                    Opcode::ExceptionClass | Opcode::ExceptionReason | Opcode::ExceptionTrace => {
+                        if let Some(dst) = self.dest(results[0]) {
+                            let index = match op {
+                                Opcode::ExceptionClass => 0,
+                                Opcode::ExceptionReason => 1,
+                                _ => 2,
+                            };
+                            let exception = self.register(args[0], 0)?;
+                            self.emit(
+                                opcodes::GET_TUPLE_ELEMENT,
+                                vec![exception, Operand::unsigned(index), dst],
+                            );
+                        }
+                    }
+                    Opcode::Raise => {
+                        let args = self.operands(args)?;
+                        self.call(inst, Callee::RawRaise, args, true);
+                    }
+                    Opcode::BitsMatchStart | Opcode::BitsTestTail => bail!(BINARIES_UNSUPPORTED),
+                    other => bail!("{} is not supported by the beam backend", other),
+                }
+            }
+            InstData::PrimOpImm(PrimOpImm { op, .. }) => match op {
+                Opcode::BitsMatchStart | Opcode::BitsTestTail => bail!(BINARIES_UNSUPPORTED),
+                other => bail!("{} is not supported by the beam backend", other),
+            },
+            InstData::IsType(IsType { arg, ty }) => {
+                let Type::Term(ty) = ty else { bail!("type tests of {} are not supported by the beam backend", ty) };
+                if let TermType::Any = ty {
+                    let t = self.boolean(true);
+                    self.store(t, results[0]);
+                } else {
+                    let arg = *arg;
+                    self.test(results[0], |builder, fail| builder.type_test(fail, arg, ty))?;
+                }
+            }
+            InstData::BitsMatch(_) | InstData::BitsMatchSkip(_) | InstData::BitsPush(_) => {
+                bail!(BINARIES_UNSUPPORTED)
+            }
+            InstData::SetElement(SetElement { op, index, args }) => {
+                let index = index.as_i64().unwrap() as usize;
+                let value = self.operand(args[1])?;
+                self.build_set_element(*op, args[0], index, value, results[0])?;
+            }
+            InstData::SetElementImm(SetElementImm {
+                op,
+                arg,
+                index,
+                value,
+            }) => {
+                let index = index.as_i64().unwrap() as usize;
+                let value = self.immediate(*value)?;
+                self.build_set_element(*op, *arg, index, value, results[0])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn build_binary_op(
+        &mut self,
+        op: Opcode,
+        lhs: Operand,
+        rhs: Operand,
+        result: Value,
+    ) -> anyhow::Result<()> {
+        if let Some((opcode, swapped)) = comparison(op) {
+            let (lhs, rhs) = if swapped { (rhs, lhs) } else { (lhs, rhs) };
+            return self.test(result, |builder, fail| {
+                builder.emit(opcode, vec![Operand::Label(fail), lhs, rhs]);
+                Ok(())
+            });
+        }
+        match op {
+            Opcode::Cons => {
+                if let Some(dst) = self.dest(result) {
+                    self.emit(
+                        opcodes::TEST_HEAP,
+                        vec![Operand::unsigned(2), Operand::unsigned(0)],
+                    );
+                    self.emit(opcodes::PUT_LIST, vec![lhs, rhs, dst]);
+                }
+                Ok(())
+            }
+            _ => match operator(op) {
+                Some(name) => {
+                    self.call_operator(name, vec![lhs, rhs], result);
+                    Ok(())
+                }
+                None => bail!("{} is not supported by the beam backend", op),
+            },
+        }
+    }
+
+    fn build_set_element(
+        &mut self,
+        op: Opcode,
+        tuple: Value,
+        index: usize,
+        value: Operand,
+        result: Value,
+    ) -> anyhow::Result<()> {
+        if op == Opcode::SetElementMut {
+            let tuple = self.root(tuple);
+            let Some(elements) = self.pending.get_mut(&tuple) else {
+                bail!(
+                    "tuples can only be updated in place before they are used by the beam backend"
+                )
+            };
+            let element = elements
+                .get_mut(index)
+                .ok_or_else(|| anyhow!("invalid tuple index {}", index))?;
+            *element = value;
+            return Ok(());
+        }
+        let tuple = self.operand(tuple)?;
+        let index = Operand::integer(index as i64 + 1);
+        self.call_operator("setelement", vec![index, tuple, value], result);
+        Ok(())
+    }
+
+    /// Builds the tuples used by the given instruction which are still pending
+    ///
+    /// All of them are built before a branch, as they may be used in another block
+    fn build_pending_tuples(&mut self, inst: Inst) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let data = &**self.dfg.insts[inst];
+        let mut tuples = match data {
+            InstData::Br(_)
+            | InstData::CondBr(_)
+            | InstData::Switch(_)
+            | InstData::Ret(_)
+            | InstData::RetImm(_) => self.pending.keys().copied().collect(),
+            InstData::Call(Call { op, .. }) | InstData::CallIndirect(CallIndirect { op, .. })
+                if op.is_terminator() =>
+            {
+                self.pending.keys().copied().collect()
+            }
+            // Setting an element of a pending tuple does not use it, but the element may be one
+            InstData::SetElement(SetElement {
+                op: Opcode::SetElementMut,
+                args,
+                ..
+            }) => vec![self.root(args[1])],
+            InstData::SetElementImm(SetElementImm {
+                op: Opcode::SetElementMut,
+                ..
+            }) => vec![],
+            _ => self
+                .inst_uses(inst)
+                .into_iter()
+                .map(|value| self.root(value))
+                .collect::<Vec<_>>(),
+        };
+        tuples.retain(|tuple| self.pending.contains_key(tuple));
+        tuples.sort();
+        tuples.dedup();
+        for tuple in tuples {
+            self.build_tuple(tuple)?;
+        }
+        Ok(())
+    }
+
+    fn build_tuple(&mut self, tuple: Value) -> anyhow::Result<()> {
+        let elements = self.pending.remove(&tuple).unwrap();
+        let Some(dst) = self.dest(tuple) else { return Ok(()); };
+        if elements.is_empty() {
+            let empty = self.module.literal(etf::Tuple::nil().into())?;
+            self.emit(opcodes::MOVE, vec![Operand::Literal(empty), dst]);
+        } else {
+            let need = elements.len() as u32 + 1;
+            self.emit(
+                opcodes::TEST_HEAP,
+                vec![Operand::unsigned(need), Operand::unsigned(0)],
+            );
+            self.emit(opcodes::PUT_TUPLE2, vec![dst, Operand::List(elements)]);
+        }
+        Ok(())
+    }
+
+    fn build_call(
+        &mut self,
+        inst: Inst,
+        callee: FuncRef,
+        args: &[Value],
+        tail: bool,
+    ) -> anyhow::Result<()> {
+        let signature = self.dfg.callee_signature(callee).clone();
+        if let Some(intrinsic) = Intrinsic::get(&signature) {
+            if tail {
+                bail!("unexpected tail call to {}", signature.mfa());
+            }
+            return self.build_intrinsic(inst, intrinsic, args);
+        }
+
+        let mut operands = self.operands(args)?;
+        let arity = args.len() as u32;
+        let callee = if signature.module == symbols::Empty {
+            // The map natives take the map first, unlike their equivalents in `maps`
+            let name = match signature.name {
+                symbols::NifMapPut | symbols::NifMapPutMut => "put",
+                symbols::NifMapUpdate | symbols::NifMapUpdateMut => "update",
+                symbols::NifBsInit | symbols::NifBsFinish => bail!(BINARIES_UNSUPPORTED),
+                other => bail!("{} is not supported by the beam backend", other),
+            };
+            operands.rotate_left(1);
+            let import = self
+                .module
+                .import(Symbol::intern("maps"), Symbol::intern(name), arity);
+            Callee::External { import, arity }
+        } else if signature.module == symbols::Erlang && signature.name == symbols::RawRaise {
+            Callee::RawRaise
+        } else if let Some(definition) = self.module.definition(callee) {
+            match definition.num_free {
+                // The closure is passed last, which is where `call_fun` expects the fun
+                Some(_) => Callee::Fun { arity: arity - 1 },
+                None => Callee::Local {
+                    label: definition.entry,
+                    arity: definition.arity,
+                },
+            }
+        } else {
+            let name = match (signature.module, signature.name) {
+                (symbols::Erlang, symbols::AndAlso) => symbols::And,
+                (symbols::Erlang, symbols::OrElse) => symbols::Or,
+                (_, name) => name,
+            };
+            let import = self.module.import(signature.module, name, arity);
+            Callee::External { import, arity }
+        };
+        self.call(inst, callee, operands, tail);
+        Ok(())
+    }
+
+    fn call(&mut self, inst: Inst, callee: Callee, args: Vec<Operand>, tail: bool) {
+        let dfg = self.dfg;
+        let results = dfg.inst_results(inst);
+        if tail {
+            self.move_arguments(args);
+            match callee {
+                Callee::Local { label, arity } => self.emit_with_frame(
+                    opcodes::CALL_LAST,
+                    vec![Operand::unsigned(arity), Operand::Label(label), NIL],
+                    2,
+                ),
+                Callee::External { import, arity } => self.emit_with_frame(
+                    opcodes::CALL_EXT_LAST,
+                    vec![Operand::unsigned(arity), Operand::unsigned(import), NIL],
+                    2,
+                ),
+                Callee::Fun { arity } => {
+                    self.emit(opcodes::CALL_FUN, vec![Operand::unsigned(arity)]);
+                    self.emit_return();
+                }
+                Callee::RawRaise => self.emit(opcodes::RAW_RAISE, vec![]),
+            }
+            return;
+        }
+
+        let guard = if self.guarded.contains(&inst) {
+            let tag = Operand::Y(self.allocate_slot());
+            let handler = self.module.next_label();
+            self.emit(opcodes::TRY, vec![tag.clone(), Operand::Label(handler)]);
+            Some((tag, handler))
+        } else {
+            None
+        };
+        self.move_arguments(args);
+        match callee {
+            Callee::Local { label, arity } => self.emit(
+                opcodes::CALL,
+                vec![Operand::unsigned(arity), Operand::Label(label)],
+            ),
+            Callee::External { import, arity } => self.emit(
+                opcodes::CALL_EXT,
+                vec![Operand::unsigned(arity), Operand::unsigned(import)],
+            ),
+            Callee::Fun { arity } => self.emit(opcodes::CALL_FUN, vec![Operand::unsigned(arity)]),
+            Callee::RawRaise => self.emit(opcodes::RAW_RAISE, vec![]),
+        }
+
+        match guard {
+            None => {
+                if let Some(value) = results.last() {
+                    self.store(Operand::X(0), *value);
+                }
+            }
+            Some((tag, handler)) => {
+                let (is_err, value) = (results[0], results[1]);
+                let done = self.module.next_label();
+                self.emit(opcodes::TRY_END, vec![tag.clone()]);
+                self.store(Operand::X(0), value);
+                let ok = self.boolean(false);
+                self.store(ok, is_err);
+                self.emit(opcodes::JUMP, vec![Operand::Label(done)]);
+                self.emit(opcodes::LABEL, vec![Operand::unsigned(handler)]);
+                self.emit(opcodes::TRY_CASE, vec![tag]);
+                // The class, reason and raw stacktrace are in x0-x2
+                if let Some(dst) = self.dest(value) {
+                    self.emit(
+                        opcodes::TEST_HEAP,
+                        vec![Operand::unsigned(4), Operand::unsigned(3)],
+                    );
+                    let exception = vec![Operand::X(0), Operand::X(1), Operand::X(2)];
+                    self.emit(opcodes::PUT_TUPLE2, vec![dst, Operand::List(exception)]);
+                }
+                let err = self.boolean(true);
+                self.store(err, is_err);
+                self.emit(opcodes::LABEL, vec![Operand::unsigned(done)]);
+            }
+        }
+    }
+
+    fn build_intrinsic(
+        &mut self,
+        inst: Inst,
+        intrinsic: Intrinsic,
+        args: &[Value],
+    ) -> anyhow::Result<()> {
+        let dfg = self.dfg;
+        let results = dfg.inst_results(inst);
+        match intrinsic {
+            // The empty map is assigned a literal up front
+            Intrinsic::MapEmpty | Intrinsic::NifStart => (),
+            Intrinsic::RemoveMessage => self.emit(opcodes::REMOVE_MESSAGE, vec![]),
+            Intrinsic::RecvNext => {
+                let next = self.module.next_label();
+                self.emit(opcodes::LOOP_REC_END, vec![Operand::Label(next)]);
+                self.emit(opcodes::LABEL, vec![Operand::unsigned(next)]);
+            }
+            Intrinsic::RecvPeekMessage => {
+                let (available, message) = (results[0], results[1]);
+                let empty = self.module.next_label();
+                let done = self.module.next_label();
+                self.emit(
+                    opcodes::LOOP_REC,
+                    vec![Operand::Label(empty), Operand::X(0)],
+                );
+                self.store(Operand::X(0), message);
+                let t = self.boolean(true);
+                self.store(t, available);
+                self.emit(opcodes::JUMP, vec![Operand::Label(done)]);
+                self.emit(opcodes::LABEL, vec![Operand::unsigned(empty)]);
+                let f = self.boolean(false);
+                self.store(f, available);
+                self.emit(opcodes::LABEL, vec![Operand::unsigned(done)]);
+            }
+            Intrinsic::RecvWaitTimeout => {
+                let timed_out = results[1];
+                let message = self.module.next_label();
+                let done = self.module.next_label();
+                // Without a timeout, the wait is infinite
+                if let Some(timeout) = args.first() {
+                    let timeout = self.operand(*timeout)?;
+                    self.emit(
+                        opcodes::WAIT_TIMEOUT,
+                        vec![Operand::Label(message), timeout],
+                    );
+                    self.emit(opcodes::TIMEOUT, vec![]);
+                    let t = self.boolean(true);
+                    self.store(t, timed_out);
+                    self.emit(opcodes::JUMP, vec![Operand::Label(done)]);
+                } else {
+                    self.emit(opcodes::WAIT, vec![Operand::Label(message)]);
+                }
+                self.emit(opcodes::LABEL, vec![Operand::unsigned(message)]);
+                let f = self.boolean(false);
+                self.store(f, timed_out);
+                self.emit(opcodes::LABEL, vec![Operand::unsigned(done)]);
+            }
+            Intrinsic::BuildStacktrace => {
+                let trace = self.operand(args[0])?;
+                self.emit(opcodes::MOVE, vec![trace, Operand::X(0)]);
+                self.emit(opcodes::BUILD_STACKTRACE, vec![]);
+                self.store(Operand::X(0), results[0]);
+            }
+            Intrinsic::TupleSize => {
+                let (is_err, size) = (results[0], results[1]);
+                let fail = self.module.next_label();
+                let done = self.module.next_label();
+                let tuple = self.operand(args[0])?;
+                self.emit(opcodes::MOVE, vec![tuple, Operand::X(0)]);
+                self.emit(opcodes::IS_TUPLE, vec![Operand::Label(fail), Operand::X(0)]);
+                let import = self.module.import(symbols::Erlang, symbols::TupleSize, 1);
+                self.emit(
+                    opcodes::CALL_EXT,
+                    vec![Operand::unsigned(1), Operand::unsigned(import)],
+                );
+                self.store(Operand::X(0), size);
+                self.branch_result(is_err, fail, done);
+            }
+            Intrinsic::MapFetch => {
+                let (is_err, value) = (results[0], results[1]);
+                let fail = self.module.next_label();
+                let done = self.module.next_label();
+                let map = self.register(args[0], 0)?;
+                let key = self.operand(args[1])?;
+                let dst = self.dest(value).unwrap_or(Operand::X(1));
+                self.emit(
+                    opcodes::GET_MAP_ELEMENTS,
+                    vec![Operand::Label(fail), map, Operand::List(vec![key, dst])],
+                );
+                self.branch_result(is_err, fail, done);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets `is_err` after code which jumps to `fail` when it fails, and falls through otherwise
+    fn branch_result(&mut self, is_err: Value, fail: u32, done: u32) {
+        let f = self.boolean(false);
+        self.store(f, is_err);
+        self.emit(opcodes::JUMP, vec![Operand::Label(done)]);
+        self.emit(opcodes::LABEL, vec![Operand::unsigned(fail)]);
+        let t = self.boolean(true);
+        self.store(t, is_err);
+        self.emit(opcodes::LABEL, vec![Operand::unsigned(done)]);
+    }
+
+    /// Stores a boolean in `result` which is true if the tests built by `build` succeed
+    ///
+    /// The tests jump to the label they are given when they fail.
+    fn test<F>(&mut self, result: Value, build: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(&mut Self, u32) -> anyhow::Result<()>,
+    {
+        let Some(dst) = self.dest(result) else { return Ok(()); };
+        let fail = self.module.next_label();
+        let done = self.module.next_label();
+        build(self, fail)?;
+        let t = self.boolean(true);
+        self.emit(opcodes::MOVE, vec![t, dst.clone()]);
+        self.emit(opcodes::JUMP, vec![Operand::Label(done)]);
+        self.emit(opcodes::LABEL, vec![Operand::unsigned(fail)]);
+        let f = self.boolean(false);
+        self.emit(opcodes::MOVE, vec![f, dst]);
+        self.emit(opcodes::LABEL, vec![Operand::unsigned(done)]);
+        Ok(())
+    }
+
+    fn type_test(&mut self, fail: u32, value: Value, ty: &TermType) -> anyhow::Result<()> {
+        let term = self.register(value, 0)?;
+        let fail = Operand::Label(fail);
+        let opcode = match ty {
+            TermType::Any => return Ok(()),
+            TermType::Bool => opcodes::IS_BOOLEAN,
+            TermType::Integer => opcodes::IS_INTEGER,
+            TermType::Float => opcodes::IS_FLOAT,
+            TermType::Number => opcodes::IS_NUMBER,
+            TermType::Atom => opcodes::IS_ATOM,
+            TermType::Bitstring => opcodes::IS_BITSTR,
+            TermType::Binary => opcodes::IS_BINARY,
+            TermType::Nil => opcodes::IS_NIL,
+            TermType::Cons => opcodes::IS_NONEMPTY_LIST,
+            TermType::List(_) | TermType::MaybeImproperList => opcodes::IS_LIST,
+            TermType::Tuple(_) => opcodes::IS_TUPLE,
+            TermType::Map => opcodes::IS_MAP,
+            TermType::Reference => opcodes::IS_REFERENCE,
+            TermType::Port => opcodes::IS_PORT,
+            TermType::Pid => opcodes::IS_PID,
+            TermType::Fun(Some(ty)) => {
+                let arity = Operand::integer(ty.arity() as i64);
+                self.emit(opcodes::IS_FUNCTION2, vec![fail, term, arity]);
+                return Ok(());
+            }
+            TermType::Fun(None) => opcodes::IS_FUNCTION,
+        };
+        self.emit(opcode, vec![fail.clone(), term.clone()]);
+        if let TermType::Tuple(Some(elements)) = ty {
+            let arity = Operand::unsigned(elements.len() as u32);
+            self.emit(opcodes::TEST_ARITY, vec![fail, term, arity]);
+        }
+        Ok(())
+    }
+
+    /// Returns the code which passes `args` to `block` and jumps to it
+    fn jump(&mut self, block: Block, args: &[Value]) -> anyhow::Result<Vec<Operation>> {
+        let params = self.dfg.block_params(block);
+        let mut moves = vec![];
+        for (param, arg) in params.iter().zip(args) {
+            if let Some(dst) = self.dest(*param) {
+                let src = self.operand(*arg)?;
+                if src != dst {
+                    moves.push((src, dst));
+                }
+            }
+        }
+        let mut code = vec![];
+        // The arguments are moved in parallel, as a parameter may be the argument of another
+        if moves.len() > 1 {
+            for (i, (src, _)) in moves.iter_mut().enumerate() {
+                let tmp = Operand::X(i as u32);
+                code.push(op(opcodes::MOVE, vec![src.clone(), tmp.clone()]));
+                *src = tmp;
+            }
+        }
+        for (src, dst) in moves {
+            code.push(op(opcodes::MOVE, vec![src, dst]));
+        }
+        let label = Operand::Label(self.blocks[&block]);
+        code.push(op(opcodes::JUMP, vec![label]));
+        Ok(code)
+    }
+
+    /// Emits `jump`, the result of `Self::jump`, when `cond` is equal to `when`
+    fn branch_if(&mut self, cond: Operand, when: bool, jump: Vec<Operation>) {
+        let t = self.boolean(true);
+        let f = self.boolean(false);
+        if cond == t || cond == f {
+            if (cond == t) == when {
+                self.code.extend(jump);
+            }
+            return;
+        }
+        // Without arguments to move, the test can jump straight to the destination
+        let target = if jump.len() == 1 {
+            jump[0].operands[0].clone()
+        } else {
+            let label = self.module.next_label();
+            self.deferred
+                .push(op(opcodes::LABEL, vec![Operand::unsigned(label)]));
+            self.deferred.extend(jump);
+            Operand::Label(label)
+        };
+        // Tests fall through when they succeed, and jump when they fail
+        let opcode = if when {
+            opcodes::IS_NE_EXACT
+        } else {
+            opcodes::IS_EQ_EXACT
+        };
+        self.emit(opcode, vec![target, cond, t]);
+    }
+
+    fn ret(&mut self, value: Value) -> anyhow::Result<()> {
+        let value = self.operand(value)?;
+        self.emit(opcodes::MOVE, vec![value, Operand::X(0)]);
+        self.emit_return();
+        Ok(())
+    }
+
+    /// Re-raises an exception caught by a guarded call
+    fn raise(&mut self, exception: Value) -> anyhow::Result<()> {
+        let exception = self.register(exception, 3)?;
+        for i in 0..3 {
+            self.emit(
+                opcodes::GET_TUPLE_ELEMENT,
+                vec![exception.clone(), Operand::unsigned(i), Operand::X(i)],
+            );
+        }
+        self.emit(opcodes::RAW_RAISE, vec![]);
+        Ok(())
+    }
+
+    fn call_operator(&mut self, name: &str, args: Vec<Operand>, result: Value) {
+        let arity = args.len() as u32;
+        let import = self
+            .module
+            .import(symbols::Erlang, Symbol::intern(name), arity);
+        self.move_arguments(args);
+        self.emit(
+            opcodes::CALL_EXT,
+            vec![Operand::unsigned(arity), Operand::unsigned(import)],
+        );
+        self.store(Operand::X(0), result);
+    }
+
+    fn move_arguments(&mut self, args: Vec<Operand>) {
+        for (i, arg) in args.into_iter().enumerate() {
+            self.emit(opcodes::MOVE, vec![arg, Operand::X(i as u32)]);
+        }
+    }
+
+    fn emit_return(&mut self) {
+        self.emit_with_frame(opcodes::DEALLOCATE, vec![NIL], 0);
+        self.emit(opcodes::RETURN, vec![]);
+    }
+
+    fn emit(&mut self, opcode: u8, operands: Vec<Operand>) {
+        self.code.push(op(opcode, operands));
+    }
+
+    /// Emits an operation whose operand at `index` is replaced by the frame size
+    fn emit_with_frame(&mut self, opcode: u8, operands: Vec<Operand>, index: usize) {
+        self.frame_refs.push((self.code.len(), index));
+        self.emit(opcode, operands);
+    }
+
+    /// Returns the value the given value is an alias of, or itself
+    fn root(&self, mut value: Value) -> Value {
+        while let Some(aliased) = self.aliases.get(&value) {
+            value = *aliased;
+        }
+        value
+    }
+
+    fn operand(&self, value: Value) -> anyhow::Result<Operand> {
+        let root = self.root(value);
+        self.values
+            .get(&root)
+            .cloned()
+            .ok_or_else(|| anyhow!("no operand was assigned to {}", value))
+    }
+
+    fn operands(&self, values: &[Value]) -> anyhow::Result<Vec<Operand>> {
+        values.iter().map(|value| self.operand(*value)).collect()
+    }
+
+    /// Returns the register the given value is stored in, if it is used
+    fn dest(&self, value: Value) -> Option<Operand> {
+        match self.values.get(&value) {
+            Some(register @ Operand::Y(_)) => Some(register.clone()),
+            _ => None,
+        }
+    }
+
+    /// Stores `src` in the register of `value`, if it has one
+    fn store(&mut self, src: Operand, value: Value) {
+        if let Some(dst) = self.dest(value) {
+            if dst != src {
+                self.emit(opcodes::MOVE, vec![src, dst]);
+            }
+        }
+    }
+
+    /// Returns the given value as a register, moving it to `x(scratch)` if it is a constant
+    fn register(&mut self, value: Value, scratch: u32) -> anyhow::Result<Operand> {
+        let operand = self.operand(value)?;
+        match operand {
+            Operand::X(_) | Operand::Y(_) => Ok(operand),
+            _ => {
+                self.emit(opcodes::MOVE, vec![operand, Operand::X(scratch)]);
+                Ok(Operand::X(scratch))
+            }
+        }
+    }
+
+    fn boolean(&mut self, value: bool) -> Operand {
+        let atom = if value { symbols::True } else { symbols::False };
+        Operand::Atom(self.module.atom(atom))
+    }
+
+    fn immediate(&mut self, imm: Immediate) -> anyhow::Result<Operand> {
+        match imm {
+            Immediate::Term(ImmediateTerm::Bool(value)) | Immediate::I1(value) => {
+                Ok(self.boolean(value))
+            }
+            Immediate::Term(ImmediateTerm::Atom(atom)) => Ok(Operand::Atom(self.module.atom(atom))),
+            Immediate::Term(ImmediateTerm::Integer(i)) | Immediate::I64(i) => {
+                Ok(Operand::integer(i))
+            }
+            Immediate::I8(i) => Ok(Operand::integer(i as i64)),
+            Immediate::I16(i) => Ok(Operand::integer(i as i64)),
+            Immediate::I32(i) => Ok(Operand::integer(i as i64)),
+            Immediate::Isize(i) => Ok(Operand::integer(i as i64)),
+            Immediate::Term(ImmediateTerm::Float(f)) | Immediate::F64(f) => {
+                let literal = self.module.literal(etf::Float::from(f).into())?;
+                Ok(Operand::Literal(literal))
+            }
+            Immediate::Term(ImmediateTerm::Nil | ImmediateTerm::None) => Ok(NIL),
+        }
+    }
+
+    fn constant(&mut self, constant: Constant) -> anyhow::Result<Operand> {
+        let dfg = self.dfg;
+        let item = dfg.constant(constant);
+        match &*item {
+            ConstantItem::Integer(Integer::Small(i)) => Ok(Operand::integer(*i)),
+            ConstantItem::Bool(value) => Ok(self.boolean(*value)),
+            ConstantItem::Atom(atom) => Ok(Operand::Atom(self.module.atom(*atom))),
+            ConstantItem::Term(Lit::Atom(atom)) => Ok(Operand::Atom(self.module.atom(*atom))),
+            ConstantItem::Term(Lit::Nil) => Ok(NIL),
+            item => {
+                let literal = self.module.literal(constant_term(item))?;
+                Ok(Operand::Literal(literal))
+            }
+        }
+    }
+}
+
+fn op(opcode: u8, operands: Vec<Operand>) -> Operation {
+    Operation { opcode, operands }
+}
+
+/// Returns the test for the given comparison, and whether its operands are swapped
+fn comparison(op: Opcode) -> Option<(u8, bool)> {
+    match op {
+        Opcode::Eq => Some((opcodes::IS_EQ, false)),
+        Opcode::EqExact | Opcode::IcmpEq => Some((opcodes::IS_EQ_EXACT, false)),
+        Opcode::Neq => Some((opcodes::IS_NE, false)),
+        Opcode::NeqExact | Opcode::IcmpNeq => Some((opcodes::IS_NE_EXACT, false)),
+        Opcode::Lt | Opcode::IcmpLt => Some((opcodes::IS_LT, false)),
+        Opcode::Gte | Opcode::IcmpGte => Some((opcodes::IS_GE, false)),
+        Opcode::Gt | Opcode::IcmpGt => Some((opcodes::IS_LT, true)),
+        Opcode::Lte | Opcode::IcmpLte => Some((opcodes::IS_GE, true)),
+        _ => None,
+    }
+}
+
+/// Returns the name of the `erlang` function implementing the given operator
+fn operator(op: Opcode) -> Option<&'static str> {
+    match op {
+        Opcode::Add => Some("+"),
+        Opcode::Sub | Opcode::Neg => Some("-"),
+        Opcode::Mul => Some("*"),
+        Opcode::Div => Some("div"),
+        Opcode::Fdiv => Some("/"),
+        Opcode::Rem => Some("rem"),
+        Opcode::Not => Some("not"),
+        Opcode::Bnot => Some("bnot"),
+        Opcode::And | Opcode::AndAlso => Some("and"),
+        Opcode::Or | Opcode::OrElse => Some("or"),
+        Opcode::Xor => Some("xor"),
+        Opcode::Band => Some("band"),
+        Opcode::Bor => Some("bor"),
+        Opcode::Bxor => Some("bxor"),
+        Opcode::Bsl => Some("bsl"),
+        Opcode::Bsr => Some("bsr"),
+        Opcode::ListConcat => Some("++"),
+        Opcode::ListSubtract => Some("--"),
+        _ => None,
+    }
+}
+
+fn constant_term(item: &ConstantItem) -> etf::Term {
+    match item {
+        ConstantItem::Integer(i) => integer_term(i),
+        ConstantItem::Float(f) => etf::Float::from(*f).into(),
+        ConstantItem::Bool(value) => etf::Atom::from(if *value { "true" } else { "false" }).into(),
+        ConstantItem::Atom(atom) => etf::Atom::from(atom.as_str().get()).into(),
+        ConstantItem::Bytes(bytes) => etf::Binary::from(bytes.as_slice()).into(),
+        ConstantItem::Bitstring(bits) => bitstring_term(bits),
+        ConstantItem::String(s) => etf::Binary::from(s.as_bytes()).into(),
+        ConstantItem::InternedStr(s) => etf::Binary::from(s.as_str().get().as_bytes()).into(),
+        ConstantItem::Term(lit) => lit_term(lit),
+    }
+}
+
+fn lit_term(lit: &Lit) -> etf::Term {
+    match lit {
+        Lit::Atom(atom) => etf::Atom::from(atom.as_str().get()).into(),
+        Lit::Integer(i) => integer_term(i),
+        Lit::Float(f) => etf::Float::from(f.inner()).into(),
+        Lit::Nil => etf::List::nil().into(),
+        Lit::Cons(head, tail) => {
+            let mut elements = vec![lit_term(&head.value)];
+            let mut tail = &tail.value;
+            while let Lit::Cons(head, rest) = tail {
+                elements.push(lit_term(&head.value));
+                tail = &rest.value;
+            }
+            match tail {
+                Lit::Nil => etf::List::from(elements).into(),
+                tail => etf::ImproperList::from((elements, lit_term(tail))).into(),
+            }
+        }
+        Lit::Tuple(elements) => {
+            let elements = elements.iter().map(|e| lit_term(&e.value)).collect();
+            etf::Tuple::from(elements).into()
+        }
+        Lit::Map(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(k, v)| (lit_term(&k.value), lit_term(&v.value)))
+                .collect::<Vec<_>>();
+            etf::Map::from(entries).into()
+        }
+        Lit::Binary(bits) => bitstring_term(bits),
+    }
+}
+
+fn integer_term(i: &Integer) -> etf::Term {
+    match i {
+        Integer::Small(i) => match i32::try_from(*i) {
+            Ok(i) => etf::FixInteger::from(i).into(),
+            Err(_) => etf::BigInteger::from(*i).into(),
+        },
+        Integer::Big(i) => etf::BigInteger::from_signed_bytes_be(&i.to_signed_bytes_be()).into(),
+    }
+}
+
+fn bitstring_term(bits: &BitVec) -> etf::Term {
+    let mut bytes = unsafe { bits.as_bytes_unchecked() }.to_vec();
+    let tail_bits = (bits.bit_size() % 8) as u8;
+    if tail_bits == 0 {
+        return etf::Binary::from(bytes).into();
+    }
+    // The trailing bits are the high bits of the last byte, but are encoded as the low bits
+    if let Some(last) = bytes.last_mut() {
+        *last >>= 8 - tail_bits;
+    }
+    etf::BitBinary::from((bytes, tail_bits)).into()
+}
@@ -0,0 +1,282 @@
+mod function;
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use log::debug;
+
+use firefly_beam::beam::compact::{self, opcodes, Operation};
+use firefly_beam::beam::{self as beam, StandardBeamFile, StandardChunk};
+use firefly_beam::serialization::etf;
+use firefly_intern::Symbol;
+use firefly_syntax_ssa::{self as syntax_ssa, ir::instructions::*, FuncRef};
+
+use self::function::FunctionBuilder;
+
+/// The labels and arity of a function defined in the module being built
+#[derive(Debug, Copy, Clone)]
+struct Definition {
+    name: Symbol,
+    /// The arity of the function in BEAM, which differs from its signature for closures
+    arity: u32,
+    /// The label of the `func_info` instruction
+    info: u32,
+    /// The label called to enter the function
+    entry: u32,
+    /// For closures, the number of free variables it receives after its arguments
+    num_free: Option<u32>,
+}
+
+/// This builder holds the state necessary to build a BEAM module
+/// from an SSA IR module.
+///
+/// It maintains the tables which are written alongside the code, i.e. atoms, imports,
+/// literals and lambdas, and allocates the labels used by the code of each function.
+pub struct ModuleBuilder<'m> {
+    module: &'m syntax_ssa::Module,
+    // The atom table, the id of an atom is its index + 1, as 0 represents `[]`
+    atoms: Vec<Symbol>,
+    atom_ids: HashMap<Symbol, u32>,
+    imports: Vec<beam::Import>,
+    import_ids: HashMap<(Symbol, Symbol, u32), u32>,
+    // The literal table, each literal is encoded in the external term format
+    literals: Vec<Vec<u8>>,
+    literal_ids: HashMap<Vec<u8>, u32>,
+    lambdas: Vec<beam::Function>,
+    lambda_ids: HashMap<FuncRef, u32>,
+    // The functions defined in this module, in the order they are emitted
+    definitions: Vec<(FuncRef, Definition)>,
+    definition_ids: HashMap<FuncRef, usize>,
+    next_label: u32,
+    code: Vec<Operation>,
+}
+impl<'m> ModuleBuilder<'m> {
+    pub fn new(module: &'m syntax_ssa::Module) -> Self {
+        Self {
+            module,
+            atoms: vec![],
+            atom_ids: HashMap::new(),
+            imports: vec![],
+            import_ids: HashMap::new(),
+            literals: vec![],
+            literal_ids: HashMap::new(),
+            lambdas: vec![],
+            lambda_ids: HashMap::new(),
+            definitions: vec![],
+            definition_ids: HashMap::new(),
+            // Label 0 means "no label" in BEAM
+            next_label: 1,
+            code: vec![],
+        }
+    }
+
+    /// Builds the module, returning the contents of its `.beam` file
+    pub fn build(mut self) -> anyhow::Result<StandardBeamFile> {
+        let module = self.module;
+        // The module name must always be the first atom
+        self.atom(module.name());
+        self.declare_functions();
+
+        for function in module.functions.iter() {
+            debug!("building beam code for {}", function.signature.mfa());
+            FunctionBuilder::new(&mut self, function).build()?;
+        }
+
+        self.finish()
+    }
+
+    /// Assigns labels to every function, so that calls can be built before their callee
+    fn declare_functions(&mut self) {
+        // Closures receive their free variables in place of the closure itself, so the arity of
+        // a closure in BEAM depends on the size of its environment, which is only known where
+        // the closure is constructed
+        let mut env_sizes: HashMap<FuncRef, u32> = HashMap::new();
+        for function in self.module.functions.iter() {
+            let dfg = &function.dfg;
+            for (block, _) in dfg.blocks() {
+                for inst in dfg.block_insts(block) {
+                    if let InstData::MakeFun(MakeFun { callee, env }) = &**dfg.insts[inst] {
+                        let size = env_sizes.entry(*callee).or_default();
+                        *size = (*size).max(env.len(&dfg.value_lists) as u32);
+                    }
+                }
+            }
+        }
+
+        for function in self.module.functions.iter() {
+            let signature = &function.signature;
+            let arity = signature.arity() as u32;
+            let num_free = if self.module.is_closure(&signature.mfa().to_local()) {
+                Some(env_sizes.get(&function.id).copied().unwrap_or(0))
+            } else {
+                None
+            };
+            let definition = Definition {
+                name: signature.name,
+                arity: num_free.map(|n| arity - 1 + n).unwrap_or(arity),
+                info: self.next_label(),
+                entry: self.next_label(),
+                num_free,
+            };
+            self.definition_ids
+                .insert(function.id, self.definitions.len());
+            self.definitions.push((function.id, definition));
+        }
+    }
+
+    fn definition(&self, id: FuncRef) -> Option<Definition> {
+        self.definition_ids
+            .get(&id)
+            .map(|index| self.definitions[*index].1)
+    }
+
+    fn next_label(&mut self) -> u32 {
+        let label = self.next_label;
+        self.next_label += 1;
+        label
+    }
+
+    /// Returns the id of the given atom in the atom table
+    fn atom(&mut self, name: Symbol) -> u32 {
+        if let Some(id) = self.atom_ids.get(&name) {
+            return *id;
+        }
+        self.atoms.push(name);
+        let id = self.atoms.len() as u32;
+        self.atom_ids.insert(name, id);
+        id
+    }
+
+    /// Returns the index of the given function in the import table
+    fn import(&mut self, module: Symbol, function: Symbol, arity: u32) -> u32 {
+        let key = (module, function, arity);
+        if let Some(index) = self.import_ids.get(&key) {
+            return *index;
+        }
+        let import = beam::Import {
+            module: self.atom(module),
+            function: self.atom(function),
+            arity,
+        };
+        let index = self.imports.len() as u32;
+        self.imports.push(import);
+        self.import_ids.insert(key, index);
+        index
+    }
+
+    /// Returns the index of the given term in the literal table
+    fn literal(&mut self, term: etf::Term) -> anyhow::Result<u32> {
+        let mut encoded = vec![];
+        term.encode(&mut encoded)
+            .map_err(|err| anyhow!("unable to encode literal {}: {}", term, err))?;
+        if let Some(index) = self.literal_ids.get(&encoded) {
+            return Ok(*index);
+        }
+        let index = self.literals.len() as u32;
+        self.literals.push(encoded.clone());
+        self.literal_ids.insert(encoded, index);
+        Ok(index)
+    }
+
+    /// Returns the index of the given function in the lambda table
+    fn lambda(&mut self, callee: FuncRef) -> anyhow::Result<u32> {
+        if let Some(index) = self.lambda_ids.get(&callee) {
+            return Ok(*index);
+        }
+        let definition = self
+            .definition(callee)
+            .ok_or_else(|| anyhow!("cannot make a fun from a function which is not defined"))?;
+        let index = self.lambdas.len() as u32;
+        let lambda = beam::Function {
+            function: self.atom(definition.name),
+            arity: definition.arity,
+            label: definition.entry,
+            index,
+            num_free: definition.num_free.unwrap_or(0),
+            old_uniq: 0,
+        };
+        self.lambdas.push(lambda);
+        self.lambda_ids.insert(callee, index);
+        Ok(index)
+    }
+
+    fn finish(mut self) -> anyhow::Result<StandardBeamFile> {
+        self.code.push(Operation {
+            opcode: opcodes::INT_CODE_END,
+            operands: vec![],
+        });
+        let mut bytecode = vec![];
+        let mut opcode_max = 0;
+        for operation in self.code.iter() {
+            opcode_max = opcode_max.max(operation.opcode as u32);
+            compact::write_operation(&mut bytecode, operation)?;
+        }
+
+        let mut exports = vec![];
+        let mut locals = vec![];
+        let module = self.module;
+        let definitions = self.definitions.clone();
+        for (function, (_, definition)) in module.functions.iter().zip(definitions.iter()) {
+            let name = self.atom(definition.name);
+            if function.signature.visibility.is_public() {
+                exports.push(beam::Export {
+                    function: name,
+                    arity: definition.arity,
+                    label: definition.entry,
+                });
+            } else {
+                locals.push(beam::Local {
+                    function: name,
+                    arity: definition.arity,
+                    label: definition.entry,
+                });
+            }
+        }
+
+        // Neither attributes nor compile options are recorded, both chunks are expected by
+        // `Module:module_info/1` however, so they are written as empty lists
+        let mut empty_list = vec![];
+        etf::Term::from(etf::List::nil())
+            .encode(&mut empty_list)
+            .map_err(|err| anyhow!("{}", err))?;
+
+        let mut file = StandardBeamFile::new();
+        file.push_chunk(StandardChunk::Atom(beam::AtomChunk {
+            is_unicode: true,
+            atoms: self
+                .atoms
+                .iter()
+                .map(|atom| beam::Atom {
+                    name: atom.as_str().get().to_string(),
+                })
+                .collect(),
+        }));
+        file.push_chunk(StandardChunk::Code(beam::CodeChunk {
+            info_size: 16,
+            version: 0,
+            opcode_max,
+            label_count: self.next_label,
+            function_count: self.definitions.len() as u32,
+            bytecode,
+        }));
+        file.push_chunk(StandardChunk::StrT(beam::StrTChunk { strings: vec![] }));
+        file.push_chunk(StandardChunk::ImpT(beam::ImpTChunk {
+            imports: self.imports,
+        }));
+        file.push_chunk(StandardChunk::ExpT(beam::ExpTChunk { exports }));
+        file.push_chunk(StandardChunk::FunT(beam::FunTChunk {
+            functions: self.lambdas,
+        }));
+        if !self.literals.is_empty() {
+            file.push_chunk(StandardChunk::LitT(beam::LitTChunk {
+                literals: self.literals,
+            }));
+        }
+        file.push_chunk(StandardChunk::LocT(beam::LocTChunk { locals }));
+        file.push_chunk(StandardChunk::Attr(beam::AttrChunk {
+            term: empty_list.clone(),
+        }));
+        file.push_chunk(StandardChunk::CInf(beam::CInfChunk { term: empty_list }));
+        Ok(file)
+    }
+}
@@ -0,0 +1,57 @@
+mod builder;
+
+use self::builder::*;
+
+use firefly_beam::beam::StandardBeamFile;
+use firefly_intern::Symbol;
+use firefly_pass::Pass;
+use firefly_syntax_ssa as syntax_ssa;
+use firefly_util::emit::Emit;
+use log::debug;
+
+/// A module compiled to BEAM bytecode, ready to be written as a `.beam` file
+pub struct BeamModule {
+    name: Symbol,
+    file: StandardBeamFile,
+}
+impl BeamModule {
+    /// Returns the name of the module
+    pub fn name(&self) -> Symbol {
+        self.name
+    }
+}
+impl Emit for BeamModule {
+    fn file_type(&self) -> Option<&'static str> {
+        Some("beam")
+    }
+
+    fn emit(&self, f: &mut std::fs::File) -> anyhow::Result<()> {
+        self.file.to_writer(f)?;
+        Ok(())
+    }
+}
+
+/// Translates an SSA IR module to BEAM bytecode, which can be loaded by the Erlang VM
+///
+/// This is an alternative to lowering through MLIR and LLVM, and does not depend on
+/// our runtime. The generated code keeps every value in a `y` register, which is far
+/// from what `erlc` would produce, but is straightforward to get right.
+///
+/// NOTE: Binary construction and matching are not yet supported, and will fail with
+/// an error if they are present in the module.
+pub struct SsaToBeam;
+impl Pass for SsaToBeam {
+    type Input<'a> = &'a syntax_ssa::Module;
+    type Output<'a> = BeamModule;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        debug!("building beam module for {}", module.name());
+
+        let builder = ModuleBuilder::new(module);
+        let file = builder.build()?;
+        Ok(BeamModule {
+            name: module.name(),
+            file,
+        })
+    }
+}
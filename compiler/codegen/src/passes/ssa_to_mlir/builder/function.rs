@@ -5,7 +5,7 @@ use firefly_mlir::llvm::{ICmpPredicate, LinkageAttr};
 use firefly_mlir::*;
 use firefly_number::Integer;
 use firefly_rt::term::OpaqueTerm;
-use firefly_syntax_base::{self as syntax_base, Signature};
+use firefly_syntax_base::{self as syntax_base, Lit, Signature};
 use firefly_syntax_ssa::{self as syntax_ssa, ir::instructions::*, DataFlowGraph};
 use firefly_syntax_ssa::{ConstantItem, Immediate, ImmediateTerm};
 
@@ -260,9 +260,28 @@ impl<'m> ModuleBuilder<'m> {
             ConstantItem::InternedStr(ident) => {
                 self.bitstring_to_constant(loc, ident.as_str().get())
             }
+            ConstantItem::Term(lit) => self.literal_to_constant(loc, lit),
         }
     }
 
+    /// Constant tuples and lists are lowered to a constant op carrying the serialized term,
+    /// from which the term is emitted as read-only data during lowering to LLVM.
+    ///
+    /// See `createLiteralConstant` in the CIR to LLVM conversion for the serialization format.
+    fn literal_to_constant(&self, loc: Location, lit: &Lit) -> ValueBase {
+        let builder = CirBuilder::new(&self.builder);
+        let ty = match lit {
+            Lit::Tuple(_) => builder.get_cir_box_type(builder.get_tuple_type(&[])),
+            Lit::Cons(_, _) => builder.get_cir_box_type(builder.get_cir_cons_type()),
+            other => panic!("invalid literal term constant: {:?}", other),
+        };
+        let mut bytes = vec![];
+        encode_literal(lit, &mut bytes);
+        let attr = StringAttr::get_with_type(bytes.as_slice(), ty.base());
+        let op = builder.build_constant(loc, ty, attr);
+        op.get_result(0).base()
+    }
+
     fn bitstring_to_constant<B: ?Sized + Bitstring>(
         &self,
         loc: Location,
@@ -514,7 +533,7 @@ impl<'m> ModuleBuilder<'m> {
         let imm = self.const_to_constant(loc, &dfg.constant(op.imm));
         let results = dfg.inst_results(inst);
         let mlir_op = match op.op {
-            Opcode::ConstBigInt | Opcode::ConstBinary => {
+            Opcode::ConstBigInt | Opcode::ConstBinary | Opcode::ConstTerm => {
                 self.values.insert(dfg.first_result(inst), imm);
                 return Ok(());
             }
@@ -1626,6 +1645,62 @@ fn translate_primitive_ir_type<'a, B: OpBuilder>(
     }
 }
 
+/// Serializes a constant term in the form expected by `createLiteralConstant`
+fn encode_literal(lit: &Lit, out: &mut Vec<u8>) {
+    match lit {
+        Lit::Nil => out.push(b'n'),
+        Lit::Atom(a) => {
+            let name = a.as_str().get().as_bytes();
+            out.push(b'a');
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(name);
+        }
+        Lit::Integer(Integer::Small(i)) => {
+            out.push(b'i');
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        Lit::Float(f) => {
+            out.push(b'f');
+            out.extend_from_slice(&f.inner().to_bits().to_le_bytes());
+        }
+        Lit::Binary(bin) => {
+            assert!(bin.is_binary(), "bitstring literals must be binaries");
+            let bytes = unsafe { bin.as_bytes_unchecked() };
+            let is_utf8 = match Encoding::detect(bytes) {
+                Encoding::Utf8 => 1,
+                _ => 0,
+            };
+            out.push(b'b');
+            out.push(is_utf8);
+            out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            out.extend_from_slice(bytes);
+        }
+        Lit::Tuple(elements) => {
+            out.push(b't');
+            out.extend_from_slice(&(elements.len() as u32).to_le_bytes());
+            for element in elements.iter() {
+                encode_literal(&element.value, out);
+            }
+        }
+        Lit::Cons(_, _) => {
+            // Lists are flattened into their elements followed by the tail
+            let mut elements = vec![];
+            let mut tail = lit;
+            while let Lit::Cons(head, rest) = tail {
+                elements.push(&head.value);
+                tail = &rest.value;
+            }
+            out.push(b'l');
+            out.extend_from_slice(&(elements.len() as u32).to_le_bytes());
+            for element in elements {
+                encode_literal(element, out);
+            }
+            encode_literal(tail, out);
+        }
+        other => panic!("invalid literal term constant: {:?}", other),
+    }
+}
+
 fn translate_term_ir_type<'a, B: OpBuilder>(
     module: &syntax_ssa::Module,
     options: &Options,
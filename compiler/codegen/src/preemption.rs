@@ -0,0 +1,24 @@
+use log::debug;
+
+use firefly_llvm as llvm;
+
+/// Makes the code in the given module preemptible by the scheduler.
+///
+/// Processes are scheduled cooperatively, but Erlang code never yields explicitly, so generated
+/// code consumes a reduction on entry to every function and on every iteration of a loop, and
+/// yields to the scheduler once the current process has used up its reductions. Since all
+/// iteration in Erlang is recursion, the entry check alone bounds the time between yields in
+/// most code; the loop checks cover any loops introduced during lowering.
+///
+/// Reductions are counted in a thread-local credit owned by the runtime, so the fast path is a
+/// decrement and compare, see `firefly_rt::process::reductions`.
+pub fn insert_yield_points(module: llvm::Module) -> llvm::YieldPointStats {
+    let stats = module.insert_yield_points();
+
+    debug!(
+        "yield points: {} function entries, {} loop headers",
+        stats.entries, stats.loops
+    );
+
+    stats
+}
@@ -0,0 +1,96 @@
+use firefly_diagnostics::{CodeMap, SourceSpan};
+use firefly_llvm::{self as llvm, Align, GlobalValue, Linkage, Value, Visibility};
+use firefly_session::{DebugInfo, Options};
+use firefly_syntax_ssa::{self as syntax_ssa, Opcode};
+
+pub use firefly_rt::backtrace::spans::{SourceRange, SpanTableBuilder, SPANS_SECTION};
+
+/// Builds the span table for the given module, see `firefly_rt::backtrace::spans` for its format
+///
+/// Only instructions which can appear in a stack trace, i.e. calls and raises, are recorded.
+/// Positions use the same 1-based columns as the locations we attach to MLIR operations, so that
+/// they agree with what is recorded in debug info.
+pub fn span_table(codemap: &CodeMap, module: &syntax_ssa::Module) -> SpanTableBuilder {
+    let mut builder = SpanTableBuilder::default();
+    for function in module.functions.iter() {
+        let dfg = &function.dfg;
+        for (block, _) in dfg.blocks() {
+            for inst in dfg.block_insts(block) {
+                let inst_data = &dfg[inst];
+                match inst_data.opcode() {
+                    Opcode::Call
+                    | Opcode::Enter
+                    | Opcode::CallIndirect
+                    | Opcode::EnterIndirect
+                    | Opcode::Raise => (),
+                    _ => continue,
+                }
+                if let Some((file, range)) = source_range(codemap, inst_data.span()) {
+                    builder.push(file.as_str(), range);
+                }
+            }
+        }
+    }
+    builder
+}
+
+fn source_range(codemap: &CodeMap, span: SourceSpan) -> Option<(String, SourceRange)> {
+    if span.is_unknown() {
+        return None;
+    }
+    let source_file = codemap.get_with_span(span).ok()?;
+    let start = codemap.location_for_span(span).ok()?;
+    let end = codemap.location(span.source_id(), span.end_index()).ok()?;
+    let name = source_file.name();
+    let file = match name.as_str() {
+        Some(file) => file.to_string(),
+        None => name.to_string(),
+    };
+    let range = SourceRange {
+        start_line: start.line.number().to_usize() as u32,
+        start_column: (start.column.to_usize() + 1) as u32,
+        end_line: end.line.number().to_usize() as u32,
+        end_column: (end.column.to_usize() + 1) as u32,
+    };
+    Some((file, range))
+}
+
+/// Emits the span table for `ssa` into the given module
+///
+/// The table is only useful in combination with debug info, so nothing is emitted without it.
+pub fn emit_span_table(
+    options: &Options,
+    codemap: &CodeMap,
+    module: llvm::Module,
+    name: &str,
+    ssa: &syntax_ssa::Module,
+) {
+    if options.debug_info == DebugInfo::None {
+        return;
+    }
+    let table = span_table(codemap, ssa);
+    if table.is_empty() {
+        return;
+    }
+    let table = table.finish();
+
+    let context = module.context();
+    // NOTE: `const_string` only appends a null terminator when the data contains no null bytes,
+    // which can't happen here, as the high byte of each record's size is always zero in practice
+    assert!(table.contains(&0));
+    let data = context.const_string(table.as_slice());
+    let global_name = format!("__firefly_spans.{}", name);
+    let global = module.add_global(data.get_type(), global_name.as_str(), Some(data.base()));
+    global.set_constant(true);
+    // Like the ABI stamp, the table must be externally visible so that it isn't stripped by the
+    // optimizer, but nothing outside of the executable ever needs to reference it
+    global.set_linkage(Linkage::External);
+    global.set_visibility(Visibility::Hidden);
+    // Records are concatenated without padding
+    global.set_alignment(1);
+    if options.target.options.is_like_osx {
+        global.set_section(format!("__DATA,{}", SPANS_SECTION).as_str());
+    } else {
+        global.set_section(SPANS_SECTION);
+    }
+}
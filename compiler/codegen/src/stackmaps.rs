@@ -0,0 +1,75 @@
+use log::debug;
+
+use firefly_llvm::{self as llvm, GlobalValue, Linkage, PointerType, Visibility};
+use firefly_session::Options;
+
+pub use firefly_rt::process::stackmaps::STACK_MAPS_SECTION;
+
+/// Emits stack maps for the given module, so that the garbage collector can find the terms held
+/// by its frames, see `firefly_rt::process::stackmaps` for details.
+///
+/// This must run after optimization, as every term live across a call is moved to a stack slot,
+/// which would otherwise be promoted back to a register. The stack map itself is emitted by LLVM
+/// into its own section, so we mark the start of this module's contribution with a label, and
+/// place the address of that label in the stack maps section, where the runtime finds it.
+///
+/// Stack maps are only supported by LLVM on some targets, on others nothing is emitted, and the
+/// collector never compacts the heap, as it cannot find the roots on the stack.
+pub fn emit_stack_maps(options: &Options, module: llvm::Module, name: &str) -> llvm::StackMapStats {
+    let target = &options.target;
+    let supported = matches!(target.arch.as_ref(), "x86_64" | "aarch64")
+        && !target.options.is_like_windows
+        && !target.options.is_like_wasm;
+    if !supported {
+        return llvm::StackMapStats::default();
+    }
+
+    let stats = module.emit_stack_maps();
+    debug!(
+        "stack maps: {} safepoints, {} slots, {} pinning",
+        stats.safepoints, stats.slots, stats.pinning
+    );
+    if stats.safepoints == 0 {
+        return stats;
+    }
+
+    // The label is emitted before any code, so it precedes the stack map LLVM emits for the module
+    let label = format!("__firefly_stackmap.{}", name);
+    let asm = if target.options.is_like_osx {
+        format!(
+            concat!(
+                ".section __LLVM_STACKMAPS,__llvm_stackmaps\n",
+                ".private_extern \"_{0}\"\n\"_{0}\":\n.text\n"
+            ),
+            label
+        )
+    } else {
+        format!(
+            ".section .llvm_stackmaps,\"a\",@progbits\n.hidden \"{0}\"\n\"{0}\":\n.text\n",
+            label
+        )
+    };
+    module.append_inline_asm(&asm);
+
+    let context = module.context();
+    let i8_type = context.get_i8_type();
+    let stack_map = module.get_or_add_global(i8_type, label.as_str(), None);
+    stack_map.set_linkage(Linkage::External);
+    stack_map.set_visibility(Visibility::Hidden);
+
+    let ptr_type = PointerType::new(i8_type, 0);
+    let ref_name = format!("__firefly_stackmap_ref.{}", name);
+    let stack_map_ref = module.add_global(ptr_type, ref_name.as_str(), Some(stack_map.base()));
+    stack_map_ref.set_constant(true);
+    // See `abi::stamp_module`, the reference must not be stripped, but is only used by the runtime
+    stack_map_ref.set_linkage(Linkage::External);
+    stack_map_ref.set_visibility(Visibility::Hidden);
+    stack_map_ref.set_alignment(target.pointer_width / 8);
+    if target.options.is_like_osx {
+        stack_map_ref.set_section(format!("__DATA,{}", STACK_MAPS_SECTION).as_str());
+    } else {
+        stack_map_ref.set_section(STACK_MAPS_SECTION);
+    }
+
+    stats
+}
@@ -0,0 +1,46 @@
+use log::debug;
+
+use firefly_llvm as llvm;
+use firefly_rt::term::OpaqueTerm;
+use firefly_session::Options;
+
+/// Guarantees that calls in tail position in the given module do not grow the native stack.
+///
+/// Erlang relies on tail calls for all iteration, including mutual recursion and calls to other
+/// modules through the dispatch table, so a tail call must never leave the caller's frame behind.
+/// LLVM only guarantees this for `musttail` calls, which require the caller and callee to have
+/// identical prototypes, i.e. the same arity. A call marked `tail` is merely a hint, which the
+/// backend may or may not act on, so no call is left to it. Where the C calling convention has the
+/// caller pop the arguments it passes, a function may be called with more arguments than it takes,
+/// so a callee of lower arity is called with the caller's prototype, padded with undefined values.
+///
+/// So, a call in tail position is:
+///
+/// * `musttail`, if the callee has the same arity as the caller
+/// * `musttail` with padded arguments, if the callee has a lower arity and the target permits it
+/// * otherwise, scheduled via the runtime's last-call trampoline, see `firefly_rt::function::apply`
+///
+/// The trampoline returns a marker result to the nearest caller not in tail position, so every
+/// such call to an Erlang function checks its result for the marker and makes the scheduled call.
+pub fn guarantee_tail_calls(options: &Options, module: llvm::Module) -> llvm::TailCallStats {
+    let stats = module.guarantee_tail_calls(can_pad_args(options), OpaqueTerm::NONE.raw());
+
+    debug!(
+        "tail calls: {} musttail, {} padded, {} trampolined, {} call sites checked",
+        stats.must_tail, stats.padded, stats.trampolined, stats.checked
+    );
+
+    stats
+}
+
+/// Returns true if the C calling convention of the target has the caller pop the arguments it
+/// passes, so that a function may be called with more arguments than it takes
+///
+/// Targets without a known convention, and wasm32, which checks the signature of every call, are
+/// treated as not permitting it.
+fn can_pad_args(options: &Options) -> bool {
+    match options.target.arch.as_ref() {
+        "x86_64" | "aarch64" | "riscv64" => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,207 @@
+//! Machine-readable renderings of diagnostics, selected with `--error-format`.
+//!
+//! In both formats, the locations of labels are given as byte offsets into their source file, along
+//! with the 1-based line and column numbers they correspond to.
+//!
+//! * `json` renders each diagnostic as a single-line JSON object as soon as it is reported
+//! * `sarif` renders all of the diagnostics reported during a compilation as a single [SARIF 2.1.0]
+//! log once it is finished, which is the format expected by e.g. GitHub code scanning
+//!
+//! [SARIF 2.1.0]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::{CodeMap, Diagnostic, Label, LabelStyle, Severity};
+
+/// The format in which diagnostics are rendered
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Rendered for people, with source snippets
+    Human,
+    /// Rendered as one JSON object per diagnostic
+    Json,
+    /// Rendered as a SARIF log
+    Sarif,
+}
+impl Default for ErrorFormat {
+    fn default() -> Self {
+        Self::Human
+    }
+}
+impl ErrorFormat {
+    pub const VARIANTS: &'static [&'static str] = &["human", "json", "sarif"];
+}
+impl FromStr for ErrorFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            _ => Err(()),
+        }
+    }
+}
+impl fmt::Display for ErrorFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Human => f.write_str("human"),
+            Self::Json => f.write_str("json"),
+            Self::Sarif => f.write_str("sarif"),
+        }
+    }
+}
+
+/// Renders `diagnostic` as a JSON object
+pub fn to_json(codemap: &CodeMap, diagnostic: &Diagnostic) -> Value {
+    let spans = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            let region = Region::new(codemap, label);
+            json!({
+                "file": region.file,
+                "byte_start": region.byte_start,
+                "byte_end": region.byte_end,
+                "line_start": region.line_start,
+                "column_start": region.column_start,
+                "line_end": region.line_end,
+                "column_end": region.column_end,
+                "is_primary": label.style == LabelStyle::Primary,
+                "label": non_empty(&label.message),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "message": diagnostic.message,
+        "code": diagnostic.code,
+        "severity": severity_name(diagnostic.severity),
+        "spans": spans,
+        "notes": diagnostic.notes,
+    })
+}
+
+/// Renders `diagnostics` as a SARIF log of a single run of the compiler
+pub fn to_sarif(codemap: &CodeMap, diagnostics: &[Diagnostic]) -> Value {
+    let results = diagnostics
+        .iter()
+        .map(|diagnostic| to_sarif_result(codemap, diagnostic))
+        .collect::<Vec<_>>();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "firefly",
+                    "informationUri": "https://github.com/GetFirefly/firefly",
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// Renders `diagnostic` as the result object of a SARIF log
+fn to_sarif_result(codemap: &CodeMap, diagnostic: &Diagnostic) -> Value {
+    let location = |label: &Label| {
+        let region = Region::new(codemap, label);
+        json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": region.file },
+                "region": {
+                    "byteOffset": region.byte_start,
+                    "byteLength": region.byte_end - region.byte_start,
+                    "startLine": region.line_start,
+                    "startColumn": region.column_start,
+                    "endLine": region.line_end,
+                    "endColumn": region.column_end,
+                },
+            },
+            "message": { "text": label.message },
+        })
+    };
+    let (primary, secondary): (Vec<&Label>, Vec<&Label>) = diagnostic
+        .labels
+        .iter()
+        .partition(|label| label.style == LabelStyle::Primary);
+    let level = match diagnostic.severity {
+        Severity::Bug | Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note | Severity::Help => "note",
+    };
+    let mut text = diagnostic.message.clone();
+    for note in diagnostic.notes.iter() {
+        text.push('\n');
+        text.push_str(note);
+    }
+    let mut result = json!({
+        "level": level,
+        "message": { "text": text },
+        "locations": primary.into_iter().map(location).collect::<Vec<_>>(),
+        "relatedLocations": secondary.into_iter().map(location).collect::<Vec<_>>(),
+    });
+    if let Some(code) = diagnostic.code.as_ref() {
+        result["ruleId"] = json!(code);
+    }
+    result
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+fn non_empty(message: &str) -> Option<&str> {
+    if message.is_empty() {
+        None
+    } else {
+        Some(message)
+    }
+}
+
+/// The location of a label in its source file
+struct Region {
+    file: String,
+    byte_start: usize,
+    byte_end: usize,
+    line_start: usize,
+    column_start: usize,
+    line_end: usize,
+    column_end: usize,
+}
+impl Region {
+    fn new(codemap: &CodeMap, label: &Label) -> Self {
+        let file = codemap
+            .name(label.file_id)
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+        let (line_start, column_start) = line_column(codemap, label, label.range.start);
+        let (line_end, column_end) = line_column(codemap, label, label.range.end);
+        Self {
+            file,
+            byte_start: label.range.start,
+            byte_end: label.range.end,
+            line_start,
+            column_start,
+            line_end,
+            column_end,
+        }
+    }
+}
+
+/// Returns the 1-based line and column of `index` in the source file of `label`
+fn line_column(codemap: &CodeMap, label: &Label, index: usize) -> (usize, usize) {
+    match codemap.location(label.file_id, index as u32) {
+        Ok(location) => (location.line.to_usize() + 1, location.column.to_usize() + 1),
+        Err(_) => (0, 0),
+    }
+}
@@ -1,5 +1,6 @@
 mod codemap;
 mod filename;
+pub mod format;
 mod index;
 mod source;
 mod span;
@@ -19,6 +20,7 @@ pub use firefly_diagnostics_macros::*;
 
 pub use self::codemap::CodeMap;
 pub use self::filename::FileName;
+pub use self::format::ErrorFormat;
 pub use self::index::SourceIndex;
 pub use self::source::{SourceFile, SourceId};
 pub use self::span::{SourceSpan, Span, Spanned};
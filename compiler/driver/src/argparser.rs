@@ -5,13 +5,26 @@ use clap::{App, AppSettings, Arg, ArgMatches};
 
 use firefly_session::{CodegenOptions, DebuggingOptions, OptionGroup, OutputType};
 use firefly_target::Target;
-use firefly_util::diagnostics::ColorArg;
+use firefly_util::diagnostics::{ColorArg, ErrorFormat};
 
 /// Parses the provided arguments
 pub fn parse<'a>(args: impl Iterator<Item = OsString>) -> clap::Result<ArgMatches<'a>> {
     parser().get_matches_from_safe(args)
 }
 
+/// Returns true if the arguments are `--version` (or `-V`) and `--verbose` (or `-v`), in either
+/// order, and nothing else
+pub fn is_verbose_version(args: &[OsString]) -> bool {
+    let flags = args
+        .iter()
+        .skip(1)
+        .map(|arg| arg.to_str())
+        .collect::<Vec<_>>();
+    let is_version = |flag: &Option<&str>| matches!(flag, Some("--version" | "-V"));
+    let is_verbose = |flag: &Option<&str>| matches!(flag, Some("--verbose" | "-v"));
+    flags.len() == 2 && flags.iter().any(is_version) && flags.iter().any(is_verbose)
+}
+
 pub fn parser<'a, 'b>() -> App<'a, 'b> {
     App::new("firefly")
         .version(crate::FIREFLY_RELEASE)
@@ -32,6 +45,15 @@ pub fn parser<'a, 'b>() -> App<'a, 'b> {
         )
         .subcommand(print_command())
         .subcommand(compile_command())
+        .subcommand(build_command())
+        .subcommand(deps_command())
+        .subcommand(shell_command())
+        .subcommand(lsp_command())
+        .subcommand(run_command())
+        .subcommand(bench_command())
+        .subcommand(test_command())
+        .subcommand(stubs_command())
+        .subcommand(trace_dump_command())
 }
 
 /// Prints help for the given command
@@ -39,6 +61,15 @@ pub fn command_help(command: &str) {
     match command {
         "print" => print_command().print_help().unwrap(),
         "compile" => compile_command().print_help().unwrap(),
+        "build" => build_command().print_help().unwrap(),
+        "deps" => deps_command().print_help().unwrap(),
+        "shell" => shell_command().print_help().unwrap(),
+        "lsp" => lsp_command().print_help().unwrap(),
+        "run" => run_command().print_help().unwrap(),
+        "bench" => bench_command().print_help().unwrap(),
+        "test" => test_command().print_help().unwrap(),
+        "stubs" => stubs_command().print_help().unwrap(),
+        "trace-dump" => trace_dump_command().print_help().unwrap(),
         other => {
             eprintln!("Help unavailable for '{}' command!", other);
         }
@@ -88,6 +119,8 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .help(
                     "Path(s) to the source file(s) or director(y|ies) to compile.\n\
                      You may also use `-` as a file name to read a file from stdin.\n\
+                     Archives (.ez, .zip, .tar, .tar.gz) containing an application, such as\n\
+                     Hex packages, are unpacked and their sources compiled.\n\
                      If not provided, the compiler will treat the current working directory\n\
                      as the root of a standard Erlang project, using sources from <cwd>/src.",
                 )
@@ -126,6 +159,44 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .conflicts_with("app-type")
                 .conflicts_with("app-version")
         )
+        .arg(
+            Arg::with_name("config")
+                .help(
+                    "Path to a sys.config-style file, whose configuration is bundled into the\n\
+                     executable and applied at boot, before any application is started",
+                )
+                .next_line_help(true)
+                .long("config")
+                .takes_value(true)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("boot-app")
+                .help(
+                    "Start the given application (and its dependencies) at boot, before init:boot/1.\n\
+                     The optional TYPE can be one of: permanent (default), transient, or temporary.\n\
+                     The application being built is started automatically if it has a callback module",
+                )
+                .next_line_help(true)
+                .long("boot-app")
+                .takes_value(true)
+                .value_name("NAME[=TYPE]")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("app-resource")
+                .help(
+                    "Path to the resource file (.app/.app.src) of another application, e.g. a dependency,\n\
+                     to bundle into the executable, so that it can be started at boot",
+                )
+                .next_line_help(true)
+                .long("app-resource")
+                .takes_value(true)
+                .value_name("PATH")
+                .multiple(true)
+                .number_of_values(1),
+        )
         .arg(
             Arg::with_name("output")
                 .help("Write output to the given filename")
@@ -152,7 +223,13 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
         .arg(
             target
                 .clone()
-                .help("The target triple to compile against (e.g. x86_64-linux-gnu)"),
+                .help(
+                    "The target triple to compile against (e.g. aarch64-unknown-linux-musl).\n\
+                     When cross-compiling, a linker for the target is looked for in PATH,\n\
+                     e.g. aarch64-linux-musl-gcc or clang, unless one is given with -C linker,\n\
+                     and the target's libc can be given with -C target-sysroot",
+                )
+                .next_line_help(true),
         )
         .arg(
             Arg::with_name("color")
@@ -161,6 +238,20 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .possible_values(ColorArg::VARIANTS)
                 .case_insensitive(true)
         )
+        .arg(
+            Arg::with_name("error-format")
+                .help(
+                    "The format in which diagnostics are rendered.\n\
+                     human = for people, with source snippets (default)\n\
+                     json  = one JSON object per diagnostic, with byte spans\n\
+                     sarif = a SARIF 2.1.0 log of all diagnostics, once compilation finishes",
+                )
+                .next_line_help(true)
+                .long("error-format")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(ErrorFormat::VARIANTS),
+        )
         .arg(
             Arg::with_name("source-map-prefix")
                 .help("Remap source paths in all output (i.e. FROM/foo => TO/foo)")
@@ -195,6 +286,15 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .value_name("LEVEL")
                 .default_value("all"),
         )
+        .arg(
+            Arg::with_name("analyze")
+                .help(
+                    "Check functions against their -spec attributes, warning about calls and\n\
+                     returns which definitely violate them, and clauses which can never match",
+                )
+                .next_line_help(true)
+                .long("analyze"),
+        )
         .arg(
             Arg::with_name("verbose")
                 .help("Set verbosity level")
@@ -230,6 +330,20 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .number_of_values(1),
         )
+        .arg(
+            Arg::with_name("link-arg")
+                .help(
+                    "Pass ARG to the linker as-is, may be given multiple times.\n\
+                     Equivalent to `-C linker-arg=ARG`",
+                )
+                .next_line_help(true)
+                .long("link-arg")
+                .takes_value(true)
+                .value_name("ARG")
+                .multiple(true)
+                .number_of_values(1)
+                .allow_hyphen_values(true),
+        )
         .arg(
             Arg::with_name("include-paths")
                 .help("Add a path to the Erlang include path.")
@@ -250,6 +364,361 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .require_delimiter(true),
         )
+        .arg(
+            Arg::with_name("save-temps")
+                .help(
+                    "Keep all intermediate files produced for each input, i.e. MLIR, LLVM IR,\n\
+                     LLVM bitcode, assembly and objects, as well as the linker's temporaries",
+                )
+                .next_line_help(true)
+                .long("save-temps"),
+        )
+        .arg(
+            Arg::with_name("static")
+                .help(
+                    "Link a fully static executable, which loads no shared libraries.\n\
+                     The runtime, libc and native libraries are all linked statically,\n\
+                     on Linux this links against musl rather than glibc",
+                )
+                .next_line_help(true)
+                .long("static"),
+        )
+}
+
+fn build_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("build")
+        .about("Compiles the rebar3 project in the current directory, and its dependencies, to an executable")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("profile")
+                .help("The rebar3 profile whose options are merged into those of the project")
+                .long("profile")
+                .takes_value(true)
+                .value_name("PROFILE")
+                .default_value("default"),
+        )
+        .arg(
+            Arg::with_name("args")
+                .help("Arguments passed to `firefly compile`, e.g. --emit or -O")
+                .index(1)
+                .multiple(true)
+                .allow_hyphen_values(true)
+                .value_name("ARGS"),
+        )
+}
+
+fn deps_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("deps")
+        .about("Fetches the Hex packages listed in firefly.toml, and records their checksums in firefly.lock")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("locked")
+                .help("Fail if firefly.lock is missing a dependency or would be changed")
+                .long("locked"),
+        )
+        .arg(
+            Arg::with_name("repo")
+                .help("The URL of the Hex repository to fetch packages from. Defaults to $HEX_MIRROR, or https://repo.hex.pm")
+                .long("repo")
+                .takes_value(true)
+                .value_name("URL"),
+        )
+}
+
+fn shell_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("shell")
+        .about("Starts an interactive shell for evaluating Erlang expressions")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("eval")
+                .help("Evaluate the given expression(s), print the result, and exit")
+                .short("e")
+                .long("eval")
+                .takes_value(true)
+                .value_name("EXPRS"),
+        )
+        .arg(
+            Arg::with_name("records")
+                .help("Load record definitions from the given file, as if by rr/1")
+                .long("records")
+                .takes_value(true)
+                .value_name("PATH")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("include-paths")
+                .help("Add a path to the Erlang include path.")
+                .long("include")
+                .short("I")
+                .value_name("PATH")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("history")
+                .help("The number of previous results kept for use with v/1")
+                .long("history")
+                .takes_value(true)
+                .value_name("N")
+                .default_value("20"),
+        )
+}
+
+fn lsp_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("lsp")
+        .about("Starts a Language Server Protocol server for Erlang sources over stdin/stdout")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("include-paths")
+                .help("Add a path to the Erlang include path.")
+                .long("include")
+                .short("I")
+                .value_name("PATH")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+}
+
+fn run_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("run")
+        .about("Compiles and runs an escript, passing the remaining arguments to its main/1")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("include-paths")
+                .help("Add a path to the Erlang include path.")
+                .long("include")
+                .short("I")
+                .value_name("PATH")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("keep-temps")
+                .help("Keep the compiled script and intermediate build artifacts")
+                .long("keep-temps"),
+        )
+        .arg(
+            Arg::with_name("script")
+                .help("Path to the escript to run")
+                .index(1)
+                .required(true)
+                .value_name("SCRIPT"),
+        )
+        .arg(
+            Arg::with_name("args")
+                .help("Arguments passed to the script's main/1")
+                .index(2)
+                .multiple(true)
+                .allow_hyphen_values(true)
+                .value_name("ARGS"),
+        )
+}
+
+fn bench_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("bench")
+        .about("Runs the standard benchmarks of the runtime, reporting latency and throughput")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("iterations")
+                .help("The number of times each benchmark is measured")
+                .long("iterations")
+                .short("n")
+                .takes_value(true)
+                .value_name("N")
+                .default_value("20"),
+        )
+        .arg(
+            Arg::with_name("warmup")
+                .help("The number of times each benchmark is run before it is measured")
+                .long("warmup")
+                .takes_value(true)
+                .value_name("N")
+                .default_value("3"),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .help("Multiplies the size of every benchmark, e.g. the number of messages sent")
+                .long("scale")
+                .takes_value(true)
+                .value_name("N")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("keep-temps")
+                .help("Keep the compiled benchmarks and intermediate build artifacts")
+                .long("keep-temps"),
+        )
+        .arg(
+            Arg::with_name("benchmarks")
+                .help("The benchmarks to run, all of them by default")
+                .index(1)
+                .multiple(true)
+                .possible_values(&["ring", "chameneos", "big_bang", "binary"])
+                .value_name("BENCHMARK"),
+        )
+        .arg(
+            Arg::with_name("runtime-args")
+                .help("Flags passed to the runtime, e.g. +Mlimit 512M, following --")
+                .last(true)
+                .multiple(true)
+                .allow_hyphen_values(true)
+                .value_name("FLAGS"),
+        )
+}
+
+fn test_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("test")
+        .about("Compiles and runs the EUnit tests and Common Test suites in the given paths")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("include-paths")
+                .help("Add a path to the Erlang include path.")
+                .long("include")
+                .short("I")
+                .value_name("PATH")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .help("Seconds each test may run for, unless its generator gives another timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .default_value("5"),
+        )
+        .arg(
+            Arg::with_name("junit")
+                .help("Also write the results to the given path as a JUnit XML report")
+                .long("junit")
+                .takes_value(true)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("results-dir")
+                .help("Where the private directories of test cases and the results of suites go")
+                .long("results-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .default_value("_build/test/results"),
+        )
+        .arg(
+            Arg::with_name("sim-seed")
+                .help("Run the tests in a deterministic simulation seeded with SEED")
+                .long("sim-seed")
+                .takes_value(true)
+                .value_name("SEED"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .help("Report every test, not only those which failed")
+                .long("verbose")
+                .short("v"),
+        )
+        .arg(
+            Arg::with_name("keep-temps")
+                .help("Keep the compiled tests and intermediate build artifacts")
+                .long("keep-temps"),
+        )
+        .arg(
+            Arg::with_name("paths")
+                .help("The sources, or directories of sources, to compile, src and test by default")
+                .index(1)
+                .multiple(true)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("runtime-args")
+                .help("Flags passed to the runtime, e.g. +Mlimit 512M, following --")
+                .last(true)
+                .multiple(true)
+                .allow_hyphen_values(true)
+                .value_name("FLAGS"),
+        )
+}
+
+fn stubs_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("stubs")
+        .about("Generates stub modules from the BEAM files of an OTP application, for incremental porting")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("output")
+                .help("The directory to write the stub modules to")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("DIR")
+                .default_value("stubs"),
+        )
+        .arg(
+            Arg::with_name("otp-root")
+                .help("The root of the OTP installation to find applications in. Defaults to that of erl in the PATH")
+                .long("otp-root")
+                .takes_value(true)
+                .value_name("DIR"),
+        )
+        .arg(
+            Arg::with_name("native")
+                .help("A function with a native replacement, which is declared as a NIF rather than raising notsup")
+                .long("native")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("MODULE:FUNCTION/ARITY"),
+        )
+        .arg(
+            Arg::with_name("force")
+                .help("Replace stub modules which already exist in the output directory")
+                .long("force"),
+        )
+        .arg(
+            Arg::with_name("inputs")
+                .help("The applications, directories or BEAM files to generate stubs for")
+                .index(1)
+                .multiple(true)
+                .required(true)
+                .value_name("INPUT"),
+        )
+}
+
+fn trace_dump_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("trace-dump")
+        .about(
+            "Prints the trace messages in a trace file written with dbg:trace_port(file, Filename)",
+        )
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("raw")
+                .help("Print each trace message as the term it is, rather than as dbg prints it")
+                .long("raw"),
+        )
+        .arg(
+            Arg::with_name("summary")
+                .help("Print the number of trace messages by event, process and called function")
+                .long("summary")
+                .conflicts_with("raw"),
+        )
+        .arg(
+            Arg::with_name("pid")
+                .help("Only print the trace messages of the given process")
+                .long("pid")
+                .takes_value(true)
+                .value_name("PID"),
+        )
+        .arg(
+            Arg::with_name("file")
+                .help("The trace file to read")
+                .index(1)
+                .required(true)
+                .value_name("FILE"),
+        )
 }
 
 fn target_arg<'a, 'b>() -> Arg<'a, 'b> {
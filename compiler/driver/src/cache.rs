@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use log::debug;
+use sha2::{Digest, Sha256};
+
+use firefly_diagnostics::{CodeMap, SourceId};
+use firefly_intern::Symbol;
+use firefly_session::{Input, InputType, Options, OutputType};
+
+use crate::diagnostics::CompilerDiagnostics;
+use crate::interner::InternedInput;
+use crate::parser::prelude::Parser;
+use crate::parser::APP_SPECS_MODULE;
+
+/// The per-module artifacts which are cached, i.e. those produced after the frontend, which still
+/// runs for modules whose artifacts are reused
+///
+/// The cost report is not among them, as producing it reports diagnostics.
+const CACHED: &[OutputType] = &[
+    OutputType::Kernel,
+    OutputType::SSA,
+    OutputType::Beam,
+    OutputType::MLIR,
+    OutputType::LLVMAssembly,
+    OutputType::LLVMBitcode,
+    OutputType::Assembly,
+    OutputType::Object,
+];
+
+/// Returns the key under which the artifacts of `input` are cached, or None if they must always be
+/// compiled
+///
+/// Artifacts are cached in `<output_dir>/cache/<key>`, keyed by a hash of everything the code of a
+/// module depends on, and of which artifacts are produced for it:
+///
+/// * The compiler release, target, and codegen and debugging options
+/// * The macros defined on the command line
+/// * The source of the module, and of every file it includes, directly or not
+/// * Which of its functions are live, when dead functions are pruned
+/// * The artifacts requested for the module, see [`artifacts`]
+///
+/// Other modules, e.g. the behaviours a module implements, only affect the diagnostics reported for
+/// it by the frontend, which still runs for modules whose artifacts are reused, so they are not a
+/// part of the key.
+pub(crate) fn key<C>(db: &C, input: InternedInput) -> Option<String>
+where
+    C: Parser,
+{
+    let options = db.options();
+    if !is_enabled(&options) || !matches!(db.input_type(input), InputType::Erlang) {
+        return None;
+    }
+    let module = db.input_ast(input).ok()?;
+    // The boot manifest embedded in this module is derived from options which are not in the key
+    if module.name() == Symbol::intern(APP_SPECS_MODULE) {
+        return None;
+    }
+
+    let artifacts = artifacts(&options, &db.lookup_intern_input(input));
+    if artifacts.is_empty() {
+        return None;
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(crate::FIREFLY_RELEASE);
+    hasher.update(crate::FIREFLY_COMMIT_HASH);
+    hasher.update(options.target.triple());
+    hasher.update(format!(
+        "{:?} {:?} {:?}",
+        options.opt_level, options.debug_info, options.debug_assertions
+    ));
+    hasher.update(format!("{:?}", options.codegen_opts));
+    hasher.update(format!("{:?}", options.debugging_opts));
+    let defines = options.defines.iter().collect::<BTreeMap<_, _>>();
+    hasher.update(format!("{:?}", defines));
+
+    let codemap = db.codemap();
+    let root = module.span.source_id();
+    let mut files = codemap
+        .iter()
+        .filter(|file| is_included_by(codemap, file.id(), root))
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| (a.name(), a.source()).cmp(&(b.name(), b.source())));
+    for file in files.iter() {
+        hasher.update(file.name().to_string());
+        hasher.update([0u8]);
+        hasher.update(file.source());
+        hasher.update([0u8]);
+    }
+
+    for (_, path) in artifacts.iter() {
+        hasher.update(path.file_name().unwrap().to_string_lossy().as_bytes());
+        hasher.update([0u8]);
+    }
+
+    if let Some(live) = db.live_functions() {
+        let name = module.name();
+        for function in live.iter().filter(|f| f.module == Some(name)) {
+            hasher.update(function.to_string());
+            hasher.update([0u8]);
+        }
+    }
+
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the artifacts requested for `input` which are cached, with the paths they are emitted
+/// to
+///
+/// The module in the LLVM dialect, emitted alongside the requested MLIR when LLVM IR is generated,
+/// is cached with it.
+pub(crate) fn artifacts(options: &Options, input: &Input) -> Vec<(OutputType, PathBuf)> {
+    let mut artifacts = vec![];
+    for output_type in CACHED.iter().copied() {
+        if let Some(path) = options.maybe_emit(input, output_type) {
+            if output_type == OutputType::MLIR && options.output_types.should_generate_llvm() {
+                artifacts.push((output_type, path.with_extension("llvm.mlir")));
+            }
+            artifacts.push((output_type, path));
+        }
+    }
+    artifacts
+}
+
+/// Returns the directory of the artifacts cached under `key`, if every artifact in `artifacts`
+/// was cached there
+pub(crate) fn lookup(
+    options: &Options,
+    key: &str,
+    artifacts: &[(OutputType, PathBuf)],
+) -> Option<PathBuf> {
+    let dir = path(options, key);
+    artifacts
+        .iter()
+        .all(|(_, artifact)| dir.join(artifact.file_name().unwrap()).is_file())
+        .then_some(dir)
+}
+
+/// Caches a copy of each of `artifacts`, which have been emitted, under `key`
+///
+/// Failing to do so only means that the module is compiled again by the next build, so errors are
+/// logged rather than reported. The artifacts are copied to a temporary directory first, so that an
+/// interrupted build never leaves an incomplete set of artifacts in the cache.
+pub(crate) fn store(options: &Options, key: &str, artifacts: &[(OutputType, PathBuf)]) {
+    let dir = path(options, key);
+    let tmp = dir.with_extension("tmp");
+    let result = fs::create_dir_all(&tmp)
+        .and_then(|_| {
+            artifacts.iter().try_for_each(|(_, artifact)| {
+                fs::copy(artifact, tmp.join(artifact.file_name().unwrap())).map(|_| ())
+            })
+        })
+        .and_then(|_| match fs::remove_dir_all(&dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => fs::rename(&tmp, &dir),
+        });
+    match result {
+        Ok(()) => debug!("cached {} artifacts as {}", artifacts.len(), dir.display()),
+        Err(err) => {
+            debug!("unable to cache artifacts as {}: {}", dir.display(), err);
+            let _ = fs::remove_dir_all(&tmp);
+        }
+    }
+}
+
+/// Artifacts are only cached when every artifact requested is either cached, produced by the
+/// frontend, or produced for the build as a whole, and when no analysis is requested which reports
+/// diagnostics after the frontend
+fn is_enabled(options: &Options) -> bool {
+    !options.codegen_opts.no_artifact_cache
+        && !options.analyze
+        && options.will_create_output_file()
+        && options.output_types.keys().any(|ty| CACHED.contains(ty))
+        && options.output_types.keys().all(|ty| {
+            CACHED.contains(ty)
+                || matches!(
+                    ty,
+                    OutputType::AST
+                        | OutputType::Abstr
+                        | OutputType::Core
+                        | OutputType::Link
+                        | OutputType::Manifest
+                        | OutputType::DepGraph
+                )
+        })
+}
+
+fn path(options: &Options, key: &str) -> PathBuf {
+    options.output_dir().join("cache").join(key)
+}
+
+/// Returns true if `id` is the file `root`, or is included by it, directly or not
+fn is_included_by(codemap: &CodeMap, mut id: SourceId, root: SourceId) -> bool {
+    loop {
+        if id == root {
+            return true;
+        }
+        match codemap.parent(id) {
+            Some(span) => id = span.source_id(),
+            None => return false,
+        }
+    }
+}
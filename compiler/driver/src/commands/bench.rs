@@ -0,0 +1,416 @@
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+
+/// A benchmark run by the `bench` command
+struct Benchmark {
+    /// The name given on the command line, and reported in samples
+    name: &'static str,
+    /// The module implementing `run/1`, which returns the number of operations performed
+    module: &'static str,
+    source: &'static str,
+    /// The size given to `run/1`, e.g. the number of servers in the ring
+    size: u64,
+    /// What the operations counted by `run/1` are, for reporting throughput
+    unit: &'static str,
+}
+
+const BENCHMARKS: &[Benchmark] = &[
+    Benchmark {
+        name: "ring",
+        module: "firefly_bench_ring",
+        source: include_str!("bench/firefly_bench_ring.erl"),
+        size: 100,
+        unit: "msgs",
+    },
+    Benchmark {
+        name: "chameneos",
+        module: "firefly_bench_chameneos",
+        source: include_str!("bench/firefly_bench_chameneos.erl"),
+        size: 10_000,
+        unit: "meetings",
+    },
+    Benchmark {
+        name: "big_bang",
+        module: "firefly_bench_big_bang",
+        source: include_str!("bench/firefly_bench_big_bang.erl"),
+        size: 100,
+        unit: "msgs",
+    },
+    Benchmark {
+        name: "binary",
+        module: "firefly_bench_binary",
+        source: include_str!("bench/firefly_bench_binary.erl"),
+        size: 10_000,
+        unit: "records",
+    },
+];
+
+/// The module which runs the benchmarks, see `firefly_bench:run/3`
+const HARNESS: &str = include_str!("bench/firefly_bench.erl");
+
+/// The main entry point for the 'bench' command
+///
+/// The selected benchmarks are compiled, with optimizations, into a single executable along with
+/// a harness which runs each of them, and an `init` module which boots the harness. The harness
+/// times every iteration with `erlang:monotonic_time/1`, and reads the reductions and context
+/// switches of the runtime over all iterations with `erlang:statistics/1`, writing these to
+/// standard error, from where they are collected into latency histograms and reported.
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<i32> {
+    let iterations = parse_count(matches, "iterations")?;
+    let warmup = parse_count(matches, "warmup")?;
+    let scale = parse_count(matches, "scale")?;
+    if iterations == 0 || scale == 0 {
+        bail!("--iterations and --scale must be greater than zero");
+    }
+    let selected: Vec<&Benchmark> = match matches.values_of("benchmarks") {
+        None => BENCHMARKS.iter().collect(),
+        Some(names) => names
+            .map(|name| {
+                BENCHMARKS
+                    .iter()
+                    .find(|bench| bench.name == name)
+                    .ok_or_else(|| anyhow!("unknown benchmark '{}'", name))
+            })
+            .collect::<anyhow::Result<_>>()?,
+    };
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("firefly-bench-")
+        .tempdir()
+        .context("unable to create temporary directory for benchmarks")?;
+    let srcdir = tempdir.path().join("src");
+    std::fs::create_dir_all(&srcdir)?;
+    let init = init_module(&selected, scale, iterations, warmup);
+    let mut sources = vec![];
+    for (module, source) in selected
+        .iter()
+        .map(|bench| (bench.module, bench.source))
+        .chain([("firefly_bench", HARNESS), ("init", init.as_str())])
+    {
+        let path = srcdir.join(format!("{}.erl", module));
+        std::fs::write(&path, source)?;
+        sources.push(path);
+    }
+
+    let exe = tempdir.path().join("firefly_bench");
+    let mut compile_args: Vec<OsString> = vec![
+        "firefly".into(),
+        "compile".into(),
+        "-O".into(),
+        "--app-name".into(),
+        "firefly_bench".into(),
+        "--app-type".into(),
+        "bin".into(),
+        "-o".into(),
+        exe.clone().into_os_string(),
+        "--output-dir".into(),
+        tempdir.path().join("_build").into_os_string(),
+    ];
+    compile_args.extend(sources.into_iter().map(|path| path.into_os_string()));
+    let code = crate::run_compiler(cwd.clone(), compile_args.into_iter())?;
+    if code != 0 {
+        return Ok(code);
+    }
+
+    let mut child = Command::new(&exe)
+        .args(matches.values_of_os("runtime-args").into_iter().flatten())
+        .current_dir(&cwd)
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("unable to execute benchmarks {}", exe.display()))?;
+    let mut results: Vec<Results> = selected.iter().map(|bench| Results::new(bench)).collect();
+    for line in BufReader::new(child.stderr.take().unwrap()).lines() {
+        let line = line?;
+        match Report::parse(&line) {
+            Some(report) => {
+                let name = report.name();
+                let results = results
+                    .iter_mut()
+                    .find(|results| results.bench.name == name)
+                    .ok_or_else(|| anyhow!("benchmarks reported unknown benchmark '{}'", name))?;
+                results.record(report);
+                if let Some(summary) = results.summary() {
+                    print!("{}", summary);
+                }
+            }
+            // Anything else written by the benchmarks, e.g. a crash report, is passed through
+            None => eprintln!("{}", line),
+        }
+    }
+    let status = child.wait()?;
+
+    if matches.is_present("keep-temps") {
+        let kept = tempdir.into_path();
+        eprintln!("benchmark build artifacts kept in {}", kept.display());
+    }
+    if !status.success() {
+        bail!("benchmarks exited with {}", status);
+    }
+    Ok(0)
+}
+
+fn parse_count<'a>(matches: &ArgMatches<'a>, name: &str) -> anyhow::Result<u64> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse::<u64>()
+        .map_err(|_| anyhow!("--{} expects a non-negative integer, got '{}'", name, value))
+}
+
+/// Generates the `init` module which the runtime boots, and which runs the benchmarks
+fn init_module(selected: &[&Benchmark], scale: u64, iterations: u64, warmup: u64) -> String {
+    let mut benchmarks = String::new();
+    for (i, bench) in selected.iter().enumerate() {
+        if i > 0 {
+            benchmarks.push_str(", ");
+        }
+        write!(
+            &mut benchmarks,
+            "{{{}, {}, {}}}",
+            bench.name,
+            bench.module,
+            bench.size * scale
+        )
+        .unwrap();
+    }
+    format!(
+        "-module(init).\n\
+         -export([boot/1]).\n\
+         \n\
+         boot(_Argv) ->\n    \
+             firefly_bench:run([{benchmarks}], {iterations}, {warmup}).\n",
+        benchmarks = benchmarks,
+        iterations = iterations,
+        warmup = warmup,
+    )
+}
+
+/// A term reported by the harness on standard error
+enum Report {
+    /// `{sample, Name, Nanoseconds, Operations}`
+    Sample {
+        name: String,
+        nanos: u64,
+        operations: u64,
+    },
+    /// `{counters, Name, Reductions, ContextSwitches}`, which follows the last sample
+    Counters {
+        name: String,
+        reductions: u64,
+        context_switches: u64,
+    },
+}
+impl Report {
+    fn parse(line: &str) -> Option<Self> {
+        let fields = line.trim().strip_prefix('{')?.strip_suffix('}')?;
+        let fields: Vec<&str> = fields.split(',').collect();
+        let (tag, name, a, b) = match fields.as_slice() {
+            [tag, name, a, b] => (*tag, *name, a.parse().ok()?, b.parse().ok()?),
+            _ => return None,
+        };
+        let name = name.to_string();
+        match tag {
+            "sample" => Some(Self::Sample {
+                name,
+                nanos: a,
+                operations: b,
+            }),
+            "counters" => Some(Self::Counters {
+                name,
+                reductions: a,
+                context_switches: b,
+            }),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Sample { name, .. } | Self::Counters { name, .. } => name.as_str(),
+        }
+    }
+}
+
+/// The results of one benchmark
+struct Results<'b> {
+    bench: &'b Benchmark,
+    latency: Histogram,
+    operations: u64,
+    counters: Option<(u64, u64)>,
+}
+impl<'b> Results<'b> {
+    fn new(bench: &'b Benchmark) -> Self {
+        Self {
+            bench,
+            latency: Histogram::new(),
+            operations: 0,
+            counters: None,
+        }
+    }
+
+    fn record(&mut self, report: Report) {
+        match report {
+            Report::Sample {
+                nanos, operations, ..
+            } => {
+                self.latency.record(nanos);
+                self.operations += operations;
+            }
+            Report::Counters {
+                reductions,
+                context_switches,
+                ..
+            } => self.counters = Some((reductions, context_switches)),
+        }
+    }
+
+    /// Returns the summary of these results, once the benchmark has finished
+    fn summary(&self) -> Option<String> {
+        let (reductions, context_switches) = self.counters?;
+        let latency = &self.latency;
+        let seconds = latency.sum() as f64 / 1e9;
+        let mut summary = String::new();
+        writeln!(
+            &mut summary,
+            "{:<12} {} iterations, {:.0} {}/s",
+            self.bench.name,
+            latency.count(),
+            self.operations as f64 / seconds,
+            self.bench.unit
+        )
+        .unwrap();
+        write!(&mut summary, "  latency   ").unwrap();
+        for (label, value) in [
+            ("min", latency.min()),
+            ("p50", latency.value_at_percentile(50.0)),
+            ("p90", latency.value_at_percentile(90.0)),
+            ("p99", latency.value_at_percentile(99.0)),
+            ("p99.9", latency.value_at_percentile(99.9)),
+            ("max", latency.max()),
+        ] {
+            write!(&mut summary, " {} {}", label, format_nanos(value)).unwrap();
+        }
+        writeln!(&mut summary).unwrap();
+        writeln!(
+            &mut summary,
+            "  counters   reductions {}, context switches {}",
+            reductions, context_switches
+        )
+        .unwrap();
+        Some(summary)
+    }
+}
+
+fn format_nanos(nanos: u64) -> String {
+    match nanos {
+        n if n >= 1_000_000_000 => format!("{:.2}s", n as f64 / 1e9),
+        n if n >= 1_000_000 => format!("{:.2}ms", n as f64 / 1e6),
+        n if n >= 1_000 => format!("{:.2}us", n as f64 / 1e3),
+        n => format!("{}ns", n),
+    }
+}
+
+/// A high dynamic range histogram, after HdrHistogram
+///
+/// Values are counted in buckets whose width grows with the magnitude of the values they hold, so
+/// that every value is recorded to within `1 / 2^(PRECISION - 1)` of itself, i.e. better than 1%,
+/// whatever its magnitude, using a few thousand buckets for the whole range of `u64`.
+struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+impl Histogram {
+    /// The number of significant bits kept of each value
+    const PRECISION: u32 = 8;
+
+    fn new() -> Self {
+        Self {
+            counts: vec![],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    fn record(&mut self, value: u64) {
+        let index = Self::index_of(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn count(&self) -> u64 {
+        self.count
+    }
+
+    fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    fn min(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+
+    fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Returns the value below which `percentile` percent of the values recorded fall, to within
+    /// the precision of the histogram
+    fn value_at_percentile(&self, percentile: f64) -> u64 {
+        let rank = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::highest_equivalent(index).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Values below `2^PRECISION` each have a bucket of their own, above that, each doubling of
+    /// magnitude is split into `2^(PRECISION - 1)` buckets
+    fn index_of(value: u64) -> usize {
+        let bits = u64::BITS - value.leading_zeros();
+        if bits <= Self::PRECISION {
+            return value as usize;
+        }
+        let shift = bits - Self::PRECISION;
+        let half = 1u64 << (Self::PRECISION - 1);
+        let sub_bucket = (value >> shift) - half;
+        ((1u64 << Self::PRECISION) + (shift as u64 - 1) * half + sub_bucket) as usize
+    }
+
+    /// Returns the largest value counted in the bucket at `index`
+    fn highest_equivalent(index: usize) -> u64 {
+        let index = index as u64;
+        let linear = 1u64 << Self::PRECISION;
+        if index < linear {
+            return index;
+        }
+        let half = 1u64 << (Self::PRECISION - 1);
+        let shift = (index - linear) / half + 1;
+        let sub_bucket = (index - linear) % half + half;
+        let highest = ((sub_bucket as u128 + 1) << shift) - 1;
+        highest.min(u64::MAX as u128) as u64
+    }
+}
@@ -0,0 +1,35 @@
+%% The harness of `firefly bench`, which runs each benchmark a number of times and reports every
+%% sample, and the counters of the runtime over the samples, as terms on standard error.
+-module(firefly_bench).
+
+-export([run/3]).
+
+%% Runs each `{Name, Module, Size}` benchmark `Warmup` times, then `Iterations` times, reporting a
+%% `{sample, Name, Nanoseconds, Operations}` for each of the latter, followed by a
+%% `{counters, Name, Reductions, ContextSwitches}`
+run([], _Iterations, _Warmup) ->
+    ok;
+run([{Name, Module, Size} | Rest], Iterations, Warmup) ->
+    warmup(Module, Size, Warmup),
+    {Reductions0, _} = erlang:statistics(reductions),
+    {Switches0, _} = erlang:statistics(context_switches),
+    measure(Name, Module, Size, Iterations),
+    {Reductions1, _} = erlang:statistics(reductions),
+    {Switches1, _} = erlang:statistics(context_switches),
+    erlang:display({counters, Name, Reductions1 - Reductions0, Switches1 - Switches0}),
+    run(Rest, Iterations, Warmup).
+
+warmup(_Module, _Size, 0) ->
+    ok;
+warmup(Module, Size, N) ->
+    Module:run(Size),
+    warmup(Module, Size, N - 1).
+
+measure(_Name, _Module, _Size, 0) ->
+    ok;
+measure(Name, Module, Size, N) ->
+    Start = erlang:monotonic_time(nanosecond),
+    Operations = Module:run(Size),
+    Stop = erlang:monotonic_time(nanosecond),
+    erlang:display({sample, Name, Stop - Start, Operations}),
+    measure(Name, Module, Size, N - 1).
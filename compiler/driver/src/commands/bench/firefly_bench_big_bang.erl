@@ -0,0 +1,38 @@
+%% Starts `Size` servers, each of which sends a message to every other.
+-module(firefly_bench_big_bang).
+
+-behaviour(gen_server).
+
+-export([run/1]).
+-export([init/1, handle_call/3, handle_cast/2]).
+
+%% Returns the number of messages sent
+run(Size) ->
+    Servers = start(Size, []),
+    [gen_server:cast(Server, {peers, peers(Server, Servers)}) || Server <- Servers],
+    [gen_server:cast(Server, bang) || Server <- Servers],
+    [gen_server:stop(Server) || Server <- Servers],
+    Size * (Size - 1).
+
+start(0, Servers) ->
+    Servers;
+start(N, Servers) ->
+    {ok, Server} = gen_server:start_link(?MODULE, [], []),
+    start(N - 1, [Server | Servers]).
+
+peers(Server, Servers) ->
+    [Peer || Peer <- Servers, Peer =/= Server].
+
+init([]) ->
+    {ok, {[], 0}}.
+
+handle_call(_Request, _From, State) ->
+    {reply, ok, State}.
+
+handle_cast({peers, Peers}, {_, Received}) ->
+    {noreply, {Peers, Received}};
+handle_cast(bang, {Peers, _} = State) ->
+    [gen_server:cast(Peer, ping) || Peer <- Peers],
+    {noreply, State};
+handle_cast(ping, {Peers, Received}) ->
+    {noreply, {Peers, Received + 1}}.
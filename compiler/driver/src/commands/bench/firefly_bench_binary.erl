@@ -0,0 +1,33 @@
+%% Builds a binary of `Size` length-prefixed records, then parses it with the bit syntax,
+%% checksumming each record.
+-module(firefly_bench_binary).
+
+-export([run/1]).
+
+%% Returns the number of records parsed
+run(Size) ->
+    Binary = build(Size, <<>>),
+    {Records, _Checksum} = parse(Binary, 0, 0),
+    Records.
+
+build(0, Acc) ->
+    Acc;
+build(N, Acc) ->
+    Length = N rem 64,
+    Payload = payload(Length, <<>>),
+    build(N - 1, <<Acc/binary, Length:16, Payload/binary>>).
+
+payload(0, Acc) ->
+    Acc;
+payload(N, Acc) ->
+    payload(N - 1, <<Acc/binary, N:8>>).
+
+parse(<<>>, Records, Checksum) ->
+    {Records, Checksum};
+parse(<<Length:16, Payload:Length/binary, Rest/binary>>, Records, Checksum) ->
+    parse(Rest, Records + 1, checksum(Payload, Checksum)).
+
+checksum(<<>>, Checksum) ->
+    Checksum;
+checksum(<<Byte, Rest/binary>>, Checksum) ->
+    checksum(Rest, (Checksum + Byte) band 16#ffff).
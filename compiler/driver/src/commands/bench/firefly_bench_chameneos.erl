@@ -0,0 +1,57 @@
+%% Chameneos-redux: creatures of three colours meet in pairs at a broker, each taking on the
+%% complement of the two colours after a meeting, until `Size` meetings have taken place.
+-module(firefly_bench_chameneos).
+
+-behaviour(gen_server).
+
+-export([run/1]).
+-export([init/1, handle_call/3, handle_cast/2]).
+
+-define(COLOURS, [blue, red, yellow, red, yellow, blue, red, yellow, red, blue]).
+
+%% Returns the number of meetings
+run(Size) ->
+    {ok, Broker} = gen_server:start_link(?MODULE, {broker, Size}, []),
+    Creatures = [start_creature(Broker, Colour) || Colour <- ?COLOURS],
+    [gen_server:cast(Creature, {start, Creature}) || Creature <- Creatures],
+    [gen_server:stop(Creature) || Creature <- Creatures],
+    gen_server:stop(Broker),
+    Size.
+
+start_creature(Broker, Colour) ->
+    {ok, Creature} = gen_server:start_link(?MODULE, {creature, Broker, Colour}, []),
+    Creature.
+
+init({broker, Meetings}) ->
+    {ok, {broker, Meetings, none}};
+init({creature, Broker, Colour}) ->
+    {ok, {creature, Broker, undefined, Colour}}.
+
+handle_call(_Request, _From, State) ->
+    {reply, ok, State}.
+
+%% The broker pairs each creature with the next to arrive
+handle_cast({meet, _, _}, {broker, 0, _} = State) ->
+    {noreply, State};
+handle_cast({meet, Creature, Colour}, {broker, Meetings, none}) ->
+    {noreply, {broker, Meetings, {Creature, Colour}}};
+handle_cast({meet, Creature, Colour}, {broker, Meetings, {Other, OtherColour}}) ->
+    gen_server:cast(Creature, {met, OtherColour}),
+    gen_server:cast(Other, {met, Colour}),
+    {noreply, {broker, Meetings - 1, none}};
+%% Creatures go back to the broker as soon as they have met
+handle_cast({start, Self}, {creature, Broker, _, Colour}) ->
+    gen_server:cast(Broker, {meet, Self, Colour}),
+    {noreply, {creature, Broker, Self, Colour}};
+handle_cast({met, OtherColour}, {creature, Broker, Self, Colour}) ->
+    NewColour = complement(Colour, OtherColour),
+    gen_server:cast(Broker, {meet, Self, NewColour}),
+    {noreply, {creature, Broker, Self, NewColour}}.
+
+complement(Colour, Colour) -> Colour;
+complement(blue, red) -> yellow;
+complement(blue, yellow) -> red;
+complement(red, blue) -> yellow;
+complement(red, yellow) -> blue;
+complement(yellow, blue) -> red;
+complement(yellow, red) -> blue.
@@ -0,0 +1,49 @@
+%% Passes a token around a ring of `Size` servers, `Size` times around.
+-module(firefly_bench_ring).
+
+-behaviour(gen_server).
+
+-export([run/1]).
+-export([init/1, handle_call/3, handle_cast/2]).
+
+%% Returns the number of messages sent
+run(Size) ->
+    [First | _] = Servers = start(Size, []),
+    link_ring(Servers, First),
+    Hops = Size * Size,
+    gen_server:cast(First, {token, Hops}),
+    stop(Servers),
+    Hops.
+
+start(0, Servers) ->
+    Servers;
+start(N, Servers) ->
+    {ok, Server} = gen_server:start_link(?MODULE, [], []),
+    start(N - 1, [Server | Servers]).
+
+%% Makes each server forward the token to the next, and the last to the first
+link_ring([Last], First) ->
+    gen_server:cast(Last, {next, First});
+link_ring([Server | [Next | _] = Rest], First) ->
+    gen_server:cast(Server, {next, Next}),
+    link_ring(Rest, First).
+
+stop([]) ->
+    ok;
+stop([Server | Servers]) ->
+    gen_server:stop(Server),
+    stop(Servers).
+
+init([]) ->
+    {ok, undefined}.
+
+handle_call(_Request, _From, Next) ->
+    {reply, ok, Next}.
+
+handle_cast({next, Next}, _) ->
+    {noreply, Next};
+handle_cast({token, 0}, Next) ->
+    {noreply, Next};
+handle_cast({token, Hops}, Next) ->
+    gen_server:cast(Next, {token, Hops - 1}),
+    {noreply, Next}.
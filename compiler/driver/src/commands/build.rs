@@ -0,0 +1,515 @@
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+use walkdir::WalkDir;
+
+use firefly_diagnostics::{CodeMap, Reporter, Spanned, ToDiagnostic};
+use firefly_intern::{Ident, Symbol};
+use firefly_parser::{self as parse, FileMapSource, Scanner, Source};
+use firefly_session::InputType;
+use firefly_syntax_erl::evaluator;
+use firefly_syntax_erl::{Expr, Lexer, LexicalToken, Literal, ParseConfig, Token};
+
+/// The name of the rebar3 configuration file
+const REBAR_CONFIG: &'static str = "rebar.config";
+
+/// The directories in which rebar3 looks for the applications of an umbrella project
+const PROJECT_APP_DIRS: &[&'static str] = &["apps", "lib"];
+
+/// The main entry point for the 'build' command
+///
+/// The rebar3 project in the current directory, i.e. a single application or an umbrella of them,
+/// is compiled along with its dependencies into a single executable, by translating `rebar.config`
+/// into the equivalent arguments to `firefly compile`:
+///
+/// * Every application's `src_dirs` (`src` by default) and `extra_src_dirs` are compiled
+/// * Dependencies are compiled from `_build/<profile>/lib`, so they must have been fetched already,
+///   e.g. with `rebar3 get-deps`, as must their own dependencies
+/// * `{d, Name}`, `{d, Name, Value}`, `{i, Dir}` and `warnings_as_errors` in the `erl_opts` of the
+///   project are honored, along with those of the given profile; other options are ignored
+/// * The resource file of every application is bundled into the executable
+/// * If a release is described under `relx`, its name and version are those of the executable, and
+///   its applications are started at boot, otherwise an umbrella starts all of its applications
+///
+/// Since every module ends up in one executable, macros are defined for all of them, rather than
+/// per application as in rebar3. Remaining arguments are passed to `firefly compile` as-is.
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<i32> {
+    let config_path = cwd.join(REBAR_CONFIG);
+    if !config_path.is_file() {
+        bail!(
+            "no {} found in {}, is this a rebar3 project?",
+            REBAR_CONFIG,
+            cwd.display()
+        );
+    }
+    let profile = matches.value_of("profile").unwrap();
+    let config = Config::load(&config_path)?.with_profile(profile);
+    let extra_args: Vec<OsString> = matches
+        .values_of_os("args")
+        .map(|args| args.map(|a| a.to_owned()).collect())
+        .unwrap_or_default();
+
+    // The project is either a single application at the root, or an umbrella of applications
+    let mut apps = vec![];
+    if let Some(app) = ProjectApp::load(&cwd, Some(config.clone()), profile)? {
+        apps.push(app);
+    } else {
+        for dir in PROJECT_APP_DIRS.iter().map(|dir| cwd.join(dir)) {
+            if !dir.is_dir() {
+                continue;
+            }
+            let mut entries = fs::read_dir(&dir)
+                .with_context(|| format!("unable to read {}", dir.display()))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .collect::<Vec<_>>();
+            entries.sort();
+            for path in entries {
+                if let Some(app) = ProjectApp::load(&path, None, profile)? {
+                    apps.push(app);
+                }
+            }
+        }
+    }
+    if apps.is_empty() {
+        bail!(
+            "no applications found in {}, expected src/<app>.app.src, or apps/<app>/src/<app>.app.src",
+            cwd.display()
+        );
+    }
+
+    // Dependencies are resolved breadth-first, so the first occurrence of a dependency wins
+    let lib_dirs = vec![
+        cwd.join("_build").join(profile).join("lib"),
+        cwd.join("_build").join("default").join("lib"),
+    ];
+    let mut seen = apps
+        .iter()
+        .map(|app| app.name.clone())
+        .collect::<BTreeSet<_>>();
+    let mut pending = config.deps();
+    for app in apps.iter() {
+        pending.extend(app.config.deps());
+    }
+    let mut deps = vec![];
+    while !pending.is_empty() {
+        let name = pending.remove(0);
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let Some(root) = lib_dirs.iter().map(|dir| dir.join(&name)).find(|dir| dir.is_dir()) else { bail!("dependency {} has not been fetched to {}, run `rebar3 get-deps` first", name, lib_dirs[0].display()); };
+        let dep = ProjectApp::load_dep(&root, &name, profile)?;
+        pending.extend(dep.config.deps());
+        deps.push(dep);
+    }
+
+    let mut args: Vec<OsString> = vec!["firefly".into(), "compile".into()];
+
+    // The identity of the executable, and the applications started at boot
+    let release = config.release();
+    let main = match (&release, apps.as_slice()) {
+        (Some((name, version, boot_apps)), _) => {
+            args.push("--app-name".into());
+            args.push(name.into());
+            if let Some(version) = version {
+                args.push("--app-version".into());
+                args.push(version.into());
+            }
+            for app in boot_apps.iter() {
+                args.push("--boot-app".into());
+                args.push(app.into());
+            }
+            None
+        }
+        (None, [app]) => {
+            args.push("--app".into());
+            args.push(app.resource.clone().unwrap().into_os_string());
+            Some(app.name.as_str())
+        }
+        (None, _) => {
+            let name = cwd.file_name().unwrap().to_string_lossy().into_owned();
+            args.push("--app-name".into());
+            args.push(name.into());
+            for app in apps.iter() {
+                args.push("--boot-app".into());
+                args.push(app.name.as_str().into());
+            }
+            None
+        }
+    };
+    for app in apps.iter().chain(deps.iter()) {
+        if Some(app.name.as_str()) == main {
+            continue;
+        }
+        if let Some(resource) = app.resource.as_ref() {
+            args.push("--app-resource".into());
+            args.push(resource.clone().into_os_string());
+        }
+    }
+
+    // Only the options of the project itself apply, as rebar3 does not let them leak to
+    // dependencies
+    let mut erl_opts = config
+        .erl_opts()
+        .into_iter()
+        .map(|opt| (cwd.clone(), opt))
+        .collect::<Vec<_>>();
+    for app in apps.iter().filter(|app| app.root != cwd) {
+        erl_opts.extend(
+            app.config
+                .erl_opts()
+                .into_iter()
+                .map(|opt| (app.root.clone(), opt)),
+        );
+    }
+    let mut warnings_as_errors = false;
+    let mut include_paths = BTreeSet::new();
+    for (root, opt) in erl_opts.iter() {
+        match opt {
+            Literal::Atom(id) if id.name == "warnings_as_errors" => warnings_as_errors = true,
+            Literal::Tuple(_, elements) => match elements.as_slice() {
+                [Literal::Atom(d), Literal::Atom(name)] if d.name == "d" => {
+                    args.push("-D".into());
+                    args.push(name.as_str().get().into());
+                }
+                [Literal::Atom(d), Literal::Atom(name), value] if d.name == "d" => {
+                    args.push("-D".into());
+                    args.push(format!("{}={}", name.as_str().get(), value).into());
+                }
+                [Literal::Atom(i), dir] if i.name == "i" => {
+                    if let Some(dir) = string(dir) {
+                        include_paths.insert(root.join(dir));
+                    }
+                }
+                _ => continue,
+            },
+            _ => continue,
+        }
+    }
+    if warnings_as_errors && !is_given(&extra_args, &["-W", "--warn"]) {
+        args.push("--warn".into());
+        args.push("error".into());
+    }
+
+    // Headers are found in the include directory of each application, relative to the including
+    // source file, or via include_lib, i.e. relative to the directory containing the applications
+    for app in apps.iter().chain(deps.iter()) {
+        include_paths.insert(app.root.join("include"));
+        if app.root != cwd {
+            include_paths.insert(app.root.parent().unwrap().to_path_buf());
+        }
+    }
+    for path in include_paths.iter().filter(|path| path.is_dir()) {
+        args.push("-I".into());
+        args.push(path.clone().into_os_string());
+    }
+
+    if !is_given(&extra_args, &["-o", "--output"]) {
+        let build_dir = cwd.join("_build").join(profile);
+        let name = release
+            .as_ref()
+            .map(|(name, _, _)| name.clone())
+            .or_else(|| main.map(|name| name.to_string()))
+            .unwrap_or_else(|| cwd.file_name().unwrap().to_string_lossy().into_owned());
+        args.push("--output-dir".into());
+        args.push(build_dir.join("firefly").into_os_string());
+        args.push("-o".into());
+        args.push(build_dir.join("bin").join(name).into_os_string());
+    }
+    args.extend(extra_args);
+
+    for app in apps.iter() {
+        app.push_sources(&mut args, true)?;
+    }
+    for dep in deps.iter() {
+        dep.push_sources(&mut args, false)?;
+    }
+
+    crate::run_compiler(cwd, args.into_iter())
+}
+
+/// Returns true if any of the given flags, e.g. `-o` or `--output`, were passed through to the
+/// compiler
+fn is_given(args: &[OsString], flags: &[&str]) -> bool {
+    args.iter().any(|arg| {
+        let arg = arg.to_string_lossy();
+        flags.iter().any(|flag| arg.starts_with(flag))
+    })
+}
+
+/// An application of the project, or one of its dependencies
+struct ProjectApp {
+    name: String,
+    root: PathBuf,
+    /// The path to the resource file of this application, i.e. `src/<name>.app.src`, or
+    /// `ebin/<name>.app`
+    resource: Option<PathBuf>,
+    config: Config,
+}
+impl ProjectApp {
+    /// Loads the project application rooted at `root`, if it contains a `src/<name>.app.src` file
+    ///
+    /// If `config` is not given, `root/rebar.config` is used, if present
+    fn load(root: &Path, config: Option<Config>, profile: &str) -> anyhow::Result<Option<Self>> {
+        let srcdir = root.join("src");
+        if !srcdir.is_dir() {
+            return Ok(None);
+        }
+        let mut resources = fs::read_dir(&srcdir)
+            .with_context(|| format!("unable to read {}", srcdir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.to_string_lossy().ends_with(".app.src"))
+            .collect::<Vec<_>>();
+        resources.sort();
+        let Some(resource) = resources.into_iter().next() else { return Ok(None); };
+        let file_name = resource.file_name().unwrap().to_string_lossy();
+        let name = file_name.strip_suffix(".app.src").unwrap().to_string();
+        let config = match config {
+            Some(config) => config,
+            None => Config::load(&root.join(REBAR_CONFIG))?.with_profile(profile),
+        };
+        Ok(Some(Self {
+            name,
+            root: root.to_path_buf(),
+            resource: Some(resource),
+            config,
+        }))
+    }
+
+    /// Loads the dependency `name`, fetched to `root`
+    fn load_dep(root: &Path, name: &str, profile: &str) -> anyhow::Result<Self> {
+        let resource = [
+            root.join("src").join(format!("{}.app.src", name)),
+            root.join("ebin").join(format!("{}.app", name)),
+        ]
+        .into_iter()
+        .find(|path| path.is_file());
+        let config = Config::load(&root.join(REBAR_CONFIG))?.with_profile(profile);
+        Ok(Self {
+            name: name.to_string(),
+            root: root.to_path_buf(),
+            resource,
+            config,
+        })
+    }
+
+    /// Adds the sources of this application to `args`, including those of `extra_src_dirs` if
+    /// requested
+    ///
+    /// Like rebar3, source directories are searched recursively unless `{recursive, false}` is
+    /// given
+    fn push_sources(&self, args: &mut Vec<OsString>, extra: bool) -> anyhow::Result<()> {
+        let mut dirs = self.config.src_dirs("src_dirs");
+        if dirs.is_empty() {
+            dirs.push(("src".to_string(), true));
+        }
+        if extra {
+            dirs.extend(self.config.src_dirs("extra_src_dirs"));
+        }
+        for (dir, recursive) in dirs {
+            let dir = self.root.join(dir);
+            if !dir.is_dir() {
+                continue;
+            }
+            let walker = WalkDir::new(&dir)
+                .max_depth(if recursive { usize::MAX } else { 1 })
+                .sort_by(|a, b| a.file_name().cmp(b.file_name()));
+            for entry in walker {
+                let entry = entry?;
+                if entry.file_type().is_file() && InputType::Erlang.validate(entry.path()) {
+                    args.push(entry.into_path().into_os_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The terms of a `rebar.config` file
+#[derive(Clone, Default)]
+struct Config {
+    terms: Vec<Literal>,
+}
+impl Config {
+    /// Reads the configuration file at `path`, which is treated as empty if it does not exist
+    ///
+    /// Each term is parsed and evaluated as an Erlang expression, so the file may contain anything
+    /// that `file:consult/1` accepts.
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+
+        // Split the file into its terms, using the lexer so that dots in strings, atoms, comments
+        // and floats are handled correctly
+        let codemap = Arc::new(CodeMap::new());
+        let id = codemap.add(path, text.clone());
+        let file = codemap.get(id).unwrap();
+        let mut start = 0;
+        let mut sources = vec![];
+        for token in Lexer::new(Scanner::new(FileMapSource::new(file))) {
+            if let Ok(LexicalToken(_, Token::Dot, end)) = token {
+                let end = end.index().to_usize();
+                sources.push(&text[start..end - 1]);
+                start = end;
+            }
+        }
+
+        let parser = parse::Parser::new(ParseConfig::default(), codemap.clone());
+        let mut terms = vec![];
+        for source in sources {
+            let reporter = Reporter::new();
+            let expr = match parser.parse_string::<Expr, _, _>(reporter.clone(), source) {
+                Ok(expr) => expr,
+                Err(err) => {
+                    reporter.diagnostic(err.to_diagnostic());
+                    reporter.print(&codemap);
+                    bail!("unable to parse {}", path.display());
+                }
+            };
+            let term = evaluator::eval_expr(&expr, None)
+                .map_err(|err| anyhow!("invalid term in {}: {}", path.display(), err))?;
+            terms.push(term);
+        }
+        Ok(Self { terms })
+    }
+
+    /// Merges the options of the given profile into this configuration, as rebar3 does
+    ///
+    /// Only `erl_opts`, `deps` and `extra_src_dirs` are merged, by appending them to the defaults
+    fn with_profile(mut self, profile: &str) -> Self {
+        let profiles = self.get("profiles").map(list).unwrap_or_default();
+        let Some(options) = profiles.iter().find_map(|p| match p { Literal::Tuple(_, e) if e.len() == 2 && atom(&e[0]) == Some(profile) => Some(list(&e[1])), _ => None }) else { return self; };
+        for key in ["erl_opts", "deps", "extra_src_dirs"] {
+            let Some(value) = options.iter().find_map(|opt| value_of(opt, key)) else { continue; };
+            let mut merged = self.get(key).map(list).unwrap_or_default();
+            merged.extend(list(value));
+            let span = value.span();
+            self.terms.insert(
+                0,
+                Literal::Tuple(
+                    span,
+                    vec![
+                        Literal::Atom(Ident::new(Symbol::intern(key), span)),
+                        Literal::from_proper_list(span, merged),
+                    ],
+                ),
+            );
+        }
+        self
+    }
+
+    /// Returns the value of the `{key, Value}` term in this configuration, if present
+    fn get(&self, key: &str) -> Option<&Literal> {
+        self.terms.iter().find_map(|term| value_of(term, key))
+    }
+
+    fn erl_opts(&self) -> Vec<Literal> {
+        self.get("erl_opts").map(list).unwrap_or_default()
+    }
+
+    /// Returns the names of the dependencies listed under `deps`, i.e. `Name` or `{Name, ...}`
+    fn deps(&self) -> Vec<String> {
+        self.get("deps")
+            .map(list)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|dep| match dep {
+                Literal::Tuple(_, elements) => elements.first().and_then(atom),
+                dep => atom(dep),
+            })
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Returns the directories listed under `key`, i.e. `"dir"` or `{"dir", Opts}`, and whether
+    /// they are to be searched recursively
+    fn src_dirs(&self, key: &str) -> Vec<(String, bool)> {
+        self.get(key)
+            .map(list)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|dir| match dir {
+                Literal::Tuple(_, elements) if elements.len() == 2 => {
+                    let recursive = list(&elements[1])
+                        .iter()
+                        .find_map(|opt| value_of(opt, "recursive"))
+                        .and_then(atom)
+                        != Some("false");
+                    string(&elements[0]).map(|dir| (dir, recursive))
+                }
+                dir => string(dir).map(|dir| (dir, true)),
+            })
+            .collect()
+    }
+
+    /// Returns the name, version and applications of the first release described under `relx`
+    ///
+    /// Applications are given as `NAME[=TYPE]`, as expected by `--boot-app`. The version is only
+    /// known if it is given as a string, rather than e.g. `git` or `semver`.
+    fn release(&self) -> Option<(String, Option<String>, Vec<String>)> {
+        let relx = list(self.get("relx")?);
+        relx.iter().find_map(|opt| {
+            let Literal::Tuple(_, elements) = opt else { return None; };
+            let [tag, Literal::Tuple(_, id), apps] = elements.as_slice() else { return None; };
+            if atom(tag) != Some("release") || id.len() != 2 {
+                return None;
+            }
+            let name = atom(&id[0])?.to_string();
+            let version = string(&id[1]);
+            let apps = list(apps)
+                .iter()
+                .filter_map(|app| match app {
+                    Literal::Tuple(_, elements) => {
+                        let name = elements.first().and_then(atom)?;
+                        let restart = elements
+                            .iter()
+                            .skip(1)
+                            .filter_map(atom)
+                            .find(|ty| matches!(*ty, "permanent" | "transient" | "temporary"));
+                        match restart {
+                            Some(ty) => Some(format!("{}={}", name, ty)),
+                            None => Some(name.to_string()),
+                        }
+                    }
+                    app => atom(app).map(|name| name.to_string()),
+                })
+                .collect();
+            Some((name, version, apps))
+        })
+    }
+}
+
+/// Returns the value of `term` if it is a `{key, Value}` tuple
+fn value_of<'a>(term: &'a Literal, key: &str) -> Option<&'a Literal> {
+    match term {
+        Literal::Tuple(_, elements) if elements.len() == 2 && atom(&elements[0]) == Some(key) => {
+            Some(&elements[1])
+        }
+        _ => None,
+    }
+}
+
+fn atom(term: &Literal) -> Option<&'static str> {
+    match term {
+        Literal::Atom(id) => Some(id.name.as_str().get()),
+        _ => None,
+    }
+}
+
+fn string(term: &Literal) -> Option<String> {
+    match term {
+        Literal::String(id) => Some(id.as_str().get().to_string()),
+        _ => None,
+    }
+}
+
+fn list(term: &Literal) -> Vec<Literal> {
+    term.as_proper_list().unwrap_or_default()
+}
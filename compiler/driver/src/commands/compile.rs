@@ -12,7 +12,7 @@ use firefly_codegen as codegen;
 use firefly_codegen::linker;
 use firefly_codegen::meta::{CodegenResults, CompiledModule, ProjectInfo};
 use firefly_diagnostics::{CodeMap, Diagnostic, Label};
-use firefly_session::{CodegenOptions, DebuggingOptions, Options};
+use firefly_session::{CodegenOptions, DebuggingOptions, InputType, Options};
 use firefly_syntax_base::{ApplicationMetadata, Deprecation, FunctionName, ModuleMetadata};
 use firefly_util::diagnostics::Emitter;
 use firefly_util::time::HumanDuration;
@@ -20,6 +20,8 @@ use firefly_util::time::HumanDuration;
 use crate::commands::*;
 use crate::compiler::prelude::{Compiler as CompilerQueryGroup, *};
 use crate::compiler::Compiler;
+use crate::depgraph;
+use crate::manifest::emit_manifest;
 use crate::parser::prelude::Parser as ParserQueryGroup;
 use crate::task;
 
@@ -36,6 +38,13 @@ pub fn handle_command<'a>(
     let codemap = Arc::new(CodeMap::new());
     // Set up diagnostics
     let diagnostics = create_diagnostics_handler(&options, codemap.clone(), emitter);
+    for dep in options.unfetched_deps.iter() {
+        diagnostics.warn(format!(
+            "dependency {} {} has not been fetched, so it will not be compiled, run `firefly deps` \
+             first",
+            &dep.name, &dep.version
+        ));
+    }
 
     // Initialize codegen backend
     codegen::init(&options)?;
@@ -47,6 +56,7 @@ pub fn handle_command<'a>(
     //
     // The query system will use these options to construct the set of inputs on demand
     db.set_options(Arc::new(options));
+    db.set_live_functions(None);
 
     let inputs = db.inputs().unwrap_or_else(abort_on_err);
     let num_inputs = inputs.len();
@@ -69,7 +79,7 @@ pub fn handle_command<'a>(
     debug!("awaiting parse results from workers ({} units)", num_inputs);
 
     let options = db.options();
-    let diagnostics = db.diagnostics();
+    let diagnostics = db.diagnostics().clone();
 
     let mut modules = BTreeMap::new();
 
@@ -97,6 +107,23 @@ pub fn handle_command<'a>(
         modules,
     });
 
+    // Analyze the call graph of the build if it was requested, or is needed to prune dead functions
+    let depgraph_file = options.depgraph_file();
+    if depgraph_file.is_some() || options.codegen_opts.prune_functions {
+        let analysis =
+            depgraph::analyze(&db, inputs.as_slice(), app.clone()).unwrap_or_else(abort_on_err);
+        let live = depgraph::live_functions(&db, &analysis);
+        if let Some(depgraph_file) = depgraph_file {
+            match depgraph::emit_depgraph(&db, &analysis, live.as_ref(), depgraph_file) {
+                Ok(path) => debug!("wrote call graph to {}", path.display()),
+                Err(_) => diagnostics.abort_if_errors(),
+            }
+        }
+        if let Some(live) = live {
+            db.set_live_functions(Some(Arc::new(live)));
+        }
+    }
+
     // Spawn tasks for each input to be compiled
     let mut tasks = inputs
         .iter()
@@ -133,6 +160,14 @@ pub fn handle_command<'a>(
     // Do not proceed to linking if there were compilation errors
     diagnostics.abort_if_errors();
 
+    // Describe the build for external tooling, if requested
+    if let Some(manifest_file) = options.manifest_file() {
+        match emit_manifest(&db, inputs.as_slice(), app.clone(), manifest_file) {
+            Ok(path) => debug!("wrote build manifest to {}", path.display()),
+            Err(_) => diagnostics.abort_if_errors(),
+        }
+    }
+
     // do not proceed with compilation if analyze_only was set
     if options.debugging_opts.analyze_only {
         diagnostics.notice("Finished", "skipping link, -Z analyze_only was set");
@@ -178,6 +213,24 @@ where
 {
     debug!("spawning worker for {:?}", input);
 
+    // Core Erlang has no deprecation attributes, so only the name and exports are known
+    if db.input_type(input) == InputType::CoreErlang {
+        return match db.input_cerl(input) {
+            Err(err) => {
+                let diagnostics = db.diagnostics();
+                let input_info = db.lookup_intern_input(input);
+                diagnostics.failed("Failed", format!("{}", &input_info.source_name()));
+                Err(err)
+            }
+            Ok(module) => Ok(ModuleMetadata {
+                name: module.name,
+                exports: module.exports.iter().cloned().collect(),
+                deprecation: None,
+                deprecations: BTreeMap::new(),
+            }),
+        };
+    }
+
     // Generate metadata about modules read from sources provided to the compiler
     let result = db.input_ast(input);
     match result {
@@ -0,0 +1,241 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+use sha2::{Digest, Sha256};
+
+use firefly_session::{
+    deps_dir, registry_release, Dependency, LockedPackage, Lockfile, Manifest, RegistryRelease,
+    LOCK_FILE, MANIFEST_FILE,
+};
+
+/// The default Hex repository from which packages are fetched
+const DEFAULT_REPO: &'static str = "https://repo.hex.pm";
+
+/// The environment variable used by rebar3 and mix to override the Hex repository
+const HEX_MIRROR: &'static str = "HEX_MIRROR";
+
+/// The maximum size of a package tarball we're willing to download, same as the limit imposed by
+/// Hex
+const MAX_TARBALL_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The main entry point for the 'deps' command
+///
+/// Each dependency listed in the project manifest is fetched to `_build/deps` as a Hex package
+/// tarball, which `firefly compile` then treats as an archive input. Each tarball is verified
+/// against the checksum recorded in the lock file, if present, or otherwise against the checksums
+/// listed by the registry of the repository, see `verify`. New checksums are recorded in the lock
+/// file, and packages which are no longer required are removed from it.
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<()> {
+    let manifest_path = cwd.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        bail!(
+            "no {} found in {}, nothing to fetch",
+            MANIFEST_FILE,
+            cwd.display()
+        );
+    }
+    let manifest = Manifest::load(&manifest_path)?;
+    let lock_path = cwd.join(LOCK_FILE);
+    let lock = Lockfile::load(&lock_path)?;
+    let locked_only = matches.is_present("locked");
+    let repo = matches
+        .value_of("repo")
+        .map(|repo| repo.to_string())
+        .or_else(|| std::env::var(HEX_MIRROR).ok())
+        .unwrap_or_else(|| DEFAULT_REPO.to_string());
+
+    let dir = deps_dir(&cwd);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("unable to create dependency directory {}", dir.display()))?;
+
+    let mut packages = Vec::with_capacity(manifest.deps.len());
+    for dep in manifest.deps.iter() {
+        let locked = lock.get(dep);
+        if locked.is_none() && locked_only {
+            bail!(
+                "{} {} is not in {}, and --locked was given",
+                &dep.name,
+                &dep.version,
+                LOCK_FILE
+            );
+        }
+
+        let path = dir.join(dep.tarball());
+        let checksum = match fetched_checksum(&path, locked)? {
+            Some(checksum) => checksum,
+            None => {
+                eprintln!("Fetching {} {}", &dep.name, &dep.version);
+                let tarball = fetch(&repo, dep)?;
+                let listed = match locked {
+                    Some(_) => None,
+                    None => Some(fetch_release(&repo, dep)?),
+                };
+                let checksum = verify(dep, &tarball, locked, listed.as_ref())?;
+                let tmp = path.with_extension("tar.tmp");
+                fs::write(&tmp, tarball.as_slice())
+                    .and_then(|_| fs::rename(&tmp, &path))
+                    .with_context(|| format!("unable to write {}", path.display()))?;
+                checksum
+            }
+        };
+        packages.push(LockedPackage {
+            dep: dep.clone(),
+            checksum,
+        });
+    }
+
+    packages.sort_by(|a, b| a.dep.name.cmp(&b.dep.name));
+    let updated = Lockfile { packages };
+    if updated != lock {
+        if locked_only {
+            bail!("{} is out of date, and --locked was given", LOCK_FILE);
+        }
+        updated.save(&lock_path)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the checksum of the previously fetched tarball at `path`, if it is still valid
+fn fetched_checksum(path: &Path, locked: Option<&LockedPackage>) -> anyhow::Result<Option<String>> {
+    let locked = match locked {
+        Some(locked) if path.is_file() => locked,
+        _ => return Ok(None),
+    };
+    let tarball = fs::read(path).with_context(|| format!("unable to read {}", path.display()))?;
+    if sha256(&tarball) == locked.checksum {
+        Ok(Some(locked.checksum.clone()))
+    } else {
+        // The tarball is corrupt or was tampered with, so fetch it again
+        Ok(None)
+    }
+}
+
+fn fetch(repo: &str, dep: &Dependency) -> anyhow::Result<Vec<u8>> {
+    download(repo, dep, &format!("tarballs/{}", dep.tarball()))
+}
+
+/// Fetches the checksums the registry of `repo` lists for `dep`, failing if it is not listed
+fn fetch_release(repo: &str, dep: &Dependency) -> anyhow::Result<RegistryRelease> {
+    let resource = download(repo, dep, &dep.registry())?;
+    registry_release(dep, &resource)?.ok_or_else(|| {
+        anyhow!(
+            "package {} {} is not listed in the registry of {}",
+            &dep.name,
+            &dep.version,
+            repo
+        )
+    })
+}
+
+/// Downloads the resource at `path` in `repo`, which belongs to the package of `dep`
+fn download(repo: &str, dep: &Dependency, path: &str) -> anyhow::Result<Vec<u8>> {
+    let url = format!("{}/{}", repo.trim_end_matches('/'), path);
+    let response = ureq::get(&url).call().map_err(|err| match err {
+        ureq::Error::Status(404, _) => anyhow!(
+            "package {} {} does not exist in {}",
+            &dep.name,
+            &dep.version,
+            repo
+        ),
+        err => anyhow!("unable to fetch {}: {}", &url, err),
+    })?;
+    let mut bytes = vec![];
+    response
+        .into_reader()
+        .take(MAX_TARBALL_SIZE + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("unable to fetch {}", &url))?;
+    if bytes.len() as u64 > MAX_TARBALL_SIZE {
+        bail!("{} exceeds the maximum package size", &url);
+    }
+    Ok(bytes)
+}
+
+/// Verifies a package tarball, returning its checksum
+///
+/// The tarball must match its locked checksum, if there is one, or else the checksums `listed` by
+/// the registry, so that a tarball which was tampered with is rejected on its first fetch too,
+/// rather than being locked. The digest of the other files of the package is always verified,
+/// against the inner checksum listed by the registry if it was consulted, or else the `CHECKSUM`
+/// file of the package. The inner checksum is the only one listed for releases published before
+/// Hex listed the checksum of the tarball itself.
+fn verify(
+    dep: &Dependency,
+    tarball: &[u8],
+    locked: Option<&LockedPackage>,
+    listed: Option<&RegistryRelease>,
+) -> anyhow::Result<String> {
+    let checksum = sha256(tarball);
+    let expected_outer = locked
+        .map(|locked| &locked.checksum)
+        .or_else(|| listed.and_then(|listed| listed.outer_checksum.as_ref()));
+    if let Some(expected) = expected_outer {
+        if &checksum != expected {
+            bail!(
+                "checksum mismatch for {} {}: expected {}, got {}",
+                &dep.name,
+                &dep.version,
+                expected,
+                &checksum
+            );
+        }
+    }
+
+    let mut version = None;
+    let mut expected = None;
+    let mut metadata = None;
+    let mut contents = None;
+    let mut archive = tar::Archive::new(tarball);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let slot = match name.as_str() {
+            "VERSION" => &mut version,
+            "CHECKSUM" => &mut expected,
+            "metadata.config" => &mut metadata,
+            "contents.tar.gz" => &mut contents,
+            _ => continue,
+        };
+        let mut bytes = vec![];
+        entry.read_to_end(&mut bytes)?;
+        *slot = Some(bytes);
+    }
+    let invalid = || anyhow!("{} {} is not a valid Hex package", &dep.name, &dep.version);
+    let version = version.ok_or_else(invalid)?;
+    let expected = expected.ok_or_else(invalid)?;
+    let metadata = metadata.ok_or_else(invalid)?;
+    let contents = contents.ok_or_else(invalid)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(&version);
+    hasher.update(&metadata);
+    hasher.update(&contents);
+    let actual = to_hex(hasher.finalize().as_slice());
+    let expected = String::from_utf8_lossy(&expected)
+        .trim()
+        .to_ascii_lowercase();
+    let expected = listed.map_or(expected, |listed| listed.inner_checksum.clone());
+    if actual != expected {
+        bail!(
+            "inner checksum mismatch for {} {}: expected {}, got {}",
+            &dep.name,
+            &dep.version,
+            &expected,
+            &actual
+        );
+    }
+
+    Ok(checksum)
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    to_hex(Sha256::digest(bytes).as_slice())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
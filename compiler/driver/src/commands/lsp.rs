@@ -0,0 +1,727 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use serde_json::{json, Value};
+
+use firefly_diagnostics::{
+    ByteIndex, CodeMap, Diagnostic, FileName, LabelStyle, Reporter, Severity, SourceFile,
+    SourceSpan, ToDiagnostic,
+};
+use firefly_intern::Symbol;
+use firefly_number::Integer;
+use firefly_parser::{self as parse, FileMapSource, Scanner, Source};
+use firefly_syntax_erl::{self as syntax_erl, Lexer, LexicalToken, ParseConfig, Token};
+
+/// The main entry point for the 'lsp' command
+///
+/// Runs a Language Server Protocol server over stdin/stdout, which parses each document the client
+/// opens with the Erlang frontend, reparsing only the document which changed on each edit, and
+/// publishes the diagnostics of each parse. It answers go-to-definition for functions, types,
+/// records and macros, document symbols, and hover, which shows the spec of the function under the
+/// cursor. Documents are synchronized in full on each change.
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<()> {
+    let mut config = ParseConfig::default();
+    if let Some(paths) = matches.values_of("include-paths") {
+        for path in paths {
+            config.include_paths.push_back(cwd.join(path));
+        }
+    }
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut server = Server::new(config, stdout.lock());
+    while let Some(body) = read_message(&mut input)? {
+        match serde_json::from_slice::<Value>(&body) {
+            Ok(message) => {
+                if !server.handle(message)? {
+                    break;
+                }
+            }
+            Err(err) => server.send(json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": -32700, "message": err.to_string() },
+            }))?,
+        }
+    }
+
+    // The client is expected to ask the server to shut down before telling it to exit
+    if server.shutdown {
+        Ok(())
+    } else {
+        Err(anyhow!("exited without a shutdown request"))
+    }
+}
+
+/// Errors which can occur while handling a request, which are returned to the client
+#[derive(Debug, thiserror::Error)]
+enum RequestError {
+    #[error("server is shutting down")]
+    ShuttingDown,
+    #[error("unsupported method '{0}'")]
+    MethodNotFound(String),
+    #[error("invalid params, expected {0}")]
+    InvalidParams(&'static str),
+}
+impl RequestError {
+    /// The JSON-RPC error code of this error
+    fn code(&self) -> i64 {
+        match self {
+            Self::ShuttingDown => -32600,
+            Self::MethodNotFound(_) => -32601,
+            Self::InvalidParams(_) => -32602,
+        }
+    }
+}
+
+/// The state of the server, i.e. the documents the client has open
+struct Server<W> {
+    config: ParseConfig,
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    output: W,
+}
+impl<W: Write> Server<W> {
+    fn new(config: ParseConfig, output: W) -> Self {
+        Self {
+            config,
+            documents: HashMap::new(),
+            shutdown: false,
+            output,
+        }
+    }
+
+    /// Handles a message from the client, returning false once the client has asked us to exit
+    fn handle(&mut self, message: Value) -> anyhow::Result<bool> {
+        let Some(method) = message["method"].as_str() else { return Ok(true); };
+        if method == "exit" {
+            return Ok(false);
+        }
+        let params = &message["params"];
+        match message.get("id") {
+            Some(id) => {
+                let response = match self.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(err) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": err.code(), "message": err.to_string() },
+                    }),
+                };
+                self.send(response)?;
+            }
+            None => self.notification(method, params)?,
+        }
+        Ok(true)
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, RequestError> {
+        if self.shutdown {
+            return Err(RequestError::ShuttingDown);
+        }
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": { "openClose": true, "change": 1 },
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "hoverProvider": true,
+                },
+                "serverInfo": { "name": "firefly", "version": crate::FIREFLY_RELEASE },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let Some((document, offset)) = self.position(params)? else { return Ok(Value::Null); };
+                Ok(document.definition(offset).unwrap_or(Value::Null))
+            }
+            "textDocument/hover" => {
+                let Some((document, offset)) = self.position(params)? else { return Ok(Value::Null); };
+                Ok(document.hover(offset).unwrap_or(Value::Null))
+            }
+            "textDocument/documentSymbol" => {
+                let uri = document_uri(params)?;
+                Ok(self
+                    .documents
+                    .get(uri)
+                    .map(Document::symbols)
+                    .unwrap_or(Value::Null))
+            }
+            _ => Err(RequestError::MethodNotFound(method.to_string())),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> anyhow::Result<()> {
+        match method {
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let Ok(uri) = document_uri(params) else { return Ok(()); };
+                let text = if method == "textDocument/didOpen" {
+                    params["textDocument"]["text"].as_str()
+                } else {
+                    // As we only support full synchronization, the last change is the whole
+                    // document
+                    params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str())
+                };
+                let Some(text) = text else { return Ok(()); };
+                let previous = self.documents.remove(uri).and_then(|doc| doc.parsed);
+                let document = Document::parse(&self.config, uri, text.to_string(), previous);
+                let diagnostics = document.diagnostics();
+                self.documents.insert(uri.to_string(), document);
+                self.publish_diagnostics(uri, diagnostics)
+            }
+            "textDocument/didClose" => {
+                let Ok(uri) = document_uri(params) else { return Ok(()); };
+                self.documents.remove(uri);
+                self.publish_diagnostics(uri, vec![])
+            }
+            // Other notifications, e.g. `initialized` or `$/cancelRequest`, require nothing of us
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the open document and the byte offset in it of the position given in `params`
+    fn position(&self, params: &Value) -> Result<Option<(&Document, usize)>, RequestError> {
+        let uri = document_uri(params)?;
+        let position = &params["position"];
+        let (Some(line), Some(character)) = (position["line"].as_u64(), position["character"].as_u64()) else {
+            return Err(RequestError::InvalidParams("position"));
+        };
+        Ok(self.documents.get(uri).map(|document| {
+            let offset = offset(document.text(), line as usize, character as usize);
+            (document, offset)
+        }))
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str, diagnostics: Vec<Value>) -> anyhow::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }))
+    }
+
+    fn send(&mut self, message: Value) -> anyhow::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// A successfully parsed module, along with the code map its spans refer to
+struct Parsed {
+    codemap: Arc<CodeMap>,
+    module: syntax_erl::Module,
+}
+
+/// A document the client has open, as of its last change
+struct Document {
+    uri: String,
+    codemap: Arc<CodeMap>,
+    file: Arc<SourceFile>,
+    /// The tokens of the document, which are used to find what is under the cursor
+    tokens: Vec<LexicalToken>,
+    /// The macros defined in the document, and the spans of their names
+    macros: Vec<(Symbol, SourceSpan)>,
+    /// The diagnostics reported while parsing the document
+    reported: Vec<Diagnostic>,
+    /// The document as of its last successful parse, which is kept while it fails to parse, so
+    /// that navigation keeps working while the document is being edited
+    parsed: Option<Parsed>,
+}
+impl Document {
+    fn parse(config: &ParseConfig, uri: &str, text: String, previous: Option<Parsed>) -> Self {
+        // Each parse gets its own code map, as real files are only ever added to a code map once
+        let codemap = Arc::new(CodeMap::new());
+        let id = match uri_to_path(uri) {
+            Some(path) => codemap.add(path, text),
+            None => codemap.add(uri.to_string(), text),
+        };
+        let file = codemap.get(id).unwrap();
+
+        let tokens = Lexer::new(Scanner::new(FileMapSource::new(file.clone())))
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        let define = Symbol::intern("define");
+        let macros = tokens
+            .windows(4)
+            .filter_map(|window| match window {
+                [LexicalToken(_, Token::Minus, _), LexicalToken(_, Token::Atom(directive), _), LexicalToken(_, Token::LParen, _), name]
+                    if *directive == define =>
+                {
+                    name_of(name).map(|sym| (sym, name.span()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let reporter = Reporter::new();
+        let parser = parse::Parser::new(config.clone(), codemap.clone());
+        let parsed = match parser.parse::<syntax_erl::Module, _>(reporter.clone(), file.clone()) {
+            Ok(module) => Some(Parsed {
+                codemap: codemap.clone(),
+                module,
+            }),
+            Err(err) => {
+                if !reporter.is_failed() {
+                    reporter.diagnostic(err.to_diagnostic());
+                }
+                previous
+            }
+        };
+        let reported = reporter.diagnostics().to_vec();
+
+        Self {
+            uri: uri.to_string(),
+            codemap,
+            file,
+            tokens,
+            macros,
+            reported,
+            parsed,
+        }
+    }
+
+    fn text(&self) -> &str {
+        self.file.source()
+    }
+
+    /// Converts the diagnostics reported while parsing this document to those of the protocol
+    fn diagnostics(&self) -> Vec<Value> {
+        self.reported
+            .iter()
+            .map(|diagnostic| {
+                let primary = diagnostic
+                    .labels
+                    .iter()
+                    .find(|label| label.style == LabelStyle::Primary)
+                    .or_else(|| diagnostic.labels.first());
+                let mut message = diagnostic.message.clone();
+                let range = match primary {
+                    Some(label) if label.file_id == self.file.id() => {
+                        if !label.message.is_empty() {
+                            write!(&mut message, ": {}", label.message).unwrap();
+                        }
+                        range(self.text(), label.range.start, label.range.end)
+                    }
+                    // Diagnostics in other files, e.g. included ones, are shown at the top of
+                    // the document
+                    Some(label) => {
+                        if let Ok(name) = self.codemap.name(label.file_id) {
+                            write!(&mut message, " (in {})", name).unwrap();
+                        }
+                        range(self.text(), 0, 0)
+                    }
+                    None => range(self.text(), 0, 0),
+                };
+                for note in diagnostic.notes.iter() {
+                    write!(&mut message, "\n{}", note).unwrap();
+                }
+                let related = diagnostic
+                    .labels
+                    .iter()
+                    .filter(|label| label.style == LabelStyle::Secondary)
+                    .filter_map(|label| {
+                        let file = self.codemap.get(label.file_id).ok()?;
+                        Some(json!({
+                            "location": {
+                                "uri": self.file_uri(&file)?,
+                                "range": range(file.source(), label.range.start, label.range.end),
+                            },
+                            "message": label.message,
+                        }))
+                    })
+                    .collect::<Vec<_>>();
+                let severity = match diagnostic.severity {
+                    Severity::Bug | Severity::Error => 1,
+                    Severity::Warning => 2,
+                    Severity::Note => 3,
+                    Severity::Help => 4,
+                };
+                let mut result = json!({
+                    "range": range,
+                    "severity": severity,
+                    "source": "firefly",
+                    "message": message,
+                    "relatedInformation": related,
+                });
+                if let Some(code) = diagnostic.code.as_ref() {
+                    result["code"] = json!(code);
+                }
+                result
+            })
+            .collect()
+    }
+
+    /// Returns the location of the definition of the function, type, record or macro at `offset`
+    fn definition(&self, offset: usize) -> Option<Value> {
+        let (reference, _) = self.reference_at(offset)?;
+        if let Reference::Macro(name) = reference {
+            let (_, span) = self.macros.iter().find(|(sym, _)| *sym == name)?;
+            return self.location(&self.codemap, *span);
+        }
+        let parsed = self.parsed.as_ref()?;
+        let module = &parsed.module;
+        let span = match reference {
+            Reference::Record(name) => module.records.get(&name)?.name.span,
+            Reference::Function(name, arity) => match self.functions(name, arity).next() {
+                Some(function) => function.name.span,
+                // In a spec or type definition, the name may refer to a type instead
+                None => {
+                    let (_, ty) = module.types.iter().find(|(key, _)| {
+                        key.function == name && arity.map_or(true, |a| key.arity == a)
+                    })?;
+                    ty.name.span
+                }
+            },
+            Reference::Macro(_) => return None,
+        };
+        self.location(&parsed.codemap, span)
+    }
+
+    /// Returns the spec of the function at `offset`, or its name and arity if it has none
+    fn hover(&self, offset: usize) -> Option<Value> {
+        let (Reference::Function(name, arity), span) = self.reference_at(offset)? else { return None; };
+        let parsed = self.parsed.as_ref()?;
+        let function = self.functions(name, arity).next()?;
+        let spec = parsed.module.specs.iter().find_map(|(key, spec)| {
+            (key.function == name && key.arity == function.arity).then(|| spec)
+        });
+        let signature = match spec {
+            Some(spec) => {
+                let source = parsed.codemap.source_slice_for_spanned(spec).ok()?;
+                format!("-spec {}", source)
+            }
+            None => format!("{}/{}", name, function.arity),
+        };
+        Some(json!({
+            "contents": { "kind": "markdown", "value": format!("```erlang\n{}\n```", signature) },
+            "range": range(self.text(), offset_of(span.start_index()), offset_of(span.end_index())),
+        }))
+    }
+
+    /// Returns the functions, types, records and macros defined in this document
+    fn symbols(&self) -> Value {
+        let mut symbols = vec![];
+        if let Some(parsed) = self.parsed.as_ref() {
+            let module = &parsed.module;
+            // Only those in the document itself are listed, not those of included files
+            let id = parsed
+                .codemap
+                .get_by_name(self.file.name())
+                .map(|file| file.id());
+            let mut symbol = |name: String,
+                              detail: &str,
+                              kind: u32,
+                              span: SourceSpan,
+                              selection: SourceSpan| {
+                if Some(span.source_id()) != id {
+                    return;
+                }
+                let Ok(file) = parsed.codemap.get(span.source_id()) else { return; };
+                let text = file.source();
+                symbols.push((
+                    offset_of(span.start_index()),
+                    json!({
+                        "name": name,
+                        "detail": detail,
+                        "kind": kind,
+                        "range": range(text, offset_of(span.start_index()), offset_of(span.end_index())),
+                        "selectionRange": range(text, offset_of(selection.start_index()), offset_of(selection.end_index())),
+                    }),
+                ));
+            };
+            for function in module.functions.values() {
+                let name = format!("{}/{}", function.name, function.arity);
+                symbol(name, "function", 12, function.span, function.name.span);
+            }
+            for ty in module.types.values() {
+                let name = format!("{}/{}", ty.name, ty.params.len());
+                let detail = if ty.opaque { "opaque" } else { "type" };
+                symbol(name, detail, 26, ty.span, ty.name.span);
+            }
+            for record in module.records.values() {
+                symbol(
+                    record.name.to_string(),
+                    "record",
+                    23,
+                    record.span,
+                    record.name.span,
+                );
+            }
+        }
+        for (name, span) in self.macros.iter() {
+            let start = offset_of(span.start_index());
+            let range = range(self.text(), start, offset_of(span.end_index()));
+            symbols.push((
+                start,
+                json!({
+                    "name": name.to_string(),
+                    "detail": "macro",
+                    "kind": 14,
+                    "range": range,
+                    "selectionRange": range,
+                }),
+            ));
+        }
+        symbols.sort_by_key(|(start, _)| *start);
+        Value::Array(symbols.into_iter().map(|(_, symbol)| symbol).collect())
+    }
+
+    /// Returns what the name at `offset` refers to, and the span of the name
+    fn reference_at(&self, offset: usize) -> Option<(Reference, SourceSpan)> {
+        let tokens = self.tokens.as_slice();
+        let index = tokens.iter().position(|token| {
+            let span = token.span();
+            offset_of(span.start_index()) <= offset
+                && offset <= offset_of(span.end_index())
+                && name_of(token).is_some()
+        })?;
+        let token = &tokens[index];
+        let name = name_of(token)?;
+        let prev = index.checked_sub(1).map(|i| &tokens[i].1);
+        let next = tokens.get(index + 1).map(|token| &token.1);
+        let reference = match (prev, &token.1) {
+            (Some(Token::Question), _) => Reference::Macro(name),
+            (Some(Token::Pound), Token::Atom(_)) => Reference::Record(name),
+            (_, Token::Atom(_)) => {
+                // The module of a remote call, or a function of another module
+                if let Some(Token::Colon) = next {
+                    return None;
+                }
+                if let Some(Token::Colon) = prev {
+                    let module = self.parsed.as_ref()?.module.name();
+                    match index.checked_sub(2).map(|i| &tokens[i].1) {
+                        Some(Token::Atom(m)) if *m == module => (),
+                        _ => return None,
+                    }
+                }
+                let arity = match next {
+                    Some(Token::LParen) => count_args(&tokens[index + 1..]),
+                    Some(Token::Slash) => match tokens.get(index + 2).map(|token| &token.1) {
+                        Some(Token::Integer(Integer::Small(arity))) => u8::try_from(*arity).ok(),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                Reference::Function(name, arity)
+            }
+            _ => return None,
+        };
+        Some((reference, token.span()))
+    }
+
+    /// Returns the functions of this document named `name`, with the given arity, if known
+    fn functions(
+        &self,
+        name: Symbol,
+        arity: Option<u8>,
+    ) -> impl Iterator<Item = &syntax_erl::Function> + '_ {
+        self.parsed
+            .iter()
+            .flat_map(|parsed| parsed.module.functions.values())
+            .filter(move |function| {
+                function.name.name == name && arity.map_or(true, |a| function.arity == a)
+            })
+    }
+
+    fn location(&self, codemap: &CodeMap, span: SourceSpan) -> Option<Value> {
+        let file = codemap.get(span.source_id()).ok()?;
+        Some(json!({
+            "uri": self.file_uri(&file)?,
+            "range": range(file.source(), offset_of(span.start_index()), offset_of(span.end_index())),
+        }))
+    }
+
+    /// Returns the URI of `file`, which is either this document or a file it includes
+    fn file_uri(&self, file: &SourceFile) -> Option<String> {
+        if file.name() == self.file.name() {
+            return Some(self.uri.clone());
+        }
+        match file.name() {
+            FileName::Real(path) => Some(path_to_uri(path)),
+            FileName::Virtual(_) => None,
+        }
+    }
+}
+
+/// What a name in a document refers to
+enum Reference {
+    Macro(Symbol),
+    Record(Symbol),
+    /// A function, or a type, with the given name and arity, if known
+    Function(Symbol, Option<u8>),
+}
+
+fn name_of(token: &LexicalToken) -> Option<Symbol> {
+    match token.1 {
+        Token::Atom(name) | Token::Ident(name) => Some(name),
+        _ => None,
+    }
+}
+
+fn offset_of(index: ByteIndex) -> usize {
+    index.to_usize()
+}
+
+/// Counts the arguments of a call, given the tokens starting with its opening parenthesis
+fn count_args(tokens: &[LexicalToken]) -> Option<u8> {
+    if let Some(Token::RParen) = tokens.get(1).map(|token| &token.1) {
+        return Some(0);
+    }
+    let mut depth = 0usize;
+    let mut args = 1usize;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.1 {
+            Token::LParen
+            | Token::LBrace
+            | Token::LBracket
+            | Token::BinaryStart
+            | Token::Begin
+            | Token::Case
+            | Token::If
+            | Token::Maybe
+            | Token::Receive
+            | Token::Try => depth += 1,
+            // Only a fun expression has a body, not a reference such as `fun foo/1`
+            Token::Fun => match (tokens.get(i + 1), tokens.get(i + 2)) {
+                (Some(LexicalToken(_, Token::LParen, _)), _)
+                | (
+                    Some(LexicalToken(_, Token::Ident(_), _)),
+                    Some(LexicalToken(_, Token::LParen, _)),
+                ) => depth += 1,
+                _ => (),
+            },
+            Token::RParen | Token::RBrace | Token::RBracket | Token::BinaryEnd | Token::End => {
+                depth -= 1;
+                if depth == 0 {
+                    return u8::try_from(args).ok();
+                }
+            }
+            Token::Comma if depth == 1 => args += 1,
+            // The end of the form was reached without closing the call
+            Token::Dot => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Converts a byte range of `text` to a range of the protocol
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({ "start": position(text, start), "end": position(text, end) })
+}
+
+/// Converts a byte offset of `text` to a position of the protocol, whose character is counted in
+/// UTF-16 code units
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = before.matches('\n').count();
+    let character = before[line_start..]
+        .chars()
+        .map(char::len_utf16)
+        .sum::<usize>();
+    json!({ "line": line, "character": character })
+}
+
+/// Converts a position of the protocol to a byte offset of `text`
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let line_start = match line.checked_sub(1) {
+        None => 0,
+        Some(n) => match text.match_indices('\n').nth(n) {
+            Some((i, _)) => i + 1,
+            None => return text.len(),
+        },
+    };
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn document_uri(params: &Value) -> Result<&str, RequestError> {
+    params["textDocument"]["uri"]
+        .as_str()
+        .ok_or(RequestError::InvalidParams("textDocument.uri"))
+}
+
+/// Returns the path of a `file://` URI
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%' {
+            let hex = std::str::from_utf8(encoded.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(encoded[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+/// Returns the `file://` URI of a path
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => write!(&mut uri, "%{:02X}", byte).unwrap(),
+        }
+    }
+    uri
+}
+
+/// Reads the body of the next message from `input`, returning None at the end of the input
+///
+/// Messages are preceded by headers, of which we only need `Content-Length`, and a blank line.
+fn read_message(input: &mut impl BufRead) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                let value = value.trim().parse::<usize>();
+                length = Some(value.context("invalid Content-Length header")?);
+            }
+        }
+    }
+    let length = length.ok_or_else(|| anyhow!("message is missing a Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
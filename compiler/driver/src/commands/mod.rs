@@ -1,5 +1,14 @@
+pub(crate) mod bench;
+pub(crate) mod build;
 pub(crate) mod compile;
+pub(crate) mod deps;
+pub(crate) mod lsp;
 pub(crate) mod print;
+pub(crate) mod run;
+pub(crate) mod shell;
+pub(crate) mod stubs;
+pub(crate) mod test;
+pub(crate) mod trace_dump;
 
 use std::sync::Arc;
 
@@ -19,6 +28,7 @@ pub(super) fn create_diagnostics_handler(
         warnings_as_errors: options.warnings_as_errors,
         no_warn: options.no_warn,
         display: DisplayConfig::default(),
+        format: options.error_format,
     };
     Arc::new(DiagnosticsHandler::new(config, codemap, emitter))
 }
@@ -4,7 +4,7 @@ use clap::ArgMatches;
 
 use firefly_codegen as codegen;
 use firefly_llvm::{self as llvm, target::TargetMachine};
-use firefly_session::{CodegenOptions, DebuggingOptions, Options};
+use firefly_session::{build_info, CodegenOptions, DebuggingOptions, Options};
 use firefly_target::{self as target, Target};
 
 /// The main entry point for the 'print' command
@@ -19,15 +19,7 @@ pub fn handle_command<'a>(
             let verbose = subcommand_matches
                 .map(|m| m.is_present("verbose"))
                 .unwrap_or_else(|| matches.is_present("verbose"));
-            if verbose {
-                println!("release:     {}", crate::FIREFLY_RELEASE);
-                println!("commit-hash: {}", crate::FIREFLY_COMMIT_HASH);
-                println!("commit-date: {}", crate::FIREFLY_COMMIT_DATE);
-                println!("host:        {}", target::host_triple());
-                println!("llvm:        {}", llvm::version());
-            } else {
-                println!("{}", crate::FIREFLY_RELEASE);
-            }
+            print_version(verbose);
         }
         ("current-target", _) => {
             let triple = target::host_triple();
@@ -61,3 +53,20 @@ pub fn handle_command<'a>(
 
     Ok(())
 }
+
+/// Prints the release of the compiler, with the details of its build if `verbose`
+///
+/// This is what `firefly print version` and `firefly --version --verbose` print.
+pub fn print_version(verbose: bool) {
+    if verbose {
+        println!("release:     {}", crate::FIREFLY_RELEASE);
+        println!("commit-hash: {}", crate::FIREFLY_COMMIT_HASH);
+        println!("commit-date: {}", crate::FIREFLY_COMMIT_DATE);
+        println!("host:        {}", target::host_triple());
+        println!("profile:     {}", build_info::PROFILE);
+        println!("llvm:        {}", llvm::version());
+        println!("abi:         {}", codegen::abi::ABI_VERSION);
+    } else {
+        println!("{}", crate::FIREFLY_RELEASE);
+    }
+}
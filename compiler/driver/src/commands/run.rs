@@ -0,0 +1,354 @@
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+use walkdir::WalkDir;
+
+use firefly_beam::syntax::{pp, AST};
+use firefly_session::Archive;
+
+/// The name of the function every escript must export
+const ESCRIPT_MAIN: &'static str = "main";
+
+/// The main entry point for the 'run' command
+///
+/// The escript is compiled to a temporary executable along with a small `init` module which
+/// invokes `main/1` with the script arguments, which are passed to the executable after `-extra`,
+/// so that they are the plain arguments of the runtime. The exit code of that executable is
+/// returned.
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<i32> {
+    let path = cwd.join(matches.value_of("script").unwrap());
+    let script_args: Vec<OsString> = matches
+        .values_of_os("args")
+        .map(|args| args.map(|a| a.to_owned()).collect())
+        .unwrap_or_default();
+
+    let bytes = std::fs::read(&path)
+        .with_context(|| format!("unable to read escript {}", path.display()))?;
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("firefly-escript-")
+        .tempdir()
+        .context("unable to create temporary directory for escript")?;
+    let escript = Escript::parse(&path, &bytes, tempdir.path())?;
+
+    let srcdir = tempdir.path().join("src");
+    std::fs::create_dir_all(&srcdir)?;
+    let mut script_srcs = Vec::with_capacity(escript.sources.len());
+    for (module, source) in escript.sources.iter() {
+        let script_src = srcdir.join(format!("{}.erl", module));
+        std::fs::write(&script_src, source.as_bytes())?;
+        script_srcs.push(script_src);
+    }
+    let init_src = srcdir.join("init.erl");
+    std::fs::write(&init_src, escript.init_module().as_bytes())?;
+
+    let exe = tempdir.path().join(&escript.module);
+    let mut compile_args: Vec<OsString> = vec![
+        "firefly".into(),
+        "compile".into(),
+        "--app-name".into(),
+        escript.module.as_str().into(),
+        "--app-type".into(),
+        "bin".into(),
+        "-o".into(),
+        exe.clone().into_os_string(),
+        "--output-dir".into(),
+        tempdir.path().join("_build").into_os_string(),
+    ];
+    if let Some(paths) = matches.values_of_os("include-paths") {
+        for path in paths {
+            compile_args.push("-I".into());
+            compile_args.push(cwd.join(path).into_os_string());
+        }
+    }
+    compile_args.extend(script_srcs.into_iter().map(PathBuf::into_os_string));
+    compile_args.push(init_src.into_os_string());
+
+    let code = crate::run_compiler(cwd.clone(), compile_args.into_iter())?;
+    if code != 0 {
+        return Ok(code);
+    }
+
+    let status = Command::new(&exe)
+        .args(escript.emulator_args.iter())
+        .arg("-extra")
+        .args(script_args)
+        .current_dir(&cwd)
+        .status()
+        .with_context(|| format!("unable to execute compiled escript {}", exe.display()))?;
+
+    if matches.is_present("keep-temps") {
+        let kept = tempdir.into_path();
+        eprintln!("escript build artifacts kept in {}", kept.display());
+    }
+
+    Ok(exit_code(status))
+}
+
+/// Converts the exit status of the script into our own exit code
+///
+/// If the script was killed by a signal, this mirrors the shell convention of 128 + signal
+#[cfg(unix)]
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    status
+        .code()
+        .or_else(|| status.signal().map(|sig| 128 + sig))
+        .unwrap_or(1)
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+/// Represents the parsed contents of an escript
+struct Escript {
+    /// The name of the module containing `main/1`
+    module: String,
+    /// The Erlang source of each module of the script, by module name
+    ///
+    /// The source of a source script has its header blanked out so line numbers are preserved,
+    /// while that of a compiled module is printed from its abstract code, see `decompile`.
+    sources: Vec<(String, String)>,
+    /// Arguments given on the `%%!` line, passed to the runtime ahead of the script arguments
+    emulator_args: Vec<String>,
+}
+impl Escript {
+    /// Parses an escript, following the same rules as `escript`:
+    ///
+    /// * The first line is ignored (it is normally a `#!` line)
+    /// * The second line may be a comment, e.g. `%% -*- erlang -*-`
+    /// * The second or third line may begin with `%%!`, providing arguments to the emulator
+    ///
+    /// The remainder of the file is the script body, which may be Erlang source, a BEAM file,
+    /// or a zip archive, which is unpacked in `workdir`. Firefly does not load BEAM code, so
+    /// compiled modules are compiled again from the abstract code in their debug info.
+    ///
+    /// As with `escript`, `-escript main Module` on the `%%!` line names the module containing
+    /// `main/1`, which is otherwise that of a BEAM body, or is named after the script.
+    fn parse(path: &Path, bytes: &[u8], workdir: &Path) -> anyhow::Result<Self> {
+        let (header, body) = split_header(bytes);
+
+        let header = std::str::from_utf8(header)
+            .map_err(|_| anyhow!("{} has an invalid escript header", path.display()))?;
+        let mut emulator_args = vec![];
+        for line in header.lines().skip(1) {
+            if let Some(args) = line.strip_prefix("%%!") {
+                emulator_args.extend(args.split_whitespace().map(|s| s.to_string()));
+            }
+        }
+        let main = take_escript_main(&mut emulator_args);
+
+        let escript = if body.starts_with(b"PK") {
+            let sources = archive_sources(path, body, workdir)?;
+            let module = main.unwrap_or_else(|| default_module_name(path));
+            Self {
+                module,
+                sources,
+                emulator_args,
+            }
+        } else if body.starts_with(b"FOR1") {
+            let (module, source) =
+                decompile(body).with_context(|| format!("unable to read {}", path.display()))?;
+            Self {
+                module: main.unwrap_or_else(|| module.clone()),
+                sources: vec![(module, source)],
+                emulator_args,
+            }
+        } else {
+            Self::parse_source(path, header, body, main, emulator_args)?
+        };
+
+        if escript.sources.iter().any(|(module, _)| module == "init") {
+            bail!(
+                "{} defines the module 'init', which is reserved for booting the script",
+                path.display()
+            );
+        }
+        if !escript
+            .sources
+            .iter()
+            .any(|(module, _)| *module == escript.module)
+        {
+            bail!(
+                "{} does not contain the module '{}', which must export {}/1",
+                path.display(),
+                escript.module,
+                ESCRIPT_MAIN
+            );
+        }
+        Ok(escript)
+    }
+
+    /// Parses the body of a source escript
+    fn parse_source(
+        path: &Path,
+        header: &str,
+        body: &[u8],
+        main: Option<String>,
+        emulator_args: Vec<String>,
+    ) -> anyhow::Result<Self> {
+        let body = std::str::from_utf8(body)
+            .map_err(|_| anyhow!("{} is not valid UTF-8", path.display()))?;
+
+        let declared = declared_module(body);
+        let module = match declared {
+            Some(ref name) => name.clone(),
+            None => default_module_name(path),
+        };
+
+        // Replace the header with blank lines so that diagnostics refer to the original line
+        // numbers. If the script has no module declaration, the attributes escript would
+        // synthesize are placed on the first line, which is otherwise just the shebang.
+        let header_lines = header.lines().count();
+        let mut source = String::with_capacity(body.len() + 64);
+        if declared.is_none() {
+            write!(
+                &mut source,
+                "-module('{}'). -export([{}/1]).",
+                module, ESCRIPT_MAIN
+            )
+            .unwrap();
+        }
+        for _ in 0..header_lines {
+            source.push('\n');
+        }
+        source.push_str(body);
+
+        Ok(Self {
+            module: main.unwrap_or_else(|| module.clone()),
+            sources: vec![(module, source)],
+            emulator_args,
+        })
+    }
+
+    /// Generates the `init` module which the runtime boots, and which invokes the script's `main/1`
+    /// with the plain arguments, i.e. those after `-extra`
+    fn init_module(&self) -> String {
+        format!(
+            "-module(init).\n\
+             -export([boot/1]).\n\
+             \n\
+             boot(_) ->\n    \
+                 '{module}':{main}(init:get_plain_arguments()).\n",
+            module = self.module,
+            main = ESCRIPT_MAIN,
+        )
+    }
+}
+
+/// Removes `-escript main Module` from the emulator arguments, returning the module
+fn take_escript_main(args: &mut Vec<String>) -> Option<String> {
+    let index = args
+        .windows(3)
+        .position(|window| window[0] == "-escript" && window[1] == "main")?;
+    let mut removed = args.drain(index..index + 3);
+    removed.nth(2)
+}
+
+/// Returns the source of each module of an archive body, unpacked in `workdir`
+///
+/// Modules may be given as sources, or compiled, in which case they are compiled again from their
+/// debug info, see `decompile`, unless their source is also in the archive.
+fn archive_sources(
+    path: &Path,
+    body: &[u8],
+    workdir: &Path,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let zip = workdir.join(format!("{}.zip", default_module_name(path)));
+    std::fs::write(&zip, body)?;
+    let archive = Archive::extract(&zip, &workdir.join("archive"))
+        .with_context(|| format!("unable to unpack {}", path.display()))?;
+
+    let mut sources = vec![];
+    let mut beams = vec![];
+    for entry in WalkDir::new(&archive.root).sort_by_file_name() {
+        let entry = entry?;
+        let file = entry.path();
+        match file.extension().and_then(|ext| ext.to_str()) {
+            Some("erl") => {
+                let source = std::fs::read_to_string(file)
+                    .with_context(|| format!("unable to read {}", file.display()))?;
+                let module =
+                    declared_module(source.as_str()).unwrap_or_else(|| default_module_name(file));
+                sources.push((module, source));
+            }
+            Some("beam") => beams.push(file.to_path_buf()),
+            _ => (),
+        }
+    }
+    for beam in beams {
+        let bytes = std::fs::read(&beam)?;
+        let (module, source) = decompile(bytes.as_slice())
+            .with_context(|| format!("unable to read {} in {}", beam.display(), path.display()))?;
+        if !sources.iter().any(|(name, _)| *name == module) {
+            sources.push((module, source));
+        }
+    }
+    Ok(sources)
+}
+
+/// Returns the name and source of a compiled module, printed from the abstract code in its debug
+/// info, so it must have been compiled with `debug_info`
+fn decompile(beam: &[u8]) -> anyhow::Result<(String, String)> {
+    let ast = AST::from_beam_bytes(beam).map_err(|err| anyhow!("{}", err))?;
+    let source = pp::module(&ast.module);
+    let module = declared_module(source.as_str())
+        .ok_or_else(|| anyhow!("the module has no module attribute"))?;
+    Ok((module, source))
+}
+
+/// Splits an escript into its header and body
+///
+/// The header is always the first line, plus up to two further lines which are comments
+fn split_header(bytes: &[u8]) -> (&[u8], &[u8]) {
+    let mut offset = next_line(bytes, 0);
+    for _ in 0..2 {
+        if bytes[offset..].starts_with(b"%") {
+            offset = next_line(bytes, offset);
+        } else {
+            break;
+        }
+    }
+    bytes.split_at(offset)
+}
+
+fn next_line(bytes: &[u8], start: usize) -> usize {
+    match bytes[start..].iter().position(|b| *b == b'\n') {
+        Some(pos) => start + pos + 1,
+        None => bytes.len(),
+    }
+}
+
+/// Finds the name given in a `-module(..)` attribute, if present
+pub(super) fn declared_module(body: &str) -> Option<String> {
+    body.lines().find_map(|line| {
+        let rest = line.trim_start().strip_prefix("-module")?;
+        let rest = rest.trim_start().strip_prefix('(')?;
+        let end = rest.find(')')?;
+        Some(rest[..end].trim().trim_matches('\'').to_string())
+    })
+}
+
+/// Derives a module name from the script path, as escript does when no module is declared
+fn default_module_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "escript".to_string());
+    stem.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
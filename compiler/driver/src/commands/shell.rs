@@ -0,0 +1,932 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command as Process;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use clap::ArgMatches;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use firefly_diagnostics::{CodeMap, Reporter, SourceSpan, Spanned, ToDiagnostic};
+use firefly_intern::{symbols, Ident, Symbol};
+use firefly_number::ToPrimitive;
+use firefly_parser as parse;
+use firefly_syntax_erl::evaluator::{self, EvalError, ResolveRecordIndexError};
+use firefly_syntax_erl::{self as syntax_erl, Expr, Literal, ParseConfig};
+
+/// The main entry point for the 'shell' command
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<()> {
+    let mut config = ParseConfig::default();
+    if let Some(paths) = matches.values_of("include-paths") {
+        for path in paths {
+            config.include_paths.push_back(cwd.join(path));
+        }
+    }
+    let history_size = matches
+        .value_of("history")
+        .unwrap()
+        .parse::<usize>()
+        .map_err(|_| anyhow!("invalid value for --history, expected a non-negative integer"))?;
+
+    let mut shell = Shell::new(config, history_size, cwd.clone());
+    if let Some(paths) = matches.values_of("records") {
+        for path in paths {
+            shell.load_records(&cwd.join(path))?;
+        }
+    }
+
+    if let Some(exprs) = matches.value_of("eval") {
+        return match shell.eval_input(exprs) {
+            Ok(result) => {
+                println!("{}", shell.format(&result));
+                Ok(())
+            }
+            Err(err) => Err(anyhow!("{}", err)),
+        };
+    }
+
+    shell.run()
+}
+
+/// Errors which can occur while evaluating an expression in the shell
+#[derive(Debug, thiserror::Error)]
+enum ShellError {
+    #[error("syntax error, see diagnostics for details")]
+    Parse,
+    #[error("variable '{0}' is unbound")]
+    Unbound(Symbol),
+    #[error("no match of right hand side value {0}")]
+    NoMatch(String),
+    #[error("record {0} undefined")]
+    NoRecord(Symbol),
+    #[error("field {1} undefined in record {0}")]
+    NoRecordField(Symbol, Symbol),
+    #[error("{{badrecord,{0}}}")]
+    BadRecord(Symbol),
+    #[error("{0}")]
+    Eval(#[from] EvalError),
+    #[error("undefined shell command {0}/{1}")]
+    UndefinedCommand(Symbol, usize),
+    #[error("bad argument to {0}")]
+    Badarg(&'static str),
+    #[error("{0} can only be evaluated by the runtime")]
+    Unsupported(&'static str),
+    #[error("{0}: {1}")]
+    Exception(String, String),
+    #[error("unable to evaluate expression: {0}")]
+    Runtime(String),
+    #[error("unable to read back result: {0}")]
+    Unreadable(String),
+    #[error("{0}")]
+    Io(String),
+}
+
+/// The outcome of running a shell command, i.e. a local call to `f()`, `q()`, etc.
+enum Command {
+    Value(Literal),
+    Quit,
+}
+
+/// An erl-like interactive shell
+///
+/// Expressions are parsed using the Erlang frontend, and evaluated against the current set of
+/// shell bindings. Those which are not constant, e.g. calls, are evaluated by the runtime, see
+/// `eval_in_runtime`. Like the standard shell, results are numbered and can be recalled using
+/// `v/1`, and record definitions loaded via `rd/2` or `rr/1` are used when printing results.
+struct Shell {
+    cwd: PathBuf,
+    codemap: Arc<CodeMap>,
+    config: ParseConfig,
+    bindings: BTreeMap<Symbol, Literal>,
+    records: BTreeMap<Symbol, syntax_erl::Record>,
+    history: VecDeque<(usize, String, Literal)>,
+    history_size: usize,
+    counter: usize,
+}
+impl Shell {
+    fn new(config: ParseConfig, history_size: usize, cwd: PathBuf) -> Self {
+        Self {
+            cwd,
+            codemap: Arc::new(CodeMap::new()),
+            config,
+            bindings: BTreeMap::new(),
+            records: BTreeMap::new(),
+            history: VecDeque::new(),
+            history_size,
+            counter: 1,
+        }
+    }
+
+    /// Runs the read-eval-print loop until end of input, or `q()` is called
+    fn run(&mut self) -> anyhow::Result<()> {
+        println!("Firefly {} (abort with ^D or q().)", crate::FIREFLY_RELEASE);
+
+        let mut editor = Editor::<()>::new();
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() {
+                format!("{}> ", self.counter)
+            } else {
+                format!("{}| ", " ".repeat(self.counter.to_string().len()))
+            };
+
+            let line = match editor.readline(&prompt) {
+                Ok(line) => line,
+                // ^C discards any partially entered input
+                Err(ReadlineError::Interrupted) => {
+                    buffer.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => {
+                    println!();
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if !line.trim().is_empty() {
+                editor.add_history_entry(line.as_str());
+            }
+            buffer.push_str(&line);
+            buffer.push('\n');
+
+            // Keep reading until we have a complete form, terminated by `.`
+            if !is_complete(&buffer) {
+                continue;
+            }
+
+            let input = core::mem::take(&mut buffer);
+            match self.eval_command(&input) {
+                Ok(Command::Quit) => return Ok(()),
+                Ok(Command::Value(result)) => {
+                    println!("{}", self.format(&result));
+                    self.push_history(input, result);
+                }
+                Err(ShellError::Parse) => continue,
+                Err(ShellError::Exception(class, reason)) => {
+                    println!("** exception {}: {}", class, reason)
+                }
+                Err(err) => println!("** exception error: {}", err),
+            }
+        }
+    }
+
+    fn push_history(&mut self, input: String, result: Literal) {
+        self.history
+            .push_back((self.counter, input.trim().to_string(), result));
+        while self.history.len() > self.history_size {
+            self.history.pop_front();
+        }
+        self.counter += 1;
+    }
+
+    /// Parses and evaluates the given input, which may be a sequence of comma-separated expressions
+    fn eval_input(&mut self, input: &str) -> Result<Literal, ShellError> {
+        match self.eval_command(input)? {
+            Command::Value(result) => Ok(result),
+            Command::Quit => Ok(Literal::Atom(Ident::with_empty_span(symbols::Ok))),
+        }
+    }
+
+    fn eval_command(&mut self, input: &str) -> Result<Command, ShellError> {
+        let exprs = self.parse(input)?;
+
+        // Bindings are only committed if the entire input evaluates successfully
+        let saved = self.bindings.clone();
+        let mut result = Literal::Atom(Ident::with_empty_span(symbols::Ok));
+        for expr in exprs.iter() {
+            match self.eval_toplevel(expr) {
+                Ok(Command::Value(value)) => result = value,
+                Ok(Command::Quit) => return Ok(Command::Quit),
+                Err(err) => {
+                    self.bindings = saved;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(Command::Value(result))
+    }
+
+    fn parse(&self, input: &str) -> Result<Vec<Expr>, ShellError> {
+        // The expression grammar does not accept the trailing `.`, and we want to support
+        // comma-separated sequences of expressions, so we wrap the input in a begin/end block
+        let input = input.trim().trim_end_matches('.');
+        let source = format!("begin {} end", input);
+        let reporter = Reporter::new();
+        let parser = parse::Parser::new(self.config.clone(), self.codemap.clone());
+        match parser.parse_string::<Expr, _, _>(reporter.clone(), source) {
+            Ok(Expr::Begin(block)) => Ok(block.body),
+            Ok(expr) => Ok(vec![expr]),
+            Err(err) => {
+                reporter.diagnostic(err.to_diagnostic());
+                reporter.print(&self.codemap);
+                Err(ShellError::Parse)
+            }
+        }
+    }
+
+    fn eval_toplevel(&mut self, expr: &Expr) -> Result<Command, ShellError> {
+        if let Expr::Apply(apply) = expr {
+            if let Some(name) = local_callee(apply.callee.as_ref()) {
+                // Any other local call is to an auto-imported BIF, e.g. `hd/1`
+                match self.eval_shell_command(name, apply.args.as_slice()) {
+                    Err(ShellError::UndefinedCommand(_, _)) => (),
+                    result => return result,
+                }
+            }
+        }
+        match self.eval(expr) {
+            Err(ShellError::Unsupported(_)) => self.eval_in_runtime(expr).map(Command::Value),
+            result => result.map(Command::Value),
+        }
+    }
+
+    /// Evaluates an expression the shell cannot evaluate itself, by compiling it, along with the
+    /// current bindings and records, into a program which prints its result, and reading that back
+    ///
+    /// Only the bindings made by a match at the top level are kept, e.g. `X` in
+    /// `X = lists:seq(1, 3)`, and results which have no literal syntax, e.g. pids and funs, cannot
+    /// be read back.
+    fn eval_in_runtime(&mut self, expr: &Expr) -> Result<Literal, ShellError> {
+        if let Expr::Match(syntax_erl::Match { pattern, expr, .. }) = expr {
+            let value = self.eval_in_runtime(expr)?;
+            return self.match_value(pattern, value);
+        }
+
+        let source = self
+            .codemap
+            .source_slice_for_spanned(expr)
+            .map_err(|err| ShellError::Runtime(err.to_string()))?;
+        let program = self.program(source);
+        let output = self
+            .run_program(program.as_str())
+            .map_err(|err| ShellError::Runtime(format!("{:#}", err)))?;
+
+        // Anything printed by the expression itself precedes the result
+        let mut lines = output.lines().collect::<Vec<_>>();
+        let last = lines
+            .pop()
+            .ok_or_else(|| ShellError::Runtime("no result was printed".to_string()))?;
+        for line in lines {
+            println!("{}", line);
+        }
+        let result = self
+            .read_term(last)
+            .ok_or_else(|| ShellError::Unreadable(last.to_string()))?;
+        match result {
+            Literal::Tuple(_, mut elements) if elements.len() == 2 => {
+                let value = elements.pop().unwrap();
+                match elements.pop().unwrap() {
+                    Literal::Atom(tag) if tag.name == symbols::Ok => Ok(value),
+                    Literal::Atom(class) => Err(ShellError::Exception(
+                        format_atom(class.name),
+                        self.format(&value),
+                    )),
+                    _ => Err(ShellError::Unreadable(last.to_string())),
+                }
+            }
+            _ => Err(ShellError::Unreadable(last.to_string())),
+        }
+    }
+
+    /// Returns the source of an `init` module which evaluates `expr` with the current bindings,
+    /// printing `{ok, Value}`, or `{Class, Reason}` if it raises
+    ///
+    /// The variables of the handler are suffixed so as not to clash with those of the shell.
+    fn program(&self, expr: &str) -> String {
+        let mut program = String::from("-module(init).\n-export([boot/1]).\n");
+        for record in self.records.values() {
+            writeln!(&mut program, "{}", format_record_definition(record)).unwrap();
+        }
+        program.push_str("boot(_) ->\n");
+        for (name, value) in self.bindings.iter() {
+            writeln!(&mut program, "  {} = {},", name, value).unwrap();
+        }
+        writeln!(
+            &mut program,
+            "  erlang:display(try {{ok, begin\n{}\nend}}",
+            expr
+        )
+        .unwrap();
+        program.push_str("  catch Class_:Reason_ -> {Class_, Reason_} end).\n");
+        program
+    }
+
+    /// Compiles `program` to a temporary executable, runs it, and returns what it printed to stdout
+    ///
+    /// The result is printed to stdout by `erlang:display/1`, after anything the expression printed
+    /// itself. Whatever is written to stderr is passed through as it is.
+    fn run_program(&self, program: &str) -> anyhow::Result<String> {
+        let tempdir = tempfile::Builder::new()
+            .prefix("firefly-shell-")
+            .tempdir()?;
+        let src = tempdir.path().join("init.erl");
+        std::fs::write(&src, program.as_bytes())?;
+
+        let exe = tempdir.path().join("shell");
+        let mut compile_args: Vec<OsString> = vec![
+            "firefly".into(),
+            "compile".into(),
+            "--app-name".into(),
+            "shell".into(),
+            "--app-type".into(),
+            "bin".into(),
+            "-o".into(),
+            exe.clone().into_os_string(),
+            "--output-dir".into(),
+            tempdir.path().join("_build").into_os_string(),
+        ];
+        for path in self.config.include_paths.iter() {
+            compile_args.push("-I".into());
+            compile_args.push(path.clone().into_os_string());
+        }
+        compile_args.push(src.into_os_string());
+        let code = crate::run_compiler(self.cwd.clone(), compile_args.into_iter())?;
+        if code != 0 {
+            return Err(anyhow!("compilation failed, see diagnostics for details"));
+        }
+
+        let output = Process::new(&exe).current_dir(&self.cwd).output()?;
+        std::io::stderr().write_all(&output.stderr)?;
+        if !output.status.success() {
+            return Err(anyhow!("program exited with {}", output.status));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Parses a term printed by the runtime, returning None if it has no literal syntax
+    fn read_term(&mut self, text: &str) -> Option<Literal> {
+        let reporter = Reporter::new();
+        let parser = parse::Parser::new(self.config.clone(), self.codemap.clone());
+        let expr = parser
+            .parse_string::<Expr, _, _>(reporter, text.to_string())
+            .ok()?;
+        // Terms contain no variables, so evaluating them leaves the bindings untouched
+        self.eval(&expr).ok()
+    }
+
+    /// Handles the built-in shell commands, e.g. `b()`, `f(X)`, `v(N)`
+    fn eval_shell_command(&mut self, name: Symbol, args: &[Expr]) -> Result<Command, ShellError> {
+        let ok = Literal::Atom(Ident::with_empty_span(symbols::Ok));
+        match (name.as_str().get(), args) {
+            ("q", []) => Ok(Command::Quit),
+            ("b", []) => {
+                for (name, value) in self.bindings.iter() {
+                    println!("{} = {}", name, self.format(value));
+                }
+                Ok(Command::Value(ok))
+            }
+            ("f", []) => {
+                self.bindings.clear();
+                Ok(Command::Value(ok))
+            }
+            ("f", [Expr::Var(var)]) => {
+                self.bindings.remove(&var.sym());
+                Ok(Command::Value(ok))
+            }
+            ("h", []) => {
+                for (n, input, result) in self.history.iter() {
+                    println!("{}: {}", n, input);
+                    println!("-> {}", self.format(result));
+                }
+                Ok(Command::Value(ok))
+            }
+            ("v", [arg]) => {
+                let n = self
+                    .eval(arg)?
+                    .as_integer()
+                    .and_then(|i| i.to_i64())
+                    .ok_or(ShellError::Badarg("v/1"))?;
+                // Non-positive values are relative to the current command number
+                let n = if n <= 0 { self.counter as i64 + n } else { n };
+                self.history
+                    .iter()
+                    .find(|(i, _, _)| *i as i64 == n)
+                    .map(|(_, _, result)| Command::Value(result.clone()))
+                    .ok_or(ShellError::Badarg("v/1"))
+            }
+            ("rd", [Expr::Literal(Literal::Atom(name)), Expr::Tuple(fields)]) => {
+                let record = record_definition(*name, fields.elements.as_slice())
+                    .ok_or(ShellError::Badarg("rd/2"))?;
+                self.records.insert(name.name, record);
+                Ok(Command::Value(Literal::Atom(*name)))
+            }
+            ("rr", [arg]) => {
+                let path = match self.eval(arg)? {
+                    Literal::String(s) => PathBuf::from(s.as_str().get()),
+                    _ => return Err(ShellError::Badarg("rr/1")),
+                };
+                let mut loaded = self
+                    .load_records(&path)
+                    .map_err(|err| ShellError::Io(err.to_string()))?;
+                let names = loaded
+                    .drain(..)
+                    .map(|name| Literal::Atom(Ident::with_empty_span(name)))
+                    .collect();
+                Ok(Command::Value(Literal::from_proper_list(
+                    SourceSpan::UNKNOWN,
+                    names,
+                )))
+            }
+            ("rl", []) => {
+                for record in self.records.values() {
+                    println!("{}", format_record_definition(record));
+                }
+                Ok(Command::Value(ok))
+            }
+            ("rf", []) => {
+                self.records.clear();
+                Ok(Command::Value(ok))
+            }
+            ("rf", [Expr::Literal(Literal::Atom(name))]) => {
+                self.records.remove(&name.name);
+                Ok(Command::Value(ok))
+            }
+            (_, args) => Err(ShellError::UndefinedCommand(name, args.len())),
+        }
+    }
+
+    /// Loads all record definitions from the given source file, returning the names of the records
+    /// loaded
+    fn load_records(&mut self, path: &Path) -> anyhow::Result<Vec<Symbol>> {
+        let reporter = Reporter::new();
+        let parser = parse::Parser::new(self.config.clone(), self.codemap.clone());
+        match parser.parse_file::<syntax_erl::Module, _, _>(reporter.clone(), path) {
+            Ok(module) => {
+                let mut names = vec![];
+                for (name, record) in module.records.iter() {
+                    self.records.insert(*name, record.clone());
+                    names.push(*name);
+                }
+                names.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+                Ok(names)
+            }
+            Err(err) => {
+                reporter.diagnostic(err.to_diagnostic());
+                reporter.print(&self.codemap);
+                Err(anyhow!("unable to read records from {}", path.display()))
+            }
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Literal, ShellError> {
+        match expr {
+            Expr::Literal(lit) => Ok(lit.clone()),
+            Expr::Var(var) => self
+                .bindings
+                .get(&var.sym())
+                .cloned()
+                .ok_or(ShellError::Unbound(var.sym())),
+            Expr::Match(syntax_erl::Match { pattern, expr, .. }) => {
+                let value = self.eval(expr)?;
+                self.match_value(pattern, value)
+            }
+            Expr::Begin(block) => {
+                let mut result = Literal::Atom(Ident::with_empty_span(symbols::Ok));
+                for expr in block.body.iter() {
+                    result = self.eval(expr)?;
+                }
+                Ok(result)
+            }
+            Expr::Cons(cons) => {
+                let head = self.eval(&cons.head)?;
+                let tail = self.eval(&cons.tail)?;
+                Ok(Literal::Cons(cons.span, Box::new(head), Box::new(tail)))
+            }
+            Expr::Tuple(tuple) => {
+                let elements = tuple
+                    .elements
+                    .iter()
+                    .map(|e| self.eval(e))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Literal::Tuple(tuple.span, elements))
+            }
+            Expr::Map(map) => {
+                let mut fields = Vec::with_capacity(map.fields.len());
+                for field in map.fields.iter() {
+                    fields.push(match field {
+                        syntax_erl::MapField::Assoc { span, key, value } => {
+                            syntax_erl::MapField::Assoc {
+                                span: *span,
+                                key: Expr::Literal(self.eval(key)?),
+                                value: Expr::Literal(self.eval(value)?),
+                            }
+                        }
+                        syntax_erl::MapField::Exact { span, key, value } => {
+                            syntax_erl::MapField::Exact {
+                                span: *span,
+                                key: Expr::Literal(self.eval(key)?),
+                                value: Expr::Literal(self.eval(value)?),
+                            }
+                        }
+                    });
+                }
+                let map = Expr::Map(syntax_erl::Map {
+                    span: map.span,
+                    fields,
+                });
+                Ok(evaluator::eval_expr(&map, None)?)
+            }
+            Expr::BinaryExpr(expr) => {
+                let lhs = self.eval(&expr.lhs)?;
+                let rhs = self.eval(&expr.rhs)?;
+                let expr = Expr::BinaryExpr(syntax_erl::BinaryExpr {
+                    span: expr.span,
+                    op: expr.op,
+                    lhs: Box::new(Expr::Literal(lhs)),
+                    rhs: Box::new(Expr::Literal(rhs)),
+                });
+                Ok(evaluator::eval_expr(&expr, None)?)
+            }
+            Expr::UnaryExpr(expr) => {
+                let operand = self.eval(&expr.operand)?;
+                let expr = Expr::UnaryExpr(syntax_erl::UnaryExpr {
+                    span: expr.span,
+                    op: expr.op,
+                    operand: Box::new(Expr::Literal(operand)),
+                });
+                Ok(evaluator::eval_expr(&expr, None)?)
+            }
+            Expr::Binary(bin) => {
+                let mut bindings = evaluator::Bindings::default();
+                let bits =
+                    evaluator::expr_grp(&bin.elements, &mut bindings, |expr, _| match expr {
+                        Expr::Literal(_) => Ok(expr),
+                        _ => Err(()),
+                    })
+                    .map_err(|_| ShellError::Unsupported("binaries of variable segments"))?;
+                Ok(Literal::Binary(bin.span, bits))
+            }
+            Expr::Record(record) => self.eval_record(record),
+            Expr::RecordIndex(_) => {
+                let resolve = |name: Ident, field: Ident| self.record_index(name.name, field.name);
+                Ok(evaluator::eval_expr(expr, Some(&resolve))?)
+            }
+            Expr::RecordAccess(access) => {
+                let record = self.eval(&access.record)?;
+                let index = self
+                    .record_index(access.name.name, access.field.name)
+                    .map_err(|err| self.record_error(err, access.name.name, access.field.name))?;
+                match record {
+                    Literal::Tuple(_, mut elements)
+                        if self.is_record(access.name.name, elements.as_slice()) =>
+                    {
+                        Ok(elements.swap_remove(index - 1))
+                    }
+                    _ => Err(ShellError::BadRecord(access.name.name)),
+                }
+            }
+            Expr::Apply(_) => Err(ShellError::Unsupported("calls")),
+            Expr::Fun(_) => Err(ShellError::Unsupported("funs")),
+            Expr::Receive(_) => Err(ShellError::Unsupported("receive")),
+            _ => Err(ShellError::Unsupported("expression")),
+        }
+    }
+
+    /// Matches `value` against `pattern`, committing any new bindings, and returning the value
+    fn match_value(&mut self, pattern: &Expr, value: Literal) -> Result<Literal, ShellError> {
+        let mut bound = BTreeMap::new();
+        if self.bind(pattern, &value, &mut bound) {
+            self.bindings.extend(bound);
+            Ok(value)
+        } else {
+            Err(ShellError::NoMatch(self.format(&value)))
+        }
+    }
+
+    fn eval_record(&mut self, record: &syntax_erl::Record) -> Result<Literal, ShellError> {
+        let def = self
+            .records
+            .get(&record.name.name)
+            .cloned()
+            .ok_or(ShellError::NoRecord(record.name.name))?;
+
+        for field in record.fields.iter() {
+            if !def.fields.iter().any(|f| f.name == field.name) {
+                return Err(ShellError::NoRecordField(record.name.name, field.name.name));
+            }
+        }
+
+        let mut elements = Vec::with_capacity(def.fields.len() + 1);
+        elements.push(Literal::Atom(record.name));
+        for field in def.fields.iter() {
+            let value = record
+                .fields
+                .iter()
+                .find(|f| f.name == field.name)
+                .and_then(|f| f.value.as_ref())
+                .or(record.default.as_deref())
+                .or(field.value.as_ref());
+            match value {
+                Some(value) => elements.push(self.eval(value)?),
+                None => elements.push(Literal::Atom(Ident::new(symbols::Undefined, field.span))),
+            }
+        }
+        Ok(Literal::Tuple(record.span, elements))
+    }
+
+    fn record_index(&self, name: Symbol, field: Symbol) -> Result<usize, ResolveRecordIndexError> {
+        let def = self
+            .records
+            .get(&name)
+            .ok_or(ResolveRecordIndexError::NoRecord)?;
+        def.fields
+            .iter()
+            .position(|f| f.name.name == field)
+            .map(|i| i + 2)
+            .ok_or(ResolveRecordIndexError::NoField)
+    }
+
+    fn record_error(
+        &self,
+        err: ResolveRecordIndexError,
+        name: Symbol,
+        field: Symbol,
+    ) -> ShellError {
+        match err {
+            ResolveRecordIndexError::NoRecord => ShellError::NoRecord(name),
+            ResolveRecordIndexError::NoField => ShellError::NoRecordField(name, field),
+        }
+    }
+
+    fn is_record(&self, name: Symbol, elements: &[Literal]) -> bool {
+        match (self.records.get(&name), elements.first()) {
+            (Some(def), Some(Literal::Atom(tag))) => {
+                tag.name == name && def.fields.len() + 1 == elements.len()
+            }
+            _ => false,
+        }
+    }
+
+    /// Matches `pattern` against `value`, accumulating new bindings in `bound`
+    fn bind(&self, pattern: &Expr, value: &Literal, bound: &mut BTreeMap<Symbol, Literal>) -> bool {
+        match pattern {
+            Expr::Var(var) if var.is_wildcard() => true,
+            Expr::Var(var) => {
+                let name = var.sym();
+                match self.bindings.get(&name).or_else(|| bound.get(&name)) {
+                    Some(existing) => existing.eq(value),
+                    None => {
+                        bound.insert(name, value.clone());
+                        true
+                    }
+                }
+            }
+            Expr::Literal(lit) => match (lit.as_proper_list(), value.as_proper_list()) {
+                (Ok(expected), Ok(actual)) => expected == actual,
+                _ => lit.eq(value),
+            },
+            Expr::Match(syntax_erl::Match { pattern, expr, .. }) => {
+                self.bind(pattern, value, bound) && self.bind(expr, value, bound)
+            }
+            Expr::Tuple(tuple) => match value {
+                Literal::Tuple(_, elements) if elements.len() == tuple.elements.len() => tuple
+                    .elements
+                    .iter()
+                    .zip(elements.iter())
+                    .all(|(p, v)| self.bind(p, v, bound)),
+                _ => false,
+            },
+            Expr::Cons(cons) => match value {
+                Literal::Cons(_, head, tail) => {
+                    self.bind(&cons.head, head, bound) && self.bind(&cons.tail, tail, bound)
+                }
+                Literal::String(_) => match value.as_proper_list() {
+                    Ok(mut elements) if !elements.is_empty() => {
+                        let head = elements.remove(0);
+                        let tail = Literal::from_proper_list(value.span(), elements);
+                        self.bind(&cons.head, &head, bound) && self.bind(&cons.tail, &tail, bound)
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Expr::Record(record) => match value {
+                Literal::Tuple(_, elements) if self.is_record(record.name.name, elements) => {
+                    record.fields.iter().all(|field| {
+                        match (
+                            self.record_index(record.name.name, field.name.name),
+                            field.value.as_ref(),
+                        ) {
+                            (Ok(index), Some(pattern)) => {
+                                self.bind(pattern, &elements[index - 1], bound)
+                            }
+                            _ => false,
+                        }
+                    })
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Formats a value for display, printing tuples which correspond to known records using record
+    /// syntax
+    fn format(&self, value: &Literal) -> String {
+        let mut buf = String::new();
+        self.format_into(value, &mut buf);
+        buf
+    }
+
+    fn format_into(&self, value: &Literal, buf: &mut String) {
+        match value {
+            Literal::Atom(id) => buf.push_str(&format_atom(id.name)),
+            Literal::Tuple(_, elements) => match elements.first() {
+                Some(Literal::Atom(tag)) if self.is_record(tag.name, elements) => {
+                    let def = &self.records[&tag.name];
+                    write!(buf, "#{}{{", format_atom(tag.name)).unwrap();
+                    for (i, (field, value)) in def.fields.iter().zip(&elements[1..]).enumerate() {
+                        if i > 0 {
+                            buf.push_str(", ");
+                        }
+                        write!(buf, "{} = ", format_atom(field.name.name)).unwrap();
+                        self.format_into(value, buf);
+                    }
+                    buf.push('}');
+                }
+                _ => {
+                    buf.push('{');
+                    for (i, element) in elements.iter().enumerate() {
+                        if i > 0 {
+                            buf.push(',');
+                        }
+                        self.format_into(element, buf);
+                    }
+                    buf.push('}');
+                }
+            },
+            Literal::Cons(_, head, tail) => match value.as_proper_list() {
+                Ok(elements) => {
+                    if let Some(s) = printable_string(elements.as_slice()) {
+                        write!(buf, "{:?}", s).unwrap();
+                        return;
+                    }
+                    buf.push('[');
+                    for (i, element) in elements.iter().enumerate() {
+                        if i > 0 {
+                            buf.push(',');
+                        }
+                        self.format_into(element, buf);
+                    }
+                    buf.push(']');
+                }
+                Err(_) => {
+                    buf.push('[');
+                    self.format_into(head, buf);
+                    buf.push('|');
+                    self.format_into(tail, buf);
+                    buf.push(']');
+                }
+            },
+            Literal::Map(_, map) => {
+                buf.push_str("#{");
+                for (i, (k, v)) in map.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(',');
+                    }
+                    self.format_into(k, buf);
+                    buf.push_str(" => ");
+                    self.format_into(v, buf);
+                }
+                buf.push('}');
+            }
+            Literal::Char(_, c) => write!(buf, "{}", *c as u32).unwrap(),
+            other => write!(buf, "{}", other).unwrap(),
+        }
+    }
+}
+
+/// Returns the function name if the given callee refers to a local function
+fn local_callee(callee: &Expr) -> Option<Symbol> {
+    match callee {
+        Expr::Literal(Literal::Atom(name)) => Some(name.name),
+        Expr::FunctionVar(name) if name.module().is_none() => name.function(),
+        _ => None,
+    }
+}
+
+/// Constructs a record definition from the field list given to `rd/2`, e.g. `{a, b = 1}`
+fn record_definition(name: Ident, fields: &[Expr]) -> Option<syntax_erl::Record> {
+    let mut defs = Vec::with_capacity(fields.len());
+    for field in fields {
+        let (field_name, value) = match field {
+            Expr::Literal(Literal::Atom(field_name)) => (*field_name, None),
+            Expr::Match(syntax_erl::Match { pattern, expr, .. }) => match pattern.as_ref() {
+                Expr::Literal(Literal::Atom(field_name)) => {
+                    (*field_name, Some(expr.as_ref().clone()))
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        defs.push(syntax_erl::RecordField {
+            span: field_name.span,
+            name: field_name,
+            value,
+            ty: None,
+            is_default: false,
+        });
+    }
+    Some(syntax_erl::Record {
+        span: name.span,
+        name,
+        fields: defs,
+        default: None,
+    })
+}
+
+fn format_record_definition(record: &syntax_erl::Record) -> String {
+    let mut buf = format!("-record({}, {{", format_atom(record.name.name));
+    for (i, field) in record.fields.iter().enumerate() {
+        if i > 0 {
+            buf.push_str(", ");
+        }
+        buf.push_str(&format_atom(field.name.name));
+        if let Some(Expr::Literal(value)) = field.value.as_ref() {
+            write!(&mut buf, " = {}", value).unwrap();
+        }
+    }
+    buf.push_str("}).");
+    buf
+}
+
+/// Formats an atom, quoting it only when necessary
+fn format_atom(name: Symbol) -> String {
+    let s = name.as_str().get().to_string();
+    let mut chars = s.chars();
+    let unquoted = match chars.next() {
+        Some(c) if c.is_ascii_lowercase() => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+        }
+        _ => false,
+    };
+    if unquoted && !name.is_keyword() {
+        s
+    } else {
+        format!("'{}'", s.escape_default())
+    }
+}
+
+/// Returns the list as a string if all of its elements are printable characters
+fn printable_string(elements: &[Literal]) -> Option<String> {
+    if elements.is_empty() {
+        return None;
+    }
+    elements
+        .iter()
+        .map(|e| match e {
+            Literal::Char(_, c) => Some(*c),
+            Literal::Integer(_, i) => i.to_u32().and_then(char::from_u32),
+            _ => None,
+        })
+        .map(|c| c.filter(|c| !c.is_control() || c.is_ascii_whitespace()))
+        .collect()
+}
+
+/// Returns true if the buffered input forms a complete expression sequence terminated by `.`
+///
+/// This is a lightweight scan which skips over strings, quoted atoms, character literals and
+/// comments, so that a `.` inside of those does not prematurely terminate the input.
+fn is_complete(input: &str) -> bool {
+    let mut chars = input.chars().peekable();
+    let mut last = None;
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                while let Some(c) = chars.next() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '"' | '\'' => {
+                let mut terminated = false;
+                while let Some(next) = chars.next() {
+                    if next == '\\' {
+                        chars.next();
+                    } else if next == c {
+                        terminated = true;
+                        break;
+                    }
+                }
+                if !terminated {
+                    return false;
+                }
+            }
+            '$' => {
+                if let Some('\\') = chars.next() {
+                    chars.next();
+                }
+            }
+            c if c.is_whitespace() => continue,
+            _ => (),
+        }
+        last = Some(c);
+    }
+    last == Some('.')
+}
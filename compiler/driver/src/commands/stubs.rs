@@ -0,0 +1,328 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+
+use firefly_beam::beam::{ExportedFunction, ModuleInterface};
+use firefly_beam::serialization::etf::Term;
+
+/// The reserved words of Erlang, which must be quoted when used as atoms
+const RESERVED: &[&str] = &[
+    "after", "and", "andalso", "band", "begin", "bnot", "bor", "bsl", "bsr", "bxor", "case",
+    "catch", "cond", "div", "else", "end", "fun", "if", "let", "maybe", "not", "of", "or",
+    "orelse", "receive", "rem", "try", "when", "xor",
+];
+
+/// A function given with `--native`, as `(module, function, arity)`
+type Mfa = (String, String, u32);
+
+/// The main entry point for the 'stubs' command
+///
+/// Each input is a BEAM file, a directory of them, or the name of an application installed with
+/// OTP, whose BEAM files are read from `lib/<app>-<vsn>/ebin` under the OTP root. For each module,
+/// a stub module is written to the output directory which exports the same functions, so that code
+/// depending on it can be compiled before the module itself can be. Functions which do nothing but
+/// return a constant return the same constant, functions given with `--native` are declared as
+/// NIFs, to be provided by a native replacement, and all others raise
+/// `{notsup, {Module, Function, Arity}}`, so that it is clear what is missing when one is called.
+///
+/// Existing files in the output directory are left untouched unless `--force` is given, as stubs
+/// are expected to be edited as modules are ported.
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<()> {
+    let output = cwd.join(matches.value_of("output").unwrap());
+    let force = matches.is_present("force");
+    let otp_root = matches.value_of("otp-root").map(|root| cwd.join(root));
+    let mut natives = BTreeSet::new();
+    for native in matches.values_of("native").into_iter().flatten() {
+        natives.insert(parse_mfa(native)?);
+    }
+
+    let mut beams = Vec::new();
+    for input in matches.values_of("inputs").unwrap() {
+        beams.extend(resolve_input(input, &cwd, otp_root.as_deref())?);
+    }
+
+    fs::create_dir_all(&output)
+        .with_context(|| format!("unable to create output directory {}", output.display()))?;
+    let mut generated = 0;
+    let mut stubbed = BTreeSet::new();
+    for beam in beams.iter() {
+        let interface = ModuleInterface::from_file(beam)
+            .with_context(|| format!("unable to read {}", beam.display()))?;
+        let path = output.join(format!("{}.erl", &interface.name));
+        if path.exists() && !force {
+            eprintln!(
+                "Skipping {}, which already exists, use --force to replace it",
+                path.display()
+            );
+            continue;
+        }
+        let source = generate(&interface, beam, &natives);
+        fs::write(&path, source).with_context(|| format!("unable to write {}", path.display()))?;
+        for function in interface.exports.iter() {
+            stubbed.insert((
+                interface.name.clone(),
+                function.name.clone(),
+                function.arity,
+            ));
+        }
+        generated += 1;
+    }
+
+    for (module, function, arity) in natives.difference(&stubbed) {
+        eprintln!(
+            "warning: {}:{}/{} was given with --native, but is not exported by any stubbed module",
+            module, function, arity
+        );
+    }
+    eprintln!(
+        "Generated {} stub modules in {}",
+        generated,
+        output.display()
+    );
+    Ok(())
+}
+
+/// Parses `module:function/arity`
+fn parse_mfa(mfa: &str) -> anyhow::Result<Mfa> {
+    let parsed = mfa.split_once(':').and_then(|(module, fa)| {
+        let (function, arity) = fa.rsplit_once('/')?;
+        Some((module, function, arity.parse().ok()?))
+    });
+    match parsed {
+        Some((module, function, arity)) if !module.is_empty() && !function.is_empty() => {
+            Ok((module.to_string(), function.to_string(), arity))
+        }
+        _ => bail!("invalid function '{}', expected module:function/arity", mfa),
+    }
+}
+
+/// Returns the BEAM files given by `input`, a BEAM file, a directory, or an application name
+fn resolve_input(input: &str, cwd: &Path, otp_root: Option<&Path>) -> anyhow::Result<Vec<PathBuf>> {
+    let path = cwd.join(input);
+    if path.is_file() {
+        return Ok(vec![path]);
+    }
+    let ebin = if path.is_dir() {
+        let ebin = path.join("ebin");
+        if ebin.is_dir() {
+            ebin
+        } else {
+            path
+        }
+    } else {
+        let root = match otp_root {
+            Some(root) => root.to_path_buf(),
+            None => find_otp_root()?,
+        };
+        find_application(&root, input)?.join("ebin")
+    };
+    let mut beams = Vec::new();
+    let entries =
+        fs::read_dir(&ebin).with_context(|| format!("unable to read {}", ebin.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().map(|ext| ext == "beam").unwrap_or(false) {
+            beams.push(path);
+        }
+    }
+    if beams.is_empty() {
+        bail!("no BEAM files found in {}", ebin.display());
+    }
+    beams.sort();
+    Ok(beams)
+}
+
+/// Returns the root of the OTP installation which provides `erl` in the `PATH`
+fn find_otp_root() -> anyhow::Result<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .map(|dir| dir.join("erl"))
+        .find(|erl| erl.is_file())
+        .and_then(|erl| erl.canonicalize().ok())
+        // `erl` is found in `<root>/bin`, often via a symlink
+        .and_then(|erl| Some(erl.parent()?.parent()?.to_path_buf()))
+        .filter(|root| root.join("lib").is_dir())
+        .ok_or_else(|| anyhow!("unable to find an OTP installation, use --otp-root to give one"))
+}
+
+/// Returns the directory of the latest version of `app` installed under `root`
+fn find_application(root: &Path, app: &str) -> anyhow::Result<PathBuf> {
+    let lib = root.join("lib");
+    let entries =
+        fs::read_dir(&lib).with_context(|| format!("unable to read {}", lib.display()))?;
+    let prefix = format!("{}-", app);
+    let mut latest: Option<(Vec<u64>, PathBuf)> = None;
+    for entry in entries {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        let version = match name.strip_prefix(&prefix) {
+            Some(version) => version
+                .split('.')
+                .map(|part| part.parse().unwrap_or(0))
+                .collect(),
+            None if name == app => vec![],
+            None => continue,
+        };
+        if latest
+            .as_ref()
+            .map(|(latest, _)| version > *latest)
+            .unwrap_or(true)
+        {
+            latest = Some((version, path));
+        }
+    }
+    match latest {
+        Some((_, path)) => Ok(path),
+        None => bail!("no application named '{}' in {}", app, lib.display()),
+    }
+}
+
+/// Generates the source of the stub for `interface`, read from `beam`
+fn generate(interface: &ModuleInterface, beam: &Path, natives: &BTreeSet<Mfa>) -> String {
+    let module = atom(&interface.name);
+    let functions = interface
+        .exports
+        .iter()
+        // These are generated by the compiler
+        .filter(|function| function.name != "module_info")
+        .collect::<Vec<_>>();
+    let is_native = |function: &ExportedFunction| {
+        let mfa = (
+            interface.name.clone(),
+            function.name.clone(),
+            function.arity,
+        );
+        natives.contains(&mfa)
+    };
+
+    let mut source = String::new();
+    writeln!(
+        source,
+        "%% A stub of `{}`, generated by `firefly stubs` from",
+        &interface.name
+    )
+    .unwrap();
+    writeln!(source, "%% {}", beam.display()).unwrap();
+    writeln!(source, "%%").unwrap();
+    writeln!(
+        source,
+        "%% Functions which are not supported yet raise `{{notsup, {{Module, Function, Arity}}}}`."
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "-module({}).", &module).unwrap();
+    writeln!(source).unwrap();
+    let exports = functions
+        .iter()
+        .map(|function| format!("{}/{}", atom(&function.name), function.arity))
+        .collect::<Vec<_>>();
+    writeln!(source, "-export([{}]).", exports.join(", ")).unwrap();
+    let nifs = functions
+        .iter()
+        .filter(|function| is_native(function))
+        .map(|function| format!("{}/{}", atom(&function.name), function.arity))
+        .collect::<Vec<_>>();
+    if !nifs.is_empty() {
+        writeln!(source).unwrap();
+        writeln!(source, "-nifs([{}]).", nifs.join(", ")).unwrap();
+    }
+
+    for function in functions {
+        let constant = function.constant.as_ref().and_then(term);
+        let args = vec!["_"; function.arity as usize].join(", ");
+        writeln!(source).unwrap();
+        writeln!(source, "{}({}) ->", atom(&function.name), args).unwrap();
+        if is_native(function) {
+            writeln!(source, "    erlang:nif_error(undef).").unwrap();
+        } else if let Some(constant) = constant {
+            writeln!(source, "    {}.", constant).unwrap();
+        } else {
+            writeln!(
+                source,
+                "    erlang:error({{notsup, {{{}, {}, {}}}}}).",
+                &module,
+                atom(&function.name),
+                function.arity
+            )
+            .unwrap();
+        }
+    }
+    source
+}
+
+/// Formats `name` as an atom, quoting it if necessary
+fn atom(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_bare = chars
+        .next()
+        .map(|c| c.is_ascii_lowercase())
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+        && !RESERVED.contains(&name);
+    if is_bare {
+        name.to_string()
+    } else {
+        format!("'{}'", name.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
+/// Formats `term` as an Erlang expression, if it can be written as one
+///
+/// Pids, ports, references and funs cannot be, so functions returning them are not treated as
+/// constants.
+fn term(term: &Term) -> Option<String> {
+    let join = |terms: &[Term]| -> Option<String> {
+        let terms = terms.iter().map(self::term).collect::<Option<Vec<_>>>()?;
+        Some(terms.join(", "))
+    };
+    Some(match term {
+        Term::Atom(atom) => self::atom(&atom.name),
+        Term::FixInteger(int) => int.value.to_string(),
+        Term::BigInteger(int) => int.value.to_string(),
+        Term::Float(float) if float.value.is_finite() => {
+            // Erlang requires a fractional part, which Rust omits when it is zero
+            let float = format!("{:?}", float.value);
+            match float.find('e') {
+                Some(e) if !float[..e].contains('.') => format!("{}.0{}", &float[..e], &float[e..]),
+                None if !float.contains('.') => format!("{}.0", float),
+                _ => float,
+            }
+        }
+        Term::Binary(binary) => {
+            let bytes = binary
+                .bytes
+                .iter()
+                .map(|b| b.to_string())
+                .collect::<Vec<_>>();
+            format!("<<{}>>", bytes.join(","))
+        }
+        Term::BitBinary(binary) => {
+            // The bits of the last byte are its most significant
+            let (last, bytes) = binary.bytes.split_last()?;
+            let bits = binary.tail_bits_size;
+            let mut bytes = bytes.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+            bytes.push(format!("{}:{}", last >> (8 - bits), bits));
+            format!("<<{}>>", bytes.join(","))
+        }
+        Term::List(list) => format!("[{}]", join(&list.elements)?),
+        Term::ImproperList(list) => {
+            format!("[{} | {}]", join(&list.elements)?, self::term(&list.last)?)
+        }
+        Term::Tuple(tuple) => format!("{{{}}}", join(&tuple.elements)?),
+        Term::Map(map) => {
+            let mut entries = Vec::with_capacity(map.entries.len());
+            for (key, value) in map.entries.iter() {
+                entries.push(format!("{} => {}", self::term(key)?, self::term(value)?));
+            }
+            format!("#{{{}}}", entries.join(", "))
+        }
+        _ => return None,
+    })
+}
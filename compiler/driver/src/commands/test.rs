@@ -0,0 +1,888 @@
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+use serde_json::json;
+
+use super::run::declared_module;
+
+/// The module which runs the tests, see `firefly_test:run/4`
+const HARNESS: &str = include_str!("test/firefly_test.erl");
+
+/// The module which runs the test cases of Common Test suites, see `firefly_ct:run/4`
+const CT_HARNESS: &str = include_str!("test/firefly_ct.erl");
+
+/// The subset of the `ct` module available to test cases
+const CT_MODULE: &str = include_str!("test/ct.erl");
+
+/// The EUnit header, which provides the assertion macros used by tests
+const EUNIT_HRL: &str = include_str!("test/eunit.hrl");
+
+/// The Common Test header, which provides the `?config` macro
+const CT_HRL: &str = include_str!("test/ct.hrl");
+
+/// The suffix of the name of every module whose tests are run
+const TESTS_SUFFIX: &str = "_tests";
+
+/// The suffix of the name of every Common Test suite
+const SUITE_SUFFIX: &str = "_SUITE";
+
+/// The optional callbacks of a suite, which the harness only calls if they are defined
+const SUITE_CALLBACKS: &[&str] = &[
+    "suite",
+    "groups",
+    "init_per_suite",
+    "end_per_suite",
+    "init_per_group",
+    "end_per_group",
+    "init_per_testcase",
+    "end_per_testcase",
+];
+
+/// The modules which are compiled along with the tests, and so may not be defined by them
+const RESERVED_MODULES: &[&str] = &["init", "firefly_test", "firefly_ct", "ct"];
+
+/// The prefix of every line written by the harness, see `firefly_test.erl`
+const REPORT_PREFIX: &str = "firefly_test\t";
+
+/// The main entry point for the 'test' command
+///
+/// Every module found in the given paths is compiled, with `TEST` defined, into a single executable
+/// along with a harness and an `init` module which boots it. The tests run are the `..._test/0` and
+/// `..._test_/0` functions of the modules whose names end in `_tests`, the latter being generators
+/// of EUnit test representations, including fixtures. Test functions are exported by rewriting the
+/// module attribute of a copy of each of those modules, as EUnit does with a parse transform.
+///
+/// The test cases of the Common Test suites found, i.e. the modules whose names end in `_SUITE`,
+/// are run after those, each with its own private directory below the results directory. The data
+/// directory of a suite is the `..._SUITE_data` directory next to its source.
+///
+/// The harness reports each test on standard error, from where the results are collected, and
+/// reported as they would be by EUnit, and optionally in JUnit XML format. When there are suites,
+/// the results are also written to the results directory, as `results.json` and `index.html`.
+///
+/// With `--sim-seed`, the tests run in a deterministic simulation seeded with it, see `+sim` in
+/// the runtime, so that a failure which depends on the interleaving of processes or on timing can
+/// be replayed by running the tests again with the same seed.
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<i32> {
+    let timeout = matches.value_of("timeout").unwrap();
+    let timeout = timeout
+        .parse::<u64>()
+        .map_err(|_| anyhow!("--timeout expects a number of seconds, got '{}'", timeout))?;
+    let sim_seed = match matches.value_of("sim-seed") {
+        Some(seed) => Some(
+            seed.parse::<u64>()
+                .map_err(|_| anyhow!("--sim-seed expects an unsigned integer, got '{}'", seed))?,
+        ),
+        None => None,
+    };
+    let paths: Vec<PathBuf> = match matches.values_of_os("paths") {
+        Some(paths) => paths.map(|path| cwd.join(path)).collect(),
+        None => ["src", "test"]
+            .iter()
+            .map(|dir| cwd.join(dir))
+            .filter(|dir| dir.is_dir())
+            .collect(),
+    };
+    let sources = find_sources(&paths)?;
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("firefly-test-")
+        .tempdir()
+        .context("unable to create temporary directory for tests")?;
+    let srcdir = tempdir.path().join("src");
+    std::fs::create_dir_all(&srcdir)?;
+    let includedir = tempdir.path().join("include");
+    std::fs::create_dir_all(includedir.join("eunit/include"))?;
+    std::fs::write(includedir.join("eunit/include/eunit.hrl"), EUNIT_HRL)?;
+    std::fs::create_dir_all(includedir.join("common_test/include"))?;
+    std::fs::write(includedir.join("common_test/include/ct.hrl"), CT_HRL)?;
+
+    let mut include_paths = vec![includedir];
+    include_paths.extend(
+        matches
+            .values_of_os("include-paths")
+            .into_iter()
+            .flatten()
+            .map(|path| cwd.join(path)),
+    );
+    let default_include = cwd.join("include");
+    if default_include.is_dir() {
+        include_paths.push(default_include);
+    }
+
+    let mut inputs = vec![];
+    let mut functions = vec![];
+    let mut suites = vec![];
+    for path in sources.iter() {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        let module = declared_module(&source)
+            .or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+            .unwrap();
+        if RESERVED_MODULES.contains(&module.as_str()) {
+            bail!(
+                "{} defines the module '{}', which is reserved for running the tests",
+                path.display(),
+                module
+            );
+        }
+        if module.ends_with(SUITE_SUFFIX) {
+            let data_dir = path.with_file_name(format!("{}_data/", module));
+            suites.push(Suite {
+                callbacks: discover_callbacks(&source),
+                module,
+                data_dir,
+            });
+            inputs.push(path.clone());
+            continue;
+        }
+        if !module.ends_with(TESTS_SUFFIX) {
+            inputs.push(path.clone());
+            continue;
+        }
+
+        let tests = discover_tests(&source);
+        // Includes are resolved relative to the original source, not its copy
+        if let Some(dir) = path.parent() {
+            include_paths.push(dir.to_path_buf());
+        }
+        let copy = srcdir.join(path.file_name().unwrap());
+        std::fs::write(&copy, export_tests(path, &source, &tests)?)?;
+        inputs.push(copy);
+        functions.extend(tests.into_iter().map(|test| (module.clone(), test)));
+    }
+    if functions.is_empty() && suites.is_empty() {
+        bail!(
+            "no tests were found, tests are the ..._test/0 and ..._test_/0 functions of modules \
+             whose names end in {}, and the test cases of modules whose names end in {}",
+            TESTS_SUFFIX,
+            SUITE_SUFFIX
+        );
+    }
+
+    let results_dir = cwd.join(matches.value_of_os("results-dir").unwrap());
+    if !suites.is_empty() {
+        std::fs::create_dir_all(&results_dir).with_context(|| {
+            format!(
+                "unable to create results directory {}",
+                results_dir.display()
+            )
+        })?;
+    }
+
+    for (module, source) in [
+        ("firefly_test", HARNESS.to_string()),
+        ("firefly_ct", CT_HARNESS.to_string()),
+        ("ct", CT_MODULE.to_string()),
+        ("init", init_module(&functions, &suites, timeout * 1000)),
+    ] {
+        let path = srcdir.join(format!("{}.erl", module));
+        std::fs::write(&path, source)?;
+        inputs.push(path);
+    }
+
+    let exe = tempdir.path().join("firefly_test");
+    let mut compile_args: Vec<OsString> = vec![
+        "firefly".into(),
+        "compile".into(),
+        "--app-name".into(),
+        "firefly_test".into(),
+        "--app-type".into(),
+        "bin".into(),
+        "-D".into(),
+        "TEST".into(),
+        "-o".into(),
+        exe.clone().into_os_string(),
+        "--output-dir".into(),
+        tempdir.path().join("_build").into_os_string(),
+    ];
+    for path in include_paths {
+        compile_args.push("-I".into());
+        compile_args.push(path.into_os_string());
+    }
+    compile_args.extend(inputs.into_iter().map(|path| path.into_os_string()));
+    let code = crate::run_compiler(cwd.clone(), compile_args.into_iter())?;
+    if code != 0 {
+        return Ok(code);
+    }
+
+    let mut runtime_args: Vec<OsString> = matches
+        .values_of_os("runtime-args")
+        .map(|args| args.map(|a| a.to_owned()).collect())
+        .unwrap_or_default();
+    if let Some(seed) = sim_seed {
+        runtime_args.push("+sim".into());
+        runtime_args.push(seed.to_string().into());
+    }
+    let verbose = matches.is_present("verbose");
+    let results = run_tests(&exe, &cwd, &runtime_args, &results_dir, verbose)?;
+
+    let summary = Summary::new(results.iter());
+    print!("{}", summary);
+    if let Some(seed) = sim_seed {
+        println!(
+            "Simulated with seed {}, run again with --sim-seed {} to replay",
+            seed, seed
+        );
+    }
+    if let Some(path) = matches.value_of_os("junit") {
+        let path = cwd.join(path);
+        std::fs::write(&path, junit_report(&results))
+            .with_context(|| format!("unable to write JUnit report to {}", path.display()))?;
+    }
+    if !suites.is_empty() {
+        write_results(&results_dir, &results)
+            .with_context(|| format!("unable to write results to {}", results_dir.display()))?;
+        println!("Results written to {}", results_dir.display());
+    }
+
+    if matches.is_present("keep-temps") {
+        let kept = tempdir.into_path();
+        eprintln!("test build artifacts kept in {}", kept.display());
+    }
+    Ok(if summary.is_success() { 0 } else { 1 })
+}
+
+/// Returns the Erlang sources given by `paths`, each of which is a source or a directory of them
+fn find_sources(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut sources = vec![];
+    for path in paths {
+        if path.is_dir() {
+            for entry in walkdir::WalkDir::new(path).sort_by_file_name() {
+                let entry = entry?;
+                if entry.file_type().is_file() && is_erlang_source(entry.path()) {
+                    sources.push(entry.into_path());
+                }
+            }
+        } else if is_erlang_source(path) && path.is_file() {
+            sources.push(path.clone());
+        } else {
+            bail!(
+                "{} is neither an Erlang source nor a directory",
+                path.display()
+            );
+        }
+    }
+    Ok(sources)
+}
+
+fn is_erlang_source(path: &Path) -> bool {
+    path.extension().map(|ext| ext == "erl").unwrap_or(false)
+}
+
+/// A function whose tests are run
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TestFunction {
+    /// A `..._test/0` function, which is a test itself
+    Test(String),
+    /// A `..._test_/0` function, which returns an EUnit test representation
+    Generator(String),
+}
+impl TestFunction {
+    fn name(&self) -> &str {
+        match self {
+            Self::Test(name) | Self::Generator(name) => name.as_str(),
+        }
+    }
+}
+
+/// Finds the test functions defined in `source`
+///
+/// As with `run::declared_module`, the source is scanned rather than parsed, so a function is only
+/// found if its clauses begin a line, as is conventional.
+fn discover_tests(source: &str) -> Vec<TestFunction> {
+    let mut tests = vec![];
+    for line in source.lines() {
+        let name: String = line
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '@')
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+            continue;
+        }
+        let is_nullary_clause = line[name.len()..]
+            .trim_start()
+            .strip_prefix('(')
+            .and_then(|rest| rest.trim_start().strip_prefix(')'))
+            .map(|rest| {
+                let rest = rest.trim_start();
+                rest.starts_with("->") || rest.starts_with("when")
+            })
+            .unwrap_or(false);
+        if !is_nullary_clause {
+            continue;
+        }
+        let test = if name.ends_with("_test_") {
+            TestFunction::Generator(name)
+        } else if name.ends_with("_test") {
+            TestFunction::Test(name)
+        } else {
+            continue;
+        };
+        if !tests.contains(&test) {
+            tests.push(test);
+        }
+    }
+    tests
+}
+
+/// Exports `tests` from `source`, by appending an export attribute to the line of its module
+/// attribute, so that the line numbers of the source are preserved
+fn export_tests(path: &Path, source: &str, tests: &[TestFunction]) -> anyhow::Result<String> {
+    let mut exported = String::with_capacity(source.len() + 64);
+    let mut found = false;
+    for line in source.split_inclusive('\n') {
+        if found || !line.trim_start().starts_with("-module") {
+            exported.push_str(line);
+            continue;
+        }
+        found = true;
+        let (line, newline) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line, ""),
+        };
+        exported.push_str(line);
+        exported.push_str(" -export([");
+        for (i, test) in tests.iter().enumerate() {
+            if i > 0 {
+                exported.push_str(", ");
+            }
+            write!(&mut exported, "'{}'/0", test.name()).unwrap();
+        }
+        exported.push_str("]).");
+        exported.push_str(newline);
+    }
+    if !found {
+        bail!("{} has no module attribute", path.display());
+    }
+    Ok(exported)
+}
+
+/// A Common Test suite whose test cases are run
+#[derive(Debug)]
+struct Suite {
+    module: String,
+    /// The directory holding the data files of the suite
+    data_dir: PathBuf,
+    /// The optional callbacks defined by the suite, see `SUITE_CALLBACKS`
+    callbacks: Vec<&'static str>,
+}
+
+/// Finds the optional callbacks defined in the source of a suite
+///
+/// As with `discover_tests`, a callback is only found if its clauses begin a line.
+fn discover_callbacks(source: &str) -> Vec<&'static str> {
+    SUITE_CALLBACKS
+        .iter()
+        .copied()
+        .filter(|callback| {
+            source.lines().any(|line| {
+                line.strip_prefix(callback)
+                    .map(|rest| rest.trim_start().starts_with('('))
+                    .unwrap_or(false)
+            })
+        })
+        .collect()
+}
+
+/// Generates the `init` module which the runtime boots, and which runs the tests
+fn init_module(functions: &[(String, TestFunction)], suites: &[Suite], timeout_ms: u64) -> String {
+    let mut tests = String::new();
+    for (i, (module, test)) in functions.iter().enumerate() {
+        if i > 0 {
+            tests.push_str(",\n      ");
+        }
+        let kind = match test {
+            TestFunction::Test(_) => "test",
+            TestFunction::Generator(_) => "generator",
+        };
+        write!(&mut tests, "{{'{}', '{}', {}}}", module, test.name(), kind).unwrap();
+    }
+    let mut ct_suites = String::new();
+    for (i, suite) in suites.iter().enumerate() {
+        if i > 0 {
+            ct_suites.push_str(",\n      ");
+        }
+        let callbacks: Vec<String> = suite
+            .callbacks
+            .iter()
+            .map(|callback| format!("'{}'", callback))
+            .collect();
+        write!(
+            &mut ct_suites,
+            "{{'{}', {}, [{}]}}",
+            suite.module,
+            erlang_string(&suite.data_dir.display().to_string()),
+            callbacks.join(", ")
+        )
+        .unwrap();
+    }
+    format!(
+        "-module(init).\n\
+         -export([boot/1]).\n\
+         \n\
+         boot(Argv) ->\n    \
+             firefly_test:run(\n      [{tests}],\n      [{suites}],\n      {timeout},\n      \
+             Argv).\n",
+        tests = tests,
+        suites = ct_suites,
+        timeout = timeout_ms,
+    )
+}
+
+/// Quotes `s` as an Erlang string literal
+fn erlang_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The outcome of a test
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    /// The test raised the given exception, or a fixture around it failed
+    Failed(String),
+    /// The setup of a fixture failed, so its tests were not run
+    Cancelled(String),
+    /// The test case, or a callback around it, asked to be skipped for the given reason
+    Skipped(String),
+    /// The test ran for longer than the given number of milliseconds
+    TimedOut(u64),
+    /// The test executable exited while running the test, with the given status
+    Crashed(String),
+}
+impl Outcome {
+    /// The kind of outcome, as reported by the harness
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Passed => "passed",
+            Self::Failed(_) => "failed",
+            Self::Cancelled(_) => "cancelled",
+            Self::Skipped(_) => "skipped",
+            Self::TimedOut(_) => "timeout",
+            Self::Crashed(_) => "crashed",
+        }
+    }
+
+    /// Describes why the test did not pass
+    fn detail(&self) -> Option<String> {
+        match self {
+            Self::Passed => None,
+            Self::Failed(detail) | Self::Cancelled(detail) | Self::Skipped(detail) => {
+                Some(detail.clone())
+            }
+            Self::TimedOut(timeout_ms) => {
+                Some(format!("the test did not finish within {}ms", timeout_ms))
+            }
+            Self::Crashed(status) => Some(format!("the test executable exited with {}", status)),
+        }
+    }
+}
+
+/// The result of a test, as reported by the harness
+#[derive(Debug)]
+struct TestResult {
+    module: String,
+    name: String,
+    micros: u64,
+    outcome: Outcome,
+}
+
+/// A line written by the harness on standard error, see `firefly_test.erl`
+#[derive(Debug)]
+enum Report {
+    Start {
+        seq: u64,
+        module: String,
+        name: String,
+        timeout_ms: u64,
+    },
+    Result {
+        seq: u64,
+        result: TestResult,
+    },
+    Done,
+}
+impl Report {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.splitn(7, '\t').collect();
+        match fields.as_slice() {
+            ["done"] => Some(Self::Done),
+            ["start", seq, module, name, timeout_ms] => Some(Self::Start {
+                seq: seq.parse().ok()?,
+                module: module.to_string(),
+                name: name.to_string(),
+                timeout_ms: timeout_ms.parse().ok()?,
+            }),
+            [outcome, seq, module, name, micros, detail] => {
+                let outcome = match *outcome {
+                    "passed" => Outcome::Passed,
+                    "failed" => Outcome::Failed(detail.to_string()),
+                    "cancelled" => Outcome::Cancelled(detail.to_string()),
+                    "skipped" => Outcome::Skipped(detail.to_string()),
+                    _ => return None,
+                };
+                Some(Self::Result {
+                    seq: seq.parse().ok()?,
+                    result: TestResult {
+                        module: module.to_string(),
+                        name: name.to_string(),
+                        micros: micros.parse().ok()?,
+                        outcome,
+                    },
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Runs the test executable until every test has been reported
+///
+/// The timeout of each test is enforced here, by killing the executable once it has expired, and
+/// running it again from the next test. The same is done if the executable exits during a test.
+fn run_tests(
+    exe: &Path,
+    cwd: &Path,
+    runtime_args: &[OsString],
+    results_dir: &Path,
+    verbose: bool,
+) -> anyhow::Result<Vec<TestResult>> {
+    let mut results = vec![];
+    let mut skip = 0;
+    loop {
+        let mut child = Command::new(exe)
+            .args(runtime_args)
+            .arg(results_dir)
+            .arg(skip.to_string())
+            .current_dir(cwd)
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("unable to execute tests {}", exe.display()))?;
+        let (sender, receiver) = mpsc::channel();
+        let stderr = child.stderr.take().unwrap();
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines() {
+                match line {
+                    Ok(line) if sender.send(line).is_ok() => continue,
+                    _ => break,
+                }
+            }
+        });
+
+        let mut running: Option<(u64, TestResult, Instant, u64)> = None;
+        let mut done = false;
+        loop {
+            let line = match running {
+                Some((_, _, started, timeout_ms)) => {
+                    let deadline = started + Duration::from_millis(timeout_ms);
+                    receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let line = match line {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    let (seq, mut result, _, timeout_ms) = running.take().unwrap();
+                    result.micros = timeout_ms * 1000;
+                    result.outcome = Outcome::TimedOut(timeout_ms);
+                    report(&result, verbose);
+                    results.push(result);
+                    skip = seq + 1;
+                    break;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    let status = child.wait()?;
+                    if done {
+                        return Ok(results);
+                    }
+                    let Some((seq, mut result, started, _)) = running.take() else {
+                        bail!("tests exited with {}", status);
+                    };
+                    result.micros = started.elapsed().as_micros() as u64;
+                    result.outcome = Outcome::Crashed(status.to_string());
+                    report(&result, verbose);
+                    results.push(result);
+                    skip = seq + 1;
+                    break;
+                }
+            };
+
+            // Anything else written by the tests, e.g. a crash report, is passed through
+            let Some(index) = line.find(REPORT_PREFIX) else {
+                eprintln!("{}", line);
+                continue;
+            };
+            if index > 0 {
+                eprint!("{}", &line[..index]);
+            }
+            match Report::parse(&line[(index + REPORT_PREFIX.len())..]) {
+                Some(Report::Start {
+                    seq,
+                    module,
+                    name,
+                    timeout_ms,
+                }) => {
+                    let result = TestResult {
+                        module,
+                        name,
+                        micros: 0,
+                        outcome: Outcome::Passed,
+                    };
+                    running = Some((seq, result, Instant::now(), timeout_ms));
+                }
+                Some(Report::Result { seq, result }) => {
+                    running = None;
+                    report(&result, verbose);
+                    results.push(result);
+                    skip = seq + 1;
+                }
+                Some(Report::Done) => done = true,
+                None => eprintln!("{}", line),
+            }
+        }
+    }
+}
+
+/// Prints the result of a test as it is reported, which is only done for failures unless `verbose`
+fn report(result: &TestResult, verbose: bool) {
+    let duration = format_micros(result.micros);
+    match &result.outcome {
+        Outcome::Passed if verbose => {
+            println!("{}: {}...[{}] ok", result.module, result.name, duration)
+        }
+        Outcome::Passed => (),
+        Outcome::Failed(detail) => {
+            println!("{}: {}...*failed*", result.module, result.name);
+            println!("  {}", detail);
+        }
+        Outcome::Cancelled(detail) => {
+            println!("{}: {}...*cancelled*", result.module, result.name);
+            println!("  {}", detail);
+        }
+        Outcome::Skipped(detail) if verbose => {
+            println!("{}: {}...skipped", result.module, result.name);
+            println!("  {}", detail);
+        }
+        Outcome::Skipped(_) => (),
+        Outcome::TimedOut(timeout_ms) => {
+            println!("{}: {}...*timed out*", result.module, result.name);
+            println!(
+                "  the test did not finish within {}",
+                format_micros(timeout_ms * 1000)
+            );
+        }
+        Outcome::Crashed(status) => {
+            println!("{}: {}...*crashed*", result.module, result.name);
+            println!("  the test executable exited with {}", status);
+        }
+    }
+}
+
+fn format_micros(micros: u64) -> String {
+    match micros {
+        n if n >= 1_000_000 => format!("{:.3}s", n as f64 / 1e6),
+        n if n >= 1_000 => format!("{:.3}ms", n as f64 / 1e3),
+        n => format!("{}us", n),
+    }
+}
+
+/// The number of tests with each outcome, in the format of EUnit
+struct Summary {
+    passed: usize,
+    failed: usize,
+    cancelled: usize,
+    skipped: usize,
+}
+impl Summary {
+    fn new<'r>(results: impl Iterator<Item = &'r TestResult>) -> Self {
+        let mut summary = Self {
+            passed: 0,
+            failed: 0,
+            cancelled: 0,
+            skipped: 0,
+        };
+        for result in results {
+            match result.outcome {
+                Outcome::Passed => summary.passed += 1,
+                Outcome::Cancelled(_) => summary.cancelled += 1,
+                Outcome::Skipped(_) => summary.skipped += 1,
+                _ => summary.failed += 1,
+            }
+        }
+        summary
+    }
+
+    fn is_success(&self) -> bool {
+        self.failed == 0 && self.cancelled == 0
+    }
+}
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_success() && self.skipped == 0 {
+            match self.passed {
+                1 => writeln!(f, "  Test passed."),
+                n => writeln!(f, "  All {} tests passed.", n),
+            }
+        } else {
+            writeln!(
+                f,
+                "=======================================================\n  \
+                 Failed: {}.  Skipped: {}.  Passed: {}.",
+                self.failed,
+                self.cancelled + self.skipped,
+                self.passed
+            )
+        }
+    }
+}
+
+/// Renders `results` as a JUnit XML report, with a test suite for each module
+fn junit_report(results: &[TestResult]) -> String {
+    let mut modules: Vec<&str> = vec![];
+    for result in results {
+        if !modules.contains(&result.module.as_str()) {
+            modules.push(result.module.as_str());
+        }
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    for module in modules {
+        let suite: Vec<&TestResult> = results
+            .iter()
+            .filter(|result| result.module == module)
+            .collect();
+        let summary = Summary::new(suite.iter().copied());
+        let micros: u64 = suite.iter().map(|result| result.micros).sum();
+        writeln!(
+            &mut xml,
+            concat!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" ",
+                "skipped=\"{}\" time=\"{:.3}\">"
+            ),
+            escape(module),
+            suite.len(),
+            summary.failed,
+            summary.cancelled + summary.skipped,
+            micros as f64 / 1e6
+        )
+        .unwrap();
+        for result in suite {
+            write!(
+                &mut xml,
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                escape(module),
+                escape(&result.name),
+                result.micros as f64 / 1e6
+            )
+            .unwrap();
+            let element = match &result.outcome {
+                Outcome::Passed => {
+                    xml.push_str("/>\n");
+                    continue;
+                }
+                Outcome::Cancelled(_) | Outcome::Skipped(_) => "skipped",
+                _ => "failure",
+            };
+            writeln!(
+                &mut xml,
+                ">\n      <{} type=\"{}\" message=\"{}\"/>\n    </testcase>",
+                element,
+                result.outcome.kind(),
+                escape(&result.outcome.detail().unwrap_or_default())
+            )
+            .unwrap();
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+/// Writes `results` to `dir` as `results.json`, and as a table in `index.html`
+fn write_results(dir: &Path, results: &[TestResult]) -> anyhow::Result<()> {
+    let summary = Summary::new(results.iter());
+    let tests: Vec<_> = results
+        .iter()
+        .map(|result| {
+            json!({
+                "module": result.module,
+                "name": result.name,
+                "outcome": result.outcome.kind(),
+                "detail": result.outcome.detail(),
+                "time": result.micros as f64 / 1e6,
+            })
+        })
+        .collect();
+    let json = json!({
+        "passed": summary.passed,
+        "failed": summary.failed,
+        "skipped": summary.cancelled + summary.skipped,
+        "tests": tests,
+    });
+    std::fs::write(
+        dir.join("results.json"),
+        serde_json::to_string_pretty(&json)?,
+    )?;
+
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>Test Results</title>\n</head>\n<body>\n"
+    ));
+    writeln!(
+        &mut html,
+        "<p>Passed: {}. Failed: {}. Skipped: {}.</p>",
+        summary.passed,
+        summary.failed,
+        summary.cancelled + summary.skipped
+    )
+    .unwrap();
+    html.push_str(concat!(
+        "<table>\n<tr><th>Module</th><th>Test</th><th>Outcome</th><th>Time (s)</th>",
+        "<th>Detail</th></tr>\n"
+    ));
+    for result in results {
+        writeln!(
+            &mut html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.3}</td><td>{}</td></tr>",
+            escape(&result.module),
+            escape(&result.name),
+            result.outcome.kind(),
+            result.micros as f64 / 1e6,
+            escape(&result.outcome.detail().unwrap_or_default())
+        )
+        .unwrap();
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    std::fs::write(dir.join("index.html"), html)?;
+    Ok(())
+}
+
+/// Escapes `s` for use in an XML attribute value
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
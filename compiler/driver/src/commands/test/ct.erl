@@ -0,0 +1,58 @@
+%% The functions of the `ct` module of Common Test which are available to test cases run by
+%% `firefly test`. Logs are written to standard output, as there are no log files, and comments are
+%% written along with them, as they are not kept with the results.
+-module(ct).
+
+-export([pal/1, pal/2, pal/3, log/1, log/2, log/3, print/1, print/2, print/3]).
+-export([fail/1, fail/2, comment/1, comment/2, get_config/1, get_config/2, timetrap/1]).
+
+pal(Format) ->
+    pal(Format, []).
+
+pal(Format, Args) ->
+    io:format(standard_io, Format ++ "~n", Args).
+
+pal(_Category, Format, Args) ->
+    pal(Format, Args).
+
+log(Format) ->
+    pal(Format, []).
+
+log(Format, Args) ->
+    pal(Format, Args).
+
+log(_Category, Format, Args) ->
+    pal(Format, Args).
+
+print(Format) ->
+    pal(Format, []).
+
+print(Format, Args) ->
+    pal(Format, Args).
+
+print(_Category, Format, Args) ->
+    pal(Format, Args).
+
+%% Fails the current test case, as Common Test does
+fail(Reason) ->
+    erlang:exit({test_case_failed, Reason}).
+
+fail(Format, Args) ->
+    fail(io_lib:format(Format, Args)).
+
+comment(Comment) ->
+    pal("comment: ~tp", [Comment]).
+
+comment(Format, Args) ->
+    comment(io_lib:format(Format, Args)).
+
+%% There are no configuration files, so every variable is undefined
+get_config(Required) ->
+    get_config(Required, undefined).
+
+get_config(_Required, Default) ->
+    Default.
+
+%% Timetraps are enforced by `firefly test` for the whole test case, so they cannot be changed
+timetrap(_Timetrap) ->
+    ok.
@@ -0,0 +1,249 @@
+%% The Common Test runner of `firefly test`, which runs the test cases of `..._SUITE` modules,
+%% reporting each of them as `firefly_test` does for EUnit tests.
+%%
+%% The callbacks of a suite are called as they are by Common Test: `init_per_suite/1`,
+%% `init_per_group/2` and `init_per_testcase/2` return the configuration given to what they
+%% surround, or `{skip, Reason}` or `{fail, Reason}`, and are paired with the matching `end_...`
+%% callbacks. Groups are run in order, whatever their properties. Each test case is given its own
+%% private directory, `priv_dir`, below the results directory, and the `data_dir` of the suite is
+%% the `..._SUITE_data` directory next to its source.
+%%
+%% As test cases are numbered along with EUnit tests, and a run may be resumed after a test case
+%% times out, the number of each case must not depend on the outcome of another. So every case of a
+%% group or suite is reported, even when its initialization fails, and a number is reserved for the
+%% `end_...` callback of each, which is only reported if that callback fails.
+-module(firefly_ct).
+
+-export([run/4, config/2]).
+
+-record(suite, {module, callbacks, timeout, results_dir, skip}).
+
+%% Common Test gives each test case 30 minutes unless the suite sets a timetrap
+-define(DEFAULT_TIMETRAP, 1800000).
+
+%% Runs each `{Suite, DataDir, Callbacks}`, where `Callbacks` are the optional callbacks the suite
+%% defines, numbering its test cases from `Seq`, and returns the number following the last of them
+run([], _ResultsDir, _Skip, Seq) ->
+    Seq;
+run([{Module, DataDir, Callbacks} | Rest], ResultsDir, Skip, Seq) ->
+    Suite = #suite{module = Module,
+                   callbacks = Callbacks,
+                   results_dir = ResultsDir,
+                   skip = Skip},
+    Next = case firefly_test:apply_fun(fun() -> plan(Suite) end) of
+               {ok, {Timeout, Tree}} ->
+                   run_suite(Suite#suite{timeout = Timeout}, Seq, Tree, DataDir);
+               {error, Class, Reason} ->
+                   report(Suite, Seq, [all], failed, 0, {all_failed, {Class, Reason}})
+           end,
+    run(Rest, ResultsDir, Skip, Next).
+
+%% Returns the timetrap of the suite, and its test cases and groups as a tree of
+%% `{testcase, Case}` and `{group, Name, Properties, Members}`
+plan(#suite{module = Module, callbacks = Callbacks}) ->
+    Info = case member(suite, Callbacks) of
+               true -> Module:suite();
+               false -> []
+           end,
+    Groups = case member(groups, Callbacks) of
+                 true -> Module:groups();
+                 false -> []
+             end,
+    Timeout = case keyfind(timetrap, Info) of
+                  {timetrap, Timetrap} -> milliseconds(Timetrap);
+                  false -> ?DEFAULT_TIMETRAP
+              end,
+    {Timeout, expand(Module:all(), Groups)}.
+
+milliseconds({hours, Hours}) -> Hours * 3600000;
+milliseconds({minutes, Minutes}) -> Minutes * 60000;
+milliseconds({seconds, Seconds}) -> Seconds * 1000;
+milliseconds(Milliseconds) when is_integer(Milliseconds) -> Milliseconds.
+
+expand(Entries, Groups) ->
+    [expand_entry(Entry, Groups) || Entry <- Entries].
+
+expand_entry(Case, _Groups) when is_atom(Case) ->
+    {testcase, Case};
+expand_entry({testcase, Case, _Properties}, _Groups) ->
+    {testcase, Case};
+expand_entry({group, Name}, Groups) ->
+    expand_group(Name, Groups);
+expand_entry({group, Name, _Properties}, Groups) ->
+    expand_group(Name, Groups);
+expand_entry({group, Name, _Properties, _SubGroups}, Groups) ->
+    expand_group(Name, Groups);
+%% A group may be defined among the members of another
+expand_entry({Name, Properties, Members}, Groups) when is_atom(Name), is_list(Members) ->
+    {group, Name, Properties, expand(Members, Groups)};
+expand_entry({Name, Members}, Groups) when is_atom(Name), is_list(Members) ->
+    {group, Name, [], expand(Members, Groups)};
+expand_entry(Other, _Groups) ->
+    erlang:error({bad_test_spec, Other}).
+
+expand_group(Name, Groups) ->
+    case keyfind(Name, Groups) of
+        {Name, Properties, Members} -> {group, Name, Properties, expand(Members, Groups)};
+        {Name, Members} -> {group, Name, [], expand(Members, Groups)};
+        false -> erlang:error({unknown_group, Name})
+    end.
+
+%% Returns the number of test cases in a tree, plus one for every `end_...` callback it contains
+count({testcase, _Case}) ->
+    1;
+count({group, _Name, _Properties, Members}) ->
+    count(Members) + 1;
+count([]) ->
+    0;
+count([Entry | Rest]) ->
+    count(Entry) + count(Rest).
+
+run_suite(Suite = #suite{skip = Skip}, Seq, Tree, DataDir) ->
+    case Seq + count(Tree) + 1 =< Skip of
+        true ->
+            Seq + count(Tree) + 1;
+        false ->
+            Config0 = [{data_dir, DataDir}],
+            case callback(Suite, init_per_suite, [Config0], Config0) of
+                {ok, Config} ->
+                    Next = run_tree(Suite, Seq, Tree, [], Config),
+                    finish(Suite, Next, end_per_suite, [Config], []);
+                {skip, Reason} ->
+                    skip_tree(Suite, Seq, Tree, [], skipped, {init_per_suite, Reason}) + 1;
+                {fail, Reason} ->
+                    Detail = {init_per_suite_failed, Reason},
+                    skip_tree(Suite, Seq, Tree, [], cancelled, Detail) + 1
+            end
+    end.
+
+run_tree(_Suite, Seq, [], _Path, _Config) ->
+    Seq;
+run_tree(Suite = #suite{skip = Skip}, Seq, [Entry | Rest], Path, Config) ->
+    Next = case Seq + count(Entry) =< Skip of
+               true -> Seq + count(Entry);
+               false -> run_entry(Suite, Seq, Entry, Path, Config)
+           end,
+    run_tree(Suite, Next, Rest, Path, Config).
+
+run_entry(Suite, Seq, {testcase, Case}, Path, Config) ->
+    run_case(Suite, Seq, Case, Path, Config);
+run_entry(Suite, Seq, {group, Name, Properties, Members}, Path, Config0) ->
+    GroupPath = Path ++ [Name],
+    Config1 = [{tc_group_properties, [{name, Name} | Properties]} | Config0],
+    case callback(Suite, init_per_group, [Name, Config1], Config1) of
+        {ok, Config} ->
+            Next = run_tree(Suite, Seq, Members, GroupPath, Config),
+            finish(Suite, Next, end_per_group, [Name, Config], GroupPath);
+        {skip, Reason} ->
+            skip_tree(Suite, Seq, Members, GroupPath, skipped, {init_per_group, Reason}) + 1;
+        {fail, Reason} ->
+            Detail = {init_per_group_failed, Reason},
+            skip_tree(Suite, Seq, Members, GroupPath, cancelled, Detail) + 1
+    end.
+
+%% Reports every test case in a tree as not having been run
+skip_tree(_Suite, Seq, [], _Path, _Outcome, _Detail) ->
+    Seq;
+skip_tree(Suite, Seq, [{testcase, Case} | Rest], Path, Outcome, Detail) ->
+    Next = report(Suite, Seq, Path ++ [Case], Outcome, 0, Detail),
+    skip_tree(Suite, Next, Rest, Path, Outcome, Detail);
+skip_tree(Suite, Seq, [{group, Name, _Properties, Members} | Rest], Path, Outcome, Detail) ->
+    Next = skip_tree(Suite, Seq, Members, Path ++ [Name], Outcome, Detail) + 1,
+    skip_tree(Suite, Next, Rest, Path, Outcome, Detail).
+
+%% Calls an `end_...` callback, reporting it only if it fails
+finish(Suite, Seq, Callback, Args, Path) ->
+    case callback(Suite, Callback, Args, ok) of
+        {fail, Reason} -> report(Suite, Seq, Path ++ [Callback], failed, 0, Reason);
+        _ -> Seq + 1
+    end.
+
+run_case(#suite{skip = Skip}, Seq, _Case, _Path, _Config) when Seq < Skip ->
+    Seq + 1;
+run_case(Suite = #suite{module = Module}, Seq, Case, Path, Config0) ->
+    Name = name(Path ++ [Case]),
+    Config1 = [{priv_dir, priv_dir(Suite, Path ++ [Case])} | Config0],
+    firefly_test:report_start(Seq, Module, Name, Suite#suite.timeout),
+    Start = erlang:monotonic_time(microsecond),
+    {Outcome, Detail} =
+        case callback(Suite, init_per_testcase, [Case, Config1], Config1) of
+            {ok, Config} ->
+                Result = firefly_test:apply_fun(fun() -> Module:Case(Config) end),
+                _ = callback(Suite, end_per_testcase, [Case, Config], ok),
+                outcome(Result);
+            {skip, Reason} ->
+                {skipped, {init_per_testcase, Reason}};
+            {fail, Reason} ->
+                {cancelled, {init_per_testcase_failed, Reason}}
+        end,
+    Micros = erlang:monotonic_time(microsecond) - Start,
+    firefly_test:report_result(Outcome, Seq, Module, Name, Micros, Detail),
+    Seq + 1.
+
+%% Classifies the result of a test case, as Common Test does
+outcome({ok, {skip, Reason}}) -> {skipped, Reason};
+outcome({ok, {fail, Reason}}) -> {failed, Reason};
+outcome({ok, {comment, Comment}}) -> {passed, {comment, Comment}};
+outcome({ok, _}) -> {passed, ok};
+outcome({error, exit, {test_case_failed, Reason}}) -> {failed, Reason};
+outcome({error, Class, Reason}) -> {failed, {Class, Reason}}.
+
+%% Calls an optional callback of the suite, returning `{ok, Default}` if it is not defined
+callback(#suite{module = Module, callbacks = Callbacks}, Callback, Args, Default) ->
+    case member(Callback, Callbacks) of
+        false ->
+            {ok, Default};
+        true ->
+            case firefly_test:apply_fun(fun() -> erlang:apply(Module, Callback, Args) end) of
+                {ok, {skip, Reason}} -> {skip, Reason};
+                {ok, {skip_and_save, Reason, _Config}} -> {skip, Reason};
+                {ok, {fail, Reason}} -> {fail, Reason};
+                {ok, Value} -> {ok, Value};
+                {error, Class, Reason} -> {fail, {Class, Reason}}
+            end
+    end.
+
+report(#suite{skip = Skip}, Seq, _Path, _Outcome, _Micros, _Detail) when Seq < Skip ->
+    Seq + 1;
+report(#suite{module = Module}, Seq, Path, Outcome, Micros, Detail) ->
+    firefly_test:report_result(Outcome, Seq, Module, name(Path), Micros, Detail),
+    Seq + 1.
+
+%% Names a test case by the groups it is in, and its own name, separated by colons
+name([Name]) ->
+    io_lib:format("~s", [Name]);
+name([Group | Rest]) ->
+    io_lib:format("~s:~ts", [Group, name(Rest)]).
+
+%% Creates the private directory of a test case, i.e. `ResultsDir/Suite/Group.../Case/`
+priv_dir(#suite{module = Module, results_dir = ResultsDir}, Path) ->
+    make_dirs(erlang:binary_to_list(ResultsDir), [Module | Path]).
+
+make_dirs(Dir, []) ->
+    Dir ++ "/";
+make_dirs(Dir, [Name | Rest]) ->
+    Child = io_lib:format("~s/~s", [Dir, Name]),
+    _ = file:make_dir(Child),
+    make_dirs(Child, Rest).
+
+%% Returns the value of `Key` in `Config`, or `undefined`, see `?config` in `ct.hrl`
+config(Key, Config) ->
+    case keyfind(Key, Config) of
+        {Key, Value} -> Value;
+        false -> undefined
+    end.
+
+member(_Elem, []) ->
+    false;
+member(Elem, [Elem | _]) ->
+    true;
+member(Elem, [_ | Rest]) ->
+    member(Elem, Rest).
+
+%% Finds the first tuple whose first element is `Key`
+keyfind(_Key, []) ->
+    false;
+keyfind(Key, [Tuple | _]) when tuple_size(Tuple) > 0, element(1, Tuple) =:= Key ->
+    Tuple;
+keyfind(Key, [_ | Rest]) ->
+    keyfind(Key, Rest).
@@ -0,0 +1,196 @@
+%% The harness of `firefly test`, which runs EUnit tests and reports each of them as a line on
+%% standard error, from where `firefly test` collects them.
+%%
+%% Tests are numbered in the order they are reached. Before a test runs, a line reporting its start
+%% and timeout is written, and once it has run, a line reporting its outcome:
+%%
+%%   firefly_test <TAB> start <TAB> Seq <TAB> Module <TAB> Name <TAB> TimeoutMilliseconds
+%%   firefly_test <TAB> Outcome <TAB> Seq <TAB> Module <TAB> Name <TAB> Microseconds <TAB> Detail
+%%
+%% where `Outcome` is `passed`, `failed`, `skipped` or `cancelled`. A process cannot be interrupted
+%% by another in this runtime, so timeouts are enforced by `firefly test` itself: a test which runs
+%% for too long is killed along with the executable, which is then run again, skipping the tests
+%% which were already reported. The number of tests to skip is given as the last argument of the
+%% executable, preceded by the results directory, see `firefly_ct`.
+-module(firefly_test).
+
+-export([run/4, report_start/4, report_result/6, apply_fun/1]).
+
+-record(ctx, {module, function, base, description, timeout, skip}).
+
+%% Runs each `{Module, Function, Kind}`, where `Kind` is `test` for a `..._test/0` function, or
+%% `generator` for a `..._test_/0` function, given the default timeout in milliseconds, followed
+%% by the test cases of each Common Test suite, see `firefly_ct:run/4`
+run(Functions, Suites, Timeout, Argv) ->
+    [SkipArg, ResultsDir | _] = reverse(Argv, []),
+    Skip = to_integer(SkipArg),
+    Next = run_functions(Functions, Timeout, Skip, 0),
+    firefly_ct:run(Suites, ResultsDir, Skip, Next),
+    io:format(standard_error, "firefly_test\tdone~n", []),
+    erlang:halt(0).
+
+run_functions([], _Timeout, _Skip, Seq) ->
+    Seq;
+run_functions([{Module, Function, Kind} | Rest], Timeout, Skip, Seq) ->
+    Ctx = #ctx{module = Module, function = Function, timeout = Timeout, skip = Skip},
+    Fun = fun() -> Module:Function() end,
+    Next = case Kind of
+               test -> run_test(Ctx, Seq, undefined, Fun);
+               generator -> generate(Ctx#ctx{base = Seq}, Seq, Fun)
+           end,
+    run_functions(Rest, Timeout, Skip, Next).
+
+%% Runs the tests returned by `Generator`, or reports its failure
+generate(Ctx, Seq, Generator) ->
+    case apply_fun(Generator) of
+        {ok, Tests} ->
+            tests(Ctx, Seq, Tests);
+        {error, Class, Reason} ->
+            result(Ctx, Seq, undefined, failed, 0, {generator_failed, {Class, Reason}})
+    end.
+
+%% Runs the tests of an EUnit test representation, returning the number of the next test
+tests(_Ctx, Seq, []) ->
+    Seq;
+tests(Ctx, Seq, [Tests | Rest]) ->
+    tests(Ctx, tests(Ctx, Seq, Tests), Rest);
+tests(Ctx, Seq, Fun) when is_function(Fun, 0) ->
+    run_test(Ctx, Seq, undefined, Fun);
+tests(Ctx, Seq, {Line, Fun}) when is_integer(Line), is_function(Fun, 0) ->
+    run_test(Ctx, Seq, Line, Fun);
+tests(Ctx, Seq, {generator, Generator}) when is_function(Generator, 0) ->
+    generate(Ctx, Seq, Generator);
+tests(Ctx, Seq, {generator, Module, Function}) ->
+    generate(Ctx, Seq, fun() -> Module:Function() end);
+tests(Ctx, Seq, {timeout, Seconds, Tests}) when is_number(Seconds) ->
+    tests(Ctx#ctx{timeout = round(Seconds * 1000)}, Seq, Tests);
+%% Tests are always run in order, in the process running the harness
+tests(Ctx, Seq, {spawn, Tests}) ->
+    tests(Ctx, Seq, Tests);
+tests(Ctx, Seq, {inorder, Tests}) ->
+    tests(Ctx, Seq, Tests);
+tests(Ctx, Seq, {inparallel, Tests}) ->
+    tests(Ctx, Seq, Tests);
+tests(Ctx, Seq, {inparallel, _N, Tests}) ->
+    tests(Ctx, Seq, Tests);
+tests(Ctx, Seq, {setup, Setup, Instantiator}) ->
+    setup(Ctx, Seq, Setup, fun(_) -> ok end, Instantiator);
+tests(Ctx, Seq, {setup, Setup, Cleanup, Instantiator}) ->
+    setup(Ctx, Seq, Setup, Cleanup, Instantiator);
+tests(Ctx, Seq, {setup, _Where, Setup, Cleanup, Instantiator}) ->
+    setup(Ctx, Seq, Setup, Cleanup, Instantiator);
+tests(Ctx, Seq, {foreach, Setup, Instantiators}) ->
+    foreach(Ctx, Seq, Setup, fun(_) -> ok end, Instantiators);
+tests(Ctx, Seq, {foreach, Setup, Cleanup, Instantiators}) ->
+    foreach(Ctx, Seq, Setup, Cleanup, Instantiators);
+tests(Ctx, Seq, {foreach, _Where, Setup, Cleanup, Instantiators}) ->
+    foreach(Ctx, Seq, Setup, Cleanup, Instantiators);
+tests(Ctx, Seq, {Module, Function}) when is_atom(Module), is_atom(Function) ->
+    run_test(Ctx, Seq, undefined, fun() -> Module:Function() end);
+tests(Ctx, Seq, {Description, Tests}) when is_list(Description); is_binary(Description) ->
+    tests(Ctx#ctx{description = Description}, Seq, Tests);
+tests(Ctx, Seq, Other) ->
+    result(Ctx, Seq, undefined, failed, 0, {bad_test, Other}).
+
+%% Runs the tests instantiated from the result of `Setup`, followed by `Cleanup`
+%%
+%% If `Setup` fails, the tests cannot be instantiated, so their cancellation is reported as one.
+setup(Ctx, Seq, Setup, Cleanup, Instantiator) ->
+    case apply_fun(Setup) of
+        {ok, State} ->
+            Next = case apply_fun(fun() -> instantiate(Instantiator, State) end) of
+                       {ok, Tests} ->
+                           tests(Ctx, Seq, Tests);
+                       {error, Class, Reason} ->
+                           Detail = {instantiation_failed, {Class, Reason}},
+                           result(Ctx, Seq, undefined, failed, 0, Detail)
+                   end,
+            case apply_fun(fun() -> Cleanup(State) end) of
+                {ok, _} ->
+                    Next;
+                {error, Class, Reason} ->
+                    result(Ctx, Next, undefined, failed, 0, {cleanup_failed, {Class, Reason}})
+            end;
+        {error, Class, Reason} ->
+            result(Ctx, Seq, undefined, cancelled, 0, {setup_failed, {Class, Reason}})
+    end.
+
+foreach(_Ctx, Seq, _Setup, _Cleanup, []) ->
+    Seq;
+foreach(Ctx, Seq, Setup, Cleanup, [Instantiator | Rest]) ->
+    Next = setup(Ctx, Seq, Setup, Cleanup, Instantiator),
+    foreach(Ctx, Next, Setup, Cleanup, Rest).
+
+instantiate(Instantiator, State) when is_function(Instantiator, 1) ->
+    Instantiator(State);
+instantiate(Tests, _State) ->
+    Tests.
+
+%% Runs a single test, unless it was reported by a previous run of the executable
+run_test(#ctx{skip = Skip}, Seq, _Line, _Fun) when Seq < Skip ->
+    Seq + 1;
+run_test(Ctx, Seq, Line, Fun) ->
+    report_start(Seq, Ctx#ctx.module, name(Ctx, Seq, Line), Ctx#ctx.timeout),
+    Start = erlang:monotonic_time(microsecond),
+    Result = apply_fun(Fun),
+    Micros = erlang:monotonic_time(microsecond) - Start,
+    case Result of
+        {ok, _} ->
+            result(Ctx, Seq, Line, passed, Micros, ok);
+        {error, Class, Reason} ->
+            result(Ctx, Seq, Line, failed, Micros, {Class, Reason})
+    end.
+
+%% Reports the outcome of the test numbered `Seq`, returning the number of the next test
+result(#ctx{skip = Skip}, Seq, _Line, _Outcome, _Micros, _Detail) when Seq < Skip ->
+    Seq + 1;
+result(Ctx, Seq, Line, Outcome, Micros, Detail) ->
+    report_result(Outcome, Seq, Ctx#ctx.module, name(Ctx, Seq, Line), Micros, Detail),
+    Seq + 1.
+
+report_start(Seq, Module, Name, Timeout) ->
+    io:format(standard_error, "firefly_test\tstart\t~w\t~s\t~ts\t~w~n",
+              [Seq, Module, Name, Timeout]).
+
+report_result(Outcome, Seq, Module, Name, Micros, Detail) ->
+    io:format(standard_error, "firefly_test\t~s\t~w\t~s\t~ts\t~w\t~w~n",
+              [Outcome, Seq, Module, Name, Micros, Detail]).
+
+%% Names a test after the function defining it, followed, if it was returned by a generator, by its
+%% position among the tests of the generator, and its line and description, if it has them
+name(#ctx{function = Function, base = undefined}, _Seq, _Line) ->
+    io_lib:format("~s", [Function]);
+name(#ctx{function = Function, base = Base, description = Description}, Seq, Line) ->
+    Index = Seq - Base + 1,
+    case {Line, Description} of
+        {undefined, undefined} ->
+            io_lib:format("~s/~w", [Function, Index]);
+        {undefined, _} ->
+            io_lib:format("~s/~w: ~ts", [Function, Index, Description]);
+        {_, undefined} ->
+            io_lib:format("~s/~w line ~w", [Function, Index, Line]);
+        _ ->
+            io_lib:format("~s/~w line ~w: ~ts", [Function, Index, Line, Description])
+    end.
+
+apply_fun(Fun) ->
+    try Fun() of
+        Value ->
+            {ok, Value}
+    catch
+        Class:Reason ->
+            {error, Class, Reason}
+    end.
+
+reverse([], Acc) ->
+    Acc;
+reverse([Elem | Rest], Acc) ->
+    reverse(Rest, [Elem | Acc]).
+
+to_integer(Arg) ->
+    digits(erlang:binary_to_list(Arg), 0).
+
+digits([], N) ->
+    N;
+digits([C | Rest], N) ->
+    digits(Rest, N * 10 + (C - $0)).
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+
+use firefly_beam::serialization::etf::{Atom, Term};
+
+/// The main entry point for the 'trace-dump' command
+///
+/// The input is a trace file, as written by `dbg:trace_port(file, Filename)` in a Firefly
+/// executable or in OTP: a sequence of trace messages in the external term format, each preceded
+/// by a zero byte and its size as a 32-bit big-endian integer, or a record of the number of
+/// messages dropped, `<<1, Count:32>>`. Each message is printed as the default tracer of `dbg`
+/// prints it, or as it is with `--raw`, optionally only those of the process given with `--pid`.
+/// With `--summary`, the number of messages by event, process and called function is printed
+/// instead, which is what is usually looked at first in a large trace.
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<()> {
+    let path = cwd.join(matches.value_of("file").unwrap());
+    let raw = matches.is_present("raw");
+    let summary = matches.is_present("summary");
+    let pid = matches.value_of("pid");
+
+    let bytes = fs::read(&path).with_context(|| format!("unable to read {}", path.display()))?;
+    let mut summary = summary.then(Summary::default);
+    let mut rest = bytes.as_slice();
+    let mut offset = 0;
+    while !rest.is_empty() {
+        let (record, next) = next_record(rest).ok_or_else(|| {
+            anyhow!(
+                "{}: truncated trace file at byte {}",
+                path.display(),
+                offset
+            )
+        })?;
+        offset += rest.len() - next.len();
+        rest = next;
+        let message = match record {
+            Record::Message(bytes) => Term::decode(bytes).map_err(|err| {
+                anyhow!(
+                    "{}: invalid trace message at byte {}: {}",
+                    path.display(),
+                    offset,
+                    err
+                )
+            })?,
+            Record::Dropped(count) => {
+                match summary.as_mut() {
+                    Some(summary) => summary.dropped += count as u64,
+                    None => println!("*** {} trace messages were dropped", count),
+                }
+                continue;
+            }
+        };
+        let event = Event::parse(&message);
+        if let (Some(pid), Some(event)) = (pid, event.as_ref()) {
+            if Pretty(event.pid).to_string() != pid {
+                continue;
+            }
+        }
+        match (summary.as_mut(), event) {
+            (Some(summary), event) => summary.add(event),
+            (None, Some(event)) if !raw => println!("{}", event),
+            (None, _) => println!("{}", Pretty(&message)),
+        }
+    }
+
+    if let Some(summary) = summary {
+        print!("{}", summary);
+    }
+    Ok(())
+}
+
+enum Record<'a> {
+    Message(&'a [u8]),
+    Dropped(u32),
+}
+
+/// Splits the next record off `bytes`, returning it and the bytes following it
+fn next_record(bytes: &[u8]) -> Option<(Record<'_>, &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+    let size = u32::from_be_bytes(rest.get(..4)?.try_into().unwrap());
+    let rest = &rest[4..];
+    match tag {
+        0 => {
+            let size = size as usize;
+            let message = rest.get(..size)?;
+            Some((Record::Message(message), &rest[size..]))
+        }
+        1 => Some((Record::Dropped(size), rest)),
+        _ => None,
+    }
+}
+
+/// A trace message, `{trace, Pid, Tag, Info...}` or `{trace_ts, Pid, Tag, Info..., Timestamp}`
+struct Event<'a> {
+    pid: &'a Term,
+    tag: &'a str,
+    info: &'a [Term],
+    timestamp: Option<&'a Term>,
+}
+impl<'a> Event<'a> {
+    fn parse(message: &'a Term) -> Option<Self> {
+        let elements = match message {
+            Term::Tuple(tuple) => tuple.elements.as_slice(),
+            _ => return None,
+        };
+        let (elements, timestamp) = match elements {
+            [Term::Atom(kind), rest @ .., timestamp] if kind.name == "trace_ts" => {
+                (rest, Some(timestamp))
+            }
+            [Term::Atom(kind), rest @ ..] if kind.name == "trace" => (rest, None),
+            _ => return None,
+        };
+        match elements {
+            [pid, Term::Atom(tag), info @ ..] => Some(Self {
+                pid,
+                tag: tag.name.as_str(),
+                info,
+                timestamp,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the function called, as `M:F/A`, if this is a call
+    fn function(&self) -> Option<String> {
+        let call = match (self.tag, self.info) {
+            ("call", [Term::Tuple(call), ..]) => call,
+            _ => return None,
+        };
+        match call.elements.as_slice() {
+            [module, function, Term::List(args)] => Some(format!(
+                "{}:{}/{}",
+                Pretty(module),
+                Pretty(function),
+                args.elements.len()
+            )),
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}) ", Pretty(self.pid))?;
+        match (self.tag, self.info) {
+            ("call", [Term::Tuple(call), rest @ ..]) => {
+                match call.elements.as_slice() {
+                    [module, function, Term::List(args)] => {
+                        write!(f, "call {}:{}(", Pretty(module), Pretty(function))?;
+                        for (i, arg) in args.elements.iter().enumerate() {
+                            let sep = if i == 0 { "" } else { "," };
+                            write!(f, "{}{}", sep, Pretty(arg))?;
+                        }
+                        write!(f, ")")?;
+                    }
+                    _ => write!(f, "call {}", Pretty(&self.info[0]))?,
+                }
+                for extra in rest {
+                    write!(f, " ({})", Pretty(extra))?;
+                }
+            }
+            ("return_from", [mfa, value]) => {
+                write!(f, "returned from {} -> {}", Mfa(mfa), Pretty(value))?
+            }
+            ("exception_from", [mfa, value]) => {
+                write!(f, "exception_from {} {}", Mfa(mfa), Pretty(value))?
+            }
+            ("send", [message, to]) => write!(f, "{} ! {}", Pretty(to), Pretty(message))?,
+            ("receive", [message]) => write!(f, "<< {}", Pretty(message))?,
+            (tag, info) => {
+                write!(f, "{}", tag)?;
+                for (i, term) in info.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { "," }, Pretty(term))?;
+                }
+            }
+        }
+        match self.timestamp {
+            Some(timestamp) => write!(f, " (Timestamp: {})", Pretty(timestamp)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The number of trace messages by event, process and called function
+#[derive(Default)]
+struct Summary {
+    total: u64,
+    /// Messages which are not trace messages, e.g. sequential trace messages
+    other: u64,
+    dropped: u64,
+    events: BTreeMap<String, u64>,
+    processes: BTreeMap<String, u64>,
+    functions: BTreeMap<String, u64>,
+}
+impl Summary {
+    fn add(&mut self, event: Option<Event<'_>>) {
+        self.total += 1;
+        let event = match event {
+            Some(event) => event,
+            None => {
+                self.other += 1;
+                return;
+            }
+        };
+        *self.events.entry(event.tag.to_string()).or_default() += 1;
+        *self
+            .processes
+            .entry(Pretty(event.pid).to_string())
+            .or_default() += 1;
+        if let Some(function) = event.function() {
+            *self.functions.entry(function).or_default() += 1;
+        }
+    }
+}
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} trace messages", self.total)?;
+        if self.other > 0 {
+            writeln!(f, "{} other messages", self.other)?;
+        }
+        if self.dropped > 0 {
+            writeln!(f, "{} dropped", self.dropped)?;
+        }
+        for (title, counts) in [
+            ("Events", &self.events),
+            ("Processes", &self.processes),
+            ("Calls", &self.functions),
+        ] {
+            if counts.is_empty() {
+                continue;
+            }
+            writeln!(f, "\n{}:", title)?;
+            let mut counts = counts.iter().collect::<Vec<_>>();
+            counts.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+            for (name, count) in counts {
+                writeln!(f, "  {:>10}  {}", count, name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `{M, F, A}` as `M:F/A`
+struct Mfa<'a>(&'a Term);
+impl fmt::Display for Mfa<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Term::Tuple(mfa) => match mfa.elements.as_slice() {
+                [m, function, arity] => {
+                    write!(f, "{}:{}/{}", Pretty(m), Pretty(function), Pretty(arity))
+                }
+                _ => write!(f, "{}", Pretty(self.0)),
+            },
+            term => write!(f, "{}", Pretty(term)),
+        }
+    }
+}
+
+/// Writes a term as `~p` would, without quoting atoms which need not be, and with local pids
+/// written as `<0.Id.Serial>`
+struct Pretty<'a>(&'a Term);
+impl fmt::Display for Pretty<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Term::Atom(atom) => write_atom(f, atom),
+            Term::Pid(pid) if pid.node.name == "nonode@nohost" => {
+                write!(f, "<0.{}.{}>", pid.id, pid.serial)
+            }
+            Term::Tuple(tuple) => {
+                write!(f, "{{")?;
+                write_elements(f, tuple.elements.as_slice())?;
+                write!(f, "}}")
+            }
+            Term::List(list) => {
+                write!(f, "[")?;
+                write_elements(f, list.elements.as_slice())?;
+                write!(f, "]")
+            }
+            Term::ImproperList(list) => {
+                write!(f, "[")?;
+                write_elements(f, list.elements.as_slice())?;
+                write!(f, "|{}]", Pretty(&list.last))
+            }
+            Term::Map(map) => {
+                write!(f, "#{{")?;
+                for (i, (key, value)) in map.entries.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(f, "{}{} => {}", sep, Pretty(key), Pretty(value))?;
+                }
+                write!(f, "}}")
+            }
+            term => write!(f, "{}", term),
+        }
+    }
+}
+
+fn write_elements(f: &mut fmt::Formatter, elements: &[Term]) -> fmt::Result {
+    for (i, element) in elements.iter().enumerate() {
+        let sep = if i == 0 { "" } else { "," };
+        write!(f, "{}{}", sep, Pretty(element))?;
+    }
+    Ok(())
+}
+
+fn write_atom(f: &mut fmt::Formatter, atom: &Atom) -> fmt::Result {
+    let name = atom.name.as_str();
+    let bare = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@');
+    if bare {
+        write!(f, "{}", name)
+    } else {
+        write!(f, "{}", atom)
+    }
+}
@@ -1,3 +1,5 @@
+use std::fs::File;
+use std::io;
 use std::sync::Arc;
 use std::thread::ThreadId;
 
@@ -9,6 +11,8 @@ use firefly_session::OutputType;
 use firefly_syntax_base::ApplicationMetadata;
 
 use super::prelude::*;
+use crate::cache;
+use crate::parser::APP_SPECS_MODULE;
 
 macro_rules! unwrap_or_bail {
     ($db:ident, $e:expr) => {
@@ -39,6 +43,67 @@ pub(super) fn compile<C>(
     input: InternedInput,
     app: Arc<ApplicationMetadata>,
 ) -> Result<Option<CompiledModule>, ErrorReported>
+where
+    C: Compiler,
+{
+    let options = db.options();
+    let input_info = db.lookup_intern_input(input);
+    let diagnostics = db.diagnostics();
+
+    // Reuse the artifacts of a previous build if nothing they depend on has changed since
+    let cache_key = cache::key(db, input);
+    let artifacts = cache_key
+        .as_ref()
+        .map(|_| cache::artifacts(&options, &input_info))
+        .unwrap_or_default();
+    if let Some(cached) = cache_key
+        .as_deref()
+        .and_then(|key| cache::lookup(&options, key, &artifacts))
+    {
+        // The frontend still runs, so that the diagnostics of the module are reported
+        db.input_core(input, app)?;
+        let module_name = db.input_ast(input)?.name();
+        let mut object = None;
+        let mut bytecode = None;
+        for (output_type, outfile) in artifacts {
+            let cached = cached.join(outfile.file_name().unwrap());
+            let outfile = db.emit_file_with_callback(outfile, |outfile| {
+                io::copy(&mut File::open(&cached)?, outfile)?;
+                Ok(())
+            })?;
+            match output_type {
+                OutputType::Object => object = Some(outfile),
+                OutputType::LLVMBitcode => bytecode = Some(outfile),
+                _ => (),
+            }
+        }
+        diagnostics.success("Fresh", format!("{}", &module_name));
+        if !options.output_types.should_codegen() {
+            return Ok(None);
+        }
+        return Ok(Some(CompiledModule {
+            name: module_name,
+            object,
+            dwarf_object: None,
+            bytecode,
+        }));
+    }
+
+    let compiled = generate(db, thread_id, input, app)?;
+    // Every artifact requested has been emitted by now, whichever stage generation stopped at
+    if let Some(key) = cache_key.as_deref() {
+        cache::store(&options, key, &artifacts);
+    }
+    Ok(compiled)
+}
+
+/// Generates the artifacts requested for `input`, see `compile`
+fn generate<C>(
+    db: &C,
+    thread_id: ThreadId,
+    input: InternedInput,
+    app: Arc<ApplicationMetadata>,
+) -> Result<Option<CompiledModule>, ErrorReported>
 where
     C: Compiler,
 {
@@ -51,6 +116,7 @@ where
     let input_info = db.lookup_intern_input(input);
     let source_name = input_info.source_name();
     let diagnostics = db.diagnostics();
+
     let mlir_context = db.mlir_context(thread_id);
     let llvm_context = db.llvm_context(thread_id);
     let target_machine = db.target_machine(thread_id);
@@ -79,7 +145,7 @@ where
         return Ok(None);
     }
 
-    let module = db.input_mlir(thread_id, input, app)?;
+    let module = db.input_mlir(thread_id, input, app.clone())?;
 
     // Bail prior to lowering CIR dialect to LLVM dialect if we aren't
     // going to generate LLVM IR
@@ -121,17 +187,60 @@ where
         ));
         return Err(ErrorReported);
     }
-    db.maybe_emit_file_with_opts(&options, input, &module)?;
+    // The module in the CIR dialect was already emitted as `<input>.mlir`, so that both
+    // stages of the MLIR pipeline are kept, the LLVM dialect goes in `<input>.llvm.mlir`
+    if let Some(outfile) = options.maybe_emit(&input_info, OutputType::MLIR) {
+        debug!("emitting llvm dialect mlir for {:?}", input);
+        db.emit_file(outfile.with_extension("llvm.mlir"), &module)?;
+    }
 
     debug!("generating llvm for {:?} on {:?}", input, thread_id);
     let mut translation =
         TranslateMLIRToLLVMIR::new(llvm_context.borrow(), source_name.to_string());
     let module = unwrap_or_bail!(db, translation.run(&module));
 
+    // Record the ABI version this module was compiled against
+    firefly_codegen::abi::stamp_module(&options, *module, module_name.as_str());
+
+    // Record the source spans of call sites, so stack traces can report exact source ranges
+    let ssa = db.input_ssa(input, app)?;
+    firefly_codegen::spans::emit_span_table(
+        &options,
+        db.codemap(),
+        *module,
+        module_name.as_str(),
+        &ssa,
+    );
+
+    // Embed the boot manifest in the module bundling the application resources, see
+    // `firefly_rt::boot`
+    if module_name == APP_SPECS_MODULE {
+        firefly_codegen::boot::emit_boot_manifest(&options, *module);
+    }
+
+    // Ensure atom records are deduplicated across modules at link time
+    firefly_codegen::atoms::dedup_atoms(&options, *module);
+
+    // Ensure constant binaries, tuples and lists are deduplicated across modules at link time
+    firefly_codegen::literals::dedup_literals(&options, *module);
+
+    // Ensure long-running processes can be preempted by the scheduler
+    firefly_codegen::preemption::insert_yield_points(*module);
+
+    // Ensure calls in tail position never grow the stack, see `firefly_rt::function::apply`
+    firefly_codegen::tailcalls::guarantee_tail_calls(&options, *module);
+
+    // Raise exceptions by unwinding, and catch them via landing pads, see
+    // `firefly_rt::error::unwind`
+    firefly_codegen::exceptions::lower_exceptions(&options, *module);
+
     // Verify/optimize
     let mut optimizer = PassManagerPass::new(&options, target_machine.handle());
     let module = unwrap_or_bail!(db, optimizer.run(module));
 
+    // Record where terms live across calls are held, so processes can be collected precisely
+    firefly_codegen::stackmaps::emit_stack_maps(&options, *module, module_name.as_str());
+
     // Emit LLVM IR
     db.maybe_emit_file_with_opts(&options, input, &module)?;
 
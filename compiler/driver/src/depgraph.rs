@@ -0,0 +1,257 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use firefly_intern::Symbol;
+use firefly_session::{InputType, ProjectType};
+use firefly_syntax_base::{ApplicationMetadata, FunctionName};
+use firefly_syntax_ssa::callgraph::CallGraph;
+
+use crate::diagnostics::{CompilerDiagnostics, ErrorReported};
+use crate::interner::InternedInput;
+use crate::output::CompilerOutput;
+use crate::parser::prelude::Parser;
+use crate::parser::APP_SPECS_MODULE;
+
+/// The version of the JSON call graph format, bumped whenever its structure changes incompatibly
+const DEPGRAPH_VERSION: u32 = 1;
+
+/// The function the runtime calls to boot an executable
+const ENTRY: (&str, &str, u8) = ("init", "boot", 1);
+
+/// The result of analyzing the call graph of a build
+pub(crate) struct Analysis {
+    pub graph: CallGraph,
+    /// Functions which the runtime may invoke without a visible call, e.g. application callbacks
+    pub roots: BTreeSet<FunctionName>,
+    /// True if every input could be analyzed, i.e. no functions are defined outside the graph
+    pub closed: bool,
+}
+impl Analysis {
+    /// Returns the set of functions which may be called at runtime
+    pub fn live(&self) -> BTreeSet<FunctionName> {
+        self.graph.live(self.roots.iter().copied())
+    }
+}
+
+/// Builds the call graph of every module in the build, along with the set of functions that
+/// are entry points into it
+///
+/// Functions are considered roots if any of the following are true:
+///
+/// * It is the entry point of the executable, i.e. `init:boot/1`
+/// * It is the `-on_load` function of its module
+/// * Its module is compiled with `export_all`, in which case every function in the module is a root
+/// * It is exported from a module implementing a behaviour, as behaviour callbacks are invoked
+///   dynamically
+/// * It is exported from the application callback module, or from the generated `firefly_apps`
+///   module
+pub(crate) fn analyze<C>(
+    db: &C,
+    inputs: &[InternedInput],
+    app: Arc<ApplicationMetadata>,
+) -> Result<Analysis, ErrorReported>
+where
+    C: Parser,
+{
+    let options = db.options();
+
+    let mut graph = CallGraph::new();
+    let mut roots = BTreeSet::new();
+    let mut closed = true;
+    let mut callback_modules = BTreeSet::new();
+    callback_modules.insert(Symbol::intern(APP_SPECS_MODULE));
+    if let Some(module) = options.app.otp_module {
+        callback_modules.insert(module);
+    }
+
+    roots.insert(FunctionName::new(
+        Symbol::intern(ENTRY.0),
+        Symbol::intern(ENTRY.1),
+        ENTRY.2,
+    ));
+
+    for input in inputs.iter().copied() {
+        let ty = db.input_type(input);
+        match ty {
+            InputType::Erlang
+            | InputType::AbstractErlang
+            | InputType::CoreErlang
+            | InputType::SSA => (),
+            _ => {
+                closed = false;
+                continue;
+            }
+        }
+        let module = db.input_ssa(input, app.clone())?;
+        let name = module.name();
+        graph.add_module(&module);
+
+        let exports = module.exports();
+        if ty == InputType::SSA || callback_modules.contains(&name) {
+            roots.extend(exports.iter().copied());
+            continue;
+        }
+
+        // Behaviours and compile options aren't carried through Core Erlang, but any
+        // callbacks they imply are exported, so the exports are the only roots we need
+        if ty == InputType::CoreErlang {
+            roots.extend(exports.iter().copied());
+            if let Some(on_load) = db.input_cerl(input)?.on_load.as_ref() {
+                roots.insert(on_load.item.resolve(name));
+            }
+            continue;
+        }
+
+        let ast = db.input_ast(input)?;
+        if ast.compile.as_ref().map(|c| c.export_all).unwrap_or(false) {
+            roots.extend(module.functions.iter().map(|f| f.signature.mfa()));
+        }
+        if !ast.behaviours.is_empty() {
+            roots.extend(exports.iter().copied());
+        }
+        if let Some(on_load) = ast.on_load.as_ref() {
+            roots.insert(on_load.item.resolve(name));
+        }
+    }
+
+    Ok(Analysis {
+        graph,
+        roots,
+        closed,
+    })
+}
+
+/// Computes the set of live functions for builds with `-C prune-functions`, or `None` if pruning
+/// was not requested, or would be unsound for this build
+pub(crate) fn live_functions<C>(db: &C, analysis: &Analysis) -> Option<BTreeSet<FunctionName>>
+where
+    C: Parser,
+{
+    let options = db.options();
+    if !options.codegen_opts.prune_functions {
+        return None;
+    }
+
+    let diagnostics = db.diagnostics();
+    if options.app_type != ProjectType::Executable {
+        diagnostics.warn("-C prune-functions has no effect unless building an executable");
+        return None;
+    }
+    if !analysis.closed {
+        diagnostics.warn(
+            "-C prune-functions was ignored, as some inputs cannot be analyzed (e.g. .mlir sources)",
+        );
+        return None;
+    }
+
+    let live = analysis.live();
+    let total = analysis.graph.functions().len();
+    let pruned = analysis
+        .graph
+        .functions()
+        .iter()
+        .filter(|f| !live.contains(f))
+        .count();
+    diagnostics.notice(
+        "Pruned",
+        format!("{} of {} functions are unreachable", pruned, total),
+    );
+
+    Some(live)
+}
+
+/// Writes the call graph of the build to `outfile` in DOT format, and alongside it as JSON, i.e.
+/// to `<app>.depgraph.dot` and `<app>.depgraph.json`, see `Options::depgraph_file`
+///
+/// The JSON representation has the following structure:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "app": "myapp",
+///   "modules": [{"name": "mymod", "depends_on": ["lists", ...]}],
+///   "functions": [
+///     {
+///       "name": "mymod:start/0",
+///       "exported": true,
+///       "dynamic": false,
+///       "live": true,
+///       "calls": ["lists:map/2", ...]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// The `live` field is only present if dead functions are being pruned from this build.
+pub(crate) fn emit_depgraph<C>(
+    db: &C,
+    analysis: &Analysis,
+    live: Option<&BTreeSet<FunctionName>>,
+    outfile: PathBuf,
+) -> Result<PathBuf, ErrorReported>
+where
+    C: Parser,
+{
+    let options = db.options();
+    let graph = &analysis.graph;
+
+    let modules = graph
+        .functions()
+        .iter()
+        .filter_map(|f| f.module)
+        .collect::<BTreeSet<_>>();
+    let modules = modules
+        .into_iter()
+        .map(|module| {
+            let depends_on = graph
+                .module_dependencies(module)
+                .into_iter()
+                .map(|m| Value::String(m.as_str().get().to_string()))
+                .collect::<Vec<_>>();
+            json!({
+                "name": module.as_str().get(),
+                "depends_on": depends_on,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let functions = graph
+        .functions()
+        .iter()
+        .map(|function| {
+            let calls = graph
+                .callees(function)
+                .map(|callee| Value::String(callee.to_string()))
+                .collect::<Vec<_>>();
+            let mut value = json!({
+                "name": function.to_string(),
+                "exported": graph.exports().contains(function),
+                "dynamic": graph.is_dynamic(function),
+                "calls": calls,
+            });
+            if let Some(live) = live {
+                value["live"] = Value::Bool(live.contains(function));
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+
+    let depgraph = json!({
+        "version": DEPGRAPH_VERSION,
+        "app": options.app.name.as_str().get(),
+        "modules": modules,
+        "functions": functions,
+    });
+
+    db.emit_file_with_callback(outfile.with_extension("json"), |f| {
+        serde_json::to_writer_pretty(&mut *f, &depgraph)?;
+        Ok(())
+    })?;
+    db.emit_file_with_callback(outfile, |f| {
+        graph.write_dot(f)?;
+        Ok(())
+    })
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use firefly_diagnostics::Reporter;
 use firefly_util::diagnostics::{CodeMap, Diagnostic, DiagnosticsHandler};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -13,6 +14,13 @@ pub trait CompilerDiagnostics {
         self.diagnostics().emit(diag);
     }
 
+    /// Emits the diagnostics gathered by `reporter`
+    fn report_diagnostics(&self, reporter: &Reporter) {
+        for diag in reporter.diagnostics().iter() {
+            self.diagnostic(diag);
+        }
+    }
+
     #[inline]
     fn to_query_result<T>(&self, err: anyhow::Result<T>) -> Result<T, ErrorReported> {
         match err {
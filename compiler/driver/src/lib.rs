@@ -1,10 +1,13 @@
 #![deny(warnings)]
 
 mod argparser;
+mod cache;
 mod commands;
 mod compiler;
+mod depgraph;
 mod diagnostics;
 mod interner;
+mod manifest;
 mod output;
 mod parser;
 pub(crate) mod task;
@@ -20,8 +23,8 @@ use firefly_util::diagnostics::Emitter;
 use firefly_util::error::HelpRequested;
 
 pub const FIREFLY_RELEASE: &'static str = crate_version!();
-pub const FIREFLY_COMMIT_HASH: &'static str = env!("FIREFLY_COMMIT_HASH");
-pub const FIREFLY_COMMIT_DATE: &'static str = env!("FIREFLY_COMMIT_DATE");
+pub const FIREFLY_COMMIT_HASH: &'static str = firefly_session::build_info::COMMIT_HASH;
+pub const FIREFLY_COMMIT_DATE: &'static str = firefly_session::build_info::COMMIT_DATE;
 
 /// Runs the compiler using the provided working directory, args iterator, and default emitter
 ///
@@ -38,8 +41,15 @@ pub fn run_compiler_with_emitter(
     args: impl Iterator<Item = OsString>,
     emitter: Option<Arc<dyn Emitter>>,
 ) -> anyhow::Result<i32> {
+    // `--version --verbose` is handled before clap, which only prints the release for `--version`
+    let args = args.collect::<Vec<_>>();
+    if argparser::is_verbose_version(&args) {
+        commands::print::print_version(true);
+        return Ok(0);
+    }
+
     // Parse arguments
-    let matches = argparser::parse(args)?;
+    let matches = argparser::parse(args.into_iter())?;
 
     // Parse option groups first, as they can produce usage
     let c_opts = match parse_option_group::<CodegenOptions>(&matches)? {
@@ -65,6 +75,33 @@ pub fn run_compiler_with_emitter(
             emitter,
         )
         .map(|_| 0),
+        ("build", subcommand_matches) => {
+            commands::build::handle_command(subcommand_matches.unwrap(), cwd)
+        }
+        ("deps", subcommand_matches) => {
+            commands::deps::handle_command(subcommand_matches.unwrap(), cwd).map(|_| 0)
+        }
+        ("shell", subcommand_matches) => {
+            commands::shell::handle_command(subcommand_matches.unwrap(), cwd).map(|_| 0)
+        }
+        ("lsp", subcommand_matches) => {
+            commands::lsp::handle_command(subcommand_matches.unwrap(), cwd).map(|_| 0)
+        }
+        ("run", subcommand_matches) => {
+            commands::run::handle_command(subcommand_matches.unwrap(), cwd)
+        }
+        ("bench", subcommand_matches) => {
+            commands::bench::handle_command(subcommand_matches.unwrap(), cwd)
+        }
+        ("test", subcommand_matches) => {
+            commands::test::handle_command(subcommand_matches.unwrap(), cwd)
+        }
+        ("stubs", subcommand_matches) => {
+            commands::stubs::handle_command(subcommand_matches.unwrap(), cwd).map(|_| 0)
+        }
+        ("trace-dump", subcommand_matches) => {
+            commands::trace_dump::handle_command(subcommand_matches.unwrap(), cwd).map(|_| 0)
+        }
         (subcommand, _) => Err(anyhow!(format!("Unrecognized subcommand '{}'", subcommand))),
     }
 }
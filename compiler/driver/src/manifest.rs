@@ -0,0 +1,141 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use firefly_intern::Symbol;
+use firefly_session::InputType;
+use firefly_syntax_base::ApplicationMetadata;
+use firefly_syntax_ssa::{self as syntax_ssa, ConstantItem};
+
+use crate::diagnostics::ErrorReported;
+use crate::interner::InternedInput;
+use crate::output::CompilerOutput;
+use crate::parser::prelude::Parser;
+
+/// The version of the manifest format, bumped whenever its structure changes incompatibly
+const MANIFEST_VERSION: u32 = 1;
+
+/// Builds a manifest describing every module in the build, and writes it as JSON to `outfile`
+///
+/// The manifest has the following structure:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "app": "myapp",
+///   "target": "x86_64-unknown-linux-gnu",
+///   "atoms": ["ok", ...],
+///   "modules": [
+///     {
+///       "name": "mymod",
+///       "source": "src/mymod.erl",
+///       "exports": [{"name": "start", "arity": 0}, ...],
+///       "atoms": ["mymod", "ok", ...],
+///       "literals": [{"type": "binary", "value": "hello"}, ...]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Only inputs which are lowered through SSA IR (i.e. Erlang sources) are described.
+pub(crate) fn emit_manifest<C>(
+    db: &C,
+    inputs: &[InternedInput],
+    app: Arc<ApplicationMetadata>,
+    outfile: PathBuf,
+) -> Result<PathBuf, ErrorReported>
+where
+    C: Parser,
+{
+    let options = db.options();
+
+    let mut all_atoms = BTreeSet::new();
+    let mut modules = Vec::with_capacity(inputs.len());
+    for input in inputs.iter().copied() {
+        match db.input_type(input) {
+            InputType::Erlang
+            | InputType::AbstractErlang
+            | InputType::CoreErlang
+            | InputType::SSA => (),
+            _ => continue,
+        }
+        let module = db.input_ssa(input, app.clone())?;
+        let source = db.lookup_intern_input(input).source_name();
+        let atoms = module.atoms();
+        all_atoms.extend(atoms.iter().copied());
+        modules.push(module_manifest(&module, source.to_string(), &atoms));
+    }
+
+    let manifest = json!({
+        "version": MANIFEST_VERSION,
+        "app": options.app.name.as_str().get(),
+        "target": options.target.triple(),
+        "atoms": atoms_to_json(&all_atoms),
+        "modules": modules,
+    });
+
+    db.emit_file_with_callback(outfile, |f| {
+        serde_json::to_writer_pretty(&mut *f, &manifest)?;
+        Ok(())
+    })
+}
+
+fn module_manifest(module: &syntax_ssa::Module, source: String, atoms: &BTreeSet<Symbol>) -> Value {
+    let exports = module
+        .exports()
+        .into_iter()
+        .map(|name| {
+            json!({
+                "name": name.function.as_str().get(),
+                "arity": name.arity,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let constants = module.constants.borrow();
+    let literals = constants
+        .values()
+        .filter_map(literal_to_json)
+        .collect::<Vec<_>>();
+
+    json!({
+        "name": module.name().as_str().get(),
+        "source": source,
+        "exports": exports,
+        "atoms": atoms_to_json(atoms),
+        "literals": literals,
+    })
+}
+
+fn atoms_to_json(atoms: &BTreeSet<Symbol>) -> Value {
+    Value::Array(
+        atoms
+            .iter()
+            .map(|a| Value::String(a.as_str().get().to_string()))
+            .collect(),
+    )
+}
+
+/// Converts a constant to its manifest representation
+///
+/// Atoms are omitted, as they are described separately. Binary data which is not valid UTF-8
+/// is represented as hex, as is bitstring data, since neither can be represented as a JSON string.
+fn literal_to_json(constant: &ConstantItem) -> Option<Value> {
+    let (ty, value) = match constant {
+        ConstantItem::Atom(_) => return None,
+        ConstantItem::Bool(_) => return None,
+        ConstantItem::Integer(i) => ("integer", i.to_string()),
+        ConstantItem::Float(f) => ("float", f.to_string()),
+        ConstantItem::String(s) => ("binary", s.clone()),
+        ConstantItem::InternedStr(s) => ("binary", s.as_str().get().to_string()),
+        ConstantItem::Bytes(bytes) => match std::str::from_utf8(bytes.as_slice()) {
+            Ok(s) => ("binary", s.to_string()),
+            Err(_) => ("bytes", constant.to_string()),
+        },
+        ConstantItem::Bitstring(_) => ("bitstring", constant.to_string()),
+        ConstantItem::Term(_) => ("term", constant.to_string()),
+    };
+    Some(json!({ "type": ty, "value": value }))
+}
@@ -1,6 +1,7 @@
 mod queries;
 mod query_groups;
 
+pub(crate) use self::queries::APP_SPECS_MODULE;
 pub use self::query_groups::{Parser, ParserStorage};
 
 pub(crate) mod prelude {
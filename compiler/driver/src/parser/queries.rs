@@ -2,16 +2,23 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread::ThreadId;
 
+use anyhow::{anyhow, Context};
 use log::debug;
 
 use firefly_diagnostics::{Reporter, ToDiagnostic};
-use firefly_intern::symbols;
+use firefly_intern::{symbols, Symbol};
 use firefly_llvm as llvm;
 use firefly_mlir as mlir;
-use firefly_session::{Input, InputType};
+use firefly_session::{
+    build_info, Archive, ArchiveType, DebugInfo, Input, InputType, OptLevel, Options, OutputType,
+    ProjectType,
+};
 use firefly_syntax_base::ApplicationMetadata;
 use firefly_syntax_core as syntax_core;
-use firefly_syntax_erl::{self as syntax_erl, ParseConfig};
+use firefly_syntax_erl::{
+    self as syntax_erl, evaluator, Expr, Lexer, LexicalError, LexicalToken, Literal, MacroDef,
+    ParseConfig, Token,
+};
 use firefly_syntax_kernel as syntax_kernel;
 use firefly_syntax_ssa as syntax_ssa;
 use firefly_util::diagnostics::FileName;
@@ -28,14 +35,14 @@ macro_rules! unwrap_or_bail {
         }
     };
 
-    ($db:ident, $reporter:expr, $codemap:expr, $e:expr) => {
+    ($db:ident, $reporter:expr, $e:expr) => {
         match $e {
             Ok(result) => {
-                $reporter.print($codemap);
+                $db.report_diagnostics(&$reporter);
                 result
             }
             Err(ref e) => {
-                $reporter.print($codemap);
+                $db.report_diagnostics(&$reporter);
                 bail!($db, "{}", e);
             }
         }
@@ -64,11 +71,55 @@ where
     parse_config.no_warn = options.no_warn;
     parse_config.include_paths = options.include_path.clone();
     parse_config.code_paths = Default::default();
+    // Archives are unpacked as `<archives_dir>/<app>`, so they can be resolved by `include_lib`
+    if let Ok(archives) = db.archives() {
+        for archive in archives.iter() {
+            parse_config.include_paths.extend(archive.include_paths());
+        }
+        if !archives.is_empty() {
+            parse_config.code_paths.push_back(options.archives_dir());
+        }
+    }
+    for (name, value) in options.defines.iter() {
+        match value {
+            None => parse_config.define(Symbol::intern(name), true),
+            Some(value) => match lex_define(db, name, value) {
+                Ok(def) => parse_config.define(Symbol::intern(name), def),
+                Err(err) => db.diagnostics().warn(format!(
+                    "ignoring definition of {}, its value is not valid Erlang: {}",
+                    name, err
+                )),
+            },
+        }
+    }
     parse_config.define(symbols::VSN, crate::FIREFLY_RELEASE);
     parse_config.define(symbols::COMPILER_VSN, crate::FIREFLY_RELEASE);
     parse_config
 }
 
+/// Lexes the value of a `-D NAME=VALUE` definition, which is used as the body of the macro
+///
+/// An empty value defines the macro as the empty atom.
+fn lex_define<P>(db: &P, name: &str, value: &str) -> Result<MacroDef, LexicalError>
+where
+    P: Parser,
+{
+    use firefly_parser::{FileMapSource, Scanner};
+
+    let codemap = db.codemap();
+    let id = codemap.add(format!("-D{}", name), value.to_string());
+    let file = codemap.get(id).unwrap();
+    let lexer = Lexer::new(Scanner::new(FileMapSource::new(file)));
+    let tokens = lexer
+        .filter(|token| !matches!(token, Ok(LexicalToken(_, Token::EOF, _))))
+        .collect::<Result<Vec<_>, _>>()?;
+    if tokens.is_empty() {
+        Ok(MacroDef::Atom(Symbol::intern("")))
+    } else {
+        Ok(MacroDef::Dynamic(tokens))
+    }
+}
+
 pub(crate) fn output_dir<P>(db: &P) -> PathBuf
 where
     P: Parser,
@@ -128,7 +179,8 @@ where
         //
         // 1. `stdin` for standard input
         // 2. `path/to/file.erl` for a single file
-        // 3. `path/to/dir` for a directory containing a standard Erlang application
+        // 3. `path/to/app.ez` or `path/to/app.tar` for an archive containing an Erlang application
+        // 4. `path/to/dir` for a directory containing a standard Erlang application
         match input {
             // Read from standard input
             &FileName::Virtual(ref name) if name == "stdin" => {
@@ -142,6 +194,22 @@ where
                 let interned = db.intern_input(input);
                 inputs.push(interned)
             }
+            // Load sources from an archive, which were unpacked by `archives`
+            &FileName::Real(ref path) if ArchiveType::detect(path).is_some() => {
+                let archives = db.archives()?;
+                let archive = archives.iter().find(|a| &a.path == path).unwrap();
+                for skipped in archive.skipped.iter() {
+                    db.diagnostics().warn(format!(
+                        "{} contains {}, which has no source and will not be compiled",
+                        path.display(),
+                        skipped.file_name().unwrap().to_string_lossy()
+                    ));
+                }
+                for source in archive.sources.iter() {
+                    let input = Input::File(source.clone());
+                    inputs.push(db.intern_input(input));
+                }
+            }
             // Read from a single file
             &FileName::Real(ref path) if path.exists() && path.is_file() => {
                 let input = Input::File(path.clone());
@@ -171,9 +239,130 @@ where
         }
     }
 
+    // Executables bundle their application resource, for use by the application controller
+    if options.app_type == ProjectType::Executable && !inputs.is_empty() {
+        let config = match options.sys_config.as_ref() {
+            None => None,
+            Some(path) => Some(unwrap_or_bail!(db, read_sys_config(db, path))),
+        };
+        let build_info = build_info::build_info_term(crate::FIREFLY_RELEASE, &options);
+        let input = Input::new(
+            APP_SPECS_INPUT,
+            app_specs_module(&options, &build_info, config.as_ref()),
+        );
+        inputs.push(db.intern_input(input));
+    }
+
     Ok(inputs)
 }
 
+/// Unpacks every archive given as an input into the build directory
+pub(crate) fn archives<P>(db: &P) -> Result<Arc<Vec<Archive>>, ErrorReported>
+where
+    P: Parser,
+{
+    let options = db.options();
+    let dir = options.archives_dir();
+    let mut archives = Vec::new();
+    for input in options.input_files.iter() {
+        match input {
+            &FileName::Real(ref path) if ArchiveType::detect(path).is_some() => {
+                if !path.is_file() {
+                    bail!(db, "invalid input file ({}), not a file", path.display());
+                }
+                let archive = unwrap_or_bail!(db, Archive::extract(path, &dir));
+                debug!(
+                    "unpacked {} ({} sources) to {}",
+                    path.display(),
+                    archive.sources.len(),
+                    archive.root.display()
+                );
+                archives.push(archive);
+            }
+            _ => continue,
+        }
+    }
+    Ok(Arc::new(archives))
+}
+
+/// The name of the synthesized module containing the bundled application resources and
+/// configuration
+pub(crate) const APP_SPECS_MODULE: &'static str = "firefly_apps";
+
+/// The name of the synthesized input containing the bundled application resources
+const APP_SPECS_INPUT: &'static str = "firefly_apps.erl";
+
+/// Generates the `firefly_apps` module, whose `specs/0` function returns the list of
+/// application resource terms bundled into the executable, i.e. that of the application being
+/// built, followed by those given with `--app-resource`, and whose `build_info/0` function
+/// returns the metadata of the build, see `firefly_session::build_info`.
+///
+/// If configuration was provided via `--config`, it is returned by `config/0`.
+///
+/// The runtime looks these functions up dynamically when the application controller is first used,
+/// during boot, and by `erlang:system_info(firefly_build)`, respectively.
+fn app_specs_module(options: &Options, build_info: &str, config: Option<&Literal>) -> String {
+    let specs = std::iter::once(&options.app)
+        .chain(options.bundled_apps.iter())
+        .map(|app| app.resource_term())
+        .collect::<Vec<_>>()
+        .join(",\n");
+    let mut module = format!(
+        "-module({}).\n\
+         -export([specs/0, build_info/0{}]).\n\
+         \n\
+         specs() ->\n    [\n{}\n    ].\n\
+         \n\
+         build_info() ->\n    {}.\n",
+        APP_SPECS_MODULE,
+        if config.is_some() { ", config/0" } else { "" },
+        specs,
+        build_info
+    );
+    if let Some(config) = config {
+        module.push_str(&format!("\nconfig() ->\n{}.\n", config));
+    }
+    module
+}
+
+/// Reads a `sys.config`-style file, returning the configuration term it contains
+///
+/// The term is parsed and evaluated as a constant Erlang expression, so that only a literal is
+/// ever spliced into the generated module. Unlike OTP, references to other configuration files
+/// within the term are not supported.
+fn read_sys_config<P>(db: &P, path: &Path) -> anyhow::Result<Literal>
+where
+    P: Parser,
+{
+    use firefly_parser as parse;
+
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read configuration from {}", path.display()))?;
+    // The expression grammar does not accept the trailing `.`
+    let Some(term) = contents.trim_end().strip_suffix('.') else {
+        return Err(anyhow!(
+            "expected the configuration term in {} to be terminated by '.'",
+            path.display()
+        ));
+    };
+
+    let reporter = Reporter::new();
+    let parser = parse::Parser::new(ParseConfig::default(), db.codemap().clone());
+    let expr = match parser.parse_string::<Expr, _, _>(reporter.clone(), term) {
+        Ok(expr) => expr,
+        Err(err) => {
+            reporter.diagnostic(err.to_diagnostic());
+            db.report_diagnostics(&reporter);
+            return Err(anyhow!(
+                "unable to parse configuration from {}",
+                path.display()
+            ));
+        }
+    };
+    evaluator::eval_expr(&expr, None)
+        .map_err(|err| anyhow!("invalid configuration term in {}: {}", path.display(), err))
+}
+
 pub(crate) fn input_type<P>(db: &P, input: InternedInput) -> InputType
 where
     P: Parser,
@@ -217,13 +406,52 @@ where
 
     match result {
         Ok(module) => {
-            reporter.print(&codemap);
+            db.report_diagnostics(&reporter);
             db.maybe_emit_file_with_opts(&options, input, &module)?;
+            if options.output_types.contains_key(&OutputType::Abstr) {
+                let code = syntax_erl::passes::AbstractCode::new(&module, &codemap);
+                db.maybe_emit_file_with_opts(&options, input, &code)?;
+            }
+            Ok(module)
+        }
+        Err(e) => {
+            reporter.diagnostic(e.to_diagnostic());
+            db.report_diagnostics(&reporter);
+            bail!(db, "parsing failed, see diagnostics for details");
+        }
+    }
+}
+
+pub(crate) fn input_cerl<P>(
+    db: &P,
+    input: InternedInput,
+) -> Result<syntax_core::Module, ErrorReported>
+where
+    P: Parser,
+{
+    let codemap = db.codemap().clone();
+
+    let source_id = match db.input_type(input) {
+        InputType::CoreErlang => match db.lookup_intern_input(input) {
+            Input::File(ref path) => match std::fs::read_to_string(path) {
+                Ok(content) => codemap.add(path.as_path(), content),
+                Err(err) => bail!(db, "unable to read {}: {}", path.display(), err),
+            },
+            Input::Str { ref input, .. } => codemap.add("nofile", input.to_string()),
+        },
+        ty => bail!(db, "invalid input type: {}", ty),
+    };
+    let source = codemap.get(source_id).unwrap();
+
+    match syntax_core::cerl::parse(&source) {
+        Ok(module) => {
+            db.maybe_emit_file(input, &module)?;
             Ok(module)
         }
         Err(e) => {
+            let reporter = Reporter::new();
             reporter.diagnostic(e.to_diagnostic());
-            reporter.print(&codemap);
+            db.report_diagnostics(&reporter);
             bail!(db, "parsing failed, see diagnostics for details");
         }
     }
@@ -240,12 +468,23 @@ where
     use firefly_pass::Pass;
     use firefly_syntax_erl::passes::{AstToCore, CanonicalizeSyntax, SemanticAnalysis};
 
+    // Core Erlang sources are parsed directly, skipping the Erlang frontend
+    if db.input_type(input) == InputType::CoreErlang {
+        return db.input_cerl(input);
+    }
+
     // Get Erlang AST
-    let ast = db.input_ast(input)?;
+    let mut ast = db.input_ast(input)?;
 
     // Run lowering passes
     let options = db.options();
     let codemap = db.codemap().clone();
+
+    // Like `erlc +debug_info`, embed the abstract code of the module when debug info is requested
+    if options.debug_info != DebugInfo::None {
+        let code = syntax_erl::passes::AbstractCode::new(&ast, &codemap);
+        ast.debug_info = Some(code.debug_info());
+    }
     let reporter = if options.warnings_as_errors {
         Reporter::strict()
     } else {
@@ -256,7 +495,7 @@ where
         .chain(CanonicalizeSyntax::new(reporter.clone(), codemap.clone()))
         .chain(AstToCore::new(reporter.clone()));
 
-    let module = unwrap_or_bail!(db, reporter, &codemap, passes.run(ast));
+    let module = unwrap_or_bail!(db, reporter, passes.run(ast));
 
     db.maybe_emit_file(input, &module)?;
 
@@ -279,14 +518,13 @@ where
 
     // Run lowering passes
     let options = db.options();
-    let codemap = db.codemap().clone();
     let reporter = if options.warnings_as_errors {
         Reporter::strict()
     } else {
         Reporter::new()
     };
     let mut passes = CoreToKernel::new(reporter.clone());
-    let module = unwrap_or_bail!(db, reporter, &codemap, passes.run(ast));
+    let module = unwrap_or_bail!(db, reporter, passes.run(ast));
 
     db.maybe_emit_file(input, &module)?;
 
@@ -309,17 +547,61 @@ where
 
     // Run lowering passes
     let options = db.options();
-    let codemap = db.codemap().clone();
     let reporter = if options.warnings_as_errors {
         Reporter::strict()
     } else {
         Reporter::new()
     };
 
+    // Functions named in `-compile({inline, [F/A]})` are inlined at any optimization level,
+    // `-compile(inline)` inlines small functions as `-O` does
+    let inline =
+        cst.compile.inline || matches!(options.opt_level, OptLevel::Default | OptLevel::Aggressive);
     let mut passes = KernelToSsa::new(reporter.clone());
-    let module = unwrap_or_bail!(db, reporter, &codemap, passes.run(cst));
+    let mut module = unwrap_or_bail!(db, reporter, passes.run(cst));
+
+    syntax_ssa::inline::Inliner::new(inline).inline_module(&mut module);
+    if options.opt_level != OptLevel::No {
+        syntax_ssa::fold::ConstantFolding::new().fold_module(&mut module);
+    }
 
     db.maybe_emit_file(input, &module)?;
+    if options.output_types.contains_key(&OutputType::CostReport) {
+        let report = syntax_ssa::cost::CostReport::new(&module);
+        db.maybe_emit_file(input, &report)?;
+        let reporter = if options.warnings_as_errors {
+            Reporter::strict()
+        } else {
+            Reporter::new()
+        };
+        report.report_warnings(&reporter);
+        db.report_diagnostics(&reporter);
+        if reporter.is_failed() {
+            bail!(db, "cost analysis failed, see diagnostics for details");
+        }
+    }
+    // Core Erlang carries no specs to check functions against
+    if options.analyze && db.input_type(input) != InputType::CoreErlang {
+        use syntax_ssa::typecheck::TypeCheck;
+
+        let ast = db.input_ast(input)?;
+        let reporter = if options.warnings_as_errors {
+            Reporter::strict()
+        } else {
+            Reporter::new()
+        };
+        TypeCheck::new(reporter.clone(), ast.spec_kinds()).check_module(&module);
+        db.report_diagnostics(&reporter);
+        if reporter.is_failed() {
+            bail!(db, "type analysis failed, see diagnostics for details");
+        }
+    }
+    if options.output_types.contains_key(&OutputType::Beam) {
+        use firefly_codegen::passes::SsaToBeam;
+
+        let beam = unwrap_or_bail!(db, SsaToBeam.run(&module));
+        db.maybe_emit_file(input, &beam)?;
+    }
 
     Ok(module)
 }
@@ -354,9 +636,14 @@ where
                 }
             }
         }
-        InputType::Erlang | InputType::AbstractErlang | InputType::SSA => {
+        InputType::Erlang | InputType::AbstractErlang | InputType::CoreErlang | InputType::SSA => {
             debug!("generating mlir for {:?} on {:?}", input, thread_id);
-            let module = db.input_ssa(input, app)?;
+            let mut module = db.input_ssa(input, app)?;
+            if let Some(live) = db.live_functions() {
+                module
+                    .functions
+                    .retain(|f| live.contains(&f.signature.mfa()));
+            }
             let codemap = db.codemap();
             let context = db.mlir_context(thread_id);
 
@@ -1,11 +1,12 @@
+use std::collections::BTreeSet;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::thread::ThreadId;
 
 use firefly_llvm as llvm;
 use firefly_mlir as mlir;
-use firefly_session::{InputType, Options};
-use firefly_syntax_base::ApplicationMetadata;
+use firefly_session::{Archive, InputType, Options};
+use firefly_syntax_base::{ApplicationMetadata, FunctionName};
 use firefly_syntax_core as syntax_core;
 use firefly_syntax_erl::{self as syntax_erl, ParseConfig};
 use firefly_syntax_kernel as syntax_kernel;
@@ -22,6 +23,13 @@ pub trait Parser: CompilerOutput {
     #[salsa::input]
     fn options(&self) -> Arc<Options>;
 
+    /// Returns the set of functions which may be called at runtime, if dead functions are being
+    /// pruned
+    ///
+    /// When set, functions not in this set are removed from their module prior to code generation.
+    #[salsa::input]
+    fn live_functions(&self) -> Option<Arc<BTreeSet<FunctionName>>>;
+
     /// Returns configuration for the parser based on current compiler options
     #[salsa::invoke(queries::parse_config)]
     fn parse_config(&self) -> ParseConfig;
@@ -46,6 +54,10 @@ pub trait Parser: CompilerOutput {
     #[salsa::invoke(queries::inputs)]
     fn inputs(&self) -> Result<Vec<InternedInput>, ErrorReported>;
 
+    /// Returns the archive inputs, unpacked into the build directory
+    #[salsa::invoke(queries::archives)]
+    fn archives(&self) -> Result<Arc<Vec<Archive>>, ErrorReported>;
+
     /// Returns the type of an interned input
     #[salsa::invoke(queries::input_type)]
     fn input_type(&self, input: InternedInput) -> InputType;
@@ -57,6 +69,13 @@ pub trait Parser: CompilerOutput {
     #[salsa::invoke(queries::input_ast)]
     fn input_ast(&self, input: InternedInput) -> Result<syntax_erl::Module, ErrorReported>;
 
+    /// Gets the syntax_core module parsed from the given Core Erlang input
+    ///
+    /// If the input is not a Core Erlang source, or an error occurs during
+    /// parsing of the module, the result will be Err(ErrorReported).
+    #[salsa::invoke(queries::input_cerl)]
+    fn input_cerl(&self, input: InternedInput) -> Result<syntax_core::Module, ErrorReported>;
+
     /// Gets the syntax_core module associated with the given input, if it exists
     ///
     /// If the input is not compatible with producing a syntax_core module, or an
@@ -604,8 +604,8 @@ mod tests {
     fn interned_keywords_no_gaps() {
         let mut i = Interner::fresh();
         // Should already be interned with matching indexes
-        for (sym, s) in symbols::DECLARED.iter() {
-            assert_eq!(i.intern(&s), *sym)
+        for (sym, s) in symbols::__SYMBOLS {
+            assert_eq!(i.intern(s), *sym)
         }
         // Should create a new symbol resulting in an index equal to the last entry in the table
         assert_eq!(i.intern("foo").as_u32(), (i.names.len() - 1) as u32);
@@ -57,6 +57,11 @@ fn main() {
         "asmparser",
         "lto",
         "instrumentation",
+        "option",
+        "passes",
+        "textapi",
+        "debuginfodwarf",
+        "objcarcopts",
         //"orcjit",
     ];
 
@@ -135,11 +140,16 @@ fn main() {
        .file("c_src/Attributes.cpp")
        .file("c_src/Diagnostics.cpp")
        .file("c_src/ErrorHandling.cpp")
+       .file("c_src/Exceptions.cpp")
        .file("c_src/IR.cpp")
+       .file("c_src/Linker.cpp")
        //.file("c_src/Orc.cpp")
        .file("c_src/Passes.cpp")
+       .file("c_src/StackMaps.cpp")
+       .file("c_src/TailCalls.cpp")
        .file("c_src/Target.cpp")
        .file("c_src/Version.cpp")
+       .file("c_src/YieldPoints.cpp")
        .include(include_dir)
        .shared_flag(false)
        .static_flag(true)
@@ -157,6 +167,12 @@ fn main() {
         llvm_lib_dir.as_path().display()
     );
 
+    // The lld drivers invoked by the built-in linker, see c_src/Linker.cpp. These
+    // depend on LLVM, so must precede the LLVM libraries on the link line
+    for lib in &["lldELF", "lldMachO", "lldWasm", "lldCommon"] {
+        println!("cargo:rustc-link-lib=static={}", lib);
+    }
+
     if !link_static && link_llvm_dylib {
         println!("cargo:rustc-link-lib=dylib=LLVM");
     } else {
@@ -0,0 +1,293 @@
+#include "firefly/llvm/Erlang.h"
+
+#include "llvm-c/Core.h"
+#include "llvm/ADT/MapVector.h"
+#include "llvm/ADT/SetVector.h"
+#include "llvm/ADT/SmallPtrSet.h"
+#include "llvm/ADT/SmallVector.h"
+#include "llvm/Analysis/ValueTracking.h"
+#include "llvm/IR/Constants.h"
+#include "llvm/IR/DataLayout.h"
+#include "llvm/IR/DerivedTypes.h"
+#include "llvm/IR/Function.h"
+#include "llvm/IR/GlobalVariable.h"
+#include "llvm/IR/IRBuilder.h"
+#include "llvm/IR/Instructions.h"
+#include "llvm/IR/IntrinsicInst.h"
+#include "llvm/IR/Module.h"
+#include "llvm/Transforms/Utils/Local.h"
+
+using namespace llvm;
+using firefly::isErlangFunctionType;
+
+// Keep in sync with `firefly_llvm::ExceptionModel`
+enum class ExceptionModel : unsigned {
+  Itanium = 0,
+  Seh = 1,
+};
+
+// Keep in sync with `firefly_llvm::ExceptionStats`
+struct ExceptionStats {
+  unsigned handlers;
+  unsigned invokes;
+  unsigned raises;
+};
+
+// Keep in sync with `TYPE_NAME` in the `panic` crate's SEH implementation
+static const char SehTypeName[] = "firefly_panic";
+
+// Returns the phi receiving the exception in `block`, if it is the handler of a
+// try or catch, i.e. a block whose only parameter is an exception
+static PHINode *getExceptionParam(BasicBlock &block, StructType *exceptionTy) {
+  auto *phi = dyn_cast<PHINode>(&block.front());
+  if (!phi || isa<PHINode>(phi->getNextNode()))
+    return nullptr;
+  auto *ptrTy = dyn_cast<PointerType>(phi->getType());
+  if (!ptrTy)
+    return nullptr;
+  if (!ptrTy->isOpaque() &&
+      ptrTy->getNonOpaquePointerElementType() != exceptionTy)
+    return nullptr;
+  return phi;
+}
+
+// Collects the calls to Erlang functions whose result `value` may be, looking
+// through the phis which join them with the result of a resumed tail call
+static void collectCalls(Value *value, Type *termTy,
+                         SmallPtrSetImpl<Value *> &visited,
+                         SetVector<CallInst *> &calls) {
+  if (!visited.insert(value).second)
+    return;
+  if (auto *phi = dyn_cast<PHINode>(value)) {
+    for (Value *incoming : phi->incoming_values())
+      collectCalls(incoming, termTy, visited, calls);
+    return;
+  }
+  auto *call = dyn_cast<CallInst>(value);
+  if (call && !isa<IntrinsicInst>(call) && !call->isInlineAsm() &&
+      !call->isMustTailCall() &&
+      isErlangFunctionType(call->getFunctionType(), termTy))
+    calls.insert(call);
+}
+
+// Returns the call results which reach `handler` as an exception, i.e. those
+// whose `Err` payload is passed to its exception parameter
+static void findRaisingCalls(PHINode *exception, Type *termTy,
+                             SetVector<CallInst *> &calls) {
+  SmallPtrSet<Value *, 8> visited;
+  for (Value *incoming : exception->incoming_values()) {
+    Value *payload = incoming->stripPointerCasts();
+    if (auto *cast = dyn_cast<IntToPtrInst>(payload))
+      payload = cast->getOperand(0);
+    auto *extract = dyn_cast<ExtractValueInst>(payload);
+    if (!extract || extract->getNumIndices() != 1 ||
+        extract->getIndices()[0] != 1)
+      continue;
+    collectCalls(extract->getAggregateOperand(), termTy, visited, calls);
+  }
+}
+
+// Creates a landing pad which catches Erlang exceptions and branches to
+// `handler` with the exception raised
+static BasicBlock *createLandingPad(PHINode *exception,
+                                    FunctionCallee catchFn) {
+  BasicBlock *handler = exception->getParent();
+  Function *fun = handler->getParent();
+  IRBuilder<> builder(BasicBlock::Create(fun->getContext(), "lpad", fun));
+  Type *i8PtrTy = builder.getInt8PtrTy();
+
+  // The personality only stops at a catch clause for Erlang exceptions, so a
+  // catch-all clause never catches anything else, e.g. runtime panics
+  LandingPadInst *pad = builder.CreateLandingPad(
+      StructType::get(i8PtrTy, builder.getInt32Ty()), /*NumClauses=*/1);
+  pad->addClause(ConstantPointerNull::get(cast<PointerType>(i8PtrTy)));
+  Value *object = builder.CreateExtractValue(pad, 0);
+  Value *caught = builder.CreateCall(catchFn, {object});
+  exception->addIncoming(
+      builder.CreatePointerCast(caught, exception->getType()),
+      builder.GetInsertBlock());
+  builder.CreateBr(handler);
+  return pad->getParent();
+}
+
+// Returns the type descriptor matched by catch pads against Erlang exceptions,
+// which must agree with the one thrown by the runtime
+static GlobalVariable *getSehTypeDescriptor(Module &module) {
+  const char *name = "__firefly_panic_type_info";
+  if (GlobalVariable *existing = module.getGlobalVariable(name))
+    return existing;
+
+  LLVMContext &context = module.getContext();
+  Type *i8PtrTy = Type::getInt8PtrTy(context);
+  // The vtable of `std::type_info`, which LLVM must not mangle
+  Constant *vtable = module.getOrInsertGlobal("\01??_7type_info@@6B@", i8PtrTy);
+  Constant *typeName = ConstantDataArray::getString(context, SehTypeName);
+  Constant *descriptor = ConstantStruct::getAnon(
+      {vtable, ConstantPointerNull::get(cast<PointerType>(i8PtrTy)),
+       typeName});
+  auto *global = new GlobalVariable(
+      module, descriptor->getType(), /*isConstant=*/false,
+      GlobalValue::LinkOnceODRLinkage, descriptor, name);
+  global->setComdat(module.getOrInsertComdat(name));
+  return global;
+}
+
+// Creates a funclet which catches Erlang exceptions and branches to `handler`
+// with the exception raised
+//
+// The exception object lives in the frame of the thrower until the catch
+// returns, so its cause must be taken within the catch pad itself
+static BasicBlock *createCatchFunclet(PHINode *exception,
+                                      FunctionCallee catchFn,
+                                      GlobalVariable *typeDescriptor) {
+  BasicBlock *handler = exception->getParent();
+  Function *fun = handler->getParent();
+  LLVMContext &context = fun->getContext();
+  Type *i8PtrTy = Type::getInt8PtrTy(context);
+
+  IRBuilder<> entry(&*fun->getEntryBlock().getFirstInsertionPt());
+  Value *objectSlot = entry.CreateAlloca(i8PtrTy);
+  Value *causeSlot = entry.CreateAlloca(i8PtrTy);
+
+  auto *dispatch = BasicBlock::Create(context, "catch.dispatch", fun);
+  auto *catchBlock = BasicBlock::Create(context, "catch", fun);
+  auto *caughtBlock = BasicBlock::Create(context, "caught", fun);
+
+  IRBuilder<> builder(dispatch);
+  CatchSwitchInst *catchSwitch = builder.CreateCatchSwitch(
+      ConstantTokenNone::get(context), /*UnwindBB=*/nullptr,
+      /*NumHandlers=*/1);
+  catchSwitch->addHandler(catchBlock);
+
+  // The flags mark the catch as by reference, so the slot receives the
+  // address of the exception object
+  builder.SetInsertPoint(catchBlock);
+  CatchPadInst *catchPad = builder.CreateCatchPad(
+      catchSwitch, {typeDescriptor, builder.getInt32(8), objectSlot});
+  Value *object = builder.CreateLoad(i8PtrTy, objectSlot);
+  OperandBundleDef funclet("funclet", catchPad);
+  Value *caught = builder.CreateCall(catchFn, {object}, {funclet});
+  builder.CreateStore(caught, causeSlot);
+  builder.CreateCatchRet(catchPad, caughtBlock);
+
+  builder.SetInsertPoint(caughtBlock);
+  Value *cause = builder.CreateLoad(i8PtrTy, causeSlot);
+  exception->addIncoming(builder.CreatePointerCast(cause, exception->getType()),
+                         caughtBlock);
+  builder.CreateBr(handler);
+  return dispatch;
+}
+
+// Replaces a return of an `Err` result with a call which raises its exception
+// by unwinding, see `firefly_rt::error::unwind`
+static bool lowerRaise(ReturnInst *ret, FunctionCallee raiseFn) {
+  Value *result = ret->getReturnValue();
+  if (!result)
+    return false;
+  auto *tag = dyn_cast_or_null<ConstantInt>(FindInsertedValue(result, {0}));
+  Value *payload = FindInsertedValue(result, {1});
+  if (!tag || !tag->isOne() || !payload)
+    return false;
+
+  IRBuilder<> builder(ret);
+  Value *exception = builder.CreateIntToPtr(payload, builder.getInt8PtrTy());
+  CallInst *raise = builder.CreateCall(raiseFn, {exception});
+  raise->setDoesNotReturn();
+  raise->setDebugLoc(ret->getDebugLoc());
+  builder.CreateUnreachable();
+  ret->eraseFromParent();
+  return true;
+}
+
+/// Lowers exception handling in Erlang functions to native unwinding
+///
+/// * A return of an `Err` result with a known exception, i.e. a raise with no
+/// handler in the current function, instead raises it via `__firefly_raise`
+/// * A call whose `Err` result is passed to the handler of a try or catch is
+/// made via an `invoke` which unwinds to that same handler, via a landing pad
+/// or catch funclet depending on `model`
+///
+/// Callees may still return `Err`, e.g. natives, so the check of each result
+/// is left in place, and both paths reach the handler with an exception owned
+/// by the caller.
+extern "C" void LLVMFireflyLowerExceptions(LLVMModuleRef m,
+                                           ExceptionModel model,
+                                           ExceptionStats *stats) {
+  Module *module = unwrap(m);
+  LLVMContext &context = module->getContext();
+  const DataLayout &dataLayout = module->getDataLayout();
+  Type *termTy = dataLayout.getIntPtrType(context);
+  Type *i8PtrTy = Type::getInt8PtrTy(context);
+  StructType *exceptionTy =
+      StructType::getTypeByName(context, "erlang::Exception");
+
+  auto *raiseTy = FunctionType::get(Type::getVoidTy(context), {i8PtrTy},
+                                    /*isVarArg=*/false);
+  FunctionCallee raiseFn =
+      module->getOrInsertFunction("__firefly_raise", raiseTy);
+  if (auto *raise = dyn_cast<Function>(raiseFn.getCallee()))
+    raise->setDoesNotReturn();
+  auto *catchTy = FunctionType::get(i8PtrTy, {i8PtrTy}, /*isVarArg=*/false);
+  FunctionCallee catchFn =
+      module->getOrInsertFunction("__firefly_catch_exception", catchTy);
+
+  Constant *personality;
+  if (model == ExceptionModel::Seh) {
+    personality = cast<Constant>(
+        module
+            ->getOrInsertFunction("__CxxFrameHandler3",
+                                  FunctionType::get(Type::getInt32Ty(context),
+                                                    /*isVarArg=*/true))
+            .getCallee());
+  } else {
+    personality = cast<Constant>(
+        module
+            ->getOrInsertFunction("firefly_eh_personality",
+                                  FunctionType::get(Type::getInt32Ty(context),
+                                                    /*isVarArg=*/true))
+            .getCallee());
+  }
+
+  *stats = ExceptionStats{0, 0, 0};
+  for (Function &fun : *module) {
+    if (fun.isDeclaration() ||
+        !isErlangFunctionType(fun.getFunctionType(), termTy))
+      continue;
+
+    SmallVector<ReturnInst *, 4> returns;
+    MapVector<PHINode *, SetVector<CallInst *>> handlers;
+    for (BasicBlock &block : fun) {
+      if (auto *ret = dyn_cast<ReturnInst>(block.getTerminator()))
+        returns.push_back(ret);
+      if (!exceptionTy)
+        continue;
+      if (PHINode *exception = getExceptionParam(block, exceptionTy)) {
+        SetVector<CallInst *> calls;
+        findRaisingCalls(exception, termTy, calls);
+        if (!calls.empty())
+          handlers.insert({exception, std::move(calls)});
+      }
+    }
+
+    for (ReturnInst *ret : returns)
+      if (lowerRaise(ret, raiseFn))
+        stats->raises++;
+
+    if (handlers.empty())
+      continue;
+    if (model == ExceptionModel::Seh || !fun.hasPersonalityFn())
+      fun.setPersonalityFn(personality);
+    for (auto &handler : handlers) {
+      BasicBlock *pad =
+          model == ExceptionModel::Seh
+              ? createCatchFunclet(handler.first, catchFn,
+                                   getSehTypeDescriptor(*module))
+              : createLandingPad(handler.first, catchFn);
+      for (CallInst *call : handler.second) {
+        changeToInvokeAndSplitBasicBlock(call, pad);
+        stats->invokes++;
+      }
+      stats->handlers++;
+    }
+  }
+}
@@ -0,0 +1,76 @@
+// On Windows we have a custom output stream type that
+// can wrap the raw file handle we get from Rust
+#if defined(_WIN32)
+#include "firefly/llvm/raw_win32_handle_ostream.h"
+#endif
+
+#include "lld/Common/CommonLinkerContext.h"
+#include "lld/Common/Driver.h"
+#include "llvm/ADT/ArrayRef.h"
+#include "llvm/ADT/StringRef.h"
+#include "llvm/Support/Path.h"
+#include "llvm/Support/raw_ostream.h"
+
+using namespace llvm;
+
+namespace {
+enum class Flavor {
+  Invalid,
+  Gnu,    // -flavor gnu
+  Darwin, // -flavor darwin
+  Wasm,   // -flavor wasm
+};
+}
+
+// Selects the lld driver from the program name, i.e. the first argument,
+// in the same way the `lld` executable does
+static Flavor getFlavor(StringRef argv0) {
+  StringRef stem = sys::path::stem(argv0);
+  if (stem.endswith("ld.lld") || stem == "ld")
+    return Flavor::Gnu;
+  if (stem.endswith("ld64.lld") || stem == "ld64")
+    return Flavor::Darwin;
+  if (stem.endswith("wasm-ld"))
+    return Flavor::Wasm;
+  return Flavor::Invalid;
+}
+
+#if defined(_WIN32)
+extern "C" bool LLVMFireflyLink(int argc, const char **argv, HANDLE outHandle,
+                                HANDLE errHandle) {
+  raw_win32_handle_ostream outs(outHandle, /*shouldClose=*/false,
+                                /*unbuffered=*/true);
+  raw_win32_handle_ostream errs(errHandle, /*shouldClose=*/false,
+                                /*unbuffered=*/true);
+#else
+extern "C" bool LLVMFireflyLink(int argc, const char **argv, int outFd,
+                                int errFd) {
+  raw_fd_ostream outs(outFd, /*shouldClose=*/false, /*unbuffered=*/true);
+  raw_fd_ostream errs(errFd, /*shouldClose=*/false, /*unbuffered=*/true);
+#endif
+  ArrayRef<const char *> args(argv, argv + argc);
+
+  bool success;
+  switch (getFlavor(args[0])) {
+  case Flavor::Gnu:
+    success = lld::elf::link(args, outs, errs, /*exitEarly=*/false,
+                             /*disableOutput=*/false);
+    break;
+  case Flavor::Darwin:
+    success = lld::macho::link(args, outs, errs, /*exitEarly=*/false,
+                               /*disableOutput=*/false);
+    break;
+  case Flavor::Wasm:
+    success = lld::wasm::link(args, outs, errs, /*exitEarly=*/false,
+                              /*disableOutput=*/false);
+    break;
+  default:
+    errs << "unsupported linker flavor for '" << args[0] << "'\n";
+    return false;
+  }
+
+  // lld keeps its state in a global context, which must be reset so that
+  // the linker can be invoked again from the same process
+  lld::CommonLinkerContext::destroy();
+  return success;
+}
@@ -0,0 +1,268 @@
+#include "firefly/llvm/Erlang.h"
+
+#include "llvm-c/Core.h"
+#include "llvm/ADT/DenseMap.h"
+#include "llvm/ADT/MapVector.h"
+#include "llvm/ADT/SetVector.h"
+#include "llvm/ADT/SmallPtrSet.h"
+#include "llvm/ADT/SmallVector.h"
+#include "llvm/Analysis/ValueTracking.h"
+#include "llvm/IR/CFG.h"
+#include "llvm/IR/DataLayout.h"
+#include "llvm/IR/Function.h"
+#include "llvm/IR/IRBuilder.h"
+#include "llvm/IR/Instructions.h"
+#include "llvm/IR/IntrinsicInst.h"
+#include "llvm/IR/Module.h"
+#include "llvm/Transforms/Utils/BasicBlockUtils.h"
+#include "llvm/Transforms/Utils/Local.h"
+
+using namespace llvm;
+using firefly::isErlangFunctionType;
+
+// Keep in sync with `firefly_llvm::StackMapStats`
+struct StackMapStats {
+  unsigned safepoints;
+  unsigned slots;
+  unsigned pinning;
+};
+
+// The runtime finds records by return address, so the ID only tells it whether
+// anything the collector cannot update is live across the call, in which case
+// the heap must not be compacted while the frame is on the stack
+static const uint64_t StatepointID = 0;
+static const uint64_t PinningStatepointID = 1;
+
+using BlockSet = SmallPtrSet<BasicBlock *, 8>;
+
+// Computes the blocks at whose end `value`, defined in `defBlock`, is live
+static void computeLiveOut(Value *value, BasicBlock *defBlock,
+                           BlockSet &liveOut) {
+  BlockSet liveIn;
+  SmallVector<BasicBlock *, 8> worklist;
+  for (Use &use : value->uses()) {
+    auto *user = cast<Instruction>(use.getUser());
+    BasicBlock *block = user->getParent();
+    // A phi uses its incoming values at the end of the incoming block
+    if (auto *phi = dyn_cast<PHINode>(user)) {
+      block = phi->getIncomingBlock(use);
+      liveOut.insert(block);
+    }
+    if (block != defBlock)
+      worklist.push_back(block);
+  }
+  while (!worklist.empty()) {
+    BasicBlock *block = worklist.pop_back_val();
+    if (!liveIn.insert(block).second)
+      continue;
+    for (BasicBlock *pred : predecessors(block)) {
+      liveOut.insert(pred);
+      if (pred != defBlock)
+        worklist.push_back(pred);
+    }
+  }
+}
+
+// Returns true if `value` is live immediately after `call` returns
+static bool isLiveAcross(Value *value, CallBase *call,
+                         const BlockSet &liveOut) {
+  BasicBlock *block = call->getParent();
+  auto *def = dyn_cast<Instruction>(value);
+  if (def && def->getParent() == block && !def->comesBefore(call))
+    return false;
+  if (liveOut.count(block))
+    return true;
+  for (User *user : value->users()) {
+    auto *inst = cast<Instruction>(user);
+    if (inst->getParent() == block && !isa<PHINode>(inst) &&
+        call->comesBefore(inst))
+      return true;
+  }
+  return false;
+}
+
+// Returns true if values of `ty` may hold a term, or a pointer, other than as a
+// value of the term type
+static bool mayHoldUntracked(Type *ty, Type *termTy) {
+  if (ty == termTy || ty->isPointerTy())
+    return true;
+  if (auto *structTy = dyn_cast<StructType>(ty))
+    return any_of(structTy->elements(), [&](Type *elementTy) {
+      return mayHoldUntracked(elementTy, termTy);
+    });
+  if (auto *arrayTy = dyn_cast<ArrayType>(ty))
+    return mayHoldUntracked(arrayTy->getElementType(), termTy);
+  if (auto *vectorTy = dyn_cast<VectorType>(ty))
+    return mayHoldUntracked(vectorTy->getElementType(), termTy);
+  return false;
+}
+
+// Returns true if `value` may refer to a process heap without being a term,
+// i.e. is a pointer into it, such as one derived from a boxed term, or an
+// aggregate holding terms or such pointers, which the collector can neither
+// find nor update
+static bool isUntracked(Value *value, Type *termTy) {
+  Type *ty = value->getType();
+  if (ty == termTy)
+    return false;
+  if (ty->isPointerTy()) {
+    // Pointers to the frame itself, or to constants, never refer to a heap
+    const Value *base = getUnderlyingObject(value);
+    return !isa<AllocaInst>(base) && !isa<GlobalValue>(base);
+  }
+  return mayHoldUntracked(ty, termTy);
+}
+
+// Moves an argument to a stack slot, as `DemoteRegToStack` does for
+// instructions
+static AllocaInst *demoteArgToStack(Argument &arg, Instruction *allocaPoint) {
+  const DataLayout &dataLayout = arg.getParent()->getParent()->getDataLayout();
+  auto *slot = new AllocaInst(arg.getType(), dataLayout.getAllocaAddrSpace(),
+                              nullptr, arg.getName() + ".reg2mem",
+                              allocaPoint);
+  for (Use &use : make_early_inc_range(arg.uses())) {
+    Instruction *insertPt = cast<Instruction>(use.getUser());
+    if (auto *phi = dyn_cast<PHINode>(insertPt))
+      insertPt = phi->getIncomingBlock(use)->getTerminator();
+    use.set(new LoadInst(arg.getType(), slot, arg.getName() + ".reload",
+                         insertPt));
+  }
+  BasicBlock &entry = arg.getParent()->getEntryBlock();
+  new StoreInst(&arg, slot, &*entry.getFirstNonPHIOrDbgOrAlloca());
+  return slot;
+}
+
+// Replaces `call` with a statepoint whose stack map record lists `slots`, and
+// whose ID is `id`
+static void replaceWithStatepoint(CallBase *call, ArrayRef<Value *> slots,
+                                  uint64_t id) {
+  // The result of the call must be taken on an edge only it reaches
+  auto *invoke = dyn_cast<InvokeInst>(call);
+  if (invoke && !invoke->getNormalDest()->getSinglePredecessor())
+    SplitEdge(invoke->getParent(), invoke->getNormalDest());
+
+  IRBuilder<> builder(call);
+  FunctionCallee callee(call->getFunctionType(), call->getCalledOperand());
+  SmallVector<Value *, 8> args(call->args());
+  CallBase *statepoint;
+  if (invoke)
+    statepoint = builder.CreateGCStatepointInvoke(
+        id, /*NumPatchBytes=*/0, callee, invoke->getNormalDest(),
+        invoke->getUnwindDest(), args, slots, /*GCArgs=*/ArrayRef<Value *>());
+  else
+    statepoint = builder.CreateGCStatepointCall(
+        id, /*NumPatchBytes=*/0, callee, args, slots,
+        /*GCArgs=*/ArrayRef<Value *>());
+  statepoint->setCallingConv(call->getCallingConv());
+  statepoint->setDebugLoc(call->getDebugLoc());
+
+  if (!call->getType()->isVoidTy() && !call->use_empty()) {
+    if (invoke)
+      builder.SetInsertPoint(&*invoke->getNormalDest()->getFirstInsertionPt());
+    Value *result = builder.CreateGCResult(statepoint, call->getType());
+    call->replaceAllUsesWith(result);
+  }
+  call->eraseFromParent();
+}
+
+/// Emits a stack map record for every call in an Erlang function, describing
+/// the stack slots which hold the terms live across it, see
+/// `firefly_rt::process::stackmaps`
+///
+/// Every term live across a call is demoted to a stack slot, so that it is
+/// reloaded from memory after the call, where the garbage collector updates it
+/// if it was moved. Each call is then made via a statepoint, passing the
+/// addresses of the slots as deopt arguments, which records them as `Direct`
+/// locations, with the offset of the return address of the call.
+///
+/// Calls across which no term is live get a record too, so that the runtime
+/// can tell the frames of generated code from those of native code, which may
+/// hold terms it cannot find. Only values of the term type are tracked, and as
+/// this runs after optimization, pointers derived from terms, and aggregates
+/// holding terms, may also be live across a call. Such a call is given a
+/// pinning record, and while its frame is on the stack, the collector does not
+/// move anything.
+extern "C" void LLVMFireflyEmitStackMaps(LLVMModuleRef m,
+                                         StackMapStats *stats) {
+  Module *module = unwrap(m);
+  const DataLayout &dataLayout = module->getDataLayout();
+  Type *termTy = dataLayout.getIntPtrType(module->getContext());
+
+  *stats = StackMapStats{0, 0, 0};
+  for (Function &fun : *module) {
+    if (fun.isDeclaration() ||
+        !isErlangFunctionType(fun.getFunctionType(), termTy))
+      continue;
+
+    // Nothing is live across a call in tail position, and it leaves no frame
+    // behind, so these need no record
+    SmallVector<CallBase *, 8> calls;
+    SmallVector<Value *, 16> values;
+    SmallVector<Value *, 8> untracked;
+    for (Argument &arg : fun.args())
+      values.push_back(&arg);
+    for (BasicBlock &block : fun) {
+      for (Instruction &inst : block) {
+        if (inst.getType() == termTy)
+          values.push_back(&inst);
+        else if (isUntracked(&inst, termTy))
+          untracked.push_back(&inst);
+        auto *call = dyn_cast<CallBase>(&inst);
+        if (call && !call->isInlineAsm() && !isa<IntrinsicInst>(call) &&
+            !call->isMustTailCall() && !call->getFunctionType()->isVarArg())
+          calls.push_back(call);
+      }
+    }
+    if (calls.empty())
+      continue;
+
+    MapVector<CallBase *, SmallVector<Value *, 4>> safepoints;
+    for (CallBase *call : calls)
+      safepoints[call];
+    SetVector<Value *> demoted;
+    for (Value *value : values) {
+      BasicBlock *defBlock = isa<Argument>(value)
+                                 ? &fun.getEntryBlock()
+                                 : cast<Instruction>(value)->getParent();
+      BlockSet liveOut;
+      computeLiveOut(value, defBlock, liveOut);
+      for (CallBase *call : calls) {
+        if (isLiveAcross(value, call, liveOut)) {
+          safepoints[call].push_back(value);
+          demoted.insert(value);
+        }
+      }
+    }
+    SmallPtrSet<CallBase *, 8> pinning;
+    for (Value *value : untracked) {
+      BlockSet liveOut;
+      computeLiveOut(value, cast<Instruction>(value)->getParent(), liveOut);
+      for (CallBase *call : calls)
+        if (isLiveAcross(value, call, liveOut))
+          pinning.insert(call);
+    }
+
+    DenseMap<Value *, AllocaInst *> slots;
+    Instruction *allocaPoint = &fun.getEntryBlock().front();
+    for (Value *value : demoted) {
+      if (auto *inst = dyn_cast<Instruction>(value))
+        slots[value] =
+            DemoteRegToStack(*inst, /*VolatileLoads=*/false, allocaPoint);
+      else
+        slots[value] = demoteArgToStack(*cast<Argument>(value), allocaPoint);
+    }
+
+    for (auto &safepoint : safepoints) {
+      SmallVector<Value *, 4> live;
+      for (Value *value : safepoint.second)
+        live.push_back(slots[value]);
+      bool pins = pinning.count(safepoint.first);
+      replaceWithStatepoint(safepoint.first, live,
+                            pins ? PinningStatepointID : StatepointID);
+      stats->safepoints++;
+      stats->slots += live.size();
+      if (pins)
+        stats->pinning++;
+    }
+  }
+}
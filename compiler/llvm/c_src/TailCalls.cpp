@@ -0,0 +1,212 @@
+#include "firefly/llvm/Erlang.h"
+
+#include "llvm-c/Core.h"
+#include "llvm/ADT/SmallVector.h"
+#include "llvm/IR/Constants.h"
+#include "llvm/IR/DataLayout.h"
+#include "llvm/IR/DerivedTypes.h"
+#include "llvm/IR/Function.h"
+#include "llvm/IR/IRBuilder.h"
+#include "llvm/IR/Instructions.h"
+#include "llvm/IR/IntrinsicInst.h"
+#include "llvm/IR/Module.h"
+#include "llvm/Transforms/Utils/BasicBlockUtils.h"
+
+using namespace llvm;
+using firefly::isErlangFunctionType;
+
+// Keep in sync with `firefly_llvm::TailCallStats`
+struct TailCallStats {
+  unsigned mustTail;
+  unsigned padded;
+  unsigned trampolined;
+  unsigned checked;
+};
+
+// Returns true if the call is immediately followed by a return of its result
+static bool isInTailPosition(CallInst *call) {
+  auto *ret = dyn_cast_or_null<ReturnInst>(call->getNextNode());
+  return ret && ret->getReturnValue() == call;
+}
+
+// Replaces a call in tail position with a `musttail` call of the same callee
+// with the prototype of the caller, passing undefined values for the arguments
+// the callee does not take
+static void padToCaller(CallInst *call) {
+  Function *caller = call->getFunction();
+  FunctionType *callerTy = caller->getFunctionType();
+
+  IRBuilder<> builder(call);
+  SmallVector<Value *, 8> args(call->args());
+  for (unsigned i = args.size(); i < callerTy->getNumParams(); ++i)
+    args.push_back(UndefValue::get(callerTy->getParamType(i)));
+  Value *callee = builder.CreatePointerCast(call->getCalledOperand(),
+                                            callerTy->getPointerTo());
+  CallInst *padded = builder.CreateCall(callerTy, callee, args);
+  padded->setCallingConv(call->getCallingConv());
+  padded->setTailCallKind(CallInst::TCK_MustTail);
+  padded->setDebugLoc(call->getDebugLoc());
+  call->replaceAllUsesWith(padded);
+  call->eraseFromParent();
+}
+
+// Replaces a call in tail position with one which schedules it in the runtime,
+// see `firefly_rt::function::apply::tail`
+static void trampoline(CallInst *call, FunctionCallee scheduleFn,
+                       Type *termTy) {
+  Function *caller = call->getFunction();
+  unsigned argc = call->arg_size();
+
+  IRBuilder<> builder(call);
+  Value *argv = ConstantPointerNull::get(termTy->getPointerTo());
+  if (argc > 0) {
+    IRBuilder<> entry(&*caller->getEntryBlock().getFirstInsertionPt());
+    auto *argvTy = ArrayType::get(termTy, argc);
+    Value *array = entry.CreateAlloca(argvTy);
+    argv = entry.CreateConstInBoundsGEP2_32(argvTy, array, 0, 0);
+    for (unsigned i = 0; i < argc; ++i) {
+      Value *slot = builder.CreateConstInBoundsGEP1_32(termTy, argv, i);
+      builder.CreateStore(call->getArgOperand(i), slot);
+    }
+  }
+  Value *callee = builder.CreatePointerCast(call->getCalledOperand(),
+                                            builder.getInt8PtrTy());
+  Value *args[] = {callee, argv, ConstantInt::get(termTy, argc)};
+  CallInst *scheduled = builder.CreateCall(scheduleFn, args);
+  scheduled->setDebugLoc(call->getDebugLoc());
+  call->replaceAllUsesWith(scheduled);
+  call->eraseFromParent();
+}
+
+// Inserts a check before `insertPt` of whether `result` is the marker for a
+// scheduled tail call, and if so, replaces it with the result of resuming it
+static void resumeIfScheduled(Instruction *result, Instruction *insertPt,
+                              FunctionCallee resumeFn, uint64_t marker) {
+  BasicBlock *head = insertPt->getParent();
+  BasicBlock *cont = head->splitBasicBlock(insertPt);
+  head->getTerminator()->eraseFromParent();
+
+  LLVMContext &context = head->getContext();
+  Function *caller = head->getParent();
+  BasicBlock *resume = BasicBlock::Create(context, "", caller, cont);
+
+  IRBuilder<> builder(head);
+  builder.SetCurrentDebugLocation(result->getDebugLoc());
+  // The discriminant is a u8, the rest of the first word is padding
+  Value *tag = builder.CreateExtractValue(result, 0);
+  Value *value = builder.CreateExtractValue(result, 1);
+  Type *termTy = value->getType();
+  Value *isOk = builder.CreateICmpEQ(
+      builder.CreateTrunc(tag, builder.getInt8Ty()), builder.getInt8(0));
+  Value *isNone =
+      builder.CreateICmpEQ(value, ConstantInt::get(termTy, marker));
+  Value *isScheduled = builder.CreateAnd(isOk, isNone);
+  builder.CreateCondBr(isScheduled, resume, cont);
+
+  builder.SetInsertPoint(resume);
+  CallInst *resumed = builder.CreateCall(resumeFn);
+  builder.CreateBr(cont);
+
+  builder.SetInsertPoint(&cont->front());
+  PHINode *phi = builder.CreatePHI(result->getType(), 2);
+  phi->addIncoming(result, head);
+  phi->addIncoming(resumed, resume);
+  result->replaceUsesWithIf(phi, [&](Use &use) {
+    User *user = use.getUser();
+    return user != tag && user != value && user != phi;
+  });
+}
+
+/// Ensures that no call in tail position in an Erlang function grows the stack
+///
+/// * A call to a function with the same prototype as the caller is `musttail`
+/// * When `padArgs` is set, a call passing fewer arguments than the caller
+/// received is `musttail` too, made with the prototype of the caller, see
+/// `padToCaller`, which the target's calling convention must permit
+/// * Any other call is scheduled via `__firefly_tail_call`, and every other
+/// call to an Erlang function gets a check for whether its callee did so, in
+/// which case the scheduled call is made via `__firefly_resume_tail_call`
+///
+/// Only `musttail` is guaranteed by LLVM to be a tail call, so no call is left
+/// to the backend to make as a sibling call.
+extern "C" void LLVMFireflyGuaranteeTailCalls(LLVMModuleRef m, bool padArgs,
+                                              uint64_t marker,
+                                              TailCallStats *stats) {
+  Module *module = unwrap(m);
+  LLVMContext &context = module->getContext();
+  const DataLayout &dataLayout = module->getDataLayout();
+  Type *termTy = dataLayout.getIntPtrType(context);
+  Type *resultTy = StructType::get(context, {termTy, termTy});
+
+  auto *scheduleTy = FunctionType::get(
+      resultTy,
+      {Type::getInt8PtrTy(context), termTy->getPointerTo(), termTy},
+      /*isVarArg=*/false);
+  FunctionCallee scheduleFn =
+      module->getOrInsertFunction("__firefly_tail_call", scheduleTy);
+  auto *resumeTy = FunctionType::get(resultTy, /*isVarArg=*/false);
+  FunctionCallee resumeFn =
+      module->getOrInsertFunction("__firefly_resume_tail_call", resumeTy);
+
+  *stats = TailCallStats{0, 0, 0, 0};
+  for (Function &fun : *module) {
+    if (fun.isDeclaration())
+      continue;
+    bool isErlang = isErlangFunctionType(fun.getFunctionType(), termTy);
+
+    SmallVector<CallInst *, 4> paddings;
+    SmallVector<CallInst *, 4> trampolines;
+    SmallVector<CallBase *, 8> checks;
+    for (BasicBlock &block : fun) {
+      for (Instruction &inst : block) {
+        auto *call = dyn_cast<CallBase>(&inst);
+        if (!call || call->isInlineAsm() || isa<IntrinsicInst>(call) ||
+            !isErlangFunctionType(call->getFunctionType(), termTy))
+          continue;
+
+        auto *ci = dyn_cast<CallInst>(call);
+        if (!isErlang || !ci || !isInTailPosition(ci)) {
+          checks.push_back(call);
+          continue;
+        }
+        if (ci->isMustTailCall()) {
+          stats->mustTail++;
+        } else if (ci->getFunctionType() == fun.getFunctionType() &&
+                   ci->getCallingConv() == fun.getCallingConv()) {
+          ci->setTailCallKind(CallInst::TCK_MustTail);
+          stats->mustTail++;
+        } else if (padArgs && ci->arg_size() < fun.arg_size() &&
+                   ci->getCallingConv() == fun.getCallingConv()) {
+          paddings.push_back(ci);
+        } else {
+          trampolines.push_back(ci);
+        }
+      }
+    }
+
+    for (CallInst *call : paddings) {
+      padToCaller(call);
+      stats->padded++;
+    }
+
+    for (CallInst *call : trampolines) {
+      trampoline(call, scheduleFn, termTy);
+      stats->trampolined++;
+    }
+
+    // The result of a call must be checked even when unused, or the call it
+    // scheduled would never be made
+    for (CallBase *call : checks) {
+      Instruction *insertPt;
+      if (auto *invoke = dyn_cast<InvokeInst>(call)) {
+        BasicBlock *edge =
+            SplitEdge(invoke->getParent(), invoke->getNormalDest());
+        insertPt = edge->getTerminator();
+      } else {
+        insertPt = call->getNextNode();
+      }
+      resumeIfScheduled(call, insertPt, resumeFn, marker);
+      stats->checked++;
+    }
+  }
+}
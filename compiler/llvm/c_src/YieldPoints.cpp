@@ -0,0 +1,100 @@
+#include "firefly/llvm/Erlang.h"
+
+#include "llvm-c/Core.h"
+#include "llvm/ADT/SmallPtrSet.h"
+#include "llvm/ADT/SmallVector.h"
+#include "llvm/Analysis/CFG.h"
+#include "llvm/IR/Constants.h"
+#include "llvm/IR/DataLayout.h"
+#include "llvm/IR/Function.h"
+#include "llvm/IR/GlobalVariable.h"
+#include "llvm/IR/IRBuilder.h"
+#include "llvm/IR/Instructions.h"
+#include "llvm/IR/MDBuilder.h"
+#include "llvm/IR/Module.h"
+#include "llvm/Transforms/Utils/BasicBlockUtils.h"
+
+using namespace llvm;
+using firefly::isErlangFunctionType;
+
+// Keep in sync with `firefly_llvm::YieldPointStats`
+struct YieldPointStats {
+  unsigned entries;
+  unsigned loops;
+};
+
+// Consumes a reduction before `insertPt`, and calls the yield point when none
+// are left, see `firefly_rt::process::reductions`
+static void insertYieldPoint(Instruction *insertPt,
+                             GlobalVariable *reductionsLeft,
+                             FunctionCallee yieldFn) {
+  IRBuilder<> builder(insertPt);
+  Type *ty = reductionsLeft->getValueType();
+  Value *left = builder.CreateLoad(ty, reductionsLeft);
+  Value *remaining = builder.CreateSub(left, ConstantInt::get(ty, 1));
+  builder.CreateStore(remaining, reductionsLeft);
+  Value *exhausted =
+      builder.CreateICmpSLE(remaining, ConstantInt::get(ty, 0));
+
+  // A process yields at most once every `MAX_REDUCTIONS` reductions
+  MDNode *weights =
+      MDBuilder(insertPt->getContext()).createBranchWeights(1, 4000);
+  Instruction *then = SplitBlockAndInsertIfThen(exhausted, insertPt,
+                                                /*Unreachable=*/false, weights);
+  CallInst *yield = CallInst::Create(yieldFn, "", then);
+  yield->setDebugLoc(insertPt->getDebugLoc());
+}
+
+/// Inserts a yield point on entry to every Erlang function in the module, and
+/// in the header of every loop, so that generated code can be preempted
+extern "C" void LLVMFireflyInsertYieldPoints(LLVMModuleRef m,
+                                             YieldPointStats *stats) {
+  Module *module = unwrap(m);
+  LLVMContext &context = module->getContext();
+  const DataLayout &dataLayout = module->getDataLayout();
+  Type *termTy = dataLayout.getIntPtrType(context);
+
+  // Generated code is always linked into the executable with the runtime, so
+  // the cheapest model which permits the variable to be defined elsewhere
+  // is sufficient
+  GlobalVariable *reductionsLeft =
+      module->getGlobalVariable("__firefly_reductions_left");
+  if (!reductionsLeft)
+    reductionsLeft = new GlobalVariable(
+        *module, termTy, /*isConstant=*/false, GlobalValue::ExternalLinkage,
+        /*Initializer=*/nullptr, "__firefly_reductions_left",
+        /*InsertBefore=*/nullptr, GlobalValue::InitialExecTLSModel);
+  auto *yieldTy = FunctionType::get(Type::getVoidTy(context),
+                                    /*isVarArg=*/false);
+  FunctionCallee yieldFn =
+      module->getOrInsertFunction("__firefly_yield_point", yieldTy);
+
+  *stats = YieldPointStats{0, 0};
+  for (Function &fun : *module) {
+    if (fun.isDeclaration() ||
+        !isErlangFunctionType(fun.getFunctionType(), termTy))
+      continue;
+
+    // Every loop is entered via a backedge to its header, checking there
+    // rather than on the backedge itself covers all of them at once
+    SmallVector<std::pair<const BasicBlock *, const BasicBlock *>, 4> backedges;
+    FindFunctionBackedges(fun, backedges);
+    SmallPtrSet<BasicBlock *, 4> headers;
+    for (auto &backedge : backedges)
+      headers.insert(const_cast<BasicBlock *>(backedge.second));
+
+    // Allocas must stay at the start of the entry block to remain static
+    BasicBlock &entry = fun.getEntryBlock();
+    insertYieldPoint(&*entry.getFirstNonPHIOrDbgOrAlloca(), reductionsLeft,
+                     yieldFn);
+    stats->entries++;
+
+    for (BasicBlock *header : headers) {
+      auto insertPt = header->getFirstInsertionPt();
+      if (insertPt == header->end())
+        continue;
+      insertYieldPoint(&*insertPt, reductionsLeft, yieldFn);
+      stats->loops++;
+    }
+  }
+}
@@ -0,0 +1,26 @@
+#ifndef FIREFLY_ERLANG_H
+#define FIREFLY_ERLANG_H
+
+#include "llvm/IR/DerivedTypes.h"
+
+namespace firefly {
+
+// Erlang functions take only terms as arguments, and return a `{isize, term}`
+// pair corresponding to `firefly_rt::function::ErlangResult`
+inline bool isErlangFunctionType(llvm::FunctionType *ty, llvm::Type *termTy) {
+  if (ty->isVarArg())
+    return false;
+  auto *resultTy = llvm::dyn_cast<llvm::StructType>(ty->getReturnType());
+  if (!resultTy || resultTy->getNumElements() != 2 ||
+      resultTy->getElementType(0) != termTy ||
+      resultTy->getElementType(1) != termTy)
+    return false;
+  for (llvm::Type *paramTy : ty->params())
+    if (paramTy != termTy)
+      return false;
+  return true;
+}
+
+} // namespace firefly
+
+#endif
@@ -249,6 +249,10 @@ impl Module {
             .unwrap_or_else(|| self.add_function(name, ty))
     }
 
+    pub fn globals(&self) -> impl Iterator<Item = GlobalVariable> {
+        GlobalsIter::new(*self)
+    }
+
     pub fn get_global<S: Into<StringRef>>(self, name: S) -> Option<GlobalVariable> {
         extern "C" {
             fn LLVMGetNamedGlobal(
@@ -353,6 +357,87 @@ impl Module {
         unsafe { LLVMStripModuleDebugInfo(self) }
     }
 
+    /// Ensures that no call in tail position in an Erlang function grows the stack
+    ///
+    /// Calls which cannot be made as native tail calls are scheduled via the runtime's
+    /// `__firefly_tail_call` instead, and every other call to an Erlang function checks whether
+    /// its result is `marker`, in which case it makes the scheduled call.
+    ///
+    /// Only `musttail` calls are native tail calls, as LLVM guarantees no others. When `pad_args`
+    /// is true, a call passing fewer arguments than the caller received is made `musttail` with
+    /// the prototype of the caller, passing undefined values for the rest. This must only be set
+    /// when the calling convention of the target permits passing a function more arguments than
+    /// it takes.
+    pub fn guarantee_tail_calls(self, pad_args: bool, marker: u64) -> TailCallStats {
+        extern "C" {
+            fn LLVMFireflyGuaranteeTailCalls(
+                module: Module,
+                pad_args: bool,
+                marker: u64,
+                stats: *mut TailCallStats,
+            );
+        }
+        let mut stats = TailCallStats::default();
+        unsafe { LLVMFireflyGuaranteeTailCalls(self, pad_args, marker, &mut stats) }
+        stats
+    }
+
+    /// Lowers exception handling in the Erlang functions of this module to native unwinding
+    ///
+    /// Returns of an `Err` result with a known exception instead raise it via `__firefly_raise`,
+    /// and calls whose `Err` result is handled by a try or catch in the caller are made via an
+    /// `invoke`, which unwinds to the same handler via a landing pad suited to `model`.
+    pub fn lower_exceptions(self, model: ExceptionModel) -> ExceptionStats {
+        extern "C" {
+            fn LLVMFireflyLowerExceptions(
+                module: Module,
+                model: ExceptionModel,
+                stats: *mut ExceptionStats,
+            );
+        }
+        let mut stats = ExceptionStats::default();
+        unsafe { LLVMFireflyLowerExceptions(self, model, &mut stats) }
+        stats
+    }
+
+    /// Inserts a yield point on entry to every Erlang function in this module, and in the header
+    /// of every loop
+    ///
+    /// Each yield point consumes a reduction from the runtime's `__firefly_reductions_left`, and
+    /// calls `__firefly_yield_point` when none are left.
+    pub fn insert_yield_points(self) -> YieldPointStats {
+        extern "C" {
+            fn LLVMFireflyInsertYieldPoints(module: Module, stats: *mut YieldPointStats);
+        }
+        let mut stats = YieldPointStats::default();
+        unsafe { LLVMFireflyInsertYieldPoints(self, &mut stats) }
+        stats
+    }
+
+    /// Emits a stack map record for every call in an Erlang function in this module, describing
+    /// the stack slots which hold the terms live across it
+    ///
+    /// Terms live across a call are moved to stack slots, so that they are reloaded after the
+    /// call, and the call is made via a statepoint, so that LLVM emits the record. A call across
+    /// which a pointer derived from a term is live instead gets a pinning record.
+    pub fn emit_stack_maps(self) -> StackMapStats {
+        extern "C" {
+            fn LLVMFireflyEmitStackMaps(module: Module, stats: *mut StackMapStats);
+        }
+        let mut stats = StackMapStats::default();
+        unsafe { LLVMFireflyEmitStackMaps(self, &mut stats) }
+        stats
+    }
+
+    /// Appends the given assembly to the module-level inline assembly of this module
+    pub fn append_inline_asm<S: Into<StringRef>>(self, asm: S) {
+        extern "C" {
+            fn LLVMAppendModuleInlineAsm(m: Module, asm: *const u8, len: usize);
+        }
+        let asm = asm.into();
+        unsafe { LLVMAppendModuleInlineAsm(self, asm.data, asm.len) }
+    }
+
     /// Dump a debug representation of this module to stderr
     pub fn dump(&self) {
         extern "C" {
@@ -533,6 +618,65 @@ impl Emit for OwnedModule {
     }
 }
 
+/// The number of calls affected by `Module::guarantee_tail_calls`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TailCallStats {
+    /// Calls in tail position marked `musttail`
+    pub must_tail: u32,
+    /// Calls in tail position made `musttail` by padding their arguments to the caller's arity
+    pub padded: u32,
+    /// Calls in tail position scheduled via the runtime
+    pub trampolined: u32,
+    /// Calls not in tail position which check for a scheduled call
+    pub checked: u32,
+}
+
+/// The unwinding mechanism targeted by `Module::lower_exceptions`
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExceptionModel {
+    /// Landing pads, as used with DWARF unwind info, i.e. the Itanium C++ ABI
+    Itanium = 0,
+    /// Catch funclets, as used with Windows structured exception handling
+    Seh = 1,
+}
+
+/// The exception handling lowered by `Module::lower_exceptions`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExceptionStats {
+    /// Handlers of a try or catch reached by unwinding
+    pub handlers: u32,
+    /// Calls made via an `invoke` which unwinds to one of those handlers
+    pub invokes: u32,
+    /// Returns of an exception replaced with a call which raises it
+    pub raises: u32,
+}
+
+/// The stack map records emitted by `Module::emit_stack_maps`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StackMapStats {
+    /// Calls made via a statepoint, each of which has a record
+    pub safepoints: u32,
+    /// Stack slots listed in those records, i.e. the sum of the terms live across each call
+    pub slots: u32,
+    /// Records of calls across which something the collector cannot update is live, so that the
+    /// heap is not compacted while their frames are on the stack
+    pub pinning: u32,
+}
+
+/// The number of yield points inserted by `Module::insert_yield_points`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct YieldPointStats {
+    /// Yield points on function entry
+    pub entries: u32,
+    /// Yield points in loop headers
+    pub loops: u32,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ModuleFlagBehavior {
@@ -686,6 +830,33 @@ impl Iterator for FunctionsIter {
     }
 }
 
+struct GlobalsIter(GlobalVariable);
+impl GlobalsIter {
+    fn new(m: Module) -> Self {
+        extern "C" {
+            fn LLVMGetFirstGlobal(m: Module) -> GlobalVariable;
+        }
+        Self(unsafe { LLVMGetFirstGlobal(m) })
+    }
+}
+impl Iterator for GlobalsIter {
+    type Item = GlobalVariable;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        extern "C" {
+            fn LLVMGetNextGlobal(v: GlobalVariable) -> GlobalVariable;
+        }
+
+        if self.0.is_null() {
+            return None;
+        }
+
+        let next = self.0;
+        self.0 = unsafe { LLVMGetNextGlobal(next) };
+        Some(next)
+    }
+}
+
 extern "C" {
     #[cfg(not(windows))]
     pub fn LLVMEmitToFileDescriptor(
@@ -17,6 +17,7 @@
 #include "mlir/Transforms/DialectConversion.h"
 #include "llvm/ADT/StringExtras.h"
 #include "llvm/ADT/TypeSwitch.h"
+#include "llvm/Support/Endian.h"
 #include "llvm/Support/SHA1.h"
 #include <algorithm>
 #include <functional>
@@ -330,14 +331,14 @@ public:
       return exceptionTy;
 
     // Corresponds to ErlangException in firefly_rt
-    // { class: term, reason: term, trace: *mut Trace, fragment: *const
-    // HeapFragment }
+    // { class: term, reason: term, meta: term, trace: *mut Trace,
+    //   fragment: *const HeapFragment }
     Type termTy = getTermType();
     Type traceTy = LLVM::LLVMPointerType::get(getTraceType());
     Type i8Ty = getI8Type();
     Type i8PtrTy = LLVM::LLVMPointerType::get(i8Ty);
-    assert(succeeded(exceptionTy.setBody({termTy, termTy, traceTy, i8PtrTy},
-                                         /*packed=*/false)) &&
+    assert(succeeded(exceptionTy.setBody(
+               {termTy, termTy, termTy, traceTy, i8PtrTy}, /*packed=*/false)) &&
            "failed to set body of exception struct!");
     return exceptionTy;
   }
@@ -774,7 +775,10 @@ protected:
   Value createBinaryDataConstant(OpBuilder &builder, Location loc,
                                  StringRef value, bool isUtf8,
                                  ModuleOp &module) const {
+    // The encoding is part of the data, so identical bytes with a different
+    // encoding must not share a definition
     llvm::SHA1 hasher;
+    hasher.update((uint8_t)isUtf8);
     hasher.update(value);
     auto hash = llvm::toHex(hasher.result(), true);
     auto globalName = std::string("binary_") + hash;
@@ -789,8 +793,10 @@ protected:
       PatternRewriter::InsertionGuard insertGuard(builder);
       builder.setInsertionPointToStart(module.getBody());
 
+      // Literal binaries are never written to, so are placed in read-only data,
+      // where identical definitions are merged at link time
       dataConst = builder.create<LLVM::GlobalOp>(
-          loc, dataTy, /*isConstant=*/false, LLVM::Linkage::LinkonceODR,
+          loc, dataTy, /*isConstant=*/true, LLVM::Linkage::LinkonceODR,
           LLVM::ThreadLocalMode::NotThreadLocal, globalName, Attribute(),
           /*alignment=*/8, /*addrspace=*/0, /*dso_local=*/false);
 
@@ -821,6 +827,200 @@ protected:
     return ptr;
   }
 
+  // This function is used to construct a constant tuple or list term, given
+  // its serialized form as produced by codegen. The term and everything it
+  // references is emitted as read-only global data, with linkonce_odr linkage
+  // so that identical constants are shared across modules, and the value
+  // returned is the term encoded as a literal.
+  //
+  // The serialized form is a tag byte followed by the payload, with all
+  // integers in little-endian byte order:
+  //
+  // * `n`: nil
+  // * `a`: an atom, as a u32 length followed by its bytes
+  // * `i`: a small integer, as an i64
+  // * `f`: a float, as the u64 bits of an f64
+  // * `b`: a binary, as a u8 utf8 flag, a u32 length and its bytes
+  // * `t`: a tuple, as a u32 arity followed by its elements
+  // * `l`: a non-empty list, as a u32 length, its elements, and its tail
+  Value createLiteralConstant(OpBuilder &builder, Location loc, StringRef data,
+                              ModuleOp &module) const {
+    StringRef rest = data;
+    Value term = createLiteralTerm(builder, loc, rest, module);
+    assert(rest.empty() && "invalid literal constant, trailing data");
+    return term;
+  }
+
+  // Builds the term at the start of `data`, consuming it
+  Value createLiteralTerm(OpBuilder &builder, Location loc, StringRef &data,
+                          ModuleOp &module) const {
+    StringRef literal = data;
+    char tag = data.front();
+    data = data.drop_front(1);
+    switch (tag) {
+    case 'n':
+      return createTermConstant(builder, loc, NANBOX_INFINITY);
+    case 'a': {
+      StringRef name = data.substr(4, readLiteralU32(data));
+      data = data.drop_front(4 + name.size());
+      if (name == "false" || name == "true")
+        return createAtom(builder, loc, name, module);
+      auto ptr = createAtomDataGlobal(builder, loc, module, name);
+      return encodeLiteralTerm(builder, loc, ptr,
+                               NANBOX_CANONICAL_NAN | (uint64_t)0x02);
+    }
+    case 'i': {
+      uint64_t value = readLiteralU64(data);
+      data = data.drop_front(8);
+      return createIntegerConstant(builder, loc, value);
+    }
+    case 'f': {
+      uint64_t value = readLiteralU64(data);
+      data = data.drop_front(8);
+      return createTermConstant(builder, loc, value);
+    }
+    case 'b': {
+      bool isUtf8 = data.front() != 0;
+      data = data.drop_front(1);
+      StringRef bytes = data.substr(4, readLiteralU32(data));
+      data = data.drop_front(4 + bytes.size());
+      auto ptr = createBinaryDataConstant(builder, loc, bytes, isUtf8, module);
+      return encodeLiteralTerm(builder, loc, ptr,
+                               NANBOX_INFINITY | NANBOX_LITERAL_TAG);
+    }
+    case 't':
+    case 'l':
+      break;
+    default:
+      llvm_unreachable("invalid literal constant, unrecognized tag");
+    }
+
+    bool isTuple = tag == 't';
+    unsigned length = readLiteralU32(data);
+    data = data.drop_front(4);
+
+    // Constants are named by a hash of their contents, so when one has already
+    // been emitted, we can skip over it and reference the existing definition
+    literal = literal.take_front(getLiteralSize(literal));
+    llvm::SHA1 hasher;
+    hasher.update(literal);
+    auto globalName =
+        std::string("literal_") + llvm::toHex(hasher.result(), true);
+    // A list is laid out as an array of cons cells, each referencing the next
+    // as its tail
+    auto consTy = getConsType();
+    auto dataTy = isTuple ? getTupleType(length)
+                          : LLVM::LLVMArrayType::get(consTy, length);
+    auto dataConst = module.lookupSymbol<LLVM::GlobalOp>(globalName);
+    if (dataConst) {
+      data = data.drop_front(literal.size() - (data.data() - literal.data()));
+    } else {
+      PatternRewriter::InsertionGuard insertGuard(builder);
+      builder.setInsertionPointToStart(module.getBody());
+
+      // Tuples and cons cells must be aligned to at least 16 bytes, as is done
+      // by the runtime allocator
+      dataConst = builder.create<LLVM::GlobalOp>(
+          loc, dataTy, /*isConstant=*/true, LLVM::Linkage::LinkonceODR,
+          LLVM::ThreadLocalMode::NotThreadLocal, globalName, Attribute(),
+          /*alignment=*/16, /*addrspace=*/0, /*dso_local=*/false);
+
+      auto &initRegion = dataConst.getInitializerRegion();
+      builder.createBlock(&initRegion);
+
+      Value value = builder.create<LLVM::UndefOp>(loc, dataTy);
+      if (isTuple) {
+        Value header = createIsizeConstant(builder, loc, length);
+        value = builder.create<LLVM::InsertValueOp>(
+            loc, value, header, builder.getI64ArrayAttr(0));
+        for (unsigned i = 0; i < length; ++i) {
+          Value element = createLiteralTerm(builder, loc, data, module);
+          value = builder.create<LLVM::InsertValueOp>(
+              loc, value, element, builder.getI64ArrayAttr({1, i}));
+        }
+      } else {
+        auto consPtrTy = LLVM::LLVMPointerType::get(consTy);
+        Value base = builder.create<LLVM::AddressOfOp>(loc, dataConst);
+        Value zero = createI32Constant(builder, loc, 0);
+        for (unsigned i = 0; i < length; ++i) {
+          Value head = createLiteralTerm(builder, loc, data, module);
+          value = builder.create<LLVM::InsertValueOp>(
+              loc, value, head, builder.getI64ArrayAttr({i, 0}));
+          if (i + 1 < length) {
+            Value next = createI32Constant(builder, loc, i + 1);
+            Value nextPtr = builder.create<LLVM::GEPOp>(
+                loc, consPtrTy, base, ValueRange({zero, next}));
+            Value tail = encodeLiteralTerm(builder, loc, nextPtr,
+                                           NANBOX_INFINITY | (uint64_t)0x05);
+            value = builder.create<LLVM::InsertValueOp>(
+                loc, value, tail, builder.getI64ArrayAttr({i, 1}));
+          }
+        }
+        Value tail = createLiteralTerm(builder, loc, data, module);
+        value = builder.create<LLVM::InsertValueOp>(
+            loc, value, tail, builder.getI64ArrayAttr({length - 1, 1}));
+      }
+      builder.create<LLVM::ReturnOp>(loc, value);
+    }
+
+    Value ptr = builder.create<LLVM::AddressOfOp>(loc, dataConst);
+    return encodeLiteralTerm(builder, loc, ptr,
+                             NANBOX_INFINITY |
+                                 (uint64_t)(isTuple ? 0x07 : 0x05));
+  }
+
+  // Returns the size in bytes of the serialized literal at the start of `data`
+  static size_t getLiteralSize(StringRef data) {
+    switch (data.front()) {
+    case 'n':
+      return 1;
+    case 'a':
+      return 5 + readLiteralU32(data.drop_front(1));
+    case 'i':
+    case 'f':
+      return 9;
+    case 'b':
+      return 6 + readLiteralU32(data.drop_front(2));
+    case 't':
+    case 'l': {
+      size_t size = 5;
+      unsigned length = readLiteralU32(data.drop_front(1));
+      // A list is followed by its tail
+      if (data.front() == 'l')
+        ++length;
+      for (unsigned i = 0; i < length; ++i)
+        size += getLiteralSize(data.drop_front(size));
+      return size;
+    }
+    default:
+      llvm_unreachable("invalid literal constant, unrecognized tag");
+    }
+  }
+
+  static uint32_t readLiteralU32(StringRef data) {
+    assert(data.size() >= 4 && "invalid literal constant, truncated data");
+    return llvm::support::endian::read32le(data.data());
+  }
+
+  static uint64_t readLiteralU64(StringRef data) {
+    assert(data.size() >= 8 && "invalid literal constant, truncated data");
+    return llvm::support::endian::read64le(data.data());
+  }
+
+  // Encodes a pointer to constant data as a term with the given tag
+  //
+  // Unlike the other encoding helpers, this uses addition rather than a
+  // bitwise or, so that it can be used in global initializers, where the
+  // result must be a relocatable constant expression. The two are equivalent
+  // here, as the pointer and tag bits never overlap.
+  Value encodeLiteralTerm(OpBuilder &builder, Location loc, Value ptr,
+                          uint64_t tag) const {
+    auto termTy = getTermType();
+    Value ptrAsInt = builder.create<LLVM::PtrToIntOp>(loc, termTy, ptr);
+    Value tagValue = createTermConstant(builder, loc, tag);
+    return builder.create<LLVM::AddOp>(loc, ptrAsInt, tagValue);
+  }
+
   // This function is used to obtain an atom value corresponding to the given
   // StringRef
   //
@@ -1279,6 +1479,10 @@ struct ConstantOpLowering : public ConvertCIROpToLLVMPattern<cir::ConstantOp> {
                     return createBinaryDataConstant(rewriter, loc, str, isUtf8,
                                                     module);
                   })
+                  .Case<CIRConsType, TupleType>([&](Type) {
+                    StringRef data = attr.cast<StringAttr>().getValue();
+                    return createLiteralConstant(rewriter, loc, data, module);
+                  })
                   .Case<CIRBigIntType>([&](CIRBigIntType) {
                     auto bigIntAttr = attr.cast<BigIntAttr>();
                     return createBigIntConstant(rewriter, loc,
@@ -2653,7 +2857,9 @@ struct RaiseOpLowering : public ConvertCIROpToLLVMPattern<cir::RaiseOp> {
         ValueRange({klass, reason, trace}));
     auto exceptionPtr = callOp.getResult(0);
 
-    // Lastly, convert this op to our multi-value return convention
+    // Lastly, convert this op to our multi-value return convention, which is
+    // turned into a raise by unwinding where supported, see Exceptions.cpp in
+    // firefly_llvm
     Value isError = createBoolConstant(rewriter, loc, true);
     Value exceptionTerm =
         rewriter.create<LLVM::PtrToIntOp>(loc, getTermType(), exceptionPtr);
@@ -2679,9 +2885,8 @@ struct ExceptionClassOpLowering
 
     auto termPtrTy = LLVM::LLVMPointerType::get(getTermType());
     Value zero = createI32Constant(rewriter, loc, 0);
-    Value one = createI32Constant(rewriter, loc, 0);
     auto classAddr = rewriter.create<LLVM::GEPOp>(loc, termPtrTy, exceptionPtr,
-                                                  ValueRange({zero, one}));
+                                                  ValueRange({zero, zero}));
 
     rewriter.replaceOpWithNewOp<LLVM::LoadOp>(op, classAddr);
     return success();
@@ -2704,9 +2909,9 @@ struct ExceptionReasonOpLowering
 
     auto termPtrTy = LLVM::LLVMPointerType::get(getTermType());
     Value zero = createI32Constant(rewriter, loc, 0);
-    Value two = createI32Constant(rewriter, loc, 1);
+    Value one = createI32Constant(rewriter, loc, 1);
     auto reasonAddr = rewriter.create<LLVM::GEPOp>(loc, termPtrTy, exceptionPtr,
-                                                   ValueRange({zero, two}));
+                                                   ValueRange({zero, one}));
 
     rewriter.replaceOpWithNewOp<LLVM::LoadOp>(op, reasonAddr);
     return success();
@@ -2730,7 +2935,7 @@ struct ExceptionTraceOpLowering
     auto tracePtrTy =
         LLVM::LLVMPointerType::get(LLVM::LLVMPointerType::get(getTraceType()));
     Value zero = createI32Constant(rewriter, loc, 0);
-    Value three = createI32Constant(rewriter, loc, 2);
+    Value three = createI32Constant(rewriter, loc, 3);
     auto traceAddr = rewriter.create<LLVM::GEPOp>(loc, tracePtrTy, exceptionPtr,
                                                   ValueRange({zero, three}));
 
@@ -9,6 +9,10 @@ pub unsafe fn cause(_ptr: *mut u8) -> *mut ErlangPanic {
     intrinsics::abort()
 }
 
+pub unsafe fn take(_ptr: *mut u8) -> *mut ErlangPanic {
+    intrinsics::abort()
+}
+
 pub unsafe fn cleanup(_ptr: *mut u8) {
     intrinsics::abort()
 }
@@ -108,6 +108,15 @@ pub unsafe fn cause(ptr: *mut u8) -> *mut ErlangPanic {
     exception.cause
 }
 
+pub unsafe fn take(ptr: *mut u8) -> *mut ErlangPanic {
+    let exception = ptr as *mut uw::_Unwind_Exception;
+    if (*exception).exception_class != firefly_exception_class() {
+        __firefly_rethrow(ptr);
+    }
+    let exception = Box::from_raw(ptr as *mut Exception);
+    exception.cause
+}
+
 // Firefly's exception class identifier.  This is used by personality routines to
 // determine whether the exception was thrown by their own runtime.
 fn firefly_exception_class() -> uw::_Unwind_Exception_Class {
@@ -379,7 +388,10 @@ pub mod eh_frame_registry {
     }
 
     #[no_mangle]
-    pub unsafe extern "C" fn firefly_eh_register_frames(eh_frame_begin: *const u8, object: *mut u8) {
+    pub unsafe extern "C" fn firefly_eh_register_frames(
+        eh_frame_begin: *const u8,
+        object: *mut u8,
+    ) {
         __register_frame_info(eh_frame_begin, object);
     }
 
@@ -46,9 +46,9 @@ mod dwarf;
 /// This matches the structure of ErlangException in firefly_rt
 #[repr(C)]
 pub struct ErlangPanic {
-    _header: usize,
     kind: usize,
     reason: usize,
+    meta: usize,
     trace: *mut u8,
     _fragment: Option<*mut u8>,
 }
@@ -70,3 +70,31 @@ pub unsafe extern "C-unwind" fn __firefly_start_panic(payload: *mut ErlangPanic)
 pub unsafe extern "C" fn __firefly_get_exception(ptr: *mut u8) -> *mut ErlangPanic {
     imp::cause(ptr)
 }
+
+/// Entry point for generated code raising an exception, see `firefly_rt::error::unwind`
+///
+/// The runtime catches exceptions wherever it calls into generated code, so an exception which
+/// finds no handler is a bug.
+///
+/// # Safety
+///
+/// `payload` must be a valid pointer to an exception, ownership of which passes to the unwinder.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn __firefly_raise(payload: *mut ErlangPanic) -> ! {
+    imp::panic(payload);
+    core::intrinsics::abort()
+}
+
+/// Takes ownership of the cause of the exception being caught, given the exception object.
+///
+/// Exceptions other than Erlang exceptions, e.g. runtime panics, are resumed instead, so that only
+/// Erlang exceptions are ever caught.
+///
+/// # Safety
+///
+/// `ptr` must be the exception object passed to the landing pad catching it, and must not be used
+/// again afterwards.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn __firefly_catch_exception(ptr: *mut u8) -> *mut ErlangPanic {
+    imp::take(ptr)
+}
@@ -164,12 +164,14 @@ pub struct _PMD {
 pub struct _TypeDescriptor {
     pub pVFTable: *const u8,
     pub spare: *mut u8,
-    pub name: [u8; 12],
+    pub name: [u8; 14],
 }
 
 // Note that we intentionally ignore name mangling rules here: we don't want C++
 // to be able to catch Rust panics by simply declaring a `struct rust_panic`.
-const TYPE_NAME: [u8; 12] = *b"firefly_panic\0";
+//
+// Catch funclets in generated code match this name, see Exceptions.cpp in firefly_llvm
+const TYPE_NAME: [u8; 14] = *b"firefly_panic\0";
 
 static mut THROW_INFO: _ThrowInfo = _ThrowInfo {
     attributes: 0,
@@ -315,6 +317,15 @@ pub unsafe fn cause(payload: *mut u8) -> *mut ErlangPanic {
     exception.data
 }
 
+// Catch funclets in generated code only match our type descriptor, but the try intrinsic catches
+// any other exception with a null payload, which we have no way to resume
+pub unsafe fn take(payload: *mut u8) -> *mut ErlangPanic {
+    if payload.is_null() {
+        core::intrinsics::abort();
+    }
+    cause(payload)
+}
+
 pub unsafe fn cleanup(payload: *mut u8) {
     let exception = &mut *(payload as *mut Exception);
     cleanup_panic(exception.data);
@@ -31,6 +31,11 @@ pub unsafe fn cause(ptr: *mut u8) -> *mut ErlangPanic {
     exception.cause
 }
 
+pub unsafe fn take(ptr: *mut u8) -> *mut ErlangPanic {
+    let exception = Box::from_raw(ptr as *mut Exception);
+    exception.cause
+}
+
 #[inline]
 pub unsafe fn cleanup(ptr: *mut u8) {
     let exception = Box::from_raw(ptr as *mut Exception);
@@ -1,5 +1,6 @@
 extern crate which;
 
+use std::env;
 use std::process::{Command, Stdio};
 
 fn main() {
@@ -8,11 +9,22 @@ fn main() {
     let (hash, hash_date) = git_version();
     println!("cargo:rustc-env=FIREFLY_COMMIT_HASH={}", hash);
     println!("cargo:rustc-env=FIREFLY_COMMIT_DATE={}", hash_date);
+    println!(
+        "cargo:rustc-env=FIREFLY_BUILD_TARGET={}",
+        env::var("TARGET").unwrap()
+    );
+    println!(
+        "cargo:rustc-env=FIREFLY_BUILD_PROFILE={}",
+        env::var("PROFILE").unwrap()
+    );
 }
 
+/// Returns the hash and date of the commit being built, or `unknown` for both when not building
+/// from a git checkout, e.g. from a source archive
 pub fn git_version() -> (String, String) {
+    let unknown = || ("unknown".to_string(), "unknown".to_string());
     if let Err(_) = which::which("git") {
-        fail("Unable to locate 'git'");
+        return unknown();
     }
     let mut cmd = Command::new("git");
     cmd.arg("log")
@@ -20,7 +32,10 @@ pub fn git_version() -> (String, String) {
         .arg("--pretty=format:\"%h %cd\"")
         .arg("--date=iso-strict");
 
-    let out = output(&mut cmd);
+    let out = match output(&mut cmd) {
+        Some(out) => out,
+        None => return unknown(),
+    };
     let mut split = out.splitn(2, ' ');
     let hash = split
         .next()
@@ -36,24 +51,10 @@ pub fn git_version() -> (String, String) {
     (hash, date)
 }
 
-pub fn output(cmd: &mut Command) -> String {
-    let output = match cmd.stderr(Stdio::inherit()).output() {
-        Ok(status) => status,
-        Err(e) => fail(&format!(
-            "failed to execute command: {:?}\nerror: {}",
-            cmd, e
-        )),
-    };
+pub fn output(cmd: &mut Command) -> Option<String> {
+    let output = cmd.stderr(Stdio::null()).output().ok()?;
     if !output.status.success() {
-        panic!(
-            "command did not execute successfully: {:?}\n\
-             expected success, got: {}",
-            cmd, output.status
-        );
+        return None;
     }
-    String::from_utf8(output.stdout).unwrap()
-}
-
-fn fail(s: &str) -> ! {
-    panic!("\n{}\n\nbuild script failed, must exit now", s);
+    String::from_utf8(output.stdout).ok()
 }
@@ -0,0 +1,50 @@
+//! Metadata about how the compiler was built, and about the builds it produces.
+//!
+//! The commit, target and profile of the compiler are captured by the build script of this crate,
+//! so they are the same for every crate of the compiler which reports them. Executables embed the
+//! metadata of the build which produced them as `firefly_apps:build_info/0`, which the runtime
+//! reports, together with its own, as `erlang:system_info(firefly_build)`, so that the provenance
+//! of an artifact can be checked after the fact.
+use crate::{OptLevel, Options};
+
+/// The abbreviated hash of the commit the compiler was built from, or `unknown`
+pub const COMMIT_HASH: &str = env!("FIREFLY_COMMIT_HASH");
+/// The date of the commit the compiler was built from, in ISO 8601 format, or `unknown`
+pub const COMMIT_DATE: &str = env!("FIREFLY_COMMIT_DATE");
+/// The target triple the compiler itself was built for
+pub const HOST: &str = env!("FIREFLY_BUILD_TARGET");
+/// The cargo profile the compiler was built with, i.e. `debug` or `release`
+pub const PROFILE: &str = env!("FIREFLY_BUILD_PROFILE");
+
+/// Returns the Erlang term returned by `firefly_apps:build_info/0` in an executable built with
+/// `options`, by the compiler of the given release
+///
+/// The term is a proplist of the compiler version and commit, and of the target triple,
+/// optimization level, target features and whether debug assertions are enabled in generated code.
+pub fn build_info_term(release: &str, options: &Options) -> String {
+    let opt_level = match options.opt_level {
+        OptLevel::No => "0",
+        OptLevel::Less => "1",
+        OptLevel::Default => "2",
+        OptLevel::Aggressive => "3",
+        OptLevel::Size => "s",
+        OptLevel::SizeMin => "z",
+    };
+    let target_features = options
+        .codegen_opts
+        .target_features
+        .as_deref()
+        .unwrap_or("");
+    format!(
+        "[{{compiler_vsn, {:?}}}, {{commit_hash, {:?}}}, {{commit_date, {:?}}}, \
+         {{target, {:?}}}, {{opt_level, {:?}}}, {{target_features, {:?}}}, \
+         {{debug_assertions, {}}}]",
+        release,
+        COMMIT_HASH,
+        COMMIT_DATE,
+        options.target.triple(),
+        opt_level,
+        target_features,
+        options.debug_assertions
+    )
+}
@@ -31,6 +31,9 @@ pub struct App {
     /// application callback module for this application. If not present, then
     /// this is just a library application
     pub otp_module: Option<Symbol>,
+    /// The source text of the application resource term, if this application was
+    /// parsed from a resource file. This excludes the terminating `.`
+    pub resource: Option<String>,
 }
 impl App {
     /// Create a new empty application with the given name
@@ -42,9 +45,44 @@ impl App {
             modules: vec![],
             applications: vec![],
             otp_module: None,
+            resource: None,
         }
     }
 
+    /// Returns Erlang source text for the `{application, Name, Props}` term describing this
+    /// application
+    ///
+    /// If this application was parsed from a resource file, the original term is returned verbatim,
+    /// otherwise one is synthesized from the metadata we have.
+    pub fn resource_term(&self) -> String {
+        use std::fmt::Write;
+
+        if let Some(resource) = self.resource.as_ref() {
+            return resource.clone();
+        }
+
+        let mut term = String::new();
+        write!(&mut term, "{{application, {}, [", quote_atom(self.name)).unwrap();
+        write!(
+            &mut term,
+            "{{vsn, {:?}}}",
+            self.version.as_deref().unwrap_or("0.0.0")
+        )
+        .unwrap();
+        term.push_str(", {applications, [kernel, stdlib");
+        for app in self.applications.iter() {
+            if *app != "kernel" && *app != "stdlib" {
+                write!(&mut term, ", {}", quote_atom(*app)).unwrap();
+            }
+        }
+        term.push_str("]}");
+        if let Some(module) = self.otp_module {
+            write!(&mut term, ", {{mod, {{{}, []}}}}", quote_atom(module)).unwrap();
+        }
+        term.push_str("]}");
+        term
+    }
+
     /// Parse an application resource from the given path
     pub fn parse<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         let path = path.as_ref();
@@ -66,6 +104,22 @@ impl App {
     }
 }
 
+/// Renders an atom as Erlang source, quoting it if necessary
+fn quote_atom(atom: Symbol) -> String {
+    let name = atom.as_str().get();
+    let mut chars = name.chars();
+    let is_bare = chars
+        .next()
+        .map(|c| c.is_ascii_lowercase())
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@');
+    if is_bare {
+        name.to_string()
+    } else {
+        format!("'{}'", name.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
 #[derive(Logos, Copy, Clone, Debug, PartialEq)]
 enum Token {
     // Punctuation
@@ -339,7 +393,9 @@ fn parse_app<S: AsRef<str>>(source: S) -> anyhow::Result<App> {
 
     // Make sure we have a minimum viable spec
     let mut contents = parse_root(&mut lex)?;
-    let resource = contents.pop().map(|r| r.item).unwrap().as_tuple()?;
+    let resource = contents.pop().unwrap();
+    let resource_source = source[resource.span.clone()].to_string();
+    let resource = resource.item.as_tuple()?;
     if resource.len() < 3 {
         bail!("invalid resource, application spec must be a tuple of 3 elements");
     }
@@ -362,6 +418,7 @@ fn parse_app<S: AsRef<str>>(source: S) -> anyhow::Result<App> {
 
     // Initialize default app metadata with the parsed name
     let mut app = App::new(name);
+    app.resource = Some(resource_source);
 
     // We expect the third element to be a (possibly empty) list
     let mut meta = {
@@ -442,8 +499,7 @@ fn parse_root(lexer: &mut Lexer<'_>) -> anyhow::Result<Vec<Spanned<Term>>> {
             return Ok(contents);
         }
         match item.unwrap()? {
-            Ok(term) => {
-                contents.push(term);
+            Ok(mut term) => {
                 let loc = lexer.current_location();
                 let next = lexer.next();
                 if next.is_none() {
@@ -453,7 +509,12 @@ fn parse_root(lexer: &mut Lexer<'_>) -> anyhow::Result<Vec<Spanned<Term>>> {
                     );
                 }
                 match next.unwrap() {
-                    Token::Dot => continue,
+                    Token::Dot => {
+                        // Extend the span of the term to cover all of its source text
+                        term.span.end = lexer.span().start;
+                        contents.push(term);
+                        continue;
+                    }
                     token => {
                         let loc = lexer.current_location();
                         bail!("expected '.' at {}, but got '{}'", loc, token);
@@ -622,6 +683,26 @@ mod test {
             app.otp_module.map(|s| s.as_str().get()),
             Some("example_app")
         );
+        let resource = app.resource.as_ref().unwrap();
+        assert!(resource.starts_with("{application, example,"));
+        assert!(resource.ends_with("]}"));
+    }
+
+    #[test]
+    fn synthesized_app_resource_test() {
+        let mut app = App::new(Symbol::intern("synthesized"));
+        app.version = Some("1.0.0".to_string());
+        app.applications.push(Symbol::intern("kernel"));
+        app.applications.push(Symbol::intern("crypto"));
+        app.otp_module = Some(Symbol::intern("synthesized_app"));
+        let resource = app.resource_term();
+        assert_eq!(
+            resource.as_str(),
+            "{application, synthesized, [{vsn, \"1.0.0\"}, {applications, [kernel, stdlib, crypto]}, {mod, {synthesized_app, []}}]}"
+        );
+        let parsed = App::parse_str(format!("{}.", resource)).unwrap();
+        assert_eq!(parsed.name, app.name);
+        assert_eq!(parsed.otp_module, app.otp_module);
     }
 
     #[test]
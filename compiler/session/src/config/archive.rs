@@ -0,0 +1,270 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use flate2::read::GzDecoder;
+
+/// The name of the inner tarball containing the package files in a Hex package
+const HEX_CONTENTS: &str = "contents.tar.gz";
+
+/// The types of archive which are accepted as inputs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArchiveType {
+    /// A zip archive, e.g. an Erlang `.ez` archive
+    Zip,
+    /// An uncompressed tarball, e.g. a Hex package
+    Tar,
+    /// A gzip-compressed tarball
+    TarGz,
+}
+impl ArchiveType {
+    /// Returns the type of archive at `path`, based on its extension, or `None` if it isn't an
+    /// archive
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        if name.ends_with(".ez") || name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// An archive input which has been unpacked into the build directory
+///
+/// Archives are expected to contain a single application in the standard layout, optionally nested
+/// in a top-level `<app>-<vsn>` directory as in `.ez` archives. Hex packages, whose files are
+/// contained in a nested `contents.tar.gz`, are also supported.
+///
+/// The application is unpacked to `<dir>/<app>`, so that `<dir>` may be used as a code path when
+/// resolving `-include_lib("<app>/include/...")`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Archive {
+    /// The path of the archive itself
+    pub path: PathBuf,
+    /// The name of the application contained in the archive
+    pub app: String,
+    /// The directory to which the application was unpacked
+    pub root: PathBuf,
+    /// The paths of the sources contained in the `src` directory of the archive
+    pub sources: Vec<PathBuf>,
+    /// Compiled modules in the archive for which no source is available, which cannot be compiled
+    pub skipped: Vec<PathBuf>,
+}
+impl Archive {
+    /// Unpacks the archive at `path` into `dir`
+    pub fn extract(path: &Path, dir: &Path) -> anyhow::Result<Self> {
+        let ty = ArchiveType::detect(path)
+            .ok_or_else(|| anyhow!("{} is not a supported archive type", path.display()))?;
+        let file =
+            File::open(path).with_context(|| format!("unable to open {}", path.display()))?;
+        let mut entries = match ty {
+            ArchiveType::Zip => read_zip(file),
+            ArchiveType::Tar => read_tar(file),
+            ArchiveType::TarGz => read_tar(GzDecoder::new(file)),
+        }
+        .with_context(|| format!("unable to read archive {}", path.display()))?;
+
+        // Hex packages wrap the package files in another tarball
+        if let Some(index) = entries
+            .iter()
+            .position(|(name, _)| name.as_os_str() == HEX_CONTENTS)
+        {
+            let (_, contents) = entries.swap_remove(index);
+            entries = read_tar(GzDecoder::new(contents.as_slice())).with_context(|| {
+                format!("unable to read package contents of {}", path.display())
+            })?;
+        }
+
+        // If all entries are nested in a single directory, that directory is the application root
+        let top = entries.first().and_then(|(name, _)| top_level_dir(name));
+        let nested = top.is_some()
+            && entries
+                .iter()
+                .all(|(name, _)| top_level_dir(name) == top && name.components().count() > 1);
+        let app = match top {
+            Some(top) if nested => app_name(top),
+            _ => app_name(archive_stem(path)),
+        };
+        if app.is_empty() {
+            bail!(
+                "unable to determine the application name of {}",
+                path.display()
+            );
+        }
+
+        let root = dir.join(&app);
+        if root.exists() {
+            fs::remove_dir_all(&root)
+                .with_context(|| format!("unable to clean {}", root.display()))?;
+        }
+
+        let mut sources = vec![];
+        let mut beams = vec![];
+        for (name, contents) in entries.iter() {
+            let name = if nested {
+                name.components().skip(1).collect::<PathBuf>()
+            } else {
+                name.clone()
+            };
+            let dest = root.join(&name);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&dest, contents.as_slice())
+                .with_context(|| format!("unable to unpack {}", dest.display()))?;
+
+            let in_dir = |dir: &str| name.parent().map(|p| p == Path::new(dir)).unwrap_or(false);
+            match name.extension().and_then(|ext| ext.to_str()) {
+                Some("erl" | "abstr") if in_dir("src") => sources.push(dest),
+                Some("beam") if in_dir("ebin") => beams.push(dest),
+                _ => (),
+            }
+        }
+
+        let skipped = beams
+            .into_iter()
+            .filter(|beam| {
+                let stem = beam.file_stem();
+                !sources.iter().any(|src| src.file_stem() == stem)
+            })
+            .collect();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            app,
+            root,
+            sources,
+            skipped,
+        })
+    }
+
+    /// Returns the include paths provided by this archive
+    pub fn include_paths(&self) -> [PathBuf; 2] {
+        [self.root.join("include"), self.root.join("src")]
+    }
+}
+
+type Entries = Vec<(PathBuf, Vec<u8>)>;
+
+fn read_zip(file: File) -> anyhow::Result<Entries> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+        let name = entry
+            .enclosed_name()
+            .map(|name| name.to_path_buf())
+            .ok_or_else(|| anyhow!("invalid entry path: {}", entry.name()))?;
+        let mut contents = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut contents)?;
+        entries.push((name, contents));
+    }
+    Ok(entries)
+}
+
+fn read_tar<R: Read>(reader: R) -> anyhow::Result<Entries> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.into_owned();
+        if !is_enclosed(&name) {
+            bail!("invalid entry path: {}", name.display());
+        }
+        let name = name
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect::<PathBuf>();
+        let mut contents = vec![];
+        io::copy(&mut entry, &mut contents)?;
+        entries.push((name, contents));
+    }
+    Ok(entries)
+}
+
+/// Returns true if `path` is relative, and never refers to a parent directory
+fn is_enclosed(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+fn top_level_dir(path: &Path) -> Option<&str> {
+    match path.components().next()? {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    }
+}
+
+/// Strips all archive extensions from the file name of `path`
+fn archive_stem(path: &Path) -> &str {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    [".tar.gz", ".tgz", ".tar", ".ez", ".zip"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name)
+}
+
+/// Strips the version suffix from a `<app>-<vsn>` name, if present
+fn app_name(name: &str) -> String {
+    match name.rsplit_once('-') {
+        Some((app, vsn)) if vsn.starts_with(|c: char| c.is_ascii_digit()) => app.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn archive_type_detection() {
+        assert_eq!(
+            ArchiveType::detect(Path::new("foo-1.0.ez")),
+            Some(ArchiveType::Zip)
+        );
+        assert_eq!(
+            ArchiveType::detect(Path::new("foo-1.0.tar")),
+            Some(ArchiveType::Tar)
+        );
+        assert_eq!(
+            ArchiveType::detect(Path::new("foo.tar.gz")),
+            Some(ArchiveType::TarGz)
+        );
+        assert_eq!(
+            ArchiveType::detect(Path::new("foo.tgz")),
+            Some(ArchiveType::TarGz)
+        );
+        assert_eq!(ArchiveType::detect(Path::new("foo.erl")), None);
+    }
+
+    #[test]
+    fn archive_app_name() {
+        assert_eq!(
+            app_name(archive_stem(Path::new("dir/jsx-3.1.0.tar"))),
+            "jsx"
+        );
+        assert_eq!(app_name(archive_stem(Path::new("my-app.tar.gz"))), "my-app");
+        assert_eq!(app_name("cowlib-2.11.0"), "cowlib");
+        assert_eq!(app_name("cowlib"), "cowlib");
+    }
+
+    #[test]
+    fn archive_entry_paths() {
+        assert!(is_enclosed(Path::new("src/foo.erl")));
+        assert!(is_enclosed(Path::new("./src/foo.erl")));
+        assert!(!is_enclosed(Path::new("../foo.erl")));
+        assert!(!is_enclosed(Path::new("/etc/passwd")));
+    }
+}
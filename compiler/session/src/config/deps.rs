@@ -0,0 +1,368 @@
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use flate2::read::GzDecoder;
+use toml::{value::Table, Value};
+
+/// The name of the lock file, which records the checksum of each fetched dependency
+pub const LOCK_FILE: &str = "firefly.lock";
+
+/// Returns the directory to which dependencies of the project in `root` are fetched
+pub fn deps_dir(root: &Path) -> PathBuf {
+    root.join("_build").join("deps")
+}
+
+/// A Hex package required by a project
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dependency {
+    /// The name of the package
+    pub name: String,
+    /// The exact version of the package required
+    pub version: String,
+}
+impl Dependency {
+    /// Returns the file name of the package tarball, as named in the Hex repository
+    pub fn tarball(&self) -> String {
+        format!("{}-{}.tar", &self.name, &self.version)
+    }
+
+    /// Returns the path of the registry resource of the package, relative to its repository
+    pub fn registry(&self) -> String {
+        format!("packages/{}", &self.name)
+    }
+}
+
+/// Parses the `[deps]` table of the project manifest, mapping each package name to an exact version
+pub(super) fn parse_deps(deps: &Value) -> anyhow::Result<Vec<Dependency>> {
+    let deps = match deps {
+        Value::Table(deps) => deps,
+        _ => bail!("expected `deps` to be a table"),
+    };
+    let mut parsed = Vec::with_capacity(deps.len());
+    for (name, version) in deps.iter() {
+        let version = match version {
+            Value::String(version) if is_exact_version(version) => version.clone(),
+            Value::String(version) => bail!(
+                "invalid version for `{}`: expected an exact version, got `{}`",
+                name,
+                version
+            ),
+            _ => bail!("invalid version for `{}`: expected a string", name),
+        };
+        if !is_package_name(name) {
+            bail!("invalid package name `{}`", name);
+        }
+        parsed.push(Dependency {
+            name: name.clone(),
+            version,
+        });
+    }
+    Ok(parsed)
+}
+
+/// A dependency which has been fetched and verified
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LockedPackage {
+    pub dep: Dependency,
+    /// The SHA-256 checksum of the package tarball, as a lowercase hex string
+    pub checksum: String,
+}
+
+/// The lock file, i.e. `firefly.lock`, which pins the checksum of each dependency
+///
+/// A dependency whose tarball does not match its locked checksum is rejected, so that the contents
+/// of a locked dependency can never change without the lock file changing.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Lockfile {
+    pub packages: Vec<LockedPackage>,
+}
+impl Lockfile {
+    /// Loads the lock file at `path`, returning an empty lock file if it doesn't exist
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("unable to read lock file {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("invalid lock file {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let lock: Table = toml::from_str(contents)?;
+        let packages = match lock.get("package") {
+            None => return Ok(Self::default()),
+            Some(Value::Array(packages)) => packages,
+            Some(_) => bail!("expected `package` to be an array of tables"),
+        };
+        let mut parsed = Vec::with_capacity(packages.len());
+        for package in packages.iter() {
+            let field = |key: &str| {
+                package
+                    .get(key)
+                    .and_then(|value| value.as_str())
+                    .map(|value| value.to_string())
+                    .ok_or_else(|| anyhow!("expected package to have a `{}` string", key))
+            };
+            parsed.push(LockedPackage {
+                dep: Dependency {
+                    name: field("name")?,
+                    version: field("version")?,
+                },
+                checksum: field("checksum")?,
+            });
+        }
+        Ok(Self { packages: parsed })
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, self.to_string())
+            .with_context(|| format!("unable to write lock file {}", path.display()))
+    }
+
+    /// Returns the locked package for `dep`, if it has been locked at the same version
+    pub fn get(&self, dep: &Dependency) -> Option<&LockedPackage> {
+        self.packages.iter().find(|p| &p.dep == dep)
+    }
+}
+impl ToString for Lockfile {
+    fn to_string(&self) -> String {
+        let mut out = String::from("# This file is generated by `firefly deps`, do not edit it\n");
+        let mut packages = self.packages.iter().collect::<Vec<_>>();
+        packages.sort_by(|a, b| a.dep.name.cmp(&b.dep.name));
+        for package in packages {
+            write!(
+                &mut out,
+                "\n[[package]]\nname = {:?}\nversion = {:?}\nchecksum = {:?}\n",
+                &package.dep.name, &package.dep.version, &package.checksum
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+/// The checksums the registry of a repository lists for a release of a package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryRelease {
+    /// The SHA-256 checksum of the contents of the tarball, i.e. of what its `CHECKSUM` file holds
+    pub inner_checksum: String,
+    /// The SHA-256 checksum of the tarball itself, which releases published before Hex listed it
+    /// do not have
+    pub outer_checksum: Option<String>,
+}
+
+/// Returns the checksums listed for `dep` in `resource`, the registry resource of its package, see
+/// [`Dependency::registry`], or `None` if the version required is not listed
+///
+/// The resource is a gzipped `Signed` protobuf message, whose payload is a `Package` message
+/// listing each release, see `registry.proto` in `hex_core`. Only the fields needed here are
+/// decoded, and the signature is not verified, the resource is trusted as far as the connection to
+/// the repository is.
+pub fn registry_release(
+    dep: &Dependency,
+    resource: &[u8],
+) -> anyhow::Result<Option<RegistryRelease>> {
+    let mut signed = vec![];
+    GzDecoder::new(resource)
+        .read_to_end(&mut signed)
+        .context("invalid registry resource")?;
+    let invalid = || anyhow!("invalid registry resource for {}", &dep.name);
+    let payload = protobuf_fields(&signed)?
+        .into_iter()
+        .find(|(field, _)| *field == 1)
+        .ok_or_else(invalid)?
+        .1;
+    for (field, release) in protobuf_fields(payload)? {
+        if field != 1 {
+            continue;
+        }
+        let mut version = None;
+        let mut inner = None;
+        let mut outer = None;
+        for (field, value) in protobuf_fields(release)? {
+            match field {
+                1 => version = Some(value),
+                2 => inner = Some(to_hex(value)),
+                5 => outer = Some(to_hex(value)),
+                _ => (),
+            }
+        }
+        if version == Some(dep.version.as_bytes()) {
+            return Ok(Some(RegistryRelease {
+                inner_checksum: inner.ok_or_else(invalid)?,
+                outer_checksum: outer,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the length-delimited fields of the protobuf message `bytes`, with their field numbers,
+/// skipping fields of any other wire type
+fn protobuf_fields(mut bytes: &[u8]) -> anyhow::Result<Vec<(u64, &[u8])>> {
+    let mut fields = vec![];
+    while !bytes.is_empty() {
+        let key = varint(&mut bytes)?;
+        let skip = match key & 0x7 {
+            0 => {
+                varint(&mut bytes)?;
+                0
+            }
+            1 => 8,
+            2 => {
+                let len = varint(&mut bytes)? as usize;
+                if len > bytes.len() {
+                    bail!("truncated protobuf message");
+                }
+                fields.push((key >> 3, &bytes[..len]));
+                len
+            }
+            5 => 4,
+            wire => bail!("unsupported protobuf wire type {}", wire),
+        };
+        if skip > bytes.len() {
+            bail!("truncated protobuf message");
+        }
+        bytes = &bytes[skip..];
+    }
+    Ok(fields)
+}
+
+/// Reads a base 128 varint from the front of `bytes`
+fn varint(bytes: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| anyhow!("truncated protobuf message"))?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("invalid protobuf varint")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns the tarballs of the locked dependencies of the project in `root` which have been
+/// fetched, along with the locked dependencies which have not
+pub fn locked_dependencies(root: &Path) -> anyhow::Result<(Vec<PathBuf>, Vec<Dependency>)> {
+    let lock = Lockfile::load(&root.join(LOCK_FILE))?;
+    let dir = deps_dir(root);
+    let mut fetched = vec![];
+    let mut unfetched = vec![];
+    for package in lock.packages.iter() {
+        let path = dir.join(package.dep.tarball());
+        if path.is_file() {
+            fetched.push(path);
+        } else {
+            unfetched.push(package.dep.clone());
+        }
+    }
+    Ok((fetched, unfetched))
+}
+
+/// Package names in Hex are restricted to lowercase alphanumerics and underscores
+fn is_package_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Returns true if `version` is an exact semantic version, e.g. `1.2.3` or `1.0.0-rc.1`
+fn is_exact_version(version: &str) -> bool {
+    let core = version.split(&['-', '+'][..]).next().unwrap();
+    let parts = core.split('.').collect::<Vec<_>>();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write as _;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    #[test]
+    fn lockfile_roundtrip() {
+        let lock = Lockfile {
+            packages: vec![LockedPackage {
+                dep: Dependency {
+                    name: "jsx".to_string(),
+                    version: "3.1.0".to_string(),
+                },
+                checksum: "0c5cc8fdc11b53cc25cf65ac6705ad39e54ecc56d1c22e4adb8f5a53fb9427f3"
+                    .to_string(),
+            }],
+        };
+        assert_eq!(Lockfile::parse(&lock.to_string()).unwrap(), lock);
+    }
+
+    /// Encodes a length-delimited protobuf field
+    fn field(number: u8, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![(number << 3) | 2];
+        let mut len = value.len();
+        while len >= 0x80 {
+            bytes.push((len as u8 & 0x7f) | 0x80);
+            len >>= 7;
+        }
+        bytes.push(len as u8);
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    #[test]
+    fn registry_releases() {
+        let release = |version: &str, inner: u8, outer: Option<u8>| {
+            let mut release = field(1, version.as_bytes());
+            release.extend(field(2, &[inner; 32]));
+            // A retired release carries a varint field, which is skipped
+            release.extend_from_slice(&[4 << 3, 0x96, 0x01]);
+            if let Some(outer) = outer {
+                release.extend(field(5, &[outer; 32]));
+            }
+            field(1, &release)
+        };
+        let mut package = release("1.0.0", 0xab, None);
+        package.extend(release("1.1.0", 0xcd, Some(0xef)));
+        package.extend(field(2, b"jsx"));
+        let mut signed = field(1, &package);
+        signed.extend(field(2, &[0; 16]));
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&signed).unwrap();
+        let resource = encoder.finish().unwrap();
+
+        let dep = |version: &str| Dependency {
+            name: "jsx".to_string(),
+            version: version.to_string(),
+        };
+        let found = registry_release(&dep("1.1.0"), &resource).unwrap().unwrap();
+        assert_eq!(found.inner_checksum, "cd".repeat(32));
+        assert_eq!(found.outer_checksum, Some("ef".repeat(32)));
+        let found = registry_release(&dep("1.0.0"), &resource).unwrap().unwrap();
+        assert_eq!(found.inner_checksum, "ab".repeat(32));
+        assert_eq!(found.outer_checksum, None);
+        assert_eq!(registry_release(&dep("2.0.0"), &resource).unwrap(), None);
+        assert!(registry_release(&dep("1.0.0"), &resource[..20]).is_err());
+    }
+
+    #[test]
+    fn exact_versions() {
+        assert!(is_exact_version("1.0.0"));
+        assert!(is_exact_version("1.0.0-rc.1"));
+        assert!(!is_exact_version("1.0"));
+        assert!(!is_exact_version(">= 1.0.0"));
+    }
+}
@@ -8,6 +8,7 @@ use firefly_util::diagnostics::FileName;
 pub enum InputType {
     Erlang,
     AbstractErlang,
+    CoreErlang,
     SSA,
     MLIR,
     Unknown(Option<String>),
@@ -16,6 +17,7 @@ impl InputType {
     const TYPES: &'static [InputType] = &[
         InputType::Erlang,
         InputType::AbstractErlang,
+        InputType::CoreErlang,
         InputType::SSA,
         InputType::MLIR,
     ];
@@ -28,6 +30,7 @@ impl InputType {
             None => false,
             Some("erl") => true,
             Some("abstr") => true,
+            Some("core") => true,
             Some("ssa") => true,
             Some("mlir") => true,
             Some(_) => false,
@@ -42,6 +45,7 @@ impl InputType {
             None => false,
             Some("erl") if self == &Self::Erlang => true,
             Some("abstr") if self == &Self::AbstractErlang => true,
+            Some("core") if self == &Self::CoreErlang => true,
             Some("ssa") if self == &Self::SSA => true,
             Some("mlir") if self == &Self::MLIR => true,
             Some(other) => match self {
@@ -65,6 +69,7 @@ impl fmt::Display for InputType {
         match self {
             Self::Erlang => f.write_str("erl"),
             Self::AbstractErlang => f.write_str("abstr"),
+            Self::CoreErlang => f.write_str("core"),
             Self::SSA => f.write_str("ssa"),
             Self::MLIR => f.write_str("mlir"),
             Self::Unknown(None) => f.write_str("unknown (no extension)"),
@@ -99,6 +104,7 @@ impl Input {
             Input::File(ref file) => match file.extension().and_then(|ext| ext.to_str()) {
                 Some("erl") => InputType::Erlang,
                 Some("abstr") => InputType::AbstractErlang,
+                Some("core") => InputType::CoreErlang,
                 Some("ssa") => InputType::SSA,
                 Some("mlir") => InputType::MLIR,
                 Some(t) => InputType::Unknown(Some(t.to_string())),
@@ -109,6 +115,8 @@ impl Input {
                     InputType::Erlang
                 } else if name.ends_with(".abstr") {
                     InputType::AbstractErlang
+                } else if name.ends_with(".core") {
+                    InputType::CoreErlang
                 } else if name.ends_with(".ssa") {
                     InputType::SSA
                 } else if name.ends_with(".mlir") {
@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use toml::{value::Table, Value};
+
+use firefly_target::Target;
+
+use super::deps::{parse_deps, Dependency};
+
+/// The name of the project manifest
+pub const MANIFEST_FILE: &str = "firefly.toml";
+
+/// A macro definition, as given by `-D NAME[=VALUE]`
+pub type MacroDefine = (String, Option<String>);
+
+/// The project manifest, i.e. `firefly.toml`
+///
+/// Dependencies are given in a `[deps]` table, mapping each package name to an exact version.
+/// Version requirements are not resolved, so transitive dependencies must also be listed.
+///
+/// Macros may be defined for all targets in a `[defines]` table, or for specific targets in a
+/// `[target.<selector>.defines]` table, where the selector is a target triple, architecture,
+/// operating system or family, e.g. `wasm32` or `unix`. A value of `true` defines the macro as with
+/// `-D NAME`, a value of `false` leaves it undefined, and any other value is used as the Erlang
+/// source of the macro body, as with `-D NAME=VALUE`.
+///
+/// ```toml
+/// [deps]
+/// jsx = "3.1.0"
+///
+/// [defines]
+/// USE_JSX = true
+///
+/// [target.wasm32.defines]
+/// BACKEND = "browser"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub deps: Vec<Dependency>,
+    /// Macros defined for all targets
+    pub defines: Vec<MacroDefine>,
+    /// Macros defined only for targets matching a selector
+    pub targets: Vec<TargetConfig>,
+}
+impl Manifest {
+    /// Loads the manifest at `path`, returning an empty manifest if it doesn't exist
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("unable to read manifest {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("invalid manifest {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let manifest: Table = toml::from_str(contents)?;
+        let deps = match manifest.get("deps") {
+            None => vec![],
+            Some(deps) => parse_deps(deps)?,
+        };
+        let defines = match manifest.get("defines") {
+            None => vec![],
+            Some(defines) => parse_defines("defines", defines)?,
+        };
+        let targets = match manifest.get("target") {
+            None => vec![],
+            Some(Value::Table(targets)) => targets
+                .iter()
+                .map(|(selector, config)| TargetConfig::parse(selector, config))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            Some(_) => bail!("expected `target` to be a table"),
+        };
+        Ok(Self {
+            deps,
+            defines,
+            targets,
+        })
+    }
+
+    /// Returns the macros defined for `target`, with target-specific definitions last
+    pub fn defines_for<'a>(&'a self, target: &'a Target) -> impl Iterator<Item = &'a MacroDefine> {
+        let targets = self
+            .targets
+            .iter()
+            .filter(move |config| config.matches(target))
+            .flat_map(|config| config.defines.iter());
+        self.defines.iter().chain(targets)
+    }
+}
+
+/// Configuration which only applies to targets matching `selector`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetConfig {
+    pub selector: String,
+    pub defines: Vec<MacroDefine>,
+}
+impl TargetConfig {
+    fn parse(selector: &str, config: &Value) -> anyhow::Result<Self> {
+        let config = match config {
+            Value::Table(config) => config,
+            _ => bail!("expected `target.{}` to be a table", selector),
+        };
+        let defines = match config.get("defines") {
+            None => vec![],
+            Some(defines) => parse_defines(&format!("target.{}.defines", selector), defines)?,
+        };
+        Ok(Self {
+            selector: selector.to_string(),
+            defines,
+        })
+    }
+
+    /// Returns true if the selector is the triple, architecture, operating system or a family of
+    /// `target`
+    pub fn matches(&self, target: &Target) -> bool {
+        let selector = self.selector.as_str();
+        selector == target.triple()
+            || selector == target.arch
+            || selector == target.options.os
+            || target
+                .options
+                .families
+                .iter()
+                .any(|family| *family == selector)
+    }
+}
+
+fn parse_defines(key: &str, defines: &Value) -> anyhow::Result<Vec<MacroDefine>> {
+    let defines = match defines {
+        Value::Table(defines) => defines,
+        _ => bail!("expected `{}` to be a table", key),
+    };
+    let mut parsed = Vec::with_capacity(defines.len());
+    for (name, value) in defines.iter() {
+        let value = match value {
+            Value::Boolean(true) => None,
+            Value::Boolean(false) => continue,
+            Value::String(value) => Some(value.clone()),
+            Value::Integer(value) => Some(value.to_string()),
+            Value::Float(value) => Some(value.to_string()),
+            _ => bail!(
+                "invalid value for `{}.{}`: expected a boolean, number or string",
+                key,
+                name
+            ),
+        };
+        parsed.push((name.clone(), value));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest_deps() {
+        let manifest = Manifest::parse("[deps]\njsx = \"3.1.0\"\ncowlib = \"2.11.0\"\n").unwrap();
+        assert_eq!(manifest.deps.len(), 2);
+        assert!(manifest.deps.contains(&Dependency {
+            name: "jsx".to_string(),
+            version: "3.1.0".to_string(),
+        }));
+        assert_eq!(Manifest::parse("").unwrap(), Manifest::default());
+        assert!(Manifest::parse("[deps]\njsx = \"~> 3.1\"\n").is_err());
+        assert!(Manifest::parse("[deps]\nJsx = \"3.1.0\"\n").is_err());
+    }
+
+    #[test]
+    fn manifest_defines() {
+        let manifest = Manifest::parse(
+            "[defines]\nDEBUG_LOG = true\nLEGACY = false\nLEVEL = 2\n\n[target.wasm32.defines]\nBACKEND = \"browser\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.defines,
+            vec![
+                ("DEBUG_LOG".to_string(), None),
+                ("LEVEL".to_string(), Some("2".to_string())),
+            ]
+        );
+        assert_eq!(
+            manifest.targets,
+            vec![TargetConfig {
+                selector: "wasm32".to_string(),
+                defines: vec![("BACKEND".to_string(), Some("browser".to_string()))],
+            }]
+        );
+        assert!(Manifest::parse("[defines]\nBAD = [1]\n").is_err());
+    }
+}
@@ -1,10 +1,13 @@
 //! Contains infrastructure for configuring the compiler, including parsing
 //! command-line options.
 mod app;
+mod archive;
 mod cfguard;
 mod debug;
+mod deps;
 mod input;
 mod linker;
+mod manifest;
 mod mlir;
 mod optimization;
 mod options;
@@ -13,10 +16,13 @@ mod project;
 mod sanitizer;
 
 pub use self::app::*;
+pub use self::archive::*;
 pub use self::cfguard::*;
 pub use self::debug::*;
+pub use self::deps::*;
 pub use self::input::{Input, InputType};
 pub use self::linker::*;
+pub use self::manifest::*;
 pub use self::mlir::*;
 pub use self::optimization::*;
 pub use self::options::{
@@ -22,7 +22,7 @@ use clap::ArgMatches;
 use firefly_intern::Symbol;
 use firefly_target::spec::{CodeModel, RelocModel, SplitDebugInfo, TlsModel};
 use firefly_target::{self as target, Target};
-use firefly_util::diagnostics::{ColorArg, ColorChoice, FileName};
+use firefly_util::diagnostics::{ColorArg, ColorChoice, ErrorFormat, FileName};
 use firefly_util::error::{HelpRequested, Verbosity};
 use firefly_util::fs::NativeLibraryKind;
 
@@ -50,8 +50,16 @@ pub struct Options {
     pub app_type: ProjectType,
     pub output_types: OutputTypes,
     pub color: ColorChoice,
+    /// The format in which diagnostics are rendered, see `--error-format`
+    pub error_format: ErrorFormat,
     pub warnings_as_errors: bool,
     pub no_warn: bool,
+    /// Check functions against their type specifications, see `--analyze`
+    pub analyze: bool,
+    /// Keep every intermediate file produced during compilation, see `--save-temps`
+    pub save_temps: bool,
+    /// Link a fully static executable, including libc and native libraries, see `--static`
+    pub static_link: bool,
     pub verbosity: Verbosity,
 
     pub host: Target,
@@ -78,6 +86,14 @@ pub struct Options {
     pub include_path: VecDeque<PathBuf>,
     pub link_libraries: Vec<(String, Option<String>, NativeLibraryKind)>,
     pub defines: HashMap<String, Option<String>>,
+    /// A `sys.config`-style file whose configuration is bundled into executables
+    pub sys_config: Option<PathBuf>,
+    /// Additional applications to start during boot, with an optional restart type
+    pub boot_apps: Vec<(Symbol, Option<String>)>,
+    /// The resources of other applications to bundle into executables, e.g. dependencies
+    pub bundled_apps: Vec<App>,
+    /// The locked dependencies which have not been fetched by `firefly deps`, so are not compiled
+    pub unfetched_deps: Vec<Dependency>,
 
     pub cli_forced_thinlto_off: bool,
 }
@@ -90,12 +106,12 @@ macro_rules! option {
 
 impl Options {
     pub fn new<'a>(
-        codegen_opts: CodegenOptions,
+        mut codegen_opts: CodegenOptions,
         debugging_opts: DebuggingOptions,
         cwd: PathBuf,
         args: &ArgMatches<'a>,
     ) -> anyhow::Result<Self> {
-        let input_files = match args.values_of_os("inputs") {
+        let mut input_files = match args.values_of_os("inputs") {
             None => {
                 // By default treat the current working directory as a standard Erlang app
                 vec![FileName::Real(cwd.clone())]
@@ -153,11 +169,29 @@ impl Options {
 
         // Output/artifacts
         let app = detect_app(args, cwd.as_path(), input_files.as_slice())?;
+
+        // Dependencies fetched by `firefly deps` are compiled along with the application, those
+        // not yet fetched are warned about once diagnostics are set up, as not every command needs
+        // them, e.g. compiling a single file
+        let (fetched_deps, unfetched_deps) = locked_dependencies(&cwd)?;
+        for dep in fetched_deps {
+            input_files.push(dep.into());
+        }
         let app_type_opt: Option<ProjectType> =
             ParseOption::parse_option(&option!("app-type"), &args)?;
         let app_type = app_type_opt.unwrap_or(ProjectType::Executable);
-        let output_types = OutputTypes::parse_option(&option!("emit"), &args)?;
+        let mut output_types = OutputTypes::parse_option(&option!("emit"), args)?;
+        let save_temps = args.is_present("save-temps");
+        if save_temps {
+            output_types.save_temps();
+        }
         let color_arg = ColorArg::parse_option(&option!("color"), &args)?;
+        let error_format = match args.value_of("error-format") {
+            None => ErrorFormat::default(),
+            Some(format) => format.parse().map_err(|_| {
+                str_to_clap_err("error-format", "expected one of human, json, or sarif")
+            })?,
+        };
 
         let maybe_sysroot: Option<PathBuf> = ParseOption::parse_option(&option!("sysroot"), &args)?;
         let sysroot = match &maybe_sysroot {
@@ -165,7 +199,7 @@ impl Options {
             None => filesearch::get_or_default_sysroot(),
         };
 
-        let target: Target = ParseOption::parse_option(&option!("target"), &args)?;
+        let mut target: Target = ParseOption::parse_option(&option!("target"), args)?;
         match &target.pointer_width {
             32 | 64 => (),
             w => {
@@ -179,6 +213,13 @@ impl Options {
                 .into())
             }
         }
+        let static_link = args.is_present("static");
+        if static_link {
+            if app_type != ProjectType::Executable {
+                bail!("--static can only be used when building an executable");
+            }
+            target = static_target(target)?;
+        }
         let host_triple = target::host_triple();
         let host = Target::search(host_triple)?;
         let target_triple = target.triple();
@@ -191,8 +232,9 @@ impl Options {
 
         let mut defines = default_configuration(&target);
 
+        // `-O` takes no value, it is shorthand for `-C opt-level=2`
         let opt_level = if args.is_present("opt-level") {
-            ParseOption::parse_option(&option!("opt-level"), &args)?
+            OptLevel::Default
         } else {
             codegen_opts.opt_level
         };
@@ -223,10 +265,20 @@ impl Options {
         }
 
         let link_libraries = parse_link_libraries(&args)?;
+        if let Some(values) = args.values_of("link-arg") {
+            codegen_opts
+                .linker_arg
+                .extend(values.map(|value| value.to_string()));
+        }
         let source_path_prefix = parse_source_path_prefix(&args)?;
 
         let output_file = args.value_of_os("output").map(PathBuf::from);
         let output_dir = args.value_of_os("output-dir").map(PathBuf::from);
+        // Definitions from the project manifest may be overridden on the command line
+        let manifest = Manifest::load(&cwd.join(MANIFEST_FILE))?;
+        for (name, value) in manifest.defines_for(&target) {
+            defines.insert(name.clone(), value.clone());
+        }
         if let Some(values) = args.values_of("define") {
             for value in values {
                 let define = self::parse_key_value(value)?;
@@ -241,6 +293,43 @@ impl Options {
             Some("error") => (true, false),
             None | Some(_) => (false, false),
         };
+        let sys_config = args.value_of_os("config").map(|p| cwd.join(p));
+        if let Some(path) = sys_config.as_ref() {
+            if !path.is_file() {
+                bail!("invalid configuration file: {}", path.display());
+            }
+        }
+        let mut boot_apps = vec![];
+        if let Some(values) = args.values_of("boot-app") {
+            for value in values {
+                let app = self::parse_key_value(value)?;
+                match app.value() {
+                    None | Some("permanent" | "transient" | "temporary") => (),
+                    Some(_) => {
+                        return Err(str_to_clap_err(
+                            "boot-app",
+                            "expected restart type to be one of permanent, transient or temporary",
+                        )
+                        .into())
+                    }
+                }
+                boot_apps.push((
+                    Symbol::intern(app.name()),
+                    app.value().map(|s| s.to_string()),
+                ));
+            }
+        }
+        let mut bundled_apps = vec![];
+        if let Some(values) = args.values_of_os("app-resource") {
+            for value in values {
+                let path = cwd.join(value);
+                if !path.is_file() {
+                    bail!("invalid application resource file: {}", path.display());
+                }
+                bundled_apps.push(App::parse(&path)?);
+            }
+        }
+        let analyze = args.is_present("analyze");
         let verbosity = Verbosity::from_level(args.occurrences_of("verbose") as isize);
         let mut include_path = VecDeque::new();
         let local_include_path = cwd.join("include");
@@ -258,8 +347,12 @@ impl Options {
             app_type,
             output_types,
             color: color_arg.into(),
+            error_format,
             warnings_as_errors,
             no_warn,
+            analyze,
+            save_temps,
+            static_link,
             verbosity,
             host,
             target,
@@ -281,6 +374,10 @@ impl Options {
             include_path,
             link_libraries,
             defines,
+            sys_config,
+            boot_apps,
+            bundled_apps,
+            unfetched_deps,
             cli_forced_thinlto_off: false,
         })
     }
@@ -336,8 +433,12 @@ impl Options {
             app_type,
             output_types: OutputTypes::default(),
             color: ColorChoice::Auto,
+            error_format: ErrorFormat::default(),
             warnings_as_errors: false,
             no_warn: false,
+            analyze: false,
+            save_temps: false,
+            static_link: false,
             verbosity: Verbosity::from_level(0),
             host,
             target,
@@ -359,6 +460,10 @@ impl Options {
             include_path: Default::default(),
             link_libraries: Default::default(),
             defines,
+            sys_config: None,
+            boot_apps: vec![],
+            bundled_apps: vec![],
+            unfetched_deps: vec![],
             cli_forced_thinlto_off: false,
         })
     }
@@ -383,6 +488,39 @@ impl Options {
             })
     }
 
+    /// Returns the path to which the build manifest should be written, if one was requested
+    pub fn manifest_file(&self) -> Option<PathBuf> {
+        if self.debugging_opts.parse_only || self.debugging_opts.analyze_only {
+            return None;
+        }
+        if !self.output_types.contains_key(&OutputType::Manifest) {
+            return None;
+        }
+        Some(self.output_dir().join(format!(
+            "{}.manifest.{}",
+            self.app.name,
+            OutputType::Manifest.extension()
+        )))
+    }
+
+    /// Returns the path to which the call graph of the build should be written, if one was requested
+    ///
+    /// The graph is written in DOT format to `<app>.depgraph.dot`, and as JSON to the same path with
+    /// a `json` extension, i.e. `<app>.depgraph.json`.
+    pub fn depgraph_file(&self) -> Option<PathBuf> {
+        if self.debugging_opts.parse_only || self.debugging_opts.analyze_only {
+            return None;
+        }
+        if !self.output_types.contains_key(&OutputType::DepGraph) {
+            return None;
+        }
+        Some(self.output_dir().join(format!(
+            "{}.depgraph.{}",
+            self.app.name,
+            OutputType::DepGraph.extension()
+        )))
+    }
+
     pub fn lto(&self) -> Lto {
         match self.codegen_opts.lto {
             LtoCli::No => Lto::No,
@@ -406,6 +544,13 @@ impl Options {
             })
     }
 
+    /// Returns the directory to which archive inputs are unpacked
+    ///
+    /// Each archive is unpacked to a subdirectory named for the application it contains.
+    pub fn archives_dir(&self) -> PathBuf {
+        self.output_dir().join("archives")
+    }
+
     pub fn relocation_model(&self) -> RelocModel {
         self.codegen_opts
             .relocation_model
@@ -441,6 +586,9 @@ impl Options {
 
     /// Check whether this compile session and crate type use static crt.
     pub fn crt_static(&self, _app_type: Option<ProjectType>) -> bool {
+        if self.static_link {
+            return true;
+        }
         if !self.target.options.crt_static_respected {
             // If the target does not opt in to crt-static support, use its default.
             return self.target.options.crt_static_default;
@@ -477,6 +625,11 @@ impl Options {
         )
     }
 
+    /// Returns true if the target differs from the host the compiler is running on
+    pub fn is_cross_compiling(&self) -> bool {
+        self.target.triple() != target::host_triple()
+    }
+
     pub fn host_filesearch(
         &self,
         kind: crate::search_paths::PathKind,
@@ -597,26 +750,71 @@ fn detect_app<'a>(
 }
 
 /// Generate a default project configuration for the current session
+///
+/// This predefines `FIREFLY`, along with macros describing the target, e.g. `?TARGET_ARCH` and
+/// `?TARGET_WORDSIZE`, and `TARGET_FAMILY_<FAMILY>` for each family of the target (e.g. `UNIX` or
+/// `WASM`), so that sources can use `-ifdef` to select code for a given target.
 fn default_configuration(target: &Target) -> HashMap<String, Option<String>> {
     let end = target.options.endianness.to_string();
     let arch = target.arch.to_string();
     let wordsz = target.pointer_width.to_string();
+    let wordsz_bytes = (target.pointer_width / 8).to_string();
     let os = target.options.os.to_string();
     let env = target.options.env.to_string();
     let vendor = target.options.vendor.to_string();
 
     let mut ret = HashMap::default();
-    ret.reserve(6); // the minimum number of insertions
+    ret.reserve(8); // the minimum number of insertions
                     // Target bindings.
+    ret.insert("FIREFLY".to_string(), None);
     ret.insert("TARGET_OS".to_string(), Some(os));
     ret.insert("TARGET_POINTER_WIDTH".to_string(), Some(wordsz));
     ret.insert("TARGET_ARCH".to_string(), Some(arch));
     ret.insert("TARGET_ENDIANESS".to_string(), Some(end));
     ret.insert("TARGET_ENV".to_string(), Some(env));
     ret.insert("TARGET_VENDOR".to_string(), Some(vendor));
+    ret.insert("TARGET_WORDSIZE".to_string(), Some(wordsz_bytes));
+    for family in target.options.families.iter() {
+        ret.insert(
+            format!("TARGET_FAMILY_{}", family.to_ascii_uppercase()),
+            None,
+        );
+    }
     ret
 }
 
+/// Returns the target to link fully static executables for, given the requested target
+///
+/// glibc does not support static linking, so on Linux this is the musl variant of the target,
+/// e.g. `x86_64-unknown-linux-musl` for `x86_64-unknown-linux-gnu`.
+fn static_target(target: Target) -> anyhow::Result<Target> {
+    let triple = target.triple().to_string();
+    if target.options.os != "linux" || target.options.env == "musl" {
+        if !target.options.crt_static_respected && !target.options.crt_static_default {
+            bail!(
+                "fully static executables are not supported for target `{}`",
+                triple
+            );
+        }
+        return Ok(target);
+    }
+    let musl = match triple.rsplit_once('-') {
+        Some((prefix, env)) if env.starts_with("gnu") => format!("{}-musl{}", prefix, &env[3..]),
+        _ => bail!(
+            "fully static executables are not supported for target `{}`",
+            triple
+        ),
+    };
+    match Target::search(&musl) {
+        Ok(target) => Ok(target),
+        Err(_) => bail!(
+            "static linking for `{}` requires the `{}` target, which is not supported",
+            triple,
+            musl
+        ),
+    }
+}
+
 fn parse_key_value(value: &str) -> Result<Define, clap::Error> {
     let kv = value.splitn(2, '=').collect::<Vec<_>>();
     let key = kv[0].to_string();
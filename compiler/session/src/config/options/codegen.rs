@@ -110,6 +110,9 @@ pub struct CodegenOptions {
     /// the same values as the target option of the same name
     pub merge_functions: Option<MergeFunctions>,
     #[option]
+    /// Compile every module, rather than reusing the artifacts cached by previous builds
+    pub no_artifact_cache: bool,
+    #[option]
     /// Run all passes except codegen; no output
     pub no_codegen: bool,
     /// Compile without linking
@@ -156,6 +159,10 @@ pub struct CodegenOptions {
     #[option]
     /// Prefer dynamic linking to static linking
     pub prefer_dynamic: bool,
+    #[option]
+    /// Remove functions which are unreachable from the entry point of an executable,
+    /// assuming no code is loaded at runtime
+    pub prune_functions: bool,
     #[option(value_name("MODEL"), takes_value(true), hidden(true))]
     /// Choose the relocation model to use
     pub relocation_model: Option<RelocModel>,
@@ -193,6 +200,9 @@ pub struct CodegenOptions {
     #[option(value_name("FEATURES"), takes_value(true))]
     /// Select target specific attributes (see `firefly print target-features`)
     pub target_features: Option<String>,
+    #[option(value_name("PATH"), takes_value(true))]
+    /// The sysroot of the target's C toolchain, i.e. its libc, used when cross-compiling
+    pub target_sysroot: Option<PathBuf>,
     #[option(hidden(true))]
     /// Enable ThinLTO when possible
     pub thinlto: Option<bool>,
@@ -40,6 +40,8 @@ impl FromStr for OutputTypeSpec {
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
 pub enum OutputType {
     AST,
+    /// The parsed module in the Erlang Abstract Format, as read from `.abstr` inputs
+    Abstr,
     Core,
     Kernel,
     SSA,
@@ -50,12 +52,21 @@ pub enum OutputType {
     Assembly,
     Object,
     Link,
+    /// A JSON description of the exports, atoms and literals of every module in the build
+    Manifest,
+    /// The inter-module call graph of the build, as Graphviz DOT and JSON
+    DepGraph,
+    /// Estimated reductions and allocations of each function, derived from the SSA IR
+    CostReport,
+    /// BEAM bytecode, loadable by the Erlang VM, generated from the SSA IR
+    Beam,
 }
 impl FromStr for OutputType {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "ast" => Ok(Self::AST),
+            "abstr" => Ok(Self::Abstr),
             "core" => Ok(Self::Core),
             "kernel" => Ok(Self::Kernel),
             "ssa" => Ok(Self::SSA),
@@ -65,6 +76,10 @@ impl FromStr for OutputType {
             "asm" => Ok(Self::Assembly),
             "obj" | "o" => Ok(Self::Object),
             "link" | "exe" => Ok(Self::Link),
+            "manifest" => Ok(Self::Manifest),
+            "depgraph" => Ok(Self::DepGraph),
+            "cost-report" | "cost" => Ok(Self::CostReport),
+            "beam" => Ok(Self::Beam),
             _ => Err(()),
         }
     }
@@ -84,21 +99,27 @@ impl OutputType {
     pub fn as_str(&self) -> &'static str {
         match self {
             &Self::AST => "ast",
+            &Self::Abstr => "abstr",
             &Self::Core => "core",
             &Self::Kernel => "kernel",
-            &Self::SSA => "core",
+            &Self::SSA => "ssa",
             &Self::MLIR => "mlir",
             &Self::LLVMAssembly => "llvm-ir",
             &Self::LLVMBitcode => "llvm-bc",
             &Self::Assembly => "asm",
             &Self::Object => "obj",
             &Self::Link => "link",
+            &Self::Manifest => "manifest",
+            &Self::DepGraph => "depgraph",
+            &Self::CostReport => "cost-report",
+            &Self::Beam => "beam",
         }
     }
 
     pub fn variants() -> &'static [OutputType] {
         &[
             Self::AST,
+            Self::Abstr,
             Self::Core,
             Self::Kernel,
             Self::SSA,
@@ -108,6 +129,10 @@ impl OutputType {
             Self::Assembly,
             Self::Object,
             Self::Link,
+            Self::Manifest,
+            Self::DepGraph,
+            Self::CostReport,
+            Self::Beam,
         ]
     }
 
@@ -120,6 +145,7 @@ impl OutputType {
          Supported output types:\n  \
            all       = Emit everything\n  \
            ast       = Abstract Syntax Tree\n  \
+           abstr     = Erlang Abstract Format\n  \
            core      = Core Erlang\n  \
            kernel    = Kernel Erlang\n  \
            ssa       = SSA IR\n  \
@@ -128,7 +154,11 @@ impl OutputType {
            llvm-bc   = LLVM Bitcode (*)\n  \
            asm       = Assembly (*)\n  \
            obj       = Object File (*)\n  \
-           link      = Linked executable or library(*)\n\
+           link      = Linked executable or library(*)\n  \
+           manifest  = JSON manifest of module exports, atoms and literals (*)\n  \
+           depgraph  = Call graph of the build, as DOT and JSON (*)\n  \
+           cost-report = Estimated reductions and allocations per function\n  \
+           beam      = BEAM bytecode (.beam) for the Erlang VM\n\
          \n\
          (*) Indicates that globs cannot be applied to this output type"
     }
@@ -136,6 +166,7 @@ impl OutputType {
     pub fn extension(&self) -> &'static str {
         match *self {
             Self::AST => "ast",
+            Self::Abstr => "abstr",
             Self::Core => "core",
             Self::Kernel => "kernel",
             Self::SSA => "ssa",
@@ -145,6 +176,10 @@ impl OutputType {
             Self::Assembly => "s",
             Self::Object => "o",
             Self::Link => "",
+            Self::Manifest => "json",
+            Self::DepGraph => "dot",
+            Self::CostReport => "cost",
+            Self::Beam => "beam",
         }
     }
 }
@@ -254,6 +289,21 @@ impl OutputTypes {
         Ok(Self(map))
     }
 
+    /// Requests every intermediate output of code generation for all inputs
+    ///
+    /// This overrides any glob given for those output types, see `--save-temps`
+    pub fn save_temps(&mut self) {
+        for output_type in [
+            OutputType::MLIR,
+            OutputType::LLVMAssembly,
+            OutputType::LLVMBitcode,
+            OutputType::Assembly,
+            OutputType::Object,
+        ] {
+            self.0.insert(output_type, None);
+        }
+    }
+
     pub fn maybe_emit(&self, input: &Input, output_type: OutputType) -> Option<PathBuf> {
         match self.0.get(&output_type) {
             None => None,
@@ -298,14 +348,22 @@ impl OutputTypes {
 
     pub fn should_generate_ssa(&self) -> bool {
         self.0.keys().any(|k| match *k {
-            OutputType::AST | OutputType::Core | OutputType::Kernel => false,
+            OutputType::AST | OutputType::Abstr | OutputType::Core | OutputType::Kernel => false,
             _ => true,
         })
     }
 
     pub fn should_generate_mlir(&self) -> bool {
         self.0.keys().any(|k| match *k {
-            OutputType::AST | OutputType::Core | OutputType::Kernel | OutputType::SSA => false,
+            OutputType::AST
+            | OutputType::Abstr
+            | OutputType::Core
+            | OutputType::Kernel
+            | OutputType::SSA
+            | OutputType::Manifest
+            | OutputType::DepGraph
+            | OutputType::CostReport
+            | OutputType::Beam => false,
             _ => true,
         })
     }
@@ -313,10 +371,15 @@ impl OutputTypes {
     pub fn should_generate_llvm(&self) -> bool {
         self.0.keys().any(|k| match *k {
             OutputType::AST
+            | OutputType::Abstr
             | OutputType::Core
             | OutputType::Kernel
             | OutputType::SSA
-            | OutputType::MLIR => false,
+            | OutputType::MLIR
+            | OutputType::Manifest
+            | OutputType::DepGraph
+            | OutputType::CostReport
+            | OutputType::Beam => false,
             _ => true,
         })
     }
@@ -324,12 +387,17 @@ impl OutputTypes {
     pub fn should_codegen(&self) -> bool {
         self.0.keys().any(|k| match *k {
             OutputType::AST
+            | OutputType::Abstr
             | OutputType::Core
             | OutputType::Kernel
             | OutputType::SSA
             | OutputType::MLIR
             | OutputType::LLVMAssembly
-            | OutputType::LLVMBitcode => false,
+            | OutputType::LLVMBitcode
+            | OutputType::Manifest
+            | OutputType::DepGraph
+            | OutputType::CostReport
+            | OutputType::Beam => false,
             _ => true,
         })
     }
@@ -362,13 +430,17 @@ impl ParseOption for OutputTypes {
                 }
                 match OutputTypeSpec::from_str(value) {
                     Ok(OutputTypeSpec {
-                        output_type: OutputType::Link,
+                        output_type:
+                            output_type @ (OutputType::Link
+                            | OutputType::Manifest
+                            | OutputType::DepGraph),
                         pattern: Some(_),
                     }) => {
                         return Err(clap::Error {
                             kind: clap::ErrorKind::ValueValidation,
                             message: format!(
-                                "cannot specify a file pattern for the 'link' output type"
+                                "cannot specify a file pattern for the '{}' output type",
+                                output_type
                             ),
                             info: Some(vec![info.name.to_string()]),
                         });
@@ -450,8 +522,8 @@ pub fn calculate_outputs(
 // a single output path, if the input should produce an output; otherwise it returns `None`
 fn map_input_output(input: &Input, output_type: &OutputType, output_dir: &Path) -> Option<PathBuf> {
     match output_type {
-        OutputType::Link => {
-            // All inputs go into a single output when linking
+        OutputType::Link | OutputType::Manifest | OutputType::DepGraph => {
+            // All inputs go into a single output when linking or producing a build-wide artifact
             None
         }
         _ => Some(output_filename(
@@ -1,5 +1,6 @@
 #![deny(warnings)]
 
+pub mod build_info;
 mod config;
 pub mod filesearch;
 pub mod search_paths;
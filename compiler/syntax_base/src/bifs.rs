@@ -56,6 +56,7 @@ lazy_static! {
             bif!(pub erlang:alias/0(list) -> reference),
             bif!(pub erlang:apply/2(function, list) -> any),
             bif!(pub erlang:apply/3(module, function, list) -> any),
+            bif!(pub erlang:apply_nif/3(module, atom, list) -> any),
             bif!(pub erlang:atom_to_binary/1(atom) -> binary),
             bif!(pub erlang:atom_to_binary/2(atom, atom) -> binary),
             bif!(pub erlang:atom_to_list/1(atom) -> string),
@@ -125,6 +126,7 @@ lazy_static! {
             guard_bif!(pub erlang:is_list/1(any) -> boolean),
             guard_bif!(pub erlang:is_map/1(any) -> boolean),
             guard_bif!(pub erlang:is_map_key/2(any, map) -> boolean),
+            bif!(pub erlang:is_nif_loaded/3(module, atom, arity) -> boolean),
             guard_bif!(pub erlang:is_number/1(any) -> boolean),
             guard_bif!(pub erlang:is_pid/1(any) -> boolean),
             guard_bif!(pub erlang:is_port/1(any) -> boolean),
@@ -147,6 +149,7 @@ lazy_static! {
             bif!(pub erlang:list_to_ref/1(string) -> reference),
             bif!(pub erlang:list_to_tuple/1(list) -> tuple),
             bif!(pub erlang:load_nif/2(string, term) -> term),
+            bif!(pub erlang:load_nif/3(module, string, term) -> term),
             bif!(pub erlang:make_ref/0() -> reference),
             guard_bif!(pub erlang:map_get/2(any, map) -> any),
             guard_bif!(pub erlang:map_size/1(map) -> non_neg_integer),
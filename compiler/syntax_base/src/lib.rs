@@ -115,6 +115,10 @@ pub struct CompileOptions {
     pub no_warn_deprecated_functions: HashSet<Span<FunctionName>>,
     pub warn_deprecated_type: bool,
     pub warn_obsolete_guard: bool,
+    // Warns when atoms are created from data which may come from an external source
+    pub warn_unsafe_atom: bool,
+    // Warns about case expressions which do not cover all atoms a spec'd function may return
+    pub warn_case_coverage: bool,
     pub inline: bool,
     // Inlines the given functions
     pub inline_functions: HashSet<Span<FunctionName>>,
@@ -154,6 +158,8 @@ impl Default for CompileOptions {
             no_warn_deprecated_functions: HashSet::new(),
             warn_deprecated_type: true,
             warn_obsolete_guard: true,
+            warn_unsafe_atom: true,
+            warn_case_coverage: true,
         }
     }
 }
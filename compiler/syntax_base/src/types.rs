@@ -452,3 +452,144 @@ impl fmt::Display for Type {
         }
     }
 }
+
+/// A union of the disjoint classes of terms a value may belong to.
+///
+/// This is much coarser than [`TermType`], but unlike it, can represent unions such as
+/// `ok | {error, term()}`, which makes it suitable for approximating type specifications, and
+/// for deciding whether two types definitely have no values in common.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct TermKinds(u16);
+impl TermKinds {
+    pub const NONE: Self = Self(0);
+    pub const INTEGER: Self = Self(1 << 0);
+    pub const FLOAT: Self = Self(1 << 1);
+    pub const ATOM: Self = Self(1 << 2);
+    pub const NIL: Self = Self(1 << 3);
+    pub const CONS: Self = Self(1 << 4);
+    pub const TUPLE: Self = Self(1 << 5);
+    pub const MAP: Self = Self(1 << 6);
+    pub const BITSTRING: Self = Self(1 << 7);
+    pub const FUN: Self = Self(1 << 8);
+    pub const PID: Self = Self(1 << 9);
+    pub const PORT: Self = Self(1 << 10);
+    pub const REFERENCE: Self = Self(1 << 11);
+    pub const NUMBER: Self = Self(Self::INTEGER.0 | Self::FLOAT.0);
+    pub const LIST: Self = Self(Self::NIL.0 | Self::CONS.0);
+    pub const ANY: Self = Self((1 << 12) - 1);
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline]
+    pub fn is_any(self) -> bool {
+        self == Self::ANY
+    }
+
+    /// Returns true if every value of `other` is also a value of `self`
+    #[inline]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    #[inline]
+    pub fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    #[inline]
+    pub fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+impl From<&TermType> for TermKinds {
+    fn from(ty: &TermType) -> Self {
+        match ty {
+            TermType::Any => Self::ANY,
+            TermType::Bool | TermType::Atom => Self::ATOM,
+            TermType::Integer => Self::INTEGER,
+            TermType::Float => Self::FLOAT,
+            TermType::Number => Self::NUMBER,
+            TermType::Bitstring | TermType::Binary => Self::BITSTRING,
+            TermType::Nil => Self::NIL,
+            TermType::Cons => Self::CONS,
+            TermType::List(_) => Self::LIST,
+            // The tail of an improper list may be any term
+            TermType::MaybeImproperList => Self::ANY,
+            TermType::Tuple(_) => Self::TUPLE,
+            TermType::Map => Self::MAP,
+            TermType::Reference => Self::REFERENCE,
+            TermType::Port => Self::PORT,
+            TermType::Pid => Self::PID,
+            TermType::Fun(_) => Self::FUN,
+        }
+    }
+}
+impl From<&Type> for TermKinds {
+    fn from(ty: &Type) -> Self {
+        match ty {
+            Type::Term(ty) => ty.into(),
+            Type::Function(_) => Self::FUN,
+            _ => Self::ANY,
+        }
+    }
+}
+impl fmt::Debug for TermKinds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TermKinds({})", self)
+    }
+}
+impl fmt::Display for TermKinds {
+    /// Print these kinds using the syntax of Erlang type specifications, e.g. `atom() | tuple()`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("none()");
+        }
+        if self.is_any() {
+            return f.write_str("term()");
+        }
+        const NAMES: &[(TermKinds, &str)] = &[
+            (TermKinds::NUMBER, "number()"),
+            (TermKinds::INTEGER, "integer()"),
+            (TermKinds::FLOAT, "float()"),
+            (TermKinds::ATOM, "atom()"),
+            (TermKinds::LIST, "list()"),
+            (TermKinds::NIL, "[]"),
+            (TermKinds::CONS, "nonempty_list()"),
+            (TermKinds::TUPLE, "tuple()"),
+            (TermKinds::MAP, "map()"),
+            (TermKinds::BITSTRING, "bitstring()"),
+            (TermKinds::FUN, "fun()"),
+            (TermKinds::PID, "pid()"),
+            (TermKinds::PORT, "port()"),
+            (TermKinds::REFERENCE, "reference()"),
+        ];
+        let mut rest = *self;
+        let mut first = true;
+        for (kinds, name) in NAMES.iter().copied() {
+            if rest.contains(kinds) {
+                if !first {
+                    f.write_str(" | ")?;
+                }
+                f.write_str(name)?;
+                rest = rest.difference(kinds);
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The kinds of terms accepted and returned by a single clause of a function's type specification
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KindSignature {
+    pub params: Vec<TermKinds>,
+    pub ret: TermKinds,
+}
@@ -0,0 +1,372 @@
+use std::fmt;
+use std::str::FromStr;
+
+use firefly_intern::Symbol;
+use firefly_number::Integer;
+
+use super::ParseError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Token {
+    Atom(Symbol),
+    Var(Symbol),
+    Integer(Integer),
+    Float(f64),
+    String(String),
+    // Keywords
+    Module,
+    Attributes,
+    End,
+    Fun,
+    Let,
+    LetRec,
+    In,
+    Case,
+    Of,
+    When,
+    Receive,
+    After,
+    Apply,
+    Call,
+    PrimOp,
+    Try,
+    Catch,
+    Do,
+    // Punctuation
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    LAngle,
+    RAngle,
+    Comma,
+    Bar,
+    Colon,
+    Slash,
+    Equals,
+    Arrow,
+    Annotate,
+    BinaryStart,
+    BinaryEnd,
+    SegmentStart,
+    MapStart,
+    MapEnd,
+    Assoc,
+    Exact,
+}
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Atom(a) => write!(f, "'{}'", a),
+            Self::Var(v) => write!(f, "{}", v),
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Float(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "{:?}", s),
+            Self::Module => f.write_str("module"),
+            Self::Attributes => f.write_str("attributes"),
+            Self::End => f.write_str("end"),
+            Self::Fun => f.write_str("fun"),
+            Self::Let => f.write_str("let"),
+            Self::LetRec => f.write_str("letrec"),
+            Self::In => f.write_str("in"),
+            Self::Case => f.write_str("case"),
+            Self::Of => f.write_str("of"),
+            Self::When => f.write_str("when"),
+            Self::Receive => f.write_str("receive"),
+            Self::After => f.write_str("after"),
+            Self::Apply => f.write_str("apply"),
+            Self::Call => f.write_str("call"),
+            Self::PrimOp => f.write_str("primop"),
+            Self::Try => f.write_str("try"),
+            Self::Catch => f.write_str("catch"),
+            Self::Do => f.write_str("do"),
+            Self::LParen => f.write_str("("),
+            Self::RParen => f.write_str(")"),
+            Self::LBrace => f.write_str("{"),
+            Self::RBrace => f.write_str("}"),
+            Self::LBracket => f.write_str("["),
+            Self::RBracket => f.write_str("]"),
+            Self::LAngle => f.write_str("<"),
+            Self::RAngle => f.write_str(">"),
+            Self::Comma => f.write_str(","),
+            Self::Bar => f.write_str("|"),
+            Self::Colon => f.write_str(":"),
+            Self::Slash => f.write_str("/"),
+            Self::Equals => f.write_str("="),
+            Self::Arrow => f.write_str("->"),
+            Self::Annotate => f.write_str("-|"),
+            Self::BinaryStart => f.write_str("#{"),
+            Self::BinaryEnd => f.write_str("}#"),
+            Self::SegmentStart => f.write_str("#<"),
+            Self::MapStart => f.write_str("~{"),
+            Self::MapEnd => f.write_str("}~"),
+            Self::Assoc => f.write_str("=>"),
+            Self::Exact => f.write_str(":="),
+        }
+    }
+}
+
+/// A token along with the byte range it was read from
+pub(super) type Lexed = (usize, Token, usize);
+
+/// Splits Core Erlang source text into tokens, skipping whitespace and `%` comments
+pub(super) struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+}
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self { source, pos: 0 }
+    }
+
+    pub fn tokenize(mut self) -> Result<Vec<Lexed>, (usize, usize, String)> {
+        let mut tokens = vec![];
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.source[self.pos..].chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_trivia(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.bump();
+            } else if c == '%' {
+                while let Some(c) = self.bump() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Lexed>, (usize, usize, String)> {
+        self.skip_trivia();
+        let start = self.pos;
+        let Some(c) = self.bump() else { return Ok(None); };
+        let token = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '{' => Token::LBrace,
+            '}' if self.peek() == Some('#') => {
+                self.bump();
+                Token::BinaryEnd
+            }
+            '}' if self.peek() == Some('~') => {
+                self.bump();
+                Token::MapEnd
+            }
+            '}' => Token::RBrace,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '<' => Token::LAngle,
+            '>' => Token::RAngle,
+            ',' => Token::Comma,
+            '|' => Token::Bar,
+            '/' => Token::Slash,
+            ':' if self.peek() == Some('=') => {
+                self.bump();
+                Token::Exact
+            }
+            ':' => Token::Colon,
+            '=' if self.peek() == Some('>') => {
+                self.bump();
+                Token::Assoc
+            }
+            '=' => Token::Equals,
+            '#' if self.peek() == Some('{') => {
+                self.bump();
+                Token::BinaryStart
+            }
+            '#' if self.peek() == Some('<') => {
+                self.bump();
+                Token::SegmentStart
+            }
+            '~' if self.peek() == Some('{') => {
+                self.bump();
+                Token::MapStart
+            }
+            '-' if self.peek() == Some('>') => {
+                self.bump();
+                Token::Arrow
+            }
+            '-' if self.peek() == Some('|') => {
+                self.bump();
+                Token::Annotate
+            }
+            '-' | '+' if self.peek().map(|c| c.is_ascii_digit()).unwrap_or_default() => {
+                self.number(start)?
+            }
+            '0'..='9' => self.number(start)?,
+            '$' => {
+                let c = match self.bump() {
+                    Some('\\') => self.escape(start)?,
+                    Some(c) => c,
+                    None => return Err((start, self.pos, "unterminated character".to_string())),
+                };
+                Token::Integer(Integer::from(c as u32))
+            }
+            '\'' => Token::Atom(Symbol::intern(&self.quoted(start, '\'')?)),
+            '"' => Token::String(self.quoted(start, '"')?),
+            c if c.is_uppercase() || c == '_' => {
+                self.name_chars();
+                Token::Var(Symbol::intern(&self.source[start..self.pos]))
+            }
+            c if c.is_lowercase() => {
+                self.name_chars();
+                match &self.source[start..self.pos] {
+                    "module" => Token::Module,
+                    "attributes" => Token::Attributes,
+                    "end" => Token::End,
+                    "fun" => Token::Fun,
+                    "let" => Token::Let,
+                    "letrec" => Token::LetRec,
+                    "in" => Token::In,
+                    "case" => Token::Case,
+                    "of" => Token::Of,
+                    "when" => Token::When,
+                    "receive" => Token::Receive,
+                    "after" => Token::After,
+                    "apply" => Token::Apply,
+                    "call" => Token::Call,
+                    "primop" => Token::PrimOp,
+                    "try" => Token::Try,
+                    "catch" => Token::Catch,
+                    "do" => Token::Do,
+                    other => {
+                        return Err((start, self.pos, format!("unknown keyword '{}'", other)));
+                    }
+                }
+            }
+            c => return Err((start, self.pos, format!("unexpected character '{}'", c))),
+        };
+        Ok(Some((start, token, self.pos)))
+    }
+
+    fn name_chars(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_alphanumeric() || c == '_' || c == '@' {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn digits(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn number(&mut self, start: usize) -> Result<Token, (usize, usize, String)> {
+        self.digits();
+        let is_float = self.peek() == Some('.')
+            && self
+                .peek_nth(1)
+                .map(|c| c.is_ascii_digit())
+                .unwrap_or_default();
+        if !is_float {
+            let text = self.source[start..self.pos].trim_start_matches('+');
+            return Integer::from_str(text)
+                .map(Token::Integer)
+                .map_err(|_| (start, self.pos, "invalid integer".to_string()));
+        }
+        self.bump();
+        self.digits();
+        if let Some('e' | 'E') = self.peek() {
+            self.bump();
+            if let Some('-' | '+') = self.peek() {
+                self.bump();
+            }
+            self.digits();
+        }
+        self.source[start..self.pos]
+            .parse::<f64>()
+            .map(Token::Float)
+            .map_err(|_| (start, self.pos, "invalid float".to_string()))
+    }
+
+    fn quoted(&mut self, start: usize, quote: char) -> Result<String, (usize, usize, String)> {
+        let mut buf = String::new();
+        loop {
+            match self.bump() {
+                None => return Err((start, self.pos, "unterminated quoted literal".to_string())),
+                Some(c) if c == quote => break,
+                Some('\\') => buf.push(self.escape(start)?),
+                Some(c) => buf.push(c),
+            }
+        }
+        Ok(buf)
+    }
+
+    fn escape(&mut self, start: usize) -> Result<char, (usize, usize, String)> {
+        let c = match self.bump() {
+            None => return Err((start, self.pos, "unterminated escape".to_string())),
+            Some('b') => '\x08',
+            Some('d') => '\x7f',
+            Some('e') => '\x1b',
+            Some('f') => '\x0c',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('s') => ' ',
+            Some('t') => '\t',
+            Some('v') => '\x0b',
+            Some('^') => match self.bump() {
+                Some(c) => char::from_u32(c as u32 & 0x1f).unwrap(),
+                None => return Err((start, self.pos, "unterminated escape".to_string())),
+            },
+            Some(c @ '0'..='7') => {
+                let mut value = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match self.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            self.bump();
+                            value = value * 8 + digit;
+                        }
+                        None => break,
+                    }
+                }
+                char::from_u32(value).unwrap()
+            }
+            Some(c) => c,
+        };
+        Ok(c)
+    }
+}
+
+/// Converts a lexer error into a `ParseError` in the given file
+pub(super) fn lex(
+    source_id: firefly_diagnostics::SourceId,
+    source: &str,
+) -> Result<Vec<Lexed>, ParseError> {
+    Lexer::new(source)
+        .tokenize()
+        .map_err(|(start, end, message)| ParseError::Invalid {
+            span: super::parser::span(source_id, start, end),
+            message,
+        })
+}
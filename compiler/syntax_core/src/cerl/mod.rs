@@ -0,0 +1,69 @@
+///! Reading and writing modules in the standard Core Erlang syntax.
+///!
+///! This is the textual form produced by `erlc +to_core` and read by `erlc` for `.core` files,
+///! which makes it possible to compile Core Erlang produced by other tools, and to inspect or
+///! hand-edit the output of our own lowering from Erlang.
+mod lexer;
+mod parser;
+mod printer;
+
+pub use self::printer::CorePrinter;
+
+use std::fmt;
+
+use firefly_diagnostics::{Diagnostic, Label, SourceFile, SourceSpan, Spanned, ToDiagnostic};
+
+use crate::Module;
+
+#[derive(Debug, thiserror::Error, Spanned)]
+pub enum ParseError {
+    #[error("{message}")]
+    Invalid {
+        #[span]
+        span: SourceSpan,
+        message: String,
+    },
+
+    #[error("unexpected token {found}, expected {expected}")]
+    UnexpectedToken {
+        #[span]
+        span: SourceSpan,
+        found: String,
+        expected: String,
+    },
+
+    #[error("unexpected end of file")]
+    UnexpectedEof {
+        #[span]
+        span: SourceSpan,
+    },
+}
+impl ToDiagnostic for ParseError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let span = self.span();
+        Diagnostic::error()
+            .with_message("invalid core erlang")
+            .with_labels(vec![
+                Label::primary(span.source_id(), span).with_message(self.to_string())
+            ])
+    }
+}
+
+/// Parses a Core Erlang module from the given source file
+pub fn parse(source: &SourceFile) -> Result<Module, ParseError> {
+    let text = source.source();
+    let tokens = lexer::lex(source.id(), text)?;
+    parser::Parser::new(source.id(), tokens, text.len()).module()
+}
+
+/// Returns a value which displays the given module in Core Erlang syntax
+pub fn display(module: &Module) -> impl fmt::Display + '_ {
+    struct Display<'a>(&'a Module);
+    impl<'a> fmt::Display for Display<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            CorePrinter::new(f).print_module(self.0)
+        }
+    }
+
+    Display(module)
+}
@@ -0,0 +1,834 @@
+use std::cell::UnsafeCell;
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+
+use firefly_binary::{BinaryEntrySpecifier, BitVec, Endianness};
+use firefly_diagnostics::{ByteIndex, SourceId, SourceIndex, SourceSpan, Span, Spanned};
+use firefly_intern::{symbols, Ident, Symbol};
+use firefly_number::{Integer, ToPrimitive};
+use firefly_pass::Pass;
+use firefly_syntax_base::*;
+
+use super::lexer::{Lexed, Token};
+use super::ParseError;
+use crate::passes::{FunctionContext, RewriteReceivePrimitives};
+use crate::*;
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+pub(super) fn span(source_id: SourceId, start: usize, end: usize) -> SourceSpan {
+    SourceSpan::new(
+        SourceIndex::new(source_id, ByteIndex(start as u32)),
+        SourceIndex::new(source_id, ByteIndex(end as u32)),
+    )
+}
+
+/// A recursive-descent parser over the tokens of a single Core Erlang module
+pub(super) struct Parser {
+    source_id: SourceId,
+    tokens: Vec<Lexed>,
+    pos: usize,
+    last_end: usize,
+    eof: usize,
+    /// The name of the top-level function being parsed, used to name anonymous funs
+    function: FunctionName,
+    fun_counter: usize,
+}
+impl Parser {
+    pub fn new(source_id: SourceId, tokens: Vec<Lexed>, eof: usize) -> Self {
+        Self {
+            source_id,
+            tokens,
+            pos: 0,
+            last_end: 0,
+            eof,
+            function: FunctionName::new_local(symbols::Empty, 0),
+            fun_counter: 0,
+        }
+    }
+
+    fn span(&self, start: usize) -> SourceSpan {
+        span(self.source_id, start, self.last_end)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token, _)| token)
+    }
+
+    fn is_next(&self, token: &Token) -> bool {
+        self.peek() == Some(token)
+    }
+
+    /// The start offset of the next token
+    fn start(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(start, _, _)| *start)
+            .unwrap_or(self.eof)
+    }
+
+    fn next(&mut self) -> Result<Lexed> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(lexed) => {
+                self.pos += 1;
+                self.last_end = lexed.2;
+                Ok(lexed)
+            }
+            None => Err(ParseError::UnexpectedEof {
+                span: span(self.source_id, self.eof, self.eof),
+            }),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.is_next(token) {
+            self.pos += 1;
+            self.last_end = self.tokens[self.pos - 1].2;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<usize> {
+        let (start, found, end) = self.next()?;
+        if found == token {
+            Ok(start)
+        } else {
+            Err(ParseError::UnexpectedToken {
+                span: span(self.source_id, start, end),
+                found: found.to_string(),
+                expected: token.to_string(),
+            })
+        }
+    }
+
+    fn unexpected(&self, (start, found, end): Lexed, expected: &str) -> ParseError {
+        ParseError::UnexpectedToken {
+            span: span(self.source_id, start, end),
+            found: found.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    fn invalid(&self, span: SourceSpan, message: &str) -> ParseError {
+        ParseError::Invalid {
+            span,
+            message: message.to_string(),
+        }
+    }
+
+    /// Parses a comma-separated sequence of items terminated by `close`
+    fn sequence<T, F>(&mut self, close: Token, mut item: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> Result<T>,
+    {
+        let mut items = vec![];
+        if self.eat(&close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if !self.eat(&Token::Comma) {
+                self.expect(close)?;
+                return Ok(items);
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<(Symbol, SourceSpan)> {
+        match self.next()? {
+            (start, Token::Atom(a), end) => Ok((a, span(self.source_id, start, end))),
+            lexed => Err(self.unexpected(lexed, "atom")),
+        }
+    }
+
+    fn arity(&mut self) -> Result<usize> {
+        match self.next()? {
+            (_, Token::Integer(Integer::Small(i)), _) if (0..=255).contains(&i) => Ok(i as usize),
+            lexed => Err(self.unexpected(lexed, "arity")),
+        }
+    }
+
+    pub fn module(&mut self) -> Result<Module> {
+        let annotated = self.eat(&Token::LParen);
+        let start = self.expect(Token::Module)?;
+        let (name, name_span) = self.atom()?;
+
+        self.expect(Token::LBracket)?;
+        let exports = self
+            .sequence(Token::RBracket, |p| {
+                let var = p.fname()?;
+                let name = FunctionName::new_local(var.name(), var.arity.unwrap() as u8);
+                Ok(Span::new(var.span(), name))
+            })?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        self.expect(Token::Attributes)?;
+        self.expect(Token::LBracket)?;
+        let attributes = self.sequence(Token::RBracket, |p| {
+            let key = match p.expr()? {
+                Expr::Literal(Literal {
+                    value: Lit::Atom(key),
+                    ..
+                }) => key,
+                other => return Err(p.invalid(other.span(), "expected attribute name")),
+            };
+            p.expect(Token::Equals)?;
+            Ok((key, p.constant()?))
+        })?;
+        let mut on_load = None;
+        let mut nifs = HashSet::new();
+        for (key, value) in attributes {
+            match key {
+                symbols::OnLoad => on_load = function_names(&value).into_iter().next(),
+                symbols::Nifs => nifs.extend(function_names(&value)),
+                _ => continue,
+            }
+        }
+
+        let mut functions = BTreeMap::new();
+        while !self.eat(&Token::End) {
+            let var = self.fname()?;
+            self.expect(Token::Equals)?;
+            let name = FunctionName::new_local(var.name(), var.arity.unwrap() as u8);
+            self.function = name;
+            self.fun_counter = 0;
+            let fun = self.fun(var.name())?;
+            let span = fun.span;
+            let is_nif = nifs.contains(&Span::new(span, name));
+            let context = Rc::new(UnsafeCell::new(FunctionContext::new(
+                span,
+                name,
+                0,
+                self.fun_counter,
+                is_nif,
+            )));
+            let fun = RewriteReceivePrimitives::new(Rc::clone(&context))
+                .run(fun)
+                .map_err(|err| self.invalid(span, &err.to_string()))?;
+            let function = Function {
+                var_counter: unsafe { &*context.get() }.var_counter,
+                fun,
+            };
+            functions.insert(name, function);
+        }
+
+        let mut annotations = Annotations::default();
+        if annotated {
+            annotate(&mut annotations, self.annotations()?);
+            self.expect(Token::RParen)?;
+        }
+        if let Some(lexed) = self.tokens.get(self.pos).cloned() {
+            return Err(self.unexpected(lexed, "end of file"));
+        }
+
+        Ok(Module {
+            span: self.span(start),
+            annotations,
+            name: Ident::new(name, name_span),
+            compile: CompileOptions::default(),
+            on_load,
+            exports,
+            nifs,
+            functions,
+        })
+    }
+
+    /// Parses a function name, i.e. `'name'/arity`, possibly annotated
+    fn fname(&mut self) -> Result<Var> {
+        if self.eat(&Token::LParen) {
+            let mut var = self.fname()?;
+            annotate(&mut var.annotations, self.annotations()?);
+            self.expect(Token::RParen)?;
+            return Ok(var);
+        }
+        let start = self.start();
+        let (name, _) = self.atom()?;
+        self.expect(Token::Slash)?;
+        let arity = self.arity()?;
+        Ok(Var::new_with_arity(
+            Ident::new(name, self.span(start)),
+            arity,
+        ))
+    }
+
+    /// Parses a variable, possibly annotated
+    fn var(&mut self) -> Result<Var> {
+        match self.next()? {
+            (_, Token::LParen, _) => {
+                let mut var = self.var()?;
+                annotate(&mut var.annotations, self.annotations()?);
+                self.expect(Token::RParen)?;
+                Ok(var)
+            }
+            (start, Token::Var(name), end) => {
+                Ok(Var::new(Ident::new(name, span(self.source_id, start, end))))
+            }
+            lexed => Err(self.unexpected(lexed, "variable")),
+        }
+    }
+
+    /// Parses either a single variable, or a sequence of variables in angle brackets
+    fn vars(&mut self) -> Result<Vec<Var>> {
+        if self.eat(&Token::LAngle) {
+            self.sequence(Token::RAngle, |p| p.var())
+        } else {
+            Ok(vec![self.var()?])
+        }
+    }
+
+    fn annotations(&mut self) -> Result<Annotations> {
+        self.expect(Token::Annotate)?;
+        self.expect(Token::LBracket)?;
+        let mut annotations = Annotations::default();
+        for anno in self.sequence(Token::RBracket, |p| p.constant())? {
+            match anno.value {
+                Lit::Atom(key) => annotations.set(key),
+                Lit::Tuple(mut elements) if elements.len() == 2 => {
+                    let value = elements.pop().unwrap();
+                    if let Some(key) = elements[0].as_atom() {
+                        annotations.insert_mut(key, Annotation::Term(value));
+                    }
+                }
+                // Line numbers and other annotations have no equivalent in our IR
+                _ => continue,
+            }
+        }
+        Ok(annotations)
+    }
+
+    fn constant(&mut self) -> Result<Literal> {
+        let expr = self.expr()?;
+        let span = expr.span();
+        to_literal(expr).ok_or_else(|| self.invalid(span, "expected a constant"))
+    }
+
+    /// Parses a fun expression, possibly annotated, with the given name
+    fn fun(&mut self, name: Symbol) -> Result<Fun> {
+        if self.eat(&Token::LParen) {
+            let mut fun = self.fun(name)?;
+            annotate(&mut fun.annotations, self.annotations()?);
+            self.expect(Token::RParen)?;
+            return Ok(fun);
+        }
+        let start = self.expect(Token::Fun)?;
+        self.expect(Token::LParen)?;
+        let vars = self.sequence(Token::RParen, |p| p.var())?;
+        self.expect(Token::Arrow)?;
+        let body = self.expr()?;
+        Ok(Fun {
+            span: self.span(start),
+            annotations: Annotations::default(),
+            name,
+            vars,
+            body: Box::new(body),
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let start = self.start();
+        match self.peek() {
+            Some(Token::LParen) => {
+                self.next()?;
+                let mut expr = self.expr()?;
+                if self.is_next(&Token::Annotate) {
+                    annotate(expr.annotations_mut(), self.annotations()?);
+                }
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::LAngle) => {
+                self.next()?;
+                let values = self.sequence(Token::RAngle, |p| p.expr())?;
+                Ok(Values::new(self.span(start), values))
+            }
+            Some(Token::Fun) => {
+                let name = Symbol::intern(&format!(
+                    "-{}/{}-fun-{}-",
+                    self.function.function, self.function.arity, self.fun_counter
+                ));
+                self.fun_counter += 1;
+                Ok(Expr::Fun(self.fun(name)?))
+            }
+            _ => self.single_expr(),
+        }
+    }
+
+    fn single_expr(&mut self) -> Result<Expr> {
+        let lexed = self.next()?;
+        let start = lexed.0;
+        match lexed.1 {
+            Token::Atom(name) if self.is_next(&Token::Slash) => {
+                self.next()?;
+                let arity = self.arity()?;
+                let name = Ident::new(name, self.span(start));
+                Ok(Expr::Var(Var::new_with_arity(name, arity)))
+            }
+            Token::Var(_) => {
+                self.pos -= 1;
+                Ok(Expr::Var(self.var()?))
+            }
+            Token::Atom(_) | Token::Integer(_) | Token::Float(_) | Token::String(_) => {
+                Ok(Expr::Literal(self.atomic(lexed)))
+            }
+            Token::LBrace => self.tuple(start, false),
+            Token::LBracket => self.list(start, false),
+            Token::BinaryStart => self.binary(start, false),
+            Token::MapStart => self.map(start, false),
+            Token::Let => {
+                let vars = self.vars()?;
+                self.expect(Token::Equals)?;
+                let arg = self.expr()?;
+                self.expect(Token::In)?;
+                let body = self.expr()?;
+                Ok(Expr::Let(Let::new(self.span(start), vars, arg, body)))
+            }
+            Token::LetRec => {
+                let mut defs = vec![];
+                while !self.eat(&Token::In) {
+                    let var = self.fname()?;
+                    self.expect(Token::Equals)?;
+                    let fun = self.fun(var.name())?;
+                    defs.push((var, Expr::Fun(fun)));
+                }
+                let body = self.expr()?;
+                Ok(Expr::LetRec(LetRec {
+                    span: self.span(start),
+                    annotations: Annotations::default(),
+                    defs,
+                    body: Box::new(body),
+                }))
+            }
+            Token::Case => {
+                let arg = self.expr()?;
+                self.expect(Token::Of)?;
+                let mut clauses = vec![];
+                while !self.eat(&Token::End) {
+                    clauses.push(self.clause()?);
+                }
+                Ok(Expr::Case(Case {
+                    span: self.span(start),
+                    annotations: Annotations::default(),
+                    arg: Box::new(arg),
+                    clauses,
+                }))
+            }
+            Token::Receive => {
+                let mut clauses = vec![];
+                while !self.eat(&Token::After) {
+                    clauses.push(self.clause()?);
+                }
+                let timeout = self.expr()?;
+                self.expect(Token::Arrow)?;
+                let action = self.expr()?;
+                Ok(Expr::Receive(Receive {
+                    span: self.span(start),
+                    annotations: Annotations::default(),
+                    clauses,
+                    timeout: Box::new(timeout),
+                    action: Box::new(action),
+                }))
+            }
+            Token::Apply => {
+                let callee = self.expr()?;
+                self.expect(Token::LParen)?;
+                let args = self.sequence(Token::RParen, |p| p.expr())?;
+                Ok(Expr::Apply(Apply::new(self.span(start), callee, args)))
+            }
+            Token::Call => {
+                let module = self.expr()?;
+                self.expect(Token::Colon)?;
+                let function = self.expr()?;
+                self.expect(Token::LParen)?;
+                let args = self.sequence(Token::RParen, |p| p.expr())?;
+                Ok(Expr::Call(Call {
+                    span: self.span(start),
+                    annotations: Annotations::default(),
+                    module: Box::new(module),
+                    function: Box::new(function),
+                    args,
+                }))
+            }
+            Token::PrimOp => {
+                let (name, _) = self.atom()?;
+                self.expect(Token::LParen)?;
+                let args = self.sequence(Token::RParen, |p| p.expr())?;
+                Ok(Expr::PrimOp(PrimOp::new(self.span(start), name, args)))
+            }
+            Token::Try => {
+                let arg = self.expr()?;
+                self.expect(Token::Of)?;
+                let vars = self.vars()?;
+                self.expect(Token::Arrow)?;
+                let body = self.expr()?;
+                self.expect(Token::Catch)?;
+                let evars = self.vars()?;
+                self.expect(Token::Arrow)?;
+                let handler = self.expr()?;
+                Ok(Expr::Try(Try {
+                    span: self.span(start),
+                    annotations: Annotations::default(),
+                    arg: Box::new(arg),
+                    vars,
+                    body: Box::new(body),
+                    evars,
+                    handler: Box::new(handler),
+                }))
+            }
+            Token::Catch => {
+                let body = self.expr()?;
+                Ok(Expr::Catch(Catch {
+                    span: self.span(start),
+                    annotations: Annotations::default(),
+                    body: Box::new(body),
+                }))
+            }
+            Token::Do => {
+                let arg = self.expr()?;
+                let body = self.expr()?;
+                Ok(Expr::Seq(Seq::new(self.span(start), arg, body)))
+            }
+            _ => Err(self.unexpected(lexed, "expression")),
+        }
+    }
+
+    /// Converts an atomic literal token to a literal
+    fn atomic(&self, (start, token, end): Lexed) -> Literal {
+        let span = span(self.source_id, start, end);
+        match token {
+            Token::Atom(a) => Literal::atom(span, a),
+            Token::Integer(i) => Literal::integer(span, i),
+            Token::Float(f) => Literal::float(span, f),
+            // Strings are lists of character codes
+            Token::String(s) => s.chars().rev().fold(Literal::nil(span), |tail, c| {
+                Literal::cons(span, Literal::integer(span, c as u32), tail)
+            }),
+            _ => unreachable!(),
+        }
+    }
+
+    fn clause(&mut self) -> Result<Clause> {
+        // A parenthesized clause is annotated, but a clause may also begin with an annotated
+        // pattern, so we parse speculatively and backtrack if it doesn't pan out
+        if self.is_next(&Token::LParen) {
+            let checkpoint = (self.pos, self.last_end, self.fun_counter);
+            self.next()?;
+            if let Ok(mut clause) = self.clause() {
+                if self.is_next(&Token::Annotate) {
+                    annotate(&mut clause.annotations, self.annotations()?);
+                    self.expect(Token::RParen)?;
+                    return Ok(clause);
+                }
+            }
+            (self.pos, self.last_end, self.fun_counter) = checkpoint;
+        }
+
+        let start = self.start();
+        let patterns = if self.eat(&Token::LAngle) {
+            self.sequence(Token::RAngle, |p| p.pattern())?
+        } else {
+            vec![self.pattern()?]
+        };
+        self.expect(Token::When)?;
+        let guard = self.expr()?;
+        self.expect(Token::Arrow)?;
+        let body = self.expr()?;
+        let guard = if guard.is_atom_value(symbols::True) && guard.annotations().is_empty() {
+            None
+        } else {
+            Some(Box::new(guard))
+        };
+        Ok(Clause {
+            span: self.span(start),
+            annotations: Annotations::default(),
+            patterns,
+            guard,
+            body: Box::new(body),
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Expr> {
+        let start = self.start();
+        let pattern = match self.next()? {
+            (_, Token::LParen, _) => {
+                let mut pattern = self.pattern()?;
+                annotate(pattern.annotations_mut(), self.annotations()?);
+                self.expect(Token::RParen)?;
+                pattern
+            }
+            (_, Token::Var(_), _) => {
+                self.pos -= 1;
+                Expr::Var(self.var()?)
+            }
+            (_, Token::LBrace, _) => self.tuple(start, true)?,
+            (_, Token::LBracket, _) => self.list(start, true)?,
+            (_, Token::BinaryStart, _) => self.binary(start, true)?,
+            (_, Token::MapStart, _) => self.map(start, true)?,
+            lexed @ (
+                _,
+                Token::Atom(_) | Token::Integer(_) | Token::Float(_) | Token::String(_),
+                _,
+            ) => Expr::Literal(self.atomic(lexed)),
+            lexed => return Err(self.unexpected(lexed, "pattern")),
+        };
+        match pattern {
+            Expr::Var(var) if self.eat(&Token::Equals) => {
+                let pattern = self.pattern()?;
+                Ok(Expr::Alias(Alias::new(self.span(start), var, pattern)))
+            }
+            pattern => Ok(pattern),
+        }
+    }
+
+    fn element(&mut self, pattern: bool) -> Result<Expr> {
+        if pattern {
+            self.pattern()
+        } else {
+            self.expr()
+        }
+    }
+
+    fn tuple(&mut self, start: usize, pattern: bool) -> Result<Expr> {
+        let elements = self.sequence(Token::RBrace, |p| p.element(pattern))?;
+        Ok(Expr::Tuple(Tuple::new(self.span(start), elements)))
+    }
+
+    fn list(&mut self, start: usize, pattern: bool) -> Result<Expr> {
+        if self.eat(&Token::RBracket) {
+            return Ok(Expr::Literal(Literal::nil(self.span(start))));
+        }
+        let mut elements = vec![self.element(pattern)?];
+        while self.eat(&Token::Comma) {
+            elements.push(self.element(pattern)?);
+        }
+        let tail = if self.eat(&Token::Bar) {
+            self.element(pattern)?
+        } else {
+            Expr::Literal(Literal::nil(span(
+                self.source_id,
+                self.start(),
+                self.start(),
+            )))
+        };
+        self.expect(Token::RBracket)?;
+        let span = self.span(start);
+        Ok(elements
+            .drain(..)
+            .rev()
+            .fold(tail, |tail, head| Expr::Cons(Cons::new(span, head, tail))))
+    }
+
+    fn binary(&mut self, start: usize, pattern: bool) -> Result<Expr> {
+        let segments = self.sequence(Token::BinaryEnd, |p| p.segment(pattern))?;
+        let span = self.span(start);
+        match to_bitvec(segments.as_slice()) {
+            Some(bits) => Ok(Expr::Literal(Literal::binary(span, bits))),
+            None => Ok(Expr::Binary(Binary::new(span, segments))),
+        }
+    }
+
+    /// Parses a binary segment, i.e. `#<Value>(Size, Unit, Type, Flags)`
+    fn segment(&mut self, pattern: bool) -> Result<Bitstring> {
+        let start = self.expect(Token::SegmentStart)?;
+        let value = self.element(pattern)?;
+        self.expect(Token::RAngle)?;
+        self.expect(Token::LParen)?;
+        let size = self.expr()?;
+        self.expect(Token::Comma)?;
+        let unit = self.constant()?;
+        self.expect(Token::Comma)?;
+        let ty = self.constant()?;
+        self.expect(Token::Comma)?;
+        let flags = self.constant()?;
+        self.expect(Token::RParen)?;
+        let span = self.span(start);
+
+        let unit = match unit.value {
+            Lit::Integer(Integer::Small(i)) if (1..=255).contains(&i) => Some(i as u8),
+            Lit::Atom(symbols::Undefined) => None,
+            _ => return Err(self.invalid(unit.span, "invalid segment unit")),
+        };
+        let mut signed = false;
+        let mut endianness = Endianness::Big;
+        let mut flag = &flags;
+        while let Lit::Cons(ref head, ref tail) = flag.value {
+            match head.as_atom().map(|a| a.as_str().get()) {
+                Some("signed") => signed = true,
+                Some("big") => endianness = Endianness::Big,
+                Some("little") => endianness = Endianness::Little,
+                Some("native") => endianness = Endianness::Native,
+                _ => (),
+            }
+            flag = tail.as_ref();
+        }
+        let spec = match ty.as_atom().map(|a| a.as_str().get()) {
+            Some("integer") => BinaryEntrySpecifier::Integer {
+                signed,
+                endianness,
+                unit: unit.unwrap_or(1),
+            },
+            Some("float") => BinaryEntrySpecifier::Float {
+                endianness,
+                unit: unit.unwrap_or(1),
+            },
+            Some("binary") => BinaryEntrySpecifier::Binary {
+                unit: unit.unwrap_or(8),
+            },
+            Some("utf8") => BinaryEntrySpecifier::Utf8,
+            Some("utf16") => BinaryEntrySpecifier::Utf16 { endianness },
+            Some("utf32") => BinaryEntrySpecifier::Utf32 { endianness },
+            _ => return Err(self.invalid(ty.span, "invalid segment type")),
+        };
+        let size = if size.is_atom_value(symbols::All) || size.is_atom_value(symbols::Undefined) {
+            None
+        } else {
+            Some(Box::new(size))
+        };
+        Ok(Bitstring {
+            span,
+            annotations: Annotations::default(),
+            value: Box::new(value),
+            size,
+            spec,
+        })
+    }
+
+    /// Parses a map, i.e. `~{Key => Value, ... | Map}~`
+    fn map(&mut self, start: usize, pattern: bool) -> Result<Expr> {
+        let mut pairs = vec![];
+        let mut arg = None;
+        if !self.eat(&Token::MapEnd) {
+            loop {
+                let key = self.expr()?;
+                let op = match self.next()? {
+                    (_, Token::Assoc, _) => MapOp::Assoc,
+                    (_, Token::Exact, _) => MapOp::Exact,
+                    lexed => return Err(self.unexpected(lexed, "'=>' or ':='")),
+                };
+                let value = self.element(pattern)?;
+                pairs.push(MapPair {
+                    op,
+                    key: Box::new(key),
+                    value: Box::new(value),
+                });
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+            if self.eat(&Token::Bar) {
+                arg = Some(self.expr()?);
+            }
+            self.expect(Token::MapEnd)?;
+        }
+        let span = self.span(start);
+        let map = match arg {
+            Some(arg) => Map::update(span, arg, pairs),
+            None if pattern => Map::new_pattern(span, pairs),
+            None => Map::new(span, pairs),
+        };
+        Ok(Expr::Map(map))
+    }
+}
+
+fn annotate(annotations: &mut Annotations, with: Annotations) {
+    for (key, value) in with.iter() {
+        annotations.insert_mut(*key, value.clone());
+    }
+}
+
+/// Converts an expression consisting only of constant terms to a literal
+fn to_literal(expr: Expr) -> Option<Literal> {
+    match expr {
+        Expr::Literal(lit) => Some(lit),
+        Expr::Tuple(Tuple {
+            span, mut elements, ..
+        }) => {
+            let elements = elements.drain(..).map(to_literal).try_collect()?;
+            Some(Literal::tuple(span, elements))
+        }
+        Expr::Cons(Cons {
+            span, head, tail, ..
+        }) => Some(Literal::cons(span, to_literal(*head)?, to_literal(*tail)?)),
+        Expr::Map(Map {
+            span,
+            arg,
+            mut pairs,
+            ..
+        }) => {
+            match to_literal(*arg)?.value {
+                Lit::Map(m) if m.is_empty() => (),
+                _ => return None,
+            }
+            let pairs = pairs
+                .drain(..)
+                .map(|pair| match pair.op {
+                    MapOp::Assoc => Some((to_literal(*pair.key)?, to_literal(*pair.value)?)),
+                    MapOp::Exact => None,
+                })
+                .try_collect()?;
+            Some(Literal::map(span, pairs))
+        }
+        _ => None,
+    }
+}
+
+/// Converts a list of `{Name, Arity}` tuples, as found in the `on_load` and `nifs` attributes
+fn function_names(list: &Literal) -> Vec<Span<FunctionName>> {
+    let mut names = vec![];
+    let mut next = list;
+    while let Lit::Cons(ref head, ref tail) = next.value {
+        if let Lit::Tuple(ref elements) = head.value {
+            if let [name, arity] = elements.as_slice() {
+                let arity = arity.as_integer().and_then(|i| i.to_u8());
+                if let (Some(name), Some(arity)) = (name.as_atom(), arity) {
+                    let name = FunctionName::new_local(name, arity);
+                    names.push(Span::new(head.span, name));
+                }
+            }
+        }
+        next = tail.as_ref();
+    }
+    names
+}
+
+/// Builds a literal binary from segments which are all integer constants of known size
+fn to_bitvec(segments: &[Bitstring]) -> Option<BitVec> {
+    let mut bits = BitVec::new();
+    for segment in segments {
+        let unit = match segment.spec {
+            BinaryEntrySpecifier::Integer {
+                endianness: Endianness::Big,
+                unit,
+                ..
+            } => unit as usize,
+            _ => return None,
+        };
+        let value = match segment.value.as_ref() {
+            Expr::Literal(Literal {
+                value: Lit::Integer(Integer::Small(i)),
+                ..
+            }) => *i,
+            _ => return None,
+        };
+        let size = match segment.size.as_deref() {
+            Some(Expr::Literal(Literal {
+                value: Lit::Integer(Integer::Small(i)),
+                ..
+            })) if *i >= 0 => *i as usize * unit,
+            _ => return None,
+        };
+        if size > 64 {
+            return None;
+        }
+        if size == 8 {
+            bits.push_byte(value as u8);
+        } else {
+            for i in (0..size).rev() {
+                bits.push_bit((value >> i) & 1 == 1);
+            }
+        }
+    }
+    Some(bits)
+}
@@ -0,0 +1,583 @@
+use std::fmt::{self, Write};
+
+use firefly_binary::{BinaryEntrySpecifier, Bitstring as _, Endianness};
+use firefly_intern::{symbols, Symbol};
+use firefly_syntax_base::*;
+
+use crate::*;
+
+/// Prints a module in the standard Core Erlang syntax, as read by `erlc` and `cerl::parse`.
+///
+/// Unlike `PrettyPrinter`, which is meant for debugging, everything printed here must read back
+/// in, so compiler-generated names are rewritten to valid variable names, `if` is printed as
+/// a `case`, and annotations without a Core Erlang equivalent are dropped.
+pub struct CorePrinter<'b, 'a: 'b> {
+    writer: &'b mut fmt::Formatter<'a>,
+    indent: usize,
+}
+impl<'b, 'a: 'b> CorePrinter<'b, 'a> {
+    pub fn new(writer: &'b mut fmt::Formatter<'a>) -> Self {
+        Self { writer, indent: 0 }
+    }
+
+    pub fn print_module(&mut self, module: &Module) -> fmt::Result {
+        self.writer.write_str("module ")?;
+        self.print_atom(module.name.name)?;
+        self.writer.write_str(" [")?;
+        let mut exports = module.exports.iter().map(|e| e.item).collect::<Vec<_>>();
+        exports.sort();
+        for (i, export) in exports.iter().enumerate() {
+            if i > 0 {
+                self.writer.write_str(",\n    ")?;
+            }
+            self.print_fname(export.function, export.arity as usize)?;
+        }
+        self.writer.write_str("]\n    attributes [")?;
+        let mut attributes = vec![];
+        if let Some(on_load) = module.on_load.as_ref() {
+            attributes.push((symbols::OnLoad, vec![on_load.item]));
+        }
+        if !module.nifs.is_empty() {
+            let mut nifs = module.nifs.iter().map(|n| n.item).collect::<Vec<_>>();
+            nifs.sort();
+            attributes.push((symbols::Nifs, nifs));
+        }
+        for (i, (key, names)) in attributes.iter().enumerate() {
+            if i > 0 {
+                self.writer.write_str(",\n        ")?;
+            }
+            self.print_atom(*key)?;
+            self.writer.write_str(" = [")?;
+            for (i, name) in names.iter().enumerate() {
+                if i > 0 {
+                    self.writer.write_char(',')?;
+                }
+                self.writer.write_char('{')?;
+                self.print_atom(name.function)?;
+                write!(self.writer, ",{}}}", name.arity)?;
+            }
+            self.writer.write_char(']')?;
+        }
+        self.writer.write_str("]\n")?;
+
+        for (name, function) in module.functions.iter() {
+            self.print_fname(name.function, name.arity as usize)?;
+            self.writer.write_str(" =")?;
+            self.indent += 4;
+            self.newline()?;
+            self.print_fun(&function.fun)?;
+            self.indent -= 4;
+            self.writer.write_char('\n')?;
+        }
+
+        self.writer.write_str("end\n")
+    }
+
+    fn print_fun(&mut self, fun: &Fun) -> fmt::Result {
+        self.annotated(&fun.annotations, |p| {
+            p.writer.write_str("fun (")?;
+            for (i, var) in fun.vars.iter().enumerate() {
+                if i > 0 {
+                    p.writer.write_char(',')?;
+                }
+                p.print_var(var)?;
+            }
+            p.writer.write_str(") ->")?;
+            p.indent += 4;
+            p.newline()?;
+            p.print_expr(fun.body.as_ref())?;
+            p.indent -= 4;
+            Ok(())
+        })
+    }
+
+    fn print_expr(&mut self, expr: &Expr) -> fmt::Result {
+        match expr {
+            Expr::Var(var) => self.print_var(var),
+            Expr::Fun(fun) => self.print_fun(fun),
+            expr => self.annotated(expr.annotations(), |p| p.print_unannotated(expr)),
+        }
+    }
+
+    fn print_unannotated(&mut self, expr: &Expr) -> fmt::Result {
+        match expr {
+            Expr::Alias(alias) => {
+                self.print_var(&alias.var)?;
+                self.writer.write_str(" = ")?;
+                self.print_expr(alias.pattern.as_ref())
+            }
+            Expr::Apply(apply) => {
+                self.writer.write_str("apply ")?;
+                self.print_expr(apply.callee.as_ref())?;
+                self.print_args(apply.args.as_slice())
+            }
+            Expr::Binary(bin) => {
+                self.writer.write_str("#{")?;
+                for (i, segment) in bin.segments.iter().enumerate() {
+                    if i > 0 {
+                        self.writer.write_char(',')?;
+                    }
+                    self.print_segment(segment)?;
+                }
+                self.writer.write_str("}#")
+            }
+            Expr::Call(call) => {
+                self.writer.write_str("call ")?;
+                self.print_expr(call.module.as_ref())?;
+                self.writer.write_char(':')?;
+                self.print_expr(call.function.as_ref())?;
+                self.print_args(call.args.as_slice())
+            }
+            Expr::Case(case) => {
+                self.writer.write_str("case ")?;
+                self.print_expr(case.arg.as_ref())?;
+                self.writer.write_str(" of")?;
+                self.indent += 2;
+                for clause in case.clauses.iter() {
+                    self.newline()?;
+                    self.print_clause(clause)?;
+                }
+                self.indent -= 2;
+                self.newline()?;
+                self.writer.write_str("end")
+            }
+            Expr::Catch(catch) => {
+                self.writer.write_str("catch")?;
+                self.indent += 4;
+                self.newline()?;
+                self.print_expr(catch.body.as_ref())?;
+                self.indent -= 4;
+                Ok(())
+            }
+            Expr::Cons(cons) => {
+                self.writer.write_char('[')?;
+                self.print_expr(cons.head.as_ref())?;
+                let mut tail = cons.tail.as_ref();
+                loop {
+                    match tail {
+                        Expr::Cons(cons) if cons.annotations.is_empty() => {
+                            self.writer.write_char(',')?;
+                            self.print_expr(cons.head.as_ref())?;
+                            tail = cons.tail.as_ref();
+                        }
+                        Expr::Literal(Literal {
+                            value: Lit::Nil, ..
+                        }) => break,
+                        tail => {
+                            self.writer.write_char('|')?;
+                            self.print_expr(tail)?;
+                            break;
+                        }
+                    }
+                }
+                self.writer.write_char(']')
+            }
+            Expr::If(expr) => {
+                // There is no `if` in Core Erlang, so we print the equivalent `case`
+                self.writer.write_str("case ")?;
+                self.print_expr(expr.guard.as_ref())?;
+                self.writer.write_str(" of")?;
+                self.indent += 2;
+                self.newline()?;
+                self.writer.write_str("<'true'> when 'true' ->")?;
+                self.indent += 4;
+                self.newline()?;
+                self.print_expr(expr.then_body.as_ref())?;
+                self.indent -= 4;
+                self.newline()?;
+                self.writer.write_str("<_> when 'true' ->")?;
+                self.indent += 4;
+                self.newline()?;
+                self.print_expr(expr.else_body.as_ref())?;
+                self.indent -= 6;
+                self.newline()?;
+                self.writer.write_str("end")
+            }
+            Expr::Let(expr) => {
+                self.writer.write_str("let ")?;
+                self.print_vars(expr.vars.as_slice())?;
+                self.writer.write_str(" =")?;
+                self.indent += 4;
+                self.newline()?;
+                self.print_expr(expr.arg.as_ref())?;
+                self.indent -= 4;
+                self.newline()?;
+                self.writer.write_str("in  ")?;
+                self.indent += 4;
+                self.print_expr(expr.body.as_ref())?;
+                self.indent -= 4;
+                Ok(())
+            }
+            Expr::LetRec(expr) => {
+                self.writer.write_str("letrec")?;
+                self.indent += 4;
+                for (var, def) in expr.defs.iter() {
+                    self.newline()?;
+                    self.print_var(var)?;
+                    self.writer.write_str(" =")?;
+                    self.indent += 4;
+                    self.newline()?;
+                    self.print_expr(def)?;
+                    self.indent -= 4;
+                }
+                self.indent -= 4;
+                self.newline()?;
+                self.writer.write_str("in  ")?;
+                self.indent += 4;
+                self.print_expr(expr.body.as_ref())?;
+                self.indent -= 4;
+                Ok(())
+            }
+            Expr::Literal(lit) => self.print_literal(lit),
+            Expr::Map(map) => {
+                self.writer.write_str("~{")?;
+                for (i, pair) in map.pairs.iter().enumerate() {
+                    if i > 0 {
+                        self.writer.write_char(',')?;
+                    }
+                    self.print_expr(pair.key.as_ref())?;
+                    match pair.op {
+                        MapOp::Exact => self.writer.write_str(":=")?,
+                        MapOp::Assoc => self.writer.write_str("=>")?,
+                    }
+                    self.print_expr(pair.value.as_ref())?;
+                }
+                match map.arg.as_ref() {
+                    Expr::Literal(Literal {
+                        value: Lit::Map(m), ..
+                    }) if m.is_empty() => (),
+                    arg => {
+                        self.writer.write_char('|')?;
+                        self.print_expr(arg)?;
+                    }
+                }
+                self.writer.write_str("}~")
+            }
+            Expr::PrimOp(op) => {
+                self.writer.write_str("primop ")?;
+                self.print_atom(op.name)?;
+                self.print_args(op.args.as_slice())
+            }
+            Expr::Receive(recv) => {
+                self.writer.write_str("receive")?;
+                self.indent += 2;
+                for clause in recv.clauses.iter() {
+                    self.newline()?;
+                    self.print_clause(clause)?;
+                }
+                self.indent -= 2;
+                self.newline()?;
+                self.writer.write_str("after ")?;
+                self.print_expr(recv.timeout.as_ref())?;
+                self.writer.write_str(" ->")?;
+                self.indent += 4;
+                self.newline()?;
+                self.print_expr(recv.action.as_ref())?;
+                self.indent -= 4;
+                Ok(())
+            }
+            Expr::Seq(seq) => {
+                self.writer.write_str("do  ")?;
+                self.indent += 4;
+                self.print_expr(seq.arg.as_ref())?;
+                self.newline()?;
+                self.print_expr(seq.body.as_ref())?;
+                self.indent -= 4;
+                Ok(())
+            }
+            Expr::Try(expr) => {
+                self.writer.write_str("try")?;
+                self.indent += 4;
+                self.newline()?;
+                self.print_expr(expr.arg.as_ref())?;
+                self.indent -= 4;
+                self.newline()?;
+                self.writer.write_str("of ")?;
+                self.print_vars(expr.vars.as_slice())?;
+                self.writer.write_str(" ->")?;
+                self.indent += 4;
+                self.newline()?;
+                self.print_expr(expr.body.as_ref())?;
+                self.indent -= 4;
+                self.newline()?;
+                self.writer.write_str("catch ")?;
+                self.print_vars(expr.evars.as_slice())?;
+                self.writer.write_str(" ->")?;
+                self.indent += 4;
+                self.newline()?;
+                self.print_expr(expr.handler.as_ref())?;
+                self.indent -= 4;
+                Ok(())
+            }
+            Expr::Tuple(tuple) => {
+                self.writer.write_char('{')?;
+                self.print_exprs(tuple.elements.as_slice())?;
+                self.writer.write_char('}')
+            }
+            Expr::Values(values) => {
+                self.writer.write_char('<')?;
+                self.print_exprs(values.values.as_slice())?;
+                self.writer.write_char('>')
+            }
+            Expr::Var(_) | Expr::Fun(_) => unreachable!(),
+        }
+    }
+
+    fn print_exprs(&mut self, exprs: &[Expr]) -> fmt::Result {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.writer.write_char(',')?;
+            }
+            self.print_expr(expr)?;
+        }
+        Ok(())
+    }
+
+    fn print_args(&mut self, args: &[Expr]) -> fmt::Result {
+        self.writer.write_char('(')?;
+        self.print_exprs(args)?;
+        self.writer.write_char(')')
+    }
+
+    fn print_clause(&mut self, clause: &Clause) -> fmt::Result {
+        self.annotated(&clause.annotations, |p| {
+            p.writer.write_char('<')?;
+            p.print_exprs(clause.patterns.as_slice())?;
+            p.writer.write_str("> when ")?;
+            match clause.guard.as_deref() {
+                None => p.writer.write_str("'true'")?,
+                Some(guard) => p.print_expr(guard)?,
+            }
+            p.writer.write_str(" ->")?;
+            p.indent += 4;
+            p.newline()?;
+            p.print_expr(clause.body.as_ref())?;
+            p.indent -= 4;
+            Ok(())
+        })
+    }
+
+    fn print_segment(&mut self, segment: &Bitstring) -> fmt::Result {
+        self.writer.write_str("#<")?;
+        self.print_expr(segment.value.as_ref())?;
+        self.writer.write_str(">(")?;
+        let (ty, unit, endianness, default_size) = match segment.spec {
+            BinaryEntrySpecifier::Integer {
+                endianness, unit, ..
+            } => ("integer", Some(unit), endianness, "8"),
+            BinaryEntrySpecifier::Float { endianness, unit } => {
+                ("float", Some(unit), endianness, "64")
+            }
+            BinaryEntrySpecifier::Binary { unit } => {
+                ("binary", Some(unit), Endianness::Big, "'all'")
+            }
+            BinaryEntrySpecifier::Utf8 => ("utf8", None, Endianness::Big, "'undefined'"),
+            BinaryEntrySpecifier::Utf16 { endianness } => {
+                ("utf16", None, endianness, "'undefined'")
+            }
+            BinaryEntrySpecifier::Utf32 { endianness } => {
+                ("utf32", None, endianness, "'undefined'")
+            }
+        };
+        match segment.size.as_deref() {
+            None => self.writer.write_str(default_size)?,
+            Some(size) => self.print_expr(size)?,
+        }
+        match unit {
+            None => self.writer.write_str(",'undefined'")?,
+            Some(unit) => write!(self.writer, ",{}", unit)?,
+        }
+        let signedness = match segment.spec {
+            BinaryEntrySpecifier::Integer { signed: true, .. } => "signed",
+            _ => "unsigned",
+        };
+        write!(
+            self.writer,
+            ",'{}',['{}','{}'])",
+            ty, signedness, endianness
+        )
+    }
+
+    fn print_literal(&mut self, literal: &Literal) -> fmt::Result {
+        match &literal.value {
+            Lit::Atom(a) => self.print_atom(*a),
+            Lit::Integer(i) => write!(self.writer, "{}", i),
+            Lit::Float(f) => write!(self.writer, "{}", f),
+            Lit::Nil => self.writer.write_str("[]"),
+            Lit::Cons(head, tail) => {
+                self.writer.write_char('[')?;
+                self.print_literal(head)?;
+                let mut tail = tail.as_ref();
+                loop {
+                    match &tail.value {
+                        Lit::Cons(head, rest) => {
+                            self.writer.write_char(',')?;
+                            self.print_literal(head)?;
+                            tail = rest.as_ref();
+                        }
+                        Lit::Nil => break,
+                        _ => {
+                            self.writer.write_char('|')?;
+                            self.print_literal(tail)?;
+                            break;
+                        }
+                    }
+                }
+                self.writer.write_char(']')
+            }
+            Lit::Tuple(elements) => {
+                self.writer.write_char('{')?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        self.writer.write_char(',')?;
+                    }
+                    self.print_literal(element)?;
+                }
+                self.writer.write_char('}')
+            }
+            Lit::Map(map) => {
+                self.writer.write_str("~{")?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        self.writer.write_char(',')?;
+                    }
+                    self.print_literal(key)?;
+                    self.writer.write_str("=>")?;
+                    self.print_literal(value)?;
+                }
+                self.writer.write_str("}~")
+            }
+            Lit::Binary(bits) => {
+                // Binaries are printed as a sequence of byte-sized integer segments, with any
+                // trailing bits in a final segment of the appropriate size
+                self.writer.write_str("#{")?;
+                let trailing = bits.bit_size() % 8;
+                let len = bits.byte_size();
+                for (i, byte) in bits.bytes().enumerate() {
+                    if i > 0 {
+                        self.writer.write_char(',')?;
+                    }
+                    let (value, size) = if i + 1 == len && trailing > 0 {
+                        (byte >> (8 - trailing), trailing)
+                    } else {
+                        (byte, 8)
+                    };
+                    write!(
+                        self.writer,
+                        "#<{}>({},1,'integer',['unsigned','big'])",
+                        value, size
+                    )?;
+                }
+                self.writer.write_str("}#")
+            }
+        }
+    }
+
+    fn print_atom(&mut self, atom: Symbol) -> fmt::Result {
+        self.writer.write_char('\'')?;
+        for c in atom.as_str().get().chars() {
+            match c {
+                '\'' => self.writer.write_str("\\'")?,
+                '\\' => self.writer.write_str("\\\\")?,
+                c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                    write!(self.writer, "\\{:03o}", c as u32)?
+                }
+                c => self.writer.write_char(c)?,
+            }
+        }
+        self.writer.write_char('\'')
+    }
+
+    fn print_fname(&mut self, name: Symbol, arity: usize) -> fmt::Result {
+        self.print_atom(name)?;
+        write!(self.writer, "/{}", arity)
+    }
+
+    fn print_var(&mut self, var: &Var) -> fmt::Result {
+        self.annotated(&var.annotations, |p| match var.arity {
+            Some(arity) => p.print_fname(var.name(), arity),
+            None => p.print_var_name(var.name()),
+        })
+    }
+
+    fn print_vars(&mut self, vars: &[Var]) -> fmt::Result {
+        self.writer.write_char('<')?;
+        for (i, var) in vars.iter().enumerate() {
+            if i > 0 {
+                self.writer.write_char(',')?;
+            }
+            self.print_var(var)?;
+        }
+        self.writer.write_char('>')
+    }
+
+    /// Variables from Erlang source are valid as-is, but compiler-generated ones, e.g. `$3`,
+    /// are not, so those are renamed into the `_@` namespace, which Erlang variables can't use
+    fn print_var_name(&mut self, name: Symbol) -> fmt::Result {
+        let name = name.as_str().get();
+        let mut chars = name.chars();
+        let is_valid = chars
+            .next()
+            .map(|c| c == '_' || c.is_uppercase())
+            .unwrap_or_default()
+            && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '@');
+        if is_valid {
+            return self.writer.write_str(name);
+        }
+        match name.strip_prefix('$') {
+            Some(id) if id.chars().all(|c| c.is_ascii_digit()) => write!(self.writer, "_@c{}", id),
+            _ => {
+                self.writer.write_str("_@")?;
+                for c in name.chars() {
+                    if c.is_alphanumeric() || c == '_' || c == '@' {
+                        self.writer.write_char(c)?;
+                    } else {
+                        self.writer.write_char('_')?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Prints an item wrapped with its annotations, if it has any which can be represented
+    fn annotated<F>(&mut self, annotations: &Annotations, print: F) -> fmt::Result
+    where
+        F: FnOnce(&mut Self) -> fmt::Result,
+    {
+        let annotations = annotations
+            .iter()
+            .filter(|(_, anno)| matches!(anno, Annotation::Unit | Annotation::Term(_)))
+            .collect::<Vec<_>>();
+        if annotations.is_empty() {
+            return print(self);
+        }
+        self.writer.write_str("( ")?;
+        print(self)?;
+        self.writer.write_str(" -| [")?;
+        for (i, (key, anno)) in annotations.iter().enumerate() {
+            if i > 0 {
+                self.writer.write_char(',')?;
+            }
+            match anno {
+                Annotation::Term(value) => {
+                    self.writer.write_char('{')?;
+                    self.print_atom(**key)?;
+                    self.writer.write_char(',')?;
+                    self.print_literal(value)?;
+                    self.writer.write_char('}')?;
+                }
+                _ => self.print_atom(**key)?,
+            }
+        }
+        self.writer.write_str("] )")
+    }
+
+    fn newline(&mut self) -> fmt::Result {
+        self.writer.write_char('\n')?;
+        for _ in 0..self.indent {
+            self.writer.write_char(' ')?;
+        }
+        Ok(())
+    }
+}
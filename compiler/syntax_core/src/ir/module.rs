@@ -35,7 +35,7 @@ impl Emit for Module {
     fn emit(&self, f: &mut std::fs::File) -> anyhow::Result<()> {
         use std::io::Write;
 
-        write!(f, "{}", self)?;
+        write!(f, "{}", crate::cerl::display(self))?;
         Ok(())
     }
 }
@@ -6,6 +6,7 @@
 #![feature(box_patterns)]
 #![feature(slice_take)]
 
+pub mod cerl;
 mod ir;
 pub mod macros;
 pub mod passes;
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
+
 use firefly_diagnostics::{SourceSpan, Span, Spanned};
-use firefly_syntax_base::{Deprecation, FunctionName};
+use firefly_syntax_base::{Deprecation, FunctionName, KindSignature};
 
 use super::{Expr, Ident, Name, Type};
 
@@ -106,6 +108,26 @@ pub struct TypeSig {
     pub ret: Box<Type>,
     pub guards: Option<Vec<TypeGuard>>,
 }
+impl TypeSig {
+    /// Approximates this clause by the kinds of terms it accepts and returns, see [`Type::kinds`]
+    pub fn kinds(&self, types: &HashMap<FunctionName, TypeDef>) -> KindSignature {
+        let no_vars = HashMap::new();
+        let vars = self
+            .guards
+            .iter()
+            .flatten()
+            .map(|guard| (guard.var.symbol(), guard.ty.kinds(types, &no_vars)))
+            .collect();
+        KindSignature {
+            params: self
+                .params
+                .iter()
+                .map(|param| param.kinds(types, &vars))
+                .collect(),
+            ret: self.ret.kinds(types, &vars),
+        }
+    }
+}
 
 /// Contains a single subtype constraint to be applied to a type specification
 #[derive(Debug, Clone, Spanned)]
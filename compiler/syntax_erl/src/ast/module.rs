@@ -84,6 +84,8 @@ pub struct Module {
     pub deprecation: Option<Deprecation>,
     // Used for function-level deprecation
     pub deprecations: HashSet<Deprecation>,
+    // The `debug_info` to embed in the module, see `passes::AbstractCode`
+    pub debug_info: Option<ast::Literal>,
 }
 impl Emit for Module {
     fn file_type(&self) -> Option<&'static str> {
@@ -110,6 +112,18 @@ impl Module {
         self.functions.contains_key(&local_name)
     }
 
+    /// Returns the type specifications of the functions in this module, approximated by the kinds
+    /// of terms each of their clauses accept and return, see [`TypeSig::kinds`]
+    pub fn spec_kinds(&self) -> BTreeMap<FunctionName, Vec<KindSignature>> {
+        self.specs
+            .iter()
+            .map(|(name, spec)| {
+                let sigs = spec.sigs.iter().map(|sig| sig.kinds(&self.types)).collect();
+                (*name, sigs)
+            })
+            .collect()
+    }
+
     pub fn is_import(&self, name: &FunctionName) -> bool {
         let local_name = name.to_local();
         !self.is_local(&local_name) && self.imports.contains_key(&local_name)
@@ -138,6 +152,7 @@ impl Module {
             functions: BTreeMap::new(),
             deprecation: None,
             deprecations: HashSet::new(),
+            debug_info: None,
         }
     }
 
@@ -190,6 +205,7 @@ impl Module {
             functions: BTreeMap::new(),
             deprecation: None,
             deprecations: HashSet::new(),
+            debug_info: None,
         };
 
         for form in forms.drain(0..) {
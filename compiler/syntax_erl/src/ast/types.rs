@@ -1,13 +1,13 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use lazy_static::lazy_static;
 
 use firefly_diagnostics::{SourceSpan, Spanned};
 use firefly_intern::{Ident, Symbol};
 use firefly_number::Integer;
-use firefly_syntax_base::{BinaryOp, UnaryOp};
+use firefly_syntax_base::{BinaryOp, FunctionName, TermKinds, UnaryOp};
 
-use crate::ast::Name;
+use crate::ast::{Name, TypeDef};
 
 lazy_static! {
     pub static ref BUILTIN_TYPES: HashSet<(Symbol, usize)> = {
@@ -170,6 +170,97 @@ impl Type {
             _ => false,
         }
     }
+
+    /// Approximates the set of values this type represents by the kinds of terms they belong to
+    ///
+    /// Local type aliases are resolved using `types`, and type variables using `vars`, anything
+    /// else we can't reason about, e.g. remote types, is assumed to be any term.
+    pub fn kinds(
+        &self,
+        types: &HashMap<FunctionName, TypeDef>,
+        vars: &HashMap<Symbol, TermKinds>,
+    ) -> TermKinds {
+        self.kinds_at(types, vars, 0)
+    }
+
+    fn kinds_at(
+        &self,
+        types: &HashMap<FunctionName, TypeDef>,
+        vars: &HashMap<Symbol, TermKinds>,
+        depth: usize,
+    ) -> TermKinds {
+        // Guards against recursive type definitions
+        if depth > 8 {
+            return TermKinds::ANY;
+        }
+        match self {
+            Type::Name(Name::Atom(_)) => TermKinds::ATOM,
+            Type::Name(Name::Var(var)) => vars.get(&var.name).copied().unwrap_or(TermKinds::ANY),
+            Type::Annotated { ty, .. } => ty.kinds_at(types, vars, depth),
+            Type::Union { types: tys, .. } => tys.iter().fold(TermKinds::NONE, |acc, ty| {
+                acc.union(ty.kinds_at(types, vars, depth))
+            }),
+            Type::Range { .. }
+            | Type::BinaryOp { .. }
+            | Type::UnaryOp { .. }
+            | Type::Integer(_, _)
+            | Type::Char(_, _) => TermKinds::INTEGER,
+            Type::Nil(_) => TermKinds::NIL,
+            Type::List(_, _) => TermKinds::LIST,
+            Type::NonEmptyList(_, _) => TermKinds::CONS,
+            Type::Map(_, _) => TermKinds::MAP,
+            Type::Tuple(_, _) | Type::Record(_, _, _) => TermKinds::TUPLE,
+            Type::Binary(_, _, _) => TermKinds::BITSTRING,
+            Type::AnyFun { .. } | Type::Fun { .. } => TermKinds::FUN,
+            Type::Generic { fun, params, .. } => match (fun.as_str().get(), params.len()) {
+                ("any" | "term" | "dynamic", 0) => TermKinds::ANY,
+                ("none" | "no_return", 0) => TermKinds::NONE,
+                (
+                    "integer" | "arity" | "byte" | "char" | "neg_integer" | "non_neg_integer"
+                    | "pos_integer",
+                    0,
+                ) => TermKinds::INTEGER,
+                ("float", 0) => TermKinds::FLOAT,
+                ("number", 0) => TermKinds::NUMBER,
+                ("atom" | "boolean" | "bool" | "module" | "node", 0) => TermKinds::ATOM,
+                ("binary" | "bitstring", 0) => TermKinds::BITSTRING,
+                ("iodata", 0) => TermKinds::LIST.union(TermKinds::BITSTRING),
+                ("list" | "string" | "nil" | "iolist" | "maybe_improper_list", _) => {
+                    TermKinds::LIST
+                }
+                (
+                    "nonempty_list"
+                    | "nonempty_string"
+                    | "nonempty_improper_list"
+                    | "nonempty_maybe_improper_list",
+                    _,
+                ) => TermKinds::CONS,
+                ("tuple" | "mfa", 0) => TermKinds::TUPLE,
+                ("map", 0) => TermKinds::MAP,
+                ("function", 0) => TermKinds::FUN,
+                ("pid", 0) => TermKinds::PID,
+                ("port", 0) => TermKinds::PORT,
+                ("reference", 0) => TermKinds::REFERENCE,
+                ("identifier", 0) => TermKinds::PID
+                    .union(TermKinds::PORT)
+                    .union(TermKinds::REFERENCE),
+                ("timeout", 0) => TermKinds::ATOM.union(TermKinds::INTEGER),
+                (_, arity) => {
+                    let name = FunctionName::new_local(fun.name, arity.try_into().unwrap());
+                    match types.get(&name) {
+                        // The parameters of the alias are unknown to its definition
+                        Some(def) if !def.opaque => {
+                            def.ty.kinds_at(types, &HashMap::new(), depth + 1)
+                        }
+                        _ => TermKinds::ANY,
+                    }
+                }
+            },
+            Type::Remote { .. } | Type::KeyValuePair(_, _, _) | Type::Field(_, _, _) => {
+                TermKinds::ANY
+            }
+        }
+    }
 }
 impl PartialEq for Type {
     fn eq(&self, other: &Type) -> bool {
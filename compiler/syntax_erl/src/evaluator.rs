@@ -203,7 +203,16 @@ pub fn eval_expr(
             }
             Literal::Map(*span, stored)
         }
-        Expr::Binary(_) => unimplemented!(),
+        Expr::Binary(bin) => {
+            let mut bindings = Bindings::default();
+            let bits = expr_grp(&bin.elements, &mut bindings, |expr, _| {
+                eval_expr(&expr, resolve_record_index)
+                    .map(Expr::Literal)
+                    .map_err(|_| ())
+            })
+            .map_err(|_| EvalError::InvalidConstExpression { span })?;
+            Literal::Binary(bin.span, bits)
+        }
         Expr::Record(_) => unimplemented!(),
         Expr::RecordIndex(rec_idx) if resolve_record_index.is_some() => {
             let span = rec_idx.span;
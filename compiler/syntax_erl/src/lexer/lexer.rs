@@ -276,6 +276,7 @@ where
             '}' => pop!(self, Token::RBrace),
             '?' => match self.peek() {
                 '?' => pop2!(self, Token::DoubleQuestion),
+                '=' => pop2!(self, Token::MaybeMatch),
                 _ => pop!(self, Token::Question),
             },
             '-' => match self.peek() {
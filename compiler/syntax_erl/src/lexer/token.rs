@@ -87,6 +87,9 @@ impl TryFrom<LexicalToken> for AtomToken {
             LexicalToken(start, Token::If, end) => {
                 return Ok(AtomToken(start, Token::Atom(symbols::If), end));
             }
+            LexicalToken(start, Token::Else, end) => {
+                Ok(AtomToken(start, Token::Atom(symbols::Else), end))
+            }
             t => Err(TokenConvertError {
                 span: t.span(),
                 token: t.token(),
@@ -335,6 +338,9 @@ pub enum Token {
     Of,
     Receive,
     When,
+    // Keywords enabled by the maybe_expr feature
+    Maybe,
+    Else,
     // Attributes
     Record,
     Spec,
@@ -410,6 +416,8 @@ pub enum Token {
     DotDotDot,
     Question,
     DoubleQuestion,
+    // ?=
+    MaybeMatch,
 }
 impl PartialEq for Token {
     fn eq(&self, other: &Token) -> bool {
@@ -484,6 +492,8 @@ impl Token {
             "of" => Token::Of,
             "receive" => Token::Receive,
             "when" => Token::When,
+            "maybe" => Token::Maybe,
+            "else" => Token::Else,
             "andalso" => Token::AndAlso,
             "orelse" => Token::OrElse,
             "bnot" => Token::Bnot,
@@ -561,6 +571,8 @@ impl fmt::Display for Token {
             Token::Of => write!(f, "of"),
             Token::Receive => write!(f, "receive"),
             Token::When => write!(f, "when"),
+            Token::Maybe => write!(f, "maybe"),
+            Token::Else => write!(f, "else"),
             Token::Record => write!(f, "record"),
             Token::Spec => write!(f, "spec"),
             Token::Callback => write!(f, "callback"),
@@ -620,6 +632,7 @@ impl fmt::Display for Token {
             Token::DotDotDot => write!(f, "..."),
             Token::Question => write!(f, "?"),
             Token::DoubleQuestion => write!(f, "??"),
+            Token::MaybeMatch => write!(f, "?="),
         }
     }
 }
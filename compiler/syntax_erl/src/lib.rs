@@ -10,7 +10,7 @@
 #[macro_use]
 mod macros;
 mod ast;
-mod evaluator;
+pub mod evaluator;
 pub mod features;
 mod lexer;
 mod parser;
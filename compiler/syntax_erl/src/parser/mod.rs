@@ -887,6 +887,86 @@ bar() -> yay.
         );
     }
 
+    #[test]
+    fn parse_map_comprehension() {
+        let _result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            r#"-module(foo).
+
+swap(Map) -> #{V => K || K := V <- Map}.
+
+pairs(Bin) -> << <<X, Y>> || <<X>> <= Bin, <<Y>> <= Bin, X =/= Y >>.
+"#,
+        );
+    }
+
+    #[test]
+    fn parse_maybe_expression() {
+        let _result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            r#"-module(foo).
+-feature(maybe_expr, enable).
+
+foo(X) ->
+    maybe
+        {ok, Y} ?= bar(X),
+        Z = Y + 1,
+        {ok, Z}
+    else
+        {error, _} = Error -> Error;
+        _ -> undefined
+    end.
+"#,
+        );
+    }
+
+    #[test]
+    fn parse_maybe_as_atom() {
+        let _result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            r#"-module(foo).
+
+foo() -> {maybe, else}.
+"#,
+        );
+    }
+
+    #[test]
+    fn parse_spec_kinds() {
+        use firefly_syntax_base::{FunctionName, KindSignature, TermKinds};
+
+        let result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            r#"-module(foo).
+
+-type result() :: ok | {error, term()}.
+
+-spec foo(integer(), [atom()]) -> result();
+         (X, binary()) -> X when X :: float().
+foo(_, _) -> ok.
+"#,
+        );
+        let name = FunctionName::new(Symbol::intern("foo"), Symbol::intern("foo"), 2);
+        let kinds = result.spec_kinds();
+        assert_eq!(
+            kinds.get(&name),
+            Some(&vec![
+                KindSignature {
+                    params: vec![TermKinds::INTEGER, TermKinds::LIST],
+                    ret: TermKinds::ATOM.union(TermKinds::TUPLE),
+                },
+                KindSignature {
+                    params: vec![TermKinds::FLOAT, TermKinds::BITSTRING],
+                    ret: TermKinds::FLOAT,
+                },
+            ])
+        );
+    }
+
     #[test]
     fn parse_elixir_enum_erl() {
         use std::io::Read;
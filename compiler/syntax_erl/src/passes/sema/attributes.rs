@@ -508,6 +508,12 @@ fn set_compile_option(
                 "warn_nif_inline" => options.warn_nif_inline = true,
                 "nowarn_nif_inline" => options.warn_nif_inline = false,
 
+                "warn_unsafe_atom" => options.warn_unsafe_atom = true,
+                "nowarn_unsafe_atom" => options.warn_unsafe_atom = false,
+
+                "warn_case_coverage" => options.warn_case_coverage = true,
+                "nowarn_case_coverage" => options.warn_case_coverage = false,
+
                 _name => {
                     reporter.diagnostic(
                         Diagnostic::warning()
@@ -1,9 +1,12 @@
+use core::ops::ControlFlow;
+
 use firefly_diagnostics::*;
-use firefly_intern::symbols;
+use firefly_intern::{symbols, Ident, Symbol};
 use firefly_pass::Pass;
 use firefly_syntax_base::*;
 
 use crate::ast::{self, *};
+use crate::visit::{self, VisitMut};
 
 /// Registers auto-imported BIFs in the given module
 ///
@@ -45,6 +48,8 @@ impl Pass for AddAutoImports {
 /// * `record_info/2`
 /// * `behaviour_info/1` (optional)
 ///
+/// If the module carries `debug_info`, it is returned by `module_info(debug_info)`.
+///
 /// NOTE: We do not provide the `md5` module info key, as its definition in Erlang doesn't
 /// mean anything for us, and producing our own has no known benefit at this time.
 pub struct DefinePseudoLocals;
@@ -138,7 +143,7 @@ impl Pass for DefinePseudoLocals {
         define_function(module, mod_info_0);
 
         // Define module_info/1 which contains accepts the following keys: module, attributes, compile, exports, functions, nifs, md5 and native
-        let mod_info_1 = Function {
+        let mut mod_info_1 = Function {
             span: SourceSpan::UNKNOWN,
             name: ident!(module_info),
             arity: 1,
@@ -231,6 +236,20 @@ impl Pass for DefinePseudoLocals {
             var_counter: 0,
             fun_counter: 0,
         };
+        // When requested, the abstract code of the module is available via the debug_info key,
+        // which stands in for the `debug_info` chunk `erlc` stores in BEAM files
+        if let Some(debug_info) = module.debug_info.take() {
+            mod_info_1.clauses.push((
+                Some(Name::Atom(ident!(module_info))),
+                Clause {
+                    span: SourceSpan::UNKNOWN,
+                    patterns: vec![Expr::Literal(ast_lit_atom!(Symbol::intern("debug_info")))],
+                    guards: vec![],
+                    body: vec![Expr::Literal(debug_info)],
+                    compiler_generated: true,
+                },
+            ));
+        }
         define_function(module, mod_info_1);
 
         if !module.records.is_empty() {
@@ -317,6 +336,226 @@ impl Pass for DefinePseudoLocals {
     }
 }
 
+/// Rewrites calls to `erlang:load_nif/2` as calls to `erlang:load_nif/3`, which takes the module
+/// on whose behalf the library is loaded as its first argument, so the runtime need not look for
+/// the calling module on the stack.
+///
+/// Calls which are not known statically, e.g. via `erlang:apply/3`, are left as they are.
+///
+/// Only the functions declared in a `-nifs` attribute can be replaced by a loaded library (see
+/// `DispatchNifs`), so loading one in a module which declares none raises a warning.
+pub struct QualifyLoadNif {
+    reporter: Reporter,
+}
+impl QualifyLoadNif {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for QualifyLoadNif {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let load_nif = FunctionName::new(symbols::Erlang, Symbol::intern("load_nif"), 2);
+        let local = load_nif.to_local();
+        let imported = !module.functions.contains_key(&local)
+            && module.imports.get(&local).map(|sig| sig.mfa()) == Some(load_nif);
+
+        let mut visitor = QualifyLoadNifVisitor {
+            module: module.name.name,
+            load_nif,
+            imported,
+            calls: vec![],
+        };
+        for (_, function) in module.functions.iter_mut() {
+            let _ = visitor.visit_mut_function(function);
+        }
+
+        if let Some(span) = visitor.calls.first() {
+            if module.nifs.is_empty() {
+                self.reporter.show_warning(
+                    "no functions are declared as nifs",
+                    &[(
+                        *span,
+                        "the loaded library will not replace any functions, as only those \
+                         declared in a -nifs attribute can be implemented natively",
+                    )],
+                );
+            }
+        }
+
+        Ok(module)
+    }
+}
+
+struct QualifyLoadNifVisitor {
+    module: Symbol,
+    load_nif: FunctionName,
+    /// True if `load_nif/2` refers to the auto-imported BIF
+    imported: bool,
+    calls: Vec<SourceSpan>,
+}
+impl VisitMut<()> for QualifyLoadNifVisitor {
+    fn visit_mut_apply(&mut self, apply: &mut Apply) -> ControlFlow<()> {
+        visit::visit_mut_apply(self, apply)?;
+
+        let arity = apply.args.len() as u8;
+        let is_load_nif = match apply.callee.as_ref() {
+            Expr::FunctionVar(FunctionVar::Resolved(name)) => name.item == self.load_nif,
+            Expr::FunctionVar(FunctionVar::PartiallyResolved(name)) => {
+                self.imported && name.item == self.load_nif.to_local()
+            }
+            Expr::Literal(ast::Literal::Atom(f)) => {
+                self.imported && f.name == self.load_nif.function && arity == self.load_nif.arity
+            }
+            _ => false,
+        };
+        if is_load_nif {
+            let span = apply.span;
+            let mut args = vec![atom!(span, self.module)];
+            args.append(&mut apply.args);
+            *apply = Apply::remote(span, symbols::Erlang, self.load_nif.function, args);
+            self.calls.push(span);
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+/// Rewrites functions implemented as NIFs so that they dispatch to a native implementation
+/// loaded at runtime via `erlang:load_nif/2`, if one is present.
+///
+/// Only the functions declared in a `-nifs` attribute are rewritten, unlike BEAM, which permits
+/// a library to replace any function of the module loading it when that attribute is absent.
+///
+/// Each such function `f/N` is split in two: its original clauses are moved to a private
+/// fallback function, and `f/N` is redefined as follows:
+///
+/// ```erlang
+/// f(A1, .., AN) ->
+///     case erlang:is_nif_loaded(?MODULE, f, N) of
+///         true -> erlang:apply_nif(?MODULE, f, [A1, .., AN]);
+///         false -> '-f/N-nif-'(A1, .., AN)
+///     end.
+/// ```
+///
+/// The redefined function remains marked as a NIF, so that a native implementation linked
+/// statically into the executable still takes precedence over it.
+///
+/// NOTE: This pass must run after `DefinePseudoLocals`, so the fallbacks are not visible via
+/// `module_info`.
+pub struct DispatchNifs;
+impl Pass for DispatchNifs {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let nifs = module
+            .functions
+            .iter()
+            .filter_map(|(name, f)| if f.is_nif { Some(*name) } else { None })
+            .collect::<Vec<_>>();
+
+        for name in nifs {
+            let mut function = module.functions.remove(&name).unwrap();
+            let span = function.span;
+            let fallback_name = Ident::new(
+                Symbol::intern(&format!("-{}/{}-nif-", name.function, name.arity)),
+                function.name.span,
+            );
+
+            let vars = (0..function.arity)
+                .map(|_| Expr::Var(Var(function.next_var(Some(span)))))
+                .collect::<Vec<_>>();
+            let arglist = vars
+                .iter()
+                .rev()
+                .fold(nil!(span), |tail, var| cons!(span, var.clone(), tail));
+            let is_loaded = Apply::remote(
+                span,
+                symbols::Erlang,
+                Symbol::intern("is_nif_loaded"),
+                vec![
+                    atom!(span, module.name.name),
+                    atom!(span, name.function),
+                    int!(span, (name.arity as i64).into()),
+                ],
+            );
+            let apply_nif = Apply::remote(
+                span,
+                symbols::Erlang,
+                Symbol::intern("apply_nif"),
+                vec![
+                    atom!(span, module.name.name),
+                    atom!(span, name.function),
+                    arglist,
+                ],
+            );
+            let fallback = Apply::local(span, fallback_name.name, vars.clone());
+            let dispatch = Expr::Case(Case {
+                span,
+                expr: Box::new(Expr::Apply(is_loaded)),
+                clauses: vec![
+                    Clause {
+                        span,
+                        patterns: vec![atom!(span, symbols::True)],
+                        guards: vec![],
+                        body: vec![Expr::Apply(apply_nif)],
+                        compiler_generated: true,
+                    },
+                    Clause {
+                        span,
+                        patterns: vec![atom!(span, symbols::False)],
+                        guards: vec![],
+                        body: vec![Expr::Apply(fallback)],
+                        compiler_generated: true,
+                    },
+                ],
+            });
+
+            let wrapper = Function {
+                span,
+                name: function.name,
+                arity: function.arity,
+                spec: function.spec.take(),
+                is_nif: true,
+                clauses: vec![(
+                    Some(Name::Atom(function.name)),
+                    Clause {
+                        span,
+                        patterns: vars,
+                        guards: vec![],
+                        body: vec![dispatch],
+                        compiler_generated: true,
+                    },
+                )],
+                var_counter: function.var_counter,
+                fun_counter: 0,
+            };
+
+            let fallback = Function {
+                name: fallback_name,
+                is_nif: false,
+                clauses: function
+                    .clauses
+                    .drain(..)
+                    .map(|(_, clause)| (Some(Name::Atom(fallback_name)), clause))
+                    .collect(),
+                ..function
+            };
+
+            module.functions.insert(name, wrapper);
+            module.functions.insert(
+                FunctionName::new_local(fallback_name.name, fallback.arity),
+                fallback,
+            );
+        }
+
+        Ok(module)
+    }
+}
+
 fn define_function(module: &mut Module, f: Function) {
     let name = FunctionName::new_local(f.name.name, f.arity);
     module.exports.insert(Span::new(f.name.span, name));
@@ -24,6 +24,8 @@ pub use self::records::analyze_record;
 /// * If configured to do so, warns if functions are missing type specs
 /// * Warns about type specs for undefined functions
 /// * Warns about redefined attributes
+/// * Warns about atoms created from data which may come from an external source
+/// * Warns about case expressions which don't cover, or can never match, the atoms a function is spec'd to return
 /// * Errors on invalid nif declarations
 /// * Errors on invalid syntax in built-in attributes (e.g. -import(..))
 /// * Errors on mismatched function clauses (name/arity)
@@ -54,7 +56,11 @@ impl<'app> Pass for SemanticAnalysis<'app> {
             // but before VerifyCalls so that any calls to module_info are not erroneously treated as
             // errors prior to them being defined by this pass
             .chain(inject::DefinePseudoLocals)
-            .chain(verify::VerifyCalls::new(self.reporter.clone(), self.app));
+            .chain(inject::QualifyLoadNif::new(self.reporter.clone()))
+            .chain(inject::DispatchNifs)
+            .chain(verify::VerifyCalls::new(self.reporter.clone(), self.app))
+            .chain(verify::VerifyAtomCreation::new(self.reporter.clone()))
+            .chain(verify::VerifyCaseCoverage::new(self.reporter.clone()));
 
         passes.run(&mut module)?;
 
@@ -1,8 +1,8 @@
 use core::ops::ControlFlow;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use firefly_diagnostics::*;
-use firefly_intern::Symbol;
+use firefly_intern::{symbols, Symbol};
 use firefly_pass::Pass;
 use firefly_syntax_base::{ApplicationMetadata, Deprecation, FunctionName};
 
@@ -567,3 +567,581 @@ impl<'a> VisitMut<()> for VerifyCallsVisitor<'a> {
         }
     }
 }
+
+/// Functions whose results are considered to come from outside the system
+const EXTERNAL_SOURCES: &[(&str, &str)] = &[
+    ("erlang", "binary_to_term"),
+    ("io", "get_line"),
+    ("io", "get_chars"),
+    ("io", "read"),
+    ("io", "fread"),
+    ("file", "read_file"),
+    ("file", "read_line"),
+    ("file", "read"),
+    ("file", "pread"),
+    ("file", "consult"),
+    ("gen_tcp", "recv"),
+    ("gen_udp", "recv"),
+    ("gen_sctp", "recv"),
+    ("ssl", "recv"),
+    ("os", "getenv"),
+    ("os", "cmd"),
+    ("init", "get_argument"),
+    ("init", "get_arguments"),
+    ("init", "get_plain_arguments"),
+    ("httpc", "request"),
+    ("json", "decode"),
+    ("jsx", "decode"),
+    ("jiffy", "decode"),
+    ("jsone", "decode"),
+    ("cowboy_req", "binding"),
+    ("cowboy_req", "bindings"),
+    ("cowboy_req", "header"),
+    ("cowboy_req", "headers"),
+    ("cowboy_req", "parse_qs"),
+    ("cowboy_req", "match_qs"),
+    ("cowboy_req", "qs"),
+    ("cowboy_req", "path"),
+    ("cowboy_req", "path_info"),
+    ("cowboy_req", "read_body"),
+    ("cowboy_req", "read_urlencoded_body"),
+];
+
+/// The tags of messages delivered by sockets in active mode
+const EXTERNAL_MESSAGES: &[&str] = &["tcp", "udp", "sctp", "ssl", "http"];
+
+/// Warns when `list_to_atom/1` or `binary_to_atom/1,2` is applied to data which may come from an
+/// external source, as atoms are never garbage collected, and creating them from untrusted input is
+/// a common cause of atom table exhaustion.
+///
+/// This is a simple taint analysis local to each function clause: values returned from known input
+/// functions (e.g. `gen_tcp:recv/2`), and bound from socket messages, are tainted, as are any
+/// values bound from, or computed from, tainted values.
+pub struct VerifyAtomCreation {
+    reporter: Reporter,
+}
+impl VerifyAtomCreation {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyAtomCreation {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let enabled = module
+            .compile
+            .as_ref()
+            .map(|c| c.warn_unsafe_atom && !c.no_warn)
+            .unwrap_or(true);
+        if !enabled {
+            return Ok(module);
+        }
+
+        let locals = module.functions.keys().copied().collect::<BTreeSet<_>>();
+        for (_, function) in module.functions.iter_mut() {
+            for (_, clause) in function.clauses.iter_mut() {
+                let mut visitor = VerifyAtomCreationVisitor {
+                    reporter: self.reporter.clone(),
+                    locals: &locals,
+                    tainted: BTreeSet::new(),
+                    binding: false,
+                };
+                visitor.visit_mut_clause(clause);
+            }
+        }
+        Ok(module)
+    }
+}
+
+struct VerifyAtomCreationVisitor<'a> {
+    reporter: Reporter,
+    locals: &'a BTreeSet<FunctionName>,
+    /// The variables bound to tainted values
+    tainted: BTreeSet<Symbol>,
+    /// Set when visiting a pattern whose bindings are tainted
+    binding: bool,
+}
+impl<'a> VerifyAtomCreationVisitor<'a> {
+    fn visit_tainted_pattern(&mut self, pattern: &mut Expr) {
+        let binding = core::mem::replace(&mut self.binding, true);
+        let _ = self.visit_mut_pattern(pattern);
+        self.binding = binding;
+    }
+
+    fn is_tainted(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Var(var) => self.tainted.contains(&var.sym()),
+            Expr::Cons(Cons { head, tail, .. }) => self.is_tainted(head) || self.is_tainted(tail),
+            Expr::Tuple(Tuple { elements, .. }) => elements.iter().any(|e| self.is_tainted(e)),
+            Expr::Binary(Binary { elements, .. }) => {
+                elements.iter().any(|e| self.is_tainted(&e.bit_expr))
+            }
+            Expr::BinaryExpr(BinaryExpr { lhs, rhs, .. }) => {
+                self.is_tainted(lhs) || self.is_tainted(rhs)
+            }
+            Expr::Match(Match { expr, .. }) => self.is_tainted(expr),
+            Expr::Begin(Begin { body, .. }) => {
+                body.last().map(|e| self.is_tainted(e)).unwrap_or(false)
+            }
+            // Results of known input functions are tainted, and we assume that any other function
+            // applied to tainted data produces tainted data, e.g. `string:trim/1`
+            Expr::Apply(apply) => match callee_name(apply.callee.as_ref()) {
+                Some((Some(m), f))
+                    if EXTERNAL_SOURCES
+                        .iter()
+                        .any(|(sm, sf)| m.as_str().get() == *sm && f.as_str().get() == *sf) =>
+                {
+                    true
+                }
+                _ => apply.args.iter().any(|arg| self.is_tainted(arg)),
+            },
+            _ => false,
+        }
+    }
+
+    fn is_atom_constructor(&self, callee: &Expr, arity: u8) -> Option<&'static str> {
+        let (module, function) = callee_name(callee)?;
+        match module {
+            Some(m) if m.as_str().get() != "erlang" => return None,
+            // Unqualified calls may refer to local functions with the same name
+            None if self
+                .locals
+                .contains(&FunctionName::new_local(function, arity)) =>
+            {
+                return None
+            }
+            _ => (),
+        }
+        match (function.as_str().get(), arity) {
+            ("list_to_atom", 1) => Some("list_to_existing_atom/1"),
+            ("binary_to_atom", 1) => Some("binary_to_existing_atom/1"),
+            ("binary_to_atom", 2) => Some("binary_to_existing_atom/2"),
+            _ => None,
+        }
+    }
+}
+impl<'a> VisitMut<()> for VerifyAtomCreationVisitor<'a> {
+    fn visit_mut_var(&mut self, var: &mut Var) -> ControlFlow<()> {
+        if self.binding && !var.is_wildcard() {
+            self.tainted.insert(var.sym());
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_match(&mut self, expr: &mut Match) -> ControlFlow<()> {
+        self.visit_mut_expr(expr.expr.as_mut())?;
+        if self.is_tainted(expr.expr.as_ref()) {
+            self.visit_tainted_pattern(expr.pattern.as_mut());
+            ControlFlow::Continue(())
+        } else {
+            self.visit_mut_pattern(expr.pattern.as_mut())
+        }
+    }
+
+    fn visit_mut_case(&mut self, case: &mut Case) -> ControlFlow<()> {
+        self.visit_mut_expr(case.expr.as_mut())?;
+        let tainted = self.is_tainted(case.expr.as_ref());
+        for clause in case.clauses.iter_mut() {
+            if tainted {
+                for pattern in clause.patterns.iter_mut() {
+                    self.visit_tainted_pattern(pattern);
+                }
+            }
+            self.visit_mut_clause(clause)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_receive(&mut self, receive: &mut Receive) -> ControlFlow<()> {
+        if let Some(clauses) = receive.clauses.as_mut() {
+            for clause in clauses.iter_mut() {
+                for pattern in clause.patterns.iter_mut() {
+                    if is_external_message(pattern) {
+                        self.visit_tainted_pattern(pattern);
+                    }
+                }
+                self.visit_mut_clause(clause)?;
+            }
+        }
+        if let Some(after) = receive.after.as_mut() {
+            self.visit_mut_after(after)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_apply(&mut self, apply: &mut Apply) -> ControlFlow<()> {
+        for arg in apply.args.iter_mut() {
+            self.visit_mut_expr(arg)?;
+        }
+        let arity = apply.args.len() as u8;
+        let Some(alternative) = self.is_atom_constructor(apply.callee.as_ref(), arity) else {
+            return ControlFlow::Continue(());
+        };
+        if self.is_tainted(&apply.args[0]) {
+            let note = format!(
+                "consider using {} if the set of expected atoms is known ahead of time",
+                alternative
+            );
+            self.reporter.diagnostic(
+                Diagnostic::warning()
+                    .with_message("unsafe atom creation")
+                    .with_labels(vec![Label::primary(apply.span.source_id(), apply.span)
+                        .with_message(
+                            "this creates atoms from data which may come from an external source",
+                        )])
+                    .with_notes(vec![
+                        "atoms are never garbage collected, so creating them from untrusted input can exhaust the atom table".to_string(),
+                        note,
+                    ]),
+            );
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Returns the module (if qualified) and function name of the given callee, if statically known
+fn callee_name(callee: &Expr) -> Option<(Option<Symbol>, Symbol)> {
+    match callee {
+        Expr::Remote(Remote {
+            module, function, ..
+        }) => Some((Some(module.as_atom()?.name), function.as_atom()?.name)),
+        Expr::FunctionVar(name) => Some((name.module(), name.function()?)),
+        Expr::Literal(Literal::Atom(f)) => Some((None, f.name)),
+        _ => None,
+    }
+}
+
+/// Returns true if `pattern` matches messages delivered by sockets in active mode, e.g. `{tcp,
+/// Socket, Data}`
+fn is_external_message(pattern: &Expr) -> bool {
+    match pattern {
+        Expr::Tuple(Tuple { elements, .. }) => match elements.first().and_then(|e| e.as_atom()) {
+            Some(tag) => EXTERNAL_MESSAGES.contains(&tag.as_str().get()),
+            None => false,
+        },
+        Expr::Match(Match { pattern, expr, .. }) => {
+            is_external_message(pattern) || is_external_message(expr)
+        }
+        _ => false,
+    }
+}
+
+/// Warns about `case` expressions over values whose atom domain is known from a type spec, e.g. the
+/// result of calling a local function spec'd to return `ok | error`, when the clauses do not cover
+/// every possible value, or when a clause can never match.
+pub struct VerifyCaseCoverage {
+    reporter: Reporter,
+}
+impl VerifyCaseCoverage {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyCaseCoverage {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let enabled = module
+            .compile
+            .as_ref()
+            .map(|c| c.warn_case_coverage && !c.no_warn)
+            .unwrap_or(true);
+        if !enabled {
+            return Ok(module);
+        }
+
+        let mut domains = BTreeMap::new();
+        for (name, function) in module.functions.iter() {
+            let Some(spec) = function.spec.as_ref() else {
+                continue;
+            };
+            let mut domain = BTreeSet::new();
+            let complete = spec
+                .sigs
+                .iter()
+                .all(|sig| atom_domain(sig.ret.as_ref(), &module.types, 0, &mut domain));
+            if complete && !domain.is_empty() {
+                domains.insert(*name, domain);
+            }
+        }
+        if domains.is_empty() {
+            return Ok(module);
+        }
+
+        let module_name = module.name();
+        for (_, function) in module.functions.iter_mut() {
+            for (_, clause) in function.clauses.iter_mut() {
+                let mut visitor = VerifyCaseCoverageVisitor {
+                    reporter: self.reporter.clone(),
+                    module: module_name,
+                    domains: &domains,
+                    vars: BTreeMap::new(),
+                    seen: BTreeSet::new(),
+                };
+                visitor.visit_mut_clause(clause);
+            }
+        }
+        Ok(module)
+    }
+}
+
+/// Collects the set of atoms `ty` may represent into `domain`, returning false if `ty` may
+/// represent anything other than a finite set of atoms
+fn atom_domain(
+    ty: &Type,
+    types: &HashMap<FunctionName, TypeDef>,
+    depth: usize,
+    domain: &mut BTreeSet<Symbol>,
+) -> bool {
+    // Guards against recursive type definitions
+    if depth > 8 {
+        return false;
+    }
+    match ty {
+        Type::Name(Name::Atom(atom)) => {
+            domain.insert(atom.name);
+            true
+        }
+        Type::Annotated { ty, .. } => atom_domain(ty, types, depth, domain),
+        Type::Union { types: tys, .. } => {
+            tys.iter().all(|ty| atom_domain(ty, types, depth, domain))
+        }
+        Type::Generic { fun, params, .. } if params.is_empty() => match fun.as_str().get() {
+            "boolean" | "bool" => {
+                domain.insert(symbols::True);
+                domain.insert(symbols::False);
+                true
+            }
+            _ => match types.get(&FunctionName::new_local(fun.name, 0)) {
+                Some(def) if !def.opaque => atom_domain(&def.ty, types, depth + 1, domain),
+                _ => false,
+            },
+        },
+        _ => false,
+    }
+}
+
+/// The set of atoms a value is known to be one of, and the function it was produced by
+#[derive(Copy, Clone)]
+struct AtomDomain<'a> {
+    source: FunctionName,
+    atoms: &'a BTreeSet<Symbol>,
+}
+
+/// A simplified view of a case clause pattern for the purposes of coverage analysis
+enum CoveragePattern {
+    /// The pattern matches any value, e.g. `_` or a variable which is not yet bound
+    Any,
+    /// The pattern matches a single atom
+    Atom(Symbol),
+    /// The pattern can never match an atom, e.g. `{ok, _}`
+    Never,
+    /// We can't reason about the pattern
+    Unknown,
+}
+impl CoveragePattern {
+    /// Classifies `pattern`, given the variables which may already be bound where it is matched
+    ///
+    /// A bound variable only matches the value it is bound to, which may or may not be an atom
+    /// of the domain, so nothing can be said about it.
+    fn new(pattern: &Expr, bound: &BTreeSet<Symbol>) -> Self {
+        match pattern {
+            Expr::Var(var) if var.is_wildcard() || !bound.contains(&var.sym()) => Self::Any,
+            Expr::Var(_) => Self::Unknown,
+            Expr::Literal(Literal::Atom(atom)) => Self::Atom(atom.name),
+            Expr::Literal(_)
+            | Expr::Cons(_)
+            | Expr::Tuple(_)
+            | Expr::Map(_)
+            | Expr::Binary(_)
+            | Expr::Record(_) => Self::Never,
+            Expr::Match(Match { pattern, expr, .. }) => {
+                match (
+                    Self::new(pattern.as_ref(), bound),
+                    Self::new(expr.as_ref(), bound),
+                ) {
+                    (Self::Any, other) | (other, Self::Any) => other,
+                    (Self::Atom(a), Self::Atom(b)) if a == b => Self::Atom(a),
+                    (Self::Unknown, _) | (_, Self::Unknown) => Self::Unknown,
+                    _ => Self::Never,
+                }
+            }
+            _ => Self::Unknown,
+        }
+    }
+}
+
+struct VerifyCaseCoverageVisitor<'a> {
+    reporter: Reporter,
+    module: Symbol,
+    domains: &'a BTreeMap<FunctionName, BTreeSet<Symbol>>,
+    /// The variables bound to values with a known atom domain
+    vars: BTreeMap<Symbol, AtomDomain<'a>>,
+    /// The variables which occur before the expression being visited, a superset of those bound
+    seen: BTreeSet<Symbol>,
+}
+impl<'a> VerifyCaseCoverageVisitor<'a> {
+    fn domain_of(&self, expr: &Expr) -> Option<AtomDomain<'a>> {
+        match expr {
+            Expr::Var(var) => self.vars.get(&var.sym()).copied(),
+            Expr::Match(Match { expr, .. }) => self.domain_of(expr.as_ref()),
+            Expr::Apply(apply) => {
+                let (module, function) = callee_name(apply.callee.as_ref())?;
+                if module.map(|m| m != self.module).unwrap_or(false) {
+                    return None;
+                }
+                let name = FunctionName::new_local(function, apply.args.len() as u8);
+                let (source, atoms) = self.domains.get_key_value(&name)?;
+                Some(AtomDomain {
+                    source: *source,
+                    atoms,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn verify_case(&self, case: &Case, domain: AtomDomain<'a>) {
+        let values = format_atoms(domain.atoms.iter());
+        let source_note = format!("{} is specified to return {}", &domain.source, &values);
+
+        let mut covered = BTreeSet::new();
+        let mut exhaustive = false;
+        // Set if a clause may match some value of the domain, but we can't tell which
+        let mut uncertain = false;
+        for clause in case.clauses.iter() {
+            if clause.compiler_generated || clause.patterns.len() != 1 {
+                continue;
+            }
+            let pattern = &clause.patterns[0];
+            let guarded = !clause.guards.is_empty();
+            let unreachable = match CoveragePattern::new(pattern, &self.seen) {
+                _ if exhaustive => Some("previous clauses already match every possible value"),
+                CoveragePattern::Atom(atom) if !domain.atoms.contains(&atom) => {
+                    Some("this pattern never matches any possible value")
+                }
+                CoveragePattern::Atom(atom) if covered.contains(&atom) => {
+                    Some("previous clauses already match this value")
+                }
+                CoveragePattern::Atom(atom) => {
+                    if !guarded {
+                        covered.insert(atom);
+                    }
+                    None
+                }
+                CoveragePattern::Any if covered.len() == domain.atoms.len() => {
+                    Some("previous clauses already match every possible value")
+                }
+                CoveragePattern::Any => {
+                    exhaustive = !guarded;
+                    None
+                }
+                CoveragePattern::Never => Some("this pattern never matches any possible value"),
+                CoveragePattern::Unknown => {
+                    uncertain = true;
+                    None
+                }
+            };
+            if let Some(reason) = unreachable {
+                self.reporter.diagnostic(
+                    Diagnostic::warning()
+                        .with_message("unreachable case clause")
+                        .with_labels(vec![
+                            Label::primary(pattern.span().source_id(), pattern.span())
+                                .with_message(reason),
+                            Label::secondary(case.expr.span().source_id(), case.expr.span())
+                                .with_message(format!("this expression is always {}", &values)),
+                        ])
+                        .with_notes(vec![
+                            source_note.clone(),
+                            "help: remove this clause".to_string(),
+                        ]),
+                );
+            }
+        }
+
+        if exhaustive || uncertain || covered.len() == domain.atoms.len() {
+            return;
+        }
+        let missing = domain
+            .atoms
+            .iter()
+            .filter(|atom| !covered.contains(*atom))
+            .collect::<Vec<_>>();
+        let suggestion = missing
+            .iter()
+            .map(|atom| format!("    {} ->\n        ...", format_atom(**atom)))
+            .collect::<Vec<_>>()
+            .join(";\n");
+        self.reporter.diagnostic(
+            Diagnostic::warning()
+                .with_message("non-exhaustive case expression")
+                .with_labels(vec![Label::primary(case.span.source_id(), case.span)
+                    .with_message(format!(
+                        "no clause matches {}",
+                        format_atoms(missing.iter().copied())
+                    ))])
+                .with_notes(vec![
+                    source_note,
+                    format!("help: add clauses for the missing values:\n{}", suggestion),
+                ]),
+        );
+    }
+}
+impl<'a> VisitMut<()> for VerifyCaseCoverageVisitor<'a> {
+    fn visit_mut_var(&mut self, var: &mut Var) -> ControlFlow<()> {
+        self.seen.insert(var.sym());
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_match(&mut self, expr: &mut Match) -> ControlFlow<()> {
+        self.visit_mut_expr(expr.expr.as_mut())?;
+        if let Expr::Var(var) = expr.pattern.as_ref() {
+            if !var.is_wildcard() {
+                if let Some(domain) = self.domain_of(expr.expr.as_ref()) {
+                    self.vars.insert(var.sym(), domain);
+                }
+            }
+        }
+        self.visit_mut_pattern(expr.pattern.as_mut())
+    }
+
+    fn visit_mut_case(&mut self, case: &mut Case) -> ControlFlow<()> {
+        self.visit_mut_expr(case.expr.as_mut())?;
+        if let Some(domain) = self.domain_of(case.expr.as_ref()) {
+            self.verify_case(case, domain);
+        }
+        for clause in case.clauses.iter_mut() {
+            self.visit_mut_clause(clause)?;
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Formats an atom as it would be written in source
+fn format_atom(atom: Symbol) -> String {
+    let name = atom.as_str().get();
+    let mut chars = name.chars();
+    let bare = chars
+        .next()
+        .map(|c| c.is_ascii_lowercase())
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@');
+    if bare {
+        name.to_string()
+    } else {
+        format!("'{}'", name.escape_default())
+    }
+}
+
+/// Formats a set of atoms as a union type, e.g. `ok | error`
+fn format_atoms<'b>(atoms: impl Iterator<Item = &'b Symbol>) -> String {
+    let union = atoms
+        .map(|atom| format_atom(*atom))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    format!("`{}`", union)
+}
@@ -5,7 +5,7 @@ use anyhow::anyhow;
 use firefly_diagnostics::{SourceSpan, Span};
 use firefly_intern::{symbols, Ident};
 use firefly_pass::Pass;
-use firefly_syntax_base::FunctionName;
+use firefly_syntax_base::{BinaryOp, FunctionName};
 
 use crate::ast::*;
 use crate::visit::{self as visit, VisitMut};
@@ -16,6 +16,9 @@ use crate::visit::{self as visit, VisitMut};
 /// Once this pass has run, there should no longer be _any_ record expressions
 /// in the AST, anywhere. If there are, its an invariant violation and should
 /// cause an ICE.
+///
+/// Calls to `is_record/2,3` with a literal record name are also expanded, to
+/// tests of the size and first element of a tuple.
 pub struct ExpandRecords<'m> {
     module: &'m Module,
 }
@@ -98,20 +101,27 @@ impl<'m> VisitMut<anyhow::Error> for ExpandRecordsVisitor<'m> {
 
     fn visit_mut_expr(&mut self, expr: &mut Expr) -> ControlFlow<anyhow::Error> {
         match expr {
-            // Expand calls to record_info/2 if not shadowed
-            Expr::Apply(ref mut apply) if self.expand_record_info => {
+            Expr::Apply(ref mut apply) => {
                 self.visit_mut_apply(apply)?;
-                if let Some(callee) = apply.callee.as_ref().as_atom() {
-                    if callee.name != symbols::RecordInfo || apply.args.len() != 2 {
-                        return ControlFlow::Continue(());
+                // Expand calls to record_info/2 if not shadowed
+                if self.expand_record_info {
+                    if let Some(callee) = apply.callee.as_ref().as_atom() {
+                        if callee.name == symbols::RecordInfo && apply.args.len() == 2 {
+                            let prop = &apply.args[0];
+                            let record_name = &apply.args[1];
+                            if let ControlFlow::Continue(info) =
+                                self.try_expand_record_info(record_name, prop)
+                            {
+                                *expr = info;
+                            }
+                            return ControlFlow::Continue(());
+                        }
                     }
-
-                    let prop = &apply.args[0];
-                    let record_name = &apply.args[1];
-                    if let ControlFlow::Continue(info) =
-                        self.try_expand_record_info(record_name, prop)
-                    {
-                        *expr = info;
+                }
+                // Expand calls to is_record/2,3 with a literal record name
+                if self.is_record_call(apply) {
+                    if let Some(expanded) = self.expand_is_record(apply)? {
+                        *expr = expanded;
                     }
                 }
                 ControlFlow::Continue(())
@@ -136,6 +146,10 @@ impl<'m> VisitMut<anyhow::Error> for ExpandRecordsVisitor<'m> {
                 //   _0 ->
                 //     erlang:error({badrecord, _0})
                 // end
+                //
+                // Or, in a guard, where case expressions are not permitted, to:
+                //
+                // erlang:element(N, is_record(Expr, myrec, Size) andalso Expr)
                 self.visit_mut_record_access(access)?;
                 let expanded = self.expand_access(access)?;
                 *expr = expanded;
@@ -229,7 +243,7 @@ impl<'m> ExpandRecordsVisitor<'m> {
         }
     }
 
-    fn expand_record(&self, record: &Record) -> ControlFlow<anyhow::Error, Expr> {
+    fn expand_record(&mut self, record: &Record) -> ControlFlow<anyhow::Error, Expr> {
         let name = record.name;
         let symbol = name.name;
         let definition = self
@@ -254,21 +268,28 @@ impl<'m> ExpandRecordsVisitor<'m> {
             });
             if let Some(value_expr) = provided {
                 elements.push(value_expr.clone());
+            } else if let Some(box default_expr) = record.default.as_ref() {
+                // Elided fields take the value of `_ = Expr`, if given, in patterns and
+                // constructors
+                elements.push(default_expr.clone());
             } else if self.in_pattern {
                 // This is a pattern, so elided fields need a wildcard pattern
                 elements.push(Expr::Var(
                     Ident::with_empty_span(symbols::Underscore).into(),
                 ));
             } else {
-                // This is a constructor, so use the default expression, then the default initializer, or the atom 'undefined' if neither are present
-                match record.default.as_ref() {
-                    Some(box default_expr) => elements.push(default_expr.clone()),
-                    None => match defined.value.as_ref() {
-                        Some(default_init) => elements.push(default_init.clone()),
-                        None => elements.push(Expr::Literal(Literal::Atom(
-                            Ident::with_empty_span(symbols::Undefined),
-                        ))),
-                    },
+                // This is a constructor, so use the default initializer, or the atom 'undefined' if
+                // not present
+                match defined.value.as_ref() {
+                    Some(default_init) => {
+                        // The initializer may itself contain record expressions, e.g. `b = #b{}`
+                        let mut default_init = default_init.clone();
+                        self.visit_mut_expr(&mut default_init)?;
+                        elements.push(default_init);
+                    }
+                    None => elements.push(Expr::Literal(Literal::Atom(Ident::with_empty_span(
+                        symbols::Undefined,
+                    )))),
                 }
             }
         }
@@ -299,9 +320,10 @@ impl<'m> ExpandRecordsVisitor<'m> {
                     name
                 ))
             })?;
+        // The first element of the tuple is the record name
         ControlFlow::Continue(Expr::Literal(Literal::Integer(
             record_index.span.clone(),
-            (index + 1).into(),
+            (index + 2).into(),
         )))
     }
 
@@ -317,10 +339,10 @@ impl<'m> ExpandRecordsVisitor<'m> {
             .unwrap_or_else(|| {
                 ControlFlow::Break(anyhow!("reference to undefined record '{}'", name))
             })?;
-        let field = definition
+        let position = definition
             .fields
             .iter()
-            .find(|f| f.name == field_name)
+            .position(|f| f.name == field_name)
             .map(ControlFlow::Continue)
             .unwrap_or_else(|| {
                 ControlFlow::Break(anyhow!(
@@ -329,6 +351,24 @@ impl<'m> ExpandRecordsVisitor<'m> {
                     name
                 ))
             })?;
+        let field = &definition.fields[position];
+
+        if self.in_guard {
+            // The guard fails, as `element/2` raises, if the record test is false, as otherwise
+            // `true andalso Expr` is `Expr`
+            let record = record_access.record.as_ref().clone();
+            let size = definition.fields.len() + 1;
+            let test = self.is_record_test(span, record.clone(), name, size);
+            let checked = BinaryExpr::new(span, BinaryOp::AndAlso, test, record);
+            let index = Expr::Literal(Literal::Integer(span, (position + 2).into()));
+            return ControlFlow::Continue(Expr::Apply(Apply::remote(
+                span,
+                symbols::Erlang,
+                symbols::Element,
+                vec![index, Expr::BinaryExpr(checked)],
+            )));
+        }
+
         let field_var = self.next_var(Some(field_name.span));
         let catch_all_var = self.next_var(Some(span));
 
@@ -437,7 +477,7 @@ impl<'m> ExpandRecordsVisitor<'m> {
                         span,
                         FunctionName::new(symbols::Erlang, symbols::Setelement, 3),
                     )));
-                    let index = Expr::Literal(Literal::Integer(span, (position + 1).into()));
+                    let index = Expr::Literal(Literal::Integer(span, (position + 2).into()));
                     let value = update.value.as_ref().unwrap().clone();
                     ControlFlow::Continue(Expr::Apply(Apply {
                         span,
@@ -492,4 +532,115 @@ impl<'m> ExpandRecordsVisitor<'m> {
             ],
         }))
     }
+
+    /// Returns true if `apply` is a call to `erlang:is_record/2,3`, i.e. not to a local function
+    fn is_record_call(&self, apply: &Apply) -> bool {
+        let arity = apply.args.len();
+        if arity != 2 && arity != 3 {
+            return false;
+        }
+        match apply.callee.as_ref() {
+            Expr::Literal(Literal::Atom(callee)) => {
+                let local = FunctionName::new_local(symbols::IsRecord, arity as u8);
+                callee.name == symbols::IsRecord && !self.module.is_local(&local)
+            }
+            Expr::Remote(remote) => match remote.try_eval(arity as u8) {
+                Ok(name) => {
+                    name.module == Some(symbols::Erlang) && name.function == symbols::IsRecord
+                }
+                Err(_) => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Expands `is_record(Expr, myrec)` and `is_record(Expr, myrec, Size)` to tuple operations
+    ///
+    /// Calls with a record name or size which is not a literal are left to the runtime.
+    fn expand_is_record(&self, apply: &Apply) -> ControlFlow<anyhow::Error, Option<Expr>> {
+        let span = apply.span;
+        let Some(name) = apply.args[1].as_atom() else { return ControlFlow::Continue(None); };
+        let size = match apply.args.get(2) {
+            None => {
+                let definition = self
+                    .module
+                    .record(name.name)
+                    .map(ControlFlow::Continue)
+                    .unwrap_or_else(|| {
+                        ControlFlow::Break(anyhow!("reference to undefined record '{}'", name))
+                    })?;
+                definition.fields.len() + 1
+            }
+            Some(Expr::Literal(Literal::Integer(_, size))) => match size.to_usize() {
+                Some(size) if size > 0 => size,
+                // No tuple can be a record of this size
+                _ => {
+                    return ControlFlow::Continue(Some(Expr::Literal(Literal::Atom(Ident::new(
+                        symbols::False,
+                        span,
+                    )))))
+                }
+            },
+            Some(_) => return ControlFlow::Continue(None),
+        };
+
+        let term = apply.args[0].clone();
+        if self.in_guard {
+            return ControlFlow::Continue(Some(self.is_record_test(span, term, name, size)));
+        }
+
+        // Outside of guards the term is only evaluated once, by matching it against the record
+        let mut elements = Vec::with_capacity(size);
+        elements.push(Expr::Literal(Literal::Atom(name)));
+        elements.resize(
+            size,
+            Expr::Var(Ident::with_empty_span(symbols::Underscore).into()),
+        );
+        let boolean = |value| Expr::Literal(Literal::Atom(Ident::new(value, span)));
+        ControlFlow::Continue(Some(Expr::Case(Case {
+            span,
+            expr: Box::new(term),
+            clauses: vec![
+                Clause {
+                    span,
+                    patterns: vec![Expr::Tuple(Tuple { span, elements })],
+                    guards: vec![],
+                    body: vec![boolean(symbols::True)],
+                    compiler_generated: true,
+                },
+                Clause {
+                    span,
+                    patterns: vec![Expr::Var(
+                        Ident::with_empty_span(symbols::Underscore).into(),
+                    )],
+                    guards: vec![],
+                    body: vec![boolean(symbols::False)],
+                    compiler_generated: true,
+                },
+            ],
+        })))
+    }
+
+    /// Returns a guard expression which tests that `term` is a record `name` of `size` elements,
+    /// i.e. `is_tuple(Term) andalso tuple_size(Term) =:= Size andalso element(1, Term) =:= Name`
+    fn is_record_test(&self, span: SourceSpan, term: Expr, name: Ident, size: usize) -> Expr {
+        let bif =
+            |function, args| Expr::Apply(Apply::remote(span, symbols::Erlang, function, args));
+        let strict_equal =
+            |lhs, rhs| Expr::BinaryExpr(BinaryExpr::new(span, BinaryOp::StrictEqual, lhs, rhs));
+        let and_also =
+            |lhs, rhs| Expr::BinaryExpr(BinaryExpr::new(span, BinaryOp::AndAlso, lhs, rhs));
+
+        let is_tuple = bif(symbols::IsTuple, vec![term.clone()]);
+        let size_matches = strict_equal(
+            bif(symbols::TupleSize, vec![term.clone()]),
+            Expr::Literal(Literal::Integer(span, size.into())),
+        );
+        let first = Expr::Literal(Literal::Integer(span, 1.into()));
+        let name_matches = strict_equal(
+            bif(symbols::Element, vec![first, term]),
+            Expr::Literal(Literal::Atom(name)),
+        );
+        and_also(is_tuple, and_also(size_matches, name_matches))
+    }
 }
@@ -0,0 +1,957 @@
+///! Purpose : Transform an Erlang module into the Erlang Abstract Format
+///!
+///! The abstract format is the representation of Erlang source used by
+///! `erl_parse`, and consumed by tools which operate on source code, e.g.
+///! `cover`, `debugger`, or the shell's `rr/1`. It is what `erlc` stores
+///! in the `debug_info` chunk of a BEAM file.
+///!
+///! Each form is represented as an `ast::Literal`, so that it may be written
+///! out as text in the same format as `.abstr` inputs, or embedded as a
+///! constant term in the compiled module.
+///!
+///! Annotations are always just the line number of the construct, or 0 when
+///! it has no source location, i.e. it was generated by the compiler.
+///!
+///! NOTE: The `compile` and `deprecated` attributes are not represented, as
+///! the module retains only their interpretation, not the original terms.
+use std::collections::BTreeMap;
+
+use firefly_binary::{BinaryEntrySpecifier, Bitstring, Endianness};
+use firefly_diagnostics::*;
+use firefly_intern::{Ident, Symbol};
+use firefly_number::Integer;
+use firefly_syntax_base::FunctionName;
+use firefly_util::emit::Emit;
+
+use crate::ast::{self, Expr, Literal, Name, Type};
+use crate::lexer::DelayedSubstitution;
+
+/// The forms of a module in the Erlang Abstract Format
+#[derive(Debug, Clone)]
+pub struct AbstractCode {
+    pub forms: Vec<Literal>,
+}
+impl AbstractCode {
+    /// Translates the given module, using `codemap` to resolve source locations to line numbers
+    ///
+    /// This is expected to be called on the module as produced by the parser, i.e. prior to
+    /// semantic analysis, which injects pseudo-locals and desugars various constructs.
+    pub fn new(module: &ast::Module, codemap: &CodeMap) -> Self {
+        let builder = Builder {
+            codemap,
+            module: module.name,
+            function: None,
+        };
+        Self {
+            forms: builder.forms(module),
+        }
+    }
+
+    /// Returns the term `erlc` stores in the `debug_info` chunk for these forms, i.e.
+    /// `{debug_info_v1, erl_abstract_code, {Forms, CompileOptions}}`
+    pub fn debug_info(&self) -> Literal {
+        tuple(vec![
+            tag("debug_info_v1"),
+            tag("erl_abstract_code"),
+            tuple(vec![list(self.forms.clone()), list(vec![])]),
+        ])
+    }
+}
+impl Emit for AbstractCode {
+    fn file_type(&self) -> Option<&'static str> {
+        Some("abstr")
+    }
+
+    fn emit(&self, f: &mut std::fs::File) -> anyhow::Result<()> {
+        use std::io::Write;
+        for form in self.forms.iter() {
+            writeln!(f, "{}.", form)?;
+        }
+        Ok(())
+    }
+}
+
+struct Builder<'a> {
+    codemap: &'a CodeMap,
+    module: Ident,
+    // The name and arity of the function currently being translated, used for `?FUNCTION_NAME`
+    function: Option<(Ident, u8)>,
+}
+impl<'a> Builder<'a> {
+    fn forms(mut self, module: &ast::Module) -> Vec<Literal> {
+        let mut forms = vec![];
+
+        let file = self
+            .codemap
+            .name_for_span(module.span)
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+        forms.push(self.attribute(
+            SourceSpan::UNKNOWN,
+            "file",
+            tuple(vec![string(&file), int(1)]),
+        ));
+        forms.push(self.attribute(module.name.span, "module", atom(module.name.name)));
+
+        let mut behaviours = module.behaviours.iter().copied().collect::<Vec<_>>();
+        behaviours.sort_by_key(|b| b.span.start_index());
+        for behaviour in behaviours {
+            forms.push(self.attribute(behaviour.span, "behaviour", atom(behaviour.name)));
+        }
+
+        if !module.exports.is_empty() {
+            let exports = module.exports.iter().map(|e| e.item).collect::<Vec<_>>();
+            forms.push(self.attribute(module.span, "export", name_arity_list(exports)));
+        }
+
+        let mut imports: BTreeMap<Symbol, Vec<FunctionName>> = BTreeMap::new();
+        for sig in module.imports.values() {
+            let name = sig.mfa();
+            imports
+                .entry(name.module.unwrap())
+                .or_default()
+                .push(name.to_local());
+        }
+        for (imported_from, names) in imports {
+            let value = tuple(vec![atom(imported_from), name_arity_list(names)]);
+            forms.push(self.attribute(module.span, "import", value));
+        }
+
+        if !module.exported_types.is_empty() {
+            let exports = module
+                .exported_types
+                .iter()
+                .map(|e| e.item)
+                .collect::<Vec<_>>();
+            forms.push(self.attribute(module.span, "export_type", name_arity_list(exports)));
+        }
+
+        if let Some(on_load) = module.on_load.as_ref() {
+            let value = tuple(vec![atom(on_load.function), int(on_load.arity)]);
+            forms.push(self.attribute(on_load.span(), "on_load", value));
+        }
+
+        if !module.nifs.is_empty() {
+            let nifs = module.nifs.iter().map(|n| n.item).collect::<Vec<_>>();
+            forms.push(self.attribute(module.span, "nifs", name_arity_list(nifs)));
+        }
+
+        if let Some(vsn) = module.vsn.as_ref() {
+            forms.push(self.attribute(vsn.span(), "vsn", vsn.clone()));
+        }
+        if let Some(author) = module.author.as_ref() {
+            forms.push(self.attribute(author.span(), "author", author.clone()));
+        }
+        let mut attributes = module.attributes.iter().collect::<Vec<_>>();
+        attributes.sort_by_key(|(name, _)| name.span.start_index());
+        for (name, value) in attributes {
+            let form = self.node("attribute", name.span, vec![atom(name.name), value.clone()]);
+            forms.push(form);
+        }
+
+        let mut records = module.records.values().collect::<Vec<_>>();
+        records.sort_by_key(|r| r.span.start_index());
+        for record in records {
+            let fields = record
+                .fields
+                .iter()
+                .map(|field| self.record_field_def(field))
+                .collect();
+            let value = tuple(vec![atom(record.name.name), list(fields)]);
+            forms.push(self.attribute(record.span, "record", value));
+        }
+
+        let mut types = module.types.values().collect::<Vec<_>>();
+        types.sort_by_key(|t| t.span.start_index());
+        for def in types {
+            let kind = if def.opaque { "opaque" } else { "type" };
+            let params = def.params.iter().map(|p| self.name(*p)).collect();
+            let value = tuple(vec![atom(def.name.name), self.ty(&def.ty), list(params)]);
+            forms.push(self.attribute(def.span, kind, value));
+        }
+
+        let mut specs = module.specs.iter().collect::<Vec<_>>();
+        specs.sort_by_key(|(_, spec)| spec.span.start_index());
+        for (name, spec) in specs {
+            let sigs = spec.sigs.iter().map(|sig| self.type_sig(sig)).collect();
+            let value = tuple(vec![
+                tuple(vec![atom(name.function), int(name.arity)]),
+                list(sigs),
+            ]);
+            forms.push(self.attribute(spec.span, "spec", value));
+        }
+
+        let mut callbacks = module.callbacks.iter().collect::<Vec<_>>();
+        callbacks.sort_by_key(|(_, cb)| cb.span.start_index());
+        let mut optional_callbacks = vec![];
+        for (name, cb) in callbacks {
+            let sigs = cb.sigs.iter().map(|sig| self.type_sig(sig)).collect();
+            let value = tuple(vec![
+                tuple(vec![atom(name.function), int(name.arity)]),
+                list(sigs),
+            ]);
+            forms.push(self.attribute(cb.span, "callback", value));
+            if cb.optional {
+                optional_callbacks.push(name.to_local());
+            }
+        }
+        if !optional_callbacks.is_empty() {
+            let value = name_arity_list(optional_callbacks);
+            forms.push(self.attribute(module.span, "optional_callbacks", value));
+        }
+
+        let mut functions = module.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|f| f.span.start_index());
+        for function in functions {
+            forms.push(self.function(function));
+        }
+
+        let eof = self
+            .codemap
+            .location(module.span.source_id(), module.span.end_index())
+            .map(|loc| loc.line.number().to_usize() as i64)
+            .unwrap_or(0);
+        forms.push(tuple(vec![tag("eof"), int(eof)]));
+
+        forms
+    }
+
+    /// Returns the annotation for a construct at `span`, i.e. its line number
+    fn anno(&self, span: SourceSpan) -> Literal {
+        if span.is_unknown() {
+            return int(0);
+        }
+        match self.codemap.location_for_span(span) {
+            Ok(loc) => int(loc.line.number().to_usize() as i64),
+            Err(_) => int(0),
+        }
+    }
+
+    /// Constructs a node of the form `{Tag, Anno, ..Rest}`
+    fn node(&self, tag_name: &str, span: SourceSpan, mut rest: Vec<Literal>) -> Literal {
+        let mut elements = Vec::with_capacity(rest.len() + 2);
+        elements.push(tag(tag_name));
+        elements.push(self.anno(span));
+        elements.append(&mut rest);
+        tuple(elements)
+    }
+
+    fn attribute(&self, span: SourceSpan, name: &str, value: Literal) -> Literal {
+        self.node("attribute", span, vec![tag(name), value])
+    }
+
+    fn atom(&self, name: Ident) -> Literal {
+        self.node("atom", name.span, vec![atom(name.name)])
+    }
+
+    fn var(&self, name: Ident) -> Literal {
+        self.node("var", name.span, vec![atom(name.name)])
+    }
+
+    fn name(&self, name: Name) -> Literal {
+        match name {
+            Name::Atom(name) => self.atom(name),
+            Name::Var(name) => self.var(name),
+        }
+    }
+
+    fn function(&mut self, function: &ast::Function) -> Literal {
+        self.function = Some((function.name, function.arity));
+        let clauses = function
+            .clauses
+            .iter()
+            .map(|(_, clause)| self.clause(clause))
+            .collect();
+        self.function = None;
+        self.node(
+            "function",
+            function.span,
+            vec![atom(function.name.name), int(function.arity), list(clauses)],
+        )
+    }
+
+    fn clause(&self, clause: &ast::Clause) -> Literal {
+        let patterns = self.exprs(&clause.patterns);
+        self.clause_with_patterns(clause, patterns)
+    }
+
+    fn clause_with_patterns(&self, clause: &ast::Clause, patterns: Literal) -> Literal {
+        let guards = clause
+            .guards
+            .iter()
+            .map(|guard| self.exprs(&guard.conditions))
+            .collect();
+        self.node(
+            "clause",
+            clause.span,
+            vec![patterns, list(guards), self.exprs(&clause.body)],
+        )
+    }
+
+    fn clauses(&self, clauses: &[ast::Clause]) -> Literal {
+        list(clauses.iter().map(|clause| self.clause(clause)).collect())
+    }
+
+    fn exprs(&self, exprs: &[Expr]) -> Literal {
+        list(exprs.iter().map(|expr| self.expr(expr)).collect())
+    }
+
+    fn expr(&self, expr: &Expr) -> Literal {
+        match expr {
+            Expr::Var(ast::Var(name)) => self.var(*name),
+            Expr::Literal(lit) => self.literal(lit),
+            Expr::FunctionVar(name) => self.function_var(name),
+            Expr::DelayedSubstitution(span, sub) => self.substitution(*span, *sub),
+            Expr::Cons(cons) => self.node(
+                "cons",
+                cons.span,
+                vec![self.expr(&cons.head), self.expr(&cons.tail)],
+            ),
+            Expr::Tuple(tuple) => self.node("tuple", tuple.span, vec![self.exprs(&tuple.elements)]),
+            Expr::Map(map) => self.node("map", map.span, vec![self.map_fields(&map.fields)]),
+            Expr::MapUpdate(update) => self.node(
+                "map",
+                update.span,
+                vec![self.expr(&update.map), self.map_fields(&update.updates)],
+            ),
+            Expr::Binary(bin) => {
+                let elements = bin
+                    .elements
+                    .iter()
+                    .map(|element| self.bin_element(element))
+                    .collect();
+                self.node("bin", bin.span, vec![list(elements)])
+            }
+            Expr::Record(record) => {
+                let mut fields = record
+                    .fields
+                    .iter()
+                    .map(|field| self.record_field(field))
+                    .collect::<Vec<_>>();
+                if let Some(default) = record.default.as_deref() {
+                    let key = self.node("var", record.span, vec![tag("_")]);
+                    let field =
+                        self.node("record_field", record.span, vec![key, self.expr(default)]);
+                    fields.push(field);
+                }
+                self.node(
+                    "record",
+                    record.span,
+                    vec![atom(record.name.name), list(fields)],
+                )
+            }
+            Expr::RecordAccess(access) => self.node(
+                "record_field",
+                access.span,
+                vec![
+                    self.expr(&access.record),
+                    atom(access.name.name),
+                    self.atom(access.field),
+                ],
+            ),
+            Expr::RecordIndex(index) => self.node(
+                "record_index",
+                index.span,
+                vec![atom(index.name.name), self.atom(index.field)],
+            ),
+            Expr::RecordUpdate(update) => {
+                let fields = update
+                    .updates
+                    .iter()
+                    .map(|field| self.record_field(field))
+                    .collect();
+                self.node(
+                    "record",
+                    update.span,
+                    vec![
+                        self.expr(&update.record),
+                        atom(update.name.name),
+                        list(fields),
+                    ],
+                )
+            }
+            Expr::ListComprehension(lc) => self.node(
+                "lc",
+                lc.span,
+                vec![self.expr(&lc.body), self.exprs(&lc.qualifiers)],
+            ),
+            Expr::BinaryComprehension(bc) => self.node(
+                "bc",
+                bc.span,
+                vec![self.expr(&bc.body), self.exprs(&bc.qualifiers)],
+            ),
+            Expr::MapComprehension(mc) => {
+                let body = self.node(
+                    "map_field_assoc",
+                    mc.span,
+                    vec![self.expr(&mc.key), self.expr(&mc.value)],
+                );
+                self.node("mc", mc.span, vec![body, self.exprs(&mc.qualifiers)])
+            }
+            Expr::Generator(gen) => match gen.ty {
+                ast::GeneratorType::Default => self.node(
+                    "generate",
+                    gen.span,
+                    vec![self.expr(&gen.pattern), self.expr(&gen.expr)],
+                ),
+                ast::GeneratorType::Bitstring => self.node(
+                    "b_generate",
+                    gen.span,
+                    vec![self.expr(&gen.pattern), self.expr(&gen.expr)],
+                ),
+                ast::GeneratorType::Map => {
+                    // The parser represents the `K := V` pattern of a map generator as `{K, V}`
+                    let pattern = match gen.pattern.as_ref() {
+                        Expr::Tuple(ast::Tuple { elements, .. }) if elements.len() == 2 => self
+                            .node(
+                                "map_field_exact",
+                                gen.span,
+                                vec![self.expr(&elements[0]), self.expr(&elements[1])],
+                            ),
+                        pattern => self.expr(pattern),
+                    };
+                    self.node("m_generate", gen.span, vec![pattern, self.expr(&gen.expr)])
+                }
+            },
+            Expr::Begin(block) => self.node("block", block.span, vec![self.exprs(&block.body)]),
+            Expr::Apply(apply) => self.node(
+                "call",
+                apply.span,
+                vec![self.callee(&apply.callee), self.exprs(&apply.args)],
+            ),
+            Expr::Remote(remote) => self.node(
+                "remote",
+                remote.span,
+                vec![self.expr(&remote.module), self.expr(&remote.function)],
+            ),
+            Expr::BinaryExpr(op) => self.node(
+                "op",
+                op.span,
+                vec![
+                    atom(op.op.to_symbol()),
+                    self.expr(&op.lhs),
+                    self.expr(&op.rhs),
+                ],
+            ),
+            Expr::UnaryExpr(op) => self.node(
+                "op",
+                op.span,
+                vec![atom(op.op.to_symbol()), self.expr(&op.operand)],
+            ),
+            Expr::Match(m) => self.node(
+                "match",
+                m.span,
+                vec![self.expr(&m.pattern), self.expr(&m.expr)],
+            ),
+            Expr::If(expr) => {
+                // The parser gives each clause of an `if` a wildcard pattern, which isn't present
+                // in the source
+                let clauses = expr
+                    .clauses
+                    .iter()
+                    .map(|clause| self.clause_with_patterns(clause, list(vec![])))
+                    .collect();
+                self.node("if", expr.span, vec![list(clauses)])
+            }
+            Expr::Catch(expr) => self.node("catch", expr.span, vec![self.expr(&expr.expr)]),
+            Expr::Case(expr) => self.node(
+                "case",
+                expr.span,
+                vec![self.expr(&expr.expr), self.clauses(&expr.clauses)],
+            ),
+            Expr::Receive(expr) => {
+                let clauses = self.clauses(expr.clauses.as_deref().unwrap_or_default());
+                match expr.after.as_ref() {
+                    None => self.node("receive", expr.span, vec![clauses]),
+                    Some(after) => self.node(
+                        "receive",
+                        expr.span,
+                        vec![clauses, self.expr(&after.timeout), self.exprs(&after.body)],
+                    ),
+                }
+            }
+            Expr::Try(expr) => {
+                let clauses = self.clauses(expr.clauses.as_deref().unwrap_or_default());
+                // Catch clauses are represented in the abstract format with a single `{Class,
+                // Reason, Stacktrace}` pattern
+                let catch_clauses = expr
+                    .catch_clauses
+                    .as_deref()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|clause| {
+                        let pattern =
+                            self.node("tuple", clause.span, vec![self.exprs(&clause.patterns)]);
+                        self.clause_with_patterns(clause, list(vec![pattern]))
+                    })
+                    .collect();
+                let after = self.exprs(expr.after.as_deref().unwrap_or_default());
+                self.node(
+                    "try",
+                    expr.span,
+                    vec![self.exprs(&expr.exprs), clauses, list(catch_clauses), after],
+                )
+            }
+            Expr::Maybe(expr) => match expr.else_clauses.as_deref() {
+                None => self.node("maybe", expr.span, vec![self.exprs(&expr.body)]),
+                Some(clauses) => {
+                    let else_clauses = self.node("else", expr.span, vec![self.clauses(clauses)]);
+                    self.node(
+                        "maybe",
+                        expr.span,
+                        vec![self.exprs(&expr.body), else_clauses],
+                    )
+                }
+            },
+            Expr::MaybeMatch(m) => self.node(
+                "maybe_match",
+                m.span,
+                vec![self.expr(&m.pattern), self.expr(&m.expr)],
+            ),
+            Expr::Fun(ast::Fun::Anonymous(fun)) => {
+                let clauses = tuple(vec![tag("clauses"), self.clauses(&fun.clauses)]);
+                self.node("fun", fun.span, vec![clauses])
+            }
+            Expr::Fun(ast::Fun::Recursive(fun)) => {
+                let clauses = fun
+                    .clauses
+                    .iter()
+                    .map(|(_, clause)| self.clause(clause))
+                    .collect();
+                self.node(
+                    "named_fun",
+                    fun.span,
+                    vec![atom(fun.self_name.name), list(clauses)],
+                )
+            }
+            // Protect is only introduced by the compiler, and has no equivalent in the abstract
+            // format
+            Expr::Protect(protect) => self.expr(&protect.body),
+        }
+    }
+
+    fn literal(&self, lit: &Literal) -> Literal {
+        match lit {
+            Literal::Atom(name) => self.atom(*name),
+            Literal::String(s) => self.node("string", s.span, vec![lit.clone()]),
+            Literal::Char(span, _) => self.node("char", *span, vec![lit.clone()]),
+            Literal::Integer(span, _) => self.node("integer", *span, vec![lit.clone()]),
+            Literal::Float(span, _) => self.node("float", *span, vec![lit.clone()]),
+            Literal::Nil(span) => self.node("nil", *span, vec![]),
+            Literal::Cons(span, head, tail) => {
+                self.node("cons", *span, vec![self.literal(head), self.literal(tail)])
+            }
+            Literal::Tuple(span, elements) => {
+                let elements = elements.iter().map(|e| self.literal(e)).collect();
+                self.node("tuple", *span, vec![list(elements)])
+            }
+            Literal::Map(span, map) => {
+                let fields = map
+                    .iter()
+                    .map(|(k, v)| {
+                        self.node(
+                            "map_field_assoc",
+                            *span,
+                            vec![self.literal(k), self.literal(v)],
+                        )
+                    })
+                    .collect();
+                self.node("map", *span, vec![list(fields)])
+            }
+            Literal::Binary(span, bits) => {
+                // Binaries are represented as a sequence of byte-sized segments, with any
+                // trailing bits in a final segment of the appropriate size
+                let trailing = bits.bit_size() % 8;
+                let len = bits.byte_size();
+                let elements = bits
+                    .bytes()
+                    .enumerate()
+                    .map(|(i, byte)| {
+                        let (value, size) = if i + 1 == len && trailing > 0 {
+                            (byte >> (8 - trailing), trailing)
+                        } else {
+                            (byte, 8)
+                        };
+                        let value = self.node("integer", *span, vec![int(value)]);
+                        let size = self.node("integer", *span, vec![int(size as i64)]);
+                        self.node("bin_element", *span, vec![value, size, tag("default")])
+                    })
+                    .collect();
+                self.node("bin", *span, vec![list(elements)])
+            }
+        }
+    }
+
+    fn substitution(&self, span: SourceSpan, sub: DelayedSubstitution) -> Literal {
+        match sub {
+            DelayedSubstitution::Module => self.node("atom", span, vec![atom(self.module.name)]),
+            DelayedSubstitution::ModuleString => {
+                self.node("string", span, vec![string(self.module.as_str().get())])
+            }
+            DelayedSubstitution::FunctionName => {
+                let name = self
+                    .function
+                    .map(|(name, _)| atom(name.name))
+                    .unwrap_or_else(|| tag("undefined"));
+                self.node("atom", span, vec![name])
+            }
+            DelayedSubstitution::FunctionArity => {
+                let arity = self.function.map(|(_, arity)| arity).unwrap_or_default();
+                self.node("integer", span, vec![int(arity)])
+            }
+            DelayedSubstitution::File => {
+                let file = self
+                    .codemap
+                    .name_for_span(span)
+                    .map(|name| name.to_string())
+                    .unwrap_or_default();
+                self.node("string", span, vec![string(&file)])
+            }
+            DelayedSubstitution::Line => {
+                let line = self.anno(span);
+                self.node("integer", span, vec![line])
+            }
+        }
+    }
+
+    /// Translates a reference to a function, i.e. `fun F/A` or `fun M:F/A`
+    fn function_var(&self, name: &ast::FunctionVar) -> Literal {
+        let span = name.span();
+        match name {
+            ast::FunctionVar::Resolved(name) | ast::FunctionVar::PartiallyResolved(name) => {
+                match name.module {
+                    None => {
+                        let function =
+                            tuple(vec![tag("function"), atom(name.function), int(name.arity)]);
+                        self.node("fun", span, vec![function])
+                    }
+                    Some(module) => {
+                        let function = tuple(vec![
+                            tag("function"),
+                            self.node("atom", span, vec![atom(module)]),
+                            self.node("atom", span, vec![atom(name.function)]),
+                            self.node("integer", span, vec![int(name.arity)]),
+                        ]);
+                        self.node("fun", span, vec![function])
+                    }
+                }
+            }
+            ast::FunctionVar::Unresolved(name) => {
+                let module = name
+                    .module
+                    .map(|m| self.name(m))
+                    .unwrap_or_else(|| self.node("atom", span, vec![atom(self.module.name)]));
+                let arity = match name.arity {
+                    ast::Arity::Int(i) => self.node("integer", span, vec![int(i)]),
+                    ast::Arity::Var(v) => self.var(v),
+                };
+                let function = tuple(vec![
+                    tag("function"),
+                    module,
+                    self.name(name.function),
+                    arity,
+                ]);
+                self.node("fun", span, vec![function])
+            }
+        }
+    }
+
+    /// Translates the callee of a function application, which unlike a standalone function
+    /// reference, is represented by just the name of the function, or a remote expression
+    fn callee(&self, callee: &Expr) -> Literal {
+        let span = callee.span();
+        match callee {
+            Expr::FunctionVar(
+                ast::FunctionVar::Resolved(name) | ast::FunctionVar::PartiallyResolved(name),
+            ) => match name.module {
+                None => self.node("atom", span, vec![atom(name.function)]),
+                Some(module) => self.node(
+                    "remote",
+                    span,
+                    vec![
+                        self.node("atom", span, vec![atom(module)]),
+                        self.node("atom", span, vec![atom(name.function)]),
+                    ],
+                ),
+            },
+            Expr::FunctionVar(ast::FunctionVar::Unresolved(name)) => match name.module {
+                None => self.name(name.function),
+                Some(module) => self.node(
+                    "remote",
+                    span,
+                    vec![self.name(module), self.name(name.function)],
+                ),
+            },
+            callee => self.expr(callee),
+        }
+    }
+
+    fn map_fields(&self, fields: &[ast::MapField]) -> Literal {
+        let fields = fields
+            .iter()
+            .map(|field| match field {
+                ast::MapField::Assoc { span, key, value } => self.node(
+                    "map_field_assoc",
+                    *span,
+                    vec![self.expr(key), self.expr(value)],
+                ),
+                ast::MapField::Exact { span, key, value } => self.node(
+                    "map_field_exact",
+                    *span,
+                    vec![self.expr(key), self.expr(value)],
+                ),
+            })
+            .collect();
+        list(fields)
+    }
+
+    fn bin_element(&self, element: &ast::BinaryElement) -> Literal {
+        let size = element
+            .bit_size
+            .as_ref()
+            .map(|size| self.expr(size))
+            .unwrap_or_else(|| tag("default"));
+        let specifiers = element
+            .specifier
+            .map(type_specifiers)
+            .unwrap_or_else(|| tag("default"));
+        self.node(
+            "bin_element",
+            element.span,
+            vec![self.expr(&element.bit_expr), size, specifiers],
+        )
+    }
+
+    /// Translates a field in a record expression, i.e. `field = Value`
+    fn record_field(&self, field: &ast::RecordField) -> Literal {
+        let value = field
+            .value
+            .as_ref()
+            .map(|value| self.expr(value))
+            .unwrap_or_else(|| self.node("atom", field.span, vec![tag("undefined")]));
+        self.node(
+            "record_field",
+            field.span,
+            vec![self.atom(field.name), value],
+        )
+    }
+
+    /// Translates a field in a record definition, which may have a default value and type
+    fn record_field_def(&self, field: &ast::RecordField) -> Literal {
+        let mut elements = vec![self.atom(field.name)];
+        if let Some(value) = field.value.as_ref() {
+            elements.push(self.expr(value));
+        }
+        let record_field = self.node("record_field", field.span, elements);
+        match field.ty.as_ref() {
+            None => record_field,
+            Some(ty) => tuple(vec![tag("typed_record_field"), record_field, self.ty(ty)]),
+        }
+    }
+
+    fn type_sig(&self, sig: &ast::TypeSig) -> Literal {
+        let params = sig.params.iter().map(|p| self.ty(p)).collect();
+        let fun = self.node(
+            "type",
+            sig.span,
+            vec![
+                tag("fun"),
+                list(vec![
+                    self.node("type", sig.span, vec![tag("product"), list(params)]),
+                    self.ty(&sig.ret),
+                ]),
+            ],
+        );
+        match sig.guards.as_deref() {
+            None | Some([]) => fun,
+            Some(guards) => {
+                let constraints = guards
+                    .iter()
+                    .map(|guard| {
+                        let is_subtype = self.node("atom", guard.span, vec![tag("is_subtype")]);
+                        let args = list(vec![self.name(guard.var), self.ty(&guard.ty)]);
+                        self.node(
+                            "type",
+                            guard.span,
+                            vec![tag("constraint"), list(vec![is_subtype, args])],
+                        )
+                    })
+                    .collect();
+                self.node(
+                    "type",
+                    sig.span,
+                    vec![tag("bounded_fun"), list(vec![fun, list(constraints)])],
+                )
+            }
+        }
+    }
+
+    fn types(&self, types: &[Type]) -> Literal {
+        list(types.iter().map(|ty| self.ty(ty)).collect())
+    }
+
+    fn ty(&self, ty: &Type) -> Literal {
+        let span = ty.span();
+        match ty {
+            Type::Name(name) => self.name(*name),
+            Type::Annotated { name, ty, .. } => self.node(
+                "ann_type",
+                span,
+                vec![list(vec![self.name(*name), self.ty(ty)])],
+            ),
+            Type::Union { types, .. } => self.builtin_type(span, "union", self.types(types)),
+            Type::Range { start, end, .. } => {
+                self.builtin_type(span, "range", list(vec![self.ty(start), self.ty(end)]))
+            }
+            Type::BinaryOp { lhs, op, rhs, .. } => self.node(
+                "op",
+                span,
+                vec![atom(op.to_symbol()), self.ty(lhs), self.ty(rhs)],
+            ),
+            Type::UnaryOp { op, rhs, .. } => {
+                self.node("op", span, vec![atom(op.to_symbol()), self.ty(rhs)])
+            }
+            Type::Generic { fun, params, .. } => {
+                match (fun.as_str().get(), params.is_empty()) {
+                    // These are the only builtin types which are represented specially when
+                    // unparameterized
+                    ("map" | "tuple", true) => {
+                        self.node("type", span, vec![atom(fun.name), tag("any")])
+                    }
+                    _ if ty.is_builtin_type() => {
+                        self.node("type", span, vec![atom(fun.name), self.types(params)])
+                    }
+                    _ => self.node("user_type", span, vec![atom(fun.name), self.types(params)]),
+                }
+            }
+            Type::Remote {
+                module, fun, args, ..
+            } => self.node(
+                "remote_type",
+                span,
+                vec![list(vec![
+                    self.atom(*module),
+                    self.atom(*fun),
+                    self.types(args),
+                ])],
+            ),
+            Type::Nil(_) => self.builtin_type(span, "nil", list(vec![])),
+            Type::List(_, ty) => self.builtin_type(span, "list", list(vec![self.ty(ty)])),
+            Type::NonEmptyList(_, ty) => {
+                self.builtin_type(span, "nonempty_list", list(vec![self.ty(ty)]))
+            }
+            Type::Map(_, fields) => self.builtin_type(span, "map", self.types(fields)),
+            Type::Tuple(_, elements) => self.builtin_type(span, "tuple", self.types(elements)),
+            Type::Record(_, name, fields) => {
+                let mut elements = vec![self.atom(*name)];
+                elements.extend(fields.iter().map(|field| self.ty(field)));
+                self.builtin_type(span, "record", list(elements))
+            }
+            Type::Binary(_, m, n) => {
+                self.builtin_type(span, "binary", list(vec![self.ty(m), self.ty(n)]))
+            }
+            Type::Integer(_, i) => self.node("integer", span, vec![int(i.clone())]),
+            Type::Char(_, c) => self.node("char", span, vec![Literal::Char(span, *c)]),
+            Type::AnyFun { ret: None, .. } => self.builtin_type(span, "fun", list(vec![])),
+            Type::AnyFun { ret: Some(ret), .. } => {
+                let any = self.node("type", span, vec![tag("any")]);
+                self.builtin_type(span, "fun", list(vec![any, self.ty(ret)]))
+            }
+            Type::Fun { params, ret, .. } => {
+                let params = self.builtin_type(span, "product", self.types(params));
+                self.builtin_type(span, "fun", list(vec![params, self.ty(ret)]))
+            }
+            Type::KeyValuePair(_, key, value) => self.builtin_type(
+                span,
+                "map_field_assoc",
+                list(vec![self.ty(key), self.ty(value)]),
+            ),
+            Type::Field(_, name, ty) => self.builtin_type(
+                span,
+                "field_type",
+                list(vec![self.atom(*name), self.ty(ty)]),
+            ),
+        }
+    }
+
+    fn builtin_type(&self, span: SourceSpan, name: &str, params: Literal) -> Literal {
+        self.node("type", span, vec![tag(name), params])
+    }
+}
+
+/// Translates a binary segment type specifier to its list form, e.g. `[integer, signed, little,
+/// {unit, 8}]`
+fn type_specifiers(spec: BinaryEntrySpecifier) -> Literal {
+    let (ty, signed, endianness, unit, default_unit) = match spec {
+        BinaryEntrySpecifier::Integer {
+            signed,
+            endianness,
+            unit,
+        } => ("integer", signed, endianness, Some(unit), 1),
+        BinaryEntrySpecifier::Float { endianness, unit } => {
+            ("float", false, endianness, Some(unit), 1)
+        }
+        BinaryEntrySpecifier::Binary { unit } => ("binary", false, Endianness::Big, Some(unit), 8),
+        BinaryEntrySpecifier::Utf8 => ("utf8", false, Endianness::Big, None, 1),
+        BinaryEntrySpecifier::Utf16 { endianness } => ("utf16", false, endianness, None, 1),
+        BinaryEntrySpecifier::Utf32 { endianness } => ("utf32", false, endianness, None, 1),
+    };
+    let mut specifiers = vec![tag(ty)];
+    if signed {
+        specifiers.push(tag("signed"));
+    }
+    if endianness != Endianness::Big {
+        specifiers.push(tag(&endianness.to_string()));
+    }
+    match unit {
+        Some(unit) if unit != default_unit => {
+            specifiers.push(tuple(vec![tag("unit"), int(unit)]));
+        }
+        _ => (),
+    }
+    list(specifiers)
+}
+
+fn name_arity_list(mut names: Vec<FunctionName>) -> Literal {
+    names.sort();
+    list(
+        names
+            .iter()
+            .map(|name| tuple(vec![atom(name.function), int(name.arity)]))
+            .collect(),
+    )
+}
+
+#[inline]
+fn atom(name: Symbol) -> Literal {
+    Literal::Atom(Ident::with_empty_span(name))
+}
+
+#[inline]
+fn tag(name: &str) -> Literal {
+    atom(Symbol::intern(name))
+}
+
+#[inline]
+fn int<I: Into<Integer>>(i: I) -> Literal {
+    Literal::Integer(SourceSpan::UNKNOWN, i.into())
+}
+
+#[inline]
+fn string(s: &str) -> Literal {
+    Literal::String(Ident::with_empty_span(Symbol::intern(s)))
+}
+
+#[inline]
+fn tuple(elements: Vec<Literal>) -> Literal {
+    Literal::Tuple(SourceSpan::UNKNOWN, elements)
+}
+
+fn list(elements: Vec<Literal>) -> Literal {
+    elements
+        .into_iter()
+        .rev()
+        .fold(Literal::Nil(SourceSpan::UNKNOWN), |tail, head| {
+            Literal::Cons(SourceSpan::UNKNOWN, Box::new(head), Box::new(tail))
+        })
+}
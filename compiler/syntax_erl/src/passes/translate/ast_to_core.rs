@@ -1093,6 +1093,27 @@ impl TranslateAst {
                 let qualifiers = self.preprocess_quals(qualifiers)?;
                 self.bc_tq(span, *body, qualifiers)
             }
+            ast::Expr::MapComprehension(ast::MapComprehension {
+                span,
+                key,
+                value,
+                qualifiers,
+            }) => {
+                // The map is built from the list of its associations, i.e. as if by
+                // `maps:from_list([{K, V} || Qualifiers])`, so later keys take precedence
+                let qualifiers = self.preprocess_quals(qualifiers)?;
+                let body = tuple_with_span!(span, *key, *value);
+                let (lc, mut pre) = self.lc_tq(span, body, qualifiers, inil!(span))?;
+                let (assocs, mut pre2) = force_safe(self.context_mut(), lc);
+                pre.append(&mut pre2);
+                let call = IExpr::Call(ICall::new(
+                    span,
+                    Symbol::intern("maps"),
+                    Symbol::intern("from_list"),
+                    vec![assocs],
+                ));
+                Ok((call, pre))
+            }
             ast::Expr::Tuple(ast::Tuple { span, elements }) => {
                 let (elements, pre) = self.safe_list(elements)?;
                 Ok((IExpr::Tuple(ITuple::new(span, elements)), pre))
@@ -1162,6 +1183,14 @@ impl TranslateAst {
                 });
                 Ok((case, pre))
             }
+            ast::Expr::Maybe(ast::Maybe {
+                span,
+                body,
+                else_clauses,
+            }) => {
+                let body = self.maybe_body(body, else_clauses.as_deref());
+                self.expr(ast::Expr::Begin(ast::Begin { span, body }))
+            }
             ast::Expr::Receive(ast::Receive {
                 span,
                 clauses: Some(clauses),
@@ -1796,6 +1825,95 @@ impl TranslateAst {
         }
     }
 
+    /// Rewrites the body of a `maybe` expression to a sequence of nested `case` expressions, one
+    /// per conditional match, e.g. `maybe P ?= E, Body end` becomes:
+    ///
+    ///     case E of
+    ///         P = V -> Body;
+    ///         Other -> Other
+    ///     end
+    ///
+    /// Where `V` is the value of the `case` if `Body` is empty. When the `maybe` has `else`
+    /// clauses, a value which fails to match is matched against them rather than returned
+    /// as-is.
+    fn maybe_body(
+        &mut self,
+        body: Vec<ast::Expr>,
+        else_clauses: Option<&[ast::Clause]>,
+    ) -> Vec<ast::Expr> {
+        let mut exprs = Vec::with_capacity(body.len());
+        let mut iter = body.into_iter();
+        while let Some(expr) = iter.next() {
+            let (span, pattern, expr) = match expr {
+                ast::Expr::MaybeMatch(ast::MaybeMatch {
+                    span,
+                    pattern,
+                    expr,
+                }) => (span, pattern, expr),
+                expr => {
+                    exprs.push(expr);
+                    continue;
+                }
+            };
+            let rest = iter.by_ref().collect::<Vec<_>>();
+            let (pattern, rest) = if rest.is_empty() {
+                let v = self.context_mut().next_var(Some(span));
+                let pattern = ast::Expr::Match(ast::Match {
+                    span,
+                    pattern,
+                    expr: Box::new(ast::Expr::Var(ast::Var(v.name))),
+                });
+                (pattern, vec![ast::Expr::Var(ast::Var(v.name))])
+            } else {
+                (*pattern, self.maybe_body(rest, else_clauses))
+            };
+            let other = ast::Var(self.context_mut().next_var(Some(span)).name);
+            let fallthrough = self.maybe_else(span, other, else_clauses);
+            exprs.push(ast::Expr::Case(ast::Case {
+                span,
+                expr,
+                clauses: vec![
+                    ast::Clause::new(span, vec![pattern], vec![], rest, false),
+                    ast::Clause::new(
+                        span,
+                        vec![ast::Expr::Var(other)],
+                        vec![],
+                        vec![fallthrough],
+                        true,
+                    ),
+                ],
+            }));
+            break;
+        }
+        exprs
+    }
+
+    /// Returns the expression evaluated when `value` fails to match a pattern in a `maybe`
+    fn maybe_else(
+        &mut self,
+        span: SourceSpan,
+        value: ast::Var,
+        else_clauses: Option<&[ast::Clause]>,
+    ) -> ast::Expr {
+        let Some(else_clauses) = else_clauses else { return ast::Expr::Var(value); };
+        // A value which matches none of the else clauses raises {else_clause, Value}
+        let fail = ast::Var(self.context_mut().next_var(Some(span)).name);
+        let reason = tuple_with_span!(span, atom!(span, else_clause), ast::Expr::Var(fail));
+        let mut clauses = else_clauses.to_vec();
+        clauses.push(ast::Clause::new(
+            span,
+            vec![ast::Expr::Var(fail)],
+            vec![],
+            vec![apply!(span, erlang, error, (reason))],
+            true,
+        ));
+        ast::Expr::Case(ast::Case {
+            span,
+            expr: Box::new(ast::Expr::Var(value)),
+            clauses,
+        })
+    }
+
     // bc_tq(Line, Exp, [Qualifier], More, State) -> {LetRec,[PreExp],State}.
     //  This TQ from Gustafsson ERLANG'05.
     //  More could be transformed before calling bc_tq.
@@ -2159,6 +2277,13 @@ impl TranslateAst {
             ast::GeneratorType::Bitstring => {
                 self.bit_generator(gen.span, *gen.pattern, *gen.expr, guards)
             }
+            ast::GeneratorType::Map => {
+                // The pattern of a map generator is the tuple {K, V}, so it generates
+                // the associations of the map as returned by maps:to_list/1
+                let span = gen.span;
+                let assocs = apply!(span, maps, to_list, (*gen.expr));
+                self.list_generator(span, *gen.pattern, assocs, guards)
+            }
         }
     }
 
@@ -2332,6 +2457,7 @@ impl TranslateAst {
                 // Replace literal or expression with a variable (whose value will be ignored)
                 let var = self.context_mut().next_var(None);
                 *segment.value.as_mut() = IExpr::Var(var);
+                out.push(segment);
             }
         }
         out
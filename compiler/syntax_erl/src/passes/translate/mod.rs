@@ -1,3 +1,5 @@
+mod ast_to_abstr;
 mod ast_to_core;
 
+pub use self::ast_to_abstr::AbstractCode;
 pub use self::ast_to_core::AstToCore;
@@ -36,6 +36,9 @@ pub enum PreprocessorError {
         span: SourceSpan,
     },
 
+    #[error("recursive inclusion of {path:?}")]
+    RecursiveInclude { path: PathBuf, span: SourceSpan },
+
     #[error("unable to parse constant expression")]
     ParseError {
         span: SourceSpan,
@@ -131,6 +134,14 @@ impl ToDiagnostic for PreprocessorError {
                         .with_message("while processing include directive"),
                     ])
             },
+            PreprocessorError::RecursiveInclude { span, .. } => {
+                Diagnostic::error()
+                    .with_message(self.to_string())
+                    .with_labels(vec![
+                        Label::primary(span.source_id(), *span)
+                        .with_message("this file is already being included"),
+                    ])
+            },
             PreprocessorError::ParseError { span, inner } => {
                 let err = inner.to_diagnostic();
                 err.with_labels(vec![
@@ -1,6 +1,6 @@
 use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::convert::TryFrom;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use firefly_diagnostics::*;
@@ -30,6 +30,7 @@ pub struct Preprocessor<Reader: TokenReader> {
     macros: MacroContainer,
     macro_calls: BTreeMap<SourceIndex, MacroCall>,
     expanded_tokens: VecDeque<LexicalToken>,
+    features: BTreeMap<Symbol, bool>,
     warnings_as_errors: bool,
     no_warn: bool,
 }
@@ -75,6 +76,7 @@ where
             macros,
             macro_calls: BTreeMap::new(),
             expanded_tokens: VecDeque::new(),
+            features: BTreeMap::new(),
             warnings_as_errors: parser.config.warnings_as_errors,
             no_warn: parser.config.no_warn,
         }
@@ -99,6 +101,7 @@ where
             macros: self.macros.clone(),
             macro_calls: BTreeMap::new(),
             expanded_tokens: VecDeque::new(),
+            features: self.features.clone(),
             warnings_as_errors: self.warnings_as_errors,
             no_warn: self.no_warn,
         }
@@ -108,10 +111,65 @@ where
         self.branches.iter().any(|b| !b.entered)
     }
 
+    /// Returns the paths searched by an include directive at `span`, which, as with `epp`, start
+    /// with the directory of the file containing the directive
+    fn include_paths_for(&self, span: SourceSpan) -> VecDeque<PathBuf> {
+        let mut include_paths = self.include_paths.clone();
+        if let Ok(FileName::Real(file)) = self.codemap.name_for_span(span) {
+            if let Some(dir) = file.parent() {
+                include_paths.push_front(dir.to_path_buf());
+            }
+        }
+        include_paths
+    }
+
+    /// Fails if `path` is the file containing the include directive at `span`, or any of the files
+    /// which include that one, as the inclusion would otherwise never end
+    fn check_recursive_include(&self, path: &Path, span: SourceSpan) -> PResult<()> {
+        let Ok(target) = path.canonicalize() else { return Ok(()); };
+        let mut including = Some(span);
+        while let Some(span) = including {
+            if let Ok(FileName::Real(file)) = self.codemap.name_for_span(span) {
+                if matches!(file.canonicalize(), Ok(file) if file == target) {
+                    return Err(PreprocessorError::RecursiveInclude {
+                        path: path.to_path_buf(),
+                        span,
+                    });
+                }
+            }
+            including = self.codemap.parent(span.source_id());
+        }
+        Ok(())
+    }
+
+    /// Returns true if `feature` is enabled, either by default or by a `-feature` directive
+    fn feature_enabled(&self, feature: Symbol) -> bool {
+        match self.features.get(&feature) {
+            Some(enabled) => *enabled,
+            None => crate::features::get(&feature)
+                .map(|feat| feat.enabled)
+                .unwrap_or(false),
+        }
+    }
+
+    /// The lexer always reads `maybe` and `else` as keywords, but they are only keywords when the
+    /// `maybe_expr` feature is enabled, otherwise they are plain atoms
+    fn apply_features(&self, token: LexicalToken) -> LexicalToken {
+        match token {
+            LexicalToken(start, Token::Maybe, end) if !self.feature_enabled(symbols::MaybeExpr) => {
+                LexicalToken(start, Token::Atom(Symbol::intern("maybe")), end)
+            }
+            LexicalToken(start, Token::Else, end) if !self.feature_enabled(symbols::MaybeExpr) => {
+                LexicalToken(start, Token::Atom(symbols::Else), end)
+            }
+            token => token,
+        }
+    }
+
     fn next_token(&mut self) -> Result<Option<LexicalToken>, ParserError> {
         loop {
             if let Some(token) = self.expanded_tokens.pop_front() {
-                return Ok(Some(token));
+                return Ok(Some(self.apply_features(token)));
             }
             if self.can_directive_start {
                 match self.try_read_directive().map_err(ParserError::from)? {
@@ -150,7 +208,7 @@ where
                 } else {
                     self.can_directive_start = false;
                 }
-                return Ok(Some(token));
+                return Ok(Some(self.apply_features(token)));
             } else {
                 break;
             }
@@ -244,7 +302,7 @@ where
                         match arg.tokens.as_slice() {
                             [LexicalToken(_, Token::Atom(feature), _)] => {
                                 match crate::features::get(feature) {
-                                    Some(feat) if feat.enabled => LexicalToken(
+                                    Some(_) if self.feature_enabled(*feature) => LexicalToken(
                                         span.start(),
                                         Token::Atom(symbols::True),
                                         span.end(),
@@ -425,11 +483,13 @@ where
                 );
             }
             Directive::Include(ref d) if !ignore => {
-                let path = d.include(&self.include_paths)?;
+                let path = d.include(&self.include_paths_for(d.span()))?;
+                self.check_recursive_include(&path, d.span())?;
                 self.reader.inject_include(path, d.span())?;
             }
             Directive::IncludeLib(ref d) if !ignore => {
-                let path = d.include_lib(&self.include_paths, &self.code_paths)?;
+                let path = d.include_lib(&self.include_paths_for(d.span()), &self.code_paths)?;
+                self.check_recursive_include(&path, d.span())?;
                 self.reader.inject_include(path, d.span())?;
             }
             Directive::Define(ref d) if !ignore => {
@@ -508,6 +568,22 @@ where
                     });
                 }
             }
+            Directive::Feature(ref f) if !ignore => {
+                let span = f.span();
+                let name = f.name();
+                match (crate::features::get(&name), f.enable()) {
+                    (Some(_), Some(enable)) => {
+                        self.features.insert(name, enable);
+                    }
+                    (None, _) => {
+                        let msg = format!("unrecognized feature {}", &name);
+                        self.reporter.show_warning(msg.as_str(), &[(span, "this is not a recognized feature, it may be unimplemented, or may be a typo, this directive will be ignored")]);
+                    }
+                    (Some(_), None) => {
+                        self.reporter.show_warning("invalid -feature directive", &[(span, "expected either 'enable' or 'disable', this directive will be ignored")]);
+                    }
+                }
+            }
             Directive::File(ref f) if !ignore => {
                 // TODO
                 let span = f.span();
@@ -2,7 +2,7 @@ use std::assert_matches::assert_matches;
 use std::collections::HashMap;
 
 use anyhow::anyhow;
-use firefly_binary::BinaryEntrySpecifier;
+use firefly_binary::{BinaryEntrySpecifier, Bitstring};
 use firefly_diagnostics::*;
 use firefly_intern::{symbols, Symbol};
 use firefly_number::Integer;
@@ -42,11 +42,14 @@ impl Pass for KernelToSsa {
             } else {
                 Visibility::DEFAULT
             };
-            let visibility = if kfunction.has_annotation(symbols::Nif) {
+            let mut visibility = if kfunction.has_annotation(symbols::Nif) {
                 base_visibility | Visibility::NIF
             } else {
                 base_visibility
             };
+            if module.compile.inline_functions.contains(&name) {
+                visibility |= Visibility::INLINE;
+            }
             let mut params = vec![];
             params.resize(name.arity as usize, Type::Term(TermType::Any));
             let signature = Signature {
@@ -95,6 +98,17 @@ impl Pass for KernelToSsa {
             ir_module.define_function(ir_function);
         }
 
+        // Imported functions may be named for inlining too, e.g. by `inline_list_funcs`,
+        // in which case calls to them are candidates for specialization
+        for sig in ir_module.signatures.borrow_mut().values_mut() {
+            let name = Span::new(SourceSpan::UNKNOWN, sig.mfa());
+            if sig.visibility.is_externally_defined()
+                && module.compile.inline_functions.contains(&name)
+            {
+                sig.visibility |= Visibility::INLINE;
+            }
+        }
+
         debug!("successfully lowered kernel module to core ir module");
         Ok(ir_module)
     }
@@ -1755,6 +1769,13 @@ impl<'m> LowerFunctionToSsa<'m> {
         literal: Literal,
     ) -> anyhow::Result<Value> {
         let span = literal.span();
+        // Constant tuples and lists are emitted as static data, so they cost nothing at runtime
+        match literal.value {
+            Lit::Cons(_, _) | Lit::Tuple(_) if is_static_literal(&literal.value) => {
+                return Ok(builder.ins().literal(literal.value, span));
+            }
+            _ => (),
+        }
         match literal.value {
             Lit::Atom(value) => Ok(builder.ins().atom(value, span)),
             Lit::Integer(Integer::Small(value)) => Ok(builder.ins().int(value, span)),
@@ -1835,6 +1856,25 @@ impl<'m> LowerFunctionToSsa<'m> {
     }
 }
 
+/// The range of integers which can be encoded as an immediate term
+const MIN_SMALL: i64 = -(1 << 51);
+const MAX_SMALL: i64 = (1 << 51) - 1;
+
+/// Returns true if `lit` can be laid out statically by codegen
+///
+/// Big integers and maps require runtime allocation (the latter have no fixed layout), as do
+/// bitstrings which are not binaries, so any constant containing them is constructed at runtime.
+fn is_static_literal(lit: &Lit) -> bool {
+    match lit {
+        Lit::Atom(_) | Lit::Float(_) | Lit::Nil => true,
+        Lit::Integer(Integer::Small(i)) => *i >= MIN_SMALL && *i <= MAX_SMALL,
+        Lit::Integer(Integer::Big(_)) | Lit::Map(_) => false,
+        Lit::Binary(bin) => bin.is_binary(),
+        Lit::Cons(head, tail) => is_static_literal(&head.value) && is_static_literal(&tail.value),
+        Lit::Tuple(elements) => elements.iter().all(|e| is_static_literal(&e.value)),
+    }
+}
+
 // Select
 impl<'m> LowerFunctionToSsa<'m> {
     fn select_binary<'a>(
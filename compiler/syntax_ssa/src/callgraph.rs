@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use firefly_intern::Symbol;
+use firefly_syntax_base::FunctionName;
+
+use crate::ir::*;
+
+/// Functions which invoke a function named by runtime values, i.e. `Module:Function/Arity`
+///
+/// A call to any of these makes it impossible to know statically which exported functions
+/// may be called, so the presence of one in live code makes every exported function live.
+/// Starting an application is among them, as it calls `start/2` of the callback module named in
+/// the application's resource file, which need not be that of the application being built.
+const DYNAMIC_DISPATCH: &[(&str, &str, u8)] = &[
+    ("erlang", "apply", 3),
+    ("erlang", "make_fun", 3),
+    ("erlang", "spawn", 3),
+    ("erlang", "spawn", 4),
+    ("erlang", "spawn_link", 3),
+    ("erlang", "spawn_link", 4),
+    ("erlang", "spawn_monitor", 3),
+    ("erlang", "spawn_monitor", 4),
+    ("erlang", "spawn_opt", 4),
+    ("erlang", "spawn_opt", 5),
+    ("erlang", "spawn_request", 3),
+    ("erlang", "spawn_request", 4),
+    ("erlang", "spawn_request", 5),
+    ("erlang", "hibernate", 3),
+    ("application", "start", 1),
+    ("application", "start", 2),
+    ("application", "ensure_started", 1),
+    ("application", "ensure_started", 2),
+    ("application", "ensure_all_started", 1),
+    ("application", "ensure_all_started", 2),
+];
+
+/// Represents the call graph of a set of modules
+///
+/// Nodes are the functions defined in those modules, and an edge from `a` to `b` indicates that `a`
+/// calls `b` directly, or creates a closure which invokes `b`. Callees need not be defined in the
+/// graph, e.g. calls to runtime builtins are recorded as edges to functions which have no
+/// definition.
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    /// The set of functions defined in the graph
+    defined: BTreeSet<FunctionName>,
+    /// The subset of defined functions which are exported from their module
+    exported: BTreeSet<FunctionName>,
+    /// The outgoing edges of each function in the graph
+    edges: BTreeMap<FunctionName, BTreeSet<FunctionName>>,
+    /// The set of functions which contain a dynamic call, see `DYNAMIC_DISPATCH`
+    dynamic: BTreeSet<FunctionName>,
+}
+impl CallGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the functions of `module`, and the calls they make, to this graph
+    pub fn add_module(&mut self, module: &Module) {
+        let signatures = module.signatures.borrow();
+        for function in module.functions.iter() {
+            let caller = function.signature.mfa();
+            self.defined.insert(caller);
+            if function.signature.visibility.is_public() {
+                self.exported.insert(caller);
+            }
+
+            let callees = self.edges.entry(caller).or_default();
+            let dfg = &function.dfg;
+            for (block, _) in dfg.blocks() {
+                for inst in dfg.block_insts(block) {
+                    let callee = match &**dfg.insts[inst] {
+                        InstData::Call(Call { callee, .. }) => *callee,
+                        InstData::MakeFun(MakeFun { callee, .. }) => *callee,
+                        _ => continue,
+                    };
+                    let callee = signatures[callee].mfa();
+                    if is_dynamic_dispatch(&callee) {
+                        self.dynamic.insert(caller);
+                    }
+                    callees.insert(callee);
+                }
+            }
+        }
+    }
+
+    /// Returns the set of functions defined in this graph
+    pub fn functions(&self) -> &BTreeSet<FunctionName> {
+        &self.defined
+    }
+
+    /// Returns the set of exported functions defined in this graph
+    pub fn exports(&self) -> &BTreeSet<FunctionName> {
+        &self.exported
+    }
+
+    /// Returns true if `function` is defined in this graph
+    pub fn is_defined(&self, function: &FunctionName) -> bool {
+        self.defined.contains(function)
+    }
+
+    /// Returns true if `function` calls a function named by runtime values
+    pub fn is_dynamic(&self, function: &FunctionName) -> bool {
+        self.dynamic.contains(function)
+    }
+
+    /// Returns an iterator over the functions directly called by `function`
+    pub fn callees<'a>(
+        &'a self,
+        function: &FunctionName,
+    ) -> impl Iterator<Item = FunctionName> + 'a {
+        self.edges
+            .get(function)
+            .into_iter()
+            .flat_map(|callees| callees.iter().copied())
+    }
+
+    /// Returns the set of modules called by functions in `module`, excluding `module` itself
+    pub fn module_dependencies(&self, module: Symbol) -> BTreeSet<Symbol> {
+        self.edges
+            .iter()
+            .filter(|(caller, _)| caller.module == Some(module))
+            .flat_map(|(_, callees)| callees.iter().filter_map(|callee| callee.module))
+            .filter(|callee| *callee != module)
+            .collect()
+    }
+
+    /// Returns the set of functions transitively reachable from `roots`, including the roots
+    /// themselves
+    pub fn reachable<I>(&self, roots: I) -> BTreeSet<FunctionName>
+    where
+        I: IntoIterator<Item = FunctionName>,
+    {
+        let mut reachable = BTreeSet::new();
+        let mut worklist = roots.into_iter().collect::<Vec<_>>();
+        while let Some(function) = worklist.pop() {
+            if !reachable.insert(function) {
+                continue;
+            }
+            worklist.extend(self.callees(&function));
+        }
+        reachable
+    }
+
+    /// Returns the set of functions which may be called at runtime, given the entry points in
+    /// `roots`
+    ///
+    /// This is conservative in the presence of dynamic calls: if any reachable function may call a
+    /// function named by runtime values, then all exported functions are considered reachable too.
+    pub fn live<I>(&self, roots: I) -> BTreeSet<FunctionName>
+    where
+        I: IntoIterator<Item = FunctionName>,
+    {
+        let live = self.reachable(roots);
+        if live.iter().any(|f| self.is_dynamic(f)) {
+            self.reachable(live.into_iter().chain(self.exported.iter().copied()))
+        } else {
+            live
+        }
+    }
+
+    /// Writes this graph in Graphviz DOT format
+    ///
+    /// Functions are grouped by module, exported functions are drawn in bold, functions containing
+    /// dynamic calls are filled in red, and functions which are not defined in the graph are
+    /// dashed.
+    pub fn write_dot(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "digraph callgraph {{")?;
+        writeln!(w, "  node [shape=box];")?;
+
+        let mut modules: BTreeMap<Option<Symbol>, Vec<FunctionName>> = BTreeMap::new();
+        for function in self.defined.iter() {
+            modules.entry(function.module).or_default().push(*function);
+        }
+        for (i, (module, functions)) in modules.iter().enumerate() {
+            writeln!(w, "  subgraph cluster_{} {{", i)?;
+            if let Some(module) = module {
+                writeln!(w, "    label=\"{}\";", module)?;
+            }
+            for function in functions.iter() {
+                let mut style = vec![];
+                if self.exported.contains(function) {
+                    style.push("bold");
+                }
+                if self.dynamic.contains(function) {
+                    style.push("filled");
+                }
+                write!(w, "    \"{}\"", function)?;
+                if !style.is_empty() {
+                    write!(w, " [style=\"{}\"", style.join(","))?;
+                    if self.dynamic.contains(function) {
+                        write!(w, ", fillcolor=\"#f4cccc\"")?;
+                    }
+                    write!(w, "]")?;
+                }
+                writeln!(w, ";")?;
+            }
+            writeln!(w, "  }}")?;
+        }
+
+        let undefined = self
+            .edges
+            .values()
+            .flat_map(|callees| callees.iter())
+            .filter(|callee| !self.defined.contains(callee))
+            .collect::<BTreeSet<_>>();
+        for function in undefined {
+            writeln!(w, "  \"{}\" [style=dashed];", function)?;
+        }
+
+        for (caller, callees) in self.edges.iter() {
+            for callee in callees.iter() {
+                writeln!(w, "  \"{}\" -> \"{}\";", caller, callee)?;
+            }
+        }
+
+        writeln!(w, "}}")
+    }
+}
+
+fn is_dynamic_dispatch(callee: &FunctionName) -> bool {
+    let module = match callee.module {
+        None => return false,
+        Some(module) => module,
+    };
+    DYNAMIC_DISPATCH.iter().any(|(m, f, a)| {
+        callee.arity == *a && module.as_str().get() == *m && callee.function.as_str().get() == *f
+    })
+}
@@ -0,0 +1,376 @@
+//! Static estimates of the runtime cost of the functions in a module, emitted via
+//! `--emit=cost-report`.
+//!
+//! For each function, we estimate the number of reductions, and the number of heap words
+//! allocated, by a single call along the most expensive path through its body. Reductions are
+//! counted as one per call, as in BEAM, and only allocations made by the function itself are
+//! counted, not those of its callees. Some operations allocate in proportion to their inputs (e.g.
+//! `++` copies its left operand), these cannot be estimated statically, so functions containing
+//! them are flagged as such instead.
+//!
+//! In addition, we look for patterns in recursive functions which are accidentally quadratic:
+//!
+//! * `++` with a non-constant left operand, e.g. `loop([H | T], Acc) -> loop(T, Acc ++ [H])`, which
+//! copies the accumulator on every iteration
+//! * `length/1` compared against something, e.g. in a guard like `when length(L) > 0`, which walks
+//! the entire list on every iteration
+//!
+//! These are listed under their function in the report, and also reported as warnings pointing at
+//! the offending expression.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::Write;
+
+use cranelift_entity::PrimaryMap;
+
+use firefly_diagnostics::{Reporter, SourceSpan, Spanned};
+use firefly_intern::{symbols, Symbol};
+use firefly_syntax_base::{FunctionName, Signature};
+use firefly_util::emit::Emit;
+
+use crate::callgraph::CallGraph;
+use crate::ir::*;
+
+/// The number of words allocated for a cons cell
+const CONS_WORDS: usize = 2;
+/// The number of words allocated for the header of a tuple, in addition to its elements
+const TUPLE_HEADER_WORDS: usize = 1;
+/// The number of words allocated for a closure, in addition to its environment
+const CLOSURE_HEADER_WORDS: usize = 4;
+
+/// An accidentally quadratic pattern found in a recursive function
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CostWarning {
+    /// `++` is applied to a non-constant left operand
+    AppendInLoop,
+    /// The result of `length/1` is compared against something
+    LengthInGuard,
+}
+impl fmt::Display for CostWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::AppendInLoop => f.write_str(
+                "`++` in a recursive function copies its left operand on every iteration, \
+                 consider accumulating in reverse and calling lists:reverse/1 once",
+            ),
+            Self::LengthInGuard => f.write_str(
+                "length/1 in a recursive function walks the whole list on every iteration, \
+                 consider matching on [] or [_ | _] instead",
+            ),
+        }
+    }
+}
+
+/// The estimated cost of a single call to a function
+#[derive(Debug, Clone)]
+pub struct FunctionCost {
+    pub name: FunctionName,
+    pub span: SourceSpan,
+    /// The number of reductions along the most expensive path through the function
+    pub reductions: usize,
+    /// The number of heap words allocated along the most expensive path through the function
+    pub words: usize,
+    /// True if the function also allocates an amount which depends on its inputs
+    pub dynamic: bool,
+    /// True if the function may call itself, directly or via other functions in its module
+    pub recursive: bool,
+    pub warnings: Vec<(CostWarning, SourceSpan)>,
+}
+
+/// The estimated costs of all of the functions in a module
+pub struct CostReport {
+    module: Symbol,
+    functions: Vec<FunctionCost>,
+}
+impl CostReport {
+    pub fn new(module: &Module) -> Self {
+        let mut callgraph = CallGraph::new();
+        callgraph.add_module(module);
+
+        let signatures = module.signatures.borrow();
+        let functions = module
+            .functions
+            .iter()
+            .filter(|function| !function.signature.visibility.is_externally_defined())
+            .map(|function| {
+                let name = function.signature.mfa();
+                let recursive = callgraph
+                    .reachable(callgraph.callees(&name))
+                    .contains(&name);
+                let mut analysis = Analysis {
+                    function,
+                    signatures: &signatures,
+                    recursive,
+                    costs: BTreeMap::new(),
+                    visiting: BTreeSet::new(),
+                    dynamic: false,
+                    warnings: vec![],
+                };
+                let (reductions, words) = match function.dfg.blocks().next() {
+                    Some((entry, _)) => analysis.block_cost(entry),
+                    None => (0, 0),
+                };
+                FunctionCost {
+                    name,
+                    span: function.span,
+                    reductions,
+                    words,
+                    dynamic: analysis.dynamic,
+                    recursive,
+                    warnings: analysis.warnings,
+                }
+            })
+            .collect();
+
+        Self {
+            module: module.name.name,
+            functions,
+        }
+    }
+
+    #[inline]
+    pub fn functions(&self) -> &[FunctionCost] {
+        self.functions.as_slice()
+    }
+
+    /// Reports each warning as a diagnostic, so that it points at the offending expression
+    pub fn report_warnings(&self, reporter: &Reporter) {
+        for function in self.functions.iter() {
+            for (warning, span) in function.warnings.iter() {
+                reporter.show_warning(
+                    "potentially quadratic recursion",
+                    &[(*span, warning.to_string().as_str())],
+                );
+            }
+        }
+    }
+}
+impl Emit for CostReport {
+    fn file_type(&self) -> Option<&'static str> {
+        Some("cost")
+    }
+
+    fn emit(&self, f: &mut std::fs::File) -> anyhow::Result<()> {
+        writeln!(f, "%% Cost report for module {}", self.module)?;
+        writeln!(f, "%%")?;
+        writeln!(
+            f,
+            "%% Estimates are per call, along the most expensive path through each function."
+        )?;
+        writeln!(
+            f,
+            "%% Words only include allocations made by the function itself, `+` marks functions"
+        )?;
+        writeln!(f, "%% which also allocate in proportion to their inputs.")?;
+        for function in self.functions.iter() {
+            writeln!(f)?;
+            write!(
+                f,
+                "{}  reductions: {}  words: {}{}",
+                &function.name,
+                function.reductions,
+                function.words,
+                if function.dynamic { "+" } else { "" }
+            )?;
+            if function.recursive {
+                write!(f, "  (recursive)")?;
+            }
+            writeln!(f)?;
+            for (warning, _) in function.warnings.iter() {
+                writeln!(f, "    warning: {}", warning)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Analysis<'a> {
+    function: &'a Function,
+    signatures: &'a PrimaryMap<FuncRef, Signature>,
+    recursive: bool,
+    /// The cost of each block visited so far, including the most expensive of its successors
+    costs: BTreeMap<Block, (usize, usize)>,
+    /// The blocks on the current path, used to ignore back edges
+    visiting: BTreeSet<Block>,
+    dynamic: bool,
+    warnings: Vec<(CostWarning, SourceSpan)>,
+}
+impl<'a> Analysis<'a> {
+    /// Returns the cost of `block`, plus that of its most expensive successor
+    fn block_cost(&mut self, block: Block) -> (usize, usize) {
+        if let Some(cost) = self.costs.get(&block) {
+            return *cost;
+        }
+        if !self.visiting.insert(block) {
+            return (0, 0);
+        }
+
+        let function = self.function;
+        let dfg = &function.dfg;
+        let (mut reductions, mut words) = (0, 0);
+        for inst in dfg.block_insts(block) {
+            let (r, w) = self.inst_cost(inst);
+            reductions += r;
+            words += w;
+        }
+
+        let successors = match dfg.last_inst(block).map(|inst| dfg.analyze_branch(inst)) {
+            Some(BranchInfo::SingleDest(dest, _)) => vec![dest],
+            Some(BranchInfo::MultiDest(targets)) => {
+                targets.iter().map(|target| target.destination).collect()
+            }
+            Some(BranchInfo::NotABranch) | None => vec![],
+        };
+        let (r, w) = successors
+            .into_iter()
+            .map(|succ| self.block_cost(succ))
+            .max()
+            .unwrap_or_default();
+
+        self.visiting.remove(&block);
+        let cost = (reductions + r, words + w);
+        self.costs.insert(block, cost);
+        cost
+    }
+
+    fn inst_cost(&mut self, inst: Inst) -> (usize, usize) {
+        let function = self.function;
+        let dfg = &function.dfg;
+        let span = dfg[inst].span();
+        match &*dfg[inst] {
+            InstData::Call(Call { callee, args, .. }) => {
+                let callee = self.signatures[*callee].mfa();
+                if callee.module == Some(symbols::Erlang) && callee.arity == 2 {
+                    if callee.function == symbols::PlusPlus {
+                        self.append(args.as_slice(&dfg.value_lists)[0], span);
+                    } else if callee.function == symbols::MinusMinus {
+                        self.dynamic = true;
+                    }
+                }
+                if self.recursive && is_length(&callee) && self.is_compared(inst) {
+                    self.warnings.push((CostWarning::LengthInGuard, span));
+                }
+                (1, 0)
+            }
+            InstData::CallIndirect(_) => (1, 0),
+            InstData::MakeFun(MakeFun { env, .. }) => {
+                (0, CLOSURE_HEADER_WORDS + env.len(&dfg.value_lists))
+            }
+            InstData::BinaryOp(BinaryOp { op, args }) => match op {
+                Opcode::Cons => (0, CONS_WORDS),
+                Opcode::ListConcat => {
+                    self.append(args[0], span);
+                    (0, 0)
+                }
+                Opcode::ListSubtract => {
+                    self.dynamic = true;
+                    (0, 0)
+                }
+                _ => (0, 0),
+            },
+            InstData::BinaryOpImm(BinaryOpImm {
+                op: Opcode::Cons, ..
+            }) => (0, CONS_WORDS),
+            InstData::UnaryOpImm(UnaryOpImm {
+                op: Opcode::Tuple,
+                imm: Immediate::Isize(arity),
+            }) => (0, TUPLE_HEADER_WORDS + *arity as usize),
+            // Updating a tuple copies it, but its size is not known statically
+            InstData::SetElement(SetElement {
+                op: Opcode::SetElement,
+                ..
+            })
+            | InstData::SetElementImm(SetElementImm {
+                op: Opcode::SetElement,
+                ..
+            })
+            | InstData::BitsPush(_) => {
+                self.dynamic = true;
+                (0, 0)
+            }
+            _ => (0, 0),
+        }
+    }
+
+    /// Records an application of `++` to `lhs`, which copies `lhs`
+    fn append(&mut self, lhs: Value, span: SourceSpan) {
+        if self.is_constant(lhs) {
+            // Appending to a literal copies the same, fixed number of cells every time
+            return;
+        }
+        self.dynamic = true;
+        if self.recursive {
+            self.warnings.push((CostWarning::AppendInLoop, span));
+        }
+    }
+
+    fn is_constant(&self, value: Value) -> bool {
+        let function = self.function;
+        let dfg = &function.dfg;
+        match dfg.get_value(value) {
+            ValueData::Inst { inst, .. } => {
+                matches!(dfg[inst].opcode(), Opcode::ImmNil | Opcode::ConstTerm)
+            }
+            ValueData::Param { .. } => false,
+        }
+    }
+
+    /// Returns true if the result of `inst` is used as an operand of a comparison
+    fn is_compared(&self, inst: Inst) -> bool {
+        let function = self.function;
+        let dfg = &function.dfg;
+        let Some(result) = dfg.inst_results(inst).first().copied() else {
+            return false;
+        };
+        dfg.blocks()
+            .flat_map(|(block, _)| dfg.block_insts(block))
+            .any(|user| {
+                let data = &*dfg[user];
+                let is_comparison = match data {
+                    InstData::Call(Call { callee, .. }) => {
+                        is_comparison(&self.signatures[*callee].mfa())
+                    }
+                    data => matches!(
+                        data.opcode(),
+                        Opcode::Eq
+                            | Opcode::EqExact
+                            | Opcode::Neq
+                            | Opcode::NeqExact
+                            | Opcode::Gt
+                            | Opcode::Gte
+                            | Opcode::Lt
+                            | Opcode::Lte
+                            | Opcode::IcmpEq
+                            | Opcode::IcmpNeq
+                            | Opcode::IcmpGt
+                            | Opcode::IcmpGte
+                            | Opcode::IcmpLt
+                            | Opcode::IcmpLte
+                    ),
+                };
+                is_comparison && data.arguments(&dfg.value_lists).contains(&result)
+            })
+    }
+}
+
+fn is_length(callee: &FunctionName) -> bool {
+    callee.module == Some(symbols::Erlang)
+        && callee.function == symbols::Length
+        && callee.arity == 1
+}
+
+fn is_comparison(callee: &FunctionName) -> bool {
+    callee.module == Some(symbols::Erlang)
+        && callee.arity == 2
+        && matches!(
+            callee.function,
+            symbols::Equal
+                | symbols::EqualStrict
+                | symbols::NotEqual
+                | symbols::NotEqualStrict
+                | symbols::Lt
+                | symbols::Lte
+                | symbols::Gt
+                | symbols::Gte
+        )
+}
@@ -0,0 +1,626 @@
+//! Evaluates at compile time whatever can be known about a module without running it, enabled at
+//! `-O` and above.
+//!
+//! Values defined by immediates and constants are known, and from those we evaluate:
+//!
+//! * calls to pure BIFs of the `erlang` module, i.e. arithmetic, comparisons, boolean operators,
+//! `element/2`, `hd/1`, `tl/1`, `tuple_size/1`, and the type tests
+//! * comparisons and `is_type` tests on known values
+//!
+//! A call is only replaced by its result if evaluating it cannot fail, calls which would raise are
+//! left for the runtime to raise. Branches on known conditions are then replaced by jumps to the
+//! only destination they can take, which leaves the clauses that can never match unreachable, and
+//! these are removed along with everything else that can no longer be reached from the entry.
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use firefly_intern::{symbols, Symbol};
+use firefly_number::{Float, Integer};
+use firefly_syntax_base::{Lit, Literal, TermType, Type};
+
+use crate::ir::*;
+
+#[derive(Default)]
+pub struct ConstantFolding;
+impl ConstantFolding {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn fold_module(&self, module: &mut Module) {
+        for function in module.functions.iter_mut() {
+            if function.signature.visibility.is_externally_defined() {
+                continue;
+            }
+            let dfg = &mut function.dfg;
+            // Each round may make more values known, e.g. the result of a folded call
+            // may be an argument of another
+            while Folder::new(dfg).run() {}
+            remove_unreachable_blocks(dfg);
+        }
+    }
+}
+
+struct Folder<'a> {
+    dfg: &'a mut DataFlowGraph,
+    /// The terms known for values defined by immediates and constants
+    terms: BTreeMap<Value, Lit>,
+    /// The known values of primitive booleans
+    flags: BTreeMap<Value, bool>,
+    /// The instructions using each value
+    uses: BTreeMap<Value, BTreeSet<Inst>>,
+}
+impl<'a> Folder<'a> {
+    fn new(dfg: &'a mut DataFlowGraph) -> Self {
+        let mut terms = BTreeMap::new();
+        let mut flags = BTreeMap::new();
+        let mut uses = BTreeMap::<Value, BTreeSet<Inst>>::new();
+        for (block, _) in dfg.blocks() {
+            for inst in dfg.block_insts(block) {
+                match &*dfg[inst] {
+                    InstData::UnaryOpImm(UnaryOpImm {
+                        imm: Immediate::I1(b),
+                        ..
+                    }) => {
+                        flags.insert(dfg.first_result(inst), *b);
+                    }
+                    InstData::UnaryOpImm(UnaryOpImm { imm, .. }) => {
+                        if let Some(lit) = immediate_to_lit(imm) {
+                            terms.insert(dfg.first_result(inst), lit);
+                        }
+                    }
+                    InstData::UnaryOpConst(UnaryOpConst { imm, .. }) => {
+                        if let Some(lit) = constant_to_lit(&dfg.constant(*imm)) {
+                            terms.insert(dfg.first_result(inst), lit);
+                        }
+                    }
+                    _ => (),
+                }
+                let args = dfg.inst_args(inst).iter();
+                let dest_args = match dfg.analyze_branch(inst) {
+                    BranchInfo::SingleDest(_, args) => args.to_vec(),
+                    BranchInfo::MultiDest(jts) => {
+                        jts.iter().flat_map(|jt| jt.args.iter().copied()).collect()
+                    }
+                    BranchInfo::NotABranch => vec![],
+                };
+                for arg in args.copied().chain(dest_args) {
+                    uses.entry(arg).or_default().insert(inst);
+                }
+            }
+        }
+
+        Self {
+            dfg,
+            terms,
+            flags,
+            uses,
+        }
+    }
+
+    /// Folds what can be folded with the values known at the start of the round,
+    /// returning true if anything changed
+    fn run(&mut self) -> bool {
+        let blocks = self.dfg.blocks().map(|(b, _)| b).collect::<Vec<_>>();
+        let mut changed = false;
+        for block in blocks {
+            let insts = self.dfg.block_insts(block).collect::<Vec<_>>();
+            for inst in insts {
+                // Instructions following a branch which is now always taken are gone
+                if self.dfg.insts.get(inst).is_none() {
+                    continue;
+                }
+                changed |= self.fold_inst(inst);
+            }
+        }
+        changed
+    }
+
+    fn fold_inst(&mut self, inst: Inst) -> bool {
+        match self.dfg[inst].item.clone() {
+            InstData::Call(Call {
+                op: Opcode::Call,
+                callee,
+                args,
+            }) => {
+                let mfa = self.dfg.callee_signature(callee).mfa();
+                if mfa.module != Some(symbols::Erlang) {
+                    return false;
+                }
+                let args = args.as_slice(&self.dfg.value_lists);
+                let Some(args) = self.known_terms(args) else { return false; };
+                match eval_bif(mfa.function, args.as_slice()) {
+                    Some(result) => self.fold_call(inst, result),
+                    None => false,
+                }
+            }
+            InstData::BinaryOp(BinaryOp { op, args }) => {
+                let Some(function) = comparison_bif(op) else { return false; };
+                let Some(args) = self.known_terms(&args) else { return false; };
+                match eval_bif(function, args.as_slice()) {
+                    Some(result) => self.replace_with_term(inst, result),
+                    None => false,
+                }
+            }
+            InstData::BinaryOpImm(BinaryOpImm { op, arg, imm }) => {
+                let known = (
+                    comparison_bif(op),
+                    self.terms.get(&arg).cloned(),
+                    immediate_to_lit(&imm),
+                );
+                let (Some(function), Some(lhs), Some(rhs)) = known else {
+                    return false;
+                };
+                match eval_bif(function, &[lhs, rhs]) {
+                    Some(result) => self.replace_with_term(inst, result),
+                    None => false,
+                }
+            }
+            InstData::IsType(IsType { arg, ty }) => {
+                let Some(lit) = self.terms.get(&arg) else { return false; };
+                match is_type(&ty, lit) {
+                    Some(result) => self.replace_with_term(inst, bool_to_lit(result)),
+                    None => false,
+                }
+            }
+            InstData::Br(Br {
+                op: op @ (Opcode::BrIf | Opcode::BrUnless),
+                ..
+            }) => {
+                let cond = self.dfg.inst_args(inst)[0];
+                let Some(cond) = self.truth(cond) else { return false; };
+                if cond == (op == Opcode::BrIf) {
+                    self.make_unconditional(inst);
+                } else {
+                    self.dfg.remove_inst(inst);
+                }
+                true
+            }
+            InstData::CondBr(CondBr {
+                cond,
+                then_dest,
+                else_dest,
+            }) => {
+                let Some(cond) = self.truth(cond) else { return false; };
+                let (destination, args) = if cond { then_dest } else { else_dest };
+                self.dfg[inst].item = InstData::Br(Br {
+                    op: Opcode::Br,
+                    destination,
+                    args,
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Replaces a call to a BIF with its result, along with the checks of its error flag
+    ///
+    /// The flag is known to be false, so this is only done if its only uses are in branches
+    /// to the error handler, which can be removed. Otherwise the call is left as is.
+    fn fold_call(&mut self, inst: Inst, result: Lit) -> bool {
+        let results = self.dfg.inst_results(inst).to_vec();
+        let [is_err, value] = results.as_slice() else { return false; };
+        let checks = self
+            .uses
+            .get(is_err)
+            .map(|users| {
+                users
+                    .iter()
+                    .copied()
+                    .filter(|user| self.dfg.insts.get(*user).is_some())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for check in checks.iter().copied() {
+            let is_check = match &*self.dfg[check] {
+                InstData::Br(Br {
+                    op: Opcode::BrIf, ..
+                }) => {
+                    let args = self.dfg.inst_args(check);
+                    args[0] == *is_err && !args[1..].contains(is_err)
+                }
+                _ => false,
+            };
+            if !is_check {
+                return false;
+            }
+        }
+        if !self.replace_with(inst, *value, result) {
+            return false;
+        }
+        for check in checks {
+            self.dfg.remove_inst(check);
+        }
+        true
+    }
+
+    fn replace_with_term(&mut self, inst: Inst, result: Lit) -> bool {
+        let value = self.dfg.first_result(inst);
+        self.replace_with(inst, value, result)
+    }
+
+    /// Replaces `inst` with the instruction materializing `lit`, defining `value`
+    ///
+    /// Returns false if `lit` is not something we can materialize, i.e. a map or binary
+    fn replace_with(&mut self, inst: Inst, value: Value, lit: Lit) -> bool {
+        let (data, ty) = match lit {
+            Lit::Atom(a) if a == symbols::True || a == symbols::False => (
+                InstData::UnaryOpImm(UnaryOpImm {
+                    op: Opcode::ImmBool,
+                    imm: Immediate::Term(ImmediateTerm::Bool(a == symbols::True)),
+                }),
+                Type::Term(TermType::Bool),
+            ),
+            Lit::Atom(a) => (
+                InstData::UnaryOpImm(UnaryOpImm {
+                    op: Opcode::ImmAtom,
+                    imm: Immediate::Term(ImmediateTerm::Atom(a)),
+                }),
+                Type::Term(TermType::Atom),
+            ),
+            Lit::Integer(Integer::Small(i)) => (
+                InstData::UnaryOpImm(UnaryOpImm {
+                    op: Opcode::ImmInt,
+                    imm: Immediate::Term(ImmediateTerm::Integer(i)),
+                }),
+                Type::Term(TermType::Integer),
+            ),
+            Lit::Integer(i) => (
+                InstData::UnaryOpConst(UnaryOpConst {
+                    op: Opcode::ConstBigInt,
+                    imm: self.dfg.make_constant(ConstantItem::Integer(i)),
+                }),
+                Type::Term(TermType::Integer),
+            ),
+            Lit::Float(f) => (
+                InstData::UnaryOpImm(UnaryOpImm {
+                    op: Opcode::ImmFloat,
+                    imm: Immediate::Term(ImmediateTerm::Float(f.inner())),
+                }),
+                Type::Term(TermType::Float),
+            ),
+            Lit::Nil => (
+                InstData::UnaryOpImm(UnaryOpImm {
+                    op: Opcode::ImmNil,
+                    imm: Immediate::Term(ImmediateTerm::Nil),
+                }),
+                Type::Term(TermType::Nil),
+            ),
+            lit @ (Lit::Cons(_, _) | Lit::Tuple(_)) => {
+                let constant = self.dfg.make_constant(ConstantItem::Term(lit));
+                let ty = self.dfg.constant_type(constant);
+                (
+                    InstData::UnaryOpConst(UnaryOpConst {
+                        op: Opcode::ConstTerm,
+                        imm: constant,
+                    }),
+                    ty,
+                )
+            }
+            Lit::Map(_) | Lit::Binary(_) => return false,
+        };
+        self.dfg[inst].item = data;
+        let pool = &mut self.dfg.value_lists;
+        self.dfg.results[inst].clear(pool);
+        self.dfg.results[inst].push(value, pool);
+        self.dfg.values[value] = ValueData::Inst { ty, num: 0, inst };
+        true
+    }
+
+    /// Turns a `br.if`/`br.unless` which is always taken into a `br`, removing the
+    /// instructions following it in its block, which can no longer be reached
+    fn make_unconditional(&mut self, inst: Inst) {
+        let dfg = &mut *self.dfg;
+        if let InstData::Br(br) = &mut dfg.insts[inst].data.item {
+            br.op = Opcode::Br;
+            br.args.remove(0, &mut dfg.value_lists);
+        }
+        let block = dfg.insts[inst].block;
+        let unreachable = dfg
+            .block_insts(block)
+            .skip_while(|i| *i != inst)
+            .skip(1)
+            .collect::<Vec<_>>();
+        for i in unreachable {
+            dfg.remove_inst(i);
+        }
+    }
+
+    fn known_terms(&self, values: &[Value]) -> Option<Vec<Lit>> {
+        values.iter().map(|v| self.terms.get(v).cloned()).collect()
+    }
+
+    /// Returns the known value of a branch condition, either a primitive or a boolean term
+    fn truth(&self, cond: Value) -> Option<bool> {
+        if let Some(flag) = self.flags.get(&cond) {
+            return Some(*flag);
+        }
+        match self.terms.get(&cond) {
+            Some(Lit::Atom(symbols::True)) => Some(true),
+            Some(Lit::Atom(symbols::False)) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Removes the blocks which can no longer be reached from the entry block
+pub(crate) fn remove_unreachable_blocks(dfg: &mut DataFlowGraph) {
+    let Some((entry, _)) = dfg.blocks().next() else { return; };
+    let mut reachable = BTreeSet::from([entry]);
+    let mut worklist = VecDeque::from([entry]);
+    while let Some(block) = worklist.pop_front() {
+        for inst in dfg.block_insts(block) {
+            let successors = match dfg.analyze_branch(inst) {
+                BranchInfo::SingleDest(dest, _) => vec![dest],
+                BranchInfo::MultiDest(jts) => jts.iter().map(|jt| jt.destination).collect(),
+                BranchInfo::NotABranch => continue,
+            };
+            for succ in successors {
+                if reachable.insert(succ) {
+                    worklist.push_back(succ);
+                }
+            }
+        }
+    }
+    let unreachable = dfg
+        .blocks()
+        .map(|(b, _)| b)
+        .filter(|b| !reachable.contains(b))
+        .collect::<Vec<_>>();
+    for block in unreachable {
+        dfg.remove_block(block);
+    }
+}
+
+/// Returns the BIF equivalent to a comparison/boolean instruction
+fn comparison_bif(op: Opcode) -> Option<Symbol> {
+    match op {
+        Opcode::Eq => Some(symbols::Equal),
+        Opcode::EqExact => Some(symbols::EqualStrict),
+        Opcode::Neq => Some(symbols::NotEqual),
+        Opcode::NeqExact => Some(symbols::NotEqualStrict),
+        Opcode::Gt => Some(symbols::Gt),
+        Opcode::Gte => Some(symbols::Gte),
+        Opcode::Lt => Some(symbols::Lt),
+        Opcode::Lte => Some(symbols::Lte),
+        Opcode::And | Opcode::AndAlso => Some(symbols::And),
+        Opcode::Or | Opcode::OrElse => Some(symbols::Or),
+        Opcode::Xor => Some(symbols::Xor),
+        _ => None,
+    }
+}
+
+/// Evaluates a call to `erlang:<function>/<args.len()>`, returning None if the function is
+/// not one we evaluate, or if the call would raise
+fn eval_bif(function: Symbol, args: &[Lit]) -> Option<Lit> {
+    match (function, args) {
+        (symbols::Plus, [x]) if x.is_number() => Some(x.clone()),
+        (symbols::Minus, [Lit::Integer(x)]) => Some(Lit::Integer(-x.clone())),
+        (symbols::Minus, [Lit::Float(x)]) => Some(Lit::Float(-*x)),
+        (symbols::Abs, [Lit::Integer(x)]) => Some(Lit::Integer(x.abs())),
+        (symbols::Abs, [Lit::Float(x)]) => Some(Lit::Float(x.abs())),
+        (symbols::Plus | symbols::Minus | symbols::Star | symbols::Slash, [x, y]) => {
+            eval_arith(function, x, y)
+        }
+        (symbols::Bnot, [Lit::Integer(x)]) => Some(Lit::Integer(!x.clone())),
+        (
+            symbols::Div
+            | symbols::Rem
+            | symbols::Band
+            | symbols::Bor
+            | symbols::Bxor
+            | symbols::Bsl
+            | symbols::Bsr,
+            [Lit::Integer(x), Lit::Integer(y)],
+        ) => eval_integer_op(function, x.clone(), y.clone()).map(Lit::Integer),
+        (symbols::EqualStrict, [x, y]) => Some(bool_to_lit(x == y)),
+        (symbols::NotEqualStrict, [x, y]) => Some(bool_to_lit(x != y)),
+        (symbols::Equal, [x, y]) => compare(x, y).map(|o| bool_to_lit(o == Ordering::Equal)),
+        (symbols::NotEqual, [x, y]) => compare(x, y).map(|o| bool_to_lit(o != Ordering::Equal)),
+        (symbols::Lt, [x, y]) => compare(x, y).map(|o| bool_to_lit(o == Ordering::Less)),
+        (symbols::Lte, [x, y]) => compare(x, y).map(|o| bool_to_lit(o != Ordering::Greater)),
+        (symbols::Gt, [x, y]) => compare(x, y).map(|o| bool_to_lit(o == Ordering::Greater)),
+        (symbols::Gte, [x, y]) => compare(x, y).map(|o| bool_to_lit(o != Ordering::Less)),
+        (symbols::Not, [x]) => lit_to_bool(x).map(|x| bool_to_lit(!x)),
+        (symbols::And | symbols::Or | symbols::Xor, [x, y]) => {
+            let x = lit_to_bool(x)?;
+            let y = lit_to_bool(y)?;
+            let result = match function {
+                symbols::And => x && y,
+                symbols::Or => x || y,
+                _ => x != y,
+            };
+            Some(bool_to_lit(result))
+        }
+        (symbols::Element, [Lit::Integer(index), Lit::Tuple(elements)]) => {
+            let index = index.to_usize()?;
+            if index == 0 {
+                return None;
+            }
+            elements.get(index - 1).map(|element| element.value.clone())
+        }
+        (symbols::Hd, [Lit::Cons(head, _)]) => Some(head.value.clone()),
+        (symbols::Tl, [Lit::Cons(_, tail)]) => Some(tail.value.clone()),
+        (symbols::TupleSize, [Lit::Tuple(elements)]) => {
+            Some(Lit::Integer(Integer::from(elements.len())))
+        }
+        (_, [x]) => {
+            let ty = match function {
+                symbols::IsAtom => TermType::Atom,
+                symbols::IsBoolean => TermType::Bool,
+                symbols::IsInteger => TermType::Integer,
+                symbols::IsFloat => TermType::Float,
+                symbols::IsNumber => TermType::Number,
+                symbols::IsList => TermType::MaybeImproperList,
+                symbols::IsTuple => TermType::Tuple(None),
+                symbols::IsMap => TermType::Map,
+                symbols::IsBitstring => TermType::Bitstring,
+                symbols::IsPid => TermType::Pid,
+                symbols::IsPort => TermType::Port,
+                symbols::IsReference => TermType::Reference,
+                symbols::IsFunction => TermType::Fun(None),
+                _ => return None,
+            };
+            is_type(&Type::Term(ty), x).map(bool_to_lit)
+        }
+        _ => None,
+    }
+}
+
+/// Evaluates `+`, `-`, `*` or `/`, where integers are promoted to floats if either operand
+/// is a float, or the operator is `/`
+fn eval_arith(op: Symbol, x: &Lit, y: &Lit) -> Option<Lit> {
+    match (x, y) {
+        (Lit::Integer(x), Lit::Integer(y)) if op != symbols::Slash => {
+            let (x, y) = (x.clone(), y.clone());
+            let result = match op {
+                symbols::Plus => x + y,
+                symbols::Minus => x - y,
+                _ => x * y,
+            };
+            Some(Lit::Integer(result))
+        }
+        _ => {
+            let x = lit_to_float(x)?;
+            let y = lit_to_float(y)?;
+            let result = match op {
+                symbols::Plus => (x + y).ok(),
+                symbols::Minus => (x - y).ok(),
+                symbols::Star => (x * y).ok(),
+                _ => (x / y).ok(),
+            };
+            result.map(Lit::Float)
+        }
+    }
+}
+
+fn eval_integer_op(op: Symbol, x: Integer, y: Integer) -> Option<Integer> {
+    match op {
+        symbols::Div => (x / y).ok(),
+        symbols::Rem => (x % y).ok(),
+        symbols::Band => Some(x & y),
+        symbols::Bor => Some(x | y),
+        symbols::Bxor => Some(x ^ y),
+        symbols::Bsl => (x << y).ok(),
+        _ => (x >> y).ok(),
+    }
+}
+
+fn lit_to_float(lit: &Lit) -> Option<Float> {
+    match lit {
+        Lit::Float(f) => Some(*f),
+        Lit::Integer(i) => i.to_efloat().ok(),
+        _ => None,
+    }
+}
+
+fn lit_to_bool(lit: &Lit) -> Option<bool> {
+    match lit {
+        Lit::Atom(symbols::True) => Some(true),
+        Lit::Atom(symbols::False) => Some(false),
+        _ => None,
+    }
+}
+
+fn bool_to_lit(b: bool) -> Lit {
+    Lit::Atom(if b { symbols::True } else { symbols::False })
+}
+
+/// Compares two terms in the standard term order, in which numbers compare by value
+///
+/// Returns None for maps and binaries, whose order we don't evaluate
+fn compare(x: &Lit, y: &Lit) -> Option<Ordering> {
+    // number < atom < reference < fun < port < pid < tuple < map < nil < list < bit string
+    fn rank(lit: &Lit) -> u8 {
+        match lit {
+            Lit::Integer(_) | Lit::Float(_) => 0,
+            Lit::Atom(_) => 1,
+            Lit::Tuple(_) => 2,
+            Lit::Map(_) => 3,
+            Lit::Nil => 4,
+            Lit::Cons(_, _) => 5,
+            Lit::Binary(_) => 6,
+        }
+    }
+
+    match (x, y) {
+        (Lit::Integer(_) | Lit::Float(_), Lit::Integer(_) | Lit::Float(_)) => Some(x.cmp(y)),
+        (Lit::Atom(x), Lit::Atom(y)) => Some(x.as_str().get().cmp(y.as_str().get())),
+        // Tuples are ordered by size first, then element by element
+        (Lit::Tuple(xs), Lit::Tuple(ys)) => match xs.len().cmp(&ys.len()) {
+            Ordering::Equal => compare_elements(xs.iter().zip(ys.iter())),
+            other => Some(other),
+        },
+        (Lit::Nil, Lit::Nil) => Some(Ordering::Equal),
+        (Lit::Cons(h1, t1), Lit::Cons(h2, t2)) => {
+            compare_elements([(&**h1, &**h2), (&**t1, &**t2)].into_iter())
+        }
+        (Lit::Map(_), _) | (_, Lit::Map(_)) | (Lit::Binary(_), Lit::Binary(_)) => None,
+        _ => Some(rank(x).cmp(&rank(y))),
+    }
+}
+
+fn compare_elements<'a, I>(pairs: I) -> Option<Ordering>
+where
+    I: Iterator<Item = (&'a Literal, &'a Literal)>,
+{
+    for (x, y) in pairs {
+        match compare(&x.value, &y.value)? {
+            Ordering::Equal => continue,
+            other => return Some(other),
+        }
+    }
+    Some(Ordering::Equal)
+}
+
+/// Returns whether `lit` is of type `ty`, or None if that can't be decided from the type alone
+fn is_type(ty: &Type, lit: &Lit) -> Option<bool> {
+    let Type::Term(ty) = ty else { return None; };
+    let result = match (ty, lit) {
+        (TermType::Any, _) => true,
+        (TermType::Bool, lit) => lit_to_bool(lit).is_some(),
+        (TermType::Atom, lit) => matches!(lit, Lit::Atom(_)),
+        (TermType::Integer, lit) => matches!(lit, Lit::Integer(_)),
+        (TermType::Float, lit) => matches!(lit, Lit::Float(_)),
+        (TermType::Number, lit) => lit.is_number(),
+        (TermType::Nil, lit) => matches!(lit, Lit::Nil),
+        (TermType::Cons, lit) => matches!(lit, Lit::Cons(_, _)),
+        (TermType::List(None) | TermType::MaybeImproperList, lit) => {
+            matches!(lit, Lit::Nil | Lit::Cons(_, _))
+        }
+        (TermType::Tuple(None), lit) => matches!(lit, Lit::Tuple(_)),
+        (TermType::Tuple(Some(elements)), Lit::Tuple(xs)) if elements.len() != xs.len() => false,
+        (TermType::Map, lit) => matches!(lit, Lit::Map(_)),
+        (TermType::Bitstring, lit) => matches!(lit, Lit::Binary(_)),
+        (TermType::Pid | TermType::Port | TermType::Reference | TermType::Fun(_), _) => false,
+        // These depend on more than the kind of term, e.g. the element types of a list
+        (TermType::Binary, Lit::Binary(_))
+        | (TermType::List(Some(_)), Lit::Nil | Lit::Cons(_, _))
+        | (TermType::Tuple(Some(_)), Lit::Tuple(_)) => return None,
+        (TermType::Binary | TermType::List(Some(_)) | TermType::Tuple(Some(_)), _) => false,
+    };
+    Some(result)
+}
+
+fn immediate_to_lit(imm: &Immediate) -> Option<Lit> {
+    match imm {
+        Immediate::Term(ImmediateTerm::Bool(b)) => Some(bool_to_lit(*b)),
+        Immediate::Term(ImmediateTerm::Atom(a)) => Some(Lit::Atom(*a)),
+        Immediate::Term(ImmediateTerm::Integer(i)) => Some(Lit::Integer(Integer::Small(*i))),
+        Immediate::Term(ImmediateTerm::Float(f)) => Float::new(*f).ok().map(Lit::Float),
+        Immediate::Term(ImmediateTerm::Nil) => Some(Lit::Nil),
+        _ => None,
+    }
+}
+
+fn constant_to_lit(constant: &ConstantItem) -> Option<Lit> {
+    match constant {
+        ConstantItem::Integer(i) => Some(Lit::Integer(i.clone())),
+        ConstantItem::Float(f) => Float::new(*f).ok().map(Lit::Float),
+        ConstantItem::Bool(b) => Some(bool_to_lit(*b)),
+        ConstantItem::Atom(a) => Some(Lit::Atom(*a)),
+        ConstantItem::Term(lit) => Some(lit.clone()),
+        _ => None,
+    }
+}
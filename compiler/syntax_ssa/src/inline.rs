@@ -0,0 +1,752 @@
+//! Inlines calls to local functions, automatically for small functions at `-O` and above, and for
+//! the functions named by `-compile(inline)` / `-compile({inline, [F/A]})` at any level.
+//!
+//! A call is inlined by copying the body of the callee in place of the call. The instructions
+//! which followed the call are moved to a continuation block, returns of the copied body become
+//! jumps to that block, and tail calls made by the copied body become ordinary calls followed by
+//! such a jump. When the call being inlined is itself a tail call, the body is copied as-is.
+//!
+//! A function is inlined automatically when it is no larger than `INLINE_SIZE` instructions, and
+//! makes no more than `INLINE_CALLS` calls to functions other than BIFs, i.e. inlining it saves
+//! more reductions than it costs in code size. Functions which call themselves, receive messages,
+//! or are NIFs are never inlined, and only the calls present before inlining are considered, so
+//! inlining never recurses.
+//!
+//! A local closure called directly is passed the closure it was created as, which is usually built
+//! right before the call. When inlined, the body of the closure uses the free variables the closure
+//! was built from directly, rather than unpacking them from the closure, which is then removed.
+//!
+//! Lastly, calls to `lists:map/2` with a local closure of one argument are specialized, by creating
+//! a copy of `lists:map/2` which runs the body of the closure inline for each element, and takes
+//! its free variables as extra arguments. This avoids both allocating the closure and calling it
+//! indirectly for every element of the list.
+use std::collections::{BTreeMap, BTreeSet};
+
+use firefly_diagnostics::{SourceSpan, Spanned};
+use firefly_intern::{symbols, Symbol};
+use firefly_syntax_base::*;
+
+use crate::fold::remove_unreachable_blocks;
+use crate::ir::*;
+
+/// The largest function, in instructions, which is inlined automatically
+const INLINE_SIZE: usize = 24;
+/// The most calls, other than to BIFs, a function may make and still be inlined automatically
+const INLINE_CALLS: usize = 1;
+/// Inlining into a function stops once it has grown to this many instructions
+const MAX_CALLER_SIZE: usize = 2000;
+
+pub struct Inliner {
+    auto: bool,
+}
+impl Inliner {
+    /// Creates a new inliner
+    ///
+    /// When `auto` is false, only the functions marked for inlining are inlined, and calls to
+    /// `lists:map/2` are only specialized if it was marked for inlining, i.e. `inline_list_funcs`
+    pub fn new(auto: bool) -> Self {
+        Self { auto }
+    }
+
+    pub fn inline_module(&self, module: &mut Module) {
+        let candidates = module
+            .functions
+            .iter()
+            .filter_map(|function| Candidate::new(function).map(|c| (function.id, c)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut functions = std::mem::take(&mut module.functions);
+        for i in 0..functions.len() {
+            let caller = &functions[i];
+            if caller.signature.visibility.is_externally_defined() || caller.signature.is_nif() {
+                continue;
+            }
+            let dfg = &caller.dfg;
+            let mut size = num_insts(dfg);
+            let mut sites = vec![];
+            for (block, _) in dfg.blocks() {
+                for inst in dfg.block_insts(block) {
+                    let InstData::Call(Call { callee, .. }) = &*dfg[inst] else { continue; };
+                    if *callee == caller.id {
+                        continue;
+                    }
+                    let Some(candidate) = candidates.get(callee) else { continue; };
+                    let forced = dfg.callee_signature(*callee).visibility.should_inline();
+                    if forced || (self.auto && candidate.is_small()) {
+                        sites.push((inst, *callee));
+                    }
+                }
+            }
+            if sites.is_empty() {
+                continue;
+            }
+
+            for (inst, callee) in sites {
+                if size > MAX_CALLER_SIZE {
+                    break;
+                }
+                let j = functions.iter().position(|f| f.id == callee).unwrap();
+                let (caller, callee) = if j < i {
+                    let (left, right) = functions.split_at_mut(i);
+                    (&mut right[0], &left[j])
+                } else {
+                    let (left, right) = functions.split_at_mut(j);
+                    (&mut left[i], &right[0])
+                };
+                let candidate = &candidates[&callee.id];
+                let is_closure = module.is_closure(&callee.signature.mfa().to_local());
+                inline_call(&mut caller.dfg, inst, callee, candidate, is_closure);
+                size += candidate.size;
+            }
+
+            let dfg = &mut functions[i].dfg;
+            remove_dead_closures(dfg);
+            remove_unreachable_blocks(dfg);
+        }
+        module.functions = functions;
+
+        self.specialize_map(module, &candidates);
+    }
+
+    /// Rewrites calls of `lists:map/2` with a local closure to call a specialization of it
+    fn specialize_map(&self, module: &mut Module, candidates: &BTreeMap<FuncRef, Candidate>) {
+        let map = FunctionName::new(Symbol::intern("lists"), Symbol::intern("map"), 2);
+        let Some(map_ref) = module.get_callee(map) else { return; };
+        {
+            let sig = module.call_signature(map_ref);
+            if !sig.visibility.is_externally_defined() {
+                return;
+            }
+            if !self.auto && !sig.visibility.should_inline() {
+                return;
+            }
+        }
+
+        // Find the calls first, so the fallback in each specialization is not itself specialized
+        let mut sites = vec![];
+        for (i, function) in module.functions.iter().enumerate() {
+            let dfg = &function.dfg;
+            for (block, _) in dfg.blocks() {
+                for inst in dfg.block_insts(block) {
+                    let InstData::Call(Call { callee, args, .. }) = &*dfg[inst] else { continue; };
+                    if *callee != map_ref {
+                        continue;
+                    }
+                    let args = args.as_slice(&dfg.value_lists);
+                    let Some((closure, env)) = closure_env(dfg, args[0]) else { continue; };
+                    let Some(candidate) = candidates.get(&closure) else { continue; };
+                    let sig = dfg.callee_signature(closure);
+                    if sig.arity() == 2
+                        && candidate.unpacks_env
+                        && env.len() >= candidate.env_size
+                        && module.is_closure(&sig.mfa().to_local())
+                    {
+                        sites.push((i, inst, closure, env.len()));
+                    }
+                }
+            }
+        }
+
+        let mut specializations = BTreeMap::<FuncRef, FuncRef>::new();
+        let mut modified = BTreeSet::new();
+        for (i, inst, closure, env_size) in sites {
+            let spec = match specializations.get(&closure) {
+                Some(spec) => *spec,
+                None => {
+                    let spec = specialize_map(module, map_ref, closure, env_size);
+                    specializations.insert(closure, spec);
+                    spec
+                }
+            };
+            let dfg = &mut module.functions[i].dfg;
+            let (fun, list) = {
+                let args = dfg.inst_args(inst);
+                (args[0], args[1])
+            };
+            let (_, env) = closure_env(dfg, fun).unwrap();
+            let InstData::Call(call) = &mut dfg.insts[inst].data.item else { unreachable!() };
+            call.callee = spec;
+            call.args.clear(&mut dfg.value_lists);
+            call.args.push(list, &mut dfg.value_lists);
+            call.args.extend(env, &mut dfg.value_lists);
+            modified.insert(i);
+        }
+
+        for i in modified {
+            let dfg = &mut module.functions[i].dfg;
+            remove_dead_closures(dfg);
+            remove_unreachable_blocks(dfg);
+        }
+    }
+}
+
+/// What is known about a function which may be inlined
+struct Candidate {
+    /// The number of instructions in the function
+    size: usize,
+    /// The number of calls made by the function, other than to BIFs
+    calls: usize,
+    /// If the function is a closure, this is true when its closure argument is only used to
+    /// unpack its free variables, so the free variables can be used directly instead
+    unpacks_env: bool,
+    /// The number of free variables unpacked from the closure argument
+    env_size: usize,
+}
+impl Candidate {
+    /// Returns `None` if the given function can never be inlined
+    fn new(function: &Function) -> Option<Self> {
+        let visibility = function.signature.visibility;
+        if visibility.is_externally_defined() || visibility.is_nif() {
+            return None;
+        }
+        let dfg = &function.dfg;
+        let (entry, _) = dfg.blocks().next()?;
+        let fun = dfg.block_params(entry).last().copied();
+        let mut size = 0;
+        let mut calls = 0;
+        let mut unpacks_env = fun.is_some();
+        let mut env_size = 0;
+        for (block, _) in dfg.blocks() {
+            for inst in dfg.block_insts(block) {
+                size += 1;
+                match &*dfg[inst] {
+                    InstData::Call(Call { callee, .. })
+                    | InstData::MakeFun(MakeFun { callee, .. })
+                        if *callee == function.id =>
+                    {
+                        return None;
+                    }
+                    InstData::Call(Call { callee, .. }) => {
+                        if dfg.callee_signature(*callee).module != symbols::Erlang {
+                            calls += 1;
+                        }
+                    }
+                    InstData::CallIndirect(_) => calls += 1,
+                    InstData::BinaryOpImm(BinaryOpImm {
+                        op: Opcode::UnpackEnv,
+                        arg,
+                        imm,
+                    }) if Some(*arg) == fun => {
+                        let index = imm.as_i64().unwrap() as usize;
+                        env_size = env_size.max(index + 1);
+                        continue;
+                    }
+                    data => match data.opcode() {
+                        Opcode::Raise
+                        | Opcode::RecvStart
+                        | Opcode::RecvNext
+                        | Opcode::RecvPeek
+                        | Opcode::RecvPop
+                        | Opcode::RecvWait
+                        | Opcode::RecvDone
+                        | Opcode::NifStart => return None,
+                        _ => (),
+                    },
+                }
+                if let Some(fun) = fun {
+                    if uses_of(dfg, inst).contains(&fun) {
+                        unpacks_env = false;
+                    }
+                }
+            }
+        }
+        Some(Self {
+            size,
+            calls,
+            unpacks_env,
+            env_size,
+        })
+    }
+
+    fn is_small(&self) -> bool {
+        self.size <= INLINE_SIZE && self.calls <= INLINE_CALLS
+    }
+}
+
+/// Replaces the given call in `dfg` with a copy of the body of `callee`
+fn inline_call(
+    dfg: &mut DataFlowGraph,
+    inst: Inst,
+    callee: &Function,
+    candidate: &Candidate,
+    is_closure: bool,
+) {
+    let span = dfg[inst].span();
+    let block = dfg.insts[inst].block;
+    let mut args = dfg.inst_args(inst).to_vec();
+    let results = dfg.inst_results(inst).to_vec();
+
+    // If the closure passed to a closure is built in this function, use its free variables directly
+    let mut env = None;
+    if is_closure && candidate.unpacks_env {
+        if let Some((fun, free)) = args.last().and_then(|fun| closure_env(dfg, *fun)) {
+            if fun == callee.id && free.len() >= candidate.env_size {
+                args.pop();
+                env = Some(free);
+            }
+        }
+    }
+
+    let exit = if dfg[inst].opcode() == Opcode::Call {
+        // Everything after the call continues in a new block, which receives the call results
+        let cont = dfg.split_block_after(inst);
+        for (num, value) in results.iter().copied().enumerate() {
+            let ty = dfg.value_type(value);
+            dfg.blocks[cont].params.push(value, &mut dfg.value_lists);
+            dfg.values[value] = ValueData::Param {
+                ty,
+                num: num as u16,
+                block: cont,
+                span,
+            };
+        }
+        dfg.results[inst].clear(&mut dfg.value_lists);
+        Exit::Continue(cont)
+    } else {
+        Exit::Return
+    };
+
+    let entry = Copier::new(&callee.dfg, dfg, exit).copy(block, env.as_deref());
+    let args = ValueList::from_slice(args.as_slice(), &mut dfg.value_lists);
+    dfg[inst].item = InstData::Br(Br {
+        op: Opcode::Br,
+        destination: entry,
+        args,
+    });
+}
+
+/// Creates a specialization of `lists:map/2` for the given closure, with `env_size` free variables,
+/// and returns its reference
+///
+/// The specialization takes the list, followed by the free variables of the closure:
+///
+/// ```text,ignore
+/// map(L, Env...) when is_list(L), L =/= [] ->
+///     [Closure(hd(L), Env...) | map(tl(L), Env...)];
+/// map([], Env...) -> [];
+/// map(L, Env...) -> lists:map(make_fun(Closure, Env...), L).
+/// ```
+fn specialize_map(module: &mut Module, map: FuncRef, closure: FuncRef, env_size: usize) -> FuncRef {
+    let (name, span) = {
+        let function = module.get_function(closure).unwrap();
+        let name = format!("-map/2-{}-", function.signature.name);
+        (Symbol::intern(&name), function.span)
+    };
+    let mut params = vec![];
+    params.resize(env_size + 1, Type::Term(TermType::Any));
+    let signature = Signature::new(
+        Visibility::DEFAULT,
+        CallConv::Erlang,
+        module.name(),
+        name,
+        FunctionType::new(
+            params,
+            vec![
+                Type::Primitive(PrimitiveType::I1),
+                Type::Term(TermType::Any),
+            ],
+        ),
+    );
+    let id = module.declare_function(signature.clone());
+    let mut spec = Function::new(
+        id,
+        span,
+        signature,
+        module.signatures.clone(),
+        module.callees.clone(),
+        module.constants.clone(),
+    );
+
+    let dfg = &mut spec.dfg;
+    let entry = dfg.make_block();
+    let list = dfg.append_block_param(entry, Type::Term(TermType::Any), span);
+    let env = (0..env_size)
+        .map(|_| dfg.append_block_param(entry, Type::Term(TermType::Any), span))
+        .collect::<Vec<_>>();
+    let cons_block = dfg.make_block();
+    let mapped = dfg.make_block();
+    let is_err = dfg.append_block_param(mapped, Type::Primitive(PrimitiveType::I1), span);
+    let value = dfg.append_block_param(mapped, Type::Term(TermType::Any), span);
+    let not_cons = dfg.make_block();
+    let fallback = dfg.make_block();
+    let failed = dfg.make_block();
+    let exception = dfg.append_block_param(failed, Type::Term(TermType::Any), span);
+
+    let is_cons = ins(dfg, entry).is_type(Type::Term(TermType::Cons), list, span);
+    ins(dfg, entry).cond_br(is_cons, cons_block, &[], not_cons, &[], span);
+
+    // Apply the body of the closure to the head, then map the tail
+    let cons = ins(dfg, cons_block).cast(list, Type::Term(TermType::Cons), span);
+    let head = ins(dfg, cons_block).head(cons, span);
+    let tail = ins(dfg, cons_block).tail(cons, span);
+    let body = {
+        let closure = module.get_function(closure).unwrap();
+        Copier::new(&closure.dfg, dfg, Exit::Continue(mapped)).copy(cons_block, Some(&env))
+    };
+    ins(dfg, cons_block).br(body, &[head], span);
+    ins(dfg, mapped).br_if(is_err, failed, &[value], span);
+    let mut args = vec![tail];
+    args.extend_from_slice(&env);
+    let call = ins(dfg, mapped).call(id, &args, span);
+    let (is_err, rest) = {
+        let results = dfg.inst_results(call);
+        (results[0], results[1])
+    };
+    ins(dfg, mapped).br_if(is_err, failed, &[rest], span);
+    let result = ins(dfg, mapped).cons(value, rest, span);
+    ins(dfg, mapped).ret_ok(result, span);
+
+    ins(dfg, failed).ret_err(exception, span);
+
+    // The end of the list
+    let is_nil = ins(dfg, not_cons).is_type(Type::Term(TermType::Nil), list, span);
+    ins(dfg, not_cons).br_unless(is_nil, fallback, &[], span);
+    let nil = ins(dfg, not_cons).nil(span);
+    ins(dfg, not_cons).ret_ok(nil, span);
+
+    // Not a list, let lists:map/2 raise the error with the closure it was originally given
+    let make_fun = ins(dfg, fallback).make_fun(closure, &env, span);
+    let (is_err, fun) = {
+        let results = dfg.inst_results(make_fun);
+        (results[0], results[1])
+    };
+    ins(dfg, fallback).br_if(is_err, failed, &[fun], span);
+    ins(dfg, fallback).enter(map, &[fun, list], span);
+
+    module.define_function(spec);
+    id
+}
+
+/// Where control goes when a copied function body returns
+#[derive(Copy, Clone)]
+enum Exit {
+    /// The body replaces a tail call, so it returns from the function it is copied into
+    Return,
+    /// The body replaces a call, so returning jumps to the given block with the flag and result
+    Continue(Block),
+}
+
+/// Copies the body of a function into another function
+struct Copier<'a> {
+    src: &'a DataFlowGraph,
+    dst: &'a mut DataFlowGraph,
+    exit: Exit,
+    values: BTreeMap<Value, Value>,
+    blocks: BTreeMap<Block, Block>,
+}
+impl<'a> Copier<'a> {
+    fn new(src: &'a DataFlowGraph, dst: &'a mut DataFlowGraph, exit: Exit) -> Self {
+        Self {
+            src,
+            dst,
+            exit,
+            values: BTreeMap::new(),
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// Copies the body, placing its blocks after `after`, and returns the copy of the entry block
+    ///
+    /// If `env` is given, the body is that of a closure, and the free variables are used in place
+    /// of unpacking them from the closure, which is dropped from the parameters of the entry block
+    fn copy(mut self, after: Block, env: Option<&[Value]>) -> Block {
+        let src = self.src;
+        let (entry, _) = src.blocks().next().unwrap();
+        let fun = env.and_then(|_| src.block_params(entry).last().copied());
+
+        // Values may be used before they are defined in layout order, so map them all up front
+        let mut unpacked = BTreeSet::new();
+        let mut last = after;
+        for (block, _) in src.blocks() {
+            let copy = self.dst.make_block_after(last);
+            last = copy;
+            self.blocks.insert(block, copy);
+            for param in src.block_params(block).iter().copied() {
+                if Some(param) == fun {
+                    continue;
+                }
+                let ValueData::Param { ty, span, .. } = src.get_value(param) else {
+                    unreachable!()
+                };
+                let value = self.dst.append_block_param(copy, ty, span);
+                self.values.insert(param, value);
+            }
+            for inst in src.block_insts(block) {
+                if let (Some(fun), Some(env)) = (fun, env) {
+                    if let InstData::BinaryOpImm(BinaryOpImm {
+                        op: Opcode::UnpackEnv,
+                        arg,
+                        imm,
+                    }) = &*src[inst]
+                    {
+                        if *arg == fun {
+                            let index = imm.as_i64().unwrap() as usize;
+                            self.values.insert(src.first_result(inst), env[index]);
+                            unpacked.insert(inst);
+                            continue;
+                        }
+                    }
+                }
+                // The definition of these is fixed up once the instruction is copied
+                for result in src.inst_results(inst).iter().copied() {
+                    let value = self.dst.make_value(src.get_value(result));
+                    self.values.insert(result, value);
+                }
+            }
+        }
+
+        for (block, _) in src.blocks() {
+            for inst in src.block_insts(block) {
+                if !unpacked.contains(&inst) {
+                    self.copy_inst(block, inst);
+                }
+            }
+        }
+
+        self.blocks[&entry]
+    }
+
+    fn copy_inst(&mut self, block: Block, inst: Inst) {
+        let src = self.src;
+        let block = self.blocks[&block];
+        let span = src[inst].span();
+        let data = &*src[inst];
+        if let Exit::Continue(cont) = self.exit {
+            match data {
+                InstData::Ret(Ret { args, .. }) => {
+                    let args = [self.values[&args[0]], self.values[&args[1]]];
+                    ins(self.dst, block).br(cont, &args, span);
+                    return;
+                }
+                InstData::RetImm(RetImm { imm, arg, .. }) => {
+                    let Immediate::I1(is_err) = imm else { panic!("invalid return flag") };
+                    let is_err = ins(self.dst, block).i1(*is_err, span);
+                    ins(self.dst, block).br(cont, &[is_err, self.values[arg]], span);
+                    return;
+                }
+                InstData::Call(Call {
+                    op: Opcode::Enter,
+                    callee,
+                    args,
+                }) => {
+                    let args = self.map_values(args.as_slice(&src.value_lists));
+                    let call = ins(self.dst, block).call(*callee, &args, span);
+                    let results = self.dst.inst_results(call).to_vec();
+                    ins(self.dst, block).br(cont, &results, span);
+                    return;
+                }
+                InstData::CallIndirect(CallIndirect {
+                    op: Opcode::EnterIndirect,
+                    callee,
+                    args,
+                }) => {
+                    let args = self.map_values(args.as_slice(&src.value_lists));
+                    let call = ins(self.dst, block).call_indirect(self.values[callee], &args, span);
+                    let results = self.dst.inst_results(call).to_vec();
+                    ins(self.dst, block).br(cont, &results, span);
+                    return;
+                }
+                _ => (),
+            }
+        }
+
+        let data = self.map_inst_data(data);
+        let copy = self.dst.push_inst(block, data, span);
+        for (num, result) in src.inst_results(inst).iter().enumerate() {
+            let value = self.values[result];
+            self.dst.results[copy].push(value, &mut self.dst.value_lists);
+            self.dst.values[value] = ValueData::Inst {
+                ty: src.value_type(*result),
+                num: num as u16,
+                inst: copy,
+            };
+        }
+        self.dst.inst_annotations[copy] = src.inst_annotations[inst].clone();
+    }
+
+    fn map_inst_data(&mut self, data: &InstData) -> InstData {
+        let mut data = data.clone();
+        // Value lists refer to the pool of the source function, so are recreated in the destination
+        match &mut data {
+            InstData::Call(Call { args, .. })
+            | InstData::CallIndirect(CallIndirect { args, .. })
+            | InstData::MakeFun(MakeFun { env: args, .. })
+            | InstData::Br(Br { args, .. })
+            | InstData::PrimOp(PrimOp { args, .. })
+            | InstData::PrimOpImm(PrimOpImm { args, .. })
+            | InstData::BitsMatch(BitsMatch { args, .. })
+            | InstData::BitsMatchSkip(BitsMatchSkip { args, .. })
+            | InstData::BitsPush(BitsPush { args, .. }) => *args = self.map_list(args),
+            InstData::CondBr(CondBr {
+                then_dest,
+                else_dest,
+                ..
+            }) => {
+                then_dest.1 = self.map_list(&then_dest.1);
+                else_dest.1 = self.map_list(&else_dest.1);
+            }
+            _ => (),
+        }
+        // The remaining operands, and the destinations of branches
+        match &mut data {
+            InstData::BinaryOp(crate::ir::BinaryOp { args, .. })
+            | InstData::Ret(Ret { args, .. })
+            | InstData::SetElement(SetElement { args, .. }) => {
+                args[0] = self.values[&args[0]];
+                args[1] = self.values[&args[1]];
+            }
+            InstData::BinaryOpImm(BinaryOpImm { arg, .. })
+            | InstData::UnaryOp(crate::ir::UnaryOp { arg, .. })
+            | InstData::RetImm(RetImm { arg, .. })
+            | InstData::IsType(IsType { arg, .. })
+            | InstData::SetElementImm(SetElementImm { arg, .. }) => *arg = self.values[arg],
+            InstData::CallIndirect(CallIndirect { callee, .. }) => *callee = self.values[callee],
+            InstData::Br(Br { destination, .. }) => *destination = self.blocks[destination],
+            InstData::CondBr(CondBr {
+                cond,
+                then_dest,
+                else_dest,
+            }) => {
+                *cond = self.values[cond];
+                then_dest.0 = self.blocks[&then_dest.0];
+                else_dest.0 = self.blocks[&else_dest.0];
+            }
+            InstData::Switch(Switch {
+                arg, arms, default, ..
+            }) => {
+                *arg = self.values[arg];
+                for (_, dest) in arms.iter_mut() {
+                    *dest = self.blocks[dest];
+                }
+                *default = self.blocks[default];
+            }
+            _ => (),
+        }
+        data
+    }
+
+    fn map_list(&mut self, list: &ValueList) -> ValueList {
+        let values = self.map_values(list.as_slice(&self.src.value_lists));
+        ValueList::from_slice(values.as_slice(), &mut self.dst.value_lists)
+    }
+
+    fn map_values(&self, values: &[Value]) -> Vec<Value> {
+        values.iter().map(|v| self.values[v]).collect()
+    }
+}
+
+/// Appends instructions to the end of a block
+struct BlockBuilder<'f> {
+    dfg: &'f mut DataFlowGraph,
+    block: Block,
+}
+impl<'f> InstBuilderBase<'f> for BlockBuilder<'f> {
+    fn data_flow_graph(&self) -> &DataFlowGraph {
+        self.dfg
+    }
+
+    fn data_flow_graph_mut(&mut self) -> &mut DataFlowGraph {
+        self.dfg
+    }
+
+    fn build(self, data: InstData, ty: Type, span: SourceSpan) -> (Inst, &'f mut DataFlowGraph) {
+        let inst = self.dfg.push_inst(self.block, data, span);
+        self.dfg.make_inst_results(inst, ty);
+        (inst, self.dfg)
+    }
+}
+
+fn ins(dfg: &mut DataFlowGraph, block: Block) -> BlockBuilder<'_> {
+    BlockBuilder { dfg, block }
+}
+
+/// If the given value is a closure built in this function, returns the function it closes over,
+/// and the free variables it was built with
+fn closure_env(dfg: &DataFlowGraph, fun: Value) -> Option<(FuncRef, Vec<Value>)> {
+    let ValueData::Inst { inst, num: 1, .. } = dfg.get_value(fun) else { return None; };
+    match &*dfg[inst] {
+        InstData::MakeFun(MakeFun { callee, env }) => {
+            Some((*callee, env.as_slice(&dfg.value_lists).to_vec()))
+        }
+        _ => None,
+    }
+}
+
+/// Removes closures which are no longer used, other than by the check that building them succeeded
+fn remove_dead_closures(dfg: &mut DataFlowGraph) {
+    let mut uses = BTreeMap::<Value, Vec<Inst>>::new();
+    let mut closures = vec![];
+    for (block, _) in dfg.blocks() {
+        for inst in dfg.block_insts(block) {
+            if let InstData::MakeFun(_) = &*dfg[inst] {
+                closures.push(inst);
+            }
+            for value in uses_of(dfg, inst) {
+                uses.entry(value).or_default().push(inst);
+            }
+        }
+    }
+
+    for inst in closures {
+        let (is_err, fun) = {
+            let results = dfg.inst_results(inst);
+            (results[0], results[1])
+        };
+        let mut checks = BTreeSet::new();
+        let mut dead = true;
+        for user in uses
+            .get(&is_err)
+            .into_iter()
+            .chain(uses.get(&fun))
+            .flatten()
+            .copied()
+        {
+            let is_check = match &*dfg[user] {
+                InstData::Br(Br {
+                    op: Opcode::BrIf,
+                    args,
+                    ..
+                }) => {
+                    let args = args.as_slice(&dfg.value_lists);
+                    args[0] == is_err && !args[1..].contains(&is_err)
+                }
+                _ => false,
+            };
+            if !is_check {
+                dead = false;
+                break;
+            }
+            checks.insert(user);
+        }
+        if dead {
+            for check in checks {
+                dfg.remove_inst(check);
+            }
+            dfg.remove_inst(inst);
+        }
+    }
+}
+
+/// Returns every value used by the given instruction, including those passed to successor blocks
+fn uses_of(dfg: &DataFlowGraph, inst: Inst) -> Vec<Value> {
+    let mut values = dfg.inst_args(inst).to_vec();
+    match &*dfg[inst] {
+        InstData::CallIndirect(CallIndirect { callee, .. }) => values.push(*callee),
+        InstData::CondBr(CondBr {
+            then_dest,
+            else_dest,
+            ..
+        }) => {
+            values.extend_from_slice(then_dest.1.as_slice(&dfg.value_lists));
+            values.extend_from_slice(else_dest.1.as_slice(&dfg.value_lists));
+        }
+        _ => (),
+    }
+    values
+}
+
+fn num_insts(dfg: &DataFlowGraph) -> usize {
+    dfg.blocks()
+        .map(|(block, _)| dfg.block_insts(block).count())
+        .sum()
+}
@@ -2,7 +2,7 @@ use firefly_binary::{BinaryEntrySpecifier, BitVec};
 use firefly_diagnostics::SourceSpan;
 use firefly_intern::{Ident, Symbol};
 use firefly_number::{BigInt, Integer};
-use firefly_syntax_base::{PrimitiveType, TermType, Type};
+use firefly_syntax_base::{Lit, PrimitiveType, TermType, Type};
 
 use super::*;
 
@@ -354,6 +354,18 @@ pub trait InstBuilder<'f>: InstBuilderBase<'f> {
         dfg.first_result(inst)
     }
 
+    /// Builds a reference to a constant tuple or list, which is allocated statically rather than
+    /// constructed on the heap at runtime
+    fn literal(mut self, lit: Lit, span: SourceSpan) -> Value {
+        let constant = {
+            self.data_flow_graph_mut()
+                .make_constant(ConstantItem::Term(lit))
+        };
+        let ty = self.data_flow_graph().constant(constant).ty();
+        let (inst, dfg) = self.UnaryConst(Opcode::ConstTerm, ty, constant, span);
+        dfg.first_result(inst)
+    }
+
     fn is_null(self, arg: Value, span: SourceSpan) -> Value {
         let (inst, dfg) = self.Unary(
             Opcode::IsNull,
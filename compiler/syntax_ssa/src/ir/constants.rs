@@ -5,7 +5,7 @@ use std::hash::{Hash, Hasher};
 use firefly_binary::{BitVec, Bitstring};
 use firefly_intern::{symbols, Symbol};
 use firefly_number::{Float, Integer};
-use firefly_syntax_base::{PrimitiveType, TermType, Type};
+use firefly_syntax_base::{Lit, PrimitiveType, TermType, Type};
 
 use cranelift_entity::entity_impl;
 
@@ -495,6 +495,8 @@ pub enum ConstantItem {
     Bitstring(BitVec),
     String(String),
     InternedStr(Symbol),
+    /// A constant tuple or list, emitted by codegen into read-only data
+    Term(Lit),
 }
 impl Eq for ConstantItem {}
 impl PartialEq for ConstantItem {
@@ -536,6 +538,8 @@ impl PartialEq for ConstantItem {
                 Self::InternedStr(y) => x.eq(y),
                 _ => false,
             },
+            (Self::Term(x), Self::Term(y)) => x.eq(y),
+            (Self::Term(_), _) => false,
         }
     }
 }
@@ -555,6 +559,7 @@ impl Hash for ConstantItem {
             Self::Bitstring(b) => b.hash(state),
             Self::String(b) => b.as_bytes().hash(state),
             Self::InternedStr(b) => b.as_str().get().as_bytes().hash(state),
+            Self::Term(lit) => lit.hash(state),
         }
     }
 }
@@ -568,6 +573,9 @@ impl ConstantItem {
             Self::Bitstring(_) | Self::Bytes(_) | Self::String(_) | Self::InternedStr(_) => {
                 Type::Term(TermType::Bitstring)
             }
+            Self::Term(Lit::Tuple(_)) => Type::Term(TermType::Tuple(None)),
+            Self::Term(Lit::Cons(_, _)) => Type::Term(TermType::Cons),
+            Self::Term(_) => Type::Term(TermType::Any),
         }
     }
 
@@ -582,6 +590,7 @@ impl ConstantItem {
             Self::Bitstring(b) => b.byte_size(),
             Self::String(b) => b.as_bytes().len(),
             Self::InternedStr(b) => b.as_str().get().as_bytes().len(),
+            Self::Term(_) => 8,
         }
     }
 }
@@ -619,6 +628,7 @@ impl fmt::Display for ConstantItem {
                 }
                 write!(f, "\"")
             }
+            Self::Term(lit) => write!(f, "{:?}", lit),
         }
     }
 }
@@ -118,6 +118,20 @@ impl DataFlowGraph {
         inst
     }
 
+    /// Unlinks the given instruction from its block, and removes it from the graph
+    ///
+    /// NOTE: It is up to the caller to ensure that the results of the instruction are no longer
+    /// used
+    pub fn remove_inst(&mut self, inst: Inst) {
+        let block = self.insts[inst].block;
+        let node: *const InstNode = &self.insts[inst];
+        unsafe {
+            let mut cursor = self.blocks[block].insts.cursor_mut_from_ptr(node);
+            cursor.remove();
+        }
+        self.insts.remove(inst);
+    }
+
     pub fn inst_args(&self, inst: Inst) -> &[Value] {
         self.insts[inst].arguments(&self.value_lists)
     }
@@ -205,7 +219,8 @@ impl DataFlowGraph {
                 | Opcode::ImmNone
                 | Opcode::ImmNull
                 | Opcode::ConstBigInt
-                | Opcode::ConstBinary => {
+                | Opcode::ConstBinary
+                | Opcode::ConstTerm => {
                     self.append_result(inst, ty);
                     1
                 }
@@ -423,6 +438,33 @@ impl DataFlowGraph {
         self.blocks.push(BlockData::new())
     }
 
+    /// Like `make_block`, but places the new block directly after `after` in the layout
+    pub fn make_block_after(&mut self, after: Block) -> Block {
+        let block = self.blocks.create();
+        self.blocks.insert_after(block, after, BlockData::new());
+        block
+    }
+
+    /// Splits the block containing the given instruction in two, moving every instruction
+    /// which follows it to a new block, placed directly after the original in the layout
+    ///
+    /// NOTE: The new block has no parameters, and nothing branches to it, that is up to the caller
+    pub fn split_block_after(&mut self, inst: Inst) -> Block {
+        let block = self.insts[inst].block;
+        let split = self.make_block_after(block);
+        let node: *const InstNode = &self.insts[inst];
+        let tail = unsafe {
+            let mut cursor = self.blocks[block].insts.cursor_mut_from_ptr(node);
+            cursor.split_after()
+        };
+        self.blocks[split].insts = tail;
+        let moved = self.blocks[split].insts().collect::<Vec<_>>();
+        for inst in moved {
+            self.insts[inst].block = split;
+        }
+        split
+    }
+
     pub fn remove_block(&mut self, block: Block) {
         self.blocks.remove(block);
     }
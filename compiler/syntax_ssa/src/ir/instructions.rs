@@ -242,6 +242,7 @@ pub enum Opcode {
     ImmNull,
     ConstBigInt,
     ConstBinary,
+    ConstTerm,
     IsNull,
     Cast,
     Trunc,
@@ -357,7 +358,8 @@ impl Opcode {
             | Self::ImmNone
             | Self::ImmNull
             | Self::ConstBigInt
-            | Self::ConstBinary => 0,
+            | Self::ConstBinary
+            | Self::ConstTerm => 0,
             // Binary ops always have two
             Self::Add
             | Self::Sub
@@ -453,6 +455,7 @@ impl fmt::Display for Opcode {
             Self::ImmNull => f.write_str("null"),
             Self::ConstBigInt => f.write_str("const.bigint"),
             Self::ConstBinary => f.write_str("const.binary"),
+            Self::ConstTerm => f.write_str("const.term"),
             Self::IsNull => f.write_str("is_null"),
             Self::Cast => f.write_str("cast"),
             Self::Trunc => f.write_str("trunc"),
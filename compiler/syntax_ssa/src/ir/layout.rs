@@ -2,9 +2,9 @@ use std::ops::{Index, IndexMut};
 use std::ptr::NonNull;
 
 use cranelift_entity::EntityRef;
+use firefly_arena::TypedArena;
 use intrusive_collections::linked_list::{Cursor, CursorMut, LinkedList};
 use intrusive_collections::{intrusive_adapter, LinkedListLink, UnsafeRef};
-use firefly_arena::TypedArena;
 
 /// This struct holds the data for each node in an ArenaMap/OrderedArenaMap
 #[derive(Clone)]
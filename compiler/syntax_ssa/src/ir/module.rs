@@ -284,4 +284,57 @@ impl Module {
     pub fn get_function_mut(&mut self, id: FuncRef) -> Option<&mut Function> {
         self.functions.iter_mut().find(|f| f.id == id)
     }
+
+    /// Returns the set of functions exported by this module
+    pub fn exports(&self) -> BTreeSet<FunctionName> {
+        self.functions
+            .iter()
+            .filter(|f| f.signature.visibility.is_public())
+            .map(|f| f.signature.mfa())
+            .collect()
+    }
+
+    /// Returns the set of atoms referenced by this module
+    ///
+    /// This includes the module name, the names of any modules called by this module,
+    /// atoms used as instruction immediates, and atoms in the constant pool.
+    pub fn atoms(&self) -> BTreeSet<Symbol> {
+        let mut atoms = BTreeSet::new();
+        atoms.insert(self.name.name);
+        for sig in self.signatures.borrow().values() {
+            if sig.module != symbols::Empty {
+                atoms.insert(sig.module);
+            }
+        }
+        for constant in self.constants.borrow().values() {
+            if let ConstantItem::Atom(a) = constant {
+                atoms.insert(*a);
+            }
+        }
+        for function in self.functions.iter() {
+            let dfg = &function.dfg;
+            for (block, _) in dfg.blocks() {
+                for inst in dfg.block_insts(block) {
+                    let imm = match &**dfg.insts[inst] {
+                        InstData::BinaryOpImm(BinaryOpImm { imm, .. })
+                        | InstData::UnaryOpImm(UnaryOpImm { imm, .. })
+                        | InstData::RetImm(RetImm { imm, .. })
+                        | InstData::PrimOpImm(PrimOpImm { imm, .. })
+                        | InstData::SetElementImm(SetElementImm { value: imm, .. }) => imm,
+                        _ => continue,
+                    };
+                    match imm {
+                        Immediate::Term(ImmediateTerm::Atom(a)) => {
+                            atoms.insert(*a);
+                        }
+                        Immediate::Term(ImmediateTerm::Bool(b)) => {
+                            atoms.insert(if *b { symbols::True } else { symbols::False });
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+        atoms
+    }
 }
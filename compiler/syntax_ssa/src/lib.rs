@@ -1,5 +1,11 @@
 #![deny(warnings)]
+#![feature(let_else)]
+pub mod callgraph;
+pub mod cost;
+pub mod fold;
+pub mod inline;
 pub mod ir;
+pub mod typecheck;
 pub mod write;
 
 pub use self::ir::*;
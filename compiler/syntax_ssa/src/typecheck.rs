@@ -0,0 +1,471 @@
+//! Checks the functions in a module against their type specifications, enabled via `--analyze`.
+//!
+//! In the spirit of success typing, we only report what is definitely wrong, never what merely
+//! might be. To that end, terms are approximated by the kinds they belong to (see [`TermKinds`]),
+//! which we infer by a forward dataflow analysis over each function, starting from the kinds its
+//! spec admits for its parameters, and refined along each branch by the type tests guarding it.
+//! From this we report:
+//!
+//! * calls to a function with a spec, none of whose clauses accept the kinds of the arguments
+//! * returns of values whose kinds are disjoint from the return type of the function's own spec
+//! * patterns and guards which can never succeed, as the value they test can never be of the
+//! kinds they require, which make the clause they belong to unreachable
+//!
+//! Only the specs of the module being compiled are known, calls to other modules are assumed to
+//! accept and return any term.
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use cranelift_entity::PrimaryMap;
+
+use firefly_diagnostics::{Diagnostic, Label, Reporter, SourceSpan, Spanned};
+use firefly_intern::symbols;
+use firefly_syntax_base::{FunctionName, KindSignature, Signature, TermKinds, TermType, Type};
+
+use crate::ir::*;
+
+/// The kinds known for values at some point in a function, for those values whose kinds are more
+/// precise than their type, see [`Analysis::kinds`]
+type Env = BTreeMap<Value, TermKinds>;
+
+/// A test of the kinds of a value, whose boolean result is used as a branch condition
+#[derive(Copy, Clone)]
+enum Test {
+    /// The condition is true if `value` is one of `kinds`, and if `exact`, false otherwise.
+    ///
+    /// A test is inexact if it may fail for some values of those kinds, e.g. `is_boolean/1`
+    IsKind {
+        value: Value,
+        kinds: TermKinds,
+        exact: bool,
+        span: SourceSpan,
+    },
+    /// The condition is the error flag of a call, if true, `value` holds an exception rather
+    /// than the return value of the callee
+    IsErr { value: Value },
+}
+
+pub struct TypeCheck {
+    reporter: Reporter,
+    specs: BTreeMap<FunctionName, Vec<KindSignature>>,
+}
+impl TypeCheck {
+    pub fn new(reporter: Reporter, specs: BTreeMap<FunctionName, Vec<KindSignature>>) -> Self {
+        Self { reporter, specs }
+    }
+
+    pub fn check_module(&self, module: &Module) {
+        let signatures = module.signatures.borrow();
+        for function in module.functions.iter() {
+            if function.signature.visibility.is_externally_defined() {
+                continue;
+            }
+            let mut analysis = Analysis {
+                function,
+                signatures: &signatures,
+                specs: &self.specs,
+                tests: BTreeMap::new(),
+                states: BTreeMap::new(),
+                reporter: None,
+                reported: BTreeSet::new(),
+            };
+            analysis.run(&self.reporter);
+        }
+    }
+}
+
+struct Analysis<'a> {
+    function: &'a Function,
+    signatures: &'a PrimaryMap<FuncRef, Signature>,
+    specs: &'a BTreeMap<FunctionName, Vec<KindSignature>>,
+    /// The tests whose results are used as conditions, keyed by the condition
+    tests: BTreeMap<Value, Test>,
+    /// The kinds known on entry to each reachable block
+    states: BTreeMap<Block, Env>,
+    /// Set once the analysis has reached a fixpoint, so that we only report what holds in the end
+    reporter: Option<&'a Reporter>,
+    reported: BTreeSet<SourceSpan>,
+}
+impl<'a> Analysis<'a> {
+    fn run(&mut self, reporter: &'a Reporter) {
+        let function = self.function;
+        let dfg = &function.dfg;
+        let Some((entry, _)) = dfg.blocks().next() else { return; };
+
+        self.collect_tests();
+
+        // On entry, the parameters are whatever the spec admits
+        let mut env = Env::new();
+        if let Some(sigs) = self.specs.get(&function.signature.mfa()) {
+            for (i, param) in dfg.block_params(entry).iter().copied().enumerate() {
+                let kinds = sigs
+                    .iter()
+                    .filter_map(|sig| sig.params.get(i).copied())
+                    .fold(TermKinds::NONE, TermKinds::union);
+                env.insert(param, kinds.intersection(self.base_kinds(param)));
+            }
+        }
+        self.states.insert(entry, env);
+
+        let mut worklist = VecDeque::from([entry]);
+        while let Some(block) = worklist.pop_front() {
+            for succ in self.visit(block) {
+                if !worklist.contains(&succ) {
+                    worklist.push_back(succ);
+                }
+            }
+        }
+
+        self.reporter = Some(reporter);
+        let blocks = self.states.keys().copied().collect::<Vec<_>>();
+        for block in blocks {
+            self.visit(block);
+        }
+    }
+
+    fn collect_tests(&mut self) {
+        let function = self.function;
+        let dfg = &function.dfg;
+        for (block, _) in dfg.blocks() {
+            for inst in dfg.block_insts(block) {
+                let span = dfg[inst].span();
+                match &*dfg[inst] {
+                    InstData::IsType(IsType { arg, ty }) => {
+                        let (kinds, exact) = test_kinds(ty);
+                        let result = dfg.first_result(inst);
+                        self.tests.insert(
+                            result,
+                            Test::IsKind {
+                                value: *arg,
+                                kinds,
+                                exact,
+                                span,
+                            },
+                        );
+                    }
+                    InstData::Call(Call {
+                        op: Opcode::Call,
+                        callee,
+                        args,
+                    }) => {
+                        let results = dfg.inst_results(inst);
+                        if results.len() != 2 {
+                            continue;
+                        }
+                        let callee = self.signatures[*callee].mfa();
+                        let args = args.as_slice(&dfg.value_lists);
+                        if let Some((kinds, exact)) = guard_kinds(&callee) {
+                            self.tests.insert(
+                                results[1],
+                                Test::IsKind {
+                                    value: args[0],
+                                    kinds,
+                                    exact,
+                                    span,
+                                },
+                            );
+                        } else if self.specs.contains_key(&callee) {
+                            self.tests
+                                .insert(results[0], Test::IsErr { value: results[1] });
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Applies the instructions of `block` to the kinds known on entry to it, propagating what is
+    /// known to each of its successors, and returning those whose entry state has changed
+    fn visit(&mut self, block: Block) -> Vec<Block> {
+        let function = self.function;
+        let dfg = &function.dfg;
+        let mut env = self.states[&block].clone();
+        let mut changed = vec![];
+        for inst in dfg.block_insts(block) {
+            let span = dfg[inst].span();
+            match &*dfg[inst] {
+                InstData::UnaryOp(UnaryOp {
+                    op: Opcode::Cast,
+                    arg,
+                }) => {
+                    let result = dfg.first_result(inst);
+                    let kinds = self.kinds(&env, *arg).intersection(self.base_kinds(result));
+                    env.insert(result, kinds);
+                }
+                InstData::Call(Call { op, callee, args }) => {
+                    let callee = self.signatures[*callee].mfa();
+                    let args = args.as_slice(&dfg.value_lists);
+                    let Some(sigs) = self.specs.get(&callee) else { continue; };
+                    self.check_call(&env, &callee, sigs, args, span);
+                    if *op == Opcode::Call {
+                        let results = dfg.inst_results(inst);
+                        if results.len() == 2 {
+                            let ret = sigs
+                                .iter()
+                                .map(|sig| sig.ret)
+                                .fold(TermKinds::NONE, TermKinds::union);
+                            env.insert(results[1], ret);
+                        }
+                    }
+                }
+                InstData::RetImm(RetImm {
+                    imm: Immediate::I1(false),
+                    arg,
+                    ..
+                }) => self.check_return(&env, *arg, span),
+                InstData::Br(Br {
+                    op,
+                    destination,
+                    args,
+                }) => {
+                    let args = args.as_slice(&dfg.value_lists);
+                    match op {
+                        Opcode::Br => {
+                            self.propagate(&env, *destination, args, &mut changed);
+                        }
+                        Opcode::BrIf | Opcode::BrUnless => {
+                            let taken_if = *op == Opcode::BrIf;
+                            if let Some(taken) = self.refine(&env, args[0], taken_if) {
+                                self.propagate(&taken, *destination, &args[1..], &mut changed);
+                            }
+                            match self.refine(&env, args[0], !taken_if) {
+                                Some(next) => env = next,
+                                // The rest of this block is unreachable
+                                None => break,
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                InstData::CondBr(CondBr {
+                    cond,
+                    then_dest,
+                    else_dest,
+                }) => {
+                    if let Some(then_env) = self.refine(&env, *cond, true) {
+                        let args = then_dest.1.as_slice(&dfg.value_lists);
+                        self.propagate(&then_env, then_dest.0, args, &mut changed);
+                    }
+                    if let Some(else_env) = self.refine(&env, *cond, false) {
+                        let args = else_dest.1.as_slice(&dfg.value_lists);
+                        self.propagate(&else_env, else_dest.0, args, &mut changed);
+                    }
+                }
+                InstData::Switch(Switch { arms, default, .. }) => {
+                    for dest in arms.iter().map(|(_, dest)| *dest) {
+                        self.propagate(&env, dest, &[], &mut changed);
+                    }
+                    self.propagate(&env, *default, &[], &mut changed);
+                }
+                _ => (),
+            }
+        }
+        changed
+    }
+
+    /// Returns what is known after branching on `cond`, given that it is `truth`, or `None` if
+    /// that branch can never be taken
+    fn refine(&mut self, env: &Env, cond: Value, truth: bool) -> Option<Env> {
+        let mut env = env.clone();
+        match self.tests.get(&cond).copied() {
+            Some(Test::IsKind {
+                value,
+                kinds,
+                exact,
+                span,
+            }) => {
+                let known = self.kinds(&env, value);
+                let refined = if truth {
+                    known.intersection(kinds)
+                } else if exact {
+                    known.difference(kinds)
+                } else {
+                    known
+                };
+                if refined.is_empty() {
+                    if truth {
+                        self.report_unreachable(known, kinds, span);
+                    }
+                    return None;
+                }
+                env.insert(value, refined);
+            }
+            Some(Test::IsErr { value }) if truth => {
+                env.remove(&value);
+            }
+            Some(Test::IsErr { .. }) | None => (),
+        }
+        Some(env)
+    }
+
+    /// Merges what is known on an edge to `block` into its entry state
+    fn propagate(&mut self, env: &Env, block: Block, args: &[Value], changed: &mut Vec<Block>) {
+        let dfg = &self.function.dfg;
+        let mut incoming = env.clone();
+        for (param, arg) in dfg.block_params(block).iter().zip(args.iter()) {
+            incoming.insert(*param, self.kinds(env, *arg));
+        }
+        match self.states.get_mut(&block) {
+            None => {
+                self.states.insert(block, incoming);
+                changed.push(block);
+            }
+            Some(state) => {
+                // A value not known on every edge is only as precise as its type
+                let joined = state
+                    .iter()
+                    .filter_map(|(value, kinds)| {
+                        incoming
+                            .get(value)
+                            .map(|other| (*value, kinds.union(*other)))
+                    })
+                    .collect::<Env>();
+                if &joined != state {
+                    *state = joined;
+                    changed.push(block);
+                }
+            }
+        }
+    }
+
+    fn check_call(
+        &mut self,
+        env: &Env,
+        callee: &FunctionName,
+        sigs: &[KindSignature],
+        args: &[Value],
+        span: SourceSpan,
+    ) {
+        let args = args
+            .iter()
+            .map(|arg| self.kinds(env, *arg))
+            .collect::<Vec<_>>();
+        let accepted = sigs.iter().any(|sig| {
+            sig.params.len() == args.len()
+                && sig
+                    .params
+                    .iter()
+                    .zip(args.iter())
+                    .all(|(param, arg)| !param.intersection(*arg).is_empty())
+        });
+        if accepted {
+            return;
+        }
+        let args = args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let message = format!(
+            "no clause of the spec for {} accepts arguments of type ({})",
+            callee, args
+        );
+        self.report("spec_call", "call will never succeed", span, &message);
+    }
+
+    fn check_return(&mut self, env: &Env, value: Value, span: SourceSpan) {
+        let name = self.function.signature.mfa();
+        let Some(sigs) = self.specs.get(&name) else { return; };
+        let ret = sigs
+            .iter()
+            .map(|sig| sig.ret)
+            .fold(TermKinds::NONE, TermKinds::union);
+        let kinds = self.kinds(env, value);
+        if !kinds.intersection(ret).is_empty() {
+            return;
+        }
+        let message = format!(
+            "this returns {}, but the spec for {} only allows {}",
+            kinds, name, ret
+        );
+        self.report("spec_return", "return value violates spec", span, &message);
+    }
+
+    fn report_unreachable(&mut self, known: TermKinds, kinds: TermKinds, span: SourceSpan) {
+        let message = format!("this requires {}, but the value is always {}", kinds, known);
+        self.report(
+            "spec_unreachable",
+            "clause can never match, as its pattern or guard can never succeed",
+            span,
+            &message,
+        );
+    }
+
+    /// Reports a warning, with a code identifying which of the checks above it comes from
+    fn report(&mut self, code: &str, message: &str, span: SourceSpan, label: &str) {
+        let Some(reporter) = self.reporter else { return; };
+        if self.reported.insert(span) {
+            reporter.diagnostic(
+                Diagnostic::warning()
+                    .with_code(code)
+                    .with_message(message)
+                    .with_labels(vec![
+                        Label::primary(span.source_id(), span).with_message(label)
+                    ]),
+            );
+        }
+    }
+
+    /// Returns the kinds `value` may be, given what is known in `env`
+    fn kinds(&self, env: &Env, value: Value) -> TermKinds {
+        env.get(&value)
+            .copied()
+            .unwrap_or_else(|| self.base_kinds(value))
+    }
+
+    /// Returns the kinds `value` may be according to its type alone
+    fn base_kinds(&self, value: Value) -> TermKinds {
+        let dfg = &self.function.dfg;
+        match dfg.get_value(value) {
+            // Closures are produced as a term of unknown type
+            ValueData::Inst { inst, num: 1, .. } if dfg[inst].opcode() == Opcode::MakeFun => {
+                TermKinds::FUN
+            }
+            data => TermKinds::from(&data.ty()),
+        }
+    }
+}
+
+/// Returns the kinds tested for by an `is_type` instruction, and whether the test is exact
+fn test_kinds(ty: &Type) -> (TermKinds, bool) {
+    let exact = match ty {
+        Type::Term(
+            TermType::Bool
+            | TermType::Binary
+            | TermType::MaybeImproperList
+            | TermType::List(Some(_))
+            | TermType::Tuple(Some(_))
+            | TermType::Fun(Some(_)),
+        ) => false,
+        Type::Term(_) => true,
+        _ => false,
+    };
+    (TermKinds::from(ty), exact)
+}
+
+/// Returns the kinds tested for by a call to a guard BIF, and whether the test is exact
+fn guard_kinds(callee: &FunctionName) -> Option<(TermKinds, bool)> {
+    if callee.module != Some(symbols::Erlang) {
+        return None;
+    }
+    let kinds = match (callee.function, callee.arity) {
+        (symbols::IsAtom, 1) => (TermKinds::ATOM, true),
+        (symbols::IsBoolean, 1) => (TermKinds::ATOM, false),
+        (symbols::IsBinary, 1) => (TermKinds::BITSTRING, false),
+        (symbols::IsBitstring, 1) => (TermKinds::BITSTRING, true),
+        (symbols::IsFloat, 1) => (TermKinds::FLOAT, true),
+        (symbols::IsFunction, 1) => (TermKinds::FUN, true),
+        (symbols::IsFunction, 2) => (TermKinds::FUN, false),
+        (symbols::IsInteger, 1) => (TermKinds::INTEGER, true),
+        (symbols::IsList, 1) => (TermKinds::LIST, true),
+        (symbols::IsMap, 1) => (TermKinds::MAP, true),
+        (symbols::IsNumber, 1) => (TermKinds::NUMBER, true),
+        (symbols::IsPid, 1) => (TermKinds::PID, true),
+        (symbols::IsPort, 1) => (TermKinds::PORT, true),
+        (symbols::IsReference, 1) => (TermKinds::REFERENCE, true),
+        (symbols::IsTuple, 1) => (TermKinds::TUPLE, true),
+        (symbols::IsRecord, 2 | 3) => (TermKinds::TUPLE, false),
+        _ => return None,
+    };
+    Some(kinds)
+}
@@ -0,0 +1,9 @@
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!(
+        "cargo:rustc-env=FIREFLY_HOST_TRIPLE={}",
+        env::var("TARGET").unwrap()
+    );
+}
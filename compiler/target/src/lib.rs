@@ -0,0 +1,10 @@
+#![deny(warnings)]
+
+pub mod spec;
+
+pub use self::spec::*;
+
+/// Returns the triple of the target the compiler itself was built for
+pub fn host_triple() -> &'static str {
+    env!("FIREFLY_HOST_TRIPLE")
+}
@@ -0,0 +1,16 @@
+use super::{apple_base, LinkerFlavor, Target};
+
+pub fn target() -> Target {
+    let mut base = apple_base::opts("macos");
+    base.cpu = "apple-a14".into();
+    base.pre_link_args
+        .insert(LinkerFlavor::Gcc, vec!["-arch".into(), "arm64".into()]);
+
+    Target {
+        llvm_target: "aarch64-apple-darwin".into(),
+        pointer_width: 64,
+        data_layout: "e-m:o-i64:64-i128:128-n32:64-S128".into(),
+        arch: "aarch64".into(),
+        options: base,
+    }
+}
@@ -0,0 +1,41 @@
+use super::{LldFlavor, SplitDebugInfo, StaticCow, TargetOptions};
+
+pub fn opts(os: &'static str) -> TargetOptions {
+    TargetOptions {
+        os: os.into(),
+        vendor: "apple".into(),
+        // macOS has -dead_strip, which doesn't rely on function_sections
+        function_sections: false,
+        dynamic_linking: true,
+        linker_is_gnu: false,
+        executables: true,
+        families: vec!["unix".into()],
+        is_like_osx: true,
+        has_rpath: true,
+        dll_suffix: ".dylib".into(),
+        archive_format: "darwin".into(),
+        lld_flavor: LldFlavor::Ld64,
+        // The linker collects the debug info of the objects in a .dSYM bundle
+        split_debuginfo: SplitDebugInfo::Packed,
+        // The ld64 linker does not understand --eh-frame-hdr
+        eh_frame_header: false,
+        link_env_remove: macos_link_env_remove(),
+        ..Default::default()
+    }
+}
+
+/// Environment variables which make the toolchain link for iOS rather than macOS
+pub fn macos_link_env_remove() -> Vec<StaticCow<str>> {
+    let mut env_remove = Vec::with_capacity(2);
+    // Remove the `SDKROOT` environment variable if it's clearly set for the wrong platform, which
+    // may occur when we're linking a custom build script while targeting iOS for example.
+    if let Ok(sdkroot) = std::env::var("SDKROOT") {
+        if sdkroot.contains("iPhoneOS.platform") || sdkroot.contains("iPhoneSimulator.platform") {
+            env_remove.push("SDKROOT".into())
+        }
+    }
+    // Additional to the `SDKROOT` environment variable, Xcode sets `IPHONEOS_DEPLOYMENT_TARGET`,
+    // which causes the linker to target iOS rather than macOS.
+    env_remove.push("IPHONEOS_DEPLOYMENT_TARGET".into());
+    env_remove
+}
@@ -0,0 +1,77 @@
+//! Object files providing the C runtime (CRT) startup and teardown code
+//!
+//! Executables and dynamic libraries are normally linked against the CRT objects of the system
+//! toolchain, which the linker (i.e. `cc`) adds itself. Some targets, e.g. musl and wasm, instead
+//! link the objects shipped alongside the runtime when no suitable system toolchain is available;
+//! `CrtObjectsFallback` selects the heuristic used to decide when that is the case.
+use std::collections::BTreeMap;
+
+use super::{LinkOutputKind, StaticCow};
+
+/// The CRT objects to link for each kind of output
+pub type CrtObjects = BTreeMap<LinkOutputKind, Vec<StaticCow<str>>>;
+
+pub(super) fn new(obj_table: &[(LinkOutputKind, &[&'static str])]) -> CrtObjects {
+    obj_table
+        .iter()
+        .map(|(z, k)| (*z, k.iter().map(|b| (*b).into()).collect()))
+        .collect()
+}
+
+pub(super) fn pre_musl_fallback() -> CrtObjects {
+    new(&[
+        (
+            LinkOutputKind::DynamicNoPicExe,
+            &["crt1.o", "crti.o", "crtbegin.o"],
+        ),
+        (
+            LinkOutputKind::DynamicPicExe,
+            &["Scrt1.o", "crti.o", "crtbeginS.o"],
+        ),
+        (
+            LinkOutputKind::StaticNoPicExe,
+            &["crt1.o", "crti.o", "crtbegin.o"],
+        ),
+        (
+            LinkOutputKind::StaticPicExe,
+            &["rcrt1.o", "crti.o", "crtbeginS.o"],
+        ),
+        (LinkOutputKind::DynamicDylib, &["crti.o", "crtbeginS.o"]),
+        (LinkOutputKind::StaticDylib, &["crti.o", "crtbeginS.o"]),
+    ])
+}
+
+pub(super) fn post_musl_fallback() -> CrtObjects {
+    new(&[
+        (LinkOutputKind::DynamicNoPicExe, &["crtend.o", "crtn.o"]),
+        (LinkOutputKind::DynamicPicExe, &["crtendS.o", "crtn.o"]),
+        (LinkOutputKind::StaticNoPicExe, &["crtend.o", "crtn.o"]),
+        (LinkOutputKind::StaticPicExe, &["crtendS.o", "crtn.o"]),
+        (LinkOutputKind::DynamicDylib, &["crtendS.o", "crtn.o"]),
+        (LinkOutputKind::StaticDylib, &["crtendS.o", "crtn.o"]),
+    ])
+}
+
+pub(super) fn pre_wasi_fallback() -> CrtObjects {
+    // Use crt1-command.o instead of crt1.o to enable support for new-style
+    // commands. See https://reviews.llvm.org/D81689 for more info.
+    new(&[
+        (LinkOutputKind::DynamicNoPicExe, &["crt1-command.o"]),
+        (LinkOutputKind::DynamicPicExe, &["crt1-command.o"]),
+        (LinkOutputKind::StaticNoPicExe, &["crt1-command.o"]),
+        (LinkOutputKind::StaticPicExe, &["crt1-command.o"]),
+        (LinkOutputKind::WasiReactorExe, &["crt1-reactor.o"]),
+    ])
+}
+
+pub(super) fn post_wasi_fallback() -> CrtObjects {
+    new(&[])
+}
+
+/// Which logic to use to determine whether to fall back to the self-contained CRT objects
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CrtObjectsFallback {
+    Musl,
+    Mingw,
+    Wasm,
+}
@@ -0,0 +1,8 @@
+use super::{linux_base, TargetOptions};
+
+pub fn opts() -> TargetOptions {
+    TargetOptions {
+        env: "gnu".into(),
+        ..linux_base::opts()
+    }
+}
@@ -0,0 +1,665 @@
+//! Specifications of the targets the compiler can generate code for
+//!
+//! This is derived from the target specifications in `rustc_target`, trimmed down to the
+//! options the compiler consumes and the targets the runtime has been ported to. Each target
+//! lives in its own module named after its triple, and is built on top of one of the `*_base`
+//! modules, which hold the options shared by a family of targets.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+pub mod crt_objects;
+
+mod apple_base;
+mod linux_base;
+mod linux_gnu_base;
+mod linux_musl_base;
+mod wasm_base;
+
+use self::crt_objects::{CrtObjects, CrtObjectsFallback};
+
+/// A string which is almost always a literal in a target specification
+pub type StaticCow<T> = Cow<'static, T>;
+
+/// Linker arguments, keyed by the flavor of linker they apply to
+pub type LinkArgs = BTreeMap<LinkerFlavor, Vec<StaticCow<str>>>;
+
+#[derive(Debug, Error)]
+pub enum TargetError {
+    #[error("unsupported target: '{0}'")]
+    Unsupported(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+impl Endianness {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Big => "big",
+            Self::Little => "little",
+        }
+    }
+}
+impl FromStr for Endianness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "big" => Ok(Self::Big),
+            "little" => Ok(Self::Little),
+            _ => Err(format!(r#"unknown endianness: "{}""#, s)),
+        }
+    }
+}
+impl fmt::Display for Endianness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The encoding used for terms on a target, which follows from its pointer width
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Encoding32,
+    Encoding64,
+    Encoding64Nanboxed,
+}
+impl Encoding {
+    /// Returns true if floats are stored as immediates, rather than boxed on the heap
+    pub fn is_nanboxed(&self) -> bool {
+        matches!(self, Self::Encoding64Nanboxed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
+pub enum LinkerFlavor {
+    Em,
+    Gcc,
+    Ld,
+    Msvc,
+    Lld(LldFlavor),
+    PtxLinker,
+    BpfLinker,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
+pub enum LldFlavor {
+    Wasm,
+    Ld64,
+    Ld,
+    Link,
+}
+
+macro_rules! flavor_mappings {
+    ($((($($flavor:tt)*), $string:expr),)*) => (
+        impl LinkerFlavor {
+            pub const fn one_of() -> &'static str {
+                concat!("one of: ", $($string, " ",)*)
+            }
+
+            pub fn desc(&self) -> &'static str {
+                match *self {
+                    $($($flavor)* => $string,)*
+                }
+            }
+        }
+        impl FromStr for LinkerFlavor {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(match s {
+                    $($string => $($flavor)*,)*
+                    _ => return Err(format!("invalid linker flavor, expected {}", Self::one_of())),
+                })
+            }
+        }
+    )
+}
+
+impl fmt::Display for LinkerFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+flavor_mappings! {
+    ((LinkerFlavor::Em), "em"),
+    ((LinkerFlavor::Gcc), "gcc"),
+    ((LinkerFlavor::Ld), "ld"),
+    ((LinkerFlavor::Msvc), "msvc"),
+    ((LinkerFlavor::PtxLinker), "ptx-linker"),
+    ((LinkerFlavor::BpfLinker), "bpf-linker"),
+    ((LinkerFlavor::Lld(LldFlavor::Wasm)), "wasm-ld"),
+    ((LinkerFlavor::Lld(LldFlavor::Ld64)), "ld64.lld"),
+    ((LinkerFlavor::Lld(LldFlavor::Ld)), "ld.lld"),
+    ((LinkerFlavor::Lld(LldFlavor::Link)), "lld-link"),
+}
+
+/// The kind of output the linker is asked to produce, which determines the CRT objects used
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum LinkOutputKind {
+    /// Dynamically linked non position-independent executable.
+    DynamicNoPicExe,
+    /// Dynamically linked position-independent executable.
+    DynamicPicExe,
+    /// Statically linked non position-independent executable.
+    StaticNoPicExe,
+    /// Statically linked position-independent executable.
+    StaticPicExe,
+    /// Regular dynamic library ("dynamically linked").
+    DynamicDylib,
+    /// Dynamic library with bundled libc ("statically linked").
+    StaticDylib,
+    /// WASI module with a lifetime past the _initialize entry point
+    WasiReactorExe,
+}
+impl LinkOutputKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DynamicNoPicExe => "dynamic-nopic-exe",
+            Self::DynamicPicExe => "dynamic-pic-exe",
+            Self::StaticNoPicExe => "static-nopic-exe",
+            Self::StaticPicExe => "static-pic-exe",
+            Self::DynamicDylib => "dynamic-dylib",
+            Self::StaticDylib => "static-dylib",
+            Self::WasiReactorExe => "wasi-reactor-exe",
+        }
+    }
+}
+impl fmt::Display for LinkOutputKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PanicStrategy {
+    Unwind,
+    Abort,
+}
+impl PanicStrategy {
+    pub fn desc(&self) -> &'static str {
+        match *self {
+            Self::Unwind => "unwind",
+            Self::Abort => "abort",
+        }
+    }
+}
+impl FromStr for PanicStrategy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "unwind" => Ok(Self::Unwind),
+            "abort" => Ok(Self::Abort),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RelroLevel {
+    Full,
+    Partial,
+    Off,
+    None,
+}
+impl RelroLevel {
+    pub fn desc(&self) -> &'static str {
+        match *self {
+            Self::Full => "full",
+            Self::Partial => "partial",
+            Self::Off => "off",
+            Self::None => "none",
+        }
+    }
+}
+impl FromStr for RelroLevel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "full" => Ok(Self::Full),
+            "partial" => Ok(Self::Partial),
+            "off" => Ok(Self::Off),
+            "none" => Ok(Self::None),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MergeFunctions {
+    Disabled,
+    Trampolines,
+    Aliases,
+}
+impl MergeFunctions {
+    pub fn desc(&self) -> &'static str {
+        match *self {
+            Self::Disabled => "disabled",
+            Self::Trampolines => "trampolines",
+            Self::Aliases => "aliases",
+        }
+    }
+}
+impl FromStr for MergeFunctions {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "disabled" => Ok(Self::Disabled),
+            "trampolines" => Ok(Self::Trampolines),
+            "aliases" => Ok(Self::Aliases),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How debug info is split from the generated objects, if at all
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SplitDebugInfo {
+    /// Debug info is kept in the objects, and thus the final artifact
+    Off,
+    /// Debug info is collected in a single separate file, e.g. a `.dSYM` bundle or `.dwp`
+    Packed,
+    /// Debug info is left in separate files next to the objects, e.g. `.dwo` files
+    Unpacked,
+}
+impl SplitDebugInfo {
+    pub fn desc(&self) -> &'static str {
+        match *self {
+            Self::Off => "off",
+            Self::Packed => "packed",
+            Self::Unpacked => "unpacked",
+        }
+    }
+}
+impl FromStr for SplitDebugInfo {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "off" => Ok(Self::Off),
+            "packed" => Ok(Self::Packed),
+            "unpacked" => Ok(Self::Unpacked),
+            _ => Err(()),
+        }
+    }
+}
+
+/// NOTE: The order of the variants must match `CodeModel` in the LLVM bindings
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CodeModel {
+    Other,
+    Small,
+    Kernel,
+    Medium,
+    Large,
+    None,
+}
+impl FromStr for CodeModel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "small" => Ok(Self::Small),
+            "kernel" => Ok(Self::Kernel),
+            "medium" => Ok(Self::Medium),
+            "large" => Ok(Self::Large),
+            _ => Err(()),
+        }
+    }
+}
+
+/// NOTE: The order of the variants must match `RelocModel` in the LLVM bindings
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RelocModel {
+    Static,
+    Pic,
+    Pie,
+    DynamicNoPic,
+    Ropi,
+    Rwpi,
+    RopiRwpi,
+}
+impl FromStr for RelocModel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "static" => Ok(Self::Static),
+            "pic" => Ok(Self::Pic),
+            "pie" => Ok(Self::Pie),
+            "dynamic-no-pic" => Ok(Self::DynamicNoPic),
+            "ropi" => Ok(Self::Ropi),
+            "rwpi" => Ok(Self::Rwpi),
+            "ropi-rwpi" => Ok(Self::RopiRwpi),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TlsModel {
+    GeneralDynamic,
+    LocalDynamic,
+    InitialExec,
+    LocalExec,
+}
+impl FromStr for TlsModel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "global-dynamic" => Ok(Self::GeneralDynamic),
+            "local-dynamic" => Ok(Self::LocalDynamic),
+            "initial-exec" => Ok(Self::InitialExec),
+            "local-exec" => Ok(Self::LocalExec),
+            _ => Err(()),
+        }
+    }
+}
+
+macro_rules! supported_targets {
+    ($(($triple:literal, $module:ident),)+) => {
+        $(mod $module;)+
+
+        /// The triples of all supported targets
+        const TARGETS: &[&str] = &[$($triple),+];
+
+        fn load_builtin(target: &str) -> Option<Target> {
+            match target {
+                $($triple => {
+                    let mut target = $module::target();
+                    target.options.is_builtin = true;
+                    Some(target)
+                })+
+                _ => None,
+            }
+        }
+    };
+}
+
+supported_targets! {
+    ("x86_64-unknown-linux-gnu", x86_64_unknown_linux_gnu),
+    ("x86_64-unknown-linux-musl", x86_64_unknown_linux_musl),
+    ("aarch64-unknown-linux-gnu", aarch64_unknown_linux_gnu),
+    ("aarch64-unknown-linux-musl", aarch64_unknown_linux_musl),
+    ("x86_64-apple-darwin", x86_64_apple_darwin),
+    ("aarch64-apple-darwin", aarch64_apple_darwin),
+    ("wasm32-unknown-unknown", wasm32_unknown_unknown),
+    ("wasm32-wasi", wasm32_wasi),
+}
+
+/// Everything the compiler needs to know about a target
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Target {
+    /// The triple LLVM knows this target by
+    pub llvm_target: StaticCow<str>,
+    /// The width of a pointer in bits
+    pub pointer_width: u32,
+    /// The architecture, e.g. `x86_64`
+    pub arch: StaticCow<str>,
+    /// The LLVM data layout string
+    pub data_layout: StaticCow<str>,
+    /// Options which are either defaulted or shared with other targets
+    pub options: TargetOptions,
+}
+impl Target {
+    /// Looks up the supported target with the given triple
+    pub fn search(triple: &str) -> Result<Target, TargetError> {
+        load_builtin(triple).ok_or_else(|| TargetError::Unsupported(triple.to_string()))
+    }
+
+    /// Returns the triples of all supported targets
+    pub fn all() -> impl Iterator<Item = &'static str> {
+        TARGETS.iter().copied()
+    }
+
+    /// Returns the triple used to refer to this target, e.g. on the command line
+    pub fn triple(&self) -> &str {
+        self.llvm_target.as_ref()
+    }
+
+    /// Returns the encoding terms use on this target
+    pub fn term_encoding(&self) -> Encoding {
+        match self.pointer_width {
+            32 => Encoding::Encoding32,
+            64 => Encoding::Encoding64Nanboxed,
+            n => panic!("unsupported pointer width: {}", n),
+        }
+    }
+}
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.triple())
+    }
+}
+
+/// Options of a target which have sensible defaults, see the `Default` impl
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct TargetOptions {
+    /// Whether the target is built-in or loaded from a custom target specification.
+    pub is_builtin: bool,
+    /// Used as the `TARGET_ENDIANESS` define. Defaults to little endian.
+    pub endianness: Endianness,
+    /// Used as the `TARGET_OS` define. Defaults to "none".
+    pub os: StaticCow<str>,
+    /// Used as the `TARGET_ENV` define. Defaults to "".
+    pub env: StaticCow<str>,
+    /// Used as the `TARGET_VENDOR` define. Defaults to "unknown".
+    pub vendor: StaticCow<str>,
+    /// Used as the `TARGET_FAMILY_*` defines, e.g. `unix` or `wasm`.
+    pub families: Vec<StaticCow<str>>,
+    /// Default linker flavor used if `-C linker-flavor` is not given
+    pub linker_flavor: LinkerFlavor,
+    /// Linker to invoke
+    pub linker: Option<StaticCow<str>>,
+    /// The flavor of lld to use when linking with the builtin linker
+    pub lld_flavor: LldFlavor,
+    /// Whether the linker accepts the options of GNU ld
+    pub linker_is_gnu: bool,
+
+    /// Objects to link before and after all other object code
+    pub pre_link_objects: CrtObjects,
+    pub post_link_objects: CrtObjects,
+    /// Same as above, but used when the self-contained CRT objects are linked
+    pub pre_link_objects_fallback: CrtObjects,
+    pub post_link_objects_fallback: CrtObjects,
+    /// Which logic to use to decide if the self-contained CRT objects are linked
+    pub crt_objects_fallback: Option<CrtObjectsFallback>,
+
+    /// Linker arguments that are passed *before* any user-defined libraries
+    pub pre_link_args: LinkArgs,
+    /// Linker arguments that are unconditionally passed after any user-defined libraries
+    pub late_link_args: LinkArgs,
+    /// Linker arguments used in addition to `late_link_args` if at least one dependency is
+    /// linked dynamically
+    pub late_link_args_dynamic: LinkArgs,
+    /// Linker arguments used in addition to `late_link_args` if all dependencies are linked
+    /// statically
+    pub late_link_args_static: LinkArgs,
+    /// Linker arguments that are unconditionally passed *after* any user-defined libraries
+    pub post_link_args: LinkArgs,
+    /// Optional link script applied to executables and dynamic libraries
+    pub link_script: Option<StaticCow<str>>,
+    /// Environment variables to set when running the linker
+    pub link_env: Vec<(StaticCow<str>, StaticCow<str>)>,
+    /// Environment variables to remove when running the linker
+    pub link_env_remove: Vec<StaticCow<str>>,
+
+    /// Default CPU to pass to LLVM
+    pub cpu: StaticCow<str>,
+    /// Default target features to pass to LLVM, e.g. "+sse,+sse2"
+    pub features: StaticCow<str>,
+    /// ABI name to distinguish multiple ABIs on the same OS and architecture
+    pub llvm_abiname: StaticCow<str>,
+    /// Whether dynamic linking is available on this target
+    pub dynamic_linking: bool,
+    /// Whether executables are available on this target
+    pub executables: bool,
+    /// Whether only cdylibs can be produced for this target
+    pub only_cdylib: bool,
+    /// Whether the target can produce position independent executables
+    pub position_independent_executables: bool,
+    /// Whether the target can produce statically linked position independent executables
+    pub static_position_independent_executables: bool,
+    /// Whether the eh_frame_hdr section is requested from the linker
+    pub eh_frame_header: bool,
+    /// Whether the linker supports `-z relro`, and at which level it is used by default
+    pub relro_level: RelroLevel,
+    /// Archive format used by the default archiver, e.g. "gnu" or "darwin"
+    pub archive_format: StaticCow<str>,
+    /// Whether the target only supports a single thread
+    pub singlethread: bool,
+    /// Whether each function is placed in its own section
+    pub function_sections: bool,
+    /// String to prepend to the name of every dynamic library
+    pub dll_prefix: StaticCow<str>,
+    /// String to append to the name of every dynamic library
+    pub dll_suffix: StaticCow<str>,
+    /// String to append to the name of every executable
+    pub exe_suffix: StaticCow<str>,
+    /// String to prepend to the name of every static library
+    pub staticlib_prefix: StaticCow<str>,
+    /// String to append to the name of every static library
+    pub staticlib_suffix: StaticCow<str>,
+    /// Whether the target toolchain is like Windows'
+    pub is_like_windows: bool,
+    /// Whether the target toolchain is like MSVC's
+    pub is_like_msvc: bool,
+    /// Whether the target toolchain is like macOS's
+    pub is_like_osx: bool,
+    /// Whether the target toolchain is like Solaris's
+    pub is_like_solaris: bool,
+    /// Whether the target is a WebAssembly target
+    pub is_like_wasm: bool,
+    /// Whether the linker supports rpaths
+    pub has_rpath: bool,
+    /// Whether default system libraries should be excluded, e.g. with `-nodefaultlibs`
+    pub no_default_libraries: bool,
+    /// Whether `crt-static` is respected by the compiler
+    pub crt_static_respected: bool,
+    /// Whether the target links statically against the C runtime by default
+    pub crt_static_default: bool,
+    /// Whether dynamic libraries can link statically against the C runtime
+    pub crt_static_allows_dylibs: bool,
+    /// If set, only these symbols are exported from a dynamic library
+    pub override_export_symbols: Option<Vec<StaticCow<str>>>,
+    /// Whether the exports of dynamic libraries are limited to the public API
+    pub limit_rdylib_exports: bool,
+    /// The panic strategy used by the runtime
+    pub panic_strategy: PanicStrategy,
+    /// The default relocation model
+    pub relocation_model: RelocModel,
+    /// The default code model, or `None` to let LLVM decide
+    pub code_model: Option<CodeModel>,
+    /// The default thread-local storage model
+    pub tls_model: TlsModel,
+    /// Whether LLVM may relax ELF relocations
+    pub relax_elf_relocations: bool,
+    /// How identical functions are merged by default
+    pub merge_functions: MergeFunctions,
+    /// How debug info is split from the generated objects by default
+    pub split_debuginfo: SplitDebugInfo,
+}
+impl Default for TargetOptions {
+    fn default() -> Self {
+        Self {
+            is_builtin: false,
+            endianness: Endianness::Little,
+            os: "none".into(),
+            env: "".into(),
+            vendor: "unknown".into(),
+            families: vec![],
+            linker_flavor: LinkerFlavor::Gcc,
+            linker: None,
+            lld_flavor: LldFlavor::Ld,
+            linker_is_gnu: true,
+            pre_link_objects: Default::default(),
+            post_link_objects: Default::default(),
+            pre_link_objects_fallback: Default::default(),
+            post_link_objects_fallback: Default::default(),
+            crt_objects_fallback: None,
+            pre_link_args: LinkArgs::new(),
+            late_link_args: LinkArgs::new(),
+            late_link_args_dynamic: LinkArgs::new(),
+            late_link_args_static: LinkArgs::new(),
+            post_link_args: LinkArgs::new(),
+            link_script: None,
+            link_env: vec![],
+            link_env_remove: vec![],
+            cpu: "generic".into(),
+            features: "".into(),
+            llvm_abiname: "".into(),
+            dynamic_linking: false,
+            executables: false,
+            only_cdylib: false,
+            position_independent_executables: false,
+            static_position_independent_executables: false,
+            eh_frame_header: true,
+            relro_level: RelroLevel::None,
+            archive_format: "gnu".into(),
+            singlethread: false,
+            function_sections: true,
+            dll_prefix: "lib".into(),
+            dll_suffix: ".so".into(),
+            exe_suffix: "".into(),
+            staticlib_prefix: "lib".into(),
+            staticlib_suffix: ".a".into(),
+            is_like_windows: false,
+            is_like_msvc: false,
+            is_like_osx: false,
+            is_like_solaris: false,
+            is_like_wasm: false,
+            has_rpath: false,
+            no_default_libraries: true,
+            crt_static_respected: false,
+            crt_static_default: false,
+            crt_static_allows_dylibs: false,
+            override_export_symbols: None,
+            limit_rdylib_exports: true,
+            panic_strategy: PanicStrategy::Unwind,
+            relocation_model: RelocModel::Pic,
+            code_model: None,
+            tls_model: TlsModel::GeneralDynamic,
+            relax_elf_relocations: false,
+            merge_functions: MergeFunctions::Aliases,
+            split_debuginfo: SplitDebugInfo::Off,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_supported_target_loads_test() {
+        for triple in Target::all() {
+            let target = Target::search(triple).unwrap();
+            assert_eq!(target.triple(), triple);
+            assert!(target.options.is_builtin);
+        }
+        assert!(Target::search(crate::host_triple()).is_ok());
+    }
+
+    #[test]
+    fn unsupported_target_test() {
+        assert!(matches!(
+            Target::search("x86_64-unknown-plan9"),
+            Err(TargetError::Unsupported(triple)) if triple == "x86_64-unknown-plan9"
+        ));
+    }
+}
@@ -0,0 +1,30 @@
+//! A "bare wasm" target, which makes no assumptions about the environment the module runs in,
+//! e.g. a web browser. Everything the module needs from its host must be imported.
+use super::{wasm_base, LinkerFlavor, LldFlavor, Target};
+
+pub fn target() -> Target {
+    let mut options = wasm_base::options();
+    options.os = "unknown".into();
+
+    let clang_args = options.pre_link_args.entry(LinkerFlavor::Gcc).or_default();
+    // Make sure clang uses LLD as its linker and is configured appropriately
+    // otherwise
+    clang_args.push("--target=wasm32-unknown-unknown".into());
+    // For now this target just never has an entry symbol no matter the output
+    // type, so unconditionally pass this.
+    clang_args.push("-Wl,--no-entry".into());
+
+    let lld_args = options
+        .pre_link_args
+        .get_mut(&LinkerFlavor::Lld(LldFlavor::Wasm))
+        .unwrap();
+    lld_args.push("--no-entry".into());
+
+    Target {
+        llvm_target: "wasm32-unknown-unknown".into(),
+        pointer_width: 32,
+        data_layout: "e-m:e-p:32:32-i64:64-n32:64-S128-ni:1:10:20".into(),
+        arch: "wasm32".into(),
+        options,
+    }
+}
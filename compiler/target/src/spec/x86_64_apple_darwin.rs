@@ -0,0 +1,19 @@
+use super::{apple_base, LinkerFlavor, Target};
+
+pub fn target() -> Target {
+    let mut base = apple_base::opts("macos");
+    base.cpu = "core2".into();
+    base.pre_link_args.insert(
+        LinkerFlavor::Gcc,
+        vec!["-m64".into(), "-arch".into(), "x86_64".into()],
+    );
+
+    Target {
+        llvm_target: "x86_64-apple-darwin".into(),
+        pointer_width: 64,
+        data_layout: "e-m:o-p270:32:32-p271:32:32-p272:64:64-i64:64-f80:128-n8:16:32:64-S128"
+            .into(),
+        arch: "x86_64".into(),
+        options: base,
+    }
+}
@@ -1,7 +1,7 @@
 use std::io::Write;
 use std::ops::Deref;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 pub type DisplayConfig = firefly_diagnostics::term::Config;
 pub type DisplayStyle = firefly_diagnostics::term::DisplayStyle;
@@ -10,7 +10,7 @@ pub type DisplayChars = firefly_diagnostics::term::Chars;
 pub use firefly_diagnostics::term::termcolor::*;
 pub use firefly_diagnostics::term::{ColorArg, Styles};
 pub use firefly_diagnostics::{
-    ByteIndex, CodeMap, FileName, Files, SourceFile, SourceId, SourceIndex, SourceSpan,
+    ByteIndex, CodeMap, ErrorFormat, FileName, Files, SourceFile, SourceId, SourceIndex, SourceSpan,
 };
 pub use firefly_diagnostics::{Diagnostic, Label, LabelStyle, Severity};
 
@@ -21,6 +21,7 @@ pub struct DiagnosticsConfig {
     pub warnings_as_errors: bool,
     pub no_warn: bool,
     pub display: DisplayConfig,
+    pub format: ErrorFormat,
 }
 
 pub trait Emitter {
@@ -203,9 +204,13 @@ pub struct DiagnosticsHandler {
     warnings_as_errors: bool,
     no_warn: bool,
     display: DisplayConfig,
+    format: ErrorFormat,
+    /// The diagnostics emitted so far when rendering SARIF, which are printed as a log when
+    /// dropped
+    sarif_diagnostics: Mutex<Vec<Diagnostic>>,
 }
 // We can safely implement these traits for DiagnosticsHandler,
-// as the only two non-atomic fields are read-only after creation
+// as the only non-atomic fields are read-only after creation, or guarded by a mutex
 unsafe impl Send for DiagnosticsHandler {}
 unsafe impl Sync for DiagnosticsHandler {}
 impl DiagnosticsHandler {
@@ -221,6 +226,8 @@ impl DiagnosticsHandler {
             warnings_as_errors: config.warnings_as_errors,
             no_warn: config.no_warn,
             display: config.display,
+            format: config.format,
+            sarif_diagnostics: Mutex::new(vec![]),
         }
     }
 
@@ -268,6 +275,9 @@ impl DiagnosticsHandler {
 
     /// Emits an informational message
     pub fn info(&self, message: impl Into<String>) {
+        if self.format != ErrorFormat::Human {
+            return;
+        }
         let info_color = self.display.styles.header(Severity::Help);
         let mut buffer = self.emitter.buffer();
         buffer.set_color(&info_color).ok();
@@ -281,6 +291,9 @@ impl DiagnosticsHandler {
 
     /// Emits a debug message
     pub fn debug(&self, message: impl Into<String>) {
+        if self.format != ErrorFormat::Human {
+            return;
+        }
         let mut debug_color = self.display.styles.header_message.clone();
         debug_color.set_fg(Some(Color::Blue));
         let mut buffer = self.emitter.buffer();
@@ -318,11 +331,18 @@ impl DiagnosticsHandler {
 
     /// Prints an error message with the given prefix
     pub fn failed(&self, prefix: &str, message: impl Into<String>) {
+        if self.format != ErrorFormat::Human {
+            return self.error(format!("{}: {}", prefix.to_lowercase(), message.into()));
+        }
         self.err_count.fetch_add(1, Ordering::Relaxed);
         self.write_prefixed(self.display.styles.header(Severity::Error), prefix, message);
     }
 
+    /// Prefixed messages report progress to people, so are omitted from machine-readable output
     fn write_prefixed(&self, color: &ColorSpec, prefix: &str, message: impl Into<String>) {
+        if self.format != ErrorFormat::Human {
+            return;
+        }
         let mut buffer = self.emitter.buffer();
         buffer.set_color(&color).ok();
         write!(&mut buffer, "{:>12} ", prefix).unwrap();
@@ -341,11 +361,41 @@ impl DiagnosticsHandler {
     /// Emits the given diagnostic
     #[inline(always)]
     pub fn emit(&self, diagnostic: &Diagnostic) {
-        use firefly_diagnostics::term;
+        use firefly_diagnostics::{format, term};
 
+        match self.format {
+            ErrorFormat::Human => {
+                let mut buffer = self.emitter.buffer();
+                term::emit(&mut buffer, &self.display, self.codemap.deref(), diagnostic).unwrap();
+                self.emitter.print(&buffer).unwrap();
+            }
+            ErrorFormat::Json => {
+                let mut buffer = self.emitter.buffer();
+                let json = format::to_json(self.codemap.deref(), diagnostic);
+                writeln!(&mut buffer, "{}", json).unwrap();
+                self.emitter.print(&buffer).unwrap();
+            }
+            ErrorFormat::Sarif => {
+                self.sarif_diagnostics
+                    .lock()
+                    .unwrap()
+                    .push(diagnostic.clone());
+            }
+        }
+    }
+}
+impl Drop for DiagnosticsHandler {
+    fn drop(&mut self) {
+        use firefly_diagnostics::format;
+
+        if self.format != ErrorFormat::Sarif {
+            return;
+        }
+        let diagnostics = self.sarif_diagnostics.get_mut().unwrap();
+        let log = format::to_sarif(self.codemap.deref(), diagnostics.as_slice());
         let mut buffer = self.emitter.buffer();
-        term::emit(&mut buffer, &self.display, self.codemap.deref(), diagnostic).unwrap();
-        self.emitter.print(&buffer).unwrap();
+        writeln!(&mut buffer, "{}", log).unwrap();
+        self.emitter.print(&buffer).ok();
     }
 }
 
@@ -0,0 +1,158 @@
+//! The allocator backing all memory not allocated on process heaps, e.g. reference-counted
+//! binaries, heap fragments and the data structures of the runtime itself.
+//!
+//! It is selected at build time with the `jemalloc` or `mimalloc` features, and otherwise is the
+//! [`System`](super::System) allocator. Runtimes install it as the global allocator, so it serves
+//! every allocation made via `Global`, including the carriers of the allocators in
+//! [`carriers`](super::carriers). If both features are enabled, jemalloc is used.
+//!
+//! Large binaries dominate the allocations of binary-heavy workloads, which is where the choice
+//! matters most, as allocators differ widely in how they handle blocks of many sizes with mixed
+//! lifetimes. The benchmarks in this module allocate such blocks via [`Backing`], and can be run
+//! against each allocator to compare them:
+//!
+//! ```text
+//! cargo bench -p firefly_alloc backing
+//! cargo bench -p firefly_alloc --features jemalloc backing
+//! cargo bench -p firefly_alloc --features mimalloc backing
+//! ```
+//!
+//! The arenas of jemalloc can be tuned with [`set_decay`], which the runtimes expose as the
+//! `+MBdecay Ms` flag. mimalloc reads its tuning options from `MIMALLOC_*` environment variables
+//! when the program starts, e.g. `MIMALLOC_PURGE_DELAY`, so it needs no flag.
+
+#[cfg(feature = "jemalloc")]
+pub use tikv_jemallocator::Jemalloc as Backing;
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub use mimalloc::MiMalloc as Backing;
+
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub use super::System as Backing;
+
+/// The name of the backing allocator, e.g. for `erlang:system_info(allocator)`
+#[cfg(feature = "jemalloc")]
+pub const NAME: &str = "jemalloc";
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub const NAME: &str = "mimalloc";
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub const NAME: &str = "system";
+
+/// Sets the time in milliseconds after which memory freed in the arenas of the backing allocator
+/// is returned to the operating system, for existing and future arenas
+///
+/// Shorter delays keep the resident size of the program close to what it uses, at the cost of more
+/// system calls when memory is reused, e.g. when large binaries are repeatedly created and freed.
+/// Returns an error if the backing allocator cannot be tuned this way.
+#[cfg(feature = "jemalloc")]
+pub fn set_decay(ms: u32) -> Result<(), &'static str> {
+    use tikv_jemalloc_ctl::raw;
+
+    let ms = ms as isize;
+    // `arena.4096` refers to all existing arenas, i.e. MALLCTL_ARENAS_ALL
+    let names: [&[u8]; 4] = [
+        b"arenas.dirty_decay_ms\0",
+        b"arenas.muzzy_decay_ms\0",
+        b"arena.4096.dirty_decay_ms\0",
+        b"arena.4096.muzzy_decay_ms\0",
+    ];
+    for name in names {
+        unsafe { raw::write(name, ms) }
+            .map_err(|_| "unable to set the decay of jemalloc arenas")?;
+    }
+    Ok(())
+}
+
+/// Sets the time in milliseconds after which memory freed in the arenas of the backing allocator
+/// is returned to the operating system
+///
+/// Returns an error, as only jemalloc can be tuned this way.
+#[cfg(not(feature = "jemalloc"))]
+pub fn set_decay(_ms: u32) -> Result<(), &'static str> {
+    Err(concat!(
+        "arena decay can only be tuned when the backing allocator is jemalloc, mimalloc reads it ",
+        "from MIMALLOC_PURGE_DELAY"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::alloc::{GlobalAlloc, Layout};
+    use alloc::vec::Vec;
+
+    use test::Bencher;
+
+    use super::*;
+
+    /// The sizes of binaries allocated by the benchmarks, from just above the size of heap binaries
+    /// to that of large payloads
+    const SIZES: [usize; 6] = [128, 1024, 8 * 1024, 64 * 1024, 512 * 1024, 4 * 1024 * 1024];
+
+    #[test]
+    fn backing_test() {
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        unsafe {
+            let ptr = Backing.alloc(layout);
+            assert!(!ptr.is_null());
+            ptr.write_bytes(1, 1024);
+            let ptr = Backing.realloc(ptr, layout, 4096);
+            assert!(!ptr.is_null());
+            assert_eq!(*ptr.add(1023), 1);
+            Backing.dealloc(ptr, Layout::from_size_align(4096, 8).unwrap());
+        }
+    }
+
+    /// Allocates and immediately frees binaries of each size, as when binaries are built and
+    /// discarded by a single process
+    #[bench]
+    fn bench_backing_binary_churn(b: &mut Bencher) {
+        b.iter(|| unsafe {
+            for size in SIZES {
+                let layout = Layout::from_size_align(size, 16).unwrap();
+                let ptr = Backing.alloc(layout);
+                ptr.write_bytes(0, size.min(4096));
+                Backing.dealloc(ptr, layout);
+            }
+        })
+    }
+
+    /// Keeps a window of binaries of mixed sizes alive, freeing the oldest as each is allocated, as
+    /// when binaries are passed between processes and outlive their creators
+    #[bench]
+    fn bench_backing_binary_mixed_lifetimes(b: &mut Bencher) {
+        const WINDOW: usize = 64;
+
+        let mut live: Vec<(*mut u8, Layout)> = Vec::with_capacity(WINDOW);
+        let mut next = 0;
+        b.iter(|| unsafe {
+            let size = SIZES[next % SIZES.len()] + next % 7 * 16;
+            next += 1;
+            let layout = Layout::from_size_align(size, 16).unwrap();
+            let ptr = Backing.alloc(layout);
+            ptr.write_bytes(0, size.min(4096));
+            if live.len() == WINDOW {
+                let (old, old_layout) = live.remove(0);
+                Backing.dealloc(old, old_layout);
+            }
+            live.push((ptr, layout));
+        });
+        for (ptr, layout) in live {
+            unsafe { Backing.dealloc(ptr, layout) };
+        }
+    }
+
+    /// Grows a binary by doubling, as when a binary is appended to in a loop
+    #[bench]
+    fn bench_backing_binary_append(b: &mut Bencher) {
+        b.iter(|| unsafe {
+            let mut layout = Layout::from_size_align(64, 16).unwrap();
+            let mut ptr = Backing.alloc(layout);
+            while layout.size() < 1024 * 1024 {
+                let new_size = layout.size() * 2;
+                ptr = Backing.realloc(ptr, layout, new_size);
+                layout = Layout::from_size_align(new_size, 16).unwrap();
+            }
+            Backing.dealloc(ptr, layout);
+        })
+    }
+}
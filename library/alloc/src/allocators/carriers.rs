@@ -0,0 +1,572 @@
+//! Carrier-based allocators, one for each type of memory allocated by the runtime, after the
+//! allocators of ERTS.
+//!
+//! Memory is obtained from the underlying allocator in large chunks, called carriers. Blocks
+//! smaller than the single-block carrier threshold are carved out of multi-block carriers, which
+//! keeps the many small allocations of one type together, and apart from those of other types with
+//! different lifetimes, e.g. process heaps from binaries. Larger blocks are given a single-block
+//! carrier of their own, which is returned to the underlying allocator as soon as the block is
+//! freed.
+//!
+//! The free block a new block is carved from is chosen by the [`Strategy`] of the allocator, and a
+//! freed block is coalesced with the free blocks either side of it. A multi-block carrier which
+//! becomes entirely free is returned to the underlying allocator, unless it is the last one.
+//!
+//! Each allocator keeps [`Stats`] of its carriers, blocks and calls, which runtimes report with
+//! `erlang:system_info({allocator, Name})`.
+use alloc::alloc::{AllocError, Allocator, Global, Layout};
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::ptr::{self, NonNull};
+
+use firefly_system::sync::{const_mutex, Mutex};
+
+/// The alignment of every block of a multi-block carrier, and so the granularity of their sizes
+///
+/// Blocks which must be aligned more strictly than this are given a single-block carrier.
+const BLOCK_ALIGN: usize = 16;
+
+/// The allocator of process heaps and heap fragments
+pub static EHEAP_ALLOC: Carriers<Global> = Carriers::new(Global, Options::DEFAULT);
+/// The allocator of reference-counted binaries
+pub static BINARY_ALLOC: Carriers<Global> = Carriers::new(Global, Options::DEFAULT);
+/// The allocator of ETS tables
+pub static ETS_ALLOC: Carriers<Global> = Carriers::new(Global, Options::DEFAULT);
+/// The allocator of the buffers of ports and drivers
+pub static DRIVER_ALLOC: Carriers<Global> = Carriers::new(Global, Options::DEFAULT);
+
+/// The types of memory which have an allocator of their own
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AllocatorType {
+    Heap,
+    Binary,
+    Ets,
+    Driver,
+}
+impl AllocatorType {
+    pub const ALL: [Self; 4] = [Self::Heap, Self::Binary, Self::Ets, Self::Driver];
+
+    /// Returns the name of the allocator of this type, as known to ERTS, e.g. `eheap_alloc`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Heap => "eheap_alloc",
+            Self::Binary => "binary_alloc",
+            Self::Ets => "ets_alloc",
+            Self::Driver => "driver_alloc",
+        }
+    }
+
+    /// Returns the type of the allocator named `name`, see [`AllocatorType::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    /// Returns the letter identifying the allocator of this type in `+M<S><P> <V>` flags, as in
+    /// ERTS, e.g. `H` in `+MHas bf`
+    pub fn flag(self) -> char {
+        match self {
+            Self::Heap => 'H',
+            Self::Binary => 'B',
+            Self::Ets => 'E',
+            Self::Driver => 'D',
+        }
+    }
+
+    /// Returns the allocator of this type
+    pub fn allocator(self) -> &'static Carriers<Global> {
+        match self {
+            Self::Heap => &EHEAP_ALLOC,
+            Self::Binary => &BINARY_ALLOC,
+            Self::Ets => &ETS_ALLOC,
+            Self::Driver => &DRIVER_ALLOC,
+        }
+    }
+}
+
+/// How a free block is chosen to carve a new block from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    /// The smallest free block large enough is chosen, the lowest addressed of these if there are
+    /// several, which wastes the least memory
+    BestFit,
+    /// The lowest addressed free block large enough is chosen, which packs blocks towards the
+    /// start of the oldest carriers, so that the newer carriers are more likely to empty
+    AddressOrderFirstFit,
+}
+impl Strategy {
+    /// Returns the name of this strategy, as given to the `as` option in ERTS, e.g. `bf`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BestFit => "bf",
+            Self::AddressOrderFirstFit => "aoff",
+        }
+    }
+
+    /// Returns the strategy named `name`, see [`Strategy::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bf" => Some(Self::BestFit),
+            "aoff" => Some(Self::AddressOrderFirstFit),
+            _ => None,
+        }
+    }
+}
+
+/// The options of an allocator
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// The size of multi-block carriers in bytes, i.e. the `lmbcs` option in ERTS
+    ///
+    /// Carriers are all of this size, unless a block is larger, in which case its carrier is sized
+    /// to fit it.
+    pub mbc_size: usize,
+    /// The size in bytes at and above which a block is given a single-block carrier, i.e. the
+    /// `sbct` option in ERTS
+    pub sbc_threshold: usize,
+    /// The strategy by which blocks are placed in multi-block carriers, i.e. the `as` option
+    pub strategy: Strategy,
+}
+impl Options {
+    pub const DEFAULT: Self = Self {
+        mbc_size: 1024 * 1024,
+        sbc_threshold: 512 * 1024,
+        strategy: Strategy::BestFit,
+    };
+}
+impl Default for Options {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The sizes of the blocks and carriers of one kind, with the largest they have been
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CarrierStats {
+    pub blocks: usize,
+    pub blocks_size: usize,
+    pub carriers: usize,
+    pub carriers_size: usize,
+    pub max_blocks: usize,
+    pub max_blocks_size: usize,
+    pub max_carriers: usize,
+    pub max_carriers_size: usize,
+}
+impl CarrierStats {
+    const ZERO: Self = Self {
+        blocks: 0,
+        blocks_size: 0,
+        carriers: 0,
+        carriers_size: 0,
+        max_blocks: 0,
+        max_blocks_size: 0,
+        max_carriers: 0,
+        max_carriers_size: 0,
+    };
+
+    fn add_block(&mut self, size: usize) {
+        self.blocks += 1;
+        self.blocks_size += size;
+        self.max_blocks = self.max_blocks.max(self.blocks);
+        self.max_blocks_size = self.max_blocks_size.max(self.blocks_size);
+    }
+
+    fn remove_block(&mut self, size: usize) {
+        self.blocks -= 1;
+        self.blocks_size -= size;
+    }
+
+    fn add_carrier(&mut self, size: usize) {
+        self.carriers += 1;
+        self.carriers_size += size;
+        self.max_carriers = self.max_carriers.max(self.carriers);
+        self.max_carriers_size = self.max_carriers_size.max(self.carriers_size);
+    }
+
+    fn remove_carrier(&mut self, size: usize) {
+        self.carriers -= 1;
+        self.carriers_size -= size;
+    }
+}
+
+/// The number of calls made to an allocator
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CallStats {
+    pub alloc: u64,
+    pub free: u64,
+    pub realloc: u64,
+}
+
+/// The statistics of an allocator
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The blocks of multi-block carriers, and those carriers
+    pub mbcs: CarrierStats,
+    /// The blocks of single-block carriers, and those carriers
+    pub sbcs: CarrierStats,
+    pub calls: CallStats,
+}
+
+/// An allocator which allocates carriers from `A`, and blocks from those carriers
+pub struct Carriers<A> {
+    alloc: A,
+    state: Mutex<State>,
+}
+
+struct State {
+    options: Options,
+    /// The multi-block carriers, by address, with their sizes
+    carriers: BTreeMap<usize, usize>,
+    /// The free blocks of the multi-block carriers, by address, with their sizes
+    free: BTreeMap<usize, usize>,
+    /// The free blocks of the multi-block carriers, by size then address
+    by_size: BTreeSet<(usize, usize)>,
+    stats: Stats,
+    /// The number of allocations left to fail, see [`Carriers::fail_next`]
+    failures: usize,
+}
+
+impl<A: Allocator> Carriers<A> {
+    pub const fn new(alloc: A, options: Options) -> Self {
+        Self {
+            alloc,
+            state: const_mutex(State {
+                options,
+                carriers: BTreeMap::new(),
+                free: BTreeMap::new(),
+                by_size: BTreeSet::new(),
+                stats: Stats {
+                    mbcs: CarrierStats::ZERO,
+                    sbcs: CarrierStats::ZERO,
+                    calls: CallStats {
+                        alloc: 0,
+                        free: 0,
+                        realloc: 0,
+                    },
+                },
+                failures: 0,
+            }),
+        }
+    }
+
+    /// Returns the options of this allocator
+    pub fn options(&self) -> Options {
+        self.state.lock().options
+    }
+
+    /// Sets the options of this allocator
+    ///
+    /// Existing carriers are unaffected, only those allocated from now on are sized and filled
+    /// according to the new options.
+    pub fn set_options(&self, options: Options) {
+        self.state.lock().options = options;
+    }
+
+    /// Returns the statistics of this allocator
+    pub fn stats(&self) -> Stats {
+        self.state.lock().stats
+    }
+
+    /// Makes the next `n` allocations from this allocator fail, as though it were out of memory
+    ///
+    /// This is for the test suites of runtimes, so that they can exercise their handling of
+    /// allocation failures. Reallocations count as allocations, frees are unaffected.
+    pub fn fail_next(&self, n: usize) {
+        self.state.lock().failures = n;
+    }
+
+    fn allocate_block(
+        &self,
+        state: &mut State,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if state.failures > 0 {
+            state.failures -= 1;
+            return Err(AllocError);
+        }
+        let size = block_size(layout);
+        if size >= state.options.sbc_threshold || layout.align() > BLOCK_ALIGN {
+            let ptr = self.alloc.allocate(layout)?;
+            state.stats.sbcs.add_block(layout.size());
+            state.stats.sbcs.add_carrier(layout.size());
+            return Ok(ptr);
+        }
+
+        let addr = match state.find(size) {
+            Some(addr) => addr,
+            None => {
+                let carrier_size = state.options.mbc_size.max(size);
+                let carrier_layout = Layout::from_size_align(carrier_size, BLOCK_ALIGN).unwrap();
+                let carrier = self.alloc.allocate(carrier_layout)?;
+                let addr = carrier.as_mut_ptr() as usize;
+                state.carriers.insert(addr, carrier_size);
+                state.insert_free(addr, carrier_size);
+                state.stats.mbcs.add_carrier(carrier_size);
+                addr
+            }
+        };
+        let free_size = state.remove_free(addr);
+        if free_size > size {
+            state.insert_free(addr + size, free_size - size);
+        }
+        state.stats.mbcs.add_block(size);
+        let ptr = unsafe { NonNull::new_unchecked(addr as *mut u8) };
+        Ok(NonNull::slice_from_raw_parts(ptr, size))
+    }
+
+    unsafe fn deallocate_block(&self, state: &mut State, ptr: NonNull<u8>, layout: Layout) {
+        let addr = ptr.as_ptr() as usize;
+        let Some((start, carrier_size)) = state.carrier(addr) else {
+            self.alloc.deallocate(ptr, layout);
+            state.stats.sbcs.remove_block(layout.size());
+            state.stats.sbcs.remove_carrier(layout.size());
+            return;
+        };
+        let size = block_size(layout);
+        state.stats.mbcs.remove_block(size);
+
+        // Coalesce the block with the free blocks either side of it in the same carrier
+        let mut addr = addr;
+        let mut size = size;
+        let end = start + carrier_size;
+        if addr + size < end && state.free.contains_key(&(addr + size)) {
+            size += state.remove_free(addr + size);
+        }
+        if let Some((&prev, &prev_size)) = state.free.range(start..addr).next_back() {
+            if prev + prev_size == addr {
+                state.remove_free(prev);
+                addr = prev;
+                size += prev_size;
+            }
+        }
+
+        if size == carrier_size && state.carriers.len() > 1 {
+            state.carriers.remove(&start);
+            state.stats.mbcs.remove_carrier(carrier_size);
+            let carrier_layout = Layout::from_size_align(carrier_size, BLOCK_ALIGN).unwrap();
+            self.alloc
+                .deallocate(NonNull::new_unchecked(start as *mut u8), carrier_layout);
+        } else {
+            state.insert_free(addr, size);
+        }
+    }
+}
+
+impl State {
+    /// Returns the address of a free block of at least `size` bytes, chosen by the strategy
+    fn find(&self, size: usize) -> Option<usize> {
+        match self.options.strategy {
+            Strategy::BestFit => self
+                .by_size
+                .range((size, 0)..)
+                .next()
+                .map(|(_, addr)| *addr),
+            Strategy::AddressOrderFirstFit => self
+                .free
+                .iter()
+                .find(|(_, free_size)| **free_size >= size)
+                .map(|(addr, _)| *addr),
+        }
+    }
+
+    /// Returns the address and size of the multi-block carrier containing `addr`, if there is one
+    fn carrier(&self, addr: usize) -> Option<(usize, usize)> {
+        self.carriers
+            .range(..=addr)
+            .next_back()
+            .filter(|(start, size)| addr < **start + **size)
+            .map(|(start, size)| (*start, *size))
+    }
+
+    fn insert_free(&mut self, addr: usize, size: usize) {
+        self.free.insert(addr, size);
+        self.by_size.insert((size, addr));
+    }
+
+    /// Removes the free block at `addr`, returning its size
+    fn remove_free(&mut self, addr: usize) -> usize {
+        let size = self.free.remove(&addr).unwrap();
+        self.by_size.remove(&(size, addr));
+        size
+    }
+}
+
+/// Returns the size of the block allocated for `layout` in a multi-block carrier
+#[inline]
+fn block_size(layout: Layout) -> usize {
+    let size = layout.size().max(1);
+    (size + BLOCK_ALIGN - 1) & !(BLOCK_ALIGN - 1)
+}
+
+unsafe impl<A: Allocator> Allocator for Carriers<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let mut state = self.state.lock();
+        state.stats.calls.alloc += 1;
+        self.allocate_block(&mut state, layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let mut state = self.state.lock();
+        state.stats.calls.free += 1;
+        self.deallocate_block(&mut state, ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout)
+    }
+}
+
+impl<A: Allocator> Carriers<A> {
+    /// Moves a block to a new block of `new_layout`, keeping as much of its contents as fits
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let mut state = self.state.lock();
+        state.stats.calls.realloc += 1;
+        let new_ptr = self.allocate_block(&mut state, new_layout)?;
+        let len = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_mut_ptr(), len);
+        self.deallocate_block(&mut state, ptr, old_layout);
+        Ok(new_ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::super::System;
+    use super::*;
+
+    fn carriers(strategy: Strategy) -> Carriers<System> {
+        Carriers::new(
+            System,
+            Options {
+                mbc_size: 4096,
+                sbc_threshold: 1024,
+                strategy,
+            },
+        )
+    }
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 8).unwrap()
+    }
+
+    #[test]
+    fn carriers_test() {
+        let allocator = carriers(Strategy::BestFit);
+        let blocks = (0..8)
+            .map(|_| allocator.allocate(layout(100)).unwrap().as_non_null_ptr())
+            .collect::<Vec<_>>();
+        let large = allocator.allocate(layout(2048)).unwrap().as_non_null_ptr();
+
+        let stats = allocator.stats();
+        assert_eq!(stats.calls.alloc, 9);
+        assert_eq!(stats.mbcs.blocks, 8);
+        assert_eq!(stats.mbcs.blocks_size, 8 * 112);
+        assert_eq!(stats.mbcs.carriers, 1);
+        assert_eq!(stats.mbcs.carriers_size, 4096);
+        assert_eq!(stats.sbcs.blocks, 1);
+        assert_eq!(stats.sbcs.carriers_size, 2048);
+
+        // Blocks which do not fit in the carrier get a new one, which is freed when empty
+        let more = (0..40)
+            .map(|_| allocator.allocate(layout(100)).unwrap().as_non_null_ptr())
+            .collect::<Vec<_>>();
+        assert_eq!(allocator.stats().mbcs.carriers, 2);
+        for ptr in more {
+            unsafe { allocator.deallocate(ptr, layout(100)) };
+        }
+        assert_eq!(allocator.stats().mbcs.carriers, 1);
+
+        unsafe {
+            allocator.deallocate(large, layout(2048));
+            for ptr in blocks {
+                allocator.deallocate(ptr, layout(100));
+            }
+        }
+        let stats = allocator.stats();
+        assert_eq!(stats.calls.free, 49);
+        assert_eq!(stats.mbcs.blocks, 0);
+        assert_eq!(stats.mbcs.max_blocks, 48);
+        assert_eq!(stats.sbcs.carriers, 0);
+
+        // Every block has been coalesced back into the one remaining carrier
+        let state = allocator.state.lock();
+        assert_eq!(state.free.len(), 1);
+        assert_eq!(state.free.values().next(), Some(&4096));
+    }
+
+    #[test]
+    fn carriers_strategy_test() {
+        for strategy in [Strategy::BestFit, Strategy::AddressOrderFirstFit] {
+            let allocator = carriers(strategy);
+            // Leave a hole of 256 bytes, then one of 64 bytes
+            let a = allocator.allocate(layout(256)).unwrap().as_non_null_ptr();
+            let _b = allocator.allocate(layout(16)).unwrap();
+            let c = allocator.allocate(layout(64)).unwrap().as_non_null_ptr();
+            let _d = allocator.allocate(layout(16)).unwrap();
+            unsafe {
+                allocator.deallocate(a, layout(256));
+                allocator.deallocate(c, layout(64));
+            }
+
+            let e = allocator.allocate(layout(48)).unwrap().as_non_null_ptr();
+            match strategy {
+                Strategy::BestFit => assert_eq!(e, c),
+                Strategy::AddressOrderFirstFit => assert_eq!(e, a),
+            }
+        }
+    }
+
+    #[test]
+    fn carriers_realloc_test() {
+        let allocator = carriers(Strategy::BestFit);
+        unsafe {
+            let ptr = allocator.allocate(layout(64)).unwrap().as_mut_ptr();
+            ptr.write_bytes(7, 64);
+            let ptr = NonNull::new_unchecked(ptr);
+            let grown = allocator.grow(ptr, layout(64), layout(512)).unwrap();
+            assert_eq!(*grown.as_mut_ptr().add(63), 7);
+            let grown = grown.as_non_null_ptr();
+            let moved = allocator.grow(grown, layout(512), layout(2048)).unwrap();
+            assert_eq!(*moved.as_mut_ptr().add(63), 7);
+            allocator.deallocate(moved.as_non_null_ptr(), layout(2048));
+        }
+        let stats = allocator.stats();
+        assert_eq!(stats.calls.realloc, 2);
+        assert_eq!(stats.mbcs.blocks, 0);
+        assert_eq!(stats.sbcs.blocks, 0);
+    }
+
+    #[test]
+    fn carriers_fail_next_test() {
+        let allocator = carriers(Strategy::BestFit);
+        allocator.fail_next(2);
+        assert!(allocator.allocate(layout(64)).is_err());
+        assert!(allocator.allocate(layout(2048)).is_err());
+        let ptr = allocator.allocate(layout(64)).unwrap().as_non_null_ptr();
+        unsafe { allocator.deallocate(ptr, layout(64)) };
+
+        let stats = allocator.stats();
+        assert_eq!(stats.calls.alloc, 3);
+        assert_eq!(stats.mbcs.max_blocks, 1);
+        assert_eq!(stats.sbcs.max_blocks, 0);
+    }
+}
@@ -0,0 +1,153 @@
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The limit on the memory which may be allocated, or zero if there is none
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+/// The number of bytes currently allocated via any [`Limited`] allocator
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// The percentage of the limit above which the system is considered to be under memory pressure
+pub const HIGH_WATER_PERCENT: usize = 90;
+
+/// This allocator wraps another global allocator, keeping track of the memory allocated, and
+/// refusing allocations which would exceed the limit set by [`set_limit`].
+///
+/// It is intended to be used as the `#[global_allocator]`, so that the memory used by the system as
+/// a whole can be bounded, e.g. to stay within the limits of a container. The accounting is global,
+/// so there should only be one such allocator in a program.
+#[derive(Debug, Copy, Clone)]
+pub struct Limited<A> {
+    inner: A,
+}
+impl<A> Limited<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+/// Sets the limit on the memory which may be allocated, in bytes, or removes it if `None`
+///
+/// Memory which is already allocated is unaffected, even if it exceeds the new limit.
+pub fn set_limit(limit: Option<usize>) {
+    LIMIT.store(limit.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Returns the limit on the memory which may be allocated, in bytes, if there is one
+pub fn limit() -> Option<usize> {
+    match LIMIT.load(Ordering::Relaxed) {
+        0 => None,
+        limit => Some(limit),
+    }
+}
+
+/// Returns the number of bytes currently allocated
+pub fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// Returns true if the memory allocated exceeds [`HIGH_WATER_PERCENT`] of the limit
+pub fn is_under_pressure() -> bool {
+    match limit() {
+        None => false,
+        Some(limit) => allocated() >= limit / 100 * HIGH_WATER_PERCENT,
+    }
+}
+
+/// Accounts for the allocation of `size` bytes, returning false if it would exceed the limit
+#[inline]
+fn reserve(size: usize) -> bool {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let previous = ALLOCATED.fetch_add(size, Ordering::Relaxed);
+    if limit != 0 && previous.saturating_add(size) > limit {
+        ALLOCATED.fetch_sub(size, Ordering::Relaxed);
+        return false;
+    }
+    true
+}
+
+#[inline]
+fn release(size: usize) {
+    ALLOCATED.fetch_sub(size, Ordering::Relaxed);
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Limited<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if !reserve(layout.size()) {
+            return ptr::null_mut();
+        }
+        let ptr = self.inner.alloc(layout);
+        if ptr.is_null() {
+            release(layout.size());
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if !reserve(layout.size()) {
+            return ptr::null_mut();
+        }
+        let ptr = self.inner.alloc_zeroed(layout);
+        if ptr.is_null() {
+            release(layout.size());
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        release(layout.size());
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let old_size = layout.size();
+        if new_size > old_size && !reserve(new_size - old_size) {
+            return ptr::null_mut();
+        }
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if new_ptr.is_null() {
+            if new_size > old_size {
+                release(new_size - old_size);
+            }
+        } else if new_size < old_size {
+            release(old_size - new_size);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::System;
+    use super::*;
+
+    #[test]
+    fn limited_test() {
+        let allocator = Limited::new(System);
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        let base = allocated();
+        set_limit(Some(base + 2048));
+        unsafe {
+            let a = allocator.alloc(layout);
+            assert!(!a.is_null());
+            assert_eq!(allocated(), base + 1024);
+            assert!(allocator.realloc(a, layout, 4096).is_null());
+            assert_eq!(allocated(), base + 1024);
+
+            let b = allocator.alloc(layout);
+            assert!(!b.is_null());
+            assert!(is_under_pressure());
+            assert!(allocator.alloc(layout).is_null());
+
+            allocator.dealloc(a, layout);
+            allocator.dealloc(b, layout);
+        }
+        assert_eq!(allocated(), base);
+        assert!(!is_under_pressure());
+        set_limit(None);
+    }
+}
@@ -1,3 +1,9 @@
+pub mod backing;
+pub mod carriers;
+pub mod limited;
 mod system;
 
+pub use self::backing::Backing;
+pub use self::carriers::{AllocatorType, Carriers, BINARY_ALLOC, EHEAP_ALLOC};
+pub use self::limited::Limited;
 pub use self::system::System;
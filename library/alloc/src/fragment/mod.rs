@@ -1,5 +1,6 @@
-use alloc::alloc::{AllocError, Allocator, Global, Layout};
+use alloc::alloc::{AllocError, Allocator, Layout};
 use alloc::boxed::Box;
+use core::cell::UnsafeCell;
 use core::cmp;
 use core::ops::Range;
 use core::ptr::{self, NonNull};
@@ -9,6 +10,7 @@ use intrusive_collections::{LinkedListLink, UnsafeRef};
 
 use firefly_system::arch::MIN_ALIGN;
 
+use crate::allocators::EHEAP_ALLOC;
 use crate::heap::Heap;
 
 // This adapter is used to track a list of heap fragments, attached to a process
@@ -48,7 +50,7 @@ pub struct HeapFragment {
     raw: RawFragment,
     /// A pointer to the top of the allocated region of this fragment,
     /// e.g. when the fragment is unused, `top == raw.base`
-    top: *mut u8,
+    top: UnsafeCell<*mut u8>,
     /// An optional destructor for this fragment
     destructor: Option<Box<dyn Fn(NonNull<u8>)>>,
 }
@@ -69,14 +71,14 @@ impl HeapFragment {
         let layout = layout.align_to(align).unwrap().pad_to_align();
 
         let (full_layout, offset) = Layout::new::<Self>().extend(layout.clone()).unwrap();
-        let ptr: NonNull<u8> = Global.allocate(full_layout)?.cast();
+        let ptr: NonNull<u8> = EHEAP_ALLOC.allocate(full_layout)?.cast();
         let header = ptr.as_ptr() as *mut Self;
         let base = unsafe { NonNull::new_unchecked(ptr.as_ptr().add(offset)) };
         unsafe {
             header.write(Self {
                 link: LinkedListLink::new(),
                 raw: RawFragment { layout, base },
-                top: base.as_ptr(),
+                top: UnsafeCell::new(base.as_ptr()),
                 destructor,
             });
             Ok(NonNull::new_unchecked(header))
@@ -95,7 +97,7 @@ impl Drop for HeapFragment {
         let (layout, _offset) = Layout::new::<Self>().extend(self.raw.layout()).unwrap();
         unsafe {
             let ptr = NonNull::new_unchecked(self as *const _ as *mut u8);
-            Global.deallocate(ptr, layout);
+            EHEAP_ALLOC.deallocate(ptr, layout);
         }
     }
 }
@@ -106,14 +108,17 @@ unsafe impl Allocator for HeapFragment {
 
         // Calculate the base pointer of the allocation at the desired alignment,
         // then offset that pointer by the desired size to give us the new top
-        let top = self.top;
+        let top = unsafe { *self.top.get() };
         let offset = top.align_offset(layout.align());
         let base = unsafe { top.add(offset) };
         let new_top = unsafe { base.add(size) };
 
-        // Make sure the requested allocation fits within the fragment
+        // Make sure the requested allocation fits within the fragment, which it may fill exactly
         let range = self.raw.as_ptr_range();
-        if range.contains(&new_top) {
+        if new_top <= range.end {
+            unsafe {
+                self.top.get().write(new_top);
+            }
             Ok(unsafe { NonNull::new_unchecked(ptr::from_raw_parts_mut(base.cast(), size)) })
         } else {
             Err(AllocError)
@@ -156,7 +161,7 @@ impl Heap for HeapFragment {
 
     #[inline]
     fn heap_top(&self) -> *mut u8 {
-        self.top
+        unsafe { *self.top.get() }
     }
 
     #[inline]
@@ -23,6 +23,9 @@
 #![feature(min_specialization)]
 // Used for const TypeId::of::<T>()
 #![feature(const_type_id)]
+// Used for the statically-initialized allocators of allocators::carriers
+#![feature(const_btree_new)]
+#![cfg_attr(test, feature(test))]
 
 extern crate alloc;
 #[cfg(feature = "std")]
@@ -1,4 +1,4 @@
-use alloc::alloc::{AllocError, Allocator, Global, Layout};
+use alloc::alloc::{AllocError, Allocator, Layout};
 use alloc::borrow::{self, Cow};
 use core::any::{Any, TypeId};
 use core::fmt::{self, Debug, Display};
@@ -11,6 +11,8 @@ use core::sync::atomic::AtomicUsize;
 
 use static_assertions::assert_eq_size;
 
+use crate::allocators::BINARY_ALLOC;
+
 use firefly_binary::{Aligned, Binary, BinaryFlags, Bitstring, ByteIter, Encoding};
 
 use crate::WriteCloneIntoRaw;
@@ -237,7 +239,7 @@ where
         let meta = Metadata::new::<T>(&value);
         let value_layout = Layout::for_value(&value);
         let (layout, value_offset) = Layout::new::<Metadata>().extend(value_layout).unwrap();
-        let ptr: NonNull<()> = Global.allocate(layout).unwrap().cast();
+        let ptr: NonNull<()> = BINARY_ALLOC.allocate(layout).unwrap().cast();
         unsafe {
             let ptr = NonNull::new_unchecked(ptr.as_ptr().byte_add(value_offset));
             let boxed = Self {
@@ -253,7 +255,7 @@ where
     pub fn new_uninit() -> Rc<MaybeUninit<T>> {
         let value_layout = Layout::new::<T>();
         let (layout, value_offset) = Layout::new::<Metadata>().extend(value_layout).unwrap();
-        let ptr: NonNull<()> = Global.allocate(layout).unwrap().cast();
+        let ptr: NonNull<()> = BINARY_ALLOC.allocate(layout).unwrap().cast();
         unsafe {
             let ptr = NonNull::new_unchecked(ptr.as_ptr().byte_add(value_offset));
             let meta = Metadata::new::<T>(ptr.as_ptr() as *mut T);
@@ -277,7 +279,7 @@ where
         let meta = Metadata::new::<T>(unsized_);
         let value_layout = Layout::for_value(&value);
         let (layout, value_offset) = Layout::new::<Metadata>().extend(value_layout).unwrap();
-        let ptr: NonNull<()> = Global.allocate(layout).unwrap().cast();
+        let ptr: NonNull<()> = BINARY_ALLOC.allocate(layout).unwrap().cast();
         unsafe {
             let ptr = NonNull::new_unchecked(ptr.as_ptr().byte_add(value_offset));
             let boxed = Self {
@@ -432,7 +434,7 @@ where
             ptr::drop_in_place(value);
         }
         let ptr = NonNull::new_unchecked(self.ptr.as_ptr().byte_sub(value_offset));
-        Global.deallocate(ptr.cast(), layout);
+        BINARY_ALLOC.deallocate(ptr.cast(), layout);
     }
 }
 
@@ -584,9 +586,13 @@ where
     T: ?Sized + 'static + Pointee<Metadata = usize>,
 {
     pub fn with_capacity(cap: usize) -> Self {
-        Self::with_capacity_in(cap, Global).unwrap()
+        Self::with_capacity_in(cap, &BINARY_ALLOC).unwrap()
     }
 
+    /// Allocates an `Rc` of `cap` elements with `alloc`
+    ///
+    /// An `Rc` is always freed by [`BINARY_ALLOC`] when its last reference is dropped, so `alloc`
+    /// must allocate from the same memory, e.g. be `&BINARY_ALLOC` itself.
     pub fn with_capacity_in<A: Allocator>(cap: usize, alloc: A) -> Result<Self, AllocError> {
         let empty = ptr::from_raw_parts::<T>(ptr::null() as *const (), cap);
         let meta = Metadata::new::<T>(empty);
@@ -1426,6 +1432,7 @@ pub struct InvalidPtrMetadata;
 mod test {
     use super::*;
 
+    #[derive(Clone)]
     struct Cons {
         head: usize,
         tail: usize,
@@ -1491,25 +1498,25 @@ mod test {
     fn rcbox_clone_and_drop_correctly_modifies_refcount() {
         let first = Rc::new(Cons { head: 1, tail: 2 });
         {
-            let second = first.clone();
-            assert_eq!(first.strong_count(), 2);
+            let _second = first.clone();
+            assert_eq!(Rc::strong_count(&first), 2);
         }
-        assert_eq!(first.strong_count(), 1);
+        assert_eq!(Rc::strong_count(&first), 1);
     }
 
     #[test]
     fn rcbox_make_mut() {
         // A unique reference doesn't clone
         let mut first = Rc::new(Cons { head: 1, tail: 2 });
-        *Rc::make_mut(&mut first).head = 2;
-        assert_eq!(first.strong_count(), 1);
+        Rc::make_mut(&mut first).head = 2;
+        assert_eq!(Rc::strong_count(&first), 1);
         assert_eq!(first.head, 2);
         // But a non-unique references causes a clone
         let mut second = first.clone();
-        *Rc::make_mut(&mut second).head = 3;
-        assert_eq!(first.strong_count(), 1);
+        Rc::make_mut(&mut second).head = 3;
+        assert_eq!(Rc::strong_count(&first), 1);
         assert_eq!(first.head, 2);
-        assert_eq!(second.strong_count(), 1);
+        assert_eq!(Rc::strong_count(&second), 1);
         assert_eq!(second.head, 3);
     }
 }
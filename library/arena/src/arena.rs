@@ -368,6 +368,22 @@ impl DroplessArena {
         }
     }
 
+    /// Returns the number of bytes allocated for this arena's chunks
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks
+            .borrow()
+            .iter()
+            .map(|chunk| chunk.storage.len())
+            .sum()
+    }
+
+    /// Returns the number of bytes of this arena's chunks which are not free, including those
+    /// left unused at the end of chunks which were full
+    pub fn used_bytes(&self) -> usize {
+        let free = self.end.get() as usize - self.start.get() as usize;
+        self.allocated_bytes() - free
+    }
+
     #[inline]
     pub fn alloc_raw(&self, layout: Layout) -> *mut u8 {
         assert!(layout.size() != 0);
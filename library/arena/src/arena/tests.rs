@@ -1,5 +1,5 @@
 extern crate test;
-use super::TypedArena;
+use super::{DroplessArena, TypedArena};
 use std::cell::Cell;
 use test::Bencher;
 
@@ -106,6 +106,18 @@ pub fn test_typed_arena_zero_sized() {
     }
 }
 
+#[test]
+pub fn test_dropless_arena_bytes() {
+    let arena = DroplessArena::default();
+    assert_eq!(arena.allocated_bytes(), 0);
+    assert_eq!(arena.used_bytes(), 0);
+    arena.alloc_slice(b"hello");
+    assert!(arena.allocated_bytes() >= 5);
+    assert_eq!(arena.used_bytes(), 5);
+    arena.alloc_slice(b"world");
+    assert_eq!(arena.used_bytes(), 10);
+}
+
 #[test]
 pub fn test_typed_arena_clear() {
     let mut arena = TypedArena::default();
@@ -22,6 +22,12 @@ pub enum FromBeamError {
     #[error("missing module attribute")]
     NoModuleAttribute,
 
+    #[error("missing {0} chunk")]
+    MissingChunk(&'static str),
+
+    #[error("invalid code: {0}")]
+    InvalidCode(anyhow::Error),
+
     #[error("unexpected term: {0}")]
     UnexpectedTerm(UnmatchedTerms),
 }
@@ -0,0 +1,243 @@
+//! The interface of a compiled module, as far as it can be recovered from its BEAM file without
+//! debug info: its name, the functions it exports, and the values of those which do nothing but
+//! return a constant.
+//!
+//! This is what is needed to stand in for a module which cannot be compiled yet, e.g. one from an
+//! OTP application whose dependencies are not all supported, see `firefly stubs`.
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+
+use num::bigint::BigInt;
+use num::ToPrimitive;
+
+use super::compact::{self, opcodes, Operand, Operation};
+use super::{AtomChunk, CodeChunk, ExpTChunk, LitTChunk, StandardChunk};
+use super::{FromBeamError, StandardBeamFile};
+use crate::serialization::etf;
+
+/// The interface of a compiled module
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleInterface {
+    /// The name of the module
+    pub name: String,
+    /// The functions exported by the module, in the order they appear in the export table
+    pub exports: Vec<ExportedFunction>,
+}
+
+/// A function exported by a compiled module
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedFunction {
+    pub name: String,
+    pub arity: u32,
+    /// The value returned by this function, if all it does is return a constant
+    pub constant: Option<etf::Term>,
+}
+
+impl ModuleInterface {
+    /// Reads the interface of the module compiled to the BEAM file at `path`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, FromBeamError> {
+        let beam = StandardBeamFile::from_file(path)?;
+        Self::from_beam(&beam)
+    }
+
+    /// Reads the interface of the module compiled to `beam`
+    pub fn from_beam(beam: &StandardBeamFile) -> Result<Self, FromBeamError> {
+        let Some(StandardChunk::Atom(AtomChunk { atoms, .. })) = beam.atoms() else {
+            return Err(FromBeamError::MissingChunk("Atom"));
+        };
+        let Some(StandardChunk::ExpT(ExpTChunk { exports })) = beam.get_chunk(b"ExpT") else {
+            return Err(FromBeamError::MissingChunk("ExpT"));
+        };
+        let Some(StandardChunk::Code(code)) = beam.get_chunk(b"Code") else {
+            return Err(FromBeamError::MissingChunk("Code"));
+        };
+        let literals = match beam.get_chunk(b"LitT") {
+            Some(StandardChunk::LitT(LitTChunk { literals })) => literals.as_slice(),
+            _ => &[],
+        };
+        // Atoms are indexed from one, zero being reserved for `[]`
+        let atom = |index: u32| {
+            index
+                .checked_sub(1)
+                .and_then(|index| atoms.get(index as usize))
+                .map(|atom| atom.name.clone())
+                .ok_or_else(|| invalid_code(format!("invalid atom index {}", index)))
+        };
+        let code = Code::decode(code).map_err(FromBeamError::InvalidCode)?;
+
+        let name = atom(1)?;
+        let mut functions = Vec::with_capacity(exports.len());
+        for export in exports.iter() {
+            let constant = match code.constant(export.label) {
+                None => None,
+                Some(Operand::Atom(0)) => Some(etf::List::nil().into()),
+                Some(Operand::Atom(index)) => Some(etf::Atom::from(atom(*index)?).into()),
+                Some(Operand::Integer(value)) => Some(integer(value)),
+                Some(Operand::Literal(index)) => {
+                    let literal = literals
+                        .get(*index as usize)
+                        .ok_or_else(|| invalid_code(format!("invalid literal index {}", index)))?;
+                    Some(etf::Term::decode(Cursor::new(literal))?)
+                }
+                Some(_) => None,
+            };
+            functions.push(ExportedFunction {
+                name: atom(export.function)?,
+                arity: export.arity,
+                constant,
+            });
+        }
+        Ok(Self {
+            name,
+            exports: functions,
+        })
+    }
+}
+
+/// The operations of a module, with the positions of its labels
+struct Code {
+    operations: Vec<Operation>,
+    labels: HashMap<u32, usize>,
+}
+impl Code {
+    fn decode(chunk: &CodeChunk) -> anyhow::Result<Self> {
+        // The header fields are followed by any which were added after them, which are skipped
+        let extra = (chunk.info_size as usize).saturating_sub(16);
+        let mut reader = Cursor::new(chunk.bytecode.get(extra..).unwrap_or_default());
+        let mut operations = Vec::new();
+        let mut labels = HashMap::new();
+        loop {
+            let operation = compact::read_operation(&mut reader)?;
+            match operation.opcode {
+                opcodes::INT_CODE_END => break,
+                opcodes::LABEL => {
+                    let label = operation.operands.first().and_then(Operand::as_u32);
+                    let label = label.ok_or_else(|| anyhow::anyhow!("invalid label"))?;
+                    labels.insert(label, operations.len());
+                }
+                _ => (),
+            }
+            operations.push(operation);
+        }
+        Ok(Self { operations, labels })
+    }
+
+    /// Returns the operand moved into the return register by the function at `label`, if all it
+    /// does is return it
+    fn constant(&self, label: u32) -> Option<&Operand> {
+        let start = self.labels.get(&label)? + 1;
+        let mut body = self.operations[start..]
+            .iter()
+            .filter(|op| op.opcode != opcodes::LINE);
+        let (Some(mov), Some(ret)) = (body.next(), body.next()) else {
+            return None;
+        };
+        match (mov.opcode, mov.operands.as_slice(), ret.opcode) {
+            (opcodes::MOVE, [source, Operand::X(0)], opcodes::RETURN) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_code(message: String) -> FromBeamError {
+    FromBeamError::InvalidCode(anyhow::anyhow!(message))
+}
+
+fn integer(value: &BigInt) -> etf::Term {
+    match value.to_i32() {
+        Some(value) => etf::FixInteger::from(value).into(),
+        None => etf::BigInteger {
+            value: value.clone(),
+        }
+        .into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::beam::{Atom, Export};
+
+    #[test]
+    fn exports_test() {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/testdata/reader/test.beam");
+        let interface = ModuleInterface::from_file(path).unwrap();
+        assert_eq!(interface.name, "test");
+        let exports = interface
+            .exports
+            .iter()
+            .map(|f| (f.name.as_str(), f.arity, f.constant.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            exports,
+            vec![
+                ("module_info", 1, false),
+                ("module_info", 0, false),
+                ("hello", 1, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn constants_test() {
+        let mut beam = StandardBeamFile::new();
+        let atoms = ["consts", "name", "pair", "none"].map(|name| Atom {
+            name: name.to_string(),
+        });
+        beam.push_chunk(StandardChunk::Atom(AtomChunk {
+            is_unicode: true,
+            atoms: atoms.into(),
+        }));
+        #[rustfmt::skip]
+        let bytecode = vec![
+            // name() -> consts.
+            0x01, 0x10, 0x02, 0x12, 0x22, 0x00, 0x01, 0x20, 0x40, 0x12, 0x03, 0x13,
+            // pair() -> {ok, 42}, with a line annotation
+            0x01, 0x30, 0x02, 0x12, 0x32, 0x00, 0x01, 0x40, 0x99, 0x10, 0x40, 0x47, 0x00, 0x03,
+            0x13,
+            // none(X) -> X.
+            0x01, 0x50, 0x02, 0x12, 0x42, 0x10, 0x01, 0x60, 0x13,
+            0x03,
+        ];
+        beam.push_chunk(StandardChunk::Code(CodeChunk {
+            info_size: 16,
+            version: 0,
+            opcode_max: 153,
+            label_count: 7,
+            function_count: 3,
+            bytecode,
+        }));
+        let exports = [(2, 0, 2), (3, 0, 4), (4, 1, 6)].map(|(function, arity, label)| Export {
+            function,
+            arity,
+            label,
+        });
+        beam.push_chunk(StandardChunk::ExpT(ExpTChunk {
+            exports: exports.into(),
+        }));
+        let literal = vec![131, 104, 2, 100, 0, 2, b'o', b'k', 97, 42];
+        beam.push_chunk(StandardChunk::LitT(LitTChunk {
+            literals: vec![literal],
+        }));
+
+        let interface = ModuleInterface::from_beam(&beam).unwrap();
+        assert_eq!(interface.name, "consts");
+        let constants = interface
+            .exports
+            .iter()
+            .map(|f| f.constant.as_ref().map(|c| c.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            constants,
+            vec![
+                Some("'consts'".to_string()),
+                Some("{'ok',42}".to_string()),
+                None
+            ]
+        );
+    }
+}
@@ -22,8 +22,10 @@
 //!   com/KronicDeth/intellij-elixir/blob/master/src/org/elixir_lang/beam/Beam.kt) in Kotlin
 mod code;
 mod errors;
+mod interface;
 mod reader;
 
 pub use self::code::AbstractCode;
 pub use self::errors::*;
+pub use self::interface::{ExportedFunction, ModuleInterface};
 pub use self::reader::*;
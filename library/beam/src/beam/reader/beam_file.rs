@@ -9,6 +9,7 @@ use super::chunk::{Chunk, Id};
 use super::{ReadError, Result};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use memmap::Mmap;
 
 /// A BEAM File
 ///
@@ -101,9 +102,13 @@ impl<C: Chunk> BeamFile<C> {
         self.chunks
             .retain(|&id, ref mut c| predicate(&id, &c) == false)
     }
+    /// Reads a BEAM file from `path`, which is memory-mapped rather than read into a buffer
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let f = File::open(path)?;
-        Self::from_reader(f)
+        // SAFETY: The mapping is only read while decoding, during which the file must not be
+        // truncated, as with any memory-mapped file
+        let mmap = unsafe { Mmap::map(&f)? };
+        Self::from_reader(&mmap[..])
     }
     pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
         let expected = Header::new(0);
@@ -171,10 +171,16 @@ impl Chunk for AtomChunk {
             }
             Ok(_) => unicode = false,
         }
-        let count = reader.read_u32::<BigEndian>()? as usize;
-        let mut atoms = Vec::with_capacity(count);
-        for _ in 0..count {
-            let len = reader.read_u8()? as usize;
+        // Newer compilers negate the count to signal that the length of each atom is encoded in
+        // the compact term format, rather than as a byte, so that names can be longer
+        let count = reader.read_i32::<BigEndian>()?;
+        let mut atoms = Vec::with_capacity(count.unsigned_abs() as usize);
+        for _ in 0..count.unsigned_abs() {
+            let len = if count < 0 {
+                super::compact::read_unsigned(&mut reader)? as usize
+            } else {
+                reader.read_u8()? as usize
+            };
             let mut buf = vec![0; len];
             reader.read_exact(&mut buf)?;
 
@@ -452,17 +458,13 @@ impl Chunk for LitTChunk {
         Self: Sized,
     {
         auxiliary::check_chunk_id(id, b"LitT")?;
-        let _uncompressed_size = reader.read_u32::<BigEndian>()?;
-        let mut decoder = zlib::Decoder::new(reader)?;
-
-        let count = decoder.read_u32::<BigEndian>()? as usize;
-        let mut literals = Vec::with_capacity(count);
-        for _ in 0..count {
-            let literal_size = decoder.read_u32::<BigEndian>()? as usize;
-            let mut buf = vec![0; literal_size];
-            decoder.read_exact(&mut buf)?;
-            literals.push(buf);
-        }
+        // Newer compilers do not compress the literals, which is signalled by a size of zero
+        let uncompressed_size = reader.read_u32::<BigEndian>()?;
+        let literals = if uncompressed_size == 0 {
+            Self::decode_literals(reader)?
+        } else {
+            Self::decode_literals(zlib::Decoder::new(reader)?)?
+        };
         Ok(LitTChunk { literals })
     }
 
@@ -484,6 +486,22 @@ impl Chunk for LitTChunk {
     }
 }
 
+impl LitTChunk {
+    fn decode_literals<R: Read>(
+        mut reader: R,
+    ) -> anyhow::Result<Vec<parts::ExternalTermFormatBinary>> {
+        let count = reader.read_u32::<BigEndian>()? as usize;
+        let mut literals = Vec::with_capacity(count);
+        for _ in 0..count {
+            let literal_size = reader.read_u32::<BigEndian>()? as usize;
+            let mut buf = vec![0; literal_size];
+            reader.read_exact(&mut buf)?;
+            literals.push(buf);
+        }
+        Ok(literals)
+    }
+}
+
 /// A table of the FA pairs and their corresponding label in [CodeChunk](CodeChunk)
 /// 1. Index of function atom in [AtomChunk](AtomChunk)
 /// 2. Arity
@@ -0,0 +1,372 @@
+//! The compact term format, in which the operands of the operations in the [CodeChunk], and the
+//! lengths of atoms in newer [AtomChunk]s, are encoded.
+//!
+//! Each operand starts with a byte whose lowest 3 bits are its tag. Small values are stored in the
+//! upper bits of that byte, larger ones in the bytes which follow it. The extended tag introduces
+//! operands which are not a single value, such as lists and literals.
+//!
+//! This module decodes operations enough to walk the code, and encodes them when generating it,
+//! it does not interpret them.
+//!
+//! ## References
+//!
+//! * [BEAM Wisdom - BEAM File Format](http://beam-wisdoms.clau.se/en/latest/indepth-beam-compact.html)
+//! * `lib/compiler/src/genop.tab` in Erlang/OTP, from which the arities of operations are taken
+//!
+//! [CodeChunk]: super::CodeChunk
+//! [AtomChunk]: super::AtomChunk
+use std::io::{Read, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use num::bigint::{BigInt, Sign};
+use num::ToPrimitive;
+
+/// An operand of an operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    /// An unsigned value, such as an index or a count
+    Unsigned(BigInt),
+    /// An integer
+    Integer(BigInt),
+    /// An index in the atom table, or `[]` if zero
+    Atom(u32),
+    /// An `x` register
+    X(u32),
+    /// A `y` register
+    Y(u32),
+    /// A label, or no label if zero
+    Label(u32),
+    /// A character
+    Character(u32),
+    /// A float, only found in code compiled by very old compilers
+    Float(u64),
+    /// A list of operands, e.g. the destinations of a `select_val`
+    List(Vec<Operand>),
+    /// A floating-point register
+    FloatRegister(u32),
+    /// A list of allocations, as `(kind, count)`
+    AllocList(Vec<(u32, u32)>),
+    /// An index in the [LitTChunk](super::LitTChunk)
+    Literal(u32),
+    /// A register annotated with the index of its type in the `Type` chunk
+    TypedRegister(Box<Operand>, u32),
+}
+impl Operand {
+    /// Creates an unsigned operand from a small value
+    pub fn unsigned(value: u32) -> Self {
+        Self::Unsigned(BigInt::from(value))
+    }
+
+    /// Creates an integer operand
+    pub fn integer(value: i64) -> Self {
+        Self::Integer(BigInt::from(value))
+    }
+
+    /// Returns this operand as a small unsigned value, if it is one
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Self::Unsigned(value) => value.to_u32(),
+            _ => None,
+        }
+    }
+}
+
+/// An operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub opcode: u8,
+    pub operands: Vec<Operand>,
+}
+
+pub mod opcodes {
+    pub const LABEL: u8 = 1;
+    pub const FUNC_INFO: u8 = 2;
+    pub const INT_CODE_END: u8 = 3;
+    pub const CALL: u8 = 4;
+    pub const CALL_LAST: u8 = 5;
+    pub const CALL_ONLY: u8 = 6;
+    pub const CALL_EXT: u8 = 7;
+    pub const CALL_EXT_LAST: u8 = 8;
+    pub const ALLOCATE: u8 = 12;
+    pub const TEST_HEAP: u8 = 16;
+    pub const DEALLOCATE: u8 = 18;
+    pub const RETURN: u8 = 19;
+    pub const REMOVE_MESSAGE: u8 = 21;
+    pub const TIMEOUT: u8 = 22;
+    pub const LOOP_REC: u8 = 23;
+    pub const LOOP_REC_END: u8 = 24;
+    pub const WAIT: u8 = 25;
+    pub const WAIT_TIMEOUT: u8 = 26;
+    pub const IS_LT: u8 = 39;
+    pub const IS_GE: u8 = 40;
+    pub const IS_EQ: u8 = 41;
+    pub const IS_NE: u8 = 42;
+    pub const IS_EQ_EXACT: u8 = 43;
+    pub const IS_NE_EXACT: u8 = 44;
+    pub const IS_INTEGER: u8 = 45;
+    pub const IS_FLOAT: u8 = 46;
+    pub const IS_NUMBER: u8 = 47;
+    pub const IS_ATOM: u8 = 48;
+    pub const IS_PID: u8 = 49;
+    pub const IS_REFERENCE: u8 = 50;
+    pub const IS_PORT: u8 = 51;
+    pub const IS_NIL: u8 = 52;
+    pub const IS_BINARY: u8 = 53;
+    pub const IS_LIST: u8 = 55;
+    pub const IS_NONEMPTY_LIST: u8 = 56;
+    pub const IS_TUPLE: u8 = 57;
+    pub const TEST_ARITY: u8 = 58;
+    pub const SELECT_VAL: u8 = 59;
+    pub const JUMP: u8 = 61;
+    pub const MOVE: u8 = 64;
+    pub const GET_TUPLE_ELEMENT: u8 = 66;
+    pub const SET_TUPLE_ELEMENT: u8 = 67;
+    pub const PUT_LIST: u8 = 69;
+    pub const CALL_FUN: u8 = 75;
+    pub const IS_FUNCTION: u8 = 77;
+    pub const CALL_EXT_ONLY: u8 = 78;
+    pub const TRY: u8 = 104;
+    pub const TRY_END: u8 = 105;
+    pub const TRY_CASE: u8 = 106;
+    pub const IS_BOOLEAN: u8 = 114;
+    pub const IS_FUNCTION2: u8 = 115;
+    pub const IS_BITSTR: u8 = 129;
+    pub const LINE: u8 = 153;
+    pub const IS_MAP: u8 = 156;
+    pub const GET_MAP_ELEMENTS: u8 = 158;
+    pub const BUILD_STACKTRACE: u8 = 160;
+    pub const RAW_RAISE: u8 = 161;
+    pub const GET_HD: u8 = 162;
+    pub const GET_TL: u8 = 163;
+    pub const PUT_TUPLE2: u8 = 164;
+    pub const MAKE_FUN3: u8 = 171;
+    pub const INIT_YREGS: u8 = 172;
+}
+
+/// The number of operands of each operation, indexed by opcode
+const ARITIES: [u8; 184] = [
+    0, 1, 3, 0, 2, 3, 2, 2, 3, 2, // 0-9
+    4, 5, 2, 3, 2, 3, 2, 1, 1, 0, // 10-19
+    0, 0, 0, 2, 1, 1, 2, 4, 4, 4, // 20-29
+    4, 4, 4, 4, 4, 4, 4, 4, 3, 3, // 30-39
+    3, 3, 3, 3, 3, 2, 2, 2, 2, 2, // 40-49
+    2, 2, 2, 2, 2, 2, 2, 2, 3, 3, // 50-59
+    3, 1, 2, 1, 2, 3, 3, 3, 3, 3, // 60-69
+    2, 1, 1, 0, 1, 1, 3, 2, 2, 2, // 70-79
+    5, 5, 5, 4, 2, 1, 1, 2, 2, 5, // 80-89
+    5, 5, 2, 1, 0, 1, 2, 2, 4, 4, // 90-99
+    4, 4, 3, 1, 2, 1, 1, 1, 2, 6, // 100-109
+    3, 5, 1, 2, 2, 3, 5, 7, 7, 7, // 110-119
+    5, 3, 2, 2, 5, 6, 2, 2, 2, 2, // 120-129
+    1, 3, 4, 0, 8, 6, 2, 6, 5, 4, // 130-139
+    5, 4, 5, 4, 3, 3, 3, 3, 3, 0, // 140-149
+    1, 1, 7, 1, 5, 5, 2, 3, 3, 4, // 150-159
+    0, 0, 2, 2, 2, 3, 4, 3, 2, 2, // 160-169
+    4, 3, 1, 2, 1, 1, 1, 6, 3, 0, // 170-179
+    1, 5, 3, 2, // 180-183
+];
+
+/// Reads the operation at the start of `reader`
+pub fn read_operation<R: Read>(mut reader: R) -> anyhow::Result<Operation> {
+    let opcode = reader.read_u8()?;
+    let arity = match ARITIES.get(opcode as usize) {
+        Some(&arity) if opcode > 0 => arity,
+        _ => anyhow::bail!("unknown opcode {}", opcode),
+    };
+    let mut operands = Vec::with_capacity(arity as usize);
+    for _ in 0..arity {
+        operands.push(read_operand(&mut reader)?);
+    }
+    Ok(Operation { opcode, operands })
+}
+
+/// Reads the operand at the start of `reader`
+pub fn read_operand<R: Read>(mut reader: R) -> anyhow::Result<Operand> {
+    let first = reader.read_u8()?;
+    let small = |value: BigInt| {
+        value
+            .to_u32()
+            .ok_or_else(|| anyhow::anyhow!("operand {} is out of range", value))
+    };
+    Ok(match first & 0b111 {
+        0 => Operand::Unsigned(read_value(&mut reader, first, false)?),
+        1 => Operand::Integer(read_value(&mut reader, first, true)?),
+        2 => Operand::Atom(small(read_value(&mut reader, first, false)?)?),
+        3 => Operand::X(small(read_value(&mut reader, first, false)?)?),
+        4 => Operand::Y(small(read_value(&mut reader, first, false)?)?),
+        5 => Operand::Label(small(read_value(&mut reader, first, false)?)?),
+        6 => Operand::Character(small(read_value(&mut reader, first, false)?)?),
+        _ => match first >> 4 {
+            0 => {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes)?;
+                Operand::Float(u64::from_be_bytes(bytes))
+            }
+            1 => {
+                let len = read_unsigned(&mut reader)?;
+                let mut operands = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    operands.push(read_operand(&mut reader)?);
+                }
+                Operand::List(operands)
+            }
+            2 => Operand::FloatRegister(read_unsigned(&mut reader)?),
+            3 => {
+                let len = read_unsigned(&mut reader)?;
+                let mut allocs = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    allocs.push((read_unsigned(&mut reader)?, read_unsigned(&mut reader)?));
+                }
+                Operand::AllocList(allocs)
+            }
+            4 => Operand::Literal(read_unsigned(&mut reader)?),
+            5 => {
+                let register = read_operand(&mut reader)?;
+                Operand::TypedRegister(Box::new(register), read_unsigned(&mut reader)?)
+            }
+            tag => anyhow::bail!("unknown extended operand tag {}", tag),
+        },
+    })
+}
+
+/// Reads an operand which must be a small unsigned value, such as a length
+pub fn read_unsigned<R: Read>(reader: R) -> anyhow::Result<u32> {
+    match read_operand(reader)? {
+        Operand::Unsigned(value) => value
+            .to_u32()
+            .ok_or_else(|| anyhow::anyhow!("unsigned operand {} is out of range", value)),
+        operand => anyhow::bail!("expected an unsigned operand, got {:?}", operand),
+    }
+}
+
+fn read_value<R: Read>(mut reader: R, first: u8, signed: bool) -> anyhow::Result<BigInt> {
+    if first & 0b1000 == 0 {
+        // The value is stored in the upper 4 bits
+        return Ok(BigInt::from(first >> 4));
+    }
+    if first & 0b1_0000 == 0 {
+        // The value is stored in the upper 3 bits and the next byte
+        let high = (first as u32 & 0b1110_0000) << 3;
+        return Ok(BigInt::from(high | reader.read_u8()? as u32));
+    }
+    // The value is stored in the following bytes, of which the upper 3 bits give the count, or if
+    // they are all set, an unsigned operand which follows
+    let len = match first >> 5 {
+        7 => read_unsigned(&mut reader)? as usize + 9,
+        len => len as usize + 2,
+    };
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(if signed {
+        BigInt::from_signed_bytes_be(&bytes)
+    } else {
+        BigInt::from_bytes_be(Sign::Plus, &bytes)
+    })
+}
+
+/// Writes `operation` to `writer`
+pub fn write_operation<W: Write>(mut writer: W, operation: &Operation) -> anyhow::Result<()> {
+    match ARITIES.get(operation.opcode as usize) {
+        Some(&arity) if operation.opcode > 0 => {
+            if arity as usize != operation.operands.len() {
+                anyhow::bail!(
+                    "opcode {} expects {} operands, got {}",
+                    operation.opcode,
+                    arity,
+                    operation.operands.len()
+                );
+            }
+        }
+        _ => anyhow::bail!("unknown opcode {}", operation.opcode),
+    }
+    writer.write_u8(operation.opcode)?;
+    for operand in operation.operands.iter() {
+        write_operand(&mut writer, operand)?;
+    }
+    Ok(())
+}
+
+/// Writes `operand` to `writer`
+pub fn write_operand<W: Write>(mut writer: W, operand: &Operand) -> anyhow::Result<()> {
+    match operand {
+        Operand::Unsigned(value) => write_value(writer, 0, value),
+        Operand::Integer(value) => write_value(writer, 1, value),
+        Operand::Atom(index) => write_value(writer, 2, &BigInt::from(*index)),
+        Operand::X(register) => write_value(writer, 3, &BigInt::from(*register)),
+        Operand::Y(register) => write_value(writer, 4, &BigInt::from(*register)),
+        Operand::Label(label) => write_value(writer, 5, &BigInt::from(*label)),
+        Operand::Character(c) => write_value(writer, 6, &BigInt::from(*c)),
+        Operand::Float(bits) => {
+            writer.write_u8(0b0000_0111)?;
+            writer.write_all(&bits.to_be_bytes())?;
+            Ok(())
+        }
+        Operand::List(operands) => {
+            writer.write_u8(0b0001_0111)?;
+            write_unsigned(&mut writer, operands.len() as u32)?;
+            for operand in operands.iter() {
+                write_operand(&mut writer, operand)?;
+            }
+            Ok(())
+        }
+        Operand::FloatRegister(register) => {
+            writer.write_u8(0b0010_0111)?;
+            write_unsigned(writer, *register)
+        }
+        Operand::AllocList(allocs) => {
+            writer.write_u8(0b0011_0111)?;
+            write_unsigned(&mut writer, allocs.len() as u32)?;
+            for (kind, count) in allocs.iter() {
+                write_unsigned(&mut writer, *kind)?;
+                write_unsigned(&mut writer, *count)?;
+            }
+            Ok(())
+        }
+        Operand::Literal(index) => {
+            writer.write_u8(0b0100_0111)?;
+            write_unsigned(writer, *index)
+        }
+        Operand::TypedRegister(register, ty) => {
+            writer.write_u8(0b0101_0111)?;
+            write_operand(&mut writer, register)?;
+            write_unsigned(writer, *ty)
+        }
+    }
+}
+
+/// Writes a small unsigned value, such as a length, as an operand
+pub fn write_unsigned<W: Write>(writer: W, value: u32) -> anyhow::Result<()> {
+    write_value(writer, 0, &BigInt::from(value))
+}
+
+fn write_value<W: Write>(mut writer: W, tag: u8, value: &BigInt) -> anyhow::Result<()> {
+    match value.to_u16() {
+        Some(small) if small < 16 => {
+            // The value fits in the upper 4 bits
+            writer.write_u8((small as u8) << 4 | tag)?;
+            return Ok(());
+        }
+        Some(small) if small < 2048 => {
+            // The value fits in the upper 3 bits and the next byte
+            writer.write_u8((small >> 3) as u8 & 0b1110_0000 | 0b1000 | tag)?;
+            writer.write_u8(small as u8)?;
+            return Ok(());
+        }
+        _ => (),
+    }
+    // Otherwise the value is stored in two's complement in the fewest bytes which hold it, but
+    // no less than two, as that is the smallest count which can be represented
+    let mut bytes = value.to_signed_bytes_be();
+    if bytes.len() < 2 {
+        let sign = if value.sign() == Sign::Minus { 0xff } else { 0 };
+        bytes.insert(0, sign);
+    }
+    if bytes.len() <= 8 {
+        writer.write_u8(((bytes.len() - 2) as u8) << 5 | 0b1_1000 | tag)?;
+    } else {
+        writer.write_u8(0b1111_1000 | tag)?;
+        write_unsigned(&mut writer, (bytes.len() - 9) as u32)?;
+    }
+    writer.write_all(&bytes)?;
+    Ok(())
+}
@@ -26,6 +26,7 @@
 //!     beam.to_file("my.beam").unwrap();
 mod beam_file;
 mod chunk;
+pub mod compact;
 mod parts;
 #[cfg(test)]
 mod test;
@@ -228,6 +228,52 @@ fn encode_chunks() {
     assert_eq!(original, encoded);
 }
 
+#[test]
+fn encode_operations() {
+    use crate::beam::reader::compact::{self, opcodes, Operand, Operation};
+
+    let beam = StandardBeamFile::from_file(test_file("test.beam")).unwrap();
+    let code = beam
+        .chunks()
+        .into_iter()
+        .find_map(|c| match c {
+            StandardChunk::Code(ref c) => Some(c),
+            _ => None,
+        })
+        .unwrap();
+    let mut reader = std::io::Cursor::new(&code.bytecode);
+    loop {
+        let operation = compact::read_operation(&mut reader).unwrap();
+        let mut encoded = Vec::new();
+        compact::write_operation(&mut encoded, &operation).unwrap();
+        let decoded = compact::read_operation(std::io::Cursor::new(&encoded)).unwrap();
+        assert_eq!(operation, decoded);
+        if operation.opcode == opcodes::INT_CODE_END {
+            break;
+        }
+    }
+
+    // Values which need more than one byte, and negative integers, use the longer forms
+    let operation = Operation {
+        opcode: opcodes::MOVE,
+        operands: vec![Operand::integer(-1), Operand::Y(2047)],
+    };
+    let mut encoded = Vec::new();
+    compact::write_operation(&mut encoded, &operation).unwrap();
+    assert_eq!(
+        vec![64, 0b0001_1001, 0xff, 0xff, 0b1110_1100, 0xff],
+        encoded
+    );
+    let operation = Operation {
+        opcode: opcodes::MOVE,
+        operands: vec![Operand::integer(i64::MAX), Operand::X(0)],
+    };
+    let mut encoded = Vec::new();
+    compact::write_operation(&mut encoded, &operation).unwrap();
+    let decoded = compact::read_operation(std::io::Cursor::new(&encoded)).unwrap();
+    assert_eq!(operation, decoded);
+}
+
 fn test_file(name: &str) -> PathBuf {
     let mut path = PathBuf::from("tests/testdata/reader");
     path.push(name);
@@ -255,6 +255,16 @@ impl std::fmt::Display for BigInteger {
         write!(f, "{}", self.value)
     }
 }
+impl BigInteger {
+    /// Creates an integer from its big-endian two's complement representation
+    ///
+    /// This allows integers from other versions of `num` to be converted without loss.
+    pub fn from_signed_bytes_be(bytes: &[u8]) -> Self {
+        BigInteger {
+            value: BigInt::from_signed_bytes_be(bytes),
+        }
+    }
+}
 impl From<i8> for BigInteger {
     fn from(value: i8) -> Self {
         BigInteger {
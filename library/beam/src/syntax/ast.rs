@@ -15,6 +15,7 @@
 pub mod ast;
 pub mod error;
 pub mod format;
+pub mod pp;
 
 #[cfg(test)]
 mod test;
@@ -40,4 +41,14 @@ impl AST {
             module: ast::ModuleDecl { forms },
         })
     }
+
+    /// Builds AST from a BEAM file which is already in memory
+    pub fn from_beam_bytes(bytes: &[u8]) -> FromBeamResult<Self> {
+        use self::format::raw_abstract_v1::AbstractCode;
+        let code = AbstractCode::from_beam_bytes(bytes)?;
+        let forms = code.to_forms()?;
+        Ok(AST {
+            module: ast::ModuleDecl { forms },
+        })
+    }
 }
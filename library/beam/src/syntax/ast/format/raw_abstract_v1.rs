@@ -42,12 +42,46 @@ pub struct AbstractCode {
 impl AbstractCode {
     pub fn from_beam_file<P: AsRef<Path>>(path: P) -> FromBeamResult<Self> {
         let beam = crate::beam::reader::RawBeamFile::from_file(path)?;
-        let chunk = beam
-            .chunks()
-            .into_iter()
-            .find(|c| c.id() == b"Abst")
+        Self::from_raw_beam(&beam)
+    }
+
+    /// Reads the abstract code of a BEAM file which is already in memory, e.g. the body of an
+    /// escript
+    pub fn from_beam_bytes(bytes: &[u8]) -> FromBeamResult<Self> {
+        let beam = crate::beam::reader::RawBeamFile::from_reader(bytes)?;
+        Self::from_raw_beam(&beam)
+    }
+
+    /// Modules compiled since OTP 20 keep their abstract code in the `Dbgi` chunk, as
+    /// `{debug_info_v1, erl_abstract_code, {Forms, Options}}`, where `Forms` is `none` without
+    /// `debug_info`, rather than in the `Abst` chunk
+    fn from_raw_beam(beam: &crate::beam::reader::RawBeamFile) -> FromBeamResult<Self> {
+        let chunks = beam.chunks();
+        if let Some(chunk) = chunks.iter().find(|c| c.id() == b"Abst") {
+            let code = etf::Term::decode(std::io::Cursor::new(&chunk.data))?;
+            return Ok(AbstractCode { code });
+        }
+        let chunk = chunks
+            .iter()
+            .find(|c| c.id() == b"Dbgi")
             .ok_or(FromBeamError::NoDebugInfo)?;
-        let code = etf::Term::decode(std::io::Cursor::new(&chunk.data))?;
+        let info = etf::Term::decode(std::io::Cursor::new(&chunk.data))?;
+        let forms = match &info {
+            etf::Term::Tuple(info) => match info.elements.as_slice() {
+                [etf::Term::Atom(version), etf::Term::Atom(backend), etf::Term::Tuple(code)]
+                    if version.name == "debug_info_v1" && backend.name == "erl_abstract_code" =>
+                {
+                    match code.elements.as_slice() {
+                        [forms @ etf::Term::List(_), _] => forms.clone(),
+                        _ => return Err(FromBeamError::NoDebugInfo),
+                    }
+                }
+                _ => return Err(FromBeamError::NoDebugInfo),
+            },
+            _ => return Err(FromBeamError::NoDebugInfo),
+        };
+        let tag = etf::Atom::from("raw_abstract_v1").into();
+        let code = etf::Tuple::from(vec![tag, forms]).into();
         Ok(AbstractCode { code })
     }
     pub fn to_forms(&self) -> FromBeamResult<Vec<form::Form>> {
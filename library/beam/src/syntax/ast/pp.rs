@@ -0,0 +1,1289 @@
+//! Pretty printing of abstract forms as Erlang source, as `erl_pp` does.
+//!
+//! This is used to show the forms of a module loaded from its debug info, or produced by a parse
+//! transform, as the source they stand for, e.g. when reporting an error in generated code. The
+//! output reads back as the same forms: operators are parenthesized by their precedence in
+//! `erl_parse`, atoms are quoted where they have to be, and strings, characters and floats are
+//! written so that they scan back to the same value.
+//!
+//! The layout follows `erl_pp`: the body of each clause is on lines of its own, indented by four
+//! spaces, as are the clauses of `case`, `if`, `receive` and `try`, while a `fun` with a single
+//! clause and a body that fits on one line is written on one line. Comments are not part of the
+//! abstract format, so they are lost.
+use std::fmt::Write;
+
+use crate::serialization::etf;
+
+use super::ast::clause::Clause;
+use super::ast::common::{self, BinElement};
+use super::ast::expr::{self, Expression, Qualifier};
+use super::ast::form::{self, Form};
+use super::ast::guard::{Guard, OrGuard};
+use super::ast::literal;
+use super::ast::pat::Pattern;
+use super::ast::ty::{self, Type};
+use super::ast::ModuleDecl;
+
+/// How far the body of a clause is indented from its head
+const INDENT: usize = 4;
+/// The width of a line, beyond which a record declaration is broken over lines
+const WIDTH: usize = 72;
+/// The precedence of primary expressions, which are never parenthesized
+const MAX: u32 = 1000;
+
+/// Returns the source of a module, with its functions set apart by blank lines
+pub fn module(module: &ModuleDecl) -> String {
+    let mut out = String::new();
+    let mut prev: Option<&Form> = None;
+    for f in module.forms.iter() {
+        if let Form::Eof(_) = f {
+            continue;
+        }
+        let is_fun = matches!(f, Form::Fun(_) | Form::Spec(_));
+        match prev {
+            Some(Form::Fun(_)) => out.push('\n'),
+            Some(Form::Spec(_)) | None => (),
+            Some(_) if is_fun => out.push('\n'),
+            Some(_) => (),
+        }
+        out.push_str(&form(f));
+        out.push('\n');
+        prev = Some(f);
+    }
+    out
+}
+
+/// Returns the source of a form, e.g. an attribute or a function, ending in a full stop
+pub fn form(form: &Form) -> String {
+    let mut p = Printer::new(0);
+    p.form(form);
+    p.out
+}
+
+/// Returns the source of an expression
+pub fn expr(expr: &Expression) -> String {
+    let mut p = Printer::new(0);
+    expr.print(&mut p, 0);
+    p.out
+}
+
+/// Returns the source of a sequence of expressions, one per line, separated by commas
+pub fn exprs(exprs: &[Expression]) -> String {
+    let mut p = Printer::new(0);
+    p.sequence(exprs);
+    p.out
+}
+
+/// Returns the source of a pattern
+pub fn pattern(pattern: &Pattern) -> String {
+    let mut p = Printer::new(0);
+    pattern.print(&mut p, 0);
+    p.out
+}
+
+/// Returns the source of a guard, i.e. of the guard sequence following `when`
+pub fn guard(guards: &[OrGuard]) -> String {
+    let mut p = Printer::new(0);
+    p.guards(guards);
+    p.out
+}
+
+/// Returns the source of a type
+pub fn ty(ty: &Type) -> String {
+    let mut p = Printer::new(0);
+    p.ty(ty, 0);
+    p.out
+}
+
+/// Writes an expression, pattern or guard, which share most of their syntax
+trait Print: Sized {
+    /// Writes the node, in parentheses if it binds less tightly than `prec`
+    fn print(&self, p: &mut Printer, prec: u32);
+
+    fn as_cons(&self) -> Option<&common::Cons<Self>>;
+
+    fn as_unary_op(&self) -> Option<&common::UnaryOp<Self>>;
+}
+
+impl Print for Expression {
+    fn print(&self, p: &mut Printer, prec: u32) {
+        match self {
+            Self::Integer(x) => p.integer(x),
+            Self::Float(x) => p.float(x.value),
+            Self::String(x) => p.string(&x.value),
+            Self::Char(x) => p.char(x.value),
+            Self::Atom(x) => p.atom(&x.value),
+            Self::Var(x) => p.str(&x.name),
+            Self::Match(x) => p.matches(x, prec),
+            Self::Tuple(x) => p.tuple(&x.elements),
+            Self::Nil(_) => p.str("[]"),
+            Self::Cons(x) => p.cons(x),
+            Self::Binary(x) => p.binary(x),
+            Self::UnaryOp(x) => p.unary_op(x, prec),
+            Self::BinaryOp(x) => p.binary_op(x, prec),
+            Self::Record(x) => p.record(x, prec),
+            Self::RecordIndex(x) => p.record_index(x, prec),
+            Self::Map(x) => p.map(x, prec),
+            Self::Catch(x) => p.paren(prec > 0, |p| {
+                p.str("catch ");
+                x.expr.print(p, 100);
+            }),
+            Self::LocalCall(x) => p.local_call(x, prec),
+            Self::RemoteCall(x) => p.remote_call(x, prec),
+            Self::Comprehension(x) => p.comprehension(x),
+            Self::Block(x) => p.block(x),
+            Self::If(x) => p.if_expr(x),
+            Self::Case(x) => p.case(x),
+            Self::Try(x) => p.try_expr(x),
+            Self::Receive(x) => p.receive(x),
+            Self::InternalFun(x) => {
+                p.str("fun ");
+                p.atom(&x.function);
+                let _ = write!(p.out, "/{}", x.arity);
+            }
+            Self::ExternalFun(x) => {
+                p.str("fun ");
+                x.module.print(p, MAX);
+                p.str(":");
+                x.function.print(p, MAX);
+                p.str("/");
+                x.arity.print(p, MAX);
+            }
+            Self::AnonymousFun(x) => p.fun(x),
+        }
+    }
+
+    fn as_cons(&self) -> Option<&common::Cons<Self>> {
+        match self {
+            Self::Cons(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn as_unary_op(&self) -> Option<&common::UnaryOp<Self>> {
+        match self {
+            Self::UnaryOp(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl Print for Pattern {
+    fn print(&self, p: &mut Printer, prec: u32) {
+        match self {
+            Self::Integer(x) => p.integer(x),
+            Self::Float(x) => p.float(x.value),
+            Self::String(x) => p.string(&x.value),
+            Self::Char(x) => p.char(x.value),
+            Self::Atom(x) => p.atom(&x.value),
+            Self::Var(x) => p.str(&x.name),
+            Self::Match(x) => p.matches(x, prec),
+            Self::Tuple(x) => p.tuple(&x.elements),
+            Self::Nil(_) => p.str("[]"),
+            Self::Cons(x) => p.cons(x),
+            Self::Binary(x) => p.binary(x),
+            Self::UnaryOp(x) => p.unary_op(x, prec),
+            Self::BinaryOp(x) => p.binary_op(x, prec),
+            Self::Record(x) => p.record(x, prec),
+            Self::RecordIndex(x) => p.record_index(x, prec),
+            Self::Map(x) => p.map(x, prec),
+        }
+    }
+
+    fn as_cons(&self) -> Option<&common::Cons<Self>> {
+        match self {
+            Self::Cons(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn as_unary_op(&self) -> Option<&common::UnaryOp<Self>> {
+        match self {
+            Self::UnaryOp(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl Print for Guard {
+    fn print(&self, p: &mut Printer, prec: u32) {
+        match self {
+            Self::Integer(x) => p.integer(x),
+            Self::Float(x) => p.float(x.value),
+            Self::String(x) => p.string(&x.value),
+            Self::Char(x) => p.char(x.value),
+            Self::Atom(x) => p.atom(&x.value),
+            Self::Var(x) => p.str(&x.name),
+            Self::Tuple(x) => p.tuple(&x.elements),
+            Self::Nil(_) => p.str("[]"),
+            Self::Cons(x) => p.cons(x),
+            Self::Binary(x) => p.binary(x),
+            Self::UnaryOp(x) => p.unary_op(x, prec),
+            Self::BinaryOp(x) => p.binary_op(x, prec),
+            Self::Record(x) => p.record(x, prec),
+            Self::RecordIndex(x) => p.record_index(x, prec),
+            Self::LocalCall(x) => p.local_call(x, prec),
+            Self::RemoteCall(x) => p.remote_call(x, prec),
+        }
+    }
+
+    fn as_cons(&self) -> Option<&common::Cons<Self>> {
+        match self {
+            Self::Cons(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn as_unary_op(&self) -> Option<&common::UnaryOp<Self>> {
+        match self {
+            Self::UnaryOp(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the precedence of a binary operator, and those its left and right operands are
+/// written at, as in `erl_parse`
+fn binary_prec(op: &str) -> (u32, u32, u32) {
+    match op {
+        "=" | "!" => (150, 100, 100),
+        "orelse" => (160, 150, 150),
+        "andalso" => (200, 160, 160),
+        "==" | "/=" | "=<" | "<" | ">=" | ">" | "=:=" | "=/=" => (300, 200, 300),
+        "++" | "--" => (400, 300, 300),
+        "+" | "-" | "bor" | "bxor" | "bsl" | "bsr" | "or" | "xor" => (400, 400, 500),
+        "*" | "/" | "div" | "rem" | "band" | "and" => (500, 500, 600),
+        // Not an operator of Erlang, so written in parentheses wherever it appears
+        _ => (MAX, 0, MAX),
+    }
+}
+
+/// Returns whether `name` can be written without quotes, i.e. is not a reserved word and starts
+/// with a lowercase letter followed by letters, digits, `_` and `@`, in Latin-1
+fn is_bare_atom(name: &str) -> bool {
+    const RESERVED: &[&str] = &[
+        "after", "and", "andalso", "band", "begin", "bnot", "bor", "bsl", "bsr", "bxor", "case",
+        "catch", "cond", "div", "else", "end", "fun", "if", "let", "maybe", "not", "of", "or",
+        "orelse", "receive", "rem", "try", "when", "xor",
+    ];
+    let is_lower = |c: char| matches!(c, 'a'..='z' | 'ß'..='ÿ') && c != '÷';
+    let is_upper = |c: char| matches!(c, 'A'..='Z' | 'À'..='Þ') && c != '×';
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if is_lower(c) => (),
+        _ => return false,
+    }
+    chars.all(|c| is_lower(c) || is_upper(c) || c.is_ascii_digit() || c == '_' || c == '@')
+        && !RESERVED.contains(&name)
+}
+
+/// Writes `c` as it appears in a string or atom quoted by `quote`
+fn escape(out: &mut String, c: char, quote: char) {
+    match c {
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\x0b' => out.push_str("\\v"),
+        '\x08' => out.push_str("\\b"),
+        '\x0c' => out.push_str("\\f"),
+        '\x1b' => out.push_str("\\e"),
+        '\x7f' => out.push_str("\\d"),
+        '\\' => out.push_str("\\\\"),
+        c if c == quote => {
+            out.push('\\');
+            out.push(c);
+        }
+        // Other control characters are written in octal, always with three digits so that a
+        // digit following them is not taken as part of the escape
+        c if c < ' ' || ('\u{80}'..'\u{a0}').contains(&c) => {
+            let _ = write!(out, "\\{:03o}", c as u32);
+        }
+        c => out.push(c),
+    }
+}
+
+/// Returns the shortest representation of `value` which reads back as it, with the fraction and
+/// exponent Erlang requires, e.g. `1.0e16` rather than `1e16`
+fn float(value: f64) -> String {
+    let text = format!("{:?}", value);
+    match text.find('e') {
+        Some(i) if !text[..i].contains('.') => format!("{}.0{}", &text[..i], &text[i..]),
+        _ => text,
+    }
+}
+
+/// Writes source into a string, keeping track of the indentation of new lines
+struct Printer {
+    out: String,
+    indent: usize,
+}
+impl Printer {
+    fn new(indent: usize) -> Self {
+        Self {
+            out: String::new(),
+            indent,
+        }
+    }
+
+    /// Returns what `f` writes, as if written at the current position
+    fn render(&self, f: impl FnOnce(&mut Self)) -> String {
+        let mut p = Self::new(self.indent);
+        f(&mut p);
+        p.out
+    }
+
+    /// Returns the column the next character is written at
+    fn column(&self) -> usize {
+        let line = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[line..].chars().count()
+    }
+
+    fn str(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(self.indent));
+    }
+
+    /// Runs `f` on a new line, indented a level further
+    fn nested(&mut self, f: impl FnOnce(&mut Self)) {
+        self.indent += INDENT;
+        self.newline();
+        f(self);
+        self.indent -= INDENT;
+    }
+
+    fn paren(&mut self, paren: bool, f: impl FnOnce(&mut Self)) {
+        if paren {
+            self.str("(");
+        }
+        f(self);
+        if paren {
+            self.str(")");
+        }
+    }
+
+    fn separated<T>(&mut self, items: &[T], sep: &str, mut f: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.str(sep);
+            }
+            f(self, item);
+        }
+    }
+
+    fn args<T: Print>(&mut self, args: &[T]) {
+        self.str("(");
+        self.separated(args, ", ", |p, arg| arg.print(p, 0));
+        self.str(")");
+    }
+
+    fn atom(&mut self, name: &str) {
+        if is_bare_atom(name) {
+            self.str(name);
+            return;
+        }
+        self.out.push('\'');
+        for c in name.chars() {
+            escape(&mut self.out, c, '\'');
+        }
+        self.out.push('\'');
+    }
+
+    fn integer(&mut self, integer: &literal::Integer) {
+        let _ = write!(self.out, "{}", integer.value);
+    }
+
+    fn float(&mut self, value: f64) {
+        self.str(&float(value));
+    }
+
+    fn string(&mut self, value: &str) {
+        self.out.push('"');
+        for c in value.chars() {
+            escape(&mut self.out, c, '"');
+        }
+        self.out.push('"');
+    }
+
+    fn char(&mut self, value: char) {
+        self.out.push('$');
+        match value {
+            ' ' => self.str("\\s"),
+            c => escape(&mut self.out, c, '\0'),
+        }
+    }
+
+    fn matches<L: Print, R: Print>(&mut self, m: &common::Match<L, R>, prec: u32) {
+        let (this, left, right) = binary_prec("=");
+        self.paren(this < prec, |p| {
+            m.left.print(p, left);
+            p.str(" = ");
+            m.right.print(p, right);
+        });
+    }
+
+    fn tuple<T: Print>(&mut self, elements: &[T]) {
+        self.str("{");
+        self.separated(elements, ", ", |p, e| e.print(p, 0));
+        self.str("}");
+    }
+
+    fn cons<T: Print>(&mut self, mut cons: &common::Cons<T>) {
+        self.str("[");
+        loop {
+            cons.head.print(self, 0);
+            match cons.tail.as_cons() {
+                Some(tail) => {
+                    self.str(", ");
+                    cons = tail;
+                }
+                None => break,
+            }
+        }
+        let tail = self.render(|p| cons.tail.print(p, 0));
+        if tail != "[]" {
+            self.str(" | ");
+            self.str(&tail);
+        }
+        self.str("]");
+    }
+
+    fn binary<T: Print>(&mut self, binary: &common::Binary<T>) {
+        self.str("<<");
+        self.separated(&binary.elements, ", ", |p, e| p.bin_element(e));
+        self.str(">>");
+    }
+
+    fn bin_element<T: Print>(&mut self, element: &BinElement<T>) {
+        // The value may be preceded by a unary operator, but is otherwise a primary expression, as
+        // is the size
+        match element.element.as_unary_op() {
+            Some(op) => self.prefix_op(op, MAX),
+            None => element.element.print(self, MAX),
+        }
+        if let Some(size) = &element.size {
+            self.str(":");
+            size.print(self, MAX);
+        }
+        if let Some(tsl) = &element.tsl {
+            self.str("/");
+            self.separated(tsl, "-", |p, spec| {
+                p.str(&spec.name);
+                if let Some(value) = spec.value {
+                    let _ = write!(p.out, ":{}", value);
+                }
+            });
+        }
+    }
+
+    fn unary_op<T: Print>(&mut self, op: &common::UnaryOp<T>, prec: u32) {
+        self.paren(600 < prec, |p| p.prefix_op(op, 700));
+    }
+
+    fn prefix_op<T: Print>(&mut self, op: &common::UnaryOp<T>, operand_prec: u32) {
+        let operand = self.render(|p| op.operand.print(p, operand_prec));
+        self.str(&op.operator);
+        // Words are set apart from their operand, as are signs from another sign, e.g. `- -1`,
+        // which would otherwise be read as `--`
+        let is_word = op.operator.chars().all(|c| c.is_ascii_alphabetic());
+        if is_word || operand.starts_with(['-', '+']) {
+            self.str(" ");
+        }
+        self.str(&operand);
+    }
+
+    fn binary_op<T: Print>(&mut self, op: &common::BinaryOp<T>, prec: u32) {
+        let (this, left, right) = binary_prec(&op.operator);
+        self.paren(this < prec, |p| {
+            op.left_operand.print(p, left);
+            p.str(" ");
+            p.str(&op.operator);
+            p.str(" ");
+            op.right_operand.print(p, right);
+        });
+    }
+
+    fn record<T: Print>(&mut self, record: &common::Record<T>, prec: u32) {
+        self.paren(record.base.is_some() && 700 < prec, |p| {
+            if let Some(base) = &record.base {
+                base.print(p, 800);
+            }
+            p.str("#");
+            p.atom(&record.name);
+            p.str("{");
+            p.separated(&record.fields, ", ", |p, field| {
+                match &field.name {
+                    Some(name) => p.atom(name),
+                    None => p.str("_"),
+                }
+                p.str(" = ");
+                field.value.print(p, 0);
+            });
+            p.str("}");
+        });
+    }
+
+    fn record_index<T: Print>(&mut self, index: &common::RecordIndex<T>, prec: u32) {
+        self.paren(index.base.is_some() && 700 < prec, |p| {
+            if let Some(base) = &index.base {
+                base.print(p, 800);
+            }
+            p.str("#");
+            p.atom(&index.record);
+            p.str(".");
+            p.atom(&index.field);
+        });
+    }
+
+    fn map<T: Print>(&mut self, map: &common::Map<T>, prec: u32) {
+        self.paren(map.base.is_some() && 700 < prec, |p| {
+            if let Some(base) = &map.base {
+                base.print(p, 800);
+            }
+            p.str("#{");
+            p.separated(&map.pairs, ", ", |p, pair| {
+                pair.key.print(p, 0);
+                p.str(if pair.is_assoc { " => " } else { " := " });
+                pair.value.print(p, 0);
+            });
+            p.str("}");
+        });
+    }
+
+    fn local_call<T: Print>(&mut self, call: &common::LocalCall<T>, prec: u32) {
+        self.paren(700 < prec, |p| {
+            call.function.print(p, 800);
+            p.args(&call.args);
+        });
+    }
+
+    fn remote_call<T: Print>(&mut self, call: &common::RemoteCall<T>, prec: u32) {
+        self.paren(700 < prec, |p| {
+            call.module.print(p, 900);
+            p.str(":");
+            call.function.print(p, 900);
+            p.args(&call.args);
+        });
+    }
+
+    fn comprehension(&mut self, comprehension: &expr::Comprehension) {
+        let (open, close) = match comprehension.is_list {
+            true => ("[", "]"),
+            false => ("<< ", " >>"),
+        };
+        self.str(open);
+        comprehension.expr.print(self, 0);
+        self.str(" || ");
+        self.separated(
+            &comprehension.qualifiers,
+            ", ",
+            |p, qualifier| match qualifier {
+                Qualifier::Generator(g) => {
+                    g.pattern.print(p, 0);
+                    p.str(" <- ");
+                    g.expr.print(p, 0);
+                }
+                Qualifier::BitStringGenerator(g) => {
+                    g.pattern.print(p, 0);
+                    p.str(" <= ");
+                    g.expr.print(p, 0);
+                }
+                Qualifier::Filter(filter) => filter.print(p, 0),
+            },
+        );
+        self.str(close);
+    }
+
+    /// Writes expressions separated by commas, each on a line of its own
+    fn sequence(&mut self, exprs: &[Expression]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.str(",");
+                self.newline();
+            }
+            expr.print(self, 0);
+        }
+    }
+
+    /// Writes a sequence of guards, separated by `;`, of tests separated by `,`
+    fn guards(&mut self, guards: &[OrGuard]) {
+        self.separated(guards, "; ", |p, guard| {
+            p.separated(&guard.and_guards, ", ", |p, test| test.print(p, 0));
+        });
+    }
+
+    /// Writes ` when` followed by the guards, if there are any
+    fn when(&mut self, guards: &[OrGuard]) {
+        if !guards.is_empty() {
+            self.str(" when ");
+            self.guards(guards);
+        }
+    }
+
+    /// Writes clauses separated by `;`, each starting on a new line with what `head` writes,
+    /// followed by its body on the lines after it
+    fn clauses(&mut self, clauses: &[Clause], mut head: impl FnMut(&mut Self, &Clause)) {
+        for (i, clause) in clauses.iter().enumerate() {
+            if i > 0 {
+                self.str(";");
+                self.newline();
+            }
+            head(self, clause);
+            self.str(" ->");
+            self.nested(|p| p.sequence(&clause.body));
+        }
+    }
+
+    /// Writes the head of a clause of `case`, `receive` or the `of` section of `try`
+    fn pattern_head(&mut self, clause: &Clause) {
+        self.separated(&clause.patterns, ", ", |p, pattern| pattern.print(p, 0));
+        self.when(&clause.guards);
+    }
+
+    /// Writes the head of a clause of the `catch` section of `try`, written `Class:Reason:Stack`
+    /// with the class left out if it is `throw` and the stacktrace if it is `_`
+    fn catch_head(&mut self, clause: &Clause) {
+        match clause.patterns.as_slice() {
+            [Pattern::Tuple(tuple)] if tuple.elements.len() == 3 => {
+                let [class, reason, stack] = [0, 1, 2].map(|i| &tuple.elements[i]);
+                let is_throw = matches!(class, Pattern::Atom(a) if a.value == "throw");
+                let is_anonymous = matches!(stack, Pattern::Var(v) if v.is_anonymous());
+                if !(is_throw && is_anonymous) {
+                    class.print(self, MAX);
+                    self.str(":");
+                }
+                reason.print(self, MAX);
+                if !is_anonymous {
+                    self.str(":");
+                    stack.print(self, MAX);
+                }
+            }
+            _ => self.separated(&clause.patterns, ", ", |p, pattern| pattern.print(p, 0)),
+        }
+        self.when(&clause.guards);
+    }
+
+    /// Writes `keyword`, followed by the clauses indented on the lines after it
+    fn section(&mut self, keyword: &str, clauses: &[Clause], head: impl FnMut(&mut Self, &Clause)) {
+        self.str(keyword);
+        self.nested(|p| p.clauses(clauses, head));
+    }
+
+    fn block(&mut self, block: &expr::Block) {
+        self.str("begin");
+        self.nested(|p| p.sequence(&block.body));
+        self.newline();
+        self.str("end");
+    }
+
+    fn if_expr(&mut self, expr: &expr::If) {
+        self.section("if", &expr.clauses, |p, clause| p.guards(&clause.guards));
+        self.newline();
+        self.str("end");
+    }
+
+    fn case(&mut self, expr: &expr::Case) {
+        self.str("case ");
+        expr.expr.print(self, 0);
+        self.section(" of", &expr.clauses, Self::pattern_head);
+        self.newline();
+        self.str("end");
+    }
+
+    fn receive(&mut self, expr: &expr::Receive) {
+        match expr.clauses.is_empty() {
+            true => self.str("receive"),
+            false => self.section("receive", &expr.clauses, Self::pattern_head),
+        }
+        if let Some(timeout) = &expr.timeout {
+            self.newline();
+            self.str("after");
+            self.nested(|p| {
+                timeout.print(p, 0);
+                p.str(" ->");
+                p.nested(|p| p.sequence(&expr.after));
+            });
+        }
+        self.newline();
+        self.str("end");
+    }
+
+    fn try_expr(&mut self, expr: &expr::Try) {
+        self.str("try");
+        self.nested(|p| p.sequence(&expr.body));
+        if !expr.case_clauses.is_empty() {
+            self.newline();
+            self.section("of", &expr.case_clauses, Self::pattern_head);
+        }
+        if !expr.catch_clauses.is_empty() {
+            self.newline();
+            self.section("catch", &expr.catch_clauses, Self::catch_head);
+        }
+        if !expr.after.is_empty() {
+            self.newline();
+            self.str("after");
+            self.nested(|p| p.sequence(&expr.after));
+        }
+        self.newline();
+        self.str("end");
+    }
+
+    fn fun(&mut self, fun: &expr::AnonymousFun) {
+        let head = |p: &mut Self, clause: &Clause| {
+            if let Some(name) = &fun.name {
+                p.str(" ");
+                p.str(name);
+            }
+            p.args(&clause.patterns);
+            p.when(&clause.guards);
+        };
+        // A fun with a single clause is written on one line if its body fits on one
+        if let [clause] = fun.clauses.as_slice() {
+            if let [body] = clause.body.as_slice() {
+                let body = self.render(|p| body.print(p, 0));
+                if !body.contains('\n') {
+                    self.str("fun");
+                    head(self, clause);
+                    self.str(" -> ");
+                    self.str(&body);
+                    self.str(" end");
+                    return;
+                }
+            }
+            self.str("fun");
+            self.clauses(&fun.clauses, head);
+        } else {
+            self.str("fun");
+            self.nested(|p| p.clauses(&fun.clauses, head));
+        }
+        self.newline();
+        self.str("end");
+    }
+
+    fn ty(&mut self, ty: &Type, prec: u32) {
+        match ty {
+            Type::Atom(x) => self.atom(&x.value),
+            Type::Integer(x) => self.integer(x),
+            Type::Var(x) => self.str(&x.name),
+            Type::Annotated(x) => self.paren(100 < prec, |p| {
+                p.str(&x.name.name);
+                p.str(" :: ");
+                p.ty(&x.ty, 100);
+            }),
+            Type::UnaryOp(x) => self.paren(600 < prec, |p| {
+                p.str(&x.operator);
+                if x.operator.chars().all(|c| c.is_ascii_alphabetic()) {
+                    p.str(" ");
+                }
+                p.ty(&x.operand, 700);
+            }),
+            Type::BinaryOp(x) => {
+                let (this, left, right) = binary_prec(&x.operator);
+                self.paren(this < prec, |p| {
+                    p.ty(&x.left_operand, left);
+                    p.str(" ");
+                    p.str(&x.operator);
+                    p.str(" ");
+                    p.ty(&x.right_operand, right);
+                });
+            }
+            Type::BitString(x) => match (x.bytes, x.tail_bits) {
+                (0, 0) => self.str("<<>>"),
+                (size, 0) => {
+                    let _ = write!(self.out, "<<_:{}>>", size);
+                }
+                (0, unit) => {
+                    let _ = write!(self.out, "<<_:_*{}>>", unit);
+                }
+                (size, unit) => {
+                    let _ = write!(self.out, "<<_:{}, _:_*{}>>", size, unit);
+                }
+            },
+            Type::Nil(_) => self.str("[]"),
+            Type::AnyFun(x) => match &x.return_type {
+                Some(ret) => {
+                    self.str("fun((...) -> ");
+                    self.ty(ret, 0);
+                    self.str(")");
+                }
+                None => self.str("fun()"),
+            },
+            Type::Function(x) => {
+                self.str("fun(");
+                self.fun_type(x);
+                self.str(")");
+            }
+            Type::Range(x) => self.paren(200 < prec, |p| {
+                p.ty(&x.low, 300);
+                p.str("..");
+                p.ty(&x.high, 300);
+            }),
+            Type::Map(x) => {
+                self.str("#{");
+                self.separated(&x.pairs, ", ", |p, pair| {
+                    p.ty(&pair.key, 0);
+                    p.str(" => ");
+                    p.ty(&pair.value, 0);
+                });
+                self.str("}");
+            }
+            Type::BuiltIn(x) => {
+                self.atom(&x.name);
+                self.type_args(&x.args);
+            }
+            Type::Record(x) => {
+                self.str("#");
+                self.atom(&x.name);
+                self.str("{");
+                self.separated(&x.fields, ", ", |p, field| {
+                    p.atom(&field.name);
+                    p.str(" :: ");
+                    p.ty(&field.ty, 0);
+                });
+                self.str("}");
+            }
+            Type::Remote(x) => {
+                self.atom(&x.module);
+                self.str(":");
+                self.atom(&x.function);
+                self.type_args(&x.args);
+            }
+            Type::AnyTuple(_) => self.str("tuple()"),
+            Type::Tuple(x) => {
+                self.str("{");
+                self.separated(&x.elements, ", ", |p, ty| p.ty(ty, 0));
+                self.str("}");
+            }
+            // The members of a union are types, so annotated types and unions are parenthesized
+            Type::Union(x) => self.paren(150 < prec, |p| {
+                p.separated(&x.types, " | ", |p, ty| p.ty(ty, 160));
+            }),
+            Type::User(x) => {
+                self.atom(&x.name);
+                self.type_args(&x.args);
+            }
+        }
+    }
+
+    fn type_args(&mut self, args: &[Type]) {
+        self.str("(");
+        self.separated(args, ", ", |p, ty| p.ty(ty, 0));
+        self.str(")");
+    }
+
+    /// Writes a function type as it appears in a spec, i.e. `(Args) -> Return when Constraints`
+    fn fun_type(&mut self, fun: &ty::Fun) {
+        self.type_args(&fun.args);
+        self.str(" -> ");
+        self.ty(&fun.return_type, 0);
+        if !fun.constraints.is_empty() {
+            self.str(" when ");
+            self.separated(&fun.constraints, ", ", |p, constraint| {
+                p.str(&constraint.var.name);
+                p.str(" :: ");
+                p.ty(&constraint.subtype, 0);
+            });
+        }
+    }
+
+    /// Writes a term given as the value of an attribute, as a literal
+    fn term(&mut self, term: &etf::Term) {
+        match term {
+            etf::Term::Atom(x) => self.atom(&x.name),
+            etf::Term::FixInteger(x) => {
+                let _ = write!(self.out, "{}", x.value);
+            }
+            etf::Term::BigInteger(x) => {
+                let _ = write!(self.out, "{}", x.value);
+            }
+            etf::Term::Float(x) => self.float(x.value),
+            etf::Term::List(x) => match printable(&x.elements) {
+                Some(string) => self.string(&string),
+                None => {
+                    self.str("[");
+                    self.separated(&x.elements, ", ", Self::term);
+                    self.str("]");
+                }
+            },
+            etf::Term::ImproperList(x) => {
+                self.str("[");
+                self.separated(&x.elements, ", ", Self::term);
+                self.str(" | ");
+                self.term(&x.last);
+                self.str("]");
+            }
+            etf::Term::Tuple(x) => {
+                self.str("{");
+                self.separated(&x.elements, ", ", Self::term);
+                self.str("}");
+            }
+            etf::Term::Map(x) => {
+                self.str("#{");
+                self.separated(&x.entries, ", ", |p, (key, value)| {
+                    p.term(key);
+                    p.str(" => ");
+                    p.term(value);
+                });
+                self.str("}");
+            }
+            etf::Term::Binary(x) => match std::str::from_utf8(&x.bytes) {
+                Ok(string) if string.chars().all(is_printable) => {
+                    self.str("<<");
+                    self.string(string);
+                    self.str("/utf8>>");
+                }
+                _ => {
+                    self.str("<<");
+                    self.separated(&x.bytes, ", ", |p, byte| {
+                        let _ = write!(p.out, "{}", byte);
+                    });
+                    self.str(">>");
+                }
+            },
+            // Pids, ports, references, funs and bitstrings have no literal syntax
+            term => {
+                let _ = write!(self.out, "{}", term);
+            }
+        }
+    }
+
+    fn form(&mut self, form: &Form) {
+        match form {
+            Form::Module(x) => self.attribute("module", |p| p.atom(&x.name)),
+            Form::Behaviour(x) => {
+                let name = if x.is_british {
+                    "behaviour"
+                } else {
+                    "behavior"
+                };
+                self.attribute(name, |p| p.atom(&x.name));
+            }
+            Form::Export(x) => self.attribute("export", |p| {
+                p.str("[");
+                p.separated(&x.funs, ", ", |p, f| p.name_arity(&f.fun, f.arity));
+                p.str("]");
+            }),
+            Form::Import(x) => self.attribute("import", |p| {
+                p.atom(&x.module);
+                p.str(", [");
+                p.separated(&x.funs, ", ", |p, f| p.name_arity(&f.fun, f.arity));
+                p.str("]");
+            }),
+            Form::ExportType(x) => self.attribute("export_type", |p| {
+                p.str("[");
+                p.separated(&x.types, ", ", |p, t| p.name_arity(&t.typ, t.arity));
+                p.str("]");
+            }),
+            Form::Compile(x) => self.attribute("compile", |p| p.term(&x.options)),
+            Form::File(x) => self.attribute("file", |p| {
+                p.string(&x.original_file);
+                let _ = write!(p.out, ", {}", x.original_line);
+            }),
+            Form::Record(x) => self.record_decl(x),
+            Form::Type(x) => {
+                self.str(if x.is_opaque { "-opaque " } else { "-type " });
+                self.atom(&x.name);
+                self.str("(");
+                self.separated(&x.vars, ", ", |p, var| p.str(&var.name));
+                self.str(") :: ");
+                self.ty(&x.ty, 0);
+                self.str(".");
+            }
+            Form::Spec(x) => {
+                self.str(if x.is_callback {
+                    "-callback "
+                } else {
+                    "-spec "
+                });
+                if let Some(module) = &x.module {
+                    self.atom(module);
+                    self.str(":");
+                }
+                self.atom(&x.name);
+                // The clauses after the first line up with it
+                let indent = self.indent;
+                self.indent = self.column();
+                for (i, fun) in x.types.iter().enumerate() {
+                    if i > 0 {
+                        self.str(";");
+                        self.newline();
+                    }
+                    self.fun_type(fun);
+                }
+                self.indent = indent;
+                self.str(".");
+            }
+            Form::Attr(x) => self.attribute(&x.name, |p| p.term(&x.value)),
+            Form::Fun(x) => {
+                self.clauses(&x.clauses, |p, clause| {
+                    p.atom(&x.name);
+                    p.args(&clause.patterns);
+                    p.when(&clause.guards);
+                });
+                self.str(".");
+            }
+            Form::Eof(_) => (),
+        }
+    }
+
+    fn attribute(&mut self, name: &str, value: impl FnOnce(&mut Self)) {
+        self.str("-");
+        self.atom(name);
+        self.str("(");
+        value(self);
+        self.str(").");
+    }
+
+    fn name_arity(&mut self, name: &str, arity: u32) {
+        self.atom(name);
+        let _ = write!(self.out, "/{}", arity);
+    }
+
+    /// Writes a record declaration, with a field per line if it does not fit on one
+    fn record_decl(&mut self, record: &form::RecordDecl) {
+        let field = |p: &mut Self, field: &form::RecordFieldDecl| {
+            p.atom(&field.name);
+            // Fields without a default are given `undefined`, and those without a type `any()`
+            if !matches!(&field.default_value, Expression::Atom(a) if a.value == "undefined") {
+                p.str(" = ");
+                field.default_value.print(p, 0);
+            }
+            if !matches!(&field.ty, Type::BuiltIn(t) if t.name == "any" && t.args.is_empty()) {
+                p.str(" :: ");
+                p.ty(&field.ty, 0);
+            }
+        };
+        let name = self.render(|p| p.atom(&record.name));
+        let line = self.render(|p| {
+            p.str("-record(");
+            p.str(&name);
+            p.str(", {");
+            p.separated(&record.fields, ", ", field);
+            p.str("}).");
+        });
+        if !line.contains('\n') && line.chars().count() <= WIDTH {
+            self.str(&line);
+            return;
+        }
+        let indent = self.indent;
+        self.str("-record(");
+        self.str(&name);
+        self.str(",");
+        self.indent = self.column() - name.chars().count() - 1;
+        self.newline();
+        self.str("{");
+        self.indent += 1;
+        for (i, f) in record.fields.iter().enumerate() {
+            if i > 0 {
+                self.str(",");
+                self.newline();
+            }
+            field(self, f);
+        }
+        self.indent = indent;
+        self.str("}).");
+    }
+}
+
+/// Returns the characters of a list, if they are all printable, so that it is written as a string
+fn printable(elements: &[etf::Term]) -> Option<String> {
+    if elements.is_empty() {
+        return None;
+    }
+    elements
+        .iter()
+        .map(|element| match element {
+            etf::Term::FixInteger(x) => char::from_u32(x.value as u32).filter(|c| is_printable(*c)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_printable(c: char) -> bool {
+    !c.is_control() || matches!(c, '\n' | '\r' | '\t' | '\x0b' | '\x08' | '\x0c' | '\x1b')
+}
+
+#[cfg(test)]
+mod tests {
+    use num::bigint::BigUint;
+
+    use super::super::ast::common::Var;
+    use super::super::ast::expr::AnonymousFun;
+    use super::super::ast::form::{FunDecl, FunSpec, RecordDecl, RecordFieldDecl};
+    use super::super::ast::literal::{Atom, Char, Float, Integer, Str};
+    use super::super::ast::ty::{BuiltInType, Union};
+    use super::*;
+
+    fn var(name: &str) -> Expression {
+        Var::new(1, name.to_string()).into()
+    }
+
+    fn atom(name: &str) -> Expression {
+        Atom::new(1, name.to_string()).into()
+    }
+
+    fn int(value: u32) -> Expression {
+        Integer::new(1, BigUint::from(value)).into()
+    }
+
+    fn op(operator: &str, left: Expression, right: Expression) -> Expression {
+        expr::BinaryOp::new(1, operator.to_string(), left, right).into()
+    }
+
+    fn clause(patterns: Vec<Pattern>, body: Vec<Expression>) -> Clause {
+        Clause::new(1, patterns, vec![], body)
+    }
+
+    fn pvar(name: &str) -> Pattern {
+        Var::new(1, name.to_string()).into()
+    }
+
+    fn builtin(name: &str) -> Type {
+        BuiltInType::new(1, name.to_string(), vec![]).into()
+    }
+
+    #[test]
+    fn operators_are_parenthesized_by_precedence() {
+        let sum = op("+", var("A"), var("B"));
+        assert_eq!(expr(&op("*", sum.clone(), var("C"))), "(A + B) * C");
+        assert_eq!(expr(&op("+", var("C"), sum.clone())), "C + (A + B)");
+        assert_eq!(expr(&op("-", sum.clone(), var("C"))), "A + B - C");
+        let append = op("++", var("A"), op("++", var("B"), var("C")));
+        assert_eq!(expr(&append), "A ++ B ++ C");
+        let negated = expr::UnaryOp::new(1, "-".to_string(), sum).into();
+        assert_eq!(expr(&negated), "-(A + B)");
+        let twice = expr::UnaryOp::new(1, "-".to_string(), negated).into();
+        assert_eq!(expr(&twice), "-(-(A + B))");
+        let not = expr::UnaryOp::new(1, "not".to_string(), var("X")).into();
+        assert_eq!(expr(&not), "not X");
+        let caught = expr::Catch::new(1, var("X")).into();
+        let matched = expr::Match::new(1, pvar("Y"), caught).into();
+        assert_eq!(expr(&matched), "Y = (catch X)");
+    }
+
+    #[test]
+    fn literals_read_back_as_themselves() {
+        assert_eq!(expr(&atom("ok")), "ok");
+        assert_eq!(expr(&atom("Ok")), "'Ok'");
+        assert_eq!(expr(&atom("case")), "'case'");
+        assert_eq!(expr(&atom("it's")), "'it\\'s'");
+        assert_eq!(expr(&atom("node@host")), "node@host");
+        assert_eq!(expr(&atom("")), "''");
+        let string = Str::new(1, "a \"b\"\n\x01".to_string()).into();
+        assert_eq!(expr(&string), "\"a \\\"b\\\"\\n\\001\"");
+        assert_eq!(expr(&Char::new(1, ' ').into()), "$\\s");
+        assert_eq!(expr(&Char::new(1, '\n').into()), "$\\n");
+        assert_eq!(expr(&Char::new(1, 'a').into()), "$a");
+        assert_eq!(expr(&Float::new(1, 1.0).into()), "1.0");
+        assert_eq!(expr(&Float::new(1, 0.1).into()), "0.1");
+        assert_eq!(expr(&Float::new(1, 1e100).into()), "1.0e100");
+        assert_eq!(expr(&Float::new(1, 2.5e-7).into()), "2.5e-7");
+    }
+
+    #[test]
+    fn lists_and_binaries() {
+        let nil: Expression = common::Nil::new(1).into();
+        let list = expr::Cons::new(1, int(1), expr::Cons::new(1, int(2), nil).into());
+        assert_eq!(expr(&list.clone().into()), "[1, 2]");
+        let improper = expr::Cons::new(1, int(0), var("T"));
+        assert_eq!(expr(&improper.into()), "[0 | T]");
+        let size = BinElement::new(1, op("+", var("X"), int(1))).size(int(8));
+        let typed = BinElement::new(1, var("Y")).tsl(vec![
+            common::BinElementTypeSpec::new("integer".to_string(), None),
+            common::BinElementTypeSpec::new("unit".to_string(), Some(8)),
+        ]);
+        let binary = expr::Binary::new(1, vec![size, typed]);
+        assert_eq!(expr(&binary.into()), "<<(X + 1):8, Y/integer-unit:8>>");
+    }
+
+    #[test]
+    fn functions_are_laid_out_as_erl_pp_does() {
+        let case = expr::Case::new(
+            1,
+            var("X"),
+            vec![
+                clause(
+                    vec![Integer::new(1, BigUint::from(0u32)).into()],
+                    vec![atom("zero")],
+                ),
+                clause(vec![pvar("_")], vec![atom("other")]),
+            ],
+        );
+        let fun = FunDecl::new(
+            1,
+            "classify".to_string(),
+            vec![clause(vec![pvar("X")], vec![var("Y"), case.into()])],
+        );
+        let expected = "classify(X) ->
+    Y,
+    case X of
+        0 ->
+            zero;
+        _ ->
+            other
+    end.";
+        assert_eq!(form(&fun.into()), expected);
+    }
+
+    #[test]
+    fn funs_fit_on_one_line_when_they_can() {
+        let inc = AnonymousFun::new(
+            1,
+            vec![clause(vec![pvar("X")], vec![op("+", var("X"), int(1))])],
+        );
+        assert_eq!(expr(&inc.into()), "fun(X) -> X + 1 end");
+        let two = AnonymousFun::new(
+            1,
+            vec![
+                clause(vec![pvar("X")], vec![var("X")]),
+                clause(vec![pvar("_")], vec![atom("ok")]),
+            ],
+        );
+        assert_eq!(
+            expr(&two.into()),
+            "fun\n    (X) ->\n        X;\n    (_) ->\n        ok\nend"
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let field = RecordFieldDecl::new(1, "name".to_string());
+        let count = RecordFieldDecl::new(1, "count".to_string())
+            .default_value(int(0))
+            .typ(builtin("integer"));
+        let record = RecordDecl::new(1, "state".to_string(), vec![field.clone(), count.clone()]);
+        assert_eq!(
+            form(&record.into()),
+            "-record(state, {name, count = 0 :: integer()})."
+        );
+        let fields = vec![count; 4];
+        let record = RecordDecl::new(1, "state".to_string(), fields);
+        let expected = "-record(state,
+        {count = 0 :: integer(),
+         count = 0 :: integer(),
+         count = 0 :: integer(),
+         count = 0 :: integer()}).";
+        assert_eq!(form(&record.into()), expected);
+
+        let union = Union::new(
+            1,
+            vec![
+                builtin("integer"),
+                Atom::new(1, "infinity".to_string()).into(),
+            ],
+        );
+        let spec = FunSpec::new(
+            1,
+            "timeout".to_string(),
+            vec![
+                ty::Fun::new(1, vec![union.into()], builtin("ok")),
+                ty::Fun::new(1, vec![], builtin("ok")),
+            ],
+        );
+        let expected = "-spec timeout(integer() | infinity) -> ok();\n             () -> ok().";
+        assert_eq!(form(&spec.into()), expected);
+    }
+}
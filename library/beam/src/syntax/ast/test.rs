@@ -9,3 +9,64 @@ fn it_works() {
         })
         .unwrap();
 }
+
+/// Modules compiled since OTP 20 keep their abstract code in the `Dbgi` chunk
+#[test]
+fn debug_info_chunk() {
+    use crate::beam::reader::{RawBeamFile, RawChunk};
+    use crate::serialization::etf::{Atom, FixInteger, List, Term, Tuple};
+
+    fn atom(name: &str) -> Term {
+        Atom::from(name).into()
+    }
+    fn int(value: i32) -> Term {
+        FixInteger::from(value).into()
+    }
+    fn tuple(elements: Vec<Term>) -> Term {
+        Tuple::from(elements).into()
+    }
+    fn list(elements: Vec<Term>) -> Term {
+        List::from(elements).into()
+    }
+
+    // -module(m). -export([f/0]). f() -> ok.
+    let forms = list(vec![
+        tuple(vec![atom("attribute"), int(1), atom("module"), atom("m")]),
+        tuple(vec![
+            atom("attribute"),
+            int(1),
+            atom("export"),
+            list(vec![tuple(vec![atom("f"), int(0)])]),
+        ]),
+        tuple(vec![
+            atom("function"),
+            int(2),
+            atom("f"),
+            int(0),
+            list(vec![tuple(vec![
+                atom("clause"),
+                int(2),
+                list(vec![]),
+                list(vec![]),
+                list(vec![tuple(vec![atom("atom"), int(2), atom("ok")])]),
+            ])]),
+        ]),
+        tuple(vec![atom("eof"), int(3)]),
+    ]);
+    let info = tuple(vec![
+        atom("debug_info_v1"),
+        atom("erl_abstract_code"),
+        tuple(vec![forms, list(vec![])]),
+    ]);
+    let mut data = vec![];
+    info.encode(&mut data).unwrap();
+    let mut beam = RawBeamFile::new();
+    beam.push_chunk(RawChunk { id: *b"Dbgi", data });
+    let mut bytes = vec![];
+    beam.to_writer(&mut bytes).unwrap();
+
+    let ast = AST::from_beam_bytes(bytes.as_slice()).unwrap();
+    let source = pp::module(&ast.module);
+    assert!(source.contains("-module(m)."), "{}", source);
+    assert!(source.contains("f() ->"), "{}", source);
+}
@@ -0,0 +1,398 @@
+//! The functions of `erl_driver.h` available to drivers
+//!
+//! These are exported from the executable under their C names, so that drivers opened with
+//! `dlopen` can resolve them.
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_long, c_ulong, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::errno::errno_name;
+use crate::port::{self, ExitReason};
+use crate::select;
+use crate::sys::*;
+
+#[export_name = "driver_alloc"]
+pub unsafe extern "C" fn alloc(size: ErlDrvSizeT) -> *mut c_void {
+    libc::malloc(size)
+}
+
+#[export_name = "driver_realloc"]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: ErlDrvSizeT) -> *mut c_void {
+    libc::realloc(ptr, size)
+}
+
+#[export_name = "driver_free"]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    libc::free(ptr)
+}
+
+/// The header preceding every `ErlDrvBinary`, holding its reference count
+///
+/// The header is padded so that the data of the binary is aligned as if allocated by `malloc`.
+#[repr(C, align(16))]
+struct BinaryHeader {
+    refc: AtomicIsize,
+}
+impl BinaryHeader {
+    const SIZE: usize = core::mem::size_of::<Self>();
+
+    #[inline]
+    unsafe fn of(bin: *mut ErlDrvBinary) -> *mut Self {
+        bin.cast::<u8>().sub(Self::SIZE).cast()
+    }
+
+    #[inline]
+    unsafe fn binary(header: *mut Self) -> *mut ErlDrvBinary {
+        header.cast::<u8>().add(Self::SIZE).cast()
+    }
+}
+
+/// Returns the contents of `bin` as a slice
+unsafe fn binary_bytes<'a>(bin: *const ErlDrvBinary) -> &'a [u8] {
+    let bytes = (*bin).orig_bytes.as_ptr().cast::<u8>();
+    core::slice::from_raw_parts(bytes, (*bin).orig_size as usize)
+}
+
+#[export_name = "driver_alloc_binary"]
+pub unsafe extern "C" fn alloc_binary(size: ErlDrvSizeT) -> *mut ErlDrvBinary {
+    let header = libc::malloc(BinaryHeader::SIZE + core::mem::size_of::<ErlDrvSint>() + size)
+        .cast::<BinaryHeader>();
+    if header.is_null() {
+        return ptr::null_mut();
+    }
+    header.write(BinaryHeader {
+        refc: AtomicIsize::new(1),
+    });
+    let bin = BinaryHeader::binary(header);
+    (*bin).orig_size = size as ErlDrvSint;
+    bin
+}
+
+#[export_name = "driver_realloc_binary"]
+pub unsafe extern "C" fn realloc_binary(
+    bin: *mut ErlDrvBinary,
+    size: ErlDrvSizeT,
+) -> *mut ErlDrvBinary {
+    let header = libc::realloc(
+        BinaryHeader::of(bin).cast(),
+        BinaryHeader::SIZE + core::mem::size_of::<ErlDrvSint>() + size,
+    )
+    .cast::<BinaryHeader>();
+    if header.is_null() {
+        return ptr::null_mut();
+    }
+    let bin = BinaryHeader::binary(header);
+    (*bin).orig_size = size as ErlDrvSint;
+    bin
+}
+
+/// Releases a reference to `bin`, deallocating it when the last reference is released
+#[export_name = "driver_free_binary"]
+pub unsafe extern "C" fn free_binary(bin: *mut ErlDrvBinary) {
+    if binary_dec_refc(bin) == 0 {
+        libc::free(BinaryHeader::of(bin).cast());
+    }
+}
+
+#[export_name = "driver_binary_get_refc"]
+pub unsafe extern "C" fn binary_get_refc(bin: *mut ErlDrvBinary) -> c_long {
+    (*BinaryHeader::of(bin)).refc.load(Ordering::Acquire) as c_long
+}
+
+#[export_name = "driver_binary_inc_refc"]
+pub unsafe extern "C" fn binary_inc_refc(bin: *mut ErlDrvBinary) -> c_long {
+    (*BinaryHeader::of(bin)).refc.fetch_add(1, Ordering::AcqRel) as c_long + 1
+}
+
+#[export_name = "driver_binary_dec_refc"]
+pub unsafe extern "C" fn binary_dec_refc(bin: *mut ErlDrvBinary) -> c_long {
+    (*BinaryHeader::of(bin)).refc.fetch_sub(1, Ordering::AcqRel) as c_long - 1
+}
+
+unsafe fn bytes<'a>(buf: *const c_char, len: ErlDrvSizeT) -> &'a [u8] {
+    if buf.is_null() || len == 0 {
+        &[]
+    } else {
+        core::slice::from_raw_parts(buf.cast(), len)
+    }
+}
+
+/// Collects the contents of `ev`, skipping the first `skip` bytes
+unsafe fn iovec_bytes(ev: *const ErlIOVec, mut skip: usize) -> Vec<u8> {
+    let ev = &*ev;
+    let mut data = Vec::with_capacity(ev.size.saturating_sub(skip));
+    for i in 0..(ev.vsize as usize) {
+        let iov = &*ev.iov.add(i);
+        let chunk = bytes(iov.iov_base, iov.iov_len);
+        if skip >= chunk.len() {
+            skip -= chunk.len();
+            continue;
+        }
+        data.extend_from_slice(&chunk[skip..]);
+        skip = 0;
+    }
+    data
+}
+
+/// Queues output from the driver for delivery to the owner of `port`
+fn output(port: ErlDrvPort, data: Vec<u8>) -> c_int {
+    match port::from_handle(port) {
+        Some(port) => {
+            port.output(data);
+            0
+        }
+        None => -1,
+    }
+}
+
+#[export_name = "driver_output"]
+pub unsafe extern "C" fn driver_output(
+    port: ErlDrvPort,
+    buf: *mut c_char,
+    len: ErlDrvSizeT,
+) -> c_int {
+    output(port, bytes(buf, len).to_vec())
+}
+
+#[export_name = "driver_output2"]
+pub unsafe extern "C" fn driver_output2(
+    port: ErlDrvPort,
+    hbuf: *mut c_char,
+    hlen: ErlDrvSizeT,
+    buf: *mut c_char,
+    len: ErlDrvSizeT,
+) -> c_int {
+    let mut data = bytes(hbuf, hlen).to_vec();
+    data.extend_from_slice(bytes(buf, len));
+    output(port, data)
+}
+
+#[export_name = "driver_output_binary"]
+pub unsafe extern "C" fn driver_output_binary(
+    port: ErlDrvPort,
+    hbuf: *mut c_char,
+    hlen: ErlDrvSizeT,
+    bin: *mut ErlDrvBinary,
+    offset: ErlDrvSizeT,
+    len: ErlDrvSizeT,
+) -> c_int {
+    let Some(body) = binary_bytes(bin).get(offset..(offset + len)) else {
+        return -1;
+    };
+    let mut data = bytes(hbuf, hlen).to_vec();
+    data.extend_from_slice(body);
+    output(port, data)
+}
+
+#[export_name = "driver_outputv"]
+pub unsafe extern "C" fn driver_outputv(
+    port: ErlDrvPort,
+    hbuf: *mut c_char,
+    hlen: ErlDrvSizeT,
+    ev: *mut ErlIOVec,
+    skip: ErlDrvSizeT,
+) -> c_int {
+    let mut data = bytes(hbuf, hlen).to_vec();
+    data.extend(iovec_bytes(ev, skip));
+    output(port, data)
+}
+
+fn failure(port: ErlDrvPort, reason: ExitReason) -> c_int {
+    match port::from_handle(port) {
+        Some(port) => {
+            port.exit(reason);
+            0
+        }
+        None => -1,
+    }
+}
+
+#[export_name = "driver_failure_eof"]
+pub extern "C" fn driver_failure_eof(port: ErlDrvPort) -> c_int {
+    failure(port, ExitReason::Atom("normal".to_string()))
+}
+
+#[export_name = "driver_failure_atom"]
+pub unsafe extern "C" fn driver_failure_atom(port: ErlDrvPort, string: *mut c_char) -> c_int {
+    let reason = CStr::from_ptr(string).to_string_lossy().into_owned();
+    failure(port, ExitReason::Atom(reason))
+}
+
+#[export_name = "driver_failure_posix"]
+pub extern "C" fn driver_failure_posix(port: ErlDrvPort, error: c_int) -> c_int {
+    failure(port, ExitReason::Atom(errno_name(error).to_string()))
+}
+
+#[export_name = "driver_failure"]
+pub extern "C" fn driver_failure(port: ErlDrvPort, error: c_int) -> c_int {
+    if error == 0 {
+        return driver_failure_eof(port);
+    }
+    failure(port, ExitReason::Integer(error))
+}
+
+#[export_name = "driver_exit"]
+pub extern "C" fn driver_exit(port: ErlDrvPort, err: c_int) -> c_int {
+    if err == 0 {
+        return driver_failure_eof(port);
+    }
+    driver_failure_posix(port, err)
+}
+
+#[export_name = "driver_select"]
+pub extern "C" fn driver_select(
+    port: ErlDrvPort,
+    event: ErlDrvEvent,
+    mode: c_int,
+    on: c_int,
+) -> c_int {
+    match port::from_handle(port) {
+        Some(port) => select::select(&port, event, mode, on != 0),
+        None => -1,
+    }
+}
+
+#[export_name = "driver_enq"]
+pub unsafe extern "C" fn driver_enq(port: ErlDrvPort, buf: *mut c_char, len: ErlDrvSizeT) -> c_int {
+    let Some(port) = port::from_handle(port) else {
+        return -1;
+    };
+    port.queue.lock().enqueue(bytes(buf, len));
+    0
+}
+
+#[export_name = "driver_pushq"]
+pub unsafe extern "C" fn driver_pushq(
+    port: ErlDrvPort,
+    buf: *mut c_char,
+    len: ErlDrvSizeT,
+) -> c_int {
+    let Some(port) = port::from_handle(port) else {
+        return -1;
+    };
+    port.queue.lock().push(bytes(buf, len));
+    0
+}
+
+#[export_name = "driver_enq_bin"]
+pub unsafe extern "C" fn driver_enq_bin(
+    port: ErlDrvPort,
+    bin: *mut ErlDrvBinary,
+    offset: ErlDrvSizeT,
+    len: ErlDrvSizeT,
+) -> c_int {
+    let Some(data) = binary_bytes(bin).get(offset..(offset + len)) else {
+        return -1;
+    };
+    driver_enq(port, data.as_ptr() as *mut c_char, data.len())
+}
+
+#[export_name = "driver_pushq_bin"]
+pub unsafe extern "C" fn driver_pushq_bin(
+    port: ErlDrvPort,
+    bin: *mut ErlDrvBinary,
+    offset: ErlDrvSizeT,
+    len: ErlDrvSizeT,
+) -> c_int {
+    let Some(data) = binary_bytes(bin).get(offset..(offset + len)) else {
+        return -1;
+    };
+    driver_pushq(port, data.as_ptr() as *mut c_char, data.len())
+}
+
+#[export_name = "driver_enqv"]
+pub unsafe extern "C" fn driver_enqv(
+    port: ErlDrvPort,
+    ev: *mut ErlIOVec,
+    skip: ErlDrvSizeT,
+) -> c_int {
+    let data = iovec_bytes(ev, skip);
+    driver_enq(port, data.as_ptr() as *mut c_char, data.len())
+}
+
+/// Removes `size` bytes from the front of the queue of `port`, returning the number of bytes
+/// remaining
+#[export_name = "driver_deq"]
+pub extern "C" fn driver_deq(port: ErlDrvPort, size: ErlDrvSizeT) -> ErlDrvSizeT {
+    let Some(port) = port::from_handle(port) else {
+        return -1isize as ErlDrvSizeT;
+    };
+    let mut queue = port.queue.lock();
+    if !queue.dequeue(size) {
+        return -1isize as ErlDrvSizeT;
+    }
+    queue.size()
+}
+
+#[export_name = "driver_sizeq"]
+pub extern "C" fn driver_sizeq(port: ErlDrvPort) -> ErlDrvSizeT {
+    match port::from_handle(port) {
+        Some(port) => port.queue.lock().size(),
+        None => -1isize as ErlDrvSizeT,
+    }
+}
+
+/// Returns the contents of the queue of `port` as an I/O vector of `*vlen` elements
+///
+/// The vector is valid until the queue is next modified.
+#[export_name = "driver_peekq"]
+pub unsafe extern "C" fn driver_peekq(port: ErlDrvPort, vlen: *mut c_int) -> *mut SysIOVec {
+    let Some(port) = port::from_handle(port) else {
+        *vlen = -1;
+        return ptr::null_mut();
+    };
+    let mut queue = port.queue.lock();
+    let iov = queue.peek();
+    *vlen = iov.len() as c_int;
+    if iov.is_empty() {
+        ptr::null_mut()
+    } else {
+        iov.as_mut_ptr()
+    }
+}
+
+#[export_name = "driver_set_timer"]
+pub extern "C" fn driver_set_timer(port: ErlDrvPort, time: c_ulong) -> c_int {
+    let Some(port) = port::from_handle(port) else {
+        return -1;
+    };
+    port.set_timer(Some(Instant::now() + Duration::from_millis(time as u64)));
+    0
+}
+
+#[export_name = "driver_cancel_timer"]
+pub extern "C" fn driver_cancel_timer(port: ErlDrvPort) -> c_int {
+    let Some(port) = port::from_handle(port) else {
+        return -1;
+    };
+    port.set_timer(None);
+    0
+}
+
+/// Writes the number of milliseconds remaining until the timer of `port` expires to `time_left`
+#[export_name = "driver_read_timer"]
+pub unsafe extern "C" fn driver_read_timer(port: ErlDrvPort, time_left: *mut c_ulong) -> c_int {
+    let Some(port) = port::from_handle(port) else {
+        return -1;
+    };
+    *time_left = match port.timer() {
+        Some(deadline) => deadline
+            .saturating_duration_since(Instant::now())
+            .as_millis() as c_ulong,
+        None => 0,
+    };
+    0
+}
+
+#[export_name = "set_port_control_flags"]
+pub extern "C" fn set_port_control_flags(port: ErlDrvPort, flags: c_int) {
+    if let Some(port) = port::from_handle(port) {
+        port.set_control_flags(flags);
+    }
+}
+
+/// Ports never block their callers, so this has no effect
+#[export_name = "set_busy_port"]
+pub extern "C" fn set_busy_port(_port: ErlDrvPort, _on: c_int) {}
@@ -0,0 +1,245 @@
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+
+use firefly_system::sync::RwLock;
+
+use crate::sys::*;
+
+lazy_static! {
+    /// The drivers available to `erlang:open_port/2`, by name
+    static ref DRIVERS: RwLock<HashMap<String, Arc<Driver>>> = Default::default();
+}
+
+/// A driver, either loaded from a shared library by `erl_ddll:load_driver/2`, or linked into the
+/// executable and registered with `register`
+pub(crate) struct Driver {
+    entry: *mut ErlDrvEntry,
+    /// The handle returned by `dlopen`, or null for statically linked drivers
+    handle: *mut c_void,
+}
+unsafe impl Send for Driver {}
+unsafe impl Sync for Driver {}
+impl Driver {
+    #[inline]
+    pub fn entry(&self) -> &ErlDrvEntry {
+        unsafe { &*self.entry }
+    }
+}
+
+/// Returns the driver with the given name, if loaded
+pub(crate) fn lookup(name: &str) -> Option<Arc<Driver>> {
+    DRIVERS.read().get(name).cloned()
+}
+
+/// Returns the names of all loaded drivers, as by `erl_ddll:loaded_drivers/0`
+pub fn loaded() -> Vec<String> {
+    DRIVERS.read().keys().cloned().collect()
+}
+
+/// The reasons `load` or `register` may fail, these correspond to the error reasons of
+/// `erl_ddll:load_driver/2`
+#[derive(Debug)]
+pub enum LoadError {
+    /// The library could not be opened
+    OpenError(String),
+    /// The library is not a valid driver, or does not implement the named driver
+    BadDriverName(String),
+    /// The `init` callback of the driver failed
+    InitFailed,
+    /// A driver with the same name is already loaded
+    AlreadyLoaded,
+}
+impl LoadError {
+    /// Returns the reason atom of the `{error, Reason}` tuple returned by `erl_ddll:load_driver/2`
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::OpenError(_) => "open_error",
+            Self::BadDriverName(_) => "bad_driver_name",
+            Self::InitFailed => "driver_init_failed",
+            Self::AlreadyLoaded => "already_loaded",
+        }
+    }
+}
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OpenError(text) | Self::BadDriverName(text) => f.write_str(text),
+            Self::InitFailed => f.write_str("the driver init function failed"),
+            Self::AlreadyLoaded => f.write_str("a driver with that name is already loaded"),
+        }
+    }
+}
+
+/// The reasons `unload` may fail, these correspond to the error reasons of
+/// `erl_ddll:unload_driver/1`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnloadError {
+    /// No driver with the given name is loaded
+    NotLoaded,
+    /// The driver is linked into the executable, and cannot be unloaded
+    Permanent,
+    /// Ports using the driver are still open
+    ///
+    /// BEAM defers unloading until such ports are closed, here it is up to the caller to close
+    /// them first.
+    InUse,
+}
+impl UnloadError {
+    /// Returns the reason atom of the `{error, Reason}` tuple returned by
+    /// `erl_ddll:unload_driver/1`
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::NotLoaded => "not_loaded",
+            Self::Permanent => "permanent",
+            Self::InUse => "in_use",
+        }
+    }
+}
+
+/// Loads the driver `name` from the shared library `name` in the directory `path`, as by
+/// `erl_ddll:load_driver/2`
+///
+/// As with BEAM, the library file name is the name of the driver with the platform-specific file
+/// extension, and the library must export `driver_init`, describing a driver of the same name.
+pub fn load(path: &str, name: &str) -> Result<(), LoadError> {
+    if DRIVERS.read().contains_key(name) {
+        return Err(LoadError::AlreadyLoaded);
+    }
+
+    let filename = format!(
+        "{}/{}{}",
+        path.trim_end_matches('/'),
+        name,
+        std::env::consts::DLL_SUFFIX
+    );
+    let Ok(cfilename) = CString::new(filename.as_str()) else {
+        return Err(LoadError::OpenError(format!(
+            "Invalid driver path '{}'",
+            filename
+        )));
+    };
+    let handle = unsafe { libc::dlopen(cfilename.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        let reason = unsafe { CStr::from_ptr(libc::dlerror()) };
+        return Err(LoadError::OpenError(format!(
+            "Failed to load driver {}: '{}'",
+            filename,
+            reason.to_string_lossy()
+        )));
+    }
+
+    let symbol = CString::new(DRIVER_INIT_SYMBOL).unwrap();
+    let init = unsafe { libc::dlsym(handle, symbol.as_ptr()) };
+    if init.is_null() {
+        unsafe {
+            libc::dlclose(handle);
+        }
+        return Err(LoadError::BadDriverName(format!(
+            "Failed to find driver init function: '{}'",
+            filename
+        )));
+    }
+    let init = unsafe { core::mem::transmute::<*mut c_void, DriverInitFn>(init) };
+    let entry = unsafe { init() };
+    let result = unsafe { add(name, entry, handle) };
+    if result.is_err() {
+        unsafe {
+            libc::dlclose(handle);
+        }
+    }
+    result
+}
+
+/// Registers a driver linked into the executable, making it available to `erlang:open_port/2`
+///
+/// Such drivers cannot be unloaded.
+///
+/// # Safety
+///
+/// `entry` must point to a valid driver entry which lives for the remainder of the program.
+pub unsafe fn register(entry: *mut ErlDrvEntry) -> Result<(), LoadError> {
+    if entry.is_null() || (*entry).driver_name.is_null() {
+        return Err(LoadError::BadDriverName(
+            "Driver entry has no name".to_string(),
+        ));
+    }
+    let name = CStr::from_ptr((*entry).driver_name)
+        .to_string_lossy()
+        .into_owned();
+    add(name.as_str(), entry, ptr::null_mut())
+}
+
+unsafe fn add(name: &str, entry: *mut ErlDrvEntry, handle: *mut c_void) -> Result<(), LoadError> {
+    if entry.is_null() {
+        return Err(LoadError::BadDriverName(
+            "Driver init-call unsuccessful".to_string(),
+        ));
+    }
+    let e = &*entry;
+    if e.extended_marker != ERL_DRV_EXTENDED_MARKER
+        || e.major_version != ERL_DRV_EXTENDED_MAJOR_VERSION
+        || e.minor_version > ERL_DRV_EXTENDED_MINOR_VERSION
+    {
+        return Err(LoadError::BadDriverName(format!(
+            "Driver version ({}.{}) not compatible (with {}.{}).",
+            e.major_version,
+            e.minor_version,
+            ERL_DRV_EXTENDED_MAJOR_VERSION,
+            ERL_DRV_EXTENDED_MINOR_VERSION
+        )));
+    }
+    let driver_name = if e.driver_name.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(e.driver_name).to_string_lossy())
+    };
+    if driver_name.as_deref() != Some(name) {
+        return Err(LoadError::BadDriverName(format!(
+            "Driver name '{}' does not match requested name '{}'",
+            driver_name.as_deref().unwrap_or(""),
+            name
+        )));
+    }
+
+    let mut drivers = DRIVERS.write();
+    if drivers.contains_key(name) {
+        return Err(LoadError::AlreadyLoaded);
+    }
+    if let Some(init) = e.init {
+        if init() != 0 {
+            return Err(LoadError::InitFailed);
+        }
+    }
+    drivers.insert(name.to_string(), Arc::new(Driver { entry, handle }));
+    Ok(())
+}
+
+/// Unloads the driver `name`, as by `erl_ddll:unload_driver/1`
+pub fn unload(name: &str) -> Result<(), UnloadError> {
+    let driver = {
+        let mut drivers = DRIVERS.write();
+        let Some(driver) = drivers.get(name) else {
+            return Err(UnloadError::NotLoaded);
+        };
+        if driver.handle.is_null() {
+            return Err(UnloadError::Permanent);
+        }
+        if crate::port::uses_driver(driver) {
+            return Err(UnloadError::InUse);
+        }
+        drivers.remove(name).unwrap()
+    };
+    if let Some(finish) = driver.entry().finish {
+        unsafe { finish() };
+    }
+    unsafe {
+        libc::dlclose(driver.handle);
+    }
+    Ok(())
+}
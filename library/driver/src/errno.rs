@@ -0,0 +1,62 @@
+use std::os::raw::c_int;
+
+macro_rules! errno_names {
+    ($($errno:ident => $name:literal),* $(,)?) => {
+        /// Returns the POSIX error atom name for `errno`, as a null-terminated string
+        fn errno_cstr(errno: c_int) -> &'static str {
+            match errno {
+                $(libc::$errno => concat!($name, "\0"),)*
+                _ => "unknown\0",
+            }
+        }
+    }
+}
+
+errno_names! {
+    EACCES => "eacces",
+    EADDRINUSE => "eaddrinuse",
+    EADDRNOTAVAIL => "eaddrnotavail",
+    EAGAIN => "eagain",
+    EBADF => "ebadf",
+    EBUSY => "ebusy",
+    ECONNABORTED => "econnaborted",
+    ECONNREFUSED => "econnrefused",
+    ECONNRESET => "econnreset",
+    EEXIST => "eexist",
+    EHOSTUNREACH => "ehostunreach",
+    EINTR => "eintr",
+    EINVAL => "einval",
+    EIO => "eio",
+    EISDIR => "eisdir",
+    EMFILE => "emfile",
+    ENETUNREACH => "enetunreach",
+    ENFILE => "enfile",
+    ENODEV => "enodev",
+    ENOENT => "enoent",
+    ENOMEM => "enomem",
+    ENOSPC => "enospc",
+    ENOTCONN => "enotconn",
+    ENOTDIR => "enotdir",
+    ENXIO => "enxio",
+    EPERM => "eperm",
+    EPIPE => "epipe",
+    EROFS => "erofs",
+    ETIMEDOUT => "etimedout",
+}
+
+/// Returns the name of the POSIX error atom corresponding to `errno`, e.g. `enoent`
+pub(crate) fn errno_name(errno: c_int) -> &'static str {
+    let name = errno_cstr(errno);
+    &name[..name.len() - 1]
+}
+
+/// Returns the name of the POSIX error atom corresponding to `errno`, as a C string
+#[export_name = "erl_errno_id"]
+pub extern "C" fn erl_errno_id(errno: c_int) -> *const std::os::raw::c_char {
+    errno_cstr(errno).as_ptr().cast()
+}
+
+/// Returns the value of `errno` for the calling thread
+pub(crate) fn last_errno() -> c_int {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
@@ -0,0 +1,36 @@
+//! This crate provides an implementation of the `erl_driver` interface, for implementing ports in
+//! native code, and loading them from shared libraries at runtime via `erl_ddll:load_driver/2`.
+//!
+//! Drivers are opened via `erlang:open_port({spawn_driver, Command}, Options)`, and run in-process:
+//! data sent with `erlang:port_command/2` is passed to their `output` (or `outputv`) callback,
+//! `erlang:port_control/3` calls their `control` callback, and data they produce via
+//! `driver_output*` is delivered to the owner of the port as `{Port, {data, Data}}`. Drivers may
+//! buffer data in the queue of the port (`driver_enq`, `driver_peekq`, `driver_deq`, etc.), set
+//! timers, and select file descriptors for readiness, in which case their `ready_input` and
+//! `ready_output` callbacks are invoked by `poll`, which the runtime calls from its event loop.
+//!
+//! Only a subset of the interface is supported: there are no async threads, monitors, or
+//! `erlang:port_call/3`, and drivers cannot construct terms (`erl_drv_output_term` etc.). As with
+//! `firefly_nif`, the names and layouts in `sys` match those of BEAM, and the `driver_*` symbols are
+//! exported from the executable so that drivers opened with `dlopen` can resolve them. Drivers
+//! linked into the executable can instead be made available with `register`.
+//!
+//! Ports are not processes, so rather than sending messages directly, this crate queues the
+//! messages produced by drivers, and the runtime delivers them to the owners of ports after each
+//! operation on a port, and after each `poll`, see `drain_events`.
+#![feature(let_else)]
+
+mod api;
+mod driver;
+mod errno;
+mod port;
+mod queue;
+mod select;
+pub mod sys;
+
+pub use self::driver::{load, loaded, register, unload, LoadError, UnloadError};
+pub use self::port::{
+    close, command, connect, control, drain_events, open, owner, ports, ControlReply, ExitReason,
+    OpenError, PortError, PortEvent, PortMessage, PortOptions,
+};
+pub use self::select::{is_active, poll, wake};
@@ -0,0 +1,432 @@
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+
+use firefly_rt::term::{PortId, ProcessId};
+use firefly_system::sync::Mutex;
+
+use crate::api;
+use crate::driver::{self, Driver};
+use crate::errno::{errno_name, last_errno};
+use crate::queue::DriverQueue;
+use crate::select;
+use crate::sys::*;
+
+lazy_static! {
+    /// The open ports, by identifier
+    static ref PORTS: Mutex<HashMap<PortId, Arc<PortState>>> = Default::default();
+    /// Messages produced by ports, awaiting delivery to their owners
+    static ref EVENTS: Mutex<VecDeque<PortEvent>> = Default::default();
+}
+
+/// Port identifiers start at 1, so that no port is ever represented by a null `ErlDrvPort`
+static NEXT_PORT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A message produced by a port, to be delivered to its owner by the runtime
+pub struct PortEvent {
+    pub port: PortId,
+    pub owner: ProcessId,
+    pub message: PortMessage,
+}
+
+pub enum PortMessage {
+    /// Output from the driver, delivered as `{Port, {data, Data}}`, where `Data` is a binary if
+    /// the port was opened in binary mode, and a list of bytes otherwise
+    Data { data: Vec<u8>, binary: bool },
+    /// The driver terminated the port, delivered as `{'EXIT', Port, Reason}`
+    ///
+    /// Ports closed via `close` terminate silently, as with `erlang:port_close/1`.
+    Exit(ExitReason),
+}
+
+/// The reason a driver gave for terminating a port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The reason is the atom with the given name
+    Atom(String),
+    /// The reason is the given integer, as passed to `driver_failure`
+    Integer(c_int),
+}
+
+/// Options given to `open`, corresponding to those of `erlang:open_port/2`
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PortOptions {
+    /// Deliver data as binaries rather than lists
+    pub binary: bool,
+}
+
+/// The reasons `open` may fail, these correspond to the errors raised by `erlang:open_port/2`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OpenError {
+    /// No driver with the given name is loaded
+    NoDriver,
+    /// The driver rejected the command with `ERL_DRV_ERROR_BADARG`
+    BadArg,
+    /// The driver failed with the given `errno`
+    Posix(c_int),
+    /// The driver failed with `ERL_DRV_ERROR_GENERAL`
+    General,
+}
+impl OpenError {
+    /// Returns the reason of the `error` exception raised by `erlang:open_port/2`
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::NoDriver | Self::BadArg => "badarg",
+            Self::Posix(errno) => errno_name(*errno),
+            Self::General => "einval",
+        }
+    }
+}
+
+/// The reasons an operation on a port may fail, in all cases `badarg` is raised
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PortError {
+    /// The port is closed, or never existed
+    Closed,
+    /// The driver does not support the operation, or rejected its arguments
+    BadArg,
+}
+
+/// The reply to `erlang:port_control/3`
+pub struct ControlReply {
+    pub data: Vec<u8>,
+    /// True if the driver set `PORT_CONTROL_FLAG_BINARY`, in which case the reply is a binary,
+    /// otherwise it is a list of bytes
+    pub binary: bool,
+}
+
+pub(crate) struct PortState {
+    id: PortId,
+    driver: Arc<Driver>,
+    data: AtomicPtr<c_void>,
+    owner: Mutex<ProcessId>,
+    binary: bool,
+    control_flags: AtomicI32,
+    pub(crate) queue: Mutex<DriverQueue>,
+    timer: Mutex<Option<Instant>>,
+    /// Set once the port is closing, it terminates as soon as its queue is empty
+    closing: AtomicBool,
+    /// Set when the driver fails the port, it terminates once the current callback returns
+    exit_reason: Mutex<Option<ExitReason>>,
+}
+impl PortState {
+    #[inline]
+    pub fn id(&self) -> PortId {
+        self.id
+    }
+
+    #[inline]
+    pub fn driver(&self) -> &Driver {
+        &self.driver
+    }
+
+    #[inline]
+    pub fn data(&self) -> ErlDrvData {
+        self.data.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn handle(&self) -> ErlDrvPort {
+        self.id.as_u64() as usize as ErlDrvPort
+    }
+
+    pub fn set_control_flags(&self, flags: c_int) {
+        self.control_flags.store(flags, Ordering::Relaxed);
+    }
+
+    pub fn set_timer(&self, deadline: Option<Instant>) {
+        *self.timer.lock() = deadline;
+    }
+
+    pub fn timer(&self) -> Option<Instant> {
+        *self.timer.lock()
+    }
+
+    /// Queues `data` for delivery to the owner of this port
+    pub fn output(&self, data: Vec<u8>) {
+        push_event(
+            self,
+            PortMessage::Data {
+                data,
+                binary: self.binary,
+            },
+        );
+    }
+
+    /// Fails this port with `reason`, terminating it once control returns from the driver
+    pub fn exit(&self, reason: ExitReason) {
+        self.exit_reason.lock().get_or_insert(reason);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closing.load(Ordering::Acquire) || self.exit_reason.lock().is_some()
+    }
+}
+
+fn push_event(port: &PortState, message: PortMessage) {
+    EVENTS.lock().push_back(PortEvent {
+        port: port.id,
+        owner: *port.owner.lock(),
+        message,
+    });
+}
+
+/// Returns the port with the given identifier, if it has not yet terminated
+pub(crate) fn get(port: PortId) -> Option<Arc<PortState>> {
+    PORTS.lock().get(&port).cloned()
+}
+
+/// Returns the port with the given handle, if it has not yet terminated
+pub(crate) fn from_handle(port: ErlDrvPort) -> Option<Arc<PortState>> {
+    get(unsafe { PortId::from_raw(port as usize as u64) })
+}
+
+fn lookup(port: PortId) -> Result<Arc<PortState>, PortError> {
+    match PORTS.lock().get(&port) {
+        Some(state) if !state.is_closed() => Ok(state.clone()),
+        _ => Err(PortError::Closed),
+    }
+}
+
+/// Returns true if any open port uses `driver`
+pub(crate) fn uses_driver(driver: &Driver) -> bool {
+    PORTS
+        .lock()
+        .values()
+        .any(|port| ptr::eq(port.driver(), driver))
+}
+
+/// Returns the identifiers of all open ports, as by `erlang:ports/0`
+pub fn ports() -> Vec<PortId> {
+    PORTS.lock().keys().copied().collect()
+}
+
+/// Returns the owner of `port`, if it is open
+pub fn owner(port: PortId) -> Option<ProcessId> {
+    lookup(port).ok().map(|state| *state.owner.lock())
+}
+
+/// Takes all messages produced by ports since the last call, in the order they were produced
+pub fn drain_events() -> Vec<PortEvent> {
+    EVENTS.lock().drain(..).collect()
+}
+
+/// Opens a port owned by `owner`, as by `erlang:open_port({spawn_driver, Command}, Options)`
+///
+/// The driver is named by the first word of `command`, and the whole of `command` is passed to its
+/// `start` callback.
+pub fn open(command: &str, owner: ProcessId, options: PortOptions) -> Result<PortId, OpenError> {
+    let name = command.split_whitespace().next().unwrap_or("");
+    let Some(driver) = driver::lookup(name) else {
+        return Err(OpenError::NoDriver);
+    };
+    let Ok(command) = CString::new(command) else {
+        return Err(OpenError::BadArg);
+    };
+
+    let id = unsafe { PortId::from_raw(NEXT_PORT_ID.fetch_add(1, Ordering::Relaxed)) };
+    let state = Arc::new(PortState {
+        id,
+        driver,
+        data: AtomicPtr::new(ptr::null_mut()),
+        owner: Mutex::new(owner),
+        binary: options.binary,
+        control_flags: AtomicI32::new(0),
+        queue: Mutex::new(DriverQueue::default()),
+        timer: Mutex::new(None),
+        closing: AtomicBool::new(false),
+        exit_reason: Mutex::new(None),
+    });
+    // The port must be visible to the driver while it is starting, e.g. to select events
+    PORTS.lock().insert(id, state.clone());
+
+    let data = match state.driver().entry().start {
+        None => ptr::null_mut(),
+        Some(start) => unsafe { start(state.handle(), command.as_ptr() as *mut c_char) },
+    };
+    let error = if data == ERL_DRV_ERROR_GENERAL {
+        Some(OpenError::General)
+    } else if data == ERL_DRV_ERROR_ERRNO {
+        Some(OpenError::Posix(last_errno()))
+    } else if data == ERL_DRV_ERROR_BADARG {
+        Some(OpenError::BadArg)
+    } else {
+        state.data.store(data, Ordering::Release);
+        None
+    };
+    if let Some(error) = error {
+        PORTS.lock().remove(&id);
+        select::forget(id);
+        return Err(error);
+    }
+
+    reap();
+    Ok(id)
+}
+
+/// Sends `data` to `port`, as by `erlang:port_command/2`
+pub fn command(port: PortId, data: &[u8]) -> Result<(), PortError> {
+    let state = lookup(port)?;
+    let entry = state.driver().entry();
+    if let Some(outputv) = entry.outputv {
+        // The data is passed as a single driver binary, which the driver may retain
+        unsafe {
+            let mut bin = api::alloc_binary(data.len());
+            let bytes = (*bin).orig_bytes.as_mut_ptr();
+            ptr::copy_nonoverlapping(data.as_ptr(), bytes.cast(), data.len());
+            let mut iov = SysIOVec {
+                iov_base: bytes,
+                iov_len: data.len(),
+            };
+            let mut ev = ErlIOVec {
+                vsize: 1,
+                size: data.len(),
+                iov: &mut iov,
+                binv: &mut bin,
+            };
+            outputv(state.data(), &mut ev);
+            api::free_binary(bin);
+        }
+    } else if let Some(output) = entry.output {
+        unsafe { output(state.data(), data.as_ptr() as *mut c_char, data.len()) };
+    } else {
+        return Err(PortError::BadArg);
+    }
+    reap();
+    Ok(())
+}
+
+/// Performs a synchronous operation on `port`, as by `erlang:port_control/3`
+pub fn control(port: PortId, operation: c_uint, data: &[u8]) -> Result<ControlReply, PortError> {
+    let state = lookup(port)?;
+    let Some(control) = state.driver().entry().control else {
+        return Err(PortError::BadArg);
+    };
+
+    // Drivers may write small replies to this buffer, or replace it with one of their own
+    let mut buf = [0u8; 64];
+    let default = buf.as_mut_ptr() as *mut c_char;
+    let mut rbuf = default;
+    let len = unsafe {
+        control(
+            state.data(),
+            operation,
+            data.as_ptr() as *mut c_char,
+            data.len(),
+            &mut rbuf,
+            buf.len(),
+        )
+    };
+    let flags = state.control_flags.load(Ordering::Relaxed);
+    let reply = if len < 0 {
+        Err(PortError::BadArg)
+    } else if rbuf.is_null() || len == 0 {
+        Ok(Vec::new())
+    } else if rbuf == default {
+        Ok(buf[..(len as usize).min(buf.len())].to_vec())
+    } else if flags & PORT_CONTROL_FLAG_BINARY != 0 {
+        // The driver replaced the buffer with a binary allocated by `driver_alloc_binary`
+        unsafe {
+            let bin = rbuf as *mut ErlDrvBinary;
+            let bytes = (*bin).orig_bytes.as_ptr().cast::<u8>();
+            let data = core::slice::from_raw_parts(bytes, len as usize).to_vec();
+            api::free_binary(bin);
+            Ok(data)
+        }
+    } else {
+        // The driver replaced the buffer with one allocated by `driver_alloc`
+        unsafe {
+            let data = core::slice::from_raw_parts(rbuf as *const u8, len as usize).to_vec();
+            api::free(rbuf.cast());
+            Ok(data)
+        }
+    };
+    reap();
+    reply.map(|data| ControlReply {
+        data,
+        binary: flags & PORT_CONTROL_FLAG_BINARY != 0,
+    })
+}
+
+/// Closes `port`, as by `erlang:port_close/1`
+///
+/// If data remains in the driver queue, the driver is asked to `flush` it, and the port terminates
+/// once the queue is empty.
+pub fn close(port: PortId) -> Result<(), PortError> {
+    let state = lookup(port)?;
+    state.closing.store(true, Ordering::Release);
+    let flush = state.driver().entry().flush;
+    if let Some(flush) = flush {
+        if !state.queue.lock().is_empty() {
+            unsafe { flush(state.data()) };
+        }
+    }
+    reap();
+    Ok(())
+}
+
+/// Makes `owner` the owner of `port`, as by `erlang:port_connect/2`
+pub fn connect(port: PortId, owner: ProcessId) -> Result<(), PortError> {
+    let state = lookup(port)?;
+    *state.owner.lock() = owner;
+    Ok(())
+}
+
+/// Returns the earliest deadline of the timers set by drivers, if any
+pub(crate) fn next_timer() -> Option<Instant> {
+    PORTS.lock().values().filter_map(|port| port.timer()).min()
+}
+
+/// Invokes the `timeout` callback of ports whose timers have expired
+pub(crate) fn run_timers() {
+    let now = Instant::now();
+    let expired = PORTS
+        .lock()
+        .values()
+        .filter(|port| port.timer().map(|t| t <= now).unwrap_or(false))
+        .cloned()
+        .collect::<Vec<_>>();
+    for port in expired {
+        port.set_timer(None);
+        if let Some(timeout) = port.driver().entry().timeout {
+            unsafe { timeout(port.data()) };
+        }
+    }
+}
+
+/// Terminates ports which have failed, or which were closed and have an empty queue
+pub(crate) fn reap() {
+    loop {
+        let terminated = PORTS.lock().values().find_map(|port| {
+            let reason = port.exit_reason.lock().clone();
+            if reason.is_some()
+                || (port.closing.load(Ordering::Acquire) && port.queue.lock().is_empty())
+            {
+                Some((port.clone(), reason))
+            } else {
+                None
+            }
+        });
+        let Some((port, reason)) = terminated else {
+            break;
+        };
+        // The port remains visible to the driver while it is stopping, so that it can deselect
+        // events
+        port.closing.store(true, Ordering::Release);
+        if let Some(stop) = port.driver().entry().stop {
+            unsafe { stop(port.data()) };
+        }
+        PORTS.lock().remove(&port.id);
+        select::forget(port.id);
+        if let Some(reason) = reason {
+            push_event(&port, PortMessage::Exit(reason));
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::os::raw::c_char;
+
+use crate::sys::SysIOVec;
+
+/// The queue of a port, used by drivers to buffer data until it can be written to a device
+///
+/// Data is stored as a sequence of chunks, in the order in which it was enqueued. Drivers inspect
+/// the queue via `driver_peekq`, which exposes the chunks as an I/O vector suitable for `writev`,
+/// and remove data via `driver_deq` once it has been written.
+#[derive(Default)]
+pub(crate) struct DriverQueue {
+    chunks: VecDeque<Box<[u8]>>,
+    /// The number of bytes already dequeued from the front chunk
+    offset: usize,
+    /// The total number of bytes in the queue
+    size: usize,
+    /// The I/O vector last returned by `peek`, which must live until the queue is next modified
+    iov: Vec<SysIOVec>,
+}
+// The I/O vector only points into the chunks owned by the queue
+unsafe impl Send for DriverQueue {}
+impl DriverQueue {
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Appends `bytes` to the end of the queue
+    pub fn enqueue(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.chunks.push_back(bytes.into());
+        self.size += bytes.len();
+    }
+
+    /// Prepends `bytes` to the front of the queue
+    pub fn push(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        // The remainder of a partially dequeued chunk must stay in front of the new data
+        if self.offset > 0 {
+            let front = self.chunks.pop_front().unwrap();
+            self.chunks.push_front(front[self.offset..].into());
+            self.offset = 0;
+        }
+        self.chunks.push_front(bytes.into());
+        self.size += bytes.len();
+    }
+
+    /// Removes `size` bytes from the front of the queue, returning false if the queue is smaller
+    /// than that
+    pub fn dequeue(&mut self, mut size: usize) -> bool {
+        if size > self.size {
+            return false;
+        }
+        self.size -= size;
+        while size > 0 {
+            let available = self.chunks[0].len() - self.offset;
+            if size < available {
+                self.offset += size;
+                break;
+            }
+            size -= available;
+            self.offset = 0;
+            self.chunks.pop_front();
+        }
+        true
+    }
+
+    /// Returns the contents of the queue as an I/O vector
+    pub fn peek(&mut self) -> &mut [SysIOVec] {
+        self.iov.clear();
+        let offset = self.offset;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let skip = if i == 0 { offset } else { 0 };
+            self.iov.push(SysIOVec {
+                iov_base: chunk[skip..].as_ptr() as *mut c_char,
+                iov_len: chunk.len() - skip,
+            });
+        }
+        self.iov.as_mut_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(queue: &mut DriverQueue) -> Vec<u8> {
+        queue
+            .peek()
+            .iter()
+            .flat_map(|iov| unsafe {
+                core::slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len)
+            })
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn driver_queue_test() {
+        let mut queue = DriverQueue::default();
+        queue.enqueue(b"hello ");
+        queue.enqueue(b"world");
+        assert_eq!(queue.size(), 11);
+
+        assert!(queue.dequeue(3));
+        assert_eq!(contents(&mut queue), b"lo world");
+        queue.push(b"hel");
+        assert_eq!(contents(&mut queue), b"hello world");
+
+        assert!(!queue.dequeue(12));
+        assert!(queue.dequeue(11));
+        assert!(queue.is_empty());
+        assert!(queue.peek().is_empty());
+    }
+}
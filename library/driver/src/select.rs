@@ -0,0 +1,202 @@
+use std::os::raw::c_int;
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+
+use firefly_rt::term::PortId;
+use firefly_system::sync::Mutex;
+
+use crate::port::{self, PortState};
+use crate::sys::*;
+
+lazy_static! {
+    /// The events selected by drivers, by file descriptor
+    static ref SELECTED: Mutex<HashMap<c_int, Selection>> = Default::default();
+    /// The pipe written to by `wake`, whose read end is polled along with the selected events
+    static ref WAKER: Option<[c_int; 2]> = waker();
+}
+
+#[derive(Copy, Clone)]
+struct Selection {
+    port: PortId,
+    /// The combination of `ERL_DRV_READ` and `ERL_DRV_WRITE` the driver is waiting for
+    mode: c_int,
+}
+
+/// Selects or deselects `event` for `port`, as by `driver_select`
+pub(crate) fn select(port: &PortState, event: ErlDrvEvent, mode: c_int, on: bool) -> c_int {
+    let fd = event as usize as c_int;
+    let mut selected = SELECTED.lock();
+    if on {
+        let selection = selected.entry(fd).or_insert(Selection {
+            port: port.id(),
+            mode: 0,
+        });
+        if selection.port != port.id() {
+            return -1;
+        }
+        selection.mode |= mode & (ERL_DRV_READ | ERL_DRV_WRITE);
+        return 0;
+    }
+
+    let Some(selection) = selected.get_mut(&fd) else {
+        return 0;
+    };
+    if selection.port != port.id() {
+        return -1;
+    }
+    selection.mode &= !(mode & (ERL_DRV_READ | ERL_DRV_WRITE));
+    if mode & ERL_DRV_USE != 0 {
+        // The driver is done with the event, and may now close it
+        selected.remove(&fd);
+        drop(selected);
+        if mode & ERL_DRV_USE_NO_CALLBACK != ERL_DRV_USE_NO_CALLBACK {
+            if let Some(stop_select) = port.driver().entry().stop_select {
+                unsafe { stop_select(event, core::ptr::null_mut()) };
+            }
+        }
+    }
+    0
+}
+
+/// Removes all selections of `port`, called when it terminates
+pub(crate) fn forget(port: PortId) {
+    SELECTED
+        .lock()
+        .retain(|_, selection| selection.port != port);
+}
+
+/// Returns true if any port is open, in which case the runtime should keep calling `poll`
+pub fn is_active() -> bool {
+    !port::ports().is_empty()
+}
+
+/// Wakes up the thread waiting in `poll`, if any, or else has the next call return immediately
+///
+/// This may be called from any thread, e.g. the thread handling signals, so that the runtime can
+/// observe a request to shut down without waiting for the next event of a port.
+pub fn wake() {
+    if let Some([_, write]) = *WAKER {
+        // The pipe being full means a wake up is already pending
+        unsafe { libc::write(write, [1u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// Waits up to `timeout` for selected events to become ready, or for the timer of a port to
+/// expire, invoking the corresponding driver callbacks
+///
+/// If `timeout` is `None`, waits until at least one event is ready or timer expires. Messages
+/// produced by the callbacks are queued for delivery, see `drain_events`.
+///
+/// Returns false without waiting if there is nothing to wait for, i.e. `timeout` is `None`, and no
+/// port has selected an event or set a timer.
+pub fn poll(timeout: Option<Duration>) -> bool {
+    let timeout = match (timeout, port::next_timer()) {
+        (None, None) => None,
+        (timeout, None) => timeout,
+        (timeout, Some(deadline)) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            Some(timeout.map(|t| t.min(remaining)).unwrap_or(remaining))
+        }
+    };
+
+    let mut fds = SELECTED
+        .lock()
+        .iter()
+        .filter(|(_, selection)| selection.mode != 0)
+        .map(|(fd, selection)| libc::pollfd {
+            fd: *fd,
+            events: event_mask(selection.mode),
+            revents: 0,
+        })
+        .collect::<Vec<_>>();
+    let timeout = timeout
+        .map(|t| t.as_millis().min(c_int::MAX as u128) as c_int)
+        .unwrap_or(-1);
+    if fds.is_empty() && timeout < 0 {
+        // Nothing can ever become ready, so waiting would block forever
+        return false;
+    }
+    if let Some([read, _]) = *WAKER {
+        fds.push(libc::pollfd {
+            fd: read,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+    }
+
+    let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+    if ready > 0 {
+        for pollfd in fds.iter().filter(|pollfd| pollfd.revents != 0) {
+            match *WAKER {
+                Some([read, _]) if pollfd.fd == read => drain_waker(read),
+                _ => dispatch(pollfd.fd, pollfd.revents),
+            }
+        }
+    }
+
+    port::run_timers();
+    port::reap();
+    true
+}
+
+/// Creates the pipe used by `wake`, both ends of which are non-blocking, or `None` if it cannot be
+fn waker() -> Option<[c_int; 2]> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    for fd in fds {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+    Some(fds)
+}
+
+/// Consumes the pending wake ups, so that the next `poll` waits again
+fn drain_waker(read: c_int) {
+    let mut buf = [0u8; 64];
+    while unsafe { libc::read(read, buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+}
+
+fn event_mask(mode: c_int) -> libc::c_short {
+    let mut events = 0;
+    if mode & ERL_DRV_READ != 0 {
+        events |= libc::POLLIN;
+    }
+    if mode & ERL_DRV_WRITE != 0 {
+        events |= libc::POLLOUT;
+    }
+    events
+}
+
+fn dispatch(fd: c_int, revents: libc::c_short) {
+    let event = fd as usize as ErlDrvEvent;
+    // Errors and hangups are reported as readiness, so that the driver observes them when it
+    // next reads from, or writes to, the event
+    let failed = revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0;
+    for (mask, mode) in [(libc::POLLIN, ERL_DRV_READ), (libc::POLLOUT, ERL_DRV_WRITE)] {
+        // The selection is checked before each callback, as the previous one may have changed it
+        let Some(selection) = SELECTED.lock().get(&fd).copied() else {
+            return;
+        };
+        if selection.mode & mode == 0 || (revents & mask == 0 && !failed) {
+            continue;
+        }
+        let Some(port) = port::get(selection.port) else {
+            return;
+        };
+        let entry = port.driver().entry();
+        let callback = if mode == ERL_DRV_READ {
+            entry.ready_input
+        } else {
+            entry.ready_output
+        };
+        if let Some(callback) = callback {
+            unsafe { callback(port.data(), event) };
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! The C ABI of the port driver interface, mirroring the subset of `erl_driver.h` we support.
+//!
+//! As with `firefly_nif::sys`, the names and layouts in this module intentionally match those of
+//! `erl_driver.h`, so that drivers written against it can be built for Firefly without changes.
+#![allow(non_camel_case_types)]
+
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_void};
+
+/// The marker which identifies an extended driver entry, drivers without it are rejected
+pub const ERL_DRV_EXTENDED_MARKER: c_int = 0xfeeeeeed_u32 as c_int;
+/// The major version of the interface, drivers built against a different major version are rejected
+pub const ERL_DRV_EXTENDED_MAJOR_VERSION: c_int = 3;
+/// The minor version of the interface, drivers built against a newer minor version are rejected
+pub const ERL_DRV_EXTENDED_MINOR_VERSION: c_int = 3;
+
+/// Flags accepted in `ErlDrvEntry::driver_flags`
+///
+/// Ports are only ever accessed by the scheduler which owns them, so the locking and busy port
+/// flags have no effect, they are accepted for compatibility.
+pub const ERL_DRV_FLAG_USE_PORT_LOCKING: c_int = 1 << 0;
+pub const ERL_DRV_FLAG_SOFT_BUSY: c_int = 1 << 1;
+pub const ERL_DRV_FLAG_NO_BUSY_MSGQ: c_int = 1 << 2;
+pub const ERL_DRV_FLAG_USE_INIT_ACK: c_int = 1 << 3;
+
+/// Modes accepted by `driver_select`
+pub const ERL_DRV_READ: c_int = 1 << 0;
+pub const ERL_DRV_WRITE: c_int = 1 << 1;
+pub const ERL_DRV_USE: c_int = 1 << 2;
+pub const ERL_DRV_USE_NO_CALLBACK: c_int = ERL_DRV_USE | (1 << 3);
+
+/// Flags accepted by `set_port_control_flags`
+pub const PORT_CONTROL_FLAG_BINARY: c_int = 1 << 0;
+pub const PORT_CONTROL_FLAG_HEAVY: c_int = 1 << 1;
+
+pub type ErlDrvSizeT = usize;
+pub type ErlDrvSSizeT = isize;
+pub type ErlDrvSint = c_long;
+pub type ErlDrvUInt = usize;
+
+/// The state of a port, as returned by the `start` callback of its driver
+pub type ErlDrvData = *mut c_void;
+
+/// Returned by the `start` callback to fail with `einval`
+pub const ERL_DRV_ERROR_GENERAL: ErlDrvData = -1isize as ErlDrvData;
+/// Returned by the `start` callback to fail with the reason given by `errno`
+pub const ERL_DRV_ERROR_ERRNO: ErlDrvData = -2isize as ErlDrvData;
+/// Returned by the `start` callback to fail with `badarg`
+pub const ERL_DRV_ERROR_BADARG: ErlDrvData = -3isize as ErlDrvData;
+
+/// A handle to a port, passed to drivers as an opaque pointer
+///
+/// The value of the pointer is the identifier of the port, see `crate::port`.
+#[repr(C)]
+pub struct ErlDrvPortOpaque {
+    _private: [u8; 0],
+}
+pub type ErlDrvPort = *mut ErlDrvPortOpaque;
+
+/// An event selected via `driver_select`, on unix this is a file descriptor
+pub type ErlDrvEvent = *mut c_void;
+
+pub type ErlDrvThreadData = *mut c_void;
+
+/// Monitors are not supported, this type exists so that `ErlDrvEntry` has the correct layout
+#[repr(C)]
+pub struct ErlDrvMonitor {
+    pub data: [u8; 32],
+}
+
+/// A reference-counted binary allocated via `driver_alloc_binary`
+///
+/// The reference count is stored in a header preceding this struct, see `crate::api`.
+#[repr(C)]
+pub struct ErlDrvBinary {
+    pub orig_size: ErlDrvSint,
+    pub orig_bytes: [c_char; 1],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SysIOVec {
+    pub iov_base: *mut c_char,
+    pub iov_len: usize,
+}
+
+#[repr(C)]
+pub struct ErlIOVec {
+    pub vsize: c_int,
+    pub size: ErlDrvSizeT,
+    pub iov: *mut SysIOVec,
+    pub binv: *mut *mut ErlDrvBinary,
+}
+
+/// The description of a driver, as returned by its `driver_init` function
+#[repr(C)]
+pub struct ErlDrvEntry {
+    pub init: Option<unsafe extern "C" fn() -> c_int>,
+    pub start: Option<unsafe extern "C" fn(port: ErlDrvPort, command: *mut c_char) -> ErlDrvData>,
+    pub stop: Option<unsafe extern "C" fn(data: ErlDrvData)>,
+    pub output: Option<unsafe extern "C" fn(data: ErlDrvData, buf: *mut c_char, len: ErlDrvSizeT)>,
+    pub ready_input: Option<unsafe extern "C" fn(data: ErlDrvData, event: ErlDrvEvent)>,
+    pub ready_output: Option<unsafe extern "C" fn(data: ErlDrvData, event: ErlDrvEvent)>,
+    pub driver_name: *mut c_char,
+    pub finish: Option<unsafe extern "C" fn()>,
+    /// Reserved for use by the runtime
+    pub handle: *mut c_void,
+    pub control: Option<
+        unsafe extern "C" fn(
+            data: ErlDrvData,
+            command: c_uint,
+            buf: *mut c_char,
+            len: ErlDrvSizeT,
+            rbuf: *mut *mut c_char,
+            rlen: ErlDrvSizeT,
+        ) -> ErlDrvSSizeT,
+    >,
+    pub timeout: Option<unsafe extern "C" fn(data: ErlDrvData)>,
+    pub outputv: Option<unsafe extern "C" fn(data: ErlDrvData, ev: *mut ErlIOVec)>,
+    /// Async threads are not supported, so this is never called
+    pub ready_async: Option<unsafe extern "C" fn(data: ErlDrvData, thread_data: ErlDrvThreadData)>,
+    pub flush: Option<unsafe extern "C" fn(data: ErlDrvData)>,
+    /// `erlang:port_call/3` is not supported, so this is never called
+    pub call: Option<
+        unsafe extern "C" fn(
+            data: ErlDrvData,
+            command: c_uint,
+            buf: *mut c_char,
+            len: ErlDrvSizeT,
+            rbuf: *mut *mut c_char,
+            rlen: ErlDrvSizeT,
+            flags: *mut c_uint,
+        ) -> ErlDrvSSizeT,
+    >,
+    /// Deprecated upstream, and never called
+    pub unused_event_callback: *mut c_void,
+    pub extended_marker: c_int,
+    pub major_version: c_int,
+    pub minor_version: c_int,
+    pub driver_flags: c_int,
+    /// Reserved for use by the runtime
+    pub handle2: *mut c_void,
+    /// Monitors are not supported, so this is never called
+    pub process_exit: Option<unsafe extern "C" fn(data: ErlDrvData, monitor: *mut ErlDrvMonitor)>,
+    pub stop_select: Option<unsafe extern "C" fn(event: ErlDrvEvent, reserved: *mut c_void)>,
+    /// Called instead of `stop` when the port is closed because the runtime is shutting down
+    pub emergency_close: Option<unsafe extern "C" fn(data: ErlDrvData)>,
+}
+
+/// The signature of the function every dynamically loaded driver exports to describe itself
+pub type DriverInitFn = unsafe extern "C" fn() -> *mut ErlDrvEntry;
+
+/// The name of the symbol every dynamically loaded driver exports to describe itself
+pub const DRIVER_INIT_SYMBOL: &str = "driver_init";
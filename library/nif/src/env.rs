@@ -0,0 +1,94 @@
+use std::os::raw::c_void;
+
+use firefly_rt::process::Process;
+use firefly_rt::term::OpaqueTerm;
+
+use crate::sys::{ErlNifEnv, NifFn};
+
+/// The environment in which a native function executes
+///
+/// An environment is bound to the process calling the native function, and terms constructed
+/// through it are allocated on that process' heap. It also carries the side effects of the call
+/// which are acted on once the native function returns, i.e. raising an exception, or rescheduling
+/// via `enif_schedule_nif`.
+///
+/// Environments are handed to native code as an opaque `*mut ErlNifEnv`.
+pub struct Env<'a> {
+    process: &'a Process,
+    priv_data: *mut c_void,
+    exception: Option<OpaqueTerm>,
+    scheduled: Option<Scheduled>,
+}
+
+/// A native function call requested via `enif_schedule_nif`
+pub(crate) struct Scheduled {
+    pub fptr: NifFn,
+    pub args: Vec<OpaqueTerm>,
+}
+
+impl<'a> Env<'a> {
+    pub fn new(process: &'a Process, priv_data: *mut c_void) -> Self {
+        Self {
+            process,
+            priv_data,
+            exception: None,
+            scheduled: None,
+        }
+    }
+
+    /// Returns the process on whose behalf native code is executing
+    #[inline]
+    pub fn process(&self) -> &'a Process {
+        self.process
+    }
+
+    /// Returns the private data of the library the executing function belongs to
+    #[inline]
+    pub fn priv_data(&self) -> *mut c_void {
+        self.priv_data
+    }
+
+    #[inline]
+    pub(crate) fn set_priv_data(&mut self, priv_data: *mut c_void) {
+        self.priv_data = priv_data;
+    }
+
+    /// Returns the exception raised by native code, if one is pending
+    #[inline]
+    pub fn exception(&self) -> Option<OpaqueTerm> {
+        self.exception
+    }
+
+    /// Raises an `error` exception with `reason` once the executing function returns
+    ///
+    /// Only the first exception raised during a call is kept, as with `erl_nif`.
+    pub(crate) fn raise(&mut self, reason: OpaqueTerm) {
+        if self.exception.is_none() {
+            self.exception = Some(reason);
+        }
+    }
+
+    pub(crate) fn schedule(&mut self, scheduled: Scheduled) {
+        self.scheduled = Some(scheduled);
+    }
+
+    pub(crate) fn take_scheduled(&mut self) -> Option<Scheduled> {
+        self.scheduled.take()
+    }
+
+    #[inline]
+    pub fn as_raw(&mut self) -> *mut ErlNifEnv {
+        self as *mut Self as *mut ErlNifEnv
+    }
+
+    /// Recovers the environment from the pointer handed to native code
+    ///
+    /// # Safety
+    ///
+    /// The pointer must have been obtained from `Env::as_raw`, and the environment must still be
+    /// live.
+    #[inline]
+    pub(crate) unsafe fn from_raw<'b>(env: *mut ErlNifEnv) -> &'b mut Env<'a> {
+        &mut *(env as *mut Env<'a>)
+    }
+}
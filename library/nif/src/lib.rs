@@ -0,0 +1,36 @@
+//! This crate provides an implementation of the `erl_nif` interface, for loading native
+//! functions from shared libraries at runtime via `erlang:load_nif/2`.
+//!
+//! Only a subset of the interface is supported, but it is sufficient for most libraries which
+//! construct and inspect terms, use resources, and reschedule long-running work, which covers the
+//! libraries generated by Rustler. Such libraries only need to be rebuilt against Firefly's
+//! `erl_nif.h` (or its bindings), as the names and layouts in `sys` match those of BEAM.
+//!
+//! Since calls in compiled code are resolved statically, functions which may be implemented
+//! natively are dispatched at runtime: the compiler rewrites each function declared in a
+//! `-nifs` attribute to check whether a native implementation has been loaded, calling it via
+//! `erlang:apply_nif/3` if so, and falling back to its Erlang definition otherwise. A library
+//! loaded by a module which declares no such functions replaces none of them.
+//!
+//! The `enif_*` symbols are defined by this crate, and exported from the executable so that
+//! libraries opened with `dlopen` can resolve them.
+//!
+//! For fully static executables, native functions can instead be linked in ahead-of-time, on unix
+//! targets: the [`nif`] attribute macro exports a Rust function under the symbol name of the Erlang
+//! function it implements, so that calls from compiled code resolve to it at link time, and
+//! registers it with the runtime's dispatch table at startup, so that it can also be applied
+//! dynamically.
+#![feature(let_else)]
+
+mod env;
+mod library;
+mod resource;
+pub mod sys;
+mod terms;
+
+pub use self::env::Env;
+pub use self::library::{is_loaded, load, lookup, LoadError, Nif};
+pub use self::resource::ResourceType;
+
+pub use firefly_nif_macros::nif;
+pub use firefly_rt::function::StaticNif;
@@ -0,0 +1,287 @@
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::{c_int, c_uint, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+
+use firefly_rt::function::ModuleFunctionArity;
+use firefly_rt::process::Process;
+use firefly_rt::term::{Atom, OpaqueTerm};
+use firefly_system::sync::RwLock;
+
+use crate::env::Env;
+use crate::sys::*;
+
+lazy_static! {
+    /// The libraries loaded by `erlang:load_nif/2`, and the functions they implement
+    static ref REGISTRY: RwLock<Registry> = Default::default();
+}
+
+#[derive(Default)]
+struct Registry {
+    libraries: HashMap<Atom, Arc<Library>>,
+    functions: HashMap<ModuleFunctionArity, Nif>,
+}
+
+/// A shared library loaded on behalf of a module
+///
+/// Libraries are never closed, as we have no way of knowing whether terms or resources
+/// referencing code in the library are still live.
+struct Library {
+    entry: *const ErlNifEntry,
+    priv_data: AtomicPtr<c_void>,
+}
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+/// A native function implemented by a loaded library
+#[derive(Clone)]
+pub struct Nif {
+    mfa: ModuleFunctionArity,
+    fptr: NifFn,
+    flags: c_uint,
+    library: Arc<Library>,
+}
+impl Nif {
+    #[inline]
+    pub fn mfa(&self) -> ModuleFunctionArity {
+        self.mfa
+    }
+
+    /// Returns true if this function was declared as a dirty NIF
+    ///
+    /// Dirty NIFs are executed on the calling scheduler, as there are no dirty schedulers to hand
+    /// them off to.
+    #[inline]
+    pub fn is_dirty(&self) -> bool {
+        self.flags & (ERL_NIF_DIRTY_JOB_CPU_BOUND | ERL_NIF_DIRTY_JOB_IO_BOUND) != 0
+    }
+
+    /// Calls this function on behalf of `process` with the given arguments
+    ///
+    /// If the function raises an exception, `Err` is returned with the reason of the `error` to
+    /// raise.
+    ///
+    /// Calls rescheduled via `enif_schedule_nif` are run as soon as the calling function returns,
+    /// so the result is always that of the last function in the chain.
+    pub fn call(&self, process: &Process, args: &[OpaqueTerm]) -> Result<OpaqueTerm, OpaqueTerm> {
+        let priv_data = self.library.priv_data.load(Ordering::Acquire);
+        let mut env = Env::new(process, priv_data);
+        let mut fptr = self.fptr;
+        let mut args = args.to_vec();
+        loop {
+            let result = unsafe { fptr(env.as_raw(), args.len() as c_int, args.as_ptr()) };
+            if let Some(reason) = env.exception() {
+                return Err(reason);
+            }
+            match env.take_scheduled() {
+                None => return Ok(result),
+                Some(scheduled) => {
+                    fptr = scheduled.fptr;
+                    args = scheduled.args;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the native function which implements `mfa`, if one has been loaded
+pub fn lookup(mfa: &ModuleFunctionArity) -> Option<Nif> {
+    REGISTRY.read().functions.get(mfa).cloned()
+}
+
+/// Returns true if `mfa` is implemented by a loaded native function
+pub fn is_loaded(mfa: &ModuleFunctionArity) -> bool {
+    REGISTRY.read().functions.contains_key(mfa)
+}
+
+/// The reasons `load` may fail, these correspond to the error reasons of `erlang:load_nif/2`
+#[derive(Debug)]
+pub enum LoadError {
+    /// The library could not be opened
+    LoadFailed(String),
+    /// The library is not a valid NIF library for the calling module
+    BadLib(String),
+    /// The load callback of the library failed
+    Load(String),
+    /// A library was already loaded for the calling module, and the upgrade failed
+    Upgrade(String),
+}
+impl LoadError {
+    /// Returns the reason atom of the `{error, {Reason, Text}}` tuple returned by
+    /// `erlang:load_nif/2`
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::LoadFailed(_) => "load_failed",
+            Self::BadLib(_) => "bad_lib",
+            Self::Load(_) => "load",
+            Self::Upgrade(_) => "upgrade",
+        }
+    }
+
+    /// Returns the human-readable description of this error
+    pub fn text(&self) -> &str {
+        match self {
+            Self::LoadFailed(text)
+            | Self::BadLib(text)
+            | Self::Load(text)
+            | Self::Upgrade(text) => text.as_str(),
+        }
+    }
+}
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.text())
+    }
+}
+
+/// Loads the native library at `path` on behalf of `module`, as by `erlang:load_nif/2`
+///
+/// As with BEAM, `path` is given without the platform-specific file extension. Once loaded, the
+/// functions implemented by the library replace their Erlang definitions in `module`.
+pub fn load(
+    process: &Process,
+    module: Atom,
+    path: &str,
+    load_info: OpaqueTerm,
+) -> Result<(), LoadError> {
+    let filename = format!("{}{}", path, std::env::consts::DLL_SUFFIX);
+    let handle = open(filename.as_str())?;
+
+    let init = unsafe { symbol(handle, NIF_INIT_SYMBOL) };
+    if init.is_null() {
+        return Err(LoadError::BadLib(format!(
+            "Failed to find library init function: '{}'",
+            filename
+        )));
+    }
+    let init = unsafe { core::mem::transmute::<*mut c_void, NifInitFn>(init) };
+    let entry = unsafe { init() };
+    if entry.is_null() {
+        return Err(LoadError::BadLib(
+            "Library init-call unsuccessful".to_string(),
+        ));
+    }
+    let e = unsafe { &*entry };
+    if e.major != ERL_NIF_MAJOR_VERSION || e.minor > ERL_NIF_MINOR_VERSION {
+        return Err(LoadError::BadLib(format!(
+            "Library version ({}.{}) not compatible (with {}.{}).",
+            e.major, e.minor, ERL_NIF_MAJOR_VERSION, ERL_NIF_MINOR_VERSION
+        )));
+    }
+    let name = unsafe { CStr::from_ptr(e.name) }.to_string_lossy();
+    if name != module.as_str() {
+        return Err(LoadError::BadLib(format!(
+            "Library module name '{}' does not match calling module '{}'",
+            name, module
+        )));
+    }
+
+    // Resolve the functions implemented by the library before calling into it
+    let mut functions = Vec::with_capacity(e.num_of_funcs as usize);
+    for i in 0..(e.num_of_funcs as usize) {
+        let func = unsafe { &*e.funcs.add(i) };
+        let name = unsafe { CStr::from_ptr(func.name) };
+        let Some(function) = name
+            .to_str()
+            .ok()
+            .and_then(|name| Atom::try_from(name).ok())
+        else {
+            return Err(LoadError::BadLib(format!(
+                "Function not found {}:{}/{}",
+                module,
+                name.to_string_lossy(),
+                func.arity
+            )));
+        };
+        let mfa = ModuleFunctionArity::new(module, function, func.arity as usize);
+        functions.push((mfa, func.fptr, func.flags));
+    }
+
+    let mut registry = REGISTRY.write();
+    let mut env = Env::new(process, ptr::null_mut());
+    let mut priv_data = ptr::null_mut();
+    match registry.libraries.get(&module) {
+        None => {
+            if let Some(load) = e.load {
+                let result = unsafe { load(env.as_raw(), &mut priv_data, load_info) };
+                if result != 0 {
+                    return Err(LoadError::Load(format!(
+                        "Library load-call unsuccessful ({}).",
+                        result
+                    )));
+                }
+            }
+        }
+        Some(old) => {
+            let Some(upgrade) = e.upgrade else {
+                return Err(LoadError::Upgrade(
+                    "Upgrade not supported by this NIF library.".to_string(),
+                ));
+            };
+            let mut old_priv_data = old.priv_data.load(Ordering::Acquire);
+            env.set_priv_data(old_priv_data);
+            let result =
+                unsafe { upgrade(env.as_raw(), &mut priv_data, &mut old_priv_data, load_info) };
+            if result != 0 {
+                return Err(LoadError::Upgrade(format!(
+                    "Library upgrade-call unsuccessful ({}).",
+                    result
+                )));
+            }
+            // The old library is superseded, give it a chance to clean up its private data
+            if let Some(unload) = unsafe { (*old.entry).unload } {
+                env.set_priv_data(old_priv_data);
+                unsafe { unload(env.as_raw(), old_priv_data) };
+            }
+        }
+    }
+
+    let library = Arc::new(Library {
+        entry,
+        priv_data: AtomicPtr::new(priv_data),
+    });
+    registry.functions.retain(|mfa, _| mfa.module != module);
+    for (mfa, fptr, flags) in functions {
+        registry.functions.insert(
+            mfa,
+            Nif {
+                mfa,
+                fptr,
+                flags,
+                library: library.clone(),
+            },
+        );
+    }
+    registry.libraries.insert(module, library);
+
+    Ok(())
+}
+
+fn open(filename: &str) -> Result<*mut c_void, LoadError> {
+    let Ok(cfilename) = CString::new(filename) else {
+        return Err(LoadError::LoadFailed(format!(
+            "Invalid library path '{}'",
+            filename
+        )));
+    };
+    let handle = unsafe { libc::dlopen(cfilename.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        let reason = unsafe { CStr::from_ptr(libc::dlerror()) };
+        return Err(LoadError::LoadFailed(format!(
+            "Failed to load NIF library {}: '{}'",
+            filename,
+            reason.to_string_lossy()
+        )));
+    }
+    Ok(handle)
+}
+
+unsafe fn symbol(handle: *mut c_void, name: &str) -> *mut c_void {
+    let name = CString::new(name).unwrap();
+    libc::dlsym(handle, name.as_ptr())
+}
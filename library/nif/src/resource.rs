@@ -0,0 +1,264 @@
+use std::alloc::{self, Layout};
+use std::any::Any;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::term::{OpaqueTerm, Reference, ReferenceId, Term};
+use firefly_system::sync::{Mutex, RwLock};
+
+use crate::env::Env;
+use crate::sys::*;
+
+lazy_static! {
+    /// Resource types are never deallocated, so they are leaked and referenced by name here
+    static ref RESOURCE_TYPES: Mutex<HashMap<String, &'static ResourceType>> = Default::default();
+}
+
+/// The scheduler id used in the identifiers of references to resources
+///
+/// The remaining bits of the identifier are the address of the resource, so that all terms
+/// referring to the same resource compare equal.
+const RESOURCE_REFERENCE_SCHEDULER_ID: u16 = u16::MAX;
+
+/// Resource data is aligned as if allocated by `malloc`
+const RESOURCE_ALIGN: usize = 16;
+
+/// A type of resource, as created by `enif_open_resource_type`
+pub struct ResourceType {
+    name: String,
+    dtor: RwLock<Option<ErlNifResourceDtor>>,
+}
+impl ResourceType {
+    #[inline]
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn as_raw(&'static self) -> *mut ErlNifResourceType {
+        self as *const Self as *mut ErlNifResourceType
+    }
+
+    unsafe fn from_raw(ty: *const ErlNifResourceType) -> &'static Self {
+        &*(ty as *const Self)
+    }
+}
+
+/// The header preceding the data of every resource
+///
+/// Resources are reference counted, native code holds references via `enif_keep_resource`, and
+/// every term referring to the resource holds one as well. When the last reference is released,
+/// the destructor of its type is called, and the resource is deallocated.
+#[repr(C)]
+struct ResourceHeader {
+    ty: &'static ResourceType,
+    refc: AtomicUsize,
+    size: usize,
+}
+impl ResourceHeader {
+    const DATA_OFFSET: usize =
+        (core::mem::size_of::<Self>() + RESOURCE_ALIGN - 1) & !(RESOURCE_ALIGN - 1);
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(Self::DATA_OFFSET + size, RESOURCE_ALIGN).unwrap()
+    }
+
+    #[inline]
+    fn data(header: NonNull<Self>) -> *mut c_void {
+        unsafe { header.as_ptr().cast::<u8>().add(Self::DATA_OFFSET).cast() }
+    }
+
+    #[inline]
+    unsafe fn from_data(data: *const c_void) -> NonNull<Self> {
+        NonNull::new_unchecked(data.cast::<u8>().sub(Self::DATA_OFFSET) as *mut Self)
+    }
+
+    unsafe fn keep(header: NonNull<Self>) {
+        header.as_ref().refc.fetch_add(1, Ordering::Relaxed);
+    }
+
+    unsafe fn release(header: NonNull<Self>) {
+        if header.as_ref().refc.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+        let ty = header.as_ref().ty;
+        let size = header.as_ref().size;
+        if let Some(dtor) = *ty.dtor.read() {
+            dtor(ptr::null_mut(), Self::data(header));
+        }
+        alloc::dealloc(header.as_ptr().cast(), Self::layout(size));
+    }
+}
+
+/// The value referenced by the magic reference representing a resource in Erlang
+///
+/// Each handle holds a reference to the resource, released when the handle is dropped.
+struct ResourceHandle(NonNull<ResourceHeader>);
+impl Drop for ResourceHandle {
+    fn drop(&mut self) {
+        unsafe { ResourceHeader::release(self.0) }
+    }
+}
+
+#[export_name = "enif_open_resource_type"]
+pub unsafe extern "C" fn open_resource_type(
+    _env: *mut ErlNifEnv,
+    _module_str: *const c_char,
+    name: *const c_char,
+    dtor: Option<ErlNifResourceDtor>,
+    flags: ErlNifResourceFlags,
+    tried: *mut ErlNifResourceFlags,
+) -> *mut ErlNifResourceType {
+    let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+    let mut types = RESOURCE_TYPES.lock();
+    let (ty, result) = match types.get(&name).copied() {
+        None if flags & ERL_NIF_RT_CREATE != 0 => {
+            let ty: &'static ResourceType = Box::leak(Box::new(ResourceType {
+                name: name.clone(),
+                dtor: RwLock::new(dtor),
+            }));
+            types.insert(name, ty);
+            (ty.as_raw(), ERL_NIF_RT_CREATE)
+        }
+        Some(ty) if flags & ERL_NIF_RT_TAKEOVER != 0 => {
+            *ty.dtor.write() = dtor;
+            (ty.as_raw(), ERL_NIF_RT_TAKEOVER)
+        }
+        _ => (ptr::null_mut(), 0),
+    };
+    if !tried.is_null() {
+        *tried = result;
+    }
+    ty
+}
+
+#[export_name = "enif_alloc_resource"]
+pub unsafe extern "C" fn alloc_resource(ty: *const ErlNifResourceType, size: usize) -> *mut c_void {
+    let layout = ResourceHeader::layout(size);
+    let Some(header) = NonNull::new(alloc::alloc(layout).cast::<ResourceHeader>()) else {
+        alloc::handle_alloc_error(layout);
+    };
+    header.as_ptr().write(ResourceHeader {
+        ty: ResourceType::from_raw(ty),
+        refc: AtomicUsize::new(1),
+        size,
+    });
+    ResourceHeader::data(header)
+}
+
+#[export_name = "enif_keep_resource"]
+pub unsafe extern "C" fn keep_resource(obj: *mut c_void) {
+    ResourceHeader::keep(ResourceHeader::from_data(obj));
+}
+
+#[export_name = "enif_release_resource"]
+pub unsafe extern "C" fn release_resource(obj: *mut c_void) {
+    ResourceHeader::release(ResourceHeader::from_data(obj));
+}
+
+#[export_name = "enif_make_resource"]
+pub unsafe extern "C" fn make_resource(env: *mut ErlNifEnv, obj: *mut c_void) -> ERL_NIF_TERM {
+    let env = Env::from_raw(env);
+    let process = env.process();
+    let header = ResourceHeader::from_data(obj);
+    ResourceHeader::keep(header);
+    let id = ReferenceId::new(RESOURCE_REFERENCE_SCHEDULER_ID, header.as_ptr() as u64);
+    let handle = GcBox::<dyn Any>::new_unsize_in(ResourceHandle(header), process).unwrap();
+    let reference = GcBox::new_in(Reference::new_magic(id, handle), process).unwrap();
+    Term::Reference(reference).into()
+}
+
+#[export_name = "enif_get_resource"]
+pub unsafe extern "C" fn get_resource(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    ty: *const ErlNifResourceType,
+    objp: *mut *mut c_void,
+) -> c_int {
+    match resource_of(term) {
+        Some(header) if ptr::eq(header.as_ref().ty, ResourceType::from_raw(ty)) => {
+            *objp = ResourceHeader::data(header);
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// Returns the resource referred to by `term`, if it refers to one
+unsafe fn resource_of(term: OpaqueTerm) -> Option<NonNull<ResourceHeader>> {
+    let Term::Reference(reference) = term.into() else {
+        return None;
+    };
+    let handle = reference.magic()?.downcast_ref::<ResourceHandle>()?;
+    Some(handle.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    static DESTROYED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn dtor(_env: *mut ErlNifEnv, obj: *mut c_void) {
+        assert_eq!(*obj.cast::<u64>(), 42);
+        DESTROYED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn resource_lifecycle() {
+        let name = CString::new("resource_lifecycle").unwrap();
+        let mut tried = 0;
+        unsafe {
+            let ty = open_resource_type(
+                ptr::null_mut(),
+                ptr::null(),
+                name.as_ptr(),
+                Some(dtor),
+                ERL_NIF_RT_CREATE,
+                &mut tried,
+            );
+            assert!(!ty.is_null());
+            assert_eq!(tried, ERL_NIF_RT_CREATE);
+
+            // Creating the same type again fails, but it can be taken over
+            let again = open_resource_type(
+                ptr::null_mut(),
+                ptr::null(),
+                name.as_ptr(),
+                Some(dtor),
+                ERL_NIF_RT_CREATE,
+                &mut tried,
+            );
+            assert!(again.is_null());
+            let taken = open_resource_type(
+                ptr::null_mut(),
+                ptr::null(),
+                name.as_ptr(),
+                Some(dtor),
+                ERL_NIF_RT_TAKEOVER,
+                &mut tried,
+            );
+            assert_eq!(taken, ty);
+            assert_eq!(tried, ERL_NIF_RT_TAKEOVER);
+
+            let obj = alloc_resource(ty, 8);
+            assert_eq!(obj as usize % RESOURCE_ALIGN, 0);
+            obj.cast::<u64>().write(42);
+
+            keep_resource(obj);
+            release_resource(obj);
+            assert_eq!(DESTROYED.load(Ordering::SeqCst), 0);
+            release_resource(obj);
+            assert_eq!(DESTROYED.load(Ordering::SeqCst), 1);
+        }
+    }
+}
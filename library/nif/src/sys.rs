@@ -0,0 +1,118 @@
+//! The C ABI of the native interface, mirroring the subset of `erl_nif.h` we support.
+//!
+//! The names and layouts in this module intentionally match those of `erl_nif.h`, so that
+//! libraries written against it (or bindings generated from it, as used by Rustler) can be built
+//! for Firefly without changes to their source.
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+
+use firefly_rt::term::OpaqueTerm;
+
+/// The major version of the interface, libraries built against a different major version are
+/// rejected
+pub const ERL_NIF_MAJOR_VERSION: c_int = 2;
+/// The minor version of the interface, libraries built against a newer minor version are rejected
+pub const ERL_NIF_MINOR_VERSION: c_int = 16;
+
+/// The value of `ErlNifEntry::vm_variant` for libraries built against the standard `erl_nif.h`
+pub const ERL_NIF_VM_VARIANT: &str = "beam.vanilla";
+
+/// Flags accepted in `ErlNifFunc::flags` and by `enif_schedule_nif`
+pub const ERL_NIF_DIRTY_JOB_CPU_BOUND: c_uint = 1;
+pub const ERL_NIF_DIRTY_JOB_IO_BOUND: c_uint = 2;
+
+/// Terms are passed across the interface in their native representation
+pub type ERL_NIF_TERM = OpaqueTerm;
+
+/// The environment passed to every native function, see `Env`
+#[repr(C)]
+pub struct ErlNifEnv {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ErlNifPid {
+    pub pid: ERL_NIF_TERM,
+}
+
+pub type NifFn = unsafe extern "C" fn(
+    env: *mut ErlNifEnv,
+    argc: c_int,
+    argv: *const ERL_NIF_TERM,
+) -> ERL_NIF_TERM;
+
+#[repr(C)]
+pub struct ErlNifFunc {
+    pub name: *const c_char,
+    pub arity: c_uint,
+    pub fptr: NifFn,
+    pub flags: c_uint,
+}
+
+pub type LoadFn = unsafe extern "C" fn(
+    env: *mut ErlNifEnv,
+    priv_data: *mut *mut c_void,
+    load_info: ERL_NIF_TERM,
+) -> c_int;
+
+pub type UpgradeFn = unsafe extern "C" fn(
+    env: *mut ErlNifEnv,
+    priv_data: *mut *mut c_void,
+    old_priv_data: *mut *mut c_void,
+    load_info: ERL_NIF_TERM,
+) -> c_int;
+
+pub type UnloadFn = unsafe extern "C" fn(env: *mut ErlNifEnv, priv_data: *mut c_void);
+
+/// The description of a library, as returned by its `nif_init` function
+#[repr(C)]
+pub struct ErlNifEntry {
+    pub major: c_int,
+    pub minor: c_int,
+    pub name: *const c_char,
+    pub num_of_funcs: c_int,
+    pub funcs: *const ErlNifFunc,
+    pub load: Option<LoadFn>,
+    /// Deprecated upstream, and never called
+    pub reload: Option<LoadFn>,
+    pub upgrade: Option<UpgradeFn>,
+    pub unload: Option<UnloadFn>,
+    pub vm_variant: *const c_char,
+    pub options: c_uint,
+    pub sizeof_ErlNifResourceTypeInit: usize,
+    pub min_erts: *const c_char,
+}
+
+/// The signature of the function every library exports to describe itself
+pub type NifInitFn = unsafe extern "C" fn() -> *const ErlNifEntry;
+
+/// The name of the symbol every library exports to describe itself
+pub const NIF_INIT_SYMBOL: &str = "nif_init";
+
+#[repr(C)]
+pub struct ErlNifBinary {
+    pub size: usize,
+    pub data: *mut u8,
+    /// Non-null if the binary was allocated with `enif_alloc_binary` and not yet released or made
+    /// into a term
+    pub ref_bin: *mut c_void,
+    pub __spare__: [*mut c_void; 2],
+}
+
+#[repr(C)]
+pub struct ErlNifResourceType {
+    _private: [u8; 0],
+}
+
+pub type ErlNifResourceDtor = unsafe extern "C" fn(env: *mut ErlNifEnv, obj: *mut c_void);
+
+pub type ErlNifResourceFlags = c_int;
+pub const ERL_NIF_RT_CREATE: ErlNifResourceFlags = 1;
+pub const ERL_NIF_RT_TAKEOVER: ErlNifResourceFlags = 2;
+
+pub type ErlNifCharEncoding = c_int;
+pub const ERL_NIF_LATIN1: ErlNifCharEncoding = 1;
+pub const ERL_NIF_UTF8: ErlNifCharEncoding = 2;
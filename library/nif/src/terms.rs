@@ -0,0 +1,727 @@
+//! The `enif_*` functions for constructing, inspecting and comparing terms
+//!
+//! These follow the conventions of `erl_nif`: functions which inspect a term return a non-zero
+//! value on success, and write their results through the given out-pointers, while functions
+//! which raise exceptions return a term which must be returned from the calling native function.
+use std::cmp::Ordering;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_double, c_int, c_long, c_uint, c_ulong, c_void};
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+use firefly_alloc::gc::GcBox;
+use firefly_alloc::rc::Rc;
+use firefly_number::ToPrimitive;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::env::{Env, Scheduled};
+use crate::sys::*;
+
+/// The scheduler id used in the identifiers of references created by `enif_make_ref`
+///
+/// These are allocated from a counter separate from those of the schedulers, so a distinct id
+/// guarantees they never collide with references created by Erlang code.
+const NIF_REFERENCE_SCHEDULER_ID: u16 = u16::MAX - 1;
+
+static NEXT_REFERENCE_ID: AtomicU64 = AtomicU64::new(0);
+
+#[export_name = "enif_alloc"]
+pub unsafe extern "C" fn alloc(size: usize) -> *mut c_void {
+    libc::malloc(size)
+}
+
+#[export_name = "enif_realloc"]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    libc::realloc(ptr, size)
+}
+
+#[export_name = "enif_free"]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    libc::free(ptr)
+}
+
+#[export_name = "enif_priv_data"]
+pub unsafe extern "C" fn priv_data(env: *mut ErlNifEnv) -> *mut c_void {
+    Env::from_raw(env).priv_data()
+}
+
+#[export_name = "enif_self"]
+pub unsafe extern "C" fn self_pid(env: *mut ErlNifEnv, pid: *mut ErlNifPid) -> *mut ErlNifPid {
+    let process = Env::from_raw(env).process();
+    let term = GcBox::new_in(Pid::Local { id: process.pid() }, process).unwrap();
+    (*pid).pid = term.into();
+    pid
+}
+
+#[export_name = "enif_make_badarg"]
+pub unsafe extern "C" fn make_badarg(env: *mut ErlNifEnv) -> ERL_NIF_TERM {
+    raise_exception(env, atoms::Badarg.into())
+}
+
+#[export_name = "enif_raise_exception"]
+pub unsafe extern "C" fn raise_exception(
+    env: *mut ErlNifEnv,
+    reason: ERL_NIF_TERM,
+) -> ERL_NIF_TERM {
+    Env::from_raw(env).raise(reason);
+    OpaqueTerm::NONE
+}
+
+#[export_name = "enif_has_pending_exception"]
+pub unsafe extern "C" fn has_pending_exception(
+    env: *mut ErlNifEnv,
+    reason: *mut ERL_NIF_TERM,
+) -> c_int {
+    match Env::from_raw(env).exception() {
+        None => 0,
+        Some(exception) => {
+            if !reason.is_null() {
+                *reason = exception;
+            }
+            1
+        }
+    }
+}
+
+#[export_name = "enif_is_exception"]
+pub unsafe extern "C" fn is_exception(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    term.is_none() as c_int
+}
+
+#[export_name = "enif_schedule_nif"]
+pub unsafe extern "C" fn schedule_nif(
+    env: *mut ErlNifEnv,
+    _fun_name: *const c_char,
+    _flags: c_int,
+    fptr: NifFn,
+    argc: c_int,
+    argv: *const ERL_NIF_TERM,
+) -> ERL_NIF_TERM {
+    let args = terms_from_raw(argv, argc as usize).to_vec();
+    Env::from_raw(env).schedule(Scheduled { fptr, args });
+    OpaqueTerm::NONE
+}
+
+#[export_name = "enif_make_atom"]
+pub unsafe extern "C" fn make_atom(env: *mut ErlNifEnv, name: *const c_char) -> ERL_NIF_TERM {
+    make_atom_len(env, name, CStr::from_ptr(name).to_bytes().len())
+}
+
+#[export_name = "enif_make_atom_len"]
+pub unsafe extern "C" fn make_atom_len(
+    env: *mut ErlNifEnv,
+    name: *const c_char,
+    len: usize,
+) -> ERL_NIF_TERM {
+    match Atom::try_from(latin1_to_string(name, len).as_str()) {
+        Ok(atom) => atom.into(),
+        Err(_) => make_badarg(env),
+    }
+}
+
+#[export_name = "enif_make_existing_atom"]
+pub unsafe extern "C" fn make_existing_atom(
+    env: *mut ErlNifEnv,
+    name: *const c_char,
+    atom: *mut ERL_NIF_TERM,
+    encoding: ErlNifCharEncoding,
+) -> c_int {
+    make_existing_atom_len(
+        env,
+        name,
+        CStr::from_ptr(name).to_bytes().len(),
+        atom,
+        encoding,
+    )
+}
+
+#[export_name = "enif_make_existing_atom_len"]
+pub unsafe extern "C" fn make_existing_atom_len(
+    _env: *mut ErlNifEnv,
+    name: *const c_char,
+    len: usize,
+    atom: *mut ERL_NIF_TERM,
+    encoding: ErlNifCharEncoding,
+) -> c_int {
+    let name = match encoding {
+        ERL_NIF_UTF8 => std::str::from_utf8(bytes_from_raw(name, len))
+            .ok()
+            .map(|s| s.to_string()),
+        _ => Some(latin1_to_string(name, len)),
+    };
+    match name.and_then(|name| Atom::try_from_str_existing(name).ok()) {
+        Some(existing) => {
+            *atom = existing.into();
+            1
+        }
+        None => 0,
+    }
+}
+
+#[export_name = "enif_make_int"]
+pub unsafe extern "C" fn make_int(env: *mut ErlNifEnv, i: c_int) -> ERL_NIF_TERM {
+    make_integer(Env::from_raw(env).process(), i as i128)
+}
+
+#[export_name = "enif_make_uint"]
+pub unsafe extern "C" fn make_uint(env: *mut ErlNifEnv, i: c_uint) -> ERL_NIF_TERM {
+    make_integer(Env::from_raw(env).process(), i as i128)
+}
+
+#[export_name = "enif_make_long"]
+pub unsafe extern "C" fn make_long(env: *mut ErlNifEnv, i: c_long) -> ERL_NIF_TERM {
+    make_integer(Env::from_raw(env).process(), i as i128)
+}
+
+#[export_name = "enif_make_ulong"]
+pub unsafe extern "C" fn make_ulong(env: *mut ErlNifEnv, i: c_ulong) -> ERL_NIF_TERM {
+    make_integer(Env::from_raw(env).process(), i as i128)
+}
+
+#[export_name = "enif_make_int64"]
+pub unsafe extern "C" fn make_int64(env: *mut ErlNifEnv, i: i64) -> ERL_NIF_TERM {
+    make_integer(Env::from_raw(env).process(), i as i128)
+}
+
+#[export_name = "enif_make_uint64"]
+pub unsafe extern "C" fn make_uint64(env: *mut ErlNifEnv, i: u64) -> ERL_NIF_TERM {
+    make_integer(Env::from_raw(env).process(), i as i128)
+}
+
+#[export_name = "enif_make_double"]
+pub unsafe extern "C" fn make_double(env: *mut ErlNifEnv, d: c_double) -> ERL_NIF_TERM {
+    if d.is_finite() {
+        d.into()
+    } else {
+        make_badarg(env)
+    }
+}
+
+#[export_name = "enif_make_tuple_from_array"]
+pub unsafe extern "C" fn make_tuple_from_array(
+    env: *mut ErlNifEnv,
+    arr: *const ERL_NIF_TERM,
+    cnt: c_uint,
+) -> ERL_NIF_TERM {
+    let process = Env::from_raw(env).process();
+    Tuple::from_slice(terms_from_raw(arr, cnt as usize), process)
+        .unwrap()
+        .into()
+}
+
+#[export_name = "enif_make_list_from_array"]
+pub unsafe extern "C" fn make_list_from_array(
+    env: *mut ErlNifEnv,
+    arr: *const ERL_NIF_TERM,
+    cnt: c_uint,
+) -> ERL_NIF_TERM {
+    let process = Env::from_raw(env).process();
+    let mut builder = ListBuilder::new(process);
+    for element in terms_from_raw(arr, cnt as usize).iter().rev().copied() {
+        builder.push(element.into()).unwrap();
+    }
+    builder
+        .finish()
+        .map(|ptr| ptr.into())
+        .unwrap_or(OpaqueTerm::NIL)
+}
+
+#[export_name = "enif_make_list_cell"]
+pub unsafe extern "C" fn make_list_cell(
+    env: *mut ErlNifEnv,
+    head: ERL_NIF_TERM,
+    tail: ERL_NIF_TERM,
+) -> ERL_NIF_TERM {
+    let process = Env::from_raw(env).process();
+    let cell = Cons::new_in(process).unwrap();
+    cell.as_ptr().write(Cons { head, tail });
+    cell.into()
+}
+
+#[export_name = "enif_make_string_len"]
+pub unsafe extern "C" fn make_string_len(
+    env: *mut ErlNifEnv,
+    string: *const c_char,
+    len: usize,
+    encoding: ErlNifCharEncoding,
+) -> ERL_NIF_TERM {
+    let process = Env::from_raw(env).process();
+    let bytes = bytes_from_raw(string, len);
+    let list = match encoding {
+        ERL_NIF_UTF8 => match std::str::from_utf8(bytes) {
+            Ok(s) => Cons::charlist_from_str(s, process).unwrap(),
+            Err(_) => return make_badarg(env),
+        },
+        _ => Cons::from_bytes(bytes, process).unwrap(),
+    };
+    list.map(|ptr| ptr.into()).unwrap_or(OpaqueTerm::NIL)
+}
+
+#[export_name = "enif_make_new_map"]
+pub unsafe extern "C" fn make_new_map(env: *mut ErlNifEnv) -> ERL_NIF_TERM {
+    Map::new_in(Env::from_raw(env).process()).unwrap().into()
+}
+
+#[export_name = "enif_make_map_put"]
+pub unsafe extern "C" fn make_map_put(
+    env: *mut ErlNifEnv,
+    map_in: ERL_NIF_TERM,
+    key: ERL_NIF_TERM,
+    value: ERL_NIF_TERM,
+    map_out: *mut ERL_NIF_TERM,
+) -> c_int {
+    let Term::Map(map) = map_in.into() else {
+        return 0;
+    };
+    let process = Env::from_raw(env).process();
+    let map = map.insert(key.into(), value.into());
+    *map_out = GcBox::new_in(map, process).unwrap().into();
+    1
+}
+
+#[export_name = "enif_make_ref"]
+pub unsafe extern "C" fn make_ref(env: *mut ErlNifEnv) -> ERL_NIF_TERM {
+    let process = Env::from_raw(env).process();
+    let id = NEXT_REFERENCE_ID.fetch_add(1, AtomicOrdering::Relaxed);
+    let id = ReferenceId::new(NIF_REFERENCE_SCHEDULER_ID, id);
+    GcBox::new_in(Reference::Local { id }, process)
+        .unwrap()
+        .into()
+}
+
+#[export_name = "enif_alloc_binary"]
+pub unsafe extern "C" fn alloc_binary(size: usize, bin: *mut ErlNifBinary) -> c_int {
+    let data = libc::malloc(size.max(1));
+    if data.is_null() {
+        return 0;
+    }
+    bin.write(ErlNifBinary {
+        size,
+        data: data.cast(),
+        ref_bin: data,
+        __spare__: [ptr::null_mut(); 2],
+    });
+    1
+}
+
+#[export_name = "enif_realloc_binary"]
+pub unsafe extern "C" fn realloc_binary(bin: *mut ErlNifBinary, size: usize) -> c_int {
+    let bin = &mut *bin;
+    if bin.ref_bin.is_null() {
+        // The binary is read-only, i.e. it was obtained by inspecting a term, so copy it
+        let data = libc::malloc(size.max(1));
+        if data.is_null() {
+            return 0;
+        }
+        ptr::copy_nonoverlapping(bin.data, data.cast(), bin.size.min(size));
+        bin.ref_bin = data;
+    } else {
+        let data = libc::realloc(bin.ref_bin, size.max(1));
+        if data.is_null() {
+            return 0;
+        }
+        bin.ref_bin = data;
+    }
+    bin.data = bin.ref_bin.cast();
+    bin.size = size;
+    1
+}
+
+#[export_name = "enif_release_binary"]
+pub unsafe extern "C" fn release_binary(bin: *mut ErlNifBinary) {
+    let bin = &mut *bin;
+    if !bin.ref_bin.is_null() {
+        libc::free(bin.ref_bin);
+        bin.ref_bin = ptr::null_mut();
+    }
+}
+
+#[export_name = "enif_make_binary"]
+pub unsafe extern "C" fn make_binary(env: *mut ErlNifEnv, bin: *mut ErlNifBinary) -> ERL_NIF_TERM {
+    let bin = &mut *bin;
+    let mut term = OpaqueTerm::NONE;
+    let data = new_binary(Env::from_raw(env).process(), bin.size, &mut term);
+    ptr::copy_nonoverlapping(bin.data, data, bin.size);
+    // Ownership of the data is transferred to the term
+    release_binary(bin);
+    term
+}
+
+#[export_name = "enif_make_new_binary"]
+pub unsafe extern "C" fn make_new_binary(
+    env: *mut ErlNifEnv,
+    size: usize,
+    termp: *mut ERL_NIF_TERM,
+) -> *mut u8 {
+    new_binary(Env::from_raw(env).process(), size, &mut *termp)
+}
+
+#[export_name = "enif_inspect_binary"]
+pub unsafe extern "C" fn inspect_binary(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    bin: *mut ErlNifBinary,
+) -> c_int {
+    let term: Term = term.into();
+    let Some(bits) = term.as_bitstring() else {
+        return 0;
+    };
+    if !bits.is_binary() || !bits.is_aligned() {
+        return 0;
+    }
+    let bytes = bits.as_bytes_unchecked();
+    bin.write(ErlNifBinary {
+        size: bytes.len(),
+        data: bytes.as_ptr() as *mut u8,
+        ref_bin: ptr::null_mut(),
+        __spare__: [ptr::null_mut(); 2],
+    });
+    1
+}
+
+#[export_name = "enif_get_atom"]
+pub unsafe extern "C" fn get_atom(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    buf: *mut c_char,
+    len: c_uint,
+    encoding: ErlNifCharEncoding,
+) -> c_int {
+    let Term::Atom(atom) = term.into() else {
+        return 0;
+    };
+    let Some(bytes) = encode_atom(atom, encoding) else {
+        return 0;
+    };
+    // The buffer must have room for the name and its null terminator
+    if bytes.len() >= len as usize {
+        return 0;
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr(), buf.cast(), bytes.len());
+    *buf.add(bytes.len()) = 0;
+    (bytes.len() + 1) as c_int
+}
+
+#[export_name = "enif_get_atom_length"]
+pub unsafe extern "C" fn get_atom_length(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    len: *mut c_uint,
+    encoding: ErlNifCharEncoding,
+) -> c_int {
+    let Term::Atom(atom) = term.into() else {
+        return 0;
+    };
+    let Some(bytes) = encode_atom(atom, encoding) else {
+        return 0;
+    };
+    *len = bytes.len() as c_uint;
+    1
+}
+
+#[export_name = "enif_get_int"]
+pub unsafe extern "C" fn get_int(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    ip: *mut c_int,
+) -> c_int {
+    get_integer(term, ip)
+}
+
+#[export_name = "enif_get_uint"]
+pub unsafe extern "C" fn get_uint(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    ip: *mut c_uint,
+) -> c_int {
+    get_integer(term, ip)
+}
+
+#[export_name = "enif_get_long"]
+pub unsafe extern "C" fn get_long(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    ip: *mut c_long,
+) -> c_int {
+    get_integer(term, ip)
+}
+
+#[export_name = "enif_get_ulong"]
+pub unsafe extern "C" fn get_ulong(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    ip: *mut c_ulong,
+) -> c_int {
+    get_integer(term, ip)
+}
+
+#[export_name = "enif_get_int64"]
+pub unsafe extern "C" fn get_int64(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    ip: *mut i64,
+) -> c_int {
+    get_integer(term, ip)
+}
+
+#[export_name = "enif_get_uint64"]
+pub unsafe extern "C" fn get_uint64(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    ip: *mut u64,
+) -> c_int {
+    get_integer(term, ip)
+}
+
+#[export_name = "enif_get_double"]
+pub unsafe extern "C" fn get_double(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    dp: *mut c_double,
+) -> c_int {
+    match term.into() {
+        Term::Float(f) => {
+            *dp = f.inner();
+            1
+        }
+        _ => 0,
+    }
+}
+
+#[export_name = "enif_get_tuple"]
+pub unsafe extern "C" fn get_tuple(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    arity: *mut c_int,
+    array: *mut *const ERL_NIF_TERM,
+) -> c_int {
+    let Term::Tuple(tuple) = term.into() else {
+        return 0;
+    };
+    let elements = tuple.as_ref().as_slice();
+    *arity = elements.len() as c_int;
+    *array = elements.as_ptr();
+    1
+}
+
+#[export_name = "enif_get_list_cell"]
+pub unsafe extern "C" fn get_list_cell(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    head: *mut ERL_NIF_TERM,
+    tail: *mut ERL_NIF_TERM,
+) -> c_int {
+    let Term::Cons(cell) = term.into() else {
+        return 0;
+    };
+    let cell = cell.as_ref();
+    *head = cell.head;
+    *tail = cell.tail;
+    1
+}
+
+#[export_name = "enif_get_list_length"]
+pub unsafe extern "C" fn get_list_length(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    len: *mut c_uint,
+) -> c_int {
+    match term.into() {
+        Term::Nil => {
+            *len = 0;
+            1
+        }
+        Term::Cons(cell) => {
+            let mut count = 0;
+            for element in cell.as_ref().iter() {
+                if element.is_err() {
+                    return 0;
+                }
+                count += 1;
+            }
+            *len = count;
+            1
+        }
+        _ => 0,
+    }
+}
+
+#[export_name = "enif_get_map_size"]
+pub unsafe extern "C" fn get_map_size(
+    _env: *mut ErlNifEnv,
+    term: ERL_NIF_TERM,
+    size: *mut usize,
+) -> c_int {
+    let Term::Map(map) = term.into() else {
+        return 0;
+    };
+    *size = map.size();
+    1
+}
+
+#[export_name = "enif_get_map_value"]
+pub unsafe extern "C" fn get_map_value(
+    _env: *mut ErlNifEnv,
+    map: ERL_NIF_TERM,
+    key: ERL_NIF_TERM,
+    value: *mut ERL_NIF_TERM,
+) -> c_int {
+    let Term::Map(map) = map.into() else {
+        return 0;
+    };
+    match map.get(key) {
+        Some(found) => {
+            *value = found.into();
+            1
+        }
+        None => 0,
+    }
+}
+
+#[export_name = "enif_is_atom"]
+pub unsafe extern "C" fn is_atom(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Atom(_) | Term::Bool(_)) as c_int
+}
+
+#[export_name = "enif_is_binary"]
+pub unsafe extern "C" fn is_binary(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    let term: Term = term.into();
+    term.as_bitstring()
+        .map(|bits| bits.is_binary())
+        .unwrap_or(false) as c_int
+}
+
+#[export_name = "enif_is_list"]
+pub unsafe extern "C" fn is_list(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Nil | Term::Cons(_)) as c_int
+}
+
+#[export_name = "enif_is_empty_list"]
+pub unsafe extern "C" fn is_empty_list(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Nil) as c_int
+}
+
+#[export_name = "enif_is_tuple"]
+pub unsafe extern "C" fn is_tuple(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Tuple(_)) as c_int
+}
+
+#[export_name = "enif_is_number"]
+pub unsafe extern "C" fn is_number(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Int(_) | Term::BigInt(_) | Term::Float(_)) as c_int
+}
+
+#[export_name = "enif_is_map"]
+pub unsafe extern "C" fn is_map(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Map(_)) as c_int
+}
+
+#[export_name = "enif_is_ref"]
+pub unsafe extern "C" fn is_ref(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Reference(_)) as c_int
+}
+
+#[export_name = "enif_is_pid"]
+pub unsafe extern "C" fn is_pid(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Pid(_)) as c_int
+}
+
+#[export_name = "enif_is_fun"]
+pub unsafe extern "C" fn is_fun(_env: *mut ErlNifEnv, term: ERL_NIF_TERM) -> c_int {
+    matches!(term.into(), Term::Closure(_)) as c_int
+}
+
+#[export_name = "enif_is_identical"]
+pub unsafe extern "C" fn is_identical(lhs: ERL_NIF_TERM, rhs: ERL_NIF_TERM) -> c_int {
+    let lhs: Term = lhs.into();
+    let rhs: Term = rhs.into();
+    lhs.exact_eq(&rhs) as c_int
+}
+
+#[export_name = "enif_compare"]
+pub unsafe extern "C" fn compare(lhs: ERL_NIF_TERM, rhs: ERL_NIF_TERM) -> c_int {
+    let lhs: Term = lhs.into();
+    let rhs: Term = rhs.into();
+    match lhs.cmp(&rhs) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
+/// Constructs an integer term, allocating a bignum on the process heap if necessary
+fn make_integer(process: &Process, i: i128) -> OpaqueTerm {
+    match i64::try_from(i).ok().and_then(|i| Term::try_from(i).ok()) {
+        Some(small) => small.into(),
+        None => GcBox::new_in(BigInt::from(i), process).unwrap().into(),
+    }
+}
+
+/// Writes the value of the integer `term` through `ip`, if it is representable as a `T`
+unsafe fn get_integer<T: TryFrom<i128>>(term: OpaqueTerm, ip: *mut T) -> c_int {
+    let value = match term.into() {
+        Term::Int(i) => Some(i as i128),
+        Term::BigInt(i) => i.to_i128(),
+        _ => None,
+    };
+    match value.and_then(|i| T::try_from(i).ok()) {
+        Some(i) => {
+            *ip = i;
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Allocates a binary term of `size` bytes, returning a pointer to its uninitialized data
+///
+/// The data remains valid for as long as the term is live.
+unsafe fn new_binary(process: &Process, size: usize, termp: &mut OpaqueTerm) -> *mut u8 {
+    if size <= BinaryData::MAX_HEAP_BYTES {
+        let mut bin = BinaryData::with_capacity_small(size, process).unwrap();
+        let data = bin[..].as_mut_ptr();
+        *termp = bin.into();
+        data
+    } else {
+        let mut bin = BinaryData::with_capacity_large(size, process).unwrap();
+        // SAFETY: There can be no other references to this Rc yet
+        let data = Rc::get_mut(&mut bin).unwrap_unchecked()[..].as_mut_ptr();
+        *termp = bin.into();
+        data
+    }
+}
+
+/// Returns the name of `atom` in the requested encoding, if it is representable in it
+fn encode_atom(atom: Atom, encoding: ErlNifCharEncoding) -> Option<Vec<u8>> {
+    let name = atom.as_str();
+    match encoding {
+        ERL_NIF_UTF8 => Some(name.as_bytes().to_vec()),
+        _ => name.chars().map(|c| u8::try_from(c as u32).ok()).collect(),
+    }
+}
+
+unsafe fn latin1_to_string(ptr: *const c_char, len: usize) -> String {
+    bytes_from_raw(ptr, len)
+        .iter()
+        .map(|b| *b as char)
+        .collect()
+}
+
+#[inline]
+unsafe fn bytes_from_raw<'a>(ptr: *const c_char, len: usize) -> &'a [u8] {
+    match NonNull::new(ptr as *mut u8) {
+        Some(ptr) => slice::from_raw_parts(ptr.as_ptr(), len),
+        None => &[],
+    }
+}
+
+#[inline]
+unsafe fn terms_from_raw<'a>(ptr: *const ERL_NIF_TERM, len: usize) -> &'a [ERL_NIF_TERM] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
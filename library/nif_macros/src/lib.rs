@@ -0,0 +1,178 @@
+//! This crate provides the `#[nif]` attribute macro, re-exported by `firefly_nif`, which defines a
+//! native implementation of an Erlang function that is linked into the executable ahead-of-time.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+use proc_macro2::Span;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, parse_quote, Error, FnArg, ItemFn, LitStr};
+
+/// Defines a native implementation of an Erlang function, e.g.:
+///
+/// ```ignore
+/// #[nif("crypto:hash")]
+/// fn hash(ty: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+///     ...
+/// }
+/// ```
+///
+/// The function must use the Erlang calling convention, i.e. it takes only `OpaqueTerm` arguments
+/// and returns `ErlangResult`, and its arity is the number of arguments it takes. An arity may also
+/// be given explicitly, as in `"crypto:hash/2"`, in which case it must match the signature.
+///
+/// The function is exported with the symbol name of the Erlang function, so calls from compiled
+/// Erlang code are resolved to it by the linker. If the module also defines the function in
+/// Erlang, it must be declared in a `-nifs` attribute, as such definitions are linked weakly, and
+/// the native implementation takes precedence over them. The function is also registered with the
+/// runtime's dispatch table at startup, so that it can be called dynamically, e.g. via
+/// `erlang:apply/3`.
+///
+/// Registration relies on the linker defining the bounds of the section the functions are placed
+/// in, as for the dispatch table itself, so only unix targets, including macOS, are supported, and
+/// using this macro on any other target is a compile error.
+///
+/// NOTE: Native functions are only registered if the object file containing them is linked into the
+/// executable, so libraries defining them should be linked with `-l static:+whole-archive=<name>`.
+#[proc_macro_attribute]
+pub fn nif(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let name = parse_macro_input!(attr as NifName);
+    let item = parse_macro_input!(item as ItemFn);
+
+    match expand(name, item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// The name of the Erlang function being implemented, i.e. `"module:function"` or
+/// `"module:function/arity"`
+struct NifName {
+    span: Span,
+    module: String,
+    function: String,
+    arity: Option<u8>,
+}
+impl Parse for NifName {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lit = input.parse::<LitStr>()?;
+        let span = lit.span();
+        let value = lit.value();
+        let invalid = || {
+            Error::new(
+                span,
+                "expected a function name of the form \"module:function\" or \"module:function/arity\"",
+            )
+        };
+        let (module, rest) = value.split_once(':').ok_or_else(invalid)?;
+        let (function, arity) = match rest.split_once('/') {
+            Some((function, arity)) => {
+                (function, Some(arity.parse::<u8>().map_err(|_| invalid())?))
+            }
+            None => (rest, None),
+        };
+        if module.is_empty() || function.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            span,
+            module: module.to_string(),
+            function: function.to_string(),
+            arity,
+        })
+    }
+}
+
+fn expand(name: NifName, mut item: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &item.sig;
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new(
+            sig.generics.span(),
+            "native functions cannot be generic",
+        ));
+    }
+    if let Some(asyncness) = sig.asyncness {
+        return Err(Error::new(
+            asyncness.span(),
+            "native functions cannot be async",
+        ));
+    }
+    if let Some(receiver) = sig
+        .inputs
+        .iter()
+        .find(|arg| matches!(arg, FnArg::Receiver(_)))
+    {
+        return Err(Error::new(
+            receiver.span(),
+            "native functions cannot take self",
+        ));
+    }
+    let arity: u8 = sig.inputs.len().try_into().map_err(|_| {
+        Error::new(
+            sig.inputs.span(),
+            "native functions cannot take more than 255 arguments",
+        )
+    })?;
+    if let Some(expected) = name.arity {
+        if expected != arity {
+            return Err(Error::new(
+                name.span,
+                format!(
+                    "the arity of this function is {}, but its signature has {} arguments",
+                    expected, arity
+                ),
+            ));
+        }
+    }
+    let abi = sig
+        .abi
+        .as_ref()
+        .map(|abi| abi.name.as_ref().map(|name| name.value()));
+    match abi {
+        None => {
+            item.sig.abi = Some(parse_quote!(extern "C-unwind"));
+        }
+        Some(Some(abi)) if abi == "C-unwind" => (),
+        Some(_) => {
+            return Err(Error::new(
+                item.sig.abi.span(),
+                "native functions must use the \"C-unwind\" calling convention",
+            ));
+        }
+    }
+    if let Some(attr) = item
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("export_name") || attr.path.is_ident("no_mangle"))
+    {
+        return Err(Error::new(
+            attr.span(),
+            "the symbol name of native functions is derived from the Erlang function they implement",
+        ));
+    }
+
+    let export_name = format!("{}:{}/{}", &name.module, &name.function, arity);
+    item.attrs.push(parse_quote!(#[export_name = #export_name]));
+    item.attrs
+        .push(parse_quote!(#[allow(improper_ctypes_definitions)]));
+
+    let ident = &item.sig.ident;
+    let module = name.module.as_str();
+    let function = name.function.as_str();
+    Ok(quote! {
+        #item
+
+        const _: () = {
+            #[cfg(not(unix))]
+            compile_error!("native functions can only be linked in ahead-of-time on unix targets");
+
+            #[used]
+            #[cfg_attr(target_os = "macos", link_section = "__DATA,__firefly_nifs")]
+            #[cfg_attr(all(unix, not(target_os = "macos")), link_section = "__firefly_nifs")]
+            static NIF: ::firefly_nif::StaticNif =
+                ::firefly_nif::StaticNif::new(#module, #function, #arity, #ident as *const ());
+        };
+    })
+}
@@ -7,7 +7,7 @@ use core::ops::{Add, Div, Mul, Neg, Rem, Sub};
 
 pub use half::f16;
 use num_bigint::{BigInt, Sign};
-use num_traits::ToPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::{DivisionError, Integer};
 
@@ -99,8 +99,9 @@ impl fmt::Debug for Float {
     }
 }
 impl fmt::Display for Float {
+    /// Writes the float as Erlang does, e.g. `1.0` rather than `1`, see `format::short`
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        f.write_str(&crate::format::short(self.0))
     }
 }
 impl Ord for Float {
@@ -176,15 +177,10 @@ impl PartialEq<i64> for Float {
             x if x.is_infinite() => false,
             x if x >= Self::I64_UPPER_BOUNDARY || x <= Self::I64_LOWER_BOUNDARY => {
                 // We're out of the range where f64 is more precise than an i64,
-                // so cast the float to integer and comapre.
-                //
-                // # Safety
-                //
-                // We've guarded against infinite values, the float cannot be NaN
-                // due to our encoding scheme, and we know the value can be represented
-                // in i64, so this is guaranteed safe.
-                let x: i64 = unsafe { x.to_int_unchecked() };
-                x.eq(y)
+                // so convert the float to integer and compare. Such floats are always
+                // integral, but may be out of the range of i64, in which case they
+                // cannot be equal
+                x.to_i64().map(|x| x.eq(y)).unwrap_or(false)
             }
             x => x.eq(&(*y as f64)),
         }
@@ -192,8 +188,12 @@ impl PartialEq<i64> for Float {
 }
 impl PartialEq<BigInt> for Float {
     fn eq(&self, y: &BigInt) -> bool {
-        let Some(y) = y.to_i64() else { return false; };
-        self.eq(&y)
+        match y.to_i64() {
+            Some(y) => self.eq(&y),
+            // Only floats out of the range of i64 can be equal, and those are integral
+            None if self.0.is_finite() => BigInt::from_f64(self.0).as_ref() == Some(y),
+            None => false,
+        }
     }
 }
 impl PartialEq<Integer> for Float {
@@ -216,15 +216,13 @@ impl PartialOrd<i64> for Float {
             }
             x if x >= Self::I64_UPPER_BOUNDARY || x <= Self::I64_LOWER_BOUNDARY => {
                 // We're out of the range where f64 is more precise than an i64,
-                // so cast the float to integer and comapre.
-                //
-                // # Safety
-                //
-                // We've guarded against infinite values, the float cannot be NaN
-                // due to our encoding scheme, and we know the value can be represented
-                // in i64, so this is guaranteed safe.
-                let x: i64 = unsafe { x.to_int_unchecked() };
-                Some(x.cmp(y))
+                // so convert the float to integer and compare. Such floats are always
+                // integral, and those out of the range of i64 are beyond any i64
+                match x.to_i64() {
+                    Some(x) => Some(x.cmp(y)),
+                    None if x.is_sign_negative() => Some(Ordering::Less),
+                    None => Some(Ordering::Greater),
+                }
             }
             x => x.partial_cmp(&(*y as f64)),
         }
@@ -240,13 +238,21 @@ impl PartialOrd<BigInt> for Float {
                     Some(Ordering::Greater)
                 }
             }
-            _ => {
+            x => {
                 let too_large = if y.sign() == Sign::Minus {
                     Ordering::Greater
                 } else {
                     Ordering::Less
                 };
-                let Some(y) = y.to_i64() else { return Some(too_large); };
+                let Some(y) = y.to_i64() else {
+                    // Floats in the range where f64 is more precise than an i64 are smaller
+                    // in magnitude than any integer out of the range of i64, the rest are
+                    // integral, so convert them to compare exactly
+                    if x < Self::I64_UPPER_BOUNDARY && x > Self::I64_LOWER_BOUNDARY {
+                        return Some(too_large);
+                    }
+                    return BigInt::from_f64(x).map(|x| x.cmp(y));
+                };
                 self.partial_cmp(&y)
             }
         }
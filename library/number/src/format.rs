@@ -0,0 +1,164 @@
+//! Formatting of floats as Erlang does, for `io_lib` and the term printer.
+//!
+//! The digits are produced by `core::fmt`, which never consults the C locale, so the output is the
+//! same whatever locale a program embedding the runtime has set, e.g. one with a decimal comma.
+//! Only the layout of the digits is done here, following `io_lib_format` in OTP.
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use num_traits::float::FloatCore;
+
+/// Formats `value` with the fewest digits which read back as the same float, as `~w` and
+/// `float_to_list(Value, [short])` do, choosing whichever of plain and scientific notation is
+/// shorter, e.g. `100.0`, `1.0e3` and `0.001`
+pub fn short(value: f64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let (negative, digits, exponent) = digits(&format!("{:e}", value));
+    let digits = digits.as_str();
+    // The position of the decimal point relative to the digits, i.e. `0.Digits * 10^Place`
+    let place = exponent + 1;
+    let len = digits.len() as i32;
+    let mut text = String::with_capacity(digits.len() + 8);
+    if negative {
+        text.push('-');
+    }
+    if place == 0 {
+        text.push_str("0.");
+        text.push_str(digits);
+        return text;
+    }
+    if 0 < place && place < len {
+        let (int, frac) = digits.split_at(place as usize);
+        text.push_str(int);
+        text.push('.');
+        text.push_str(frac);
+        return text;
+    }
+    let exp = (place - 1).to_string();
+    let exp_cost = exp.len() as i32 + 1 + if len == 1 { 2 } else { 1 };
+    if place < 0 && 2 - place <= exp_cost {
+        text.push_str("0.");
+        text.push_str(&"0".repeat(-place as usize));
+        text.push_str(digits);
+    } else if place > 0 && place - len + 2 <= exp_cost {
+        text.push_str(digits);
+        text.push_str(&"0".repeat((place - len) as usize));
+        text.push_str(".0");
+    } else {
+        let (first, rest) = digits.split_at(1);
+        text.push_str(first);
+        text.push('.');
+        text.push_str(if rest.is_empty() { "0" } else { rest });
+        text.push('e');
+        text.push_str(&exp);
+    }
+    text
+}
+
+/// Formats `value` with `decimals` digits after the decimal point, as `~.Decimalsf` and
+/// `float_to_list(Value, [{decimals, Decimals}])` do
+pub fn fixed(value: f64, decimals: usize) -> String {
+    format!("{:.*}", decimals, value)
+}
+
+/// Formats `value` in scientific notation with `digits` significant digits, as `~.Digitse` does,
+/// e.g. `1.23450e+3`
+pub fn exponential(value: f64, digits: usize) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let text = format!("{:.*e}", digits.max(1) - 1, value);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    match exponent.strip_prefix('-') {
+        Some(exponent) => format!("{}e-{}", mantissa, exponent),
+        None => format!("{}e+{}", mantissa, exponent),
+    }
+}
+
+/// Formats `value` with `digits` significant digits, as `~.Digitsg` does, which is as `~f` if
+/// `0.1 <= abs(Value) < 10000.0`, and as `~e` otherwise
+pub fn general(value: f64, digits: usize) -> String {
+    let abs = FloatCore::abs(value);
+    // The exponent of the leading digit, for the magnitudes written as `~f`
+    let exponent = if abs < 0.1 {
+        None
+    } else if abs < 1.0 {
+        Some(-1)
+    } else if abs < 10.0 {
+        Some(0)
+    } else if abs < 100.0 {
+        Some(1)
+    } else if abs < 1000.0 {
+        Some(2)
+    } else if abs < 10000.0 {
+        Some(3)
+    } else {
+        None
+    };
+    let digits = digits as i32;
+    match exponent {
+        Some(-1) if digits <= 1 => fixed(value, 1),
+        Some(exponent) if digits - 1 > exponent => fixed(value, (digits - 1 - exponent) as usize),
+        _ => exponential(value, digits.max(2) as usize),
+    }
+}
+
+/// Splits the output of `{:e}`, e.g. `-1.25e-3`, into its sign, its digits and its exponent
+fn digits(text: &str) -> (bool, String, i32) {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    (
+        negative,
+        mantissa.replace('.', ""),
+        exponent.parse().unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_chooses_the_shorter_notation() {
+        assert_eq!(short(0.0), "0.0");
+        assert_eq!(short(-0.0), "-0.0");
+        assert_eq!(short(1.0), "1.0");
+        assert_eq!(short(0.1), "0.1");
+        assert_eq!(short(0.001), "0.001");
+        assert_eq!(short(1.0e-5), "1.0e-5");
+        assert_eq!(short(100.0), "100.0");
+        assert_eq!(short(1000.0), "1.0e3");
+        assert_eq!(short(123.456), "123.456");
+        assert_eq!(short(-2.5e20), "-2.5e20");
+        assert_eq!(short(1.2345e-10), "1.2345e-10");
+    }
+
+    #[test]
+    fn fixed_rounds_to_decimals() {
+        assert_eq!(fixed(1.0, 6), "1.000000");
+        assert_eq!(fixed(1.23456, 2), "1.23");
+        assert_eq!(fixed(-2.5, 1), "-2.5");
+        assert_eq!(fixed(1.0e10, 1), "10000000000.0");
+    }
+
+    #[test]
+    fn exponential_writes_a_signed_exponent() {
+        assert_eq!(exponential(1234.5, 6), "1.23450e+3");
+        assert_eq!(exponential(0.0, 6), "0.00000e+0");
+        assert_eq!(exponential(-0.00012, 3), "-1.20e-4");
+    }
+
+    #[test]
+    fn general_switches_to_exponential_outside_of_range() {
+        assert_eq!(general(1234.5, 6), "1234.50");
+        assert_eq!(general(0.5, 6), "0.500000");
+        assert_eq!(general(1.0, 6), "1.00000");
+        assert_eq!(general(0.05, 6), "5.00000e-2");
+        assert_eq!(general(12345.0, 6), "1.23450e+4");
+    }
+}
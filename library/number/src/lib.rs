@@ -1,5 +1,6 @@
 #![feature(let_else)]
 #![feature(const_type_id)]
+#![cfg_attr(test, feature(test))]
 #![no_std]
 
 extern crate alloc;
@@ -18,6 +19,8 @@ pub use float::{f16, Float, FloatError};
 mod number;
 pub use number::Number;
 
+pub mod format;
+
 pub use num_bigint as bigint;
 pub use num_bigint::{BigInt, Sign};
 pub use num_traits as traits;
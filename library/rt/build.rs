@@ -91,8 +91,8 @@ fn generate_symbols_rs(symbols: Vec<Symbol>) -> std::io::Result<()> {
             r#"
 pub const {0}_VALUE: &'static [u8] = b"{1}";
 
-#[cfg_attr(target_os = "macos", link_section = "__DATA,atoms")]
-#[cfg_attr(all(linux, not(target_os = "macos")), link_section = "__atoms")]
+#[cfg_attr(target_os = "macos", link_section = "__DATA,__atoms")]
+#[cfg_attr(all(unix, not(target_os = "macos")), link_section = "__atoms")]
 #[export_name = "atom_{1}"]
 #[linkage = "linkonce_odr"]
 pub static {0}_ATOM: AtomData = AtomData {{
@@ -130,6 +130,17 @@ pub static {0}: Atom = Atom(&{0}_ATOM as *const AtomData);"#,
         }
     }
 
+    // The set of atoms defined by the runtime, which the compiler references rather than defines
+    file.write_all(b"\n\n/// The values of all atoms defined by the runtime\n")?;
+    file.write_all(b"pub const BUILTIN_ATOMS: &'static [&'static str] = &[\n")?;
+    for symbol in symbols.iter() {
+        if symbol.key == "False" || symbol.key == "True" {
+            continue;
+        }
+        writeln!(&mut file, "    {:?},", &symbol.value)?;
+    }
+    file.write_all(b"];\n")?;
+
     file.sync_data()?;
 
     Ok(())
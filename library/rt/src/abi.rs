@@ -0,0 +1,175 @@
+//! This module documents and versions the ABI shared between compiler-generated code and the
+//! runtime.
+//!
+//! Generated code makes a number of assumptions about the runtime it is linked against, and vice
+//! versa. Those assumptions are summarized here, and any change to them _must_ be accompanied by
+//! bumping [`ABI_VERSION`], so that objects produced by a compiler with a different notion of the
+//! ABI are rejected rather than silently corrupting memory.
+//!
+//! # Function calls
+//!
+//! * All Erlang functions use the C calling convention (with unwinding permitted, i.e. `C-unwind`)
+//! * Every argument is passed as an immediate-sized [`OpaqueTerm`]; there is no stack-based
+//! argument vector, so the maximum arity of a function is [`MAX_ARITY`], the maximum value of the
+//! `u8` arity field in [`FunctionSymbol`](crate::function::FunctionSymbol). When calling
+//! dynamically, the runtime spills arguments beyond those passed in registers according to the
+//! platform C ABI (see `function::apply`).
+//! * Calls in tail position are `musttail` calls whenever the callee takes as many arguments as the
+//! caller, or, on targets whose C convention has the caller pop the arguments, no more than it, in
+//! which case the callee is passed undefined values for the rest. Other tail calls are scheduled
+//! with `__firefly_tail_call` and performed by the nearest caller not in tail position (see
+//! `function::apply::tail`). No call is left to the backend to make as a sibling call.
+//!
+//! # Return values
+//!
+//! * Every Erlang function returns an [`ErlangResult`], a `#[repr(u8)]` enum whose discriminant is
+//! `0` for `Ok` and `1` for `Err`. The payload follows the discriminant at the alignment of
+//! `OpaqueTerm`.
+//! * When `Err`, the payload is an owned pointer to an
+//! [`ErlangException`](crate::error::ErlangException), which generated code either handles in a
+//! landing pad, or propagates to its caller unchanged.
+//! * `Ok` with the `NONE` term is never a valid result; it is returned in place of the result of a
+//! scheduled tail call, and callers must obtain the real result from `__firefly_resume_tail_call`.
+//!
+//! # Exceptions
+//!
+//! Generated code raises exceptions by unwinding via `__firefly_raise`, and catches them in landing
+//! pads using `firefly_eh_personality` as its personality, or `__CxxFrameHandler3` on Windows.
+//! Exceptions raised by natives are returned as `Err`, never unwound, and wherever the runtime
+//! calls into generated code, exceptions unwinding out of it are caught and returned as `Err`, see
+//! `error::unwind`.
+//!
+//! # Preemption
+//!
+//! Generated code consumes reductions from the thread-local `isize` named
+//! `__firefly_reductions_left` on entry to every function and on every loop iteration, and calls
+//! `__firefly_yield_point` once it reaches zero, see `process::reductions`.
+//!
+//! # Safepoints
+//!
+//! Every call in generated code is a safepoint, made via an LLVM statepoint whose stack map record
+//! lists the stack slots holding the terms live across it. The garbage collector finds the roots of
+//! a process by walking its stack and looking up the record for the return address of each frame,
+//! and updates the slots in place when it moves a term. A frame without a record is native, and the
+//! record of a call across which a pointer derived from a term is live has the ID `1`, either of
+//! which keeps the collector from moving anything, see `process::stackmaps` for the format.
+//!
+//! # Version checking
+//!
+//! Every module produced by the compiler contains a pointer-sized global in the [`ABI_SECTION`]
+//! section, which refers to the symbol named by [`ABI_VERSION_SYMBOL`]. That symbol is only defined
+//! by a runtime built against the same ABI version, so linking objects from an incompatible
+//! compiler fails with an undefined symbol error. The runtime additionally validates the contents
+//! of that section at startup.
+use crate::function::ErlangResult;
+use crate::term::OpaqueTerm;
+
+macro_rules! define_abi_version {
+    ($version:literal) => {
+        /// The current version of the ABI between generated code and the runtime
+        pub const ABI_VERSION: u32 = $version;
+
+        /// The name of the symbol which the runtime defines, and which every compiled module
+        /// references
+        pub const ABI_VERSION_SYMBOL: &'static str = concat!("__firefly_abi_version_", $version);
+    };
+}
+
+define_abi_version!(6);
+
+/// The name of the section in which the compiler places each module's ABI stamp
+///
+/// On Mach-O targets, this section is placed in the `__DATA` segment.
+pub const ABI_SECTION: &str = "__firefly_abi";
+
+/// The maximum arity of a function which can be called via the Erlang calling convention
+pub const MAX_ARITY: usize = u8::MAX as usize;
+
+/// The value stored in the runtime's ABI version symbol
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AbiVersion {
+    pub version: u32,
+    /// The size in bytes of `ErlangResult` as understood by the producer
+    pub result_size: u32,
+}
+impl AbiVersion {
+    /// The ABI version of this runtime
+    pub const CURRENT: Self = Self {
+        version: ABI_VERSION,
+        result_size: core::mem::size_of::<ErlangResult>() as u32,
+    };
+}
+
+/// Each compiled module emits one of these, referring to the runtime's [`AbiVersion`]
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone)]
+pub struct AbiStamp(pub *const AbiVersion);
+
+/// Stamps only ever refer to the read-only version static of the runtime, and are therefore Sync
+unsafe impl Sync for AbiStamp {}
+
+impl AbiStamp {
+    /// Returns true if this stamp refers to a version compatible with the current runtime
+    pub fn is_compatible(&self) -> bool {
+        if self.0.is_null() {
+            return false;
+        }
+        unsafe { *self.0 == AbiVersion::CURRENT }
+    }
+}
+
+/// Validates all of the ABI stamps in the given range, returning the number of stamps found
+///
+/// Returns `Err` with the index of the first incompatible stamp if any are found.
+///
+/// # Safety
+///
+/// The given pointers must denote the bounds of the ABI section in the current executable
+pub unsafe fn verify_stamps(start: *const AbiStamp, end: *const AbiStamp) -> Result<usize, usize> {
+    if start.is_null() || end.is_null() || end <= start {
+        return Ok(0);
+    }
+    let len = end.offset_from(start) as usize;
+    let stamps = core::slice::from_raw_parts(start, len);
+    for (i, stamp) in stamps.iter().enumerate() {
+        if !stamp.is_compatible() {
+            return Err(i);
+        }
+    }
+    Ok(len)
+}
+
+// These are the layout assumptions made by generated code, so verify them at compile-time
+const _: () = assert!(core::mem::size_of::<OpaqueTerm>() == 8);
+const _: () =
+    assert!(core::mem::size_of::<ErlangResult>() == 2 * core::mem::size_of::<OpaqueTerm>());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abi_version_symbol_matches_version() {
+        let expected = alloc::format!("__firefly_abi_version_{}", ABI_VERSION);
+        assert_eq!(ABI_VERSION_SYMBOL, expected.as_str());
+    }
+
+    #[test]
+    fn abi_stamp_compatibility() {
+        let current = AbiVersion::CURRENT;
+        let other = AbiVersion {
+            version: ABI_VERSION + 1,
+            result_size: current.result_size,
+        };
+        assert!(AbiStamp(&current).is_compatible());
+        assert!(!AbiStamp(&other).is_compatible());
+        assert!(!AbiStamp(core::ptr::null()).is_compatible());
+
+        let stamps = [AbiStamp(&current), AbiStamp(&other)];
+        let range = stamps.as_ptr_range();
+        assert_eq!(unsafe { verify_stamps(range.start, range.end) }, Err(1));
+        let range = stamps[..1].as_ptr_range();
+        assert_eq!(unsafe { verify_stamps(range.start, range.end) }, Ok(1));
+    }
+}
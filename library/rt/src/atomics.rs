@@ -0,0 +1,173 @@
+//! Arrays of atomic integers, as created by `atomics:new/2`.
+//!
+//! An array is referred to in Erlang by a magic reference, and its elements are shared rather than
+//! copied: every term referring to the array holds a reference to it, as does any Rust code which
+//! obtained it via [`Atomics::shared`]. This makes it possible to share counters between an
+//! embedding application and Erlang processes without message passing, in either direction:
+//!
+//! * An array created in Erlang can be viewed from Rust as a `&[AtomicI64]`, e.g. from a native
+//! function which receives the array as an argument, using [`Atomics::from_term`]
+//! * An existing `Arc<[AtomicI64]>` owned by the application can be handed to Erlang, e.g. as the
+//! result of a native function, using [`Atomics::from_shared`] and [`Atomics::into_term`], after
+//! which it can be used with the functions of the `atomics` module like any other array
+//!
+//! Elements are always stored as 64-bit integers. Unsigned arrays store the bit pattern of the
+//! unsigned value, so Rust code sharing such an array should reinterpret elements as `u64`.
+use alloc::sync::Arc;
+use core::alloc::{AllocError, Allocator};
+use core::any::Any;
+use core::fmt;
+use core::sync::atomic::AtomicI64;
+
+use firefly_alloc::gc::GcBox;
+
+use crate::term::{Reference, ReferenceId, Term};
+
+/// The scheduler id used in the identifiers of references to atomics arrays
+///
+/// The remaining bits of the identifier are the address of the array, so that all terms referring
+/// to the same array compare equal.
+const ATOMICS_REFERENCE_SCHEDULER_ID: u16 = u16::MAX - 1;
+
+/// A shared, fixed-size array of atomic 64-bit integers
+#[derive(Clone)]
+pub struct Atomics {
+    values: Arc<[AtomicI64]>,
+    signed: bool,
+}
+impl fmt::Debug for Atomics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Atomics")
+            .field("values", &self.values)
+            .field("signed", &self.signed)
+            .finish()
+    }
+}
+impl Atomics {
+    /// Creates a new array of `arity` elements, all initialized to zero
+    pub fn new(arity: usize, signed: bool) -> Self {
+        let values = (0..arity).map(|_| AtomicI64::new(0)).collect();
+        Self { values, signed }
+    }
+
+    /// Wraps an existing array, so that it can be shared with Erlang code
+    ///
+    /// Updates made from either side are visible to the other, as the array is not copied.
+    pub fn from_shared(values: Arc<[AtomicI64]>, signed: bool) -> Self {
+        Self { values, signed }
+    }
+
+    /// Returns the array referred to by `term`, if it refers to one
+    pub fn from_term(term: Term) -> Option<Self> {
+        let Term::Reference(reference) = term else {
+            return None;
+        };
+        reference.magic()?.downcast_ref::<Self>().cloned()
+    }
+
+    /// Allocates a term referring to this array using `alloc`, typically a process heap
+    pub fn into_term<A: Allocator>(self, alloc: A) -> Result<Term, AllocError> {
+        let address = Arc::as_ptr(&self.values) as *const AtomicI64 as usize;
+        let id = ReferenceId::new(ATOMICS_REFERENCE_SCHEDULER_ID, address as u64);
+        let handle = GcBox::<dyn Any>::new_unsize_in(self, &alloc)?;
+        let reference = GcBox::new_in(Reference::new_magic(id, handle), &alloc)?;
+        Ok(Term::Reference(reference))
+    }
+
+    /// Returns true if the elements of this array are signed
+    #[inline]
+    pub fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    /// Returns the number of elements in this array
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if this array has no elements
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the elements of this array
+    #[inline]
+    pub fn as_slice(&self) -> &[AtomicI64] {
+        &self.values
+    }
+
+    /// Returns a new reference to the elements of this array, which keeps them alive independently
+    /// of any Erlang term
+    #[inline]
+    pub fn shared(&self) -> Arc<[AtomicI64]> {
+        self.values.clone()
+    }
+
+    /// Returns the smallest value which can be stored in this array
+    pub fn min(&self) -> i128 {
+        if self.signed {
+            i64::MIN as i128
+        } else {
+            0
+        }
+    }
+
+    /// Returns the largest value which can be stored in this array
+    pub fn max(&self) -> i128 {
+        if self.signed {
+            i64::MAX as i128
+        } else {
+            u64::MAX as i128
+        }
+    }
+
+    /// Converts a stored element to the value it represents
+    pub fn decode(&self, raw: i64) -> i128 {
+        if self.signed {
+            raw as i128
+        } else {
+            raw as u64 as i128
+        }
+    }
+
+    /// Converts `value` to its stored representation, if it is in the range of this array
+    pub fn encode(&self, value: i128) -> Option<i64> {
+        if value < self.min() || value > self.max() {
+            return None;
+        }
+        Some(value as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::Ordering;
+
+    use super::*;
+
+    #[test]
+    fn atomics_encoding_test() {
+        let signed = Atomics::new(2, true);
+        assert_eq!(signed.len(), 2);
+        assert_eq!(signed.encode(-1), Some(-1));
+        assert_eq!(signed.encode(u64::MAX as i128), None);
+
+        let unsigned = Atomics::new(1, false);
+        assert_eq!(unsigned.encode(-1), None);
+        let raw = unsigned.encode(u64::MAX as i128).unwrap();
+        assert_eq!(unsigned.decode(raw), u64::MAX as i128);
+    }
+
+    #[test]
+    fn atomics_shared_test() {
+        let values: Arc<[AtomicI64]> = (0..4).map(|_| AtomicI64::new(0)).collect();
+        let atomics = Atomics::from_shared(values.clone(), true);
+        atomics.as_slice()[1].fetch_add(5, Ordering::SeqCst);
+        assert_eq!(values[1].load(Ordering::SeqCst), 5);
+        assert_eq!(Arc::strong_count(&values), 2);
+        drop(atomics);
+        assert_eq!(Arc::strong_count(&values), 1);
+    }
+}
@@ -50,11 +50,20 @@ impl Frame for backtrace::Frame {
             });
             let line = resolved_symbol.lineno();
             let column = resolved_symbol.colno();
+            // Debug info only records where an expression starts, so recover where it ends from
+            // the span table emitted by the compiler
+            let end = match (filename.as_deref(), line, column) {
+                (Some(file), Some(line), Some(column)) => {
+                    super::spans::lookup_span(file, line, column).map(|range| range.end())
+                }
+                _ => None,
+            };
             result = Some(Symbolication {
                 symbol,
                 filename,
                 line,
                 column,
+                end,
             });
         });
 
@@ -1,4 +1,5 @@
 mod frame;
+pub mod spans;
 mod symbolication;
 mod trace;
 
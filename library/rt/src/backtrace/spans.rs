@@ -0,0 +1,303 @@
+//! This module defines the source span table, which the compiler embeds in every module it
+//! compiles.
+//!
+//! Debug info only records the position at which each instruction starts, so while a stack frame
+//! can be resolved to a line and column, the extent of the failing expression is lost. The span
+//! table recovers it: for every call site in a module, it maps the start of the call's source span
+//! to the end of that span, so exceptions like `badmatch` can report the exact range of the failing
+//! expression.
+//!
+//! # Format
+//!
+//! Each module contributes one record to the [`SPANS_SECTION`] section. Records are concatenated
+//! without padding, and all integers are little-endian:
+//!
+//! ```text
+//! record := size:u32 files spans           ; size is the number of bytes following it
+//! files  := count:u16 (len:u16 utf8)*count ; the source files referenced by this record
+//! spans  := count:u32 span*count
+//! span   := file:u16 start_line:u32 start_column:u32 end_line:u32 end_column:u32
+//! ```
+//!
+//! Lines and columns are 1-based, and the end position is exclusive. When multiple spans start at
+//! the same position, only the shortest (i.e. innermost) is recorded. A record with a size of zero
+//! is valid, and is emitted by the runtime itself so that the section always exists.
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use firefly_system::sync::{const_rwlock, RwLock};
+
+/// The name of the section in which the compiler places each module's span table
+///
+/// On Mach-O targets, this section is placed in the `__DATA` segment.
+pub const SPANS_SECTION: &str = "__firefly_spans";
+
+/// The span tables of the running executable, set during startup
+static SPAN_TABLE: RwLock<&'static [u8]> = const_rwlock(&[]);
+
+/// The source range of an expression
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceRange {
+    pub start_line: u32,
+    pub start_column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+}
+impl SourceRange {
+    const ENCODED_SIZE: usize = 2 + 4 * 4;
+
+    #[inline]
+    pub fn end(&self) -> (u32, u32) {
+        (self.end_line, self.end_column)
+    }
+}
+
+/// Builds the span table record for a single module
+#[derive(Default)]
+pub struct SpanTableBuilder {
+    spans: BTreeMap<String, BTreeMap<(u32, u32), SourceRange>>,
+}
+impl SpanTableBuilder {
+    /// Records `range` as a span in `file`, unless a shorter span starting at the same position
+    /// exists
+    pub fn push(&mut self, file: &str, range: SourceRange) {
+        let spans = self.spans.entry(file.to_string()).or_default();
+        let start = (range.start_line, range.start_column);
+        match spans.get(&start) {
+            Some(existing) if existing.end() <= range.end() => (),
+            _ => {
+                spans.insert(start, range);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Encodes the table as a single record
+    pub fn finish(self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(self.spans.len() as u16).to_le_bytes());
+        for file in self.spans.keys() {
+            body.extend_from_slice(&(file.len() as u16).to_le_bytes());
+            body.extend_from_slice(file.as_bytes());
+        }
+        let count: usize = self.spans.values().map(|spans| spans.len()).sum();
+        body.extend_from_slice(&(count as u32).to_le_bytes());
+        for (index, spans) in self.spans.values().enumerate() {
+            for range in spans.values() {
+                body.extend_from_slice(&(index as u16).to_le_bytes());
+                body.extend_from_slice(&range.start_line.to_le_bytes());
+                body.extend_from_slice(&range.start_column.to_le_bytes());
+                body.extend_from_slice(&range.end_line.to_le_bytes());
+                body.extend_from_slice(&range.end_column.to_le_bytes());
+            }
+        }
+
+        let mut record = Vec::with_capacity(4 + body.len());
+        record.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        record.extend_from_slice(body.as_slice());
+        record
+    }
+}
+
+/// Sets the span tables of the running executable, given the bounds of the span table section
+///
+/// Returns false if the section contains a malformed record, in which case no spans are available.
+///
+/// # Safety
+///
+/// The given pointers must denote the bounds of the span table section in the current executable
+pub unsafe fn set_span_table(start: *const u8, end: *const u8) -> bool {
+    if start.is_null() || end.is_null() || end <= start {
+        return true;
+    }
+    let len = end.offset_from(start) as usize;
+    let table = core::slice::from_raw_parts(start, len);
+    if records(table).any(|record| record.is_none()) {
+        return false;
+    }
+    *SPAN_TABLE.write() = table;
+    true
+}
+
+/// Returns the source range of the expression starting at the given position in `file`, if known
+///
+/// The file name is matched by suffix, as it may be relative to a different directory than the one
+/// in which the module was compiled.
+pub fn lookup_span(file: &str, line: u32, column: u32) -> Option<SourceRange> {
+    let table = *SPAN_TABLE.read();
+    find_span(table, file, line, column)
+}
+
+fn find_span(table: &[u8], file: &str, line: u32, column: u32) -> Option<SourceRange> {
+    for record in records(table) {
+        let record = record?;
+        let files = record.files().collect::<Vec<_>>();
+        let found = record.spans().find(|(index, range)| {
+            range.start_line == line
+                && range.start_column == column
+                && files
+                    .get(*index as usize)
+                    .map(|name| is_same_file(name, file))
+                    .unwrap_or(false)
+        });
+        if let Some((_, range)) = found {
+            return Some(range);
+        }
+    }
+    None
+}
+
+/// Returns true if one of the given paths is a suffix of the other, on a path separator boundary
+fn is_same_file(a: &str, b: &str) -> bool {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    match long.strip_suffix(short) {
+        Some("") => true,
+        Some(prefix) => prefix.ends_with('/') || prefix.ends_with('\\'),
+        None => false,
+    }
+}
+
+/// Iterates over the records in `table`, producing `None` for a malformed record
+fn records(mut table: &[u8]) -> impl Iterator<Item = Option<Record<'_>>> {
+    core::iter::from_fn(move || {
+        if table.is_empty() {
+            return None;
+        }
+        let Some(size) = read_u32(table, 0) else {
+            table = &[];
+            return Some(None);
+        };
+        let end = 4 + size as usize;
+        if table.len() < end {
+            table = &[];
+            return Some(None);
+        }
+        let body = &table[4..end];
+        table = &table[end..];
+        Some(Record::parse(body))
+    })
+}
+
+struct Record<'a> {
+    files: &'a [u8],
+    num_files: usize,
+    spans: &'a [u8],
+}
+impl<'a> Record<'a> {
+    fn parse(body: &'a [u8]) -> Option<Self> {
+        // An empty record is used as a placeholder
+        if body.is_empty() {
+            return Some(Self {
+                files: &[],
+                num_files: 0,
+                spans: &[],
+            });
+        }
+        let num_files = read_u16(body, 0)? as usize;
+        let mut offset = 2;
+        for _ in 0..num_files {
+            let len = read_u16(body, offset)? as usize;
+            let name = body.get(offset + 2..offset + 2 + len)?;
+            core::str::from_utf8(name).ok()?;
+            offset += 2 + len;
+        }
+        let files = &body[2..offset];
+        let count = read_u32(body, offset)? as usize;
+        let spans = &body[offset + 4..];
+        if spans.len() != count * SourceRange::ENCODED_SIZE {
+            return None;
+        }
+        Some(Self {
+            files,
+            num_files,
+            spans,
+        })
+    }
+
+    fn files(&self) -> impl Iterator<Item = &'a str> {
+        let mut files = self.files;
+        (0..self.num_files).map(move |_| {
+            let len = read_u16(files, 0).unwrap() as usize;
+            let name = unsafe { core::str::from_utf8_unchecked(&files[2..2 + len]) };
+            files = &files[2 + len..];
+            name
+        })
+    }
+
+    fn spans(&self) -> impl Iterator<Item = (u16, SourceRange)> + 'a {
+        self.spans
+            .chunks_exact(SourceRange::ENCODED_SIZE)
+            .map(|span| {
+                let range = SourceRange {
+                    start_line: read_u32(span, 2).unwrap(),
+                    start_column: read_u32(span, 6).unwrap(),
+                    end_line: read_u32(span, 10).unwrap(),
+                    end_column: read_u32(span, 14).unwrap(),
+                };
+                (read_u16(span, 0).unwrap(), range)
+            })
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start_line: u32, start_column: u32, end_line: u32, end_column: u32) -> SourceRange {
+        SourceRange {
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+        }
+    }
+
+    #[test]
+    fn span_table_lookup() {
+        let mut builder = SpanTableBuilder::default();
+        builder.push("src/foo.erl", range(10, 5, 10, 20));
+        // A shorter span starting at the same position replaces the longer one
+        builder.push("src/foo.erl", range(10, 5, 10, 12));
+        builder.push("src/foo.erl", range(10, 5, 11, 1));
+        builder.push("include/foo.hrl", range(3, 1, 4, 8));
+
+        let mut table = builder.finish();
+        // Placeholder records are skipped
+        table.extend_from_slice(&[0, 0, 0, 0]);
+
+        assert_eq!(
+            find_span(&table, "/home/user/app/src/foo.erl", 10, 5),
+            Some(range(10, 5, 10, 12))
+        );
+        assert_eq!(
+            find_span(&table, "include/foo.hrl", 3, 1),
+            Some(range(3, 1, 4, 8))
+        );
+        assert_eq!(find_span(&table, "src/afoo.erl", 10, 5), None);
+        assert_eq!(find_span(&table, "src/foo.erl", 10, 6), None);
+    }
+
+    #[test]
+    fn span_table_malformed() {
+        let mut builder = SpanTableBuilder::default();
+        builder.push("foo.erl", range(1, 1, 1, 2));
+        let mut table = builder.finish();
+        assert!(records(&table).all(|record| record.is_some()));
+        table.pop();
+        assert!(records(&table).any(|record| record.is_none()));
+    }
+}
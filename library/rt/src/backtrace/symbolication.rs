@@ -61,6 +61,7 @@ pub struct Symbolication {
     pub(super) filename: Option<String>,
     pub(super) line: Option<u32>,
     pub(super) column: Option<u32>,
+    pub(super) end: Option<(u32, u32)>,
 }
 impl Symbolication {
     #[inline]
@@ -102,6 +103,15 @@ impl Symbolication {
     pub fn column(&self) -> Option<u32> {
         self.column
     }
+
+    /// Returns the line and column at which the expression for this frame ends, if known
+    ///
+    /// The end position is exclusive, i.e. it refers to the first character following the
+    /// expression.
+    #[inline]
+    pub fn end_location(&self) -> Option<(u32, u32)> {
+        self.end
+    }
 }
 impl TryFrom<Term> for Symbolication {
     type Error = ();
@@ -149,6 +159,7 @@ impl TryFrom<Term> for Symbolication {
                 filename: None,
                 line: None,
                 column: None,
+                end: None,
             }),
             Term::Cons(ptr) => {
                 let list = unsafe { ptr.as_ref() };
@@ -168,11 +179,34 @@ impl TryFrom<Term> for Symbolication {
                 } else {
                     None
                 };
+                let column = if let Some(Term::Int(column)) =
+                    list.keyfind(0, atoms::Column).ok().unwrap_or(None)
+                {
+                    Some(column.try_into().unwrap())
+                } else {
+                    None
+                };
+                let end = match list.keyfind(0, atoms::EndLocation).ok().unwrap_or(None) {
+                    Some(Term::Tuple(ptr)) => {
+                        let loc = unsafe { ptr.as_ref() };
+                        match loc.as_slice() {
+                            [l, c] => match ((*l).into(), (*c).into()) {
+                                (Term::Int(l), Term::Int(c)) => {
+                                    Some((l.try_into().unwrap(), c.try_into().unwrap()))
+                                }
+                                _ => None,
+                            },
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
                 Ok(Self {
                     symbol: Some(Symbol::Erlang(mfa)),
                     filename,
                     line,
-                    column: None,
+                    column,
+                    end,
                 })
             }
             // Technically a bug, but it is optional info, so we ignore it in the
@@ -182,6 +216,7 @@ impl TryFrom<Term> for Symbolication {
                 filename: None,
                 line: None,
                 column: None,
+                end: None,
             }),
         }
     }
@@ -204,7 +239,7 @@ pub const MAX_FILENAME_LEN: usize = 120;
 /// Each frame looks like:
 ///
 /// ```ignore
-///     {module, function, arity, [{file, "path/to/file"}, {line, 1}]}
+///     {module, function, arity, [{file, "path/to/file"}, {line, 1}, {column, 5}, {end_location, {1, 12}}]}
 /// ```
 ///
 /// However, the first frame may optionally contain the arguments, used for certain
@@ -212,7 +247,7 @@ pub const MAX_FILENAME_LEN: usize = 120;
 /// like so:
 ///
 /// ```ignore
-///     {module, function, [..args], [{file, "path/to/file"}, {line, 1}]}
+///     {module, function, [..args], [{file, "path/to/file"}, {line, 1}, {column, 5}, {end_location, {1, 12}}]}
 /// ```
 ///
 /// The frames themselves are contained in a list.
@@ -230,9 +265,12 @@ pub const MAX_FILENAME_LEN: usize = 120;
 ///   frame: Tuple<4>, // references `meta`
 ///   arity: Int, // elided for the first frame if the argument list was provided
 ///   args: Nil | [Cons<Term>; ARITY], // elided for all but the first frame if the argument list was provided
-///   meta: [Cons<Tuple>; 2], // cons cells for `file` and `line`
+///   meta: [Cons<Tuple>; 4], // cons cells for `file`, `line`, `column` and `end_location`
 ///   file: Tuple<2>, // references data in `filename`
 ///   line: Tuple<2>, // both elements are immediate
+///   column: Tuple<2>, // both elements are immediate
+///   end_location: Tuple<2>, // references `end`
+///   end: Tuple<2>, // both elements are immediate
 ///   filename: [Cons<Int>; MAX_FILENAME_LEN],
 /// }
 ///
@@ -277,9 +315,12 @@ pub fn calculate_fragment_layout(
     let base = min_tuple_layout(4);
     let first_frame_arity_or_args = arguments_layout;
     let arity_or_args = Layout::new::<OpaqueTerm>();
-    let meta = Layout::array::<Cons>(2).unwrap();
+    let meta = Layout::array::<Cons>(4).unwrap();
     let file = min_tuple_layout(2);
     let line = min_tuple_layout(2);
+    let column = min_tuple_layout(2);
+    let end_location = min_tuple_layout(2);
+    let end = min_tuple_layout(2);
     let filename = Layout::array::<Cons>(MAX_FILENAME_LEN).unwrap();
 
     let frame_tail_layout = meta
@@ -289,6 +330,15 @@ pub fn calculate_fragment_layout(
         .extend(line)
         .unwrap()
         .0
+        .extend(column)
+        .unwrap()
+        .0
+        .extend(end_location)
+        .unwrap()
+        .0
+        .extend(end)
+        .unwrap()
+        .0
         .extend(filename)
         .unwrap()
         .0
@@ -342,6 +392,8 @@ pub fn format_mfa<H>(
     argv: Option<&[OpaqueTerm]>,
     filename: Option<&str>,
     line: Option<u32>,
+    column: Option<u32>,
+    end: Option<(u32, u32)>,
     alloc: &H,
 ) -> Result<Term, AllocError>
 where
@@ -350,7 +402,7 @@ where
     let module: OpaqueTerm = mfa.module.into();
     let function: OpaqueTerm = mfa.function.into();
 
-    let locs = format_locations(filename, line, alloc)
+    let locs = format_locations(filename, line, column, end, alloc)
         .unwrap_or(Term::Nil)
         .into();
 
@@ -372,12 +424,15 @@ where
 pub fn format_locations<H>(
     filename: Option<&str>,
     line: Option<u32>,
+    column: Option<u32>,
+    end: Option<(u32, u32)>,
     alloc: &H,
 ) -> Result<Term, AllocError>
 where
     H: Heap,
 {
-    // Each location is a pair of: {file, "<path>"}, {line, <line>}
+    // Each location is a pair of: {file, "<path>"}, {line, <line>}, and when known,
+    // {column, <column>} and {end_location, {<line>, <column>}}
     let file_key = atoms::File.into();
     let line_key = atoms::Line.into();
     let file = if let Some(f) = filename {
@@ -390,6 +445,24 @@ where
     let line = Tuple::from_slice(&[line_key, line.into()], alloc)?;
 
     let mut builder = ListBuilder::new(alloc);
+    if let Some((end_line, end_column)) = end {
+        let end = Tuple::from_slice(
+            &[
+                Term::Int(end_line as i64).into(),
+                Term::Int(end_column as i64).into(),
+            ],
+            alloc,
+        )?;
+        let end_location = Tuple::from_slice(&[atoms::EndLocation.into(), end.into()], alloc)?;
+        builder.push(end_location.into())?;
+    }
+    if let Some(column) = column {
+        let column = Tuple::from_slice(
+            &[atoms::Column.into(), Term::Int(column as i64).into()],
+            alloc,
+        )?;
+        builder.push(column.into())?;
+    }
     builder.push(line.into())?;
     builder.push(file.into())?;
 
@@ -117,9 +117,15 @@ impl Trace {
         let heap_ptr = self.get_or_create_fragment(Some(arguments)).unwrap_or(None);
         if let Some(mut heap) = heap_ptr {
             let heap_mut = unsafe { heap.as_mut() };
-            if let Ok(frame) =
-                super::symbolication::format_mfa(mfa, Some(arguments), None, None, heap_mut)
-            {
+            if let Ok(frame) = super::symbolication::format_mfa(
+                mfa,
+                Some(arguments),
+                None,
+                None,
+                None,
+                None,
+                heap_mut,
+            ) {
                 unsafe {
                     self.top.set(Some(frame));
                 }
@@ -307,6 +313,8 @@ impl Trace {
                         None,
                         symbol.filename(),
                         symbol.line(),
+                        symbol.column(),
+                        symbol.end_location(),
                         heap,
                     )?;
                     erlang_frames.push(erlang_frame);
@@ -0,0 +1,219 @@
+//! This module defines the boot manifest which the compiler embeds in every executable.
+//!
+//! The manifest plays the role of a release boot script: it lists the applications to start before
+//! `init:boot/1` is invoked, in the order they should be started, and indicates whether the
+//! executable bundles system configuration (i.e. the equivalent of `sys.config`).
+//!
+//! # Format
+//!
+//! The manifest is a NUL-terminated UTF-8 string referenced by the symbol [`BOOT_MANIFEST_SYMBOL`].
+//! It consists of a header line, followed by one directive per line:
+//!
+//! ```text
+//! firefly-boot 1
+//! config
+//! start kernel permanent
+//! start stdlib permanent
+//! start myapp permanent
+//! ```
+//!
+//! * `start NAME TYPE` starts the application `NAME` (and any dependencies not yet started) with
+//!   the given restart type, one of `permanent`, `transient` or `temporary`
+//! * `config` indicates that configuration is bundled as `firefly_apps:config/0`, which returns a
+//!   list of `{Application, [{Key, Value}]}`, applied before any application is started
+//!
+//! Empty lines are ignored. Any change to the format must be accompanied by bumping
+//! [`BOOT_MANIFEST_VERSION`].
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use firefly_system::sync::{const_rwlock, RwLock};
+
+/// The name of the symbol at which the boot manifest is placed
+pub const BOOT_MANIFEST_SYMBOL: &str = "__firefly_boot_manifest";
+
+/// The current version of the boot manifest format
+pub const BOOT_MANIFEST_VERSION: u32 = 1;
+
+const HEADER: &str = "firefly-boot";
+
+/// The boot script of the running executable, set during startup
+static BOOT_SCRIPT: RwLock<Option<BootScript>> = const_rwlock(None);
+
+/// Sets the boot script for the running executable
+///
+/// This is expected to be called once, during the earliest phase of startup.
+pub fn set_boot_script(script: BootScript) {
+    *BOOT_SCRIPT.write() = Some(script);
+}
+
+/// Returns the boot script of the running executable, if one was provided
+pub fn boot_script() -> Option<BootScript> {
+    BOOT_SCRIPT.read().clone()
+}
+
+/// The restart type of an application, which determines what happens when it terminates
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RestartType {
+    Permanent,
+    Transient,
+    Temporary,
+}
+impl RestartType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Permanent => "permanent",
+            Self::Transient => "transient",
+            Self::Temporary => "temporary",
+        }
+    }
+}
+impl Default for RestartType {
+    fn default() -> Self {
+        Self::Permanent
+    }
+}
+impl FromStr for RestartType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "permanent" => Ok(Self::Permanent),
+            "transient" => Ok(Self::Transient),
+            "temporary" => Ok(Self::Temporary),
+            _ => Err(()),
+        }
+    }
+}
+impl fmt::Display for RestartType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The decoded form of a boot manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BootScript {
+    /// The applications to start, in start order
+    pub applications: Vec<(String, RestartType)>,
+    /// True if configuration is bundled as `firefly_apps:config/0`
+    pub config: bool,
+}
+impl BootScript {
+    /// Decodes a boot manifest
+    pub fn parse(manifest: &str) -> Result<Self, BootScriptError> {
+        let mut lines = manifest.lines().enumerate();
+        let version = match lines.next() {
+            Some((_, line)) => match line.split_once(' ') {
+                Some((HEADER, version)) => version
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| BootScriptError::InvalidLine(1))?,
+                _ => return Err(BootScriptError::MissingHeader),
+            },
+            None => return Err(BootScriptError::MissingHeader),
+        };
+        if version != BOOT_MANIFEST_VERSION {
+            return Err(BootScriptError::UnsupportedVersion(version));
+        }
+
+        let mut script = Self::default();
+        for (index, line) in lines {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next(), words.next()) {
+                (None, ..) => continue,
+                (Some("config"), None, ..) => script.config = true,
+                (Some("start"), Some(name), restart_type, None) => {
+                    let restart_type = match restart_type {
+                        None => RestartType::default(),
+                        Some(ty) => ty
+                            .parse()
+                            .map_err(|_| BootScriptError::InvalidLine(index + 1))?,
+                    };
+                    script.applications.push((name.to_string(), restart_type));
+                }
+                _ => return Err(BootScriptError::InvalidLine(index + 1)),
+            }
+        }
+
+        Ok(script)
+    }
+}
+impl fmt::Display for BootScript {
+    /// Encodes this script as a boot manifest, excluding the NUL terminator
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}", HEADER, BOOT_MANIFEST_VERSION)?;
+        if self.config {
+            writeln!(f, "config")?;
+        }
+        for (name, restart_type) in self.applications.iter() {
+            writeln!(f, "start {} {}", name, restart_type)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error produced when a boot manifest cannot be decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BootScriptError {
+    MissingHeader,
+    UnsupportedVersion(u32),
+    /// The given line (1-based) is malformed
+    InvalidLine(usize),
+}
+impl fmt::Display for BootScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingHeader => write!(f, "missing boot manifest header"),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported boot manifest version {} (expected {})",
+                v, BOOT_MANIFEST_VERSION
+            ),
+            Self::InvalidLine(line) => {
+                write!(f, "invalid boot manifest directive on line {}", line)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boot_script_roundtrip() {
+        let script = BootScript {
+            applications: alloc::vec![
+                ("kernel".to_string(), RestartType::Permanent),
+                ("myapp".to_string(), RestartType::Transient),
+            ],
+            config: true,
+        };
+        let manifest = script.to_string();
+        assert_eq!(
+            manifest,
+            "firefly-boot 1\nconfig\nstart kernel permanent\nstart myapp transient\n"
+        );
+        assert_eq!(BootScript::parse(manifest.as_str()), Ok(script));
+    }
+
+    #[test]
+    fn boot_script_errors() {
+        assert_eq!(BootScript::parse(""), Err(BootScriptError::MissingHeader));
+        assert_eq!(
+            BootScript::parse("firefly-boot 2\n"),
+            Err(BootScriptError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            BootScript::parse("firefly-boot 1\n\nstart myapp forever\n"),
+            Err(BootScriptError::InvalidLine(3))
+        );
+        assert_eq!(
+            BootScript::parse("firefly-boot 1\nstart myapp\n").map(|s| s.applications),
+            Ok(alloc::vec![("myapp".to_string(), RestartType::Permanent)])
+        );
+    }
+}
@@ -5,7 +5,7 @@ use core::ptr::NonNull;
 use firefly_alloc::fragment::HeapFragment;
 
 use crate::backtrace::Trace;
-use crate::term::{Atom, OpaqueTerm, Term};
+use crate::term::{atoms, Atom, OpaqueTerm, Term};
 
 /// The raw representation of an Erlang panic.
 ///
@@ -71,6 +71,35 @@ impl ErlangException {
         })
     }
 
+    /// Constructs a new `error` exception with `badarg` as the reason
+    pub fn badarg(trace: Arc<Trace>) -> Box<Self> {
+        Self::new(atoms::Error, atoms::Badarg.into(), trace)
+    }
+
+    /// Constructs a new `error` exception with extended error information attached.
+    ///
+    /// This corresponds to `erlang:error/3` with an `{error_info, Map}` option, and is
+    /// what natives should use when they want `erl_error:format_exception/3` to be able
+    /// to describe which argument was at fault.
+    pub fn with_error_info(reason: Term, error_info: Term, trace: Arc<Trace>) -> Box<Self> {
+        Self::new_with_meta(atoms::Error, reason, error_info, trace)
+    }
+
+    /// Converts this exception into the raw pointer carried by `ErlangResult::Err`
+    ///
+    /// Ownership of the exception is transferred to the caller, which in generated code
+    /// means the landing pad that eventually handles it, or the scheduler if uncaught.
+    #[inline]
+    pub fn into_raw(self: Box<Self>) -> NonNull<ErlangException> {
+        unsafe { NonNull::new_unchecked(Box::into_raw(self)) }
+    }
+
+    /// Returns true if `kind` is a valid exception class, i.e. one of `error`, `exit`, or `throw`
+    #[inline]
+    pub fn is_valid_kind(kind: Atom) -> bool {
+        kind == atoms::Error || kind == atoms::Exit || kind == atoms::Throw
+    }
+
     #[inline]
     pub fn kind(&self) -> Atom {
         self.kind
@@ -86,6 +115,15 @@ impl ErlangException {
         self.meta.into()
     }
 
+    /// Returns the `error_info` map attached to this exception, if one was provided
+    #[inline]
+    pub fn error_info(&self) -> Option<Term> {
+        match self.meta.into() {
+            term @ Term::Map(_) => Some(term),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn fragment(&self) -> Option<NonNull<HeapFragment>> {
         self.fragment
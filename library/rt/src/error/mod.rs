@@ -1,4 +1,5 @@
 mod erlang;
 pub mod printer;
+pub mod unwind;
 
 pub use self::erlang::ErlangException;
@@ -51,6 +51,16 @@ pub fn print(process: &Process, exception: &ErlangException) -> io::Result<()> {
                     write!(writer, ":")?;
                     writer.set_color(&yellow)?;
                     write!(writer, "{}", col)?;
+                    // The end of the expression is printed as `-col` when on the same line
+                    match (symbol.line(), symbol.end_location()) {
+                        (Some(line), Some((end_line, end_col))) if line == end_line => {
+                            write!(writer, "-{}", end_col)?;
+                        }
+                        (_, Some((end_line, end_col))) => {
+                            write!(writer, "-{}:{}", end_line, end_col)?;
+                        }
+                        _ => (),
+                    }
                 }
             }
             None => {
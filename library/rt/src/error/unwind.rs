@@ -0,0 +1,81 @@
+//! Generated code raises exceptions by unwinding, rather than by returning them as `Err`.
+//!
+//! A raise calls `__firefly_raise` with the exception, which starts unwinding the stack using the
+//! native mechanism of the target, i.e. Itanium-style unwinding, or SEH on Windows. Each call made
+//! by generated code within the scope of a `try` or `catch` is an invoke, whose landing pad takes
+//! the exception via `__firefly_catch_exception`, and branches to the handler with it, exactly as
+//! it would have been had the call returned `Err`. As such, a call which returns normally costs no
+//! more than one outside of a handler. The `after` block of a `try` is lowered to a handler which
+//! re-raises once it has run, so it runs whichever way the `try` is left.
+//!
+//! Natives still return their exceptions as `Err`, as do functions on targets without native
+//! unwinding, i.e. WebAssembly, so generated code continues to check the result of every call.
+//!
+//! Exceptions must never unwind out of generated code into the runtime, so wherever the runtime
+//! calls into generated code, it does so via [`catch`], which returns any exception reaching it as
+//! `Err`, as though generated code had returned it.
+#[cfg(not(any(target_arch = "wasm32", test)))]
+use core::mem::ManuallyDrop;
+#[cfg(not(any(target_arch = "wasm32", test)))]
+use core::ptr::NonNull;
+
+use crate::function::ErlangResult;
+
+#[cfg(not(any(target_arch = "wasm32", test)))]
+use super::ErlangException;
+
+#[cfg(not(any(target_arch = "wasm32", test)))]
+extern "C-unwind" {
+    /// Takes the exception being caught from the exception object, provided by `firefly_panic`
+    #[allow(improper_ctypes)]
+    fn __firefly_catch_exception(ptr: *mut u8) -> *mut ErlangException;
+}
+
+/// Calls `f`, returning any exception raised by unwinding out of it as `Err`
+///
+/// Panics of the runtime are not Erlang exceptions, and continue unwinding.
+#[cfg(not(any(target_arch = "wasm32", test)))]
+pub fn catch<F: FnOnce() -> ErlangResult>(f: F) -> ErlangResult {
+    union Data<F> {
+        f: ManuallyDrop<F>,
+        result: ErlangResult,
+        exception: *mut ErlangException,
+    }
+
+    #[inline]
+    fn do_call<F: FnOnce() -> ErlangResult>(data: *mut u8) {
+        unsafe {
+            let data = &mut *(data as *mut Data<F>);
+            let f = ManuallyDrop::take(&mut data.f);
+            data.result = f();
+        }
+    }
+
+    #[inline]
+    fn do_catch<F: FnOnce() -> ErlangResult>(data: *mut u8, payload: *mut u8) {
+        unsafe {
+            let data = &mut *(data as *mut Data<F>);
+            data.exception = __firefly_catch_exception(payload);
+        }
+    }
+
+    let mut data = Data {
+        f: ManuallyDrop::new(f),
+    };
+    let data_ptr = &mut data as *mut Data<F> as *mut u8;
+    unsafe {
+        if core::intrinsics::r#try(do_call::<F>, data_ptr, do_catch::<F>) == 0 {
+            data.result
+        } else {
+            ErlangResult::Err(NonNull::new_unchecked(data.exception))
+        }
+    }
+}
+
+/// Calls `f`, exceptions never unwind on this target, nor in unit tests, which run no generated
+/// code, and are not linked against `firefly_panic`
+#[cfg(any(target_arch = "wasm32", test))]
+#[inline(always)]
+pub fn catch<F: FnOnce() -> ErlangResult>(f: F) -> ErlangResult {
+    f()
+}
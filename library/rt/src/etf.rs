@@ -0,0 +1,1034 @@
+//! Encoding of terms in the external term format, as produced by `erlang:term_to_binary/1`.
+//!
+//! Rather than building the encoded term in memory, the [`Encoder`] writes it to a [`Sink`] as it
+//! goes, e.g. a file or socket, or an [`IoVec`], which refers to large binaries instead of copying
+//! them, as with `erlang:term_to_iovec/1`. Encoding is also resumable: each call to
+//! [`Encoder::encode`] does a bounded amount of work, so that the encoding of a large term can be
+//! interleaved with other processes by yielding to the scheduler in between.
+//!
+//! The term being encoded is read in place, so it must not be moved, e.g. by a garbage collection,
+//! while the encoding of it is suspended.
+//!
+//! Function captures of exported functions are encoded as such, by name. Other closures are
+//! encoded with an index and uniq under which they are registered by [`register_fun`], which are
+//! resolved back to the same entry point when decoded on the same node. A closure which cannot be
+//! resolved, e.g. one encoded on another node, decodes to a fun which raises `undef` when called.
+//!
+//! Terms are decoded onto a heap with [`decode`], which handles everything produced by the encoder
+//! other than pids, ports, references and bitstrings which are not binaries.
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::alloc::AllocError;
+use core::convert::Infallible;
+use core::fmt;
+use core::mem;
+use core::ops::Deref;
+use core::ptr::NonNull;
+
+use firefly_alloc::gc::GcBox;
+use firefly_alloc::heap::Heap;
+use firefly_number::{BigInt, Sign, ToPrimitive};
+
+use crate::function::{self, register_fun, FunId, ModuleFunctionArity};
+use crate::term::{atoms, Atom, Closure, Cons, IntoTerm, Map, Node, OpaqueTerm, PinnedBinary};
+use crate::term::{Port, Reference, Term, TermType, Tuple};
+
+/// The version number which precedes every encoded term
+pub const VERSION: u8 = 131;
+
+const NEW_FLOAT_EXT: u8 = 70;
+const BIT_BINARY_EXT: u8 = 77;
+const NEW_PID_EXT: u8 = 88;
+const NEWER_REFERENCE_EXT: u8 = 90;
+const SMALL_INTEGER_EXT: u8 = 97;
+const INTEGER_EXT: u8 = 98;
+const SMALL_TUPLE_EXT: u8 = 104;
+const LARGE_TUPLE_EXT: u8 = 105;
+const NIL_EXT: u8 = 106;
+const STRING_EXT: u8 = 107;
+const LIST_EXT: u8 = 108;
+const BINARY_EXT: u8 = 109;
+const SMALL_BIG_EXT: u8 = 110;
+const LARGE_BIG_EXT: u8 = 111;
+const NEW_FUN_EXT: u8 = 112;
+const EXPORT_EXT: u8 = 113;
+const MAP_EXT: u8 = 116;
+const ATOM_UTF8_EXT: u8 = 118;
+const SMALL_ATOM_UTF8_EXT: u8 = 119;
+const V4_PORT_EXT: u8 = 120;
+
+/// The name of the local node when it is not distributed
+const NO_NODE: &str = "nonode@nohost";
+
+/// The error produced when a term cannot be encoded
+#[derive(Debug)]
+pub enum EncodeError<E> {
+    /// The sink failed to write the encoded term
+    Sink(E),
+    /// The term contains a value of a type which cannot be encoded
+    Unsupported(TermType),
+    /// The term contains a value whose size cannot be represented in the encoding
+    TooLarge,
+}
+impl<E: fmt::Display> fmt::Display for EncodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sink(err) => write!(f, "failed to write encoded term: {}", err),
+            Self::Unsupported(ty) => write!(f, "terms of type {:?} cannot be encoded", ty),
+            Self::TooLarge => f.write_str("term is too large to encode"),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl<E: std::error::Error> std::error::Error for EncodeError<E> {}
+
+/// The error produced when bytes cannot be decoded as a term
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes end before the term does
+    Truncated,
+    /// The bytes are not a valid encoding of a term
+    Invalid,
+    /// The term contains a value with the given tag, which cannot be decoded
+    Unsupported(u8),
+    /// The heap is too small to hold the decoded term
+    Alloc,
+}
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("encoded term is truncated"),
+            Self::Invalid => f.write_str("invalid encoded term"),
+            Self::Unsupported(tag) => write!(f, "terms with tag {} cannot be decoded", tag),
+            Self::Alloc => f.write_str("unable to allocate decoded term"),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+impl From<AllocError> for DecodeError {
+    fn from(_: AllocError) -> Self {
+        Self::Alloc
+    }
+}
+
+/// A destination for encoded terms
+pub trait Sink {
+    type Error;
+
+    /// Writes all of `bytes`
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Writes the bytes of a binary contained in the term being encoded
+    ///
+    /// By default these are written like any other bytes, but a sink may instead hold on to
+    /// `binary`, which keeps its bytes alive, to avoid copying them.
+    fn write_binary(&mut self, binary: PinnedBinary) -> Result<(), Self::Error> {
+        self.write_bytes(&binary)
+    }
+}
+#[cfg(feature = "std")]
+impl<W: std::io::Write + ?Sized> Sink for W {
+    type Error = std::io::Error;
+
+    #[inline]
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write_all(bytes)
+    }
+}
+#[cfg(not(feature = "std"))]
+impl Sink for Vec<u8> {
+    type Error = Infallible;
+
+    #[inline]
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// A sink which collects the encoded term as a sequence of segments, referring to the binaries
+/// it contains rather than copying them, as with `erlang:term_to_iovec/1`
+#[derive(Debug, Default)]
+pub struct IoVec {
+    segments: Vec<Segment>,
+    buffer: Vec<u8>,
+}
+impl IoVec {
+    /// Binaries smaller than this are copied, as the size of a heap binary in BEAM
+    const MIN_SHARED_BYTES: usize = 64;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the segments which make up the encoded term, in order
+    pub fn into_segments(mut self) -> Vec<Segment> {
+        self.flush();
+        self.segments
+    }
+
+    fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            let bytes = mem::take(&mut self.buffer);
+            self.segments.push(Segment::Bytes(bytes));
+        }
+    }
+}
+impl Sink for IoVec {
+    type Error = Infallible;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_binary(&mut self, binary: PinnedBinary) -> Result<(), Self::Error> {
+        if binary.is_copy() || binary.len() < Self::MIN_SHARED_BYTES {
+            return self.write_bytes(&binary);
+        }
+        self.flush();
+        self.segments.push(Segment::Binary(binary));
+        Ok(())
+    }
+}
+
+/// A part of an encoded term collected by [`IoVec`]
+#[derive(Debug)]
+pub enum Segment {
+    /// Bytes written by the encoder
+    Bytes(Vec<u8>),
+    /// The bytes of a binary contained in the term
+    Binary(PinnedBinary),
+}
+impl Deref for Segment {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Bytes(bytes) => bytes.as_slice(),
+            Self::Binary(binary) => binary,
+        }
+    }
+}
+
+/// The result of a call to [`Encoder::encode`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// The term has been fully encoded
+    Done,
+    /// The budget was exhausted before the term was fully encoded
+    Yield,
+}
+
+/// A unit of work remaining to be done by the encoder
+#[derive(Copy, Clone)]
+enum Op {
+    /// Encode the given term
+    Term(Term),
+    /// Encode the remaining elements of a list, starting with the given cell, followed by its tail
+    Elements(Term),
+}
+
+/// Encodes a term in the external term format, incrementally
+pub struct Encoder {
+    stack: Vec<Op>,
+    node: &'static str,
+    creation: u32,
+    started: bool,
+    /// The number of terms encoded so far, i.e. the work done
+    encoded: usize,
+}
+impl Encoder {
+    /// A budget for each call to [`Encoder::encode`] similar to the reductions a process is given
+    /// each time it is scheduled
+    pub const DEFAULT_BUDGET: usize = 4000;
+
+    /// Creates an encoder for `term`, which is written as if on a node which is not distributed
+    pub fn new(term: Term) -> Self {
+        Self {
+            stack: alloc::vec![Op::Term(term)],
+            node: NO_NODE,
+            creation: 0,
+            started: false,
+            encoded: 0,
+        }
+    }
+
+    /// Sets the name and creation of the local node, with which local pids, ports and references
+    /// are encoded
+    pub fn with_node(mut self, name: Atom, creation: u32) -> Self {
+        self.node = name.as_str();
+        self.creation = creation;
+        self
+    }
+
+    /// Returns the number of terms encoded so far, with which the work done can be accounted for
+    pub fn encoded(&self) -> usize {
+        self.encoded
+    }
+
+    /// Returns true if the term has been fully encoded
+    pub fn is_done(&self) -> bool {
+        self.started && self.stack.is_empty()
+    }
+
+    /// Writes the next part of the encoded term to `sink`
+    ///
+    /// Roughly `budget` terms are encoded before this returns [`Status::Yield`], after which it may
+    /// be called again, with the same sink, to continue where it left off.
+    pub fn encode<S: Sink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+        budget: usize,
+    ) -> Result<Status, EncodeError<S::Error>> {
+        if !self.started {
+            write(sink, &[VERSION])?;
+            self.started = true;
+        }
+        let mut remaining = budget.max(1);
+        while let Some(op) = self.stack.pop() {
+            if remaining == 0 {
+                self.stack.push(op);
+                return Ok(Status::Yield);
+            }
+            remaining -= 1;
+            self.encoded += 1;
+            match op {
+                Op::Term(term) => self.encode_term(term, sink)?,
+                Op::Elements(Term::Cons(ptr)) => {
+                    let cell = unsafe { ptr.as_ref() };
+                    self.stack.push(Op::Elements(cell.tail()));
+                    self.stack.push(Op::Term(cell.head()));
+                }
+                // The tail of the list, which is nil if it is proper
+                Op::Elements(tail) => self.encode_term(tail, sink)?,
+            }
+        }
+        Ok(Status::Done)
+    }
+
+    fn encode_term<S: Sink + ?Sized>(
+        &mut self,
+        term: Term,
+        sink: &mut S,
+    ) -> Result<(), EncodeError<S::Error>> {
+        match term {
+            Term::None => Err(EncodeError::Unsupported(TermType::None)),
+            Term::Nil => write(sink, &[NIL_EXT]),
+            Term::Bool(b) => write_atom(sink, if b { "true" } else { "false" }),
+            Term::Atom(atom) => write_atom(sink, atom.as_str()),
+            Term::Int(i) => write_integer(sink, i),
+            Term::BigInt(i) => write_big_integer(sink, &i),
+            Term::Float(f) => {
+                write(sink, &[NEW_FLOAT_EXT])?;
+                write(sink, &f.inner().to_be_bytes())
+            }
+            Term::Cons(ptr) => self.encode_list(ptr, sink),
+            Term::Tuple(ptr) => {
+                let elements = unsafe { ptr.as_ref() }.as_slice();
+                if elements.len() <= u8::MAX as usize {
+                    write(sink, &[SMALL_TUPLE_EXT, elements.len() as u8])?;
+                } else {
+                    write(sink, &[LARGE_TUPLE_EXT])?;
+                    write(sink, &len32(elements.len())?.to_be_bytes())?;
+                }
+                self.stack.extend(
+                    elements
+                        .iter()
+                        .rev()
+                        .map(|element| Op::Term((*element).into())),
+                );
+                Ok(())
+            }
+            Term::Map(map) => {
+                write(sink, &[MAP_EXT])?;
+                write(sink, &len32(map.size())?.to_be_bytes())?;
+                // Reversed, so that the first key is popped first, followed by its value
+                let start = self.stack.len();
+                for (key, value) in map.iter() {
+                    self.stack.push(Op::Term(*key));
+                    self.stack.push(Op::Term(*value));
+                }
+                self.stack[start..].reverse();
+                Ok(())
+            }
+            Term::Closure(fun) => self.encode_closure(&fun, sink),
+            Term::Pid(pid) => {
+                let (node, creation) = self.node(pid.node());
+                let id = pid.id();
+                write(sink, &[NEW_PID_EXT])?;
+                write_atom(sink, node)?;
+                write(sink, &id.number().to_be_bytes())?;
+                write(sink, &id.serial().to_be_bytes())?;
+                write(sink, &creation.to_be_bytes())
+            }
+            Term::Port(port) => {
+                let (id, node) = match &*port {
+                    Port::Local { id } => (*id, None),
+                    Port::External { id, node, .. } => (*id, Some(node.clone())),
+                };
+                let (node, creation) = self.node(node);
+                write(sink, &[V4_PORT_EXT])?;
+                write_atom(sink, node)?;
+                write(sink, &id.as_u64().to_be_bytes())?;
+                write(sink, &creation.to_be_bytes())
+            }
+            Term::Reference(reference) => {
+                let node = match &*reference {
+                    Reference::External { node, .. } => Some(node.clone()),
+                    _ => None,
+                };
+                let (node, creation) = self.node(node);
+                let id = reference.id().as_u64();
+                write(sink, &[NEWER_REFERENCE_EXT])?;
+                write(sink, &2u16.to_be_bytes())?;
+                write_atom(sink, node)?;
+                write(sink, &creation.to_be_bytes())?;
+                write(sink, &(id as u32).to_be_bytes())?;
+                write(sink, &((id >> 32) as u32).to_be_bytes())
+            }
+            Term::HeapBinary(_)
+            | Term::RcBinary(_)
+            | Term::RefBinary(_)
+            | Term::ConstantBinary(_) => encode_bitstring(term, sink),
+        }
+    }
+
+    /// Lists of bytes are encoded as strings, and all others as their length followed by their
+    /// elements and tail
+    ///
+    /// Determining which requires a traversal of the list, which is not interrupted.
+    fn encode_list<S: Sink + ?Sized>(
+        &mut self,
+        ptr: NonNull<Cons>,
+        sink: &mut S,
+    ) -> Result<(), EncodeError<S::Error>> {
+        let mut len = 0;
+        let mut string = Some(Vec::new());
+        for element in unsafe { ptr.as_ref() }.iter() {
+            let Ok(element) = element else { string = None; break; };
+            len += 1;
+            match (element, string.as_mut()) {
+                (Term::Int(i @ 0..=255), Some(bytes)) => bytes.push(i as u8),
+                _ => string = None,
+            }
+        }
+        match string {
+            Some(bytes) if bytes.len() <= u16::MAX as usize => {
+                write(sink, &[STRING_EXT])?;
+                write(sink, &(bytes.len() as u16).to_be_bytes())?;
+                write(sink, bytes.as_slice())
+            }
+            _ => {
+                write(sink, &[LIST_EXT])?;
+                write(sink, &len32(len)?.to_be_bytes())?;
+                self.stack.push(Op::Elements(Term::Cons(ptr)));
+                Ok(())
+            }
+        }
+    }
+
+    /// Captures of exported functions are encoded by name, and all other closures by the index and
+    /// uniq they are registered under, followed by their free variables
+    ///
+    /// The encoding of a closure begins with its size, including that of its free variables, so
+    /// these are encoded up front rather than interleaved with other terms.
+    fn encode_closure<S: Sink + ?Sized>(
+        &mut self,
+        fun: &Closure,
+        sink: &mut S,
+    ) -> Result<(), EncodeError<S::Error>> {
+        let arity = if fun.is_thin() {
+            fun.arity
+        } else {
+            fun.arity - 1
+        };
+        if fun.is_thin() {
+            let mfa = ModuleFunctionArity::new(fun.module, fun.name, arity);
+            let callee = function::find_symbol(&mfa).map(|callee| callee as *const ());
+            if callee == Some(fun.callee()) {
+                write(sink, &[EXPORT_EXT])?;
+                write_atom(sink, fun.module.as_str())?;
+                write_atom(sink, fun.name.as_str())?;
+                return write(sink, &[SMALL_INTEGER_EXT, arity as u8]);
+            }
+        }
+
+        let FunId { index, uniq } = register_fun(fun.module, fun.name, arity as u8, fun.callee());
+        let mut free = Buffer(Vec::new());
+        let env = fun.env().iter().rev().map(|term| Op::Term((*term).into()));
+        let mut encoder = Self {
+            stack: env.collect(),
+            node: self.node,
+            creation: self.creation,
+            started: true,
+            encoded: 0,
+        };
+        encoder.encode(&mut free, usize::MAX).map_err(widen)?;
+        self.encoded += encoder.encoded;
+
+        let num_free = len32(fun.env_size())?;
+        let old_uniq = u32::from_be_bytes(uniq[..4].try_into().unwrap()) >> 5;
+        let mut body = Buffer(Vec::new());
+        body.0.push(arity as u8);
+        body.0.extend_from_slice(&uniq);
+        body.0.extend_from_slice(&index.to_be_bytes());
+        body.0.extend_from_slice(&num_free.to_be_bytes());
+        write_atom(&mut body, fun.module.as_str()).map_err(widen)?;
+        // The old index and uniq, which are no longer used
+        write_integer(&mut body, index as i64).map_err(widen)?;
+        write_integer(&mut body, old_uniq as i64).map_err(widen)?;
+        // The pid of the creator of the fun, which closures do not record
+        body.0.push(NEW_PID_EXT);
+        write_atom(&mut body, self.node).map_err(widen)?;
+        body.0.extend_from_slice(&[0; 8]);
+        body.0.extend_from_slice(&self.creation.to_be_bytes());
+
+        let size = len32(4 + body.0.len() + free.0.len())?;
+        write(sink, &[NEW_FUN_EXT])?;
+        write(sink, &size.to_be_bytes())?;
+        write(sink, &body.0)?;
+        write(sink, &free.0)
+    }
+
+    /// Returns the name and creation of `node`, or of the local node if `None`
+    fn node(&self, node: Option<Arc<Node>>) -> (&'static str, u32) {
+        match node {
+            None => (self.node, self.creation),
+            Some(node) => {
+                let name = node.name().map(|name| name.as_str()).unwrap_or(NO_NODE);
+                (name, node.creation())
+            }
+        }
+    }
+}
+
+/// A sink for parts of an encoded term which must be written out of order
+struct Buffer(Vec<u8>);
+impl Sink for Buffer {
+    type Error = Infallible;
+
+    #[inline]
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Converts an error encoding to a [`Buffer`], which cannot fail to write, to one for any sink
+fn widen<E>(err: EncodeError<Infallible>) -> EncodeError<E> {
+    match err {
+        EncodeError::Sink(err) => match err {},
+        EncodeError::Unsupported(ty) => EncodeError::Unsupported(ty),
+        EncodeError::TooLarge => EncodeError::TooLarge,
+    }
+}
+
+/// Writes the whole of `term` to `sink`, without interruption
+pub fn encode<S: Sink + ?Sized>(term: Term, sink: &mut S) -> Result<(), EncodeError<S::Error>> {
+    let mut encoder = Encoder::new(term);
+    while encoder.encode(sink, usize::MAX)? == Status::Yield {}
+    Ok(())
+}
+
+#[inline]
+fn write<S: Sink + ?Sized>(sink: &mut S, bytes: &[u8]) -> Result<(), EncodeError<S::Error>> {
+    sink.write_bytes(bytes).map_err(EncodeError::Sink)
+}
+
+fn len32<E>(len: usize) -> Result<u32, EncodeError<E>> {
+    u32::try_from(len).map_err(|_| EncodeError::TooLarge)
+}
+
+fn write_atom<S: Sink + ?Sized>(sink: &mut S, name: &str) -> Result<(), EncodeError<S::Error>> {
+    let len = name.len();
+    if len <= u8::MAX as usize {
+        write(sink, &[SMALL_ATOM_UTF8_EXT, len as u8])?;
+    } else {
+        write(sink, &[ATOM_UTF8_EXT])?;
+        write(sink, &(len as u16).to_be_bytes())?;
+    }
+    write(sink, name.as_bytes())
+}
+
+fn write_integer<S: Sink + ?Sized>(sink: &mut S, i: i64) -> Result<(), EncodeError<S::Error>> {
+    if let Ok(byte) = u8::try_from(i) {
+        return write(sink, &[SMALL_INTEGER_EXT, byte]);
+    }
+    if let Ok(i) = i32::try_from(i) {
+        write(sink, &[INTEGER_EXT])?;
+        return write(sink, &i.to_be_bytes());
+    }
+    let magnitude = i.unsigned_abs();
+    let n = 8 - (magnitude.leading_zeros() / 8) as usize;
+    write(sink, &[SMALL_BIG_EXT, n as u8, (i < 0) as u8])?;
+    write(sink, &magnitude.to_le_bytes()[..n])
+}
+
+fn write_big_integer<S: Sink + ?Sized>(
+    sink: &mut S,
+    i: &BigInt,
+) -> Result<(), EncodeError<S::Error>> {
+    let (sign, digits) = i.to_bytes_le();
+    let sign = (sign == Sign::Minus) as u8;
+    if digits.len() <= u8::MAX as usize {
+        write(sink, &[SMALL_BIG_EXT, digits.len() as u8, sign])?;
+    } else {
+        write(sink, &[LARGE_BIG_EXT])?;
+        write(sink, &len32(digits.len())?.to_be_bytes())?;
+        write(sink, &[sign])?;
+    }
+    write(sink, digits.as_slice())
+}
+
+/// Binaries are handed to the sink as such, while the bytes of other bitstrings are copied
+fn encode_bitstring<S: Sink + ?Sized>(
+    term: Term,
+    sink: &mut S,
+) -> Result<(), EncodeError<S::Error>> {
+    let bits = term.as_bitstring().unwrap();
+    let bit_size = bits.bit_size();
+    let len = len32((bit_size + 7) / 8)?;
+    let trailing_bits = (bit_size % 8) as u8;
+    if trailing_bits == 0 {
+        write(sink, &[BINARY_EXT])?;
+        write(sink, &len.to_be_bytes())?;
+        if let Some(binary) = PinnedBinary::new(term) {
+            return sink.write_binary(binary).map_err(EncodeError::Sink);
+        }
+    } else {
+        write(sink, &[BIT_BINARY_EXT])?;
+        write(sink, &len.to_be_bytes())?;
+        write(sink, &[trailing_bits])?;
+    }
+    let bytes = bits.bytes().collect::<Vec<_>>();
+    write(sink, bytes.as_slice())
+}
+
+/// Decodes the term at the start of `bytes` onto `heap`, returning it with the number of bytes read
+pub fn decode<H: Heap>(bytes: &[u8], heap: &H) -> Result<(Term, usize), DecodeError> {
+    let mut decoder = Decoder {
+        bytes,
+        pos: 0,
+        heap,
+    };
+    if decoder.u8()? != VERSION {
+        return Err(DecodeError::Invalid);
+    }
+    let term = decoder.term()?;
+    Ok((term, decoder.pos))
+}
+
+struct Decoder<'a, H: Heap> {
+    bytes: &'a [u8],
+    pos: usize,
+    heap: &'a H,
+}
+impl<'a, H: Heap> Decoder<'a, H> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(DecodeError::Truncated)?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Returns the number of elements with which to preallocate a vector of `len` elements
+    ///
+    /// Every element takes at least a byte, so this guards against lengths which are invalid.
+    fn capacity(&self, len: usize) -> usize {
+        len.min(self.bytes.len() - self.pos)
+    }
+
+    fn term(&mut self) -> Result<Term, DecodeError> {
+        match self.u8()? {
+            SMALL_INTEGER_EXT => Ok(Term::Int(self.u8()? as i64)),
+            INTEGER_EXT => Ok(Term::Int(self.u32()? as i32 as i64)),
+            SMALL_BIG_EXT => {
+                let len = self.u8()? as usize;
+                self.big_integer(len)
+            }
+            LARGE_BIG_EXT => {
+                let len = self.u32()? as usize;
+                self.big_integer(len)
+            }
+            NEW_FLOAT_EXT => {
+                let f = f64::from_be_bytes(self.take(8)?.try_into().unwrap());
+                if !f.is_finite() {
+                    return Err(DecodeError::Invalid);
+                }
+                Ok(Term::Float(f.into()))
+            }
+            SMALL_ATOM_UTF8_EXT => {
+                let len = self.u8()? as usize;
+                self.atom(len)
+            }
+            ATOM_UTF8_EXT => {
+                let len = self.u16()? as usize;
+                self.atom(len)
+            }
+            NIL_EXT => Ok(Term::Nil),
+            STRING_EXT => {
+                let len = self.u16()? as usize;
+                let bytes = self.take(len)?;
+                Ok(Cons::from_bytes(bytes, self.heap)?
+                    .map(Term::Cons)
+                    .unwrap_or(Term::Nil))
+            }
+            LIST_EXT => {
+                let len = self.u32()? as usize;
+                let mut elements = Vec::with_capacity(self.capacity(len));
+                for _ in 0..len {
+                    elements.push(self.term()?);
+                }
+                let mut list = self.term()?;
+                for element in elements.into_iter().rev() {
+                    let cell = Cons::new_in(self.heap)?;
+                    unsafe {
+                        cell.as_ptr().write(Cons {
+                            head: element.into(),
+                            tail: list.into(),
+                        });
+                    }
+                    list = Term::Cons(cell);
+                }
+                Ok(list)
+            }
+            SMALL_TUPLE_EXT => {
+                let len = self.u8()? as usize;
+                self.tuple(len)
+            }
+            LARGE_TUPLE_EXT => {
+                let len = self.u32()? as usize;
+                self.tuple(len)
+            }
+            MAP_EXT => {
+                let len = self.u32()? as usize;
+                let mut pairs = Vec::with_capacity(self.capacity(len));
+                for _ in 0..len {
+                    let key = self.term()?;
+                    let value = self.term()?;
+                    pairs.push((key, value));
+                }
+                Ok(Term::Map(Map::new_from_iter_in(
+                    pairs.into_iter(),
+                    self.heap,
+                )?))
+            }
+            BINARY_EXT => {
+                let len = self.u32()? as usize;
+                Ok(self.take(len)?.into_term(self.heap)?)
+            }
+            BIT_BINARY_EXT => {
+                let len = self.u32()? as usize;
+                if self.u8()? != 8 {
+                    return Err(DecodeError::Unsupported(BIT_BINARY_EXT));
+                }
+                Ok(self.take(len)?.into_term(self.heap)?)
+            }
+            NEW_FUN_EXT => self.closure(),
+            EXPORT_EXT => {
+                let module = self.atom_term()?;
+                let name = self.atom_term()?;
+                let Term::Int(arity @ 0..=255) = self.term()? else { return Err(DecodeError::Invalid); };
+                let mfa = ModuleFunctionArity::new(module, name, arity as usize);
+                let callee = match function::find_symbol(&mfa) {
+                    Some(callee) => callee as *const (),
+                    None => function::undefined_fun as *const (),
+                };
+                let fun = Closure::new_in(module, name, arity as u8, callee, &[], self.heap)?;
+                Ok(Term::Closure(fun))
+            }
+            tag => Err(DecodeError::Unsupported(tag)),
+        }
+    }
+
+    fn big_integer(&mut self, len: usize) -> Result<Term, DecodeError> {
+        let sign = match self.u8()? {
+            0 => Sign::Plus,
+            1 => Sign::Minus,
+            _ => return Err(DecodeError::Invalid),
+        };
+        let i = BigInt::from_bytes_le(sign, self.take(len)?);
+        match i.to_i64().and_then(|i| Term::try_from(i).ok()) {
+            Some(term) => Ok(term),
+            None => Ok(Term::BigInt(GcBox::new_in(i, self.heap)?)),
+        }
+    }
+
+    fn atom(&mut self, len: usize) -> Result<Term, DecodeError> {
+        let name = core::str::from_utf8(self.take(len)?).map_err(|_| DecodeError::Invalid)?;
+        match name {
+            "true" => Ok(Term::Bool(true)),
+            "false" => Ok(Term::Bool(false)),
+            name => Atom::try_from(name)
+                .map(Term::Atom)
+                .map_err(|_| DecodeError::Invalid),
+        }
+    }
+
+    /// Decodes an atom, including `true` and `false`, which are otherwise decoded as booleans
+    fn atom_term(&mut self) -> Result<Atom, DecodeError> {
+        match self.term()? {
+            Term::Atom(atom) => Ok(atom),
+            Term::Bool(true) => Ok(atoms::True),
+            Term::Bool(false) => Ok(atoms::False),
+            _ => Err(DecodeError::Invalid),
+        }
+    }
+
+    fn closure(&mut self) -> Result<Term, DecodeError> {
+        let start = self.pos;
+        let size = self.u32()? as usize;
+        let arity = self.u8()?;
+        let uniq: [u8; 16] = self.take(16)?.try_into().unwrap();
+        let index = self.u32()?;
+        let num_free = self.u32()? as usize;
+        let module = self.atom_term()?;
+        // The old index and uniq, and the pid of the creator, none of which are needed
+        self.term()?;
+        self.term()?;
+        match self.u8()? {
+            NEW_PID_EXT => {
+                self.atom_term()?;
+                self.take(12)?;
+            }
+            tag => return Err(DecodeError::Unsupported(tag)),
+        }
+        let mut env = Vec::with_capacity(self.capacity(num_free));
+        for _ in 0..num_free {
+            env.push(OpaqueTerm::from(self.term()?));
+        }
+        if self.pos - start != size {
+            return Err(DecodeError::Invalid);
+        }
+
+        let (name, callee) = function::find_fun(module, FunId { index, uniq }, arity)
+            .unwrap_or((atoms::Undefined, function::undefined_fun as *const ()));
+        // Closures with free variables take themselves as an extra argument
+        let arity = if env.is_empty() {
+            arity
+        } else {
+            arity.checked_add(1).ok_or(DecodeError::Invalid)?
+        };
+        let fun = Closure::new_in(module, name, arity, callee, env.as_slice(), self.heap)?;
+        Ok(Term::Closure(fun))
+    }
+
+    fn tuple(&mut self, len: usize) -> Result<Term, DecodeError> {
+        let mut elements = Vec::with_capacity(self.capacity(len));
+        for _ in 0..len {
+            elements.push(OpaqueTerm::from(self.term()?));
+        }
+        Ok(Term::Tuple(Tuple::from_slice(
+            elements.as_slice(),
+            self.heap,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::alloc::Global;
+    use core::alloc::Layout;
+
+    use firefly_alloc::fragment::HeapFragment;
+    use firefly_alloc::rc::Rc;
+    use firefly_binary::Binary;
+
+    use crate::function::ErlangResult;
+    use crate::term::{BinaryData, OpaqueTerm, Tuple};
+
+    use super::*;
+
+    fn encode_to_vec(term: Term) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode(term, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn encode_immediate_test() {
+        assert_eq!(encode_to_vec(Term::Int(1)), [131, 97, 1]);
+        assert_eq!(encode_to_vec(Term::Int(-1)), [131, 98, 255, 255, 255, 255]);
+        assert_eq!(
+            encode_to_vec(Term::Int(1 << 40)),
+            [131, 110, 6, 0, 0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(encode_to_vec(Term::Nil), [131, 106]);
+        assert_eq!(
+            encode_to_vec(Term::Bool(true)),
+            [131, 119, 4, b't', b'r', b'u', b'e']
+        );
+        assert_eq!(
+            encode_to_vec(Term::Float(1.5.into())),
+            [131, 70, 63, 248, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn encode_tuple_test() {
+        let elements = [Atom::str_to_term("ok"), OpaqueTerm::from(Term::Int(1))];
+        let tuple = Tuple::from_slice(&elements, Global).unwrap();
+        assert_eq!(
+            encode_to_vec(Term::Tuple(tuple)),
+            [131, 104, 2, 119, 2, b'o', b'k', 97, 1]
+        );
+    }
+
+    #[test]
+    fn encode_list_test() {
+        let last = Cons::cons(Term::Int(99), Term::Nil);
+        let string = Cons::cons(Term::Int(98), Term::Cons(NonNull::from(&last)));
+        assert_eq!(
+            encode_to_vec(Term::Cons(NonNull::from(&string))),
+            [131, 107, 0, 2, 98, 99]
+        );
+
+        let improper = Cons::cons(Term::Int(1000), Term::Int(1));
+        assert_eq!(
+            encode_to_vec(Term::Cons(NonNull::from(&improper))),
+            [131, 108, 0, 0, 0, 1, 98, 0, 0, 3, 232, 97, 1]
+        );
+    }
+
+    #[test]
+    fn encode_binary_test() {
+        let rc = BinaryData::from_str("hi");
+        let term = Term::RcBinary(Rc::into_weak(rc));
+        assert_eq!(encode_to_vec(term), [131, 109, 0, 0, 0, 2, b'h', b'i']);
+    }
+
+    #[test]
+    fn encode_resumable_test() {
+        let last = Cons::cons(Term::Int(1000), Term::Nil);
+        let list = Cons::cons(Term::Int(1000), Term::Cons(NonNull::from(&last)));
+        let term = Term::Cons(NonNull::from(&list));
+
+        let mut encoder = Encoder::new(term);
+        let mut bytes = Vec::new();
+        let mut yields = 0;
+        while encoder.encode(&mut bytes, 1).unwrap() == Status::Yield {
+            yields += 1;
+        }
+        assert!(encoder.is_done());
+        assert!(yields > 1);
+        assert_eq!(bytes, encode_to_vec(term));
+    }
+
+    #[test]
+    fn encode_iovec_test() {
+        let rc = BinaryData::from_str(core::str::from_utf8(&[b'x'; 100]).unwrap());
+        let term = Term::RcBinary(Rc::into_weak(rc.clone()));
+        let mut iovec = IoVec::new();
+        encode(term, &mut iovec).unwrap();
+        let segments = iovec.into_segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(&*segments[0], &[131, 109, 0, 0, 0, 100]);
+        assert!(matches!(&segments[1], Segment::Binary(_)));
+        assert_eq!(segments[1].as_ptr(), rc.as_bytes().as_ptr());
+    }
+
+    #[test]
+    fn decode_round_trip_test() {
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        let fragment = HeapFragment::new(layout, None).unwrap();
+        let heap = unsafe { fragment.as_ref() };
+
+        let last = Cons::cons(Term::Int(1000), Term::Int(1));
+        let list = Cons::cons(Term::Int(-7), Term::Cons(NonNull::from(&last)));
+        let big = GcBox::new_in(BigInt::from(1i128 << 100), heap).unwrap();
+        let elements = [
+            Atom::str_to_term("ok"),
+            OpaqueTerm::from(Term::Cons(NonNull::from(&list))),
+            OpaqueTerm::from(Term::BigInt(big)),
+            OpaqueTerm::from(Term::Float(1.5.into())),
+            OpaqueTerm::from(Term::Bool(true)),
+        ];
+        let tuple = Term::Tuple(Tuple::from_slice(&elements, Global).unwrap());
+        let bytes = encode_to_vec(tuple);
+        let (decoded, len) = decode(bytes.as_slice(), heap).unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!(decoded, tuple);
+
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1], heap),
+            Err(DecodeError::Truncated)
+        );
+        assert_eq!(decode(&[130, 106], heap), Err(DecodeError::Invalid));
+    }
+
+    extern "C" fn adder(_x: OpaqueTerm, _this: OpaqueTerm) -> ErlangResult {
+        ErlangResult::Ok(OpaqueTerm::NIL)
+    }
+
+    #[test]
+    fn closure_round_trip_test() {
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        let fragment = HeapFragment::new(layout, None).unwrap();
+        let heap = unsafe { fragment.as_ref() };
+
+        let module = Atom::try_from("etf_test").unwrap();
+        let name = Atom::try_from("-adder/1-fun-0-").unwrap();
+        let env = [OpaqueTerm::from(Term::Int(1)), Atom::str_to_term("ok")];
+        let fun = Closure::new_in(module, name, 2, adder as *const (), &env, Global).unwrap();
+        let bytes = encode_to_vec(Term::Closure(fun));
+        assert_eq!(bytes[1], NEW_FUN_EXT);
+        assert_eq!(bytes[6], 1);
+
+        let (decoded, len) = decode(bytes.as_slice(), heap).unwrap();
+        assert_eq!(len, bytes.len());
+        let Term::Closure(decoded) = decoded else { panic!("expected closure, got {:?}", decoded); };
+        assert_eq!(decoded.name, name);
+        assert_eq!(decoded.arity, 2);
+        assert_eq!(decoded.callee(), adder as *const ());
+        assert_eq!(decoded.env(), &env);
+
+        // The same fun from another node, or another build, cannot be resolved
+        let mut foreign = bytes;
+        foreign[7] ^= 0xff;
+        let (decoded, _) = decode(foreign.as_slice(), heap).unwrap();
+        let Term::Closure(decoded) = decoded else { panic!("expected closure, got {:?}", decoded); };
+        assert_eq!(decoded.module, module);
+        assert_eq!(decoded.arity, 2);
+        assert_eq!(decoded.callee(), function::undefined_fun as *const ());
+        assert_eq!(decoded.env(), &env);
+    }
+
+    #[test]
+    fn export_round_trip_test() {
+        let layout = Layout::from_size_align(256, 8).unwrap();
+        let fragment = HeapFragment::new(layout, None).unwrap();
+        let heap = unsafe { fragment.as_ref() };
+
+        let bytes = [
+            131, 113, 119, 5, b'l', b'i', b's', b't', b's', 119, 3, b'm', b'a', b'p', 97, 2,
+        ];
+        let (decoded, len) = decode(&bytes, heap).unwrap();
+        assert_eq!(len, bytes.len());
+        let Term::Closure(decoded) = decoded else { panic!("expected closure, got {:?}", decoded); };
+        assert_eq!(decoded.module.as_str(), "lists");
+        assert_eq!(decoded.name.as_str(), "map");
+        assert_eq!(decoded.arity, 2);
+        assert!(decoded.is_thin());
+        // Not linked into the tests, so calling it raises undef
+        assert_eq!(decoded.callee(), function::undefined_fun as *const ());
+    }
+}
@@ -1,6 +1,11 @@
 mod dynamic;
+mod tail;
 
 pub use self::dynamic::DynamicCallee;
+pub use self::tail::{
+    is_tail_call, resolve as resolve_tail_call, resume as resume_tail_call,
+    schedule as schedule_tail_call, TAIL_CALL,
+};
 
 use core::alloc::Layout;
 use core::mem;
@@ -12,9 +17,10 @@ use lazy_static::lazy_static;
 use firefly_arena::DroplessArena;
 use firefly_system::sync::RwLock;
 
+use crate::error::unwind;
 use crate::term::{Atom, OpaqueTerm};
 
-use super::{ErlangResult, FunctionSymbol, ModuleFunctionArity};
+use super::{ErlangResult, FunctionSymbol, ModuleFunctionArity, StaticNif};
 
 lazy_static! {
     /// The symbol table used by the runtime system
@@ -31,20 +37,25 @@ lazy_static! {
 ///   - Accepts only immediate-sized terms as arguments
 ///   - Returns an immediate-sized term as a result
 ///
-/// This function returns `Err` if the called function returns the NONE value,
-/// or if the given symbol doesn't exist.
+/// This function returns `Err` if the given symbol doesn't exist. Any tail call scheduled by the
+/// called function is made before returning, so the result is never [`TAIL_CALL`]. An exception
+/// raised by the called function is returned as `Err`, see [`crate::error::unwind`].
 ///
 /// This function will panic if the symbol table has not been initialized.
 pub fn apply(symbol: &ModuleFunctionArity, args: &[OpaqueTerm]) -> Result<ErlangResult, ()> {
     if let Some(f) = find_symbol(symbol) {
-        Ok(unsafe { dynamic::apply(f, args.as_ptr(), args.len()) })
+        Ok(unwind::catch(|| unsafe {
+            tail::resolve(dynamic::apply(f, args.as_ptr(), args.len()))
+        }))
     } else {
         Err(())
     }
 }
 
+/// Dynamically invokes `callee`, making any tail call it schedules before returning, and returning
+/// any exception it raises as `Err`
 pub unsafe fn apply_callee(callee: DynamicCallee, args: &[OpaqueTerm]) -> ErlangResult {
-    dynamic::apply(callee, args.as_ptr(), args.len())
+    unwind::catch(|| tail::resolve(dynamic::apply(callee, args.as_ptr(), args.len())))
 }
 
 pub fn find_symbol(mfa: &ModuleFunctionArity) -> Option<DynamicCallee> {
@@ -59,6 +70,16 @@ pub fn module_loaded(module: Atom) -> bool {
     SYMBOLS.read().contains_module(module)
 }
 
+/// Returns the number of functions in the symbol table for which `filter` returns true
+pub fn count_symbols<F: Fn(&ModuleFunctionArity) -> bool>(filter: F) -> usize {
+    SYMBOLS
+        .read()
+        .functions
+        .keys()
+        .filter(|mfa| filter(mfa))
+        .count()
+}
+
 /// Performs one-time initialization of the atom table at program start, using the
 /// array of constant atom values present in the compiled program.
 ///
@@ -110,6 +131,68 @@ pub unsafe extern "C-unwind" fn init(
     true
 }
 
+/// Registers the native functions linked into the executable ahead-of-time in the dispatch table.
+///
+/// This must be called after the dispatch table is initialized. A native function replaces any
+/// definition of the same function provided by compiled Erlang code, e.g. the stub body of a
+/// function declared in `-nifs`.
+///
+/// Returns false if the given range is invalid, or if a native function has an invalid name.
+///
+/// # Safety
+///
+/// `start` and `end` must delimit the native functions section of the executable.
+#[export_name = "__firefly_register_static_nifs"]
+pub unsafe extern "C-unwind" fn register_static_nifs(
+    start: *const StaticNif,
+    end: *const StaticNif,
+) -> bool {
+    // Both are null when no native functions were linked in, as the section will not exist
+    if start == end {
+        return true;
+    }
+    if start.is_null() || end.is_null() {
+        return false;
+    }
+
+    debug_assert_eq!(
+        ((end as usize) - (start as usize)) % mem::size_of::<StaticNif>(),
+        0,
+        "invalid static nif range"
+    );
+
+    let len = end.offset_from(start);
+    let data = slice::from_raw_parts::<'static, _>(start, len as usize);
+
+    let mut table = SYMBOLS.write();
+    for nif in data.iter() {
+        let Ok(module) = Atom::try_from(nif.module) else { return false; };
+        let Ok(function) = Atom::try_from(nif.function) else { return false; };
+        let mfa = ModuleFunctionArity {
+            module,
+            function,
+            arity: nif.arity,
+        };
+        let callee = nif.ptr;
+        let sym = match table.functions.get_key_value(&mfa) {
+            Some((sym, _)) => *sym,
+            None => {
+                let ptr = table.arena.alloc_raw(Layout::new::<ModuleFunctionArity>())
+                    as *mut ModuleFunctionArity;
+                ptr.write(mfa);
+                mem::transmute::<&ModuleFunctionArity, &'static ModuleFunctionArity>(&*ptr)
+            }
+        };
+        if let Some(replaced) = table.functions.insert(sym, callee) {
+            table.idents.remove(&replaced);
+        }
+        table.idents.insert(callee, sym);
+        table.modules.insert(module);
+    }
+
+    true
+}
+
 struct SymbolTable {
     functions: HashMap<&'static ModuleFunctionArity, *const ()>,
     idents: HashMap<*const (), &'static ModuleFunctionArity>,
@@ -0,0 +1,166 @@
+//! This module implements the trampoline used for tail calls which cannot be made natively.
+//!
+//! Generated code performs a call in tail position as a native tail call whenever it can be made
+//! `musttail`, i.e. when the callee takes as many arguments as its caller, or fewer where the
+//! target permits padding them, see `crate::abi`. Any other tail call could grow the stack, so the
+//! compiler instead emits a call to [`schedule`], which records the callee and its arguments in a
+//! per-thread slot, and returns [`TAIL_CALL`] in place of a result.
+//!
+//! Every call site which is not itself in tail position checks for that marker, and when found,
+//! calls [`resume`], which performs the scheduled call, and any calls it schedules in turn, until
+//! one produces a real result. The native stack therefore never holds more than one frame for a
+//! chain of tail calls, no matter how they are made.
+//!
+//! Native code calling into generated code must do the same, which [`super::apply`],
+//! [`super::apply_callee`], and calls through a `Closure` take care of, and which is otherwise
+//! done using [`resolve`].
+use core::ptr;
+
+use crate::abi::MAX_ARITY;
+use crate::function::ErlangResult;
+use crate::term::OpaqueTerm;
+
+use super::{dynamic, DynamicCallee};
+
+/// The result returned by a function which has scheduled a tail call rather than making it
+pub const TAIL_CALL: ErlangResult = ErlangResult::Ok(OpaqueTerm::NONE);
+
+struct PendingCall {
+    callee: Option<DynamicCallee>,
+    argc: usize,
+    argv: [OpaqueTerm; MAX_ARITY],
+}
+
+/// At most one tail call is ever pending, as scheduling one is immediately followed by a return
+/// to the caller which will make it.
+#[thread_local]
+static mut PENDING: PendingCall = PendingCall {
+    callee: None,
+    argc: 0,
+    argv: [OpaqueTerm::NONE; MAX_ARITY],
+};
+
+/// Returns true if the given result is the marker for a scheduled tail call
+#[inline]
+pub fn is_tail_call(result: &ErlangResult) -> bool {
+    match result {
+        ErlangResult::Ok(term) => term.is_none(),
+        ErlangResult::Err(_) => false,
+    }
+}
+
+/// Schedules a tail call to `callee` with the given arguments, returning [`TAIL_CALL`]
+///
+/// # Safety
+///
+/// The caller must immediately return the result of this function, and `argv` must point to
+/// `argc` terms, where `argc` is the arity of `callee`.
+#[export_name = "__firefly_tail_call"]
+pub unsafe extern "C-unwind" fn schedule(
+    callee: DynamicCallee,
+    argv: *const OpaqueTerm,
+    argc: usize,
+) -> ErlangResult {
+    assert!(argc <= MAX_ARITY, "invalid arity for tail call");
+    let pending = &mut PENDING;
+    debug_assert!(pending.callee.is_none(), "a tail call is already pending");
+    ptr::copy_nonoverlapping(argv, pending.argv.as_mut_ptr(), argc);
+    pending.argc = argc;
+    pending.callee = Some(callee);
+    TAIL_CALL
+}
+
+/// Performs the pending tail call, and any tail calls it schedules, returning the first real result
+///
+/// # Safety
+///
+/// This must only be called after receiving [`TAIL_CALL`] as the result of a call.
+#[export_name = "__firefly_resume_tail_call"]
+pub unsafe extern "C-unwind" fn resume() -> ErlangResult {
+    // The slot is reused by any tail call the callee schedules, so the arguments are moved out
+    // of it first
+    let mut argv = [OpaqueTerm::NONE; MAX_ARITY];
+    loop {
+        let pending = &mut PENDING;
+        let callee = pending.callee.take().expect("no tail call is pending");
+        let argc = pending.argc;
+        argv[..argc].copy_from_slice(&pending.argv[..argc]);
+        let result = dynamic::apply(callee, argv.as_ptr(), argc);
+        if !is_tail_call(&result) {
+            return result;
+        }
+    }
+}
+
+/// Returns the real result of a call, making any tail call it scheduled
+///
+/// # Safety
+///
+/// This must be called with the result of a call to an Erlang function, before any other call.
+#[inline]
+pub unsafe fn resolve(result: ErlangResult) -> ErlangResult {
+    if is_tail_call(&result) {
+        resume()
+    } else {
+        result
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64", unix))]
+mod tests {
+    use core::mem;
+
+    use super::*;
+
+    const ITERATIONS: i64 = 10_000_000;
+
+    type Fun2 = extern "C-unwind" fn(OpaqueTerm, OpaqueTerm) -> ErlangResult;
+
+    fn int(i: i64) -> OpaqueTerm {
+        i.try_into().unwrap()
+    }
+
+    fn tail_call(f: Fun2, args: [OpaqueTerm; 2]) -> ErlangResult {
+        let callee = unsafe { mem::transmute::<Fun2, DynamicCallee>(f) };
+        unsafe { schedule(callee, args.as_ptr(), args.len()) }
+    }
+
+    fn apply(f: Fun2, args: [OpaqueTerm; 2]) -> ErlangResult {
+        let callee = unsafe { mem::transmute::<Fun2, DynamicCallee>(f) };
+        unsafe { super::super::apply_callee(callee, &args) }
+    }
+
+    extern "C-unwind" fn countdown(n: OpaqueTerm, acc: OpaqueTerm) -> ErlangResult {
+        match n.as_integer() {
+            0 => ErlangResult::Ok(acc),
+            n => tail_call(countdown, [int(n - 1), int(acc.as_integer() + 1)]),
+        }
+    }
+
+    extern "C-unwind" fn even(n: OpaqueTerm, count: OpaqueTerm) -> ErlangResult {
+        match n.as_integer() {
+            0 => ErlangResult::Ok(count),
+            n => tail_call(odd, [int(n - 1), count]),
+        }
+    }
+
+    extern "C-unwind" fn odd(n: OpaqueTerm, count: OpaqueTerm) -> ErlangResult {
+        let count = count.as_integer() + 1;
+        match n.as_integer() {
+            0 => ErlangResult::Ok(int(count)),
+            n => tail_call(even, [int(n - 1), int(count)]),
+        }
+    }
+
+    #[test]
+    fn self_tail_calls_do_not_grow_the_stack() {
+        let result = apply(countdown, [int(ITERATIONS), int(0)]);
+        assert_eq!(result, ErlangResult::Ok(int(ITERATIONS)));
+    }
+
+    #[test]
+    fn mutually_recursive_tail_calls_do_not_grow_the_stack() {
+        let result = apply(even, [int(ITERATIONS), int(0)]);
+        assert_eq!(result, ErlangResult::Ok(int(ITERATIONS / 2)));
+    }
+}
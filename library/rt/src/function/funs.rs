@@ -0,0 +1,125 @@
+//! The registry of funs which have been encoded in the external term format.
+//!
+//! A closure knows only the native entry point of its body, whereas the external term format
+//! identifies a fun by its module, an index and a 16-byte uniq value. Each fun encoded is
+//! registered here under the next free index, with a uniq derived from its module, name and arity,
+//! so that decoding it on the same node resolves it back to the same entry point.
+//!
+//! A fun encoded on another node resolves only if a fun with the same module, name and arity was
+//! registered under the same index here, and is otherwise decoded as a fun which raises `undef`
+//! when called, see [`undefined_fun`].
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+
+use firefly_system::sync::RwLock;
+
+use crate::backtrace::Trace;
+use crate::term::{atoms, Atom};
+
+use super::ErlangResult;
+
+lazy_static! {
+    static ref FUNS: RwLock<FunTable> = Default::default();
+}
+
+/// Identifies a fun in the external term format
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FunId {
+    pub index: u32,
+    pub uniq: [u8; 16],
+}
+
+/// Returns the identifier of the fun with the given name, arity and entry point, registering it
+/// if it has not been already
+///
+/// The arity is that of the fun as seen by its callers, i.e. excluding the closure itself.
+pub fn register_fun(module: Atom, name: Atom, arity: u8, callee: *const ()) -> FunId {
+    if let Some(id) = FUNS.read().get(callee) {
+        return id;
+    }
+    let mut table = FUNS.write();
+    // The fun may have been registered while the lock was released
+    if let Some(id) = table.get(callee) {
+        return id;
+    }
+    let uniq = uniq(module, name, arity);
+    let index = table.funs.len() as u32;
+    table.funs.push(FunEntry {
+        module,
+        name,
+        arity,
+        uniq,
+        callee,
+    });
+    table.indices.insert(callee, index);
+    FunId { index, uniq }
+}
+
+/// Returns the name and entry point of the fun identified by `id` in `module`, which takes `arity`
+/// arguments, if one has been registered
+pub fn find_fun(module: Atom, id: FunId, arity: u8) -> Option<(Atom, *const ())> {
+    let table = FUNS.read();
+    let entry = table.funs.get(id.index as usize)?;
+    if entry.module != module || entry.uniq != id.uniq || entry.arity != arity {
+        return None;
+    }
+    Some((entry.name, entry.callee))
+}
+
+/// The entry point of funs decoded from the external term format which do not exist on this node
+///
+/// It raises `undef` regardless of how many arguments it is called with, which the C calling
+/// convention permits, as the caller is responsible for its arguments.
+pub extern "C-unwind" fn undefined_fun() -> ErlangResult {
+    ErlangResult::raise(atoms::Error, atoms::Undef.into(), Trace::capture())
+}
+
+/// Derives the uniq of a fun from its module, name and arity, using the 128-bit FNV-1a hash, so
+/// that funs with the same index on different nodes are only confused if they are the same fun
+fn uniq(module: Atom, name: Atom, arity: u8) -> [u8; 16] {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    let mut hash = OFFSET;
+    let bytes = module
+        .as_str()
+        .bytes()
+        .chain(core::iter::once(b':'))
+        .chain(name.as_str().bytes())
+        .chain(core::iter::once(b'/'))
+        .chain(core::iter::once(arity));
+    for byte in bytes {
+        hash ^= byte as u128;
+        hash = hash.wrapping_mul(PRIME);
+    }
+    hash.to_be_bytes()
+}
+
+struct FunEntry {
+    module: Atom,
+    name: Atom,
+    arity: u8,
+    uniq: [u8; 16],
+    callee: *const (),
+}
+
+#[derive(Default)]
+struct FunTable {
+    funs: Vec<FunEntry>,
+    indices: HashMap<*const (), u32>,
+}
+impl FunTable {
+    fn get(&self, callee: *const ()) -> Option<FunId> {
+        let index = *self.indices.get(&callee)?;
+        Some(FunId {
+            index,
+            uniq: self.funs[index as usize].uniq,
+        })
+    }
+}
+
+// These are safe to implement because the entry points in the table are static
+unsafe impl Sync for FunTable {}
+unsafe impl Send for FunTable {}
@@ -1,18 +1,28 @@
 mod apply;
+mod funs;
 mod mfa;
 
 pub use self::apply::*;
+pub use self::funs::{find_fun, register_fun, undefined_fun, FunId};
 pub use self::mfa::ModuleFunctionArity;
 
+use alloc::sync::Arc;
 use core::convert::Infallible;
 use core::fmt;
 use core::ops::{self, ControlFlow};
 use core::ptr::NonNull;
 
+use crate::backtrace::Trace;
 use crate::error::ErlangException;
-use crate::term::{Atom, OpaqueTerm};
+use crate::term::{Atom, OpaqueTerm, Term};
 
 /// This type reflects the implicit return type expected by the Erlang calling convention
+///
+/// When used with the default type parameters, the `Err` variant carries an owned pointer to
+/// an `ErlangException`, which is how native functions raise exceptions, while generated code
+/// raises them by unwinding, see [`crate::error::unwind`]. Generated code still checks the
+/// discriminant after every call which may raise, and branches to the active handler (or
+/// propagates to its caller) with the exception pointer when it is `Err`.
 #[derive(Debug, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(u8)]
 pub enum ErlangResult<T = OpaqueTerm, E = NonNull<ErlangException>> {
@@ -141,12 +151,37 @@ impl<T, E> ErlangResult<T, E> {
         debug_assert!(self.is_err());
         match self {
             // SAFETY: the safety contract must be upheld by the caller.
-            Self::Ok(_) => unsafe { core::hint::unreachable_unchecked() },
+            Self::Ok(_) => core::hint::unreachable_unchecked(),
             Self::Err(e) => e,
         }
     }
 }
 
+impl<T> ErlangResult<T> {
+    /// Raises an exception of class `kind` with the given reason from a native function.
+    ///
+    /// The reason may be allocated on the calling process' heap. Generated code does not
+    /// distinguish between exceptions raised by natives and those raised in Erlang, the
+    /// error value is handed to the nearest landing pad in the caller either way.
+    #[inline]
+    pub fn raise(kind: Atom, reason: Term, trace: Arc<Trace>) -> Self {
+        debug_assert!(ErlangException::is_valid_kind(kind));
+        Self::Err(ErlangException::new(kind, reason, trace).into_raw())
+    }
+
+    /// Raises an `error` exception with extended error information, as by `erlang:error/3`
+    #[inline]
+    pub fn raise_with_error_info(reason: Term, error_info: Term, trace: Arc<Trace>) -> Self {
+        Self::Err(ErlangException::with_error_info(reason, error_info, trace).into_raw())
+    }
+
+    /// Raises a `badarg` error
+    #[inline]
+    pub fn badarg(trace: Arc<Trace>) -> Self {
+        Self::Err(ErlangException::badarg(trace).into_raw())
+    }
+}
+
 impl<T, E> ops::Try for ErlangResult<T, E> {
     type Output = T;
     type Residual = ErlangResult<Infallible, E>;
@@ -228,3 +263,46 @@ unsafe impl Sync for FunctionSymbol {}
 
 /// Function symbols are read-only and pinned, and therefore Send
 unsafe impl Send for FunctionSymbol {}
+
+/// This struct represents a native implementation of an Erlang function, linked into the
+/// executable ahead-of-time, and registered with the dispatch table at startup.
+///
+/// Unlike `FunctionSymbol`, the module and function names are stored as strings, as crates
+/// defining native functions have no way to emit atoms which the linker will merge with those
+/// generated by the compiler. Records of this type are placed in the `__firefly_nifs` section
+/// (`__DATA,__firefly_nifs` on Mach-O) by the `firefly_nif::nif` attribute macro.
+///
+/// NOTE: This struct must have a size that is a power of 8
+#[repr(C, align(8))]
+#[derive(Debug, Clone, Copy)]
+pub struct StaticNif {
+    /// Module name
+    pub module: &'static str,
+    /// Function name
+    pub function: &'static str,
+    /// The arity of the function
+    pub arity: u8,
+    /// An opaque pointer to the function, see `FunctionSymbol::ptr`
+    pub ptr: *const (),
+}
+impl StaticNif {
+    pub const fn new(
+        module: &'static str,
+        function: &'static str,
+        arity: u8,
+        ptr: *const (),
+    ) -> Self {
+        Self {
+            module,
+            function,
+            arity,
+            ptr,
+        }
+    }
+}
+
+/// Static NIFs are read-only and pinned, and therefore Sync
+unsafe impl Sync for StaticNif {}
+
+/// Static NIFs are read-only and pinned, and therefore Send
+unsafe impl Send for StaticNif {}
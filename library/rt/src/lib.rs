@@ -3,6 +3,8 @@
 #![feature(linkage)]
 // Used for syntax sugar
 #![feature(let_else)]
+// Used for the pending tail call slot
+#![feature(thread_local)]
 // Used for the `unlikely` compiler hint
 #![feature(core_intrinsics)]
 // Used for custom allocators
@@ -51,10 +53,17 @@ extern crate std;
 #[cfg(test)]
 extern crate test;
 
+pub mod abi;
+pub mod atomics;
 pub mod backtrace;
+pub mod boot;
 pub mod cmp;
 pub mod error;
+pub mod etf;
 pub mod function;
 pub mod intrinsics;
+pub mod match_spec;
 pub mod process;
+#[cfg(feature = "serde")]
+pub mod serde;
 pub mod term;
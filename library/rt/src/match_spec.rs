@@ -0,0 +1,687 @@
+//! Match specifications, the small programs used by `erlang:trace_pattern/3` to select the calls
+//! to trace and the actions to take when they are.
+//!
+//! A match specification is a list of clauses `{Head, Guards, Body}`, tried in order:
+//!
+//! * `Head` is a pattern matched against the term given to the specification, e.g. the list of
+//! arguments of a call. The atoms `'$1'`, `'$2'`, and so on, are variables, bound where they first
+//! occur and compared with their binding after that, and `'_'` matches anything.
+//! * `Guards` is a list of expressions, all of which must evaluate to `true` for the clause to be
+//! selected. A guard which fails, e.g. `{hd, '$1'}` when `'$1'` is not a list, is not `true`.
+//! * `Body` is a list of expressions, the value of the last of which is the result of the match.
+//! This is where the action functions `return_trace/0`, `exception_trace/0` and `message/1` are
+//! called.
+//!
+//! Expressions are variables, `'$_'` for the whole term matched, `'$$'` for the list of all the
+//! variables bound, in the order of their numbers, `{const, Term}` for a term to take as is,
+//! `{{Expr, ...}}` for a tuple, lists of expressions, and calls `{Function, Expr, ...}` of the
+//! guard functions below. Any other term is a constant.
+//!
+//! Arithmetic is supported on small integers and floats, and fails on anything else, including
+//! an overflow, rather than promoting to a big integer.
+//!
+//! Specifications are compiled once with [`MatchSpec::compile`], which rejects anything invalid,
+//! and run with [`MatchSpec::run`]. The terms a specification contains are referenced rather than
+//! copied, so they must outlive it, which is why callers compile specifications made of global
+//! terms.
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::alloc::AllocError;
+use core::fmt;
+
+use firefly_alloc::heap::Heap;
+
+use crate::term::__support as support;
+use crate::term::{Atom, Cons, IntoTerm, Term};
+
+/// The guard functions which may be called from a match specification, with their arities
+const FUNCTIONS: &[(&str, usize)] = &[
+    ("is_atom", 1),
+    ("is_binary", 1),
+    ("is_float", 1),
+    ("is_function", 1),
+    ("is_integer", 1),
+    ("is_list", 1),
+    ("is_map", 1),
+    ("is_number", 1),
+    ("is_pid", 1),
+    ("is_port", 1),
+    ("is_reference", 1),
+    ("is_tuple", 1),
+    ("not", 1),
+    ("and", 2),
+    ("or", 2),
+    ("xor", 2),
+    ("==", 2),
+    ("/=", 2),
+    ("=:=", 2),
+    ("=/=", 2),
+    ("<", 2),
+    (">", 2),
+    ("=<", 2),
+    (">=", 2),
+    ("+", 2),
+    ("-", 2),
+    ("*", 2),
+    ("div", 2),
+    ("rem", 2),
+    ("-", 1),
+    ("abs", 1),
+    ("element", 2),
+    ("hd", 1),
+    ("tl", 1),
+    ("length", 1),
+    ("tuple_size", 1),
+    ("map_size", 1),
+    ("map_get", 2),
+    ("is_map_key", 2),
+];
+
+/// The reason a term is not a valid match specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchSpecError {
+    /// The specification is not a proper list of clauses
+    NotAList,
+    /// A clause is not a tuple of a head, a list of guards and a list of body expressions
+    InvalidClause,
+    /// A variable is used which is not bound by the head
+    UnboundVariable(Atom),
+    /// A function is called which is not a guard function, or an action outside of a body
+    UnknownFunction(Atom, usize),
+    /// A tuple expression is neither `{{...}}`, `{const, _}`, nor a call
+    InvalidExpression,
+}
+impl fmt::Display for MatchSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotAList => f.write_str("a match specification must be a list of clauses"),
+            Self::InvalidClause => f.write_str("a clause must be a tuple of {Head, Guards, Body}"),
+            Self::UnboundVariable(var) => write!(f, "variable '{}' is not bound by the head", var),
+            Self::UnknownFunction(name, arity) => {
+                write!(f, "'{}'/{} cannot be called here", name, arity)
+            }
+            Self::InvalidExpression => f.write_str("invalid tuple expression"),
+        }
+    }
+}
+
+/// The actions called by the body of the clause which matched
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Actions {
+    /// `return_trace/0` was called
+    pub return_trace: bool,
+    /// `exception_trace/0` was called
+    pub exception_trace: bool,
+    /// The argument of the last call to `message/1`, if any
+    pub message: Option<Term>,
+}
+
+/// The outcome of a successful match
+#[derive(Debug, Clone, Copy)]
+pub struct Match {
+    /// The value of the last expression of the body, or `'EXIT'` if the body failed
+    pub result: Term,
+    /// The actions called by the body
+    pub actions: Actions,
+}
+
+/// A compiled match specification
+#[derive(Debug)]
+pub struct MatchSpec {
+    clauses: Vec<Clause>,
+}
+impl MatchSpec {
+    /// Compiles `spec`, a list of `{Head, Guards, Body}` clauses
+    pub fn compile(spec: Term) -> Result<Self, MatchSpecError> {
+        let clauses = proper_list(spec)
+            .ok_or(MatchSpecError::NotAList)?
+            .into_iter()
+            .map(Clause::compile)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { clauses })
+    }
+
+    /// Runs this specification against `term`, allocating the result on `heap`
+    ///
+    /// Returns `None` if no clause matched.
+    pub fn run<H: Heap>(&self, term: Term, heap: &H) -> Result<Option<Match>, AllocError> {
+        for clause in self.clauses.iter() {
+            let mut bindings = BTreeMap::new();
+            if !clause.head.matches(term, &mut bindings) {
+                continue;
+            }
+            let mut env = Env {
+                term,
+                bindings,
+                heap,
+                actions: Actions::default(),
+            };
+            if !env.guards(clause.guards.as_slice())? {
+                continue;
+            }
+            // An empty body, as is common in trace patterns, simply selects
+            let mut result = Term::Bool(true);
+            for expr in clause.body.iter() {
+                match env.eval(expr) {
+                    Ok(value) => result = value,
+                    Err(Fail::Badarg) => {
+                        result = Term::Atom(Atom::try_from("EXIT").unwrap());
+                        break;
+                    }
+                    Err(Fail::Alloc(err)) => return Err(err),
+                }
+            }
+            return Ok(Some(Match {
+                result,
+                actions: env.actions,
+            }));
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Debug)]
+struct Clause {
+    head: Pattern,
+    guards: Vec<Expr>,
+    body: Vec<Expr>,
+}
+impl Clause {
+    fn compile(clause: Term) -> Result<Self, MatchSpecError> {
+        let tuple = clause.as_tuple().ok_or(MatchSpecError::InvalidClause)?;
+        let [head, guards, body] = tuple.as_slice() else {
+            return Err(MatchSpecError::InvalidClause);
+        };
+        let mut bound = Vec::new();
+        let head = Pattern::compile((*head).into(), &mut bound);
+        let guards = proper_list((*guards).into()).ok_or(MatchSpecError::InvalidClause)?;
+        let body = proper_list((*body).into()).ok_or(MatchSpecError::InvalidClause)?;
+        let guards = guards
+            .into_iter()
+            .map(|guard| Expr::compile(guard, &bound, false))
+            .collect::<Result<Vec<_>, _>>()?;
+        let body = body
+            .into_iter()
+            .map(|expr| Expr::compile(expr, &bound, true))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { head, guards, body })
+    }
+}
+
+#[derive(Debug)]
+enum Pattern {
+    Any,
+    Var(u32),
+    Const(Term),
+    Tuple(Vec<Pattern>),
+    Cons(Box<Pattern>, Box<Pattern>),
+    Map(Vec<(Term, Pattern)>),
+}
+impl Pattern {
+    fn compile(term: Term, bound: &mut Vec<u32>) -> Self {
+        match term {
+            Term::Atom(atom) if atom.as_str() == "_" => Self::Any,
+            Term::Atom(atom) => match variable(atom) {
+                Some(var) => {
+                    bound.push(var);
+                    Self::Var(var)
+                }
+                None => Self::Const(term),
+            },
+            Term::Tuple(_) => {
+                let elements = term.as_tuple().unwrap().as_slice();
+                let elements = elements.iter().map(|e| Self::compile((*e).into(), bound));
+                Self::Tuple(elements.collect())
+            }
+            Term::Cons(cons) => {
+                let cons = unsafe { cons.as_ref() };
+                let head = Self::compile(cons.head(), bound);
+                let tail = Self::compile(cons.tail(), bound);
+                Self::Cons(Box::new(head), Box::new(tail))
+            }
+            Term::Map(ref map) => {
+                let entries = map.iter().map(|(k, v)| (*k, Self::compile(*v, bound)));
+                Self::Map(entries.collect())
+            }
+            term => Self::Const(term),
+        }
+    }
+
+    fn matches(&self, term: Term, bindings: &mut BTreeMap<u32, Term>) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Var(var) => match bindings.get(var) {
+                Some(bound) => bound.exact_eq(&term),
+                None => {
+                    bindings.insert(*var, term);
+                    true
+                }
+            },
+            Self::Const(value) => value.exact_eq(&term),
+            Self::Tuple(patterns) => {
+                let Some(tuple) = term.as_tuple() else { return false; };
+                let elements = tuple.as_slice();
+                elements.len() == patterns.len()
+                    && patterns
+                        .iter()
+                        .zip(elements.iter())
+                        .all(|(pattern, e)| pattern.matches((*e).into(), bindings))
+            }
+            Self::Cons(head, tail) => {
+                let Term::Cons(cons) = term else { return false; };
+                let cons = unsafe { cons.as_ref() };
+                head.matches(cons.head(), bindings) && tail.matches(cons.tail(), bindings)
+            }
+            Self::Map(entries) => {
+                let Some(map) = term.as_map() else { return false; };
+                entries.iter().all(|(key, pattern)| match map.get(*key) {
+                    Some(value) => pattern.matches(value, bindings),
+                    None => false,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    ReturnTrace,
+    ExceptionTrace,
+    Message,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Var(u32),
+    Whole,
+    Bindings,
+    Const(Term),
+    Tuple(Vec<Expr>),
+    Cons(Box<Expr>, Box<Expr>),
+    Call(Atom, Vec<Expr>),
+    Action(Action, Vec<Expr>),
+}
+impl Expr {
+    fn compile(term: Term, bound: &[u32], in_body: bool) -> Result<Self, MatchSpecError> {
+        let compile_all = |terms: &[crate::term::OpaqueTerm]| {
+            terms
+                .iter()
+                .map(|t| Self::compile((*t).into(), bound, in_body))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match term {
+            Term::Atom(atom) => match atom.as_str() {
+                "$_" => Ok(Self::Whole),
+                "$$" => Ok(Self::Bindings),
+                _ => match variable(atom) {
+                    Some(var) if bound.contains(&var) => Ok(Self::Var(var)),
+                    Some(_) => Err(MatchSpecError::UnboundVariable(atom)),
+                    None => Ok(Self::Const(term)),
+                },
+            },
+            Term::Tuple(_) => {
+                let elements = term.as_tuple().unwrap().as_slice();
+                let Some((first, args)) = elements.split_first() else {
+                    return Err(MatchSpecError::InvalidExpression);
+                };
+                let first: Term = (*first).into();
+                if args.is_empty() {
+                    if let Some(tuple) = first.as_tuple() {
+                        return Ok(Self::Tuple(compile_all(tuple.as_slice())?));
+                    }
+                }
+                let name = match first {
+                    Term::Atom(name) => name,
+                    Term::Bool(b) => Atom::try_from(if b { "true" } else { "false" }).unwrap(),
+                    _ => return Err(MatchSpecError::InvalidExpression),
+                };
+                let arity = args.len();
+                match (name.as_str(), arity) {
+                    ("const", 1) => Ok(Self::Const(args[0].into())),
+                    ("return_trace", 0) if in_body => {
+                        Ok(Self::Action(Action::ReturnTrace, Vec::new()))
+                    }
+                    ("exception_trace", 0) if in_body => {
+                        Ok(Self::Action(Action::ExceptionTrace, Vec::new()))
+                    }
+                    ("message", 1) if in_body => {
+                        Ok(Self::Action(Action::Message, compile_all(args)?))
+                    }
+                    ("andalso" | "orelse", n) if n > 0 => Ok(Self::Call(name, compile_all(args)?)),
+                    (f, n) if FUNCTIONS.contains(&(f, n)) => {
+                        Ok(Self::Call(name, compile_all(args)?))
+                    }
+                    _ => Err(MatchSpecError::UnknownFunction(name, arity)),
+                }
+            }
+            Term::Cons(cons) => {
+                let cons = unsafe { cons.as_ref() };
+                let head = Self::compile(cons.head(), bound, in_body)?;
+                let tail = Self::compile(cons.tail(), bound, in_body)?;
+                Ok(Self::Cons(Box::new(head), Box::new(tail)))
+            }
+            term => Ok(Self::Const(term)),
+        }
+    }
+}
+
+/// Why an expression could not be evaluated
+enum Fail {
+    Badarg,
+    Alloc(AllocError),
+}
+impl From<AllocError> for Fail {
+    fn from(err: AllocError) -> Self {
+        Self::Alloc(err)
+    }
+}
+
+struct Env<'a, H: Heap> {
+    term: Term,
+    bindings: BTreeMap<u32, Term>,
+    heap: &'a H,
+    actions: Actions,
+}
+impl<'a, H: Heap> Env<'a, H> {
+    fn guards(&mut self, guards: &[Expr]) -> Result<bool, AllocError> {
+        for guard in guards.iter() {
+            match self.eval(guard) {
+                Ok(value) if as_bool(value) == Some(true) => continue,
+                Ok(_) | Err(Fail::Badarg) => return Ok(false),
+                Err(Fail::Alloc(err)) => return Err(err),
+            }
+        }
+        Ok(true)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Term, Fail> {
+        match expr {
+            Expr::Var(var) => Ok(self.bindings[var]),
+            Expr::Whole => Ok(self.term),
+            Expr::Bindings => {
+                let values = self.bindings.values().copied().collect::<Vec<_>>();
+                Ok(values.into_term(self.heap)?)
+            }
+            Expr::Const(value) => Ok(*value),
+            Expr::Tuple(elements) => {
+                let elements = self.eval_all(elements)?;
+                Ok(support::tuple(elements.as_slice(), self.heap)?)
+            }
+            Expr::Cons(head, tail) => {
+                let head = self.eval(head)?;
+                let tail = self.eval(tail)?;
+                let mut cell = Cons::new_in(self.heap)?;
+                unsafe {
+                    *cell.as_mut() = Cons::cons(head, tail);
+                }
+                Ok(Term::Cons(cell))
+            }
+            Expr::Action(action, args) => {
+                match action {
+                    Action::ReturnTrace => self.actions.return_trace = true,
+                    Action::ExceptionTrace => self.actions.exception_trace = true,
+                    Action::Message => self.actions.message = Some(self.eval(&args[0])?),
+                }
+                Ok(Term::Bool(true))
+            }
+            Expr::Call(name, args) => match name.as_str() {
+                "andalso" | "orelse" => {
+                    let until = name.as_str() == "orelse";
+                    for arg in args.iter() {
+                        match as_bool(self.eval(arg)?) {
+                            Some(value) if value == until => return Ok(Term::Bool(until)),
+                            Some(_) => continue,
+                            None => return Err(Fail::Badarg),
+                        }
+                    }
+                    Ok(Term::Bool(!until))
+                }
+                name => {
+                    let args = self.eval_all(args)?;
+                    call(name, args.as_slice(), self.heap)
+                }
+            },
+        }
+    }
+
+    fn eval_all(&mut self, exprs: &[Expr]) -> Result<Vec<Term>, Fail> {
+        exprs.iter().map(|expr| self.eval(expr)).collect()
+    }
+}
+
+/// Calls the guard function `name` with `args`, which the compiler has checked the arity of
+fn call<H: Heap>(name: &str, args: &[Term], heap: &H) -> Result<Term, Fail> {
+    let test = |result: bool| Ok(Term::Bool(result));
+    match (name, args) {
+        ("is_atom", [t]) => test(matches!(t, Term::Atom(_) | Term::Bool(_))),
+        ("is_binary", [t]) => test(t.as_bitstring().map(|b| b.is_binary()).unwrap_or(false)),
+        ("is_float", [t]) => test(matches!(t, Term::Float(_))),
+        ("is_function", [t]) => test(matches!(t, Term::Closure(_))),
+        ("is_integer", [t]) => test(matches!(t, Term::Int(_) | Term::BigInt(_))),
+        ("is_list", [t]) => test(matches!(t, Term::Nil | Term::Cons(_))),
+        ("is_map", [t]) => test(matches!(t, Term::Map(_))),
+        ("is_number", [t]) => test(matches!(t, Term::Int(_) | Term::BigInt(_) | Term::Float(_))),
+        ("is_pid", [t]) => test(matches!(t, Term::Pid(_))),
+        ("is_port", [t]) => test(matches!(t, Term::Port(_))),
+        ("is_reference", [t]) => test(matches!(t, Term::Reference(_))),
+        ("is_tuple", [t]) => test(matches!(t, Term::Tuple(_))),
+        ("not", [t]) => test(!as_bool(*t).ok_or(Fail::Badarg)?),
+        ("and" | "or" | "xor", [a, b]) => {
+            let a = as_bool(*a).ok_or(Fail::Badarg)?;
+            let b = as_bool(*b).ok_or(Fail::Badarg)?;
+            test(match name {
+                "and" => a && b,
+                "or" => a || b,
+                _ => a != b,
+            })
+        }
+        ("==", [a, b]) => test(a == b),
+        ("/=", [a, b]) => test(a != b),
+        ("=:=", [a, b]) => test(a.exact_eq(b)),
+        ("=/=", [a, b]) => test(!a.exact_eq(b)),
+        ("<", [a, b]) => test(a < b),
+        (">", [a, b]) => test(a > b),
+        ("=<", [a, b]) => test(a <= b),
+        (">=", [a, b]) => test(a >= b),
+        ("+" | "-" | "*" | "div" | "rem", [a, b]) => arith(name, *a, *b, heap),
+        ("-", [t]) => arith("-", Term::Int(0), *t, heap),
+        ("abs", [t]) => match t {
+            Term::Int(i) => Ok(i.checked_abs().ok_or(Fail::Badarg)?.into_term(heap)?),
+            Term::Float(f) => Ok(f.inner().abs().into_term(heap)?),
+            _ => Err(Fail::Badarg),
+        },
+        ("element", [Term::Int(index), t]) => {
+            let tuple = t.as_tuple().ok_or(Fail::Badarg)?;
+            let index = usize::try_from(*index).map_err(|_| Fail::Badarg)?;
+            let element = index.checked_sub(1).and_then(|i| tuple.as_slice().get(i));
+            element.map(|e| (*e).into()).ok_or(Fail::Badarg)
+        }
+        ("hd", [Term::Cons(cons)]) => Ok(unsafe { cons.as_ref() }.head()),
+        ("tl", [Term::Cons(cons)]) => Ok(unsafe { cons.as_ref() }.tail()),
+        ("length", [Term::Nil]) => Ok(Term::Int(0)),
+        ("length", [Term::Cons(cons)]) => {
+            let elements = proper_list(Term::Cons(*cons)).ok_or(Fail::Badarg)?;
+            Ok(elements.len().into_term(heap)?)
+        }
+        ("tuple_size", [t]) => Ok(t.as_tuple().ok_or(Fail::Badarg)?.len().into_term(heap)?),
+        ("map_size", [t]) => Ok(t.as_map().ok_or(Fail::Badarg)?.size().into_term(heap)?),
+        ("map_get", [key, t]) => {
+            let map = t.as_map().ok_or(Fail::Badarg)?;
+            map.get(*key).ok_or(Fail::Badarg)
+        }
+        ("is_map_key", [key, t]) => test(t.as_map().ok_or(Fail::Badarg)?.contains_key(*key)),
+        _ => Err(Fail::Badarg),
+    }
+}
+
+fn arith<H: Heap>(op: &str, a: Term, b: Term, heap: &H) -> Result<Term, Fail> {
+    let float = |value: f64| {
+        if value.is_finite() {
+            Ok(value.into_term(heap)?)
+        } else {
+            Err(Fail::Badarg)
+        }
+    };
+    match (a, b) {
+        (Term::Int(x), Term::Int(y)) => {
+            let result = match op {
+                "+" => x.checked_add(y),
+                "-" => x.checked_sub(y),
+                "*" => x.checked_mul(y),
+                "div" => x.checked_div(y),
+                _ => x.checked_rem(y),
+            };
+            Ok(result.ok_or(Fail::Badarg)?.into_term(heap)?)
+        }
+        (Term::Int(_) | Term::Float(_), Term::Int(_) | Term::Float(_)) => {
+            let (x, y) = (as_f64(a), as_f64(b));
+            match op {
+                "+" => float(x + y),
+                "-" => float(x - y),
+                "*" => float(x * y),
+                _ => Err(Fail::Badarg),
+            }
+        }
+        _ => Err(Fail::Badarg),
+    }
+}
+
+fn as_f64(term: Term) -> f64 {
+    match term {
+        Term::Int(i) => i as f64,
+        Term::Float(f) => f.inner(),
+        _ => unreachable!(),
+    }
+}
+
+fn as_bool(term: Term) -> Option<bool> {
+    match term {
+        Term::Bool(b) => Some(b),
+        Term::Atom(atom) if atom.as_str() == "true" => Some(true),
+        Term::Atom(atom) if atom.as_str() == "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Returns the number of the variable `atom`, if it is one, i.e. `'$1'`, `'$2'`, ...
+fn variable(atom: Atom) -> Option<u32> {
+    let digits = atom.as_str().strip_prefix('$')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Returns the elements of `term` if it is a proper list
+fn proper_list(term: Term) -> Option<Vec<Term>> {
+    match term {
+        Term::Nil => Some(Vec::new()),
+        Term::Cons(cons) => unsafe { cons.as_ref() }
+            .iter()
+            .collect::<Result<Vec<_>, _>>()
+            .ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use firefly_alloc::fragment::HeapFragment;
+
+    use super::*;
+    use crate::term::__support::{atom, list, tuple};
+
+    fn with_heap<F: FnOnce(&HeapFragment)>(fun: F) {
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        let fragment = HeapFragment::new(layout, None).unwrap();
+        fun(unsafe { fragment.as_ref() });
+    }
+
+    #[test]
+    fn match_spec_binds_and_guards_test() {
+        with_heap(|heap| {
+            // [{['$1', '$2'], [{'>', '$1', 1}], [{message, '$$'}, return_trace]}]
+            let head = list(&[atom("$1"), atom("$2")], heap).unwrap();
+            let guard = tuple(&[atom(">"), atom("$1"), Term::Int(1)], heap).unwrap();
+            let message = tuple(&[atom("message"), atom("$$")], heap).unwrap();
+            let return_trace = tuple(&[atom("return_trace")], heap).unwrap();
+            let body = list(&[message, return_trace], heap).unwrap();
+            let clause = tuple(&[head, list(&[guard], heap).unwrap(), body], heap).unwrap();
+            let spec = MatchSpec::compile(list(&[clause], heap).unwrap()).unwrap();
+
+            let args = list(&[Term::Int(2), atom("foo")], heap).unwrap();
+            let matched = spec.run(args, heap).unwrap().unwrap();
+            assert!(matched.actions.return_trace);
+            assert!(!matched.actions.exception_trace);
+            let message = matched.actions.message.unwrap();
+            assert_eq!(message, list(&[Term::Int(2), atom("foo")], heap).unwrap());
+
+            let args = list(&[Term::Int(1), atom("foo")], heap).unwrap();
+            assert!(spec.run(args, heap).unwrap().is_none());
+            let args = list(&[Term::Int(2)], heap).unwrap();
+            assert!(spec.run(args, heap).unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn match_spec_repeated_variable_test() {
+        with_heap(|heap| {
+            // [{{'$1', '$1', '_'}, [], []}]
+            let head = tuple(&[atom("$1"), atom("$1"), atom("_")], heap).unwrap();
+            let clause = tuple(&[head, Term::Nil, Term::Nil], heap).unwrap();
+            let spec = MatchSpec::compile(list(&[clause], heap).unwrap()).unwrap();
+
+            let same = tuple(&[Term::Int(1), Term::Int(1), atom("x")], heap).unwrap();
+            let result = spec.run(same, heap).unwrap().unwrap().result;
+            assert_eq!(result, Term::Bool(true));
+            let different = tuple(&[Term::Int(1), Term::Int(2), atom("x")], heap).unwrap();
+            assert!(spec.run(different, heap).unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn match_spec_failing_guard_test() {
+        with_heap(|heap| {
+            // [{'$1', [{'==', {hd, '$1'}, a}], ['$_']}]
+            let hd = tuple(&[atom("hd"), atom("$1")], heap).unwrap();
+            let guard = tuple(&[atom("=="), hd, atom("a")], heap).unwrap();
+            let body = list(&[atom("$_")], heap).unwrap();
+            let clause = tuple(&[atom("$1"), list(&[guard], heap).unwrap(), body], heap).unwrap();
+            let spec = MatchSpec::compile(list(&[clause], heap).unwrap()).unwrap();
+
+            let args = list(&[atom("a")], heap).unwrap();
+            assert_eq!(spec.run(args, heap).unwrap().unwrap().result, args);
+            assert!(spec.run(Term::Nil, heap).unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn match_spec_invalid_test() {
+        with_heap(|heap| {
+            assert_eq!(
+                MatchSpec::compile(atom("x")).unwrap_err(),
+                MatchSpecError::NotAList
+            );
+
+            let body = list(&[atom("$2")], heap).unwrap();
+            let clause = tuple(&[atom("$1"), Term::Nil, body], heap).unwrap();
+            let spec = list(&[clause], heap).unwrap();
+            assert_eq!(
+                MatchSpec::compile(spec).unwrap_err(),
+                MatchSpecError::UnboundVariable(Atom::try_from("$2").unwrap())
+            );
+
+            // Actions may only be called from the body
+            let guard = tuple(&[atom("return_trace")], heap).unwrap();
+            let clause = tuple(&[atom("_"), list(&[guard], heap).unwrap(), Term::Nil], heap);
+            let spec = list(&[clause.unwrap()], heap).unwrap();
+            assert!(matches!(
+                MatchSpec::compile(spec),
+                Err(MatchSpecError::UnknownFunction(_, 0))
+            ));
+        });
+    }
+}
@@ -0,0 +1,367 @@
+//! Mark-compact collection of process heaps.
+//!
+//! A process heap is bump-allocated, so the memory of terms which are no longer live can only be
+//! reclaimed by moving the live terms together. This is done by sliding them towards the start of
+//! the heap, keeping them in the order they were allocated, so that long-lived processes holding
+//! many binaries and maps do not end up with a heap made mostly of holes.
+//!
+//! Cons cells and tuples have no header by which they could be found on the heap, so unlike ERTS,
+//! the heap is never walked from start to end. Instead, each live term is recorded in a table
+//! ordered by address as it is marked, and a pointer is relocated by looking up the term it points
+//! into. This also relocates pointers into the middle of a term, e.g. from a sub-binary to the
+//! bytes of the binary it is a slice of.
+//!
+//! Marking is cheap next to moving, so unless a full sweep is requested, a heap is only compacted
+//! once marking has found that at least [`FRAGMENTATION_THRESHOLD`] of it is garbage.
+use alloc::alloc::Layout;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ptr;
+
+use firefly_alloc::gc::Metadata;
+use firefly_alloc::heap::Heap;
+
+use crate::term::{Cons, OpaqueTerm, Term};
+
+use super::ProcessHeap;
+
+/// The fraction of a heap which must be garbage for it to be compacted, see [`Sweep::Fragmented`]
+pub const FRAGMENTATION_THRESHOLD: f64 = 0.5;
+
+/// When a heap is compacted once its live terms have been marked
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sweep {
+    /// The heap is compacted only if at least this fraction of it is garbage
+    Fragmented(f64),
+    /// The heap is always compacted, as for `erlang:garbage_collect/2` with `{type, major}`
+    Full,
+    /// The heap is only marked, never compacted, as when something the collector cannot update
+    /// refers to it, see `stackmaps::StackRoots::pinned`
+    Mark,
+}
+impl Default for Sweep {
+    fn default() -> Self {
+        Self::Fragmented(FRAGMENTATION_THRESHOLD)
+    }
+}
+
+/// The outcome of a collection
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Compaction {
+    /// The bytes of the heap which were in use before the collection
+    pub used: usize,
+    /// The bytes of the heap occupied by live terms, not counting padding between them
+    pub live: usize,
+    /// True if the live terms were moved together, freeing the rest of the heap
+    pub compacted: bool,
+}
+impl Compaction {
+    /// Returns the fraction of the heap in use which was garbage
+    pub fn fragmentation(&self) -> f64 {
+        if self.used == 0 {
+            0.0
+        } else {
+            (self.used - self.live) as f64 / self.used as f64
+        }
+    }
+}
+
+/// A live term on the heap being compacted, keyed by the address its allocation starts at
+struct Object {
+    /// A term pointing to this one, by which its fields are found
+    term: OpaqueTerm,
+    /// The layout of the allocation, including any header
+    layout: Layout,
+    /// The address the allocation is moved to
+    to: usize,
+}
+
+/// Collects `heap`, see `Process::compact`
+pub(super) unsafe fn compact(
+    heap: &ProcessHeap,
+    roots: &mut [OpaqueTerm],
+    sweep: Sweep,
+) -> Compaction {
+    let start = heap.heap_start() as usize;
+    let mut objects = mark(heap, roots);
+    let mut compaction = Compaction {
+        used: heap.heap_top() as usize - start,
+        live: objects.values().map(|object| object.layout.size()).sum(),
+        compacted: false,
+    };
+    match sweep {
+        Sweep::Mark => return compaction,
+        Sweep::Fragmented(threshold) if compaction.fragmentation() < threshold => {
+            return compaction
+        }
+        _ => (),
+    }
+
+    // Assign each term its new address, in the order they were allocated, so that no term is
+    // moved to an address above the one it is at
+    let mut top = start;
+    for object in objects.values_mut() {
+        let align = object.layout.align();
+        top = (top + align - 1) & !(align - 1);
+        object.to = top;
+        top += object.layout.size();
+    }
+
+    // Rewrite every pointer to a live term, while the terms holding them are still where they were
+    for root in roots.iter_mut() {
+        *root = relocate_term(&objects, *root);
+    }
+    for object in objects.values() {
+        relocate_fields(&objects, object.term);
+    }
+
+    // Slide the terms down to their new addresses, lowest first, so none is overwritten before it
+    // has been moved
+    for (from, object) in objects.iter() {
+        ptr::copy(
+            *from as *const u8,
+            object.to as *mut u8,
+            object.layout.size(),
+        );
+    }
+    heap.set_top(top as *mut u8);
+    compaction.compacted = true;
+    compaction
+}
+
+/// Returns the live terms on `heap`, i.e. those reachable from `roots`
+unsafe fn mark(heap: &ProcessHeap, roots: &[OpaqueTerm]) -> BTreeMap<usize, Object> {
+    let mut objects = BTreeMap::new();
+    let mut stack = Vec::from(roots);
+    while let Some(term) = stack.pop() {
+        if !is_on(heap, term) {
+            continue;
+        }
+        let (from, layout) = allocation(term);
+        if objects.contains_key(&from) {
+            continue;
+        }
+        objects.insert(
+            from,
+            Object {
+                term,
+                layout,
+                to: from,
+            },
+        );
+        match term.into() {
+            Term::Cons(ptr) => {
+                let cons = ptr.as_ref();
+                stack.push(cons.head);
+                stack.push(cons.tail);
+            }
+            Term::Tuple(ptr) => stack.extend_from_slice(ptr.as_ref().as_slice()),
+            Term::Map(map) => {
+                for (key, value) in map.iter() {
+                    stack.push((*key).into());
+                    stack.push((*value).into());
+                }
+            }
+            Term::Closure(fun) => stack.extend_from_slice(fun.env()),
+            Term::RefBinary(slice) => stack.push(slice.owner()),
+            _ => (),
+        }
+    }
+    objects
+}
+
+/// Returns true if `term` points to a term allocated on `heap`
+///
+/// Literals and reference-counted terms are never on a process heap, so are ruled out by their
+/// tags before the address is checked.
+#[inline]
+fn is_on(heap: &ProcessHeap, term: OpaqueTerm) -> bool {
+    term.is_box() && !term.is_literal() && !term.is_rc() && heap.contains(unsafe { term.as_ptr() })
+}
+
+/// Returns the address and layout of the allocation `term` points to
+unsafe fn allocation(term: OpaqueTerm) -> (usize, Layout) {
+    let ptr = term.as_ptr() as usize;
+    match term.into() {
+        Term::Cons(_) => (ptr, Layout::new::<Cons>()),
+        Term::Tuple(tuple) => {
+            // See `Tuple::new_in`
+            let (layout, _) = Layout::new::<usize>()
+                .align_to(16)
+                .unwrap()
+                .extend(Layout::array::<OpaqueTerm>(tuple.as_ref().len()).unwrap())
+                .unwrap();
+            (ptr, layout)
+        }
+        Term::BigInt(boxed) => boxed_allocation(ptr, Layout::for_value(&*boxed)),
+        Term::Pid(boxed) => boxed_allocation(ptr, Layout::for_value(&*boxed)),
+        Term::Port(boxed) => boxed_allocation(ptr, Layout::for_value(&*boxed)),
+        Term::Reference(boxed) => boxed_allocation(ptr, Layout::for_value(&*boxed)),
+        Term::HeapBinary(boxed) => boxed_allocation(ptr, Layout::for_value(&*boxed)),
+        Term::RefBinary(boxed) => boxed_allocation(ptr, Layout::for_value(&*boxed)),
+        Term::Map(boxed) => boxed_allocation(ptr, Layout::for_value(&*boxed)),
+        Term::Closure(boxed) => boxed_allocation(ptr, Layout::for_value(&*boxed)),
+        other => unreachable!("{:?} is not allocated on a process heap", other),
+    }
+}
+
+/// Returns the address and layout of a `GcBox` whose value is at `value`, see `GcBox::new_in`
+fn boxed_allocation(value: usize, layout: Layout) -> (usize, Layout) {
+    let (layout, value_offset) = Layout::new::<Metadata>().extend(layout).unwrap();
+    (value - value_offset, layout)
+}
+
+/// Returns the address `addr` is moved to, if it is in a live term, or `addr` otherwise
+fn relocate(objects: &BTreeMap<usize, Object>, addr: usize) -> usize {
+    match objects.range(..=addr).next_back() {
+        Some((from, object)) if addr < from + object.layout.size() => object.to + (addr - from),
+        _ => addr,
+    }
+}
+
+/// Returns `term` pointing to where the term it points to is moved to
+fn relocate_term(objects: &BTreeMap<usize, Object>, term: OpaqueTerm) -> OpaqueTerm {
+    if !term.is_box() || term.is_literal() || term.is_rc() {
+        return term;
+    }
+    let addr = unsafe { term.as_ptr() } as usize;
+    match relocate(objects, addr) {
+        to if to == addr => term,
+        to => unsafe { term.with_ptr(to as *mut ()) },
+    }
+}
+
+/// Rewrites the pointers held by the term `term` points to
+unsafe fn relocate_fields(objects: &BTreeMap<usize, Object>, term: OpaqueTerm) {
+    match term.into() {
+        Term::Cons(mut ptr) => {
+            let cons = ptr.as_mut();
+            cons.head = relocate_term(objects, cons.head);
+            cons.tail = relocate_term(objects, cons.tail);
+        }
+        Term::Tuple(mut ptr) => {
+            for element in ptr.as_mut().as_mut_slice() {
+                *element = relocate_term(objects, *element);
+            }
+        }
+        Term::Map(mut map) => {
+            map.relocate(|term| relocate_term(objects, term.into()).into());
+        }
+        Term::Closure(mut fun) => {
+            for element in fun.env_mut() {
+                *element = relocate_term(objects, *element);
+            }
+        }
+        Term::RefBinary(mut slice) => {
+            let owner = relocate_term(objects, slice.owner());
+            slice.relocate(owner, |addr| relocate(objects, addr as usize) as *const u8);
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use firefly_alloc::gc::GcBox;
+    use firefly_binary::{Binary, BinaryFlags, Encoding};
+
+    use crate::term::*;
+
+    use super::*;
+
+    /// Returns `{[1, 2, 3], <<"hello">>, <<"ell">>, #{key => [1, 2, 3]}}` allocated on `heap`,
+    /// where the third element is a slice of the second, with garbage allocated around its parts
+    fn live(heap: &ProcessHeap) -> OpaqueTerm {
+        let garbage = |heap: &ProcessHeap| {
+            Tuple::from_slice(&[OpaqueTerm::NIL; 5], heap).unwrap();
+        };
+
+        garbage(heap);
+        let list = [Term::Int(1), Term::Int(2), Term::Int(3)];
+        let list = Term::Cons(Cons::from_slice(&list, heap).unwrap().unwrap());
+        garbage(heap);
+        let mut bin = GcBox::<BinaryData>::with_capacity_in(5, heap).unwrap();
+        unsafe {
+            bin.set_flags(BinaryFlags::new(5, Encoding::Raw));
+        }
+        bin.copy_from_slice(b"hello");
+        let owner: OpaqueTerm = Term::HeapBinary(bin).into();
+        let slice = unsafe { BitSlice::new(owner, &bin.as_bytes()[1..4], 0, 24) };
+        let slice = Term::RefBinary(GcBox::new_in(slice, heap).unwrap());
+        garbage(heap);
+        let key = Term::Atom(Atom::from_str("key").unwrap());
+        let map = Term::Map(Map::new_from_iter_in([(key, list)].into_iter(), heap).unwrap());
+        garbage(heap);
+        let elements = [list.into(), owner, slice.into(), map.into()];
+        let tuple = Tuple::from_slice(&elements, heap).unwrap().into();
+        garbage(heap);
+        tuple
+    }
+
+    #[test]
+    fn compact_test() {
+        let heap = ProcessHeap::with_size(4096).unwrap();
+        let mut roots = [live(&heap), Term::Int(42).into()];
+        let (expected, _fragment) = Term::from(roots[0]).copy_to_fragment().unwrap();
+
+        let compaction = unsafe { compact(&heap, &mut roots, Sweep::Full) };
+        assert!(compaction.compacted);
+        assert!(compaction.live < compaction.used);
+        assert!(compaction.fragmentation() > 0.0);
+        let used = heap.heap_top() as usize - heap.heap_start() as usize;
+        assert!(used < compaction.used);
+        assert!(used >= compaction.live);
+
+        // The terms have moved, but are otherwise unchanged
+        assert!(heap.contains(unsafe { roots[0].as_ptr() }));
+        assert_eq!(Term::from(roots[0]), expected);
+        assert_eq!(roots[1], Term::Int(42).into());
+        let Term::Tuple(tuple) = roots[0].into() else {
+            panic!("expected a tuple")
+        };
+        let tuple = unsafe { tuple.as_ref() };
+        let Term::RefBinary(slice) = tuple.get(2).unwrap() else {
+            panic!("expected a sub-binary")
+        };
+        assert_eq!(slice.owner(), tuple.as_slice()[1]);
+        assert_eq!(slice.as_selection().to_bytes().as_ref(), b"ell");
+
+        // The freed space can be allocated again
+        assert!(Tuple::from_slice(&[OpaqueTerm::NIL; 100], &heap).is_ok());
+    }
+
+    #[test]
+    fn compact_fragmented_test() {
+        let heap = ProcessHeap::with_size(4096).unwrap();
+        let mut roots = [live(&heap)];
+        let before = roots[0];
+        let top = heap.heap_top();
+
+        // The heap is not compacted unless enough of it is garbage
+        let compaction = unsafe { compact(&heap, &mut roots, Sweep::Fragmented(0.99)) };
+        assert!(!compaction.compacted);
+        assert_eq!(roots[0], before);
+        assert_eq!(heap.heap_top(), top);
+
+        let compaction = unsafe { compact(&heap, &mut roots, Sweep::Fragmented(0.1)) };
+        assert!(compaction.compacted);
+        assert!(heap.heap_top() < top);
+    }
+
+    #[test]
+    fn compact_mark_test() {
+        let heap = ProcessHeap::with_size(4096).unwrap();
+        let mut roots = [live(&heap)];
+        let before = roots[0];
+        let top = heap.heap_top();
+
+        // Marking finds the garbage, but nothing is moved
+        let compaction = unsafe { compact(&heap, &mut roots, Sweep::Mark) };
+        assert!(!compaction.compacted);
+        assert!(compaction.fragmentation() > 0.0);
+        assert_eq!(roots[0], before);
+        assert_eq!(heap.heap_top(), top);
+    }
+}
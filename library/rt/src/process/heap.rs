@@ -1,8 +1,9 @@
-use alloc::alloc::{AllocError, Allocator, Global, Layout};
-use core::cell::UnsafeCell;
+use alloc::alloc::{AllocError, Allocator, Layout};
+use core::cell::{Cell, UnsafeCell};
 use core::mem;
 use core::ptr::{self, NonNull};
 
+use firefly_alloc::allocators::EHEAP_ALLOC;
 use firefly_alloc::heap::Heap;
 
 use crate::term::Term;
@@ -10,25 +11,53 @@ use crate::term::Term;
 pub struct ProcessHeap {
     range: *mut [u8],
     top: UnsafeCell<*mut u8>,
+    /// Set when an allocation fails because the heap is full
+    exhausted: Cell<bool>,
 }
 impl ProcessHeap {
-    const DEFAULT_HEAP_SIZE: usize = 4 * 1024;
+    pub const DEFAULT_HEAP_SIZE: usize = 4 * 1024;
 
     pub fn new() -> Self {
+        Self::with_size(Self::DEFAULT_HEAP_SIZE).unwrap()
+    }
+
+    /// Creates a heap of `size` bytes, or returns an error if it cannot be allocated
+    ///
+    /// Heaps do not grow, so this is all the memory the heap will ever have.
+    pub fn with_size(size: usize) -> Result<Self, AllocError> {
         let layout =
-            Layout::from_size_align(Self::DEFAULT_HEAP_SIZE, mem::align_of::<Term>()).unwrap();
-        let nonnull = Global.allocate(layout).unwrap();
-        Self {
+            Layout::from_size_align(size, mem::align_of::<Term>()).map_err(|_| AllocError)?;
+        let nonnull = EHEAP_ALLOC.allocate(layout)?;
+        Ok(Self {
             range: nonnull.as_ptr(),
             top: UnsafeCell::new(nonnull.as_non_null_ptr().as_ptr()),
-        }
+            exhausted: Cell::new(false),
+        })
+    }
+
+    /// Returns the size of the heap in bytes
+    pub fn size(&self) -> usize {
+        self.range.len()
+    }
+
+    /// Returns true if an allocation on this heap has failed for lack of space
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.get()
+    }
+
+    /// Moves the top of this heap back to `top`, once the collector has moved the live terms
+    /// below it, see `compact`
+    pub(super) unsafe fn set_top(&self, top: *mut u8) {
+        debug_assert!(top >= self.heap_start() && top <= self.heap_top());
+        self.top.get().write(top);
+        self.exhausted.set(false);
     }
 }
 impl Drop for ProcessHeap {
     fn drop(&mut self) {
         let size = ptr::metadata(self.range) as usize;
         let layout = Layout::from_size_align(size, mem::align_of::<Term>()).unwrap();
-        unsafe { Global.deallocate(NonNull::new_unchecked(self.range.cast()), layout) }
+        unsafe { EHEAP_ALLOC.deallocate(NonNull::new_unchecked(self.range.cast()), layout) }
     }
 }
 unsafe impl Allocator for ProcessHeap {
@@ -43,16 +72,17 @@ unsafe impl Allocator for ProcessHeap {
         let base = unsafe { top.add(offset) };
         let new_top = unsafe { base.add(size) } as *const u8;
 
-        // Make sure the requested allocation fits within the fragment
+        // Make sure the requested allocation fits within the heap, which it may fill exactly
         let start = self.range.as_mut_ptr() as *const u8;
         let heap_size = self.range.len();
-        let range = start..(unsafe { start.add(heap_size) });
-        if range.contains(&new_top) {
+        let end = unsafe { start.add(heap_size) };
+        if new_top <= end {
             unsafe {
                 self.top.get().write(new_top as *mut u8);
             }
             Ok(unsafe { NonNull::new_unchecked(ptr::from_raw_parts_mut(base.cast(), size)) })
         } else {
+            self.exhausted.set(true);
             Err(AllocError)
         }
     }
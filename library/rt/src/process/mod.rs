@@ -1,17 +1,27 @@
+mod compact;
 mod heap;
+mod options;
+pub mod reductions;
 mod stack;
+pub mod stackmaps;
 
 use alloc::alloc::{AllocError, Allocator, Layout};
-use core::cell::UnsafeCell;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell, UnsafeCell};
+use core::mem;
+use core::ops::Range;
 use core::ptr::NonNull;
 
 use firefly_alloc::heap::Heap;
 
 use crate::error::ErlangException;
 use crate::function::ModuleFunctionArity;
-use crate::term::ProcessId;
+use crate::term::{OpaqueTerm, ProcessId};
 
+pub use self::compact::{Compaction, Sweep, FRAGMENTATION_THRESHOLD};
 pub use self::heap::ProcessHeap;
+pub use self::options::{MaxHeapSize, MessageQueueData, Priority, SpawnOptions, WORD_SIZE};
+pub use self::reductions::{Resumable, Step, MAX_REDUCTIONS};
 pub use self::stack::ProcessStack;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -26,7 +36,6 @@ pub enum ProcessStatus {
 pub struct Process {
     parent: Option<ProcessId>,
     pid: ProcessId,
-    #[allow(dead_code)]
     mfa: ModuleFunctionArity,
     /// The process status is only ever manipulated/accessed by the owning scheduler
     status: UnsafeCell<ProcessStatus>,
@@ -41,17 +50,60 @@ pub struct Process {
     /// are properly updated so that the aliasing in that case is safe.
     heap: UnsafeCell<ProcessHeap>,
     stack: UnsafeCell<ProcessStack>,
+    options: Cell<SpawnOptions>,
+    priority: Cell<Priority>,
+    /// The priority inherited from others while doing work on their behalf, see `inherit_priority`
+    inherited: Cell<Priority>,
+    /// The reductions consumed over the lifetime of the process
+    reductions: Cell<u64>,
+    /// The reductions left before the process must yield, see `reductions`
+    budget: Cell<usize>,
+    /// The number and total size of the reference-counted binaries allocated by this process
+    binaries: Cell<(usize, usize)>,
+    /// The reference-counted binaries allocated by this process, whose references are held by its
+    /// heap, with their sizes
+    off_heap: RefCell<Vec<(OpaqueTerm, usize)>>,
 }
 impl Process {
     pub fn new(parent: Option<ProcessId>, pid: ProcessId, mfa: ModuleFunctionArity) -> Self {
-        Self {
+        Self::with_options(
+            parent,
+            pid,
+            mfa,
+            SpawnOptions::default(),
+            Priority::default(),
+        )
+        .unwrap()
+    }
+
+    /// Creates a process with the given spawn options, whose heap is sized accordingly, or returns
+    /// an error if its heap cannot be allocated
+    ///
+    /// The priority is that of the options, or `inherited`, i.e. the priority of the parent, if
+    /// the options do not specify one.
+    pub fn with_options(
+        parent: Option<ProcessId>,
+        pid: ProcessId,
+        mfa: ModuleFunctionArity,
+        options: SpawnOptions,
+        inherited: Priority,
+    ) -> Result<Self, AllocError> {
+        let heap = ProcessHeap::with_size(options.heap_size(ProcessHeap::DEFAULT_HEAP_SIZE))?;
+        Ok(Self {
             parent,
             pid,
             mfa,
             status: UnsafeCell::new(ProcessStatus::Waiting),
-            heap: UnsafeCell::new(ProcessHeap::new()),
+            heap: UnsafeCell::new(heap),
             stack: UnsafeCell::new(ProcessStack::new(32).unwrap()),
-        }
+            options: Cell::new(options),
+            priority: Cell::new(options.priority.unwrap_or(inherited)),
+            inherited: Cell::new(Priority::Low),
+            reductions: Cell::new(0),
+            budget: Cell::new(MAX_REDUCTIONS),
+            binaries: Cell::new((0, 0)),
+            off_heap: RefCell::new(Vec::new()),
+        })
     }
 
     pub fn parent(&self) -> Option<ProcessId> {
@@ -62,6 +114,11 @@ impl Process {
         self.pid
     }
 
+    /// Returns the function this process was spawned to call
+    pub fn initial_call(&self) -> ModuleFunctionArity {
+        self.mfa
+    }
+
     pub fn status(&self) -> ProcessStatus {
         unsafe { self.status.get().read() }
     }
@@ -70,6 +127,167 @@ impl Process {
         unsafe { &*self.stack.get() }
     }
 
+    /// Returns the options this process was spawned with, as changed by `set_options` since
+    pub fn options(&self) -> SpawnOptions {
+        self.options.get()
+    }
+
+    /// Replaces the options of this process, e.g. via `erlang:process_flag/2`
+    ///
+    /// The heap is sized when the process is spawned, so changing the heap sizes afterwards only
+    /// determines whether running out of heap is reported as reaching `max_heap_size`.
+    pub fn set_options(&self, options: SpawnOptions) {
+        self.options.set(options);
+    }
+
+    /// Returns the priority of this process, as spawned or set with `erlang:process_flag/2`
+    pub fn priority(&self) -> Priority {
+        self.priority.get()
+    }
+
+    /// Sets the priority of this process, returning the previous one
+    pub fn set_priority(&self, priority: Priority) -> Priority {
+        self.priority.replace(priority)
+    }
+
+    /// Returns the priority this process is scheduled at, i.e. the higher of its own priority and
+    /// the priority it has inherited
+    pub fn effective_priority(&self) -> Priority {
+        self.priority.get().max(self.inherited.get())
+    }
+
+    /// Raises the priority this process is scheduled at to `priority`, if that is higher than its
+    /// own, while it works on behalf of a process of that priority, returning the priority it
+    /// inherited previously, to be restored once that work is done
+    ///
+    /// `Priority::Low` is never higher than its own priority, so inheriting it removes the boost.
+    pub fn inherit_priority(&self, priority: Priority) -> Priority {
+        self.inherited.replace(priority)
+    }
+
+    /// Returns the number of reductions consumed over the lifetime of this process
+    pub fn reductions(&self) -> u64 {
+        self.reductions.get()
+    }
+
+    /// Returns the number of reductions left before this process must yield
+    pub fn reductions_left(&self) -> usize {
+        self.budget.get()
+    }
+
+    /// Consumes `n` reductions, returning true if the budget of the process is exhausted
+    pub fn reduce(&self, n: usize) -> bool {
+        self.reductions.set(self.reductions.get() + n as u64);
+        let left = self.budget.get().saturating_sub(n);
+        self.budget.set(left);
+        left == 0
+    }
+
+    /// Gives this process a full budget of reductions, which is done each time it is scheduled
+    pub fn reset_reductions(&self) {
+        self.budget.set(MAX_REDUCTIONS);
+    }
+
+    /// Records `binary`, a reference-counted binary of `size` bytes allocated by this process
+    ///
+    /// These binaries live outside of the heap, so are counted separately, as the virtual binary
+    /// heap of the process. A binary stays counted for as long as the process lives, as without a
+    /// collector there is no point at which the process is known to have released it. The
+    /// reference held by the heap is only released once the process has exited, see
+    /// `take_binaries`.
+    pub fn track_binary(&self, binary: OpaqueTerm, size: usize) {
+        let (count, total) = self.binaries.get();
+        self.binaries.set((count + 1, total + size));
+        self.off_heap.borrow_mut().push((binary, size));
+    }
+
+    /// Takes the binaries allocated by this process, see `track_binary`, whose references the
+    /// caller must release once nothing can read the heap of the process any longer
+    pub fn take_binaries(&self) -> Vec<(OpaqueTerm, usize)> {
+        self.binaries.set((0, 0));
+        mem::take(&mut *self.off_heap.borrow_mut())
+    }
+
+    /// Returns the number and total size in bytes of the reference-counted binaries allocated by
+    /// this process, see `track_binary`
+    pub fn binaries(&self) -> (usize, usize) {
+        self.binaries.get()
+    }
+
+    /// Returns the size of the heap of this process in words
+    pub fn heap_size(&self) -> usize {
+        self.heap().size() / WORD_SIZE
+    }
+
+    /// Returns true if the heap of this process has reached its `max_heap_size`
+    ///
+    /// Heaps do not grow, so the limit is reached when an allocation fails on a heap which is at
+    /// least as large as the limit, i.e. one whose size was clamped to it at spawn, or which is
+    /// larger than a limit set later with `erlang:process_flag/2`.
+    pub fn max_heap_size_exceeded(&self) -> bool {
+        match self.options.get().max_heap_size {
+            Some(max) => self.heap().is_exhausted() && self.heap_size() >= max.size,
+            None => false,
+        }
+    }
+
+    /// Collects the heap of this process, by marking the terms reachable from `roots` and, as
+    /// `sweep` directs, sliding them together to free the memory of those which are not
+    ///
+    /// # Safety
+    ///
+    /// `roots` must hold every term outside of the heap which points into it, as any other pointer
+    /// into the heap is left dangling, and the process must not be running.
+    pub unsafe fn compact(&self, roots: &mut [OpaqueTerm], sweep: Sweep) -> Compaction {
+        compact::compact(self.heap(), roots, sweep)
+    }
+
+    /// Collects the heap of this process while it is running, using the terms held by the frames
+    /// of generated code on its stack as roots, see `stackmaps`, in addition to `roots`
+    ///
+    /// If the stack may refer to the heap in ways the stack maps do not describe, the heap is only
+    /// marked, as with `Sweep::Mark`, see `stackmaps::StackRoots::pinned`.
+    ///
+    /// # Safety
+    ///
+    /// Must be called by this process, i.e. on its stack, and `roots` must hold every term held by
+    /// the caller which points into the heap, as for `compact`.
+    #[cfg(feature = "std")]
+    pub unsafe fn collect(&self, roots: &mut [OpaqueTerm], sweep: Sweep) -> Compaction {
+        let stack = stackmaps::stack_roots(self.stack());
+        let sweep = if stack.pinned { Sweep::Mark } else { sweep };
+        let slots = stack.slots;
+        let mut all = Vec::with_capacity(slots.len() + roots.len());
+        all.extend(slots.iter().map(|slot| slot.read()));
+        all.extend_from_slice(roots);
+
+        let compaction = self.compact(all.as_mut_slice(), sweep);
+        for (slot, root) in slots.iter().zip(all.iter()) {
+            slot.write(*root);
+        }
+        roots.copy_from_slice(&all[slots.len()..]);
+        compaction
+    }
+
+    /// Returns true if this process may refer to a literal in `area`, i.e. if the terms held by the
+    /// frames of generated code on its stack, or `roots`, do, see `OpaqueTerm::refers_to_literals`
+    ///
+    /// This is how a purge finds the processes which must be rid of their references to a literal
+    /// area before it can be freed. If the stack may refer to terms in ways the stack maps do not
+    /// describe, see `stackmaps::StackRoots::pinned`, this returns true, as a reference cannot be
+    /// ruled out.
+    ///
+    /// # Safety
+    ///
+    /// Must be called by this process, i.e. on its stack, as for `collect`.
+    #[cfg(feature = "std")]
+    pub unsafe fn refers_to_literals(&self, roots: &[OpaqueTerm], area: Range<*const u8>) -> bool {
+        let stack = stackmaps::stack_roots(self.stack());
+        stack.pinned
+            || stack.slots.iter().any(|slot| slot.read().refers_to_literals(area.clone()))
+            || roots.iter().any(|root| root.refers_to_literals(area.clone()))
+    }
+
     pub fn exit_normal(&self) {
         unsafe {
             self.set_status(ProcessStatus::Exiting);
@@ -0,0 +1,184 @@
+use core::mem;
+
+use crate::term::OpaqueTerm;
+
+/// The size of a word on the process heap, the unit in which heap sizes are given
+pub const WORD_SIZE: usize = mem::size_of::<OpaqueTerm>();
+
+/// The scheduling priority of a process
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+    Max,
+}
+impl Default for Priority {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+            Self::Max => "max",
+        }
+    }
+}
+impl TryFrom<&str> for Priority {
+    type Error = ();
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name {
+            "low" => Ok(Self::Low),
+            "normal" => Ok(Self::Normal),
+            "high" => Ok(Self::High),
+            "max" => Ok(Self::Max),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where the messages in the queue of a process are stored
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MessageQueueData {
+    /// Messages are copied to the heap of the process as they are received
+    OnHeap,
+    /// Messages are kept in heap fragments until they are matched, so that a process with a long
+    /// queue doesn't make its heap large
+    OffHeap,
+}
+impl Default for MessageQueueData {
+    fn default() -> Self {
+        Self::OnHeap
+    }
+}
+impl MessageQueueData {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::OnHeap => "on_heap",
+            Self::OffHeap => "off_heap",
+        }
+    }
+}
+impl TryFrom<&str> for MessageQueueData {
+    type Error = ();
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name {
+            "on_heap" => Ok(Self::OnHeap),
+            "off_heap" => Ok(Self::OffHeap),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The limit on the size of the heap of a process, and what happens when it is reached
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MaxHeapSize {
+    /// The size in words
+    pub size: usize,
+    /// Whether the process is killed when the limit is reached
+    pub kill: bool,
+    /// Whether an error report is logged when the limit is reached
+    pub error_logger: bool,
+}
+impl MaxHeapSize {
+    /// Creates a limit of `size` words, which kills the process and logs an error report when
+    /// reached, as is the default in ERTS
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            kill: true,
+            error_logger: true,
+        }
+    }
+}
+
+/// The options a process is spawned with, see `erlang:spawn_opt/4`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpawnOptions {
+    /// The minimum size of the heap in words, or the default size if `None`
+    pub min_heap_size: Option<usize>,
+    /// The limit on the size of the heap, or `None` if unlimited
+    pub max_heap_size: Option<MaxHeapSize>,
+    /// The minimum size in words of the virtual heap of binaries referenced by the process
+    pub min_bin_vheap_size: usize,
+    /// The number of minor collections after which a full sweep is forced
+    pub fullsweep_after: usize,
+    /// The priority of the process, or the priority of its parent if `None`
+    pub priority: Option<Priority>,
+    pub message_queue_data: MessageQueueData,
+}
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        Self {
+            min_heap_size: None,
+            max_heap_size: None,
+            min_bin_vheap_size: Self::DEFAULT_MIN_BIN_VHEAP_SIZE,
+            fullsweep_after: Self::DEFAULT_FULLSWEEP_AFTER,
+            priority: None,
+            message_queue_data: MessageQueueData::default(),
+        }
+    }
+}
+impl SpawnOptions {
+    pub const DEFAULT_FULLSWEEP_AFTER: usize = 65535;
+    pub const DEFAULT_MIN_BIN_VHEAP_SIZE: usize = 46422;
+
+    /// Returns the size in bytes of the heap a process spawned with these options starts with
+    ///
+    /// This is the minimum heap size, if larger than `default`, but no larger than the maximum heap
+    /// size, if there is one.
+    pub fn heap_size(&self, default: usize) -> usize {
+        let size = self
+            .min_heap_size
+            .map(|words| words.saturating_mul(WORD_SIZE))
+            .map_or(default, |size| size.max(default));
+        match self.max_heap_size {
+            Some(max) => size.min(max.size.saturating_mul(WORD_SIZE)),
+            None => size,
+        }
+    }
+
+    /// Returns true if the options are consistent, i.e. the minimum heap size does not exceed the
+    /// maximum heap size
+    pub fn is_valid(&self) -> bool {
+        match (self.min_heap_size, self.max_heap_size) {
+            (Some(min), Some(max)) => min <= max.size,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_size_test() {
+        let default = 4096;
+        let mut options = SpawnOptions::default();
+        assert_eq!(options.heap_size(default), default);
+
+        options.min_heap_size = Some(10);
+        assert_eq!(options.heap_size(default), default);
+
+        options.min_heap_size = Some(1024);
+        assert_eq!(options.heap_size(default), 1024 * WORD_SIZE);
+
+        options.max_heap_size = Some(MaxHeapSize::new(100));
+        assert!(!options.is_valid());
+        assert_eq!(options.heap_size(default), 100 * WORD_SIZE);
+
+        options.max_heap_size = Some(MaxHeapSize::new(2048));
+        assert!(options.is_valid());
+        assert_eq!(options.heap_size(default), 1024 * WORD_SIZE);
+
+        options.min_heap_size = None;
+        assert_eq!(options.heap_size(default), default);
+    }
+}
@@ -0,0 +1,140 @@
+//! Reductions are the unit in which the work done by a process is measured, and which bound how
+//! long it may run before it must yield to the scheduler.
+//!
+//! Each time a process is scheduled it is given a budget of [`MAX_REDUCTIONS`]. Long-running native
+//! functions are written as [`Resumable`] computations, which consume the budget as they go, and
+//! return [`Step::Yield`] when it runs out, keeping whatever state they need to continue in `self`.
+//! The runtime then yields the process, and resumes the computation once it is scheduled again.
+//!
+//! Encoding terms in the external term format and flattening iodata are resumable in this way.
+//! Copying terms between heaps is not yet, as the copy must be complete before the process can be
+//! descheduled, nor is sorting, which is done by the Erlang implementation of `lists`.
+//!
+//! Generated code consumes a reduction on entry to every function, and on every loop iteration.
+//! It does not have access to the process itself, so the scheduler [`grant`]s the budget of a
+//! process to the thread running it, in [`REDUCTIONS_LEFT`], which generated code decrements
+//! inline. When it reaches zero, generated code calls `__firefly_yield_point`, which [`settle`]s
+//! the reductions consumed with the process, and yields if its budget is exhausted. The scheduler
+//! also settles whenever a process yields for any other reason, so that reductions are always
+//! charged to the process which consumed them.
+
+/// The number of reductions a process may consume each time it is scheduled
+pub const MAX_REDUCTIONS: usize = 4000;
+
+/// The reductions generated code may consume before it must call `__firefly_yield_point`
+///
+/// NOTE: The name and type of this symbol are part of the ABI, see `crate::abi`.
+#[thread_local]
+#[export_name = "__firefly_reductions_left"]
+pub static mut REDUCTIONS_LEFT: isize = 0;
+
+/// The value of [`REDUCTIONS_LEFT`] when it was last granted or settled
+#[thread_local]
+static mut GRANTED: isize = 0;
+
+/// Allows generated code running on the current thread to consume up to `budget` reductions
+pub fn grant(budget: usize) {
+    let budget = budget.min(isize::MAX as usize) as isize;
+    unsafe {
+        REDUCTIONS_LEFT = budget;
+        GRANTED = budget;
+    }
+}
+
+/// Returns the number of reductions consumed by generated code on the current thread since they
+/// were last granted or settled
+pub fn settle() -> usize {
+    unsafe {
+        let left = REDUCTIONS_LEFT.max(0);
+        let consumed = GRANTED.saturating_sub(left).max(0) as usize;
+        REDUCTIONS_LEFT = left;
+        GRANTED = left;
+        consumed
+    }
+}
+
+/// The outcome of resuming a [`Resumable`] computation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Step<T> {
+    /// The budget ran out, and the computation must be resumed to continue
+    Yield,
+    /// The computation completed with the given output
+    Done(T),
+}
+
+/// A long-running computation which can be suspended when its process runs out of reductions, and
+/// resumed where it left off
+pub trait Resumable {
+    type Output;
+
+    /// Continues the computation, decrementing `budget` by the reductions consumed
+    ///
+    /// Returns [`Step::Yield`] when the budget reaches zero before the computation completes.
+    fn resume(&mut self, budget: &mut usize) -> Step<Self::Output>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts to a target, consuming a reduction for each number counted
+    struct Count {
+        n: usize,
+        target: usize,
+    }
+    impl Resumable for Count {
+        type Output = usize;
+
+        fn resume(&mut self, budget: &mut usize) -> Step<Self::Output> {
+            while self.n < self.target {
+                if *budget == 0 {
+                    return Step::Yield;
+                }
+                *budget -= 1;
+                self.n += 1;
+            }
+            Step::Done(self.n)
+        }
+    }
+
+    #[test]
+    fn resumable_test() {
+        let mut count = Count {
+            n: 0,
+            target: MAX_REDUCTIONS * 2 + 1,
+        };
+        let mut yields = 0;
+        let n = loop {
+            let mut budget = MAX_REDUCTIONS;
+            match count.resume(&mut budget) {
+                Step::Yield => {
+                    assert_eq!(budget, 0);
+                    yields += 1;
+                }
+                Step::Done(n) => {
+                    assert_eq!(budget, MAX_REDUCTIONS - 1);
+                    break n;
+                }
+            }
+        };
+        assert_eq!(yields, 2);
+        assert_eq!(n, MAX_REDUCTIONS * 2 + 1);
+    }
+
+    #[test]
+    fn settle_test() {
+        grant(MAX_REDUCTIONS);
+        unsafe {
+            REDUCTIONS_LEFT -= 10;
+        }
+        assert_eq!(settle(), 10);
+        assert_eq!(settle(), 0);
+
+        // Consuming beyond zero is charged as if generated code had stopped at zero
+        unsafe {
+            REDUCTIONS_LEFT = -1;
+        }
+        assert_eq!(settle(), MAX_REDUCTIONS - 10);
+        assert_eq!(unsafe { REDUCTIONS_LEFT }, 0);
+    }
+}
@@ -0,0 +1,384 @@
+//! Stack maps describe where the frames of generated code hold live terms, so that the roots of a
+//! running process can be found precisely, rather than by scanning its stack for anything which
+//! looks like a term.
+//!
+//! The compiler moves every term which is live across a call to a stack slot, which is stored to
+//! when the term is computed, and loaded from wherever it is used after the call. Each such call is
+//! made via an LLVM statepoint, whose stack map record lists the slots of the terms live across it,
+//! keyed by the return address of the call. When a process is collected, its stack is walked, and
+//! the slots listed for the return address of each frame are its roots. As generated code always
+//! reloads a term from its slot after a call, the collector moves a term by updating its slot.
+//!
+//! A slot may also hold a value which is not a term, e.g. an index, which the collector skips, as
+//! it is indistinguishable from an immediate.
+//!
+//! Not everything which refers to the heap can be found this way. Stack maps are emitted after
+//! optimization, which may leave a pointer derived from a term live across a call, and the frames
+//! of native code, e.g. a BIF which calls a fun, hold terms without describing them. So every call
+//! in generated code has a record, even if no term is live across it, and a frame without one is
+//! taken to be native. The records of calls across which a derived pointer is live are marked as
+//! pinning, see [`CallSite::pinning`]. While a native frame is on the stack between frames of
+//! generated code, or the frame of a pinning call is, the collector must not move anything, see
+//! [`StackRoots::pinned`]. Native frames above the most recent frame of generated code are those of
+//! the collector's caller, which passes the terms it holds as roots of its own.
+//!
+//! # Format
+//!
+//! Each module contributes the address of its LLVM stack map to the [`STACK_MAPS_SECTION`] section,
+//! see the [LLVM documentation](https://llvm.org/docs/StackMaps.html#stack-map-format) for the
+//! format of version 3, which is the one supported. The locations of each record are those of a
+//! statepoint, i.e. three constants, the last of which is the number of deopt locations which
+//! follow. Those are the slots, as `Direct` locations relative to the stack or frame pointer of the
+//! caller. The ID of a record is [`PINNING_ID`] for a pinning call, and zero otherwise. A null
+//! address is valid, and is emitted by the runtime itself so that the section always exists.
+use alloc::vec::Vec;
+use core::mem;
+use core::ptr;
+
+use firefly_system::sync::{const_rwlock, RwLock};
+
+#[cfg(feature = "std")]
+use crate::term::OpaqueTerm;
+
+#[cfg(feature = "std")]
+use super::ProcessStack;
+
+/// The name of the section in which the compiler places the address of each module's stack map
+///
+/// On Mach-O targets, this section is placed in the `__DATA` segment.
+pub const STACK_MAPS_SECTION: &str = "__firefly_stackmaps";
+
+/// The address of the stack map of a module, see [`STACK_MAPS_SECTION`]
+#[repr(transparent)]
+pub struct StackMapRef(pub *const u8);
+unsafe impl Sync for StackMapRef {}
+
+/// The ID of the record of a call across which something the collector cannot update is live
+pub const PINNING_ID: u64 = 1;
+
+/// The register relative to which the address of a slot is given
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Base {
+    StackPointer,
+    /// The frame pointer is assumed to point at the frame record, which is at the top of the frame
+    FramePointer,
+}
+
+/// A stack slot holding a term live across a call
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Slot {
+    pub base: Base,
+    pub offset: i32,
+}
+
+/// A call in generated code, as described by its record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallSite {
+    /// The slots of the terms live across the call
+    pub slots: Vec<Slot>,
+    /// True if something which is not a term, but refers to the heap, is also live across the
+    /// call, e.g. a pointer derived from a term, so that nothing on the heap may be moved while
+    /// the frame which made it is on the stack
+    pub pinning: bool,
+}
+
+// The DWARF register numbers of the stack and frame pointers
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "aarch64")] {
+        const SP: u16 = 31;
+        const FP: u16 = 29;
+    } else {
+        const SP: u16 = 7;
+        const FP: u16 = 6;
+    }
+}
+
+const VERSION: u8 = 3;
+const DIRECT: u8 = 2;
+const CONSTANT: u8 = 4;
+
+/// The calls in generated code, sorted by their return address
+static STACK_MAPS: RwLock<Vec<(usize, CallSite)>> = const_rwlock(Vec::new());
+
+/// Sets the stack maps of the running executable, given the bounds of the stack map section
+///
+/// Returns false if a stack map is malformed, in which case none are used.
+///
+/// # Safety
+///
+/// The given pointers must denote the bounds of the stack map section in the current executable
+pub unsafe fn set_stack_maps(start: *const StackMapRef, end: *const StackMapRef) -> bool {
+    if start.is_null() || end.is_null() || end <= start {
+        return true;
+    }
+    let len = end.offset_from(start) as usize;
+    let mut calls = Vec::new();
+    for stack_map in core::slice::from_raw_parts(start, len) {
+        if !stack_map.0.is_null() && parse(stack_map.0, &mut calls).is_none() {
+            return false;
+        }
+    }
+    calls.sort_unstable_by_key(|(return_address, _)| *return_address);
+    *STACK_MAPS.write() = calls;
+    true
+}
+
+/// Appends the calls described by the stack map at `ptr` to `calls`
+///
+/// Returns `None` if the stack map is malformed.
+unsafe fn parse(ptr: *const u8, calls: &mut Vec<(usize, CallSite)>) -> Option<()> {
+    let mut reader = Reader(ptr);
+    let version = reader.read::<u8>();
+    let reserved = (reader.read::<u8>(), reader.read::<u16>());
+    if version != VERSION || reserved != (0, 0) {
+        return None;
+    }
+    let num_functions = reader.read::<u32>() as usize;
+    let num_constants = reader.read::<u32>() as usize;
+    let _num_records = reader.read::<u32>();
+    let functions = (0..num_functions)
+        .map(|_| {
+            let address = reader.read::<u64>() as usize;
+            let _stack_size = reader.read::<u64>();
+            (address, reader.read::<u64>() as usize)
+        })
+        .collect::<Vec<_>>();
+    reader.skip(num_constants * mem::size_of::<u64>());
+
+    for (address, num_records) in functions {
+        for _ in 0..num_records {
+            let pinning = match reader.read::<u64>() {
+                0 => false,
+                PINNING_ID => true,
+                _ => return None,
+            };
+            let offset = reader.read::<u32>() as usize;
+            let _flags = reader.read::<u16>();
+            let num_locations = reader.read::<u16>() as usize;
+            let locations = (0..num_locations)
+                .map(|_| {
+                    let kind = reader.read::<u8>();
+                    let _reserved = reader.read::<u8>();
+                    let _size = reader.read::<u16>();
+                    let register = reader.read::<u16>();
+                    let _reserved = reader.read::<u16>();
+                    (kind, register, reader.read::<i32>())
+                })
+                .collect::<Vec<_>>();
+            reader.align(8);
+            let _padding = reader.read::<u16>();
+            let num_live_outs = reader.read::<u16>() as usize;
+            reader.skip(num_live_outs * 4);
+            reader.align(8);
+
+            let (CONSTANT, _, num_deopt) = *locations.get(2)? else { return None };
+            let deopt = locations.get(3..(3 + usize::try_from(num_deopt).ok()?))?;
+            let mut slots = Vec::with_capacity(deopt.len());
+            for (kind, register, offset) in deopt.iter().copied() {
+                let base = match (kind, register) {
+                    (DIRECT, SP) => Base::StackPointer,
+                    (DIRECT, FP) => Base::FramePointer,
+                    _ => return None,
+                };
+                slots.push(Slot { base, offset });
+            }
+            calls.push((address + offset, CallSite { slots, pinning }));
+        }
+    }
+    Some(())
+}
+
+/// Reads the unaligned fields of a stack map in order
+struct Reader(*const u8);
+impl Reader {
+    unsafe fn read<T: Copy>(&mut self) -> T {
+        let value = ptr::read_unaligned(self.0 as *const T);
+        self.0 = self.0.add(mem::size_of::<T>());
+        value
+    }
+
+    unsafe fn skip(&mut self, bytes: usize) {
+        self.0 = self.0.add(bytes);
+    }
+
+    unsafe fn align(&mut self, align: usize) {
+        self.0 = self.0.add(self.0.align_offset(align));
+    }
+}
+
+/// Returns the call in generated code with the given return address, if it has a stack map
+pub fn find_call_site(return_address: usize) -> Option<CallSite> {
+    let calls = STACK_MAPS.read();
+    calls
+        .binary_search_by_key(&return_address, |(address, _)| *address)
+        .ok()
+        .map(|index| calls[index].1.clone())
+}
+
+/// The roots found on the stack of a process, see [`stack_roots`]
+#[cfg(feature = "std")]
+pub struct StackRoots {
+    /// The addresses of the slots holding the terms live in the frames of generated code
+    pub slots: Vec<*mut OpaqueTerm>,
+    /// True if the stack may refer to the heap other than from `slots`, so that nothing on the
+    /// heap may be moved, i.e. a native frame is on the stack between frames of generated code, a
+    /// frame of generated code made a pinning call, see [`CallSite::pinning`], or there are no
+    /// stack maps at all
+    pub pinned: bool,
+}
+
+/// Returns the roots of the running process, i.e. the slots listed by the stack maps of the frames
+/// of generated code on its stack
+///
+/// # Safety
+///
+/// Must be called on `stack`, i.e. by the process being collected, and the slots are only valid
+/// until the frame which called this returns.
+#[cfg(feature = "std")]
+pub unsafe fn stack_roots(stack: &ProcessStack) -> StackRoots {
+    let mut roots = StackRoots {
+        slots: Vec::new(),
+        pinned: true,
+    };
+    if STACK_MAPS.read().is_empty() {
+        return roots;
+    }
+    roots.pinned = false;
+
+    let bounds = (stack.bottom as usize)..=(stack.top as usize);
+    // The canonical frame address of the frame called by the current one, which is the value the
+    // stack pointer of the current frame had when it made that call
+    let mut callee_cfa = None;
+    // Whether a frame of generated code has been walked, and whether a native frame has been
+    // walked since, which is only allowed below the oldest frame of generated code
+    let mut generated = false;
+    let mut native = false;
+    backtrace::trace(|frame| {
+        let cfa = frame.sp() as usize;
+        if !bounds.contains(&cfa) {
+            // Frames below the process stack are those of the runtime calling into the process
+            return callee_cfa.is_none();
+        }
+        if let Some(sp) = callee_cfa {
+            match find_call_site(frame.ip() as usize) {
+                Some(call) => {
+                    roots.pinned |= native || call.pinning;
+                    generated = true;
+                    let fp = cfa - 2 * mem::size_of::<usize>();
+                    for slot in call.slots {
+                        let base = match slot.base {
+                            Base::StackPointer => sp,
+                            Base::FramePointer => fp,
+                        };
+                        let address = (base as isize + slot.offset as isize) as usize;
+                        roots.slots.push(address as *mut OpaqueTerm);
+                    }
+                }
+                None => native |= generated,
+            }
+        }
+        callee_cfa = Some(cfa);
+        true
+    });
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack_map(id: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&[VERSION, 0, 0, 0]);
+        // One function, no constants, one record
+        bytes.extend_from_slice(&1u32.to_ne_bytes());
+        bytes.extend_from_slice(&0u32.to_ne_bytes());
+        bytes.extend_from_slice(&1u32.to_ne_bytes());
+        bytes.extend_from_slice(&0x1000u64.to_ne_bytes());
+        bytes.extend_from_slice(&32u64.to_ne_bytes());
+        bytes.extend_from_slice(&1u64.to_ne_bytes());
+        // The record for the call returning to 0x1010, with two slots
+        bytes.extend_from_slice(&id.to_ne_bytes());
+        bytes.extend_from_slice(&0x10u32.to_ne_bytes());
+        bytes.extend_from_slice(&0u16.to_ne_bytes());
+        bytes.extend_from_slice(&5u16.to_ne_bytes());
+        let mut location = |kind: u8, register: u16, offset: i32| {
+            bytes.extend_from_slice(&[kind, 0]);
+            bytes.extend_from_slice(&8u16.to_ne_bytes());
+            bytes.extend_from_slice(&register.to_ne_bytes());
+            bytes.extend_from_slice(&0u16.to_ne_bytes());
+            bytes.extend_from_slice(&offset.to_ne_bytes());
+        };
+        location(CONSTANT, 0, 0);
+        location(CONSTANT, 0, 0);
+        location(CONSTANT, 0, 2);
+        location(DIRECT, SP, 8);
+        location(DIRECT, FP, -16);
+        // Padding to 8 bytes, then no live outs
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    /// Returns a copy of `bytes` aligned as it would be in the stack maps section
+    fn aligned_copy(bytes: &[u8]) -> Vec<u64> {
+        let mut aligned = alloc::vec![0u64; (bytes.len() + 7) / 8];
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), aligned.as_mut_ptr() as *mut u8, bytes.len());
+        }
+        aligned
+    }
+
+    #[test]
+    fn parse_test() {
+        let mut aligned = aligned_copy(&stack_map(0));
+
+        let mut calls = Vec::new();
+        assert_eq!(
+            unsafe { parse(aligned.as_ptr() as *const u8, &mut calls) },
+            Some(())
+        );
+        assert_eq!(calls.len(), 1);
+        let (return_address, call) = &calls[0];
+        assert_eq!(*return_address, 0x1010);
+        assert!(!call.pinning);
+        assert_eq!(
+            call.slots.as_slice(),
+            &[
+                Slot {
+                    base: Base::StackPointer,
+                    offset: 8
+                },
+                Slot {
+                    base: Base::FramePointer,
+                    offset: -16
+                },
+            ]
+        );
+
+        unsafe { *(aligned.as_mut_ptr() as *mut u8) = 2 };
+        assert_eq!(
+            unsafe { parse(aligned.as_ptr() as *const u8, &mut calls) },
+            None
+        );
+    }
+
+    #[test]
+    fn pinning_test() {
+        let aligned = aligned_copy(&stack_map(PINNING_ID));
+        let mut calls = Vec::new();
+        assert_eq!(
+            unsafe { parse(aligned.as_ptr() as *const u8, &mut calls) },
+            Some(())
+        );
+        assert!(calls[0].1.pinning);
+        assert_eq!(calls[0].1.slots.len(), 2);
+
+        // No other IDs are emitted by the compiler
+        let aligned = aligned_copy(&stack_map(2));
+        assert_eq!(
+            unsafe { parse(aligned.as_ptr() as *const u8, &mut calls) },
+            None
+        );
+    }
+}
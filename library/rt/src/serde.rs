@@ -0,0 +1,1062 @@
+//! Conversion of Rust values to and from terms via `serde`, enabled by the `serde` feature.
+//!
+//! [`to_term`] serializes any value implementing `Serialize` to a term on a heap, and [`from_term`]
+//! deserializes any value implementing `Deserialize` from a term, with the following shapes:
+//!
+//! | Rust                                  | Erlang                                      |
+//! |---------------------------------------|---------------------------------------------|
+//! | `bool`                                | `true`, `false`                             |
+//! | integers, `char`                      | integer                                     |
+//! | floats                                | float                                       |
+//! | strings                               | binary, or a charlist when deserializing    |
+//! | bytes                                 | binary                                      |
+//! | `None`, `Some(x)`                     | `undefined`, `x`                            |
+//! | `()`                                  | `ok`                                        |
+//! | unit struct `S`, unit variant `V`     | `'S'`, `'V'`                                |
+//! | newtype struct                        | its value                                   |
+//! | sequence                              | list                                        |
+//! | tuple, tuple struct                   | tuple                                       |
+//! | map, struct                           | map                                         |
+//! | variants `V(x)`, `V(x, y)`, `V { .. }`| `{'V', x}`, `{'V', x, y}`, `{'V', #{..}}`   |
+//!
+//! Atoms are named as serde names them, so `#[serde(rename_all = "snake_case")]` gives the usual
+//! Erlang style. Whether the fields of structs and the string keys of maps are atoms or binaries is
+//! chosen with a [`KeyPolicy`], while either is accepted when deserializing.
+//!
+//! Unlike [`IntoTerm`](crate::term::IntoTerm) and [`FromTerm`](crate::term::FromTerm), the shapes
+//! are fixed by serde's data model, but any type implementing serde's traits can be converted.
+//! Strings and bytes can also be borrowed from the binaries of a term by [`from_term_borrowed`],
+//! rather than copied.
+use alloc::string::{String, ToString};
+use alloc::vec::{self, Vec};
+use core::alloc::AllocError;
+use core::fmt;
+use core::marker::PhantomData;
+
+use ::serde::de::{self, DeserializeOwned, DeserializeSeed, Unexpected, Visitor};
+use ::serde::ser::{self, Serialize};
+use ::serde::{forward_to_deserialize_any, Deserialize};
+
+use firefly_alloc::heap::Heap;
+use firefly_binary::Bitstring;
+use firefly_number::ToPrimitive;
+
+use crate::term::__support as support;
+use crate::term::{Atom, IntoTerm, Term};
+
+/// How the fields of structs, and the string keys of maps, are represented
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyPolicy {
+    /// Keys are atoms, e.g. `#{name => <<"x">>}`, which is the default
+    Atom,
+    /// Keys are binaries, e.g. `#{<<"name">> => <<"x">>}`, which avoids creating atoms from
+    /// arbitrary strings
+    Binary,
+}
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self::Atom
+    }
+}
+
+/// The error produced when a value cannot be converted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The heap is too small to hold the term
+    Alloc,
+    /// The value or term cannot be converted, for the given reason
+    Message(String),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Alloc => f.write_str("unable to allocate term"),
+            Self::Message(message) => f.write_str(message),
+        }
+    }
+}
+impl ::serde::ser::StdError for Error {}
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Self::Message(message.to_string())
+    }
+}
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Self::Message(message.to_string())
+    }
+}
+impl From<AllocError> for Error {
+    fn from(_: AllocError) -> Self {
+        Self::Alloc
+    }
+}
+
+/// Serializes `value` to a term allocated on `heap`, with keys which are atoms
+pub fn to_term<T, H>(value: &T, heap: &H) -> Result<Term, Error>
+where
+    T: Serialize + ?Sized,
+    H: Heap,
+{
+    value.serialize(Serializer::new(heap, KeyPolicy::Atom))
+}
+
+/// Serializes `value` to a term allocated on `heap`, with keys represented according to `keys`
+pub fn to_term_with<T, H>(value: &T, heap: &H, keys: KeyPolicy) -> Result<Term, Error>
+where
+    T: Serialize + ?Sized,
+    H: Heap,
+{
+    value.serialize(Serializer::new(heap, keys))
+}
+
+/// Deserializes a value from `term`, copying any strings or bytes it contains
+pub fn from_term<T: DeserializeOwned>(term: Term) -> Result<T, Error> {
+    // SAFETY: The value cannot borrow from the term
+    unsafe { from_term_borrowed(term) }
+}
+
+/// Deserializes a value from `term`, which may borrow strings and bytes from its binaries
+///
+/// # Safety
+///
+/// The caller must ensure that `term` is neither freed nor moved, e.g. by a garbage collection,
+/// while the value is alive.
+pub unsafe fn from_term_borrowed<'de, T: Deserialize<'de>>(term: Term) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(term))
+}
+
+/// A `serde::Serializer` which produces terms allocated on a heap
+pub struct Serializer<'a, H: Heap> {
+    heap: &'a H,
+    keys: KeyPolicy,
+    /// Whether a key of a map is being serialized, so that strings are subject to the key policy
+    key: bool,
+}
+impl<'a, H: Heap> Clone for Serializer<'a, H> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<'a, H: Heap> Copy for Serializer<'a, H> {}
+impl<'a, H: Heap> Serializer<'a, H> {
+    pub fn new(heap: &'a H, keys: KeyPolicy) -> Self {
+        Self {
+            heap,
+            keys,
+            key: false,
+        }
+    }
+
+    fn value<T: IntoTerm>(&self, value: T) -> Result<Term, Error> {
+        Ok(value.into_term(self.heap)?)
+    }
+
+    fn atom(&self, name: &str) -> Result<Term, Error> {
+        match name {
+            "true" => Ok(Term::Bool(true)),
+            "false" => Ok(Term::Bool(false)),
+            name => Atom::try_from(name)
+                .map(Term::Atom)
+                .map_err(|_| ser::Error::custom(format_args!("invalid atom '{}'", name))),
+        }
+    }
+
+    /// Returns the key for the field or string key `name`, according to the key policy
+    fn key(&self, name: &str) -> Result<Term, Error> {
+        match self.keys {
+            KeyPolicy::Atom => self.atom(name),
+            KeyPolicy::Binary => self.value(name),
+        }
+    }
+
+    fn tuple(&self, elements: &[Term]) -> Result<Term, Error> {
+        Ok(support::tuple(elements, self.heap)?)
+    }
+
+    /// Returns a serializer for the elements of a compound value
+    fn element(&self) -> Self {
+        Self {
+            key: false,
+            ..*self
+        }
+    }
+
+    fn elements(
+        self,
+        tag: Option<&str>,
+        len: usize,
+        list: bool,
+    ) -> Result<SerializeVec<'a, H>, Error> {
+        let mut elements = Vec::with_capacity(len + 1);
+        if let Some(tag) = tag {
+            elements.push(self.atom(tag)?);
+        }
+        Ok(SerializeVec {
+            ser: self.element(),
+            elements,
+            list,
+        })
+    }
+
+    fn pairs(self, tag: Option<&str>, len: usize) -> Result<SerializeMap<'a, H>, Error> {
+        let tag = match tag {
+            Some(tag) => Some(self.atom(tag)?),
+            None => None,
+        };
+        Ok(SerializeMap {
+            ser: self.element(),
+            pairs: Vec::with_capacity(len),
+            key: None,
+            tag,
+        })
+    }
+}
+
+impl<'a, H: Heap> ser::Serializer for Serializer<'a, H> {
+    type Ok = Term;
+    type Error = Error;
+    type SerializeSeq = SerializeVec<'a, H>;
+    type SerializeTuple = SerializeVec<'a, H>;
+    type SerializeTupleStruct = SerializeVec<'a, H>;
+    type SerializeTupleVariant = SerializeVec<'a, H>;
+    type SerializeMap = SerializeMap<'a, H>;
+    type SerializeStruct = SerializeMap<'a, H>;
+    type SerializeStructVariant = SerializeMap<'a, H>;
+
+    fn serialize_bool(self, v: bool) -> Result<Term, Error> {
+        Ok(Term::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Term, Error> {
+        self.value(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Term, Error> {
+        if self.key {
+            self.key(v)
+        } else {
+            self.value(v)
+        }
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Term, Error> {
+        self.value(v)
+    }
+
+    fn serialize_none(self) -> Result<Term, Error> {
+        self.atom("undefined")
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Term, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Term, Error> {
+        self.atom("ok")
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Term, Error> {
+        self.atom(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Term, Error> {
+        self.atom(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Term, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Term, Error> {
+        let tag = self.atom(variant)?;
+        let value = value.serialize(self.element())?;
+        self.tuple(&[tag, value])
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec<'a, H>, Error> {
+        self.elements(None, len.unwrap_or(0), true)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec<'a, H>, Error> {
+        self.elements(None, len, false)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec<'a, H>, Error> {
+        self.elements(None, len, false)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec<'a, H>, Error> {
+        self.elements(Some(variant), len, false)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeMap<'a, H>, Error> {
+        self.pairs(None, len.unwrap_or(0))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeMap<'a, H>, Error> {
+        self.pairs(None, len)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeMap<'a, H>, Error> {
+        self.pairs(Some(variant), len)
+    }
+}
+
+/// Serializes sequences to lists, and tuples to tuples
+pub struct SerializeVec<'a, H: Heap> {
+    ser: Serializer<'a, H>,
+    elements: Vec<Term>,
+    list: bool,
+}
+impl<'a, H: Heap> SerializeVec<'a, H> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.elements.push(value.serialize(self.ser)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Term, Error> {
+        if self.list {
+            Ok(support::list(self.elements.as_slice(), self.ser.heap)?)
+        } else {
+            self.ser.tuple(self.elements.as_slice())
+        }
+    }
+}
+impl<'a, H: Heap> ser::SerializeSeq for SerializeVec<'a, H> {
+    type Ok = Term;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Term, Error> {
+        self.finish()
+    }
+}
+impl<'a, H: Heap> ser::SerializeTuple for SerializeVec<'a, H> {
+    type Ok = Term;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Term, Error> {
+        self.finish()
+    }
+}
+impl<'a, H: Heap> ser::SerializeTupleStruct for SerializeVec<'a, H> {
+    type Ok = Term;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Term, Error> {
+        self.finish()
+    }
+}
+impl<'a, H: Heap> ser::SerializeTupleVariant for SerializeVec<'a, H> {
+    type Ok = Term;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Term, Error> {
+        self.finish()
+    }
+}
+
+/// Serializes maps and structs to maps, tagged with the variant of a struct variant
+pub struct SerializeMap<'a, H: Heap> {
+    ser: Serializer<'a, H>,
+    pairs: Vec<(Term, Term)>,
+    key: Option<Term>,
+    tag: Option<Term>,
+}
+impl<'a, H: Heap> SerializeMap<'a, H> {
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        let key = self.ser.key(key)?;
+        let value = value.serialize(self.ser)?;
+        self.pairs.push((key, value));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Term, Error> {
+        let map = support::map(self.pairs.as_slice(), self.ser.heap)?;
+        match self.tag {
+            None => Ok(map),
+            Some(tag) => self.ser.tuple(&[tag, map]),
+        }
+    }
+}
+impl<'a, H: Heap> ser::SerializeMap for SerializeMap<'a, H> {
+    type Ok = Term;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let ser = Serializer {
+            key: true,
+            ..self.ser
+        };
+        self.key = Some(key.serialize(ser)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::Message("map value serialized before its key".to_string()))?;
+        let value = value.serialize(self.ser)?;
+        self.pairs.push((key, value));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Term, Error> {
+        self.finish()
+    }
+}
+impl<'a, H: Heap> ser::SerializeStruct for SerializeMap<'a, H> {
+    type Ok = Term;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Term, Error> {
+        self.finish()
+    }
+}
+impl<'a, H: Heap> ser::SerializeStructVariant for SerializeMap<'a, H> {
+    type Ok = Term;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Term, Error> {
+        self.finish()
+    }
+}
+
+/// A `serde::Deserializer` which reads a term
+pub struct Deserializer<'de> {
+    term: Term,
+    marker: PhantomData<&'de ()>,
+}
+impl<'de> Deserializer<'de> {
+    /// Creates a deserializer for `term`, which may lend the strings and bytes of its binaries for
+    /// the lifetime `'de`
+    ///
+    /// # Safety
+    ///
+    /// See [`from_term_borrowed`].
+    pub unsafe fn new(term: Term) -> Self {
+        Self {
+            term,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the bytes of the term if it is a binary
+    fn bytes(&self) -> Option<&'de [u8]> {
+        let bits = self.term.as_bitstring()?;
+        if !bits.is_binary() || !bits.is_aligned() {
+            return None;
+        }
+        let bytes = unsafe { bits.as_bytes_unchecked() };
+        // SAFETY: The caller of `new` guarantees that the binary outlives `'de`
+        Some(unsafe { core::slice::from_raw_parts(bytes.as_ptr(), bytes.len()) })
+    }
+
+    /// Returns the elements of the term if it is a list, tuple or binary
+    fn elements(&self) -> Result<Vec<Term>, Error> {
+        match self.term {
+            Term::Nil => Ok(Vec::new()),
+            Term::Cons(ptr) => unsafe { ptr.as_ref() }
+                .iter()
+                .map(|element| element.map_err(|_| de::Error::custom("improper list")))
+                .collect(),
+            Term::Tuple(ptr) => Ok(unsafe { ptr.as_ref() }
+                .as_slice()
+                .iter()
+                .map(|element| (*element).into())
+                .collect()),
+            _ => match self.bytes() {
+                Some(bytes) => Ok(bytes.iter().map(|b| Term::Int(*b as i64)).collect()),
+                None => Err(self.invalid("a list or tuple")),
+            },
+        }
+    }
+
+    fn invalid(&self, expected: &str) -> Error {
+        let unexpected = match self.term {
+            Term::Bool(b) => Unexpected::Bool(b),
+            Term::Int(i) => Unexpected::Signed(i),
+            Term::Float(f) => Unexpected::Float(f.inner()),
+            Term::Atom(atom) => Unexpected::Str(atom.as_str()),
+            Term::Nil | Term::Cons(_) => Unexpected::Seq,
+            Term::Map(_) => Unexpected::Map,
+            Term::BigInt(_) => Unexpected::Other("big integer"),
+            Term::Tuple(_) => Unexpected::Other("tuple"),
+            Term::Closure(_) => Unexpected::Other("fun"),
+            Term::Pid(_) => Unexpected::Other("pid"),
+            Term::Port(_) => Unexpected::Other("port"),
+            Term::Reference(_) => Unexpected::Other("reference"),
+            Term::None => Unexpected::Other("none"),
+            _ => Unexpected::Other("bitstring"),
+        };
+        de::Error::invalid_type(unexpected, &expected)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.term {
+            Term::Bool(b) => visitor.visit_bool(b),
+            Term::Atom(atom) => visitor.visit_borrowed_str(atom.as_str()),
+            Term::Int(i) => visitor.visit_i64(i),
+            Term::BigInt(ref i) => match (i.to_i128(), i.to_u128()) {
+                (Some(i), _) => visitor.visit_i128(i),
+                (None, Some(i)) => visitor.visit_u128(i),
+                (None, None) => Err(self.invalid("an integer of at most 128 bits")),
+            },
+            Term::Float(f) => visitor.visit_f64(f.inner()),
+            Term::Nil | Term::Cons(_) | Term::Tuple(_) => self.deserialize_seq(visitor),
+            Term::Map(_) => self.deserialize_map(visitor),
+            _ => match self.bytes() {
+                Some(bytes) => match core::str::from_utf8(bytes) {
+                    Ok(s) => visitor.visit_borrowed_str(s),
+                    Err(_) => visitor.visit_borrowed_bytes(bytes),
+                },
+                None => Err(self.invalid("a term supported by serde")),
+            },
+        }
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.term {
+            Term::Int(i) => match u32::try_from(i).ok().and_then(char::from_u32) {
+                Some(c) => visitor.visit_char(c),
+                None => Err(self.invalid("a character")),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.term {
+            Term::Atom(atom) => visitor.visit_borrowed_str(atom.as_str()),
+            Term::Bool(b) => visitor.visit_borrowed_str(if b { "true" } else { "false" }),
+            Term::Nil => visitor.visit_borrowed_str(""),
+            Term::Cons(ptr) => match unsafe { ptr.as_ref() }.to_string() {
+                Some(s) => visitor.visit_string(s),
+                None => Err(self.invalid("a string")),
+            },
+            _ => match self.bytes().map(core::str::from_utf8) {
+                Some(Ok(s)) => visitor.visit_borrowed_str(s),
+                _ => Err(self.invalid("a string")),
+            },
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.bytes() {
+            Some(bytes) => visitor.visit_borrowed_bytes(bytes),
+            None => {
+                let bytes = self
+                    .elements()?
+                    .into_iter()
+                    .map(|element| match element {
+                        Term::Int(i) => u8::try_from(i).ok(),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                match bytes {
+                    Some(bytes) => visitor.visit_byte_buf(bytes),
+                    None => Err(self.invalid("a binary or list of bytes")),
+                }
+            }
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.term {
+            Term::Atom(atom) if atom.as_str() == "undefined" => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.term {
+            Term::Atom(atom) if atom.as_str() == "ok" => visitor.visit_unit(),
+            _ => Err(self.invalid("ok")),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.term {
+            Term::Atom(atom) if atom.as_str() == name => visitor.visit_unit(),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let elements = self.elements()?;
+        visitor.visit_seq(SeqAccess::new(elements))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.term {
+            Term::Map(ref map) => {
+                let pairs = map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+                visitor.visit_map(MapAccess::new(pairs))
+            }
+            _ => Err(self.invalid("a map")),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.term {
+            Term::Atom(atom) => visitor.visit_enum(EnumAccess {
+                tag: atom.as_str(),
+                elements: None,
+            }),
+            Term::Tuple(ptr) => {
+                let mut elements = unsafe { ptr.as_ref() }
+                    .as_slice()
+                    .iter()
+                    .map(|element| Term::from(*element))
+                    .collect::<Vec<_>>();
+                let tag = match elements.first() {
+                    Some(Term::Atom(atom)) => atom.as_str(),
+                    _ => return Err(self.invalid("a tuple tagged with an atom")),
+                };
+                elements.remove(0);
+                visitor.visit_enum(EnumAccess {
+                    tag,
+                    elements: Some(elements),
+                })
+            }
+            _ => Err(self.invalid("an atom or tagged tuple")),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64
+    }
+}
+
+struct SeqAccess<'de> {
+    elements: vec::IntoIter<Term>,
+    marker: PhantomData<&'de ()>,
+}
+impl<'de> SeqAccess<'de> {
+    fn new(elements: Vec<Term>) -> Self {
+        Self {
+            elements: elements.into_iter(),
+            marker: PhantomData,
+        }
+    }
+}
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.elements.next() {
+            // SAFETY: The element is part of the term given to the parent deserializer
+            Some(term) => seed
+                .deserialize(unsafe { Deserializer::new(term) })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct MapAccess<'de> {
+    pairs: vec::IntoIter<(Term, Term)>,
+    value: Option<Term>,
+    marker: PhantomData<&'de ()>,
+}
+impl<'de> MapAccess<'de> {
+    fn new(pairs: Vec<(Term, Term)>) -> Self {
+        Self {
+            pairs: pairs.into_iter(),
+            value: None,
+            marker: PhantomData,
+        }
+    }
+}
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.pairs.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                // SAFETY: The key is part of the term given to the parent deserializer
+                seed.deserialize(unsafe { Deserializer::new(key) })
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            // SAFETY: The value is part of the term given to the parent deserializer
+            Some(value) => seed.deserialize(unsafe { Deserializer::new(value) }),
+            None => Err(de::Error::custom("map value deserialized before its key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.pairs.len())
+    }
+}
+
+/// The variant of an enum, i.e. an atom, or a tuple tagged with one
+struct EnumAccess<'de> {
+    tag: &'de str,
+    elements: Option<Vec<Term>>,
+}
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = Error;
+    type Variant = VariantAccess<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantAccess<'de>), Error> {
+        let tag = de::value::BorrowedStrDeserializer::<Error>::new(self.tag);
+        let variant = seed.deserialize(tag)?;
+        Ok((
+            variant,
+            VariantAccess {
+                elements: self.elements,
+                marker: PhantomData,
+            },
+        ))
+    }
+}
+
+struct VariantAccess<'de> {
+    elements: Option<Vec<Term>>,
+    marker: PhantomData<&'de ()>,
+}
+impl<'de> VariantAccess<'de> {
+    /// Returns the value of a newtype or struct variant, i.e. the second element of a pair
+    fn value(self, expected: &str) -> Result<Deserializer<'de>, Error> {
+        match self.elements.as_deref() {
+            // SAFETY: The value is part of the term given to the parent deserializer
+            Some([value]) => Ok(unsafe { Deserializer::new(*value) }),
+            _ => Err(de::Error::invalid_type(
+                Unexpected::Other("variant"),
+                &expected,
+            )),
+        }
+    }
+}
+impl<'de> de::VariantAccess<'de> for VariantAccess<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.elements {
+            None => Ok(()),
+            Some(_) => Err(de::Error::invalid_type(
+                Unexpected::TupleVariant,
+                &"an atom",
+            )),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.value("a tagged pair")?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.elements {
+            Some(elements) => visitor.visit_seq(SeqAccess::new(elements)),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"a tagged tuple",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self.value("a tagged map")?, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use core::alloc::Layout;
+
+    use ::serde::{Deserialize, Serialize};
+    use firefly_alloc::fragment::HeapFragment;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect(u32, u32),
+        Named { name: String, sides: Option<u8> },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        name: String,
+        port: u16,
+        tags: Vec<String>,
+        shapes: Vec<Shape>,
+        limits: BTreeMap<String, i64>,
+        pair: (bool, char),
+    }
+
+    fn with_heap<F: FnOnce(&HeapFragment)>(fun: F) {
+        let layout = Layout::from_size_align(8192, 8).unwrap();
+        let fragment = HeapFragment::new(layout, None).unwrap();
+        fun(unsafe { fragment.as_ref() });
+    }
+
+    fn config() -> Config {
+        let mut limits = BTreeMap::new();
+        limits.insert(String::from("max"), 1 << 40);
+        Config {
+            name: String::from("server"),
+            port: 8080,
+            tags: alloc::vec![String::from("a"), String::from("b")],
+            shapes: alloc::vec![
+                Shape::Empty,
+                Shape::Circle(1.5),
+                Shape::Rect(2, 3),
+                Shape::Named {
+                    name: String::from("hex"),
+                    sides: Some(6),
+                },
+            ],
+            limits,
+            pair: (true, 'x'),
+        }
+    }
+
+    #[test]
+    fn round_trip_test() {
+        with_heap(|heap| {
+            for keys in [KeyPolicy::Atom, KeyPolicy::Binary] {
+                let term = to_term_with(&config(), heap, keys).unwrap();
+                assert_eq!(from_term::<Config>(term).unwrap(), config());
+            }
+        });
+    }
+
+    #[test]
+    fn shape_test() {
+        with_heap(|heap| {
+            let term = to_term(&Shape::Rect(2, 3), heap).unwrap();
+            let elements = term.as_tuple().unwrap().as_slice();
+            assert_eq!(elements.len(), 3);
+            assert_eq!(Term::from(elements[0]), support::atom("rect"));
+
+            let term = to_term(&config(), heap).unwrap();
+            let map = term.as_map().unwrap();
+            assert!(map.get(Atom::try_from("port").unwrap()).is_some());
+
+            let term = to_term_with(&config(), heap, KeyPolicy::Binary).unwrap();
+            let map = term.as_map().unwrap();
+            assert!(map.get(Atom::try_from("port").unwrap()).is_none());
+        });
+    }
+
+    #[test]
+    fn borrowed_test() {
+        with_heap(|heap| {
+            let term = ("hello", &b"bytes"[..]).into_term(heap).unwrap();
+            let (s, b): (&str, &[u8]) = unsafe { from_term_borrowed(term).unwrap() };
+            assert_eq!(s, "hello");
+            assert_eq!(b, b"bytes");
+
+            let bytes: Vec<u8> = from_term(term.as_tuple().unwrap().get(1).unwrap()).unwrap();
+            assert_eq!(bytes.as_slice(), b"bytes");
+        });
+    }
+}
@@ -8,7 +8,7 @@ pub mod atoms {
 
 mod table;
 
-pub use self::table::AtomData;
+pub use self::table::{AtomData, AtomTableMemory};
 
 use core::convert::AsRef;
 use core::fmt::{self, Debug, Display};
@@ -144,6 +144,11 @@ impl Atom {
         }
     }
 
+    /// Returns the memory allocated for the atom table, and how much of it is in use
+    pub fn table_memory() -> AtomTableMemory {
+        table::memory()
+    }
+
     /// Returns `true` if this atom represents a boolean
     pub fn is_boolean(self) -> bool {
         self == atoms::False || self == atoms::True
@@ -33,6 +33,14 @@ impl fmt::Display for TryAtomFromTermError {
 /// compile-time or runtime).
 ///
 /// NOTE: This struct must have a size that is a power of 8
+///
+/// # Section format
+///
+/// Records for atoms known at compile-time are placed in the `__atoms` section (`__DATA,__atoms`
+/// on Mach-O), which the linker merges into a single contiguous array of `AtomData` across all
+/// modules and the runtime itself. Each record is named `atom_<value>` (or `atom_<sha1>` when the
+/// value is not a valid symbol name), and the compiler ensures that only one record per atom
+/// survives linking, so the section can be consumed as-is by [`init`].
 #[derive(Debug, Copy, Clone)]
 #[repr(C, align(8))]
 pub struct AtomData {
@@ -111,6 +119,23 @@ pub(super) fn get_data(name: &str) -> Option<NonNull<AtomData>> {
     ATOMS.read().get_data(name)
 }
 
+/// The memory of the atom table, in bytes, see [`super::Atom::table_memory`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AtomTableMemory {
+    /// The memory allocated for the table, and for the atoms created at runtime
+    pub allocated: usize,
+    /// The part of `allocated` in use
+    pub used: usize,
+}
+
+/// Returns the memory of the global atom table
+///
+/// The names of the atoms in the atom section of the executable are not counted, as they are part
+/// of its read-only data rather than allocated.
+pub(super) fn memory() -> AtomTableMemory {
+    ATOMS.read().memory()
+}
+
 /// This struct represents the atom table, of which a program will only ever have one at a time,
 /// with static lifetime. The atoms it contains are never collected.
 struct AtomTable {
@@ -144,10 +169,26 @@ impl fmt::Debug for AtomTable {
 }
 impl AtomTable {
     fn extend(&mut self, data: &'static [AtomData]) {
+        self.ids.reserve(data.len());
         for atom in data {
             let ptr = unsafe { NonNull::new_unchecked(atom as *const AtomData as *mut AtomData) };
             let name = unsafe { atom.as_str().unwrap() };
-            self.ids.entry(name).or_insert(ptr);
+            let _existing = self.ids.entry(name).or_insert(ptr);
+            // The linker is expected to have merged duplicate records, if it did not, then
+            // atoms with the same value may not compare equal
+            debug_assert_eq!(
+                *_existing, ptr,
+                "duplicate atom record found in atom section for '{}'",
+                name
+            );
+        }
+    }
+
+    fn memory(&self) -> AtomTableMemory {
+        let entry = mem::size_of::<(&'static str, NonNull<AtomData>)>();
+        AtomTableMemory {
+            allocated: self.arena.allocated_bytes() + self.ids.capacity() * entry,
+            used: self.arena.used_bytes() + self.ids.len() * entry,
         }
     }
 
@@ -1,7 +1,9 @@
 mod matching;
+mod shared;
 mod slice;
 
 pub use self::matching::{MatchContext, MatchResult};
+pub use self::shared::{ExternalBinary, PinnedBinary};
 pub use self::slice::BitSlice;
 
 use alloc::alloc::{AllocError, Allocator};
@@ -13,6 +15,7 @@ use core::hash::{Hash, Hasher};
 use core::ops::{Index, IndexMut};
 use core::slice::SliceIndex;
 
+use firefly_alloc::allocators::BINARY_ALLOC;
 use firefly_alloc::gc::GcBox;
 use firefly_alloc::rc::Rc;
 use firefly_binary::{Aligned, Binary, BinaryFlags, Bitstring, Encoding};
@@ -98,12 +101,16 @@ impl BinaryData {
         Ok(gcbox)
     }
 
+    /// Allocates a reference-counted binary of `cap` bytes
+    ///
+    /// Reference-counted binaries outlive the heap they are created for, so they are always
+    /// allocated by `binary_alloc` rather than `_alloc`, see `firefly_alloc::allocators::carriers`.
     pub fn with_capacity_large<A: Allocator>(
         cap: usize,
-        alloc: A,
+        _alloc: A,
     ) -> Result<Rc<BinaryData>, AllocError> {
         assert!(cap > 64);
-        let mut rcbox = Rc::<BinaryData>::with_capacity_in(cap, alloc)?;
+        let mut rcbox = Rc::<BinaryData>::with_capacity_in(cap, &BINARY_ALLOC)?;
         {
             let value = unsafe { Rc::get_mut_unchecked(&mut rcbox) };
             value.flags = BinaryFlags::new(cap, Encoding::Raw);
@@ -0,0 +1,275 @@
+//! Sharing of binary data between an embedding application and the runtime, without copying.
+//!
+//! In one direction, [`ExternalBinary`] wraps a buffer owned by the application, e.g. a
+//! `bytes::Bytes`, `Arc<[u8]>` or `Vec<u8>`, so that it can be handed to Erlang code as a binary.
+//! The buffer is reference-counted like any other large binary, and released once the last term
+//! referring to it is dropped.
+//!
+//! In the other, [`PinnedBinary`] borrows the bytes of a binary term as a `&[u8]`, holding a
+//! reference to the data so that it remains valid for as long as the guard lives, even if every
+//! term referring to it is collected in the meantime. Only binaries on a process heap, which are
+//! at most [`BinaryData::MAX_HEAP_BYTES`] long and may be moved by the garbage collector, are
+//! copied.
+use alloc::alloc::{AllocError, Allocator};
+use alloc::boxed::Box;
+use core::any::TypeId;
+use core::fmt;
+use core::mem;
+use core::ops::Deref;
+use core::slice;
+
+use firefly_alloc::gc::GcBox;
+use firefly_alloc::rc::Rc;
+use firefly_binary::{Binary, Bitstring};
+
+use crate::term::{OpaqueTerm, Term};
+
+use super::{BinaryData, BitSlice};
+
+/// A binary whose bytes are owned by the embedding application
+///
+/// As a term, this is represented by a [`BitSlice`] over the whole buffer, whose owner is the
+/// reference-counted `ExternalBinary`, so sub-binaries and matches share the buffer too.
+pub struct ExternalBinary {
+    buffer: Box<dyn AsRef<[u8]> + Send + Sync>,
+}
+impl ExternalBinary {
+    pub const TYPE_ID: TypeId = TypeId::of::<ExternalBinary>();
+
+    /// Wraps `buffer`, which is dropped once no terms refer to it
+    ///
+    /// The bytes returned by `buffer.as_ref()` must not change, or move, while it is alive, as
+    /// is the case for all of the standard buffer types.
+    pub fn new<B>(buffer: B) -> Self
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        Self {
+            buffer: Box::new(buffer),
+        }
+    }
+
+    /// Wraps `len` bytes at `ptr`, calling `release` once no terms refer to them
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the bytes remain valid and unchanged until `release` is called.
+    pub unsafe fn from_raw_parts<F>(ptr: *const u8, len: usize, release: F) -> Self
+    where
+        F: FnOnce() + Send + Sync + 'static,
+    {
+        Self::new(RawBuffer {
+            ptr,
+            len,
+            release: Some(Box::new(release)),
+        })
+    }
+
+    /// Returns the bytes of this binary
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        (*self.buffer).as_ref()
+    }
+
+    /// Allocates a binary term referring to this buffer using `alloc`, typically a process heap
+    pub fn into_term<A: Allocator>(self, alloc: A) -> Result<Term, AllocError> {
+        let len = self.as_bytes().len();
+        if len == 0 {
+            // An empty selection cannot be represented as a slice, and there is nothing to share
+            return BinaryData::with_capacity_small(0, alloc).map(Term::HeapBinary);
+        }
+        let rc = Rc::new(self);
+        // SAFETY: The bytes live as long as the buffer, which the slice keeps alive via its owner
+        let bytes = unsafe { mem::transmute::<&[u8], &'static [u8]>(rc.as_bytes()) };
+        let owner: OpaqueTerm = rc.into();
+        let slice = unsafe { BitSlice::new(owner, bytes, 0, len * 8) };
+        GcBox::new_in(slice, alloc).map(Term::RefBinary)
+    }
+}
+impl fmt::Debug for ExternalBinary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExternalBinary")
+            .field("len", &self.as_bytes().len())
+            .finish()
+    }
+}
+
+/// A buffer described by a raw pointer and length, with a function which releases it
+struct RawBuffer {
+    ptr: *const u8,
+    len: usize,
+    release: Option<Box<dyn FnOnce() + Send + Sync>>,
+}
+// SAFETY: The creator of a RawBuffer guarantees the bytes are immutable until released
+unsafe impl Send for RawBuffer {}
+unsafe impl Sync for RawBuffer {}
+impl AsRef<[u8]> for RawBuffer {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+impl Drop for RawBuffer {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+/// The bytes of a binary term, borrowed such that they remain valid while this guard lives
+///
+/// This is obtained with [`PinnedBinary::new`], and dereferences to `&[u8]`.
+pub struct PinnedBinary {
+    pin: Pin,
+}
+enum Pin {
+    /// The bytes are owned by a reference-counted value, a strong reference to which we hold
+    Rc {
+        owner: OpaqueTerm,
+        bytes: &'static [u8],
+    },
+    /// The bytes are a literal, which lives as long as the program
+    Static(&'static [u8]),
+    /// The bytes were on a process heap, so were copied
+    Copied(Box<[u8]>),
+}
+impl PinnedBinary {
+    /// Borrows the bytes of `term`, returning `None` if it is not a binary
+    ///
+    /// Bitstrings which are not a whole number of bytes, or which do not start on a byte boundary,
+    /// are not binaries, and so cannot be borrowed.
+    pub fn new(term: Term) -> Option<Self> {
+        let pin = match term {
+            Term::RcBinary(weak) => {
+                let owner: OpaqueTerm = weak.into();
+                owner.maybe_increment_refcount();
+                // SAFETY: The strong reference we now hold keeps these bytes alive
+                let bytes = unsafe { mem::transmute::<&[u8], &'static [u8]>(weak.as_bytes()) };
+                Pin::Rc { owner, bytes }
+            }
+            Term::ConstantBinary(bin) => Pin::Static(bin.as_bytes()),
+            Term::HeapBinary(bin) => Pin::Copied(bin.as_bytes().into()),
+            Term::RefBinary(slice) => {
+                if !slice.is_binary() || !slice.is_aligned() {
+                    return None;
+                }
+                let bytes = unsafe { slice.as_bytes_unchecked() };
+                let owner = slice.owner();
+                if owner.is_rc() {
+                    owner.maybe_increment_refcount();
+                    let bytes = unsafe { mem::transmute::<&[u8], &'static [u8]>(bytes) };
+                    Pin::Rc { owner, bytes }
+                } else if owner.is_literal() {
+                    let bytes = unsafe { mem::transmute::<&[u8], &'static [u8]>(bytes) };
+                    Pin::Static(bytes)
+                } else {
+                    Pin::Copied(bytes.into())
+                }
+            }
+            _ => return None,
+        };
+        Some(Self { pin })
+    }
+
+    /// Returns true if the bytes were copied rather than borrowed
+    #[inline]
+    pub fn is_copy(&self) -> bool {
+        matches!(self.pin, Pin::Copied(_))
+    }
+}
+impl Deref for PinnedBinary {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        match &self.pin {
+            Pin::Rc { bytes, .. } => bytes,
+            Pin::Static(bytes) => bytes,
+            Pin::Copied(bytes) => bytes,
+        }
+    }
+}
+impl AsRef<[u8]> for PinnedBinary {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+impl Drop for PinnedBinary {
+    fn drop(&mut self) {
+        if let Pin::Rc { owner, .. } = &self.pin {
+            owner.maybe_decrement_refcount();
+        }
+    }
+}
+impl fmt::Debug for PinnedBinary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PinnedBinary")
+            .field("len", &self.len())
+            .field("copied", &self.is_copy())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::alloc::Global;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn external_binary_shares_buffer_test() {
+        let buffer: Arc<[u8]> = (0..=255u8).collect::<Vec<_>>().into();
+        let external = ExternalBinary::new(buffer.clone());
+        assert_eq!(external.as_bytes().as_ptr(), buffer.as_ptr());
+
+        let term = external.into_term(Global).unwrap();
+        let Term::RefBinary(slice) = term else { panic!("expected a binary reference"); };
+        assert_eq!(unsafe { slice.as_bytes_unchecked() }, &buffer[..]);
+
+        let pinned = PinnedBinary::new(term).unwrap();
+        assert!(!pinned.is_copy());
+        assert_eq!(pinned.as_ptr(), buffer.as_ptr());
+    }
+
+    #[test]
+    fn external_binary_release_test() {
+        static RELEASED: AtomicBool = AtomicBool::new(false);
+
+        let bytes = b"hello";
+        let external = unsafe {
+            ExternalBinary::from_raw_parts(bytes.as_ptr(), bytes.len(), || {
+                RELEASED.store(true, Ordering::SeqCst)
+            })
+        };
+        assert_eq!(external.as_bytes(), b"hello");
+        assert!(!RELEASED.load(Ordering::SeqCst));
+        drop(external);
+        assert!(RELEASED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn pinned_binary_test() {
+        let rc = BinaryData::from_str("testing 1 2 3");
+        let term = Term::RcBinary(Rc::into_weak(rc.clone()));
+        let count = Rc::strong_count(&rc);
+        let pinned = PinnedBinary::new(term).unwrap();
+        assert!(!pinned.is_copy());
+        assert_eq!(Rc::strong_count(&rc), count + 1);
+        assert_eq!(&*pinned, b"testing 1 2 3");
+        drop(pinned);
+        assert_eq!(Rc::strong_count(&rc), count);
+
+        let mut bin = BinaryData::with_capacity_small(3, Global).unwrap();
+        bin.copy_from_slice(b"abc");
+        let pinned = PinnedBinary::new(Term::HeapBinary(bin)).unwrap();
+        assert!(pinned.is_copy());
+        assert_eq!(&*pinned, b"abc");
+
+        assert!(PinnedBinary::new(Term::Nil).is_none());
+    }
+}
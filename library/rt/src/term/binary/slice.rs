@@ -14,7 +14,8 @@ pub struct BitSlice {
     /// of the garbage collector, or reference counting, until this slice is no
     /// longer needed.
     ///
-    /// If the original data is not from a term, this will be None
+    /// If the original data is not from a term, this will be None, or if it is owned by the
+    /// embedding application, a reference-counted `ExternalBinary`
     owner: OpaqueTerm,
     /// We give the selection static lifetime because we are managing the lifetime
     /// of the referenced data manually. The Rust borrow checker is of no help to
@@ -50,11 +51,46 @@ impl BitSlice {
         Self { owner, selection }
     }
 
+    /// Returns the term which owns the data this slice refers to
+    #[inline]
+    pub fn owner(&self) -> OpaqueTerm {
+        self.owner
+    }
+
     /// Returns the selection represented by this slice
     #[inline]
     pub fn as_selection(&self) -> Selection<'static> {
         self.selection
     }
+
+    /// Rewrites the owner of this slice, and the pointer to the bytes it selects, after the owner
+    /// has been moved by the collector
+    ///
+    /// `owner` is the new owner, and `relocate` maps addresses in the old owner to the new.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the bytes selected are those of `owner` at their new address.
+    pub(crate) unsafe fn relocate<F>(&mut self, owner: OpaqueTerm, relocate: F)
+    where
+        F: Fn(*const u8) -> *const u8,
+    {
+        let moved = |bytes: &'static [u8]| -> &'static [u8] {
+            if bytes.is_empty() {
+                bytes
+            } else {
+                core::slice::from_raw_parts(relocate(bytes.as_ptr()), bytes.len())
+            }
+        };
+        self.owner = owner;
+        self.selection = match self.selection {
+            Selection::AlignedBinary(bytes) => Selection::AlignedBinary(moved(bytes)),
+            Selection::Binary(l, bytes, r) => Selection::Binary(l, moved(bytes), r),
+            Selection::AlignedBitstring(bytes, r) => Selection::AlignedBitstring(moved(bytes), r),
+            Selection::Bitstring(l, bytes, r) => Selection::Bitstring(l, moved(bytes), r),
+            selection @ (Selection::Empty | Selection::Byte(_)) => selection,
+        };
+    }
 }
 impl Bitstring for BitSlice {
     #[inline]
@@ -72,6 +108,24 @@ impl Bitstring for BitSlice {
         self.selection.bit_offset()
     }
 
+    // The selection cannot hand out the bytes underlying an unaligned slice, so selections of it
+    // are taken from the selection itself, rather than from `as_bytes_unchecked`
+
+    #[inline]
+    fn select_bytes(&self, n: usize) -> Result<Selection<'_>, Selection<'_>> {
+        self.selection.take(n * 8)
+    }
+
+    #[inline]
+    fn select_bits(&self, n: usize) -> Result<Selection<'_>, Selection<'_>> {
+        self.selection.take(n)
+    }
+
+    #[inline]
+    fn select_all(&self) -> Selection<'_> {
+        self.selection
+    }
+
     #[inline]
     unsafe fn as_bytes_unchecked(&self) -> &[u8] {
         self.selection.as_bytes_unchecked()
@@ -8,7 +8,8 @@ use seq_macro::seq;
 
 use firefly_alloc::gc::GcBox;
 
-use crate::function::ErlangResult;
+use crate::error::unwind;
+use crate::function::{resolve_tail_call, ErlangResult};
 
 use super::{Atom, OpaqueTerm};
 
@@ -99,6 +100,10 @@ impl Closure {
         &self.env
     }
 
+    pub fn env_mut(&mut self) -> &mut [OpaqueTerm] {
+        &mut self.env
+    }
+
     pub fn callee(&self) -> *const () {
         self.fun
     }
@@ -113,6 +118,9 @@ impl Closure {
 
     /// Applies the given slice of arguments to this closure.
     ///
+    /// As when calling the closure directly, any tail call it schedules is made before returning,
+    /// and any exception it raises is returned as `Err`, see [`crate::error::unwind`].
+    ///
     /// This function will panic if the number of arguments given does not match
     /// the arity of the closure.
     ///
@@ -137,7 +145,7 @@ seq!(A in 0..10 {
             /// This type represents a function which implements a closure of arity A
             ///
             /// See the `Closure` docs for more information on how closures are implemented.
-            pub type Closure~A = extern "C" fn (#(
+            pub type Closure~A = extern "C-unwind" fn (#(
                                                     OpaqueTerm,
                                                 )*
                                                 OpaqueTerm
@@ -146,7 +154,7 @@ seq!(A in 0..10 {
             /// This type represents a function capture of arity A
             ///
             /// This differs from `ClosureA` in that a function capture has no implicit self argument.
-            pub type Fun~A = extern "C" fn (#(OpaqueTerm,)*) -> ErlangResult;
+            pub type Fun~A = extern "C-unwind" fn (#(OpaqueTerm,)*) -> ErlangResult;
 
             /// This type represents a tuple of A arguments
             pub type Args~A = (#(OpaqueTerm,)*);
@@ -159,12 +167,12 @@ seq!(A in 0..10 {
                     if self.is_thin() {
                         assert_eq!(self.arity, A, "mismatched arity");
                         let fun = unsafe { core::mem::transmute::<_, Fun~A>(self.fun) };
-                        fun(#(_args.N,)*)
+                        unwind::catch(|| unsafe { resolve_tail_call(fun(#(_args.N,)*)) })
                     } else {
                         assert_eq!(self.arity, A + 1, "mismatched arity");
                         let fun = unsafe { core::mem::transmute::<_, Closure~A>(self.fun) };
                         let this = unsafe { OpaqueTerm::from_gcbox_closure(self) };
-                        fun(#(_args.N,)* this)
+                        unwind::catch(|| unsafe { resolve_tail_call(fun(#(_args.N,)* this)) })
                     }
                 }
             }
@@ -174,12 +182,12 @@ seq!(A in 0..10 {
                     if self.is_thin() {
                         assert_eq!(self.arity, A, "mismatched arity");
                         let fun = unsafe { core::mem::transmute::<_, Fun~A>(self.fun) };
-                        fun(#(_args.N,)*)
+                        unwind::catch(|| unsafe { resolve_tail_call(fun(#(_args.N,)*)) })
                     } else {
                         assert_eq!(self.arity, A + 1, "mismatched arity");
                         let fun = unsafe { core::mem::transmute::<_, Closure~A>(self.fun) };
                         let this = unsafe { OpaqueTerm::from_gcbox_closure(self) };
-                        fun(#(_args.N,)* this)
+                        unwind::catch(|| unsafe { resolve_tail_call(fun(#(_args.N,)* this)) })
                     }
                 }
             }
@@ -189,12 +197,12 @@ seq!(A in 0..10 {
                     if self.is_thin() {
                         assert_eq!(self.arity, A, "mismatched arity");
                         let fun = unsafe { core::mem::transmute::<_, Fun~A>(self.fun) };
-                        fun(#(_args.N,)*)
+                        unwind::catch(|| unsafe { resolve_tail_call(fun(#(_args.N,)*)) })
                     } else {
                         assert_eq!(self.arity, A + 1, "mismatched arity");
                         let fun = unsafe { core::mem::transmute::<_, Closure~A>(self.fun) };
                         let this = unsafe { OpaqueTerm::from_gcbox_closure(self) };
-                        fun(#(_args.N,)* this)
+                        unwind::catch(|| unsafe { resolve_tail_call(fun(#(_args.N,)* this)) })
                     }
                 }
             }
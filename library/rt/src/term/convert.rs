@@ -0,0 +1,559 @@
+//! Conversions between Rust values and terms, for embedders and authors of native functions.
+//!
+//! [`IntoTerm`] allocates a term on a heap from a Rust value, and [`FromTerm`] converts a term back
+//! to a Rust value. Both are implemented for the primitive types, strings, vectors, tuples, options
+//! and maps, and can be derived for structs and enums with `#[derive(IntoTerm, FromTerm)]`, where:
+//!
+//! * A struct with named fields is a record, i.e. a tuple tagged with the name of the struct in
+//! snake case, or a map with an atom key per field if it has the `#[term(map)]` attribute
+//! * A tuple struct is a tuple of its fields, unless it has one field, which it is converted as
+//! * A unit struct or variant is an atom, and other variants are tuples tagged with their name
+//!
+//! The name used for a struct, variant or field can be changed with `#[term(rename = "name")]`.
+//!
+//! The [`term!`](crate::term!) macro constructs terms using a syntax similar to Erlang, in which
+//! identifiers are atoms, and any other Rust expression can be used in parentheses, e.g.:
+//!
+//! ```ignore
+//! let reply = term!(process, {ok, [1, 2, 3], #{name => "firefly", pid => (pid)}})?;
+//! ```
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::alloc::AllocError;
+use core::fmt;
+
+use firefly_alloc::gc::GcBox;
+use firefly_alloc::heap::Heap;
+use firefly_alloc::rc::Rc;
+use firefly_number::ToPrimitive;
+
+use super::*;
+
+/// A value which can be allocated as a term
+pub trait IntoTerm {
+    /// Converts this value to a term, allocating any data it refers to on `heap`
+    fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError>;
+}
+
+/// A value which can be obtained from a term
+pub trait FromTerm: Sized {
+    fn from_term(term: Term) -> Result<Self, FromTermError>;
+}
+
+/// The error produced when a term cannot be converted to a Rust value
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FromTermError {
+    expected: &'static str,
+}
+impl FromTermError {
+    pub const fn new(expected: &'static str) -> Self {
+        Self { expected }
+    }
+
+    /// Returns a description of the value which was expected
+    pub fn expected(&self) -> &'static str {
+        self.expected
+    }
+}
+impl fmt::Display for FromTermError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}", self.expected)
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for FromTermError {}
+
+impl IntoTerm for Term {
+    #[inline]
+    fn into_term<H: Heap>(self, _heap: &H) -> Result<Term, AllocError> {
+        Ok(self)
+    }
+}
+impl FromTerm for Term {
+    #[inline]
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        Ok(term)
+    }
+}
+
+impl IntoTerm for OpaqueTerm {
+    #[inline]
+    fn into_term<H: Heap>(self, _heap: &H) -> Result<Term, AllocError> {
+        Ok(self.into())
+    }
+}
+impl FromTerm for OpaqueTerm {
+    #[inline]
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        Ok(term.into())
+    }
+}
+
+impl IntoTerm for Atom {
+    #[inline]
+    fn into_term<H: Heap>(self, _heap: &H) -> Result<Term, AllocError> {
+        Ok(Term::Atom(self))
+    }
+}
+impl FromTerm for Atom {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        term.try_into().map_err(|_| FromTermError::new("an atom"))
+    }
+}
+
+impl IntoTerm for bool {
+    #[inline]
+    fn into_term<H: Heap>(self, _heap: &H) -> Result<Term, AllocError> {
+        Ok(Term::Bool(self))
+    }
+}
+impl FromTerm for bool {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        term.try_into().map_err(|_| FromTermError::new("a boolean"))
+    }
+}
+
+impl IntoTerm for char {
+    #[inline]
+    fn into_term<H: Heap>(self, _heap: &H) -> Result<Term, AllocError> {
+        Ok(Term::Int(self as i64))
+    }
+}
+impl FromTerm for char {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        term.as_char()
+            .map_err(|_| FromTermError::new("a character"))
+    }
+}
+
+macro_rules! impl_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl IntoTerm for $ty {
+                #[inline]
+                fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+                    integer(self as i128, heap)
+                }
+            }
+            impl FromTerm for $ty {
+                fn from_term(term: Term) -> Result<Self, FromTermError> {
+                    let value = match term {
+                        Term::Int(i) => <$ty>::try_from(i).ok(),
+                        Term::BigInt(i) => i.to_i128().and_then(|i| <$ty>::try_from(i).ok()),
+                        _ => None,
+                    };
+                    const EXPECTED: &str = concat!("an integer in the range of ", stringify!($ty));
+                    value.ok_or(FromTermError::new(EXPECTED))
+                }
+            }
+        )*
+    }
+}
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, i128);
+
+impl IntoTerm for u128 {
+    fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+        match i128::try_from(self) {
+            Ok(i) => integer(i, heap),
+            Err(_) => GcBox::new_in(BigInt::from(self), heap).map(Term::BigInt),
+        }
+    }
+}
+impl FromTerm for u128 {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        let value = match term {
+            Term::Int(i) => u128::try_from(i).ok(),
+            Term::BigInt(i) => i.to_u128(),
+            _ => None,
+        };
+        value.ok_or(FromTermError::new("an integer in the range of u128"))
+    }
+}
+
+/// Allocates `i` as a small integer if it fits, or a big integer otherwise
+fn integer<H: Heap>(i: i128, heap: &H) -> Result<Term, AllocError> {
+    match i64::try_from(i).ok().and_then(|i| Term::try_from(i).ok()) {
+        Some(term) => Ok(term),
+        None => GcBox::new_in(BigInt::from(i), heap).map(Term::BigInt),
+    }
+}
+
+impl IntoTerm for f64 {
+    #[inline]
+    fn into_term<H: Heap>(self, _heap: &H) -> Result<Term, AllocError> {
+        Ok(Term::Float(self.into()))
+    }
+}
+impl FromTerm for f64 {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        match term {
+            Term::Float(f) => Ok(f.inner()),
+            _ => Err(FromTermError::new("a float")),
+        }
+    }
+}
+
+/// Byte slices are binaries
+impl IntoTerm for &[u8] {
+    fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+        if self.len() <= BinaryData::MAX_HEAP_BYTES {
+            let mut bin = BinaryData::with_capacity_small(self.len(), heap)?;
+            bin.copy_from_slice(self);
+            Ok(Term::HeapBinary(bin))
+        } else {
+            let mut bin = BinaryData::with_capacity_large(self.len(), heap)?;
+            {
+                // SAFETY: There can be no other references to this Rc yet
+                let b = unsafe { Rc::get_mut_unchecked(&mut bin) };
+                b.copy_from_slice(self);
+            }
+            Ok(Term::RcBinary(Rc::into_weak(bin)))
+        }
+    }
+}
+
+/// Strings are binaries, as is conventional in Erlang APIs, rather than charlists
+impl IntoTerm for &str {
+    #[inline]
+    fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+        self.as_bytes().into_term(heap)
+    }
+}
+impl IntoTerm for String {
+    #[inline]
+    fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+        self.as_str().into_term(heap)
+    }
+}
+/// Strings may be obtained from either binaries or charlists
+impl FromTerm for String {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        const EXPECTED: FromTermError = FromTermError::new("a string");
+        match term {
+            Term::Nil => Ok(String::new()),
+            Term::Cons(cons) => {
+                let mut s = String::new();
+                for element in unsafe { cons.as_ref() }.iter() {
+                    let c = element.map_err(|_| EXPECTED)?.as_char();
+                    s.push(c.map_err(|_| EXPECTED)?);
+                }
+                Ok(s)
+            }
+            term => term
+                .as_bitstring()
+                .and_then(|bits| bits.as_str())
+                .map(String::from)
+                .ok_or(EXPECTED),
+        }
+    }
+}
+
+/// Binaries are borrowed without copying, where possible
+impl FromTerm for PinnedBinary {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        PinnedBinary::new(term).ok_or(FromTermError::new("a binary"))
+    }
+}
+
+/// Vectors are proper lists
+impl<T: IntoTerm> IntoTerm for Vec<T> {
+    fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+        let elements = self
+            .into_iter()
+            .map(|element| element.into_term(heap))
+            .collect::<Result<Vec<_>, _>>()?;
+        support::list(elements.as_slice(), heap)
+    }
+}
+impl<T: FromTerm> FromTerm for Vec<T> {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        const EXPECTED: FromTermError = FromTermError::new("a proper list");
+        match term {
+            Term::Nil => Ok(Vec::new()),
+            Term::Cons(cons) => unsafe { cons.as_ref() }
+                .iter()
+                .map(|element| T::from_term(element.map_err(|_| EXPECTED)?))
+                .collect(),
+            _ => Err(EXPECTED),
+        }
+    }
+}
+
+/// `None` is the atom `undefined`, as is conventional in Erlang APIs
+impl<T: IntoTerm> IntoTerm for Option<T> {
+    fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+        match self {
+            None => Ok(Term::Atom(atoms::Undefined)),
+            Some(value) => value.into_term(heap),
+        }
+    }
+}
+impl<T: FromTerm> FromTerm for Option<T> {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        match term {
+            Term::Atom(atom) if atom == atoms::Undefined => Ok(None),
+            term => T::from_term(term).map(Some),
+        }
+    }
+}
+
+impl<K: IntoTerm, V: IntoTerm> IntoTerm for BTreeMap<K, V> {
+    fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+        let pairs = self
+            .into_iter()
+            .map(|(k, v)| Ok((k.into_term(heap)?, v.into_term(heap)?)))
+            .collect::<Result<Vec<_>, AllocError>>()?;
+        support::map(pairs.as_slice(), heap)
+    }
+}
+impl<K: FromTerm + Ord, V: FromTerm> FromTerm for BTreeMap<K, V> {
+    fn from_term(term: Term) -> Result<Self, FromTermError> {
+        let map = term.as_map().ok_or(FromTermError::new("a map"))?;
+        map.iter()
+            .map(|(k, v)| Ok((K::from_term(*k)?, V::from_term(*v)?)))
+            .collect()
+    }
+}
+
+macro_rules! impl_tuple {
+    ($arity:literal => $($name:ident : $index:tt),+) => {
+        impl<$($name: IntoTerm),+> IntoTerm for ($($name,)+) {
+            fn into_term<H: Heap>(self, heap: &H) -> Result<Term, AllocError> {
+                let elements = [$(self.$index.into_term(heap)?),+];
+                support::tuple(&elements, heap)
+            }
+        }
+        impl<$($name: FromTerm),+> FromTerm for ($($name,)+) {
+            fn from_term(term: Term) -> Result<Self, FromTermError> {
+                const EXPECTED: FromTermError =
+                    FromTermError::new(concat!("a tuple of ", stringify!($arity), " elements"));
+                let elements = support::elements(term, $arity).ok_or(EXPECTED)?;
+                Ok(($($name::from_term(elements[$index].into())?,)+))
+            }
+        }
+    }
+}
+impl_tuple!(1 => A: 0);
+impl_tuple!(2 => A: 0, B: 1);
+impl_tuple!(3 => A: 0, B: 1, C: 2);
+impl_tuple!(4 => A: 0, B: 1, C: 2, D: 3);
+impl_tuple!(5 => A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_tuple!(6 => A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_tuple!(7 => A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_tuple!(8 => A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, I: 7);
+
+/// Functions used by the code generated by `term!` and the derives of `firefly_rt_macros`
+#[doc(hidden)]
+pub mod support {
+    pub use firefly_alloc::heap::Heap;
+
+    use super::*;
+
+    pub fn atom(name: &str) -> Term {
+        Term::Atom(Atom::try_from(name).unwrap())
+    }
+
+    pub fn is_atom(term: Term, name: &str) -> bool {
+        match term {
+            Term::Atom(atom) => atom.as_str() == name,
+            Term::Bool(b) => name == if b { "true" } else { "false" },
+            _ => false,
+        }
+    }
+
+    pub fn tuple<H: Heap>(elements: &[Term], heap: &H) -> Result<Term, AllocError> {
+        let elements = elements
+            .iter()
+            .copied()
+            .map(OpaqueTerm::from)
+            .collect::<Vec<_>>();
+        Tuple::from_slice(elements.as_slice(), heap).map(Term::Tuple)
+    }
+
+    pub fn list<H: Heap>(elements: &[Term], heap: &H) -> Result<Term, AllocError> {
+        let mut builder = ListBuilder::new(heap);
+        for element in elements.iter().rev() {
+            builder.push(*element)?;
+        }
+        Ok(builder.finish().map(Term::Cons).unwrap_or(Term::Nil))
+    }
+
+    pub fn map<H: Heap>(pairs: &[(Term, Term)], heap: &H) -> Result<Term, AllocError> {
+        Map::new_from_iter_in(pairs.iter().copied(), heap).map(Term::Map)
+    }
+
+    /// Returns the elements of `term` if it is a tuple of `arity` elements
+    pub fn elements(term: Term, arity: usize) -> Option<Vec<OpaqueTerm>> {
+        let tuple = term.as_tuple()?;
+        if tuple.len() != arity {
+            return None;
+        }
+        Some(tuple.as_slice().to_vec())
+    }
+
+    /// Returns the elements of `term` following its tag, if it is a tuple of `arity` elements
+    /// tagged with the atom `tag`
+    pub fn tagged_elements(term: Term, tag: &str, arity: usize) -> Option<Vec<OpaqueTerm>> {
+        let mut elements = elements(term, arity + 1)?;
+        if !is_atom(elements[0].into(), tag) {
+            return None;
+        }
+        elements.remove(0);
+        Some(elements)
+    }
+
+    /// Returns the value of the field `key` of the map `term`, converted to `T`
+    pub fn field<T: FromTerm>(term: Term, key: &str) -> Result<T, FromTermError> {
+        let value = term
+            .as_map()
+            .and_then(|map| Atom::try_from(key).ok().and_then(|key| map.get(key)));
+        match value {
+            Some(value) => T::from_term(value),
+            None => Err(FromTermError::new("a map containing all of the fields")),
+        }
+    }
+}
+
+/// Constructs a term on a heap using a syntax similar to that of Erlang, returning
+/// `Result<Term, AllocError>`
+///
+/// * `{A, B}` is a tuple, `[A, B]` a list, and `#{K => V}` a map, where keys must be a single token
+/// * Identifiers are atoms, while literals and parenthesized expressions are converted with
+/// [`IntoTerm`](crate::term::IntoTerm), e.g. `"hello"` is a binary and `(pid)` is the value of a
+/// variable
+#[macro_export]
+macro_rules! term {
+    ($heap:expr, $($term:tt)+) => {{
+        let heap = &$heap;
+        (|| -> ::core::result::Result<$crate::term::Term, ::core::alloc::AllocError> {
+            ::core::result::Result::Ok($crate::__term!(heap; $($term)+))
+        })()
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __term {
+    // Splits the elements of a tuple or list on commas
+    (@elements $heap:ident; [$($done:expr,)*] []) => { [$($done,)*] };
+    (@elements $heap:ident; [$($done:expr,)*] [$($cur:tt)+]) => {
+        [$($done,)* $crate::__term!($heap; $($cur)+),]
+    };
+    (@elements $heap:ident; [$($done:expr,)*] [$($cur:tt)+] , $($rest:tt)*) => {
+        $crate::__term!(
+            @elements $heap; [$($done,)* $crate::__term!($heap; $($cur)+),] [] $($rest)*
+        )
+    };
+    (@elements $heap:ident; [$($done:expr,)*] [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__term!(@elements $heap; [$($done,)*] [$($cur)* $next] $($rest)*)
+    };
+    // Splits the associations of a map on commas
+    (@pairs $heap:ident; [$($done:expr,)*]) => { [$($done,)*] };
+    (@pairs $heap:ident; [$($done:expr,)*] $key:tt => $($rest:tt)+) => {
+        $crate::__term!(@value $heap; [$($done,)*] $key [] $($rest)+)
+    };
+    (@value $heap:ident; [$($done:expr,)*] $key:tt [$($cur:tt)+]) => {
+        [$($done,)* ($crate::__term!($heap; $key), $crate::__term!($heap; $($cur)+)),]
+    };
+    (@value $heap:ident; [$($done:expr,)*] $key:tt [$($cur:tt)+] , $($rest:tt)*) => {
+        $crate::__term!(
+            @pairs $heap;
+            [$($done,)* ($crate::__term!($heap; $key), $crate::__term!($heap; $($cur)+)),]
+            $($rest)*
+        )
+    };
+    (@value $heap:ident; [$($done:expr,)*] $key:tt [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__term!(@value $heap; [$($done,)*] $key [$($cur)* $next] $($rest)*)
+    };
+    ($heap:ident; { $($elements:tt)* }) => {
+        $crate::term::__support::tuple(
+            &$crate::__term!(@elements $heap; [] [] $($elements)*),
+            $heap,
+        )?
+    };
+    ($heap:ident; [ $($elements:tt)* ]) => {
+        $crate::term::__support::list(
+            &$crate::__term!(@elements $heap; [] [] $($elements)*),
+            $heap,
+        )?
+    };
+    ($heap:ident; # { $($pairs:tt)* }) => {
+        $crate::term::__support::map(&$crate::__term!(@pairs $heap; [] $($pairs)*), $heap)?
+    };
+    ($heap:ident; - $value:literal) => {
+        $crate::term::IntoTerm::into_term(-$value, $heap)?
+    };
+    ($heap:ident; $value:literal) => {
+        $crate::term::IntoTerm::into_term($value, $heap)?
+    };
+    ($heap:ident; $atom:ident) => {
+        $crate::term::__support::atom(stringify!($atom))
+    };
+    ($heap:ident; ( $value:expr )) => {
+        $crate::term::IntoTerm::into_term($value, $heap)?
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use firefly_alloc::fragment::HeapFragment;
+
+    use super::*;
+
+    fn with_heap<F: FnOnce(&HeapFragment)>(fun: F) {
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        let fragment = HeapFragment::new(layout, None).unwrap();
+        fun(unsafe { fragment.as_ref() });
+    }
+
+    #[test]
+    fn round_trip_test() {
+        with_heap(|heap| {
+            let value = (
+                1i32,
+                String::from("hello"),
+                alloc::vec![true, false],
+                None::<u8>,
+            );
+            let term = value.clone().into_term(heap).unwrap();
+            assert_eq!(FromTerm::from_term(term), Ok(value));
+
+            let big = u64::MAX.into_term(heap).unwrap();
+            assert!(matches!(big, Term::BigInt(_)));
+            assert_eq!(u64::from_term(big), Ok(u64::MAX));
+            assert!(u8::from_term(big).is_err());
+
+            let mut map = BTreeMap::new();
+            map.insert(Atom::try_from("a").unwrap(), 1.5f64);
+            let term = map.clone().into_term(heap).unwrap();
+            assert_eq!(BTreeMap::from_term(term), Ok(map));
+        });
+    }
+
+    #[test]
+    fn term_macro_test() {
+        with_heap(|heap| {
+            let name = "firefly";
+            let term = crate::term!(heap, {ok, [1, -2, "x"], #{name => (name)}}).unwrap();
+
+            let elements = support::tagged_elements(term, "ok", 2).unwrap();
+            let list: (i64, i64, String) = match Vec::<Term>::from_term(elements[0].into()) {
+                Ok(list) => (
+                    FromTerm::from_term(list[0]).unwrap(),
+                    FromTerm::from_term(list[1]).unwrap(),
+                    FromTerm::from_term(list[2]).unwrap(),
+                ),
+                Err(err) => panic!("{}", err),
+            };
+            assert_eq!(list, (1, -2, String::from("x")));
+            let name: String = support::field(elements[1].into(), "name").unwrap();
+            assert_eq!(name, "firefly");
+
+            let empty = crate::term!(heap, {}).unwrap();
+            assert_eq!(support::elements(empty, 0), Some(Vec::new()));
+        });
+    }
+}
@@ -0,0 +1,583 @@
+//! Copying terms between heaps, as when a message is sent to another process.
+//!
+//! Unlike [`Term::clone_to_heap`], which copies only the outermost term, a copy includes every
+//! term reachable from it, so that the copy does not depend on the heap it was made from, which
+//! may be freed as soon as the sender exits.
+//!
+//! Only the structure of a term is copied though. Reference-counted binaries are shared rather
+//! than copied, by incrementing their reference count as the copy is made, i.e. by the sender, and
+//! literals are shared as they are never freed. A message made mostly of large binaries therefore
+//! costs little more to send than its structure, however large the binaries are.
+//!
+//! Literals are recognised by the tag of the pointers to them, see [`OpaqueTerm::is_literal`], so
+//! sharing them costs one check of the tag bits per term, rather than a check of whether the term
+//! is on a given heap. A constant list or tuple, e.g. a lookup table compiled into a module, is
+//! therefore shared by a message however large it is. Note that this is only possible where a term
+//! is still an `OpaqueTerm`, as the tag is lost when it is converted to a `Term`; callers holding
+//! an `OpaqueTerm` should use the methods here rather than convert it first.
+use alloc::alloc::{AllocError, Layout};
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::ptr::{self, NonNull};
+
+use firefly_alloc::fragment::HeapFragment;
+use firefly_alloc::gc::{GcBox, Metadata};
+use firefly_alloc::heap::Heap;
+use firefly_alloc::rc::{Rc, Weak};
+use firefly_binary::{BinaryFlags, Bitstring, Encoding, Selection};
+
+use super::*;
+
+impl Term {
+    /// Returns the layout of the memory needed to copy this term to another heap, see
+    /// [`Term::copy_to_heap`]
+    ///
+    /// This is zero-sized for immediates, literals and reference-counted binaries, which need no
+    /// memory of their own on the heap they are copied to.
+    pub fn copy_layout(&self) -> Layout {
+        match self {
+            Self::None
+            | Self::Nil
+            | Self::Bool(_)
+            | Self::Atom(_)
+            | Self::Int(_)
+            | Self::Float(_)
+            | Self::RcBinary(_)
+            | Self::ConstantBinary(_) => Layout::new::<()>(),
+            Self::BigInt(_) => boxed(Layout::new::<BigInt>()),
+            Self::Pid(_) => boxed(Layout::new::<Pid>()),
+            Self::Port(_) => boxed(Layout::new::<Port>()),
+            Self::Reference(_) => boxed(Layout::new::<Reference>()),
+            Self::HeapBinary(bin) => boxed(Layout::for_value(bin.as_ref())),
+            Self::RefBinary(slice) => {
+                let layout = boxed(Layout::new::<BitSlice>());
+                if is_shared(slice.owner()) {
+                    layout
+                } else {
+                    extend(layout, binary_layout(slice.byte_size()))
+                }
+            }
+            Self::Cons(ptr) => {
+                // Lists are walked iteratively, as they may be very long
+                let mut layout = Layout::new::<()>();
+                let mut cons = unsafe { ptr.as_ref() };
+                loop {
+                    layout = extend(layout, Layout::new::<Cons>());
+                    layout = extend(layout, cons.head.copy_layout());
+                    match next_cons(cons.tail) {
+                        Some(tail) => cons = unsafe { tail.as_ref() },
+                        None => break extend(layout, cons.tail.copy_layout()),
+                    }
+                }
+            }
+            Self::Tuple(ptr) => {
+                let tuple = unsafe { ptr.as_ref() };
+                tuple
+                    .as_slice()
+                    .iter()
+                    .fold(tuple_layout(tuple.len()), |layout, element| {
+                        extend(layout, element.copy_layout())
+                    })
+            }
+            Self::Map(map) => {
+                map.iter()
+                    .fold(boxed(Layout::new::<Map>()), |layout, (key, value)| {
+                        extend(extend(layout, key.copy_layout()), value.copy_layout())
+                    })
+            }
+            Self::Closure(fun) => {
+                let base = boxed(Layout::for_value(fun.as_ref()));
+                fun.env().iter().fold(base, |layout, element| {
+                    extend(layout, element.copy_layout())
+                })
+            }
+        }
+    }
+
+    /// Copies this term, and every term reachable from it, to `heap`
+    ///
+    /// The heap must have room for [`Term::copy_layout`].
+    pub fn copy_to_heap<H: Heap>(self, heap: &H) -> Result<Self, AllocError> {
+        let copied = match self {
+            Self::None
+            | Self::Nil
+            | Self::Bool(_)
+            | Self::Atom(_)
+            | Self::Int(_)
+            | Self::Float(_)
+            | Self::ConstantBinary(_) => self,
+            Self::RcBinary(ref weak) => Self::RcBinary(Rc::into_weak(Weak::upgrade(weak))),
+            Self::BigInt(_)
+            | Self::Pid(_)
+            | Self::Port(_)
+            | Self::Reference(_)
+            | Self::HeapBinary(_) => self.clone_to_heap(heap)?,
+            Self::RefBinary(slice) => {
+                if is_shared(slice.owner()) {
+                    self.clone_to_heap(heap)?
+                } else {
+                    // The binary this is a slice of is on the heap being copied from, so the bytes
+                    // of the slice are copied to a binary of their own
+                    let selection = slice.as_selection();
+                    let bytes = selection.to_bytes();
+                    let mut owner = GcBox::<BinaryData>::with_capacity_in(bytes.len(), heap)?;
+                    unsafe {
+                        owner.set_flags(BinaryFlags::new(bytes.len(), Encoding::Raw));
+                    }
+                    owner.copy_from_slice(&bytes);
+                    let slice = unsafe {
+                        BitSlice::new(
+                            Term::HeapBinary(owner).into(),
+                            owner.as_bytes(),
+                            0,
+                            slice.bit_size(),
+                        )
+                    };
+                    Self::RefBinary(GcBox::new_in(slice, heap)?)
+                }
+            }
+            Self::Cons(ptr) => {
+                let mut heads = Vec::new();
+                let mut cons = unsafe { ptr.as_ref() };
+                let tail = loop {
+                    heads.push(cons.head.copy_to_heap(heap)?);
+                    match next_cons(cons.tail) {
+                        Some(tail) => cons = unsafe { tail.as_ref() },
+                        None => break cons.tail.copy_to_heap(heap)?,
+                    }
+                };
+                let list = heads.into_iter().rev().try_fold(tail, |tail, head| {
+                    let cons = Cons::new_in(heap)?;
+                    unsafe {
+                        cons.as_ptr().write(Cons { head, tail });
+                    }
+                    Ok::<_, AllocError>(cons.into())
+                })?;
+                list.into()
+            }
+            Self::Tuple(ptr) => {
+                let tuple = unsafe { ptr.as_ref() };
+                let elements = tuple
+                    .as_slice()
+                    .iter()
+                    .map(|element| element.copy_to_heap(heap))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Self::Tuple(Tuple::from_slice(elements.as_slice(), heap)?)
+            }
+            Self::Map(map) => {
+                let items = map
+                    .iter()
+                    .map(|(key, value)| Ok((key.copy_to_heap(heap)?, value.copy_to_heap(heap)?)))
+                    .collect::<Result<Vec<_>, AllocError>>()?;
+                Self::Map(Map::new_from_iter_in(items.into_iter(), heap)?)
+            }
+            Self::Closure(fun) => {
+                let env = fun
+                    .env()
+                    .iter()
+                    .map(|element| element.copy_to_heap(heap))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Self::Closure(Closure::new_in(
+                    fun.module,
+                    fun.name,
+                    fun.arity as u8,
+                    fun.callee(),
+                    env.as_slice(),
+                    heap,
+                )?)
+            }
+        };
+        Ok(copied)
+    }
+
+    /// Copies this term, and every term reachable from it, to a new heap fragment sized to fit
+    pub fn copy_to_fragment(self) -> Result<(Self, NonNull<HeapFragment>), AllocError> {
+        let layout = self.copy_layout();
+        // Fragments must have room for something, even if this term needs no memory of its own
+        let layout = layout.align_to(layout.align().max(8)).unwrap();
+        let layout = Layout::from_size_align(layout.size().max(8), layout.align()).unwrap();
+        let frag = HeapFragment::new(layout, None)?;
+        let term = self.copy_to_heap(unsafe { frag.as_ref() })?;
+        Ok((term, frag))
+    }
+}
+
+impl OpaqueTerm {
+    /// Returns the layout of the memory needed to copy this term to another heap, which is
+    /// zero-sized for immediates and literals, see [`Term::copy_layout`]
+    #[inline]
+    pub fn copy_layout(self) -> Layout {
+        if self.is_box() && !self.is_literal() {
+            let term: Term = self.into();
+            term.copy_layout()
+        } else {
+            Layout::new::<()>()
+        }
+    }
+
+    /// Copies this term, and every term reachable from it, to `heap`, sharing literals
+    ///
+    /// The heap must have room for [`OpaqueTerm::copy_layout`].
+    #[inline]
+    pub fn copy_to_heap<H: Heap>(self, heap: &H) -> Result<Self, AllocError> {
+        if self.is_box() && !self.is_literal() {
+            let term: Term = self.into();
+            term.copy_to_heap(heap).map(Self::from)
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Copies the outermost term to `heap` if it is not already there, see [`Term::clone_to_heap`]
+    ///
+    /// Immediates and literals are returned as they are, without checking which heap they are on.
+    #[inline]
+    pub fn clone_to_heap<H: Heap>(self, heap: H) -> Result<Self, AllocError> {
+        if self.is_box() && !self.is_literal() {
+            let term: Term = self.into();
+            term.clone_to_heap(heap).map(Self::from)
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Returns true if this term is, or refers to, a literal in `area`
+    ///
+    /// This is used when purging a literal area to find the processes whose terms refer to it, see
+    /// `Process::refers_to_literals`. Literals are not walked, as a literal can only refer to
+    /// literals of its own area. Every pointer is checked against the area though, not only those
+    /// tagged as literals, as the contents of a map are kept as `Term`, and so have lost their
+    /// tags.
+    ///
+    /// The bytes selected by a sub-binary are checked as well as its owner, as the owner of a
+    /// slice of constant data need not be in the area, e.g. an `ExternalBinary` wrapping it.
+    /// Reference-counted terms are never walked, as they hold no terms.
+    pub fn refers_to_literals(self, area: Range<*const u8>) -> bool {
+        let mut stack = Vec::new();
+        let mut term = self;
+        loop {
+            if term.is_box() {
+                if area.contains(&(unsafe { term.as_ptr() } as *const u8)) {
+                    return true;
+                }
+                if !term.is_literal() && !term.is_rc() {
+                    match term.into() {
+                        Term::Cons(ptr) => {
+                            let cons = unsafe { ptr.as_ref() };
+                            stack.push(cons.head);
+                            stack.push(cons.tail);
+                        }
+                        Term::Tuple(ptr) => {
+                            stack.extend_from_slice(unsafe { ptr.as_ref() }.as_slice())
+                        }
+                        Term::Map(map) => {
+                            for (key, value) in map.iter() {
+                                stack.push((*key).into());
+                                stack.push((*value).into());
+                            }
+                        }
+                        Term::Closure(fun) => stack.extend_from_slice(fun.env()),
+                        Term::RefBinary(slice) => {
+                            let selected = selected_bytes(slice.as_selection());
+                            if selected.map_or(false, |bytes| area.contains(&bytes.as_ptr())) {
+                                return true;
+                            }
+                            stack.push(slice.owner());
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            match stack.pop() {
+                Some(next) => term = next,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Returns the bytes underlying `selection`, if it refers to any rather than holding them itself
+fn selected_bytes(selection: Selection<'static>) -> Option<&'static [u8]> {
+    match selection {
+        Selection::AlignedBinary(bytes)
+        | Selection::Binary(_, bytes, _)
+        | Selection::AlignedBitstring(bytes, _)
+        | Selection::Bitstring(_, bytes, _) => Some(bytes).filter(|bytes| !bytes.is_empty()),
+        Selection::Empty | Selection::Byte(_) => None,
+    }
+}
+
+/// Returns the next cell of a list whose tail is `tail`, if it is a cell to be copied, i.e. it is
+/// not a literal
+#[inline]
+fn next_cons(tail: OpaqueTerm) -> Option<NonNull<Cons>> {
+    if tail.is_nonempty_list() && !tail.is_literal() {
+        match tail.into() {
+            Term::Cons(ptr) => Some(ptr),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// Returns true if `owner`, the owner of a slice, is not on any process heap, and so can be shared
+/// by the slice and its copy
+fn is_shared(owner: OpaqueTerm) -> bool {
+    owner.is_none() || owner.is_rc() || owner.is_literal()
+}
+
+#[inline]
+fn extend(layout: Layout, next: Layout) -> Layout {
+    layout.extend(next).unwrap().0.pad_to_align()
+}
+
+/// Returns the layout of a value of the given layout allocated in a `GcBox`
+#[inline]
+fn boxed(value: Layout) -> Layout {
+    extend(Layout::new::<Metadata>(), value)
+}
+
+/// Returns the layout of a `GcBox<BinaryData>` of `len` bytes
+fn binary_layout(len: usize) -> Layout {
+    let empty = ptr::from_raw_parts::<BinaryData>(ptr::null(), len);
+    boxed(unsafe { Layout::for_value_raw(empty) })
+}
+
+/// Returns the layout of a tuple of `len` elements, see `Tuple::new_in`
+fn tuple_layout(len: usize) -> Layout {
+    let (layout, _) = Layout::new::<usize>()
+        .align_to(16)
+        .unwrap()
+        .extend(Layout::array::<OpaqueTerm>(len).unwrap())
+        .unwrap();
+    layout.pad_to_align()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::alloc::Global;
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use core::str::FromStr;
+
+    use test::Bencher;
+
+    use super::*;
+
+    /// Returns a message of the shape `{data, Ref, [Bin, ...]}`, with `count` binaries of
+    /// `size` bytes each, allocated on `heap`
+    fn message<H: Heap>(heap: &H, count: usize, size: usize) -> Term {
+        let binaries = (0..count)
+            .map(|i| Term::RcBinary(Rc::into_weak(BinaryData::from_bytes(&vec![i as u8; size]))))
+            .collect::<Vec<_>>();
+        let list = Cons::from_slice(binaries.as_slice(), heap)
+            .unwrap()
+            .map(Term::Cons)
+            .unwrap_or(Term::Nil);
+        let reference = GcBox::new_in(
+            Reference::Local {
+                id: ReferenceId::new(0, 1),
+            },
+            heap,
+        );
+        let elements = [
+            Term::Atom(Atom::from_str("data").unwrap()).into(),
+            Term::Reference(reference.unwrap()).into(),
+            list.into(),
+        ];
+        Term::Tuple(Tuple::from_slice(&elements, heap).unwrap())
+    }
+
+    /// Returns a literal list of `len` integers, as a constant table compiled into a module would
+    /// be
+    fn literal_list(len: usize) -> OpaqueTerm {
+        (0..len).rev().fold(OpaqueTerm::NIL, |tail, i| {
+            let cons: &'static Cons = Box::leak(Cons::new(Term::Int(i as i64), tail));
+            cons.into()
+        })
+    }
+
+    fn strong_count(term: Term) -> usize {
+        let Term::RcBinary(weak) = term else {
+            panic!("expected a reference-counted binary")
+        };
+        Rc::strong_count(&Weak::upgrade(&weak)) - 1
+    }
+
+    #[test]
+    fn copy_test() {
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        let sender = HeapFragment::new(layout, None).unwrap();
+        let sender = unsafe { sender.as_ref() };
+        let original = message(&sender, 3, 1024);
+        let Term::Tuple(tuple) = original else {
+            panic!("expected a tuple")
+        };
+        let Term::Cons(list) = unsafe { tuple.as_ref() }.get(2).unwrap() else {
+            panic!("expected a list")
+        };
+        let counts = unsafe { list.as_ref() }
+            .iter()
+            .map(|binary| strong_count(binary.unwrap()))
+            .collect::<Vec<_>>();
+
+        let (copy, fragment) = original.copy_to_fragment().unwrap();
+        let fragment = unsafe { fragment.as_ref() };
+        assert_eq!(copy, original);
+
+        // The structure of the copy is entirely on the new fragment, but the binaries are shared
+        let Term::Tuple(tuple) = copy else {
+            panic!("expected a tuple")
+        };
+        assert!(fragment.contains(tuple.as_ptr()));
+        let tuple = unsafe { tuple.as_ref() };
+        let Term::Reference(reference) = tuple.get(1).unwrap() else {
+            panic!("expected a reference")
+        };
+        assert!(fragment.contains(GcBox::as_ptr(&reference)));
+        let Term::Cons(list) = tuple.get(2).unwrap() else {
+            panic!("expected a list")
+        };
+        assert!(fragment.contains(list.as_ptr()));
+        for (binary, count) in unsafe { list.as_ref() }.iter().zip(counts) {
+            assert_eq!(strong_count(binary.unwrap()), count + 1);
+        }
+
+        // Only the structure counts towards the size of the copy
+        assert!(original.copy_layout().size() < 1024);
+    }
+
+    #[test]
+    fn copy_literals_test() {
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        let sender = HeapFragment::new(layout, None).unwrap();
+        let sender = unsafe { sender.as_ref() };
+        let table = literal_list(100);
+        let elements = [Term::Atom(Atom::from_str("table").unwrap()).into(), table];
+        let message: OpaqueTerm = Tuple::from_slice(&elements, &sender).unwrap().into();
+
+        // Only the tuple is copied, the list is shared as it is a literal
+        assert_eq!(message.copy_layout(), tuple_layout(2));
+        assert_eq!(table.copy_layout(), Layout::new::<()>());
+        let (copy, _fragment) = Term::from(message).copy_to_fragment().unwrap();
+        let Term::Tuple(tuple) = copy else {
+            panic!("expected a tuple")
+        };
+        let tuple = unsafe { tuple.as_ref() };
+        assert_eq!(tuple.as_slice()[1], table);
+        assert_eq!(copy, message.into());
+
+        // A literal can be cloned without a heap to clone it to
+        assert_eq!(table.clone_to_heap(sender).unwrap(), table);
+
+        // Only the area the list is in is referred to by the message
+        let cell = unsafe { table.as_ptr() } as *const u8;
+        assert!(message.refers_to_literals(cell..unsafe { cell.add(1) }));
+        let other = [0u64; 2];
+        let other = other.as_ptr() as *const u8;
+        assert!(!message.refers_to_literals(other..unsafe { other.add(16) }));
+    }
+
+    #[test]
+    fn refers_to_literals_external_binary_test() {
+        static AREA: [u8; 64] = [7; 64];
+
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        let heap = HeapFragment::new(layout, None).unwrap();
+        let heap = unsafe { heap.as_ref() };
+        let area = AREA.as_ptr_range();
+
+        // The slice and its owner are both outside of the area, only the bytes are in it
+        let bytes = &AREA[16..48];
+        let external =
+            unsafe { ExternalBinary::from_raw_parts(bytes.as_ptr(), bytes.len(), || ()) };
+        let binary = external.into_term(heap).unwrap();
+        let message: OpaqueTerm = Cons::from_slice(&[binary], heap).unwrap().unwrap().into();
+        assert!(message.refers_to_literals(area.clone()));
+
+        let outside = [7u8; 32];
+        let external =
+            unsafe { ExternalBinary::from_raw_parts(outside.as_ptr(), outside.len(), || ()) };
+        let binary: OpaqueTerm = external.into_term(heap).unwrap().into();
+        assert!(!binary.refers_to_literals(area));
+    }
+
+    /// Sends a message containing eight binaries of 1MB, as a process serving large payloads would
+    #[bench]
+    fn bench_copy_message_1mb_binaries(b: &mut Bencher) {
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        let sender = HeapFragment::new(layout, None).unwrap();
+        let sender = unsafe { sender.as_ref() };
+        let message = message(&sender, 8, 1024 * 1024);
+        b.iter(|| {
+            let (copy, fragment) = message.copy_to_fragment().unwrap();
+            test::black_box(copy);
+            unsafe {
+                ptr::drop_in_place(fragment.as_ptr());
+            }
+        });
+    }
+
+    /// Sends a message containing a constant table of 10,000 elements, which is shared rather than
+    /// copied, so this costs the same as sending a message of two immediates
+    #[bench]
+    fn bench_copy_message_literal_table(b: &mut Bencher) {
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        let sender = HeapFragment::new(layout, None).unwrap();
+        let sender = unsafe { sender.as_ref() };
+        let elements = [
+            Term::Atom(Atom::from_str("table").unwrap()).into(),
+            literal_list(10_000),
+        ];
+        let message: Term = Term::Tuple(Tuple::from_slice(&elements, &sender).unwrap());
+        b.iter(|| {
+            let (copy, fragment) = message.copy_to_fragment().unwrap();
+            test::black_box(copy);
+            unsafe {
+                ptr::drop_in_place(fragment.as_ptr());
+            }
+        });
+    }
+
+    /// Checks a message of 10,000 elements for references to a literal area it does not refer to,
+    /// which walks every term of the message, as when purging an area most processes do not use
+    #[bench]
+    fn bench_refers_to_literals(b: &mut Bencher) {
+        let layout = Layout::from_size_align(1024 * 1024, 16).unwrap();
+        let heap = HeapFragment::new(layout, None).unwrap();
+        let heap = unsafe { heap.as_ref() };
+        let elements = (0..10_000)
+            .map(|i| {
+                let pair = [Term::Int(i).into(), Term::Nil.into()];
+                Term::Tuple(Tuple::from_slice(&pair, heap).unwrap())
+            })
+            .collect::<Vec<_>>();
+        let message: OpaqueTerm = Cons::from_slice(&elements, heap).unwrap().unwrap().into();
+        let area = [0u64; 2];
+        let area = area.as_ptr_range();
+        let area = area.start.cast()..area.end.cast();
+        b.iter(|| assert!(!test::black_box(message).refers_to_literals(area.clone())));
+    }
+
+    /// Clones each element of a constant table to a heap, which returns each element as it is after
+    /// checking its tag, rather than checking whether it is on the heap
+    #[bench]
+    fn bench_clone_literals_to_heap(b: &mut Bencher) {
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        let heap = HeapFragment::new(layout, None).unwrap();
+        let heap = unsafe { heap.as_ref() };
+        let tuples = (0..1000)
+            .map(|i| {
+                let tuple = Tuple::from_slice(&[Term::Int(i).into()], Global).unwrap();
+                let tuple: &'static Tuple = unsafe { &*tuple.as_ptr() };
+                OpaqueTerm::from(tuple)
+            })
+            .collect::<Vec<_>>();
+        b.iter(|| {
+            for tuple in tuples.iter() {
+                test::black_box(tuple.clone_to_heap(heap).unwrap());
+            }
+        });
+    }
+}
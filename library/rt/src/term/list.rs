@@ -373,7 +373,26 @@ impl PartialOrd for Cons {
 }
 impl Ord for Cons {
     fn cmp(&self, other: &Self) -> core::cmp::Ordering {
-        self.iter().cmp(other.iter())
+        use core::cmp::Ordering;
+
+        // Lists are compared element by element, and once either runs out, by their tails, so an
+        // improper tail is compared as a term with the rest of the other list, e.g. `[1 | 2]` is
+        // less than `[1, 2]`, as numbers are less than lists
+        let mut x = self;
+        let mut y = other;
+        loop {
+            match x.head().cmp(&y.head()) {
+                Ordering::Equal => (),
+                ordering => return ordering,
+            }
+            match (x.tail(), y.tail()) {
+                (Term::Cons(xs), Term::Cons(ys)) => unsafe {
+                    x = xs.as_ref();
+                    y = ys.as_ref();
+                },
+                (xs, ys) => return xs.cmp(&ys),
+            }
+        }
     }
 }
 impl Hash for Cons {
@@ -118,6 +118,22 @@ impl Map {
         Ok(GcBox::new_in(Self::from_keyword_list(list)?, alloc)?)
     }
 
+    /// Rewrites every key and value of this map with `relocate`, as when the terms they point to
+    /// have been moved by the collector
+    ///
+    /// The map is rebuilt rather than updated in place, as the hash of a key may depend on the
+    /// address of the term it points to.
+    pub(crate) fn relocate<F>(&mut self, relocate: F)
+    where
+        F: Fn(Term) -> Term,
+    {
+        let mut map = HashTrieMap::new();
+        for (key, value) in self.map.iter() {
+            map.insert_mut(MapKey(relocate(key.0)), relocate(*value));
+        }
+        self.map = map;
+    }
+
     /// Returns the number of keys in this map
     #[inline]
     pub fn size(&self) -> usize {
@@ -1,6 +1,8 @@
 mod atom;
 mod binary;
 mod closure;
+mod convert;
+mod copy;
 mod index;
 mod list;
 mod map;
@@ -14,11 +16,14 @@ mod tuple;
 pub use self::atom::{atoms, Atom, AtomData};
 pub use self::binary::*;
 pub use self::closure::Closure;
+#[doc(hidden)]
+pub use self::convert::support as __support;
+pub use self::convert::{FromTerm, FromTermError, IntoTerm};
 pub use self::index::{NonPrimitiveIndex, OneBasedIndex, TupleIndex, ZeroBasedIndex};
 pub use self::list::{Cons, ImproperList, ListBuilder};
 pub use self::map::Map;
 pub use self::node::Node;
-pub use self::opaque::{OpaqueTerm, TermType};
+pub use self::opaque::{OpaqueTerm, TermType, MAX_SMALL, MIN_SMALL};
 pub use self::pid::{Pid, ProcessId};
 pub use self::port::{Port, PortId};
 pub use self::reference::{Reference, ReferenceId};
@@ -26,10 +31,13 @@ pub use self::tuple::Tuple;
 
 pub use firefly_number::{BigInt, Float, Integer, Number};
 use firefly_number::{DivisionError, InvalidArithmeticError, Sign, ToPrimitive};
+pub use firefly_rt_macros::{FromTerm, IntoTerm};
 
 use alloc::alloc::{AllocError, Layout};
 use core::convert::AsRef;
 use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem;
 use core::ptr::NonNull;
 
 use anyhow::anyhow;
@@ -61,7 +69,7 @@ use crate::cmp::ExactEq;
 ///
 /// See notes on the individual variants for why a specific representation was chosen for that
 /// variant.
-#[derive(Debug, Copy, Clone, Hash)]
+#[derive(Debug, Copy, Clone)]
 #[repr(C)]
 pub enum Term {
     None,
@@ -283,16 +291,9 @@ impl Term {
         self.try_into()
     }
 
+    #[inline]
     pub fn exact_eq(&self, other: &Self) -> bool {
-        // With exception of bitstring variants, if the discriminant is different, the
-        // types can never be exactly equal
-        if core::mem::discriminant(self) != core::mem::discriminant(other) {
-            if self.is_bitstring() && other.is_bitstring() {
-                return self.eq(other);
-            }
-            return false;
-        }
-        self.eq(other)
+        ExactEq::exact_eq(self, other)
     }
 
     /// Returns a Layout which can be used to allocate sufficient memory to
@@ -641,6 +642,34 @@ impl fmt::Display for Term {
     }
 }
 impl Eq for Term {}
+impl Hash for Term {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Booleans are equal to the atoms `true` and `false`, so must hash alike
+        if let Self::Bool(b) = self {
+            return Self::Atom((*b).into()).hash(state);
+        }
+        mem::discriminant(self).hash(state);
+        match self {
+            Self::None | Self::Nil | Self::Bool(_) => (),
+            Self::Atom(x) => x.hash(state),
+            Self::Int(x) => x.hash(state),
+            Self::BigInt(x) => x.hash(state),
+            Self::Float(x) => x.hash(state),
+            Self::Cons(x) => x.hash(state),
+            Self::Tuple(x) => x.hash(state),
+            Self::Map(x) => x.hash(state),
+            Self::Closure(x) => x.hash(state),
+            Self::Pid(x) => x.hash(state),
+            Self::Port(x) => x.hash(state),
+            Self::Reference(x) => x.hash(state),
+            Self::HeapBinary(x) => x.hash(state),
+            Self::RcBinary(x) => x.hash(state),
+            Self::RefBinary(x) => x.hash(state),
+            Self::ConstantBinary(x) => x.hash(state),
+        }
+    }
+}
+
 impl PartialEq for Term {
     fn eq(&self, other: &Self) -> bool {
         match self {
@@ -648,10 +677,12 @@ impl PartialEq for Term {
             Self::Nil => other.is_nil(),
             Self::Bool(x) => match other {
                 Self::Bool(y) => x == y,
+                Self::Atom(y) => Atom::from(*x) == *y,
                 _ => false,
             },
             Self::Atom(x) => match other {
                 Self::Atom(y) => x == y,
+                Self::Bool(y) => *x == Atom::from(*y),
                 _ => false,
             },
             Self::Int(x) => match other {
@@ -744,10 +775,12 @@ impl ExactEq for Term {
             Self::Nil => other.is_nil(),
             Self::Bool(x) => match other {
                 Self::Bool(y) => x == y,
+                Self::Atom(y) => Atom::from(*x) == *y,
                 _ => false,
             },
             Self::Atom(x) => match other {
                 Self::Atom(y) => x == y,
+                Self::Bool(y) => *x == Atom::from(*y),
                 _ => false,
             },
             Self::Int(x) => match other {
@@ -896,17 +929,18 @@ impl Ord for Term {
                 },
                 _ => Ordering::Less,
             },
+            // Booleans are the atoms `false` and `true`, so are ordered by name among the others
             Self::Bool(x) => match other {
                 Self::Bool(y) => x.cmp(y),
                 Self::Atom(a) if a.is_boolean() => x.cmp(&a.as_boolean()),
-                Self::Atom(_) => Ordering::Less,
+                Self::Atom(a) => Atom::from(*x).cmp(a),
                 Self::None | Self::Int(_) | Self::BigInt(_) | Self::Float(_) => Ordering::Greater,
                 _ => Ordering::Less,
             },
             Self::Atom(x) => match other {
                 Self::Atom(y) => x.cmp(y),
                 Self::Bool(y) if x.is_boolean() => x.as_boolean().cmp(y),
-                Self::Bool(_) => Ordering::Greater,
+                Self::Bool(y) => x.cmp(&Atom::from(*y)),
                 Self::None | Self::Int(_) | Self::BigInt(_) | Self::Float(_) => Ordering::Greater,
                 _ => Ordering::Less,
             },
@@ -1161,6 +1195,246 @@ impl core::ops::BitXor for Term {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use alloc::alloc::Global;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::cmp::Ordering;
+    use core::str::FromStr;
+
+    use crate::function::ErlangResult;
+
+    use super::*;
+
+    // Used for closure construction
+    fn erlang_error_1(a: OpaqueTerm) -> ErlangResult {
+        ErlangResult::Ok(a)
+    }
+
+    fn atom(name: &str) -> Term {
+        Term::Atom(Atom::from_str(name).unwrap())
+    }
+
+    fn big(i: i128) -> Term {
+        Term::BigInt(GcBox::new_in(BigInt::from(i), Global).unwrap())
+    }
+
+    fn tuple(elements: &[Term]) -> Term {
+        let elements = elements
+            .iter()
+            .copied()
+            .map(OpaqueTerm::from)
+            .collect::<Vec<_>>();
+        Term::Tuple(Tuple::from_slice(&elements, Global).unwrap())
+    }
+
+    fn map(pairs: &[(Term, Term)]) -> Term {
+        let mut map = Map::new_in(Global).unwrap();
+        for (key, value) in pairs.iter().copied() {
+            map.insert_mut(key, value);
+        }
+        Term::Map(map)
+    }
+
+    /// Builds a list of `elements` ending in `tail`, the cells of which are leaked
+    fn list(elements: &[Term], tail: Term) -> Term {
+        elements.iter().rev().fold(tail, |tail, head| {
+            let cell = Box::leak(Cons::new(*head, tail));
+            Term::Cons(NonNull::from(&*cell))
+        })
+    }
+
+    fn binary(s: &str) -> Term {
+        let rc = BinaryData::from_str(s);
+        let weak = Rc::into_weak(rc.clone());
+        core::mem::forget(rc);
+        Term::RcBinary(weak)
+    }
+
+    /// Samples of each class of term, in ascending term order, both across and within classes:
+    /// `number < atom < reference < fun < port < pid < tuple < map < nil < list < bitstring`
+    fn classes() -> Vec<(&'static str, Vec<Term>)> {
+        let fun = erlang_error_1 as *const ();
+        alloc::vec![
+            (
+                "number",
+                alloc::vec![
+                    Term::Float((-1.0e300).into()),
+                    big(-(1 << 100)),
+                    Term::Int(-1),
+                    Term::Float(0.5.into()),
+                    Term::Int(1),
+                    big(1 << 100),
+                    Term::Float(1.0e300.into()),
+                ],
+            ),
+            (
+                "atom",
+                alloc::vec![
+                    atom("abc"),
+                    Term::Bool(false),
+                    atom("kappa"),
+                    Term::Bool(true),
+                    atom("zebra"),
+                ],
+            ),
+            (
+                "reference",
+                alloc::vec![Term::Reference(GcBox::new(Reference::Local {
+                    id: ReferenceId::new(1, 1),
+                }))],
+            ),
+            (
+                "fun",
+                alloc::vec![
+                    Term::Closure(
+                        Closure::new_in(atoms::Erlang, atoms::Error, 1, fun, &[], Global).unwrap(),
+                    ),
+                    Term::Closure(
+                        Closure::new_in(atoms::Erlang, atoms::Exit, 1, fun, &[], Global).unwrap(),
+                    ),
+                ],
+            ),
+            (
+                "port",
+                alloc::vec![Term::Port(GcBox::new(Port::Local {
+                    id: unsafe { PortId::from_raw(1) },
+                }))],
+            ),
+            (
+                "pid",
+                alloc::vec![Term::Pid(GcBox::new(Pid::new_local(1, 1).unwrap()))],
+            ),
+            (
+                "tuple",
+                alloc::vec![
+                    tuple(&[]),
+                    tuple(&[atom("zebra")]),
+                    tuple(&[Term::Int(1), Term::Int(1)]),
+                    tuple(&[Term::Int(1), atom("abc")]),
+                ],
+            ),
+            (
+                "map",
+                alloc::vec![
+                    map(&[]),
+                    map(&[(Term::Int(1), atom("zebra"))]),
+                    map(&[(atom("abc"), Term::Int(1))]),
+                    map(&[(atom("abc"), Term::Int(2))]),
+                ],
+            ),
+            ("nil", alloc::vec![Term::Nil]),
+            (
+                "list",
+                alloc::vec![
+                    list(&[Term::Int(1)], Term::Int(2)),
+                    list(&[Term::Int(1)], Term::Nil),
+                    list(&[Term::Int(1), Term::Int(2)], Term::Nil),
+                    list(&[Term::Int(1)], binary("")),
+                    list(&[Term::Int(2)], Term::Nil),
+                ],
+            ),
+            (
+                "bitstring",
+                alloc::vec![binary(""), binary("a"), binary("ab"), binary("b")],
+            ),
+        ]
+    }
+
+    #[test]
+    fn term_order_conformance_test() {
+        let terms = classes()
+            .into_iter()
+            .flat_map(|(class, samples)| samples.into_iter().map(move |term| (class, term)))
+            .collect::<Vec<_>>();
+
+        for (i, (xclass, x)) in terms.iter().enumerate() {
+            for (j, (yclass, y)) in terms.iter().enumerate() {
+                let expected = i.cmp(&j);
+                assert_eq!(
+                    x.cmp(y),
+                    expected,
+                    "{} {:?} vs {} {:?}",
+                    xclass,
+                    x,
+                    yclass,
+                    y
+                );
+                assert_eq!(x.partial_cmp(y), Some(expected));
+                assert_eq!(x == y, i == j, "{} {:?} == {} {:?}", xclass, x, yclass, y);
+            }
+        }
+    }
+
+    #[test]
+    fn bool_atom_order_test() {
+        let false_atom = Term::Atom(atoms::False);
+        let true_atom = Term::Atom(atoms::True);
+
+        assert_eq!(Term::Bool(false).cmp(&false_atom), Ordering::Equal);
+        assert_eq!(true_atom.cmp(&Term::Bool(true)), Ordering::Equal);
+        assert_eq!(Term::Bool(false).cmp(&true_atom), Ordering::Less);
+        assert_eq!(true_atom.cmp(&Term::Bool(false)), Ordering::Greater);
+        assert_eq!(Term::Bool(true), true_atom);
+        assert!(false_atom.exact_eq(&Term::Bool(false)));
+        assert!(Term::Bool(true).exact_ne(&false_atom));
+
+        // Booleans are ordered by name among atoms, rather than before or after all of them
+        assert_eq!(Term::Bool(true).cmp(&atom("zzz")), Ordering::Less);
+        assert_eq!(Term::Bool(true).cmp(&atom("aaa")), Ordering::Greater);
+        assert_eq!(atom("undefined").cmp(&Term::Bool(true)), Ordering::Greater);
+        assert_eq!(atom("error").cmp(&Term::Bool(false)), Ordering::Less);
+    }
+
+    #[test]
+    fn bool_atom_map_key_test() {
+        let mut map = Map::new();
+        map.insert_mut(Term::Bool(true), Term::Int(1));
+        map.insert_mut(Term::Atom(atoms::False), Term::Int(0));
+
+        assert_eq!(map.get(Term::Atom(atoms::True)), Some(Term::Int(1)));
+        assert_eq!(map.get(Term::Bool(false)), Some(Term::Int(0)));
+
+        // Either form replaces the other
+        map.insert_mut(Term::Atom(atoms::True), Term::Int(2));
+        assert_eq!(map.size(), 2);
+        assert_eq!(map.get(Term::Bool(true)), Some(Term::Int(2)));
+    }
+
+    #[test]
+    fn improper_list_order_test() {
+        let improper = list(&[Term::Int(1)], Term::Int(2));
+
+        // The improper tail `2` is compared with `[2]`, and with `[]`
+        assert!(improper < list(&[Term::Int(1), Term::Int(2)], Term::Nil));
+        assert!(improper < list(&[Term::Int(1)], Term::Nil));
+        // A bitstring is greater than any list
+        let proper = list(&[Term::Int(1), Term::Int(2)], Term::Nil);
+        assert!(list(&[Term::Int(1)], binary("")) > proper);
+        assert_eq!(
+            improper.cmp(&list(&[Term::Int(1)], Term::Int(2))),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn int_float_tie_order_test() {
+        // Equal integers and floats compare equal, but floats sort first, so that they are
+        // distinct in the total order as they are with `=:=`
+        assert_eq!(Term::Int(1), Term::Float(1.0.into()));
+        assert_eq!(Term::Float(1.0.into()).cmp(&Term::Int(1)), Ordering::Less);
+        assert_eq!(
+            Term::Int(1).cmp(&Term::Float(1.0.into())),
+            Ordering::Greater
+        );
+        assert_eq!(
+            big(1 << 70).cmp(&Term::Float(((1u128 << 70) as f64).into())),
+            Ordering::Greater
+        );
+    }
+}
+
 /*
 #[cfg(test)]
 mod test {
@@ -130,14 +130,11 @@ const INT_MASK: u64 = !INTEGER_TAG;
 const PTR_MASK: u64 = !(SIGN_BIT | NAN | TAG_MASK);
 
 // This tag indicates a negative integer (i.e. it has our designated sign bit set)
-#[cfg(test)]
 const NEG_INTEGER_TAG: u64 = INTEGER_TAG | QUIET_BIT;
-// This is the largest negative value allowed in an immediate integer
-#[cfg(test)]
-const MIN_SMALL: i64 = NEG_INTEGER_TAG as i64;
-// This is the largest positive value allowed in an immediate integer
-#[cfg(test)]
-const MAX_SMALL: i64 = (!NEG_INTEGER_TAG) as i64;
+/// The largest negative value allowed in an immediate integer, i.e. `Term::Int`
+pub const MIN_SMALL: i64 = NEG_INTEGER_TAG as i64;
+/// The largest positive value allowed in an immediate integer, i.e. `Term::Int`
+pub const MAX_SMALL: i64 = (!NEG_INTEGER_TAG) as i64;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ImmediateOutOfRangeError;
@@ -391,10 +388,20 @@ impl OpaqueTerm {
         self.0 & (NAN | SIGN_BIT | TAG_MASK) == (INFINITY | RC_TAG)
     }
 
-    /// Returns true if this term is a non-null pointer to a literal term
+    /// Returns true if this term is a non-null pointer to a literal term, i.e. a constant binary,
+    /// or a list or tuple in a literal area
+    ///
+    /// Literals are never garbage-collected, so anything which copies or collects terms can skip
+    /// them on the strength of this check alone, without asking whether they are on a given heap.
     #[inline]
     pub fn is_literal(self) -> bool {
-        self.0 & (NAN | SIGN_BIT | TAG_MASK) == (INFINITY | LITERAL_TAG)
+        const IS_LITERAL: u64 = INFINITY | LITERAL_TAG;
+        const IS_CONS_LITERAL: u64 = INFINITY | CONS_LITERAL_TAG;
+        const IS_TUPLE_LITERAL: u64 = INFINITY | TUPLE_LITERAL_TAG;
+        matches!(
+            self.0 & (NAN | SIGN_BIT | TAG_MASK),
+            IS_LITERAL | IS_CONS_LITERAL | IS_TUPLE_LITERAL
+        )
     }
 
     /// Returns true if this term is the None value
@@ -527,6 +534,21 @@ impl OpaqueTerm {
         (self.0 & PTR_MASK) as *mut ()
     }
 
+    /// Returns this term with the pointer it holds replaced by `ptr`, keeping its tag, as when the
+    /// pointee has been moved by the collector
+    ///
+    /// # Safety
+    ///
+    /// As with `as_ptr`, this term must be a pointer value, and `ptr` must point to a value of the
+    /// same type, with at least 8-byte alignment.
+    #[inline]
+    pub(crate) unsafe fn with_ptr(self, ptr: *mut ()) -> Self {
+        debug_assert!(self.is_box());
+        debug_assert_eq!(ptr as u64 & !PTR_MASK, 0);
+
+        Self((self.0 & !PTR_MASK) | ptr as u64)
+    }
+
     /// Extracts a NonNull<Tuple> from this term
     ///
     /// # Safety
@@ -612,6 +634,9 @@ impl OpaqueTerm {
             super::BinaryData::TYPE_ID => {
                 let _: Rc<super::BinaryData> = unsafe { Rc::from_raw_unchecked(ptr) };
             }
+            super::ExternalBinary::TYPE_ID => {
+                let _: Rc<super::ExternalBinary> = unsafe { Rc::from_raw_unchecked(ptr) };
+            }
             _ => {
                 todo!("should implement a smarter rc container so we can call destructors opaquely")
             }
@@ -773,6 +798,37 @@ impl From<NonNull<Tuple>> for OpaqueTerm {
         Self(raw | INFINITY | TUPLE_TAG)
     }
 }
+impl From<&'static Cons> for OpaqueTerm {
+    /// Returns a literal term for a cons cell in a literal area
+    fn from(cons: &'static Cons) -> Self {
+        let raw = cons as *const Cons as u64;
+        debug_assert!(
+            raw & INFINITY == 0,
+            "expected nan bits to be unused in pointers"
+        );
+        debug_assert!(
+            raw & TAG_MASK == 0,
+            "expected pointer to have at least 8-byte alignment"
+        );
+        Self(raw | INFINITY | CONS_LITERAL_TAG)
+    }
+}
+impl From<&'static Tuple> for OpaqueTerm {
+    /// Returns a literal term for a tuple in a literal area
+    fn from(tuple: &'static Tuple) -> Self {
+        let (raw, _meta) = (tuple as *const Tuple).to_raw_parts();
+        let raw = raw as u64;
+        debug_assert!(
+            raw & INFINITY == 0,
+            "expected nan bits to be unused in pointers"
+        );
+        debug_assert!(
+            raw & TAG_MASK == 0,
+            "expected pointer to have at least 8-byte alignment"
+        );
+        Self(raw | INFINITY | TUPLE_LITERAL_TAG)
+    }
+}
 impl From<&'static BinaryData> for OpaqueTerm {
     fn from(data: &'static BinaryData) -> Self {
         let raw = data as *const _ as *const () as u64;
@@ -811,13 +867,14 @@ impl From<Term> for OpaqueTerm {
         }
     }
 }
-impl Into<Term> for OpaqueTerm {
+impl From<OpaqueTerm> for Term {
     #[inline]
-    fn into(self) -> Term {
+    fn from(term: OpaqueTerm) -> Self {
+        let opaque = term;
         let mut term = MaybeUninit::uninit();
         unsafe {
-            let valid = Self::decode(self, term.as_mut_ptr());
-            debug_assert!(valid, "improperly encoded opaque term: {:064b}", self.0);
+            let valid = OpaqueTerm::decode(opaque, term.as_mut_ptr());
+            debug_assert!(valid, "improperly encoded opaque term: {:064b}", opaque.0);
             term.assume_init()
         }
     }
@@ -1389,6 +1446,53 @@ mod tests {
         assert!(!tuple.is_tuple(NonZeroU32::new(4)));
     }
 
+    #[test]
+    fn opaque_term_literal_cons() {
+        // A constant list, e.g. `[[]]` compiled into a module
+        let list: &'static Cons = Box::leak(Cons::new(OpaqueTerm::NIL, OpaqueTerm::NIL));
+        let cons: OpaqueTerm = list.into();
+
+        assert!(cons.is_box());
+        assert!(!cons.is_gcbox());
+        assert!(!cons.is_rc());
+        assert!(cons.is_literal());
+        assert!(cons.is_nonempty_list());
+        assert!(cons.is_list());
+        assert!(!cons.is_tuple(None));
+        let Term::Cons(ptr) = cons.into() else {
+            panic!("expected a cons cell")
+        };
+        assert_eq!(ptr.as_ptr() as *const Cons, list as *const Cons);
+    }
+
+    #[test]
+    fn opaque_term_literal_tuple() {
+        // A constant tuple, e.g. `{true, false, []}` compiled into a module
+        let ptr = Tuple::from_slice(
+            &[atoms::True.into(), atoms::False.into(), OpaqueTerm::NIL],
+            Global,
+        )
+        .unwrap();
+        let literal: &'static Tuple = unsafe { &*ptr.as_ptr() };
+        let tuple: OpaqueTerm = literal.into();
+
+        assert_eq!(ptr.as_ptr().to_raw_parts(), unsafe {
+            tuple.as_tuple_ptr().as_ptr().to_raw_parts()
+        });
+        assert!(tuple.is_box());
+        assert!(!tuple.is_gcbox());
+        assert!(!tuple.is_rc());
+        assert!(tuple.is_literal());
+        assert!(!tuple.is_list());
+        assert_eq!(tuple.tuple_size(), ErlangResult::Ok(3));
+        assert!(tuple.is_tuple(NonZeroU32::new(3)));
+
+        // The tags of the other pointers never overlap with those of literals
+        let rc: OpaqueTerm = Rc::into_weak(BinaryData::from_bytes(b"shared")).into();
+        assert!(!rc.is_literal());
+        assert!(!OpaqueTerm::from(true).is_literal());
+    }
+
     #[test]
     fn opaque_term_gcbox() {
         let mut boxed = Map::new_in(Global).unwrap();
@@ -0,0 +1,350 @@
+//! This crate provides the `IntoTerm` and `FromTerm` derives, re-exported by `firefly_rt::term`,
+//! which convert structs and enums to and from terms as described in the docs of those traits.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, Generics, Ident,
+    Lit, Meta, NestedMeta,
+};
+
+/// Derives `IntoTerm`, see `firefly_rt::term::IntoTerm`
+#[proc_macro_derive(IntoTerm, attributes(term))]
+pub fn derive_into_term(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_into_term(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Derives `FromTerm`, see `firefly_rt::term::FromTerm`
+#[proc_macro_derive(FromTerm, attributes(term))]
+pub fn derive_from_term(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_from_term(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// The options given in `#[term(...)]` attributes
+#[derive(Default)]
+struct Options {
+    rename: Option<String>,
+    map: bool,
+}
+impl Options {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("term")) {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                meta => return Err(Error::new(meta.span(), "expected #[term(...)]")),
+            };
+            for nested in list.nested.iter() {
+                match nested {
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("map") => {
+                        options.map = true;
+                    }
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("rename") => {
+                        match &nv.lit {
+                            Lit::Str(name) => options.rename = Some(name.value()),
+                            lit => return Err(Error::new(lit.span(), "expected a string")),
+                        }
+                    }
+                    nested => {
+                        return Err(Error::new(
+                            nested.span(),
+                            "unknown option, expected `map` or `rename = \"...\"`",
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// How a struct or variant is represented as a term
+enum Shape {
+    /// An atom
+    Unit { tag: String },
+    /// A tuple tagged with an atom, followed by the fields
+    Record { tag: String, len: usize },
+    /// A map with an atom key for each field
+    Map { keys: Vec<String> },
+    /// A tuple of the fields
+    Tuple { len: usize },
+    /// The only field
+    Newtype,
+}
+
+impl Shape {
+    fn new(ident: &Ident, attrs: &[Attribute], fields: &Fields, tagged: bool) -> syn::Result<Self> {
+        let options = Options::parse(attrs)?;
+        let tag = options
+            .rename
+            .unwrap_or_else(|| to_snake_case(&ident.to_string()));
+        if options.map && !matches!(fields, Fields::Named(_)) {
+            return Err(Error::new(
+                ident.span(),
+                "only structs with named fields can be maps",
+            ));
+        }
+        let shape = match fields {
+            Fields::Unit => Self::Unit { tag },
+            Fields::Named(named) => {
+                if options.map {
+                    if tagged {
+                        return Err(Error::new(ident.span(), "variants cannot be maps"));
+                    }
+                    let keys = named
+                        .named
+                        .iter()
+                        .map(|field| {
+                            let key = Options::parse(&field.attrs)?.rename;
+                            Ok(key.unwrap_or_else(|| field.ident.as_ref().unwrap().to_string()))
+                        })
+                        .collect::<syn::Result<Vec<_>>>()?;
+                    Self::Map { keys }
+                } else {
+                    Self::Record {
+                        tag,
+                        len: named.named.len(),
+                    }
+                }
+            }
+            // The fields of a tuple variant follow its tag, as in a record
+            Fields::Unnamed(unnamed) if tagged => Self::Record {
+                tag,
+                len: unnamed.unnamed.len(),
+            },
+            Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => Self::Newtype,
+            Fields::Unnamed(unnamed) => Self::Tuple {
+                len: unnamed.unnamed.len(),
+            },
+        };
+        Ok(shape)
+    }
+
+    /// Returns the pattern which binds the fields of `path` to `__field0`, `__field1`, etc.
+    fn pattern(&self, path: TokenStream2, fields: &Fields) -> TokenStream2 {
+        match fields {
+            Fields::Unit => path,
+            Fields::Named(named) => {
+                let idents = named.named.iter().map(|field| &field.ident);
+                let bindings = (0..named.named.len()).map(|i| format_ident!("__field{}", i));
+                quote!(#path { #(#idents: #bindings),* })
+            }
+            Fields::Unnamed(unnamed) => {
+                let bindings = (0..unnamed.unnamed.len()).map(|i| format_ident!("__field{}", i));
+                quote!(#path(#(#bindings),*))
+            }
+        }
+    }
+
+    /// Returns the expression which converts the bound fields to a term
+    fn to_term_expr(&self) -> TokenStream2 {
+        let support = quote!(::firefly_rt::term::__support);
+        let into_term = quote!(::firefly_rt::term::IntoTerm::into_term);
+        match self {
+            Self::Unit { tag } => quote!(::core::result::Result::Ok(#support::atom(#tag))),
+            Self::Record { tag, len } => {
+                let bindings = (0..*len).map(|i| format_ident!("__field{}", i));
+                quote! {
+                    #support::tuple(&[#support::atom(#tag), #(#into_term(#bindings, __heap)?),*], __heap)
+                }
+            }
+            Self::Map { keys } => {
+                let bindings = (0..keys.len()).map(|i| format_ident!("__field{}", i));
+                quote! {
+                    #support::map(&[#((#support::atom(#keys), #into_term(#bindings, __heap)?)),*], __heap)
+                }
+            }
+            Self::Tuple { len } => {
+                let bindings = (0..*len).map(|i| format_ident!("__field{}", i));
+                quote!(#support::tuple(&[#(#into_term(#bindings, __heap)?),*], __heap))
+            }
+            Self::Newtype => quote!(#into_term(__field0, __heap)),
+        }
+    }
+
+    /// Returns the statement which returns `__term` converted to `path`, if it has this shape
+    fn term_conversion_stmt(&self, path: TokenStream2, fields: &Fields) -> TokenStream2 {
+        let support = quote!(::firefly_rt::term::__support);
+        let from_term = quote!(::firefly_rt::term::FromTerm::from_term);
+        let construct = |values: Vec<TokenStream2>| match fields {
+            Fields::Unit => path.clone(),
+            Fields::Named(named) => {
+                let idents = named.named.iter().map(|field| &field.ident);
+                quote!(#path { #(#idents: #values),* })
+            }
+            Fields::Unnamed(_) => quote!(#path(#(#values),*)),
+        };
+        let from_elements = |len: usize| {
+            (0..len)
+                .map(|i| {
+                    let i = Literal::usize_unsuffixed(i);
+                    quote!(#from_term(__elements[#i].into())?)
+                })
+                .collect::<Vec<_>>()
+        };
+        match self {
+            Self::Unit { tag } => {
+                let value = construct(vec![]);
+                quote! {
+                    if #support::is_atom(__term, #tag) {
+                        return ::core::result::Result::Ok(#value);
+                    }
+                }
+            }
+            Self::Record { tag, len } => {
+                let value = construct(from_elements(*len));
+                quote! {
+                    if let ::core::option::Option::Some(__elements) = #support::tagged_elements(__term, #tag, #len) {
+                        return ::core::result::Result::Ok(#value);
+                    }
+                }
+            }
+            Self::Map { keys } => {
+                let values = keys
+                    .iter()
+                    .map(|key| quote!(#support::field(__term, #key)?))
+                    .collect();
+                let value = construct(values);
+                quote! {
+                    if __term.as_map().is_some() {
+                        return ::core::result::Result::Ok(#value);
+                    }
+                }
+            }
+            Self::Tuple { len } => {
+                let value = construct(from_elements(*len));
+                quote! {
+                    if let ::core::option::Option::Some(__elements) = #support::elements(__term, #len) {
+                        return ::core::result::Result::Ok(#value);
+                    }
+                }
+            }
+            Self::Newtype => {
+                let value = construct(vec![quote!(#from_term(__term)?)]);
+                quote!(return ::core::result::Result::Ok(#value);)
+            }
+        }
+    }
+}
+
+fn expand_into_term(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let arms = match &input.data {
+        Data::Struct(data) => {
+            let shape = Shape::new(&input.ident, &input.attrs, &data.fields, false)?;
+            let pattern = shape.pattern(quote!(Self), &data.fields);
+            let body = shape.to_term_expr();
+            vec![quote!(#pattern => #body)]
+        }
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let shape = Shape::new(&variant.ident, &variant.attrs, &variant.fields, true)?;
+                let ident = &variant.ident;
+                let pattern = shape.pattern(quote!(Self::#ident), &variant.fields);
+                let body = shape.to_term_expr();
+                Ok(quote!(#pattern => #body))
+            })
+            .collect::<syn::Result<Vec<_>>>()?,
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span(),
+                "unions cannot be converted to terms",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let generics = add_bounds(input.generics.clone(), quote!(::firefly_rt::term::IntoTerm));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::firefly_rt::term::IntoTerm for #name #ty_generics #where_clause {
+            fn into_term<__H: ::firefly_rt::term::__support::Heap>(
+                self,
+                __heap: &__H,
+            ) -> ::core::result::Result<::firefly_rt::term::Term, ::core::alloc::AllocError> {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        }
+    })
+}
+
+fn expand_from_term(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let attempts = match &input.data {
+        Data::Struct(data) => {
+            let shape = Shape::new(&input.ident, &input.attrs, &data.fields, false)?;
+            vec![shape.term_conversion_stmt(quote!(Self), &data.fields)]
+        }
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let shape = Shape::new(&variant.ident, &variant.attrs, &variant.fields, true)?;
+                let ident = &variant.ident;
+                Ok(shape.term_conversion_stmt(quote!(Self::#ident), &variant.fields))
+            })
+            .collect::<syn::Result<Vec<_>>>()?,
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span(),
+                "unions cannot be converted from terms",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let expected = name.to_string();
+    let generics = add_bounds(input.generics.clone(), quote!(::firefly_rt::term::FromTerm));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::firefly_rt::term::FromTerm for #name #ty_generics #where_clause {
+            #[allow(unreachable_code)]
+            fn from_term(
+                __term: ::firefly_rt::term::Term,
+            ) -> ::core::result::Result<Self, ::firefly_rt::term::FromTermError> {
+                #(#attempts)*
+                ::core::result::Result::Err(::firefly_rt::term::FromTermError::new(#expected))
+            }
+        }
+    })
+}
+
+/// Requires each type parameter of `generics` to implement `bound`
+fn add_bounds(mut generics: Generics, bound: TokenStream2) -> Generics {
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+/// Converts a Rust type or variant name, e.g. `HttpRequest`, to the conventional form of an atom,
+/// e.g. `http_request`
+fn to_snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in chars.iter().copied().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).map(|c| c.is_lowercase()).unwrap_or(false);
+            if prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
@@ -0,0 +1,82 @@
+use std::alloc::{AllocError, Allocator, Layout};
+use std::ptr::NonNull;
+use std::rc::Rc;
+
+use firefly_alloc::heap::Heap;
+use firefly_rt::process::ProcessHeap;
+
+/// The size of the heaps returned by [`heap`], which is enough for the terms generated for every
+/// case of a property, including those generated while shrinking a failing case
+pub const HEAP_SIZE: usize = 64 * 1024 * 1024;
+
+/// Returns a new heap on which to allocate the terms generated for a property
+///
+/// Heaps do not grow, so each property should be given its own. The heap is freed once the
+/// strategies it was given to have been dropped, i.e. at the end of the test, as proptest may keep
+/// generated terms until then, e.g. to report a failing case.
+pub fn heap() -> SharedHeap {
+    SharedHeap(Rc::new(ProcessHeap::with_size(HEAP_SIZE).unwrap()))
+}
+
+/// A heap shared by the strategies it is cloned into, see [`heap`]
+#[derive(Clone)]
+pub struct SharedHeap(Rc<ProcessHeap>);
+
+unsafe impl Allocator for SharedHeap {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.allocate(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.grow(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl Heap for SharedHeap {
+    #[inline]
+    fn heap_start(&self) -> *mut u8 {
+        self.0.heap_start()
+    }
+
+    #[inline]
+    fn heap_top(&self) -> *mut u8 {
+        self.0.heap_top()
+    }
+
+    #[inline]
+    fn heap_end(&self) -> *mut u8 {
+        self.0.heap_end()
+    }
+}
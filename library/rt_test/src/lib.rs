@@ -0,0 +1,32 @@
+//! This crate provides property-based testing support for code which works with terms, such as
+//! BIFs and NIFs, in the form of [`proptest`] strategies generating arbitrary terms.
+//!
+//! Strategies compose as any other: [`strategy::term`] generates terms of any type, and the
+//! modules of [`strategy::term`] generate terms of each type, with those generating containers
+//! taking the strategy generating their elements. Terms are allocated on the heap given to the
+//! strategy, which may be any [`Heap`](firefly_alloc::heap::Heap) which outlives the terms, such
+//! as the one returned by [`heap`], which the strategies it is given to keep alive.
+//!
+//! ```ignore
+//! use firefly_rt::term::Term;
+//! use firefly_rt_test::{heap, strategy};
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn tuple_size_is_arity(tuple in strategy::term::tuple::with_arity(
+//!         strategy::term(heap()),
+//!         2,
+//!         heap(),
+//!     )) {
+//!         prop_assert_eq!(tuple.as_tuple().unwrap().len(), 2);
+//!     }
+//! }
+//! ```
+// Used for AllocError
+#![feature(allocator_api)]
+
+mod heap;
+pub mod strategy;
+
+pub use self::heap::{heap, SharedHeap, HEAP_SIZE};
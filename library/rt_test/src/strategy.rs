@@ -0,0 +1,109 @@
+use std::alloc::AllocError;
+use std::ops::RangeInclusive;
+
+use proptest::collection::{self, SizeRange};
+use proptest::prelude::*;
+
+use firefly_alloc::heap::Heap;
+use firefly_rt::term::{Atom, Term};
+
+pub mod term;
+
+/// A heap on which strategies allocate the terms they generate, such as the one returned by
+/// [`heap`](crate::heap)
+///
+/// The heap is cloned into each strategy, so it is typically a reference.
+pub trait TestHeap: Heap + Clone + 'static {}
+impl<H: Heap + Clone + 'static> TestHeap for H {}
+
+/// The maximum depth to which [`term`] nests containers
+pub const DEPTH: u32 = 3;
+/// The maximum number of elements in the containers generated by [`term`]
+pub const MAX_LEN: usize = 3;
+pub const RANGE_INCLUSIVE: RangeInclusive<usize> = 0..=MAX_LEN;
+pub const NON_EMPTY_RANGE_INCLUSIVE: RangeInclusive<usize> = 1..=MAX_LEN;
+/// Generated atoms never start with this prefix, so tests may use it for atoms which must not
+/// exist, e.g. when testing `binary_to_existing_atom/2`
+pub const NON_EXISTENT_ATOM_PREFIX: &str = "non_existent";
+
+pub fn atom() -> BoxedStrategy<Atom> {
+    "\\PC{0,16}"
+        .prop_filter("Reserved for non-existent atom tests", |s| {
+            !s.starts_with(NON_EXISTENT_ATOM_PREFIX)
+        })
+        .prop_map(|s| Atom::try_from(s.as_str()).unwrap())
+        .boxed()
+}
+
+pub fn byte_vec(size_range: impl Into<SizeRange>) -> BoxedStrategy<Vec<u8>> {
+    collection::vec(any::<u8>(), size_range).boxed()
+}
+
+pub fn size_range() -> SizeRange {
+    RANGE_INCLUSIVE.into()
+}
+
+/// Generates terms of any type, nesting containers up to [`DEPTH`] deep
+pub fn term<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    let container_heap = heap.clone();
+
+    term::leaf(heap)
+        .prop_recursive(
+            DEPTH,
+            (MAX_LEN * (DEPTH as usize + 1)) as u32,
+            MAX_LEN as u32,
+            move |element| term::container(element, size_range(), container_heap.clone()),
+        )
+        .boxed()
+}
+
+/// Unwraps the result of allocating a generated term
+pub(crate) fn alloc<T>(result: Result<T, AllocError>) -> T {
+    result.expect("the test heap is exhausted, give each property its own heap")
+}
+
+#[cfg(test)]
+mod tests {
+    use firefly_rt::term::{BigInt, OpaqueTerm, MAX_SMALL, MIN_SMALL};
+
+    use crate::heap;
+
+    use super::term::{binary, integer, list};
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn small_integers_are_immediates(term in integer::small()) {
+            prop_assert!(matches!(term, Term::Int(_)));
+            prop_assert_eq!(Term::from(OpaqueTerm::from(term)), term);
+        }
+
+        #[test]
+        fn big_integers_are_not_small(term in integer::big(heap())) {
+            let small = BigInt::from(MIN_SMALL)..=BigInt::from(MAX_SMALL);
+            match term {
+                Term::BigInt(i) => prop_assert!(!small.contains(&*i)),
+                _ => prop_assert!(false, "expected a big integer, got {:?}", term),
+            }
+        }
+
+        #[test]
+        fn binaries_are_whole_bytes(term in binary::binary(heap())) {
+            prop_assert!(term.as_bitstring().unwrap().is_binary());
+        }
+
+        #[test]
+        fn improper_lists_are_not_proper(term in list::improper(
+            super::term(heap()),
+            NON_EMPTY_RANGE_INCLUSIVE.into(),
+            heap(),
+        )) {
+            prop_assert!(!term.as_cons().unwrap().is_proper());
+        }
+
+        #[test]
+        fn non_atoms_are_not_atoms(term in term::is_not_atom(heap())) {
+            prop_assert!(!matches!(term, Term::Atom(_) | Term::Bool(_)));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use proptest::collection::SizeRange;
+use proptest::prelude::*;
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::term::{Float, Pid, Reference, ReferenceId, Term};
+
+use super::{alloc, TestHeap};
+
+pub mod binary;
+pub mod integer;
+pub mod list;
+pub mod map;
+pub mod tuple;
+
+/// The largest number and serial of generated pids, which are those which can be encoded in the
+/// External Term Format
+const MAX_PID_NUMBER: usize = (1 << 15) - 1;
+const MAX_PID_SERIAL: usize = (1 << 13) - 1;
+
+pub fn atom() -> BoxedStrategy<Term> {
+    super::atom().prop_map(Term::Atom).boxed()
+}
+
+pub fn boolean() -> BoxedStrategy<Term> {
+    any::<bool>().prop_map(Term::Bool).boxed()
+}
+
+pub fn float() -> BoxedStrategy<Term> {
+    any::<f64>()
+        .prop_filter_map("Erlang floats are finite", |f| Float::new(f).ok())
+        .prop_map(Term::Float)
+        .boxed()
+}
+
+pub fn number<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    prop_oneof![integer::integer(heap), float()].boxed()
+}
+
+pub fn pid<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    (0..=MAX_PID_NUMBER, 0..=MAX_PID_SERIAL)
+        .prop_map(move |(number, serial)| {
+            let pid = Pid::new_local(number, serial).unwrap();
+            Term::Pid(alloc(GcBox::new_in(pid, heap.clone())))
+        })
+        .boxed()
+}
+
+pub fn reference<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    (any::<u16>(), 0..(1u64 << 48))
+        .prop_map(move |(scheduler_id, id)| {
+            let reference = Reference::Local {
+                id: ReferenceId::new(scheduler_id, id),
+            };
+            Term::Reference(alloc(GcBox::new_in(reference, heap.clone())))
+        })
+        .boxed()
+}
+
+/// Generates terms of every type which contains no other terms
+pub fn leaf<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    prop_oneof![
+        Just(Term::Nil),
+        atom(),
+        boolean(),
+        integer::integer(heap.clone()),
+        float(),
+        binary::bitstring(heap.clone()),
+        pid(heap.clone()),
+        reference(heap),
+    ]
+    .boxed()
+}
+
+/// Generates lists, tuples and maps of the terms generated by `element`
+pub fn container<H: TestHeap>(
+    element: BoxedStrategy<Term>,
+    size_range: SizeRange,
+    heap: H,
+) -> BoxedStrategy<Term> {
+    prop_oneof![
+        list::proper(element.clone(), size_range.clone(), heap.clone()),
+        tuple::intermediate(element.clone(), size_range.clone(), heap.clone()),
+        map::intermediate(element, size_range, heap),
+    ]
+    .boxed()
+}
+
+pub fn is_not_atom<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    super::term(heap)
+        .prop_filter("Term cannot be an atom", |term| {
+            !matches!(term, Term::Atom(_) | Term::Bool(_))
+        })
+        .boxed()
+}
+
+pub fn is_not_binary<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    super::term(heap)
+        .prop_filter("Term cannot be a binary", |term| {
+            !term
+                .as_bitstring()
+                .map(|bitstring| bitstring.is_binary())
+                .unwrap_or(false)
+        })
+        .boxed()
+}
+
+pub fn is_not_bitstring<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    super::term(heap)
+        .prop_filter("Term cannot be a bitstring", |term| !term.is_bitstring())
+        .boxed()
+}
+
+pub fn is_not_integer<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    super::term(heap)
+        .prop_filter("Term cannot be an integer", |term| {
+            !matches!(term, Term::Int(_) | Term::BigInt(_))
+        })
+        .boxed()
+}
+
+pub fn is_not_list<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    super::term(heap)
+        .prop_filter("Term cannot be a list", |term| {
+            !matches!(term, Term::Nil | Term::Cons(_))
+        })
+        .boxed()
+}
+
+pub fn is_not_map<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    super::term(heap)
+        .prop_filter("Term cannot be a map", |term| !matches!(term, Term::Map(_)))
+        .boxed()
+}
+
+pub fn is_not_number<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    super::term(heap)
+        .prop_filter("Term cannot be a number", |term| {
+            !matches!(term, Term::Int(_) | Term::BigInt(_) | Term::Float(_))
+        })
+        .boxed()
+}
+
+pub fn is_not_tuple<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    super::term(heap)
+        .prop_filter("Term cannot be a tuple", |term| {
+            !matches!(term, Term::Tuple(_))
+        })
+        .boxed()
+}
@@ -0,0 +1,68 @@
+use proptest::prelude::*;
+
+use firefly_alloc::gc::GcBox;
+use firefly_alloc::heap::Heap;
+use firefly_binary::Binary;
+use firefly_rt::term::{BinaryData, BitSlice, IntoTerm, OpaqueTerm, Term};
+
+use crate::strategy::{alloc, byte_vec, TestHeap};
+
+/// The largest number of bytes a sub-binary is taken from
+const MAX_SUB_BYTES: usize = 8;
+
+/// Generates binaries small enough to be allocated on the heap
+pub fn heap<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    byte_vec(0..=BinaryData::MAX_HEAP_BYTES)
+        .prop_map(move |bytes| alloc(bytes.as_slice().into_term(&heap)))
+        .boxed()
+}
+
+/// Generates binaries large enough to be reference-counted
+pub fn rc<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    byte_vec((BinaryData::MAX_HEAP_BYTES + 1)..=(BinaryData::MAX_HEAP_BYTES * 4))
+        .prop_map(move |bytes| alloc(bytes.as_slice().into_term(&heap)))
+        .boxed()
+}
+
+/// Generates sub-binaries of any number of bits, starting at any bit of the binary they refer to
+pub fn sub<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    (byte_vec(1..=MAX_SUB_BYTES), 0..8u8)
+        .prop_flat_map(|(bytes, bit_offset)| {
+            let max_bits = bytes.len() * 8 - bit_offset as usize;
+            (Just(bytes), Just(bit_offset), 1..=max_bits)
+        })
+        .prop_map(move |(bytes, bit_offset, num_bits)| slice(&bytes, bit_offset, num_bits, &heap))
+        .boxed()
+}
+
+/// Generates sub-binaries of whole bytes, which are binaries even if they are not aligned
+pub fn sub_binary<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    (byte_vec(2..=MAX_SUB_BYTES), 0..8u8)
+        .prop_flat_map(|(bytes, bit_offset)| {
+            let max_bytes = (bytes.len() * 8 - bit_offset as usize) / 8;
+            (Just(bytes), Just(bit_offset), 1..=max_bytes)
+        })
+        .prop_map(move |(bytes, bit_offset, num_bytes)| {
+            slice(&bytes, bit_offset, num_bytes * 8, &heap)
+        })
+        .boxed()
+}
+
+pub fn binary<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    prop_oneof![self::heap(heap.clone()), rc(heap.clone()), sub_binary(heap)].boxed()
+}
+
+pub fn bitstring<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    prop_oneof![binary(heap.clone()), sub(heap)].boxed()
+}
+
+/// Allocates `bytes` as a binary, and returns a sub-binary of `num_bits` of it, from `bit_offset`
+fn slice<H: Heap>(bytes: &[u8], bit_offset: u8, num_bits: usize, heap: &H) -> Term {
+    let bin = match alloc(bytes.into_term(heap)) {
+        Term::HeapBinary(bin) => bin,
+        term => panic!("expected a heap binary, got {:?}", term),
+    };
+    let owner: OpaqueTerm = Term::HeapBinary(bin).into();
+    let slice = unsafe { BitSlice::new(owner, bin.as_bytes(), bit_offset, num_bits) };
+    Term::RefBinary(alloc(GcBox::new_in(slice, heap)))
+}
@@ -0,0 +1,29 @@
+use proptest::prelude::*;
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::term::{BigInt, Term, MAX_SMALL, MIN_SMALL};
+
+use crate::strategy::{alloc, TestHeap};
+
+/// Generates integers small enough to be immediates
+pub fn small() -> BoxedStrategy<Term> {
+    (MIN_SMALL..=MAX_SMALL).prop_map(Term::Int).boxed()
+}
+
+/// Generates integers too large to be immediates
+pub fn big<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    prop_oneof![
+        i128::MIN..(MIN_SMALL as i128),
+        ((MAX_SMALL as i128) + 1)..=i128::MAX
+    ]
+    .prop_map(move |i| Term::BigInt(alloc(GcBox::new_in(BigInt::from(i), heap.clone()))))
+    .boxed()
+}
+
+pub fn integer<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    prop_oneof![small(), big(heap)].boxed()
+}
+
+pub fn byte() -> BoxedStrategy<Term> {
+    any::<u8>().prop_map(|byte| Term::Int(byte as i64)).boxed()
+}
@@ -0,0 +1,61 @@
+use std::alloc::AllocError;
+
+use proptest::collection::{self, SizeRange};
+use proptest::prelude::*;
+
+use firefly_alloc::heap::Heap;
+use firefly_rt::term::{Cons, Term};
+
+use crate::strategy::{alloc, TestHeap};
+
+/// Generates proper lists of the terms generated by `element`
+pub fn proper<H: TestHeap>(
+    element: BoxedStrategy<Term>,
+    size_range: SizeRange,
+    heap: H,
+) -> BoxedStrategy<Term> {
+    collection::vec(element, size_range)
+        .prop_map(move |elements| alloc(list(&elements, Term::Nil, &heap)))
+        .boxed()
+}
+
+/// Generates improper lists of the terms generated by `element`, the tail of which is also
+/// generated by `element`, but is not a list
+///
+/// Improper lists have at least one element, so `size_range` must not include zero.
+pub fn improper<H: TestHeap>(
+    element: BoxedStrategy<Term>,
+    size_range: SizeRange,
+    heap: H,
+) -> BoxedStrategy<Term> {
+    let tail = element
+        .clone()
+        .prop_filter("Tail of an improper list cannot be a list", |tail| {
+            !matches!(tail, Term::Nil | Term::Cons(_))
+        });
+    (collection::vec(element, size_range), tail)
+        .prop_map(move |(elements, tail)| alloc(list(&elements, tail, &heap)))
+        .boxed()
+}
+
+/// Generates lists of the codepoints of arbitrary strings
+pub fn charlist<H: TestHeap>(heap: H) -> BoxedStrategy<Term> {
+    any::<String>()
+        .prop_map(move |string| {
+            alloc(Cons::charlist_from_str(&string, &heap))
+                .map(Term::Cons)
+                .unwrap_or(Term::Nil)
+        })
+        .boxed()
+}
+
+/// Allocates a list of `elements`, ending in `tail`
+fn list<H: Heap>(elements: &[Term], tail: Term, heap: &H) -> Result<Term, AllocError> {
+    elements.iter().rev().try_fold(tail, |tail, head| {
+        let cell = Cons::new_in(heap)?;
+        unsafe {
+            cell.as_ptr().write(Cons::cons(*head, tail));
+        }
+        Ok(Term::Cons(cell))
+    })
+}
@@ -0,0 +1,24 @@
+use proptest::collection::{self, SizeRange};
+use proptest::prelude::*;
+
+use firefly_rt::term::{Map, Term};
+
+use crate::strategy::{alloc, TestHeap};
+
+/// Generates maps whose keys and values are generated by `element`
+///
+/// Keys may be generated more than once, so a map may have fewer entries than were generated.
+pub fn intermediate<H: TestHeap>(
+    element: BoxedStrategy<Term>,
+    size_range: SizeRange,
+    heap: H,
+) -> BoxedStrategy<Term> {
+    collection::vec((element.clone(), element), size_range)
+        .prop_map(move |pairs| {
+            Term::Map(alloc(Map::new_from_iter_in(
+                pairs.into_iter(),
+                heap.clone(),
+            )))
+        })
+        .boxed()
+}
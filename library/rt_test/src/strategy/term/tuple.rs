@@ -0,0 +1,28 @@
+use proptest::collection::{self, SizeRange};
+use proptest::prelude::*;
+
+use firefly_rt::term::{OpaqueTerm, Term, Tuple};
+
+use crate::strategy::{alloc, TestHeap};
+
+/// Generates tuples of the terms generated by `element`
+pub fn intermediate<H: TestHeap>(
+    element: BoxedStrategy<Term>,
+    size_range: SizeRange,
+    heap: H,
+) -> BoxedStrategy<Term> {
+    collection::vec(element, size_range)
+        .prop_map(move |elements| {
+            let elements: Vec<OpaqueTerm> = elements.into_iter().map(OpaqueTerm::from).collect();
+            Term::Tuple(alloc(Tuple::from_slice(&elements, heap.clone())))
+        })
+        .boxed()
+}
+
+pub fn with_arity<H: TestHeap>(
+    element: BoxedStrategy<Term>,
+    arity: usize,
+    heap: H,
+) -> BoxedStrategy<Term> {
+    intermediate(element, arity.into(), heap)
+}
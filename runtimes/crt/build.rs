@@ -23,7 +23,7 @@ fn main() {
     let sysroot = PathBuf::from(output(&mut sysroot_cmd).trim());
     println!("Found sysroot at {}", sysroot.display());
     // Search through all of the libs bundled with the toolchain for libstd-<hash>.rlib
-    let toolchain_libs = sysroot.join("lib/rustlib").join(target).join("lib");
+    let toolchain_libs = sysroot.join("lib/rustlib").join(&target).join("lib");
     println!("Searching for libstd rlib in {}", toolchain_libs.display());
     let libstd_rlib = toolchain_libs
         .read_dir()
@@ -81,6 +81,38 @@ fn main() {
         "cargo:rustc-env=LANG_START_SYMBOL_NAME={}",
         lang_start_symbol
     );
+
+    // Record the metadata of this build, reported by `erlang:system_info(firefly_build)`
+    let (hash, hash_date) = git_version();
+    println!("cargo:rustc-env=FIREFLY_COMMIT_HASH={}", hash);
+    println!("cargo:rustc-env=FIREFLY_COMMIT_DATE={}", hash_date);
+    println!("cargo:rustc-env=FIREFLY_BUILD_TARGET={}", &target);
+    println!(
+        "cargo:rustc-env=FIREFLY_BUILD_PROFILE={}",
+        env::var("PROFILE").unwrap()
+    );
+}
+
+/// Returns the hash and date of the commit being built, or `unknown` for both when not building
+/// from a git checkout, e.g. from a source archive
+pub fn git_version() -> (String, String) {
+    let unknown = || ("unknown".to_string(), "unknown".to_string());
+    let output = Command::new("git")
+        .args(&["log", "-n1", "--pretty=format:%h %cd", "--date=iso-strict"])
+        .stderr(Stdio::null())
+        .output();
+    let out = match output {
+        Ok(output) if output.status.success() => String::from_utf8(output.stdout).ok(),
+        _ => None,
+    };
+    let out = match out {
+        Some(out) => out,
+        None => return unknown(),
+    };
+    match out.trim().split_once(' ') {
+        Some((hash, date)) => (hash.to_string(), date.to_string()),
+        None => unknown(),
+    }
 }
 
 pub fn output(cmd: &mut Command) -> String {
@@ -0,0 +1,77 @@
+use firefly_rt::abi::{AbiStamp, AbiVersion};
+
+/// Expands to the name of the symbol below, which must be a literal to be used as its export name
+macro_rules! abi_version_symbol {
+    () => {
+        "__firefly_abi_version_6"
+    };
+}
+
+// The name is checked against the one generated code refers to, so bumping the version without
+// renaming the symbol fails to compile, rather than to link
+const _: () = assert!(
+    str_eq(abi_version_symbol!(), firefly_rt::abi::ABI_VERSION_SYMBOL),
+    "the ABI version symbol does not match firefly_rt::abi::ABI_VERSION_SYMBOL"
+);
+
+/// The symbol referenced by every module compiled against this version of the ABI
+#[export_name = abi_version_symbol!()]
+pub static ABI_VERSION: AbiVersion = AbiVersion::CURRENT;
+
+/// The runtime stamps itself, which ensures the section always exists, even when no
+/// compiled modules are linked in.
+#[cfg_attr(target_os = "macos", link_section = "__DATA,__firefly_abi")]
+#[cfg_attr(all(unix, not(target_os = "macos")), link_section = "__firefly_abi")]
+#[used]
+static RUNTIME_STAMP: AbiStamp = AbiStamp(&ABI_VERSION);
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    #[link_name = "\x01section$start$__DATA$__firefly_abi"]
+    static ABI_START: AbiStamp;
+
+    #[link_name = "\x01section$end$__DATA$__firefly_abi"]
+    static ABI_END: AbiStamp;
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+extern "C" {
+    #[link_name = "__start___firefly_abi"]
+    static ABI_START: AbiStamp;
+
+    #[link_name = "__stop___firefly_abi"]
+    static ABI_END: AbiStamp;
+}
+
+/// Verifies that all modules linked into this executable were compiled against the runtime ABI
+pub(super) fn verify() -> bool {
+    match unsafe { firefly_rt::abi::verify_stamps(&ABI_START, &ABI_END) } {
+        Ok(_) => true,
+        Err(index) => {
+            eprintln!(
+                "firefly: module #{} was compiled against an incompatible runtime ABI (expected \
+                 version {})",
+                index,
+                firefly_rt::abi::ABI_VERSION
+            );
+            false
+        }
+    }
+}
+
+// Only used by the assertion above, which dead code analysis does not see through
+#[allow(dead_code)]
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
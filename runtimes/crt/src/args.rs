@@ -0,0 +1,46 @@
+//! The arguments the executable was invoked with, as given to `main`
+//!
+//! These are kept as given, rather than read back from the standard library, so that the runtime
+//! sees exactly what the generated executable was passed, see `firefly_rt_tiny::env`. When the
+//! runtime is embedded in another program, `main` is not ours, and there are none.
+use std::ffi::{CStr, OsString};
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicIsize, AtomicPtr, Ordering};
+
+static ARGC: AtomicIsize = AtomicIsize::new(0);
+static ARGV: AtomicPtr<*const c_char> = AtomicPtr::new(ptr::null_mut());
+
+/// Records the arguments given to `main`, which live for as long as the program does
+#[cfg_attr(not(feature = "entry"), allow(dead_code))]
+pub(super) fn init(argc: isize, argv: *const *const c_char) {
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv as *mut _, Ordering::Release);
+}
+
+/// Returns the arguments given to `main`, including the name of the executable, or `None` if the
+/// executable was not entered via `main`
+pub fn argv() -> Option<Vec<OsString>> {
+    let argv = ARGV.load(Ordering::Acquire);
+    if argv.is_null() {
+        return None;
+    }
+    let argc = ARGC.load(Ordering::Relaxed);
+    let args = (0..argc)
+        .map(|i| unsafe { CStr::from_ptr(*argv.offset(i)) })
+        .map(to_os_string)
+        .collect();
+    Some(args)
+}
+
+#[cfg(unix)]
+fn to_os_string(arg: &CStr) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+
+    OsString::from_vec(arg.to_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+fn to_os_string(arg: &CStr) -> OsString {
+    OsString::from(arg.to_string_lossy().into_owned())
+}
@@ -0,0 +1,41 @@
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+use firefly_rt::boot::{self, BootScript};
+
+extern "C" {
+    /// The boot manifest generated by the compiler, see `firefly_rt::boot`
+    ///
+    /// NOTE: The name of this symbol must match `firefly_rt::boot::BOOT_MANIFEST_SYMBOL`.
+    ///
+    /// This is a weak reference, as executables linked without a manifest (e.g. those not
+    /// produced by the compiler) simply have no applications to start during boot.
+    #[linkage = "extern_weak"]
+    #[link_name = "__firefly_boot_manifest"]
+    static BOOT_MANIFEST: *const c_char;
+}
+
+/// Decodes the boot manifest embedded in this executable, making it available to the runtime
+///
+/// Returns false if the manifest is present, but invalid.
+pub(super) fn init() -> bool {
+    let manifest = unsafe { BOOT_MANIFEST };
+    if manifest.is_null() {
+        return true;
+    }
+    let manifest = unsafe { CStr::from_ptr(manifest) };
+    let script = manifest
+        .to_str()
+        .map_err(|_| "boot manifest is not valid UTF-8".to_string())
+        .and_then(|manifest| BootScript::parse(manifest).map_err(|err| err.to_string()));
+    match script {
+        Ok(script) => {
+            boot::set_boot_script(script);
+            true
+        }
+        Err(reason) => {
+            eprintln!("firefly: invalid boot manifest: {}", reason);
+            false
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! Metadata about the build of the runtime, captured by the build script of this crate.
+//!
+//! This is the runtime half of what `erlang:system_info(firefly_build)` reports, the other half
+//! being what the compiler embedded in the executable, see `firefly_session::build_info`.
+
+/// The version of the runtime
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The abbreviated hash of the commit the runtime was built from, or `unknown`
+pub const COMMIT_HASH: &str = env!("FIREFLY_COMMIT_HASH");
+/// The date of the commit the runtime was built from, in ISO 8601 format, or `unknown`
+pub const COMMIT_DATE: &str = env!("FIREFLY_COMMIT_DATE");
+/// The target triple the runtime was built for
+pub const TARGET: &str = env!("FIREFLY_BUILD_TARGET");
+/// The cargo profile the runtime was built with, i.e. `debug` or `release`
+pub const PROFILE: &str = env!("FIREFLY_BUILD_PROFILE");
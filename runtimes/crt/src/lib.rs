@@ -1,9 +1,18 @@
 #![feature(rustc_attrs)]
 #![feature(c_unwind)]
+#![feature(linkage)]
 
+mod abi;
+mod args;
 mod atoms;
+mod boot;
+pub mod build_info;
+mod spans;
+mod stackmaps;
 mod symbols;
 
+pub use self::args::argv;
+
 extern "C" {
     /// The target-defined entry point for the generated executable.
     ///
@@ -19,8 +28,10 @@ extern "C" {
     fn lang_start(main: &dyn Fn() -> i32, argc: isize, argv: *const *const i8) -> isize;
 }
 
+#[cfg(feature = "entry")]
 #[no_mangle]
 pub extern "C" fn main(argc: i32, argv: *const *const std::os::raw::c_char) -> i32 {
+    args::init(argc as isize, argv);
     unsafe { lang_start(&move || main_internal(), argc as isize, argv) as i32 }
 }
 
@@ -32,16 +43,54 @@ pub extern "C" fn main(argc: i32, argv: *const *const std::os::raw::c_char) -> i
 /// up the schedulers and other high-level runtime functionality.
 #[rustc_main]
 pub fn main_internal() -> i32 {
+    if let Err(code) = init() {
+        return code;
+    }
+
+    // Invoke platform-specific entry point
+    unsafe { firefly_entry() }
+}
+
+/// Initializes the core functionality required by the higher-level runtime, without invoking the
+/// platform-specific entry point
+///
+/// This is used when the runtime is embedded in another program, which then starts the schedulers
+/// itself. It must be called at most once, and returns the exit code to report on failure.
+pub fn init() -> Result<(), i32> {
+    // Ensure the generated code agrees with us on the ABI
+    if !abi::verify() {
+        return Err(101);
+    }
+
     // Initialize atom table
     if unsafe { atoms::init(atoms::start(), atoms::end()) } == false {
-        return 102;
+        return Err(102);
     }
 
     // Initialize the dispatch table
     if unsafe { symbols::init(symbols::start(), symbols::end()) } == false {
-        return 103;
+        return Err(103);
     }
 
-    // Invoke platform-specific entry point
-    unsafe { firefly_entry() }
+    // Load the boot script, which determines the applications started during boot
+    if !boot::init() {
+        return Err(104);
+    }
+
+    // Load the source span tables used to report precise locations in stack traces
+    if !spans::init() {
+        return Err(105);
+    }
+
+    // Register native functions linked in ahead-of-time, overriding their definitions in the dispatch table
+    if !unsafe { symbols::register_nifs(symbols::nifs_start(), symbols::nifs_end()) } {
+        return Err(106);
+    }
+
+    // Load the stack maps used to find the roots of processes when collecting their heaps
+    if !stackmaps::init() {
+        return Err(107);
+    }
+
+    Ok(())
 }
@@ -0,0 +1,35 @@
+/// The runtime contributes an empty span table, which ensures the section always exists, even when
+/// no compiled modules are linked in.
+#[cfg_attr(target_os = "macos", link_section = "__DATA,__firefly_spans")]
+#[cfg_attr(all(unix, not(target_os = "macos")), link_section = "__firefly_spans")]
+#[used]
+static RUNTIME_SPANS: [u8; 4] = [0; 4];
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    #[link_name = "\x01section$start$__DATA$__firefly_spans"]
+    static SPANS_START: u8;
+
+    #[link_name = "\x01section$end$__DATA$__firefly_spans"]
+    static SPANS_END: u8;
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+extern "C" {
+    #[link_name = "__start___firefly_spans"]
+    static SPANS_START: u8;
+
+    #[link_name = "__stop___firefly_spans"]
+    static SPANS_END: u8;
+}
+
+/// Loads the source span tables emitted by the compiler, see `firefly_rt::backtrace::spans`
+///
+/// Returns false if the span table section is malformed.
+pub(super) fn init() -> bool {
+    let valid = unsafe { firefly_rt::backtrace::spans::set_span_table(&SPANS_START, &SPANS_END) };
+    if !valid {
+        eprintln!("firefly: the source span table in this executable is malformed");
+    }
+    valid
+}
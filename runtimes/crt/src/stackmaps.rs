@@ -0,0 +1,42 @@
+use firefly_rt::process::stackmaps::StackMapRef;
+
+/// The runtime contributes a null stack map, which ensures the section always exists, even when no
+/// compiled modules are linked in.
+#[cfg_attr(target_os = "macos", link_section = "__DATA,__firefly_stackmaps")]
+#[cfg_attr(
+    all(unix, not(target_os = "macos")),
+    link_section = "__firefly_stackmaps"
+)]
+#[used]
+static RUNTIME_STACK_MAP: StackMapRef = StackMapRef(core::ptr::null());
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    #[link_name = "\x01section$start$__DATA$__firefly_stackmaps"]
+    static STACK_MAPS_START: StackMapRef;
+
+    #[link_name = "\x01section$end$__DATA$__firefly_stackmaps"]
+    static STACK_MAPS_END: StackMapRef;
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+extern "C" {
+    #[link_name = "__start___firefly_stackmaps"]
+    static STACK_MAPS_START: StackMapRef;
+
+    #[link_name = "__stop___firefly_stackmaps"]
+    static STACK_MAPS_END: StackMapRef;
+}
+
+/// Loads the stack maps emitted by the compiler, see `firefly_rt::process::stackmaps`
+///
+/// Returns false if a stack map is malformed.
+pub(super) fn init() -> bool {
+    let valid = unsafe {
+        firefly_rt::process::stackmaps::set_stack_maps(&STACK_MAPS_START, &STACK_MAPS_END)
+    };
+    if !valid {
+        eprintln!("firefly: the stack maps in this executable are malformed");
+    }
+    valid
+}
@@ -1,8 +1,13 @@
-use firefly_rt::function::FunctionSymbol;
+use firefly_rt::function::{FunctionSymbol, StaticNif};
 
 extern "C-unwind" {
     #[link_name = "__firefly_initialize_dispatch_table"]
     pub fn init(start: *const FunctionSymbol, end: *const FunctionSymbol) -> bool;
+
+    /// This function is defined in `firefly_rt::function::apply`
+    #[allow(improper_ctypes)]
+    #[link_name = "__firefly_register_static_nifs"]
+    pub fn register_nifs(start: *const StaticNif, end: *const StaticNif) -> bool;
 }
 
 #[cfg(target_os = "macos")]
@@ -30,3 +35,40 @@ pub(super) fn start() -> *const FunctionSymbol {
 pub(super) fn end() -> *const FunctionSymbol {
     unsafe { &DISPATCH_END }
 }
+
+// The native functions section only exists when a crate defining them is linked in, so these are
+// weak references, which are null when the section is absent. As with `extern_weak` statics in
+// general, the value of each static is the address of the symbol.
+#[cfg(target_os = "macos")]
+extern "C" {
+    #[allow(improper_ctypes)]
+    #[linkage = "extern_weak"]
+    #[link_name = "\x01section$start$__DATA$__firefly_nifs"]
+    static NIFS_START: *const StaticNif;
+
+    #[allow(improper_ctypes)]
+    #[linkage = "extern_weak"]
+    #[link_name = "\x01section$end$__DATA$__firefly_nifs"]
+    static NIFS_END: *const StaticNif;
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+extern "C" {
+    #[allow(improper_ctypes)]
+    #[linkage = "extern_weak"]
+    #[link_name = "__start___firefly_nifs"]
+    static NIFS_START: *const StaticNif;
+
+    #[allow(improper_ctypes)]
+    #[linkage = "extern_weak"]
+    #[link_name = "__stop___firefly_nifs"]
+    static NIFS_END: *const StaticNif;
+}
+
+pub(super) fn nifs_start() -> *const StaticNif {
+    unsafe { NIFS_START }
+}
+
+pub(super) fn nifs_end() -> *const StaticNif {
+    unsafe { NIFS_END }
+}
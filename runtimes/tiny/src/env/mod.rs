@@ -1,27 +1,83 @@
 use std::alloc::Layout;
 use std::borrow::Borrow;
-use std::env::ArgsOs;
+use std::ffi::OsString;
 use std::mem;
 use std::path::Path;
 use std::ptr;
 use std::sync::OnceLock;
+use std::time::Duration;
 
 use anyhow::anyhow;
 
+use firefly_alloc::allocators::backing;
 use firefly_arena::DroplessArena;
 use firefly_binary::{BinaryFlags, Encoding};
 use firefly_rt::term::BinaryData;
 
+use crate::memory;
+use crate::sim;
+use crate::time;
+
 static ARGV: OnceLock<EnvTable> = OnceLock::new();
+static ARGUMENTS: OnceLock<Arguments> = OnceLock::new();
 
 /// Returns all arguments this executable was invoked with
 pub fn argv() -> &'static [&'static BinaryData] {
     ARGV.get().unwrap().argv.as_slice()
 }
 
+/// Returns the arguments passed on to `init`, parsed into flags and plain arguments
+pub fn arguments() -> &'static Arguments {
+    ARGUMENTS.get().unwrap()
+}
+
+/// The arguments of `init`, as returned by `init:get_arguments/0` and `init:get_plain_arguments/0`
+///
+/// As with ERTS, a flag is an argument starting with `-`, followed by its values, i.e. the
+/// arguments up to the next flag. A flag may be given more than once, each time with its own
+/// values. Arguments before the first flag are plain arguments, as is everything after `-extra`,
+/// which is never itself a flag. `-root`, `-progname` and `-home` are always given, see [`init`].
+#[derive(Debug, Default)]
+pub struct Arguments {
+    pub flags: Vec<(String, Vec<String>)>,
+    pub plain: Vec<String>,
+}
+impl Arguments {
+    fn parse(args: &[String]) -> Self {
+        let mut arguments = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-extra" {
+                arguments.plain.extend(args.by_ref().cloned());
+                break;
+            }
+            match arg.strip_prefix('-') {
+                Some(flag) if !flag.is_empty() => arguments.flags.push((flag.to_string(), vec![])),
+                _ => match arguments.flags.last_mut() {
+                    Some((_, values)) => values.push(arg.clone()),
+                    None => arguments.plain.push(arg.clone()),
+                },
+            }
+        }
+        arguments
+    }
+
+    /// Returns the values of each occurrence of `flag`, without its leading `-`
+    pub fn get(&self, flag: &str) -> Vec<&[String]> {
+        self.flags
+            .iter()
+            .filter(|(name, _)| name == flag)
+            .map(|(_, values)| values.as_slice())
+            .collect()
+    }
+}
+
 /// Performs one-time initialization of the environment for the current executable.
 /// This is used to cache the arguments vector as constant binary values.
-pub fn init(mut argv: ArgsOs) -> anyhow::Result<()> {
+pub fn init<I>(mut argv: I) -> anyhow::Result<()>
+where
+    I: ExactSizeIterator<Item = OsString>,
+{
     let mut table = EnvTable::with_capacity(argv.len());
 
     let arg0 = argv.next().unwrap();
@@ -35,12 +91,19 @@ pub fn init(mut argv: ArgsOs) -> anyhow::Result<()> {
     }
 
     // Register `root` flag
+    //
+    // There is no executable on wasm32, so the root is the root of the (virtual) filesystem
+    #[cfg(not(target_arch = "wasm32"))]
     let current_exe = std::env::current_exe()?;
+    #[cfg(not(target_arch = "wasm32"))]
     let root = current_exe.parent().unwrap().to_string_lossy();
+    #[cfg(target_arch = "wasm32")]
+    let root = "/";
     unsafe {
         table.insert("-root".as_bytes());
         table.insert(root.as_bytes());
     }
+    let mut args = vec!["-root".to_string(), root.to_string()];
 
     // Register 'progname' flag
     let arg0 = {
@@ -52,35 +115,249 @@ pub fn init(mut argv: ArgsOs) -> anyhow::Result<()> {
         table.insert("-progname".as_bytes());
         table.insert(progname.as_bytes());
     }
+    args.extend(["-progname".to_string(), progname.to_string()]);
 
     // Register `home` flag
+    args.push("-home".to_string());
     if let Some(home) = dirs::home_dir() {
+        let home = home.to_string_lossy();
         unsafe {
             table.insert("-home".as_bytes());
-            let home = home.to_string_lossy();
             table.insert(home.as_bytes());
         }
+        args.push(home.into_owned());
     } else {
         unsafe {
             table.insert("-home".as_bytes());
             table.argv.push(empty);
         }
+        args.push(String::new());
     }
 
-    for arg in argv {
+    let mut inetd = false;
+    let mut extra = false;
+    while let Some(arg) = argv.next() {
         let arg = arg.to_string_lossy();
+        // Everything after `-extra` is a plain argument, so is passed on to `init` as it is, even
+        // if it looks like a flag of the runtime
+        extra |= arg == "-extra";
+        if extra {
+            unsafe {
+                table.insert(arg.as_bytes());
+            }
+            args.push(arg.into_owned());
+            continue;
+        }
+        // The memory limit is a flag of the runtime, so is not passed on to `init`
+        if arg == "+Mlimit" {
+            let size = argv.next().map(|size| size.to_string_lossy().into_owned());
+            let limit = size
+                .as_deref()
+                .and_then(memory::parse_size)
+                .ok_or_else(|| {
+                    anyhow!("+Mlimit expects a size in bytes, e.g. 512M, got {:?}", size)
+                })?;
+            memory::set_limit(Some(limit));
+            continue;
+        }
+        // As is the tuning of the backing allocator
+        if arg == "+MBdecay" {
+            let ms = argv.next().map(|ms| ms.to_string_lossy().into_owned());
+            let ms = ms
+                .as_deref()
+                .and_then(|ms| ms.parse::<u32>().ok())
+                .ok_or_else(|| anyhow!("+MBdecay expects a delay in milliseconds, got {:?}", ms))?;
+            backing::set_decay(ms).map_err(|reason| anyhow!("+MBdecay: {}", reason))?;
+            continue;
+        }
+        // As are the options of the allocators of each type of memory
+        if let Some((ty, option)) = memory::allocator_flag(&arg) {
+            let value = argv
+                .next()
+                .map(|value| value.to_string_lossy().into_owned());
+            memory::set_allocator_option(ty, option, value.as_deref())
+                .map_err(|reason| anyhow!("{} {}", arg, reason))?;
+            continue;
+        }
+        // As is the endpoint serving the diagnostics report, see `erlang::firefly_diag`
+        if arg == "+diag" {
+            let addr = argv.next().map(|addr| addr.to_string_lossy().into_owned());
+            let Some(addr) = addr else {
+                return Err(anyhow!("+diag expects a port or an address to listen on"));
+            };
+            serve_diagnostics(&addr)?;
+            continue;
+        }
+        // As is the endpoint serving the metrics to Prometheus, see `crate::metrics`
+        if arg == "+metrics" {
+            let addr = argv.next().map(|addr| addr.to_string_lossy().into_owned());
+            let Some(addr) = addr else {
+                return Err(anyhow!(
+                    "+metrics expects a port or an address to listen on"
+                ));
+            };
+            serve_metrics(&addr)?;
+            continue;
+        }
+        // As is whether standard input is a socket inherited from inetd, see `sys::activation`
+        if arg == "+inetd" {
+            inetd = true;
+            continue;
+        }
+        // As is how long connections are drained for when shutting down, see `sys::drain`
+        if arg == "+drain" {
+            let ms = argv.next().map(|ms| ms.to_string_lossy().into_owned());
+            let ms = ms
+                .as_deref()
+                .and_then(|ms| ms.parse::<u64>().ok())
+                .ok_or_else(|| anyhow!("+drain expects a timeout in milliseconds, got {:?}", ms))?;
+            set_drain_timeout(Duration::from_millis(ms));
+            continue;
+        }
+        // As is the scheduler watchdog, see `sys::watchdog`
+        if arg == "+watchdog" {
+            let ms = argv.next().map(|ms| ms.to_string_lossy().into_owned());
+            let ms = ms
+                .as_deref()
+                .and_then(|ms| ms.parse::<u64>().ok())
+                .ok_or_else(|| {
+                    anyhow!("+watchdog expects a limit in milliseconds, got {:?}", ms)
+                })?;
+            set_watchdog(Some(Duration::from_millis(ms)), None)?;
+            continue;
+        }
+        if arg == "+watchdog_abort" {
+            let value = argv
+                .next()
+                .map(|value| value.to_string_lossy().into_owned());
+            let abort = match value.as_deref() {
+                Some("true") => true,
+                Some("false") => false,
+                _ => {
+                    return Err(anyhow!(
+                        "+watchdog_abort expects true or false, got {:?}",
+                        value
+                    ))
+                }
+            };
+            set_watchdog(None, Some(abort))?;
+            continue;
+        }
+        // As is the time warp mode, see `crate::time`
+        if arg == "+C" {
+            let mode = argv.next().map(|mode| mode.to_string_lossy().into_owned());
+            let mode = mode
+                .as_deref()
+                .and_then(time::WarpMode::from_name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "+C expects no_time_warp, single_time_warp or multi_time_warp, got {:?}",
+                        mode
+                    )
+                })?;
+            time::set_warp_mode(mode);
+            continue;
+        }
+        // As is the seed of a deterministic simulation, see `crate::sim`
+        if arg == "+sim" {
+            let seed = argv.next().map(|seed| seed.to_string_lossy().into_owned());
+            let seed = seed
+                .as_deref()
+                .and_then(|seed| seed.parse::<u64>().ok())
+                .ok_or_else(|| anyhow!("+sim expects a seed, got {:?}", seed))?;
+            sim::enable(seed);
+            continue;
+        }
+        // This runtime has a single scheduler, so there is no load to compact onto fewer
+        // schedulers, nor any migration of processes between them to limit. The flags controlling
+        // these in ERTS are validated and ignored, so that `vm.args` written for ERTS can be used
+        if arg == "+scl" || arg == "+sub" {
+            let value = argv
+                .next()
+                .map(|value| value.to_string_lossy().into_owned());
+            if !matches!(value.as_deref(), Some("true" | "false")) {
+                return Err(anyhow!("{} expects true or false, got {:?}", arg, value));
+            }
+            continue;
+        }
         unsafe {
             table.insert(arg.as_bytes());
         }
+        args.push(arg.into_owned());
     }
 
+    inherit_sockets(inetd);
+
     ARGV.set(table)
         .map_err(|_| anyhow!("arguments were already initialized"))
         .unwrap();
+    ARGUMENTS.set(Arguments::parse(args.as_slice())).unwrap();
+
+    Ok(())
+}
+
+/// Serves the diagnostics report over HTTP on `addr`, see `crate::sys::diagnostics`
+#[cfg(not(target_arch = "wasm32"))]
+fn serve_diagnostics(addr: &str) -> anyhow::Result<()> {
+    crate::sys::diagnostics::serve(addr)
+        .map_err(|reason| anyhow!("+diag: unable to listen on {}: {}", addr, reason))
+}
+
+/// There are no sockets to listen on in the browser, nor any threads to serve them with on WASI
+#[cfg(target_arch = "wasm32")]
+fn serve_diagnostics(_addr: &str) -> anyhow::Result<()> {
+    Err(anyhow!("+diag is not supported on this target"))
+}
+
+/// Serves the metrics over HTTP on `addr`, see `crate::sys::metrics`
+#[cfg(not(target_arch = "wasm32"))]
+fn serve_metrics(addr: &str) -> anyhow::Result<()> {
+    crate::sys::metrics::serve(addr)
+        .map_err(|reason| anyhow!("+metrics: unable to listen on {}: {}", addr, reason))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn serve_metrics(_addr: &str) -> anyhow::Result<()> {
+    Err(anyhow!("+metrics is not supported on this target"))
+}
+
+/// Takes the sockets inherited from the service manager or inetd, see `crate::sys::activation`
+#[cfg(not(target_arch = "wasm32"))]
+fn inherit_sockets(inetd: bool) {
+    crate::sys::activation::init(inetd)
+}
 
+#[cfg(target_arch = "wasm32")]
+fn inherit_sockets(_inetd: bool) {}
+
+/// Configures the scheduler watchdog, see `crate::sys::watchdog`
+#[cfg(not(target_arch = "wasm32"))]
+fn set_watchdog(limit: Option<Duration>, abort: Option<bool>) -> anyhow::Result<()> {
+    if let Some(limit) = limit {
+        crate::sys::watchdog::set_limit(limit);
+    }
+    if let Some(abort) = abort {
+        crate::sys::watchdog::set_abort(abort);
+    }
     Ok(())
 }
 
+/// There are no threads to watch the scheduler from on this target
+#[cfg(target_arch = "wasm32")]
+fn set_watchdog(_limit: Option<Duration>, _abort: Option<bool>) -> anyhow::Result<()> {
+    Err(anyhow!("+watchdog is not supported on this target"))
+}
+
+/// Sets how long connections are drained for when shutting down, see `crate::sys::drain`
+#[cfg(not(target_arch = "wasm32"))]
+fn set_drain_timeout(timeout: Duration) {
+    crate::sys::drain::set_shutdown_timeout(timeout)
+}
+
+/// There are no signals asking the runtime to shut down on this target, so nothing to drain for
+#[cfg(target_arch = "wasm32")]
+fn set_drain_timeout(_timeout: Duration) {}
+
 #[derive(Default)]
 struct EnvTable {
     argv: Vec<&'static BinaryData>,
@@ -0,0 +1,884 @@
+//! A minimal application controller, implementing the core of the `application` module.
+//!
+//! The compiler bundles application resources into executables as the `firefly_apps` module,
+//! whose `specs/0` function returns a list of `{application, Name, Props}` terms. These are
+//! made available for loading the first time the controller is used.
+//!
+//! This runtime has no supervision, so an application's `Mod:start/2` callback is invoked in
+//! the calling process, and the state it returns is recorded for use by `Mod:stop/1`, rather than
+//! the top-level supervisor being linked to an application master. Terms held by the controller
+//! (start arguments and environment values) are copied to heap fragments which are never freed.
+//!
+//! An application terminates when the top-level supervisor returned by `Mod:start/2`, a server of
+//! `super::gen`, does, and what follows depends on its restart type, as in OTP: the system halts
+//! when a `permanent` application terminates, or a `transient` one terminates abnormally, while
+//! the termination of a `temporary` application is only reported. An application stopped with
+//! `stop/1` is not considered to have terminated.
+use std::collections::{BTreeMap, BTreeSet};
+use std::ptr::NonNull;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::boot::RestartType;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::{self, ErlangResult, ModuleFunctionArity};
+use firefly_rt::term::*;
+
+use crate::halt::{Request, Status};
+
+use super::badarg;
+use super::util::*;
+
+/// The module synthesized by the compiler containing the bundled application resources
+const BUNDLED_SPECS_MODULE: &str = "firefly_apps";
+
+/// Applications which are part of the runtime itself, and so are always loaded and started
+const BUILTIN_APPS: &[(&str, &str)] = &[
+    ("kernel", "ERTS  CXC 138 10"),
+    ("stdlib", "ERTS  CXC 138 10"),
+];
+
+static CONTROLLER: OnceLock<Mutex<Controller>> = OnceLock::new();
+
+struct AppSpec {
+    description: String,
+    vsn: String,
+    /// The applications which must be started before this one
+    applications: Vec<Atom>,
+    /// The application callback module and its start arguments, if this is not a library
+    /// application
+    module: Option<(Atom, OpaqueTerm)>,
+    /// Default environment for this application, applied when loaded
+    env: Vec<(Atom, OpaqueTerm)>,
+}
+
+struct RunningApp {
+    name: Atom,
+    module: Option<Atom>,
+    /// The state returned from `Mod:start/2`, which is passed to `Mod:stop/1`
+    state: OpaqueTerm,
+    /// The top-level supervisor returned from `Mod:start/2`, if it is a local server
+    supervisor: Option<ProcessId>,
+    restart_type: RestartType,
+}
+
+#[derive(Default)]
+struct Controller {
+    /// Application resources which are available to be loaded
+    bundled: BTreeMap<Atom, OpaqueTerm>,
+    loaded: BTreeMap<Atom, AppSpec>,
+    /// Running applications, in the order they were started
+    running: Vec<RunningApp>,
+    env: BTreeMap<(Atom, Atom), OpaqueTerm>,
+    /// The parameters set with `{persistent, true}`, which are not overridden by reloading the
+    /// configuration, see `super::firefly_config`
+    persistent: BTreeSet<(Atom, Atom)>,
+}
+impl Controller {
+    fn new() -> Self {
+        let mut controller = Self::default();
+        for (name, description) in BUILTIN_APPS.iter().copied() {
+            let name = atom(name);
+            controller.loaded.insert(
+                name,
+                AppSpec {
+                    description: description.to_string(),
+                    vsn: env!("CARGO_PKG_VERSION").to_string(),
+                    applications: vec![],
+                    module: None,
+                    env: vec![],
+                },
+            );
+            controller.running.push(RunningApp {
+                name,
+                module: None,
+                state: OpaqueTerm::NIL,
+                supervisor: None,
+                restart_type: RestartType::Permanent,
+            });
+        }
+        for spec in bundled_specs() {
+            if let Some(name) = spec_name(spec) {
+                controller.bundled.insert(name, spec);
+            }
+        }
+        controller
+    }
+
+    fn is_running(&self, name: Atom) -> bool {
+        self.running.iter().any(|app| app.name == name)
+    }
+
+    /// Loads the bundled resource for `name`, returning false if there is no such application
+    fn load(&mut self, name: Atom) -> bool {
+        match self.bundled.remove(&name).and_then(parse_spec) {
+            Some((_, spec)) => {
+                self.insert(name, spec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, name: Atom, spec: AppSpec) {
+        // Values set explicitly prior to loading take precedence over the defaults
+        for (key, value) in spec.env.iter().copied() {
+            self.env.entry((name, key)).or_insert(value);
+        }
+        self.loaded.insert(name, spec);
+    }
+}
+
+fn controller() -> MutexGuard<'static, Controller> {
+    CONTROLLER
+        .get_or_init(|| Mutex::new(Controller::new()))
+        .lock()
+        .unwrap()
+}
+
+#[export_name = "application:load/1"]
+pub extern "C-unwind" fn load1(app: OpaqueTerm) -> ErlangResult {
+    match app.into() {
+        Term::Atom(name) => {
+            let mut controller = controller();
+            if controller.loaded.contains_key(&name) {
+                return error(reason2("already_loaded", name.into()));
+            }
+            if controller.load(name) {
+                ErlangResult::Ok(atoms::Ok.into())
+            } else {
+                error(not_found(name))
+            }
+        }
+        Term::Tuple(_) => match parse_spec(app) {
+            Some((name, spec)) => {
+                let mut controller = controller();
+                if controller.loaded.contains_key(&name) {
+                    return error(reason2("already_loaded", name.into()));
+                }
+                controller.bundled.remove(&name);
+                controller.insert(name, spec);
+                ErlangResult::Ok(atoms::Ok.into())
+            }
+            None => badarg(Trace::capture()),
+        },
+        _ => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "application:start/1"]
+pub extern "C-unwind" fn start1(app: OpaqueTerm) -> ErlangResult {
+    start2(app, atom("temporary").into())
+}
+
+#[export_name = "application:start/2"]
+pub extern "C-unwind" fn start2(app: OpaqueTerm, restart_type: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(name) = app.into() else {
+        return badarg(Trace::capture());
+    };
+    let Some(restart_type) = parse_restart_type(restart_type) else {
+        return badarg(Trace::capture());
+    };
+    match start(name, restart_type)? {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(reason) => error(reason),
+    }
+}
+
+#[export_name = "application:ensure_started/1"]
+pub extern "C-unwind" fn ensure_started1(app: OpaqueTerm) -> ErlangResult {
+    ensure_started2(app, atom("temporary").into())
+}
+
+#[export_name = "application:ensure_started/2"]
+pub extern "C-unwind" fn ensure_started2(
+    app: OpaqueTerm,
+    restart_type: OpaqueTerm,
+) -> ErlangResult {
+    let Term::Atom(name) = app.into() else {
+        return badarg(Trace::capture());
+    };
+    let Some(restart_type) = parse_restart_type(restart_type) else {
+        return badarg(Trace::capture());
+    };
+    if controller().is_running(name) {
+        return ErlangResult::Ok(atoms::Ok.into());
+    }
+    match start(name, restart_type)? {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(reason) => error(reason),
+    }
+}
+
+#[export_name = "application:ensure_all_started/1"]
+pub extern "C-unwind" fn ensure_all_started1(app: OpaqueTerm) -> ErlangResult {
+    ensure_all_started2(app, atom("temporary").into())
+}
+
+/// Starts the given application, after first starting any applications it depends on which are not
+/// already running, in dependency order.
+///
+/// Returns `{ok, Started}`, where `Started` is the list of applications started, in start order.
+#[export_name = "application:ensure_all_started/2"]
+pub extern "C-unwind" fn ensure_all_started2(
+    app: OpaqueTerm,
+    restart_type: OpaqueTerm,
+) -> ErlangResult {
+    let Term::Atom(name) = app.into() else {
+        return badarg(Trace::capture());
+    };
+    let Some(restart_type) = parse_restart_type(restart_type) else {
+        return badarg(Trace::capture());
+    };
+    let mut started = vec![];
+    let mut visiting = vec![];
+    match start_all(name, restart_type, &mut started, &mut visiting)? {
+        Ok(()) => with_process(|proc| {
+            let started: Vec<OpaqueTerm> = started.iter().map(|app| (*app).into()).collect();
+            let list = make_list(proc, started.as_slice());
+            ErlangResult::Ok(make_tuple(proc, &[atoms::Ok.into(), list]))
+        }),
+        Err((app, reason)) => error(with_process(|proc| make_tuple(proc, &[app.into(), reason]))),
+    }
+}
+
+#[export_name = "application:stop/1"]
+pub extern "C-unwind" fn stop1(app: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(name) = app.into() else {
+        return badarg(Trace::capture());
+    };
+    let running = {
+        let mut controller = controller();
+        let index = controller.running.iter().position(|app| app.name == name);
+        match index {
+            Some(index) => controller.running.remove(index),
+            None => return error(reason2("not_started", name.into())),
+        }
+    };
+    if let Some(module) = running.module {
+        let state = match call_if_exported(module, "prep_stop", &[running.state])? {
+            Some(state) => state,
+            None => running.state,
+        };
+        call_if_exported(module, "stop", &[state])?;
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Returns `[{Name, Description, Vsn}]` for all running applications, most recently started first
+#[export_name = "application:which_applications/0"]
+pub extern "C-unwind" fn which_applications0() -> ErlangResult {
+    let controller = controller();
+    let apps = controller
+        .running
+        .iter()
+        .rev()
+        .map(|app| (app.name, &controller.loaded[&app.name]))
+        .collect::<Vec<_>>();
+    ErlangResult::Ok(describe(apps.as_slice()))
+}
+
+/// Returns `[{Name, Description, Vsn}]` for all loaded applications
+#[export_name = "application:loaded_applications/0"]
+pub extern "C-unwind" fn loaded_applications0() -> ErlangResult {
+    let controller = controller();
+    let apps = controller
+        .loaded
+        .iter()
+        .map(|(name, spec)| (*name, spec))
+        .collect::<Vec<_>>();
+    ErlangResult::Ok(describe(apps.as_slice()))
+}
+
+#[export_name = "application:get_env/2"]
+pub extern "C-unwind" fn get_env2(app: OpaqueTerm, key: OpaqueTerm) -> ErlangResult {
+    let (Term::Atom(app), Term::Atom(key)) = (app.into(), key.into()) else {
+        return badarg(Trace::capture());
+    };
+    let value = controller().env.get(&(app, key)).copied();
+    match value {
+        Some(value) => ErlangResult::Ok(with_process(|proc| {
+            make_tuple(proc, &[atoms::Ok.into(), value])
+        })),
+        None => ErlangResult::Ok(atoms::Undefined.into()),
+    }
+}
+
+#[export_name = "application:get_env/3"]
+pub extern "C-unwind" fn get_env3(
+    app: OpaqueTerm,
+    key: OpaqueTerm,
+    default: OpaqueTerm,
+) -> ErlangResult {
+    let (Term::Atom(app), Term::Atom(key)) = (app.into(), key.into()) else {
+        return badarg(Trace::capture());
+    };
+    ErlangResult::Ok(
+        controller()
+            .env
+            .get(&(app, key))
+            .copied()
+            .unwrap_or(default),
+    )
+}
+
+#[export_name = "application:get_all_env/1"]
+pub extern "C-unwind" fn get_all_env1(app: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(app) = app.into() else {
+        return badarg(Trace::capture());
+    };
+    let env = controller()
+        .env
+        .iter()
+        .filter(|((a, _), _)| *a == app)
+        .map(|((_, key), value)| (*key, *value))
+        .collect::<Vec<_>>();
+    with_process(|proc| {
+        let pairs = env
+            .iter()
+            .map(|(key, value)| make_tuple(proc, &[(*key).into(), *value]))
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(make_list(proc, pairs.as_slice()))
+    })
+}
+
+#[export_name = "application:set_env/3"]
+pub extern "C-unwind" fn set_env3(
+    app: OpaqueTerm,
+    key: OpaqueTerm,
+    value: OpaqueTerm,
+) -> ErlangResult {
+    set_env4(app, key, value, OpaqueTerm::NIL)
+}
+
+/// Sets the value of an application environment parameter
+///
+/// With `{persistent, true}`, the value is kept when the configuration is reloaded, see
+/// `super::firefly_config`. The `timeout` option is accepted for compatibility, but has no effect,
+/// as there is no application controller process to time out.
+#[export_name = "application:set_env/4"]
+pub extern "C-unwind" fn set_env4(
+    app: OpaqueTerm,
+    key: OpaqueTerm,
+    value: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    let (Term::Atom(app), Term::Atom(key)) = (app.into(), key.into()) else {
+        return badarg(Trace::capture());
+    };
+    let Some(persistent) = persistent(opts) else {
+        return badarg(Trace::capture());
+    };
+    let mut controller = controller();
+    controller.env.insert((app, key), make_global(value));
+    if persistent {
+        controller.persistent.insert((app, key));
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "application:unset_env/2"]
+pub extern "C-unwind" fn unset_env2(app: OpaqueTerm, key: OpaqueTerm) -> ErlangResult {
+    unset_env3(app, key, OpaqueTerm::NIL)
+}
+
+/// Removes an application environment parameter, which stays removed when the configuration is
+/// reloaded if `{persistent, true}` is given
+#[export_name = "application:unset_env/3"]
+pub extern "C-unwind" fn unset_env3(
+    app: OpaqueTerm,
+    key: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    let (Term::Atom(app), Term::Atom(key)) = (app.into(), key.into()) else {
+        return badarg(Trace::capture());
+    };
+    let Some(persistent) = persistent(opts) else {
+        return badarg(Trace::capture());
+    };
+    let mut controller = controller();
+    controller.env.remove(&(app, key));
+    if persistent {
+        controller.persistent.insert((app, key));
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Returns whether the options of `set_env/4` or `unset_env/3` ask for the change to persist
+fn persistent(opts: OpaqueTerm) -> Option<bool> {
+    let mut persistent = false;
+    for opt in list_to_vec(opts)? {
+        match tuple_elements(opt)? {
+            [key, value] if is_atom(*key, "persistent") => match (*value).into() {
+                Term::Bool(value) => persistent = value,
+                _ => return None,
+            },
+            [key, value] if is_atom(*key, "timeout") => {
+                timeout_ms(*value)?;
+            }
+            _ => return None,
+        }
+    }
+    Some(persistent)
+}
+
+/// The changes made to the environment of an application by [`replace_env`], as passed to
+/// `Mod:config_change/3`
+pub(super) struct EnvChange {
+    pub app: Atom,
+    /// The callback module of the application, if it is running
+    pub module: Option<Atom>,
+    pub changed: Vec<(Atom, OpaqueTerm)>,
+    pub new: Vec<(Atom, OpaqueTerm)>,
+    pub removed: Vec<Atom>,
+}
+
+/// Replaces the environment of each application in `config` with the defaults of its resource,
+/// overridden by the values given, returning the changes made to each
+///
+/// The environment of every application is replaced at once, so no process sees the environment
+/// of one application reloaded and that of another not yet. Parameters set or unset with
+/// `{persistent, true}` keep their values, and applications not in `config` are left as they are.
+pub(super) fn replace_env(config: Vec<(Atom, Vec<(Atom, OpaqueTerm)>)>) -> Vec<EnvChange> {
+    let mut controller = controller();
+    let mut changes = vec![];
+    for (app, pairs) in config {
+        let old = controller
+            .env
+            .iter()
+            .filter(|((a, _), _)| *a == app)
+            .map(|((_, key), value)| (*key, *value))
+            .collect::<BTreeMap<_, _>>();
+        let mut env = controller
+            .loaded
+            .get(&app)
+            .map(|spec| spec.env.iter().copied().collect::<BTreeMap<_, _>>())
+            .unwrap_or_default();
+        env.extend(pairs);
+        let persistent = controller
+            .persistent
+            .iter()
+            .filter(|(a, _)| *a == app)
+            .map(|(_, key)| *key)
+            .collect::<Vec<_>>();
+        for key in persistent {
+            match old.get(&key) {
+                Some(value) => env.insert(key, *value),
+                None => env.remove(&key),
+            };
+        }
+
+        let mut change = EnvChange {
+            app,
+            module: controller
+                .running
+                .iter()
+                .find(|running| running.name == app)
+                .and_then(|running| running.module),
+            changed: vec![],
+            new: vec![],
+            removed: vec![],
+        };
+        for (key, value) in env.iter() {
+            match old.get(key) {
+                None => change.new.push((*key, *value)),
+                Some(previous) if !equals(*previous, *value) => change.changed.push((*key, *value)),
+                Some(_) => (),
+            }
+        }
+        change.removed = old
+            .keys()
+            .filter(|key| !env.contains_key(key))
+            .copied()
+            .collect();
+
+        for key in change.removed.iter() {
+            controller.env.remove(&(app, *key));
+        }
+        for (key, value) in change.changed.iter().chain(change.new.iter()) {
+            controller.env.insert((app, *key), make_global(*value));
+        }
+        changes.push(change);
+    }
+    changes
+}
+
+/// Runs the boot script bundled into this executable, if any, see `firefly_rt::boot`
+///
+/// The bundled configuration is applied first, taking precedence over the defaults in application
+/// resources. Then each application in the script is started, along with its dependencies, in
+/// order. If an application fails to start, this raises `exit` with `{boot_failed, {App, Reason}}`.
+pub(crate) fn boot() -> Result<(), NonNull<ErlangException>> {
+    let Some(script) = firefly_rt::boot::boot_script() else {
+        return Ok(());
+    };
+
+    if script.config {
+        let config =
+            call_if_exported(atom(BUNDLED_SPECS_MODULE), "config", &[])?.unwrap_or(OpaqueTerm::NIL);
+        if !apply_config(config) {
+            return Err(boot_failed(reason2("invalid_config", config)));
+        }
+    }
+
+    for (name, restart_type) in script.applications.iter() {
+        let name = atom(name.as_str());
+        let mut started = vec![];
+        let mut visiting = vec![];
+        if let Err((app, reason)) = start_all(name, *restart_type, &mut started, &mut visiting)? {
+            let reason = with_process(|proc| make_tuple(proc, &[app.into(), reason]));
+            return Err(boot_failed(reason));
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies configuration of the form `[{App, [{Key, Value}]}]`, returning false if it is malformed
+fn apply_config(config: OpaqueTerm) -> bool {
+    let Some(config) = parse_config(config) else {
+        return false;
+    };
+    let mut controller = controller();
+    for (name, pairs) in config {
+        for (key, value) in pairs {
+            controller.env.insert((name, key), make_global(value));
+        }
+    }
+    true
+}
+
+/// Configuration parameters, by application
+pub(super) type Config = Vec<(Atom, Vec<(Atom, OpaqueTerm)>)>;
+
+/// Parses configuration of the form `[{App, [{Key, Value}]}]`, returning `None` if it is malformed
+pub(super) fn parse_config(config: OpaqueTerm) -> Option<Config> {
+    let mut apps = vec![];
+    for app in list_to_vec(config)? {
+        let [name, pairs] = tuple_elements(app)? else {
+            return None;
+        };
+        let Term::Atom(name) = (*name).into() else {
+            return None;
+        };
+        let mut env = vec![];
+        for pair in list_to_vec(*pairs)? {
+            let [key, value] = tuple_elements(pair)? else {
+                return None;
+            };
+            let Term::Atom(key) = (*key).into() else {
+                return None;
+            };
+            env.push((key, *value));
+        }
+        apps.push((name, env));
+    }
+    Some(apps)
+}
+
+fn boot_failed(reason: OpaqueTerm) -> NonNull<ErlangException> {
+    let reason = reason2("boot_failed", reason);
+    ErlangException::new(atoms::Exit, reason.into(), Trace::capture()).into_raw()
+}
+
+/// Starts `name`, which must be loadable, and whose dependencies must already be running
+///
+/// Returns `Ok(Err(reason))` if the application could not be started, and `Err` if the start
+/// callback raised an exception.
+fn start(
+    name: Atom,
+    restart_type: RestartType,
+) -> Result<Result<(), OpaqueTerm>, NonNull<ErlangException>> {
+    let module = {
+        let mut controller = controller();
+        if controller.is_running(name) {
+            return Ok(Err(reason2("already_started", name.into())));
+        }
+        if !controller.loaded.contains_key(&name) && !controller.load(name) {
+            return Ok(Err(not_found(name)));
+        }
+        let spec = &controller.loaded[&name];
+        if let Some(dep) = spec
+            .applications
+            .iter()
+            .copied()
+            .find(|dep| !controller.is_running(*dep))
+        {
+            return Ok(Err(reason2("not_started", dep.into())));
+        }
+        spec.module
+    };
+
+    let (supervisor, state) = match module {
+        None => (None, OpaqueTerm::NIL),
+        Some((module, args)) => match call_start(module, args)? {
+            Ok((supervisor, state)) => (super::process::local_pid(supervisor), make_global(state)),
+            Err(reason) => return Ok(Err(reason)),
+        },
+    };
+
+    controller().running.push(RunningApp {
+        name,
+        module: module.map(|(m, _)| m),
+        state,
+        supervisor,
+        restart_type,
+    });
+    Ok(Ok(()))
+}
+
+/// Called by `super::gen` when the server `pid` terminates with `reason`, which terminates the
+/// application of which it is the top-level supervisor, if any
+///
+/// The system is halted if the application is `permanent`, or if it is `transient` and `reason`
+/// is abnormal, see `crate::halt`.
+pub(super) fn terminated(pid: ProcessId, reason: OpaqueTerm) {
+    let app = {
+        let mut controller = controller();
+        let index = controller
+            .running
+            .iter()
+            .position(|app| app.supervisor == Some(pid));
+        match index {
+            Some(index) => controller.running.remove(index),
+            None => return,
+        }
+    };
+    eprintln!(
+        "=INFO REPORT====\n    application: {}\n    exited: {}\n    type: {}",
+        app.name.as_str(),
+        Term::from(reason),
+        app.restart_type
+    );
+    let halt = match app.restart_type {
+        RestartType::Permanent => true,
+        RestartType::Transient => !super::gen::is_normal_exit(reason),
+        RestartType::Temporary => false,
+    };
+    if halt {
+        crate::halt::request(Request {
+            status: Status::Slogan(format!(
+                "Kernel pid terminated (application_controller) \
+                 ({{application_terminated,{},{}}})",
+                app.name.as_str(),
+                Term::from(reason)
+            )),
+            flush: true,
+            flush_timeout: None,
+        });
+    }
+}
+
+/// Starts `name` and its dependencies, recording the applications started in `started`
+fn start_all(
+    name: Atom,
+    restart_type: RestartType,
+    started: &mut Vec<Atom>,
+    visiting: &mut Vec<Atom>,
+) -> Result<Result<(), (Atom, OpaqueTerm)>, NonNull<ErlangException>> {
+    let deps = {
+        let mut controller = controller();
+        if controller.is_running(name) {
+            return Ok(Ok(()));
+        }
+        if !controller.loaded.contains_key(&name) && !controller.load(name) {
+            return Ok(Err((name, not_found(name))));
+        }
+        controller.loaded[&name].applications.clone()
+    };
+
+    if visiting.contains(&name) {
+        let cycle = with_process(|proc| {
+            let apps: Vec<OpaqueTerm> = visiting.iter().map(|app| (*app).into()).collect();
+            make_list(proc, apps.as_slice())
+        });
+        return Ok(Err((name, reason2("circular_dependencies", cycle))));
+    }
+    visiting.push(name);
+    for dep in deps {
+        if let Err(err) = start_all(dep, restart_type, started, visiting)? {
+            return Ok(Err(err));
+        }
+    }
+    visiting.pop();
+
+    match start(name, restart_type)? {
+        Ok(()) => {
+            started.push(name);
+            Ok(Ok(()))
+        }
+        Err(reason) => Ok(Err((name, reason))),
+    }
+}
+
+/// Invokes `Mod:start(normal, Args)`, returning the top-level supervisor and the application state
+/// on success
+fn call_start(
+    module: Atom,
+    args: OpaqueTerm,
+) -> Result<Result<(OpaqueTerm, OpaqueTerm), OpaqueTerm>, NonNull<ErlangException>> {
+    let normal: OpaqueTerm = atoms::Normal.into();
+    let mfa = with_process(|proc| {
+        let argv = make_list(proc, &[normal, args]);
+        make_tuple(proc, &[module.into(), atom("start").into(), argv])
+    });
+    let returned = match call_if_exported(module, "start", &[normal, args])? {
+        Some(returned) => returned,
+        None => return Ok(Err(reason2("undef", mfa))),
+    };
+    let ok: OpaqueTerm = atoms::Ok.into();
+    let error: OpaqueTerm = atoms::Error.into();
+    if let Term::Tuple(ptr) = returned.into() {
+        match unsafe { ptr.as_ref() }.as_slice() {
+            [tag, pid] if *tag == ok => return Ok(Ok((*pid, OpaqueTerm::NIL))),
+            [tag, pid, state] if *tag == ok => return Ok(Ok((*pid, *state))),
+            [tag, reason] if *tag == error => {
+                return Ok(Err(with_process(|proc| make_tuple(proc, &[*reason, mfa]))))
+            }
+            _ => (),
+        }
+    }
+    let bad_return = with_process(|proc| make_tuple(proc, &[mfa, returned]));
+    Ok(Err(reason2("bad_return", bad_return)))
+}
+
+/// Fetches the application resources bundled into this executable by the compiler, if any
+/// Returns the metadata of the build embedded in the executable by the compiler, if any, see
+/// `erlang:system_info(firefly_build)`
+pub(super) fn bundled_build_info() -> Option<OpaqueTerm> {
+    let mfa = ModuleFunctionArity::new(atom(BUNDLED_SPECS_MODULE), atom("build_info"), 0);
+    let callee = function::find_symbol(&mfa)?;
+    match unsafe { function::apply_callee(callee, &[]) } {
+        ErlangResult::Ok(info) => Some(info),
+        ErlangResult::Err(_) => None,
+    }
+}
+
+fn bundled_specs() -> Vec<OpaqueTerm> {
+    let mfa = ModuleFunctionArity::new(atom(BUNDLED_SPECS_MODULE), atom("specs"), 0);
+    let Some(callee) = function::find_symbol(&mfa) else {
+        return vec![];
+    };
+    match unsafe { function::apply_callee(callee, &[]) } {
+        ErlangResult::Ok(specs) => list_to_vec(specs).unwrap_or_default(),
+        ErlangResult::Err(_) => vec![],
+    }
+}
+
+fn spec_name(spec: OpaqueTerm) -> Option<Atom> {
+    let Term::Tuple(ptr) = spec.into() else {
+        return None;
+    };
+    match unsafe { ptr.as_ref() }.as_slice() {
+        [tag, name, _] => match ((*tag).into(), (*name).into()) {
+            (Term::Atom(tag), Term::Atom(name)) if tag.as_str() == "application" => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parses an `{application, Name, Props}` term, returning `None` if it is malformed
+fn parse_spec(spec: OpaqueTerm) -> Option<(Atom, AppSpec)> {
+    let name = spec_name(spec)?;
+    let Term::Tuple(ptr) = spec.into() else {
+        return None;
+    };
+    let props = list_to_vec(unsafe { ptr.as_ref() }.as_slice()[2])?;
+
+    let mut app = AppSpec {
+        description: String::new(),
+        vsn: String::new(),
+        applications: vec![],
+        module: None,
+        env: vec![],
+    };
+    for prop in props {
+        let Term::Tuple(ptr) = prop.into() else {
+            return None;
+        };
+        let [key, value] = unsafe { ptr.as_ref() }.as_slice() else {
+            return None;
+        };
+        let Term::Atom(key) = (*key).into() else {
+            return None;
+        };
+        match key.as_str() {
+            "description" => app.description = charlist_to_string(*value)?,
+            "vsn" => app.vsn = charlist_to_string(*value)?,
+            "applications" => {
+                for dep in list_to_vec(*value)? {
+                    let Term::Atom(dep) = dep.into() else {
+                        return None;
+                    };
+                    app.applications.push(dep);
+                }
+            }
+            "mod" => {
+                let Term::Tuple(ptr) = (*value).into() else {
+                    return None;
+                };
+                let [module, args] = unsafe { ptr.as_ref() }.as_slice() else {
+                    return None;
+                };
+                let Term::Atom(module) = (*module).into() else {
+                    return None;
+                };
+                app.module = Some((module, make_global(*args)));
+            }
+            "env" => {
+                for pair in list_to_vec(*value)? {
+                    let Term::Tuple(ptr) = pair.into() else {
+                        return None;
+                    };
+                    let [key, value] = unsafe { ptr.as_ref() }.as_slice() else {
+                        return None;
+                    };
+                    let Term::Atom(key) = (*key).into() else {
+                        return None;
+                    };
+                    app.env.push((key, make_global(*value)));
+                }
+            }
+            _ => continue,
+        }
+    }
+    Some((name, app))
+}
+
+fn parse_restart_type(term: OpaqueTerm) -> Option<RestartType> {
+    match term.into() {
+        Term::Atom(a) => a.as_str().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Builds `[{Name, Description, Vsn}]` for the given applications
+fn describe(apps: &[(Atom, &AppSpec)]) -> OpaqueTerm {
+    with_process(|proc| {
+        let described = apps
+            .iter()
+            .map(|(name, spec)| {
+                let description = charlist(proc, spec.description.as_str());
+                let vsn = charlist(proc, spec.vsn.as_str());
+                make_tuple(proc, &[(*name).into(), description, vsn])
+            })
+            .collect::<Vec<_>>();
+        make_list(proc, described.as_slice())
+    })
+}
+
+/// The reason returned when no resource can be found for an application, as in OTP
+fn not_found(name: Atom) -> OpaqueTerm {
+    with_process(|proc| {
+        let message = charlist(proc, "no such file or directory");
+        let file = charlist(proc, &format!("{}.app", name.as_str()));
+        make_tuple(proc, &[message, file])
+    })
+}
+
+fn error(reason: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atoms::Error.into(), reason])
+    }))
+}
@@ -0,0 +1,239 @@
+//! The BIFs of the `atomics` module, see `firefly_rt::atomics` for the arrays themselves, and for
+//! sharing them with Rust code.
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use firefly_number::ToPrimitive;
+use firefly_rt::atomics::Atomics;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use super::badarg;
+use super::util::*;
+
+#[export_name = "atomics:new/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn new2(arity: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let (Term::Int(arity), Some(options)) = (arity.into(), list_to_vec(options)) else {
+        return badarg(Trace::capture());
+    };
+    if arity < 1 {
+        return badarg(Trace::capture());
+    }
+    let mut signed = true;
+    for option in options {
+        match tuple_elements(option) {
+            Some([key, value]) if is_atom(*key, "signed") => match (*value).into() {
+                Term::Bool(value) => signed = value,
+                _ => return badarg(Trace::capture()),
+            },
+            _ => return badarg(Trace::capture()),
+        }
+    }
+
+    let atomics = Atomics::new(arity as usize, signed);
+    ErlangResult::Ok(with_process(|proc| atomics.into_term(proc).unwrap().into()))
+}
+
+#[export_name = "atomics:put/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn put3(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    value: OpaqueTerm,
+) -> ErlangResult {
+    let Some((atomics, ix, value)) = args_with_value(atomics, ix, value) else {
+        return badarg(Trace::capture());
+    };
+    atomics.as_slice()[ix].store(value, Ordering::SeqCst);
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "atomics:get/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get2(atomics: OpaqueTerm, ix: OpaqueTerm) -> ErlangResult {
+    let Some((atomics, ix)) = args(atomics, ix) else {
+        return badarg(Trace::capture());
+    };
+    let value = atomics.as_slice()[ix].load(Ordering::SeqCst);
+    ErlangResult::Ok(with_process(|proc| make_value(proc, atomics.decode(value))))
+}
+
+#[export_name = "atomics:add/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn add3(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    incr: OpaqueTerm,
+) -> ErlangResult {
+    match update(atomics, ix, incr, AtomicI64::fetch_add) {
+        Some(_) => ErlangResult::Ok(atoms::Ok.into()),
+        None => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "atomics:add_get/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn add_get3(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    incr: OpaqueTerm,
+) -> ErlangResult {
+    match update(atomics, ix, incr, AtomicI64::fetch_add) {
+        Some((atomics, previous, incr)) => ErlangResult::Ok(with_process(|proc| {
+            make_value(proc, atomics.decode(previous.wrapping_add(incr)))
+        })),
+        None => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "atomics:sub/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sub3(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    decr: OpaqueTerm,
+) -> ErlangResult {
+    match update(atomics, ix, decr, AtomicI64::fetch_sub) {
+        Some(_) => ErlangResult::Ok(atoms::Ok.into()),
+        None => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "atomics:sub_get/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sub_get3(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    decr: OpaqueTerm,
+) -> ErlangResult {
+    match update(atomics, ix, decr, AtomicI64::fetch_sub) {
+        Some((atomics, previous, decr)) => ErlangResult::Ok(with_process(|proc| {
+            make_value(proc, atomics.decode(previous.wrapping_sub(decr)))
+        })),
+        None => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "atomics:exchange/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn exchange3(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    desired: OpaqueTerm,
+) -> ErlangResult {
+    let Some((atomics, ix, desired)) = args_with_value(atomics, ix, desired) else {
+        return badarg(Trace::capture());
+    };
+    let previous = atomics.as_slice()[ix].swap(desired, Ordering::SeqCst);
+    ErlangResult::Ok(with_process(|proc| {
+        make_value(proc, atomics.decode(previous))
+    }))
+}
+
+#[export_name = "atomics:compare_exchange/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compare_exchange4(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    expected: OpaqueTerm,
+    desired: OpaqueTerm,
+) -> ErlangResult {
+    let Some((atomics, ix, desired)) = args_with_value(atomics, ix, desired) else {
+        return badarg(Trace::capture());
+    };
+    let Some(expected) = to_integer(expected).and_then(|value| atomics.encode(value)) else {
+        return badarg(Trace::capture());
+    };
+    match atomics.as_slice()[ix].compare_exchange(
+        expected,
+        desired,
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    ) {
+        Ok(_) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(actual) => ErlangResult::Ok(with_process(|proc| {
+            make_value(proc, atomics.decode(actual))
+        })),
+    }
+}
+
+#[export_name = "atomics:info/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn info1(atomics: OpaqueTerm) -> ErlangResult {
+    let Some(atomics) = Atomics::from_term(atomics.into()) else {
+        return badarg(Trace::capture());
+    };
+    ErlangResult::Ok(with_process(|proc| {
+        let memory = atomics.len() * core::mem::size_of::<AtomicI64>();
+        let items = [
+            ("size", make_value(proc, atomics.len() as i128)),
+            ("max", make_value(proc, atomics.max())),
+            ("min", make_value(proc, atomics.min())),
+            ("memory", make_value(proc, memory as i128)),
+        ];
+        let items = items
+            .into_iter()
+            .map(|(key, value)| (Term::Atom(atom(key)), value.into()));
+        Map::new_from_iter_in(items, proc).unwrap().into()
+    }))
+}
+
+/// Resolves an array and a one-based index into it
+fn args(atomics: OpaqueTerm, ix: OpaqueTerm) -> Option<(Atomics, usize)> {
+    let atomics = Atomics::from_term(atomics.into())?;
+    let Term::Int(ix) = ix.into() else {
+        return None;
+    };
+    if ix < 1 || ix as usize > atomics.len() {
+        return None;
+    }
+    Some((atomics, ix as usize - 1))
+}
+
+/// As `args`, but also converts `value` to the representation used by the array
+fn args_with_value(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    value: OpaqueTerm,
+) -> Option<(Atomics, usize, i64)> {
+    let (atomics, ix) = args(atomics, ix)?;
+    let value = atomics.encode(to_integer(value)?)?;
+    Some((atomics, ix, value))
+}
+
+/// Applies `op` with `operand` to the given element, returning the previous value
+///
+/// As with BEAM, the operand may be any 64-bit integer, and the result wraps on overflow.
+fn update(
+    atomics: OpaqueTerm,
+    ix: OpaqueTerm,
+    operand: OpaqueTerm,
+    op: fn(&AtomicI64, i64, Ordering) -> i64,
+) -> Option<(Atomics, i64, i64)> {
+    let (atomics, ix) = args(atomics, ix)?;
+    let operand = to_integer(operand)?;
+    if operand < i64::MIN as i128 || operand > u64::MAX as i128 {
+        return None;
+    }
+    let operand = operand as i64;
+    let previous = op(&atomics.as_slice()[ix], operand, Ordering::SeqCst);
+    Some((atomics, previous, operand))
+}
+
+fn to_integer(term: OpaqueTerm) -> Option<i128> {
+    match term.into() {
+        Term::Int(i) => Some(i as i128),
+        Term::BigInt(i) => i.to_i128(),
+        _ => None,
+    }
+}
+
+fn make_value(proc: &Process, value: i128) -> OpaqueTerm {
+    let integer = match i64::try_from(value) {
+        Ok(value) => Integer::from(value),
+        Err(_) => Integer::from(value as u64),
+    };
+    make_integer(proc, integer)
+}
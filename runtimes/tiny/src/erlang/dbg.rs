@@ -0,0 +1,692 @@
+//! A native implementation of the `dbg` module, the text based front end of `erlang:trace/3` and
+//! `erlang:trace_pattern/3`, see `super::trace`.
+//!
+//! The tracer is started with one of:
+//!
+//! * `tracer()` - a server printing each trace message to standard output, as in OTP
+//! * `tracer(process, {HandlerFun, Data})` - a server calling `HandlerFun(TraceMsg, Data)` for each
+//! trace message, whose result is the `Data` of the next call
+//! * `tracer(port, Port)` - a port, e.g. of a trace driver, which is sent each trace message in the
+//! external term format
+//! * `tracer(port, trace_port(file, Filename))` - a trace file, see below
+//!
+//! The server is registered as `dbg`, and is an in-process server, see `super::gen`, so the handler
+//! is called by the traced process itself. Its own events are not traced, so that a handler which
+//! sends messages does not trace them, forever.
+//!
+//! `p/1,2` trace processes, starting the printing tracer if none is started, with the flags `s`
+//! (`send`), `r` (`'receive'`), `m` (both), `c` (`call`), `p` (`procs`), `sos` (`set_on_spawn`),
+//! `garbage_collection`, `timestamp`, `monotonic_timestamp`, `all` and `clear`. `tp/2,3,4` and
+//! `tpl/2,3,4` set trace patterns, which are the same here, with a match specification or the
+//! shorthand `x` for `exception_trace`, and `ctp/0,1,2,3` and `ctpl/0,1,2,3` clear them. `stop/0`
+//! clears every trace flag and pattern and stops the tracer.
+//!
+//! A trace file is written as trace files are by the `trace_file_drv` of OTP, each trace message
+//! encoded in the external term format and preceded by a zero byte and its size as a big-endian
+//! 32-bit integer, so that it can be read by `dbg:trace_client/2` of OTP as well as that of this
+//! runtime, and by `firefly trace-dump`. Trace messages are encoded directly from the heap they are
+//! built on, and written through a buffer, which is flushed by `flush_trace_port/0`, `stop/0`, and
+//! when the runtime shuts down. `trace_client(file, Filename)` and `trace_client(file, Filename,
+//! {HandlerFun, Data})` read a trace file, printing or handling each of its messages as a tracer
+//! would, but do so before returning `ok`, rather than in a process of their own.
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::file::{posix, to_path};
+use super::gen;
+use super::trace::{self, Tracer};
+use super::util::*;
+
+/// The name of the tracer server, and of the callback module implementing it
+const SERVER: &str = "dbg";
+/// The tag of the term returned by `trace_port(file, Filename)`
+const TRACE_FILE: &str = "$dbg_trace_file";
+/// The size of the buffer trace messages are written to a trace file through
+const BUFFER_SIZE: usize = 64 * 1024;
+
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+#[derive(Default)]
+struct State {
+    tracer: Option<Tracer>,
+    file: Option<TraceFile>,
+}
+
+/// A trace file being written, see `trace_port/2`
+struct TraceFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// The trace message being written, kept to reuse its allocation
+    encoded: Vec<u8>,
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.get_or_init(Default::default).lock().unwrap()
+}
+
+#[export_name = "dbg:tracer/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tracer0() -> ErlangResult {
+    start_server(atom("print").into(), atom("user").into())
+}
+
+#[export_name = "dbg:tracer/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tracer2(kind: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    if is_atom(kind, "process") {
+        let Some([handler, data]) = tuple_elements(data) else {
+            return super::badarg(Trace::capture());
+        };
+        return match (*handler).into() {
+            Term::Closure(fun) if fun.arity == 2 => start_server(*handler, *data),
+            _ => super::badarg(Trace::capture()),
+        };
+    }
+    if !is_atom(kind, "port") {
+        return super::badarg(Trace::capture());
+    }
+    if already_started() {
+        return already_started_error();
+    }
+    match tuple_elements(data) {
+        Some([tag, filename]) if is_atom(*tag, TRACE_FILE) => {
+            let Some(path) = to_path(*filename) else { return super::badarg(Trace::capture()); };
+            match File::create(&path) {
+                Ok(file) => {
+                    let mut state = state();
+                    state.file = Some(TraceFile {
+                        path,
+                        writer: BufWriter::with_capacity(BUFFER_SIZE, file),
+                        encoded: Vec::new(),
+                    });
+                    state.tracer = Some(Tracer::File);
+                    crate::halt::on_halt("dbg", flush_on_halt);
+                }
+                Err(err) => return error(atom(posix(&err)).into()),
+            }
+        }
+        _ => match data.into() {
+            Term::Port(port) => match *port {
+                Port::Local { id } => state().tracer = Some(Tracer::Port(id)),
+                Port::External { .. } => return super::badarg(Trace::capture()),
+            },
+            _ => return super::badarg(Trace::capture()),
+        },
+    }
+    get_tracer0()
+}
+
+/// Returns the term `tracer(port, _)` takes to write trace messages to `Filename`
+///
+/// Only `file` trace ports are supported, not `ip`, as there is no distribution to serve them to.
+#[export_name = "dbg:trace_port/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn trace_port2(kind: OpaqueTerm, filename: OpaqueTerm) -> ErlangResult {
+    if !is_atom(kind, "file") || to_path(filename).is_none() {
+        return super::badarg(Trace::capture());
+    }
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atom(TRACE_FILE).into(), filename])
+    }))
+}
+
+#[export_name = "dbg:get_tracer/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_tracer0() -> ErlangResult {
+    let (tracer, path) = {
+        let state = state();
+        (
+            state.tracer,
+            state.file.as_ref().map(|file| file.path.clone()),
+        )
+    };
+    let tracer = match (tracer, path) {
+        (Some(Tracer::Process(pid)), _) if gen::module(pid).is_some() => {
+            with_process(|proc| make_pid(proc, pid))
+        }
+        (Some(Tracer::Port(port)), _) => with_process(|proc| make_port(proc, port)),
+        (Some(Tracer::File), Some(path)) => with_process(|proc| {
+            let filename = charlist(proc, &path.to_string_lossy());
+            make_tuple(proc, &[atom("file").into(), filename])
+        }),
+        _ => return error(atom("no_tracer_on_this_node").into()),
+    };
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atoms::Ok.into(), tracer])
+    }))
+}
+
+#[export_name = "dbg:p/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn p1(item: OpaqueTerm) -> ErlangResult {
+    p2(item, atom("m").into())
+}
+
+/// Traces the processes `Item` with `Flags`, a flag or a list of them, starting the printing tracer
+/// if none is started
+#[export_name = "dbg:p/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn p2(item: OpaqueTerm, flags: OpaqueTerm) -> ErlangResult {
+    let flags = match flags.into() {
+        Term::Atom(_) => vec![flags],
+        _ => match list_to_vec(flags) {
+            Some(flags) => flags,
+            None => return super::badarg(Trace::capture()),
+        },
+    };
+    let mut set = 0;
+    let mut clear = false;
+    for flag in flags {
+        let Term::Atom(name) = flag.into() else { return super::badarg(Trace::capture()); };
+        match name.as_str() {
+            "clear" => clear = true,
+            name => match parse_flag(name) {
+                Some(bits) => set |= bits,
+                None => return error(tagged("bad_flag", flag)),
+            },
+        }
+    }
+    // A registered server may be given by name
+    let item = match item.into() {
+        Term::Atom(name) if is_pid_spec(name.as_str()) => item,
+        Term::Atom(_) => match gen::whereis(item) {
+            Some(pid) => with_process(|proc| make_pid(proc, pid)),
+            None => return error(tagged("no_process", item)),
+        },
+        _ => item,
+    };
+
+    let count = if clear {
+        trace::set_flags(item, false, trace::flag("all").unwrap(), None)
+    } else {
+        let tracer = match current_tracer() {
+            Some(tracer) => tracer,
+            None => {
+                tracer0()?;
+                current_tracer().unwrap()
+            }
+        };
+        trace::set_flags(item, true, set, Some(tracer))
+    };
+    match count {
+        Some(count) => matched(count),
+        None => error(tagged("no_process", item)),
+    }
+}
+
+#[export_name = "dbg:tp/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tp2(module: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    tpl2(module, spec)
+}
+
+#[export_name = "dbg:tp/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tp3(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    spec: OpaqueTerm,
+) -> ErlangResult {
+    tpl3(module, function, spec)
+}
+
+#[export_name = "dbg:tp/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tp4(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    arity: OpaqueTerm,
+    spec: OpaqueTerm,
+) -> ErlangResult {
+    tpl4(module, function, arity, spec)
+}
+
+/// Sets the trace pattern of the functions of `Module`, or of `{Module, Function, Arity}`
+#[export_name = "dbg:tpl/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tpl2(module: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    if tuple_elements(module).is_some() {
+        return pattern(module, spec);
+    }
+    tpl4(module, wildcard(), wildcard(), spec)
+}
+
+#[export_name = "dbg:tpl/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tpl3(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    spec: OpaqueTerm,
+) -> ErlangResult {
+    tpl4(module, function, wildcard(), spec)
+}
+
+#[export_name = "dbg:tpl/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tpl4(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    arity: OpaqueTerm,
+    spec: OpaqueTerm,
+) -> ErlangResult {
+    let mfa = with_process(|proc| make_tuple(proc, &[module, function, arity]));
+    pattern(mfa, spec)
+}
+
+#[export_name = "dbg:ctp/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ctp0() -> ErlangResult {
+    ctpl0()
+}
+
+#[export_name = "dbg:ctp/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ctp1(module: OpaqueTerm) -> ErlangResult {
+    ctpl1(module)
+}
+
+#[export_name = "dbg:ctp/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ctp2(module: OpaqueTerm, function: OpaqueTerm) -> ErlangResult {
+    ctpl2(module, function)
+}
+
+#[export_name = "dbg:ctp/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ctp3(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    arity: OpaqueTerm,
+) -> ErlangResult {
+    ctpl3(module, function, arity)
+}
+
+#[export_name = "dbg:ctpl/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ctpl0() -> ErlangResult {
+    ctpl3(wildcard(), wildcard(), wildcard())
+}
+
+/// Clears the trace patterns of the functions of `Module`, or of `{Module, Function, Arity}`
+#[export_name = "dbg:ctpl/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ctpl1(module: OpaqueTerm) -> ErlangResult {
+    if tuple_elements(module).is_some() {
+        return pattern(module, false.into());
+    }
+    ctpl3(module, wildcard(), wildcard())
+}
+
+#[export_name = "dbg:ctpl/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ctpl2(module: OpaqueTerm, function: OpaqueTerm) -> ErlangResult {
+    ctpl3(module, function, wildcard())
+}
+
+#[export_name = "dbg:ctpl/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ctpl3(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    arity: OpaqueTerm,
+) -> ErlangResult {
+    tpl4(module, function, arity, false.into())
+}
+
+/// Flushes the trace file, if one is being written
+#[export_name = "dbg:flush_trace_port/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn flush_trace_port0() -> ErlangResult {
+    match flush() {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => error(atom(posix(&err)).into()),
+    }
+}
+
+/// Clears every trace flag and pattern, and stops the tracer
+#[export_name = "dbg:stop/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn stop0() -> ErlangResult {
+    let all = trace::flag("all").unwrap();
+    trace::set_flags(atom("all").into(), false, all, None);
+    ctpl0()?;
+    let (tracer, file) = {
+        let mut state = state();
+        (state.tracer.take(), state.file.take())
+    };
+    if let Some(mut file) = file {
+        let _ = file.writer.flush();
+    }
+    if let Some(Tracer::Process(pid)) = tracer {
+        gen::shutdown(pid, atoms::Normal.into());
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "dbg:trace_client/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn trace_client2(kind: OpaqueTerm, filename: OpaqueTerm) -> ErlangResult {
+    let handler =
+        with_process(|proc| make_tuple(proc, &[atom("print").into(), atom("user").into()]));
+    trace_client3(kind, filename, handler)
+}
+
+/// Reads the trace file `Filename`, calling `HandlerFun(TraceMsg, Data)` for each of its messages,
+/// and `HandlerFun(end_of_trace, Data)` once there are no more
+#[export_name = "dbg:trace_client/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn trace_client3(
+    kind: OpaqueTerm,
+    filename: OpaqueTerm,
+    handler: OpaqueTerm,
+) -> ErlangResult {
+    let Some([handler, data]) = tuple_elements(handler) else {
+        return super::badarg(Trace::capture());
+    };
+    let valid = match (*handler).into() {
+        Term::Closure(fun) => fun.arity == 2,
+        _ => is_atom(*handler, "print"),
+    };
+    let path = to_path(filename).filter(|_| valid && is_atom(kind, "file"));
+    let Some(path) = path else { return super::badarg(Trace::capture()); };
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) => return error(atom(posix(&err)).into()),
+    };
+
+    let mut data = *data;
+    let mut rest = bytes.as_slice();
+    while let Some((message, next)) = next_message(rest) {
+        let Ok(message) = with_process(|proc| firefly_rt::etf::decode(message, proc)) else {
+            break;
+        };
+        data = handle(*handler, message.0.into(), data)?;
+        rest = next;
+    }
+    handle(*handler, atom("end_of_trace").into(), data)?;
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Initializes the tracer server, whose state is `{HandlerFun, Data}`
+#[export_name = "dbg:init/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn init1(state: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atoms::Ok.into(), state])
+    }))
+}
+
+/// Handles a trace message sent to the tracer server
+#[export_name = "dbg:handle_info/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn handle_info2(message: OpaqueTerm, state: OpaqueTerm) -> ErlangResult {
+    let Some([handler, data]) = tuple_elements(state) else {
+        return super::badarg(Trace::capture());
+    };
+    let data = handle(*handler, message, *data)?;
+    ErlangResult::Ok(with_process(|proc| {
+        let state = make_tuple(proc, &[*handler, data]);
+        make_tuple(proc, &[atom("noreply").into(), state])
+    }))
+}
+
+/// Writes `message`, built on a scratch heap, to the trace file, see `super::trace`
+pub(super) fn write(message: Term) {
+    let mut state = state();
+    let Some(file) = state.file.as_mut() else { return; };
+    file.encoded.clear();
+    if firefly_rt::etf::encode(message, &mut file.encoded).is_err() {
+        return;
+    }
+    let Ok(size) = u32::try_from(file.encoded.len()) else { return; };
+    let mut header = [0u8; 5];
+    header[1..].copy_from_slice(&size.to_be_bytes());
+    // A trace file which cannot be written is as a trace port which cannot keep up, the messages
+    // which do not fit are lost
+    let _ = file.writer.write_all(&header);
+    let _ = file.writer.write_all(file.encoded.as_slice());
+}
+
+/// Flushes the trace file, if one is being written, e.g. before the runtime exits
+pub(crate) fn flush() -> io::Result<()> {
+    let mut state = state();
+    state
+        .file
+        .as_mut()
+        .map_or(Ok(()), |file| file.writer.flush())
+}
+
+/// Flushes the trace file as the system halts, unless halting without flushing
+fn flush_on_halt(flush: bool) {
+    if flush {
+        let _ = self::flush();
+    }
+}
+
+/// Starts the tracer server, calling `handler` with `data` for each trace message
+fn start_server(handler: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    if already_started() {
+        return already_started_error();
+    }
+    let (name, args) = with_process(|proc| {
+        let name = make_tuple(proc, &[atom("local").into(), atom(SERVER).into()]);
+        (name, make_tuple(proc, &[handler, data]))
+    });
+    let result = super::gen_server::start4(name, atom(SERVER).into(), args, OpaqueTerm::NIL)?;
+    if let Some(pid) = gen::whereis(atom(SERVER).into()) {
+        state().tracer = Some(Tracer::Process(pid));
+    }
+    ErlangResult::Ok(result)
+}
+
+/// Passes `message` to `handler`, returning the data to pass with the next message
+fn handle(handler: OpaqueTerm, message: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    match handler.into() {
+        Term::Closure(fun) => trace::untraced(|| fun.apply(&[message, data])),
+        _ if is_atom(message, "end_of_trace") => ErlangResult::Ok(data),
+        _ => {
+            let line = format!("{}\n", Formatted(message.into()));
+            let _ = io::stdout().lock().write_all(line.as_bytes());
+            ErlangResult::Ok(data)
+        }
+    }
+}
+
+/// Returns the tracer, if it is a port or trace file, or a server which is still running
+fn current_tracer() -> Option<Tracer> {
+    let tracer = state().tracer;
+    match tracer {
+        Some(Tracer::Process(pid)) if gen::module(pid).is_none() => {
+            let mut state = state();
+            // The tracer may have been replaced in the meantime
+            if state.tracer == tracer {
+                state.tracer = None;
+            }
+            None
+        }
+        tracer => tracer,
+    }
+}
+
+fn already_started() -> bool {
+    current_tracer().is_some()
+}
+
+fn already_started_error() -> ErlangResult {
+    error(atom("already_started").into())
+}
+
+/// Parses a flag of `p/2`, returning the trace flags it stands for
+fn parse_flag(flag: &str) -> Option<u16> {
+    match flag {
+        "s" => trace::flag("send"),
+        "r" => trace::flag("receive"),
+        "m" => Some(trace::flag("send")? | trace::flag("receive")?),
+        "c" => trace::flag("call"),
+        "p" => trace::flag("procs"),
+        "sos" => trace::flag("set_on_spawn"),
+        flag => trace::flag(flag),
+    }
+}
+
+fn is_pid_spec(name: &str) -> bool {
+    matches!(
+        name,
+        "all" | "new" | "existing" | "processes" | "new_processes" | "existing_processes"
+    )
+}
+
+/// Sets the trace pattern of `{Module, Function, Arity}` to `spec`, or its shorthand
+fn pattern(mfa: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    let spec = match spec.into() {
+        Term::Atom(name) if name.as_str() == "x" || name.as_str() == "exception_trace" => {
+            with_process(|proc| {
+                let action = make_tuple(proc, &[atom("exception_trace").into()]);
+                let actions = make_list(proc, &[action]);
+                let clause = make_tuple(proc, &[wildcard(), OpaqueTerm::NIL, actions]);
+                make_list(proc, &[clause])
+            })
+        }
+        _ => spec,
+    };
+    let count = trace::trace_pattern3(mfa, spec, OpaqueTerm::NIL)?;
+    let Term::Int(count) = count.into() else {
+        unreachable!()
+    };
+    matched(count as usize)
+}
+
+/// Returns `{ok, [{matched, Node, Count}]}`, as the functions setting flags and patterns do
+fn matched(count: usize) -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        let count = Term::Int(count as i64).into();
+        let matched = make_tuple(
+            proc,
+            &[atom("matched").into(), super::spawn::local_node(), count],
+        );
+        let matched = make_list(proc, &[matched]);
+        make_tuple(proc, &[atoms::Ok.into(), matched])
+    }))
+}
+
+fn error(reason: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atoms::Error.into(), reason])
+    }))
+}
+
+fn tagged(tag: &str, value: OpaqueTerm) -> OpaqueTerm {
+    with_process(|proc| make_tuple(proc, &[atom(tag).into(), value]))
+}
+
+fn wildcard() -> OpaqueTerm {
+    atom("_").into()
+}
+
+/// Splits the next trace message off `bytes`, returning its encoding and the bytes following it
+///
+/// Messages dropped by a trace port which could not keep up are recorded as `<<1, Count:32>>`, and
+/// are skipped.
+fn next_message(mut bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    loop {
+        let (&tag, rest) = bytes.split_first()?;
+        let size = u32::from_be_bytes(rest.get(..4)?.try_into().unwrap()) as usize;
+        let rest = &rest[4..];
+        match tag {
+            0 if rest.len() >= size => return Some((&rest[..size], &rest[size..])),
+            1 => bytes = rest,
+            _ => return None,
+        }
+    }
+}
+
+/// A trace message, formatted as by the default handler of `dbg` in OTP
+struct Formatted(Term);
+impl std::fmt::Display for Formatted {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let Term::Tuple(ptr) = self.0 else { return write!(f, "{}", self.0); };
+        let elements = unsafe { ptr.as_ref() }.as_slice();
+        let elements = elements.iter().copied().map(Term::from).collect::<Vec<_>>();
+        let (elements, timestamp) = match elements.as_slice() {
+            [Term::Atom(kind), rest @ .., timestamp] if kind.as_str() == "trace_ts" => {
+                (rest, Some(timestamp))
+            }
+            [Term::Atom(kind), rest @ ..] if kind.as_str() == "trace" => (rest, None),
+            _ => return write!(f, "{}", self.0),
+        };
+        match elements {
+            [pid, Term::Atom(tag), info @ ..] => {
+                write!(f, "({}) ", pid)?;
+                match (tag.as_str(), info) {
+                    ("call", [call, rest @ ..]) => {
+                        write_call(f, call)?;
+                        for extra in rest {
+                            write!(f, " ({})", extra)?;
+                        }
+                    }
+                    ("return_from", [mfa, value]) => {
+                        write!(f, "returned from ")?;
+                        write_mfa(f, mfa)?;
+                        write!(f, " -> {}", value)?;
+                    }
+                    ("exception_from", [mfa, value]) => {
+                        write!(f, "exception_from ")?;
+                        write_mfa(f, mfa)?;
+                        write!(f, " {}", value)?;
+                    }
+                    ("send", [message, to]) => write!(f, "{} ! {}", to, message)?,
+                    ("receive", [message]) => write!(f, "<< {}", message)?,
+                    (tag, info) => {
+                        write!(f, "{}", tag)?;
+                        for (i, term) in info.iter().enumerate() {
+                            write!(f, "{} {}", if i == 0 { ":" } else { "," }, term)?;
+                        }
+                    }
+                }
+            }
+            _ => write!(f, "{}", self.0)?,
+        }
+        match timestamp {
+            Some(timestamp) => write!(f, " (Timestamp: {})", timestamp),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes `{M, F, Args}` as `M:F(Arg1, Arg2, ...)`
+fn write_call(f: &mut std::fmt::Formatter, call: &Term) -> std::fmt::Result {
+    let Term::Tuple(ptr) = call else { return write!(f, "{}", call); };
+    let [module, function, args] = unsafe { ptr.as_ref() }.as_slice() else {
+        return write!(f, "{}", call);
+    };
+    let Some(args) = list_to_vec(*args) else { return write!(f, "{}", call); };
+    write!(f, "{}:{}(", Term::from(*module), Term::from(*function))?;
+    for (i, arg) in args.iter().enumerate() {
+        let sep = if i == 0 { "" } else { "," };
+        write!(f, "{}{}", sep, Term::from(*arg))?;
+    }
+    write!(f, ")")
+}
+
+/// Writes `{M, F, A}` as `M:F/A`
+fn write_mfa(f: &mut std::fmt::Formatter, mfa: &Term) -> std::fmt::Result {
+    match mfa {
+        Term::Tuple(ptr) => match unsafe { ptr.as_ref() }.as_slice() {
+            [module, function, arity] => write!(
+                f,
+                "{}:{}/{}",
+                Term::from(*module),
+                Term::from(*function),
+                Term::from(*arity)
+            ),
+            _ => write!(f, "{}", mfa),
+        },
+        _ => write!(f, "{}", mfa),
+    }
+}
@@ -0,0 +1,951 @@
+//! A native implementation of the `disk_log` module, supporting halt and wrap logs of terms in the
+//! internal format.
+//!
+//! Each file of a log begins with a header recording whether it was closed properly. A log which
+//! was not, e.g. because the system crashed while it was open, is repaired when it is next opened:
+//! its items are checked, and the file is truncated at the first which is incomplete or corrupt.
+//! Items are framed by their size and a checksum, and contain a term in the external term format.
+//!
+//! A wrap log is a sequence of files named `File.1` to `File.N`, along with `File.idx`, which
+//! records the index of the file being written. When that file is full the log moves on to the next
+//! one, wrapping around to the first after the last, and discarding what was in it.
+//!
+//! Logs are not owned by the processes which open them, so they remain open until each of those has
+//! called `close/1`, or the system halts. Writes are made directly to the file, and are
+//! synchronous, as are those made by `alog/2` and `alog_terms/2`.
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::etf;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use super::file::{posix, to_path};
+use super::util::*;
+
+static LOGS: OnceLock<Mutex<BTreeMap<Atom, Log>>> = OnceLock::new();
+
+/// The bytes which begin every log file
+const MAGIC: &[u8; 8] = b"FFDLOG\x00\x01";
+/// Follows the magic bytes of a file while it is open
+const OPENED: &[u8; 4] = b"OPND";
+/// Follows the magic bytes of a file once it has been closed properly
+const CLOSED: &[u8; 4] = b"CLSD";
+const HEADER_BYTES: u64 = 12;
+/// Each item is preceded by its size and checksum
+const ITEM_HEADER_BYTES: u64 = 8;
+/// The number of bytes of items after which `chunk/2` returns what it has read so far
+const CHUNK_BYTES: u64 = 64 * 1024;
+
+/// The continuation which begins reading from the oldest item of a log
+const START: &str = "start";
+/// The tag of the continuations returned by `chunk/2`
+const CONTINUATION: &str = "$disk_log_cont";
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Halt {
+        max_bytes: Option<u64>,
+    },
+    Wrap {
+        max_bytes: u64,
+        max_files: u32,
+        current: u32,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum RepairMode {
+    Repair,
+    Fail,
+    Truncate,
+}
+
+/// The arguments given to `open/1`
+struct Options {
+    name: Atom,
+    path: PathBuf,
+    kind: Kind,
+    read_only: bool,
+    repair: RepairMode,
+}
+impl Options {
+    fn parse(args: OpaqueTerm) -> Result<Self, Error> {
+        let args = list_to_vec(args).ok_or(Error::Badarg("args"))?;
+        let mut name = None;
+        let mut path = None;
+        let mut wrap = false;
+        let mut size = None;
+        let mut read_only = false;
+        let mut repair = RepairMode::Repair;
+        for arg in args {
+            let Some([key, value]) = tuple_elements(arg) else {
+                return Err(Error::Badarg("args"));
+            };
+            let Term::Atom(key) = (*key).into() else {
+                return Err(Error::Badarg("args"));
+            };
+            let value = *value;
+            match key.as_str() {
+                "name" => match value.into() {
+                    Term::Atom(value) => name = Some(value),
+                    _ => return Err(Error::Badarg("name")),
+                },
+                "file" => path = Some(to_path(value).ok_or(Error::Badarg("file"))?),
+                "type" if is_atom(value, "halt") => wrap = false,
+                "type" if is_atom(value, "wrap") => wrap = true,
+                "size" => size = Some(value),
+                "format" if is_atom(value, "internal") => (),
+                "mode" if is_atom(value, "read_write") => read_only = false,
+                "mode" if is_atom(value, "read_only") => read_only = true,
+                "repair" => {
+                    repair = match value.into() {
+                        Term::Bool(true) => RepairMode::Repair,
+                        Term::Bool(false) => RepairMode::Fail,
+                        _ if is_atom(value, "truncate") => RepairMode::Truncate,
+                        _ => return Err(Error::Badarg("repair")),
+                    }
+                }
+                // Options which have no effect on a log of this implementation
+                "notify" | "head" | "head_func" | "quiet" | "linkto" | "distributed" => (),
+                "type" => return Err(Error::Badarg("type")),
+                "format" => return Err(Error::Badarg("format")),
+                "mode" => return Err(Error::Badarg("mode")),
+                _ => return Err(Error::Badarg("args")),
+            }
+        }
+
+        let name = name.ok_or(Error::Badarg("name"))?;
+        let path = path.unwrap_or_else(|| PathBuf::from(format!("{}.LOG", name.as_str())));
+        let kind = match (wrap, size) {
+            (false, None) => Kind::Halt { max_bytes: None },
+            (false, Some(size)) if is_atom(size, "infinity") => Kind::Halt { max_bytes: None },
+            (false, Some(size)) => match size.into() {
+                Term::Int(max_bytes) if max_bytes > 0 => Kind::Halt {
+                    max_bytes: Some(max_bytes as u64),
+                },
+                _ => return Err(Error::Badarg("size")),
+            },
+            (true, size) => match size.and_then(tuple_elements) {
+                Some([max_bytes, max_files]) => match ((*max_bytes).into(), (*max_files).into()) {
+                    (Term::Int(max_bytes), Term::Int(max_files))
+                        if max_bytes > 0 && max_files > 0 && max_files <= u16::MAX as i64 =>
+                    {
+                        Kind::Wrap {
+                            max_bytes: max_bytes as u64,
+                            max_files: max_files as u32,
+                            current: 1,
+                        }
+                    }
+                    _ => return Err(Error::Badarg("size")),
+                },
+                _ => return Err(Error::Badarg("size")),
+            },
+        };
+        Ok(Self {
+            name,
+            path,
+            kind,
+            read_only,
+            repair,
+        })
+    }
+}
+
+/// The errors returned as `{error, Reason}`
+enum Error {
+    Badarg(&'static str),
+    NoSuchLog,
+    ArgMismatch(&'static str),
+    NotALogFile(PathBuf),
+    NeedRepair(Atom),
+    ReadOnly(Atom),
+    Full(Atom),
+    File(PathBuf, io::Error),
+}
+impl Error {
+    fn into_result(self) -> ErlangResult {
+        ErlangResult::Ok(with_process(|proc| {
+            let reason = match self {
+                Self::Badarg(arg) => make_tuple(proc, &[atoms::Badarg.into(), atom(arg).into()]),
+                Self::NoSuchLog => atom("no_such_log").into(),
+                Self::ArgMismatch(arg) => {
+                    make_tuple(proc, &[atom("arg_mismatch").into(), atom(arg).into()])
+                }
+                Self::NotALogFile(path) => {
+                    let path = charlist(proc, &path.to_string_lossy());
+                    make_tuple(proc, &[atom("not_a_log_file").into(), path])
+                }
+                Self::NeedRepair(name) => {
+                    make_tuple(proc, &[atom("need_repair").into(), name.into()])
+                }
+                Self::ReadOnly(name) => {
+                    make_tuple(proc, &[atom("read_only_mode").into(), name.into()])
+                }
+                Self::Full(name) => make_tuple(proc, &[atom("full").into(), name.into()]),
+                Self::File(path, err) => {
+                    let path = charlist(proc, &path.to_string_lossy());
+                    let posix = atom(posix(&err)).into();
+                    make_tuple(proc, &[atom("file_error").into(), path, posix])
+                }
+            };
+            make_tuple(proc, &[atoms::Error.into(), reason])
+        }))
+    }
+}
+
+/// A position in a log, from which `chunk/2` continues reading
+#[derive(Copy, Clone)]
+struct Continuation {
+    /// The index of the file of a wrap log, or zero for a halt log
+    index: u32,
+    pos: u64,
+}
+
+/// The result of reading a chunk of a log
+struct Chunk {
+    continuation: Continuation,
+    items: Vec<Vec<u8>>,
+    badbytes: u64,
+}
+
+struct Log {
+    name: Atom,
+    /// The file of a halt log, or the base name of the files of a wrap log
+    path: PathBuf,
+    kind: Kind,
+    read_only: bool,
+    /// The file being written, i.e. the current file of a wrap log
+    file: File,
+    /// The size of the current file
+    bytes: u64,
+    /// The number of items in the current file
+    items: u64,
+    /// The number of times the log has been opened but not closed
+    users: usize,
+}
+impl Log {
+    /// Opens the log described by `options`, returning the number of items recovered and bytes
+    /// discarded if it had to be repaired
+    fn open(options: &Options) -> Result<(Self, Option<(u64, u64)>), Error> {
+        let mut kind = options.kind;
+        if let Kind::Wrap {
+            max_files,
+            ref mut current,
+            ..
+        } = kind
+        {
+            let index = index_path(&options.path);
+            match read_index(&index) {
+                Ok(Some(index)) if index >= 1 && index <= max_files => *current = index,
+                Ok(_) => (),
+                Err(err) => return Err(Error::File(index, err)),
+            }
+        }
+        let path = file_path(&options.path, kind, current_index(kind));
+        let io = |err| Error::File(path.clone(), err);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(!options.read_only)
+            .create(!options.read_only)
+            .open(&path)
+            .map_err(io)?;
+        let len = file.metadata().map_err(io)?.len();
+        let mut repaired = None;
+        let (bytes, items) = if len == 0 && !options.read_only {
+            write_header(&mut file, OPENED).map_err(io)?;
+            (HEADER_BYTES, 0)
+        } else {
+            let mut header = [0; HEADER_BYTES as usize];
+            if len < HEADER_BYTES {
+                return Err(Error::NotALogFile(path.clone()));
+            }
+            file.read_exact(&mut header).map_err(io)?;
+            if &header[..8] != MAGIC {
+                return Err(Error::NotALogFile(path.clone()));
+            }
+            let closed = &header[8..] == CLOSED;
+            match options.repair {
+                _ if closed || options.read_only => {
+                    let (items, _) = scan(&file, len).map_err(io)?;
+                    (len, items)
+                }
+                RepairMode::Fail => return Err(Error::NeedRepair(options.name)),
+                RepairMode::Truncate => {
+                    file.set_len(HEADER_BYTES).map_err(io)?;
+                    (HEADER_BYTES, 0)
+                }
+                RepairMode::Repair => {
+                    let (items, end) = scan(&file, len).map_err(io)?;
+                    file.set_len(end).map_err(io)?;
+                    repaired = Some((items, len - end));
+                    (end, items)
+                }
+            }
+        };
+        if !options.read_only {
+            set_status(&mut file, OPENED).map_err(io)?;
+            file.seek(SeekFrom::End(0)).map_err(io)?;
+            if let Kind::Wrap { current, .. } = kind {
+                write_index(&options.path, current)?;
+            }
+        }
+
+        let log = Self {
+            name: options.name,
+            path: options.path.clone(),
+            kind,
+            read_only: options.read_only,
+            file,
+            bytes,
+            items,
+            users: 1,
+        };
+        Ok((log, repaired))
+    }
+
+    /// Appends `items`, each of which is an encoded term
+    fn write(&mut self, items: &[Vec<u8>]) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly(self.name));
+        }
+        if let Kind::Halt {
+            max_bytes: Some(max_bytes),
+        } = self.kind
+        {
+            let size = items
+                .iter()
+                .map(|item| ITEM_HEADER_BYTES + item.len() as u64)
+                .sum::<u64>();
+            if self.bytes + size > max_bytes {
+                return Err(Error::Full(self.name));
+            }
+        }
+        for item in items {
+            let size = ITEM_HEADER_BYTES + item.len() as u64;
+            if let Kind::Wrap { max_bytes, .. } = self.kind {
+                // An item larger than a whole file is written to a file of its own
+                if self.bytes + size > max_bytes && self.items > 0 {
+                    self.wrap()?;
+                }
+            }
+            let mut frame = Vec::with_capacity(size as usize);
+            frame.extend_from_slice(&(item.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&checksum(item).to_be_bytes());
+            frame.extend_from_slice(item);
+            self.file.write_all(&frame).map_err(|err| self.error(err))?;
+            self.bytes += size;
+            self.items += 1;
+        }
+        Ok(())
+    }
+
+    /// Closes the current file of a wrap log, and moves on to the next, discarding its contents
+    fn wrap(&mut self) -> Result<(), Error> {
+        let Kind::Wrap {
+            max_files,
+            ref mut current,
+            ..
+        } = self.kind
+        else {
+            return Ok(());
+        };
+        set_status(&mut self.file, CLOSED).map_err(|err| Error::File(self.path.clone(), err))?;
+        *current = *current % max_files + 1;
+        let current = *current;
+        let path = file_path(&self.path, self.kind, current);
+        let io = |err| Error::File(path.clone(), err);
+        self.file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(io)?;
+        write_header(&mut self.file, OPENED).map_err(io)?;
+        self.bytes = HEADER_BYTES;
+        self.items = 0;
+        write_index(&self.path, current)
+    }
+
+    /// Discards all of the items in the log
+    fn truncate(&mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly(self.name));
+        }
+        if let Kind::Wrap {
+            max_files,
+            ref mut current,
+            ..
+        } = self.kind
+        {
+            // Start over from the first file, by wrapping around to it from the last
+            *current = max_files;
+            for index in 1..=max_files {
+                let path = file_path(&self.path, self.kind, index);
+                match fs::remove_file(&path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        return Err(Error::File(path, err));
+                    }
+                    _ => (),
+                }
+            }
+            return self.wrap();
+        }
+        self.file
+            .set_len(HEADER_BYTES)
+            .and_then(|_| self.file.seek(SeekFrom::End(0)))
+            .map_err(|err| self.error(err))?;
+        self.bytes = HEADER_BYTES;
+        self.items = 0;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync_data().map_err(|err| self.error(err))
+    }
+
+    /// Marks the current file as having been closed properly
+    fn close(mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        set_status(&mut self.file, CLOSED).map_err(|err| self.error(err))
+    }
+
+    /// Returns the position of the oldest item in the log
+    fn start(&self) -> Continuation {
+        match self.kind {
+            Kind::Halt { .. } => Continuation { index: 0, pos: 0 },
+            Kind::Wrap {
+                max_files, current, ..
+            } => {
+                // The file after the current one is the oldest, unless the log has yet to wrap
+                let next = current % max_files + 1;
+                let index = if file_path(&self.path, self.kind, next).exists() {
+                    next
+                } else {
+                    1
+                };
+                Continuation { index, pos: 0 }
+            }
+        }
+    }
+
+    /// Reads up to `max_items` items following `continuation`, returning `None` at the end of the
+    /// log
+    ///
+    /// Corrupt or incomplete items, which may be found in logs which were not repaired, are skipped
+    /// along with the rest of the file they are in.
+    fn chunk(
+        &self,
+        mut continuation: Continuation,
+        max_items: usize,
+    ) -> Result<Option<Chunk>, Error> {
+        loop {
+            let path = file_path(&self.path, self.kind, continuation.index);
+            let io = |err| Error::File(path.clone(), err);
+            let file = match File::open(&path) {
+                Ok(file) => Some(file),
+                // A file of a wrap log may have been removed by `truncate/1`
+                Err(err) if err.kind() == io::ErrorKind::NotFound && continuation.index > 0 => None,
+                Err(err) => return Err(io(err)),
+            };
+            let mut items = Vec::new();
+            let mut badbytes = 0;
+            if let Some(file) = file {
+                let len = file.metadata().map_err(io)?.len();
+                continuation.pos = continuation.pos.max(HEADER_BYTES);
+                let mut reader = BufReader::new(file);
+                reader.seek(SeekFrom::Start(continuation.pos)).map_err(io)?;
+                let mut read = 0;
+                while continuation.pos < len && items.len() < max_items && read < CHUNK_BYTES {
+                    match read_item(&mut reader, len - continuation.pos).map_err(io)? {
+                        Some(item) => {
+                            let size = ITEM_HEADER_BYTES + item.len() as u64;
+                            continuation.pos += size;
+                            read += size;
+                            items.push(item);
+                        }
+                        None => {
+                            badbytes = len - continuation.pos;
+                            continuation.pos = len;
+                        }
+                    }
+                }
+            }
+            if !items.is_empty() || badbytes > 0 {
+                return Ok(Some(Chunk {
+                    continuation,
+                    items,
+                    badbytes,
+                }));
+            }
+            match self.kind {
+                Kind::Wrap {
+                    max_files, current, ..
+                } if continuation.index != current => {
+                    continuation = Continuation {
+                        index: continuation.index % max_files + 1,
+                        pos: 0,
+                    };
+                }
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    fn info(&self, proc: &Process) -> OpaqueTerm {
+        let int = |i: u64| make_integer(proc, Integer::from(i));
+        let pair = |key: &str, value: OpaqueTerm| make_tuple(proc, &[atom(key).into(), value]);
+        let path = charlist(proc, &self.path.to_string_lossy());
+        let mode = if self.read_only {
+            "read_only"
+        } else {
+            "read_write"
+        };
+        let mut info = vec![
+            pair("name", self.name.into()),
+            pair("file", path),
+            pair("format", atom("internal").into()),
+            pair("mode", atom(mode).into()),
+        ];
+        match self.kind {
+            Kind::Halt { max_bytes } => {
+                let size = max_bytes
+                    .map(int)
+                    .unwrap_or_else(|| atom("infinity").into());
+                info.push(pair("type", atom("halt").into()));
+                info.push(pair("size", size));
+                info.push(pair("no_items", int(self.items)));
+            }
+            Kind::Wrap {
+                max_bytes,
+                max_files,
+                current,
+            } => {
+                let size = make_tuple(proc, &[int(max_bytes), int(max_files as u64)]);
+                info.push(pair("type", atom("wrap").into()));
+                info.push(pair("size", size));
+                info.push(pair("current_file", int(current as u64)));
+                info.push(pair("no_current_bytes", int(self.bytes)));
+                info.push(pair("no_current_items", int(self.items)));
+            }
+        }
+        make_list(proc, info.as_slice())
+    }
+
+    fn error(&self, err: io::Error) -> Error {
+        Error::File(
+            file_path(&self.path, self.kind, current_index(self.kind)),
+            err,
+        )
+    }
+}
+
+/// Opens a log, as described by the list of options `args`
+///
+/// Returns `{ok, Log}`, or `{repaired, Log, {recovered, Items}, {badbytes, Bytes}}` if the log was
+/// not closed properly and had to be repaired. Opening a log which is already open returns
+/// `{ok, Log}` if the options agree, and must be matched by another call to `close/1`.
+#[export_name = "disk_log:open/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn open1(args: OpaqueTerm) -> ErlangResult {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => return err.into_result(),
+    };
+    let name = options.name;
+    let mut logs = logs();
+    if let Some(log) = logs.get_mut(&name) {
+        let mismatch = if log.path != options.path {
+            Some("file")
+        } else if log.kind != options.kind && !is_same_wrap(log.kind, options.kind) {
+            Some("type")
+        } else if log.read_only != options.read_only {
+            Some("mode")
+        } else {
+            None
+        };
+        if let Some(arg) = mismatch {
+            return Error::ArgMismatch(arg).into_result();
+        }
+        log.users += 1;
+        return ErlangResult::Ok(with_process(|proc| {
+            make_tuple(proc, &[atoms::Ok.into(), name.into()])
+        }));
+    }
+    match Log::open(&options) {
+        Ok((log, repaired)) => {
+            logs.insert(name, log);
+            crate::halt::on_halt("disk_log", close_all);
+            ErlangResult::Ok(with_process(|proc| match repaired {
+                None => make_tuple(proc, &[atoms::Ok.into(), name.into()]),
+                Some((recovered, badbytes)) => {
+                    let recovered = make_integer(proc, Integer::from(recovered));
+                    let badbytes = make_integer(proc, Integer::from(badbytes));
+                    let recovered = make_tuple(proc, &[atom("recovered").into(), recovered]);
+                    let badbytes = make_tuple(proc, &[atom("badbytes").into(), badbytes]);
+                    make_tuple(
+                        proc,
+                        &[atom("repaired").into(), name.into(), recovered, badbytes],
+                    )
+                }
+            }))
+        }
+        Err(err) => err.into_result(),
+    }
+}
+
+#[export_name = "disk_log:log/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn log2(log: OpaqueTerm, term: OpaqueTerm) -> ErlangResult {
+    log_terms(log, &[term])
+}
+
+#[export_name = "disk_log:log_terms/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn log_terms2(log: OpaqueTerm, terms: OpaqueTerm) -> ErlangResult {
+    let Some(terms) = list_to_vec(terms) else {
+        return super::badarg(Trace::capture());
+    };
+    log_terms(log, terms.as_slice())
+}
+
+#[export_name = "disk_log:alog/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn alog2(log: OpaqueTerm, term: OpaqueTerm) -> ErlangResult {
+    log2(log, term)
+}
+
+#[export_name = "disk_log:alog_terms/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn alog_terms2(log: OpaqueTerm, terms: OpaqueTerm) -> ErlangResult {
+    log_terms2(log, terms)
+}
+
+/// Flushes the log to disk
+#[export_name = "disk_log:sync/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sync1(log: OpaqueTerm) -> ErlangResult {
+    with_log(log, |log| log.sync().map(|_| atoms::Ok.into()))
+}
+
+/// Discards all of the items in the log
+#[export_name = "disk_log:truncate/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn truncate1(log: OpaqueTerm) -> ErlangResult {
+    with_log(log, |log| log.truncate().map(|_| atoms::Ok.into()))
+}
+
+#[export_name = "disk_log:close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn close1(log: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(name) = log.into() else {
+        return Error::NoSuchLog.into_result();
+    };
+    let mut logs = logs();
+    let Some(log) = logs.get_mut(&name) else {
+        return Error::NoSuchLog.into_result();
+    };
+    log.users -= 1;
+    if log.users > 0 {
+        return ErlangResult::Ok(atoms::Ok.into());
+    }
+    let log = logs.remove(&name).unwrap();
+    drop(logs);
+    match log.close() {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => err.into_result(),
+    }
+}
+
+#[export_name = "disk_log:chunk/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn chunk2(log: OpaqueTerm, continuation: OpaqueTerm) -> ErlangResult {
+    chunk(log, continuation, usize::MAX)
+}
+
+/// Reads up to `n` terms from the log, starting from `continuation`, which is `start` to read from
+/// the oldest term, or a continuation returned by a previous call
+///
+/// Returns `{Continuation2, Terms}`, `{Continuation2, Terms, Badbytes}` if corrupt items were
+/// skipped, or `eof` at the end of the log.
+#[export_name = "disk_log:chunk/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn chunk3(
+    log: OpaqueTerm,
+    continuation: OpaqueTerm,
+    n: OpaqueTerm,
+) -> ErlangResult {
+    match n.into() {
+        Term::Atom(n) if n.as_str() == "infinity" => chunk(log, continuation, usize::MAX),
+        Term::Int(n) if n > 0 => chunk(log, continuation, n as usize),
+        _ => super::badarg(Trace::capture()),
+    }
+}
+
+/// Returns a list of `{Tag, Value}` pairs describing the log
+#[export_name = "disk_log:info/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn info1(log: OpaqueTerm) -> ErlangResult {
+    with_log(log, |log| Ok(with_process(|proc| log.info(proc))))
+}
+
+/// Returns `{[Name], []}`, i.e. the names of the open logs, none of which are distributed
+#[export_name = "disk_log:accessible_logs/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn accessible_logs0() -> ErlangResult {
+    let names = logs()
+        .keys()
+        .map(|name| OpaqueTerm::from(*name))
+        .collect::<Vec<_>>();
+    ErlangResult::Ok(with_process(|proc| {
+        let names = make_list(proc, names.as_slice());
+        make_tuple(proc, &[names, OpaqueTerm::NIL])
+    }))
+}
+
+fn log_terms(log: OpaqueTerm, terms: &[OpaqueTerm]) -> ErlangResult {
+    let Term::Atom(name) = log.into() else {
+        return Error::NoSuchLog.into_result();
+    };
+    if !logs().contains_key(&name) {
+        return Error::NoSuchLog.into_result();
+    }
+    // The terms are encoded before the log is locked, as encoding may yield to the scheduler
+    let mut items = Vec::with_capacity(terms.len());
+    for term in terms.iter().copied() {
+        let mut bytes = Vec::new();
+        if super::etf::encode(term, &mut bytes).is_err() {
+            return super::badarg(Trace::capture());
+        }
+        items.push(bytes);
+    }
+    let mut logs = logs();
+    let Some(log) = logs.get_mut(&name) else {
+        return Error::NoSuchLog.into_result();
+    };
+    match log.write(items.as_slice()) {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => err.into_result(),
+    }
+}
+
+fn chunk(log: OpaqueTerm, continuation: OpaqueTerm, max_items: usize) -> ErlangResult {
+    let Term::Atom(name) = log.into() else {
+        return Error::NoSuchLog.into_result();
+    };
+    let logs = logs();
+    let Some(log) = logs.get(&name) else {
+        return Error::NoSuchLog.into_result();
+    };
+    let continuation = if is_atom(continuation, START) {
+        log.start()
+    } else {
+        match parse_continuation(continuation, name) {
+            Some(continuation) => continuation,
+            None => return Error::Badarg("continuation").into_result(),
+        }
+    };
+    let chunk = match log.chunk(continuation, max_items) {
+        Ok(Some(chunk)) => chunk,
+        Ok(None) => return ErlangResult::Ok(atom("eof").into()),
+        Err(err) => return err.into_result(),
+    };
+    drop(logs);
+
+    ErlangResult::Ok(with_process(|proc| {
+        let mut badbytes = chunk.badbytes;
+        let mut terms = Vec::with_capacity(chunk.items.len());
+        for item in chunk.items.iter() {
+            // Items whose terms cannot be decoded are reported as if they were corrupt
+            match etf::decode(item.as_slice(), proc) {
+                Ok((term, len)) if len == item.len() => terms.push(term.into()),
+                _ => badbytes += ITEM_HEADER_BYTES + item.len() as u64,
+            }
+        }
+        let continuation = make_tuple(
+            proc,
+            &[
+                atom(CONTINUATION).into(),
+                name.into(),
+                make_integer(proc, Integer::from(chunk.continuation.index as u64)),
+                make_integer(proc, Integer::from(chunk.continuation.pos)),
+            ],
+        );
+        let terms = make_list(proc, terms.as_slice());
+        if badbytes > 0 {
+            let badbytes = make_integer(proc, Integer::from(badbytes));
+            make_tuple(proc, &[continuation, terms, badbytes])
+        } else {
+            make_tuple(proc, &[continuation, terms])
+        }
+    }))
+}
+
+/// Applies `fun` to the open log named by `log`
+fn with_log<F>(log: OpaqueTerm, fun: F) -> ErlangResult
+where
+    F: FnOnce(&mut Log) -> Result<OpaqueTerm, Error>,
+{
+    let Term::Atom(name) = log.into() else {
+        return Error::NoSuchLog.into_result();
+    };
+    let result = {
+        let mut logs = logs();
+        logs.get_mut(&name).map_or(Err(Error::NoSuchLog), fun)
+    };
+    match result {
+        Ok(result) => ErlangResult::Ok(result),
+        Err(err) => err.into_result(),
+    }
+}
+
+fn parse_continuation(term: OpaqueTerm, name: Atom) -> Option<Continuation> {
+    let [tag, log, index, pos] = tuple_elements(term)? else {
+        return None;
+    };
+    if !is_atom(*tag, CONTINUATION) || !is_atom(*log, name.as_str()) {
+        return None;
+    }
+    match ((*index).into(), (*pos).into()) {
+        (Term::Int(index), Term::Int(pos)) if index >= 0 && pos >= 0 => Some(Continuation {
+            index: u32::try_from(index).ok()?,
+            pos: pos as u64,
+        }),
+        _ => None,
+    }
+}
+
+/// Wrap logs are considered the same when they differ only in their current file
+fn is_same_wrap(a: Kind, b: Kind) -> bool {
+    match (a, b) {
+        (
+            Kind::Wrap {
+                max_bytes: a_bytes,
+                max_files: a_files,
+                ..
+            },
+            Kind::Wrap {
+                max_bytes: b_bytes,
+                max_files: b_files,
+                ..
+            },
+        ) => a_bytes == b_bytes && a_files == b_files,
+        _ => false,
+    }
+}
+
+/// Closes every log as the system halts, so that none needs repairing when next opened
+fn close_all(_flush: bool) {
+    let logs = std::mem::take(&mut *logs());
+    for log in logs.into_values() {
+        let _ = log.close();
+    }
+}
+
+fn logs() -> MutexGuard<'static, BTreeMap<Atom, Log>> {
+    LOGS.get_or_init(|| Mutex::new(BTreeMap::new()))
+        .lock()
+        .unwrap()
+}
+
+fn current_index(kind: Kind) -> u32 {
+    match kind {
+        Kind::Halt { .. } => 0,
+        Kind::Wrap { current, .. } => current,
+    }
+}
+
+/// Returns the path of the file with the given index, i.e. `File.N` for a wrap log
+fn file_path(path: &Path, kind: Kind, index: u32) -> PathBuf {
+    match kind {
+        Kind::Halt { .. } => path.to_path_buf(),
+        Kind::Wrap { .. } => with_extension(path, &index.to_string()),
+    }
+}
+
+fn index_path(path: &Path) -> PathBuf {
+    with_extension(path, "idx")
+}
+
+/// Appends `.extension` to `path`, rather than replacing any extension it has
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = OsString::from(path.as_os_str());
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+fn read_index(path: &Path) -> io::Result<Option<u32>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(bytes.try_into().ok().map(u32::from_be_bytes)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn write_index(path: &Path, index: u32) -> Result<(), Error> {
+    let path = index_path(path);
+    fs::write(&path, index.to_be_bytes()).map_err(|err| Error::File(path, err))
+}
+
+fn write_header(file: &mut File, status: &[u8; 4]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(MAGIC)?;
+    file.write_all(status)?;
+    file.sync_data()
+}
+
+fn set_status(file: &mut File, status: &[u8; 4]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(MAGIC.len() as u64))?;
+    file.write_all(status)?;
+    file.sync_data()
+}
+
+/// Counts the valid items of `file`, returning their number and the offset following the last
+fn scan(file: &File, len: u64) -> io::Result<(u64, u64)> {
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(HEADER_BYTES))?;
+    let mut items = 0;
+    let mut pos = HEADER_BYTES;
+    while pos < len {
+        let Some(item) = read_item(&mut reader, len - pos)? else { break; };
+        pos += ITEM_HEADER_BYTES + item.len() as u64;
+        items += 1;
+    }
+    Ok((items, pos))
+}
+
+/// Reads the next item, given the number of bytes `remaining` in the file, returning `None` if the
+/// item is incomplete or corrupt
+fn read_item<R: Read>(reader: &mut R, remaining: u64) -> io::Result<Option<Vec<u8>>> {
+    if remaining < ITEM_HEADER_BYTES {
+        return Ok(None);
+    }
+    let mut header = [0; ITEM_HEADER_BYTES as usize];
+    reader.read_exact(&mut header)?;
+    let size = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+    let sum = u32::from_be_bytes(header[4..].try_into().unwrap());
+    if size > remaining - ITEM_HEADER_BYTES {
+        return Ok(None);
+    }
+    let mut item = vec![0; size as usize];
+    reader.read_exact(&mut item)?;
+    Ok((checksum(&item) == sum).then_some(item))
+}
+
+/// The 32-bit FNV-1a hash of `bytes`, with which corrupt items are detected
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
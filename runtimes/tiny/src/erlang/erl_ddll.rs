@@ -0,0 +1,71 @@
+//! The BIFs of `erl_ddll`, for loading and unloading port drivers, see `firefly_driver`.
+//!
+//! Unlike BEAM, drivers are not reference counted per process: a driver can be unloaded once all
+//! ports using it are closed, and attempting to unload it before then fails with `in_use`.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::badarg;
+use super::util::*;
+
+#[export_name = "erl_ddll:load_driver/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn load_driver2(path: OpaqueTerm, name: OpaqueTerm) -> ErlangResult {
+    let (Some(path), Some(name)) = (name_to_string(path), name_to_string(name)) else {
+        return badarg(Trace::capture());
+    };
+    match firefly_driver::load(path.as_str(), name.as_str()) {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => ErlangResult::Ok(with_process(|proc| {
+            make_tuple(proc, &[atoms::Error.into(), atom(err.reason()).into()])
+        })),
+    }
+}
+
+#[export_name = "erl_ddll:load/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn load2(path: OpaqueTerm, name: OpaqueTerm) -> ErlangResult {
+    load_driver2(path, name)
+}
+
+#[export_name = "erl_ddll:unload_driver/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unload_driver1(name: OpaqueTerm) -> ErlangResult {
+    let Some(name) = name_to_string(name) else {
+        return badarg(Trace::capture());
+    };
+    match firefly_driver::unload(name.as_str()) {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => ErlangResult::Ok(with_process(|proc| {
+            make_tuple(proc, &[atoms::Error.into(), atom(err.reason()).into()])
+        })),
+    }
+}
+
+#[export_name = "erl_ddll:unload/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unload1(name: OpaqueTerm) -> ErlangResult {
+    unload_driver1(name)
+}
+
+#[export_name = "erl_ddll:loaded_drivers/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn loaded_drivers0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        let names = firefly_driver::loaded()
+            .iter()
+            .map(|name| charlist(proc, name.as_str()))
+            .collect::<Vec<_>>();
+        let names = make_list(proc, names.as_slice());
+        make_tuple(proc, &[atoms::Ok.into(), names])
+    }))
+}
+
+/// Paths and driver names may be given as atoms or strings
+fn name_to_string(term: OpaqueTerm) -> Option<String> {
+    match term.into() {
+        Term::Atom(a) => Some(a.as_str().to_string()),
+        _ => charlist_to_string(term),
+    }
+}
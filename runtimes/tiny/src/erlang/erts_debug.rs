@@ -0,0 +1,144 @@
+//! `erts_debug:set_internal_state/2` and `get_internal_state/1`, the knobs runtime test suites turn
+//! to push the runtime into edge cases which are otherwise hard to hit.
+//!
+//! This module only exists when the `test` feature is enabled, and as in ERTS, its knobs can only
+//! be turned once `set_internal_state(available_internal_state, true)` has been called, raising
+//! `undef` until then. The knobs are:
+//!
+//! * `force_gc`, `true | false`, to collect the heap of a process on every allocation. There is no
+//! garbage collector in this runtime yet, see `super::process`, so this is only recorded, so that
+//! suites which set it run unchanged, and take effect once there is one.
+//! * `timer_tick`, the length in microseconds of a millisecond of timer time, 1000 by default.
+//! Shrinking it makes timers fire early, e.g. with 10, a timeout of one second fires after 10ms,
+//! so that suites exercising timeouts, restart intensities and the like run quickly.
+//! * `random_schedule`, `false` or an integer seed, to run the runnable processes in a
+//! pseudo-random order determined by the seed, rather than round-robin, so that suites can explore
+//! the interleavings of their processes, and reproduce a failing one from its seed. The schedule of
+//! a simulation, see `crate::sim`, takes precedence.
+//! * `fail_allocations`, `{Allocator, N}`, to make the next `N` allocations of the allocator named
+//! `Allocator`, e.g. `eheap_alloc`, fail as though memory were exhausted, see
+//! `firefly_alloc::allocators::carriers`.
+//!
+//! The knobs are global, they affect every process rather than just the caller.
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use firefly_alloc::allocators::carriers::AllocatorType;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::sim::Prng;
+
+use super::util::*;
+
+/// Whether the knobs are available, i.e. `available_internal_state` has been set
+static AVAILABLE: AtomicBool = AtomicBool::new(false);
+static FORCE_GC: AtomicBool = AtomicBool::new(false);
+/// The length of a millisecond of timer time, in microseconds
+static TIMER_TICK: AtomicU64 = AtomicU64::new(1000);
+/// The generator of the order in which processes are scheduled, or `None` if processes are
+/// scheduled round-robin
+static RANDOM_SCHEDULE: Mutex<Option<Prng>> = Mutex::new(None);
+
+/// Sets the internal state `Name` to `Value`, returning its previous value
+#[export_name = "erts_debug:set_internal_state/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_internal_state2(name: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(name) = name.into() else { return super::badarg(Trace::capture()) };
+    if name.as_str() == "available_internal_state" {
+        let Term::Bool(available) = value.into() else { return super::badarg(Trace::capture()) };
+        return ErlangResult::Ok(AVAILABLE.swap(available, Ordering::Relaxed).into());
+    }
+    if !AVAILABLE.load(Ordering::Relaxed) {
+        return ErlangResult::Err(undef());
+    }
+    let old = match (name.as_str(), Term::from(value)) {
+        ("force_gc", Term::Bool(force)) => FORCE_GC.swap(force, Ordering::Relaxed).into(),
+        ("timer_tick", Term::Int(us)) if us > 0 => {
+            let old = TIMER_TICK.swap(us as u64, Ordering::Relaxed);
+            with_process(|proc| old.into_term(proc).unwrap().into())
+        }
+        ("random_schedule", value) => {
+            let seed = match value {
+                Term::Bool(false) => None,
+                Term::Int(seed) => Some(seed as u64),
+                _ => return super::badarg(Trace::capture()),
+            };
+            let old = std::mem::replace(&mut *RANDOM_SCHEDULE.lock().unwrap(), seed.map(Prng::new));
+            make_seed(old.as_ref().map(Prng::seed))
+        }
+        ("fail_allocations", _) => {
+            let Some([allocator, n]) = tuple_elements(value) else {
+                return super::badarg(Trace::capture());
+            };
+            let ty = match (*allocator).into() {
+                Term::Atom(allocator) => AllocatorType::from_name(allocator.as_str()),
+                _ => None,
+            };
+            let (Some(ty), Term::Int(n)) = (ty, (*n).into()) else {
+                return super::badarg(Trace::capture());
+            };
+            if n < 0 {
+                return super::badarg(Trace::capture());
+            }
+            ty.allocator().fail_next(n as usize);
+            true.into()
+        }
+        _ => return super::badarg(Trace::capture()),
+    };
+    ErlangResult::Ok(old)
+}
+
+/// Returns the value of the internal state `Name`
+#[export_name = "erts_debug:get_internal_state/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_internal_state1(name: OpaqueTerm) -> ErlangResult {
+    if !AVAILABLE.load(Ordering::Relaxed) {
+        return ErlangResult::Err(undef());
+    }
+    let Term::Atom(name) = name.into() else { return super::badarg(Trace::capture()) };
+    let value = match name.as_str() {
+        "available_internal_state" => true.into(),
+        "force_gc" => FORCE_GC.load(Ordering::Relaxed).into(),
+        "timer_tick" => {
+            let tick = TIMER_TICK.load(Ordering::Relaxed);
+            with_process(|proc| tick.into_term(proc).unwrap().into())
+        }
+        "random_schedule" => make_seed(RANDOM_SCHEDULE.lock().unwrap().as_ref().map(Prng::seed)),
+        _ => return super::badarg(Trace::capture()),
+    };
+    ErlangResult::Ok(value)
+}
+
+/// Returns how long a timer of `timeout` milliseconds runs for, given the `timer_tick`
+pub(crate) fn timer_duration(timeout: u64) -> Duration {
+    Duration::from_micros(timeout.saturating_mul(TIMER_TICK.load(Ordering::Relaxed)))
+}
+
+/// Returns the index of the next of `len` runnable processes to run, or `None` if they are run in
+/// order, see `random_schedule`
+pub(crate) fn random_index(len: usize) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+    RANDOM_SCHEDULE
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(|random| random.below(len))
+}
+
+fn make_seed(seed: Option<u64>) -> OpaqueTerm {
+    match seed {
+        Some(seed) => with_process(|proc| (seed as i64).into_term(proc).unwrap().into()),
+        None => false.into(),
+    }
+}
+
+fn undef() -> NonNull<ErlangException> {
+    ErlangException::new(atoms::Error, atoms::Undef.into(), Trace::capture()).into_raw()
+}
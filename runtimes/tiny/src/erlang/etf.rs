@@ -0,0 +1,97 @@
+//! The BIFs which encode terms in the external term format, see `firefly_rt::etf`.
+//!
+//! Large terms are encoded in chunks, yielding to the scheduler whenever the process runs out of
+//! reductions, so that encoding them does not hold up other processes. The same is done by
+//! [`encode`], with which terms are written directly to files, sockets and the like, rather than
+//! first being encoded as a binary, as `disk_log` does. There is no distribution in this runtime,
+//! so no fragments are sent to other nodes this way.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::etf::{EncodeError, Encoder, IoVec, Segment, Sink, Status};
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::{Resumable, Step};
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::util::*;
+
+/// Returns a binary containing `term` in the external term format
+#[export_name = "erlang:term_to_binary/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn term_to_binary1(term: OpaqueTerm) -> ErlangResult {
+    let mut bytes = Vec::new();
+    if let Err(err) = encode(term, &mut bytes) {
+        return raise(err);
+    }
+    ErlangResult::Ok(with_process(|proc| make_binary(proc, bytes.as_slice())))
+}
+
+/// Returns `term` in the external term format as a list of binaries, which refers to the large
+/// binaries contained in `term` rather than copying them
+#[export_name = "erlang:term_to_iovec/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn term_to_iovec1(term: OpaqueTerm) -> ErlangResult {
+    let mut iovec = IoVec::new();
+    if let Err(err) = encode(term, &mut iovec) {
+        return raise(err);
+    }
+    ErlangResult::Ok(with_process(|proc| {
+        let segments = iovec
+            .into_segments()
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Bytes(bytes) => make_binary(proc, bytes.as_slice()),
+                Segment::Binary(binary) => {
+                    ExternalBinary::new(binary).into_term(proc).unwrap().into()
+                }
+            })
+            .collect::<Vec<_>>();
+        make_list(proc, segments.as_slice())
+    }))
+}
+
+/// Writes `term` to `sink` in the external term format, yielding to the scheduler whenever the
+/// process runs out of reductions, one of which is consumed by each term encoded
+///
+/// The term is encoded in place rather than copied. The heap of the process stays pinned while
+/// it is suspended in `scheduler::trampoline`, as it only runs the system tasks which collect it
+/// at the yield points of generated code, so the term does not move while other processes run
+/// in the meantime, and the process cannot collect its own heap before the encoding is done.
+pub(crate) fn encode<S: Sink + ?Sized>(
+    term: OpaqueTerm,
+    sink: &mut S,
+) -> Result<(), EncodeError<S::Error>> {
+    scheduler::trampoline(Encoding {
+        encoder: Encoder::new(term.into()),
+        sink,
+    })
+}
+
+/// The encoding of a term to a sink, as a computation which can be suspended
+struct Encoding<'a, S: ?Sized> {
+    encoder: Encoder,
+    sink: &'a mut S,
+}
+impl<'a, S: Sink + ?Sized> Resumable for Encoding<'a, S> {
+    type Output = Result<(), EncodeError<S::Error>>;
+
+    fn resume(&mut self, budget: &mut usize) -> Step<Self::Output> {
+        let encoded = self.encoder.encoded();
+        let status = self.encoder.encode(self.sink, *budget);
+        *budget = budget.saturating_sub(self.encoder.encoded() - encoded);
+        match status {
+            Ok(Status::Yield) => Step::Yield,
+            Ok(Status::Done) => Step::Done(Ok(())),
+            Err(err) => Step::Done(Err(err)),
+        }
+    }
+}
+
+fn raise<E>(err: EncodeError<E>) -> ErlangResult {
+    match err {
+        EncodeError::TooLarge => {
+            ErlangResult::raise(atoms::Error, atom("system_limit").into(), Trace::capture())
+        }
+        _ => super::badarg(Trace::capture()),
+    }
+}
@@ -1,8 +1,181 @@
+//! The BIFs of the `file` module which operate on whole files and directories.
+//!
+//! These are implemented directly on `std::fs`, so on wasm32-wasi they are WASI syscalls, and can
+//! only access the directories preopened by the host, e.g. with `wasmtime --dir`. Filenames may be
+//! strings or binaries, and errors are returned as `{error, Posix}` as in OTP.
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use firefly_rt::backtrace::Trace;
 use firefly_rt::function::ErlangResult;
 use firefly_rt::term::*;
 
+use super::util::*;
+
 #[export_name = "file:native_name_encoding/0"]
 #[allow(improper_ctypes_definitions)]
 pub extern "C-unwind" fn native_name_encoding() -> ErlangResult {
     ErlangResult::Ok(atoms::Utf8.into())
 }
+
+/// Returns `{ok, Binary}` with the contents of the file `filename`
+#[export_name = "file:read_file/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn read_file1(filename: OpaqueTerm) -> ErlangResult {
+    let Some(path) = to_path(filename) else {
+        return super::badarg(Trace::capture());
+    };
+    match fs::read(path) {
+        Ok(bytes) => ErlangResult::Ok(with_process(|proc| {
+            let bytes = make_binary(proc, bytes.as_slice());
+            make_tuple(proc, &[atoms::Ok.into(), bytes])
+        })),
+        Err(err) => error(err),
+    }
+}
+
+/// Writes `bytes`, which may be any iodata, to the file `filename`, replacing its contents
+#[export_name = "file:write_file/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn write_file2(filename: OpaqueTerm, bytes: OpaqueTerm) -> ErlangResult {
+    let (Some(path), Some(bytes)) = (to_path(filename), iodata_to_bytes(bytes)) else {
+        return super::badarg(Trace::capture());
+    };
+    to_result(fs::write(path, bytes))
+}
+
+/// Deletes the file `filename`
+#[export_name = "file:delete/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn delete1(filename: OpaqueTerm) -> ErlangResult {
+    let Some(path) = to_path(filename) else {
+        return super::badarg(Trace::capture());
+    };
+    to_result(fs::remove_file(path))
+}
+
+/// Returns `{ok, Filenames}` with the names of the entries in the directory `dir`, in no particular
+/// order
+#[export_name = "file:list_dir/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn list_dir1(dir: OpaqueTerm) -> ErlangResult {
+    let Some(path) = to_path(dir) else {
+        return super::badarg(Trace::capture());
+    };
+    let names = fs::read_dir(path).and_then(|entries| {
+        entries
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<Vec<_>>>()
+    });
+    match names {
+        Ok(names) => ErlangResult::Ok(with_process(|proc| {
+            let names = names
+                .iter()
+                .map(|name| charlist(proc, name))
+                .collect::<Vec<_>>();
+            let names = make_list(proc, names.as_slice());
+            make_tuple(proc, &[atoms::Ok.into(), names])
+        })),
+        Err(err) => error(err),
+    }
+}
+
+/// Creates the directory `dir`, whose parent must already exist
+#[export_name = "file:make_dir/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn make_dir1(dir: OpaqueTerm) -> ErlangResult {
+    let Some(path) = to_path(dir) else {
+        return super::badarg(Trace::capture());
+    };
+    to_result(fs::create_dir(path))
+}
+
+/// Deletes the directory `dir`, which must be empty
+#[export_name = "file:del_dir/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn del_dir1(dir: OpaqueTerm) -> ErlangResult {
+    let Some(path) = to_path(dir) else {
+        return super::badarg(Trace::capture());
+    };
+    to_result(fs::remove_dir(path))
+}
+
+/// Converts a filename, i.e. a string or a binary, to a path
+pub(super) fn to_path(filename: OpaqueTerm) -> Option<PathBuf> {
+    if let Some(name) = charlist_to_string(filename) {
+        return Some(name.into());
+    }
+    let term: Term = filename.into();
+    match term {
+        Term::Nil => Some(PathBuf::new()),
+        term => {
+            let bits = term.as_bitstring()?;
+            if !bits.is_binary() || !bits.is_aligned() {
+                return None;
+            }
+            let bytes = unsafe { bits.as_bytes_unchecked() };
+            core::str::from_utf8(bytes).ok().map(PathBuf::from)
+        }
+    }
+}
+
+fn to_result(result: io::Result<()>) -> ErlangResult {
+    match result {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => error(err),
+    }
+}
+
+/// Returns `{error, Posix}` for `err`
+fn error(err: io::Error) -> ErlangResult {
+    let reason = posix(&err);
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atoms::Error.into(), atom(reason).into()])
+    }))
+}
+
+/// Returns the POSIX error code corresponding to `err`, e.g. `enoent`
+pub(super) fn posix(err: &io::Error) -> &'static str {
+    match err.kind() {
+        io::ErrorKind::NotFound => "enoent",
+        io::ErrorKind::PermissionDenied => "eacces",
+        io::ErrorKind::AlreadyExists => "eexist",
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => "einval",
+        io::ErrorKind::Unsupported => "enotsup",
+        _ => match err.raw_os_error() {
+            Some(code) => posix_name(code),
+            None => "eio",
+        },
+    }
+}
+
+/// Returns the name of the errors which have no `io::ErrorKind` of their own
+///
+/// The codes are those of the platform's libc, which on wasm32-wasi is wasi-libc.
+#[cfg(any(unix, target_os = "wasi"))]
+fn posix_name(code: i32) -> &'static str {
+    #[cfg(unix)]
+    use libc::{EISDIR, ENOSPC, ENOTDIR, ENOTEMPTY};
+    #[cfg(target_os = "wasi")]
+    const ENOTDIR: i32 = 54;
+    #[cfg(target_os = "wasi")]
+    const EISDIR: i32 = 31;
+    #[cfg(target_os = "wasi")]
+    const ENOTEMPTY: i32 = 55;
+    #[cfg(target_os = "wasi")]
+    const ENOSPC: i32 = 51;
+
+    match code {
+        ENOTDIR => "enotdir",
+        EISDIR => "eisdir",
+        ENOTEMPTY => "eexist",
+        ENOSPC => "enospc",
+        _ => "eio",
+    }
+}
+
+#[cfg(not(any(unix, target_os = "wasi")))]
+fn posix_name(_code: i32) -> &'static str {
+    "eio"
+}
@@ -0,0 +1,565 @@
+//! `firefly_config`, reloading the application environment from a configuration file while the
+//! runtime is running, as `release_handler` does for a release upgrade in OTP:
+//!
+//! * `reload(File)` reads `File`, which is in the format of `sys.config`, i.e. a single term
+//! `[{App, [{Key, Value}]}]` followed by a full stop, and replaces the environment of each `App` in
+//! it with the defaults of its application resource, overridden by the values given. It returns
+//! `ok` once the environment is replaced and everyone has been notified, `{error, Posix}` if `File`
+//! could not be read, `{error, {Line, firefly_config, Message}}` if it could not be parsed, as with
+//! `file:consult/1`, or `{error, {badconfig, Term}}` if it is not a configuration.
+//! * `subscribe()` and `subscribe(App)` subscribe the calling process to changes to the
+//! environment of every application, or of `App`, which it receives as `{config_change, App,
+//! Changed, New, Removed}`, where `Changed` and `New` are `[{Key, Value}]` and `Removed` is
+//! `[Key]`.
+//! * `unsubscribe()` removes every subscription of the calling process.
+//!
+//! The environment of every application in the file is replaced at once, so a process never sees
+//! the environment of some of them reloaded and that of others not yet. Parameters set or unset
+//! with `application:set_env/4` or `unset_env/3` given `{persistent, true}` keep their values, and
+//! applications missing from the file keep their environment, see `application::replace_env`.
+//!
+//! Once the environment is replaced, each running application whose environment changed is told
+//! with `Mod:config_change(Changed, New, Removed)`, if its callback module exports it, and so are
+//! the subscribers. An exception raised by a callback is raised by `reload/1`, after the
+//! environment has been replaced, and the applications after it are not told.
+//!
+//! The file is parsed by this module rather than by `erl_scan` and `erl_parse`, which are not part
+//! of this runtime. It accepts the terms found in configuration: atoms, numbers, characters,
+//! strings, binaries, lists, tuples and maps, but not pids, references or funs, nor the names of
+//! other configuration files which `sys.config` may include in OTP.
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use super::application::{self, EnvChange};
+use super::util::*;
+
+/// The processes subscribed to changes of the environment, of every application if `None`
+static SUBSCRIBERS: Mutex<Vec<(ProcessId, Option<Atom>)>> = Mutex::new(Vec::new());
+
+/// Reloads the application environment from `File`
+#[export_name = "firefly_config:reload/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn reload1(file: OpaqueTerm) -> ErlangResult {
+    let Some(path) = super::file::to_path(file) else {
+        return super::badarg(Trace::capture());
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) => return error(atom(super::file::posix(&err)).into()),
+    };
+    let parsed = with_process(|proc| Parser::new(proc, &text).config());
+    let term = match parsed {
+        Ok(term) => term,
+        Err((line, message)) => {
+            return error(with_process(|proc| {
+                let line = Term::Int(line as i64).into();
+                let message = charlist(proc, &message);
+                make_tuple(proc, &[line, atom("firefly_config").into(), message])
+            }))
+        }
+    };
+    let Some(config) = application::parse_config(term) else {
+        return error(reason2("badconfig", term));
+    };
+
+    for change in application::replace_env(config) {
+        if change.changed.is_empty() && change.new.is_empty() && change.removed.is_empty() {
+            continue;
+        }
+        notify(&change);
+        let Some(module) = change.module else {
+            continue;
+        };
+        let args = with_process(|proc| {
+            let changed = make_pairs(proc, &change.changed);
+            let new = make_pairs(proc, &change.new);
+            let removed = change
+                .removed
+                .iter()
+                .map(|key| (*key).into())
+                .collect::<Vec<_>>();
+            [changed, new, make_list(proc, &removed)]
+        });
+        if let Err(err) = call_if_exported(module, "config_change", &args) {
+            return ErlangResult::Err(err);
+        }
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Subscribes the calling process to changes to the environment of every application
+#[export_name = "firefly_config:subscribe/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn subscribe0() -> ErlangResult {
+    subscribe(None)
+}
+
+/// Subscribes the calling process to changes to the environment of `App`
+#[export_name = "firefly_config:subscribe/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn subscribe1(app: OpaqueTerm) -> ErlangResult {
+    match app.into() {
+        Term::Atom(app) => subscribe(Some(app)),
+        _ => super::badarg(Trace::capture()),
+    }
+}
+
+/// Removes every subscription of the calling process
+#[export_name = "firefly_config:unsubscribe/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unsubscribe0() -> ErlangResult {
+    let pid = with_process(|proc| proc.pid());
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|(subscriber, _)| *subscriber != pid);
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+fn subscribe(app: Option<Atom>) -> ErlangResult {
+    let pid = with_process(|proc| proc.pid());
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if !subscribers.contains(&(pid, app)) {
+        subscribers.push((pid, app));
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Sends `{config_change, App, Changed, New, Removed}` to the subscribers to changes of `App`,
+/// forgetting those which have exited
+fn notify(change: &EnvChange) {
+    let subscribers = {
+        let mut subscribers = SUBSCRIBERS.lock().unwrap();
+        subscribers.retain(|(pid, _)| super::trace::is_alive(*pid));
+        subscribers
+            .iter()
+            .filter(|(_, app)| app.map_or(true, |app| app == change.app))
+            .map(|(pid, _)| *pid)
+            .collect::<Vec<_>>()
+    };
+    if subscribers.is_empty() {
+        return;
+    }
+    with_process(|proc| {
+        let removed = change
+            .removed
+            .iter()
+            .map(|key| (*key).into())
+            .collect::<Vec<_>>();
+        let message = make_tuple(
+            proc,
+            &[
+                atom("config_change").into(),
+                change.app.into(),
+                make_pairs(proc, &change.changed),
+                make_pairs(proc, &change.new),
+                make_list(proc, &removed),
+            ],
+        );
+        for pid in subscribers {
+            let _ = super::send2(make_pid(proc, pid), message);
+        }
+    });
+}
+
+fn make_pairs(proc: &Process, pairs: &[(Atom, OpaqueTerm)]) -> OpaqueTerm {
+    let pairs = pairs
+        .iter()
+        .map(|(key, value)| make_tuple(proc, &[(*key).into(), *value]))
+        .collect::<Vec<_>>();
+    make_list(proc, &pairs)
+}
+
+fn error(reason: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atoms::Error.into(), reason])
+    }))
+}
+
+/// A parse error, as the line it occurred on and a description
+type ParseError = (usize, String);
+
+/// A recursive descent parser for a term in the text format of `file:consult/1`, constructing the
+/// term on the heap of `proc`
+struct Parser<'a> {
+    proc: &'a Process,
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+impl<'a> Parser<'a> {
+    fn new(proc: &'a Process, text: &str) -> Self {
+        Self {
+            proc,
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    /// Parses a single term followed by a full stop, and nothing else but whitespace and comments
+    fn config(&mut self) -> Result<OpaqueTerm, ParseError> {
+        let term = self.term()?;
+        self.expect('.')?;
+        self.skip_blank();
+        match self.peek() {
+            None => Ok(term),
+            Some(c) => Err(self.error(format!("unexpected '{}' after the configuration", c))),
+        }
+    }
+
+    fn term(&mut self) -> Result<OpaqueTerm, ParseError> {
+        self.skip_blank();
+        match self.peek() {
+            None => Err(self.error("unexpected end of file".to_string())),
+            Some('[') => {
+                self.next();
+                self.list()
+            }
+            Some('{') => {
+                self.next();
+                let elements = self.sequence('}')?;
+                Ok(make_tuple(self.proc, &elements))
+            }
+            Some('#') => {
+                self.next();
+                self.expect('{')?;
+                self.map()
+            }
+            Some('<') => {
+                self.next();
+                self.expect('<')?;
+                self.binary()
+            }
+            Some('"') => {
+                let string = self.string()?;
+                Ok(charlist(self.proc, &string))
+            }
+            Some('\'') => {
+                self.next();
+                let name = self.quoted('\'')?;
+                self.atom(&name)
+            }
+            Some('$') => {
+                self.next();
+                let c = self.char()?;
+                Ok(Term::Int(c as i64).into())
+            }
+            Some(c) if c == '-' || c == '+' || c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_lowercase() => {
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '@');
+                self.atom(&name)
+            }
+            Some(c) => Err(self.error(format!("unexpected '{}'", c))),
+        }
+    }
+
+    /// Parses the rest of a list, after its `[`
+    fn list(&mut self) -> Result<OpaqueTerm, ParseError> {
+        self.skip_blank();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(OpaqueTerm::NIL);
+        }
+        let mut elements = vec![self.term()?];
+        let mut tail = OpaqueTerm::NIL;
+        loop {
+            self.skip_blank();
+            match self.next() {
+                Some(',') => elements.push(self.term()?),
+                Some('|') => {
+                    tail = self.term()?;
+                    self.expect(']')?;
+                    break;
+                }
+                Some(']') => break,
+                _ => return Err(self.error("expected ',', '|' or ']' in list".to_string())),
+            }
+        }
+        Ok(elements
+            .into_iter()
+            .rev()
+            .fold(tail, |tail, head| make_cons(self.proc, head, tail)))
+    }
+
+    /// Parses the rest of a map, after its `#{`
+    fn map(&mut self) -> Result<OpaqueTerm, ParseError> {
+        let mut pairs = vec![];
+        self.skip_blank();
+        if self.peek() == Some('}') {
+            self.next();
+        } else {
+            loop {
+                let key = self.term()?;
+                self.expect('=')?;
+                self.expect('>')?;
+                let value = self.term()?;
+                pairs.push((key.into(), value.into()));
+                self.skip_blank();
+                match self.next() {
+                    Some(',') => continue,
+                    Some('}') => break,
+                    _ => return Err(self.error("expected ',' or '}' in map".to_string())),
+                }
+            }
+        }
+        Ok(Map::new_from_iter_in(pairs.into_iter(), self.proc)
+            .unwrap()
+            .into())
+    }
+
+    /// Parses the rest of a binary, after its `<<`, whose segments are strings or bytes
+    fn binary(&mut self) -> Result<OpaqueTerm, ParseError> {
+        let mut bytes = vec![];
+        self.skip_blank();
+        if self.peek() == Some('>') {
+            self.next();
+            self.expect('>')?;
+            return Ok(make_binary(self.proc, &bytes));
+        }
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                Some('"') => {
+                    let string = self.string()?;
+                    // As in Erlang, each character of a string segment is truncated to a byte
+                    bytes.extend(string.chars().map(|c| c as u32 as u8));
+                }
+                Some(c) if c.is_ascii_digit() => {
+                    let byte = self.take_while(|c| c.is_ascii_digit());
+                    match byte.parse::<u8>() {
+                        Ok(byte) => bytes.push(byte),
+                        Err(_) => return Err(self.error(format!("invalid byte {}", byte))),
+                    }
+                }
+                _ => return Err(self.error("expected a string or byte in binary".to_string())),
+            }
+            self.skip_blank();
+            match self.next() {
+                Some(',') => continue,
+                Some('>') => {
+                    self.expect('>')?;
+                    break;
+                }
+                _ => return Err(self.error("expected ',' or '>>' in binary".to_string())),
+            }
+        }
+        Ok(make_binary(self.proc, &bytes))
+    }
+
+    /// Parses terms separated by commas, up to and including `close`
+    fn sequence(&mut self, close: char) -> Result<Vec<OpaqueTerm>, ParseError> {
+        let mut elements = vec![];
+        self.skip_blank();
+        if self.peek() == Some(close) {
+            self.next();
+            return Ok(elements);
+        }
+        loop {
+            elements.push(self.term()?);
+            self.skip_blank();
+            match self.next() {
+                Some(',') => continue,
+                Some(c) if c == close => return Ok(elements),
+                _ => return Err(self.error(format!("expected ',' or '{}'", close))),
+            }
+        }
+    }
+
+    fn atom(&self, name: &str) -> Result<OpaqueTerm, ParseError> {
+        match Atom::from_str(name) {
+            Ok(atom) => Ok(atom.into()),
+            Err(_) => Err(self.error(format!("invalid atom '{}'", name))),
+        }
+    }
+
+    /// Parses an integer, which may be given in a base as `Base#Digits`, or a float
+    fn number(&mut self) -> Result<OpaqueTerm, ParseError> {
+        let mut text = String::new();
+        if let Some(sign @ ('-' | '+')) = self.peek() {
+            self.next();
+            text.push(sign);
+        }
+        let digits = self.take_while(|c| c.is_ascii_digit() || c == '_');
+        if digits.is_empty() {
+            return Err(self.error("expected a number".to_string()));
+        }
+        text.push_str(&digits.replace('_', ""));
+
+        let integer = if self.peek() == Some('#') {
+            self.next();
+            let base = text.trim_start_matches(['-', '+']).parse::<u32>().ok();
+            let digits = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            let digits = digits.replace('_', "");
+            let negative = text.starts_with('-');
+            base.filter(|base| (2..=36).contains(base))
+                .and_then(|base| Integer::from_string_radix(&digits, base))
+                .map(|integer| if negative { -integer } else { integer })
+        } else if self.peek() == Some('.') && self.peek_at(1).map_or(false, |c| c.is_ascii_digit())
+        {
+            self.next();
+            text.push('.');
+            text.push_str(&self.take_while(|c| c.is_ascii_digit()));
+            if let Some('e' | 'E') = self.peek() {
+                self.next();
+                text.push('e');
+                if let Some(sign @ ('-' | '+')) = self.peek() {
+                    self.next();
+                    text.push(sign);
+                }
+                text.push_str(&self.take_while(|c| c.is_ascii_digit()));
+            }
+            return match text.parse::<f64>() {
+                Ok(float) if float.is_finite() => Ok(float.into()),
+                _ => Err(self.error(format!("invalid float {}", text))),
+            };
+        } else {
+            text.parse::<Integer>().ok()
+        };
+        match integer {
+            Some(integer) => Ok(make_integer(self.proc, integer)),
+            None => Err(self.error(format!("invalid integer {}", text))),
+        }
+    }
+
+    /// Parses a string, concatenating adjacent strings as the compiler does
+    fn string(&mut self) -> Result<String, ParseError> {
+        let mut string = String::new();
+        while self.peek() == Some('"') {
+            self.next();
+            string.push_str(&self.quoted('"')?);
+            self.skip_blank();
+        }
+        Ok(string)
+    }
+
+    /// Parses the rest of a string or quoted atom, up to and including `quote`
+    fn quoted(&mut self, quote: char) -> Result<String, ParseError> {
+        let mut string = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error(format!("missing terminating {}", quote))),
+                Some(c) if c == quote => {
+                    self.next();
+                    return Ok(string);
+                }
+                Some(_) => string.push(self.char()?),
+            }
+        }
+    }
+
+    /// Parses a character, which may be an escape sequence
+    fn char(&mut self) -> Result<char, ParseError> {
+        let Some(c) = self.next() else {
+            return Err(self.error("unexpected end of file".to_string()));
+        };
+        if c != '\\' {
+            return Ok(c);
+        }
+        let Some(c) = self.next() else {
+            return Err(self.error("unexpected end of file".to_string()));
+        };
+        let code = match c {
+            'b' => 8,
+            'd' => 127,
+            'e' => 27,
+            'f' => 12,
+            'n' => 10,
+            'r' => 13,
+            's' => 32,
+            't' => 9,
+            'v' => 11,
+            '0'..='7' => {
+                let mut code = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match self.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            self.next();
+                            code = code * 8 + digit;
+                        }
+                        None => break,
+                    }
+                }
+                code
+            }
+            'x' => {
+                let digits = if self.peek() == Some('{') {
+                    self.next();
+                    let digits = self.take_while(|c| c.is_ascii_hexdigit());
+                    self.expect('}')?;
+                    digits
+                } else {
+                    let mut digits = String::new();
+                    while digits.len() < 2 && self.peek().map_or(false, |c| c.is_ascii_hexdigit()) {
+                        digits.push(self.next().unwrap());
+                    }
+                    digits
+                };
+                u32::from_str_radix(&digits, 16).unwrap_or(u32::MAX)
+            }
+            '^' => match self.next() {
+                Some(c) if c.is_ascii_alphabetic() => c as u32 % 32,
+                _ => return Err(self.error("invalid control escape".to_string())),
+            },
+            c => c as u32,
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid character escape".to_string()))
+    }
+
+    /// Skips whitespace and comments, then consumes `expected`
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_blank();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}', found end of file", expected))),
+        }
+    }
+
+    fn skip_blank(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '%' {
+                self.take_while(|c| c != '\n');
+            } else if c.is_whitespace() {
+                self.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.peek().filter(|c| predicate(*c)) {
+            self.next();
+            taken.push(c);
+        }
+        taken
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        (self.line, message)
+    }
+}
@@ -0,0 +1,340 @@
+//! `firefly_diag`, diagnostics of the running system after those of `recon`, for finding the
+//! processes behind a load or a leak, from the shell or over HTTP:
+//!
+//! * `proc_count(Attribute, N)` returns the `N` processes with the largest `Attribute`, largest
+//! first, as `[{Pid, Value, Info}]`, where `Attribute` is `memory`, the size of the heap in bytes,
+//! `reductions`, `message_queue_len`, or `binary_memory`, the size in bytes of the
+//! reference-counted binaries held, and `Info` is `[{initial_call, {M, F, A}}]`.
+//! * `bin_leak(N)` returns the `N` processes holding the most reference-counted binaries, as
+//! `[{Pid, Count, [{binary_memory, Bytes} | Info]}]`.
+//! * `scheduler_usage(Milliseconds)` samples the utilization of the schedulers over the given
+//! interval, returning `[{SchedulerId, Ratio}]`, where `Ratio` is the fraction of the interval
+//! spent running processes, see `Scheduler::wall_time`.
+//!
+//! Processes have no mailbox in this runtime, messages are only ever queued for the servers of the
+//! `gen_*` behaviours, see `super::gen`. `message_queue_len` therefore ranks those servers, with an
+//! `Info` of `[{server, Module}]`, rather than processes.
+//!
+//! In ERTS, `bin_leak` collects every process and reports how many binaries each one released, as
+//! binaries a process holds on to without needing them are released by a collection. There is no
+//! collector here, and a process holds every binary it has allocated until it exits, see
+//! `Process::track_binary`, so the binaries held are reported instead. These are the most a
+//! collection could release, and a process whose count keeps growing is the one leaking.
+//!
+//! When the runtime is started with `+diag Port`, a report of the same diagnostics is served as
+//! plain text over HTTP, see `crate::sys::diagnostics`. The report is refreshed by the scheduler at
+//! most once a second while processes are running, and gives the utilization since the last
+//! refresh.
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use instant::Instant;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::{Process, Resumable, Step, WORD_SIZE};
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::gen;
+use super::util::*;
+
+/// The number of processes listed for each attribute in the report served over HTTP
+const REPORT_TOP: usize = 10;
+/// How long the report is served before it is refreshed
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Whether the report is served over HTTP, and so must be refreshed
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REPORT: OnceLock<Mutex<Option<Report>>> = OnceLock::new();
+
+/// Returns the `N` processes with the largest `Attribute`, largest first
+#[export_name = "firefly_diag:proc_count/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn proc_count2(attribute: OpaqueTerm, n: OpaqueTerm) -> ErlangResult {
+    let attribute = match attribute.into() {
+        Term::Atom(attribute) => Attribute::parse(attribute.as_str()),
+        _ => None,
+    };
+    let (Some(attribute), Some(n)) = (attribute, count(n)) else {
+        return super::badarg(Trace::capture());
+    };
+    let entries = top(attribute, n);
+    ErlangResult::Ok(with_process(|proc| make_entries(proc, &entries)))
+}
+
+/// Returns the `N` processes holding the most reference-counted binaries
+#[export_name = "firefly_diag:bin_leak/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn bin_leak1(n: OpaqueTerm) -> ErlangResult {
+    let Some(n) = count(n) else { return super::badarg(Trace::capture()) };
+    let entries = top(Attribute::Binaries, n);
+    ErlangResult::Ok(with_process(|proc| make_entries(proc, &entries)))
+}
+
+/// Samples the utilization of the schedulers for `Milliseconds`, returning `[{Id, Ratio}]`
+///
+/// The calling process yields until the interval has passed, and the time it spends checking
+/// whether it has is not counted as running. On wasm32, where processes cannot yield, nothing
+/// else runs in the meantime.
+#[export_name = "firefly_diag:scheduler_usage/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn scheduler_usage1(ms: OpaqueTerm) -> ErlangResult {
+    let ms = match ms.into() {
+        Term::Int(ms) if ms > 0 => ms as u64,
+        _ => return super::badarg(Trace::capture()),
+    };
+    let usage = scheduler::trampoline(Sample {
+        deadline: Instant::now() + Duration::from_millis(ms),
+        start: wall_time(),
+    });
+    ErlangResult::Ok(with_process(|proc| {
+        let scheduler = make_tuple(proc, &[Term::Int(1).into(), usage.into()]);
+        make_list(proc, &[scheduler])
+    }))
+}
+
+/// Serves the report over HTTP, refreshing it as processes run, see `crate::sys::diagnostics`
+pub(crate) fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Refreshes the report served over HTTP, if it is served and is due to be
+///
+/// This is called by the scheduler each time a process yields to it.
+pub(crate) fn poll() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut report = report();
+    if matches!(report.as_ref(), Some(report) if report.at.elapsed() < REFRESH) {
+        return;
+    }
+    let last = report.as_ref().map_or((0, 0), |report| report.wall_time);
+    let wall_time = wall_time();
+    *report = Some(Report {
+        at: Instant::now(),
+        wall_time,
+        text: format_report(usage(last, wall_time)),
+    });
+}
+
+/// Returns the latest report, as served over HTTP
+pub(crate) fn latest_report() -> String {
+    report().as_ref().map_or_else(
+        || "# no report yet, as no process has run\n".to_string(),
+        |report| report.text.clone(),
+    )
+}
+
+/// The report served over HTTP, and when it was made
+struct Report {
+    at: Instant,
+    /// The wall time of the scheduler when the report was made, from which the utilization in
+    /// the next report is measured
+    wall_time: (u64, u64),
+    text: String,
+}
+
+fn report() -> MutexGuard<'static, Option<Report>> {
+    REPORT.get_or_init(|| Mutex::new(None)).lock().unwrap()
+}
+
+fn format_report(usage: f64) -> String {
+    let mut text = String::new();
+    let processes = processes();
+    writeln!(text, "scheduler_usage {:.4}", usage).unwrap();
+    writeln!(text, "processes {}", processes.len()).unwrap();
+    for attribute in Attribute::ALL {
+        writeln!(text).unwrap();
+        writeln!(text, "# top {} by {}", REPORT_TOP, attribute.name()).unwrap();
+        for entry in top(attribute, REPORT_TOP) {
+            let pid = Pid::Local { id: entry.pid };
+            write!(text, "{} {}", pid, entry.value).unwrap();
+            if let Some(bytes) = entry.binary_memory {
+                write!(text, " {}", bytes).unwrap();
+            }
+            match entry.origin {
+                Origin::Process(mfa) => writeln!(text, " {}", mfa).unwrap(),
+                Origin::Server(module) => writeln!(text, " server {}", module).unwrap(),
+            }
+        }
+    }
+    text
+}
+
+/// An attribute by which processes are ranked
+#[derive(Copy, Clone)]
+enum Attribute {
+    Memory,
+    Reductions,
+    MessageQueueLen,
+    BinaryMemory,
+    /// The number of reference-counted binaries held, as ranked by `bin_leak/1`
+    Binaries,
+}
+impl Attribute {
+    /// The attributes in the order they are reported over HTTP
+    const ALL: [Self; 5] = [
+        Self::Memory,
+        Self::Reductions,
+        Self::MessageQueueLen,
+        Self::BinaryMemory,
+        Self::Binaries,
+    ];
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "memory" => Some(Self::Memory),
+            "reductions" => Some(Self::Reductions),
+            "message_queue_len" => Some(Self::MessageQueueLen),
+            "binary_memory" => Some(Self::BinaryMemory),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Memory => "memory",
+            Self::Reductions => "reductions",
+            Self::MessageQueueLen => "message_queue_len",
+            Self::BinaryMemory => "binary_memory",
+            Self::Binaries => "binaries",
+        }
+    }
+
+    fn of(self, process: &Process) -> u64 {
+        let (binaries, binary_memory) = process.binaries();
+        match self {
+            Self::Memory => (process.heap_size() * WORD_SIZE) as u64,
+            Self::Reductions => process.reductions(),
+            Self::MessageQueueLen => 0,
+            Self::BinaryMemory => binary_memory as u64,
+            Self::Binaries => binaries as u64,
+        }
+    }
+}
+
+/// A process ranked by an attribute
+struct Entry {
+    pid: ProcessId,
+    value: u64,
+    origin: Origin,
+    /// The size of the binaries held, when ranked by their number
+    binary_memory: Option<usize>,
+}
+
+/// What a ranked process is running
+enum Origin {
+    /// A process, and the function it was spawned with
+    Process(ModuleFunctionArity),
+    /// A server, and the module implementing it
+    Server(Atom),
+}
+
+/// Returns the `n` processes with the largest `attribute`, largest first
+fn top(attribute: Attribute, n: usize) -> Vec<Entry> {
+    let mut entries = match attribute {
+        Attribute::MessageQueueLen => gen::mailboxes()
+            .into_iter()
+            .map(|(pid, module, len)| Entry {
+                pid,
+                value: len as u64,
+                origin: Origin::Server(module),
+                binary_memory: None,
+            })
+            .collect::<Vec<_>>(),
+        _ => processes()
+            .iter()
+            .map(|process| Entry {
+                pid: process.pid(),
+                value: attribute.of(process),
+                origin: Origin::Process(process.initial_call()),
+                binary_memory: match attribute {
+                    Attribute::Binaries => Some(process.binaries().1),
+                    _ => None,
+                },
+            })
+            .collect(),
+    };
+    entries.sort_by(|a, b| b.value.cmp(&a.value).then(a.pid.cmp(&b.pid)));
+    entries.truncate(n);
+    entries
+}
+
+/// Returns every live process, in the order of their pids
+fn processes() -> Vec<Arc<Process>> {
+    scheduler::with_current(|scheduler| {
+        let mut processes = vec![];
+        let mut last = None;
+        while let Some(pid) = scheduler.next_process(last) {
+            processes.extend(scheduler.process(pid));
+            last = Some(pid);
+        }
+        processes
+    })
+}
+
+fn make_entries(proc: &Process, entries: &[Entry]) -> OpaqueTerm {
+    let entries = entries
+        .iter()
+        .map(|entry| {
+            let origin = match entry.origin {
+                Origin::Process(mfa) => firefly_rt::term!(proc, {
+                    initial_call,
+                    {(mfa.module), (mfa.function), (mfa.arity)}
+                }),
+                Origin::Server(module) => firefly_rt::term!(proc, {server, (module)}),
+            };
+            let mut info = vec![origin.unwrap().into()];
+            if let Some(bytes) = entry.binary_memory {
+                let bytes = firefly_rt::term!(proc, {binary_memory, (bytes)}).unwrap();
+                info.insert(0, bytes.into());
+            }
+            let info = make_list(proc, &info);
+            let value = entry.value.into_term(proc).unwrap().into();
+            make_tuple(proc, &[make_pid(proc, entry.pid), value, info])
+        })
+        .collect::<Vec<_>>();
+    make_list(proc, &entries)
+}
+
+/// Waits for a deadline by yielding, returning the utilization of the scheduler in the meantime
+struct Sample {
+    deadline: Instant,
+    /// The wall time of the scheduler when sampling started
+    start: (u64, u64),
+}
+impl Resumable for Sample {
+    type Output = f64;
+
+    fn resume(&mut self, budget: &mut usize) -> Step<Self::Output> {
+        scheduler::with_current(|scheduler| scheduler.exclude_slice());
+        if Instant::now() < self.deadline {
+            *budget = 0;
+            return Step::Yield;
+        }
+        Step::Done(usage(self.start, wall_time()))
+    }
+}
+
+fn wall_time() -> (u64, u64) {
+    scheduler::with_current(|scheduler| scheduler.wall_time())
+}
+
+/// Returns the fraction of the time between two wall times spent running processes
+fn usage((busy0, total0): (u64, u64), (busy1, total1): (u64, u64)) -> f64 {
+    let total = total1.saturating_sub(total0).max(1);
+    busy1.saturating_sub(busy0) as f64 / total as f64
+}
+
+/// Returns `n` as a count of processes, if it is a non-negative integer
+fn count(n: OpaqueTerm) -> Option<usize> {
+    match n.into() {
+        Term::Int(n) if n >= 0 => Some(n as usize),
+        _ => None,
+    }
+}
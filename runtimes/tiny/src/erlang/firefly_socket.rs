@@ -0,0 +1,145 @@
+//! `firefly_socket`, the sockets inherited from the process which started the runtime, for services
+//! started by systemd socket activation or by inetd, see `crate::sys::activation`:
+//!
+//! * `listen_fds()` returns the inherited sockets, in the order they were passed on, as
+//! `[{Fd, Name, Kind}]`, where `Name` is the binary given to the socket by `FileDescriptorName=`,
+//! `<<"unknown">>` if none was given, or `<<"stdin">>` for the socket inherited from inetd, and
+//! `Kind` is `listen`, `stream` for a connection, `dgram`, `seqpacket`, or `other` if the
+//! descriptor is not a socket.
+//! * `listen_fd(Name)` returns `{ok, Fd}` for the first inherited socket named `Name`, a binary,
+//! string or atom, or `error` if there is none.
+//!
+//! The sockets are already bound, and those of kind `listen` are already listening, so `Fd` is what
+//! `gen_tcp:listen(0, [{fd, Fd}])` and `socket:open(Fd)` expect in ERTS, and what a driver or NIF
+//! serving them is handed in this runtime, e.g. with `open_port({spawn_driver, "my_tcp 3"}, [])`.
+//! The descriptors are not closed by the runtime, as the service manager holds on to the sockets
+//! across restarts of the service.
+//!
+//! It also drains connections, so that a service can be stopped without dropping the requests it is
+//! serving, see `crate::sys::drain`:
+//!
+//! * `listener(Port)` marks `Port` as accepting connections, so that it is closed once draining
+//! starts, or right away if it already has.
+//! * `drain(Timeout)` closes the listeners, then waits for the other ports to be closed by their
+//! owners, for at most `Timeout` milliseconds or `infinity`. It returns `ok` once every port is
+//! closed, or `{timeout, Ports}` if `Ports` were still open at the deadline, and were closed.
+//! * `draining()` returns true once draining has started, so that servers can stop keeping
+//! connections alive, e.g. by answering with `Connection: close`.
+//!
+//! The same happens when the runtime is asked to shut down by `SIGTERM` or `SIGINT`, with the
+//! timeout given by `+drain`. Closing a listener inherited from the service manager only closes the
+//! descriptor of the runtime, so connections queue up on the socket for the next instance.
+use std::thread;
+use std::time::Duration;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::{Resumable, Step};
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::sys::{activation, drain};
+
+use super::port;
+use super::util::*;
+
+/// How long a process draining connections waits for ports at a time, before yielding
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+#[export_name = "firefly_socket:listen_fds/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn listen_fds0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        let sockets = activation::inherited()
+            .iter()
+            .map(|socket| {
+                let name = make_binary(proc, socket.name.as_bytes());
+                let kind = atom(socket.kind.as_str()).into();
+                make_tuple(proc, &[Term::Int(socket.fd as i64).into(), name, kind])
+            })
+            .collect::<Vec<_>>();
+        make_list(proc, &sockets)
+    }))
+}
+
+#[export_name = "firefly_socket:listen_fd/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn listen_fd1(name: OpaqueTerm) -> ErlangResult {
+    let name = match name.into() {
+        Term::Atom(name) => Some(name.as_str().as_bytes().to_vec()),
+        _ => iodata_to_bytes(name),
+    };
+    let Some(name) = name else { return super::badarg(Trace::capture()); };
+    let socket = activation::inherited()
+        .iter()
+        .find(|socket| socket.name.as_bytes() == name.as_slice());
+    match socket {
+        Some(socket) => ErlangResult::Ok(with_process(|proc| {
+            make_tuple(
+                proc,
+                &[atoms::Ok.into(), Term::Int(socket.fd as i64).into()],
+            )
+        })),
+        None => ErlangResult::Ok(atoms::Error.into()),
+    }
+}
+
+#[export_name = "firefly_socket:listener/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn listener1(port: OpaqueTerm) -> ErlangResult {
+    match port::port_id(port) {
+        Some(id) if firefly_driver::owner(id).is_some() => {
+            drain::add_listener(id);
+            ErlangResult::Ok(atoms::Ok.into())
+        }
+        _ => super::badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "firefly_socket:drain/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn drain1(timeout: OpaqueTerm) -> ErlangResult {
+    let Some(timeout) = timeout_ms(timeout) else { return super::badarg(Trace::capture()); };
+    drain::start(timeout.map(Duration::from_millis));
+    let forced = scheduler::trampoline(Drain);
+    if forced.is_empty() {
+        return ErlangResult::Ok(atoms::Ok.into());
+    }
+    ErlangResult::Ok(with_process(|proc| {
+        let ports = forced
+            .iter()
+            .map(|&id| make_port(proc, id))
+            .collect::<Vec<_>>();
+        let ports = make_list(proc, &ports);
+        make_tuple(proc, &[atom("timeout").into(), ports])
+    }))
+}
+
+#[export_name = "firefly_socket:draining/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn draining0() -> ErlangResult {
+    ErlangResult::Ok(drain::is_draining().into())
+}
+
+/// Waits for every port to be closed by yielding, returning the ports closed at the deadline
+///
+/// The drivers are polled while waiting, as the init process cannot while this process runs, so
+/// that the owners of the connections receive their messages and finish their requests.
+struct Drain;
+impl Resumable for Drain {
+    type Output = Vec<PortId>;
+
+    fn resume(&mut self, budget: &mut usize) -> Step<Self::Output> {
+        let left = drain::poll();
+        if !firefly_driver::is_active() {
+            return Step::Done(drain::take_forced());
+        }
+        let timeout = left.map_or(DRAIN_INTERVAL, |left| left.min(DRAIN_INTERVAL));
+        if !firefly_driver::poll(Some(timeout)) {
+            thread::sleep(timeout);
+        }
+        port::deliver();
+        *budget = 0;
+        Step::Yield
+    }
+}
@@ -0,0 +1,793 @@
+//! Shared infrastructure for the `gen_server`, `gen_statem` and `supervisor` behaviours.
+//!
+//! Processes have no mailbox in this runtime, and cannot receive, so instances of these behaviours
+//! ("servers") cannot be processes of their own. Instead, each server is an entry in a registry
+//! owned by the runtime, is identified by a pid allocated for it, and has its callbacks invoked in
+//! whichever process calls, casts or sends to it:
+//!
+//! * Messages delivered to a server are placed in its mailbox, which is drained as soon as the
+//!   server is not executing a callback. A request (`call`) is delivered as a `$gen_call` with a
+//!   `From` of the form `{Pid, Tag}`, and completes once the mailbox is drained. The reply may be
+//!   returned by the callback, or sent explicitly with `reply/2` from any callback executed before
+//!   the request completes; a request which has not been replied to at that point fails with
+//!   `timeout`.
+//! * Messages sent to a server while it is executing a callback, e.g. a cast to itself, are handled
+//!   in order as soon as that callback returns. A request to a server which is executing a callback
+//!   would deadlock in OTP, and here fails with `calling_self`.
+//! * Timeouts which have expired are handled once a server's mailbox is empty. Any remaining timers
+//!   are run by [`run_timers`] after the boot function returns, which keeps the system alive while
+//!   any server has a pending timeout, or any port is open, delivering data which becomes available
+//!   on ports. In the browser, they are instead run from the event loop, see the `web` module.
+//!   Timers measure virtual time in a simulation, see `crate::sim`.
+//! * A server which returns a stop result, or raises an exception from a callback, terminates, and
+//!   its supervisor (if started via `start_link` by a supervisor) is notified so it can apply its
+//!   restart strategy. Links to anything other than a supervisor are not modeled.
+//! * A suspended server handles no messages until it is resumed, see `super::suspend`.
+//!
+//! Special processes built directly on `proc_lib`/`sys` are not supported, as they require a
+//! receive loop of their own.
+//!
+//! Terms held by servers are copied to heap fragments which are never freed, as in the application
+//! controller.
+use std::collections::{BTreeMap, VecDeque};
+use std::ptr::NonNull;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use instant::Instant;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::sim;
+
+#[cfg(feature = "test")]
+use super::erts_debug::timer_duration;
+use super::gen_server::ServerState;
+use super::gen_statem::StatemState;
+use super::seq_trace::{self, Token};
+use super::supervisor::SupervisorState;
+use super::util::*;
+
+static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+/// A registered name, as given by `{local, Name}` or `{global, Name}`
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Name {
+    Local(Atom),
+    Global(Atom),
+}
+impl Name {
+    /// Parses a server name given to one of the `start` functions
+    pub(crate) fn parse(term: OpaqueTerm) -> Option<Self> {
+        match tuple_elements(term)? {
+            [scope, name] => match ((*scope).into(), (*name).into()) {
+                (Term::Atom(scope), Term::Atom(name)) if scope.as_str() == "local" => {
+                    Some(Self::Local(name))
+                }
+                (Term::Atom(scope), Term::Atom(name)) if scope.as_str() == "global" => {
+                    Some(Self::Global(name))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The callback state of a server, by behaviour
+pub(crate) enum Behaviour {
+    Server(ServerState),
+    Statem(StatemState),
+    Supervisor(SupervisorState),
+}
+
+/// The kinds of timeout a server may have pending
+#[derive(Copy, Clone)]
+pub(crate) enum TimerKind {
+    /// A `gen_server` timeout, or a `gen_statem` event timeout, cancelled by any message
+    Event,
+    /// A `gen_statem` state timeout, cancelled by a change of state
+    State,
+    /// A named `gen_statem` generic timeout
+    Generic(OpaqueTerm),
+}
+impl TimerKind {
+    fn is(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Event, Self::Event) | (Self::State, Self::State) => true,
+            (Self::Generic(a), Self::Generic(b)) => equals(*a, *b),
+            _ => false,
+        }
+    }
+}
+
+pub(crate) enum Message {
+    /// A request, i.e. `{'$gen_call', From, Request}`
+    Call {
+        from: OpaqueTerm,
+        request: OpaqueTerm,
+    },
+    /// A cast, i.e. `{'$gen_cast', Message}`
+    Cast(OpaqueTerm),
+    /// Any other message
+    Info(OpaqueTerm),
+    /// An expired timer
+    Timeout { kind: TimerKind, msg: OpaqueTerm },
+    /// Resumes work deferred by a callback before any other message is handled, i.e. `{continue,
+    /// C}` for `gen_server`, or events inserted by `gen_statem` actions
+    Continue(OpaqueTerm),
+    /// Notifies a supervisor that one of its children terminated
+    Exit { from: ProcessId, reason: OpaqueTerm },
+}
+
+/// The result of handling a message
+pub(crate) enum Outcome {
+    Continue(Behaviour),
+    Stop(OpaqueTerm, Behaviour),
+}
+
+/// The result of initializing a server
+pub(crate) enum Started {
+    Ok(Behaviour),
+    Ignore,
+    Stop(OpaqueTerm),
+}
+
+/// The reason a server could not be used
+pub(crate) enum Unavailable {
+    NoProc,
+    /// The server is executing a callback further up the stack
+    Busy,
+}
+
+struct Server {
+    module: Atom,
+    name: Option<Name>,
+    supervisor: Option<ProcessId>,
+    /// The callback state, or `None` while the server is executing a callback
+    behaviour: Option<Behaviour>,
+    /// The messages waiting to be handled, with the sequential trace tokens they carry
+    mailbox: VecDeque<(Message, Option<Token>)>,
+}
+
+struct Timer {
+    id: u64,
+    deadline: Instant,
+    server: ProcessId,
+    kind: TimerKind,
+    msg: OpaqueTerm,
+}
+
+#[derive(Default)]
+struct Registry {
+    servers: BTreeMap<ProcessId, Server>,
+    names: BTreeMap<Name, ProcessId>,
+    /// Requests awaiting a reply, by tag, and the reply if one has been sent
+    pending: BTreeMap<ReferenceId, Option<OpaqueTerm>>,
+    timers: Vec<Timer>,
+    next_timer_id: u64,
+    /// The supervisor starting children, if any, which children started with `start_link` attach
+    /// to
+    parent: Option<ProcessId>,
+}
+impl Registry {
+    fn remove(&mut self, pid: ProcessId) -> Option<Server> {
+        let server = self.servers.remove(&pid)?;
+        if let Some(name) = server.name {
+            self.names.remove(&name);
+        }
+        self.timers.retain(|timer| timer.server != pid);
+        Some(server)
+    }
+}
+
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY
+        .get_or_init(|| Mutex::new(Registry::default()))
+        .lock()
+        .unwrap()
+}
+
+/// Starts a new server, registering it under `name`, and initializing it with `init`
+///
+/// Returns `{ok, Pid}`, `ignore`, or `{error, Reason}` as in OTP. If `link` is true and the server
+/// is being started by a supervisor, the server becomes a child of that supervisor.
+pub(crate) fn start<F>(name: Option<Name>, module: Atom, link: bool, init: F) -> ErlangResult
+where
+    F: FnOnce(ProcessId) -> Result<Started, NonNull<ErlangException>>,
+{
+    let (pid, parent) = {
+        let mut registry = registry();
+        if let Some(existing) = name.and_then(|name| registry.names.get(&name).copied()) {
+            let reason = with_process(|proc| {
+                let pid = make_pid(proc, existing);
+                make_tuple(proc, &[atom("already_started").into(), pid])
+            });
+            return error(reason);
+        }
+        let pid = ProcessId::next();
+        let supervisor = if link { registry.parent } else { None };
+        registry.servers.insert(
+            pid,
+            Server {
+                module,
+                name,
+                supervisor,
+                behaviour: None,
+                mailbox: VecDeque::new(),
+            },
+        );
+        if let Some(name) = name {
+            registry.names.insert(name, pid);
+        }
+        // Servers started during initialization are not children of our supervisor
+        (pid, registry.parent.take())
+    };
+
+    let result = init(pid);
+    registry().parent = parent;
+
+    let reason = match result {
+        Ok(Started::Ok(behaviour)) => {
+            if let Some(server) = registry().servers.get_mut(&pid) {
+                server.behaviour = Some(behaviour);
+            }
+            let _ = drain(pid);
+            return ErlangResult::Ok(with_process(|proc| {
+                let pid = make_pid(proc, pid);
+                make_tuple(proc, &[atoms::Ok.into(), pid])
+            }));
+        }
+        Ok(Started::Ignore) => {
+            registry().remove(pid);
+            return ErlangResult::Ok(atom("ignore").into());
+        }
+        Ok(Started::Stop(reason)) => reason,
+        Err(exception) => exit_reason(exception),
+    };
+    registry().remove(pid);
+    error(reason)
+}
+
+/// Runs `fun` with `parent` as the supervisor of any servers started with `start_link`
+pub(crate) fn with_parent<F, R>(parent: ProcessId, fun: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let prev = registry().parent.replace(parent);
+    let result = fun();
+    registry().parent = prev;
+    result
+}
+
+/// Resolves a server reference, i.e. a pid, registered name, or `{global, Name}`
+pub(crate) fn whereis(server: OpaqueTerm) -> Option<ProcessId> {
+    let registry = registry();
+    let pid = match server.into() {
+        Term::Pid(pid) => pid.id(),
+        Term::Atom(name) => *registry.names.get(&Name::Local(name))?,
+        Term::Tuple(_) => *registry.names.get(&Name::parse(server)?)?,
+        _ => return None,
+    };
+    registry.servers.contains_key(&pid).then_some(pid)
+}
+
+/// Returns the module implementing the given server
+pub(crate) fn module(pid: ProcessId) -> Option<Atom> {
+    registry().servers.get(&pid).map(|server| server.module)
+}
+
+/// Returns the pid, module and number of messages waiting in the mailbox of every server
+pub(crate) fn mailboxes() -> Vec<(ProcessId, Atom, usize)> {
+    registry()
+        .servers
+        .iter()
+        .map(|(pid, server)| (*pid, server.module, server.mailbox.len()))
+        .collect()
+}
+
+/// Sends a request to `server`, returning the reply
+///
+/// `location` is used to construct the exit reason on failure, e.g. `{gen_server, call, [S, R]}`.
+pub(crate) fn call(
+    server: OpaqueTerm,
+    request: OpaqueTerm,
+    location: OpaqueTerm,
+) -> Result<OpaqueTerm, NonNull<ErlangException>> {
+    let Some(pid) = whereis(server) else {
+        return Err(exit_err(reason2("noproc", location)));
+    };
+    if is_busy(pid) {
+        return Err(exit_err(reason2("calling_self", location)));
+    }
+
+    let (tag, from) = with_process(|proc| {
+        let tag = make_ref(proc);
+        let pid = make_pid(proc, proc.pid());
+        (tag, make_tuple(proc, &[pid, tag]))
+    });
+    let tag = reference_id(tag).unwrap();
+    registry().pending.insert(tag, None);
+    send(
+        pid,
+        Message::Call {
+            from: make_global(from),
+            request: make_global(request),
+        },
+    );
+    let result = drain(pid);
+
+    let reply = registry().pending.remove(&tag).flatten();
+    match (reply, result) {
+        (Some(reply), _) => Ok(reply),
+        (None, Err(reason)) => Err(exit_err(with_process(|proc| {
+            make_tuple(proc, &[reason, location])
+        }))),
+        (None, Ok(())) => Err(exit_err(reason2("timeout", location))),
+    }
+}
+
+/// Sends `reply` to the client which made the request identified by `from`
+///
+/// Replies to requests which have already completed are discarded, as in OTP.
+pub(crate) fn reply(from: OpaqueTerm, reply: OpaqueTerm) -> bool {
+    let Some([_pid, tag]) = tuple_elements(from) else {
+        return false;
+    };
+    let Some(tag) = reference_id(*tag) else {
+        return false;
+    };
+    if let Some(slot @ None) = registry().pending.get_mut(&tag) {
+        *slot = Some(make_global(reply));
+    }
+    true
+}
+
+/// Delivers `message` to `pid`, and handles it unless the server is executing a callback
+pub(crate) fn cast(pid: ProcessId, message: Message) {
+    send(pid, message);
+    let _ = drain(pid);
+}
+
+/// Places `message` in the mailbox of `pid`, returning false if there is no such server
+pub(crate) fn send(pid: ProcessId, message: Message) -> bool {
+    super::trace::receive(pid, &message);
+    let token = seq_trace::send(pid, &message);
+    let mut registry = registry();
+    let Some(server) = registry.servers.get_mut(&pid) else {
+        return false;
+    };
+    server.mailbox.push_back((message, token));
+    true
+}
+
+/// Places `message` at the front of the mailbox of `pid`, so that it is handled next
+///
+/// The message is deferred work of the server itself, so it carries the current token as is.
+pub(crate) fn send_first(pid: ProcessId, message: Message) {
+    let token = seq_trace::token();
+    if let Some(server) = registry().servers.get_mut(&pid) {
+        server.mailbox.push_front((message, token));
+    }
+}
+
+/// Handles the messages which arrived in the mailbox of `pid` while it was suspended
+pub(crate) fn resume(pid: ProcessId) {
+    let _ = drain(pid);
+}
+
+/// Returns true if `pid` is executing a callback
+pub(crate) fn is_busy(pid: ProcessId) -> bool {
+    registry()
+        .servers
+        .get(&pid)
+        .map(|server| server.behaviour.is_none())
+        .unwrap_or(false)
+}
+
+/// Handles all messages in the mailbox of `pid`, returning the exit reason if it terminates
+///
+/// This does nothing if the server is executing a callback, as its mailbox will be drained when
+/// that callback returns.
+fn drain(pid: ProcessId) -> Result<(), OpaqueTerm> {
+    loop {
+        let (message, token, behaviour) = {
+            let mut registry = registry();
+            let now = sim::now();
+            let Some(server) = registry.servers.get_mut(&pid) else {
+                return Ok(());
+            };
+            if server.behaviour.is_none() || super::suspend::is_suspended(pid) {
+                return Ok(());
+            }
+            let (message, token) = match server.mailbox.pop_front() {
+                Some(entry) => {
+                    // Any message arriving cancels the event timeout
+                    registry
+                        .timers
+                        .retain(|t| t.server != pid || !t.kind.is(&TimerKind::Event));
+                    entry
+                }
+                None => {
+                    let expired = registry
+                        .timers
+                        .iter()
+                        .position(|t| t.server == pid && t.deadline <= now);
+                    match expired {
+                        Some(index) => {
+                            let timer = registry.timers.remove(index);
+                            let message = Message::Timeout {
+                                kind: timer.kind,
+                                msg: timer.msg,
+                            };
+                            (message, None)
+                        }
+                        None => return Ok(()),
+                    }
+                }
+            };
+            let server = registry.servers.get_mut(&pid).unwrap();
+            (message, token, server.behaviour.take().unwrap())
+        };
+
+        seq_trace::receive(pid, token, &message);
+        match seq_trace::with_token(token, || handle(pid, behaviour, message)) {
+            Outcome::Continue(behaviour) => {
+                if let Some(server) = registry().servers.get_mut(&pid) {
+                    server.behaviour = Some(behaviour);
+                }
+            }
+            Outcome::Stop(reason, behaviour) => {
+                terminate(pid, behaviour, reason, true);
+                return Err(reason);
+            }
+        }
+    }
+}
+
+fn handle(pid: ProcessId, behaviour: Behaviour, message: Message) -> Outcome {
+    match behaviour {
+        Behaviour::Server(state) => super::gen_server::handle(pid, state, message),
+        Behaviour::Statem(state) => super::gen_statem::handle(pid, state, message),
+        Behaviour::Supervisor(state) => super::supervisor::handle(pid, state, message),
+    }
+}
+
+/// Runs `fun` with the state of `pid`, as if the server were handling a message
+pub(crate) fn with_state<F, R>(pid: ProcessId, fun: F) -> Result<R, Unavailable>
+where
+    F: FnOnce(Behaviour) -> (Outcome, R),
+{
+    let behaviour = {
+        let mut registry = registry();
+        let Some(server) = registry.servers.get_mut(&pid) else {
+            return Err(Unavailable::NoProc);
+        };
+        server.behaviour.take().ok_or(Unavailable::Busy)?
+    };
+    let (outcome, result) = fun(behaviour);
+    match outcome {
+        Outcome::Continue(behaviour) => {
+            if let Some(server) = registry().servers.get_mut(&pid) {
+                server.behaviour = Some(behaviour);
+            }
+            let _ = drain(pid);
+        }
+        Outcome::Stop(reason, behaviour) => terminate(pid, behaviour, reason, true),
+    }
+    Ok(result)
+}
+
+/// Stops `server` with `reason`, as with `gen_server:stop/3`
+pub(crate) fn stop(
+    server: OpaqueTerm,
+    reason: OpaqueTerm,
+    location: OpaqueTerm,
+) -> Result<(), NonNull<ErlangException>> {
+    let Some(pid) = whereis(server) else {
+        return Err(exit_err(reason2("noproc", location)));
+    };
+    let reason = make_global(reason);
+    match with_state(pid, |behaviour| (Outcome::Stop(reason, behaviour), ())) {
+        Ok(()) => Ok(()),
+        Err(Unavailable::NoProc) => Err(exit_err(reason2("noproc", location))),
+        Err(Unavailable::Busy) => Err(exit_err(reason2("calling_self", location))),
+    }
+}
+
+/// Terminates `pid` with `reason` on behalf of its supervisor, without notifying the supervisor
+///
+/// Returns false if the server is executing a callback, in which case it cannot be terminated.
+pub(crate) fn shutdown(pid: ProcessId, reason: OpaqueTerm) -> bool {
+    let behaviour = {
+        let mut registry = registry();
+        let Some(server) = registry.servers.get_mut(&pid) else {
+            return true;
+        };
+        match server.behaviour.take() {
+            None => return false,
+            Some(behaviour) => behaviour,
+        }
+    };
+    terminate(pid, behaviour, reason, false);
+    true
+}
+
+/// Removes `pid` from the registry, invokes its `terminate` callback, and notifies its supervisor
+fn terminate(pid: ProcessId, behaviour: Behaviour, reason: OpaqueTerm, notify: bool) {
+    let Some(server) = registry().remove(pid) else {
+        return;
+    };
+    super::suspend::exit(pid);
+
+    let result = match behaviour {
+        Behaviour::Server(state) => super::gen_server::terminate(state, reason),
+        Behaviour::Statem(state) => super::gen_statem::terminate(state, reason),
+        Behaviour::Supervisor(state) => super::supervisor::terminate(state, reason),
+    };
+    let reason = match result {
+        Ok(()) => reason,
+        Err(exception) => exit_reason(exception),
+    };
+
+    if !is_normal_exit(reason) {
+        report(pid, &server, reason);
+    }
+
+    if let (true, Some(supervisor)) = (notify, server.supervisor) {
+        cast(supervisor, Message::Exit { from: pid, reason });
+    }
+    super::application::terminated(pid, reason);
+}
+
+fn report(pid: ProcessId, server: &Server, reason: OpaqueTerm) {
+    let name = match server.name {
+        Some(Name::Local(name) | Name::Global(name)) => name.as_str().to_string(),
+        None => Pid::Local { id: pid }.to_string(),
+    };
+    eprintln!(
+        "=ERROR REPORT====\n** Server {} ({}) terminating\n** Reason for termination ==\n** {}",
+        name,
+        server.module.as_str(),
+        Term::from(reason)
+    );
+}
+
+/// Starts a timer of the given kind for `pid`, replacing any existing timer of that kind
+pub(crate) fn start_timer(pid: ProcessId, kind: TimerKind, timeout: u64, msg: OpaqueTerm) {
+    let mut registry = registry();
+    registry
+        .timers
+        .retain(|t| t.server != pid || !t.kind.is(&kind));
+    let id = registry.next_timer_id;
+    registry.next_timer_id += 1;
+    registry.timers.push(Timer {
+        id,
+        deadline: sim::now() + timer_duration(timeout),
+        server: pid,
+        kind,
+        msg: make_global(msg),
+    });
+}
+
+/// Returns how long a timer of `timeout` milliseconds runs for
+#[cfg(not(feature = "test"))]
+fn timer_duration(timeout: u64) -> Duration {
+    Duration::from_millis(timeout)
+}
+
+/// Cancels the timer of the given kind for `pid`, if one is running
+pub(crate) fn cancel_timer(pid: ProcessId, kind: TimerKind) {
+    registry()
+        .timers
+        .retain(|t| t.server != pid || !t.kind.is(&kind));
+}
+
+/// Handles timeouts as they expire, until no server has a pending timeout
+///
+/// While any port is open, this also waits for its driver to become ready, delivering the messages
+/// it produces, and keeps running until the port is closed or nothing can wake it.
+///
+/// This is run by the `init` process once the boot function returns.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn run_timers() {
+    loop {
+        // Once asked to shut down, the init process returns as soon as the ports are drained
+        let drain = crate::sys::drain::poll();
+        crate::sys::signals::deliver();
+        if crate::sys::drain::shutdown_requested() && !firefly_driver::is_active() {
+            break;
+        }
+        let next = next_timer();
+
+        if firefly_driver::is_active() {
+            let timeout = next.map(|(_, deadline)| deadline.saturating_duration_since(sim::now()));
+            let timeout = match (timeout, drain) {
+                (Some(timeout), Some(drain)) => Some(timeout.min(drain)),
+                (timeout, drain) => timeout.or(drain),
+            };
+            let waited = idle(|| firefly_driver::poll(timeout));
+            super::port::deliver();
+            if !waited {
+                break;
+            }
+        }
+        let Some((id, deadline)) = next else {
+            if firefly_driver::is_active() {
+                continue;
+            }
+            break;
+        };
+
+        let now = sim::now();
+        if deadline > now {
+            if firefly_driver::is_active() {
+                continue;
+            }
+            idle(|| sim::sleep(deadline - now));
+        }
+
+        expire(id);
+    }
+}
+
+/// Handles the timeouts which have expired, returning the time until the next one expires, if any
+///
+/// This is used where the timers are not run by the init process, i.e. in the browser, where this
+/// is called from the event loop, and to implement [`run_timers`] on WASI, where there are no
+/// ports.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn run_expired_timers() -> Option<Duration> {
+    loop {
+        let (id, deadline) = next_timer()?;
+        let now = sim::now();
+        if deadline > now {
+            return Some(deadline - now);
+        }
+        expire(id);
+    }
+}
+
+/// Handles timeouts as they expire, until no server has a pending timeout
+///
+/// This is run by the `init` process once the boot function returns.
+#[cfg(target_os = "wasi")]
+pub(crate) fn run_timers() {
+    while let Some(timeout) = run_expired_timers() {
+        idle(|| sim::sleep(timeout));
+    }
+}
+
+/// Runs `fun`, which blocks until the next timer or port event, as time the scheduler is idle
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn idle<F, R>(fun: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    crate::scheduler::with_current(|scheduler| scheduler.idle(fun))
+}
+
+/// Returns the id and deadline of the timer which expires next, if any
+///
+/// In a simulation, one of the timers which expire at the same moment is picked pseudo-randomly.
+fn next_timer() -> Option<(u64, Instant)> {
+    let registry = registry();
+    let deadline = registry.timers.iter().map(|timer| timer.deadline).min()?;
+    let due: Vec<u64> = registry
+        .timers
+        .iter()
+        .filter(|timer| timer.deadline == deadline)
+        .map(|timer| timer.id)
+        .collect();
+    let index = sim::random_index(due.len()).unwrap_or(0);
+    Some((due[index], deadline))
+}
+
+/// Delivers the timeout of the given timer to its server, unless it was cancelled
+fn expire(id: u64) {
+    let timer = {
+        let mut registry = registry();
+        let index = registry.timers.iter().position(|timer| timer.id == id);
+        match index {
+            Some(index) => registry.timers.remove(index),
+            None => return,
+        }
+    };
+    cast(
+        timer.server,
+        Message::Timeout {
+            kind: timer.kind,
+            msg: timer.msg,
+        },
+    );
+}
+
+/// Invokes a required callback, raising `undef` if it is not exported
+///
+/// As in OTP, a value thrown from a callback is treated as its return value.
+pub(crate) fn callback(
+    module: Atom,
+    function: &str,
+    args: &[OpaqueTerm],
+) -> Result<OpaqueTerm, NonNull<ErlangException>> {
+    match optional_callback(module, function, args)? {
+        Some(result) => Ok(result),
+        None => Err(
+            ErlangException::new(atoms::Error, atoms::Undef.into(), Trace::capture()).into_raw(),
+        ),
+    }
+}
+
+/// Invokes a callback if it is exported, returning `None` if it is not
+pub(crate) fn optional_callback(
+    module: Atom,
+    function: &str,
+    args: &[OpaqueTerm],
+) -> Result<Option<OpaqueTerm>, NonNull<ErlangException>> {
+    match call_if_exported(module, function, args) {
+        Err(exception) if unsafe { exception.as_ref() }.kind() == atoms::Throw => {
+            let thrown = make_global(unsafe { exception.as_ref() }.reason().into());
+            let _ = unsafe { Box::from_raw(exception.as_ptr()) };
+            Ok(Some(thrown))
+        }
+        result => result,
+    }
+}
+
+/// Converts an exception raised by a callback into the exit reason of its server, as in OTP
+///
+/// This consumes the exception.
+pub(crate) fn exit_reason(exception: NonNull<ErlangException>) -> OpaqueTerm {
+    let exception = unsafe { Box::from_raw(exception.as_ptr()) };
+    let reason: OpaqueTerm = exception.reason().into();
+    let kind = exception.kind();
+    if kind == atoms::Exit {
+        return make_global(reason);
+    }
+    let reason = with_process(|proc| {
+        let stacktrace = exception
+            .trace()
+            .as_term()
+            .map(|term| term.into())
+            .unwrap_or(OpaqueTerm::NIL);
+        let reason = if kind == atoms::Throw {
+            make_tuple(proc, &[atom("nocatch").into(), reason])
+        } else {
+            reason
+        };
+        make_tuple(proc, &[reason, stacktrace])
+    });
+    make_global(reason)
+}
+
+/// Returns true if `reason` is `normal`, `shutdown` or `{shutdown, _}`
+pub(crate) fn is_normal_exit(reason: OpaqueTerm) -> bool {
+    if is_atom(reason, "normal") || is_atom(reason, "shutdown") {
+        return true;
+    }
+    matches!(tuple_elements(reason), Some([tag, _]) if is_atom(*tag, "shutdown"))
+}
+
+/// Constructs `{Module, Function, Args}` for use as the location in exit reasons
+pub(crate) fn location(module: &str, function: &str, args: &[OpaqueTerm]) -> OpaqueTerm {
+    with_process(|proc| {
+        let args = make_list(proc, args);
+        make_tuple(proc, &[atom(module).into(), atom(function).into(), args])
+    })
+}
+
+pub(crate) fn error(reason: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atoms::Error.into(), reason])
+    }))
+}
+
+fn reference_id(term: OpaqueTerm) -> Option<ReferenceId> {
+    match term.into() {
+        Term::Reference(reference) => Some(reference.id()),
+        _ => None,
+    }
+}
@@ -0,0 +1,271 @@
+//! The `gen_server` behaviour, implemented on the in-process servers described in `gen`.
+//!
+//! Servers may be started with `start/3,4` or `start_link/3,4`, named with `{local, Name}` or
+//! `{global, Name}`, and used with `call/2,3`, `cast/2`, `reply/2` and `stop/1,3`. All of the
+//! callback return values of OTP are supported, including `{continue, Continue}`, `hibernate`
+//! (which has no effect) and timeouts, which deliver `timeout` to `handle_info/2`.
+use std::ptr::NonNull;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::badarg;
+use super::gen::{self, Behaviour, Message, Name, Outcome, Started, TimerKind};
+use super::util::*;
+
+pub(crate) struct ServerState {
+    module: Atom,
+    state: OpaqueTerm,
+}
+
+#[export_name = "gen_server:start/3"]
+pub extern "C-unwind" fn start3(
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    start(None, module, args, opts, false)
+}
+
+#[export_name = "gen_server:start/4"]
+pub extern "C-unwind" fn start4(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = Name::parse(name) else {
+        return badarg(Trace::capture());
+    };
+    start(Some(name), module, args, opts, false)
+}
+
+#[export_name = "gen_server:start_link/3"]
+pub extern "C-unwind" fn start_link3(
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    start(None, module, args, opts, true)
+}
+
+#[export_name = "gen_server:start_link/4"]
+pub extern "C-unwind" fn start_link4(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = Name::parse(name) else {
+        return badarg(Trace::capture());
+    };
+    start(Some(name), module, args, opts, true)
+}
+
+/// Starts a server, ignoring the options given, as none of them (e.g. `debug` or `spawn_opt`) are
+/// applicable to in-process servers
+fn start(
+    name: Option<Name>,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+    link: bool,
+) -> ErlangResult {
+    let (Term::Atom(module), Some(_)) = (module.into(), list_to_vec(opts)) else {
+        return badarg(Trace::capture());
+    };
+    gen::start(name, module, link, |pid| {
+        let result = gen::callback(module, "init", &[args])?;
+        let (state, action) = match tuple_elements(result) {
+            Some([tag, state]) if is_atom(*tag, "ok") => (*state, None),
+            Some([tag, state, action]) if is_atom(*tag, "ok") => (*state, Some(*action)),
+            Some([tag, reason]) if is_atom(*tag, "stop") || is_atom(*tag, "error") => {
+                return Ok(Started::Stop(make_global(*reason)));
+            }
+            _ if is_atom(result, "ignore") => return Ok(Started::Ignore),
+            _ => return Ok(Started::Stop(bad_return_value(result))),
+        };
+        let state = ServerState {
+            module,
+            state: make_global(state),
+        };
+        match action.map(|action| apply_action(pid, action)) {
+            Some(false) => Ok(Started::Stop(bad_return_value(result))),
+            _ => Ok(Started::Ok(Behaviour::Server(state))),
+        }
+    })
+}
+
+#[export_name = "gen_server:call/2"]
+pub extern "C-unwind" fn call2(server: OpaqueTerm, request: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("gen_server", "call", &[server, request]);
+    ErlangResult::Ok(gen::call(server, request, location)?)
+}
+
+#[export_name = "gen_server:call/3"]
+pub extern "C-unwind" fn call3(
+    server: OpaqueTerm,
+    request: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    let location = gen::location("gen_server", "call", &[server, request, timeout]);
+    call(server, request, timeout, location)
+}
+
+/// Sends a request to `server`
+///
+/// As requests are handled synchronously, the timeout is only validated: a request fails with
+/// `timeout` if it has not been replied to once the server has handled all of its pending messages.
+fn call(
+    server: OpaqueTerm,
+    request: OpaqueTerm,
+    timeout: OpaqueTerm,
+    location: OpaqueTerm,
+) -> ErlangResult {
+    if timeout_ms(timeout).is_none() {
+        return badarg(Trace::capture());
+    }
+    ErlangResult::Ok(gen::call(server, request, location)?)
+}
+
+/// Sends an asynchronous request to `server`, which always succeeds, even if the server doesn't
+/// exist
+#[export_name = "gen_server:cast/2"]
+pub extern "C-unwind" fn cast2(server: OpaqueTerm, request: OpaqueTerm) -> ErlangResult {
+    if let Some(pid) = gen::whereis(server) {
+        gen::cast(pid, Message::Cast(make_global(request)));
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "gen_server:reply/2"]
+pub extern "C-unwind" fn reply2(from: OpaqueTerm, reply: OpaqueTerm) -> ErlangResult {
+    if !gen::reply(from, reply) {
+        return badarg(Trace::capture());
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "gen_server:stop/1"]
+pub extern "C-unwind" fn stop1(server: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("gen_server", "stop", &[server]);
+    gen::stop(server, atoms::Normal.into(), location)?;
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "gen_server:stop/3"]
+pub extern "C-unwind" fn stop3(
+    server: OpaqueTerm,
+    reason: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    if timeout_ms(timeout).is_none() {
+        return badarg(Trace::capture());
+    }
+    let location = gen::location("gen_server", "stop", &[server, reason, timeout]);
+    gen::stop(server, reason, location)?;
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+pub(crate) fn handle(pid: ProcessId, mut server: ServerState, message: Message) -> Outcome {
+    let module = server.module;
+    let state = server.state;
+    let (result, from) = match message {
+        Message::Call { from, request } => (
+            gen::callback(module, "handle_call", &[request, from, state]),
+            Some(from),
+        ),
+        Message::Cast(request) => (
+            gen::callback(module, "handle_cast", &[request, state]),
+            None,
+        ),
+        Message::Info(msg) | Message::Timeout { msg, .. } => {
+            // As in OTP, messages are discarded if `handle_info/2` is not implemented
+            match gen::optional_callback(module, "handle_info", &[msg, state]) {
+                Ok(None) => return Outcome::Continue(Behaviour::Server(server)),
+                result => (result.map(Option::unwrap), None),
+            }
+        }
+        Message::Continue(continuation) => (
+            gen::callback(module, "handle_continue", &[continuation, state]),
+            None,
+        ),
+        // Links are not modeled for generic servers
+        Message::Exit { .. } => return Outcome::Continue(Behaviour::Server(server)),
+    };
+
+    let result = match result {
+        Ok(result) => result,
+        Err(exception) => {
+            return Outcome::Stop(gen::exit_reason(exception), Behaviour::Server(server));
+        }
+    };
+
+    let elements = tuple_elements(result).unwrap_or(&[]);
+    let (state, action) = match (from, elements) {
+        (Some(from), [tag, reply, state]) if is_atom(*tag, "reply") => {
+            gen::reply(from, *reply);
+            (*state, None)
+        }
+        (Some(from), [tag, reply, state, action]) if is_atom(*tag, "reply") => {
+            gen::reply(from, *reply);
+            (*state, Some(*action))
+        }
+        (_, [tag, state]) if is_atom(*tag, "noreply") => (*state, None),
+        (_, [tag, state, action]) if is_atom(*tag, "noreply") => (*state, Some(*action)),
+        (Some(from), [tag, reason, reply, state]) if is_atom(*tag, "stop") => {
+            gen::reply(from, *reply);
+            server.state = make_global(*state);
+            return Outcome::Stop(make_global(*reason), Behaviour::Server(server));
+        }
+        (_, [tag, reason, state]) if is_atom(*tag, "stop") => {
+            server.state = make_global(*state);
+            return Outcome::Stop(make_global(*reason), Behaviour::Server(server));
+        }
+        _ => return Outcome::Stop(bad_return_value(result), Behaviour::Server(server)),
+    };
+
+    server.state = make_global(state);
+    match action {
+        Some(action) if !apply_action(pid, action) => {
+            Outcome::Stop(bad_return_value(result), Behaviour::Server(server))
+        }
+        _ => Outcome::Continue(Behaviour::Server(server)),
+    }
+}
+
+/// Applies the `Timeout | hibernate | {continue, Continue}` element of a callback result, returning
+/// false if it is invalid
+fn apply_action(pid: ProcessId, action: OpaqueTerm) -> bool {
+    if is_atom(action, "hibernate") {
+        return true;
+    }
+    if let Some([tag, continuation]) = tuple_elements(action) {
+        if is_atom(*tag, "continue") {
+            gen::send_first(pid, Message::Continue(make_global(*continuation)));
+            return true;
+        }
+    }
+    match timeout_ms(action) {
+        Some(Some(timeout)) => {
+            gen::start_timer(pid, TimerKind::Event, timeout, atom("timeout").into());
+            true
+        }
+        Some(None) => true,
+        None => false,
+    }
+}
+
+pub(crate) fn terminate(
+    server: ServerState,
+    reason: OpaqueTerm,
+) -> Result<(), NonNull<ErlangException>> {
+    gen::optional_callback(server.module, "terminate", &[reason, server.state])?;
+    Ok(())
+}
+
+fn bad_return_value(result: OpaqueTerm) -> OpaqueTerm {
+    make_global(reason2("bad_return_value", result))
+}
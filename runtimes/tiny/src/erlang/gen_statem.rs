@@ -0,0 +1,514 @@
+//! The `gen_statem` behaviour, implemented on the in-process servers described in `gen`.
+//!
+//! Both the `state_functions` and `handle_event_function` callback modes are supported, optionally
+//! with `state_enter`. Of the transition actions, `postpone`, `next_event`, `reply` and all three
+//! kinds of timeout are supported, while `hibernate` is accepted but has no effect.
+use std::collections::VecDeque;
+use std::ptr::NonNull;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::badarg;
+use super::gen::{self, Behaviour, Message, Name, Outcome, Started, TimerKind};
+use super::util::*;
+
+pub(crate) struct StatemState {
+    module: Atom,
+    /// True if the callback mode is `handle_event_function`, rather than `state_functions`
+    handle_event: bool,
+    state_enter: bool,
+    state: OpaqueTerm,
+    data: OpaqueTerm,
+    /// Events postponed in the current state, in the order they arrived
+    postponed: Vec<(OpaqueTerm, OpaqueTerm)>,
+    /// Events to handle before any further messages, i.e. inserted or retried events
+    pending: VecDeque<(OpaqueTerm, OpaqueTerm)>,
+}
+
+/// The transition requested by a state callback
+enum Transition {
+    Next(OpaqueTerm, OpaqueTerm),
+    /// Keeps the current state, and optionally replaces the data
+    Keep(Option<OpaqueTerm>),
+    /// As `Keep`, but retries postponed events and calls the state enter callback
+    Repeat(Option<OpaqueTerm>),
+    Stop(OpaqueTerm, Option<OpaqueTerm>),
+}
+
+/// The effect of the actions of a transition
+#[derive(Default)]
+struct Actions {
+    postpone: bool,
+    next_events: Vec<(OpaqueTerm, OpaqueTerm)>,
+}
+
+#[export_name = "gen_statem:start/3"]
+pub extern "C-unwind" fn start3(
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    start(None, module, args, opts, false)
+}
+
+#[export_name = "gen_statem:start/4"]
+pub extern "C-unwind" fn start4(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = Name::parse(name) else {
+        return badarg(Trace::capture());
+    };
+    start(Some(name), module, args, opts, false)
+}
+
+#[export_name = "gen_statem:start_link/3"]
+pub extern "C-unwind" fn start_link3(
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    start(None, module, args, opts, true)
+}
+
+#[export_name = "gen_statem:start_link/4"]
+pub extern "C-unwind" fn start_link4(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = Name::parse(name) else {
+        return badarg(Trace::capture());
+    };
+    start(Some(name), module, args, opts, true)
+}
+
+fn start(
+    name: Option<Name>,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    opts: OpaqueTerm,
+    link: bool,
+) -> ErlangResult {
+    let (Term::Atom(module), Some(_)) = (module.into(), list_to_vec(opts)) else {
+        return badarg(Trace::capture());
+    };
+    gen::start(name, module, link, |pid| {
+        let result = gen::callback(module, "init", &[args])?;
+        let (state, data, actions) = match tuple_elements(result) {
+            Some([tag, state, data]) if is_atom(*tag, "ok") => (*state, *data, None),
+            Some([tag, state, data, actions]) if is_atom(*tag, "ok") => {
+                (*state, *data, Some(*actions))
+            }
+            Some([tag, reason]) if is_atom(*tag, "stop") || is_atom(*tag, "error") => {
+                return Ok(Started::Stop(make_global(*reason)));
+            }
+            _ if is_atom(result, "ignore") => return Ok(Started::Ignore),
+            _ => return Ok(Started::Stop(bad_return_from_init(result))),
+        };
+
+        let mode = gen::callback(module, "callback_mode", &[])?;
+        let Some((handle_event, state_enter)) = parse_callback_mode(mode) else {
+            return Ok(Started::Stop(make_global(reason2(
+                "bad_callback_mode",
+                mode,
+            ))));
+        };
+
+        let mut statem = StatemState {
+            module,
+            handle_event,
+            state_enter,
+            state: make_global(state),
+            data: make_global(data),
+            postponed: vec![],
+            pending: VecDeque::new(),
+        };
+        let actions = match actions.map(|actions| apply_actions(pid, actions, false)) {
+            None => Actions::default(),
+            Some(Some(actions)) => actions,
+            Some(None) => return Ok(Started::Stop(bad_return_from_init(result))),
+        };
+        statem.pending.extend(actions.next_events);
+
+        if statem.state_enter {
+            let state = statem.state;
+            if let Err(reason) = enter(pid, &mut statem, state) {
+                return Ok(Started::Stop(reason));
+            }
+        }
+        if !statem.pending.is_empty() {
+            gen::send_first(pid, Message::Continue(OpaqueTerm::NIL));
+        }
+        Ok(Started::Ok(Behaviour::Statem(statem)))
+    })
+}
+
+#[export_name = "gen_statem:call/2"]
+pub extern "C-unwind" fn call2(server: OpaqueTerm, request: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("gen_statem", "call", &[server, request]);
+    ErlangResult::Ok(gen::call(server, request, location)?)
+}
+
+/// Sends a request to `server`, see `gen_server:call/3` for the handling of the timeout
+#[export_name = "gen_statem:call/3"]
+pub extern "C-unwind" fn call3(
+    server: OpaqueTerm,
+    request: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    let valid = match tuple_elements(timeout) {
+        Some([tag, timeout])
+            if is_atom(*tag, "dirty_timeout") || is_atom(*tag, "clean_timeout") =>
+        {
+            timeout_ms(*timeout).is_some()
+        }
+        _ => timeout_ms(timeout).is_some(),
+    };
+    if !valid {
+        return badarg(Trace::capture());
+    }
+    let location = gen::location("gen_statem", "call", &[server, request, timeout]);
+    ErlangResult::Ok(gen::call(server, request, location)?)
+}
+
+#[export_name = "gen_statem:cast/2"]
+pub extern "C-unwind" fn cast2(server: OpaqueTerm, msg: OpaqueTerm) -> ErlangResult {
+    if let Some(pid) = gen::whereis(server) {
+        gen::cast(pid, Message::Cast(make_global(msg)));
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Sends the replies given as `{reply, From, Reply}` actions, or a list of them
+#[export_name = "gen_statem:reply/1"]
+pub extern "C-unwind" fn reply1(replies: OpaqueTerm) -> ErlangResult {
+    let replies = list_to_vec(replies).unwrap_or_else(|| vec![replies]);
+    for reply in replies {
+        match tuple_elements(reply) {
+            Some([tag, from, reply]) if is_atom(*tag, "reply") && gen::reply(*from, *reply) => (),
+            _ => return badarg(Trace::capture()),
+        }
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "gen_statem:reply/2"]
+pub extern "C-unwind" fn reply2(from: OpaqueTerm, reply: OpaqueTerm) -> ErlangResult {
+    if !gen::reply(from, reply) {
+        return badarg(Trace::capture());
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "gen_statem:stop/1"]
+pub extern "C-unwind" fn stop1(server: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("gen_statem", "stop", &[server]);
+    gen::stop(server, atoms::Normal.into(), location)?;
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "gen_statem:stop/3"]
+pub extern "C-unwind" fn stop3(
+    server: OpaqueTerm,
+    reason: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    if timeout_ms(timeout).is_none() {
+        return badarg(Trace::capture());
+    }
+    let location = gen::location("gen_statem", "stop", &[server, reason, timeout]);
+    gen::stop(server, reason, location)?;
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+pub(crate) fn handle(pid: ProcessId, mut statem: StatemState, message: Message) -> Outcome {
+    let event = with_process(|proc| match message {
+        Message::Call { from, request } => {
+            let ty = make_tuple(proc, &[atom("call").into(), from]);
+            Some((make_global(ty), request))
+        }
+        Message::Cast(msg) => Some((atom("cast").into(), msg)),
+        Message::Info(msg) => Some((atom("info").into(), msg)),
+        Message::Timeout { kind, msg } => {
+            let ty = match kind {
+                TimerKind::Event => atom("timeout").into(),
+                TimerKind::State => atom("state_timeout").into(),
+                TimerKind::Generic(name) => {
+                    make_global(make_tuple(proc, &[atom("timeout").into(), name]))
+                }
+            };
+            Some((ty, msg))
+        }
+        Message::Continue(_) | Message::Exit { .. } => None,
+    });
+    if let Some(event) = event {
+        statem.pending.push_back(event);
+    }
+
+    while let Some((ty, content)) = statem.pending.pop_front() {
+        // Any event cancels the event timeout
+        gen::cancel_timer(pid, TimerKind::Event);
+        if let Err(reason) = handle_event(pid, &mut statem, ty, content) {
+            return Outcome::Stop(reason, Behaviour::Statem(statem));
+        }
+    }
+    Outcome::Continue(Behaviour::Statem(statem))
+}
+
+/// Calls the state callback for an event, and performs the resulting transition
+fn handle_event(
+    pid: ProcessId,
+    statem: &mut StatemState,
+    ty: OpaqueTerm,
+    content: OpaqueTerm,
+) -> Result<(), OpaqueTerm> {
+    let result = call_state_callback(statem, ty, content, statem.state)?;
+    let Some((transition, actions)) = parse_result(result) else {
+        return Err(bad_return_from_state_function(result));
+    };
+
+    let (state, data, repeat) = match transition {
+        Transition::Next(state, data) => (state, data, false),
+        Transition::Keep(data) => (statem.state, data.unwrap_or(statem.data), false),
+        Transition::Repeat(data) => (statem.state, data.unwrap_or(statem.data), true),
+        Transition::Stop(reason, data) => {
+            if let Some(data) = data {
+                statem.data = make_global(data);
+            }
+            apply_actions(pid, actions, false);
+            return Err(make_global(reason));
+        }
+    };
+    let Some(actions) = apply_actions(pid, actions, true) else {
+        return Err(bad_return_from_state_function(result));
+    };
+
+    let prev = statem.state;
+    let changed = !equals(prev, state);
+    statem.state = make_global(state);
+    statem.data = make_global(data);
+    if actions.postpone {
+        statem.postponed.push((ty, content));
+    }
+    if changed {
+        // Postponed events are retried in a new state, after any inserted events
+        gen::cancel_timer(pid, TimerKind::State);
+        for event in statem.postponed.drain(..).rev() {
+            statem.pending.push_front(event);
+        }
+    }
+    for event in actions.next_events.into_iter().rev() {
+        statem.pending.push_front(event);
+    }
+
+    if repeat {
+        for event in statem.postponed.drain(..).rev() {
+            statem.pending.push_front(event);
+        }
+    }
+    if statem.state_enter && (changed || repeat) {
+        enter(pid, statem, prev)?;
+    }
+    Ok(())
+}
+
+/// Calls the state enter callback for the current state, having transitioned from `prev`
+fn enter(pid: ProcessId, statem: &mut StatemState, prev: OpaqueTerm) -> Result<(), OpaqueTerm> {
+    let result = call_state_callback(statem, atom("enter").into(), prev, statem.state)?;
+    let Some((transition, actions)) = parse_result(result) else {
+        return Err(bad_return_from_state_function(result));
+    };
+    let data = match transition {
+        Transition::Next(state, data) if equals(state, statem.state) => data,
+        Transition::Keep(data) | Transition::Repeat(data) => data.unwrap_or(statem.data),
+        Transition::Stop(reason, data) => {
+            if let Some(data) = data {
+                statem.data = make_global(data);
+            }
+            apply_actions(pid, actions, false);
+            return Err(make_global(reason));
+        }
+        Transition::Next(..) => {
+            return Err(make_global(reason2(
+                "bad_state_enter_return_from_state_function",
+                result,
+            )));
+        }
+    };
+    // Neither postponing nor inserting events is permitted from a state enter call
+    match apply_actions(pid, actions, false) {
+        Some(actions) if !actions.postpone && actions.next_events.is_empty() => {
+            statem.data = make_global(data);
+            Ok(())
+        }
+        _ => Err(make_global(reason2(
+            "bad_state_enter_action_from_state_function",
+            result,
+        ))),
+    }
+}
+
+fn call_state_callback(
+    statem: &StatemState,
+    ty: OpaqueTerm,
+    content: OpaqueTerm,
+    state: OpaqueTerm,
+) -> Result<OpaqueTerm, OpaqueTerm> {
+    let result = if statem.handle_event {
+        gen::callback(
+            statem.module,
+            "handle_event",
+            &[ty, content, state, statem.data],
+        )
+    } else {
+        let Term::Atom(state) = state.into() else {
+            return Err(make_global(reason2("bad_state", state)));
+        };
+        gen::callback(statem.module, state.as_str(), &[ty, content, statem.data])
+    };
+    result.map_err(gen::exit_reason)
+}
+
+/// Parses the result of a state callback into the transition and its actions
+fn parse_result(result: OpaqueTerm) -> Option<(Transition, OpaqueTerm)> {
+    let none = OpaqueTerm::NIL;
+    if let Term::Atom(tag) = result.into() {
+        return match tag.as_str() {
+            "keep_state_and_data" => Some((Transition::Keep(None), none)),
+            "repeat_state_and_data" => Some((Transition::Repeat(None), none)),
+            "stop" => Some((Transition::Stop(atoms::Normal.into(), None), none)),
+            _ => None,
+        };
+    }
+    let (tag, rest) = tuple_elements(result)?.split_first()?;
+    let Term::Atom(tag) = (*tag).into() else {
+        return None;
+    };
+    let parsed = match (tag.as_str(), rest) {
+        ("next_state", [state, data]) => (Transition::Next(*state, *data), none),
+        ("next_state", [state, data, actions]) => (Transition::Next(*state, *data), *actions),
+        ("keep_state", [data]) => (Transition::Keep(Some(*data)), none),
+        ("keep_state", [data, actions]) => (Transition::Keep(Some(*data)), *actions),
+        ("keep_state_and_data", [actions]) => (Transition::Keep(None), *actions),
+        ("repeat_state", [data]) => (Transition::Repeat(Some(*data)), none),
+        ("repeat_state", [data, actions]) => (Transition::Repeat(Some(*data)), *actions),
+        ("repeat_state_and_data", [actions]) => (Transition::Repeat(None), *actions),
+        ("stop", [reason]) => (Transition::Stop(*reason, None), none),
+        ("stop", [reason, data]) => (Transition::Stop(*reason, Some(*data)), none),
+        ("stop_and_reply", [reason, replies]) => (Transition::Stop(*reason, None), *replies),
+        ("stop_and_reply", [reason, replies, data]) => {
+            (Transition::Stop(*reason, Some(*data)), *replies)
+        }
+        _ => return None,
+    };
+    Some(parsed)
+}
+
+/// Applies a list of transition actions (or a single action), returning `None` if any are invalid
+///
+/// Timeouts of zero expire as soon as all pending events have been handled.
+fn apply_actions(pid: ProcessId, actions: OpaqueTerm, may_postpone: bool) -> Option<Actions> {
+    let actions = list_to_vec(actions).unwrap_or_else(|| vec![actions]);
+    let mut result = Actions::default();
+    for action in actions {
+        if is_atom(action, "postpone") && may_postpone {
+            result.postpone = true;
+            continue;
+        }
+        if is_atom(action, "hibernate") {
+            continue;
+        }
+        if let Some(timeout) = timeout_ms(action) {
+            set_timer(pid, TimerKind::Event, timeout, action);
+            continue;
+        }
+        match tuple_elements(action)? {
+            [tag, value] if is_atom(*tag, "postpone") && may_postpone => {
+                result.postpone = matches!((*value).into(), Term::Bool(true));
+            }
+            [tag, _] if is_atom(*tag, "hibernate") => (),
+            [tag, from, reply] if is_atom(*tag, "reply") => {
+                if !gen::reply(*from, *reply) {
+                    return None;
+                }
+            }
+            [tag, ty, content] if is_atom(*tag, "next_event") => {
+                result
+                    .next_events
+                    .push((make_global(*ty), make_global(*content)));
+            }
+            [kind, cancel] if is_atom(*cancel, "cancel") => {
+                gen::cancel_timer(pid, timer_kind(*kind)?);
+            }
+            [kind, timeout, msg] | [kind, timeout, msg, _] => {
+                set_timer(pid, timer_kind(*kind)?, timeout_ms(*timeout)?, *msg);
+            }
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+fn timer_kind(term: OpaqueTerm) -> Option<TimerKind> {
+    if is_atom(term, "timeout") {
+        return Some(TimerKind::Event);
+    }
+    if is_atom(term, "state_timeout") {
+        return Some(TimerKind::State);
+    }
+    match tuple_elements(term)? {
+        [tag, name] if is_atom(*tag, "timeout") => Some(TimerKind::Generic(make_global(*name))),
+        _ => None,
+    }
+}
+
+/// Starts a timer, or cancels it if the timeout is `infinity`
+fn set_timer(pid: ProcessId, kind: TimerKind, timeout: Option<u64>, msg: OpaqueTerm) {
+    match timeout {
+        Some(timeout) => gen::start_timer(pid, kind, timeout, msg),
+        None => gen::cancel_timer(pid, kind),
+    }
+}
+
+/// Parses the result of `callback_mode/0`, returning `(handle_event, state_enter)`
+fn parse_callback_mode(mode: OpaqueTerm) -> Option<(bool, bool)> {
+    let modes = list_to_vec(mode).unwrap_or_else(|| vec![mode]);
+    let mut handle_event = None;
+    let mut state_enter = false;
+    for mode in modes {
+        match mode.into() {
+            Term::Atom(a) if a.as_str() == "state_functions" => handle_event = Some(false),
+            Term::Atom(a) if a.as_str() == "handle_event_function" => handle_event = Some(true),
+            Term::Atom(a) if a.as_str() == "state_enter" => state_enter = true,
+            _ => return None,
+        }
+    }
+    Some((handle_event?, state_enter))
+}
+
+pub(crate) fn terminate(
+    statem: StatemState,
+    reason: OpaqueTerm,
+) -> Result<(), NonNull<ErlangException>> {
+    gen::optional_callback(
+        statem.module,
+        "terminate",
+        &[reason, statem.state, statem.data],
+    )?;
+    Ok(())
+}
+
+fn bad_return_from_init(result: OpaqueTerm) -> OpaqueTerm {
+    make_global(reason2("bad_return_from_init", result))
+}
+
+fn bad_return_from_state_function(result: OpaqueTerm) -> OpaqueTerm {
+    make_global(reason2("bad_return_from_state_function", result))
+}
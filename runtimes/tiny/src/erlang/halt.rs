@@ -0,0 +1,90 @@
+//! `erlang:halt/0,1,2`, which stop the system with an exit status, see `crate::halt`.
+//!
+//! `Status` is a non-negative integer, the exit code, truncated to 8 bits as by the OS, `abort`, or
+//! a string, which is printed as the system exits with a code of 1. The options of `halt/2` are
+//! `{flush, boolean()}`, true by default, which has buffered output flushed and the queues of ports
+//! drained before exiting, and `{flush_timeout, Timeout}`, after which ports still flushing are
+//! abandoned, `infinity` by default.
+//!
+//! The calling process exits as the halt begins, and no other process is scheduled afterwards.
+//! Processes cannot be suspended on wasm32, so there the caller is unwound with `exit` and the
+//! reason `normal` instead, which it could catch, though nothing else is scheduled either way.
+use std::time::Duration;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::halt::{self, Request, Status};
+
+use super::badarg;
+use super::util::*;
+
+/// Halts the system with a status of 0
+#[export_name = "erlang:halt/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn halt0() -> ErlangResult {
+    halt1(Term::Int(0).into())
+}
+
+/// Halts the system with `Status`, flushing before it exits
+#[export_name = "erlang:halt/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn halt1(status: OpaqueTerm) -> ErlangResult {
+    halt2(status, OpaqueTerm::NIL)
+}
+
+/// Halts the system with `Status`, flushing before it exits unless `{flush, false}` is given
+#[export_name = "erlang:halt/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn halt2(status: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let Some(status) = parse_status(status) else {
+        return badarg(Trace::capture());
+    };
+    let mut request = Request {
+        status,
+        flush: true,
+        flush_timeout: None,
+    };
+    let Some(options) = list_to_vec(options) else {
+        return badarg(Trace::capture());
+    };
+    for option in options {
+        match tuple_elements(option) {
+            Some([key, value]) if is_atom(*key, "flush") => match (*value).into() {
+                Term::Bool(flush) => request.flush = flush,
+                _ => return badarg(Trace::capture()),
+            },
+            Some([key, value]) if is_atom(*key, "flush_timeout") => match timeout_ms(*value) {
+                Some(timeout) => request.flush_timeout = timeout.map(Duration::from_millis),
+                None => return badarg(Trace::capture()),
+            },
+            _ => return badarg(Trace::capture()),
+        }
+    }
+    halt::request(request);
+    stop()
+}
+
+fn parse_status(status: OpaqueTerm) -> Option<Status> {
+    match status.into() {
+        Term::Int(code) if code >= 0 => Some(Status::Code(code as u8)),
+        Term::Atom(a) if a.as_str() == "abort" => Some(Status::Abort),
+        _ => charlist_to_string(status).map(Status::Slogan),
+    }
+}
+
+/// Exits the calling process, which is never scheduled again as the system is halting
+#[cfg(not(target_arch = "wasm32"))]
+fn stop() -> ErlangResult {
+    crate::scheduler::with_current(|scheduler| {
+        scheduler.current_process().exit_normal();
+        scheduler.process_yield()
+    });
+    unreachable!("halted process was rescheduled")
+}
+
+#[cfg(target_arch = "wasm32")]
+fn stop() -> ErlangResult {
+    ErlangResult::raise(atoms::Exit, atoms::Normal.into(), Trace::capture())
+}
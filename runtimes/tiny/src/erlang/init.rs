@@ -0,0 +1,72 @@
+//! The arguments of the `init` module, `get_argument/1`, `get_arguments/0` and
+//! `get_plain_arguments/0`, with which programs read the command line they were invoked with.
+//!
+//! The arguments are those the executable was given, less the flags of the runtime itself, e.g.
+//! `+Mlimit`, parsed into flags and plain arguments as by ERTS, see `crate::env::Arguments`. Flags
+//! are atoms, without their leading `-`, and values and plain arguments are strings, so that
+//! `prog -name foo a -extra b c` has `{ok, [["foo", "a"]]}` as its argument `name`, and `["b",
+//! "c"]` as its plain arguments.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::env;
+
+use super::util::*;
+
+/// Returns `{ok, [Values]}`, the values of each occurrence of `Flag`, or `error` if it was not
+/// given
+#[export_name = "init:get_argument/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_argument1(flag: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(flag) = flag.into() else {
+        return super::badarg(Trace::capture());
+    };
+    let occurrences = env::arguments().get(flag.as_str());
+    if occurrences.is_empty() {
+        return ErlangResult::Ok(atom("error").into());
+    }
+    ErlangResult::Ok(with_process(|proc| {
+        let occurrences = occurrences
+            .iter()
+            .map(|values| make_strings(proc, values))
+            .collect::<Vec<_>>();
+        let occurrences = make_list(proc, occurrences.as_slice());
+        make_tuple(proc, &[atom("ok").into(), occurrences])
+    }))
+}
+
+/// Returns every flag given, with its values, as `[{Flag, Values}]`
+#[export_name = "init:get_arguments/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_arguments0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        let flags = env::arguments()
+            .flags
+            .iter()
+            .map(|(flag, values)| {
+                let values = make_strings(proc, values);
+                make_tuple(proc, &[atom(flag.as_str()).into(), values])
+            })
+            .collect::<Vec<_>>();
+        make_list(proc, flags.as_slice())
+    }))
+}
+
+/// Returns the plain arguments, those before the first flag and after `-extra`
+#[export_name = "init:get_plain_arguments/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_plain_arguments0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        make_strings(proc, env::arguments().plain.as_slice())
+    }))
+}
+
+fn make_strings(proc: &Process, strings: &[String]) -> OpaqueTerm {
+    let strings = strings
+        .iter()
+        .map(|s| charlist(proc, s.as_str()))
+        .collect::<Vec<_>>();
+    make_list(proc, strings.as_slice())
+}
@@ -0,0 +1,116 @@
+//! The BIFs of the `io` module which write to the standard streams, formatted by `io_lib` or not.
+//!
+//! There are no I/O servers in this runtime, so only the devices `standard_io` and `standard_error`
+//! are supported, which write directly to stdout and stderr respectively, i.e. on wasm32-wasi, to
+//! the file descriptors provided by the host.
+use std::io::{self, Write};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::util::*;
+
+/// Writes `chars` to standard output
+#[export_name = "io:put_chars/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn put_chars1(chars: OpaqueTerm) -> ErlangResult {
+    put_chars2(atom("standard_io").into(), chars)
+}
+
+/// Writes `chars`, which may be a string or any iodata, to `device`
+#[export_name = "io:put_chars/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn put_chars2(device: OpaqueTerm, chars: OpaqueTerm) -> ErlangResult {
+    let bytes = match charlist_to_string(chars) {
+        Some(s) => Some(s.into_bytes()),
+        None => iodata_to_bytes(chars),
+    };
+    let Some(bytes) = bytes else {
+        return super::badarg(Trace::capture());
+    };
+    write(device, bytes.as_slice())
+}
+
+/// Writes `format` to standard output
+#[export_name = "io:format/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn format1(format: OpaqueTerm) -> ErlangResult {
+    format3(atom("standard_io").into(), format, OpaqueTerm::NIL)
+}
+
+/// Writes `format`, with the control sequences in it replaced by `data`, to standard output
+#[export_name = "io:format/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn format2(format: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    format3(atom("standard_io").into(), format, data)
+}
+
+/// Writes `format`, with the control sequences in it replaced by `data`, to `device`, see
+/// `io_lib:format/2`
+#[export_name = "io:format/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn format3(
+    device: OpaqueTerm,
+    format: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    let Some(chars) = super::io_lib::format(format, data) else {
+        return super::badarg(Trace::capture());
+    };
+    write(device, chars.as_bytes())
+}
+
+/// As `format/1`
+#[export_name = "io:fwrite/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn fwrite1(format: OpaqueTerm) -> ErlangResult {
+    format1(format)
+}
+
+/// As `format/2`
+#[export_name = "io:fwrite/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn fwrite2(format: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    format2(format, data)
+}
+
+/// As `format/3`
+#[export_name = "io:fwrite/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn fwrite3(
+    device: OpaqueTerm,
+    format: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    format3(device, format, data)
+}
+
+/// Writes a newline to standard output
+#[export_name = "io:nl/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn nl0() -> ErlangResult {
+    nl1(atom("standard_io").into())
+}
+
+/// Writes a newline to `device`
+#[export_name = "io:nl/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn nl1(device: OpaqueTerm) -> ErlangResult {
+    write(device, b"\n")
+}
+
+fn write(device: OpaqueTerm, bytes: &[u8]) -> ErlangResult {
+    let result = if is_atom(device, "standard_io") {
+        io::stdout().lock().write_all(bytes)
+    } else if is_atom(device, "standard_error") {
+        io::stderr().lock().write_all(bytes)
+    } else {
+        return super::badarg(Trace::capture());
+    };
+    match result {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        // As when the I/O server of the device has terminated in OTP
+        Err(_) => ErlangResult::raise(atoms::Error, atom("terminated").into(), Trace::capture()),
+    }
+}
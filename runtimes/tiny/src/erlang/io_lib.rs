@@ -0,0 +1,214 @@
+//! `io_lib:format/2`, the formatter behind `io:format/1,2,3`.
+//!
+//! Control sequences are written `~F.P.PadModC` as in OTP, where the field width `F` and the
+//! precision `P` may be `*` to take them from the arguments, and a negative field width
+//! left-justifies the field. The controls supported are `~`, `n`, `c`, `f`, `e`, `g`, `s`, `w`,
+//! `p`, `W`, `P`, `b`, `B`, `x`, `X`, `+`, `#` and `i`, and the modifiers `t`, `l` and `k` are
+//! accepted, but change nothing. `~p` writes terms as `~w` does, without breaking them over lines,
+//! and both write lists of printable characters as strings.
+//!
+//! Numbers are formatted by Rust, see `firefly_number::format`, rather than by the C library, so
+//! the output does not depend on the locale, which a program embedding the runtime may have set,
+//! e.g. to one with a decimal comma. `~f` defaults to 6 decimals and `~e` and `~g` to 6
+//! significant digits, and as in OTP, a number which does not fit its field is written as `*`s.
+use std::fmt::Write;
+
+use firefly_number::{format as float, BigInt};
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::badarg;
+use super::util::*;
+
+/// Returns the characters of `Format` with the control sequences in it replaced by `Data`
+#[export_name = "io_lib:format/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn format2(format: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    let Some(chars) = self::format(format, data) else {
+        return badarg(Trace::capture());
+    };
+    ErlangResult::Ok(with_process(|proc| charlist(proc, &chars)))
+}
+
+/// As `format/2`
+#[export_name = "io_lib:fwrite/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn fwrite2(format: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    format2(format, data)
+}
+
+/// Formats `data` as directed by `format`, an atom, string or binary, returning `None` if either
+/// is invalid, as `io_lib:format/2` does
+pub(super) fn format(format: OpaqueTerm, data: OpaqueTerm) -> Option<String> {
+    let format = match format.into() {
+        Term::Atom(format) => format.as_str().to_string(),
+        _ => string(format)?,
+    };
+    let mut args = list_to_vec(data)?.into_iter();
+    let mut out = String::with_capacity(format.len());
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            out.push(c);
+            continue;
+        }
+
+        // The field width, which is negative to left-justify the field
+        let mut left = chars.next_if_eq(&'-').is_some();
+        let width = if chars.next_if_eq(&'*').is_some() {
+            let Term::Int(n) = args.next()?.into() else { return None; };
+            left |= n < 0;
+            Some(n.unsigned_abs() as usize)
+        } else {
+            number(&mut chars)
+        };
+        let mut precision = None;
+        let mut pad = ' ';
+        if chars.next_if_eq(&'.').is_some() {
+            if chars.next_if_eq(&'*').is_some() {
+                let Term::Int(n) = args.next()?.into() else { return None; };
+                precision = Some(usize::try_from(n).ok()?);
+            } else {
+                precision = number(&mut chars);
+            }
+            if chars.next_if_eq(&'.').is_some() {
+                pad = chars.next()?;
+            }
+        }
+        while let Some(modifier) = chars.next_if(|c| matches!(c, 't' | 'l' | 'k' | 'K')) {
+            // The callback module of `K` is only used for maps ordered by it
+            if modifier == 'K' {
+                args.next()?;
+            }
+        }
+        let field = Field { width, left, pad };
+
+        match chars.next()? {
+            '~' => out.push('~'),
+            'n' => out.push('\n'),
+            'i' => {
+                args.next()?;
+            }
+            'c' => {
+                let Term::Int(c) = args.next()?.into() else { return None; };
+                let c = char::from_u32(u32::try_from(c).ok()?)?;
+                let count = precision.or(width).unwrap_or(1);
+                field.write(&mut out, &c.to_string().repeat(count), false);
+            }
+            control @ ('f' | 'e' | 'g') => {
+                let value = match args.next()?.into() {
+                    Term::Float(value) => value.inner(),
+                    Term::Int(value) => value as f64,
+                    _ => return None,
+                };
+                let precision = precision.unwrap_or(6);
+                let text = match control {
+                    'f' if precision >= 1 => float::fixed(value, precision),
+                    'e' if precision >= 2 => float::exponential(value, precision),
+                    'g' if precision >= 1 => float::general(value, precision),
+                    _ => return None,
+                };
+                field.write(&mut out, &text, true);
+            }
+            's' => {
+                let arg = args.next()?;
+                let text = match arg.into() {
+                    Term::Atom(atom) => atom.as_str().to_string(),
+                    _ => string(arg)?,
+                };
+                // The string is cut short to fit the field, or to the precision if given
+                let text = match precision.or(width) {
+                    Some(limit) => text.chars().take(limit).collect(),
+                    None => text,
+                };
+                field.write(&mut out, &text, false);
+            }
+            control @ ('w' | 'p' | 'W' | 'P') => {
+                let term = Term::from(args.next()?);
+                // The depth to which `~W` and `~P` write the term, which is written in full here
+                if matches!(control, 'W' | 'P') && !matches!(args.next()?.into(), Term::Int(_)) {
+                    return None;
+                }
+                let text = term.to_string();
+                // The field width of `~p` is the width of a line, which is never exceeded here
+                if matches!(control, 'p' | 'P') {
+                    out.push_str(&text);
+                } else {
+                    field.write(&mut out, &text, true);
+                }
+            }
+            control @ ('b' | 'B' | 'x' | 'X' | '+' | '#') => {
+                let value = match args.next()?.into() {
+                    Term::Int(value) => BigInt::from(value),
+                    Term::BigInt(value) => (*value).clone(),
+                    _ => return None,
+                };
+                let base = precision.unwrap_or(10);
+                if !(2..=36).contains(&base) {
+                    return None;
+                }
+                let prefix = match control {
+                    'x' | 'X' => string(args.next()?)?,
+                    '+' | '#' => format!("{}#", base),
+                    _ => String::new(),
+                };
+                let digits = value.magnitude().to_str_radix(base as u32);
+                let digits = match control {
+                    'B' | 'X' | '#' => digits.to_uppercase(),
+                    _ => digits,
+                };
+                let sign = if value < BigInt::from(0) { "-" } else { "" };
+                field.write(&mut out, &format!("{}{}{}", sign, prefix, digits), true);
+            }
+            _ => return None,
+        }
+    }
+    if args.next().is_some() {
+        return None;
+    }
+    Some(out)
+}
+
+/// Returns the characters of `term`, a string or UTF-8 iodata
+fn string(term: OpaqueTerm) -> Option<String> {
+    charlist_to_string(term).or_else(|| String::from_utf8(iodata_to_bytes(term)?).ok())
+}
+
+/// Parses the digits of a field width or precision
+fn number(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<usize> {
+    let mut number = None;
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        let digit = digit.to_digit(10).unwrap() as usize;
+        number = Some(number.unwrap_or(0) * 10 + digit);
+    }
+    number
+}
+
+/// How the text written by a control sequence is laid out
+struct Field {
+    width: Option<usize>,
+    left: bool,
+    pad: char,
+}
+impl Field {
+    /// Writes `text` padded to the width of the field, or as `*`s if it does not fit and
+    /// `stars` is set, as for numbers and terms
+    fn write(&self, out: &mut String, text: &str, stars: bool) {
+        let len = text.chars().count();
+        let Some(width) = self.width else {
+            out.push_str(text);
+            return;
+        };
+        if len > width && stars {
+            out.push_str(&"*".repeat(width));
+            return;
+        }
+        let padding = self.pad.to_string().repeat(width.saturating_sub(len));
+        if self.left {
+            let _ = write!(out, "{}{}", text, padding);
+        } else {
+            let _ = write!(out, "{}{}", padding, text);
+        }
+    }
+}
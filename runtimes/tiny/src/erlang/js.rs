@@ -0,0 +1,129 @@
+//! The BIFs of the `js` module, which gives Erlang code access to JavaScript on wasm32.
+//!
+//! Arguments are converted to JavaScript values and results back to terms as described in
+//! `web::convert`, so objects such as DOM nodes are referred to by references, e.g.:
+//!
+//! ```erlang
+//! Document = js:get(js:global(), document),
+//! App = js:call(Document, getElementById, [<<"app">>]),
+//! ok = js:set(App, textContent, <<"Hello from Erlang">>).
+//! ```
+//!
+//! An exception thrown by JavaScript code is raised as an error of the form `{js_error,
+//! Exception}`.
+use wasm_bindgen::JsValue;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::web::{self, convert};
+
+use super::util::*;
+
+/// Returns the global object, i.e. `globalThis`
+#[export_name = "js:global/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn global0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        convert::from_js(&js_sys::global(), proc)
+    }))
+}
+
+/// Returns the value of the property `key` of `object`
+#[export_name = "js:get/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get2(object: OpaqueTerm, key: OpaqueTerm) -> ErlangResult {
+    let object = convert::to_js(object);
+    if !object.is_object() {
+        return super::badarg(Trace::capture());
+    }
+    to_result(js_sys::Reflect::get(&object, &convert::to_js(key)))
+}
+
+/// Sets the property `key` of `object` to `value`
+#[export_name = "js:set/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set3(
+    object: OpaqueTerm,
+    key: OpaqueTerm,
+    value: OpaqueTerm,
+) -> ErlangResult {
+    let object = convert::to_js(object);
+    if !object.is_object() {
+        return super::badarg(Trace::capture());
+    }
+    let key = convert::to_js(key);
+    match js_sys::Reflect::set(&object, &key, &convert::to_js(value)) {
+        Ok(_) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(exception) => raise(exception),
+    }
+}
+
+/// Calls the method `name` of `object` with `args`, returning its result
+#[export_name = "js:call/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn call3(
+    object: OpaqueTerm,
+    name: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    let Some(args) = list_to_vec(args) else {
+        return super::badarg(Trace::capture());
+    };
+    let object = convert::to_js(object);
+    if !object.is_object() {
+        return super::badarg(Trace::capture());
+    }
+    let method = match js_sys::Reflect::get(&object, &convert::to_js(name)) {
+        Ok(method) if method.is_function() => js_sys::Function::from(method),
+        Ok(_) => return super::badarg(Trace::capture()),
+        Err(exception) => return raise(exception),
+    };
+    let args = args
+        .into_iter()
+        .map(convert::to_js)
+        .collect::<js_sys::Array>();
+    to_result(js_sys::Reflect::apply(&method, &object, &args))
+}
+
+/// Writes `term` to the console, as with `console.log`
+#[export_name = "js:console_log/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn console_log1(term: OpaqueTerm) -> ErlangResult {
+    web_sys::console::log_1(&convert::to_js(term));
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Writes `term` to the console, as with `console.error`
+#[export_name = "js:console_error/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn console_error1(term: OpaqueTerm) -> ErlangResult {
+    web_sys::console::error_1(&convert::to_js(term));
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Passes `message` to the listeners registered from JavaScript with `onMessage`
+#[export_name = "js:post/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn post1(message: OpaqueTerm) -> ErlangResult {
+    match web::post(&convert::to_js(message)) {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(exception) => raise(exception),
+    }
+}
+
+fn to_result(result: Result<JsValue, JsValue>) -> ErlangResult {
+    match result {
+        Ok(value) => ErlangResult::Ok(with_process(|proc| convert::from_js(&value, proc))),
+        Err(exception) => raise(exception),
+    }
+}
+
+fn raise(exception: JsValue) -> ErlangResult {
+    let reason = with_process(|proc| {
+        let exception = convert::from_js(&exception, proc);
+        make_tuple(proc, &[atom("js_error").into(), exception])
+    });
+    ErlangResult::raise(atoms::Error, reason.into(), Trace::capture())
+}
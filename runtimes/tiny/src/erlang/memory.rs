@@ -0,0 +1,148 @@
+//! `erlang:memory/0,1`, the memory used by the system, in bytes, by type, in the same form as in
+//! ERTS, so that existing monitoring tools can read it.
+//!
+//! The totals are taken from the allocators of each type of memory, see
+//! `firefly_alloc::allocators::carriers`, and from the atom table:
+//!
+//! * `processes` is the size of the carriers of the process heap allocator, and `processes_used`
+//! the size of the blocks allocated from them.
+//! * `binary` and `ets` are the size of the blocks allocated by the binary and ETS allocators.
+//! * `atom` is the memory allocated for the atom table, and `atom_used` the part of it in use. The
+//! atoms compiled into the executable are part of its read-only data, and are not counted.
+//! * `code` is always zero, as code is compiled into the executable rather than loaded.
+//! * `system` is all of the memory which is not `processes`, and `total` the sum of both.
+//!
+//! When the global allocator is `firefly_alloc::allocators::Limited`, as in executables, `total` is
+//! all of the memory allocated, so `system` includes memory the allocators do not know about, e.g.
+//! that of the runtime itself. Otherwise `system` is the sum of the carriers of the other
+//! allocators and the atom table.
+use firefly_alloc::allocators::carriers::{AllocatorType, Stats};
+use firefly_alloc::allocators::limited;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use super::util::*;
+
+/// The types of memory, in the order they are returned by `memory/0`
+const TYPES: [&str; 9] = [
+    "total",
+    "processes",
+    "processes_used",
+    "system",
+    "atom",
+    "atom_used",
+    "binary",
+    "code",
+    "ets",
+];
+
+/// Returns `[{Type, Size}]` for each type of memory
+#[export_name = "erlang:memory/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn memory0() -> ErlangResult {
+    let memory = Memory::get();
+    ErlangResult::Ok(with_process(|proc| {
+        let types = TYPES.map(|ty| memory.tuple(proc, ty).unwrap());
+        make_list(proc, &types)
+    }))
+}
+
+/// Returns the size of the memory of type `Type`, or for a list of types, `[{Type, Size}]`
+#[export_name = "erlang:memory/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn memory1(ty: OpaqueTerm) -> ErlangResult {
+    let memory = Memory::get();
+    let result = with_process(|proc| match ty.into() {
+        Term::Atom(ty) => memory.size(ty.as_str()).map(|size| make_size(proc, size)),
+        _ => {
+            let types = list_to_vec(ty)?;
+            let mut sizes = Vec::with_capacity(types.len());
+            for ty in types {
+                let Term::Atom(ty) = ty.into() else { return None };
+                sizes.push(memory.tuple(proc, ty.as_str())?);
+            }
+            Some(make_list(proc, sizes.as_slice()))
+        }
+    });
+    match result {
+        Some(result) => ErlangResult::Ok(result),
+        None => super::badarg(Trace::capture()),
+    }
+}
+
+/// A snapshot of the memory used by the system, in bytes
+struct Memory {
+    total: usize,
+    processes: usize,
+    processes_used: usize,
+    system: usize,
+    atom: usize,
+    atom_used: usize,
+    binary: usize,
+    ets: usize,
+}
+impl Memory {
+    fn get() -> Self {
+        let heap = AllocatorType::Heap.allocator().stats();
+        let processes = carriers_size(&heap);
+        let processes_used = blocks_size(&heap);
+        let atoms = Atom::table_memory();
+        let [binary, ets, driver] = [
+            AllocatorType::Binary,
+            AllocatorType::Ets,
+            AllocatorType::Driver,
+        ]
+        .map(|ty| ty.allocator().stats());
+        let system =
+            atoms.allocated + carriers_size(&binary) + carriers_size(&ets) + carriers_size(&driver);
+        // The allocators are themselves backed by the global allocator, so when it keeps count,
+        // everything it has allocated beyond the process heaps is system memory
+        let total = limited::allocated().max(processes + system);
+        Self {
+            total,
+            processes,
+            processes_used,
+            system: total - processes,
+            atom: atoms.allocated,
+            atom_used: atoms.used,
+            binary: blocks_size(&binary),
+            ets: blocks_size(&ets),
+        }
+    }
+
+    /// Returns the size of the memory of type `ty`, or `None` if there is no such type
+    fn size(&self, ty: &str) -> Option<usize> {
+        match ty {
+            "total" => Some(self.total),
+            "processes" => Some(self.processes),
+            "processes_used" => Some(self.processes_used),
+            "system" => Some(self.system),
+            "atom" => Some(self.atom),
+            "atom_used" => Some(self.atom_used),
+            "binary" => Some(self.binary),
+            "code" => Some(0),
+            "ets" => Some(self.ets),
+            _ => None,
+        }
+    }
+
+    /// Returns `{Type, Size}` for the memory of type `ty`
+    fn tuple(&self, proc: &Process, ty: &str) -> Option<OpaqueTerm> {
+        let size = make_size(proc, self.size(ty)?);
+        Some(make_tuple(proc, &[atom(ty).into(), size]))
+    }
+}
+
+fn carriers_size(stats: &Stats) -> usize {
+    stats.mbcs.carriers_size + stats.sbcs.carriers_size
+}
+
+fn blocks_size(stats: &Stats) -> usize {
+    stats.mbcs.blocks_size + stats.sbcs.blocks_size
+}
+
+fn make_size(proc: &Process, size: usize) -> OpaqueTerm {
+    size.into_term(proc).unwrap().into()
+}
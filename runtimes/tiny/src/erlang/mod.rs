@@ -1,6 +1,51 @@
+pub mod application;
+pub mod atomics;
+pub mod dbg;
+pub mod disk_log;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod erl_ddll;
+#[cfg(feature = "test")]
+pub mod erts_debug;
+pub mod etf;
 pub mod file;
+pub mod firefly_config;
+pub mod firefly_diag;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod firefly_socket;
+pub(crate) mod gen;
+pub mod gen_server;
+pub mod gen_statem;
+pub mod halt;
+pub mod init;
+pub mod io;
+pub mod io_lib;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub mod js;
 pub mod lists;
+pub mod memory;
+pub mod msacc;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod nif;
+pub mod os;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod port;
+pub mod process;
+pub mod re;
+pub mod seq_trace;
+pub mod spawn;
+pub mod statistics;
+pub mod string;
+pub mod supervisor;
+pub mod suspend;
+pub mod sys;
+pub mod system_info;
+pub mod system_monitor;
+pub mod time;
+pub mod trace;
 pub mod unicode;
+pub mod zlib;
+
+pub(crate) mod util;
 
 use std::io::Write;
 use std::ops::Deref;
@@ -247,23 +292,22 @@ pub extern "C-unwind" fn apply3(
         }
         Some(callee) => callee,
     };
-    // Ensure the call is in tail position to allow for tail call optimization
-    // if it can be applied by the compiler
-    unsafe { function::apply_callee(callee, args.as_slice()) }
+    let Some(call) = trace::call(&mfa, arglist) else {
+        // Leave the call to our caller rather than making it here, so that tail calls
+        // through the dispatch table never grow the stack
+        return unsafe { function::schedule_tail_call(callee, args.as_ptr(), args.len()) };
+    };
+    // The outcome of a traced call is traced as well, so it cannot be a tail call
+    let result = unsafe { function::apply_callee(callee, args.as_slice()) };
+    call.returned(&result);
+    result
 }
 
 #[track_caller]
 fn list_element_or_err(element: Result<Term, ImproperList>) -> ErlangResult {
     match element {
         Ok(term) => ErlangResult::Ok(term.into()),
-        Err(_) => {
-            let exception = Box::into_raw(ErlangException::new(
-                atoms::Error,
-                atoms::Badarg.into(),
-                Trace::capture(),
-            ));
-            ErlangResult::Err(unsafe { NonNull::new_unchecked(exception) })
-        }
+        Err(_) => badarg(Trace::capture()),
     }
 }
 
@@ -342,10 +386,17 @@ pub extern "C-unwind" fn binary_to_list(term: OpaqueTerm) -> ErlangResult {
     }
 }
 
+/// Writes `term` to stdout, followed by a newline
+///
+/// This bypasses group leaders and I/O servers, so that it can still be relied on when the I/O
+/// subsystem itself is what is broken. The output goes to stdout as in OTP, which is where the
+/// output of programs is checked, e.g. by the lit tests and by `firefly shell`, which reads the
+/// result of an expression back from it. Errors writing are ignored, as there is nowhere left to
+/// report them.
 #[export_name = "erlang:display/1"]
 pub extern "C-unwind" fn display(term: OpaqueTerm) -> ErlangResult {
     let term: Term = term.into();
-    println!("{}", &term);
+    let _ = writeln!(std::io::stdout().lock(), "{}", &term);
     ErlangResult::Ok(true.into())
 }
 
@@ -356,29 +407,40 @@ pub extern "C-unwind" fn debug(term: OpaqueTerm) -> ErlangResult {
     ErlangResult::Ok(true.into())
 }
 
+/// Writes a newline to stdout, see `display/1`
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:display_nl/0"]
 pub extern "C-unwind" fn display_nl() -> ErlangResult {
-    println!();
+    let _ = writeln!(std::io::stdout().lock());
     ErlangResult::Ok(true.into())
 }
 
+/// Writes the string or binary `chars` to stdout as it is, see `display/1`
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:display_string/1"]
-pub extern "C-unwind" fn display_string(term: OpaqueTerm) -> ErlangResult {
-    let list: Term = term.into();
-    match list {
-        Term::Nil => ErlangResult::Ok(true.into()),
-        Term::Cons(ptr) => {
-            let cons = unsafe { ptr.as_ref() };
-            match cons.to_string() {
-                Some(ref s) => print!("{}", s),
-                None => return badarg(Trace::capture()),
-            }
-            ErlangResult::Ok(true.into())
-        }
-        _other => badarg(Trace::capture()),
-    }
+pub extern "C-unwind" fn display_string(chars: OpaqueTerm) -> ErlangResult {
+    display_string2(util::atom("stdout").into(), chars)
+}
+
+/// Writes the string or binary `chars` to `Device`, which is `stdout` or `stderr`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:display_string/2"]
+pub extern "C-unwind" fn display_string2(device: OpaqueTerm, chars: OpaqueTerm) -> ErlangResult {
+    let bytes = match util::charlist_to_string(chars) {
+        Some(s) => s.into_bytes(),
+        None => match Term::from(chars).as_bitstring() {
+            Some(bits) if bits.is_binary() => bits.bytes().collect(),
+            _ => return badarg(Trace::capture()),
+        },
+    };
+    let _ = if util::is_atom(device, "stderr") {
+        std::io::stderr().lock().write_all(bytes.as_slice())
+    } else if util::is_atom(device, "stdout") {
+        std::io::stdout().lock().write_all(bytes.as_slice())
+    } else {
+        return badarg(Trace::capture());
+    };
+    ErlangResult::Ok(true.into())
 }
 
 #[allow(improper_ctypes_definitions)]
@@ -395,11 +457,44 @@ pub extern "C-unwind" fn puts(printable: OpaqueTerm) -> ErlangResult {
     ErlangResult::Ok(true.into())
 }
 
+/// Sends `message` to `dest`, which may be a pid or a registered name, returning `message`
+///
+/// Only servers (see `gen`) and the program embedding the runtime (see `Runtime`) have mailboxes in
+/// this runtime, so a message sent to any other pid is discarded, as if the process had exited.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:!/2"]
+pub extern "C-unwind" fn bang2(dest: OpaqueTerm, message: OpaqueTerm) -> ErlangResult {
+    let pid = match dest.into() {
+        Term::Pid(pid) => pid.id(),
+        Term::Atom(_) => match gen::whereis(dest) {
+            Some(pid) => pid,
+            None => return badarg(Trace::capture()),
+        },
+        _ => return badarg(Trace::capture()),
+    };
+    trace::send(message, dest);
+    if pid == scheduler::with_current(|scheduler| scheduler.root_pid()) {
+        let received = gen::Message::Info(message);
+        trace::receive(pid, &received);
+        let token = seq_trace::send(pid, &received);
+        seq_trace::receive(pid, token, &received);
+        crate::runtime::deliver(util::make_global(message));
+    } else {
+        gen::cast(pid, gen::Message::Info(util::make_global(message)));
+    }
+    ErlangResult::Ok(message)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:send/2"]
+pub extern "C-unwind" fn send2(dest: OpaqueTerm, message: OpaqueTerm) -> ErlangResult {
+    bang2(dest, message)
+}
+
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:error/1"]
 pub extern "C-unwind" fn error1(reason: OpaqueTerm) -> ErlangResult {
-    let err = ErlangException::new(atoms::Error, reason.into(), Trace::capture());
-    ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(err)) })
+    ErlangResult::raise(atoms::Error, reason.into(), Trace::capture())
 }
 
 #[allow(improper_ctypes_definitions)]
@@ -413,23 +508,44 @@ pub extern "C-unwind" fn error2(reason: OpaqueTerm, _args: OpaqueTerm) -> Erlang
 pub extern "C-unwind" fn error3(
     reason: OpaqueTerm,
     _args: OpaqueTerm,
-    _options: OpaqueTerm,
+    options: OpaqueTerm,
 ) -> ErlangResult {
-    error1(reason)
+    match options.into() {
+        Term::Nil => error1(reason),
+        Term::Cons(ptr) => {
+            let options = unsafe { ptr.as_ref() };
+            match options.keyfind(0, atoms::ErrorInfo) {
+                Ok(None) => error1(reason),
+                Ok(Some(Term::Tuple(tuple))) => {
+                    let tuple = unsafe { tuple.as_ref() };
+                    match tuple.get(1) {
+                        Some(error_info @ Term::Map(_)) if tuple.len() == 2 => {
+                            ErlangResult::raise_with_error_info(
+                                reason.into(),
+                                error_info,
+                                Trace::capture(),
+                            )
+                        }
+                        _ => badarg(Trace::capture()),
+                    }
+                }
+                _ => badarg(Trace::capture()),
+            }
+        }
+        _ => badarg(Trace::capture()),
+    }
 }
 
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:exit/1"]
 pub extern "C-unwind" fn exit1(reason: OpaqueTerm) -> ErlangResult {
-    let err = ErlangException::new(atoms::Exit, reason.into(), Trace::capture());
-    ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(err)) })
+    ErlangResult::raise(atoms::Exit, reason.into(), Trace::capture())
 }
 
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:throw/1"]
 pub extern "C-unwind" fn throw1(reason: OpaqueTerm) -> ErlangResult {
-    let err = ErlangException::new(atoms::Throw, reason.into(), Trace::capture());
-    ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(err)) })
+    ErlangResult::raise(atoms::Throw, reason.into(), Trace::capture())
 }
 
 #[allow(improper_ctypes_definitions)]
@@ -438,12 +554,40 @@ pub extern "C-unwind" fn nif_error1(reason: OpaqueTerm) -> ErlangResult {
     error1(reason)
 }
 
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:nif_error/2"]
+pub extern "C-unwind" fn nif_error2(reason: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    error2(reason, args)
+}
+
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:raise/2"]
 pub extern "C-unwind" fn raise2(reason: OpaqueTerm, trace: NonNull<Trace>) -> ErlangResult {
     let trace = unsafe { Trace::from_raw(trace.as_ptr()) };
-    let err = ErlangException::new(atoms::Error, reason.into(), trace);
-    ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(err)) })
+    ErlangResult::raise(atoms::Error, reason.into(), trace)
+}
+
+/// Raises an exception of the given class, reason and stacktrace.
+///
+/// Per OTP, if the class is invalid or the stacktrace is not a list, this raises
+/// `badarg` rather than the requested exception.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:raise/3"]
+pub extern "C-unwind" fn raise3(
+    class: OpaqueTerm,
+    reason: OpaqueTerm,
+    stacktrace: OpaqueTerm,
+) -> ErlangResult {
+    let Term::Atom(class) = class.into() else { return badarg(Trace::capture()) };
+    if !ErlangException::is_valid_kind(class) {
+        return badarg(Trace::capture());
+    }
+    let trace = match stacktrace.into() {
+        Term::Nil => Trace::capture(),
+        stacktrace @ Term::Cons(_) => Trace::from_term(stacktrace),
+        _ => return badarg(Trace::capture()),
+    };
+    ErlangResult::raise(class, reason.into(), trace)
 }
 
 fn make_reason<R: Into<OpaqueTerm>>(tag: Atom, reason: R) -> OpaqueTerm {
@@ -463,10 +607,9 @@ pub(self) fn undef(trace: Arc<Trace>) -> ErlangResult {
 }
 
 pub(self) fn badarg(trace: Arc<Trace>) -> ErlangResult {
-    ErlangResult::Err(badarg_err(trace))
+    ErlangResult::badarg(trace)
 }
 
 pub(self) fn badarg_err(trace: Arc<Trace>) -> NonNull<ErlangException> {
-    let err = ErlangException::new(atoms::Error, atoms::Badarg.into(), trace);
-    unsafe { NonNull::new_unchecked(Box::into_raw(err)) }
+    ErlangException::badarg(trace).into_raw()
 }
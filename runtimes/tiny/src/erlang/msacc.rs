@@ -0,0 +1,143 @@
+//! `msacc`, microstate accounting as in OTP's `runtime_tools`, which measures the time the
+//! schedulers spend in each of their states, see `erlang:statistics(microstate_accounting)`.
+//!
+//! `start/0`, `stop/0` and `reset/0` set `erlang:system_flag(microstate_accounting, _)`, returning
+//! its previous value, and `start(Milliseconds)` measures from zero over the given interval, which
+//! the calling process spends yielding to the others. `stats/0` returns the counters, and
+//! `print/0` prints the share of the time measured spent in each state.
+//!
+//! There is a single scheduler, and no async or dirty scheduler threads, so the statistics cover
+//! one thread of type `scheduler`. Accounting is cheap enough to be left on, at the cost of reading
+//! the clock each time the scheduler switches state.
+use std::fmt::Write as _;
+use std::io::{self, Write as _};
+use std::time::Duration;
+
+use instant::Instant;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::{Resumable, Step};
+use firefly_rt::term::*;
+
+use crate::scheduler::{self, Microstate};
+
+use super::statistics::statistics1;
+use super::util::*;
+
+/// Returns true, as microstate accounting is always available
+#[export_name = "msacc:available/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn available0() -> ErlangResult {
+    ErlangResult::Ok(true.into())
+}
+
+/// Starts accounting, returning whether it was already started
+#[export_name = "msacc:start/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start0() -> ErlangResult {
+    ErlangResult::Ok(enable(true).into())
+}
+
+/// Resets the counters and accounts for `Milliseconds`, returning true
+#[export_name = "msacc:start/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start1(ms: OpaqueTerm) -> ErlangResult {
+    let ms = match ms.into() {
+        Term::Int(ms) if ms >= 0 => ms as u64,
+        _ => return super::badarg(Trace::capture()),
+    };
+    enable(false);
+    scheduler::with_current(|scheduler| scheduler.msacc().reset());
+    enable(true);
+    scheduler::trampoline(Wait {
+        deadline: Instant::now() + Duration::from_millis(ms),
+    });
+    ErlangResult::Ok(enable(false).into())
+}
+
+/// Stops accounting, returning whether it was started
+#[export_name = "msacc:stop/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn stop0() -> ErlangResult {
+    ErlangResult::Ok(enable(false).into())
+}
+
+/// Zeroes the counters, returning whether accounting is started
+#[export_name = "msacc:reset/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn reset0() -> ErlangResult {
+    let enabled = scheduler::with_current(|scheduler| {
+        scheduler.msacc().reset();
+        scheduler.msacc().is_enabled()
+    });
+    ErlangResult::Ok(enabled.into())
+}
+
+/// Returns the counters of each thread, as `erlang:statistics(microstate_accounting)`
+#[export_name = "msacc:stats/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn stats0() -> ErlangResult {
+    statistics1(atom("microstate_accounting").into())
+}
+
+/// Prints the share of the time measured spent in each state
+#[export_name = "msacc:print/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn print0() -> ErlangResult {
+    let counters = scheduler::with_current(|scheduler| scheduler.msacc().counters());
+    let _ = io::stdout().lock().write_all(report(counters).as_bytes());
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+fn enable(enable: bool) -> bool {
+    scheduler::with_current(|scheduler| scheduler.msacc().enable(enable))
+}
+
+/// Formats the counters as the table printed by `msacc:print/0` in OTP
+fn report(counters: Option<[u64; 7]>) -> String {
+    let mut report = String::new();
+    let Some(counters) = counters else {
+        report.push_str("Microstate accounting has not been started, see msacc:start/0,1\n");
+        return report;
+    };
+    let total = counters.iter().sum::<u64>();
+    let sleep = counters[Microstate::Sleep as usize];
+    let _ = writeln!(
+        report,
+        "Average thread real-time    : {:>10} us",
+        total / 1_000
+    );
+    let _ = writeln!(
+        report,
+        "Average scheduler run-time  : {:>10} us",
+        (total - sleep) / 1_000
+    );
+    let _ = write!(report, "\n{:>14}", "Thread");
+    for state in Microstate::ALL {
+        let _ = write!(report, " {:>8}", state.name());
+    }
+    let _ = write!(report, "\n\nStats per thread:\n{:>14}", "scheduler( 1)");
+    for counter in counters {
+        let share = counter as f64 * 100.0 / total.max(1) as f64;
+        let _ = write!(report, " {:>7.2}%", share);
+    }
+    report.push('\n');
+    report
+}
+
+/// Waits for a deadline by yielding to the other processes
+struct Wait {
+    deadline: Instant,
+}
+impl Resumable for Wait {
+    type Output = ();
+
+    fn resume(&mut self, budget: &mut usize) -> Step<Self::Output> {
+        if Instant::now() < self.deadline {
+            *budget = 0;
+            return Step::Yield;
+        }
+        Step::Done(())
+    }
+}
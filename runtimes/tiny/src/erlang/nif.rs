@@ -0,0 +1,108 @@
+//! The BIFs backing `erlang:load_nif/2`, and the dispatch of calls to the native functions it
+//! loads.
+//!
+//! Calls to `erlang:load_nif/2` are compiled to calls to `erlang:load_nif/3`, which is given the
+//! module on whose behalf the library is loaded. Only calls made dynamically reach
+//! `erlang:load_nif/2`, which looks for the calling module on the stack instead.
+//!
+//! Calls to functions declared as NIFs are compiled to check `erlang:is_nif_loaded/3`, calling
+//! `erlang:apply_nif/3` if a native implementation has been loaded, and the Erlang definition
+//! of the function otherwise. See `firefly_nif` for the implementation of the interface itself.
+use firefly_rt::backtrace::{Symbol, Trace};
+use firefly_rt::function::{ErlangResult, ModuleFunctionArity};
+use firefly_rt::term::*;
+
+use super::util::*;
+use super::{badarg, undef};
+
+#[export_name = "erlang:load_nif/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn load_nif2(path: OpaqueTerm, load_info: OpaqueTerm) -> ErlangResult {
+    let trace = Trace::capture();
+    // As with BEAM, the library is loaded on behalf of the module calling this function
+    let Some(module) = calling_module(&trace) else {
+        return badarg(trace);
+    };
+    load_nif(module, path, load_info)
+}
+
+#[export_name = "erlang:load_nif/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn load_nif3(
+    module: OpaqueTerm,
+    path: OpaqueTerm,
+    load_info: OpaqueTerm,
+) -> ErlangResult {
+    let Term::Atom(module) = module.into() else {
+        return badarg(Trace::capture());
+    };
+    load_nif(module, path, load_info)
+}
+
+fn load_nif(module: Atom, path: OpaqueTerm, load_info: OpaqueTerm) -> ErlangResult {
+    let Some(path) = charlist_to_string(path) else {
+        return badarg(Trace::capture());
+    };
+
+    with_process(
+        |proc| match firefly_nif::load(proc, module, path.as_str(), load_info) {
+            Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+            Err(err) => {
+                let reason = atom(err.reason()).into();
+                let text = charlist(proc, err.text());
+                let error = make_tuple(proc, &[reason, text]);
+                ErlangResult::Ok(make_tuple(proc, &[atoms::Error.into(), error]))
+            }
+        },
+    )
+}
+
+#[export_name = "erlang:is_nif_loaded/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn is_nif_loaded3(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    arity: OpaqueTerm,
+) -> ErlangResult {
+    let (Term::Atom(module), Term::Atom(function), Term::Int(arity)) =
+        (module.into(), function.into(), arity.into())
+    else {
+        return badarg(Trace::capture());
+    };
+    let mfa = ModuleFunctionArity::new(module, function, arity as usize);
+    ErlangResult::Ok(firefly_nif::is_loaded(&mfa).into())
+}
+
+#[export_name = "erlang:apply_nif/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn apply_nif3(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    let (Term::Atom(module), Term::Atom(function)) = (module.into(), function.into()) else {
+        return badarg(Trace::capture());
+    };
+    let Some(args) = list_to_vec(args) else {
+        return badarg(Trace::capture());
+    };
+    let mfa = ModuleFunctionArity::new(module, function, args.len());
+    let Some(nif) = firefly_nif::lookup(&mfa) else {
+        return undef(Trace::capture());
+    };
+
+    match with_process(|proc| nif.call(proc, args.as_slice())) {
+        Ok(result) => ErlangResult::Ok(result),
+        Err(reason) => ErlangResult::raise(atoms::Error, reason.into(), Trace::capture()),
+    }
+}
+
+/// Returns the module of the innermost Erlang function on the stack, ignoring the BIF itself
+fn calling_module(trace: &Trace) -> Option<Atom> {
+    trace
+        .iter_symbols()
+        .find_map(|symbol| match symbol.symbol() {
+            Some(Symbol::Erlang(mfa)) if mfa.module != atoms::Erlang => Some(mfa.module),
+            _ => None,
+        })
+}
@@ -0,0 +1,313 @@
+//! The `os` module, for interacting with the operating system the runtime is running on.
+//!
+//! `getenv/0,1,2`, `putenv/2` and `unsetenv/1` read and write the environment of the OS process,
+//! `type/0` returns `{Family, Name}`, e.g. `{unix, linux}` or `{win32, nt}`, and `getpid/0` the OS
+//! process id as a string. On WebAssembly the family is `wasm`, with the name `wasi` or `web`, and
+//! the pid is always `"0"`. In the browser the environment is empty, and writing it fails with
+//! `badarg`. `system_time/0,1` and `timestamp/0` return OS system time, which unlike Erlang system
+//! time warps whenever the clock of the OS is adjusted, whatever the time warp mode, see
+//! `crate::time`.
+//!
+//! `cmd/1,2` runs a command with the shell, and returns what it writes to standard output, as a
+//! string if it is valid UTF-8, or else as a list of bytes. The command is run by a port of the
+//! `firefly_cmd` driver, see `crate::sys::cmd`, which the caller waits on until the command exits,
+//! delivering the messages of any other port in the meantime. With `#{max_size => Size}`, the port
+//! is closed, killing the command, once `Size` bytes have been read. There are no commands on
+//! WebAssembly, so `cmd/1,2` fail with `badarg` there.
+//!
+//! `set_signal/2` has OS signals handled by Erlang rather than the runtime.
+//! `os:set_signal(Signal, handle)` has `Signal` delivered to the server registered as
+//! `erl_signal_server`, as `{notify, Signal}`, e.g. so that a service can shut down gracefully on
+//! `sigterm`, `ignore` has it dropped, and `default` restores what the runtime does with it, see
+//! `crate::sys::signals`. The signals are `sigint`, `sigterm`, `sigquit`, `sighup`, `sigabrt`,
+//! `sigalrm`, `sigusr1`, `sigusr2` and `sigchld`. There are no signals on WebAssembly, so this
+//! fails with `badarg` there.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::time;
+
+use super::badarg;
+use super::util::*;
+
+/// Returns the environment as a list of `"Name=Value"` strings
+#[export_name = "os:getenv/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn getenv0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        let vars = std::env::vars_os()
+            .map(|(name, value)| {
+                let var = format!("{}={}", name.to_string_lossy(), value.to_string_lossy());
+                charlist(proc, var.as_str())
+            })
+            .collect::<Vec<_>>();
+        make_list(proc, vars.as_slice())
+    }))
+}
+
+/// Returns the value of the environment variable `VarName`, or false if it is not set
+#[export_name = "os:getenv/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn getenv1(name: OpaqueTerm) -> ErlangResult {
+    getenv2(name, false.into())
+}
+
+/// Returns the value of the environment variable `VarName`, or `DefaultValue` if it is not set
+#[export_name = "os:getenv/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn getenv2(name: OpaqueTerm, default: OpaqueTerm) -> ErlangResult {
+    let Some(name) = env_name(name) else {
+        return badarg(Trace::capture());
+    };
+    match std::env::var_os(name) {
+        Some(value) => ErlangResult::Ok(with_process(|proc| {
+            charlist(proc, value.to_string_lossy().as_ref())
+        })),
+        None => ErlangResult::Ok(default),
+    }
+}
+
+/// Sets the environment variable `VarName` to `Value`, returning true
+#[export_name = "os:putenv/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn putenv2(name: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let (Some(name), Some(value)) = (env_name(name), charlist_to_string(value)) else {
+        return badarg(Trace::capture());
+    };
+    if value.contains('\0') || !set_env(name.as_str(), Some(value.as_str())) {
+        return badarg(Trace::capture());
+    }
+    ErlangResult::Ok(true.into())
+}
+
+/// Removes the environment variable `VarName`, returning true
+#[export_name = "os:unsetenv/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unsetenv1(name: OpaqueTerm) -> ErlangResult {
+    let Some(name) = env_name(name) else {
+        return badarg(Trace::capture());
+    };
+    if !set_env(name.as_str(), None) {
+        return badarg(Trace::capture());
+    }
+    ErlangResult::Ok(true.into())
+}
+
+/// Returns the name of an environment variable, which is a non-empty string without `=` or NUL
+fn env_name(name: OpaqueTerm) -> Option<String> {
+    charlist_to_string(name).filter(|name| !name.is_empty() && !name.contains(['=', '\0']))
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn set_env(name: &str, value: Option<&str>) -> bool {
+    match value {
+        Some(value) => std::env::set_var(name, value),
+        None => std::env::remove_var(name),
+    }
+    true
+}
+
+/// There is no environment in the browser, see the module documentation
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn set_env(_name: &str, _value: Option<&str>) -> bool {
+    false
+}
+
+/// Returns `{Family, Name}` for the operating system
+#[export_name = "os:type/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn type0() -> ErlangResult {
+    let (family, name) = if cfg!(windows) {
+        ("win32", "nt")
+    } else if cfg!(target_os = "macos") {
+        ("unix", "darwin")
+    } else if cfg!(target_os = "wasi") {
+        ("wasm", "wasi")
+    } else if cfg!(target_arch = "wasm32") {
+        ("wasm", "web")
+    } else {
+        ("unix", std::env::consts::OS)
+    };
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[atom(family).into(), atom(name).into()])
+    }))
+}
+
+/// Returns the OS process id of the runtime, as a string
+#[export_name = "os:getpid/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn getpid0() -> ErlangResult {
+    #[cfg(not(target_arch = "wasm32"))]
+    let pid = std::process::id();
+    #[cfg(target_arch = "wasm32")]
+    let pid = 0;
+    ErlangResult::Ok(with_process(|proc| {
+        charlist(proc, pid.to_string().as_str())
+    }))
+}
+
+/// Returns OS system time in `native` time units
+#[export_name = "os:system_time/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_time0() -> ErlangResult {
+    let now = time::os_system_time() as i128;
+    ErlangResult::Ok(with_process(|proc| super::time::make_i128(proc, now)))
+}
+
+/// Returns OS system time in `Unit`
+#[export_name = "os:system_time/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_time1(unit: OpaqueTerm) -> ErlangResult {
+    super::time::in_unit(time::os_system_time() as i128, unit)
+}
+
+/// Returns OS system time as `{MegaSecs, Secs, MicroSecs}`
+#[export_name = "os:timestamp/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn timestamp0() -> ErlangResult {
+    let micros = time::convert(time::os_system_time() as i128, time::NATIVE, 1_000_000);
+    ErlangResult::Ok(with_process(|proc| {
+        super::time::make_timestamp(proc, micros)
+    }))
+}
+
+/// Runs `Command` with the shell, returning its standard output
+#[export_name = "os:cmd/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn cmd1(command: OpaqueTerm) -> ErlangResult {
+    cmd(command, None)
+}
+
+/// Runs `Command` with the shell, returning at most `max_size` bytes of its standard output
+#[export_name = "os:cmd/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn cmd2(command: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let Term::Map(options) = options.into() else {
+        return badarg(Trace::capture());
+    };
+    let mut max_size = None;
+    for (key, value) in options.iter() {
+        match (key, value) {
+            (Term::Atom(key), Term::Atom(value))
+                if key.as_str() == "max_size" && value.as_str() == "infinity" => {}
+            (Term::Atom(key), Term::Int(size)) if key.as_str() == "max_size" && *size >= 0 => {
+                max_size = Some(*size as usize)
+            }
+            _ => return badarg(Trace::capture()),
+        }
+    }
+    cmd(command, max_size)
+}
+
+fn cmd(command: OpaqueTerm, max_size: Option<usize>) -> ErlangResult {
+    let command = match command.into() {
+        Term::Atom(command) => Some(command.as_str().to_string()),
+        _ => charlist_to_string(command),
+    };
+    let Some(command) = command else {
+        return badarg(Trace::capture());
+    };
+    match run(command.as_str(), max_size) {
+        Ok(output) => ErlangResult::Ok(with_process(|proc| match String::from_utf8(output) {
+            Ok(output) => charlist(proc, output.as_str()),
+            Err(err) => Cons::from_bytes(err.as_bytes(), proc)
+                .unwrap()
+                .map(|ptr| ptr.into())
+                .unwrap_or(OpaqueTerm::NIL),
+        })),
+        Err(None) => badarg(Trace::capture()),
+        Err(Some(reason)) => {
+            ErlangResult::raise(atoms::Error, Term::Atom(atom(reason)), Trace::capture())
+        }
+    }
+}
+
+/// Runs `command` on a port of the `firefly_cmd` driver, returning its output, or the reason the
+/// port could not be opened, `None` being `badarg`
+#[cfg(not(target_arch = "wasm32"))]
+fn run(command: &str, max_size: Option<usize>) -> Result<Vec<u8>, Option<&'static str>> {
+    use firefly_driver::{OpenError, PortMessage, PortOptions};
+
+    use crate::sys::cmd;
+
+    cmd::register();
+    let owner = with_process(|proc| proc.pid());
+    let command = format!("{} {}", cmd::NAME, command);
+    let port = match firefly_driver::open(command.as_str(), owner, PortOptions { binary: true }) {
+        Ok(port) => port,
+        Err(OpenError::NoDriver | OpenError::BadArg) => return Err(None),
+        Err(err) => return Err(Some(err.reason())),
+    };
+
+    let mut output = Vec::new();
+    loop {
+        let (events, others) = firefly_driver::drain_events()
+            .into_iter()
+            .partition::<Vec<_>, _>(|event| event.port == port);
+        super::port::deliver_events(others);
+        let mut exited = false;
+        for event in events {
+            match event.message {
+                PortMessage::Data { data, .. } => output.extend_from_slice(data.as_slice()),
+                PortMessage::Exit(_) => exited = true,
+            }
+        }
+        if let Some(max_size) = max_size {
+            if output.len() >= max_size {
+                output.truncate(max_size);
+                let _ = firefly_driver::close(port);
+                break;
+            }
+        }
+        if exited {
+            break;
+        }
+        // The output of the command is selected until it exits, so this always has something to
+        // wait for
+        let polled = crate::scheduler::with_current(|scheduler| {
+            scheduler.idle(|| firefly_driver::poll(None))
+        });
+        if !polled {
+            break;
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn run(_command: &str, _max_size: Option<usize>) -> Result<Vec<u8>, Option<&'static str>> {
+    Err(None)
+}
+
+/// Sets how `Signal` is handled, to `default`, `handle` or `ignore`
+#[export_name = "os:set_signal/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_signal2(signal: OpaqueTerm, option: OpaqueTerm) -> ErlangResult {
+    let (Term::Atom(signal), Term::Atom(option)) = (signal.into(), option.into()) else {
+        return badarg(Trace::capture());
+    };
+    if !set_signal(signal.as_str(), option.as_str()) {
+        return badarg(Trace::capture());
+    }
+    ErlangResult::Ok(atom("ok").into())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn set_signal(signal: &str, option: &str) -> bool {
+    use crate::sys::break_handler::Signal;
+    use crate::sys::signals::{self, Disposition};
+
+    match (Signal::from_name(signal), Disposition::from_name(option)) {
+        (Some(signal), Some(disposition)) => {
+            signals::set_disposition(signal, disposition);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn set_signal(_signal: &str, _option: &str) -> bool {
+    false
+}
@@ -0,0 +1,270 @@
+//! The BIFs for opening and operating on ports implemented by drivers, see `firefly_driver`.
+//!
+//! Only driver ports (`{spawn, Command}` and `{spawn_driver, Command}`) are supported. Drivers run
+//! in-process, so every operation on a port completes synchronously, and the messages produced by
+//! the driver are delivered to the owner of the port once the operation returns, or by the event
+//! loop in `gen::run_timers` for data which becomes available later.
+//!
+//! Since processes have no mailbox in this runtime, a port is only useful once it is handed to a
+//! server (`gen_server`, `gen_statem`) with `erlang:port_connect/2`, whose `handle_info` receives
+//! `{Port, {data, Data}}` and `{'EXIT', Port, Reason}`. Messages for the owning process itself are
+//! discarded.
+//!
+//! Ports have no priority of their own. Instead, a port inherits the priority of the process which
+//! last signalled it, i.e. opened, connected, closed or sent a command or control to it, and its
+//! messages are delivered at that priority: messages of ports of higher priority are delivered
+//! first, and the process delivering them, whose servers handle them, inherits the highest of the
+//! priorities for the duration, see `Process::inherit_priority`.
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::sync::Mutex;
+
+use firefly_driver::{ExitReason, OpenError, PortError, PortEvent, PortMessage, PortOptions};
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::{Priority, Process};
+use firefly_rt::term::*;
+
+use crate::scheduler::{self, Microstate};
+
+use super::badarg;
+use super::gen::{self, Message};
+use super::util::*;
+
+/// The priority each port has inherited from the process which last signalled it
+static PRIORITIES: Mutex<BTreeMap<PortId, Priority>> = Mutex::new(BTreeMap::new());
+
+#[export_name = "erlang:open_port/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn open_port2(name: OpaqueTerm, settings: OpaqueTerm) -> ErlangResult {
+    let Some([kind, command]) = tuple_elements(name) else {
+        return badarg(Trace::capture());
+    };
+    if !is_atom(*kind, "spawn") && !is_atom(*kind, "spawn_driver") {
+        return badarg(Trace::capture());
+    }
+    let Some(command) = charlist_to_string(*command).or_else(|| binary_to_string(*command)) else {
+        return badarg(Trace::capture());
+    };
+    let Some(settings) = list_to_vec(settings) else {
+        return badarg(Trace::capture());
+    };
+    let mut options = PortOptions::default();
+    for setting in settings {
+        match setting.into() {
+            Term::Atom(a) if a.as_str() == "binary" => options.binary = true,
+            // Other settings only apply to external programs, and are accepted for compatibility
+            _ => (),
+        }
+    }
+
+    let owner = with_process(|proc| proc.pid());
+    let result = firefly_driver::open(command.as_str(), owner, options);
+    if let Ok(id) = result {
+        signal(id);
+    }
+    deliver();
+    match result {
+        Ok(id) => ErlangResult::Ok(with_process(|proc| make_port(proc, id))),
+        Err(OpenError::NoDriver | OpenError::BadArg) => badarg(Trace::capture()),
+        Err(err) => ErlangResult::raise(
+            atoms::Error,
+            Term::Atom(atom(err.reason())),
+            Trace::capture(),
+        ),
+    }
+}
+
+#[export_name = "erlang:port_command/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn port_command2(port: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    let (Some(port), Some(data)) = (port_id(port), iodata_to_bytes(data)) else {
+        return badarg(Trace::capture());
+    };
+    signal(port);
+    let result = firefly_driver::command(port, data.as_slice());
+    deliver();
+    match result {
+        Ok(()) => ErlangResult::Ok(true.into()),
+        Err(_) => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "erlang:port_command/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn port_command3(
+    port: OpaqueTerm,
+    data: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    // Ports never block, so the `force` and `nosuspend` options have no effect
+    if list_to_vec(options).is_none() {
+        return badarg(Trace::capture());
+    }
+    port_command2(port, data)
+}
+
+#[export_name = "erlang:port_control/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn port_control3(
+    port: OpaqueTerm,
+    operation: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(port), Term::Int(operation), Some(data)) =
+        (port_id(port), operation.into(), iodata_to_bytes(data))
+    else {
+        return badarg(Trace::capture());
+    };
+    let Ok(operation) = u32::try_from(operation) else {
+        return badarg(Trace::capture());
+    };
+    signal(port);
+    let result = firefly_driver::control(port, operation, data.as_slice());
+    deliver();
+    match result {
+        Ok(reply) => ErlangResult::Ok(with_process(|proc| {
+            make_data(proc, reply.data.as_slice(), reply.binary)
+        })),
+        Err(_) => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "erlang:port_close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn port_close1(port: OpaqueTerm) -> ErlangResult {
+    let Some(port) = port_id(port) else {
+        return badarg(Trace::capture());
+    };
+    signal(port);
+    let result = firefly_driver::close(port);
+    deliver();
+    match result {
+        Ok(()) => ErlangResult::Ok(true.into()),
+        Err(_) => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "erlang:port_connect/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn port_connect2(port: OpaqueTerm, pid: OpaqueTerm) -> ErlangResult {
+    let (Some(port), Term::Pid(pid)) = (port_id(port), pid.into()) else {
+        return badarg(Trace::capture());
+    };
+    signal(port);
+    match firefly_driver::connect(port, pid.id()) {
+        Ok(()) => ErlangResult::Ok(true.into()),
+        Err(PortError::Closed | PortError::BadArg) => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "erlang:ports/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ports0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        let ports = firefly_driver::ports()
+            .into_iter()
+            .map(|id| make_port(proc, id))
+            .collect::<Vec<_>>();
+        make_list(proc, ports.as_slice())
+    }))
+}
+
+/// Delivers the messages produced by ports to their owners
+///
+/// Messages for anything other than a server are discarded, see the module documentation.
+pub(crate) fn deliver() {
+    deliver_events(firefly_driver::drain_events());
+}
+
+/// Delivers `events`, taken from `firefly_driver::drain_events`, to the owners of their ports
+///
+/// The messages of ports of higher priority are delivered first, each port's in the order they
+/// were produced, with the current process inheriting the highest of their priorities meanwhile.
+pub(crate) fn deliver_events(mut events: Vec<PortEvent>) {
+    if events.is_empty() {
+        return;
+    }
+    let inherited = {
+        let mut priorities = PRIORITIES.lock().unwrap();
+        let of = |event: &PortEvent| priorities.get(&event.port).copied().unwrap_or_default();
+        events.sort_by_key(|event| Reverse(of(event)));
+        let highest = of(&events[0]);
+        // A port which has exited is never signalled again
+        for event in events.iter() {
+            if let PortMessage::Exit(_) = event.message {
+                priorities.remove(&event.port);
+            }
+        }
+        highest
+    };
+    // Deliveries may be nested, as servers may signal ports while handling messages, in which case
+    // the priority inherited by the outer delivery is kept if it is higher
+    let previous = with_process(|proc| {
+        let previous = proc.inherit_priority(inherited);
+        proc.inherit_priority(inherited.max(previous));
+        previous
+    });
+    let state = scheduler::with_current(|scheduler| scheduler.msacc().switch(Microstate::Port));
+    for event in events {
+        let message = with_process(|proc| {
+            let port = make_port(proc, event.port);
+            let message = match event.message {
+                PortMessage::Data { data, binary } => {
+                    let data = make_data(proc, data.as_slice(), binary);
+                    let data = make_tuple(proc, &[atom("data").into(), data]);
+                    make_tuple(proc, &[port, data])
+                }
+                PortMessage::Exit(reason) => {
+                    let reason = match reason {
+                        ExitReason::Atom(name) => atom(name.as_str()).into(),
+                        ExitReason::Integer(code) => Term::Int(code as i64).into(),
+                    };
+                    make_tuple(proc, &[atoms::Exit.into(), port, reason])
+                }
+            };
+            make_global(message)
+        });
+        gen::cast(event.owner, Message::Info(message));
+    }
+    scheduler::with_current(|scheduler| scheduler.msacc().switch(state));
+    with_process(|proc| proc.inherit_priority(previous));
+}
+
+/// Records that the current process has signalled `port`, which inherits its priority
+fn signal(port: PortId) {
+    let priority = with_process(|proc| proc.effective_priority());
+    PRIORITIES.lock().unwrap().insert(port, priority);
+}
+
+pub(crate) fn port_id(term: OpaqueTerm) -> Option<PortId> {
+    match term.into() {
+        Term::Port(port) => match port.deref() {
+            Port::Local { id } => Some(*id),
+            Port::External { .. } => None,
+        },
+        _ => None,
+    }
+}
+
+/// Constructs the data of a port message, as a binary or a list of bytes
+fn make_data(proc: &Process, bytes: &[u8], binary: bool) -> OpaqueTerm {
+    if !binary {
+        return Cons::from_bytes(bytes, proc)
+            .unwrap()
+            .map(|ptr| ptr.into())
+            .unwrap_or(OpaqueTerm::NIL);
+    }
+    make_binary(proc, bytes)
+}
+
+fn binary_to_string(term: OpaqueTerm) -> Option<String> {
+    let term: Term = term.into();
+    let bits = term.as_bitstring()?;
+    if !bits.is_binary() || !bits.is_aligned() {
+        return None;
+    }
+    let bytes = unsafe { bits.as_bytes_unchecked() };
+    core::str::from_utf8(bytes).ok().map(|s| s.to_string())
+}
@@ -0,0 +1,417 @@
+//! The BIFs which enumerate, inspect and configure processes, `processes_iterator/0`,
+//! `process_flag/2` and `process_info/2`, and the explicit collection of their heaps with
+//! `garbage_collect/0,1,2`, and of the binaries of exited processes with
+//! `garbage_collect_message_area/0`. The processes a purge of a literal area would have to visit
+//! are found with `erts_internal:literal_area_referrers/1`.
+//!
+//! There is no garbage collector in this runtime yet which runs on its own, as process heaps are
+//! sized when a process is spawned and never grow, see `super::spawn`. A process may collect its
+//! own heap by request, which compacts it with `Process::collect`, using the stack maps of the
+//! frames on its stack to find the terms it holds. Collections are never generational, so none are
+//! ever counted in `minor_gcs`. The options which tune the collector, `fullsweep_after`,
+//! `min_heap_size` and `min_bin_vheap_size`, are recorded on the process, so that they are reported
+//! by `process_info/2` and are in place once the collector exists.
+//!
+//! As in ERTS, collecting another process is a system task, queued on the process collected, as
+//! its stack can only be walked by the process itself, once it is next at a yield point, see
+//! `Scheduler::request_task`. The requester is blocked until the collection is done, and is
+//! reported as `waiting` by `process_info/2` meanwhile. A process which has yet to start holds no
+//! terms, so is compacted by the requester directly. As in ERTS, the process collected inherits
+//! the priority of the requester until the task has run, so that a high-priority requester is not
+//! held up by a low-priority target, see `Process::inherit_priority`.
+//!
+//! The priority set with `process_flag(priority, Level)` takes effect the next time the process is
+//! scheduled, see `scheduler::queue` for how processes of each priority are scheduled.
+use std::mem;
+use std::ops::Range;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::{MaxHeapSize, Priority, Process, ProcessHeap, Sweep, WORD_SIZE};
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::util::*;
+
+/// Returns an iterator over all processes, for use with `processes_next/1`
+///
+/// The iterator is `{processes_iterator, Last}`, where `Last` is the pid last returned, or
+/// `undefined` before the first. Processes are enumerated in the order of their pids, one at a
+/// time, rather than by copying the process table, so enumerating many processes takes no more
+/// memory than enumerating a few. A process spawned during the enumeration is returned if its pid
+/// is ordered after `Last`, and one which exits before it is reached is not returned.
+#[export_name = "erlang:processes_iterator/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn processes_iterator0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| make_iterator(proc, None)))
+}
+
+/// Returns `{Pid, NewIterator}` for the next process of `Iterator`, or `none` when there are no
+/// more processes
+#[export_name = "erlang:processes_next/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn processes_next1(iterator: OpaqueTerm) -> ErlangResult {
+    let last = match tuple_elements(iterator) {
+        Some([tag, last]) if is_atom(*tag, "processes_iterator") => {
+            if is_atom(*last, "undefined") {
+                None
+            } else {
+                let Some(last) = local_pid(*last) else {
+                    return super::badarg(Trace::capture());
+                };
+                Some(last)
+            }
+        }
+        _ => return super::badarg(Trace::capture()),
+    };
+    let Some(next) = scheduler::with_current(|scheduler| scheduler.next_process(last)) else {
+        return ErlangResult::Ok(atom("none").into());
+    };
+    ErlangResult::Ok(with_process(|proc| {
+        let iterator = make_iterator(proc, Some(next));
+        make_tuple(proc, &[make_pid(proc, next), iterator])
+    }))
+}
+
+fn make_iterator(proc: &Process, last: Option<ProcessId>) -> OpaqueTerm {
+    let last = match last {
+        Some(pid) => make_pid(proc, pid),
+        None => atom("undefined").into(),
+    };
+    make_tuple(proc, &[atom("processes_iterator").into(), last])
+}
+
+/// Collects the heap of the current process
+#[export_name = "erlang:garbage_collect/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn garbage_collect0() -> ErlangResult {
+    with_process(|proc| {
+        super::trace::garbage_collection(proc.pid(), proc.heap_size());
+        unsafe { proc.collect(&mut [], Sweep::Full) };
+    });
+    ErlangResult::Ok(true.into())
+}
+
+/// Collects the heap of `Pid`, returning false if it is not alive
+#[export_name = "erlang:garbage_collect/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn garbage_collect1(pid: OpaqueTerm) -> ErlangResult {
+    garbage_collect2(pid, OpaqueTerm::NIL)
+}
+
+/// Collects the heap of `Pid` with the given options, returning false if it is not alive
+///
+/// A `major` collection, the default, is a full sweep, which compacts the heap whatever its
+/// fragmentation, while a `minor` collection only compacts it once enough of it is garbage, see
+/// `firefly_rt::process::Sweep`. The caller is blocked until another process has been collected,
+/// see the module documentation. `{async, RequestId}` is rejected with `badarg`, as the result
+/// would be sent to the caller, which has no mailbox to receive it in, see `super::bang2`.
+#[export_name = "erlang:garbage_collect/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn garbage_collect2(pid: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let Some(pid) = local_pid(pid) else { return super::badarg(Trace::capture()) };
+    let Some(options) = list_to_vec(options) else { return super::badarg(Trace::capture()) };
+    let mut sweep = Sweep::Full;
+    for option in options {
+        let Some([key, value]) = tuple_elements(option) else {
+            return super::badarg(Trace::capture());
+        };
+        match (*key).into() {
+            Term::Atom(key) if key.as_str() == "type" && is_atom(*value, "major") => {
+                sweep = Sweep::Full
+            }
+            Term::Atom(key) if key.as_str() == "type" && is_atom(*value, "minor") => {
+                sweep = Sweep::default()
+            }
+            _ => return super::badarg(Trace::capture()),
+        }
+    }
+
+    let Some(process) = scheduler::with_current(|scheduler| scheduler.process(pid)) else {
+        return ErlangResult::Ok(false.into());
+    };
+    let started = scheduler::with_current(|scheduler| scheduler.has_started(pid));
+    if pid == with_process(|proc| proc.pid()) || !started {
+        super::trace::garbage_collection(pid, process.heap_size());
+        unsafe { process.collect(&mut [], sweep) };
+        return ErlangResult::Ok(true.into());
+    }
+    ErlangResult::Ok(collect_other(pid, sweep).into())
+}
+
+/// Has the process `pid`, which has started, collect its heap, returning false if it exited first
+#[cfg(not(target_arch = "wasm32"))]
+fn collect_other(pid: ProcessId, sweep: Sweep) -> bool {
+    let task = scheduler::Task::Collect(sweep);
+    scheduler::with_current(|scheduler| scheduler.request_task(pid, task))
+}
+
+/// Processes run to completion on wasm32, so no other process which is alive has started
+#[cfg(target_arch = "wasm32")]
+fn collect_other(_pid: ProcessId, _sweep: Sweep) -> bool {
+    unreachable!()
+}
+
+/// Releases the reference-counted binaries of processes which have exited, returning true
+///
+/// This runtime has no shared message area, as messages are copied, but binaries are shared by
+/// reference. The binaries a process allocates are only released once it has exited, by a sweep
+/// which runs when the memory allocated passes the high-water mark, see `crate::memory`, or is
+/// requested with this. The sweeps so far, and the words they released, are reported by
+/// `erlang:statistics(garbage_collection)`.
+#[export_name = "erlang:garbage_collect_message_area/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn garbage_collect_message_area0() -> ErlangResult {
+    crate::memory::sweep();
+    ErlangResult::Ok(true.into())
+}
+
+/// Returns false, as there is never a literal area to release
+///
+/// This is called by the literal area collector of ERTS once code has been purged, to switch to the
+/// next literal area to release. Literals are compiled into the executable here, and as code is
+/// never purged, their areas are never released, so there is nothing to compact. The processes
+/// referring to an area are found as they would be for a purge by `literal_area_referrers/1`.
+#[export_name = "erts_internal:release_literal_area_switch/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn release_literal_area_switch0() -> ErlangResult {
+    ErlangResult::Ok(false.into())
+}
+
+/// Returns the pids of the processes which may refer to the literal area holding `Literal`, i.e.
+/// the processes a purge of that area would have to rid of their references to it first
+///
+/// The area is the allocation of `Literal` itself, e.g. the first cell of a constant list, so a
+/// process referring only to the tail of the list is not found. Each process is checked with
+/// `Process::refers_to_literals`, as a system task in the case of another process which has
+/// started, as for `garbage_collect/2`. `Literal` must be a constant list, tuple or binary, or
+/// `badarg` is raised.
+#[export_name = "erts_internal:literal_area_referrers/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn literal_area_referrers1(literal: OpaqueTerm) -> ErlangResult {
+    let Some(area) = literal_area(literal) else { return super::badarg(Trace::capture()) };
+    let current = with_process(|proc| proc.pid());
+    let mut referrers = vec![];
+    let mut last = None;
+    while let Some(pid) = scheduler::with_current(|scheduler| scheduler.next_process(last)) {
+        last = Some(pid);
+        let Some(process) = scheduler::with_current(|scheduler| scheduler.process(pid)) else {
+            continue;
+        };
+        let started = scheduler::with_current(|scheduler| scheduler.has_started(pid));
+        let refers = if pid == current {
+            unsafe { process.refers_to_literals(&[literal], area.clone()) }
+        } else if !started {
+            unsafe { process.refers_to_literals(&[], area.clone()) }
+        } else {
+            check_other(pid, area.clone())
+        };
+        if refers {
+            referrers.push(pid);
+        }
+    }
+    ErlangResult::Ok(with_process(|proc| {
+        let pids = referrers
+            .into_iter()
+            .map(|pid| make_pid(proc, pid))
+            .collect::<Vec<_>>();
+        make_list(proc, &pids)
+    }))
+}
+
+/// Returns the addresses spanned by the allocation of `literal`, if it is a constant list, tuple or
+/// binary
+fn literal_area(literal: OpaqueTerm) -> Option<Range<*const u8>> {
+    if !literal.is_literal() {
+        return None;
+    }
+    let size = match literal.into() {
+        Term::Cons(_) => mem::size_of::<Cons>(),
+        Term::Tuple(tuple) => mem::size_of_val(unsafe { tuple.as_ref() }),
+        Term::ConstantBinary(bin) => mem::size_of_val(bin),
+        _ => return None,
+    };
+    let start = unsafe { literal.as_ptr() } as *const u8;
+    Some(start..start.wrapping_add(size))
+}
+
+/// Has the process `pid`, which has started, check whether it refers to `area`, returning false if
+/// it exited first
+#[cfg(not(target_arch = "wasm32"))]
+fn check_other(pid: ProcessId, area: Range<*const u8>) -> bool {
+    let task = scheduler::Task::CheckLiterals(area.start as usize, area.end as usize);
+    scheduler::with_current(|scheduler| scheduler.request_task(pid, task))
+}
+
+/// Processes run to completion on wasm32, so no other process which is alive has started
+#[cfg(target_arch = "wasm32")]
+fn check_other(_pid: ProcessId, _area: Range<*const u8>) -> bool {
+    unreachable!()
+}
+
+/// Sets `Flag` of the current process to `Value`, returning its previous value
+///
+/// Only `priority` and the flags concerning the heap of the process are supported.
+#[export_name = "erlang:process_flag/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn process_flag2(flag: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(flag) = flag.into() else { return super::badarg(Trace::capture()) };
+    match with_process(|proc| set_flag(proc, flag.as_str(), value)) {
+        Some(old) => ErlangResult::Ok(old),
+        None => super::badarg(Trace::capture()),
+    }
+}
+
+fn set_flag(proc: &Process, flag: &str, value: OpaqueTerm) -> Option<OpaqueTerm> {
+    if flag == "priority" {
+        let Term::Atom(priority) = value.into() else { return None };
+        let priority = Priority::try_from(priority.as_str()).ok()?;
+        return Some(atom(proc.set_priority(priority).as_str()).into());
+    }
+    let mut options = proc.options();
+    let old = match (flag, Term::from(value)) {
+        ("fullsweep_after", Term::Int(n)) if n >= 0 => {
+            make_size(proc, mem::replace(&mut options.fullsweep_after, n as usize))
+        }
+        ("min_heap_size", Term::Int(size)) if size >= 0 => {
+            let old = min_heap_size(proc);
+            options.min_heap_size = Some(size as usize);
+            make_size(proc, old)
+        }
+        ("min_bin_vheap_size", Term::Int(size)) if size >= 0 => make_size(
+            proc,
+            mem::replace(&mut options.min_bin_vheap_size, size as usize),
+        ),
+        ("max_heap_size", value) => {
+            let max = super::spawn::parse_max_heap_size(value)?;
+            make_max_heap_size(proc, mem::replace(&mut options.max_heap_size, max))
+        }
+        _ => return None,
+    };
+    if !options.is_valid() {
+        return None;
+    }
+    proc.set_options(options);
+    Some(old)
+}
+
+/// Returns information about `Pid` as `{Item, Value}`, or a list of these if given a list of items,
+/// or `undefined` if it is not alive
+///
+/// The items supported are `garbage_collection`, `heap_size`, `total_heap_size`, `min_heap_size`,
+/// `min_bin_vheap_size`, `reductions`, `priority`, `status` and `suspending`. Suspensions take
+/// effect right away here, so none are ever outstanding in `suspending`, see `super::suspend`.
+#[export_name = "erlang:process_info/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn process_info2(pid: OpaqueTerm, items: OpaqueTerm) -> ErlangResult {
+    let Some(pid) = local_pid(pid) else { return super::badarg(Trace::capture()) };
+    let Some(process) = scheduler::with_current(|scheduler| scheduler.process(pid)) else {
+        return ErlangResult::Ok(atom("undefined").into());
+    };
+    let info = with_process(|proc| match items.into() {
+        Term::Atom(item) => info(proc, &process, item.as_str()),
+        Term::Nil | Term::Cons(_) => {
+            let infos = list_to_vec(items)?
+                .into_iter()
+                .map(|item| match item.into() {
+                    Term::Atom(item) => info(proc, &process, item.as_str()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(make_list(proc, infos.as_slice()))
+        }
+        _ => None,
+    });
+    match info {
+        Some(info) => ErlangResult::Ok(info),
+        None => super::badarg(Trace::capture()),
+    }
+}
+
+/// Returns `{Item, Value}` for `process`, allocated on the heap of `proc`
+fn info(proc: &Process, process: &Process, item: &str) -> Option<OpaqueTerm> {
+    let options = process.options();
+    let value = match item {
+        "garbage_collection" => {
+            let max_heap_size = make_max_heap_size(proc, options.max_heap_size);
+            firefly_rt::term!(proc, [
+                {max_heap_size, (max_heap_size)},
+                {min_bin_vheap_size, (options.min_bin_vheap_size)},
+                {min_heap_size, (min_heap_size(process))},
+                {fullsweep_after, (options.fullsweep_after)},
+                {minor_gcs, 0}
+            ])
+            .unwrap()
+            .into()
+        }
+        // Messages and binaries are never allocated on the heap of a process here, so its heap is
+        // all there is to it
+        "heap_size" | "total_heap_size" => make_size(proc, process.heap_size()),
+        "min_heap_size" => make_size(proc, min_heap_size(process)),
+        "min_bin_vheap_size" => make_size(proc, options.min_bin_vheap_size),
+        "reductions" => process.reductions().into_term(proc).unwrap().into(),
+        "priority" => atom(process.priority().as_str()).into(),
+        "status" => {
+            let status = if super::suspend::is_suspended(process.pid()) {
+                "suspended"
+            } else if process.pid() == proc.pid() {
+                "running"
+            } else if scheduler::with_current(|scheduler| scheduler.is_blocked(process.pid())) {
+                "waiting"
+            } else {
+                "runnable"
+            };
+            atom(status).into()
+        }
+        "suspending" => {
+            let suspending = super::suspend::suspending(process.pid())
+                .into_iter()
+                .map(|(suspendee, count)| {
+                    let suspendee = make_pid(proc, suspendee);
+                    let count = make_size(proc, count);
+                    make_tuple(proc, &[suspendee, count, Term::Int(0).into()])
+                })
+                .collect::<Vec<_>>();
+            make_list(proc, suspending.as_slice())
+        }
+        _ => return None,
+    };
+    Some(make_tuple(proc, &[atom(item).into(), value]))
+}
+
+/// Returns the minimum size of the heap of `process` in words, as configured, or the default size
+fn min_heap_size(process: &Process) -> usize {
+    process
+        .options()
+        .min_heap_size
+        .unwrap_or(ProcessHeap::DEFAULT_HEAP_SIZE / WORD_SIZE)
+}
+
+fn make_size(proc: &Process, size: usize) -> OpaqueTerm {
+    size.into_term(proc).unwrap().into()
+}
+
+/// Returns `max_heap_size` in the map form used by `process_flag/2` and `process_info/2`, where a
+/// size of zero means there is no limit
+fn make_max_heap_size(proc: &Process, max: Option<MaxHeapSize>) -> OpaqueTerm {
+    let max = max.unwrap_or_else(|| MaxHeapSize::new(0));
+    firefly_rt::term!(proc, #{
+        error_logger => (max.error_logger),
+        include_shared_binaries => false,
+        kill => (max.kill),
+        size => (max.size)
+    })
+    .unwrap()
+    .into()
+}
+
+pub(super) fn local_pid(pid: OpaqueTerm) -> Option<ProcessId> {
+    match pid.into() {
+        Term::Pid(pid) => match *pid {
+            Pid::Local { id } => Some(id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,477 @@
+//! `re`, regular expressions in the syntax of PCRE, as far as `engine` supports it.
+//!
+//! `compile/1,2` returns `{ok, MP}` or `{error, {ErrString, Position}}`, `run/2,3` returns
+//! `{match, Captured}`, `match` when nothing is captured, or `nomatch`, and `inspect(MP, namelist)`
+//! returns `{namelist, Names}`, the names of the named groups in alphabetical order, as binaries.
+//! The compile options supported are `unicode`, `ucp`, `caseless`, `multiline`, `dotall`,
+//! `extended`, `anchored`, `ungreedy`, `dollar_endonly` and `no_auto_capture`, which `run/3` also
+//! accepts with a pattern which is not yet compiled. Its own options are `global`, `anchored`,
+//! `notbol`, `noteol`, `notempty`, `report_errors`, `{offset, Offset}`, `{match_limit, N}`,
+//! `{match_limit_recursion, N}`, and `{capture, ValueSpec}` or `{capture, ValueSpec, Type}`.
+//!
+//! With `unicode`, the pattern and the subject are chardata, lists of code points and UTF-8
+//! binaries, and `.`, classes and `{n,m}` match whole characters. With `ucp` as well, `\d`, `\w`,
+//! `\s`, `\b` and the POSIX classes match by Unicode property rather than only ASCII. `\p{Name}`
+//! and `\P{Name}` match the Unicode properties `Any`, `L`, `Lu`, `Ll`, `N`, `Nd`, `Z`, `C`, `Xan`,
+//! `Xsp`, `Xps` and `Xwd`, and the common scripts, in either mode. Offsets are in bytes, as in
+//! ERTS.
+//!
+//! Named groups are written `(?<Name>...)`, `(?'Name'...)` or `(?P<Name>...)`, and referred to by
+//! `\k<Name>` or `(?P=Name)`. Besides the `ValueSpec`s of ERTS, `all_names` and lists of names and
+//! numbers among them, `{capture, map, Type}` returns the named groups as a map of their names, as
+//! binaries, to their values, `{match, #{Name => Value}}`, rather than a list which must be zipped
+//! with the result of `inspect/2`.
+//!
+//! `MP` is `{re_pattern, Groups, Unicode, 0, Bin}`, as in ERTS, but `Bin` holds the compile options
+//! and the pattern itself rather than compiled code. Patterns are compiled once and cached by their
+//! source, so that running an `MP`, or a pattern used often without compiling it first, does not
+//! compile it again.
+mod engine;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use self::engine::{Limit, MatchOptions, Regex};
+
+use super::badarg;
+use super::util::*;
+
+/// The number of compiled patterns cached, beyond which the cache is emptied
+const CACHE_SIZE: usize = 256;
+
+/// Compiled patterns, by their options and source
+type Cache = HashMap<(u32, Vec<u8>), Arc<Regex>>;
+
+static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+
+/// Compiles `Regexp` with no options
+#[export_name = "re:compile/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compile1(regexp: OpaqueTerm) -> ErlangResult {
+    compile2(regexp, OpaqueTerm::NIL)
+}
+
+/// Compiles `Regexp` with `Options`, returning `{ok, MP}` or `{error, {ErrString, Position}}`
+#[export_name = "re:compile/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compile2(regexp: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let flags = list_to_vec(options).and_then(|options| {
+        options
+            .into_iter()
+            .try_fold(0, |flags, option| Some(flags | compile_option(option)?))
+    });
+    let Some(flags) = flags else { return badarg(Trace::capture()); };
+    let Some(pattern) = to_bytes(regexp, flags & engine::UNICODE != 0) else {
+        return badarg(Trace::capture());
+    };
+    ErlangResult::Ok(with_process(|proc| match compile(&pattern, flags) {
+        Ok(regex) => {
+            let mp = make_mp(proc, &regex, &pattern);
+            make_tuple(proc, &[atoms::Ok.into(), mp])
+        }
+        Err(err) => {
+            let err = compile_error(proc, &err);
+            make_tuple(proc, &[atoms::Error.into(), err])
+        }
+    }))
+}
+
+/// Matches `Subject` against `RE` with no options
+#[export_name = "re:run/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn run2(subject: OpaqueTerm, re: OpaqueTerm) -> ErlangResult {
+    run3(subject, re, OpaqueTerm::NIL)
+}
+
+/// Matches `Subject` against `RE`, a compiled pattern or one to compile, with `Options`
+#[export_name = "re:run/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn run3(
+    subject: OpaqueTerm,
+    re: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(run) = list_to_vec(options).and_then(|options| Run::parse(&options)) else {
+        return badarg(Trace::capture());
+    };
+    let regex = match from_mp(re) {
+        // Only `unicode` may be given again when the pattern is compiled already
+        Some(regex) if run.flags & !engine::UNICODE == 0 => regex,
+        Some(_) => return badarg(Trace::capture()),
+        None => {
+            let Some(pattern) = to_bytes(re, run.flags & engine::UNICODE != 0) else {
+                return badarg(Trace::capture());
+            };
+            match compile(&pattern, run.flags) {
+                Ok(regex) => regex,
+                Err(err) if run.report_errors => {
+                    return ErlangResult::Ok(with_process(|proc| {
+                        let err = compile_error(proc, &err);
+                        let err = make_tuple(proc, &[atom("compile").into(), err]);
+                        make_tuple(proc, &[atoms::Error.into(), err])
+                    }));
+                }
+                Err(_) => return badarg(Trace::capture()),
+            }
+        }
+    };
+    let unicode = regex.flags() & engine::UNICODE != 0;
+    let Some(subject) = to_bytes(subject, unicode) else { return badarg(Trace::capture()); };
+    // The subject is valid UTF-8 in `unicode` mode, but the offset may split a character
+    let in_bounds = match std::str::from_utf8(&subject) {
+        Ok(subject) if unicode => subject.is_char_boundary(run.offset),
+        _ => run.offset <= subject.len(),
+    };
+    if !in_bounds {
+        return badarg(Trace::capture());
+    }
+    let Some(spec) = run.spec(&regex) else { return badarg(Trace::capture()); };
+
+    let mut matches = Vec::new();
+    let mut options = run.options;
+    let mut offset = run.offset;
+    loop {
+        match regex.captures(&subject, offset, &options) {
+            Ok(Some(captures)) => {
+                let (start, end) = captures[0].unwrap();
+                matches.push(captures);
+                if !run.global {
+                    break;
+                }
+                // The next match may not be empty where this one ended, lest it be found again
+                offset = end;
+                options.notempty_at = (start == end).then_some(end);
+            }
+            Ok(None) => break,
+            Err(limit) if run.report_errors => {
+                let limit = match limit {
+                    Limit::Match => "match_limit",
+                    Limit::Recursion => "match_limit_recursion",
+                };
+                return ErlangResult::Ok(with_process(|proc| {
+                    make_tuple(proc, &[atoms::Error.into(), atom(limit).into()])
+                }));
+            }
+            Err(_) => break,
+        }
+    }
+    if matches.is_empty() {
+        return ErlangResult::Ok(atom("nomatch").into());
+    }
+    if let Spec::None = spec {
+        return ErlangResult::Ok(atom("match").into());
+    }
+    ErlangResult::Ok(with_process(|proc| {
+        let mut captured = matches
+            .iter()
+            .map(|captures| spec.capture(proc, &regex, &subject, captures, run.ty));
+        let captured = if run.global {
+            make_list(proc, &captured.collect::<Vec<_>>())
+        } else {
+            captured.next().unwrap()
+        };
+        make_tuple(proc, &[atom("match").into(), captured])
+    }))
+}
+
+/// Returns information about the compiled pattern `MP`, of which only `namelist` is supported
+#[export_name = "re:inspect/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inspect2(mp: OpaqueTerm, item: OpaqueTerm) -> ErlangResult {
+    let Some(regex) = from_mp(mp) else { return badarg(Trace::capture()); };
+    if !is_atom(item, "namelist") {
+        return badarg(Trace::capture());
+    }
+    ErlangResult::Ok(with_process(|proc| {
+        let names = regex
+            .names()
+            .iter()
+            .map(|(name, _)| make_binary(proc, name.as_bytes()))
+            .collect::<Vec<_>>();
+        let names = make_list(proc, &names);
+        make_tuple(proc, &[item, names])
+    }))
+}
+
+/// The options of `run/3`
+struct Run {
+    /// The compile options, for a pattern which is not compiled yet
+    flags: u32,
+    options: MatchOptions,
+    offset: usize,
+    global: bool,
+    report_errors: bool,
+    spec: OpaqueTerm,
+    ty: Type,
+}
+impl Run {
+    fn parse(options: &[OpaqueTerm]) -> Option<Self> {
+        let mut run = Self {
+            flags: 0,
+            options: MatchOptions::default(),
+            offset: 0,
+            global: false,
+            report_errors: false,
+            spec: atom("all").into(),
+            ty: Type::Index,
+        };
+        for &option in options {
+            if let Term::Atom(name) = option.into() {
+                match name.as_str() {
+                    "global" => run.global = true,
+                    "anchored" => run.options.anchored = true,
+                    "notbol" => run.options.notbol = true,
+                    "noteol" => run.options.noteol = true,
+                    "notempty" => run.options.notempty = true,
+                    "report_errors" => run.report_errors = true,
+                    _ => run.flags |= compile_option(option)?,
+                }
+                continue;
+            }
+            let count = |term: &OpaqueTerm| match (*term).into() {
+                Term::Int(n) if n >= 0 => Some(n as u64),
+                _ => None,
+            };
+            match tuple_elements(option)? {
+                [tag, offset] if is_atom(*tag, "offset") => run.offset = count(offset)? as usize,
+                [tag, limit] if is_atom(*tag, "match_limit") => run.options.limit = count(limit)?,
+                [tag, limit] if is_atom(*tag, "match_limit_recursion") => {
+                    run.options.recursion_limit = count(limit)?
+                }
+                [tag, spec] if is_atom(*tag, "capture") => run.spec = *spec,
+                [tag, spec, ty] if is_atom(*tag, "capture") => {
+                    run.spec = *spec;
+                    run.ty = match (*ty).into() {
+                        Term::Atom(ty) if ty.as_str() == "index" => Type::Index,
+                        Term::Atom(ty) if ty.as_str() == "list" => Type::List,
+                        Term::Atom(ty) if ty.as_str() == "binary" => Type::Binary,
+                        _ => return None,
+                    };
+                }
+                _ => return None,
+            }
+        }
+        Some(run)
+    }
+
+    /// Resolves the `ValueSpec` of the `capture` option against the groups of `regex`
+    fn spec(&self, regex: &Regex) -> Option<Spec> {
+        let groups = regex.groups();
+        if let Term::Atom(spec) = self.spec.into() {
+            return Some(match spec.as_str() {
+                "all" => Spec::Groups((0..=groups).map(Some).collect()),
+                "all_but_first" => Spec::Groups((1..=groups).map(Some).collect()),
+                "first" => Spec::Groups(vec![Some(0)]),
+                "none" => Spec::None,
+                "all_names" => Spec::Groups(regex.names().iter().map(|(_, i)| Some(*i)).collect()),
+                "map" => Spec::Map,
+                _ => return None,
+            });
+        }
+        let groups = list_to_vec(self.spec)?
+            .into_iter()
+            .map(|group| match group.into() {
+                Term::Int(n) if n >= 0 => Some((n as usize <= groups).then_some(n as usize)),
+                Term::Atom(name) => Some(regex.group(name.as_str())),
+                _ => {
+                    let name = charlist_to_string(group)
+                        .or_else(|| String::from_utf8(iodata_to_bytes(group)?).ok())?;
+                    Some(regex.group(&name))
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Spec::Groups(groups))
+    }
+}
+
+/// What is captured, see `Run::spec`
+enum Spec {
+    None,
+    /// The given groups, with those which do not exist as if they did not match
+    Groups(Vec<Option<usize>>),
+    /// The named groups, as a map of their names to their values
+    Map,
+}
+impl Spec {
+    fn capture(
+        &self,
+        proc: &Process,
+        regex: &Regex,
+        subject: &[u8],
+        captures: &[Option<(usize, usize)>],
+        ty: Type,
+    ) -> OpaqueTerm {
+        let unicode = regex.flags() & engine::UNICODE != 0;
+        let value = |group: Option<usize>| {
+            let capture = group.and_then(|group| captures[group]);
+            ty.value(proc, subject, capture, unicode)
+        };
+        match self {
+            Self::None => OpaqueTerm::NIL,
+            Self::Groups(groups) => {
+                let values = groups.iter().map(|group| value(*group)).collect::<Vec<_>>();
+                make_list(proc, &values)
+            }
+            Self::Map => {
+                let entries = regex.names().iter().map(|(name, group)| {
+                    let name = make_binary(proc, name.as_bytes());
+                    (name.into(), value(Some(*group)).into())
+                });
+                Map::new_from_iter_in(entries, proc).unwrap().into()
+            }
+        }
+    }
+}
+
+/// How captured groups are returned
+#[derive(Copy, Clone)]
+enum Type {
+    /// `{Offset, Length}`, or `{-1, 0}` for a group which did not match
+    Index,
+    /// A list of characters, or `[]` for a group which did not match
+    List,
+    /// A binary, or `<<>>` for a group which did not match
+    Binary,
+}
+impl Type {
+    fn value(
+        self,
+        proc: &Process,
+        subject: &[u8],
+        capture: Option<(usize, usize)>,
+        unicode: bool,
+    ) -> OpaqueTerm {
+        let bytes = capture.map_or(&[][..], |(start, end)| &subject[start..end]);
+        match self {
+            Self::Index => {
+                let (offset, length) = match capture {
+                    Some((start, end)) => (start as i64, (end - start) as i64),
+                    None => (-1, 0),
+                };
+                make_tuple(proc, &[Term::Int(offset).into(), Term::Int(length).into()])
+            }
+            Self::Binary => make_binary(proc, bytes),
+            Self::List if unicode => charlist(proc, std::str::from_utf8(bytes).unwrap()),
+            Self::List => {
+                let chars = bytes
+                    .iter()
+                    .map(|&byte| Term::Int(byte as i64).into())
+                    .collect::<Vec<_>>();
+                make_list(proc, &chars)
+            }
+        }
+    }
+}
+
+fn compile_option(option: OpaqueTerm) -> Option<u32> {
+    let Term::Atom(name) = option.into() else { return None; };
+    Some(match name.as_str() {
+        "unicode" => engine::UNICODE,
+        "ucp" => engine::UCP,
+        "caseless" => engine::CASELESS,
+        "multiline" => engine::MULTILINE,
+        "dotall" => engine::DOTALL,
+        "extended" => engine::EXTENDED,
+        "anchored" => engine::ANCHORED,
+        "ungreedy" => engine::UNGREEDY,
+        "dollar_endonly" => engine::DOLLAR_ENDONLY,
+        "no_auto_capture" => engine::NO_AUTO_CAPTURE,
+        _ => return None,
+    })
+}
+
+/// Compiles `pattern`, or returns it from the cache if it was compiled with the same options
+fn compile(pattern: &[u8], flags: u32) -> Result<Arc<Regex>, engine::Error> {
+    let cache = CACHE.get_or_init(Default::default);
+    let key = (flags, pattern.to_vec());
+    if let Some(regex) = cache.lock().unwrap().get(&key) {
+        return Ok(regex.clone());
+    }
+    let regex = Arc::new(Regex::new(pattern, flags)?);
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(key, regex.clone());
+    Ok(regex)
+}
+
+/// Returns `{ErrString, Position}`
+fn compile_error(proc: &Process, err: &engine::Error) -> OpaqueTerm {
+    let message = charlist(proc, err.message);
+    make_tuple(proc, &[message, Term::Int(err.position as i64).into()])
+}
+
+/// Returns `{re_pattern, Groups, Unicode, 0, <<Flags:32, Pattern/binary>>}`
+fn make_mp(proc: &Process, regex: &Regex, pattern: &[u8]) -> OpaqueTerm {
+    let mut bin = regex.flags().to_be_bytes().to_vec();
+    bin.extend_from_slice(pattern);
+    let unicode = (regex.flags() & engine::UNICODE != 0) as i64;
+    make_tuple(
+        proc,
+        &[
+            atom("re_pattern").into(),
+            Term::Int(regex.groups() as i64).into(),
+            Term::Int(unicode).into(),
+            Term::Int(0).into(),
+            make_binary(proc, &bin),
+        ],
+    )
+}
+
+/// Returns the pattern compiled into `mp`, if it is an `MP` returned by `compile/1,2`
+fn from_mp(mp: OpaqueTerm) -> Option<Arc<Regex>> {
+    let [tag, _, _, _, bin] = tuple_elements(mp)? else { return None; };
+    if !is_atom(*tag, "re_pattern") {
+        return None;
+    }
+    let bin = iodata_to_bytes(*bin)?;
+    if bin.len() < 4 {
+        return None;
+    }
+    let (flags, pattern) = bin.split_at(4);
+    compile(pattern, u32::from_be_bytes(flags.try_into().unwrap())).ok()
+}
+
+/// Flattens a pattern or subject to bytes, UTF-8 in `unicode` mode, where it is chardata
+fn to_bytes(term: OpaqueTerm, unicode: bool) -> Option<Vec<u8>> {
+    if !unicode {
+        return iodata_to_bytes(term);
+    }
+    let mut bytes = Vec::new();
+    push_chardata(term.into(), &mut bytes)?;
+    Some(bytes)
+}
+
+fn push_chardata(term: Term, bytes: &mut Vec<u8>) -> Option<()> {
+    let mut term = term;
+    while let Term::Cons(ptr) = term {
+        let cell = unsafe { ptr.as_ref() };
+        match cell.head() {
+            Term::Int(c) => {
+                let c = char::from_u32(u32::try_from(c).ok()?)?;
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            head => push_chardata(head, bytes)?,
+        }
+        term = cell.tail();
+    }
+    match term {
+        Term::Nil => Some(()),
+        term => {
+            let bits = term.as_bitstring()?;
+            if !bits.is_binary() || !bits.is_aligned() {
+                return None;
+            }
+            let binary = unsafe { bits.as_bytes_unchecked() };
+            std::str::from_utf8(binary).ok()?;
+            bytes.extend_from_slice(binary);
+            Some(())
+        }
+    }
+}
@@ -0,0 +1,1382 @@
+//! The regular expression engine behind `re`, a backtracking matcher for the subset of PCRE
+//! syntax programs use most.
+//!
+//! Patterns are parsed to a tree of `Node`s, which is matched against the subject by recursive
+//! descent with continuations, so that each node can backtrack into those before it. Repetitions
+//! of a single character, e.g. `.*` or `[a-z]+`, are matched with a loop instead, so that they do
+//! not nest as deeply as the subject is long.
+//!
+//! In `unicode` mode, patterns and subjects are UTF-8 and matched by code point, otherwise they are
+//! matched by byte, each byte standing for the Latin-1 character of the same value. Positions are
+//! always byte offsets into the subject.
+use std::cell::Cell;
+use std::mem;
+
+/// Compile options, see `re:compile/2`
+pub const CASELESS: u32 = 1 << 0;
+pub const MULTILINE: u32 = 1 << 1;
+pub const DOTALL: u32 = 1 << 2;
+pub const EXTENDED: u32 = 1 << 3;
+pub const ANCHORED: u32 = 1 << 4;
+pub const UNICODE: u32 = 1 << 5;
+pub const UCP: u32 = 1 << 6;
+pub const UNGREEDY: u32 = 1 << 7;
+pub const DOLLAR_ENDONLY: u32 = 1 << 8;
+pub const NO_AUTO_CAPTURE: u32 = 1 << 9;
+
+/// The error for a pattern which could not be compiled, with the offset in the pattern at which it
+/// was detected
+#[derive(Debug)]
+pub struct Error {
+    pub message: &'static str,
+    pub position: usize,
+}
+
+/// Why matching was abandoned, see `MatchOptions::limit`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Limit {
+    Match,
+    Recursion,
+}
+
+/// The start and end offsets of each group, the whole match being group 0
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+pub struct MatchOptions {
+    pub anchored: bool,
+    pub notbol: bool,
+    pub noteol: bool,
+    pub notempty: bool,
+    /// An offset at which an empty match is rejected, as when looking for the next match after an
+    /// empty one
+    pub notempty_at: Option<usize>,
+    /// The number of nodes which may be tried before matching is abandoned
+    pub limit: u64,
+    /// How deeply nodes may nest before matching is abandoned
+    pub recursion_limit: u64,
+}
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            anchored: false,
+            notbol: false,
+            noteol: false,
+            notempty: false,
+            notempty_at: None,
+            limit: 10_000_000,
+            recursion_limit: 10_000,
+        }
+    }
+}
+
+/// A compiled regular expression
+pub struct Regex {
+    node: Node,
+    flags: u32,
+    groups: usize,
+    /// The named groups and their numbers, in order of name
+    names: Vec<(String, usize)>,
+}
+impl Regex {
+    pub fn new(pattern: &[u8], flags: u32) -> Result<Self, Error> {
+        let chars = if flags & UNICODE != 0 {
+            match std::str::from_utf8(pattern) {
+                Ok(pattern) => pattern.chars().collect(),
+                Err(err) => {
+                    return Err(Error {
+                        message: "UTF-8 error",
+                        position: err.valid_up_to(),
+                    })
+                }
+            }
+        } else {
+            pattern.iter().map(|&byte| byte as char).collect()
+        };
+        let mut parser = Parser {
+            chars,
+            pos: 0,
+            flags,
+            groups: 0,
+            names: Vec::new(),
+        };
+        let node = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unmatched parentheses"));
+        }
+        let mut names = parser.names;
+        names.sort();
+        Ok(Self {
+            node,
+            flags,
+            groups: parser.groups,
+            names,
+        })
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Returns the number of capturing groups, not counting the whole match
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    /// Returns the named groups and their numbers, in order of name
+    pub fn names(&self) -> &[(String, usize)] {
+        self.names.as_slice()
+    }
+
+    /// Returns the number of the group named `name`, if any
+    pub fn group(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .find(|(group, _)| group == name)
+            .map(|(_, index)| *index)
+    }
+
+    /// Finds the first match in `subject` starting at or after `offset`
+    ///
+    /// In `unicode` mode, `subject` must be UTF-8 and `offset` on a character boundary.
+    pub fn captures(
+        &self,
+        subject: &[u8],
+        offset: usize,
+        options: &MatchOptions,
+    ) -> Result<Option<Captures>, Limit> {
+        let matcher = Matcher {
+            input: Input {
+                bytes: subject,
+                unicode: self.flags & UNICODE != 0,
+            },
+            ucp: self.flags & UCP != 0,
+            options,
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            exceeded: Cell::new(None),
+        };
+        let anchored = options.anchored || self.flags & ANCHORED != 0;
+        let mut start = offset;
+        loop {
+            let mut captures = vec![None; self.groups + 1];
+            let matched = matcher.m(&self.node, start, &mut captures, &mut |end, captures| {
+                let empty = end == start;
+                if empty && (options.notempty || options.notempty_at == Some(start)) {
+                    return false;
+                }
+                captures[0] = Some((start, end));
+                true
+            });
+            if let Some(limit) = matcher.exceeded.get() {
+                return Err(limit);
+            }
+            if matched {
+                return Ok(Some(captures));
+            }
+            if anchored {
+                return Ok(None);
+            }
+            match matcher.input.at(start) {
+                Some((_, next)) => start = next,
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char {
+        c: char,
+        caseless: bool,
+    },
+    Any {
+        dotall: bool,
+    },
+    Class {
+        class: Class,
+        caseless: bool,
+    },
+    /// `^`
+    LineStart {
+        multiline: bool,
+    },
+    /// `$`
+    LineEnd {
+        multiline: bool,
+        endonly: bool,
+    },
+    /// `\A`
+    SubjectStart,
+    /// `\z`
+    SubjectEnd,
+    /// `\Z`
+    SubjectEndNewline,
+    /// `\b` and `\B`
+    WordBoundary {
+        negate: bool,
+    },
+    Group {
+        node: Box<Node>,
+        index: Option<usize>,
+    },
+    /// `(?>...)`, which is not backtracked into once matched
+    Atomic(Box<Node>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Repeat>),
+    Backref {
+        index: usize,
+        caseless: bool,
+    },
+    /// Lookahead and lookbehind assertions
+    Look {
+        node: Box<Node>,
+        ahead: bool,
+        negate: bool,
+    },
+}
+
+#[derive(Debug)]
+struct Repeat {
+    node: Node,
+    min: u32,
+    /// The maximum number of repetitions, `u32::MAX` if unbounded
+    max: u32,
+    greedy: bool,
+    possessive: bool,
+}
+
+#[derive(Debug)]
+struct Class {
+    negate: bool,
+    items: Vec<ClassItem>,
+}
+impl Class {
+    fn contains(&self, c: char, ucp: bool) -> bool {
+        self.items.iter().any(|item| item.contains(c, ucp)) != self.negate
+    }
+}
+
+#[derive(Debug)]
+enum ClassItem {
+    Range(char, char),
+    Set(Set, bool),
+}
+impl ClassItem {
+    fn contains(&self, c: char, ucp: bool) -> bool {
+        match *self {
+            Self::Range(first, last) => first <= c && c <= last,
+            Self::Set(set, negate) => set.contains(c, ucp) != negate,
+        }
+    }
+}
+
+/// The sets of characters named by escapes, POSIX classes and Unicode properties
+///
+/// Without `ucp`, `\d`, `\s`, `\w` and the POSIX classes only match ASCII characters, as in PCRE.
+/// The Unicode properties are derived from the classification of characters by the standard
+/// library, which has no general categories, so `Nd` is the same as `N`, and scripts are matched
+/// by the blocks they are mostly encoded in.
+#[derive(Debug, Copy, Clone)]
+enum Set {
+    Digit,
+    Space,
+    Word,
+    Alpha,
+    Alnum,
+    Upper,
+    Lower,
+    Punct,
+    Xdigit,
+    Cntrl,
+    Graph,
+    Print,
+    Blank,
+    Ascii,
+    Property(Property),
+}
+impl Set {
+    fn contains(self, c: char, ucp: bool) -> bool {
+        match self {
+            Self::Digit if ucp => c.is_numeric(),
+            Self::Digit => c.is_ascii_digit(),
+            Self::Space if ucp => c.is_whitespace(),
+            Self::Space => c.is_ascii_whitespace() || c == '\x0b',
+            Self::Word if ucp => c.is_alphanumeric() || c == '_',
+            Self::Word => c.is_ascii_alphanumeric() || c == '_',
+            Self::Alpha if ucp => c.is_alphabetic(),
+            Self::Alpha => c.is_ascii_alphabetic(),
+            Self::Alnum if ucp => c.is_alphanumeric(),
+            Self::Alnum => c.is_ascii_alphanumeric(),
+            Self::Upper if ucp => c.is_uppercase(),
+            Self::Upper => c.is_ascii_uppercase(),
+            Self::Lower if ucp => c.is_lowercase(),
+            Self::Lower => c.is_ascii_lowercase(),
+            Self::Punct => c.is_ascii_punctuation(),
+            Self::Xdigit => c.is_ascii_hexdigit(),
+            Self::Cntrl => c.is_control(),
+            Self::Graph => c.is_ascii_graphic(),
+            Self::Print => c.is_ascii_graphic() || c == ' ',
+            Self::Blank => c == ' ' || c == '\t',
+            Self::Ascii => c.is_ascii(),
+            Self::Property(property) => property.contains(c),
+        }
+    }
+
+    fn posix(name: &str) -> Option<Self> {
+        Some(match name {
+            "alpha" => Self::Alpha,
+            "alnum" => Self::Alnum,
+            "ascii" => Self::Ascii,
+            "blank" => Self::Blank,
+            "cntrl" => Self::Cntrl,
+            "digit" => Self::Digit,
+            "graph" => Self::Graph,
+            "lower" => Self::Lower,
+            "print" => Self::Print,
+            "punct" => Self::Punct,
+            "space" => Self::Space,
+            "upper" => Self::Upper,
+            "word" => Self::Word,
+            "xdigit" => Self::Xdigit,
+            _ => return None,
+        })
+    }
+}
+
+/// A Unicode property, as matched by `\p{Name}`
+#[derive(Debug, Copy, Clone)]
+enum Property {
+    Any,
+    Letter,
+    Uppercase,
+    Lowercase,
+    Number,
+    Separator,
+    Control,
+    /// `Xan`, letters and numbers
+    Alnum,
+    /// `Xsp` and `Xps`, white space
+    Space,
+    /// `Xwd`, letters, numbers and underscore
+    Word,
+    Script(&'static [(u32, u32)]),
+}
+impl Property {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Any" => Self::Any,
+            "L" | "L&" => Self::Letter,
+            "Lu" => Self::Uppercase,
+            "Ll" => Self::Lowercase,
+            "N" | "Nd" => Self::Number,
+            "Z" | "Zs" => Self::Separator,
+            "C" | "Cc" => Self::Control,
+            "Xan" => Self::Alnum,
+            "Xsp" | "Xps" => Self::Space,
+            "Xwd" => Self::Word,
+            "Latin" => Self::Script(&[
+                (0x41, 0x5a),
+                (0x61, 0x7a),
+                (0xaa, 0xaa),
+                (0xba, 0xba),
+                (0xc0, 0xd6),
+                (0xd8, 0xf6),
+                (0xf8, 0x24f),
+                (0x1e00, 0x1eff),
+            ]),
+            "Greek" => Self::Script(&[(0x370, 0x3ff), (0x1f00, 0x1fff)]),
+            "Cyrillic" => Self::Script(&[(0x400, 0x52f)]),
+            "Armenian" => Self::Script(&[(0x531, 0x58f)]),
+            "Hebrew" => Self::Script(&[(0x591, 0x5ff)]),
+            "Arabic" => Self::Script(&[(0x600, 0x6ff), (0x750, 0x77f)]),
+            "Devanagari" => Self::Script(&[(0x900, 0x97f)]),
+            "Thai" => Self::Script(&[(0xe01, 0xe5b)]),
+            "Hangul" => Self::Script(&[(0x1100, 0x11ff), (0xac00, 0xd7a3)]),
+            "Hiragana" => Self::Script(&[(0x3041, 0x309f)]),
+            "Katakana" => Self::Script(&[(0x30a0, 0x30ff)]),
+            "Han" => Self::Script(&[(0x4e00, 0x9fff), (0x3400, 0x4dbf), (0x20000, 0x2a6df)]),
+            _ => return None,
+        })
+    }
+
+    fn contains(self, c: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Letter => c.is_alphabetic(),
+            Self::Uppercase => c.is_uppercase(),
+            Self::Lowercase => c.is_lowercase(),
+            Self::Number => c.is_numeric(),
+            Self::Separator => c.is_whitespace() && !c.is_control(),
+            Self::Control => c.is_control(),
+            Self::Alnum => c.is_alphanumeric(),
+            Self::Space => c.is_whitespace(),
+            Self::Word => c.is_alphanumeric() || c == '_',
+            Self::Script(ranges) => {
+                let c = c as u32;
+                ranges.iter().any(|&(first, last)| first <= c && c <= last)
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// The options in effect, as changed by `(?i)` and the like
+    flags: u32,
+    groups: usize,
+    names: Vec<(String, usize)>,
+}
+impl Parser {
+    fn error(&self, message: &'static str) -> Error {
+        Error {
+            message,
+            position: self.pos,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn has(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Parses alternatives up to the end of the enclosing group, whose options are restored after
+    fn parse_alt(&mut self) -> Result<Node, Error> {
+        let flags = self.flags;
+        let mut alternatives = vec![self.parse_concat()?];
+        while self.eat('|') {
+            alternatives.push(self.parse_concat()?);
+        }
+        self.flags = flags;
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Node::Alt(alternatives)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_extended();
+            match self.peek() {
+                None | Some('|') | Some(')') => break,
+                Some(_) => (),
+            }
+            let atom = self.parse_atom()?;
+            let node = self.parse_quantifier(atom)?;
+            if !matches!(node, Node::Empty) {
+                nodes.push(node);
+            }
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    /// Skips white space and comments in `extended` mode
+    fn skip_extended(&mut self) {
+        if !self.has(EXTENDED) {
+            return;
+        }
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.pos += 1;
+            } else if c == '#' {
+                while !matches!(self.peek(), None | Some('\n')) {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, Error> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any {
+                dotall: self.has(DOTALL),
+            },
+            '^' => Node::LineStart {
+                multiline: self.has(MULTILINE),
+            },
+            '$' => Node::LineEnd {
+                multiline: self.has(MULTILINE),
+                endonly: self.has(DOLLAR_ENDONLY),
+            },
+            '[' => Node::Class {
+                class: self.parse_class()?,
+                caseless: self.has(CASELESS),
+            },
+            '(' => self.parse_group()?,
+            '\\' => self.parse_escape()?,
+            '*' | '+' | '?' => return Err(self.error("nothing to repeat")),
+            '{' if self.quantifier_follows() => return Err(self.error("nothing to repeat")),
+            c => self.char(c),
+        })
+    }
+
+    fn char(&self, c: char) -> Node {
+        Node::Char {
+            c,
+            caseless: self.has(CASELESS),
+        }
+    }
+
+    /// Returns true if `{` at the position before this one starts a quantifier, rather than being
+    /// a literal
+    fn quantifier_follows(&self) -> bool {
+        let rest = &self.chars[self.pos..];
+        let Some(end) = rest.iter().position(|&c| c == '}') else { return false; };
+        let body = rest[..end].iter().collect::<String>();
+        let mut parts = body.splitn(2, ',');
+        let min = parts.next().unwrap();
+        !min.is_empty()
+            && min.chars().all(|c| c.is_ascii_digit())
+            && parts.all(|max| max.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, Error> {
+        self.skip_extended();
+        let (min, max) = match self.peek() {
+            Some('*') => (0, u32::MAX),
+            Some('+') => (1, u32::MAX),
+            Some('?') => (0, 1),
+            Some('{') => {
+                self.pos += 1;
+                if !self.quantifier_follows() {
+                    self.pos -= 1;
+                    return Ok(atom);
+                }
+                let min = self.parse_number().unwrap_or(0);
+                let max = if self.eat(',') {
+                    self.parse_number().unwrap_or(u32::MAX)
+                } else {
+                    min
+                };
+                if max < min {
+                    return Err(self.error("numbers out of order in {} quantifier"));
+                }
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+        // `{n,m}` leaves the closing brace to be skipped, the other quantifiers are one character
+        self.pos += 1;
+        if let Node::LineStart { .. }
+        | Node::LineEnd { .. }
+        | Node::SubjectStart
+        | Node::SubjectEnd
+        | Node::SubjectEndNewline
+        | Node::WordBoundary { .. } = atom
+        {
+            return Err(self.error("nothing to repeat"));
+        }
+        let mut greedy = !self.has(UNGREEDY);
+        let mut possessive = false;
+        if self.eat('?') {
+            greedy = !greedy;
+        } else if self.eat('+') {
+            possessive = true;
+        }
+        Ok(Node::Repeat(Box::new(Repeat {
+            node: atom,
+            min,
+            max,
+            greedy,
+            possessive,
+        })))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits = self.chars[start..self.pos].iter().collect::<String>();
+        digits.parse().ok()
+    }
+
+    fn parse_group(&mut self) -> Result<Node, Error> {
+        if !self.eat('?') {
+            let index = if self.has(NO_AUTO_CAPTURE) {
+                None
+            } else {
+                Some(self.next_group())
+            };
+            return self.finish_group(index);
+        }
+        match self.peek() {
+            Some(':') => {
+                self.pos += 1;
+                self.finish_group(None)
+            }
+            Some('>') => {
+                self.pos += 1;
+                let node = self.parse_alt()?;
+                self.close()?;
+                Ok(Node::Atomic(Box::new(node)))
+            }
+            Some('=') | Some('!') => {
+                let negate = self.peek() == Some('!');
+                self.pos += 1;
+                self.finish_look(true, negate)
+            }
+            Some('<') if matches!(self.chars.get(self.pos + 1), Some('=') | Some('!')) => {
+                let negate = self.chars[self.pos + 1] == '!';
+                self.pos += 2;
+                self.finish_look(false, negate)
+            }
+            Some('<') | Some('\'') => {
+                let close = if self.peek() == Some('<') { '>' } else { '\'' };
+                self.pos += 1;
+                self.named_group(close)
+            }
+            Some('P') if self.chars.get(self.pos + 1) == Some(&'<') => {
+                self.pos += 2;
+                self.named_group('>')
+            }
+            Some('P') if self.chars.get(self.pos + 1) == Some(&'=') => {
+                self.pos += 2;
+                let name = self.parse_name(')')?;
+                self.backref_to(&name)
+            }
+            Some('#') => {
+                while !matches!(self.peek(), None | Some(')')) {
+                    self.pos += 1;
+                }
+                self.close()?;
+                Ok(Node::Empty)
+            }
+            _ => self.parse_options(),
+        }
+    }
+
+    fn next_group(&mut self) -> usize {
+        self.groups += 1;
+        self.groups
+    }
+
+    fn named_group(&mut self, close: char) -> Result<Node, Error> {
+        let name = self.parse_name(close)?;
+        if self.names.iter().any(|(other, _)| *other == name) {
+            return Err(self.error("two named subpatterns have the same name"));
+        }
+        let index = self.next_group();
+        self.names.push((name, index));
+        self.finish_group(Some(index))
+    }
+
+    /// Parses a group name up to `close`, which is skipped
+    fn parse_name(&mut self, close: char) -> Result<String, Error> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        let name = self.chars[start..self.pos].iter().collect::<String>();
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(self.error("group name must start with a non-digit"));
+        }
+        if !self.eat(close) {
+            return Err(self.error("syntax error in subpattern name (missing terminator)"));
+        }
+        Ok(name)
+    }
+
+    fn finish_group(&mut self, index: Option<usize>) -> Result<Node, Error> {
+        let node = self.parse_alt()?;
+        self.close()?;
+        Ok(Node::Group {
+            node: Box::new(node),
+            index,
+        })
+    }
+
+    fn finish_look(&mut self, ahead: bool, negate: bool) -> Result<Node, Error> {
+        let node = self.parse_alt()?;
+        self.close()?;
+        Ok(Node::Look {
+            node: Box::new(node),
+            ahead,
+            negate,
+        })
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        if self.eat(')') {
+            Ok(())
+        } else {
+            Err(self.error("missing )"))
+        }
+    }
+
+    /// Parses `(?imsxU-imsxU)`, which changes the options for the rest of the enclosing group, or
+    /// `(?imsxU-imsxU:...)`, which changes them within its own
+    fn parse_options(&mut self) -> Result<Node, Error> {
+        let mut flags = self.flags;
+        let mut negate = false;
+        loop {
+            let flag = match self.peek() {
+                Some('i') => CASELESS,
+                Some('m') => MULTILINE,
+                Some('s') => DOTALL,
+                Some('x') => EXTENDED,
+                Some('U') => UNGREEDY,
+                Some('-') if !negate => {
+                    negate = true;
+                    self.pos += 1;
+                    continue;
+                }
+                Some(')') => {
+                    self.pos += 1;
+                    self.flags = flags;
+                    return Ok(Node::Empty);
+                }
+                Some(':') => {
+                    self.pos += 1;
+                    let outer = mem::replace(&mut self.flags, flags);
+                    let node = self.finish_group(None);
+                    self.flags = outer;
+                    return node;
+                }
+                _ => return Err(self.error("unrecognized character after (? or (?-")),
+            };
+            if negate {
+                flags &= !flag;
+            } else {
+                flags |= flag;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, Error> {
+        let Some(c) = self.peek() else { return Err(self.error("\\ at end of pattern")); };
+        self.pos += 1;
+        Ok(match c {
+            'A' => Node::SubjectStart,
+            'z' => Node::SubjectEnd,
+            'Z' => Node::SubjectEndNewline,
+            'b' => Node::WordBoundary { negate: false },
+            'B' => Node::WordBoundary { negate: true },
+            'k' => {
+                let close = match self.peek() {
+                    Some('<') => '>',
+                    Some('{') => '}',
+                    Some('\'') => '\'',
+                    _ => return Err(self.error("\\k is not followed by a name")),
+                };
+                self.pos += 1;
+                let name = self.parse_name(close)?;
+                self.backref_to(&name)?
+            }
+            'g' => {
+                let braced = self.eat('{');
+                let relative = self.eat('-');
+                let Some(n) = self.parse_number() else {
+                    return Err(self.error("a numbered reference must not be zero"));
+                };
+                if braced && !self.eat('}') {
+                    return Err(self.error("\\g is not followed by a number"));
+                }
+                let index = if relative {
+                    (self.groups + 1).saturating_sub(n as usize)
+                } else {
+                    n as usize
+                };
+                self.backref(index)?
+            }
+            '1'..='9' => {
+                self.pos -= 1;
+                let n = self.parse_number().unwrap_or(u32::MAX);
+                self.backref(n as usize)?
+            }
+            'Q' => {
+                let mut nodes = Vec::new();
+                while let Some(c) = self.peek() {
+                    if c == '\\' && self.chars.get(self.pos + 1) == Some(&'E') {
+                        self.pos += 2;
+                        break;
+                    }
+                    nodes.push(self.char(c));
+                    self.pos += 1;
+                }
+                Node::Group {
+                    node: Box::new(Node::Concat(nodes)),
+                    index: None,
+                }
+            }
+            'E' => Node::Empty,
+            c => match self.parse_class_escape(c)? {
+                ClassItem::Range(c, _) => self.char(c),
+                item => Node::Class {
+                    class: Class {
+                        negate: false,
+                        items: vec![item],
+                    },
+                    caseless: false,
+                },
+            },
+        })
+    }
+
+    fn backref_to(&self, name: &str) -> Result<Node, Error> {
+        match self.names.iter().find(|(group, _)| group == name) {
+            Some((_, index)) => self.backref(*index),
+            None => Err(self.error("reference to non-existent subpattern")),
+        }
+    }
+
+    fn backref(&self, index: usize) -> Result<Node, Error> {
+        if index == 0 || index > self.groups {
+            return Err(self.error("reference to non-existent subpattern"));
+        }
+        Ok(Node::Backref {
+            index,
+            caseless: self.has(CASELESS),
+        })
+    }
+
+    /// Parses an escape which stands for a character or a set of them, after the backslash
+    ///
+    /// A single character is returned as a range of one.
+    fn parse_class_escape(&mut self, c: char) -> Result<ClassItem, Error> {
+        let single = |c| Ok(ClassItem::Range(c, c));
+        match c {
+            'd' | 'D' => Ok(ClassItem::Set(Set::Digit, c == 'D')),
+            's' | 'S' => Ok(ClassItem::Set(Set::Space, c == 'S')),
+            'w' | 'W' => Ok(ClassItem::Set(Set::Word, c == 'W')),
+            'p' | 'P' => {
+                let name = if self.eat('{') {
+                    let start = self.pos;
+                    while !matches!(self.peek(), None | Some('}')) {
+                        self.pos += 1;
+                    }
+                    let name = self.chars[start..self.pos].iter().collect::<String>();
+                    if !self.eat('}') {
+                        return Err(self.error("malformed \\P or \\p sequence"));
+                    }
+                    name
+                } else {
+                    match self.peek() {
+                        Some(c) => {
+                            self.pos += 1;
+                            c.to_string()
+                        }
+                        None => return Err(self.error("malformed \\P or \\p sequence")),
+                    }
+                };
+                let (name, caret) = match name.strip_prefix('^') {
+                    Some(name) => (name, true),
+                    None => (name.as_str(), false),
+                };
+                match Property::from_name(name) {
+                    Some(property) => {
+                        Ok(ClassItem::Set(Set::Property(property), (c == 'P') != caret))
+                    }
+                    None => Err(self.error("unknown property name after \\P or \\p")),
+                }
+            }
+            'n' => single('\n'),
+            'r' => single('\r'),
+            't' => single('\t'),
+            'f' => single('\x0c'),
+            'e' => single('\x1b'),
+            'a' => single('\x07'),
+            'x' => {
+                let digits = if self.eat('{') {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_hexdigit()) {
+                        self.pos += 1;
+                    }
+                    let digits = self.chars[start..self.pos].iter().collect::<String>();
+                    if !self.eat('}') {
+                        return Err(self.error("malformed \\x{} sequence"));
+                    }
+                    digits
+                } else {
+                    let start = self.pos;
+                    while self.pos < start + 2
+                        && matches!(self.peek(), Some(c) if c.is_ascii_hexdigit())
+                    {
+                        self.pos += 1;
+                    }
+                    self.chars[start..self.pos].iter().collect::<String>()
+                };
+                let code = u32::from_str_radix(&digits, 16).unwrap_or(0);
+                let limit = if self.has(UNICODE) { 0x10ffff } else { 0xff };
+                match char::from_u32(code).filter(|_| code <= limit) {
+                    Some(c) => single(c),
+                    None => Err(self.error("character value in \\x{} or \\o{} is too large")),
+                }
+            }
+            '0' => {
+                let start = self.pos;
+                while self.pos < start + 2 && matches!(self.peek(), Some('0'..='7')) {
+                    self.pos += 1;
+                }
+                let digits = self.chars[start..self.pos].iter().collect::<String>();
+                let code = u32::from_str_radix(&digits, 8).unwrap_or(0);
+                single(char::from_u32(code).unwrap())
+            }
+            c if c.is_ascii_alphanumeric() => Err(self.error("unrecognized character follows \\")),
+            c => single(c),
+        }
+    }
+
+    /// Parses a character class, after the opening bracket
+    fn parse_class(&mut self) -> Result<Class, Error> {
+        let negate = self.eat('^');
+        let mut items = Vec::new();
+        // A closing bracket first is a literal
+        if self.eat(']') {
+            items.push(ClassItem::Range(']', ']'));
+        }
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("missing terminating ] for character class"));
+            };
+            self.pos += 1;
+            let item = match c {
+                ']' => break,
+                '[' if self.peek() == Some(':') => {
+                    let start = self.pos + 1;
+                    let end = self.chars[start..]
+                        .windows(2)
+                        .position(|pair| pair == [':', ']'])
+                        .map(|end| start + end);
+                    let Some(end) = end else {
+                        items.push(ClassItem::Range('[', '['));
+                        continue;
+                    };
+                    let name = self.chars[start..end].iter().collect::<String>();
+                    let (name, negate) = match name.strip_prefix('^') {
+                        Some(name) => (name, true),
+                        None => (name.as_str(), false),
+                    };
+                    let Some(set) = Set::posix(name) else {
+                        return Err(self.error("unknown POSIX class name"));
+                    };
+                    self.pos = end + 2;
+                    ClassItem::Set(set, negate)
+                }
+                '\\' => {
+                    let Some(c) = self.peek() else { return Err(self.error("\\ at end of pattern")); };
+                    self.pos += 1;
+                    match c {
+                        'b' => ClassItem::Range('\x08', '\x08'),
+                        c => self.parse_class_escape(c)?,
+                    }
+                }
+                c => ClassItem::Range(c, c),
+            };
+            // A range, unless the hyphen is last or follows a set
+            let first = match item {
+                ClassItem::Range(first, _)
+                    if self.peek() == Some('-')
+                        && !matches!(self.chars.get(self.pos + 1), None | Some(']')) =>
+                {
+                    first
+                }
+                item => {
+                    items.push(item);
+                    continue;
+                }
+            };
+            self.pos += 1;
+            let c = self.peek().unwrap();
+            self.pos += 1;
+            let last = match c {
+                '\\' => {
+                    let Some(c) = self.peek() else { return Err(self.error("\\ at end of pattern")); };
+                    self.pos += 1;
+                    match self.parse_class_escape(c)? {
+                        ClassItem::Range(last, _) => last,
+                        _ => return Err(self.error("invalid range in character class")),
+                    }
+                }
+                c => c,
+            };
+            if last < first {
+                return Err(self.error("range out of order in character class"));
+            }
+            items.push(ClassItem::Range(first, last));
+        }
+        Ok(Class { negate, items })
+    }
+}
+
+struct Input<'a> {
+    bytes: &'a [u8],
+    unicode: bool,
+}
+impl Input<'_> {
+    /// Returns the character at `pos`, and the position after it
+    #[inline]
+    fn at(&self, pos: usize) -> Option<(char, usize)> {
+        let rest = self.bytes.get(pos..).filter(|rest| !rest.is_empty())?;
+        if !self.unicode {
+            return Some((rest[0] as char, pos + 1));
+        }
+        // SAFETY: The subject was checked to be UTF-8, and positions are on character boundaries
+        let c = unsafe { std::str::from_utf8_unchecked(rest) }
+            .chars()
+            .next()?;
+        Some((c, pos + c.len_utf8()))
+    }
+
+    /// Returns the character before `pos`
+    fn before(&self, pos: usize) -> Option<char> {
+        let bytes = &self.bytes[..pos];
+        if !self.unicode {
+            return bytes.last().map(|&byte| byte as char);
+        }
+        unsafe { std::str::from_utf8_unchecked(bytes) }
+            .chars()
+            .next_back()
+    }
+
+    fn is_boundary(&self, pos: usize) -> bool {
+        !self.unicode || pos == self.bytes.len() || (self.bytes[pos] as i8) >= -0x40
+    }
+}
+
+struct Matcher<'a> {
+    input: Input<'a>,
+    ucp: bool,
+    options: &'a MatchOptions,
+    steps: Cell<u64>,
+    depth: Cell<u64>,
+    exceeded: Cell<Option<Limit>>,
+}
+impl Matcher<'_> {
+    /// Matches `node` at `pos`, then the rest of the pattern by calling `k` with the position after
+    /// the match, backtracking into `node` for as long as `k` fails and `node` can match otherwise
+    fn m(
+        &self,
+        node: &Node,
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        if self.exceeded.get().is_some() {
+            return false;
+        }
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if steps > self.options.limit {
+            self.exceeded.set(Some(Limit::Match));
+            return false;
+        }
+        let depth = self.depth.get();
+        if depth >= self.options.recursion_limit {
+            self.exceeded.set(Some(Limit::Recursion));
+            return false;
+        }
+        self.depth.set(depth + 1);
+        let matched = self.m_node(node, pos, caps, k);
+        self.depth.set(depth);
+        matched
+    }
+
+    fn m_node(
+        &self,
+        node: &Node,
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        let bytes = self.input.bytes;
+        let len = bytes.len();
+        match node {
+            Node::Empty => k(pos, caps),
+            Node::Char { .. } | Node::Any { .. } | Node::Class { .. } => {
+                match self.single(node, pos) {
+                    Some(next) => k(next, caps),
+                    None => false,
+                }
+            }
+            Node::LineStart { multiline } => {
+                let start = pos == 0 && !self.options.notbol;
+                let line = *multiline && pos > 0 && pos < len && bytes[pos - 1] == b'\n';
+                (start || line) && k(pos, caps)
+            }
+            Node::LineEnd { multiline, endonly } => {
+                let end = !self.options.noteol
+                    && (pos == len || (!endonly && pos + 1 == len && bytes[pos] == b'\n'));
+                let line = *multiline && pos < len && bytes[pos] == b'\n';
+                (end || line) && k(pos, caps)
+            }
+            Node::SubjectStart => pos == 0 && k(pos, caps),
+            Node::SubjectEnd => pos == len && k(pos, caps),
+            Node::SubjectEndNewline => {
+                (pos == len || (pos + 1 == len && bytes[pos] == b'\n')) && k(pos, caps)
+            }
+            Node::WordBoundary { negate } => {
+                let is_word = |c: char| Set::Word.contains(c, self.ucp);
+                let before = matches!(self.input.before(pos), Some(c) if is_word(c));
+                let after = matches!(self.input.at(pos), Some((c, _)) if is_word(c));
+                ((before != after) != *negate) && k(pos, caps)
+            }
+            Node::Group { node, index: None } => self.m(node, pos, caps, k),
+            Node::Group {
+                node,
+                index: Some(index),
+            } => {
+                let index = *index;
+                self.m(node, pos, caps, &mut |end, caps| {
+                    let inner = caps[index].replace((pos, end));
+                    if k(end, caps) {
+                        return true;
+                    }
+                    caps[index] = inner;
+                    false
+                })
+            }
+            Node::Atomic(node) => {
+                let saved = caps.clone();
+                let mut end = None;
+                self.m(node, pos, caps, &mut |next, _| {
+                    end = Some(next);
+                    true
+                });
+                match end {
+                    Some(end) if k(end, caps) => true,
+                    _ => {
+                        *caps = saved;
+                        false
+                    }
+                }
+            }
+            Node::Concat(nodes) => self.seq(nodes, pos, caps, k),
+            Node::Alt(alternatives) => alternatives
+                .iter()
+                .any(|alternative| self.m(alternative, pos, caps, k)),
+            Node::Repeat(repeat) => self.repeat(repeat, 0, pos, caps, k),
+            Node::Backref { index, caseless } => {
+                let Some((start, end)) = caps[*index] else { return false; };
+                let mut at = pos;
+                let mut from = start;
+                while from < end {
+                    let (Some((a, next_from)), Some((b, next_at))) =
+                        (self.input.at(from), self.input.at(at))
+                    else {
+                        return false;
+                    };
+                    if !self.same(a, b, *caseless) {
+                        return false;
+                    }
+                    from = next_from;
+                    at = next_at;
+                }
+                k(at, caps)
+            }
+            Node::Look {
+                node,
+                ahead,
+                negate,
+            } => {
+                let mut inner = caps.clone();
+                let matched = if *ahead {
+                    self.m(node, pos, &mut inner, &mut |_, _| true)
+                } else {
+                    (0..=pos)
+                        .rev()
+                        .filter(|&start| self.input.is_boundary(start))
+                        .any(|start| self.m(node, start, &mut inner, &mut |end, _| end == pos))
+                };
+                if *negate {
+                    return !matched && self.exceeded.get().is_none() && k(pos, caps);
+                }
+                if !matched {
+                    return false;
+                }
+                let saved = mem::replace(caps, inner);
+                if k(pos, caps) {
+                    return true;
+                }
+                *caps = saved;
+                false
+            }
+        }
+    }
+
+    fn seq(
+        &self,
+        nodes: &[Node],
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        match nodes.split_first() {
+            None => k(pos, caps),
+            Some((first, rest)) => self.m(first, pos, caps, &mut |next, caps| {
+                self.seq(rest, next, caps, k)
+            }),
+        }
+    }
+
+    fn repeat(
+        &self,
+        repeat: &Repeat,
+        count: u32,
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        if let Node::Char { .. } | Node::Any { .. } | Node::Class { .. } = repeat.node {
+            return self.repeat_single(repeat, pos, caps, k);
+        }
+        if repeat.possessive {
+            let saved = caps.clone();
+            let mut end = pos;
+            let mut count = 0;
+            while count < repeat.max {
+                let mut next = None;
+                self.m(&repeat.node, end, caps, &mut |at, _| {
+                    next = Some(at);
+                    true
+                });
+                match next {
+                    Some(next) if next != end || count < repeat.min => end = next,
+                    _ => break,
+                }
+                count += 1;
+            }
+            if count >= repeat.min && k(end, caps) {
+                return true;
+            }
+            *caps = saved;
+            return false;
+        }
+        if count < repeat.min {
+            return self.m(&repeat.node, pos, caps, &mut |next, caps| {
+                self.repeat(repeat, count + 1, next, caps, k)
+            });
+        }
+        // An iteration which matches nothing would repeat forever, so ends the repetition
+        let more = |caps: &mut Captures, k: &mut dyn FnMut(usize, &mut Captures) -> bool| {
+            count < repeat.max
+                && self.m(&repeat.node, pos, caps, &mut |next, caps| {
+                    next != pos && self.repeat(repeat, count + 1, next, caps, k)
+                })
+        };
+        if !repeat.greedy && k(pos, caps) {
+            return true;
+        }
+        more(caps, k) || (repeat.greedy && k(pos, caps))
+    }
+
+    /// Repeats a node which matches a single character, by a loop rather than by recursion
+    fn repeat_single(
+        &self,
+        repeat: &Repeat,
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        let (min, max) = (repeat.min as usize, repeat.max as usize);
+        let mut end = pos;
+        let mut count = 0;
+        if !repeat.greedy && !repeat.possessive {
+            loop {
+                if count >= min && k(end, caps) {
+                    return true;
+                }
+                if count == max || self.exceeded.get().is_some() {
+                    return false;
+                }
+                match self.single(&repeat.node, end) {
+                    Some(next) => end = next,
+                    None => return false,
+                }
+                count += 1;
+            }
+        }
+        let mut ends = vec![pos];
+        while count < max {
+            match self.single(&repeat.node, end) {
+                Some(next) => end = next,
+                None => break,
+            }
+            ends.push(end);
+            count += 1;
+        }
+        if count < min {
+            return false;
+        }
+        if repeat.possessive {
+            return k(end, caps);
+        }
+        ends[min..]
+            .iter()
+            .rev()
+            .any(|&end| self.exceeded.get().is_none() && k(end, caps))
+    }
+
+    /// Matches a node which matches a single character at `pos`, returning the position after it
+    #[inline]
+    fn single(&self, node: &Node, pos: usize) -> Option<usize> {
+        let (c, next) = self.input.at(pos)?;
+        let matched = match node {
+            Node::Char {
+                c: expected,
+                caseless,
+            } => self.same(*expected, c, *caseless),
+            Node::Any { dotall } => *dotall || c != '\n',
+            Node::Class { class, caseless } => {
+                class.contains(c, self.ucp)
+                    || (*caseless
+                        && (class.contains(self.lower(c), self.ucp)
+                            || class.contains(self.upper(c), self.ucp)))
+            }
+            _ => unreachable!(),
+        };
+        matched.then_some(next)
+    }
+
+    fn same(&self, a: char, b: char, caseless: bool) -> bool {
+        a == b || (caseless && self.lower(a) == self.lower(b))
+    }
+
+    fn lower(&self, c: char) -> char {
+        if c.is_ascii() || !self.input.unicode {
+            return c.to_ascii_lowercase();
+        }
+        let mut lower = c.to_lowercase();
+        match (lower.next(), lower.next()) {
+            (Some(lower), None) => lower,
+            _ => c,
+        }
+    }
+
+    fn upper(&self, c: char) -> char {
+        if c.is_ascii() || !self.input.unicode {
+            return c.to_ascii_uppercase();
+        }
+        let mut upper = c.to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(upper), None) => upper,
+            _ => c,
+        }
+    }
+}
@@ -0,0 +1,503 @@
+//! Sequential tracing, i.e. the `seq_trace` module.
+//!
+//! A process given a trace token with `seq_trace:set_token/1,2` passes it on with every message it
+//! sends, and to every process it spawns. Each process receiving the token takes it over, and
+//! passes it on in turn, so the token follows a request from process to process. The serial of the
+//! token is incremented with each send, so that events can be put in causal order, as the clock of
+//! each process is kept ahead of the serials of the tokens it has received.
+//!
+//! The events of a token whose flags ask for them are sent to the system tracer, set with
+//! `seq_trace:set_system_tracer/1`, as `{seq_trace, Label, Info}`, or `{seq_trace, Label, Info,
+//! Timestamp}` with one of the timestamp flags, where `Info` is one of:
+//!
+//! * `{send, Serial, From, To, Message}` - with the `send` flag
+//! * `{'receive', Serial, From, To, Message}` - with the `receive` flag, for messages received by a
+//! server or the root process, as only these have mailboxes here, see `super::gen`
+//! * `{print, Serial, From, [], Info}` - with the `print` flag, from `seq_trace:print/1,2`
+//! * `{spawn, Serial, Parent, Child, []}` and `{spawned, Serial, Parent, Child, {M, F, Args}}` -
+//! with the `spawn` flag
+//!
+//! where `Serial` is `{PreviousSerial, ThisSerial}`.
+//!
+//! Servers handle messages in the process which sent them, see `super::gen`, so the token carried
+//! by a message is taken over by that process while the server handles it, and the token it had
+//! before is restored afterwards. A token set by a server callback is therefore only in effect
+//! until the callback returns. As in ERTS, a message sent without a token clears the token while it
+//! is handled.
+use std::alloc::AllocError;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_alloc::fragment::HeapFragment;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::__support as support;
+use firefly_rt::term::*;
+
+use super::gen::Message;
+use super::trace::{self, Tracer};
+use super::util::*;
+
+const SEND: u8 = 1 << 0;
+const RECEIVE: u8 = 1 << 1;
+const PRINT: u8 = 1 << 2;
+const TIMESTAMP: u8 = 1 << 3;
+const STRICT_MONOTONIC_TIMESTAMP: u8 = 1 << 4;
+const MONOTONIC_TIMESTAMP: u8 = 1 << 5;
+const SPAWN: u8 = 1 << 6;
+
+/// The flags of a token, as set by `set_token/2`, with the bits of the flags in `get_token/0`
+const FLAGS: &[(&str, u8)] = &[
+    ("send", SEND),
+    ("receive", RECEIVE),
+    ("print", PRINT),
+    ("timestamp", TIMESTAMP),
+    ("strict_monotonic_timestamp", STRICT_MONOTONIC_TIMESTAMP),
+    ("monotonic_timestamp", MONOTONIC_TIMESTAMP),
+    ("spawn", SPAWN),
+];
+
+/// Whether any process has a token, so that untraced sends cost no more than reading this
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Whether trace messages are being sent, which are never traced themselves
+static SUPPRESSED: AtomicBool = AtomicBool::new(false);
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+/// A sequential trace token, as carried by a process or a message
+#[derive(Copy, Clone)]
+pub(crate) struct Token {
+    flags: u8,
+    /// The label of the token, a global term
+    label: OpaqueTerm,
+    serial: u64,
+    /// The process which last passed the token on
+    from: ProcessId,
+    /// The serial of the token before it was last passed on
+    last_cnt: u64,
+}
+impl Token {
+    fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Returns the serial as in events, i.e. `{PreviousSerial, ThisSerial}`
+    fn serial(&self, heap: &HeapFragment) -> Result<Term, AllocError> {
+        let (last_cnt, serial) = (self.last_cnt, self.serial);
+        firefly_rt::term!(heap, {(last_cnt), (serial)})
+    }
+
+    fn make_serial(&self, proc: &Process) -> OpaqueTerm {
+        let (last_cnt, serial) = (self.last_cnt as i64, self.serial as i64);
+        make_tuple(
+            proc,
+            &[Term::Int(last_cnt).into(), Term::Int(serial).into()],
+        )
+    }
+}
+
+#[derive(Default)]
+struct State {
+    tokens: BTreeMap<ProcessId, Token>,
+    /// The clock of each process, which is kept ahead of the serials of the tokens it receives
+    clocks: BTreeMap<ProcessId, u64>,
+    tracer: Option<Tracer>,
+}
+impl State {
+    /// Sets the token of `pid`, returning the previous one
+    fn swap(&mut self, pid: ProcessId, token: Option<Token>) -> Option<Token> {
+        let previous = match token {
+            Some(token) => {
+                let clock = self.clocks.entry(pid).or_insert(0);
+                *clock = (*clock).max(token.serial);
+                self.tokens.insert(pid, token)
+            }
+            None => self.tokens.remove(&pid),
+        };
+        ACTIVE.store(!self.tokens.is_empty(), Ordering::Relaxed);
+        previous
+    }
+
+    /// Increments the serial of the token of `pid`, as it is about to be passed on
+    fn pass_on(&mut self, pid: ProcessId) -> Option<Token> {
+        let token = self.tokens.get_mut(&pid)?;
+        let clock = self.clocks.entry(pid).or_insert(0);
+        *clock = (*clock).max(token.serial) + 1;
+        token.last_cnt = token.serial;
+        token.serial = *clock;
+        Some(Token {
+            from: pid,
+            ..*token
+        })
+    }
+}
+// The labels of tokens are global, see `set_token/2`
+unsafe impl Send for State {}
+
+/// Sets the token of the calling process, returning the previous one, or `[]` if it had none
+///
+/// `Token` is a token returned by `get_token/0`, or `[]` to clear it.
+#[export_name = "seq_trace:set_token/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_token1(token: OpaqueTerm) -> ErlangResult {
+    let token = match token.into() {
+        Term::Nil => None,
+        _ => match parse_token(token) {
+            Some(token) => Some(token),
+            None => return super::badarg(Trace::capture()),
+        },
+    };
+    let pid = current_pid();
+    let previous = state().swap(pid, token);
+    ErlangResult::Ok(with_process(|proc| make_token(proc, previous.as_ref())))
+}
+
+/// Sets a component of the token of the calling process, returning its previous value
+///
+/// `Component` is `label`, with any term as `Value`, `serial`, with `{Previous, Current}`, or a
+/// flag, with a boolean. The calling process is given a new token if it has none.
+#[export_name = "seq_trace:set_token/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_token2(component: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(component) = component.into() else { return super::badarg(Trace::capture()); };
+    let pid = current_pid();
+    let mut state = state();
+    let mut token = state.tokens.get(&pid).copied().unwrap_or(Token {
+        flags: 0,
+        label: Term::Int(0).into(),
+        serial: 0,
+        from: pid,
+        last_cnt: 0,
+    });
+    let previous = match (component.as_str(), value.into()) {
+        ("label", _) => {
+            // The label is sent with the events of the token, so it must outlive the caller
+            let label = token.label;
+            token.label = make_global(value);
+            label
+        }
+        ("serial", _) => {
+            let Some((last_cnt, serial)) = parse_serial(value) else {
+                return super::badarg(Trace::capture());
+            };
+            let previous = with_process(|proc| token.make_serial(proc));
+            token.last_cnt = last_cnt;
+            token.serial = serial;
+            previous
+        }
+        (name, Term::Bool(enable)) => {
+            let Some((_, flag)) = FLAGS.iter().find(|(flag, _)| *flag == name) else {
+                return super::badarg(Trace::capture());
+            };
+            let previous = token.has(*flag);
+            if enable {
+                token.flags |= flag;
+            } else {
+                token.flags &= !flag;
+            }
+            previous.into()
+        }
+        _ => return super::badarg(Trace::capture()),
+    };
+    state.swap(pid, Some(token));
+    ErlangResult::Ok(previous)
+}
+
+/// Returns the token of the calling process, or `[]` if it has none
+#[export_name = "seq_trace:get_token/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_token0() -> ErlangResult {
+    let token = token();
+    ErlangResult::Ok(with_process(|proc| make_token(proc, token.as_ref())))
+}
+
+/// Returns `{Component, Value}` for a component of the token of the calling process, or `[]` if it
+/// has none
+#[export_name = "seq_trace:get_token/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_token1(component: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(name) = component.into() else { return super::badarg(Trace::capture()); };
+    let flag = FLAGS.iter().find(|(flag, _)| *flag == name.as_str());
+    if !matches!(name.as_str(), "label" | "serial") && flag.is_none() {
+        return super::badarg(Trace::capture());
+    }
+    let Some(token) = token() else { return ErlangResult::Ok(OpaqueTerm::NIL); };
+    ErlangResult::Ok(with_process(|proc| {
+        let value = match (name.as_str(), flag) {
+            ("label", _) => token.label,
+            ("serial", _) => token.make_serial(proc),
+            (_, Some((_, flag))) => token.has(*flag).into(),
+            _ => unreachable!(),
+        };
+        make_tuple(proc, &[component, value])
+    }))
+}
+
+/// Sends `{print, Serial, From, [], TraceInfo}` to the system tracer, if the calling process has a
+/// token with the `print` flag
+#[export_name = "seq_trace:print/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn print1(info: OpaqueTerm) -> ErlangResult {
+    if let Some(token) = token().filter(|token| token.has(PRINT)) {
+        print(&token, info);
+    }
+    ErlangResult::Ok(true.into())
+}
+
+/// As `print/1`, but only if the label of the token is `Label`
+#[export_name = "seq_trace:print/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn print2(label: OpaqueTerm, info: OpaqueTerm) -> ErlangResult {
+    let token = token().filter(|token| token.has(PRINT) && equals(token.label, label));
+    if let Some(token) = token {
+        print(&token, info);
+    }
+    ErlangResult::Ok(true.into())
+}
+
+/// Clears the tokens of all processes
+///
+/// Messages already in the mailboxes of servers keep their tokens, unlike in ERTS.
+#[export_name = "seq_trace:reset_trace/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn reset_trace0() -> ErlangResult {
+    let mut state = state();
+    state.tokens.clear();
+    state.clocks.clear();
+    ACTIVE.store(false, Ordering::Relaxed);
+    ErlangResult::Ok(true.into())
+}
+
+/// Sets the process or port events are sent to, or `false` to discard them, returning the previous
+/// one
+#[export_name = "seq_trace:set_system_tracer/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_system_tracer1(tracer: OpaqueTerm) -> ErlangResult {
+    let tracer = match tracer.into() {
+        Term::Bool(false) => None,
+        _ => match trace::parse_tracer(tracer) {
+            Some(tracer) => Some(tracer),
+            None => return super::badarg(Trace::capture()),
+        },
+    };
+    let previous = std::mem::replace(&mut state().tracer, tracer);
+    ErlangResult::Ok(make_tracer(previous))
+}
+
+/// Returns the process or port events are sent to, or `false` if there is none
+#[export_name = "seq_trace:get_system_tracer/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_system_tracer0() -> ErlangResult {
+    let tracer = state().tracer;
+    ErlangResult::Ok(make_tracer(tracer))
+}
+
+/// Returns the token of the current process, to be carried by a message it defers to itself
+pub(crate) fn token() -> Option<Token> {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return None;
+    }
+    let pid = current_pid();
+    state().tokens.get(&pid).copied()
+}
+
+/// Passes the token of the current process on with `message`, sent to `to`, returning the token
+/// the message carries
+///
+/// This is called by `super::gen` as the message is placed in a mailbox.
+pub(crate) fn send(to: ProcessId, message: &Message) -> Option<Token> {
+    if !ACTIVE.load(Ordering::Relaxed) || SUPPRESSED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let received = trace::received(message)?;
+    let pid = current_pid();
+    let token = state().pass_on(pid)?;
+    if token.has(SEND) {
+        emit(&token, "send", pid, Some(to), received);
+    }
+    Some(token)
+}
+
+/// Traces the receipt of a message carrying `token` by `to`, before it is handled
+pub(crate) fn receive(to: ProcessId, token: Option<Token>, message: &Message) {
+    let Some(token) = token.filter(|token| token.has(RECEIVE)) else { return; };
+    let Some(received) = trace::received(message) else { return; };
+    emit(&token, "receive", token.from, Some(to), received);
+}
+
+/// Runs `fun` with `token` as the token of the current process, as when handling a message which
+/// carries it, restoring the previous token afterwards
+pub(crate) fn with_token<F, R>(token: Option<Token>, fun: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    if token.is_none() && !ACTIVE.load(Ordering::Relaxed) {
+        return fun();
+    }
+    let pid = current_pid();
+    let previous = state().swap(pid, token);
+    let result = fun();
+    state().swap(pid, previous);
+    result
+}
+
+/// Runs `fun` without passing on the token of the current process, as when sending trace messages
+pub(crate) fn untraced<F, R>(fun: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let suppressed = SUPPRESSED.swap(true, Ordering::Relaxed);
+    let result = fun();
+    SUPPRESSED.store(suppressed, Ordering::Relaxed);
+    result
+}
+
+/// Passes the token of the current process on to `child`, spawned to apply `mfa` to `args`
+pub(crate) fn spawn(child: ProcessId, mfa: ModuleFunctionArity, args: &[OpaqueTerm]) {
+    if !ACTIVE.load(Ordering::Relaxed) || SUPPRESSED.load(Ordering::Relaxed) {
+        return;
+    }
+    let parent = current_pid();
+    let token = {
+        let mut state = state();
+        let Some(token) = state.pass_on(parent) else { return; };
+        state.swap(child, Some(token));
+        token
+    };
+    if !token.has(SPAWN) {
+        return;
+    }
+    emit(&token, "spawn", parent, Some(child), |_| Ok(Term::Nil));
+    // Processes are spawned to apply `apply/2,3`, whose arguments are those of the call reported
+    let call = |heap: &HeapFragment| {
+        let args = match args {
+            [_, _, args] => (*args).into(),
+            args => {
+                let args = args.iter().copied().map(Term::from).collect::<Vec<_>>();
+                support::list(args.as_slice(), heap)?
+            }
+        };
+        support::tuple(&[mfa.module.into(), mfa.function.into(), args], heap)
+    };
+    emit(&token, "spawned", parent, Some(child), call);
+}
+
+/// Forgets the token of `pid`, which has exited
+pub(crate) fn exit(pid: ProcessId) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut state = state();
+    state.swap(pid, None);
+    state.clocks.remove(&pid);
+}
+
+fn print(token: &Token, info: OpaqueTerm) {
+    let pid = current_pid();
+    emit(token, "print", pid, None, |_| Ok(info.into()));
+}
+
+/// Sends `{seq_trace, Label, {Tag, Serial, From, To, Info}}` to the system tracer, where `To` is
+/// `[]` if not given, and `Info` is built by `info`
+fn emit<F>(token: &Token, tag: &str, from: ProcessId, to: Option<ProcessId>, info: F)
+where
+    F: FnOnce(&HeapFragment) -> Result<Term, AllocError>,
+{
+    // The lock is released before the message is sent, as a server tracer handles it right away
+    let Some(tracer) = state().tracer else { return; };
+    let Some(scratch) = trace::scratch() else { return; };
+    let heap = unsafe { scratch.as_ref() };
+    let message = (|| {
+        let to = match to {
+            Some(to) => trace::pid(to, heap)?,
+            None => Term::Nil,
+        };
+        let event = support::tuple(
+            &[
+                atom(tag).into(),
+                token.serial(heap)?,
+                trace::pid(from, heap)?,
+                to,
+                info(heap)?,
+            ],
+            heap,
+        )?;
+        let mut message = vec![atom("seq_trace").into(), token.label.into(), event];
+        if token.has(STRICT_MONOTONIC_TIMESTAMP) {
+            let time = crate::time::monotonic();
+            let unique = crate::time::unique();
+            message.push(firefly_rt::term!(heap, {(time), (unique)})?);
+        } else if token.has(MONOTONIC_TIMESTAMP) {
+            message.push(crate::time::monotonic().into_term(heap)?);
+        } else if token.has(TIMESTAMP) {
+            message.push(trace::now(heap)?);
+        }
+        support::tuple(message.as_slice(), heap)
+    })();
+    if let Ok(message) = message {
+        trace::deliver(tracer, message);
+    }
+    trace::free(scratch);
+}
+
+/// Parses a token as returned by `get_token/0`, i.e. `{Flags, Label, Serial, From, LastCnt}`
+fn parse_token(token: OpaqueTerm) -> Option<Token> {
+    let [flags, label, serial, from, last_cnt] = tuple_elements(token)? else { return None; };
+    let flags = u8::try_from(count(*flags)?).ok()?;
+    let from = match (*from).into() {
+        Term::Pid(pid) => pid.id(),
+        _ => return None,
+    };
+    Some(Token {
+        flags,
+        label: make_global(*label),
+        serial: count(*serial)?,
+        from,
+        last_cnt: count(*last_cnt)?,
+    })
+}
+
+/// Parses a serial as returned by `get_token/1`, i.e. `{Previous, Current}`
+fn parse_serial(serial: OpaqueTerm) -> Option<(u64, u64)> {
+    let [last_cnt, serial] = tuple_elements(serial)? else { return None; };
+    Some((count(*last_cnt)?, count(*serial)?))
+}
+
+fn count(term: OpaqueTerm) -> Option<u64> {
+    match term.into() {
+        Term::Int(n) => u64::try_from(n).ok(),
+        _ => None,
+    }
+}
+
+fn make_token(proc: &Process, token: Option<&Token>) -> OpaqueTerm {
+    let Some(token) = token else { return OpaqueTerm::NIL; };
+    make_tuple(
+        proc,
+        &[
+            Term::Int(token.flags as i64).into(),
+            token.label,
+            Term::Int(token.serial as i64).into(),
+            make_pid(proc, token.from),
+            Term::Int(token.last_cnt as i64).into(),
+        ],
+    )
+}
+
+fn make_tracer(tracer: Option<Tracer>) -> OpaqueTerm {
+    match tracer {
+        // The trace file of `dbg` only receives the events of processes it traces
+        None | Some(Tracer::File) => false.into(),
+        Some(Tracer::Process(pid)) => with_process(|proc| make_pid(proc, pid)),
+        Some(Tracer::Port(port)) => with_process(|proc| make_port(proc, port)),
+    }
+}
+
+fn current_pid() -> ProcessId {
+    with_process(|proc| proc.pid())
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.get_or_init(Default::default).lock().unwrap()
+}
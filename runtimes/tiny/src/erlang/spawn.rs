@@ -0,0 +1,366 @@
+//! `erlang:spawn_opt/2,4` and `erlang:spawn_request/1..5`, and the enforcement of the
+//! `max_heap_size` of processes.
+//!
+//! Process heaps do not grow in this runtime, so the options which size the heap determine all the
+//! memory a process will ever have: the heap has the default size, or `min_heap_size` if larger,
+//! clamped to `max_heap_size`. A process which fails to allocate on a heap clamped to its
+//! `max_heap_size` has reached the limit, and when it is next descheduled an error report is logged
+//! and it is killed, as its `kill` and `error_logger` flags ask. A process whose heap is smaller
+//! than its limit runs out of memory as any other process does. Spawning fails with `system_limit`
+//! if the heap cannot be allocated.
+//!
+//! `fullsweep_after` and `min_bin_vheap_size` are recorded, and reported by `process_info/2`, but
+//! have no effect, as the heap is only collected when requested with `garbage_collect/0,1,2`,
+//! which compacts it in a single sweep, see `super::process`. `priority`, or the priority of the
+//! parent if it is not given, decides which run queue the process is scheduled on, see
+//! `crate::scheduler`. Messages are always kept off the heap of a process here, as only servers
+//! have mailboxes, so `message_queue_data` is only recorded. Neither `link` nor `monitor` are
+//! supported.
+use std::mem;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::{MaxHeapSize, MessageQueueData, Priority, Process, SpawnOptions};
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::util::*;
+
+/// Spawns a process which applies `Fun` to no arguments, with the given options
+#[export_name = "erlang:spawn_opt/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_opt2(fun: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let Some(start) = apply_fun(fun) else { return super::badarg(Trace::capture()) };
+    let Some(options) = parse_options(options) else { return super::badarg(Trace::capture()) };
+    spawn(start, options)
+}
+
+/// Spawns a process which applies `Module:Function` to `Args`, with the given options
+#[export_name = "erlang:spawn_opt/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_opt4(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    args: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(start) = apply_mfa(module, function, args) else {
+        return super::badarg(Trace::capture());
+    };
+    let Some(options) = parse_options(options) else { return super::badarg(Trace::capture()) };
+    spawn(start, options)
+}
+
+/// Requests the spawn of a process which applies `Fun` to no arguments, see `spawn_request/5`
+#[export_name = "erlang:spawn_request/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_request1(fun: OpaqueTerm) -> ErlangResult {
+    spawn_request3(local_node(), fun, OpaqueTerm::NIL)
+}
+
+/// Either `spawn_request(Fun, Options)` or `spawn_request(Node, Fun)`
+#[export_name = "erlang:spawn_request/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_request2(fun_or_node: OpaqueTerm, arg: OpaqueTerm) -> ErlangResult {
+    match fun_or_node.into() {
+        Term::Atom(_) => spawn_request3(fun_or_node, arg, OpaqueTerm::NIL),
+        _ => spawn_request3(local_node(), fun_or_node, arg),
+    }
+}
+
+/// Either `spawn_request(Node, Fun, Options)` or `spawn_request(Module, Function, Args)`
+#[export_name = "erlang:spawn_request/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_request3(
+    arg1: OpaqueTerm,
+    arg2: OpaqueTerm,
+    arg3: OpaqueTerm,
+) -> ErlangResult {
+    match arg2.into() {
+        Term::Closure(_) => {
+            let Some(start) = apply_fun(arg2) else { return super::badarg(Trace::capture()) };
+            request(arg1, start, arg3)
+        }
+        _ => spawn_request5(local_node(), arg1, arg2, arg3, OpaqueTerm::NIL),
+    }
+}
+
+/// Either `spawn_request(Module, Function, Args, Options)` or
+/// `spawn_request(Node, Module, Function, Args)`
+#[export_name = "erlang:spawn_request/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_request4(
+    arg1: OpaqueTerm,
+    arg2: OpaqueTerm,
+    arg3: OpaqueTerm,
+    arg4: OpaqueTerm,
+) -> ErlangResult {
+    match arg3.into() {
+        Term::Atom(_) => spawn_request5(arg1, arg2, arg3, arg4, OpaqueTerm::NIL),
+        _ => spawn_request5(local_node(), arg1, arg2, arg3, arg4),
+    }
+}
+
+/// Requests the spawn of a process on `Node` which applies `Module:Function` to `Args`, returning
+/// a reference which identifies the request
+///
+/// The outcome is sent to the caller as `{ReplyTag, ReqId, ok, Pid}` or
+/// `{ReplyTag, ReqId, error, Reason}`, where the tag is given by the `{reply_tag, ReplyTag}` option
+/// and is `spawn_reply` by default. Which of these are sent is chosen by the `{reply, Reply}`
+/// option, where `Reply` is one of `yes` (the default), `no`, `error_only` or `success_only`. The
+/// other options are those of `spawn_opt/4`.
+///
+/// Processes have no mailboxes in this runtime, see `super::bang2`, so a reply sent to the caller
+/// could never be received. Unless the caller is the program embedding the runtime, which has one,
+/// any request which may be replied to, i.e. without `{reply, no}`, raises `notsup` and spawns
+/// nothing. There is no distribution in this runtime either, so spawning on any node but the local
+/// one fails with `noconnection`, as when a node is not connected in ERTS.
+#[export_name = "erlang:spawn_request/5"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_request5(
+    node: OpaqueTerm,
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    args: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(start) = apply_mfa(module, function, args) else {
+        return super::badarg(Trace::capture());
+    };
+    request(node, start, options)
+}
+
+/// Abandons the spawn request `ReqId`, returning true if its reply was yet to be sent
+///
+/// Requests complete before `spawn_request` returns in this runtime, so this always returns false,
+/// as it does in ERTS once the reply has been sent.
+#[export_name = "erlang:spawn_request_abandon/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_request_abandon1(request_id: OpaqueTerm) -> ErlangResult {
+    match request_id.into() {
+        Term::Reference(_) => ErlangResult::Ok(false.into()),
+        _ => super::badarg(Trace::capture()),
+    }
+}
+
+/// Which replies to a spawn request are sent, see `spawn_request/5`
+#[derive(Copy, Clone)]
+enum Reply {
+    Yes,
+    No,
+    ErrorOnly,
+    SuccessOnly,
+}
+
+fn request(node: OpaqueTerm, start: Start, options: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(node) = node.into() else { return super::badarg(Trace::capture()) };
+    let mut tag: OpaqueTerm = atom("spawn_reply").into();
+    let mut reply = Reply::Yes;
+    let mut spawn_options = SpawnOptions::default();
+    let Some(options) = list_to_vec(options) else { return super::badarg(Trace::capture()) };
+    for option in options {
+        let ok = match tuple_elements(option) {
+            Some([key, value]) if is_atom(*key, "reply_tag") => {
+                tag = *value;
+                true
+            }
+            Some([key, value]) if is_atom(*key, "reply") => {
+                let value = match Term::from(*value) {
+                    Term::Atom(value) => value.as_str(),
+                    _ => "",
+                };
+                reply = match value {
+                    "yes" => Reply::Yes,
+                    "no" => Reply::No,
+                    "error_only" => Reply::ErrorOnly,
+                    "success_only" => Reply::SuccessOnly,
+                    _ => return super::badarg(Trace::capture()),
+                };
+                true
+            }
+            _ => parse_option(&mut spawn_options, option).is_some(),
+        };
+        if !ok {
+            return super::badarg(Trace::capture());
+        }
+    }
+    if !spawn_options.is_valid() {
+        return super::badarg(Trace::capture());
+    }
+    if !matches!(reply, Reply::No) && !has_mailbox() {
+        return ErlangResult::raise(atoms::Error, atom("notsup").into(), Trace::capture());
+    }
+
+    let outcome = if node.as_str() == NO_NODE {
+        start_process(start, spawn_options).map_err(|_| atom("system_limit"))
+    } else {
+        Err(atom("noconnection"))
+    };
+    let request_id = with_process(make_ref);
+    let message = with_process(|proc| {
+        let (status, value) = match outcome {
+            Ok(pid) if matches!(reply, Reply::Yes | Reply::SuccessOnly) => {
+                ("ok", make_pid(proc, pid))
+            }
+            Err(reason) if matches!(reply, Reply::Yes | Reply::ErrorOnly) => {
+                ("error", reason.into())
+            }
+            _ => return None,
+        };
+        let status = atom(status).into();
+        Some(make_tuple(proc, &[tag, request_id, status, value]))
+    });
+    if let Some(message) = message {
+        if let ErlangResult::Err(err) = super::send2(self_pid(), message) {
+            return ErlangResult::Err(err);
+        }
+    }
+    ErlangResult::Ok(request_id)
+}
+
+/// Returns true if the caller has a mailbox, i.e. is the program embedding the runtime
+fn has_mailbox() -> bool {
+    let pid = with_process(|proc| proc.pid());
+    pid == scheduler::with_current(|scheduler| scheduler.root_pid())
+}
+
+/// The name of the local node, which is not alive as there is no distribution
+const NO_NODE: &str = "nonode@nohost";
+
+pub(super) fn local_node() -> OpaqueTerm {
+    atom(NO_NODE).into()
+}
+
+/// What a process is spawned to do: the function it is reported to start in, the function it
+/// actually starts in, and the arguments to the latter
+type Start = (ModuleFunctionArity, DynamicCallee, Vec<OpaqueTerm>);
+
+/// Returns how to start a process which applies `Fun` to no arguments
+fn apply_fun(fun: OpaqueTerm) -> Option<Start> {
+    let Term::Closure(_) = fun.into() else { return None; };
+    // The process starts in `erlang:apply/2`, so that calling the fun is done by the process
+    let apply = super::apply2 as extern "C-unwind" fn(OpaqueTerm, OpaqueTerm) -> ErlangResult;
+    let callee = unsafe { mem::transmute::<_, DynamicCallee>(apply) };
+    let mfa = ModuleFunctionArity::new(atom("erlang"), atom("apply"), 2);
+    Some((mfa, callee, vec![make_global(fun), OpaqueTerm::NIL]))
+}
+
+/// Returns how to start a process which applies `Module:Function` to `Args`
+fn apply_mfa(module: OpaqueTerm, function: OpaqueTerm, args: OpaqueTerm) -> Option<Start> {
+    let (Term::Atom(m), Term::Atom(f)) = (module.into(), function.into()) else { return None; };
+    let arity = list_to_vec(args)?.len();
+    // The process starts in `erlang:apply/3`, so that an undefined function raises `undef` in the
+    // spawned process rather than here, as in ERTS
+    let apply =
+        super::apply3 as extern "C-unwind" fn(OpaqueTerm, OpaqueTerm, OpaqueTerm) -> ErlangResult;
+    let callee = unsafe { mem::transmute::<_, DynamicCallee>(apply) };
+    let mfa = ModuleFunctionArity::new(m, f, arity);
+    Some((mfa, callee, vec![module, function, make_global(args)]))
+}
+
+fn spawn(start: Start, options: SpawnOptions) -> ErlangResult {
+    match start_process(start, options) {
+        Ok(pid) => ErlangResult::Ok(with_process(|proc| make_pid(proc, pid))),
+        Err(_) => ErlangResult::raise(atoms::Error, atom("system_limit").into(), Trace::capture()),
+    }
+}
+
+fn start_process((mfa, callee, args): Start, options: SpawnOptions) -> anyhow::Result<ProcessId> {
+    let traced_args = args.clone();
+    let pid = scheduler::with_current(|scheduler| scheduler.spawn_opt(mfa, callee, args, options))
+        .map(|process| process.pid())?;
+    super::trace::spawn(pid, mfa, traced_args.as_slice());
+    super::seq_trace::spawn(pid, mfa, traced_args.as_slice());
+    Ok(pid)
+}
+
+/// Parses a list of spawn options, returning `None` if any is invalid or unsupported
+fn parse_options(list: OpaqueTerm) -> Option<SpawnOptions> {
+    let mut options = SpawnOptions::default();
+    for option in list_to_vec(list)? {
+        parse_option(&mut options, option)?;
+    }
+    options.is_valid().then_some(options)
+}
+
+/// Parses a single spawn option into `options`, returning `None` if it is invalid or unsupported
+fn parse_option(options: &mut SpawnOptions, option: OpaqueTerm) -> Option<()> {
+    let [key, value] = tuple_elements(option)? else { return None; };
+    let Term::Atom(key) = (*key).into() else { return None; };
+    match (key.as_str(), Term::from(*value)) {
+        ("min_heap_size", Term::Int(size)) if size >= 0 => {
+            options.min_heap_size = Some(size as usize);
+        }
+        ("max_heap_size", value) => {
+            options.max_heap_size = parse_max_heap_size(value)?;
+        }
+        ("min_bin_vheap_size", Term::Int(size)) if size >= 0 => {
+            options.min_bin_vheap_size = size as usize;
+        }
+        ("fullsweep_after", Term::Int(n)) if n >= 0 => {
+            options.fullsweep_after = n as usize;
+        }
+        ("priority", Term::Atom(priority)) => {
+            options.priority = Some(Priority::try_from(priority.as_str()).ok()?);
+        }
+        ("message_queue_data", Term::Atom(data)) => {
+            options.message_queue_data = MessageQueueData::try_from(data.as_str()).ok()?;
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Parses `Size | #{size => Size, kill => boolean(), error_logger => boolean()}`, where a size of
+/// zero means there is no limit
+pub(super) fn parse_max_heap_size(value: Term) -> Option<Option<MaxHeapSize>> {
+    let max = match value {
+        Term::Int(size) if size >= 0 => MaxHeapSize::new(size as usize),
+        Term::Map(map) => {
+            let Some(Term::Int(size)) = map.get(atom("size")) else { return None; };
+            let mut max = MaxHeapSize::new(usize::try_from(size).ok()?);
+            for (key, value) in map.iter() {
+                let Term::Atom(key) = *key else { return None; };
+                match (key.as_str(), *value) {
+                    ("size", _) | ("include_shared_binaries", Term::Bool(_)) => (),
+                    ("kill", Term::Bool(flag)) => max.kill = flag,
+                    ("error_logger", Term::Bool(flag)) => max.error_logger = flag,
+                    _ => return None,
+                }
+            }
+            max
+        }
+        _ => return None,
+    };
+    Some(if max.size == 0 { None } else { Some(max) })
+}
+
+/// Logs and kills `process` if it has reached its `max_heap_size`, as its options ask
+///
+/// This is called by the scheduler each time the process is descheduled.
+pub(crate) fn enforce_max_heap_size(process: &Process) {
+    if !process.max_heap_size_exceeded() {
+        return;
+    }
+    let Some(max) = process.options().max_heap_size else { return; };
+    if max.error_logger {
+        eprintln!(
+            "=ERROR REPORT====\n     \
+             Process:          {}\n     \
+             Context:          maximum heap size reached\n     \
+             Max Heap Size:    {}\n     \
+             Total Heap Size:  {}\n     \
+             Kill:             {}\n     \
+             Error Logger:     {}",
+            Pid::Local { id: process.pid() },
+            max.size,
+            process.heap_size(),
+            max.kill,
+            max.error_logger,
+        );
+    }
+    if max.kill {
+        process.exit_error(exit_err(atom("killed").into()));
+    }
+}
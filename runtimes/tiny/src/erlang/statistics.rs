@@ -0,0 +1,166 @@
+//! `erlang:statistics/1`, the counters with which programs, and the benchmarks run by
+//! `firefly bench`, measure the work done by the runtime.
+//!
+//! The `statistics/1` items supported are `reductions` and `exact_reductions`,
+//! which are the same here, `context_switches` and `wall_clock`. Items of the form
+//! `{Total, SinceLastCall}` track the last call across all processes, as in ERTS.
+//! `garbage_collection` is `{Sweeps, WordsReclaimed, 0}`, see `crate::memory::sweep`.
+//!
+//! `scheduler_wall_time` and `scheduler_wall_time_all` are also supported, as `[{1, Active,
+//! Total}]` for the single scheduler, in nanoseconds. Measuring them costs little here, so they are
+//! always enabled, rather than only after `erlang:system_flag(scheduler_wall_time, true)`.
+//!
+//! `run_queue` and `total_run_queue_lengths` are the number of processes waiting to run,
+//! `run_queue_lengths` the same as a list for the single scheduler, and `total_active_tasks` and
+//! `active_tasks` also count the calling process. `runtime` is `{Total, SinceLastCall}` in
+//! milliseconds of CPU time used by the runtime, user and system time combined, or of time spent
+//! running processes on WebAssembly, where CPU time cannot be read. The `_all` variants are the
+//! same, as there are no dirty schedulers.
+//!
+//! `microstate_accounting` returns `[#{type => scheduler, id => 1, counters => Counters}]`, where
+//! `Counters` maps each state the scheduler can be in to the time it has spent in it, in
+//! `perf_counter` time units, or `undefined` if accounting was never enabled with
+//! `erlang:system_flag(microstate_accounting, true)`, see `super::msacc`. The states are
+//! `emulator`, running processes, `gc`, sweeping binaries, `port`, delivering the messages of ports
+//! to their owners, `sleep`, waiting on ports or timers, `aux`, the work the scheduler does between
+//! processes, and `other`. `check_io` is always 0, as ports are polled while waiting, as `sleep`.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::{Process, WORD_SIZE};
+use firefly_rt::term::*;
+
+use crate::scheduler::{self, Microstate};
+
+use super::util::*;
+
+/// The values of `reductions` and `wall_clock` when they were last read
+static LAST_REDUCTIONS: AtomicU64 = AtomicU64::new(0);
+static LAST_WALL_CLOCK: AtomicU64 = AtomicU64::new(0);
+static LAST_RUNTIME: AtomicU64 = AtomicU64::new(0);
+
+/// Returns statistics about the system, as selected by `Item`
+#[export_name = "erlang:statistics/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn statistics1(item: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(item) = item.into() else { return super::badarg(Trace::capture()) };
+    if let "scheduler_wall_time" | "scheduler_wall_time_all" = item.as_str() {
+        let (active, total) = scheduler::with_current(|scheduler| scheduler.wall_time());
+        return ErlangResult::Ok(with_process(|proc| {
+            let scheduler = [1, active, total].map(|value| make_u64(proc, value));
+            let scheduler = make_tuple(proc, &scheduler);
+            make_list(proc, &[scheduler])
+        }));
+    }
+    if item.as_str() == "microstate_accounting" {
+        return ErlangResult::Ok(with_process(microstate_accounting));
+    }
+    let (queued, active) = scheduler::with_current(|scheduler| {
+        let queued = scheduler.run_queue_len();
+        let running = scheduler.current_process().pid() != scheduler.root_pid();
+        (queued, queued + running as usize)
+    });
+    match item.as_str() {
+        "run_queue" | "total_run_queue_lengths" | "total_run_queue_lengths_all" => {
+            return ErlangResult::Ok(Term::Int(queued as i64).into());
+        }
+        "total_active_tasks" | "total_active_tasks_all" => {
+            return ErlangResult::Ok(Term::Int(active as i64).into());
+        }
+        "run_queue_lengths" | "run_queue_lengths_all" | "active_tasks" | "active_tasks_all" => {
+            let length = if item.as_str().starts_with("run_queue") {
+                queued
+            } else {
+                active
+            };
+            return ErlangResult::Ok(with_process(|proc| {
+                make_list(proc, &[Term::Int(length as i64).into()])
+            }));
+        }
+        _ => (),
+    }
+    if item.as_str() == "garbage_collection" {
+        // Heaps are not collected, so the collections counted are the sweeps of the binaries of
+        // exited processes, see `crate::memory::sweep`
+        let (sweeps, bytes) = crate::memory::sweeps();
+        let words = bytes / WORD_SIZE as u64;
+        return ErlangResult::Ok(with_process(|proc| {
+            let values = [sweeps, words, 0].map(|value| make_u64(proc, value));
+            make_tuple(proc, &values)
+        }));
+    }
+    let (total, since_last) = match item.as_str() {
+        "reductions" | "exact_reductions" => {
+            let total = scheduler::with_current(|scheduler| scheduler.reductions());
+            (total, since_last(&LAST_REDUCTIONS, total))
+        }
+        "context_switches" => {
+            let total = scheduler::with_current(|scheduler| scheduler.context_switches());
+            (total, 0)
+        }
+        "wall_clock" => {
+            let total = crate::time::monotonic() / 1_000_000;
+            (total, since_last(&LAST_WALL_CLOCK, total))
+        }
+        "runtime" => {
+            let total = runtime() / 1_000_000;
+            (total, since_last(&LAST_RUNTIME, total))
+        }
+        _ => return super::badarg(Trace::capture()),
+    };
+    ErlangResult::Ok(with_process(|proc| {
+        make_tuple(proc, &[make_u64(proc, total), make_u64(proc, since_last)])
+    }))
+}
+
+/// Returns `statistics(microstate_accounting)`, see the module documentation
+fn microstate_accounting(proc: &Process) -> OpaqueTerm {
+    let Some(counters) = scheduler::with_current(|scheduler| scheduler.msacc().counters()) else {
+        return atoms::Undefined.into();
+    };
+    let counters = Microstate::ALL
+        .into_iter()
+        .zip(counters)
+        .map(|(state, value)| (Term::Atom(atom(state.name())), make_u64(proc, value).into()));
+    let counters = Map::new_from_iter_in(counters, proc).unwrap();
+    let scheduler = [
+        ("counters", Term::from(counters)),
+        ("id", Term::Int(1)),
+        ("type", Term::Atom(atom("scheduler"))),
+    ];
+    let scheduler = scheduler
+        .into_iter()
+        .map(|(key, value)| (Term::Atom(atom(key)), value));
+    let scheduler = Map::new_from_iter_in(scheduler, proc).unwrap().into();
+    make_list(proc, &[scheduler])
+}
+
+/// Returns the nanoseconds of CPU time used by the runtime so far
+#[cfg(not(target_arch = "wasm32"))]
+fn runtime() -> u64 {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return 0;
+    }
+    let usage = unsafe { usage.assume_init() };
+    [usage.ru_utime, usage.ru_stime]
+        .iter()
+        .map(|time| time.tv_sec as u64 * 1_000_000_000 + time.tv_usec as u64 * 1_000)
+        .sum()
+}
+
+/// Returns the nanoseconds spent running processes so far, in place of CPU time
+#[cfg(target_arch = "wasm32")]
+fn runtime() -> u64 {
+    scheduler::with_current(|scheduler| scheduler.wall_time().0)
+}
+
+/// Records `total` as the last value of a counter, returning how much it has grown since the last
+fn since_last(last: &AtomicU64, total: u64) -> u64 {
+    total.saturating_sub(last.swap(total, Ordering::Relaxed))
+}
+
+fn make_u64(proc: &Process, value: u64) -> OpaqueTerm {
+    value.into_term(proc).unwrap().into()
+}
@@ -0,0 +1,623 @@
+//! `string`, the functions operating on chardata by grapheme cluster.
+//!
+//! `length/1`, `pad/2,3,4`, `replace/3,4` and `lexemes/2` take chardata, deep lists of characters
+//! and UTF-8 binaries, and walk it a character at a time without flattening it first, see `Chars`.
+//! What they return preserves the chardata given rather than converting it to lists: `pad/2,3,4`
+//! returns the string as is, wrapped in the padding, `replace/3,4` returns the parts of the string
+//! around each match, joined by the replacement, and `lexemes/2` returns its lexemes, with parts
+//! of binaries returned as binaries. Once the first match is found, `replace/3` returns the rest
+//! of the string as it was given, without walking it.
+//!
+//! Lengths, patterns and separators are in grapheme clusters, see `grapheme`, so that e.g. `"e"`
+//! does not match the start of `"e\x{301}"`, and `"\r\n"` is a single character. The obsolete
+//! `tokens/2` is `lexemes/2` over code points instead, as it was in OTP before clusters.
+mod grapheme;
+
+use smallvec::SmallVec;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use self::grapheme::Segmenter;
+
+use super::badarg;
+use super::util::*;
+
+/// Returns the number of grapheme clusters in `String`
+#[export_name = "string:length/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn length1(string: OpaqueTerm) -> ErlangResult {
+    let Some(length) = length(string) else { return badarg(Trace::capture()); };
+    ErlangResult::Ok(Term::Int(length as i64).into())
+}
+
+/// Pads `String` with spaces after it, to `Length` clusters
+#[export_name = "string:pad/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn pad2(string: OpaqueTerm, length: OpaqueTerm) -> ErlangResult {
+    pad4(
+        string,
+        length,
+        atom("trailing").into(),
+        Term::Int(' ' as i64).into(),
+    )
+}
+
+/// Pads `String` with spaces on the side given by `Dir`, to `Length` clusters
+#[export_name = "string:pad/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn pad3(
+    string: OpaqueTerm,
+    length: OpaqueTerm,
+    dir: OpaqueTerm,
+) -> ErlangResult {
+    pad4(string, length, dir, Term::Int(' ' as i64).into())
+}
+
+/// Pads `String` with `Char`, a cluster, on the side given by `Dir`, to `Length` clusters
+///
+/// Returns `[String | Pad]`, `[Pad | String]` or `[Pre, String | Post]`, for `trailing`, `leading`
+/// and `both` respectively, where `Post` is the longer of the two if the padding is uneven.
+#[export_name = "string:pad/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn pad4(
+    string: OpaqueTerm,
+    length: OpaqueTerm,
+    dir: OpaqueTerm,
+    ch: OpaqueTerm,
+) -> ErlangResult {
+    let Term::Int(target) = length.into() else { return badarg(Trace::capture()); };
+    let Some(dir) = Dir::parse(dir) else { return badarg(Trace::capture()); };
+    if cluster(ch).is_none() {
+        return badarg(Trace::capture());
+    }
+    let Some(length) = self::length(string) else {
+        return badarg(Trace::capture());
+    };
+    let padding = (target - length as i64).max(0) as usize;
+    ErlangResult::Ok(with_process(|proc| {
+        let pad = |n: usize| make_list(proc, &vec![ch; n]);
+        match dir {
+            Dir::Leading => make_cons(proc, pad(padding), string),
+            Dir::Trailing => make_cons(proc, string, pad(padding)),
+            Dir::Both => {
+                let post = make_cons(proc, string, pad(padding - padding / 2));
+                make_cons(proc, pad(padding / 2), post)
+            }
+        }
+    }))
+}
+
+/// Replaces the first match of `SearchPattern` in `String` with `Replacement`
+#[export_name = "string:replace/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn replace3(
+    string: OpaqueTerm,
+    pattern: OpaqueTerm,
+    replacement: OpaqueTerm,
+) -> ErlangResult {
+    replace4(string, pattern, replacement, atom("leading").into())
+}
+
+/// Replaces the first, last or every match of `SearchPattern` in `String` with `Replacement`, as
+/// given by `Where`, returning the parts of `String` around the matches joined by `Replacement`
+#[export_name = "string:replace/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn replace4(
+    string: OpaqueTerm,
+    pattern: OpaqueTerm,
+    replacement: OpaqueTerm,
+    place: OpaqueTerm,
+) -> ErlangResult {
+    let Some(place) = Place::parse(place) else { return badarg(Trace::capture()); };
+    let Some(pattern) = clusters(pattern) else { return badarg(Trace::capture()); };
+    let parts = with_process(|proc| {
+        let parts = split(proc, string, &pattern, place)?;
+        let mut joined = Vec::with_capacity(parts.len() * 2);
+        for (i, part) in parts.into_iter().enumerate() {
+            if i > 0 {
+                joined.push(replacement);
+            }
+            joined.push(part);
+        }
+        Some(make_list(proc, &joined))
+    });
+    match parts {
+        Some(parts) => ErlangResult::Ok(parts),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Splits `String` into the lexemes separated by any of the clusters in `SeparatorList`
+#[export_name = "string:lexemes/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn lexemes2(string: OpaqueTerm, separators: OpaqueTerm) -> ErlangResult {
+    let separators = list_to_vec(separators).and_then(|separators| {
+        separators
+            .into_iter()
+            .map(cluster)
+            .collect::<Option<Vec<_>>>()
+    });
+    let Some(separators) = separators else { return badarg(Trace::capture()); };
+    lexemes(Graphemes::new(string), &separators)
+}
+
+/// Splits `String` into the tokens separated by any of the characters in `SeparatorList`
+///
+/// This is `lexemes/2` over code points rather than clusters, and so splits `"\r\n"` on `$\n`.
+#[export_name = "string:tokens/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tokens2(string: OpaqueTerm, separators: OpaqueTerm) -> ErlangResult {
+    let Some(separators) = charlist_to_string(separators) else { return badarg(Trace::capture()); };
+    let separators = separators
+        .chars()
+        .map(|c| Cluster::from_elem(c, 1))
+        .collect::<Vec<_>>();
+    lexemes(Graphemes::code_points(string), &separators)
+}
+
+fn lexemes(mut graphemes: Graphemes, separators: &[Cluster]) -> ErlangResult {
+    let lexemes = with_process(|proc| {
+        let empty = empty_of(proc, graphemes.string);
+        let mut lexemes = vec![];
+        let mut lexeme = Piece::default();
+        for grapheme in graphemes.by_ref() {
+            if !separators.iter().any(|separator| grapheme.is(separator)) {
+                lexeme.extend(&grapheme);
+            } else if !lexeme.is_empty() {
+                lexemes.push(std::mem::take(&mut lexeme).into_term(proc, empty));
+            }
+        }
+        if !lexeme.is_empty() {
+            lexemes.push(lexeme.into_term(proc, empty));
+        }
+        make_list(proc, &lexemes)
+    });
+    if graphemes.is_invalid() {
+        return badarg(Trace::capture());
+    }
+    ErlangResult::Ok(lexemes)
+}
+
+/// Returns the parts of `string` around the first, last or every match of `pattern`
+///
+/// `string` is returned as is if there is no match, and the rest of it, from the end of the first
+/// match, if only that match is replaced.
+fn split(
+    proc: &Process,
+    string: OpaqueTerm,
+    pattern: &[Cluster],
+    place: Place,
+) -> Option<Vec<OpaqueTerm>> {
+    if pattern.is_empty() {
+        return Some(vec![string]);
+    }
+    let empty = empty_of(proc, string);
+    let mut graphemes = Graphemes::new(string);
+    let matches = |graphemes: &[Grapheme]| {
+        graphemes
+            .iter()
+            .zip(pattern.iter())
+            .all(|(grapheme, cluster)| grapheme.is(cluster))
+    };
+    if let Place::Trailing = place {
+        let all = graphemes.by_ref().collect::<Vec<_>>();
+        if graphemes.is_invalid() {
+            return None;
+        }
+        let found = (pattern.len()..=all.len())
+            .rev()
+            .find(|&end| matches(&all[end - pattern.len()..end]));
+        let Some(end) = found else { return Some(vec![string]); };
+        let before = Piece::of(&all[..end - pattern.len()]).into_term(proc, empty);
+        let after = Piece::of(&all[end..]).into_term(proc, empty);
+        return Some(vec![before, after]);
+    }
+
+    // As the pattern is of a fixed length, the first match to end is the first to start
+    let mut parts = vec![];
+    let mut part = vec![];
+    while let Some(grapheme) = graphemes.next() {
+        part.push(grapheme);
+        if part.len() < pattern.len() || !matches(&part[part.len() - pattern.len()..]) {
+            continue;
+        }
+        part.truncate(part.len() - pattern.len());
+        parts.push(Piece::of(&part).into_term(proc, empty));
+        part.clear();
+        if let Place::Leading = place {
+            parts.push(graphemes.rest(proc, empty));
+            return Some(parts);
+        }
+    }
+    if graphemes.is_invalid() {
+        return None;
+    }
+    if parts.is_empty() {
+        return Some(vec![string]);
+    }
+    parts.push(Piece::of(&part).into_term(proc, empty));
+    Some(parts)
+}
+
+/// Returns the number of clusters in `string`, if it is chardata
+fn length(string: OpaqueTerm) -> Option<usize> {
+    let mut graphemes = Graphemes::new(string);
+    let length = graphemes.by_ref().count();
+    (!graphemes.is_invalid()).then_some(length)
+}
+
+/// Returns the empty string of the same kind as `string`, for the parts of it which are empty
+fn empty_of(proc: &Process, string: OpaqueTerm) -> OpaqueTerm {
+    match Term::from(string).as_bitstring() {
+        Some(_) => make_binary(proc, &[]),
+        None => OpaqueTerm::NIL,
+    }
+}
+
+/// A grapheme cluster, as its characters
+type Cluster = SmallVec<[char; 4]>;
+
+/// Returns the cluster `term`, a character or a list of characters, as in `grapheme_cluster()`
+fn cluster(term: OpaqueTerm) -> Option<Cluster> {
+    match term.into() {
+        Term::Int(c) => {
+            let c = char::from_u32(u32::try_from(c).ok()?)?;
+            Some(Cluster::from_elem(c, 1))
+        }
+        _ => Some(charlist_to_string(term)?.chars().collect()),
+    }
+}
+
+/// Returns the clusters of the chardata `term`
+fn clusters(term: OpaqueTerm) -> Option<Vec<Cluster>> {
+    let mut graphemes = Graphemes::new(term);
+    let clusters = graphemes
+        .by_ref()
+        .map(|grapheme| grapheme.units.iter().map(|unit| unit.c).collect())
+        .collect();
+    (!graphemes.is_invalid()).then_some(clusters)
+}
+
+#[derive(Copy, Clone)]
+enum Dir {
+    Leading,
+    Trailing,
+    Both,
+}
+impl Dir {
+    fn parse(term: OpaqueTerm) -> Option<Self> {
+        let Term::Atom(dir) = term.into() else { return None; };
+        match dir.as_str() {
+            "leading" => Some(Self::Leading),
+            "trailing" => Some(Self::Trailing),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Place {
+    Leading,
+    Trailing,
+    All,
+}
+impl Place {
+    fn parse(term: OpaqueTerm) -> Option<Self> {
+        let Term::Atom(place) = term.into() else { return None; };
+        match place.as_str() {
+            "leading" => Some(Self::Leading),
+            "trailing" => Some(Self::Trailing),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+}
+
+/// Where a character of chardata came from, so that the parts of it can be returned as they were
+#[derive(Copy, Clone)]
+enum Origin {
+    List,
+    /// The bytes `start..end` of `binary`
+    Binary {
+        binary: OpaqueTerm,
+        start: usize,
+        end: usize,
+    },
+}
+
+#[derive(Copy, Clone)]
+struct Unit {
+    c: char,
+    origin: Origin,
+}
+
+enum Item {
+    /// An element of chardata
+    Term(OpaqueTerm),
+    /// The remainder of a list, whose tail must be nil or a binary
+    Elements(OpaqueTerm),
+    /// The remainder of a binary, from the given byte
+    Bytes(OpaqueTerm, usize),
+}
+
+/// The characters of chardata, walked depth-first with an explicit stack, so that what is left of
+/// it can be returned as is, see `rest`
+struct Chars {
+    stack: Vec<Item>,
+    invalid: bool,
+}
+impl Chars {
+    fn new(term: OpaqueTerm) -> Self {
+        // Characters are only valid as elements of a list
+        let invalid = matches!(term.into(), Term::Int(_));
+        Self {
+            stack: vec![Item::Term(term)],
+            invalid,
+        }
+    }
+
+    /// Puts back `unit`, the last character returned
+    fn unread(&mut self, unit: Unit) {
+        match unit.origin {
+            Origin::List => self.stack.push(Item::Term(Term::Int(unit.c as i64).into())),
+            Origin::Binary { binary, start, .. } => {
+                self.stack.pop();
+                self.stack.push(Item::Bytes(binary, start));
+            }
+        }
+    }
+
+    /// Returns the chardata not yet walked, or `empty` if there is none
+    fn rest(&mut self, proc: &Process, empty: OpaqueTerm) -> OpaqueTerm {
+        let rest = self
+            .stack
+            .drain(..)
+            .rev()
+            .map(|item| match item {
+                Item::Term(term) | Item::Elements(term) | Item::Bytes(term, 0) => term,
+                Item::Bytes(binary, start) => make_binary(proc, &bytes(binary)[start..]),
+            })
+            .collect::<Vec<_>>();
+        // A character put back by `unread` is only valid as an element of a list
+        match rest.as_slice() {
+            [] => empty,
+            [rest] if !matches!((*rest).into(), Term::Int(_)) => *rest,
+            rest => make_list(proc, rest),
+        }
+    }
+
+    /// Returns the next character, `Some(None)` at the end, or `None` if the chardata is invalid
+    fn next_unit(&mut self) -> Option<Option<Unit>> {
+        loop {
+            let Some(item) = self.stack.pop() else { return Some(None); };
+            match item {
+                Item::Term(term) => match term.into() {
+                    Term::Nil => (),
+                    Term::Int(c) => {
+                        let c = char::from_u32(u32::try_from(c).ok()?)?;
+                        let origin = Origin::List;
+                        return Some(Some(Unit { c, origin }));
+                    }
+                    Term::Cons(_) => self.stack.push(Item::Elements(term)),
+                    _ => self.push_binary(term)?,
+                },
+                Item::Elements(term) => match term.into() {
+                    Term::Nil => (),
+                    Term::Cons(ptr) => {
+                        let cell = unsafe { ptr.as_ref() };
+                        self.stack.push(Item::Elements(cell.tail().into()));
+                        self.stack.push(Item::Term(cell.head().into()));
+                    }
+                    // The tail of an improper list must be a binary
+                    _ => self.push_binary(term)?,
+                },
+                Item::Bytes(binary, start) => {
+                    let bytes = bytes(binary);
+                    if start == bytes.len() {
+                        continue;
+                    }
+                    let end = bytes.len().min(start + utf8_len(bytes[start])?);
+                    let c = std::str::from_utf8(&bytes[start..end])
+                        .ok()?
+                        .chars()
+                        .next()?;
+                    self.stack.push(Item::Bytes(binary, end));
+                    let origin = Origin::Binary { binary, start, end };
+                    return Some(Some(Unit { c, origin }));
+                }
+            }
+        }
+    }
+
+    fn push_binary(&mut self, term: OpaqueTerm) -> Option<()> {
+        let binary = Term::from(term);
+        let bits = binary.as_bitstring()?;
+        if !bits.is_binary() || !bits.is_aligned() {
+            return None;
+        }
+        self.stack.push(Item::Bytes(term, 0));
+        Some(())
+    }
+}
+impl Iterator for Chars {
+    type Item = Unit;
+
+    fn next(&mut self) -> Option<Unit> {
+        if self.invalid {
+            return None;
+        }
+        match self.next_unit() {
+            Some(unit) => unit,
+            None => {
+                self.invalid = true;
+                None
+            }
+        }
+    }
+}
+
+/// Returns the bytes of `binary`, which must be an aligned binary
+fn bytes<'a>(binary: OpaqueTerm) -> &'a [u8] {
+    let term = Term::from(binary);
+    let bytes = unsafe { term.as_bitstring().unwrap().as_bytes_unchecked() };
+    // SAFETY: The data of the binary is kept alive by `binary`, which outlives the call to the BIF
+    unsafe { std::slice::from_raw_parts(bytes.as_ptr(), bytes.len()) }
+}
+
+/// Returns the length of the UTF-8 sequence starting with `byte`
+fn utf8_len(byte: u8) -> Option<usize> {
+    match byte {
+        0x00..=0x7f => Some(1),
+        0xc0..=0xdf => Some(2),
+        0xe0..=0xef => Some(3),
+        0xf0..=0xf7 => Some(4),
+        _ => None,
+    }
+}
+
+/// The characters of a cluster, and where they came from
+struct Grapheme {
+    units: SmallVec<[Unit; 2]>,
+}
+impl Grapheme {
+    fn is(&self, cluster: &[char]) -> bool {
+        self.units
+            .iter()
+            .map(|unit| unit.c)
+            .eq(cluster.iter().copied())
+    }
+}
+
+/// The clusters of chardata, or of its code points for `tokens/2`
+struct Graphemes {
+    string: OpaqueTerm,
+    chars: Chars,
+    /// The first character of the next cluster, read to find the end of the last one
+    pending: Option<Unit>,
+    segmenter: Option<Segmenter>,
+}
+impl Graphemes {
+    fn new(string: OpaqueTerm) -> Self {
+        Self {
+            string,
+            chars: Chars::new(string),
+            pending: None,
+            segmenter: Some(Segmenter::default()),
+        }
+    }
+
+    fn code_points(string: OpaqueTerm) -> Self {
+        Self {
+            segmenter: None,
+            ..Self::new(string)
+        }
+    }
+
+    fn is_invalid(&self) -> bool {
+        self.chars.invalid
+    }
+
+    /// Returns the chardata not yet walked, see `Chars::rest`
+    fn rest(&mut self, proc: &Process, empty: OpaqueTerm) -> OpaqueTerm {
+        if let Some(unit) = self.pending.take() {
+            self.chars.unread(unit);
+        }
+        self.chars.rest(proc, empty)
+    }
+}
+impl Iterator for Graphemes {
+    type Item = Grapheme;
+
+    fn next(&mut self) -> Option<Grapheme> {
+        let first = match self.pending.take() {
+            Some(unit) => unit,
+            None => {
+                let unit = self.chars.next()?;
+                if let Some(segmenter) = self.segmenter.as_mut() {
+                    segmenter.starts(unit.c);
+                }
+                unit
+            }
+        };
+        let mut units = SmallVec::new();
+        units.push(first);
+        let Some(segmenter) = self.segmenter.as_mut() else { return Some(Grapheme { units }); };
+        for unit in self.chars.by_ref() {
+            if segmenter.starts(unit.c) {
+                self.pending = Some(unit);
+                break;
+            }
+            units.push(unit);
+        }
+        Some(Grapheme { units })
+    }
+}
+
+/// A part of chardata being rebuilt from its characters, see `Origin`
+#[derive(Default)]
+struct Piece {
+    segments: Vec<Segment>,
+}
+
+enum Segment {
+    Chars(String),
+    /// The bytes `start..end` of a binary
+    Bytes(OpaqueTerm, usize, usize),
+}
+
+impl Piece {
+    fn of(graphemes: &[Grapheme]) -> Self {
+        let mut piece = Self::default();
+        for grapheme in graphemes {
+            piece.extend(grapheme);
+        }
+        piece
+    }
+
+    fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    fn extend(&mut self, grapheme: &Grapheme) {
+        for unit in grapheme.units.iter() {
+            let last = self.segments.last_mut();
+            match unit.origin {
+                Origin::List => match last {
+                    Some(Segment::Chars(chars)) => chars.push(unit.c),
+                    _ => self.segments.push(Segment::Chars(unit.c.to_string())),
+                },
+                // Characters next to each other in a binary are returned as a single part of it
+                Origin::Binary { binary, start, end } => match last {
+                    Some(Segment::Bytes(last, _, last_end))
+                        if *last == binary && *last_end == start =>
+                    {
+                        *last_end = end
+                    }
+                    _ => self.segments.push(Segment::Bytes(binary, start, end)),
+                },
+            }
+        }
+    }
+
+    /// Returns the piece as chardata, or `empty` if it is empty
+    fn into_term(self, proc: &Process, empty: OpaqueTerm) -> OpaqueTerm {
+        let mut segments = self
+            .segments
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Chars(chars) => charlist(proc, &chars),
+                Segment::Bytes(binary, 0, end) if end == bytes(binary).len() => binary,
+                Segment::Bytes(binary, start, end) => make_binary(proc, &bytes(binary)[start..end]),
+            })
+            .collect::<Vec<_>>();
+        match segments.len() {
+            0 => empty,
+            1 => segments.pop().unwrap(),
+            _ => make_list(proc, &segments),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! The segmentation of text into grapheme clusters, following the rules of UAX #29 which matter in
+//! practice: `\r\n`, combining marks and other extending characters, emoji joined with ZWJ, pairs
+//! of regional indicators, and Hangul syllables. As with the properties of `re`, the tables cover
+//! the common scripts rather than all of Unicode, and prepended characters are not supported.
+const CR: char = '\r';
+const LF: char = '\n';
+const ZWJ: char = '\u{200d}';
+
+/// The characters which extend the cluster before them, i.e. combining marks, spacing marks,
+/// variation selectors, emoji modifiers and tags
+const EXTEND: &[(u32, u32)] = &[
+    (0x300, 0x36f),
+    (0x483, 0x489),
+    (0x591, 0x5bd),
+    (0x5bf, 0x5bf),
+    (0x5c1, 0x5c2),
+    (0x5c4, 0x5c5),
+    (0x5c7, 0x5c7),
+    (0x610, 0x61a),
+    (0x64b, 0x65f),
+    (0x670, 0x670),
+    (0x6d6, 0x6dc),
+    (0x6df, 0x6e4),
+    (0x6e7, 0x6e8),
+    (0x6ea, 0x6ed),
+    (0x711, 0x711),
+    (0x730, 0x74a),
+    (0x7a6, 0x7b0),
+    (0x900, 0x903),
+    (0x93a, 0x93c),
+    (0x93e, 0x94f),
+    (0x951, 0x957),
+    (0x962, 0x963),
+    (0x981, 0x983),
+    (0x9bc, 0x9bc),
+    (0x9be, 0x9cd),
+    (0x9d7, 0x9d7),
+    (0x9e2, 0x9e3),
+    (0xe31, 0xe31),
+    (0xe34, 0xe3a),
+    (0xe47, 0xe4e),
+    (0x1ab0, 0x1aff),
+    (0x1dc0, 0x1dff),
+    (0x200c, 0x200c),
+    (0x20d0, 0x20ff),
+    (0x302a, 0x302f),
+    (0x3099, 0x309a),
+    (0xfe00, 0xfe0f),
+    (0xfe20, 0xfe2f),
+    (0x1f3fb, 0x1f3ff),
+    (0xe0020, 0xe007f),
+    (0xe0100, 0xe01ef),
+];
+
+/// The pictographs which may be joined into a single emoji with ZWJ
+const PICTOGRAPHIC: &[(u32, u32)] = &[(0x2600, 0x27bf), (0x2b00, 0x2bff), (0x1f000, 0x1faff)];
+
+const REGIONAL_INDICATOR: (u32, u32) = (0x1f1e6, 0x1f1ff);
+
+fn is_in(c: char, ranges: &[(u32, u32)]) -> bool {
+    let c = c as u32;
+    ranges.iter().any(|&(first, last)| first <= c && c <= last)
+}
+
+fn is_extend(c: char) -> bool {
+    c == ZWJ || is_in(c, EXTEND)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    is_in(c, &[REGIONAL_INDICATOR])
+}
+
+/// The parts of Hangul syllables, which are written as sequences of leading consonants, vowels
+/// and trailing consonants, or as precomposed syllables of the first two or all three
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Hangul {
+    L,
+    V,
+    T,
+    Lv,
+    Lvt,
+}
+impl Hangul {
+    fn of(c: char) -> Option<Self> {
+        let c = c as u32;
+        Some(match c {
+            0x1100..=0x115f | 0xa960..=0xa97c => Self::L,
+            0x1160..=0x11a7 | 0xd7b0..=0xd7c6 => Self::V,
+            0x11a8..=0x11ff | 0xd7cb..=0xd7fb => Self::T,
+            0xac00..=0xd7a3 if (c - 0xac00) % 28 == 0 => Self::Lv,
+            0xac00..=0xd7a3 => Self::Lvt,
+            _ => return None,
+        })
+    }
+
+    /// Whether `next` continues the syllable this is part of
+    fn continues(self, next: Self) -> bool {
+        match self {
+            Self::L => next != Self::T,
+            Self::V | Self::Lv => matches!(next, Self::V | Self::T),
+            Self::T | Self::Lvt => next == Self::T,
+        }
+    }
+}
+
+/// Finds the boundaries between the clusters of a sequence of characters, fed to it one at a time
+#[derive(Default)]
+pub struct Segmenter {
+    prev: Option<char>,
+    /// The number of regional indicators in a row up to `prev`, as they pair up into flags
+    regional_indicators: usize,
+}
+impl Segmenter {
+    /// Returns whether `c` starts a new cluster, rather than extending the one before it
+    pub fn starts(&mut self, c: char) -> bool {
+        let starts = match self.prev {
+            None => true,
+            Some(prev) => self.is_boundary(prev, c),
+        };
+        self.regional_indicators = if is_regional_indicator(c) {
+            self.regional_indicators + 1
+        } else {
+            0
+        };
+        self.prev = Some(c);
+        starts
+    }
+
+    fn is_boundary(&self, prev: char, c: char) -> bool {
+        if prev == CR && c == LF {
+            return false;
+        }
+        if prev.is_control() || c.is_control() {
+            return true;
+        }
+        if let (Some(prev), Some(c)) = (Hangul::of(prev), Hangul::of(c)) {
+            return !prev.continues(c);
+        }
+        if is_extend(c) {
+            return false;
+        }
+        if prev == ZWJ && is_in(c, PICTOGRAPHIC) {
+            return false;
+        }
+        if is_regional_indicator(c) {
+            return self.regional_indicators % 2 == 0;
+        }
+        true
+    }
+}
@@ -0,0 +1,628 @@
+//! The `supervisor` behaviour, implemented on the in-process servers described in `gen`.
+//!
+//! Children started via `start_link` from a supervisor's start function (i.e. with `gen_server`,
+//! `gen_statem` or `supervisor`) are supervised: when one terminates, the supervisor applies its
+//! restart strategy (`one_for_one`, `one_for_all`, `rest_for_one` or `simple_one_for_one`)
+//! according to the child's restart type. If more than `intensity` restarts occur within `period`
+//! seconds, the supervisor terminates all of its children and shuts down, as in OTP. Since children
+//! are not processes, the `shutdown` of a child spec has no effect; children are always terminated
+//! via their `terminate` callback.
+use std::collections::VecDeque;
+use std::ptr::NonNull;
+use std::time::Duration;
+
+use instant::Instant;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::gen::{self, Behaviour, Message, Name, Outcome, Started, Unavailable};
+use super::util::*;
+use super::{badarg, badarg_err};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Strategy {
+    OneForOne,
+    OneForAll,
+    RestForOne,
+    SimpleOneForOne,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Restart {
+    Permanent,
+    Transient,
+    Temporary,
+}
+
+#[derive(Clone)]
+struct ChildSpec {
+    id: OpaqueTerm,
+    module: Atom,
+    function: Atom,
+    args: Vec<OpaqueTerm>,
+    restart: Restart,
+    supervisor: bool,
+    modules: OpaqueTerm,
+}
+
+struct Child {
+    spec: ChildSpec,
+    /// The pid of the running child, if any
+    pid: Option<ProcessId>,
+}
+
+pub(crate) struct SupervisorState {
+    strategy: Strategy,
+    intensity: usize,
+    period: Duration,
+    /// The times at which recent restarts occurred, oldest first
+    restarts: VecDeque<Instant>,
+    /// The children of this supervisor, in start order
+    children: Vec<Child>,
+    /// The child spec used to start children of a `simple_one_for_one` supervisor
+    template: Option<ChildSpec>,
+}
+
+#[export_name = "supervisor:start_link/2"]
+pub extern "C-unwind" fn start_link2(module: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    start_link(None, module, args)
+}
+
+#[export_name = "supervisor:start_link/3"]
+pub extern "C-unwind" fn start_link3(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = Name::parse(name) else {
+        return badarg(Trace::capture());
+    };
+    start_link(Some(name), module, args)
+}
+
+fn start_link(name: Option<Name>, module: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(module) = module.into() else {
+        return badarg(Trace::capture());
+    };
+    gen::start(name, module, true, |pid| {
+        let result = gen::callback(module, "init", &[args])?;
+        let spec = match tuple_elements(result) {
+            Some([tag, spec]) if is_atom(*tag, "ok") => *spec,
+            _ if is_atom(result, "ignore") => return Ok(Started::Ignore),
+            _ => {
+                let location = with_process(|proc| {
+                    make_tuple(proc, &[module.into(), atom("init").into(), result])
+                });
+                return Ok(Started::Stop(make_global(reason2("bad_return", location))));
+            }
+        };
+        let Some([flags, child_specs]) = tuple_elements(spec) else {
+            return Ok(Started::Stop(make_global(reason2("supervisor_data", spec))));
+        };
+        let Some((strategy, intensity, period)) = parse_flags(*flags) else {
+            return Ok(Started::Stop(make_global(reason2(
+                "supervisor_data",
+                *flags,
+            ))));
+        };
+        let specs = match parse_specs(*child_specs) {
+            Ok(specs) => specs,
+            Err(reason) => return Ok(Started::Stop(make_global(reason2("start_spec", reason)))),
+        };
+
+        let mut state = SupervisorState {
+            strategy,
+            intensity,
+            period,
+            restarts: VecDeque::new(),
+            children: vec![],
+            template: None,
+        };
+        if strategy == Strategy::SimpleOneForOne {
+            match <[ChildSpec; 1]>::try_from(specs) {
+                Ok([template]) => state.template = Some(template),
+                Err(_) => {
+                    return Ok(Started::Stop(make_global(reason2(
+                        "bad_start_spec",
+                        *child_specs,
+                    ))));
+                }
+            }
+            return Ok(Started::Ok(Behaviour::Supervisor(state)));
+        }
+
+        for spec in specs {
+            match start_child_spec(pid, &spec) {
+                Ok(child) => state.children.push(Child { spec, pid: child }),
+                Err(reason) => {
+                    terminate_children(&mut state.children);
+                    let reason = with_process(|proc| {
+                        let failed = make_tuple(
+                            proc,
+                            &[atom("failed_to_start_child").into(), spec.id, reason],
+                        );
+                        make_tuple(proc, &[atom("shutdown").into(), failed])
+                    });
+                    return Ok(Started::Stop(make_global(reason)));
+                }
+            }
+        }
+        Ok(Started::Ok(Behaviour::Supervisor(state)))
+    })
+}
+
+/// Starts a child, from a child spec, or for `simple_one_for_one` supervisors, a list of extra
+/// arguments to append to those of the template
+#[export_name = "supervisor:start_child/2"]
+pub extern "C-unwind" fn start_child2(supervisor: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("supervisor", "start_child", &[supervisor, spec]);
+    with_supervisor(supervisor, location, |pid, state| {
+        let spec = match &state.template {
+            Some(template) => {
+                let Some(extra) = list_to_vec(spec) else {
+                    return Err(badarg_err(Trace::capture()));
+                };
+                let mut spec = template.clone();
+                spec.args.extend(extra.into_iter().map(make_global));
+                spec
+            }
+            None => match parse_spec(spec) {
+                Ok(spec) => spec,
+                Err(reason) => return Ok(gen::error(reason)),
+            },
+        };
+        if state.template.is_none() {
+            if let Some(child) = state.find(spec.id) {
+                return Ok(gen::error(match child.pid {
+                    Some(pid) => with_process(|proc| {
+                        let pid = make_pid(proc, pid);
+                        make_tuple(proc, &[atom("already_started").into(), pid])
+                    }),
+                    None => reason2("already_present", spec.id),
+                }));
+            }
+        }
+        match start_child_spec(pid, &spec) {
+            Ok(child) => {
+                let result = started(child);
+                // Temporary children which are not started are not kept, nor are any dynamic
+                // children
+                if child.is_some()
+                    || (spec.restart != Restart::Temporary && state.template.is_none())
+                {
+                    state.children.push(Child { spec, pid: child });
+                }
+                Ok(result)
+            }
+            Err(reason) => Ok(gen::error(reason)),
+        }
+    })
+}
+
+/// Terminates a child, identified by its id, or for `simple_one_for_one` supervisors, its pid
+#[export_name = "supervisor:terminate_child/2"]
+pub extern "C-unwind" fn terminate_child2(supervisor: OpaqueTerm, id: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("supervisor", "terminate_child", &[supervisor, id]);
+    with_supervisor(supervisor, location, |_pid, state| {
+        let index = match id.into() {
+            Term::Pid(pid) => {
+                let pid = pid.id();
+                state
+                    .children
+                    .iter()
+                    .position(|child| child.pid == Some(pid))
+            }
+            _ if state.template.is_none() => state.position(id),
+            _ => None,
+        };
+        let Some(index) = index else {
+            return Ok(gen::error(atom("not_found").into()));
+        };
+        let child = &mut state.children[index];
+        if let Some(pid) = child.pid.take() {
+            gen::shutdown(pid, atom("shutdown").into());
+        }
+        if state.template.is_some() || child.spec.restart == Restart::Temporary {
+            state.children.remove(index);
+        }
+        Ok(ErlangResult::Ok(atoms::Ok.into()))
+    })
+}
+
+#[export_name = "supervisor:restart_child/2"]
+pub extern "C-unwind" fn restart_child2(supervisor: OpaqueTerm, id: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("supervisor", "restart_child", &[supervisor, id]);
+    with_supervisor(supervisor, location, |pid, state| {
+        let Some(index) = state.position(id).filter(|_| state.template.is_none()) else {
+            return Ok(gen::error(atom("not_found").into()));
+        };
+        if state.children[index].pid.is_some() {
+            return Ok(gen::error(atom("running").into()));
+        }
+        match start_child_spec(pid, &state.children[index].spec) {
+            Ok(child) => {
+                state.children[index].pid = child;
+                Ok(started(child))
+            }
+            Err(reason) => Ok(gen::error(reason)),
+        }
+    })
+}
+
+#[export_name = "supervisor:delete_child/2"]
+pub extern "C-unwind" fn delete_child2(supervisor: OpaqueTerm, id: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("supervisor", "delete_child", &[supervisor, id]);
+    with_supervisor(supervisor, location, |_pid, state| {
+        let Some(index) = state.position(id).filter(|_| state.template.is_none()) else {
+            return Ok(gen::error(atom("not_found").into()));
+        };
+        if state.children[index].pid.is_some() {
+            return Ok(gen::error(atom("running").into()));
+        }
+        state.children.remove(index);
+        Ok(ErlangResult::Ok(atoms::Ok.into()))
+    })
+}
+
+/// Returns `[{Id, Child, Type, Modules}]` for each child, where `Child` is `undefined` if the child
+/// is not running
+#[export_name = "supervisor:which_children/1"]
+pub extern "C-unwind" fn which_children1(supervisor: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("supervisor", "which_children", &[supervisor]);
+    with_supervisor(supervisor, location, |_pid, state| {
+        let simple = state.template.is_some();
+        let children = with_process(|proc| {
+            let children = state
+                .children
+                .iter()
+                .map(|child| {
+                    let id = if simple {
+                        atom("undefined").into()
+                    } else {
+                        child.spec.id
+                    };
+                    let pid = match child.pid {
+                        Some(pid) => make_pid(proc, pid),
+                        None => atom("undefined").into(),
+                    };
+                    let ty = atom(if child.spec.supervisor {
+                        "supervisor"
+                    } else {
+                        "worker"
+                    });
+                    make_tuple(proc, &[id, pid, ty.into(), child.spec.modules])
+                })
+                .collect::<Vec<_>>();
+            make_list(proc, children.as_slice())
+        });
+        Ok(ErlangResult::Ok(children))
+    })
+}
+
+/// Returns `[{specs, N}, {active, N}, {supervisors, N}, {workers, N}]`
+#[export_name = "supervisor:count_children/1"]
+pub extern "C-unwind" fn count_children1(supervisor: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("supervisor", "count_children", &[supervisor]);
+    with_supervisor(supervisor, location, |_pid, state| {
+        let specs = if state.template.is_some() {
+            1
+        } else {
+            state.children.len()
+        };
+        let active = state.children.iter().filter(|c| c.pid.is_some()).count();
+        let supervisors = state.children.iter().filter(|c| c.spec.supervisor).count();
+        let workers = state.children.len() - supervisors;
+        let counts = with_process(|proc| {
+            let counts = [
+                ("specs", specs),
+                ("active", active),
+                ("supervisors", supervisors),
+                ("workers", workers),
+            ]
+            .iter()
+            .map(|(key, n)| make_tuple(proc, &[atom(key).into(), (*n as i64).try_into().unwrap()]))
+            .collect::<Vec<_>>();
+            make_list(proc, counts.as_slice())
+        });
+        Ok(ErlangResult::Ok(counts))
+    })
+}
+
+#[export_name = "supervisor:check_childspecs/1"]
+pub extern "C-unwind" fn check_childspecs1(specs: OpaqueTerm) -> ErlangResult {
+    match parse_specs(specs) {
+        Ok(_) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(reason) => gen::error(reason),
+    }
+}
+
+/// Runs `fun` with the state of `supervisor`, as if it were handling a request
+fn with_supervisor<F>(supervisor: OpaqueTerm, location: OpaqueTerm, fun: F) -> ErlangResult
+where
+    F: FnOnce(ProcessId, &mut SupervisorState) -> Result<ErlangResult, NonNull<ErlangException>>,
+{
+    let noproc = || exit_err(reason2("noproc", location));
+    let Some(pid) = gen::whereis(supervisor) else {
+        return ErlangResult::Err(noproc());
+    };
+    let result = gen::with_state(pid, |behaviour| match behaviour {
+        Behaviour::Supervisor(mut state) => {
+            let result = fun(pid, &mut state);
+            (Outcome::Continue(Behaviour::Supervisor(state)), result)
+        }
+        behaviour => (Outcome::Continue(behaviour), Err(noproc())),
+    });
+    match result {
+        Ok(Ok(result)) => result,
+        Ok(Err(exception)) => ErlangResult::Err(exception),
+        Err(Unavailable::NoProc) => ErlangResult::Err(noproc()),
+        Err(Unavailable::Busy) => ErlangResult::Err(exit_err(reason2("calling_self", location))),
+    }
+}
+
+pub(crate) fn handle(pid: ProcessId, mut state: SupervisorState, message: Message) -> Outcome {
+    let Message::Exit { from, reason } = message else {
+        // Supervisors do not handle any other messages
+        return Outcome::Continue(Behaviour::Supervisor(state));
+    };
+    let Some(index) = state
+        .children
+        .iter()
+        .position(|child| child.pid == Some(from))
+    else {
+        return Outcome::Continue(Behaviour::Supervisor(state));
+    };
+
+    let child = &mut state.children[index];
+    child.pid = None;
+    let restart = match child.spec.restart {
+        Restart::Permanent => true,
+        Restart::Transient => !gen::is_normal_exit(reason),
+        Restart::Temporary => false,
+    };
+    if !restart {
+        if state.template.is_some() || child.spec.restart == Restart::Temporary {
+            state.children.remove(index);
+        }
+        return Outcome::Continue(Behaviour::Supervisor(state));
+    }
+
+    match restart_children(pid, &mut state, index) {
+        Ok(()) => Outcome::Continue(Behaviour::Supervisor(state)),
+        Err(reason) => {
+            terminate_children(&mut state.children);
+            Outcome::Stop(reason, Behaviour::Supervisor(state))
+        }
+    }
+}
+
+/// Restarts the child at `index`, and any siblings required by the restart strategy
+///
+/// Returns the exit reason of the supervisor if the maximum restart intensity is reached.
+fn restart_children(
+    pid: ProcessId,
+    state: &mut SupervisorState,
+    index: usize,
+) -> Result<(), OpaqueTerm> {
+    let first = match state.strategy {
+        Strategy::OneForOne | Strategy::SimpleOneForOne => index,
+        Strategy::OneForAll => 0,
+        Strategy::RestForOne => index,
+    };
+    let last = match state.strategy {
+        Strategy::OneForOne | Strategy::SimpleOneForOne => index,
+        Strategy::OneForAll | Strategy::RestForOne => state.children.len() - 1,
+    };
+    terminate_children(&mut state.children[first..=last]);
+
+    let mut next = first;
+    while next <= last {
+        let now = crate::sim::now();
+        state.restarts.push_back(now);
+        while let Some(oldest) = state.restarts.front() {
+            if now.duration_since(*oldest) <= state.period {
+                break;
+            }
+            state.restarts.pop_front();
+        }
+        if state.restarts.len() > state.intensity {
+            return Err(atom("shutdown").into());
+        }
+
+        let child = &mut state.children[next];
+        match start_child_spec(pid, &child.spec) {
+            Ok(started) => {
+                child.pid = started;
+                next += 1;
+            }
+            // A failed restart counts against the restart intensity, and is retried
+            Err(_) => continue,
+        }
+    }
+    Ok(())
+}
+
+/// Terminates the running children among `children`, in reverse start order
+fn terminate_children(children: &mut [Child]) {
+    for child in children.iter_mut().rev() {
+        if let Some(pid) = child.pid.take() {
+            gen::shutdown(pid, atom("shutdown").into());
+        }
+    }
+}
+
+/// Calls the start function of a child, returning its pid, or `None` if it returned `ignore`
+fn start_child_spec(
+    supervisor: ProcessId,
+    spec: &ChildSpec,
+) -> Result<Option<ProcessId>, OpaqueTerm> {
+    let result = gen::with_parent(supervisor, || {
+        call_if_exported(spec.module, spec.function.as_str(), spec.args.as_slice())
+    });
+    let result = match result {
+        Ok(Some(result)) => result,
+        Ok(None) => {
+            let mfa = with_process(|proc| {
+                let args = make_list(proc, spec.args.as_slice());
+                make_tuple(proc, &[spec.module.into(), spec.function.into(), args])
+            });
+            return Err(make_global(reason2("undef", mfa)));
+        }
+        Err(exception) => return Err(gen::exit_reason(exception)),
+    };
+    if is_atom(result, "ignore") {
+        return Ok(None);
+    }
+    match tuple_elements(result) {
+        Some([tag, pid]) | Some([tag, pid, _]) if is_atom(*tag, "ok") => match (*pid).into() {
+            Term::Pid(pid) => Ok(Some(pid.id())),
+            _ => Err(make_global(reason2("bad_return_value", result))),
+        },
+        Some([tag, reason]) if is_atom(*tag, "error") => Err(make_global(*reason)),
+        _ => Err(make_global(reason2("bad_return_value", result))),
+    }
+}
+
+pub(crate) fn terminate(
+    mut state: SupervisorState,
+    _reason: OpaqueTerm,
+) -> Result<(), NonNull<ErlangException>> {
+    terminate_children(&mut state.children);
+    Ok(())
+}
+
+impl SupervisorState {
+    fn position(&self, id: OpaqueTerm) -> Option<usize> {
+        self.children
+            .iter()
+            .position(|child| equals(child.spec.id, id))
+    }
+
+    fn find(&self, id: OpaqueTerm) -> Option<&Child> {
+        self.position(id).map(|index| &self.children[index])
+    }
+}
+
+/// Returns `{ok, Pid}`, or `{ok, undefined}` for a child which returned `ignore`
+fn started(pid: Option<ProcessId>) -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        let pid = match pid {
+            Some(pid) => make_pid(proc, pid),
+            None => atom("undefined").into(),
+        };
+        make_tuple(proc, &[atoms::Ok.into(), pid])
+    }))
+}
+
+/// Parses supervisor flags, either as a map or a `{Strategy, Intensity, Period}` tuple
+fn parse_flags(flags: OpaqueTerm) -> Option<(Strategy, usize, Duration)> {
+    let (strategy, intensity, period) = match flags.into() {
+        Term::Map(map) => (
+            map_get(&map, "strategy").unwrap_or_else(|| atom("one_for_one").into()),
+            map_get(&map, "intensity").unwrap_or_else(|| 1i64.try_into().unwrap()),
+            map_get(&map, "period").unwrap_or_else(|| 5i64.try_into().unwrap()),
+        ),
+        _ => match tuple_elements(flags)? {
+            [strategy, intensity, period] => (*strategy, *intensity, *period),
+            _ => return None,
+        },
+    };
+    let strategy = match strategy.into() {
+        Term::Atom(a) => match a.as_str() {
+            "one_for_one" => Strategy::OneForOne,
+            "one_for_all" => Strategy::OneForAll,
+            "rest_for_one" => Strategy::RestForOne,
+            "simple_one_for_one" => Strategy::SimpleOneForOne,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let (Term::Int(intensity), Term::Int(period)) = (intensity.into(), period.into()) else {
+        return None;
+    };
+    if intensity < 0 || period <= 0 {
+        return None;
+    }
+    Some((
+        strategy,
+        intensity as usize,
+        Duration::from_secs(period as u64),
+    ))
+}
+
+fn parse_specs(specs: OpaqueTerm) -> Result<Vec<ChildSpec>, OpaqueTerm> {
+    let Some(specs) = list_to_vec(specs) else {
+        return Err(make_global(reason2("invalid_child_specs", specs)));
+    };
+    let specs = specs.into_iter().map(parse_spec).try_collect::<Vec<_>>()?;
+    for (i, spec) in specs.iter().enumerate() {
+        if specs[..i].iter().any(|other| equals(other.id, spec.id)) {
+            return Err(make_global(reason2("duplicate_child_name", spec.id)));
+        }
+    }
+    Ok(specs)
+}
+
+/// Parses a child spec, either as a map or a `{Id, Start, Restart, Shutdown, Type, Modules}` tuple
+fn parse_spec(spec: OpaqueTerm) -> Result<ChildSpec, OpaqueTerm> {
+    let invalid = |what: &str, value: OpaqueTerm| make_global(reason2(what, value));
+    let (id, start, restart, ty, modules) = match spec.into() {
+        Term::Map(map) => {
+            let id = map_get(&map, "id").ok_or_else(|| invalid("missing_id", spec))?;
+            let start = map_get(&map, "start").ok_or_else(|| invalid("missing_start", spec))?;
+            (
+                id,
+                start,
+                map_get(&map, "restart").unwrap_or_else(|| atom("permanent").into()),
+                map_get(&map, "type").unwrap_or_else(|| atom("worker").into()),
+                map_get(&map, "modules"),
+            )
+        }
+        _ => match tuple_elements(spec) {
+            Some([id, start, restart, _shutdown, ty, modules]) => {
+                (*id, *start, *restart, *ty, Some(*modules))
+            }
+            _ => return Err(invalid("invalid_child_spec", spec)),
+        },
+    };
+
+    let (module, function, args) = match tuple_elements(start) {
+        Some([m, f, a]) => match ((*m).into(), (*f).into(), list_to_vec(*a)) {
+            (Term::Atom(m), Term::Atom(f), Some(a)) => (m, f, a),
+            _ => return Err(invalid("invalid_mfa", start)),
+        },
+        _ => return Err(invalid("invalid_mfa", start)),
+    };
+    let restart = match restart.into() {
+        Term::Atom(a) if a.as_str() == "permanent" => Restart::Permanent,
+        Term::Atom(a) if a.as_str() == "transient" => Restart::Transient,
+        Term::Atom(a) if a.as_str() == "temporary" => Restart::Temporary,
+        _ => return Err(invalid("invalid_restart_type", restart)),
+    };
+    let supervisor = match ty.into() {
+        Term::Atom(a) if a.as_str() == "worker" => false,
+        Term::Atom(a) if a.as_str() == "supervisor" => true,
+        _ => return Err(invalid("invalid_child_type", ty)),
+    };
+    let modules = match modules {
+        Some(modules) => make_global(modules),
+        None => make_global(with_process(|proc| make_list(proc, &[module.into()]))),
+    };
+
+    Ok(ChildSpec {
+        id: make_global(id),
+        module,
+        function,
+        args: args.into_iter().map(make_global).collect(),
+        restart,
+        supervisor,
+        modules,
+    })
+}
+
+fn map_get(map: &Map, key: &str) -> Option<OpaqueTerm> {
+    map.get(atom(key)).map(|value| value.into())
+}
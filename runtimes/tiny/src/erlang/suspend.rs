@@ -0,0 +1,206 @@
+//! The suspension of processes and servers, by `erlang:suspend_process/1,2` and
+//! `erlang:resume_process/1`, as debuggers do, and by `sys:suspend/1,2` and `sys:resume/1,2`, see
+//! `super::sys`.
+//!
+//! Something is suspended for as long as anyone has suspended it more times than they have resumed
+//! it. Each suspender has a count of its own, which only it can decrement, and which is dropped
+//! when it exits, as in ERTS. `sys` is a suspender of its own, which suspends a server at most
+//! once, as in OTP.
+//!
+//! A suspended process is kept out of the run queue, see `crate::scheduler`, and carries on from
+//! where it was once resumed. A suspended server handles no messages, which wait in its mailbox, so
+//! a request to it fails with `timeout`, see `super::gen`. Its timers keep running, and a timeout
+//! which expires meanwhile is handled once the server is resumed, after the messages which arrived
+//! before it expired, so that an event timeout is only cancelled by the messages which arrived in
+//! time to do so. Processes have no mailboxes here, so servers are the only ones with receive
+//! timeouts to account for.
+//!
+//! Nothing can suspend itself synchronously, as it could never report that it had been suspended:
+//! `suspend_process(self())` raises `badarg`, as does suspending a server which is executing a
+//! callback, unless given `asynchronous`, in which case the process is suspended once it next
+//! yields, and the server once the callback returns. A server resumed because its suspender exited
+//! handles its mailbox once it is next delivered a message, or one of its timers expires.
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::gen;
+use super::process::local_pid;
+use super::util::*;
+
+/// The suspenders of each process or server which is suspended, with the number of times each has
+/// suspended it
+static SUSPENSIONS: Mutex<BTreeMap<ProcessId, BTreeMap<Suspender, usize>>> =
+    Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Suspender {
+    Process(ProcessId),
+    Sys,
+}
+
+/// Suspends `Suspendee`, returning true
+#[export_name = "erlang:suspend_process/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn suspend_process1(suspendee: OpaqueTerm) -> ErlangResult {
+    suspend_process2(suspendee, Term::Nil.into())
+}
+
+/// Suspends `Suspendee`, returning true, or false if it is already suspended by the caller and
+/// `unless_suspending` is given
+///
+/// The options supported are `asynchronous` and `unless_suspending`. As the caller has no mailbox,
+/// `{asynchronous, ReplyTag}` is not.
+#[export_name = "erlang:suspend_process/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn suspend_process2(
+    suspendee: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(pid), Some(options)) = (local_pid(suspendee), list_to_vec(options)) else {
+        return super::badarg(Trace::capture());
+    };
+    let mut asynchronous = false;
+    let mut unless_suspending = false;
+    for option in options {
+        match option.into() {
+            Term::Atom(a) if a.as_str() == "asynchronous" => asynchronous = true,
+            Term::Atom(a) if a.as_str() == "unless_suspending" => unless_suspending = true,
+            _ => return super::badarg(Trace::capture()),
+        }
+    }
+    let suspender = with_process(|proc| proc.pid());
+    let is_self = if gen::module(pid).is_some() {
+        gen::is_busy(pid)
+    } else if scheduler::with_current(|scheduler| scheduler.process(pid)).is_some() {
+        pid == suspender
+    } else {
+        return super::badarg(Trace::capture());
+    };
+    if is_self && !asynchronous {
+        return super::badarg(Trace::capture());
+    }
+    if !suspend(pid, Suspender::Process(suspender), unless_suspending) {
+        return ErlangResult::Ok(false.into());
+    }
+    if gen::module(pid).is_none() {
+        scheduler::with_current(|scheduler| scheduler.suspend(pid));
+    }
+    ErlangResult::Ok(true.into())
+}
+
+/// Undoes one suspension of `Suspendee` by the caller, raising `badarg` if it has none
+#[export_name = "erlang:resume_process/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn resume_process1(suspendee: OpaqueTerm) -> ErlangResult {
+    let Some(pid) = local_pid(suspendee) else {
+        return super::badarg(Trace::capture());
+    };
+    let suspender = with_process(|proc| proc.pid());
+    match resume(pid, Suspender::Process(suspender)) {
+        Some(resumed) => {
+            if resumed {
+                wake(pid);
+            }
+            ErlangResult::Ok(true.into())
+        }
+        None => super::badarg(Trace::capture()),
+    }
+}
+
+/// Returns true if `pid` is suspended
+pub(crate) fn is_suspended(pid: ProcessId) -> bool {
+    SUSPENSIONS.lock().unwrap().contains_key(&pid)
+}
+
+/// Returns the processes and servers `pid` has suspended, with the number of times it has
+/// suspended each
+pub(crate) fn suspending(pid: ProcessId) -> Vec<(ProcessId, usize)> {
+    SUSPENSIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|(suspendee, counts)| {
+            let count = counts.get(&Suspender::Process(pid))?;
+            Some((*suspendee, *count))
+        })
+        .collect()
+}
+
+/// Suspends `pid` on behalf of `suspender`, returning false if `suspender` already has, and the
+/// suspension is either `unless_suspending`, or by `sys`, which only suspends once
+pub(crate) fn suspend(pid: ProcessId, suspender: Suspender, unless_suspending: bool) -> bool {
+    let mut suspensions = SUSPENSIONS.lock().unwrap();
+    let count = suspensions
+        .entry(pid)
+        .or_default()
+        .entry(suspender)
+        .or_default();
+    if *count > 0 && (unless_suspending || suspender == Suspender::Sys) {
+        return false;
+    }
+    *count += 1;
+    true
+}
+
+/// Undoes one suspension of `pid` by `suspender`, returning whether `pid` has been resumed, or
+/// `None` if `suspender` had not suspended it
+///
+/// The caller is expected to [`wake`] `pid` once it has been resumed.
+pub(crate) fn resume(pid: ProcessId, suspender: Suspender) -> Option<bool> {
+    let mut suspensions = SUSPENSIONS.lock().unwrap();
+    let counts = suspensions.get_mut(&pid)?;
+    let count = counts.get_mut(&suspender)?;
+    *count -= 1;
+    if *count == 0 {
+        counts.remove(&suspender);
+    }
+    if !counts.is_empty() {
+        return Some(false);
+    }
+    suspensions.remove(&pid);
+    Some(true)
+}
+
+/// Puts `pid`, which has been resumed, back to work
+pub(crate) fn wake(pid: ProcessId) {
+    if gen::module(pid).is_some() {
+        gen::resume(pid);
+    } else {
+        scheduler::with_current(|scheduler| scheduler.resume(pid));
+    }
+}
+
+/// Drops the suspensions of `pid`, which has exited, and those it held on others, resuming any
+/// processes which are no longer suspended
+///
+/// This is called when a server terminates, and by the scheduler outside of any process when a
+/// process exits, so servers are not put back to work here, see the module documentation.
+pub(crate) fn exit(pid: ProcessId) {
+    let resumed = {
+        let mut suspensions = SUSPENSIONS.lock().unwrap();
+        if suspensions.is_empty() {
+            return;
+        }
+        suspensions.remove(&pid);
+        let mut resumed = vec![];
+        suspensions.retain(|suspendee, counts| {
+            counts.remove(&Suspender::Process(pid));
+            if counts.is_empty() {
+                resumed.push(*suspendee);
+            }
+            !counts.is_empty()
+        });
+        resumed
+    };
+    for suspendee in resumed {
+        if gen::module(suspendee).is_none() {
+            scheduler::with_current(|scheduler| scheduler.resume(suspendee));
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! The parts of the `sys` module which apply to the in-process servers of `super::gen`,
+//! `suspend/1,2` and `resume/1,2`.
+//!
+//! A server suspended with `suspend` handles no messages until it is resumed with `resume`, see
+//! `super::suspend`. As in OTP, suspending a server which is already suspended by `sys` has no
+//! further effect, and a server cannot suspend itself, which fails with `calling_self` here rather
+//! than deadlocking. As requests are handled synchronously, timeouts are only validated.
+use std::ptr::NonNull;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::badarg;
+use super::gen;
+use super::suspend::{self, Suspender};
+use super::util::*;
+
+#[export_name = "sys:suspend/1"]
+pub extern "C-unwind" fn suspend1(name: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("sys", "suspend", &[name]);
+    suspend(name, location)
+}
+
+#[export_name = "sys:suspend/2"]
+pub extern "C-unwind" fn suspend2(name: OpaqueTerm, timeout: OpaqueTerm) -> ErlangResult {
+    if timeout_ms(timeout).is_none() {
+        return badarg(Trace::capture());
+    }
+    let location = gen::location("sys", "suspend", &[name, timeout]);
+    suspend(name, location)
+}
+
+#[export_name = "sys:resume/1"]
+pub extern "C-unwind" fn resume1(name: OpaqueTerm) -> ErlangResult {
+    let location = gen::location("sys", "resume", &[name]);
+    resume(name, location)
+}
+
+#[export_name = "sys:resume/2"]
+pub extern "C-unwind" fn resume2(name: OpaqueTerm, timeout: OpaqueTerm) -> ErlangResult {
+    if timeout_ms(timeout).is_none() {
+        return badarg(Trace::capture());
+    }
+    let location = gen::location("sys", "resume", &[name, timeout]);
+    resume(name, location)
+}
+
+fn suspend(name: OpaqueTerm, location: OpaqueTerm) -> ErlangResult {
+    let pid = server(name, location)?;
+    suspend::suspend(pid, Suspender::Sys, false);
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+fn resume(name: OpaqueTerm, location: OpaqueTerm) -> ErlangResult {
+    let pid = server(name, location)?;
+    if let Some(true) = suspend::resume(pid, Suspender::Sys) {
+        suspend::wake(pid);
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Returns the pid of the server `name`, which must not be executing a callback
+fn server(name: OpaqueTerm, location: OpaqueTerm) -> Result<ProcessId, NonNull<ErlangException>> {
+    let Some(pid) = gen::whereis(name) else {
+        return Err(exit_err(reason2("noproc", location)));
+    };
+    if gen::is_busy(pid) {
+        return Err(exit_err(reason2("calling_self", location)));
+    }
+    Ok(pid)
+}
@@ -0,0 +1,226 @@
+//! `erlang:system_info/1`, for the items this runtime can report on.
+//!
+//! Besides the time items, `time_warp_mode`, `time_offset`, which is `preliminary`, `final` or
+//! `volatile`, and `time_correction`, which is always `false`, see `crate::time`, only the
+//! allocator items are supported so far. `allocator` returns
+//! `{Allocator, [], Features, Settings}`, where `Allocator` is the name of the allocator backing
+//! the system, see `firefly_alloc::allocators::backing`, `Features` the names of the allocators of
+//! each type of memory, and `Settings` their options. `{allocator, Name}` returns the statistics of
+//! the allocator named `Name`, in the same form as ERTS, so that tools such as `recon_alloc` can
+//! read them, or `false` if there is no such allocator. There is a single instance of each
+//! allocator, shared by all schedulers.
+//!
+//! `firefly_build` returns the metadata of the build, for bug reports and provenance checks, as
+//! `[{runtime, Runtime}, {compiler, Compiler}]`. `Runtime` is the `version`, `commit_hash`,
+//! `commit_date`, `target` and `profile` of the runtime, as strings, and the cargo `features` it
+//! was built with, as atoms, see `firefly_crt::build_info`. `Compiler` is what the compiler
+//! embedded in the executable, see `firefly_session::build_info`, or `undefined` if nothing was.
+//!
+//! `erlang:system_flag/2` is here too, for the flags of the statistics this runtime keeps:
+//! `microstate_accounting`, which is `true`, `false`, or `reset` to zero the counters, and
+//! `scheduler_wall_time`, which is accepted but has no effect, as it is always measured, see
+//! `super::statistics`. Both return the previous value of the flag. `time_offset` may be set to
+//! `finalize`, returning the state of the offset beforehand.
+use firefly_alloc::allocators::backing;
+use firefly_alloc::allocators::carriers::{AllocatorType, CarrierStats, Options, Stats};
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::time;
+
+use super::util::*;
+
+/// The version of the allocators, reported in place of the versions of `erts_alloc`
+const VERSION: &str = "0.1.0";
+
+/// Returns information about the system, as selected by `Item`
+#[export_name = "erlang:system_info/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_info1(item: OpaqueTerm) -> ErlangResult {
+    let info = with_process(|proc| {
+        if is_atom(item, "allocator") {
+            return Some(allocators(proc));
+        }
+        if is_atom(item, "time_warp_mode") {
+            return Some(atom(time::warp_mode().name()).into());
+        }
+        if is_atom(item, "time_offset") {
+            return Some(atom(time::offset_state().name()).into());
+        }
+        if is_atom(item, "time_correction") {
+            return Some(false.into());
+        }
+        if is_atom(item, "firefly_build") {
+            return Some(firefly_build(proc));
+        }
+        match tuple_elements(item) {
+            Some([tag, name]) if is_atom(*tag, "allocator") => {
+                let Term::Atom(name) = (*name).into() else { return None };
+                Some(match AllocatorType::from_name(name.as_str()) {
+                    Some(ty) => allocator(proc, ty),
+                    None => false.into(),
+                })
+            }
+            _ => None,
+        }
+    });
+    match info {
+        Some(info) => ErlangResult::Ok(info),
+        None => super::badarg(Trace::capture()),
+    }
+}
+
+/// Sets the system flag `Flag` to `Value`, returning its previous value
+#[export_name = "erlang:system_flag/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_flag2(flag: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(flag) = flag.into() else { return super::badarg(Trace::capture()) };
+    if flag.as_str() == "time_offset" && is_atom(value, "finalize") {
+        return ErlangResult::Ok(atom(time::finalize_offset().name()).into());
+    }
+    let previous = match (flag.as_str(), value.into()) {
+        ("microstate_accounting", Term::Atom(value)) if value.as_str() == "reset" => {
+            scheduler::with_current(|scheduler| {
+                scheduler.msacc().reset();
+                scheduler.msacc().is_enabled()
+            })
+        }
+        ("microstate_accounting", Term::Bool(enable)) => {
+            scheduler::with_current(|scheduler| scheduler.msacc().enable(enable))
+        }
+        ("scheduler_wall_time", Term::Bool(_)) => true,
+        _ => return super::badarg(Trace::capture()),
+    };
+    ErlangResult::Ok(previous.into())
+}
+
+/// Returns the metadata of the builds of the runtime and of the executable
+fn firefly_build(proc: &Process) -> OpaqueTerm {
+    use firefly_crt::build_info;
+
+    let features = [
+        ("entry", cfg!(feature = "entry")),
+        ("jemalloc", cfg!(feature = "jemalloc")),
+        ("mimalloc", cfg!(feature = "mimalloc")),
+        ("test", cfg!(feature = "test")),
+    ];
+    let features = features
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| atom(name).into())
+        .collect::<Vec<_>>();
+    let runtime = [
+        ("version", charlist(proc, build_info::VERSION)),
+        ("commit_hash", charlist(proc, build_info::COMMIT_HASH)),
+        ("commit_date", charlist(proc, build_info::COMMIT_DATE)),
+        ("target", charlist(proc, build_info::TARGET)),
+        ("profile", charlist(proc, build_info::PROFILE)),
+        ("features", make_list(proc, &features)),
+    ];
+    let runtime = runtime
+        .iter()
+        .map(|(key, value)| make_tuple(proc, &[atom(key).into(), *value]))
+        .collect::<Vec<_>>();
+    let compiler =
+        super::application::bundled_build_info().unwrap_or_else(|| atom("undefined").into());
+    let items = [
+        make_tuple(proc, &[atom("runtime").into(), make_list(proc, &runtime)]),
+        make_tuple(proc, &[atom("compiler").into(), compiler]),
+    ];
+    make_list(proc, &items)
+}
+
+/// Returns `{Allocator, [], Features, Settings}`
+fn allocators(proc: &Process) -> OpaqueTerm {
+    let features = AllocatorType::ALL.map(|ty| atom(ty.name()).into());
+    let settings = AllocatorType::ALL.map(|ty| {
+        let mut options = vec![make_tuple(proc, &[atom("e").into(), true.into()])];
+        options.extend(options_list(proc, ty.allocator().options()));
+        let options = make_list(proc, options.as_slice());
+        make_tuple(proc, &[atom(ty.name()).into(), options])
+    });
+    make_tuple(
+        proc,
+        &[
+            atom(backing::NAME).into(),
+            OpaqueTerm::NIL,
+            make_list(proc, &features),
+            make_list(proc, &settings),
+        ],
+    )
+}
+
+/// Returns `[{instance, 0, Info}]` for the allocator of type `ty`
+fn allocator(proc: &Process, ty: AllocatorType) -> OpaqueTerm {
+    let allocator = ty.allocator();
+    let Stats { mbcs, sbcs, calls } = allocator.stats();
+    let version = charlist(proc, VERSION);
+    let versions = make_tuple(proc, &[atom("versions").into(), version, version]);
+    let options = options_list(proc, allocator.options());
+    let options = make_list(proc, options.as_slice());
+    let options = make_tuple(proc, &[atom("options").into(), options]);
+    let mbcs = carriers(proc, "mbcs", mbcs);
+    let sbcs = carriers(proc, "sbcs", sbcs);
+    // The name of each call is prefixed by the type of the allocator, e.g. `eheap_alloc`
+    let prefix = ty.name().trim_end_matches("_alloc");
+    let calls = [
+        ("alloc", calls.alloc),
+        ("free", calls.free),
+        ("realloc", calls.realloc),
+    ]
+    .map(|(call, count)| {
+        let name = atom(&format!("{}_{}", prefix, call)).into();
+        make_tuple(proc, &[name, make_size(proc, 0), make_count(proc, count)])
+    });
+    let calls = make_tuple(proc, &[atom("calls").into(), make_list(proc, &calls)]);
+    let info = make_list(proc, &[versions, options, mbcs, sbcs, calls]);
+    let instance = make_tuple(proc, &[atom("instance").into(), make_size(proc, 0), info]);
+    make_list(proc, &[instance])
+}
+
+/// Returns the options of an allocator as `[{as, Strategy}, {sbct, Bytes}, {lmbcs, Bytes}]`
+fn options_list(proc: &Process, options: Options) -> Vec<OpaqueTerm> {
+    [
+        ("as", atom(options.strategy.name()).into()),
+        ("sbct", make_size(proc, options.sbc_threshold)),
+        ("lmbcs", make_size(proc, options.mbc_size)),
+    ]
+    .map(|(name, value)| make_tuple(proc, &[atom(name).into(), value]))
+    .to_vec()
+}
+
+/// Returns `{Kind, [{blocks, Cur, Max, Max}, ...]}` for the carriers and blocks of one kind
+///
+/// ERTS reports the largest value both since the last call and ever, and these are always the
+/// same here, as the largest values are never reset.
+fn carriers(proc: &Process, kind: &str, stats: CarrierStats) -> OpaqueTerm {
+    let values = [
+        ("blocks", stats.blocks, stats.max_blocks),
+        ("blocks_size", stats.blocks_size, stats.max_blocks_size),
+        ("carriers", stats.carriers, stats.max_carriers),
+        (
+            "carriers_size",
+            stats.carriers_size,
+            stats.max_carriers_size,
+        ),
+    ]
+    .map(|(name, current, max)| {
+        let max = make_size(proc, max);
+        make_tuple(
+            proc,
+            &[atom(name).into(), make_size(proc, current), max, max],
+        )
+    });
+    make_tuple(proc, &[atom(kind).into(), make_list(proc, &values)])
+}
+
+fn make_size(proc: &Process, size: usize) -> OpaqueTerm {
+    size.into_term(proc).unwrap().into()
+}
+
+fn make_count(proc: &Process, count: u64) -> OpaqueTerm {
+    count.into_term(proc).unwrap().into()
+}
@@ -0,0 +1,100 @@
+//! The BIFs which set the system monitor, i.e. the process notified of events concerning the system
+//! as a whole, see `crate::memory`.
+use std::alloc::Layout;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_alloc::fragment::HeapFragment;
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::gen::{self, Message};
+use super::util::*;
+
+/// The process set by `erlang:system_monitor/2`, and the options it was given
+static MONITOR: OnceLock<Mutex<Option<(ProcessId, OpaqueTerm)>>> = OnceLock::new();
+
+/// Sends a `memory_high` event to the system monitor, if it asked for them
+pub(crate) fn memory_high(pid: ProcessId, allocated: usize, limit: usize) {
+    let Some((monitor, options)) = *monitor() else { return; };
+    let wanted = list_to_vec(options)
+        .map(|options| options.iter().any(|option| is_atom(*option, "memory_high")))
+        .unwrap_or(false);
+    if !wanted {
+        return;
+    }
+
+    // The event is allocated in a heap fragment of its own, as it is sent from the scheduler
+    let layout = Layout::from_size_align(256, 8).unwrap();
+    let Ok(fragment) = HeapFragment::new(layout, None) else { return; };
+    let heap = unsafe { fragment.as_ref() };
+    let event = GcBox::new_in(Pid::Local { id: pid }, heap).and_then(|pid| {
+        let pid = Term::Pid(pid);
+        firefly_rt::term!(
+            heap,
+            {monitor, (pid), memory_high, [{allocated, (allocated)}, {limit, (limit)}]}
+        )
+    });
+    let Ok(event) = event else { return; };
+    if monitor == scheduler::with_current(|scheduler| scheduler.root_pid()) {
+        crate::runtime::deliver(event.into());
+    } else {
+        gen::cast(monitor, Message::Info(event.into()));
+    }
+}
+
+/// Returns the current system monitor as `{MonitorPid, Options}`, or `undefined`
+#[export_name = "erlang:system_monitor/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_monitor0() -> ErlangResult {
+    let monitor = *monitor();
+    ErlangResult::Ok(make_monitor(monitor))
+}
+
+/// Sets the system monitor from `{MonitorPid, Options}`, or clears it if `undefined`, returning the
+/// previous one
+#[export_name = "erlang:system_monitor/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_monitor1(settings: OpaqueTerm) -> ErlangResult {
+    if is_atom(settings, "undefined") {
+        let previous = monitor().take();
+        return ErlangResult::Ok(make_monitor(previous));
+    }
+    match tuple_elements(settings) {
+        Some([pid, options]) => system_monitor2(*pid, *options),
+        _ => super::badarg(Trace::capture()),
+    }
+}
+
+/// Sets `monitor` as the process notified of the events in `options`, returning the previous
+/// monitor
+///
+/// Only `memory_high` events are raised by this runtime. Other options are accepted, but as they
+/// concern garbage collection, ports and scheduling, which are not monitored, have no effect.
+#[export_name = "erlang:system_monitor/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_monitor2(monitor: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let (Term::Pid(pid), Some(_)) = (monitor.into(), list_to_vec(options)) else {
+        return super::badarg(Trace::capture());
+    };
+    let settings = Some((pid.id(), make_global(options)));
+    let previous = std::mem::replace(&mut *self::monitor(), settings);
+    ErlangResult::Ok(make_monitor(previous))
+}
+
+fn make_monitor(monitor: Option<(ProcessId, OpaqueTerm)>) -> OpaqueTerm {
+    match monitor {
+        None => atoms::Undefined.into(),
+        Some((pid, options)) => with_process(|proc| {
+            let pid = make_pid(proc, pid);
+            make_tuple(proc, &[pid, options])
+        }),
+    }
+}
+
+fn monitor() -> MutexGuard<'static, Option<(ProcessId, OpaqueTerm)>> {
+    MONITOR.get_or_init(|| Mutex::new(None)).lock().unwrap()
+}
@@ -0,0 +1,162 @@
+//! The time BIFs of the `erlang` module, reading the clocks in `crate::time`.
+//!
+//! `monotonic_time/0,1`, `system_time/0,1` and `time_offset/0,1` return Erlang monotonic time,
+//! Erlang system time and the offset between them, in `native` time units, i.e. nanoseconds, or in
+//! `Unit`, which is a time unit name or a number of parts per second, as is accepted by
+//! `convert_time_unit/3`. `timestamp/0` is system time as `{MegaSecs, Secs, MicroSecs}`.
+//!
+//! `unique_integer/0,1` returns integers unique to the runtime instance, which are also strictly
+//! monotonic here, whether or not `monotonic` is given, and positive, whether or not `positive` is.
+//! `now/0` returns a timestamp which is unique and strictly monotonic, as it has always done.
+//!
+//! Changes of the time offset cannot be monitored with `erlang:monitor(time_offset,
+//! clock_service)`, as there are no monitors in this runtime.
+use firefly_number::ToPrimitive;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::time;
+
+use super::util::*;
+
+/// Returns the current monotonic time in `native` time units
+#[export_name = "erlang:monotonic_time/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn monotonic_time0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        make_i128(proc, time::monotonic() as i128)
+    }))
+}
+
+/// Returns the current monotonic time in `Unit`
+#[export_name = "erlang:monotonic_time/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn monotonic_time1(unit: OpaqueTerm) -> ErlangResult {
+    in_unit(time::monotonic() as i128, unit)
+}
+
+/// Returns the current system time in `native` time units
+#[export_name = "erlang:system_time/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_time0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| make_i128(proc, time::system() as i128)))
+}
+
+/// Returns the current system time in `Unit`
+#[export_name = "erlang:system_time/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_time1(unit: OpaqueTerm) -> ErlangResult {
+    in_unit(time::system() as i128, unit)
+}
+
+/// Returns the current time offset in `native` time units
+#[export_name = "erlang:time_offset/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn time_offset0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| make_i128(proc, time::offset() as i128)))
+}
+
+/// Returns the current time offset in `Unit`
+#[export_name = "erlang:time_offset/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn time_offset1(unit: OpaqueTerm) -> ErlangResult {
+    in_unit(time::offset() as i128, unit)
+}
+
+/// Returns the current system time as `{MegaSecs, Secs, MicroSecs}`
+#[export_name = "erlang:timestamp/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn timestamp0() -> ErlangResult {
+    let micros = time::convert(time::system() as i128, time::NATIVE, 1_000_000);
+    ErlangResult::Ok(with_process(|proc| make_timestamp(proc, micros)))
+}
+
+/// Returns a timestamp as `{MegaSecs, Secs, MicroSecs}`, greater than any returned before
+#[export_name = "erlang:now/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn now0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        make_timestamp(proc, time::now() as i128)
+    }))
+}
+
+/// Converts `Time` from `FromUnit` to `ToUnit`, rounding towards negative infinity
+#[export_name = "erlang:convert_time_unit/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn convert_time_unit3(
+    time: OpaqueTerm,
+    from: OpaqueTerm,
+    to: OpaqueTerm,
+) -> ErlangResult {
+    let time = match time.into() {
+        Term::Int(time) => time as i128,
+        Term::BigInt(time) => match time.to_i128() {
+            Some(time) => time,
+            None => return super::badarg(Trace::capture()),
+        },
+        _ => return super::badarg(Trace::capture()),
+    };
+    let (Some(from), Some(to)) = (parse_unit(from), parse_unit(to)) else {
+        return super::badarg(Trace::capture());
+    };
+    let Some(scaled) = time.checked_mul(to as i128) else {
+        return super::badarg(Trace::capture());
+    };
+    let converted = scaled.div_euclid(from as i128);
+    ErlangResult::Ok(with_process(|proc| make_i128(proc, converted)))
+}
+
+/// Returns an integer unique to this runtime instance
+#[export_name = "erlang:unique_integer/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unique_integer0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| make_i128(proc, time::unique() as i128)))
+}
+
+/// Returns an integer unique to this runtime instance, given `[monotonic | positive]`
+#[export_name = "erlang:unique_integer/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unique_integer1(modifiers: OpaqueTerm) -> ErlangResult {
+    let Some(modifiers) = list_to_vec(modifiers) else {
+        return super::badarg(Trace::capture());
+    };
+    let valid = modifiers
+        .iter()
+        .all(|modifier| is_atom(*modifier, "monotonic") || is_atom(*modifier, "positive"));
+    if !valid {
+        return super::badarg(Trace::capture());
+    }
+    unique_integer0()
+}
+
+/// Returns the parts per second of the time unit `unit`
+pub(super) fn parse_unit(unit: OpaqueTerm) -> Option<u64> {
+    match unit.into() {
+        Term::Atom(unit) => time::unit(unit.as_str()),
+        Term::Int(parts) if parts > 0 => Some(parts as u64),
+        _ => None,
+    }
+}
+
+/// Returns `time`, in nanoseconds, in `unit`
+pub(super) fn in_unit(time: i128, unit: OpaqueTerm) -> ErlangResult {
+    let Some(parts) = parse_unit(unit) else {
+        return super::badarg(Trace::capture());
+    };
+    let time = time::convert(time, time::NATIVE, parts);
+    ErlangResult::Ok(with_process(|proc| make_i128(proc, time)))
+}
+
+pub(super) fn make_i128(proc: &Process, time: i128) -> OpaqueTerm {
+    time.into_term(proc).unwrap().into()
+}
+
+/// Returns `{MegaSecs, Secs, MicroSecs}` for a time in microseconds
+pub(super) fn make_timestamp(proc: &Process, micros: i128) -> OpaqueTerm {
+    let (seconds, micros) = (micros.div_euclid(1_000_000), micros.rem_euclid(1_000_000));
+    let (mega, seconds) = (seconds.div_euclid(1_000_000), seconds.rem_euclid(1_000_000));
+    let elements = [mega, seconds, micros].map(|part| make_i128(proc, part));
+    make_tuple(proc, &elements)
+}
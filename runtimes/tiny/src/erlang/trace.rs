@@ -0,0 +1,682 @@
+//! Process tracing, i.e. `erlang:trace/3` and `erlang:trace_pattern/2,3`.
+//!
+//! The events of a traced process are sent to its tracer as `{trace, Pid, Tag, ...}` messages, or
+//! `{trace_ts, Pid, Tag, ..., Timestamp}` with the `timestamp` or `monotonic_timestamp` flags. The
+//! events traced are chosen by the flags given to `trace/3`:
+//!
+//! * `send` - `{trace, Pid, send, Msg, To}` for each message sent with `!` or `erlang:send/2`
+//! * `'receive'` - `{trace, Pid, 'receive', Msg}` for each message received by a server or the
+//! root process, as only these have mailboxes here, see `super::gen`
+//! * `procs` - `{trace, Pid, spawn, Pid2, {M, F, Args}}` and `{trace, Pid2, spawned, Pid, {M, F,
+//! Args}}` when a process is spawned, and `{trace, Pid, exit, Reason}` when it exits. Links are not
+//! supported by this runtime, so there are no `link` or `unlink` events.
+//! * `garbage_collection` - `gc_major_start` and `gc_major_end`, with `[{heap_size, Words}]`, when
+//! the heap of the process is collected with `erlang:garbage_collect/0,1,2`
+//! * `call` - `{trace, Pid, call, {M, F, Args}}` for calls to functions with a trace pattern
+//! * `set_on_spawn` - processes spawned by the process inherit its flags and tracer
+//!
+//! The tracer is the caller, unless given as `{tracer, Pid | Port}`. Trace messages are sent to a
+//! port encoded in the external term format, as trace port drivers expect, and are written to the
+//! trace file of `dbg` the same way, see `super::dbg`.
+//!
+//! Trace patterns choose the functions whose calls are traced, with a match specification run
+//! against the list of arguments, see `firefly_rt::match_spec`. Its body may call `return_trace()`
+//! for a `{trace, Pid, return_from, {M, F, A}, Value}` message when the call returns,
+//! `exception_trace()` for an `{trace, Pid, exception_from, {M, F, A}, {Class, Reason}}` message if
+//! it raises as well, and `message(Term)` to add `Term` to the call message, or `message(false)` to
+//! suppress it.
+//!
+//! Calls are only seen where they pass through the runtime, i.e. calls made with `erlang:apply/3`,
+//! which includes the start of processes spawned with a module and function. Calls compiled as
+//! direct calls between functions are not traced, as the compiler does not instrument the entry of
+//! functions.
+use std::alloc::{AllocError, Layout};
+use std::collections::BTreeMap;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_alloc::fragment::HeapFragment;
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{self, ErlangResult, ModuleFunctionArity};
+use firefly_rt::match_spec::MatchSpec;
+use firefly_rt::term::__support as support;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::gen::{self, Message};
+use super::util::*;
+
+const CALL: u16 = 1 << 0;
+const SEND: u16 = 1 << 1;
+const RECEIVE: u16 = 1 << 2;
+const PROCS: u16 = 1 << 3;
+const GARBAGE_COLLECTION: u16 = 1 << 4;
+const TIMESTAMP: u16 = 1 << 5;
+const MONOTONIC_TIMESTAMP: u16 = 1 << 6;
+const SET_ON_SPAWN: u16 = 1 << 7;
+
+/// The flags accepted by `trace/3`, besides `all` and `{tracer, Tracer}`
+const FLAGS: &[(&str, u16)] = &[
+    ("call", CALL),
+    ("send", SEND),
+    ("receive", RECEIVE),
+    ("procs", PROCS),
+    ("garbage_collection", GARBAGE_COLLECTION),
+    ("timestamp", TIMESTAMP),
+    ("monotonic_timestamp", MONOTONIC_TIMESTAMP),
+    ("set_on_spawn", SET_ON_SPAWN),
+];
+
+/// The size of the heap fragment trace messages are built on, before they are copied to be sent
+const SCRATCH_SIZE: usize = 1024;
+
+/// Whether any process is traced, so that untraced events cost no more than reading this
+static TRACING: AtomicBool = AtomicBool::new(false);
+/// Set while the events of the current process are not traced, see `untraced`
+static SUPPRESSED: AtomicBool = AtomicBool::new(false);
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+/// Where trace messages are sent, see `super::seq_trace` for the system tracer
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(super) enum Tracer {
+    Process(ProcessId),
+    Port(PortId),
+    /// The trace file opened by `dbg:tracer(port, dbg:trace_port(file, Filename))`
+    File,
+}
+
+/// The flags of a traced process, and where its trace messages are sent
+#[derive(Copy, Clone)]
+struct Tracee {
+    flags: u16,
+    tracer: Tracer,
+}
+impl Tracee {
+    fn has(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// The functions `{Module, Function, Arity}` whose calls are traced, where `None` is `'_'`
+struct TracePattern {
+    module: Option<Atom>,
+    function: Option<Atom>,
+    arity: Option<u8>,
+    spec: Option<MatchSpec>,
+}
+impl TracePattern {
+    fn matches(&self, mfa: &ModuleFunctionArity) -> bool {
+        self.module.map_or(true, |m| m == mfa.module)
+            && self.function.map_or(true, |f| f == mfa.function)
+            && self.arity.map_or(true, |a| a == mfa.arity)
+    }
+
+    /// Returns true if every function matched by `other` is matched by this pattern
+    fn covers(&self, other: &Self) -> bool {
+        (self.module.is_none() || self.module == other.module)
+            && (self.function.is_none() || self.function == other.function)
+            && (self.arity.is_none() || self.arity == other.arity)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    traced: BTreeMap<ProcessId, Tracee>,
+    /// The flags and tracer of processes spawned from now on, set with `new` or `all`
+    new: Option<Tracee>,
+    /// The trace patterns set, the last of which matching a function applies to it
+    patterns: Vec<TracePattern>,
+}
+// The terms of match specifications are global, see `trace_pattern/3`
+unsafe impl Send for State {}
+
+/// A traced call, whose return or exception is traced as its match specification asked
+pub(crate) struct TracedCall {
+    pid: ProcessId,
+    tracee: Tracee,
+    mfa: ModuleFunctionArity,
+    return_trace: bool,
+    exception_trace: bool,
+}
+impl TracedCall {
+    /// Traces the outcome of the call
+    pub(crate) fn returned(self, result: &ErlangResult) {
+        let mfa = self.mfa;
+        let mfa = move |heap: &HeapFragment| {
+            let arity = Term::Int(mfa.arity as i64);
+            support::tuple(&[mfa.module.into(), mfa.function.into(), arity], heap)
+        };
+        match result {
+            ErlangResult::Ok(value) if self.return_trace || self.exception_trace => {
+                let value = *value;
+                emit(self.tracee, self.pid, "return_from", |heap| {
+                    Ok(vec![mfa(heap)?, value.into()])
+                });
+            }
+            ErlangResult::Err(exception) if self.exception_trace => {
+                let exception = unsafe { exception.as_ref() };
+                emit(self.tracee, self.pid, "exception_from", |heap| {
+                    let kind = Term::Atom(exception.kind());
+                    let error = support::tuple(&[kind, exception.reason()], heap)?;
+                    Ok(vec![mfa(heap)?, error])
+                });
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Sets the trace flags of the processes in `PidSpec`, returning how many processes that was
+///
+/// `PidSpec` is a pid, `existing` for the processes now alive, `new` for those spawned from now on,
+/// or `all` for both. `How` is true to set the flags, and false to clear them.
+#[export_name = "erlang:trace/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn trace3(
+    pid_spec: OpaqueTerm,
+    how: OpaqueTerm,
+    flags: OpaqueTerm,
+) -> ErlangResult {
+    let Term::Bool(enable) = how.into() else { return super::badarg(Trace::capture()); };
+    let Some((flags, tracer)) = parse_flags(flags) else { return super::badarg(Trace::capture()); };
+    match set_flags(pid_spec, enable, flags, tracer) {
+        Some(count) => ErlangResult::Ok(Term::Int(count as i64).into()),
+        None => super::badarg(Trace::capture()),
+    }
+}
+
+/// Sets or clears `flags` for the processes in `pid_spec`, as `trace/3` does, returning how many
+/// processes that was, or `None` if `pid_spec` is invalid
+pub(super) fn set_flags(
+    pid_spec: OpaqueTerm,
+    enable: bool,
+    flags: u16,
+    tracer: Option<Tracer>,
+) -> Option<usize> {
+    let (existing, new) = match pid_spec.into() {
+        Term::Pid(pid) => match *pid {
+            Pid::Local { id } if is_alive(id) => (vec![id], false),
+            _ => return None,
+        },
+        Term::Atom(spec) => match spec.as_str() {
+            "existing" | "existing_processes" => (existing_processes(), false),
+            "new" | "new_processes" => (vec![], true),
+            "all" | "processes" => (existing_processes(), true),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let count = existing.len();
+    let mut state = state();
+    let update = |tracee: Option<Tracee>| {
+        let current = tracee.map_or(0, |tracee| tracee.flags);
+        let flags = if enable {
+            current | flags
+        } else {
+            current & !flags
+        };
+        let tracer = match tracee {
+            Some(tracee) if !enable || tracer.is_none() => tracee.tracer,
+            _ => tracer.unwrap_or_else(|| Tracer::Process(with_process(|proc| proc.pid()))),
+        };
+        (flags != 0).then_some(Tracee { flags, tracer })
+    };
+    for pid in existing {
+        let tracee = update(state.traced.get(&pid).copied());
+        match tracee {
+            Some(tracee) => state.traced.insert(pid, tracee),
+            None => state.traced.remove(&pid),
+        };
+    }
+    if new {
+        state.new = update(state.new);
+    }
+    TRACING.store(
+        !state.traced.is_empty() || state.new.is_some(),
+        Ordering::Relaxed,
+    );
+    Some(count)
+}
+
+/// Returns the trace flag named `name`, as given to `trace/3`
+pub(super) fn flag(name: &str) -> Option<u16> {
+    match name {
+        "all" => Some(FLAGS.iter().fold(0, |all, (_, flag)| all | flag)),
+        _ => FLAGS
+            .iter()
+            .find(|(flag, _)| *flag == name)
+            .map(|(_, flag)| *flag),
+    }
+}
+
+/// Runs `fun` without tracing the events it causes, e.g. a trace handler of `dbg`, which would
+/// otherwise trace the messages it sends, forever
+pub(super) fn untraced<F, R>(fun: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let suppressed = SUPPRESSED.swap(true, Ordering::Relaxed);
+    let result = fun();
+    SUPPRESSED.store(suppressed, Ordering::Relaxed);
+    result
+}
+
+/// Sets the trace pattern of the functions `MFA` for call tracing, see `trace_pattern/3`
+#[export_name = "erlang:trace_pattern/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn trace_pattern2(mfa: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    trace_pattern3(mfa, spec, OpaqueTerm::NIL)
+}
+
+/// Sets the trace pattern of the functions `{Module, Function, Arity}`, any of which may be `'_'`,
+/// returning how many functions it applies to
+///
+/// `MatchSpec` is true to trace all calls, false to stop tracing them, or a match specification
+/// which selects the calls traced and the actions taken for them. Only local and global call
+/// tracing, which are the same here, are supported, not `meta`, `call_count` nor `call_time`.
+#[export_name = "erlang:trace_pattern/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn trace_pattern3(
+    mfa: OpaqueTerm,
+    spec: OpaqueTerm,
+    flags: OpaqueTerm,
+) -> ErlangResult {
+    let Some(mut pattern) = parse_pattern(mfa) else { return super::badarg(Trace::capture()); };
+    let Some(flags) = list_to_vec(flags) else { return super::badarg(Trace::capture()); };
+    if !flags
+        .iter()
+        .all(|flag| is_atom(*flag, "global") || is_atom(*flag, "local"))
+    {
+        return super::badarg(Trace::capture());
+    }
+    let enable = match spec.into() {
+        Term::Bool(enable) => enable,
+        Term::Nil => true,
+        Term::Cons(_) => {
+            // The specification refers to its terms, so they must outlive the caller
+            match MatchSpec::compile(make_global(spec).into()) {
+                Ok(spec) => pattern.spec = Some(spec),
+                Err(_) => return super::badarg(Trace::capture()),
+            }
+            true
+        }
+        _ => return super::badarg(Trace::capture()),
+    };
+
+    let count = function::count_symbols(|mfa| pattern.matches(mfa));
+    let mut state = state();
+    state.patterns.retain(|other| !pattern.covers(other));
+    if enable {
+        state.patterns.push(pattern);
+    }
+    ErlangResult::Ok(Term::Int(count as i64).into())
+}
+
+/// Traces a call to `mfa` with the arguments `args` by the current process, returning the call if
+/// its outcome is to be traced as well
+///
+/// This is called by `erlang:apply/3` before the call is made.
+pub(crate) fn call(mfa: &ModuleFunctionArity, args: OpaqueTerm) -> Option<TracedCall> {
+    if !TRACING.load(Ordering::Relaxed) || SUPPRESSED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let pid = with_process(|proc| proc.pid());
+    let state = state();
+    let tracee = *state.traced.get(&pid).filter(|tracee| tracee.has(CALL))?;
+    let pattern = state.patterns.iter().rev().find(|p| p.matches(mfa))?;
+
+    // The match is run on the heap the trace message is built on, as its result becomes part of it
+    let scratch = scratch()?;
+    let heap = unsafe { scratch.as_ref() };
+    let matched = match pattern.spec.as_ref() {
+        None => Default::default(),
+        Some(spec) => match spec.run(args.into(), heap) {
+            Ok(Some(matched)) => matched.actions,
+            Ok(None) | Err(_) => {
+                free(scratch);
+                return None;
+            }
+        },
+    };
+    drop(state);
+
+    let mfa = *mfa;
+    let message = matched
+        .message
+        .filter(|message| !is_atom((*message).into(), "true"));
+    if !matches!(message, Some(Term::Bool(false))) {
+        emit_on(heap, tracee, pid, "call", |heap| {
+            let call =
+                support::tuple(&[mfa.module.into(), mfa.function.into(), args.into()], heap)?;
+            Ok(match message {
+                Some(message) => vec![call, message],
+                None => vec![call],
+            })
+        });
+    }
+    free(scratch);
+
+    (matched.return_trace || matched.exception_trace).then_some(TracedCall {
+        pid,
+        tracee,
+        mfa,
+        return_trace: matched.return_trace,
+        exception_trace: matched.exception_trace,
+    })
+}
+
+/// Traces `message` being sent by the current process to `to`
+pub(crate) fn send(message: OpaqueTerm, to: OpaqueTerm) {
+    if !TRACING.load(Ordering::Relaxed) {
+        return;
+    }
+    let pid = with_process(|proc| proc.pid());
+    let Some(tracee) = tracee(pid, SEND) else { return; };
+    emit(tracee, pid, "send", |_| Ok(vec![message.into(), to.into()]));
+}
+
+/// Traces the receipt of a message by `pid`, a server or the root process
+pub(crate) fn receive(pid: ProcessId, message: &Message) {
+    if !TRACING.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(tracee) = tracee(pid, RECEIVE) else { return; };
+    // A tracer tracing its own receipts would trace the receipt of each trace message, forever
+    if tracee.tracer == Tracer::Process(pid) {
+        return;
+    }
+    let Some(message) = received(message) else { return; };
+    emit(tracee, pid, "receive", |heap| Ok(vec![message(heap)?]));
+}
+
+/// Traces the spawn of `child` by the current process, to start by applying `mfa` to `args`
+///
+/// The child inherits the flags of its parent if it has `set_on_spawn`, and those set by `new`
+/// otherwise.
+pub(crate) fn spawn(child: ProcessId, mfa: ModuleFunctionArity, args: &[OpaqueTerm]) {
+    if !TRACING.load(Ordering::Relaxed) {
+        return;
+    }
+    let parent = with_process(|proc| proc.pid());
+    let (parent_tracee, child_tracee) = {
+        let mut state = state();
+        let parent_tracee = state.traced.get(&parent).copied();
+        let inherited = parent_tracee.filter(|tracee| tracee.has(SET_ON_SPAWN));
+        let child_tracee = inherited.or(state.new);
+        if let Some(tracee) = child_tracee {
+            state.traced.insert(child, tracee);
+        }
+        (parent_tracee, child_tracee)
+    };
+    // Processes are spawned to apply `apply/2,3`, whose arguments are those of the call reported
+    let call = |heap: &HeapFragment| {
+        let args = match args {
+            [_, _, args] => (*args).into(),
+            args => {
+                let args = args.iter().copied().map(Term::from).collect::<Vec<_>>();
+                support::list(args.as_slice(), heap)?
+            }
+        };
+        support::tuple(&[mfa.module.into(), mfa.function.into(), args], heap)
+    };
+    if let Some(tracee) = parent_tracee.filter(|tracee| tracee.has(PROCS)) {
+        emit(tracee, parent, "spawn", |heap| {
+            Ok(vec![pid(child, heap)?, call(heap)?])
+        });
+    }
+    if let Some(tracee) = child_tracee.filter(|tracee| tracee.has(PROCS)) {
+        emit(tracee, child, "spawned", |heap| {
+            Ok(vec![pid(parent, heap)?, call(heap)?])
+        });
+    }
+}
+
+/// Traces the exit of `pid` with `reason`, after which it is no longer traced
+///
+/// This is called by the scheduler once the process has exited.
+pub(crate) fn exit(pid: ProcessId, reason: OpaqueTerm) {
+    if !TRACING.load(Ordering::Relaxed) {
+        return;
+    }
+    let tracee = {
+        let mut state = state();
+        let tracee = state.traced.remove(&pid);
+        TRACING.store(
+            !state.traced.is_empty() || state.new.is_some(),
+            Ordering::Relaxed,
+        );
+        tracee
+    };
+    if let Some(tracee) = tracee.filter(|tracee| tracee.has(PROCS)) {
+        emit(tracee, pid, "exit", |_| Ok(vec![reason.into()]));
+    }
+}
+
+/// Traces a collection of the heap of `pid`, which is `heap_size` words in size
+pub(crate) fn garbage_collection(pid: ProcessId, heap_size: usize) {
+    if !TRACING.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(tracee) = tracee(pid, GARBAGE_COLLECTION) else { return; };
+    // Nothing is collected yet, see `super::process`, so the heap is the same size after as before
+    for tag in ["gc_major_start", "gc_major_end"] {
+        emit(tracee, pid, tag, |heap| {
+            let info = firefly_rt::term!(heap, [{heap_size, (heap_size)}])?;
+            Ok(vec![info])
+        });
+    }
+}
+
+fn tracee(pid: ProcessId, flag: u16) -> Option<Tracee> {
+    state()
+        .traced
+        .get(&pid)
+        .copied()
+        .filter(|tracee| tracee.has(flag))
+}
+
+/// Sends the trace message `{trace, Pid, Tag, ...}` of `pid` to its tracer, where the elements
+/// following the tag are built by `elements`
+fn emit<F>(tracee: Tracee, pid: ProcessId, tag: &str, elements: F)
+where
+    F: FnOnce(&HeapFragment) -> Result<Vec<Term>, AllocError>,
+{
+    let Some(scratch) = scratch() else { return; };
+    emit_on(unsafe { scratch.as_ref() }, tracee, pid, tag, elements);
+    free(scratch);
+}
+
+fn emit_on<F>(heap: &HeapFragment, tracee: Tracee, pid: ProcessId, tag: &str, elements: F)
+where
+    F: FnOnce(&HeapFragment) -> Result<Vec<Term>, AllocError>,
+{
+    if SUPPRESSED.load(Ordering::Relaxed) {
+        return;
+    }
+    let timestamp = tracee.has(TIMESTAMP | MONOTONIC_TIMESTAMP);
+    let message = (|| {
+        let kind = if timestamp { "trace_ts" } else { "trace" };
+        let mut message = vec![
+            Term::Atom(atom(kind)),
+            self::pid(pid, heap)?,
+            atom(tag).into(),
+        ];
+        message.extend(elements(heap)?);
+        if tracee.has(MONOTONIC_TIMESTAMP) {
+            message.push(crate::time::monotonic().into_term(heap)?);
+        } else if timestamp {
+            message.push(now(heap)?);
+        }
+        support::tuple(message.as_slice(), heap)
+    })();
+    let Ok(message) = message else { return; };
+    deliver(tracee.tracer, message);
+}
+
+/// Sends a trace message built on a scratch heap to `tracer`
+///
+/// Trace messages are not sequentially traced themselves, even when sent by a process which is.
+pub(super) fn deliver(tracer: Tracer, message: Term) {
+    super::seq_trace::untraced(|| match tracer {
+        Tracer::Process(tracer) => {
+            // The message is copied off the scratch heap, which is freed once it is sent
+            let message = make_global(message.into());
+            if tracer == scheduler::with_current(|scheduler| scheduler.root_pid()) {
+                crate::runtime::deliver(message);
+            } else {
+                gen::cast(tracer, Message::Info(message));
+            }
+        }
+        Tracer::Port(port) => {
+            let mut bytes = Vec::new();
+            if firefly_rt::etf::encode(message, &mut bytes).is_ok() {
+                command(port, bytes.as_slice());
+            }
+        }
+        Tracer::File => super::dbg::write(message),
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn command(port: PortId, bytes: &[u8]) {
+    let _ = firefly_driver::command(port, bytes);
+}
+
+/// There are no ports on wasm32, so there are no port tracers either
+#[cfg(target_arch = "wasm32")]
+fn command(_port: PortId, _bytes: &[u8]) {}
+
+/// Returns the current system time as `{MegaSecs, Secs, MicroSecs}`, as in `timestamp` trace
+/// messages
+pub(super) fn now(heap: &HeapFragment) -> Result<Term, AllocError> {
+    let micros = (crate::time::system() / 1_000).max(0) as u64;
+    let (seconds, micros) = (micros / 1_000_000, micros % 1_000_000);
+    let (mega, seconds) = (seconds / 1_000_000, seconds % 1_000_000);
+    firefly_rt::term!(heap, {(mega), (seconds), (micros)})
+}
+
+/// Returns a function building `message` as received, if it was sent by a process
+///
+/// Only messages sent by other processes are traced, not the timers of a server itself.
+pub(super) fn received(
+    message: &Message,
+) -> Option<impl FnOnce(&HeapFragment) -> Result<Term, AllocError>> {
+    Some(match message {
+        Message::Info(message) => message_term(&[*message]),
+        Message::Cast(message) => message_term(&[atom("$gen_cast").into(), *message]),
+        Message::Call { from, request } => {
+            message_term(&[atom("$gen_call").into(), *from, *request])
+        }
+        _ => return None,
+    })
+}
+
+/// Returns a function building the message `elements`, as a tuple unless there is only one
+fn message_term(elements: &[OpaqueTerm]) -> impl FnOnce(&HeapFragment) -> Result<Term, AllocError> {
+    let elements = elements.iter().copied().map(Term::from).collect::<Vec<_>>();
+    move |heap| match elements.as_slice() {
+        [message] => Ok(*message),
+        elements => support::tuple(elements, heap),
+    }
+}
+
+pub(super) fn pid(id: ProcessId, heap: &HeapFragment) -> Result<Term, AllocError> {
+    GcBox::new_in(Pid::Local { id }, heap).map(Term::Pid)
+}
+
+pub(super) fn scratch() -> Option<ptr::NonNull<HeapFragment>> {
+    HeapFragment::new(Layout::from_size_align(SCRATCH_SIZE, 8).unwrap(), None).ok()
+}
+
+pub(super) fn free(scratch: ptr::NonNull<HeapFragment>) {
+    unsafe { ptr::drop_in_place(scratch.as_ptr()) }
+}
+
+/// Parses the flags given to `trace/3`, returning them with the tracer, if given
+fn parse_flags(list: OpaqueTerm) -> Option<(u16, Option<Tracer>)> {
+    let mut flags = 0;
+    let mut tracer = None;
+    for flag in list_to_vec(list)? {
+        match flag.into() {
+            Term::Atom(flag) => flags |= self::flag(flag.as_str())?,
+            _ => {
+                let [key, value] = tuple_elements(flag)? else { return None; };
+                if !is_atom(*key, "tracer") {
+                    return None;
+                }
+                tracer = Some(parse_tracer(*value)?);
+            }
+        }
+    }
+    Some((flags, tracer))
+}
+
+/// Parses a tracer, i.e. a live process or a port
+pub(super) fn parse_tracer(tracer: OpaqueTerm) -> Option<Tracer> {
+    match tracer.into() {
+        Term::Pid(pid) => match *pid {
+            Pid::Local { id } if is_alive(id) => Some(Tracer::Process(id)),
+            _ => None,
+        },
+        Term::Port(port) => match *port {
+            Port::Local { id } => Some(Tracer::Port(id)),
+            Port::External { .. } => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parses `{Module, Function, Arity}`, where `'_'` matches anything, but only following `'_'`
+fn parse_pattern(mfa: OpaqueTerm) -> Option<TracePattern> {
+    let [module, function, arity] = tuple_elements(mfa)? else { return None; };
+    let wildcard = |term: OpaqueTerm| is_atom(term, "_");
+    let module = match (*module).into() {
+        _ if wildcard(*module) => None,
+        Term::Atom(module) => Some(module),
+        _ => return None,
+    };
+    let function = match (*function).into() {
+        _ if wildcard(*function) => None,
+        Term::Atom(function) if module.is_some() => Some(function),
+        _ => return None,
+    };
+    let arity = match (*arity).into() {
+        _ if wildcard(*arity) => None,
+        Term::Int(arity) if function.is_some() => Some(u8::try_from(arity).ok()?),
+        _ => return None,
+    };
+    Some(TracePattern {
+        module,
+        function,
+        arity,
+        spec: None,
+    })
+}
+
+pub(super) fn is_alive(pid: ProcessId) -> bool {
+    scheduler::with_current(|scheduler| {
+        pid == scheduler.root_pid() || scheduler.process(pid).is_some()
+    }) || gen::module(pid).is_some()
+}
+
+/// Returns the processes alive, including servers
+fn existing_processes() -> Vec<ProcessId> {
+    let mut pids = vec![scheduler::with_current(|scheduler| scheduler.root_pid())];
+    let mut last = None;
+    while let Some(pid) = scheduler::with_current(|scheduler| scheduler.next_process(last)) {
+        pids.push(pid);
+        last = Some(pid);
+    }
+    pids.extend(gen::mailboxes().into_iter().map(|(pid, _, _)| pid));
+    pids.sort();
+    pids.dedup();
+    pids
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.get_or_init(Default::default).lock().unwrap()
+}
@@ -0,0 +1,275 @@
+//! Helpers for constructing and deconstructing terms in runtime-implemented modules
+use std::mem;
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::str::FromStr;
+
+use firefly_alloc::gc::GcBox;
+use firefly_alloc::rc::Rc;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::{self, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::{Process, Resumable, Step};
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+/// Calls `Module:Function(Args...)` if it is exported, returning `None` if it is not
+pub(crate) fn call_if_exported(
+    module: Atom,
+    function: &str,
+    args: &[OpaqueTerm],
+) -> Result<Option<OpaqueTerm>, NonNull<ErlangException>> {
+    let mfa = ModuleFunctionArity::new(module, atom(function), args.len());
+    match function::find_symbol(&mfa) {
+        None => Ok(None),
+        Some(callee) => match unsafe { function::apply_callee(callee, args) } {
+            ErlangResult::Ok(result) => Ok(Some(result)),
+            ErlangResult::Err(err) => Err(err),
+        },
+    }
+}
+
+pub(crate) fn reason2(tag: &str, value: OpaqueTerm) -> OpaqueTerm {
+    with_process(|proc| make_tuple(proc, &[atom(tag).into(), value]))
+}
+
+/// Copies `term` to a heap fragment, so that it may outlive the process which created it
+///
+/// Reference-counted binaries are shared with the copy rather than copied, see `Term::copy_layout`,
+/// so this is cheap for messages made mostly of large binaries.
+pub(crate) fn make_global(term: OpaqueTerm) -> OpaqueTerm {
+    if term.is_immediate() || term.is_literal() {
+        return term;
+    }
+    let term: Term = term.into();
+    let (copied, _fragment) = term.copy_to_fragment().unwrap();
+    copied.into()
+}
+
+pub(crate) fn list_to_vec(list: OpaqueTerm) -> Option<Vec<OpaqueTerm>> {
+    match list.into() {
+        Term::Nil => Some(vec![]),
+        Term::Cons(ptr) => unsafe { ptr.as_ref() }
+            .iter()
+            .map(|element| element.ok().map(|term| term.into()))
+            .collect(),
+        _ => None,
+    }
+}
+
+pub(crate) fn charlist_to_string(term: OpaqueTerm) -> Option<String> {
+    match term.into() {
+        Term::Nil => Some(String::new()),
+        Term::Cons(ptr) => unsafe { ptr.as_ref() }.to_string(),
+        _ => None,
+    }
+}
+
+pub(crate) fn charlist(proc: &Process, s: &str) -> OpaqueTerm {
+    Cons::charlist_from_str(s, proc)
+        .unwrap()
+        .map(|ptr| ptr.into())
+        .unwrap_or(OpaqueTerm::NIL)
+}
+
+/// Constructs an integer term, boxing it if it is not small
+pub(crate) fn make_integer(proc: &Process, integer: Integer) -> OpaqueTerm {
+    match integer {
+        Integer::Small(i) => i.try_into().unwrap(),
+        Integer::Big(i) => GcBox::new_in(i, proc).unwrap().into(),
+    }
+}
+
+/// Constructs a binary containing a copy of `bytes`
+pub(crate) fn make_binary(proc: &Process, bytes: &[u8]) -> OpaqueTerm {
+    if bytes.len() <= 64 {
+        let mut bin = BinaryData::with_capacity_small(bytes.len(), proc).unwrap();
+        bin.copy_from_slice(bytes);
+        bin.into()
+    } else {
+        let mut bin = BinaryData::with_capacity_large(bytes.len(), proc).unwrap();
+        {
+            // SAFETY: There can be no other references to this Rc yet
+            let b = unsafe { Rc::get_mut(&mut bin).unwrap_unchecked() };
+            b.copy_from_slice(bytes);
+        }
+        let bin: OpaqueTerm = bin.into();
+        proc.track_binary(bin, bytes.len());
+        bin
+    }
+}
+
+/// Flattens `term` to a sequence of bytes, if it is iodata
+///
+/// Each element of the iodata consumes a reduction, and the current process is yielded whenever it
+/// runs out of them, so that flattening a large iolist does not hold up other processes.
+pub(crate) fn iodata_to_bytes(term: OpaqueTerm) -> Option<Vec<u8>> {
+    let term: Term = term.into();
+    // Bytes are only valid as elements of a list
+    if let Term::Int(_) = term {
+        return None;
+    }
+    scheduler::trampoline(Flatten {
+        stack: vec![Item::Term(term)],
+        bytes: Vec::new(),
+    })
+}
+
+/// The flattening of iodata to bytes, as a computation which can be suspended
+///
+/// The iodata is walked depth-first with an explicit stack, rather than by recursion, so that the
+/// walk can be resumed where it left off.
+struct Flatten {
+    stack: Vec<Item>,
+    bytes: Vec<u8>,
+}
+
+enum Item {
+    /// An element of iodata
+    Term(Term),
+    /// The remainder of a list, whose tail must be nil or a binary
+    Elements(Term),
+}
+
+impl Resumable for Flatten {
+    type Output = Option<Vec<u8>>;
+
+    fn resume(&mut self, budget: &mut usize) -> Step<Self::Output> {
+        while let Some(item) = self.stack.pop() {
+            if *budget == 0 {
+                self.stack.push(item);
+                return Step::Yield;
+            }
+            *budget -= 1;
+            let ok = match item {
+                Item::Term(Term::Nil) | Item::Elements(Term::Nil) => true,
+                Item::Term(Term::Int(byte)) => match u8::try_from(byte) {
+                    Ok(byte) => {
+                        self.bytes.push(byte);
+                        true
+                    }
+                    Err(_) => false,
+                },
+                Item::Term(list @ Term::Cons(_)) => {
+                    self.stack.push(Item::Elements(list));
+                    true
+                }
+                Item::Elements(Term::Cons(ptr)) => {
+                    let cell = unsafe { ptr.as_ref() };
+                    self.stack.push(Item::Elements(cell.tail()));
+                    self.stack.push(Item::Term(cell.head()));
+                    true
+                }
+                // The tail of an improper iolist must be a binary
+                Item::Term(term) | Item::Elements(term) => match term.as_bitstring() {
+                    Some(bits) if bits.is_binary() && bits.is_aligned() => {
+                        self.bytes
+                            .extend_from_slice(unsafe { bits.as_bytes_unchecked() });
+                        true
+                    }
+                    _ => false,
+                },
+            };
+            if !ok {
+                return Step::Done(None);
+            }
+        }
+        Step::Done(Some(mem::take(&mut self.bytes)))
+    }
+}
+
+pub(crate) fn make_tuple(proc: &Process, elements: &[OpaqueTerm]) -> OpaqueTerm {
+    Tuple::from_slice(elements, proc).unwrap().into()
+}
+
+pub(crate) fn make_list(proc: &Process, elements: &[OpaqueTerm]) -> OpaqueTerm {
+    let mut builder = ListBuilder::new(proc);
+    for element in elements.iter().rev().copied() {
+        builder.push(element.into()).unwrap();
+    }
+    builder
+        .finish()
+        .map(|ptr| ptr.into())
+        .unwrap_or(OpaqueTerm::NIL)
+}
+
+/// Constructs the cell `[Head | Tail]`, where `Tail` need not be a list
+pub(crate) fn make_cons(proc: &Process, head: OpaqueTerm, tail: OpaqueTerm) -> OpaqueTerm {
+    let mut ptr = Cons::new_in(proc).unwrap();
+    let cell = unsafe { ptr.as_mut() };
+    cell.head = head;
+    cell.tail = tail;
+    ptr.into()
+}
+
+pub(crate) fn with_process<F, R>(fun: F) -> R
+where
+    F: FnOnce(&Process) -> R,
+{
+    scheduler::with_current(|scheduler| {
+        let arc_proc = scheduler.current_process();
+        fun(arc_proc.deref())
+    })
+}
+
+#[inline]
+pub(crate) fn atom(name: &str) -> Atom {
+    Atom::from_str(name).unwrap()
+}
+
+/// Returns the elements of `term` if it is a tuple
+pub(crate) fn tuple_elements<'a>(term: OpaqueTerm) -> Option<&'a [OpaqueTerm]> {
+    match term.into() {
+        Term::Tuple(ptr) => Some(unsafe { ptr.as_ref() }.as_slice()),
+        _ => None,
+    }
+}
+
+/// Returns true if `term` is the atom `name`
+pub(crate) fn is_atom(term: OpaqueTerm, name: &str) -> bool {
+    match term.into() {
+        Term::Atom(a) => a.as_str() == name,
+        _ => false,
+    }
+}
+
+/// Compares two terms for equality, as with `==`
+pub(crate) fn equals(lhs: OpaqueTerm, rhs: OpaqueTerm) -> bool {
+    lhs == rhs || Term::from(lhs) == Term::from(rhs)
+}
+
+/// Parses a timeout of the form `infinity | non_neg_integer()`, as milliseconds
+///
+/// Returns `Some(None)` for `infinity`, and `None` if the term is not a valid timeout.
+pub(crate) fn timeout_ms(term: OpaqueTerm) -> Option<Option<u64>> {
+    match term.into() {
+        Term::Atom(a) if a.as_str() == "infinity" => Some(None),
+        Term::Int(ms) if ms >= 0 => Some(Some(ms as u64)),
+        _ => None,
+    }
+}
+
+pub(crate) fn make_pid(proc: &Process, id: ProcessId) -> OpaqueTerm {
+    Term::Pid(GcBox::new_in(Pid::Local { id }, proc).unwrap()).into()
+}
+
+pub(crate) fn make_port(proc: &Process, id: PortId) -> OpaqueTerm {
+    Term::Port(GcBox::new_in(Port::Local { id }, proc).unwrap()).into()
+}
+
+/// Allocates a new unique reference
+pub(crate) fn make_ref(proc: &Process) -> OpaqueTerm {
+    let id = scheduler::with_current(|scheduler| scheduler.next_reference_id());
+    Term::Reference(GcBox::new_in(Reference::Local { id }, proc).unwrap()).into()
+}
+
+/// Returns the pid of the current process
+pub(crate) fn self_pid() -> OpaqueTerm {
+    with_process(|proc| make_pid(proc, proc.pid()))
+}
+
+/// Constructs an `exit` exception with the given reason
+pub(crate) fn exit_err(reason: OpaqueTerm) -> NonNull<ErlangException> {
+    ErlangException::new(atoms::Exit, reason.into(), Trace::capture()).into_raw()
+}
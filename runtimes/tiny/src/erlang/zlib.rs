@@ -0,0 +1,695 @@
+//! The BIFs of the `zlib` module, compressing with `flate2`.
+//!
+//! A stream opened with `open/0` is referred to by a magic reference, which any process holding it
+//! may use, and which is initialized for either compression with `deflateInit/1,2,6` or
+//! decompression with `inflateInit/1,2`. Both kinds of stream produce zlib data with a `WindowBits`
+//! of 8 to 15, and raw deflate data with -8 to -15, always with a window of 32 KiB, as `flate2`
+//! does not support smaller windows without zlib itself. Streams of gzip data, with a `WindowBits`
+//! of 16 or more, are not supported, but whole gzip data is, with `gzip/1` and `gunzip/1`. The
+//! `MemLevel` and `Strategy` given to `deflateInit/6` are accepted, but have no effect.
+//!
+//! `deflate/2,3` and `inflate/2` return their output as a list of binaries, in chunks of at most
+//! [`CHUNK_SIZE`] bytes, and consume a reduction for every [`BYTES_PER_REDUCTION`] bytes read or
+//! written, yielding when the process runs out of them, so that compressing large data does not
+//! hold up other processes. `safeInflate/2` returns at most one chunk at a time, as `{continue,
+//! Output}` until the data is all inflated, then as `{finished, Output}`; the input it has not yet
+//! inflated is kept by the stream, and the next call is given `[]` to go on with it.
+//!
+//! `compress/1` and `uncompress/1`, `zip/1` and `unzip/1`, and `gzip/1` and `gunzip/1` compress and
+//! decompress whole zlib, raw deflate and gzip data, with the same reduction accounting. Invalid
+//! compressed data raises `data_error`, and using a stream in the wrong state raises
+//! `not_initialized` or `already_initialized`, as in OTP.
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::{Process, Resumable, Step};
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::badarg;
+use super::util::*;
+
+/// The scheduler id used in the identifiers of references to streams
+const ZLIB_REFERENCE_SCHEDULER_ID: u16 = u16::MAX - 3;
+
+/// The largest binary in the output of a stream
+pub const CHUNK_SIZE: usize = 16 * 1024;
+/// The number of bytes read or written by a stream for each reduction consumed
+pub const BYTES_PER_REDUCTION: usize = 256;
+
+/// The gzip header written by `gzip/1`: no file name or modification time, the default compression
+/// level, and an unknown OS
+const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+
+static NEXT_STREAM_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The value of a magic reference referring to a stream
+struct Zlib(Arc<Mutex<Stream>>);
+
+enum Stream {
+    /// Opened, but not initialized for either compression or decompression
+    Idle,
+    Deflate(Compress),
+    Inflate {
+        decompress: Decompress,
+        /// Whether the data is zlib rather than raw deflate data, which `Decompress` does not keep
+        zlib_header: bool,
+        /// The input not yet inflated by `safeInflate/2`
+        pending: Vec<u8>,
+    },
+    Closed,
+}
+
+#[export_name = "zlib:open/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn open0() -> ErlangResult {
+    ErlangResult::Ok(with_process(|proc| {
+        let id = NEXT_STREAM_ID.fetch_add(1, Ordering::Relaxed);
+        let id = ReferenceId::new(ZLIB_REFERENCE_SCHEDULER_ID, id);
+        let zlib = Zlib(Arc::new(Mutex::new(Stream::Idle)));
+        let handle = GcBox::<dyn Any>::new_unsize_in(zlib, proc).unwrap();
+        let reference = GcBox::new_in(Reference::new_magic(id, handle), proc).unwrap();
+        Term::Reference(reference).into()
+    }))
+}
+
+#[export_name = "zlib:close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn close1(z: OpaqueTerm) -> ErlangResult {
+    with_stream(z, |stream| {
+        *stream = Stream::Closed;
+        Ok(atoms::Ok.into())
+    })
+}
+
+#[export_name = "zlib:deflateInit/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_init1(z: OpaqueTerm) -> ErlangResult {
+    deflate_init(z, Compression::default(), true)
+}
+
+#[export_name = "zlib:deflateInit/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_init2(z: OpaqueTerm, level: OpaqueTerm) -> ErlangResult {
+    match parse_level(level) {
+        Some(level) => deflate_init(z, level, true),
+        None => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "zlib:deflateInit/6"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_init6(
+    z: OpaqueTerm,
+    level: OpaqueTerm,
+    method: OpaqueTerm,
+    window_bits: OpaqueTerm,
+    mem_level: OpaqueTerm,
+    strategy: OpaqueTerm,
+) -> ErlangResult {
+    let strategy = ["default", "filtered", "huffman_only", "rle"]
+        .iter()
+        .any(|name| is_atom(strategy, name));
+    let mem_level = matches!(mem_level.into(), Term::Int(1..=9));
+    if !strategy || !mem_level || !is_atom(method, "deflated") {
+        return badarg(Trace::capture());
+    }
+    match (parse_level(level), parse_window_bits(window_bits)) {
+        (Some(level), Some(zlib_header)) => deflate_init(z, level, zlib_header),
+        _ => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "zlib:deflate/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate2(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    deflate3(z, data, atom("none").into())
+}
+
+#[export_name = "zlib:deflate/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate3(
+    z: OpaqueTerm,
+    data: OpaqueTerm,
+    flush: OpaqueTerm,
+) -> ErlangResult {
+    let flush = match flush.into() {
+        Term::Atom(flush) => match flush.as_str() {
+            "none" => FlushCompress::None,
+            "sync" => FlushCompress::Sync,
+            "full" => FlushCompress::Full,
+            "finish" => FlushCompress::Finish,
+            _ => return badarg(Trace::capture()),
+        },
+        _ => return badarg(Trace::capture()),
+    };
+    let (Some(stream), Some(input)) = (stream(z), iodata_to_bytes(data)) else {
+        return badarg(Trace::capture());
+    };
+    if !matches!(*stream.lock().unwrap(), Stream::Deflate(_)) {
+        return raise("not_initialized");
+    }
+    let run = Run::new(stream, input, Op::Deflate(flush));
+    match scheduler::trampoline(run) {
+        Ok((output, _)) => ErlangResult::Ok(with_process(|proc| make_chunks(proc, &output))),
+        Err(reason) => raise(reason),
+    }
+}
+
+#[export_name = "zlib:deflateReset/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_reset1(z: OpaqueTerm) -> ErlangResult {
+    with_stream(z, |stream| match stream {
+        Stream::Deflate(compress) => {
+            compress.reset();
+            Ok(atoms::Ok.into())
+        }
+        _ => Err("not_initialized"),
+    })
+}
+
+#[export_name = "zlib:deflateEnd/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_end1(z: OpaqueTerm) -> ErlangResult {
+    with_stream(z, |stream| match stream {
+        Stream::Deflate(_) => {
+            *stream = Stream::Idle;
+            Ok(atoms::Ok.into())
+        }
+        _ => Err("not_initialized"),
+    })
+}
+
+#[export_name = "zlib:inflateInit/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate_init1(z: OpaqueTerm) -> ErlangResult {
+    inflate_init(z, true)
+}
+
+#[export_name = "zlib:inflateInit/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate_init2(z: OpaqueTerm, window_bits: OpaqueTerm) -> ErlangResult {
+    match parse_window_bits(window_bits) {
+        Some(zlib_header) => inflate_init(z, zlib_header),
+        None => badarg(Trace::capture()),
+    }
+}
+
+#[export_name = "zlib:inflate/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate2(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    match inflate(z, data, false) {
+        Ok((output, _)) => ErlangResult::Ok(with_process(|proc| make_chunks(proc, &output))),
+        Err(err) => err,
+    }
+}
+
+#[export_name = "zlib:safeInflate/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn safe_inflate2(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    match inflate(z, data, true) {
+        Ok((output, progress)) => ErlangResult::Ok(with_process(|proc| {
+            let tag = match progress {
+                Progress::Continue => "continue",
+                Progress::Drained | Progress::Ended => "finished",
+            };
+            let output = make_chunks(proc, &output);
+            make_tuple(proc, &[atom(tag).into(), output])
+        })),
+        Err(err) => err,
+    }
+}
+
+#[export_name = "zlib:inflateReset/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate_reset1(z: OpaqueTerm) -> ErlangResult {
+    with_stream(z, |stream| match stream {
+        Stream::Inflate {
+            decompress,
+            zlib_header,
+            pending,
+        } => {
+            decompress.reset(*zlib_header);
+            pending.clear();
+            Ok(atoms::Ok.into())
+        }
+        _ => Err("not_initialized"),
+    })
+}
+
+#[export_name = "zlib:inflateEnd/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate_end1(z: OpaqueTerm) -> ErlangResult {
+    with_stream(z, |stream| match stream {
+        Stream::Inflate { .. } => {
+            *stream = Stream::Idle;
+            Ok(atoms::Ok.into())
+        }
+        _ => Err("not_initialized"),
+    })
+}
+
+/// Compresses `Data` as zlib data
+#[export_name = "zlib:compress/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compress1(data: OpaqueTerm) -> ErlangResult {
+    match iodata_to_bytes(data) {
+        Some(input) => binary_result(deflate_all(input, true)),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Decompresses zlib data
+#[export_name = "zlib:uncompress/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn uncompress1(data: OpaqueTerm) -> ErlangResult {
+    match iodata_to_bytes(data) {
+        Some(input) => binary_result(inflate_all(input, true).map(|(output, _)| output)),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Compresses `Data` as raw deflate data
+#[export_name = "zlib:zip/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn zip1(data: OpaqueTerm) -> ErlangResult {
+    match iodata_to_bytes(data) {
+        Some(input) => binary_result(deflate_all(input, false)),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Decompresses raw deflate data
+#[export_name = "zlib:unzip/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unzip1(data: OpaqueTerm) -> ErlangResult {
+    match iodata_to_bytes(data) {
+        Some(input) => binary_result(inflate_all(input, false).map(|(output, _)| output)),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Compresses `Data` as gzip data, i.e. raw deflate data between a header and a trailer of its
+/// CRC-32 and length
+#[export_name = "zlib:gzip/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn gzip1(data: OpaqueTerm) -> ErlangResult {
+    let Some(input) = iodata_to_bytes(data) else {
+        return badarg(Trace::capture());
+    };
+    let crc = crc32fast::hash(&input);
+    let len = input.len() as u32;
+    binary_result(deflate_all(input, false).map(|deflated| {
+        let mut output = Vec::with_capacity(GZIP_HEADER.len() + deflated.len() + 8);
+        output.extend_from_slice(&GZIP_HEADER);
+        output.extend_from_slice(&deflated);
+        output.extend_from_slice(&crc.to_le_bytes());
+        output.extend_from_slice(&len.to_le_bytes());
+        output
+    }))
+}
+
+/// Decompresses gzip data, checking its CRC-32 and length
+#[export_name = "zlib:gunzip/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn gunzip1(data: OpaqueTerm) -> ErlangResult {
+    let Some(input) = iodata_to_bytes(data) else {
+        return badarg(Trace::capture());
+    };
+    let Some(start) = gzip_body(&input) else {
+        return raise("data_error");
+    };
+    let (output, consumed) = match inflate_all(input[start..].to_vec(), false) {
+        Ok(inflated) => inflated,
+        Err(reason) => return raise(reason),
+    };
+    let trailer = &input[start + consumed..];
+    if trailer.len() < 8 {
+        return raise("data_error");
+    }
+    let crc = u32::from_le_bytes(trailer[0..4].try_into().unwrap());
+    let len = u32::from_le_bytes(trailer[4..8].try_into().unwrap());
+    if crc != crc32fast::hash(&output) || len != output.len() as u32 {
+        return raise("data_error");
+    }
+    binary_result(Ok(output))
+}
+
+/// Returns the CRC-32 of `Data`, the stream `Z` being accepted for compatibility only
+#[export_name = "zlib:crc32/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn crc32_2(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    checksum(z, Term::Int(0).into(), data, |prev, bytes| {
+        let mut hasher = crc32fast::Hasher::new_with_initial(prev);
+        hasher.update(bytes);
+        hasher.finalize()
+    })
+}
+
+/// Updates the CRC-32 `PrevCRC` with `Data`
+#[export_name = "zlib:crc32/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn crc32_3(
+    z: OpaqueTerm,
+    prev: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    checksum(z, prev, data, |prev, bytes| {
+        let mut hasher = crc32fast::Hasher::new_with_initial(prev);
+        hasher.update(bytes);
+        hasher.finalize()
+    })
+}
+
+/// Returns the Adler-32 checksum of `Data`, the stream `Z` being accepted for compatibility only
+#[export_name = "zlib:adler32/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn adler32_2(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    checksum(z, Term::Int(1).into(), data, |prev, bytes| {
+        let mut adler = adler::Adler32::from_checksum(prev);
+        adler.write_slice(bytes);
+        adler.checksum()
+    })
+}
+
+/// Updates the Adler-32 checksum `PrevAdler` with `Data`
+#[export_name = "zlib:adler32/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn adler32_3(
+    z: OpaqueTerm,
+    prev: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    checksum(z, prev, data, |prev, bytes| {
+        let mut adler = adler::Adler32::from_checksum(prev);
+        adler.write_slice(bytes);
+        adler.checksum()
+    })
+}
+
+fn deflate_init(z: OpaqueTerm, level: Compression, zlib_header: bool) -> ErlangResult {
+    with_stream(z, |stream| match stream {
+        Stream::Idle => {
+            *stream = Stream::Deflate(Compress::new(level, zlib_header));
+            Ok(atoms::Ok.into())
+        }
+        _ => Err("already_initialized"),
+    })
+}
+
+fn inflate_init(z: OpaqueTerm, zlib_header: bool) -> ErlangResult {
+    with_stream(z, |stream| match stream {
+        Stream::Idle => {
+            *stream = Stream::Inflate {
+                decompress: Decompress::new(zlib_header),
+                zlib_header,
+                pending: vec![],
+            };
+            Ok(atoms::Ok.into())
+        }
+        _ => Err("already_initialized"),
+    })
+}
+
+/// Inflates `data` after the input left over from `safeInflate/2`, which stops once a chunk is
+/// output if `safe`
+fn inflate(
+    z: OpaqueTerm,
+    data: OpaqueTerm,
+    safe: bool,
+) -> Result<(Vec<Vec<u8>>, Progress), ErlangResult> {
+    let (Some(stream), Some(data)) = (stream(z), iodata_to_bytes(data)) else {
+        return Err(badarg(Trace::capture()));
+    };
+    let input = match &mut *stream.lock().unwrap() {
+        Stream::Inflate { pending, .. } => {
+            let mut input = std::mem::take(pending);
+            input.extend_from_slice(&data);
+            input
+        }
+        _ => return Err(raise("not_initialized")),
+    };
+    let limit = if safe { Some(CHUNK_SIZE) } else { None };
+    let run = Run::new(stream, input, Op::Inflate { limit });
+    scheduler::trampoline(run).map_err(raise)
+}
+
+/// Compresses the whole of `input`, as zlib data if `zlib_header`, or raw deflate data otherwise
+fn deflate_all(input: Vec<u8>, zlib_header: bool) -> Result<Vec<u8>, &'static str> {
+    let compress = Compress::new(Compression::default(), zlib_header);
+    let stream = Arc::new(Mutex::new(Stream::Deflate(compress)));
+    let run = Run::new(stream, input, Op::Deflate(FlushCompress::Finish));
+    scheduler::trampoline(run).map(|(output, _)| output.concat())
+}
+
+/// Decompresses the whole of `input`, returning the output and the number of bytes of `input` the
+/// compressed data took up, or `data_error` if it ends early
+fn inflate_all(input: Vec<u8>, zlib_header: bool) -> Result<(Vec<u8>, usize), &'static str> {
+    let stream = Arc::new(Mutex::new(Stream::Inflate {
+        decompress: Decompress::new(zlib_header),
+        zlib_header,
+        pending: vec![],
+    }));
+    let run = Run::new(stream.clone(), input, Op::Inflate { limit: None });
+    let (output, progress) = scheduler::trampoline(run)?;
+    let result = match &*stream.lock().unwrap() {
+        Stream::Inflate { decompress, .. } if progress == Progress::Ended => {
+            Ok((output.concat(), decompress.total_in() as usize))
+        }
+        _ => Err("data_error"),
+    };
+    result
+}
+
+/// Returns where the deflate data of the gzip data `input` starts, after its header
+fn gzip_body(input: &[u8]) -> Option<usize> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    if input.len() < GZIP_HEADER.len() || input[0..3] != GZIP_HEADER[0..3] {
+        return None;
+    }
+    let flags = input[3];
+    let mut pos = GZIP_HEADER.len();
+    if flags & FEXTRA != 0 {
+        let len = u16::from_le_bytes(input.get(pos..pos + 2)?.try_into().unwrap());
+        pos += 2 + len as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            pos += input.get(pos..)?.iter().position(|&byte| byte == 0)? + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    (pos <= input.len()).then_some(pos)
+}
+
+fn checksum(
+    z: OpaqueTerm,
+    prev: OpaqueTerm,
+    data: OpaqueTerm,
+    update: fn(u32, &[u8]) -> u32,
+) -> ErlangResult {
+    let prev = match prev.into() {
+        Term::Int(prev) => u32::try_from(prev).ok(),
+        _ => None,
+    };
+    let (Some(_), Some(prev), Some(data)) = (stream(z), prev, iodata_to_bytes(data)) else {
+        return badarg(Trace::capture());
+    };
+    ErlangResult::Ok(Term::Int(update(prev, &data) as i64).into())
+}
+
+fn stream(z: OpaqueTerm) -> Option<Arc<Mutex<Stream>>> {
+    let Term::Reference(reference) = z.into() else {
+        return None;
+    };
+    let zlib = reference.magic()?.downcast_ref::<Zlib>()?;
+    if let Stream::Closed = *zlib.0.lock().unwrap() {
+        return None;
+    }
+    Some(zlib.0.clone())
+}
+
+/// Calls `fun` with the stream `Z` locked, raising the reason it returns, if any
+fn with_stream<F>(z: OpaqueTerm, fun: F) -> ErlangResult
+where
+    F: FnOnce(&mut Stream) -> Result<OpaqueTerm, &'static str>,
+{
+    let Some(stream) = stream(z) else {
+        return badarg(Trace::capture());
+    };
+    let result = fun(&mut stream.lock().unwrap());
+    match result {
+        Ok(result) => ErlangResult::Ok(result),
+        Err(reason) => raise(reason),
+    }
+}
+
+fn parse_level(level: OpaqueTerm) -> Option<Compression> {
+    match level.into() {
+        Term::Atom(level) => match level.as_str() {
+            "none" => Some(Compression::none()),
+            "default" => Some(Compression::default()),
+            "best_speed" => Some(Compression::fast()),
+            "best_compression" => Some(Compression::best()),
+            _ => None,
+        },
+        Term::Int(level @ 0..=9) => Some(Compression::new(level as u32)),
+        _ => None,
+    }
+}
+
+/// Parses the `WindowBits` of a stream, returning whether it is of zlib rather than raw data
+fn parse_window_bits(window_bits: OpaqueTerm) -> Option<bool> {
+    match window_bits.into() {
+        Term::Int(8..=15) => Some(true),
+        Term::Int(-15..=-8) => Some(false),
+        _ => None,
+    }
+}
+
+fn make_chunks(proc: &Process, chunks: &[Vec<u8>]) -> OpaqueTerm {
+    let chunks = chunks
+        .iter()
+        .map(|chunk| make_binary(proc, chunk))
+        .collect::<Vec<_>>();
+    make_list(proc, &chunks)
+}
+
+fn binary_result(result: Result<Vec<u8>, &'static str>) -> ErlangResult {
+    match result {
+        Ok(bytes) => ErlangResult::Ok(with_process(|proc| make_binary(proc, &bytes))),
+        Err(reason) => raise(reason),
+    }
+}
+
+fn raise(reason: &str) -> ErlangResult {
+    ErlangResult::raise(atoms::Error, Term::Atom(atom(reason)), Trace::capture())
+}
+
+/// How far a [`Run`] got through its input
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Progress {
+    /// The output limit was reached first, and the rest of the input was left with the stream
+    Continue,
+    /// The input was all consumed
+    Drained,
+    /// The end of the compressed data was reached
+    Ended,
+}
+
+enum Op {
+    Deflate(FlushCompress),
+    /// Inflates until the input is all inflated or, given a limit, that many bytes are output
+    Inflate {
+        limit: Option<usize>,
+    },
+}
+
+/// Compression or decompression of the input to a stream, as a computation which can be suspended
+///
+/// The stream is locked only while a chunk is being output, so it is left in a consistent state
+/// whenever the process yields.
+struct Run {
+    stream: Arc<Mutex<Stream>>,
+    input: Vec<u8>,
+    /// How much of `input` has been consumed
+    pos: usize,
+    output: Vec<Vec<u8>>,
+    op: Op,
+}
+impl Run {
+    fn new(stream: Arc<Mutex<Stream>>, input: Vec<u8>, op: Op) -> Self {
+        Self {
+            stream,
+            input,
+            pos: 0,
+            output: vec![],
+            op,
+        }
+    }
+}
+impl Resumable for Run {
+    /// The output and how far the input was consumed, or an error to raise
+    type Output = Result<(Vec<Vec<u8>>, Progress), &'static str>;
+
+    fn resume(&mut self, budget: &mut usize) -> Step<Self::Output> {
+        loop {
+            if *budget == 0 {
+                return Step::Yield;
+            }
+            let mut stream = self.stream.lock().unwrap();
+            let input = &self.input[self.pos..];
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let (consumed, produced, status) = match (&mut *stream, &self.op) {
+                (Stream::Deflate(compress), Op::Deflate(flush)) => {
+                    let before = (compress.total_in(), compress.total_out());
+                    let Ok(status) = compress.compress(input, &mut chunk, *flush) else {
+                        return Step::Done(Err("stream_error"));
+                    };
+                    let after = (compress.total_in(), compress.total_out());
+                    (after.0 - before.0, after.1 - before.1, status)
+                }
+                (Stream::Inflate { decompress, .. }, Op::Inflate { .. }) => {
+                    let before = (decompress.total_in(), decompress.total_out());
+                    let flush = FlushDecompress::None;
+                    let Ok(status) = decompress.decompress(input, &mut chunk, flush) else {
+                        return Step::Done(Err("data_error"));
+                    };
+                    let after = (decompress.total_in(), decompress.total_out());
+                    (after.0 - before.0, after.1 - before.1, status)
+                }
+                _ => return Step::Done(Err("not_initialized")),
+            };
+            let (consumed, produced) = (consumed as usize, produced as usize);
+            self.pos += consumed;
+            chunk.truncate(produced);
+            if !chunk.is_empty() {
+                self.output.push(chunk);
+            }
+            *budget = budget.saturating_sub(((consumed + produced) / BYTES_PER_REDUCTION).max(1));
+
+            // Output is only held back by the stream when the chunk is filled
+            let progress = if status == Status::StreamEnd {
+                Some(Progress::Ended)
+            } else if produced == CHUNK_SIZE {
+                None
+            } else if self.pos == self.input.len() || consumed == 0 {
+                Some(Progress::Drained)
+            } else {
+                None
+            };
+            let progress = match (progress, &self.op) {
+                // Finishing a deflate stream must go on until its end is written
+                (Some(Progress::Drained), Op::Deflate(FlushCompress::Finish)) if consumed > 0 => {
+                    None
+                }
+                (None, Op::Inflate { limit: Some(limit) })
+                    if self.output.iter().map(Vec::len).sum::<usize>() >= *limit =>
+                {
+                    // Keep the rest of the input for the next call of `safeInflate/2`
+                    if let Stream::Inflate { pending, .. } = &mut *stream {
+                        *pending = self.input.split_off(self.pos);
+                    }
+                    Some(Progress::Continue)
+                }
+                (progress, _) => progress,
+            };
+            if let Some(progress) = progress {
+                return Step::Done(Ok((std::mem::take(&mut self.output), progress)));
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Halting the system, whether by `erlang:halt/0,1,2` or once there is nothing left to run, and
+//! the callbacks subsystems register to be run as it does.
+//!
+//! Once a halt has been requested, the scheduler stops scheduling processes, and the entry point
+//! shuts it down, see `Scheduler::shutdown`, which calls [`run`]. The callbacks registered with
+//! [`on_halt`] are then run, most recently registered first, so that e.g. open logs are closed
+//! properly, and are told whether to flush what they have buffered. When flushing, every port is
+//! then closed, and the drivers polled until the ports have flushed their queues, or until the
+//! flush timeout passes. Finally the status is returned as the exit code of the executable, via
+//! `firefly_entry`.
+//!
+//! A status of `abort` aborts the executable immediately, without running the callbacks, and a
+//! string status, the slogan of the crash dump in ERTS, is printed to standard error, with an exit
+//! code of 1. There are no crash dumps in this runtime.
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+/// Called with whether to flush buffered output, see [`on_halt`]
+pub(crate) type Callback = fn(bool);
+
+/// How long to wait for the drivers at a time while the ports flush their queues
+#[cfg(not(target_arch = "wasm32"))]
+const FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Set once a halt is requested, so that the scheduler can check it on each cycle cheaply
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+#[derive(Default)]
+struct State {
+    request: Option<Request>,
+    callbacks: Vec<(&'static str, Callback)>,
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.get_or_init(Default::default).lock().unwrap()
+}
+
+/// The status the system halts with, as given to `erlang:halt/1,2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Status {
+    /// The exit code of the executable
+    Code(u8),
+    /// Aborts the executable, without running the callbacks
+    Abort,
+    /// Printed before exiting with a code of 1
+    Slogan(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    pub status: Status,
+    /// Whether to flush buffered output and the queues of ports before exiting
+    pub flush: bool,
+    /// How long to wait for ports to flush their queues, or `None` to wait for as long as it takes
+    pub flush_timeout: Option<Duration>,
+}
+impl Request {
+    /// The request made when the system halts on its own, with `code`
+    fn exit(code: u8) -> Self {
+        Self {
+            status: Status::Code(code),
+            flush: true,
+            flush_timeout: None,
+        }
+    }
+}
+
+/// Registers `callback` to be run as the system halts, under `name`
+///
+/// A callback registered under the same name is replaced, so subsystems may call this each time
+/// they have something to close, e.g. whenever a file is opened.
+pub(crate) fn on_halt(name: &'static str, callback: Callback) {
+    let mut state = state();
+    let index = state.callbacks.iter().position(|(n, _)| *n == name);
+    match index {
+        Some(index) => state.callbacks[index].1 = callback,
+        None => state.callbacks.push((name, callback)),
+    }
+}
+
+/// Requests that the system halts, returning false if a halt was already requested
+///
+/// The first request wins, as the system is already halting when any other is made.
+pub(crate) fn request(request: Request) -> bool {
+    let mut state = state();
+    if state.request.is_some() {
+        return false;
+    }
+    state.request = Some(request);
+    REQUESTED.store(true, Ordering::Release);
+    true
+}
+
+/// Returns true once a halt has been requested
+pub(crate) fn is_requested() -> bool {
+    REQUESTED.load(Ordering::Acquire)
+}
+
+/// Halts the system as requested, or else with `code`, returning the exit code of the executable
+///
+/// This is called by the scheduler as it shuts down, once no process is left to run.
+pub(crate) fn run(code: u8) -> ExitCode {
+    let request = state()
+        .request
+        .take()
+        .unwrap_or_else(|| Request::exit(code));
+    let code = match request.status {
+        Status::Abort => std::process::abort(),
+        Status::Code(code) => code,
+        Status::Slogan(ref slogan) => {
+            eprintln!("{}", slogan);
+            1
+        }
+    };
+
+    // A callback may register others, e.g. by closing a file, and those are run too
+    loop {
+        let Some((_, callback)) = state().callbacks.pop() else {
+            break;
+        };
+        callback(request.flush);
+    }
+    if request.flush {
+        flush_ports(request.flush_timeout);
+    }
+
+    ExitCode::from(code)
+}
+
+/// Closes every port, waiting up to `timeout` for them to flush their queues
+#[cfg(not(target_arch = "wasm32"))]
+fn flush_ports(timeout: Option<Duration>) {
+    use instant::Instant;
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    for port in firefly_driver::ports() {
+        let _ = firefly_driver::close(port);
+    }
+    crate::erlang::port::deliver();
+    while firefly_driver::is_active() {
+        let timeout = match deadline {
+            None => FLUSH_INTERVAL,
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => left.min(FLUSH_INTERVAL),
+                _ => break,
+            },
+        };
+        if !firefly_driver::poll(Some(timeout)) {
+            std::thread::sleep(timeout);
+        }
+        crate::erlang::port::deliver();
+    }
+}
+
+/// There are no ports on wasm32
+#[cfg(target_arch = "wasm32")]
+fn flush_ports(_timeout: Option<Duration>) {}
@@ -1,7 +1,11 @@
-use firefly_rt::function::ErlangResult;
+use firefly_rt::error::unwind;
+use firefly_rt::function::{self, ErlangResult};
 use firefly_rt::term::{ListBuilder, OpaqueTerm};
 
 use crate::env;
+use crate::erlang::application;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use crate::erlang::gen;
 use crate::scheduler;
 
 extern "C-unwind" {
@@ -14,13 +18,19 @@ extern "C-unwind" {
 /// This function acts as the entry point for the top-level `init` process.
 ///
 /// Its job is to preprocess command-line arguments and boot the system.
-/// The actual boot process is handled in `init:boot/1`, or if substituted with
+/// Applications listed in the boot script bundled with the executable are started first,
+/// then the actual boot process is handled in `init:boot/1`, or if substituted with
 /// a different module, `Module:boot/1`.
 ///
+/// Once boot completes, any timers started by generic servers are run until none remain. In the
+/// browser this is left to the event loop, so that the page is not blocked.
+///
 /// NOTE: When this function is invoked, it is on the stack of the new process, not the scheduler.
 #[allow(improper_ctypes_definitions)]
 pub(crate) extern "C-unwind" fn start() -> ErlangResult {
-    scheduler::with_current_process(|process| {
+    application::boot()?;
+
+    let result = scheduler::with_current_process(|process| {
         let argv = env::argv();
         let args = {
             let mut builder = ListBuilder::new(process);
@@ -32,6 +42,10 @@ pub(crate) extern "C-unwind" fn start() -> ErlangResult {
                 .map(|ptr| ptr.into())
                 .unwrap_or(OpaqueTerm::NIL)
         };
-        unsafe { boot(args) }
-    })
+        unwind::catch(|| unsafe { function::resolve_tail_call(boot(args)) })
+    })?;
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    gen::run_timers();
+    ErlangResult::Ok(result)
 }
@@ -8,7 +8,7 @@ use firefly_number::{f16, BigInt, Sign, ToPrimitive};
 use firefly_rt::backtrace::Trace;
 use firefly_rt::error::ErlangException;
 use firefly_rt::function::ErlangResult;
-use firefly_rt::term::{atoms, Atom, BinaryData, BitSlice, Closure, Cons, Map, Tuple};
+use firefly_rt::term::{atoms, BinaryData, BitSlice, Closure, Cons, Map, Tuple};
 use firefly_rt::term::{MatchContext, MatchResult};
 use firefly_rt::term::{OpaqueTerm, Term, TermType};
 
@@ -204,8 +204,7 @@ pub extern "C-unwind" fn map_update_mut(
 
 #[inline]
 fn map_error(key: OpaqueTerm, trace: Arc<Trace>) -> NonNull<ErlangException> {
-    let exception = ErlangException::new(atoms::Error, key.into(), trace);
-    unsafe { NonNull::new_unchecked(Box::into_raw(exception)) }
+    ErlangException::new(atoms::Error, key.into(), trace).into_raw()
 }
 
 /// This builtin differs from erlang:map_get/2 in that it is only called in contexts where
@@ -240,10 +239,12 @@ pub extern "C-unwind" fn raise(
     debug_assert!(!trace.is_null());
     let trace = unsafe { Trace::from_raw(trace) };
     let kind: Term = kind.into();
-    let kind: Atom = kind.try_into().unwrap();
-    let exception = match kind.as_str() {
-        "throw" | "error" | "exit" => ErlangException::new(kind, reason.into(), trace),
-        other => panic!("invalid exception kind: {}", &other),
+    // An invalid class is converted to badarg, matching the semantics of erlang:raise/3
+    let exception = match kind {
+        Term::Atom(kind) if ErlangException::is_valid_kind(kind) => {
+            ErlangException::new(kind, reason.into(), trace)
+        }
+        _ => ErlangException::badarg(trace),
     };
     Box::into_raw(exception)
 }
@@ -253,9 +254,17 @@ pub extern "C-unwind" fn cleanup(ptr: *mut ErlangException) {
     let _ = unsafe { Box::from_raw(ptr) };
 }
 
+/// Called by generated code to yield, see `Scheduler::yield_from_code`
 #[export_name = "__firefly_builtin_yield"]
 pub unsafe extern "C-unwind" fn process_yield() -> bool {
-    scheduler::with_current(|scheduler| scheduler.process_yield())
+    scheduler::with_current(|scheduler| scheduler.yield_from_code())
+}
+
+/// Called by generated code once it has consumed the reductions granted to it, see
+/// `firefly_rt::process::reductions`
+#[export_name = "__firefly_yield_point"]
+pub unsafe extern "C-unwind" fn yield_point() {
+    scheduler::with_current(|scheduler| scheduler.yield_point())
 }
 
 #[allow(improper_ctypes_definitions)]
@@ -462,7 +471,9 @@ pub extern "C-unwind" fn bs_finish(buffer: NonNull<BitVec>) -> ErlangResult<Opaq
                     let b = unsafe { Rc::get_mut(&mut bin).unwrap_unchecked() };
                     b.copy_from_slice(&bytes[..n]);
                 }
-                ok!(bin.into())
+                let bin: OpaqueTerm = bin.into();
+                proc.track_binary(bin, n);
+                ok!(bin)
             }
         }
     })
@@ -712,8 +723,5 @@ pub unsafe extern "C-unwind" fn bs_test_tail(ctx: NonNull<MatchContext>, size: u
 }
 
 pub(self) fn badarg(trace: Arc<Trace>) -> NonNull<ErlangException> {
-    crate::erlang::raise2(atoms::Badarg.into(), unsafe {
-        NonNull::new_unchecked(Trace::into_raw(trace))
-    })
-    .unwrap_err()
+    ErlangException::badarg(trace).into_raw()
 }
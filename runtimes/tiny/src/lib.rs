@@ -1,3 +1,4 @@
+#![feature(allocator_api)]
 #![feature(c_unwind)]
 #![feature(once_cell)]
 #![feature(ptr_metadata)]
@@ -5,21 +6,53 @@
 #![feature(thread_local)]
 #![feature(let_else)]
 #![feature(iterator_try_collect)]
+#![feature(const_btree_new)]
 
 extern crate firefly_crt;
 
 mod env;
 mod erlang;
+mod halt;
 mod init;
 mod intrinsic;
+mod memory;
+mod metrics;
+mod runtime;
 mod scheduler;
+mod sim;
+#[cfg(not(target_arch = "wasm32"))]
 mod sys;
+mod time;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod web;
 
+pub use self::metrics::Snapshot as MetricsSnapshot;
+pub use self::metrics::{Kind as MetricKind, Metric, Sink as MetricsSink};
+pub use self::runtime::{Builder, Runtime};
+
+/// Executables enforce the limit on memory set with `+Mlimit`, see `memory`, on top of the backing
+/// allocator selected by the `jemalloc` and `mimalloc` features
+#[cfg(feature = "entry")]
+#[global_allocator]
+static ALLOCATOR: firefly_alloc::allocators::Limited<firefly_alloc::allocators::Backing> =
+    firefly_alloc::allocators::Limited::new(firefly_alloc::allocators::Backing);
+
+#[cfg(not(target_arch = "wasm32"))]
 use bus::Bus;
+#[cfg(target_os = "wasi")]
+use std::ffi::OsString;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 use std::process::ExitCode;
 
+#[cfg(not(target_arch = "wasm32"))]
 use self::sys::break_handler::{self, Signal};
 
+/// The entry point for native executables, returning the exit status of the system, e.g. as given
+/// to `erlang:halt/1`, see `halt`
+///
+/// On wasm32, the entry point is defined by `firefly_rt_wasi` for WASI, which calls [`run`], and by
+/// the `web` module in the browser, where the event loop cannot be blocked.
+#[cfg(not(target_arch = "wasm32"))]
 #[export_name = "firefly_entry"]
 pub unsafe extern "C" fn main() -> i32 {
     use std::process::Termination;
@@ -29,8 +62,11 @@ pub unsafe extern "C" fn main() -> i32 {
     main_internal(name, version, vec![]).report().to_i32()
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 fn main_internal(_name: &str, _version: &str, _argv: Vec<String>) -> ExitCode {
-    self::env::init(std::env::args_os()).unwrap();
+    // The arguments given to the C `main`, or those known to the standard library when embedded
+    let argv = firefly_crt::argv().unwrap_or_else(|| std::env::args_os().collect());
+    self::env::init(argv.into_iter()).unwrap();
 
     // This bus is used to receive signals across threads in the system
     let mut bus: Bus<Signal> = Bus::new(1);
@@ -40,17 +76,19 @@ fn main_internal(_name: &str, _version: &str, _argv: Vec<String>) -> ExitCode {
     break_handler::init(bus);
 
     scheduler::init();
+    sys::watchdog::start();
     scheduler::with_current(|scheduler| scheduler.spawn_init()).unwrap();
+    let mut shutdown = None;
     loop {
         // Run the scheduler for a cycle
         let scheduled = scheduler::with_current(|scheduler| scheduler.run_once());
         // Check for system signals, and terminate if needed
         if let Ok(sig) = rx1.try_recv() {
             match sig {
-                // For now, SIGINT initiates a controlled shutdown
-                Signal::INT => {
-                    // If an error occurs, report it before shutdown
-                    break;
+                // SIGINT and SIGTERM initiate a controlled shutdown, once the connections are
+                // drained, see `sys::drain`
+                sig @ (Signal::INT | Signal::TERM) => {
+                    shutdown.get_or_insert(sig);
                 }
                 // Technically, we may never see these signals directly,
                 // we may just be terminated out of hand; but just in case,
@@ -64,6 +102,16 @@ fn main_internal(_name: &str, _version: &str, _argv: Vec<String>) -> ExitCode {
                 _ => (),
             }
         }
+        if let Some(sig) = &shutdown {
+            sys::drain::poll();
+            if !firefly_driver::is_active() {
+                match sig {
+                    // If an error occurs, report it before shutdown
+                    Signal::INT => break,
+                    _ => return ExitCode::FAILURE,
+                }
+            }
+        }
         // If the scheduler scheduled a process this cycle, then we're busy
         // and should keep working until we have an idle period
         if scheduled {
@@ -75,3 +123,21 @@ fn main_internal(_name: &str, _version: &str, _argv: Vec<String>) -> ExitCode {
 
     scheduler::with_current(|s| s.shutdown())
 }
+
+/// Boots the system and runs it until it halts
+///
+/// This is used by the entry point of `firefly_rt_wasi`, as WASI has neither signals nor threads, so
+/// the scheduler is simply run until there is nothing left to do.
+#[cfg(target_os = "wasi")]
+pub fn run<I>(argv: I) -> ExitCode
+where
+    I: ExactSizeIterator<Item = OsString>,
+{
+    self::env::init(argv).unwrap();
+
+    scheduler::init();
+    scheduler::with_current(|scheduler| scheduler.spawn_init()).unwrap();
+    while scheduler::with_current(|scheduler| scheduler.run_once()) {}
+
+    scheduler::with_current(|s| s.shutdown())
+}
@@ -0,0 +1,218 @@
+//! A limit on the memory used by the system as a whole, set with the `+Mlimit Size` flag, or with
+//! `Builder::memory_limit` when embedding the runtime.
+//!
+//! The limit is enforced by the global allocator, `firefly_alloc::allocators::Limited`, which is
+//! installed by executables, i.e. when the `entry` feature is enabled. Programs embedding the
+//! runtime must install it themselves for the limit to have any effect. An allocation which would
+//! exceed the limit fails, aborting the program with an error, rather than leaving it to be killed
+//! by the kernel at some unpredictable point.
+//!
+//! Before then, once the memory allocated passes the high-water mark, the system degrades
+//! gracefully: new processes are refused with `system_limit`, and the process set with
+//! `erlang:system_monitor/2` is sent `{monitor, Pid, memory_high, [{allocated, A}, {limit, L}]}`,
+//! where `Pid` is the process which was running at the time. The event is sent again only after the
+//! memory allocated has fallen below the low-water mark. Processes are never garbage collected in
+//! this runtime, so there is no collection to trigger in the meantime, but the reference-counted
+//! binaries of processes which have exited are swept before the monitor is notified, see `sweep`.
+//!
+//! Process heaps, binaries, ETS tables and driver buffers are each allocated by an allocator of
+//! their own, see `firefly_alloc::allocators::carriers`, which is tuned with the same flags as in
+//! ERTS, `+M<S>as bf|aoff`, `+M<S>sbct Size` and `+M<S>lmbcs Size`, where `<S>` is `H`, `B`, `E`
+//! or `D` respectively, and sizes are in kilobytes. There are no ETS tables or drivers in this
+//! runtime, so the latter two allocators are never used.
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use firefly_alloc::allocators::carriers::{AllocatorType, CarrierStats, Strategy};
+use firefly_alloc::allocators::limited;
+use firefly_rt::term::ProcessId;
+
+use crate::erlang::system_monitor;
+use crate::metrics::{Kind, Snapshot};
+use crate::scheduler;
+
+/// The percentage of the limit below which the memory allocated must fall before the monitor is
+/// notified again
+const LOW_WATER_PERCENT: usize = 80;
+
+/// Whether the system is above the high-water mark, and the monitor has been notified
+static HIGH: AtomicBool = AtomicBool::new(false);
+
+/// The number of sweeps of the binaries of exited processes, and the bytes they released
+static SWEEPS: AtomicU64 = AtomicU64::new(0);
+static SWEPT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Parses a size in bytes, optionally suffixed with `K`, `M` or `G`, e.g. `512M`
+pub(crate) fn parse_size(size: &str) -> Option<usize> {
+    let (digits, multiplier) = match size.char_indices().last()? {
+        (i, 'k' | 'K') => (&size[..i], 1 << 10),
+        (i, 'm' | 'M') => (&size[..i], 1 << 20),
+        (i, 'g' | 'G') => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|n| *n > 0)
+}
+
+/// Returns the allocator and option set by `flag` if it is an allocator flag, e.g. `+MHas`
+pub(crate) fn allocator_flag(flag: &str) -> Option<(AllocatorType, &str)> {
+    let mut chars = flag.strip_prefix("+M")?.chars();
+    let ty = chars.next()?;
+    let ty = AllocatorType::ALL.into_iter().find(|t| t.flag() == ty)?;
+    let option = chars.as_str();
+    matches!(option, "as" | "sbct" | "lmbcs").then_some((ty, option))
+}
+
+/// Sets `option` of the allocator of type `ty` from the value given to its flag
+pub(crate) fn set_allocator_option(
+    ty: AllocatorType,
+    option: &str,
+    value: Option<&str>,
+) -> Result<(), String> {
+    let allocator = ty.allocator();
+    let mut options = allocator.options();
+    match option {
+        "as" => {
+            let strategy = value.and_then(Strategy::from_name);
+            options.strategy =
+                strategy.ok_or_else(|| format!("expects bf or aoff, got {:?}", value))?;
+        }
+        _ => {
+            let size = value
+                .and_then(|size| size.parse::<usize>().ok())
+                .and_then(|kb| kb.checked_mul(1024))
+                .filter(|size| *size > 0)
+                .ok_or_else(|| format!("expects a size in kilobytes, got {:?}", value))?;
+            if option == "sbct" {
+                options.sbc_threshold = size;
+            } else {
+                options.mbc_size = size;
+            }
+        }
+    }
+    allocator.set_options(options);
+    Ok(())
+}
+
+/// Sets the limit on the memory allocated by the system, in bytes
+pub(crate) fn set_limit(limit: Option<usize>) {
+    limited::set_limit(limit);
+}
+
+/// Returns true if new processes may be spawned, i.e. the system is not under memory pressure
+pub(crate) fn may_spawn() -> bool {
+    !limited::is_under_pressure()
+}
+
+/// Checks the memory allocated after `pid` has run, notifying the monitor of any change in
+/// pressure
+pub(crate) fn poll(pid: ProcessId) {
+    let Some(limit) = limited::limit() else { return; };
+    if limited::is_under_pressure() && !HIGH.load(Ordering::Relaxed) {
+        sweep();
+    }
+    let allocated = limited::allocated();
+    if limited::is_under_pressure() {
+        if !HIGH.swap(true, Ordering::Relaxed) {
+            system_monitor::memory_high(pid, allocated, limit);
+        }
+    } else if allocated < limit / 100 * LOW_WATER_PERCENT {
+        HIGH.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Releases the reference-counted binaries allocated by processes which have exited, returning the
+/// number of bytes released
+///
+/// Processes are never garbage collected, so the binaries a process allocates are referred to by
+/// its heap until it exits, and only released by a sweep once nothing refers to the process any
+/// longer, see `Scheduler::sweep_orphans`. A binary is freed once no other process refers to it.
+pub(crate) fn sweep() -> usize {
+    let (_, bytes) = scheduler::with_current(|scheduler| scheduler.sweep_orphans());
+    SWEEPS.fetch_add(1, Ordering::Relaxed);
+    SWEPT_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+    bytes
+}
+
+/// Returns the number of sweeps so far, and the total number of bytes they released
+pub(crate) fn sweeps() -> (u64, u64) {
+    (
+        SWEEPS.load(Ordering::Relaxed),
+        SWEPT_BYTES.load(Ordering::Relaxed),
+    )
+}
+
+/// Publishes the metrics of the sweeps and of the allocators, see `crate::metrics`
+pub(crate) fn publish_metrics(snapshot: &mut Snapshot) {
+    let (sweeps, bytes) = sweeps();
+    snapshot.counter(
+        "firefly_gc_sweeps_total",
+        "Sweeps of the binaries of exited processes",
+        sweeps,
+    );
+    snapshot.counter(
+        "firefly_gc_reclaimed_bytes_total",
+        "Bytes of binaries released by sweeps",
+        bytes,
+    );
+    snapshot.gauge(
+        "firefly_memory_allocated_bytes",
+        "Bytes allocated by the program, if its global allocator enforces +Mlimit",
+        limited::allocated() as f64,
+    );
+    if let Some(limit) = limited::limit() {
+        snapshot.gauge(
+            "firefly_memory_limit_bytes",
+            "The limit set with +Mlimit",
+            limit as f64,
+        );
+    }
+
+    let stats = AllocatorType::ALL.map(|ty| (ty.name(), ty.allocator().stats()));
+    type Size = (&'static str, &'static str, fn(&CarrierStats) -> usize);
+    let sizes: [Size; 2] = [
+        (
+            "firefly_allocator_blocks_bytes",
+            "Bytes of the blocks allocated, by allocator and kind of carrier",
+            |stats| stats.blocks_size,
+        ),
+        (
+            "firefly_allocator_carriers_bytes",
+            "Bytes of the carriers blocks are allocated from, by allocator and kind of carrier",
+            |stats| stats.carriers_size,
+        ),
+    ];
+    for (name, help, size) in sizes {
+        for (allocator, stats) in stats.iter() {
+            for (carrier, carrier_stats) in [("mbcs", &stats.mbcs), ("sbcs", &stats.sbcs)] {
+                let labels = vec![
+                    ("allocator", allocator.to_string()),
+                    ("carrier", carrier.to_string()),
+                ];
+                snapshot.push(name, help, Kind::Gauge, labels, size(carrier_stats) as f64);
+            }
+        }
+    }
+    for (allocator, stats) in stats.iter() {
+        let calls = [
+            ("alloc", stats.calls.alloc),
+            ("free", stats.calls.free),
+            ("realloc", stats.calls.realloc),
+        ];
+        for (call, count) in calls {
+            let labels = vec![
+                ("allocator", allocator.to_string()),
+                ("call", call.to_string()),
+            ];
+            snapshot.push(
+                "firefly_allocator_calls_total",
+                "Calls made to each allocator",
+                Kind::Counter,
+                labels,
+                count as f64,
+            );
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! The metrics the runtime publishes to pluggable sinks, e.g. to export them to monitoring.
+//!
+//! Once a sink is added, the scheduler takes a [`Snapshot`] of the metrics at most once a second
+//! while processes are running, and hands it to each sink in turn. The scheduler, the sweeps of
+//! binaries which stand in for garbage collection, and the allocators each publish their own
+//! metrics into the snapshot, see `Scheduler::publish_metrics` and `crate::memory`.
+//!
+//! Sinks are added with [`add_sink`], or with `Builder::metrics_sink` when embedding the runtime.
+//! A sink is called on the scheduler thread, so it should only hand the snapshot off, e.g. to a
+//! thread of its own, rather than block. With `+metrics Port`, the runtime adds a sink of its own,
+//! which serves the latest snapshot in the Prometheus text format, see `crate::sys::metrics`.
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use instant::Instant;
+
+use crate::scheduler;
+
+/// How often a snapshot is taken, at most
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether any sink has been added, so that there is nothing to do otherwise
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+#[derive(Default)]
+struct State {
+    sinks: Vec<Arc<dyn Sink>>,
+    /// When the last snapshot was taken
+    last: Option<Instant>,
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.get_or_init(Default::default).lock().unwrap()
+}
+
+/// A destination for the metrics of the runtime
+pub trait Sink: Send + Sync {
+    /// Receives a snapshot of the metrics, taken at `snapshot.at()`
+    fn publish(&self, snapshot: &Snapshot);
+}
+
+/// Whether a metric is a counter, which only ever grows, or a gauge, which goes up and down
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+}
+
+/// The value of a metric, for a combination of its labels
+#[derive(Debug, Clone)]
+pub struct Metric {
+    /// The name of the metric, which for counters ends in `_total` as Prometheus expects
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: Kind,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+/// The metrics published at a point in time, with those of the same name next to each other
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    at: Instant,
+    metrics: Vec<Metric>,
+}
+impl Snapshot {
+    fn new() -> Self {
+        Self {
+            at: Instant::now(),
+            metrics: Vec::new(),
+        }
+    }
+
+    /// Returns when the snapshot was taken
+    pub fn at(&self) -> Instant {
+        self.at
+    }
+
+    pub fn metrics(&self) -> &[Metric] {
+        self.metrics.as_slice()
+    }
+
+    /// Publishes the value of a counter
+    pub(crate) fn counter(&mut self, name: &'static str, help: &'static str, value: u64) {
+        self.push(name, help, Kind::Counter, vec![], value as f64);
+    }
+
+    /// Publishes the value of a gauge
+    pub(crate) fn gauge(&mut self, name: &'static str, help: &'static str, value: f64) {
+        self.push(name, help, Kind::Gauge, vec![], value);
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        kind: Kind,
+        labels: Vec<(&'static str, String)>,
+        value: f64,
+    ) {
+        self.metrics.push(Metric {
+            name,
+            help,
+            kind,
+            labels,
+            value,
+        });
+    }
+
+    /// Returns the snapshot in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let mut last = None;
+        for metric in self.metrics.iter() {
+            if last != Some(metric.name) {
+                let kind = match metric.kind {
+                    Kind::Counter => "counter",
+                    Kind::Gauge => "gauge",
+                };
+                writeln!(text, "# HELP {} {}", metric.name, metric.help).unwrap();
+                writeln!(text, "# TYPE {} {}", metric.name, kind).unwrap();
+                last = Some(metric.name);
+            }
+            text.push_str(metric.name);
+            if !metric.labels.is_empty() {
+                text.push('{');
+                for (i, (label, value)) in metric.labels.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(text, "{}{}=\"{}\"", sep, label, Escaped(value)).unwrap();
+                }
+                text.push('}');
+            }
+            writeln!(text, " {}", metric.value).unwrap();
+        }
+        text
+    }
+}
+
+/// Writes a label value with backslashes, quotes and newlines escaped
+struct Escaped<'a>(&'a str);
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds a sink, to which every snapshot is published from then on
+pub fn add_sink(sink: Arc<dyn Sink>) {
+    state().sinks.push(sink);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Publishes a snapshot to the sinks if the last one was taken long enough ago
+///
+/// This is called by the scheduler after each slice, so costs a single load unless a sink was
+/// added.
+pub(crate) fn poll() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let sinks = {
+        let mut state = state();
+        if matches!(state.last, Some(last) if last.elapsed() < INTERVAL) {
+            return;
+        }
+        state.last = Some(Instant::now());
+        state.sinks.clone()
+    };
+    let mut snapshot = Snapshot::new();
+    scheduler::with_current(|scheduler| scheduler.publish_metrics(&mut snapshot));
+    crate::memory::publish_metrics(&mut snapshot);
+    for sink in sinks {
+        sink.publish(&snapshot);
+    }
+}
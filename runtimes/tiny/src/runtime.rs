@@ -0,0 +1,239 @@
+//! An API for embedding the runtime in a Rust program, rather than running it from the `main` of a
+//! generated executable.
+//!
+//! The host program links against the compiled Erlang code as usual, and against this crate with
+//! its `entry` feature disabled, so that it keeps its own `main`. It then starts the runtime with a
+//! [`Builder`], and drives it from the thread which built it, e.g.:
+//!
+//! ```ignore
+//! let runtime = Runtime::builder().arg("-config").arg("app.config").build()?;
+//! let pid = runtime.with_heap(|proc| {
+//!     let pid = GcBox::new_in(Pid::Local { id: runtime.pid() }, proc).unwrap();
+//!     OpaqueTerm::from(Term::Pid(pid))
+//! });
+//! runtime.spawn("worker", "start", &[pid])?;
+//! runtime.run();
+//! while let Some(message) = runtime.receive() {
+//!     // ...
+//! }
+//! let code = runtime.shutdown();
+//! ```
+//!
+//! The host participates in the system as the root process: messages Erlang code sends to the pid
+//! returned by [`Runtime::pid`] are placed in a mailbox read with [`Runtime::receive`], and the
+//! host can send messages to servers registered by name with [`Runtime::send`]. Terms passed to the
+//! runtime are copied to heap fragments, so they can be allocated anywhere, e.g. with
+//! [`Runtime::with_heap`].
+//!
+//! The scheduler belongs to the thread which built the runtime, so [`Runtime`] is neither `Send`
+//! nor `Sync`, and only one runtime may be started per program.
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::marker::PhantomData;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use anyhow::{anyhow, bail};
+
+use firefly_rt::function::{self, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::{Atom, OpaqueTerm, ProcessId};
+
+use crate::env;
+use crate::erlang::gen::{self, Message};
+use crate::erlang::util::*;
+use crate::memory;
+use crate::metrics::{self, Sink};
+use crate::scheduler;
+
+/// Set once a runtime has been built, as the global state it initializes cannot be reset
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// The messages sent to the host program which have not yet been received
+static MAILBOX: OnceLock<Mutex<VecDeque<OpaqueTerm>>> = OnceLock::new();
+
+/// Configures and starts a [`Runtime`]
+pub struct Builder {
+    argv: Vec<OsString>,
+    boot: bool,
+    memory_limit: Option<usize>,
+    metrics_sinks: Vec<Arc<dyn Sink>>,
+}
+impl Builder {
+    fn new() -> Self {
+        let arg0 = std::env::args_os()
+            .next()
+            .unwrap_or_else(|| OsString::from(env!("CARGO_PKG_NAME")));
+        Self {
+            argv: vec![arg0],
+            boot: true,
+            memory_limit: None,
+            metrics_sinks: vec![],
+        }
+    }
+
+    /// Appends `arg` to the arguments the system is booted with, as if passed on the command line
+    pub fn arg<S: Into<OsString>>(mut self, arg: S) -> Self {
+        self.argv.push(arg.into());
+        self
+    }
+
+    /// Appends each of `args` to the arguments the system is booted with
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.argv.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Whether to boot the system, i.e. start the applications in the boot script and call
+    /// `init:boot/1`, the first time the runtime is run
+    ///
+    /// This is enabled by default. When disabled, only processes spawned by the host are run.
+    pub fn boot(mut self, boot: bool) -> Self {
+        self.boot = boot;
+        self
+    }
+
+    /// Limits the memory allocated by the program as a whole to `bytes`, as with `+Mlimit`
+    ///
+    /// This is only enforced if the program uses `firefly_alloc::allocators::Limited` as its global
+    /// allocator, see `crate::memory`.
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Publishes the metrics of the runtime to `sink`, about once a second while it runs
+    ///
+    /// See `crate::metrics` for the metrics published, and `+metrics` for serving them to
+    /// Prometheus instead.
+    pub fn metrics_sink<S: Sink + 'static>(mut self, sink: S) -> Self {
+        self.metrics_sinks.push(Arc::new(sink));
+        self
+    }
+
+    /// Initializes the runtime and the scheduler for the current thread
+    ///
+    /// Returns an error if a runtime has already been built in this program, or if the compiled
+    /// code linked into it is invalid.
+    pub fn build(self) -> anyhow::Result<Runtime> {
+        if STARTED.swap(true, Ordering::SeqCst) {
+            bail!("the runtime has already been started");
+        }
+        firefly_crt::init().map_err(|code| anyhow!("failed to initialize runtime ({})", code))?;
+        env::init(self.argv.into_iter())?;
+        if self.memory_limit.is_some() {
+            memory::set_limit(self.memory_limit);
+        }
+        for sink in self.metrics_sinks {
+            metrics::add_sink(sink);
+        }
+
+        scheduler::init();
+        if self.boot {
+            scheduler::with_current(|scheduler| scheduler.spawn_init())?;
+        }
+
+        Ok(Runtime {
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// A handle to the runtime embedded in the current program
+pub struct Runtime {
+    // The scheduler is owned by the thread which built the runtime
+    _marker: PhantomData<*const ()>,
+}
+impl Runtime {
+    /// Returns a builder with which to configure and start the runtime
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Returns the pid of the host program, to which Erlang code can send messages
+    pub fn pid(&self) -> ProcessId {
+        scheduler::with_current(|scheduler| scheduler.root_pid())
+    }
+
+    /// Applies `fun` to the process of the host program, e.g. to allocate terms on its heap
+    pub fn with_heap<F, R>(&self, fun: F) -> R
+    where
+        F: FnOnce(&Process) -> R,
+    {
+        with_process(fun)
+    }
+
+    /// Spawns a process which calls `Module:Function(Args...)`, returning its pid
+    ///
+    /// The process starts the next time the runtime is run. Returns an error if the function is not
+    /// exported, or if the system is running out of memory.
+    pub fn spawn(
+        &self,
+        module: &str,
+        function: &str,
+        args: &[OpaqueTerm],
+    ) -> anyhow::Result<ProcessId> {
+        let mfa = ModuleFunctionArity::new(atom(module), atom(function), args.len());
+        let Some(callee) = function::find_symbol(&mfa) else {
+            bail!("{} is not exported", &mfa);
+        };
+        let args = args.iter().copied().map(make_global).collect();
+        let process = scheduler::with_current(|scheduler| scheduler.spawn(mfa, callee, args))?;
+        Ok(process.pid())
+    }
+
+    /// Sends `message` to the server registered as `name`, returning false if there is no such
+    /// server
+    ///
+    /// The message is handled before this returns.
+    pub fn send(&self, name: &str, message: OpaqueTerm) -> bool {
+        let server = Atom::try_from_str_existing(name)
+            .ok()
+            .and_then(|name| gen::whereis(name.into()));
+        let Some(pid) = server else {
+            return false;
+        };
+        gen::cast(pid, Message::Info(make_global(message)));
+        true
+    }
+
+    /// Runs processes until none are runnable, returning false if there were none to run
+    ///
+    /// When booting, this returns once the boot function has returned and no server has a pending
+    /// timeout.
+    pub fn run(&self) -> bool {
+        let mut scheduled = false;
+        while scheduler::with_current(|scheduler| scheduler.run_once()) {
+            scheduled = true;
+        }
+        scheduled
+    }
+
+    /// Returns the oldest message sent to the host program which has not yet been received, if any
+    pub fn receive(&self) -> Option<OpaqueTerm> {
+        mailbox().pop_front()
+    }
+
+    /// Runs any remaining processes, and returns the exit code of the system
+    pub fn shutdown(self) -> ExitCode {
+        self.run();
+        scheduler::with_current(|scheduler| scheduler.shutdown())
+    }
+}
+
+/// Places `message`, which must be allocated in a heap fragment, in the mailbox of the host program
+pub(crate) fn deliver(message: OpaqueTerm) {
+    mailbox().push_back(message);
+}
+
+fn mailbox() -> MutexGuard<'static, VecDeque<OpaqueTerm>> {
+    MAILBOX
+        .get_or_init(|| Mutex::new(VecDeque::new()))
+        .lock()
+        .unwrap()
+}
@@ -1,21 +1,34 @@
 mod exit;
+mod msacc;
 mod queue;
+mod tasks;
 
+#[cfg(not(target_arch = "wasm32"))]
 use std::arch::global_asm;
 use std::cell::{OnceCell, UnsafeCell};
+use std::collections::{BTreeMap, BTreeSet};
 use std::mem;
+use std::ops::Bound;
 use std::ptr;
 use std::sync::{
-    atomic::{AtomicI32, AtomicU64, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering},
+    Arc, Weak,
 };
 use std::thread::{self, ThreadId};
 
-use firefly_rt::function::{DynamicCallee, ModuleFunctionArity};
-use firefly_rt::process::{Process, ProcessStatus};
-use firefly_rt::term::{OpaqueTerm, Pid, ProcessId};
+use instant::Instant;
 
+use firefly_rt::function::{self, DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::{
+    reductions, Priority, Process, ProcessStatus, Resumable, SpawnOptions, Step, MAX_REDUCTIONS,
+};
+use firefly_rt::term::{atoms, OpaqueTerm, Pid, ProcessId, ReferenceId};
+
+pub use self::msacc::{Msacc, State as Microstate};
 use self::queue::RunQueue;
+use self::tasks::SystemTasks;
+pub(crate) use self::tasks::Task;
+use crate::metrics::{Kind, Snapshot};
 
 #[thread_local]
 pub static CURRENT_PROCESS: UnsafeCell<Option<Arc<Process>>> = UnsafeCell::new(None);
@@ -47,6 +60,52 @@ where
     fun(p)
 }
 
+/// Runs `computation` to completion on behalf of the current process, which is yielded each time
+/// it runs out of reductions, so that other processes can run in the meantime
+///
+/// This is how long-running native functions are preempted, see `firefly_rt::process::reductions`.
+/// The computation keeps its state on the stack of the process while it is suspended. Computations
+/// run by the scheduler itself, i.e. outside of any process, are never suspended.
+///
+/// The process is yielded with `process_yield` rather than `yield_from_code`, so it runs no system
+/// tasks while suspended here, and its heap is not collected, see `request_task`. A computation
+/// may therefore hold terms on the heap of the process across a yield, which the stack maps could
+/// not describe, as its frames are native.
+///
+/// The budget of the computation is what is left of that of the process once the reductions
+/// consumed by generated code since they were last granted are charged to it, and what is left
+/// of it once the computation is done is granted back to generated code.
+pub(crate) fn trampoline<R: Resumable>(mut computation: R) -> R::Output {
+    loop {
+        let (process, is_root) =
+            with_current(|scheduler| (scheduler.current_process(), scheduler.is_root()));
+        let left = if is_root {
+            usize::MAX
+        } else {
+            process.reduce(reductions::settle());
+            process.reductions_left()
+        };
+        let mut budget = left;
+        let step = computation.resume(&mut budget);
+        if !is_root {
+            process.reduce(left - budget);
+        }
+        match step {
+            Step::Done(output) => {
+                if !is_root {
+                    reductions::grant(process.reductions_left());
+                }
+                break output;
+            }
+            Step::Yield if is_root => continue,
+            Step::Yield => {
+                drop(process);
+                with_current(|scheduler| scheduler.process_yield());
+            }
+        }
+    }
+}
+
 struct SchedulerData {
     process: Arc<Process>,
     registers: UnsafeCell<CalleeSavedRegisters>,
@@ -77,17 +136,49 @@ impl SchedulerData {
 unsafe impl Send for SchedulerData {}
 unsafe impl Sync for SchedulerData {}
 
+/// A process which has exited, and the binaries it allocated, with their sizes
+type Orphan = (Weak<Process>, Vec<(OpaqueTerm, usize)>);
+
 pub struct Scheduler {
     pub id: ThreadId,
     // References are always 64-bits even on 32-bit platforms
-    #[allow(dead_code)]
     next_reference_id: AtomicU64,
     // In this runtime, we aren't doing work-stealing, so the run queue
     // is never accessed by any other thread
     run_queue: UnsafeCell<RunQueue>,
+    // The processes which have been suspended, which are kept out of the run queue until they are
+    // resumed, see `erlang::suspend`
+    suspended: UnsafeCell<BTreeMap<ProcessId, Arc<SchedulerData>>>,
+    // The system tasks queued on processes, and the processes blocked until a task they requested
+    // is done, which are kept out of the run queue until it is, see `request_task`
+    tasks: UnsafeCell<SystemTasks>,
+    blocked: UnsafeCell<BTreeMap<ProcessId, Arc<SchedulerData>>>,
     prev: UnsafeCell<Option<Arc<SchedulerData>>>,
     current: UnsafeCell<Arc<SchedulerData>>,
+    root: ProcessId,
+    // The function and arguments of each process spawned via `spawn` which has not yet started
+    spawned: UnsafeCell<BTreeMap<ProcessId, (DynamicCallee, Vec<OpaqueTerm>)>>,
+    // The pids of all processes which have been spawned and have not yet exited, in order, so that
+    // they can be enumerated from any point without copying the table
+    live: UnsafeCell<BTreeSet<ProcessId>>,
+    // The binaries allocated by processes which have exited, held until nothing refers to the
+    // process any longer, see `sweep_orphans`
+    orphans: UnsafeCell<Vec<Orphan>>,
     halt_code: AtomicI32,
+    // The number of times a process has been swapped out, and the reductions consumed by processes
+    // up to when they were last swapped out, see `erlang:statistics/1`
+    context_switches: AtomicU64,
+    reductions: AtomicU64,
+    // When the scheduler was created, and the nanoseconds since then spent running processes, see
+    // `wall_time`
+    started: Instant,
+    busy: AtomicU64,
+    // The nanoseconds the current process has spent waiting rather than working, and whether its
+    // whole slice is to be treated as such, see `idle` and `exclude_slice`
+    idle: AtomicU64,
+    excluded: AtomicBool,
+    // The time spent in each state, see `erlang:statistics(microstate_accounting)`
+    msacc: Msacc,
 }
 // This guarantee holds as long as `init` and `current` are only
 // ever accessed by the scheduler when scheduling
@@ -124,9 +215,23 @@ impl Scheduler {
             id,
             next_reference_id: AtomicU64::new(0),
             run_queue: UnsafeCell::new(RunQueue::default()),
+            suspended: UnsafeCell::new(BTreeMap::new()),
+            tasks: UnsafeCell::new(SystemTasks::default()),
+            blocked: UnsafeCell::new(BTreeMap::new()),
             prev: UnsafeCell::new(None),
+            root: root.process.pid(),
             current: UnsafeCell::new(root),
+            spawned: UnsafeCell::new(BTreeMap::new()),
+            live: UnsafeCell::new(BTreeSet::new()),
+            orphans: UnsafeCell::new(Vec::new()),
             halt_code: AtomicI32::new(0),
+            context_switches: AtomicU64::new(0),
+            reductions: AtomicU64::new(0),
+            started: Instant::now(),
+            busy: AtomicU64::new(0),
+            idle: AtomicU64::new(0),
+            excluded: AtomicBool::new(false),
+            msacc: Msacc::new(),
         })
     }
 
@@ -163,6 +268,344 @@ impl Scheduler {
         self.current().process.clone()
     }
 
+    /// Returns the live process with the given pid, if any
+    ///
+    /// This is either the current process, the process it was swapped in from, a process waiting in
+    /// the run queue, a suspended process, or a process blocked on a system task.
+    pub fn process(&self, pid: ProcessId) -> Option<Arc<Process>> {
+        let current = self.current();
+        if current.process.pid() == pid {
+            return Some(current.process.clone());
+        }
+        let prev = unsafe { (*self.prev.get()).as_deref() };
+        if let Some(prev) = prev.filter(|prev| prev.process.pid() == pid) {
+            return Some(prev.process.clone());
+        }
+        let rq = unsafe { &*self.run_queue.get() };
+        if let Some(data) = rq.find(pid) {
+            return Some(data.process.clone());
+        }
+        let suspended = unsafe { &*self.suspended.get() };
+        if let Some(data) = suspended.get(&pid) {
+            return Some(data.process.clone());
+        }
+        let blocked = unsafe { &*self.blocked.get() };
+        blocked.get(&pid).map(|data| data.process.clone())
+    }
+
+    /// Returns true if the process with the given pid has started executing, i.e. it may hold terms
+    /// on its stack and heap
+    ///
+    /// Only processes spawned via `spawn` are ever waiting to start, as the init process starts as
+    /// soon as the scheduler runs.
+    pub(crate) fn has_started(&self, pid: ProcessId) -> bool {
+        let spawned = unsafe { &*self.spawned.get() };
+        !spawned.contains_key(&pid)
+    }
+
+    /// Returns true if the process with the given pid is blocked until a system task it requested
+    /// is done, see `request_task`
+    pub(crate) fn is_blocked(&self, pid: ProcessId) -> bool {
+        self.with_tasks(|tasks| tasks.is_blocking(pid))
+    }
+
+    /// Has the process `pid` do `task` on behalf of the current process, which is blocked until it
+    /// is done, returning its outcome, i.e. whether it was done, or for `Task::CheckLiterals`
+    /// whether the process refers to the area, and false if `pid` exited first
+    ///
+    /// Tasks need the stack of the process they are done on, so are run by that process, once it
+    /// is next at a point at which every term it holds is described by the stack maps of its
+    /// frames, i.e. at a yield point of generated code, see `yield_from_code`, or while blocked on
+    /// a task of its own. A process which is parked at such a point, as it is suspended, see
+    /// `erlang::suspend`, or blocked, is scheduled to run its tasks, and parked again once it has.
+    /// A process suspended at any other point runs its tasks once it is resumed.
+    ///
+    /// So that the requester is not held up by a process of lower priority, the process inherits
+    /// the priority of the requester until it has run its tasks, as does any process it is itself
+    /// blocked on in turn, see `boost`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn request_task(&self, pid: ProcessId, task: Task) -> bool {
+        let requester = self.current().process.pid();
+        self.with_tasks(|tasks| tasks.request(pid, requester, task));
+        self.boost(pid, self.current().process.effective_priority());
+        if self.with_tasks(|tasks| tasks.is_ready(pid)) {
+            self.unpark(pid);
+        }
+        let mut outcome = None;
+        loop {
+            self.with_tasks(|tasks| tasks.set_ready(requester, true));
+            self.process_yield();
+            self.with_tasks(|tasks| tasks.set_ready(requester, false));
+            self.run_tasks();
+            outcome = outcome.or_else(|| self.with_tasks(|tasks| tasks.outcome(requester)));
+            // A requester which was suspended meanwhile may only be scheduled to run its tasks
+            match outcome {
+                Some(done) if !crate::erlang::suspend::is_suspended(requester) => break done,
+                _ => (),
+            }
+        }
+    }
+
+    /// Runs the system tasks queued on the current process, which must be at a point at which
+    /// every term it holds is described by the stack maps of its frames, see `request_task`
+    #[cfg(not(target_arch = "wasm32"))]
+    fn run_tasks(&self) {
+        let process = self.current_process();
+        let pid = process.pid();
+        for (requester, task) in self.with_tasks(|tasks| tasks.take(pid)) {
+            let outcome = match task {
+                Task::Collect(sweep) => {
+                    crate::erlang::trace::garbage_collection(pid, process.heap_size());
+                    unsafe { process.collect(&mut [], sweep) };
+                    true
+                }
+                Task::CheckLiterals(start, end) => unsafe {
+                    process.refers_to_literals(&[], start as *const u8..end as *const u8)
+                },
+            };
+            self.complete(requester, outcome);
+        }
+        if let Some(previous) = self.with_tasks(|tasks| tasks.unboost(pid)) {
+            process.inherit_priority(previous);
+        }
+    }
+
+    /// Has the process `pid`, on which tasks have been requested by a process of the given
+    /// priority, inherit that priority, as does the process it is blocked on, if any, and so on
+    ///
+    /// A process in the run queue is moved to the queue of its new priority.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn boost(&self, pid: ProcessId, priority: Priority) {
+        let mut next = Some(pid);
+        while let Some(pid) = next {
+            let Some(process) = self.process(pid) else {
+                break;
+            };
+            if process.effective_priority() >= priority {
+                break;
+            }
+            let previous = process.inherit_priority(priority);
+            self.with_tasks(|tasks| tasks.boost(pid, previous));
+            let rq = unsafe { &mut *self.run_queue.get() };
+            if let Some(data) = rq.remove(pid) {
+                rq.reschedule(data);
+            }
+            next = self.with_tasks(|tasks| tasks.blocked_on(pid));
+        }
+    }
+
+    /// Records the outcome of a system task for the process which requested it, which is put back
+    /// in the run queue, or with the suspended processes if it was suspended meanwhile
+    fn complete(&self, requester: ProcessId, done: bool) {
+        self.with_tasks(|tasks| tasks.complete(requester, done));
+        let blocked = unsafe { &mut *self.blocked.get() };
+        let Some(data) = blocked.remove(&requester) else {
+            return;
+        };
+        if crate::erlang::suspend::is_suspended(requester) {
+            let suspended = unsafe { &mut *self.suspended.get() };
+            suspended.insert(requester, data);
+        } else {
+            let rq = unsafe { &mut *self.run_queue.get() };
+            rq.reschedule(data);
+        }
+    }
+
+    /// Puts the process with the given pid, which is suspended or blocked, in the run queue, so
+    /// that it runs its system tasks
+    #[cfg(not(target_arch = "wasm32"))]
+    fn unpark(&self, pid: ProcessId) {
+        let blocked = unsafe { &mut *self.blocked.get() };
+        let suspended = unsafe { &mut *self.suspended.get() };
+        if let Some(data) = blocked.remove(&pid).or_else(|| suspended.remove(&pid)) {
+            let rq = unsafe { &mut *self.run_queue.get() };
+            rq.reschedule(data);
+        }
+    }
+
+    fn with_tasks<T>(&self, f: impl FnOnce(&mut SystemTasks) -> T) -> T {
+        f(unsafe { &mut *self.tasks.get() })
+    }
+
+    /// Takes the process with the given pid out of the run queue, as it has been suspended, see
+    /// `erlang::suspend`
+    ///
+    /// The current process is instead kept out of the run queue once it yields.
+    pub(crate) fn suspend(&self, pid: ProcessId) {
+        let rq = unsafe { &mut *self.run_queue.get() };
+        if let Some(data) = rq.remove(pid) {
+            let suspended = unsafe { &mut *self.suspended.get() };
+            suspended.insert(pid, data);
+        }
+    }
+
+    /// Puts the process with the given pid back in the run queue, as it has been resumed
+    pub(crate) fn resume(&self, pid: ProcessId) {
+        let suspended = unsafe { &mut *self.suspended.get() };
+        if let Some(data) = suspended.remove(&pid) {
+            let rq = unsafe { &mut *self.run_queue.get() };
+            rq.reschedule(data);
+        }
+    }
+
+    /// Returns the pid of the first live process whose pid is greater than `after`, or of the first
+    /// live process if `after` is `None`
+    ///
+    /// Processes are ordered by pid, so calling this repeatedly with the pid it last returned
+    /// enumerates all processes which stay alive throughout. The root process is not included.
+    pub fn next_process(&self, after: Option<ProcessId>) -> Option<ProcessId> {
+        let live = unsafe { &*self.live.get() };
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        live.range((start, Bound::Unbounded)).next().copied()
+    }
+
+    /// Releases the references to the reference-counted binaries allocated by processes which have
+    /// exited, returning how many binaries were released and their total size in bytes
+    ///
+    /// The heap of a process which has exited may still be read, e.g. for its exit reason, for as
+    /// long as something refers to the process, so its binaries are kept until a later sweep.
+    pub fn sweep_orphans(&self) -> (usize, usize) {
+        let orphans = unsafe { &mut *self.orphans.get() };
+        let state = self.msacc.switch(Microstate::Gc);
+        let (mut count, mut bytes) = (0, 0);
+        orphans.retain(|(process, binaries)| {
+            if process.strong_count() > 0 {
+                return true;
+            }
+            for (binary, size) in binaries.iter() {
+                binary.maybe_decrement_refcount();
+                count += 1;
+                bytes += size;
+            }
+            false
+        });
+        self.msacc.switch(state);
+        (count, bytes)
+    }
+
+    /// Returns the pid of the root process, i.e. the scheduler itself
+    ///
+    /// When the runtime is embedded, this identifies the host program, see `Runtime::pid`.
+    pub fn root_pid(&self) -> ProcessId {
+        self.root
+    }
+
+    /// Returns the number of times a process has yielded to this scheduler
+    pub fn context_switches(&self) -> u64 {
+        self.context_switches.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of reductions consumed by all processes of this scheduler, including
+    /// those consumed so far by the current process
+    pub fn reductions(&self) -> u64 {
+        let total = self.reductions.load(Ordering::Relaxed);
+        if self.is_root() {
+            return total;
+        }
+        let current = &self.current().process;
+        total + MAX_REDUCTIONS.saturating_sub(current.reductions_left()) as u64
+    }
+
+    /// Returns the nanoseconds this scheduler has spent running processes, and the nanoseconds
+    /// since it was created, as in `erlang:statistics(scheduler_wall_time)`
+    ///
+    /// Time a process spends blocked, e.g. the `init` process waiting for the next timer, is not
+    /// counted as running, see `idle`.
+    pub fn wall_time(&self) -> (u64, u64) {
+        let total = self.started.elapsed().as_nanos() as u64;
+        (self.busy.load(Ordering::Relaxed), total)
+    }
+
+    /// Runs `fun`, which blocks the current process without doing any work, so that the time it
+    /// takes is not counted as time spent running processes
+    pub(crate) fn idle<F, R>(&self, fun: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let start = Instant::now();
+        let state = self.msacc.switch(Microstate::Sleep);
+        #[cfg(not(target_arch = "wasm32"))]
+        let paused = crate::sys::watchdog::pause();
+        let result = fun();
+        #[cfg(not(target_arch = "wasm32"))]
+        crate::sys::watchdog::resume(paused);
+        self.msacc.switch(state);
+        let waited = start.elapsed().as_nanos() as u64;
+        self.idle.fetch_add(waited, Ordering::Relaxed);
+        result
+    }
+
+    /// Returns the microstate accounting of this scheduler, see `Microstate`
+    pub fn msacc(&self) -> &Msacc {
+        &self.msacc
+    }
+
+    /// Publishes the metrics of this scheduler, see `crate::metrics`
+    pub(crate) fn publish_metrics(&self, snapshot: &mut Snapshot) {
+        let live = unsafe { &*self.live.get() };
+        let (busy, total) = self.wall_time();
+        snapshot.counter(
+            "firefly_reductions_total",
+            "Reductions consumed by processes",
+            self.reductions(),
+        );
+        snapshot.counter(
+            "firefly_context_switches_total",
+            "Times a process yielded to the scheduler",
+            self.context_switches(),
+        );
+        snapshot.gauge("firefly_processes", "Processes alive", live.len() as f64);
+        snapshot.gauge(
+            "firefly_run_queue_length",
+            "Processes waiting to run",
+            self.run_queue_len() as f64,
+        );
+        snapshot.push(
+            "firefly_scheduler_busy_seconds_total",
+            "Time the scheduler spent running processes",
+            Kind::Counter,
+            vec![],
+            busy as f64 / 1e9,
+        );
+        snapshot.push(
+            "firefly_scheduler_wall_seconds_total",
+            "Time since the scheduler started",
+            Kind::Counter,
+            vec![],
+            total as f64 / 1e9,
+        );
+        let Some(counters) = self.msacc.counters() else { return; };
+        for (state, value) in Microstate::ALL.into_iter().zip(counters) {
+            snapshot.push(
+                "firefly_scheduler_microstate_seconds_total",
+                "Time the scheduler spent in each state while microstate accounting was enabled",
+                Kind::Counter,
+                vec![("state", state.name().to_string())],
+                value as f64 / 1e9,
+            );
+        }
+    }
+
+    /// Returns the number of processes waiting to run, not including the current process
+    pub fn run_queue_len(&self) -> usize {
+        let rq = unsafe { &*self.run_queue.get() };
+        rq.len()
+    }
+
+    /// Excludes the current slice of the current process from the time spent running processes
+    ///
+    /// This is used by processes sampling the utilization of the scheduler, which would otherwise
+    /// measure themselves, see `firefly_diag:scheduler_usage/1`.
+    pub(crate) fn exclude_slice(&self) {
+        self.excluded.store(true, Ordering::Relaxed);
+    }
+
+    /// Allocates a new reference id, unique to this scheduler
+    pub fn next_reference_id(&self) -> ReferenceId {
+        let id = self.next_reference_id.fetch_add(1, Ordering::Relaxed);
+        ReferenceId::new(0, id)
+    }
+
     /// Swaps the prev and current scheduler data in-place and updates CURRENT_PROCESS
     ///
     /// This is intended for use when yielding to the scheduler
@@ -193,7 +636,6 @@ impl Scheduler {
     }
 
     /// Returns true if the root process (scheduler) is running
-    #[allow(dead_code)]
     fn is_root(&self) -> bool {
         unsafe { (&*self.prev.get()).is_none() }
     }
@@ -216,8 +658,53 @@ impl Scheduler {
         Ok(self.schedule(data))
     }
 
+    /// Spawns a process which calls `callee` with `args`, which must not be allocated on the heap
+    /// of another process
+    ///
+    /// Returns an error if the system is under memory pressure, see `crate::memory`, or if the heap
+    /// of the process cannot be allocated.
+    pub(super) fn spawn(
+        &self,
+        mfa: ModuleFunctionArity,
+        callee: DynamicCallee,
+        args: Vec<OpaqueTerm>,
+    ) -> anyhow::Result<Arc<Process>> {
+        self.spawn_opt(mfa, callee, args, SpawnOptions::default())
+    }
+
+    /// Like `spawn`, but with the given options, see `erlang:spawn_opt/4`
+    pub(crate) fn spawn_opt(
+        &self,
+        mfa: ModuleFunctionArity,
+        callee: DynamicCallee,
+        args: Vec<OpaqueTerm>,
+        options: SpawnOptions,
+    ) -> anyhow::Result<Arc<Process>> {
+        if !crate::memory::may_spawn() {
+            anyhow::bail!("system_limit: the system is running out of memory");
+        }
+        let parent = self.current().process.clone();
+        let process = Arc::new(Process::with_options(
+            Some(parent.pid()),
+            ProcessId::next(),
+            mfa,
+            options,
+            parent.priority(),
+        )?);
+        let spawned = unsafe { &mut *self.spawned.get() };
+        spawned.insert(process.pid(), (callee, args));
+
+        let data = Arc::new(SchedulerData::new(process));
+
+        Self::runnable(&data, start_spawned as DynamicCallee);
+
+        Ok(self.schedule(data))
+    }
+
     fn schedule(&self, data: Arc<SchedulerData>) -> Arc<Process> {
         let handle = data.process.clone();
+        let live = unsafe { &mut *self.live.get() };
+        live.insert(handle.pid());
         let rq = unsafe { &mut *self.run_queue.get() };
         rq.schedule(data);
         handle
@@ -225,6 +712,10 @@ impl Scheduler {
 
     #[inline]
     pub(super) fn run_once(&self) -> bool {
+        // Once the system is halting, nothing else is scheduled, see `crate::halt`
+        if crate::halt::is_requested() {
+            return false;
+        }
         // The scheduler will yield to a process to execute
         self.scheduler_yield()
     }
@@ -291,16 +782,71 @@ impl Scheduler {
     // Returns `Ok(())` if shutdown was successful, `Err(anyhow::Error)` if something
     // went wrong during shutdown, and it was not able to complete normally
     pub(super) fn shutdown(&self) -> std::process::ExitCode {
-        use std::process::ExitCode;
+        // Unless halted with `erlang:halt/1,2`, the system exits with the status of the last
+        // process to exit
+        let code = if self.halt_code.load(Ordering::Relaxed) == 0 {
+            0
+        } else {
+            1
+        };
+        crate::halt::run(code)
+    }
+
+    /// Returns true if the last process to exit did so abnormally, see `shutdown`
+    #[cfg(target_arch = "wasm32")]
+    pub(super) fn has_failed(&self) -> bool {
+        self.halt_code.load(Ordering::Relaxed) != 0
+    }
 
-        if self.halt_code.load(Ordering::Relaxed) == 0 {
-            ExitCode::SUCCESS
+    /// Called by generated code once it has consumed the reductions granted to it
+    ///
+    /// They are charged to the current process, which yields if its budget is exhausted. Code run
+    /// by the scheduler itself, i.e. outside of any process, is never preempted.
+    pub(super) fn yield_point(&self) {
+        if self.is_root() {
+            reductions::settle();
+            reductions::grant(MAX_REDUCTIONS);
+            return;
+        }
+        let process = &self.current().process;
+        if process.reduce(reductions::settle()) {
+            self.yield_from_code();
         } else {
-            ExitCode::FAILURE
+            reductions::grant(process.reductions_left());
+        }
+    }
+
+    /// Yields the current process from generated code, at a point at which every term it holds is
+    /// described by the stack maps of its frames, so that it runs its system tasks once resumed
+    ///
+    /// A process which is suspended is only scheduled again to run its tasks, see `request_task`,
+    /// so it yields again once it has.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(super) fn yield_from_code(&self) -> bool {
+        let pid = self.current().process.pid();
+        loop {
+            self.with_tasks(|tasks| tasks.set_ready(pid, true));
+            self.process_yield();
+            self.with_tasks(|tasks| tasks.set_ready(pid, false));
+            self.run_tasks();
+            if !crate::erlang::suspend::is_suspended(pid) {
+                break true;
+            }
         }
     }
 
+    /// Processes run to completion on wasm32, see `process_yield`, so they never have system tasks
+    /// to run
+    #[cfg(target_arch = "wasm32")]
+    pub(super) fn yield_from_code(&self) -> bool {
+        self.process_yield()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
     pub(super) fn process_yield(&self) -> bool {
+        // Charge the reductions consumed by generated code to the process before it is suspended
+        self.current().process.reduce(reductions::settle());
+
         // Swap back to the scheduler, which is currently "suspended" in `prev`.
         // When `swap_stack` is called it will look like a return from the last call
         // to `swap_stack` from the scheduler loop.
@@ -316,6 +862,18 @@ impl Scheduler {
         true
     }
 
+    /// The stack of a process cannot be switched on wasm32, so processes run to completion on the
+    /// stack of the scheduler, and yielding simply continues execution of the current process with
+    /// a fresh budget of reductions
+    #[cfg(target_arch = "wasm32")]
+    pub(super) fn process_yield(&self) -> bool {
+        let process = &self.current().process;
+        process.reduce(reductions::settle());
+        process.reset_reductions();
+        reductions::grant(process.reductions_left());
+        true
+    }
+
     /// This function performs two roles, albeit virtually identical:
     ///
     /// First, this function is called by the scheduler to resume execution
@@ -337,6 +895,10 @@ impl Scheduler {
             match next {
                 Some(scheduler_data) => {
                     // Found a process to schedule
+                    let start = Instant::now();
+                    self.msacc.switch(Microstate::Emulator);
+                    #[cfg(not(target_arch = "wasm32"))]
+                    crate::sys::watchdog::enter(scheduler_data.process.pid());
                     unsafe {
                         // The swap takes care of setting up the to-be-scheduled process
                         // as the current process, and swaps to its stack. The code below
@@ -350,24 +912,75 @@ impl Scheduler {
                     // as the current process. We need to handle
                     // swapping it out with the scheduler process
                     // and handling its exit, if exiting
+                    #[cfg(not(target_arch = "wasm32"))]
+                    crate::sys::watchdog::leave();
                     self.swap_current();
+                    self.msacc.switch(Microstate::Aux);
+                    self.account_slice(start);
                     // At this point, `prev` is the process which just yielded
                     let prev = self.take_prev();
+                    let slice = MAX_REDUCTIONS.saturating_sub(prev.process.reductions_left());
+                    self.reductions.fetch_add(slice as u64, Ordering::Relaxed);
+                    crate::sim::consume(slice as u64);
+                    self.context_switches.fetch_add(1, Ordering::Relaxed);
+                    crate::memory::poll(prev.process.pid());
+                    crate::erlang::spawn::enforce_max_heap_size(&prev.process);
+                    if matches!(
+                        prev.process.status(),
+                        ProcessStatus::Exiting | ProcessStatus::Errored(_)
+                    ) {
+                        let live = unsafe { &mut *self.live.get() };
+                        live.remove(&prev.process.pid());
+                        crate::erlang::seq_trace::exit(prev.process.pid());
+                        crate::erlang::suspend::exit(prev.process.pid());
+                        // The tasks the process never ran are abandoned
+                        for (requester, _) in
+                            self.with_tasks(|tasks| tasks.take(prev.process.pid()))
+                        {
+                            self.complete(requester, false);
+                        }
+                        self.with_tasks(|tasks| tasks.unboost(prev.process.pid()));
+                        let binaries = prev.process.take_binaries();
+                        if !binaries.is_empty() {
+                            let orphans = unsafe { &mut *self.orphans.get() };
+                            orphans.push((Arc::downgrade(&prev.process), binaries));
+                        }
+                    }
                     match prev.process.status() {
+                        // A process blocked on a system task stops until it is done
+                        ProcessStatus::Running
+                            if self.with_tasks(|tasks| tasks.is_blocking(prev.process.pid())) =>
+                        {
+                            let blocked = unsafe { &mut *self.blocked.get() };
+                            blocked.insert(prev.process.pid(), prev);
+                        }
+                        // A process which suspended itself asynchronously stops once it yields
+                        ProcessStatus::Running
+                            if crate::erlang::suspend::is_suspended(prev.process.pid()) =>
+                        {
+                            let suspended = unsafe { &mut *self.suspended.get() };
+                            suspended.insert(prev.process.pid(), prev);
+                        }
                         ProcessStatus::Running => {
                             let rq = unsafe { &mut *self.run_queue.get() };
                             rq.reschedule(prev);
                         }
                         ProcessStatus::Exiting => {
+                            crate::erlang::trace::exit(prev.process.pid(), atoms::Normal.into());
                             self.halt_code.store(0, Ordering::Relaxed);
                             // Process has exited normally, we're done with it
                         }
                         ProcessStatus::Errored(exception) => {
+                            let reason = unsafe { exception.as_ref() }.reason();
+                            crate::erlang::trace::exit(prev.process.pid(), reason.into());
                             exit::log_exit(&prev.process, exception);
                             self.halt_code.store(1, Ordering::Relaxed);
                         }
                         other => assert_eq!(other, ProcessStatus::Running),
                     }
+                    crate::erlang::firefly_diag::poll();
+                    crate::metrics::poll();
+                    self.msacc.switch(Microstate::Other);
 
                     // When reached, either the process scheduled is the root process,
                     // or the process is exiting and we called .reduce(); either way we're
@@ -382,6 +995,17 @@ impl Scheduler {
         }
     }
 
+    /// Adds the slice of the process which just yielded, which began at `start`, to the time spent
+    /// running processes, less any time it spent idle
+    fn account_slice(&self, start: Instant) {
+        let slice = start.elapsed().as_nanos() as u64;
+        let idle = self.idle.swap(0, Ordering::Relaxed);
+        if !self.excluded.swap(false, Ordering::Relaxed) {
+            self.busy
+                .fetch_add(slice.saturating_sub(idle), Ordering::Relaxed);
+        }
+    }
+
     /// This function takes care of coordinating the scheduling of a new
     /// process/descheduling of the current process.
     ///
@@ -393,9 +1017,12 @@ impl Scheduler {
     /// Once that is complete, it swaps to the new process stack via `swap_stack`,
     /// at which point execution resumes where the newly scheduled process left
     /// off previously, or in its init function.
+    #[cfg(not(target_arch = "wasm32"))]
     unsafe fn swap_process(&self, new: Arc<SchedulerData>) {
         // Mark the new process as Running
         new.process.set_status(ProcessStatus::Running);
+        new.process.reset_reductions();
+        reductions::grant(new.process.reductions_left());
 
         self.swap_with(new);
         let prev = self.prev();
@@ -419,6 +1046,40 @@ impl Scheduler {
         // scheduler was swapped in.
         swap_stack(prev.registers_mut(), new.registers(), FIRST_SWAP);
     }
+
+    /// On wasm32, a process is run by calling its init function directly, after which it is
+    /// handled as if it had exited and yielded via `__firefly_builtin_exit`
+    #[cfg(target_arch = "wasm32")]
+    unsafe fn swap_process(&self, new: Arc<SchedulerData>) {
+        new.process.set_status(ProcessStatus::Running);
+        new.process.reset_reductions();
+        reductions::grant(new.process.reductions_left());
+
+        self.swap_with(new);
+        let registers = self.current().registers_mut();
+        // Processes never yield on this target, so every process scheduled is starting
+        assert_eq!(registers.slots[1], FIRST_SWAP);
+        registers.slots[1] = 0;
+
+        let init_fn: DynamicCallee = mem::transmute(registers.slots[2] as usize);
+        match init_fn() {
+            ErlangResult::Ok(_) => self.current_process().exit_normal(),
+            ErlangResult::Err(err) => self.current_process().exit_error(err),
+        }
+    }
+}
+
+/// The entry point of processes started via `Scheduler::spawn`
+///
+/// NOTE: When this function is invoked, it is on the stack of the new process, not the scheduler.
+extern "C-unwind" fn start_spawned() -> ErlangResult {
+    let (callee, args) = with_current(|scheduler| {
+        let pid = scheduler.current().process.pid();
+        let spawned = unsafe { &mut *scheduler.spawned.get() };
+        spawned.remove(&pid)
+    })
+    .unwrap();
+    unsafe { function::apply_callee(callee, args.as_slice()) }
 }
 
 #[derive(Default, Debug)]
@@ -490,8 +1151,32 @@ impl CalleeSavedRegisters {
     }
 }
 
+/// The stack cannot be switched on wasm32, so the only state kept for a process is that written by
+/// `Scheduler::runnable`, i.e. whether it has started, and its init function
+#[derive(Debug, Default)]
+#[repr(C)]
+#[cfg(target_arch = "wasm32")]
+struct CalleeSavedRegisters {
+    pub slots: [u64; 3],
+}
+#[cfg(target_arch = "wasm32")]
+impl CalleeSavedRegisters {
+    #[inline(always)]
+    unsafe fn set<T: Copy>(&mut self, index: isize, value: T) {
+        let base = self.slots.as_mut_ptr().offset(index) as *mut T;
+        base.write(value);
+    }
+
+    #[inline(always)]
+    unsafe fn set_stack_pointer(&mut self, _value: u64) {}
+
+    #[inline(always)]
+    unsafe fn set_frame_pointer(&mut self, _value: u64) {}
+}
+
 const FIRST_SWAP: u64 = 0xdeadbeef;
 
+#[cfg(not(target_arch = "wasm32"))]
 extern "C-unwind" {
     #[link_name = "__firefly_swap_stack"]
     fn swap_stack(
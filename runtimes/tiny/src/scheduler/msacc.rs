@@ -0,0 +1,127 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+use instant::Instant;
+
+/// A state a scheduler can be in, as named by `erlang:statistics(microstate_accounting)`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum State {
+    Aux = 0,
+    CheckIo,
+    Emulator,
+    Gc,
+    Other,
+    Port,
+    Sleep,
+}
+impl State {
+    /// All states, in the order of their names
+    pub const ALL: [Self; 7] = [
+        Self::Aux,
+        Self::CheckIo,
+        Self::Emulator,
+        Self::Gc,
+        Self::Other,
+        Self::Port,
+        Self::Sleep,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Aux => "aux",
+            Self::CheckIo => "check_io",
+            Self::Emulator => "emulator",
+            Self::Gc => "gc",
+            Self::Other => "other",
+            Self::Port => "port",
+            Self::Sleep => "sleep",
+        }
+    }
+}
+
+/// The time a scheduler has spent in each state, while microstate accounting is enabled
+///
+/// The state is tracked at all times, as switching it is a single store, but time is only added
+/// to the counters while enabled, so that accounting costs next to nothing otherwise. The counters
+/// are kept when accounting is disabled, until reset.
+pub struct Msacc {
+    enabled: AtomicBool,
+    /// Whether accounting has ever been enabled, as the counters are `undefined` until it has
+    started: AtomicBool,
+    current: AtomicU8,
+    /// The nanoseconds since `epoch` at which the current state was entered
+    since: AtomicU64,
+    epoch: Instant,
+    counters: [AtomicU64; 7],
+}
+impl Msacc {
+    pub(super) fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            started: AtomicBool::new(false),
+            current: AtomicU8::new(State::Other as u8),
+            since: AtomicU64::new(0),
+            epoch: Instant::now(),
+            counters: Default::default(),
+        }
+    }
+
+    /// Switches to `state`, returning the state switched from, so that it can be restored
+    #[inline]
+    pub fn switch(&self, state: State) -> State {
+        let prev = State::ALL[self.current.swap(state as u8, Ordering::Relaxed) as usize];
+        if self.enabled.load(Ordering::Relaxed) {
+            let now = self.now();
+            let since = self.since.swap(now, Ordering::Relaxed);
+            self.counters[prev as usize].fetch_add(now.saturating_sub(since), Ordering::Relaxed);
+        }
+        prev
+    }
+
+    /// Enables or disables accounting, returning whether it was enabled
+    pub fn enable(&self, enable: bool) -> bool {
+        if enable {
+            self.started.store(true, Ordering::Relaxed);
+            if !self.is_enabled() {
+                self.since.store(self.now(), Ordering::Relaxed);
+            }
+            return self.enabled.swap(true, Ordering::Relaxed);
+        }
+        // Account for the time spent in the current state up to now
+        let current = State::ALL[self.current.load(Ordering::Relaxed) as usize];
+        self.switch(current);
+        self.enabled.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Zeroes the counters
+    pub fn reset(&self) {
+        for counter in self.counters.iter() {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.since.store(self.now(), Ordering::Relaxed);
+    }
+
+    /// Returns the nanoseconds spent in each state, in the order of `State::ALL`, including the
+    /// time spent so far in the current state, or `None` if accounting was never enabled
+    pub fn counters(&self) -> Option<[u64; 7]> {
+        if !self.started.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut counters: [u64; 7] =
+            std::array::from_fn(|i| self.counters[i].load(Ordering::Relaxed));
+        if self.is_enabled() {
+            let current = self.current.load(Ordering::Relaxed) as usize;
+            let since = self.since.load(Ordering::Relaxed);
+            counters[current] += self.now().saturating_sub(since);
+        }
+        Some(counters)
+    }
+
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+}
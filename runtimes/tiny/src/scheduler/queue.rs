@@ -2,38 +2,97 @@ use std::collections::VecDeque;
 use std::mem;
 use std::sync::Arc;
 
+use firefly_rt::process::Priority;
+use firefly_rt::term::ProcessId;
+
 use super::SchedulerData;
 
-/// Just about the simplest of run queues, but it makes an attempt to ensure
-/// that previously scheduled processes aren't starved by a continual
-/// stream of new processes
+/// The number of times in a row a process is picked from a queue while processes of the next
+/// lower priority are waiting, before one of those is picked instead
+///
+/// As in ERTS, `max` processes run exclusively, so this only applies to `high`, and to `normal`
+/// with respect to `low`. Unlike ERTS, `high` processes never starve `normal` ones.
+const WEIGHT: usize = 8;
+
+/// A run queue per priority, each of which is just about the simplest of run queues, but makes an
+/// attempt to ensure that previously scheduled processes aren't starved by a continual stream of
+/// new processes
+///
+/// Processes are queued at their effective priority, see `Process::effective_priority`, as of when
+/// they are scheduled.
 #[derive(Default)]
 pub(super) struct RunQueue {
-    scheduled: VecDeque<Arc<SchedulerData>>,
-    visited: VecDeque<Arc<SchedulerData>>,
+    queues: [Queue; 4],
+    /// The number of times in a row a process has been picked from the queue of each priority
+    /// while the queue of the next lower priority was not empty
+    streaks: [usize; 4],
 }
 impl RunQueue {
     /// Returns the next process to execute, if any are available
     pub fn next(&mut self) -> Option<Arc<SchedulerData>> {
-        let next = self.scheduled.pop_front();
-        if next.is_some() {
-            return next;
+        let priority = self.pick()?;
+        let queue = &mut self.queues[priority as usize];
+        // Simulations, and test builds, may pick any runnable process of the priority instead, see
+        // `crate::sim` and `erts_debug`
+        let len = queue.len();
+        let index = crate::sim::random_index(len);
+        #[cfg(feature = "test")]
+        let index = index.or_else(|| crate::erlang::erts_debug::random_index(len));
+        match index {
+            Some(index) => queue.remove(index),
+            None => queue.next(),
         }
-        // We've scheduled all processes at least once this cycle, start a new cycle,
-        // but only if we've scheduled at least one process
-        if self.visited.is_empty() {
-            return None;
+    }
+
+    /// Returns the priority of the queue to take the next process from, if any are available
+    fn pick(&mut self) -> Option<Priority> {
+        if !self.queues[Priority::Max as usize].is_empty() {
+            return Some(Priority::Max);
         }
-        // To start a new cycle, we simply swap the empty schedule queue for
-        // the visited queue and recurse
-        mem::swap(&mut self.scheduled, &mut self.visited);
-        self.scheduled.pop_front()
+        let mut waiting = [Priority::High, Priority::Normal, Priority::Low]
+            .into_iter()
+            .filter(|priority| !self.queues[*priority as usize].is_empty())
+            .peekable();
+        while let Some(priority) = waiting.next() {
+            if waiting.peek().is_none() {
+                return Some(priority);
+            }
+            let streak = &mut self.streaks[priority as usize];
+            if *streak < WEIGHT {
+                *streak += 1;
+                return Some(priority);
+            }
+            *streak = 0;
+        }
+        None
+    }
+
+    /// Returns the number of processes in this queue
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(Queue::len).sum()
+    }
+
+    /// Returns the process with the given pid, if it is in this queue
+    pub fn find(&self, pid: ProcessId) -> Option<&Arc<SchedulerData>> {
+        self.queues.iter().find_map(|queue| queue.find(pid))
+    }
+
+    /// Removes the process with the given pid from this queue, if it is in it
+    pub fn remove(&mut self, pid: ProcessId) -> Option<Arc<SchedulerData>> {
+        self.queues.iter_mut().find_map(|queue| {
+            let index = queue
+                .scheduled
+                .iter()
+                .chain(queue.visited.iter())
+                .position(|data| data.process.pid() == pid)?;
+            queue.remove(index)
+        })
     }
 
     /// Schedules the given process immediately
     #[allow(dead_code)]
     pub fn schedule_now(&mut self, process: Arc<SchedulerData>) {
-        self.scheduled.push_front(process);
+        self.queue_of(&process).scheduled.push_front(process);
     }
 
     /// Schedules the given process for the first time, taking priority
@@ -44,13 +103,66 @@ impl RunQueue {
     /// starve older processes of run time, but that isn't something we're worried
     /// about here.
     pub fn schedule(&mut self, process: Arc<SchedulerData>) {
-        self.scheduled.push_back(process)
+        self.queue_of(&process).scheduled.push_back(process)
     }
 
     /// Schedules the given process again after having just executed. All
     /// processes which have not executed this cycle will get to execute before
     /// this process runs again
     pub fn reschedule(&mut self, process: Arc<SchedulerData>) {
-        self.visited.push_back(process);
+        self.queue_of(&process).visited.push_back(process);
+    }
+
+    fn queue_of(&mut self, process: &SchedulerData) -> &mut Queue {
+        &mut self.queues[process.process.effective_priority() as usize]
+    }
+}
+
+/// The processes of a single priority, run round-robin
+#[derive(Default)]
+struct Queue {
+    scheduled: VecDeque<Arc<SchedulerData>>,
+    visited: VecDeque<Arc<SchedulerData>>,
+}
+impl Queue {
+    fn next(&mut self) -> Option<Arc<SchedulerData>> {
+        let next = self.scheduled.pop_front();
+        if next.is_some() {
+            return next;
+        }
+        // We've scheduled all processes at least once this cycle, start a new cycle,
+        // but only if we've scheduled at least one process
+        if self.visited.is_empty() {
+            return None;
+        }
+        // To start a new cycle, we simply swap the empty schedule queue for
+        // the visited queue and recurse
+        mem::swap(&mut self.scheduled, &mut self.visited);
+        self.scheduled.pop_front()
+    }
+
+    /// Removes the process at `index`, counting the processes yet to run this cycle first
+    fn remove(&mut self, index: usize) -> Option<Arc<SchedulerData>> {
+        let scheduled = self.scheduled.len();
+        if index < scheduled {
+            self.scheduled.remove(index)
+        } else {
+            self.visited.remove(index - scheduled)
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.scheduled.len() + self.visited.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn find(&self, pid: ProcessId) -> Option<&Arc<SchedulerData>> {
+        self.scheduled
+            .iter()
+            .chain(self.visited.iter())
+            .find(|data| data.process.pid() == pid)
     }
 }
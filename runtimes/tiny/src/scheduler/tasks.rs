@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use firefly_rt::process::{Priority, Sweep};
+use firefly_rt::term::ProcessId;
+
+/// Work a process does on behalf of another, as it can only be done on the stack of the process
+/// it is done on, see `Scheduler::request_task`
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Task {
+    /// Collects the heap of the process, see `erlang:garbage_collect/2`
+    Collect(Sweep),
+    /// Checks whether the process refers to the literal area spanning the given addresses, the
+    /// outcome being true if it does, see `erts_internal:literal_area_referrers/1`
+    CheckLiterals(usize, usize),
+}
+
+/// A task queued on a process, and the process blocked until it is done
+struct Request {
+    requester: ProcessId,
+    task: Task,
+}
+
+/// The system tasks of the processes of a scheduler, i.e. the queue of tasks of each process, and
+/// the outcomes of the tasks done for the processes which requested them
+#[derive(Default)]
+pub(super) struct SystemTasks {
+    /// The tasks queued on each process, in the order they were requested
+    queued: BTreeMap<ProcessId, VecDeque<Request>>,
+    /// The processes blocked until a task they requested is done, and the process it is queued on
+    blocking: BTreeMap<ProcessId, ProcessId>,
+    /// The priority each process with tasks queued had inherited before it inherited that of the
+    /// processes which requested them, to be restored once it has run them
+    boosted: BTreeMap<ProcessId, Priority>,
+    /// The outcome of each task done, or abandoned, whose requester is yet to take it
+    done: BTreeMap<ProcessId, bool>,
+    /// The processes which were descheduled at a point at which they can run their tasks, i.e. a
+    /// yield point of generated code, or while blocked on a task of their own
+    ready: BTreeSet<ProcessId>,
+}
+impl SystemTasks {
+    /// Queues `task` on `pid` on behalf of `requester`, which is blocked until it is done
+    pub fn request(&mut self, pid: ProcessId, requester: ProcessId, task: Task) {
+        let request = Request { requester, task };
+        self.queued.entry(pid).or_default().push_back(request);
+        self.blocking.insert(requester, pid);
+    }
+
+    /// Takes the tasks queued on `pid`, with the processes which requested them
+    pub fn take(&mut self, pid: ProcessId) -> Vec<(ProcessId, Task)> {
+        self.queued
+            .remove(&pid)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|request| (request.requester, request.task))
+            .collect()
+    }
+
+    /// Records the priority `pid` had inherited before it inherited that of the processes which
+    /// requested its tasks, unless it already has been since it last ran them
+    pub fn boost(&mut self, pid: ProcessId, previous: Priority) {
+        self.boosted.entry(pid).or_insert(previous);
+    }
+
+    /// Takes the priority `pid` had inherited before its tasks were requested, if it was boosted
+    pub fn unboost(&mut self, pid: ProcessId) -> Option<Priority> {
+        self.boosted.remove(&pid)
+    }
+
+    /// Records the outcome of a task requested by `requester`, which is no longer blocked
+    pub fn complete(&mut self, requester: ProcessId, done: bool) {
+        self.blocking.remove(&requester);
+        self.done.insert(requester, done);
+    }
+
+    /// Takes the outcome of the task requested by `requester`, once it has been done or abandoned
+    pub fn outcome(&mut self, requester: ProcessId) -> Option<bool> {
+        self.done.remove(&requester)
+    }
+
+    /// Returns true if `pid` is blocked until a task it requested is done
+    pub fn is_blocking(&self, pid: ProcessId) -> bool {
+        self.blocking.contains_key(&pid)
+    }
+
+    /// Returns the process the task `pid` is blocked on is queued on, if it is blocked
+    pub fn blocked_on(&self, pid: ProcessId) -> Option<ProcessId> {
+        self.blocking.get(&pid).copied()
+    }
+
+    /// Records whether `pid`, which is being descheduled, can run its tasks once it is resumed
+    pub fn set_ready(&mut self, pid: ProcessId, ready: bool) {
+        if ready {
+            self.ready.insert(pid);
+        } else {
+            self.ready.remove(&pid);
+        }
+    }
+
+    /// Returns true if `pid` can run its tasks once it is resumed
+    pub fn is_ready(&self, pid: ProcessId) -> bool {
+        self.ready.contains(&pid)
+    }
+}
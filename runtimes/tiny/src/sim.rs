@@ -0,0 +1,123 @@
+//! Deterministic simulation, enabled with `+sim Seed`, under which a run of the system depends only
+//! on its seed, so that a concurrency bug a test stumbles upon can be replayed exactly.
+//!
+//! In a simulation:
+//!
+//! * The next process to run is picked from all runnable processes by a pseudo-random generator
+//! seeded with `Seed`, rather than round-robin, see `scheduler::queue`.
+//! * Time is virtual. It starts at zero, advances by [`NANOS_PER_REDUCTION`] for every reduction
+//! processes consume, and when nothing is runnable, jumps straight to the deadline of the next
+//! timer rather than waiting for it. Monotonic time, and OS system time, which starts at
+//! [`SYSTEM_EPOCH`], are read from this clock, see `crate::time`.
+//! * Timers which expire at the same moment fire in an order picked by the same generator, see
+//! `erlang::gen`.
+//!
+//! Messages are only sent by running processes and expiring timers, so the order in which messages
+//! from different senders arrive follows from the above. Anything outside of the runtime is not
+//! simulated: ports still wait on the OS in real time, as does the scheduler watchdog.
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use instant::Instant;
+
+/// The virtual time a reduction takes, in nanoseconds
+pub(crate) const NANOS_PER_REDUCTION: u64 = 1_000;
+/// OS system time when a simulation starts, in nanoseconds since the Unix epoch, i.e. midnight on
+/// the first of January 2020
+pub(crate) const SYSTEM_EPOCH: i64 = 1_577_836_800_000_000_000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RANDOM: Mutex<Option<Prng>> = Mutex::new(None);
+/// Virtual time elapsed since the simulation started, in nanoseconds
+static ELAPSED: AtomicU64 = AtomicU64::new(0);
+/// The instant virtual time is measured from, so that it can stand in for real instants
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Enables simulation, which must be done before any process is spawned or clock is read
+pub(crate) fn enable(seed: u64) {
+    EPOCH.get_or_init(Instant::now);
+    *RANDOM.lock().unwrap() = Some(Prng::new(seed));
+    ENABLED.store(true, Ordering::Release);
+}
+
+#[inline]
+pub(crate) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Returns a pseudo-random index below `len`, or `None` if no simulation is running
+pub(crate) fn random_index(len: usize) -> Option<usize> {
+    if len == 0 || !is_enabled() {
+        return None;
+    }
+    RANDOM
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(|random| random.below(len))
+}
+
+/// Returns the virtual time elapsed since the simulation started, in nanoseconds
+pub(crate) fn elapsed() -> u64 {
+    ELAPSED.load(Ordering::Relaxed)
+}
+
+/// Returns the current instant, which is virtual while a simulation is running
+pub(crate) fn now() -> Instant {
+    match EPOCH.get() {
+        Some(epoch) if is_enabled() => *epoch + Duration::from_nanos(elapsed()),
+        _ => Instant::now(),
+    }
+}
+
+/// Returns OS system time in nanoseconds since the Unix epoch, if a simulation is running
+pub(crate) fn system_time() -> Option<i64> {
+    is_enabled().then(|| SYSTEM_EPOCH + elapsed() as i64)
+}
+
+/// Advances virtual time by the time taken by `reductions`
+pub(crate) fn consume(reductions: u64) {
+    if is_enabled() {
+        ELAPSED.fetch_add(
+            reductions.saturating_mul(NANOS_PER_REDUCTION),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+/// Blocks for `duration`, which only advances virtual time while a simulation is running
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) fn sleep(duration: Duration) {
+    if is_enabled() {
+        ELAPSED.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    } else {
+        std::thread::sleep(duration);
+    }
+}
+
+/// xorshift64*, which is plenty for shuffling processes, and is reproducible from its seed
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Prng {
+    state: u64,
+}
+impl Prng {
+    pub const fn new(seed: u64) -> Self {
+        // The state must never be zero, which this constant makes unlikely, and `below` corrects
+        Self {
+            state: seed ^ 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    /// Returns the next pseudo-random number below `n`, which must not be zero
+    pub fn below(&mut self, n: usize) -> usize {
+        if self.state == 0 {
+            self.state = 0x9e37_79b9_7f4a_7c15;
+        }
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let next = self.state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (next % n as u64) as usize
+    }
+}
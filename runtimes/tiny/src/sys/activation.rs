@@ -0,0 +1,146 @@
+//! The sockets inherited from the process which started the runtime, see `firefly_socket`
+//!
+//! Under systemd socket activation, sockets are bound by the service manager and passed on as file
+//! descriptors starting at 3, with `LISTEN_PID` set to the pid they are meant for, `LISTEN_FDS` to
+//! their number, and `LISTEN_FDNAMES` to their names, as set by `FileDescriptorName=` in the socket
+//! unit, separated by colons. As the sockets outlive the service, a restarted service is handed the
+//! same sockets, and connections queue up on them in the meantime rather than being refused.
+//!
+//! When started by inetd, or with `Accept=yes` in the socket unit, the socket is instead a
+//! connection, passed on as standard input by inetd. With `+inetd`, standard input is inherited as
+//! a socket named `stdin`, if it is one.
+//!
+//! The variables are removed from the environment once read, so that they are not passed on to the
+//! programs started by the runtime, and the inherited descriptors are closed on exec for the same
+//! reason.
+use std::env;
+use std::mem;
+use std::os::raw::c_int;
+use std::sync::OnceLock;
+
+/// The first file descriptor passed on by systemd, `SD_LISTEN_FDS_START`
+const LISTEN_FDS_START: c_int = 3;
+
+static INHERITED: OnceLock<Vec<Inherited>> = OnceLock::new();
+
+/// A socket inherited from the process which started the runtime
+#[derive(Debug, Clone)]
+pub struct Inherited {
+    pub fd: c_int,
+    /// The name given to the socket by the service manager, `unknown` if none was given
+    pub name: String,
+    pub kind: Kind,
+}
+
+/// What an inherited socket is, so that it is not mistaken for another kind of socket
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// A stream socket accepting connections, e.g. a bound TCP socket
+    Listen,
+    /// A connected stream socket, as passed on by inetd
+    Stream,
+    Dgram,
+    SeqPacket,
+    /// Not a socket, e.g. a FIFO passed on with `ListenFIFO=`
+    Other,
+}
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Listen => "listen",
+            Self::Stream => "stream",
+            Self::Dgram => "dgram",
+            Self::SeqPacket => "seqpacket",
+            Self::Other => "other",
+        }
+    }
+
+    fn of(fd: c_int) -> Self {
+        match getsockopt(fd, libc::SO_TYPE) {
+            Some(libc::SOCK_STREAM) if getsockopt(fd, libc::SO_ACCEPTCONN) == Some(1) => {
+                Self::Listen
+            }
+            Some(libc::SOCK_STREAM) => Self::Stream,
+            Some(libc::SOCK_DGRAM) => Self::Dgram,
+            Some(libc::SOCK_SEQPACKET) => Self::SeqPacket,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Takes the sockets passed on by the service manager, and standard input if `inetd` is set
+///
+/// This must be called once, at startup, before any other thread reads the environment.
+pub fn init(inetd: bool) {
+    let mut inherited = listen_fds();
+    if inetd && inherited.is_empty() && Kind::of(0) != Kind::Other {
+        inherited.push(Inherited {
+            fd: 0,
+            name: "stdin".to_string(),
+            kind: Kind::of(0),
+        });
+    }
+    let _ = INHERITED.set(inherited);
+}
+
+/// Returns the inherited sockets, in the order they were passed on
+pub fn inherited() -> &'static [Inherited] {
+    INHERITED.get().map(Vec::as_slice).unwrap_or(&[])
+}
+
+/// Reads the sockets passed on by the service manager, as `sd_listen_fds_with_names` does
+fn listen_fds() -> Vec<Inherited> {
+    let pid = env::var("LISTEN_PID").ok();
+    let fds = env::var("LISTEN_FDS").ok();
+    let names = env::var("LISTEN_FDNAMES").ok();
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(var);
+    }
+
+    // The variables are inherited by every descendant of the service, but only meant for its main
+    // process, any other process must leave the descriptors alone
+    let pid = pid.and_then(|pid| pid.parse::<u32>().ok());
+    if pid != Some(std::process::id()) {
+        return vec![];
+    }
+    let Some(count) = fds.and_then(|fds| fds.parse::<c_int>().ok()) else { return vec![]; };
+    let names = names
+        .as_deref()
+        .unwrap_or("")
+        .split(':')
+        .map(Some)
+        .chain(std::iter::repeat(None));
+    (LISTEN_FDS_START..LISTEN_FDS_START.saturating_add(count))
+        .zip(names)
+        // A descriptor which is not open was not passed on, whatever the count says
+        .filter(|(fd, _)| unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) } == 0)
+        .map(|(fd, name)| Inherited {
+            fd,
+            name: match name {
+                Some(name) if !name.is_empty() => name.to_string(),
+                _ => "unknown".to_string(),
+            },
+            kind: Kind::of(fd),
+        })
+        .collect()
+}
+
+/// Returns the value of an integer socket option, or `None` if `fd` is not a socket
+fn getsockopt(fd: c_int, option: c_int) -> Option<c_int> {
+    let mut value: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            &mut value as *mut c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result == 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
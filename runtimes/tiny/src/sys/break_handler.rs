@@ -2,7 +2,9 @@ use std::thread;
 
 use bus::Bus;
 
-#[derive(Clone)]
+use super::signals::Disposition;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Signal {
     Unknown,
     INT,
@@ -16,12 +18,45 @@ pub enum Signal {
     CHLD,
 }
 impl Signal {
+    /// The signals which are handled, i.e. all but `Unknown`
+    pub const ALL: [Self; 9] = [
+        Self::INT,
+        Self::TERM,
+        Self::QUIT,
+        Self::HUP,
+        Self::ABRT,
+        Self::ALRM,
+        Self::USR1,
+        Self::USR2,
+        Self::CHLD,
+    ];
+
     pub fn should_terminate(&self) -> bool {
         match self {
             Self::TERM | Self::QUIT | Self::HUP | Self::ABRT => true,
             _ => false,
         }
     }
+
+    /// Returns the name of the signal, as given to `os:set_signal/2`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::INT => "sigint",
+            Self::TERM => "sigterm",
+            Self::QUIT => "sigquit",
+            Self::HUP => "sighup",
+            Self::ABRT => "sigabrt",
+            Self::ALRM => "sigalrm",
+            Self::USR1 => "sigusr1",
+            Self::USR2 => "sigusr2",
+            Self::CHLD => "sigchld",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|signal| signal.name() == name)
+    }
 }
 
 impl From<usize> for Signal {
@@ -59,8 +94,23 @@ pub fn init(mut bus: Bus<Signal>) {
         .expect("could not bind signal handlers");
 
         for signal in signals.forever() {
-            match Signal::from(signal as usize) {
+            let signal = Signal::from(signal as usize);
+            // Signals may be handled by Erlang or ignored instead, see `os:set_signal/2`
+            match super::signals::disposition(signal) {
+                Disposition::Default => (),
+                Disposition::Handle => {
+                    super::signals::raise(signal);
+                    continue;
+                }
+                Disposition::Ignore => continue,
+            }
+            match signal {
                 Signal::Unknown => (),
+                // The connections are drained before shutting down, see `sys::drain`
+                sig @ (Signal::INT | Signal::TERM) => {
+                    super::drain::request_shutdown();
+                    bus.broadcast(sig);
+                }
                 sig => bus.broadcast(sig),
             }
         }
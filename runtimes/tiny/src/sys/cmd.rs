@@ -0,0 +1,140 @@
+//! The `firefly_cmd` driver, linked into the executable, which runs a command with the shell and
+//! outputs what it writes to standard output, see `os:cmd/1,2`
+//!
+//! A port of this driver is opened with `{spawn_driver, "firefly_cmd Command"}`, which starts
+//! `/bin/sh -c Command`, with standard input closed and standard error inherited from the runtime.
+//! Standard output is read whenever it is ready, as selected with `driver_select`, and the port
+//! exits with `normal` once the command closes it. Closing the port first kills the command.
+use std::io::{ErrorKind, Read};
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::AsRawFd;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::ptr;
+use std::sync::Once;
+
+use firefly_driver::sys::*;
+
+/// The name of the driver, which is the first word of the command of its ports
+pub const NAME: &str = "firefly_cmd";
+
+static REGISTER: Once = Once::new();
+
+// The driver interface exported by `firefly_driver`, as for any other driver
+extern "C" {
+    fn driver_output(port: ErlDrvPort, buf: *mut c_char, len: ErlDrvSizeT) -> c_int;
+    fn driver_select(port: ErlDrvPort, event: ErlDrvEvent, mode: c_int, on: c_int) -> c_int;
+    fn driver_failure_eof(port: ErlDrvPort) -> c_int;
+    fn driver_failure_posix(port: ErlDrvPort, error: c_int) -> c_int;
+}
+
+/// The state of a port, running a command
+struct Cmd {
+    port: ErlDrvPort,
+    child: Child,
+    /// The standard output of the command, until it is closed
+    stdout: Option<ChildStdout>,
+}
+
+/// Registers the driver, if not already registered
+pub fn register() {
+    REGISTER.call_once(|| {
+        let entry = Box::leak(Box::new(ErlDrvEntry {
+            init: None,
+            start: Some(start),
+            stop: Some(stop),
+            output: None,
+            ready_input: Some(ready_input),
+            ready_output: None,
+            driver_name: b"firefly_cmd\0".as_ptr() as *mut c_char,
+            finish: None,
+            handle: ptr::null_mut(),
+            control: None,
+            timeout: None,
+            outputv: None,
+            ready_async: None,
+            flush: None,
+            call: None,
+            unused_event_callback: ptr::null_mut(),
+            extended_marker: ERL_DRV_EXTENDED_MARKER,
+            major_version: ERL_DRV_EXTENDED_MAJOR_VERSION,
+            minor_version: ERL_DRV_EXTENDED_MINOR_VERSION,
+            driver_flags: 0,
+            handle2: ptr::null_mut(),
+            process_exit: None,
+            stop_select: None,
+            emergency_close: None,
+        }));
+        unsafe { firefly_driver::register(entry) }.expect("unable to register firefly_cmd driver");
+    });
+}
+
+unsafe extern "C" fn start(port: ErlDrvPort, command: *mut c_char) -> ErlDrvData {
+    let command = std::ffi::CStr::from_ptr(command).to_string_lossy();
+    let command = command.strip_prefix(NAME).unwrap_or(&command).trim_start();
+    let spawned = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn();
+    let Ok(mut child) = spawned else {
+        return ERL_DRV_ERROR_GENERAL;
+    };
+    let stdout = child.stdout.take().unwrap();
+    let fd = stdout.as_raw_fd();
+    let flags = libc::fcntl(fd, libc::F_GETFL);
+    libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    let event = fd as usize as ErlDrvEvent;
+    driver_select(port, event, ERL_DRV_READ | ERL_DRV_USE, 1);
+    let cmd = Box::new(Cmd {
+        port,
+        child,
+        stdout: Some(stdout),
+    });
+    Box::into_raw(cmd).cast()
+}
+
+unsafe extern "C" fn stop(data: ErlDrvData) {
+    let mut cmd = Box::from_raw(data.cast::<Cmd>());
+    close_stdout(&mut cmd);
+    // The command is still running if the port was closed before it finished
+    if let Ok(None) = cmd.child.try_wait() {
+        let _ = cmd.child.kill();
+    }
+    let _ = cmd.child.wait();
+}
+
+unsafe extern "C" fn ready_input(data: ErlDrvData, _event: ErlDrvEvent) {
+    let cmd = &mut *data.cast::<Cmd>();
+    let mut buf = [0u8; 4096];
+    loop {
+        let Some(stdout) = cmd.stdout.as_mut() else {
+            return;
+        };
+        match stdout.read(&mut buf) {
+            Ok(0) => {
+                close_stdout(cmd);
+                driver_failure_eof(cmd.port);
+                return;
+            }
+            Ok(len) => {
+                driver_output(cmd.port, buf.as_mut_ptr().cast(), len);
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => {
+                close_stdout(cmd);
+                driver_failure_posix(cmd.port, err.raw_os_error().unwrap_or(libc::EIO));
+                return;
+            }
+        }
+    }
+}
+
+/// Deselects and closes the standard output of the command, if still open
+unsafe fn close_stdout(cmd: &mut Cmd) {
+    if let Some(stdout) = cmd.stdout.take() {
+        let event = stdout.as_raw_fd() as usize as ErlDrvEvent;
+        driver_select(cmd.port, event, ERL_DRV_READ | ERL_DRV_USE_NO_CALLBACK, 0);
+    }
+}
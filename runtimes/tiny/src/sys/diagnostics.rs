@@ -0,0 +1,25 @@
+//! The HTTP endpoint serving the diagnostics report of `firefly_diag`, enabled with `+diag Port`
+//!
+//! Requests are served by a thread of their own, from the report last made by the scheduler, as
+//! processes can only be inspected from the scheduler thread. `GET /` returns the report as plain
+//! text, and anything else is refused. The endpoint listens on the loopback interface unless given
+//! an address, e.g. `+diag 0.0.0.0:9100`, as the report reveals what the system is running.
+use std::io;
+
+use crate::erlang::firefly_diag;
+
+use super::http;
+
+/// Starts serving the report on `addr`, a port or an address
+pub fn serve(addr: &str) -> io::Result<()> {
+    http::serve(addr, "firefly_diag", route)?;
+    firefly_diag::enable();
+    Ok(())
+}
+
+fn route(path: &str) -> Option<(&'static str, String)> {
+    match path {
+        "/" => Some(("text/plain; charset=utf-8", firefly_diag::latest_report())),
+        _ => None,
+    }
+}
@@ -0,0 +1,146 @@
+//! The draining of connections, so that a service can be stopped without dropping the requests it
+//! is serving, see `firefly_socket:drain/1`
+//!
+//! Draining closes the ports marked as listeners, so that no new connection is accepted, then waits
+//! for the owners of the other ports, the connections, to close them as they finish their requests.
+//! The ports still open once the deadline passes are closed by the runtime. Draining is not undone,
+//! as it is meant to precede shutting down.
+//!
+//! When the runtime is asked to shut down by `SIGTERM` or `SIGINT`, it drains for the time given by
+//! `+drain Milliseconds`, 0 by default, before shutting down.
+use std::collections::BTreeSet;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use firefly_rt::term::PortId;
+use instant::Instant;
+
+use crate::erlang::port;
+
+/// How long to drain for when asked to shut down, in milliseconds
+static SHUTDOWN_TIMEOUT: AtomicU64 = AtomicU64::new(0);
+/// Set by the signal handler when the runtime is asked to shut down
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+#[derive(Default)]
+struct State {
+    listeners: BTreeSet<PortId>,
+    draining: bool,
+    /// When the ports still open are closed, or `None` to wait for as long as it takes
+    deadline: Option<Instant>,
+    /// The ports which were still open at the deadline
+    forced: BTreeSet<PortId>,
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.get_or_init(Default::default).lock().unwrap()
+}
+
+/// Sets how long to drain for when asked to shut down, see `+drain`
+pub fn set_shutdown_timeout(timeout: Duration) {
+    SHUTDOWN_TIMEOUT.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Asks the runtime to drain and shut down
+///
+/// This is called by the signal handler, so only sets a flag, and wakes up the init process if it
+/// is waiting on ports so that it starts draining.
+pub fn request_shutdown() {
+    SHUTDOWN.store(true, Ordering::Release);
+    firefly_driver::wake();
+}
+
+/// Returns true if the runtime was asked to shut down
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::Acquire)
+}
+
+/// Returns true once draining has started
+pub fn is_draining() -> bool {
+    state().draining
+}
+
+/// Marks `port` as a listener, to be closed when draining starts, or closes it if it already has
+pub fn add_listener(port: PortId) {
+    let draining = {
+        let mut state = state();
+        if !state.draining {
+            state.listeners.insert(port);
+        }
+        state.draining
+    };
+    if draining {
+        let _ = firefly_driver::close(port);
+        port::deliver();
+    }
+}
+
+/// Starts draining, closing the listeners, with the ports still open closed after `timeout`
+///
+/// If already draining, the earlier of the two deadlines is kept.
+pub fn start(timeout: Option<Duration>) {
+    let listeners = {
+        let mut state = state();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let (draining, current) = (state.draining, state.deadline);
+        state.deadline = match (draining, current, deadline) {
+            (false, _, deadline) => deadline,
+            (true, Some(a), Some(b)) => Some(a.min(b)),
+            (true, a, b) => a.or(b),
+        };
+        state.draining = true;
+        mem::take(&mut state.listeners)
+    };
+    // A listener may have been closed by its owner in the meantime
+    for listener in listeners {
+        let _ = firefly_driver::close(listener);
+    }
+    port::deliver();
+}
+
+/// Advances draining, returning the time left until the deadline while it has yet to pass
+///
+/// This starts draining if the runtime was asked to shut down, and closes the ports still open
+/// once the deadline has passed.
+pub fn poll() -> Option<Duration> {
+    if shutdown_requested() && !is_draining() {
+        let timeout = SHUTDOWN_TIMEOUT.load(Ordering::Relaxed);
+        start(Some(Duration::from_millis(timeout)));
+    }
+    let deadline = {
+        let state = state();
+        if !state.draining {
+            return None;
+        }
+        state.deadline?
+    };
+    let now = Instant::now();
+    if deadline > now {
+        return Some(deadline - now);
+    }
+    // A port which is flushing its queue stays open, but is only asked to close once
+    let ports = {
+        let mut state = state();
+        let ports = firefly_driver::ports()
+            .into_iter()
+            .filter(|port| !state.forced.contains(port))
+            .collect::<Vec<_>>();
+        state.forced.extend(ports.iter().copied());
+        ports
+    };
+    if !ports.is_empty() {
+        for port in ports {
+            let _ = firefly_driver::close(port);
+        }
+        port::deliver();
+    }
+    None
+}
+
+/// Takes the ports which had to be closed at the deadline, as they were still open
+pub fn take_forced() -> Vec<PortId> {
+    mem::take(&mut state().forced).into_iter().collect()
+}
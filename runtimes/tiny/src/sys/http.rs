@@ -0,0 +1,73 @@
+//! A minimal HTTP server for the plain text endpoints of the runtime, see `diagnostics` and
+//! `metrics`
+//!
+//! Each endpoint is served by a thread of its own, one connection at a time, which is all the
+//! tools polling them need. Only `GET` is supported, and every response closes the connection.
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+/// How long a client has to send its request before the connection is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The body of a response, and its content type, or `None` if there is nothing at the path
+pub type Route = fn(&str) -> Option<(&'static str, String)>;
+
+/// Starts serving `route` on `addr`, a port on the loopback interface or an address, from a thread
+/// named `name`
+pub fn serve(addr: &str, name: &str, route: Route) -> io::Result<()> {
+    let listener = match addr.parse::<u16>() {
+        Ok(port) => TcpListener::bind(("127.0.0.1", port))?,
+        Err(_) => TcpListener::bind(addr)?,
+    };
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            // A client which goes away must not take the endpoint with it
+            for stream in listener.incoming().flatten() {
+                let _ = respond(stream, route);
+            }
+        })?;
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, route: Route) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers are of no interest, but are read so the client sees the request was taken
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+
+    const TEXT: &str = "text/plain; charset=utf-8";
+    let mut parts = request.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => match route(path) {
+            Some((content_type, body)) => ("200 OK", content_type, body),
+            None => ("404 Not Found", TEXT, "not found\n".to_string()),
+        },
+        _ => (
+            "405 Method Not Allowed",
+            TEXT,
+            "method not allowed\n".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
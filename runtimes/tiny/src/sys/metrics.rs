@@ -0,0 +1,50 @@
+//! The HTTP endpoint exporting the metrics of the runtime to Prometheus, enabled with
+//! `+metrics Port`
+//!
+//! `GET /metrics` returns the latest snapshot published by the scheduler, see `crate::metrics`, in
+//! the Prometheus text exposition format, and anything else is refused. As with `+diag`, the
+//! endpoint listens on the loopback interface unless given an address, e.g.
+//! `+metrics 0.0.0.0:9568`.
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use crate::metrics::{self, Sink, Snapshot};
+
+use super::http;
+
+/// The version of the text exposition format, as given in the content type
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The latest snapshot, as served
+static LATEST: OnceLock<Mutex<Option<String>>> = OnceLock::new();
+
+fn latest() -> MutexGuard<'static, Option<String>> {
+    LATEST.get_or_init(|| Mutex::new(None)).lock().unwrap()
+}
+
+/// Starts serving the metrics on `addr`, a port or an address
+pub fn serve(addr: &str) -> io::Result<()> {
+    http::serve(addr, "firefly_metrics", route)?;
+    metrics::add_sink(Arc::new(Exporter));
+    Ok(())
+}
+
+fn route(path: &str) -> Option<(&'static str, String)> {
+    if path != "/metrics" {
+        return None;
+    }
+    let text = latest()
+        .clone()
+        .unwrap_or_else(|| "# no metrics yet, as no process has run\n".to_string());
+    Some((CONTENT_TYPE, text))
+}
+
+/// The sink keeping the latest snapshot in the text format, so that requests are served from the
+/// thread of the endpoint without waiting on the scheduler
+struct Exporter;
+impl Sink for Exporter {
+    fn publish(&self, snapshot: &Snapshot) {
+        let text = snapshot.to_prometheus();
+        *latest() = Some(text);
+    }
+}
@@ -1 +1,9 @@
+pub mod activation;
 pub mod break_handler;
+pub mod cmd;
+pub mod diagnostics;
+pub mod drain;
+pub mod http;
+pub mod metrics;
+pub mod signals;
+pub mod watchdog;
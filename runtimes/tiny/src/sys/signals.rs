@@ -0,0 +1,103 @@
+//! The routing of OS signals to Erlang, see `os:set_signal/2`
+//!
+//! Each signal caught by the break handler is, by default, acted on by the runtime: `SIGINT` and
+//! `SIGTERM` drain the connections and shut down, see `drain`, and `SIGQUIT`, `SIGHUP` and
+//! `SIGABRT` terminate. With `os:set_signal(Signal, handle)`, the signal is instead delivered to
+//! the server registered as `erl_signal_server`, as `{notify, Signal}` as in OTP, e.g. so that a
+//! service shuts down in its own way on `sigterm`, and with `ignore` it is dropped.
+//!
+//! As the break handler runs on a thread of its own, signals to handle are queued, and delivered
+//! by the init process as it waits on timers and ports, see `gen::run_timers`, which is woken up
+//! for them while waiting on ports. A signal is dropped if no server is registered as
+//! `erl_signal_server` by then.
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use crate::erlang::gen::{self, Message};
+use crate::erlang::util::*;
+
+use super::break_handler::Signal;
+
+/// The name of the server signals are delivered to
+const SERVER: &str = "erl_signal_server";
+
+/// The disposition of each signal, indexed by `Signal`
+static DISPOSITIONS: [AtomicU8; 10] = [
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+    AtomicU8::new(0),
+];
+/// Set when signals are queued, so that checking for them costs a single load otherwise
+static RAISED: AtomicBool = AtomicBool::new(false);
+static PENDING: OnceLock<Mutex<Vec<Signal>>> = OnceLock::new();
+
+fn pending() -> MutexGuard<'static, Vec<Signal>> {
+    PENDING.get_or_init(Default::default).lock().unwrap()
+}
+
+/// What is done with a signal when it is caught
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Disposition {
+    /// The runtime acts on it, see the module documentation
+    Default = 0,
+    /// It is delivered to `erl_signal_server`
+    Handle,
+    Ignore,
+}
+impl Disposition {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::Default),
+            "handle" => Some(Self::Handle),
+            "ignore" => Some(Self::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// Sets what is done with `signal` when it is caught
+pub fn set_disposition(signal: Signal, disposition: Disposition) {
+    DISPOSITIONS[signal as usize].store(disposition as u8, Ordering::Relaxed);
+}
+
+pub fn disposition(signal: Signal) -> Disposition {
+    match DISPOSITIONS[signal as usize].load(Ordering::Relaxed) {
+        1 => Disposition::Handle,
+        2 => Disposition::Ignore,
+        _ => Disposition::Default,
+    }
+}
+
+/// Queues `signal` for delivery to `erl_signal_server`, waking up the init process
+///
+/// This is called by the break handler, so does not touch the scheduler.
+pub fn raise(signal: Signal) {
+    pending().push(signal);
+    RAISED.store(true, Ordering::Release);
+    firefly_driver::wake();
+}
+
+/// Delivers the signals queued since the last call to `erl_signal_server`
+pub fn deliver() {
+    if !RAISED.swap(false, Ordering::Acquire) {
+        return;
+    }
+    let signals = mem::take(&mut *pending());
+    let Some(pid) = gen::whereis(atom(SERVER).into()) else { return; };
+    for signal in signals {
+        let message = with_process(|proc| {
+            let message = make_tuple(proc, &[atom("notify").into(), atom(signal.name()).into()]);
+            make_global(message)
+        });
+        gen::cast(pid, Message::Info(message));
+    }
+}
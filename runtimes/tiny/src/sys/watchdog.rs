@@ -0,0 +1,152 @@
+//! The scheduler watchdog, which reports the scheduler being stuck in a single slice of a process
+//! for longer than `+watchdog Milliseconds`, e.g. in native code which never yields.
+//!
+//! The scheduler marks the start and end of each slice, see [`enter`] and [`leave`], and the thread
+//! of the watchdog checks on it four times per limit. Once a slice has run past the limit, the
+//! watchdog logs the process and how long it has been running, then interrupts the scheduler thread
+//! with `SIGURG`, whose handler logs the backtrace of the scheduler, i.e. of the process, naming
+//! the innermost Erlang function on it as the offender. Each slice is reported once. `SIGURG` is
+//! ignored by default, and is used by Go to preempt goroutines for much the same reason, so a stray
+//! one does no harm.
+//!
+//! With `+watchdog_abort true`, the runtime aborts once the backtrace is logged, so that a core
+//! dump can be taken. This is only honoured by debug builds, as the watchdog is otherwise a
+//! diagnostic.
+//!
+//! The backtrace is captured and printed by the signal handler, which allocates, so the report
+//! deadlocks if the process is stuck holding the lock of the allocator. That is a risk worth taking
+//! for a scheduler which is stuck anyway.
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use firefly_rt::backtrace::{Symbol, Trace};
+use firefly_rt::term::{Pid, ProcessId};
+
+use crate::time;
+
+/// The longest a slice may run for, in milliseconds, or 0 if the watchdog is disabled
+static LIMIT: AtomicU64 = AtomicU64::new(0);
+static ABORT: AtomicBool = AtomicBool::new(false);
+/// When the current slice began, in nanoseconds of `time::os_monotonic` plus one, or 0 between
+/// slices
+static SLICE: AtomicU64 = AtomicU64::new(0);
+/// The process running the current slice, as `number | serial << 32`
+static PROCESS: AtomicU64 = AtomicU64::new(0);
+static SCHEDULER: OnceLock<libc::pthread_t> = OnceLock::new();
+
+/// Sets how long a slice may run for before it is reported, see `+watchdog`
+pub fn set_limit(limit: Duration) {
+    LIMIT.store(limit.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Sets whether to abort once a stuck slice is reported, see `+watchdog_abort`
+pub fn set_abort(abort: bool) {
+    ABORT.store(abort && cfg!(debug_assertions), Ordering::Relaxed);
+}
+
+/// Starts the watchdog, if enabled, watching the scheduler of the calling thread
+pub fn start() {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    if limit == 0 || SCHEDULER.set(unsafe { libc::pthread_self() }).is_err() {
+        return;
+    }
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = report as extern "C" fn(c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGURG, &action, std::ptr::null_mut());
+    }
+    thread::Builder::new()
+        .name("firefly_watchdog".to_string())
+        .spawn(move || watch(Duration::from_millis(limit)))
+        .unwrap();
+}
+
+/// Marks the start of a slice of `pid`
+#[inline]
+pub fn enter(pid: ProcessId) {
+    if LIMIT.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let process = pid.number() as u64 | (pid.serial() as u64) << 32;
+    PROCESS.store(process, Ordering::Relaxed);
+    SLICE.store(time::os_monotonic() + 1, Ordering::Release);
+}
+
+/// Marks the end of the current slice
+#[inline]
+pub fn leave() {
+    SLICE.store(0, Ordering::Release);
+}
+
+/// Pauses the current slice while the process waits without doing any work, returning whether a
+/// slice was running, to be given to [`resume`]
+#[inline]
+pub fn pause() -> bool {
+    SLICE.swap(0, Ordering::AcqRel) != 0
+}
+
+/// Resumes the slice paused by [`pause`], if any, which may run for the full limit again
+#[inline]
+pub fn resume(paused: bool) {
+    if paused {
+        SLICE.store(time::os_monotonic() + 1, Ordering::Release);
+    }
+}
+
+fn watch(limit: Duration) {
+    let interval = (limit / 4).max(Duration::from_millis(1));
+    let mut reported = 0;
+    loop {
+        thread::sleep(interval);
+        let slice = SLICE.load(Ordering::Acquire);
+        if slice == 0 || slice == reported {
+            continue;
+        }
+        let elapsed = Duration::from_nanos(time::os_monotonic().saturating_sub(slice - 1));
+        if elapsed < limit {
+            continue;
+        }
+        reported = slice;
+
+        let process = PROCESS.load(Ordering::Relaxed);
+        let pid = ProcessId::new((process as u32) as usize, (process >> 32) as usize)
+            .map(|id| Pid::Local { id }.to_string())
+            .unwrap_or_else(|_| "an unknown process".to_string());
+        eprintln!(
+            "watchdog: scheduler stuck for {}ms in {}, backtrace follows",
+            elapsed.as_millis(),
+            pid
+        );
+        unsafe {
+            libc::pthread_kill(*SCHEDULER.get().unwrap(), libc::SIGURG);
+        }
+    }
+}
+
+/// Logs the backtrace of the scheduler thread, on which this is run as a signal handler
+extern "C" fn report(_signal: c_int) {
+    let trace = Trace::capture();
+    match trace.iter_symbols().find_map(|symbol| symbol.mfa()) {
+        Some(mfa) => eprintln!("watchdog: stuck in {}", mfa),
+        None => eprintln!("watchdog: stuck outside of Erlang code"),
+    }
+    for symbol in trace.iter_symbols() {
+        let name = match symbol.symbol() {
+            Some(Symbol::Erlang(mfa)) => mfa.to_string(),
+            Some(Symbol::Native(name)) => name.clone(),
+            None => "???".to_string(),
+        };
+        match (symbol.filename(), symbol.line()) {
+            (Some(file), Some(line)) => eprintln!("    {} at {}:{}", name, file, line),
+            _ => eprintln!("    {}", name),
+        }
+    }
+    if ABORT.load(Ordering::Relaxed) {
+        std::process::abort();
+    }
+}
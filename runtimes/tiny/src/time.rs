@@ -0,0 +1,224 @@
+//! The clocks of the runtime, and the abstraction over those of the OS they are read from.
+//!
+//! As in ERTS, Erlang monotonic time never goes backwards, and Erlang system time is monotonic time
+//! plus the time offset. Monotonic time is measured from when a clock is first read, in
+//! nanoseconds, which is also the `native` time unit. How the time offset follows the system time
+//! of the OS is decided by the time warp mode, set with `+C Mode`:
+//!
+//! * `no_time_warp`, the default, fixes the offset when the runtime starts, so system time never
+//! warps, and drifts from OS system time if that is adjusted.
+//! * `single_time_warp` fixes the offset until it is finalized with
+//! `erlang:system_flag(time_offset, finalize)`, at which point it is set once more, to match OS
+//! system time at that moment, and system time may warp, once.
+//! * `multi_time_warp` has the offset follow OS system time, so system time warps whenever OS
+//! system time does, while monotonic time is unaffected.
+//!
+//! Unlike ERTS, the rate of the clocks is never corrected, there is no time correction, so system
+//! time in `no_time_warp` mode keeps whatever drift OS system time had from monotonic time.
+//!
+//! In a simulation, both monotonic time and OS system time are virtual, see `crate::sim`.
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+use instant::Instant;
+
+use crate::sim;
+
+/// The parts per second of the `native` time unit
+pub(crate) const NATIVE: u64 = 1_000_000_000;
+
+static MODE: AtomicU8 = AtomicU8::new(WarpMode::Disabled as u8);
+static CLOCK: OnceLock<Clock> = OnceLock::new();
+/// The last value returned by `unique`, and the last time in microseconds returned by `now`
+static UNIQUE: AtomicU64 = AtomicU64::new(0);
+static NOW: AtomicU64 = AtomicU64::new(0);
+
+struct Clock {
+    /// The instant monotonic time is measured from
+    epoch: Instant,
+    /// The time offset, in nanoseconds
+    offset: AtomicI64,
+    /// Whether the offset has been finalized, in `single_time_warp` mode
+    finalized: AtomicBool,
+}
+
+fn clock() -> &'static Clock {
+    // Monotonic time is zero at the epoch, so the offset is OS system time at that point
+    CLOCK.get_or_init(|| Clock {
+        epoch: Instant::now(),
+        offset: AtomicI64::new(os_system_time()),
+        finalized: AtomicBool::new(false),
+    })
+}
+
+/// How the time offset follows OS system time, see the module documentation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum WarpMode {
+    Disabled,
+    Single,
+    Multi,
+}
+impl WarpMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "no_time_warp" => Some(Self::Disabled),
+            "single_time_warp" => Some(Self::Single),
+            "multi_time_warp" => Some(Self::Multi),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Disabled => "no_time_warp",
+            Self::Single => "single_time_warp",
+            Self::Multi => "multi_time_warp",
+        }
+    }
+}
+
+/// Whether the time offset can still change, as by `erlang:system_info(time_offset)`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum OffsetState {
+    Preliminary,
+    Final,
+    Volatile,
+}
+impl OffsetState {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Preliminary => "preliminary",
+            Self::Final => "final",
+            Self::Volatile => "volatile",
+        }
+    }
+}
+
+/// Sets the time warp mode, which must be done before any clock is read, see `+C`
+pub(crate) fn set_warp_mode(mode: WarpMode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub(crate) fn warp_mode() -> WarpMode {
+    match MODE.load(Ordering::Relaxed) {
+        0 => WarpMode::Disabled,
+        1 => WarpMode::Single,
+        _ => WarpMode::Multi,
+    }
+}
+
+/// Returns Erlang monotonic time, in nanoseconds
+pub(crate) fn monotonic() -> u64 {
+    if sim::is_enabled() {
+        return sim::elapsed();
+    }
+    os_monotonic()
+}
+
+/// Returns the time elapsed since the epoch of monotonic time, in nanoseconds, which is real time
+/// even in a simulation
+pub(crate) fn os_monotonic() -> u64 {
+    clock().epoch.elapsed().as_nanos() as u64
+}
+
+/// Returns the time offset, in nanoseconds, i.e. Erlang system time less monotonic time
+pub(crate) fn offset() -> i64 {
+    let clock = clock();
+    if warp_mode() == WarpMode::Multi {
+        let offset = os_system_time() - monotonic() as i64;
+        clock.offset.store(offset, Ordering::Relaxed);
+    }
+    clock.offset.load(Ordering::Relaxed)
+}
+
+/// Returns Erlang system time, in nanoseconds since the Unix epoch
+pub(crate) fn system() -> i64 {
+    monotonic() as i64 + offset()
+}
+
+pub(crate) fn offset_state() -> OffsetState {
+    match warp_mode() {
+        WarpMode::Disabled => OffsetState::Final,
+        WarpMode::Single if is_finalized() => OffsetState::Final,
+        WarpMode::Single => OffsetState::Preliminary,
+        WarpMode::Multi => OffsetState::Volatile,
+    }
+}
+
+fn is_finalized() -> bool {
+    clock().finalized.load(Ordering::Relaxed)
+}
+
+/// Finalizes the time offset, returning its state beforehand
+///
+/// This only has an effect in `single_time_warp` mode, where the offset is set to match OS system
+/// time for the last time.
+pub(crate) fn finalize_offset() -> OffsetState {
+    let state = offset_state();
+    if state == OffsetState::Preliminary {
+        let clock = clock();
+        let offset = os_system_time() - monotonic() as i64;
+        clock.offset.store(offset, Ordering::Relaxed);
+        clock.finalized.store(true, Ordering::Relaxed);
+    }
+    state
+}
+
+/// Returns an integer unique to this runtime instance, greater than any returned before
+pub(crate) fn unique() -> u64 {
+    UNIQUE.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Returns Erlang system time in microseconds, greater than any returned before, as `erlang:now/0`
+pub(crate) fn now() -> u64 {
+    let time = (system() / 1_000).max(0) as u64;
+    let mut last = NOW.load(Ordering::Relaxed);
+    loop {
+        let next = time.max(last + 1);
+        match NOW.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => break next,
+            Err(current) => last = current,
+        }
+    }
+}
+
+/// Converts `time` from `from` to `to` parts per second, rounding towards negative infinity as
+/// `erlang:convert_time_unit/3` does
+pub(crate) fn convert(time: i128, from: u64, to: u64) -> i128 {
+    (time * to as i128).div_euclid(from as i128)
+}
+
+/// Returns the parts per second of the time unit named `name`
+pub(crate) fn unit(name: &str) -> Option<u64> {
+    match name {
+        "second" | "seconds" => Some(1),
+        "millisecond" | "milli_seconds" => Some(1_000),
+        "microsecond" | "micro_seconds" => Some(1_000_000),
+        "nanosecond" | "nano_seconds" | "native" | "perf_counter" => Some(NATIVE),
+        _ => None,
+    }
+}
+
+/// Returns OS system time, in nanoseconds since the Unix epoch
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) fn os_system_time() -> i64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    if let Some(time) = sim::system_time() {
+        return time;
+    }
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_nanos() as i64,
+        Err(err) => -(err.duration().as_nanos() as i64),
+    }
+}
+
+/// The system time of the browser is only available in milliseconds
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) fn os_system_time() -> i64 {
+    if let Some(time) = sim::system_time() {
+        return time;
+    }
+    (js_sys::Date::now() * 1_000_000.0) as i64
+}
@@ -0,0 +1,178 @@
+//! Conversion of terms to JavaScript values, and vice versa.
+//!
+//! Terms are converted to JavaScript values as follows:
+//!
+//! * `true` and `false` to booleans, `undefined` and `null` to their JavaScript equivalents, and
+//!   any other atom to a string
+//! * Integers to numbers, or to a `BigInt` if outside the range of integers a number can represent
+//!   exactly, and floats to numbers
+//! * Binaries to strings if they are valid UTF-8, and to a `Uint8Array` otherwise
+//! * Proper lists and tuples to arrays, and maps to plain objects, whose keys are converted to
+//!   strings as property keys
+//! * References to JavaScript values (see below) to the value referred to
+//! * Anything else, e.g. pids and funs, to the string of its printed representation
+//!
+//! JavaScript values are converted to terms as follows:
+//!
+//! * Booleans to `true` and `false`, and `undefined` and `null` to atoms of the same name
+//! * Numbers to integers if they are integral and in the safe range, and to floats otherwise, and
+//!   `BigInt` to integers
+//! * Strings to UTF-8 binaries, and a `Uint8Array` to a binary of its bytes
+//! * Arrays to lists, and plain objects to maps with binary keys
+//! * Anything else, e.g. functions and DOM nodes, to a reference to the value
+//!
+//! Strings are never converted to atoms, so that values from JavaScript cannot exhaust the atom
+//! table. A reference to a JavaScript value keeps the value alive as long as the term does, and
+//! two references compare equal only if they were produced by the same conversion.
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::util::*;
+
+/// The scheduler id used in the identifiers of references to JavaScript values
+const JS_REFERENCE_SCHEDULER_ID: u16 = u16::MAX - 2;
+
+/// The largest integer which can be represented exactly by a JavaScript number
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+static NEXT_HANDLE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The value of a magic reference referring to a JavaScript value
+struct JsHandle(JsValue);
+
+/// Converts `term` to a JavaScript value
+pub fn to_js(term: OpaqueTerm) -> JsValue {
+    let term: Term = term.into();
+    match term {
+        Term::None => JsValue::UNDEFINED,
+        Term::Nil => Array::new().into(),
+        Term::Bool(value) => JsValue::from_bool(value),
+        Term::Atom(atom) => match atom.as_str() {
+            "undefined" => JsValue::UNDEFINED,
+            "null" => JsValue::NULL,
+            name => JsValue::from_str(name),
+        },
+        Term::Int(i) if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&i) => {
+            JsValue::from_f64(i as f64)
+        }
+        Term::Int(i) => js_sys::BigInt::from(i).into(),
+        Term::BigInt(i) => js_sys::BigInt::new(&JsValue::from_str(&i.to_string()))
+            .map(|i| i.into())
+            .unwrap_or(JsValue::UNDEFINED),
+        Term::Float(f) => JsValue::from_f64(f.inner()),
+        Term::Cons(ptr) => {
+            let array = Array::new();
+            for element in unsafe { ptr.as_ref() }.iter() {
+                match element {
+                    Ok(element) => array.push(&to_js(element.into())),
+                    Err(_) => return JsValue::from_str(&term.to_string()),
+                };
+            }
+            array.into()
+        }
+        Term::Tuple(ptr) => unsafe { ptr.as_ref() }
+            .as_slice()
+            .iter()
+            .map(|element| to_js(*element))
+            .collect::<Array>()
+            .into(),
+        Term::Map(map) => {
+            let object = Object::new();
+            for (key, value) in map.iter() {
+                Reflect::set(&object, &to_js((*key).into()), &to_js((*value).into())).unwrap();
+            }
+            object.into()
+        }
+        Term::Reference(reference) => match reference
+            .magic()
+            .and_then(|value| value.downcast_ref::<JsHandle>())
+        {
+            Some(handle) => handle.0.clone(),
+            None => JsValue::from_str(&term.to_string()),
+        },
+        term => match term.as_bitstring() {
+            Some(bits) if bits.is_binary() && bits.is_aligned() => {
+                let bytes = unsafe { bits.as_bytes_unchecked() };
+                match core::str::from_utf8(bytes) {
+                    Ok(s) => JsValue::from_str(s),
+                    Err(_) => Uint8Array::from(bytes).into(),
+                }
+            }
+            _ => JsValue::from_str(&term.to_string()),
+        },
+    }
+}
+
+/// Converts `value` to a term allocated on the heap of `proc`
+pub fn from_js(value: &JsValue, proc: &Process) -> OpaqueTerm {
+    if value.is_undefined() {
+        return atom("undefined").into();
+    }
+    if value.is_null() {
+        return atom("null").into();
+    }
+    if let Some(value) = value.as_bool() {
+        return value.into();
+    }
+    if let Some(n) = value.as_f64() {
+        if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER as f64 {
+            return (n as i64).try_into().unwrap();
+        }
+        return n.into();
+    }
+    if let Some(s) = value.as_string() {
+        return make_binary(proc, s.as_bytes());
+    }
+    if let Some(i) = value.dyn_ref::<js_sys::BigInt>() {
+        let digits = String::from(i.to_string(10).unwrap());
+        return make_integer(proc, digits.parse().unwrap());
+    }
+    if Array::is_array(value) {
+        let elements = Array::from(value)
+            .iter()
+            .map(|element| from_js(&element, proc))
+            .collect::<Vec<_>>();
+        return make_list(proc, elements.as_slice());
+    }
+    if let Some(bytes) = value.dyn_ref::<Uint8Array>() {
+        return make_binary(proc, bytes.to_vec().as_slice());
+    }
+    if is_plain_object(value) {
+        let entries = Object::entries(value.unchecked_ref())
+            .iter()
+            .map(|entry| {
+                let entry = Array::from(&entry);
+                let key = from_js(&entry.get(0), proc);
+                let value = from_js(&entry.get(1), proc);
+                (key.into(), value.into())
+            })
+            .collect::<Vec<(Term, Term)>>();
+        return Term::from(Map::new_from_iter_in(entries.into_iter(), proc).unwrap()).into();
+    }
+    make_handle(value.clone(), proc)
+}
+
+/// Returns true if `value` is an object created by an object literal, or `Object.create(null)`
+fn is_plain_object(value: &JsValue) -> bool {
+    if !value.is_object() || value.is_function() {
+        return false;
+    }
+    let prototype: JsValue = Object::get_prototype_of(value).into();
+    prototype.is_null() || prototype == JsValue::from(Object::get_prototype_of(&Object::new()))
+}
+
+/// Allocates a reference to `value` on the heap of `proc`
+fn make_handle(value: JsValue, proc: &Process) -> OpaqueTerm {
+    let id = NEXT_HANDLE_ID.fetch_add(1, Ordering::Relaxed);
+    let id = ReferenceId::new(JS_REFERENCE_SCHEDULER_ID, id);
+    let handle = GcBox::<dyn Any>::new_unsize_in(JsHandle(value), proc).unwrap();
+    let reference = GcBox::new_in(Reference::new_magic(id, handle), proc).unwrap();
+    Term::Reference(reference).into()
+}
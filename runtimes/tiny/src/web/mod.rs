@@ -0,0 +1,171 @@
+//! Support for running in the browser, on wasm32.
+//!
+//! The browser event loop must never be blocked, so rather than running until the system halts, the
+//! entry point boots the system and returns, and everything else happens in tasks run by the event
+//! loop: servers are driven by the messages JavaScript sends them, and their timeouts by a single
+//! `setTimeout` scheduled for whichever expires next. Processes cannot be suspended on this target
+//! (see `scheduler`), so each task runs to completion. The system only shuts down once halted, by
+//! `erlang:halt/0,1,2` or by failing to boot, after which no more messages are delivered, and the
+//! callbacks registered with `crate::halt::on_halt` are run then rather than once booted.
+//!
+//! JavaScript and Erlang interact by passing messages, which are converted as described in
+//! [`convert`]:
+//!
+//! * `send(name, message)` delivers a message to the server registered as `name`, which receives it
+//! in `handle_info`, e.g. from an event listener
+//! * `js:post/1` passes a message to each of the listeners registered with `onMessage(listener)`
+//!
+//! The `js` module also gives Erlang code direct access to JavaScript objects, e.g. the DOM and the
+//! console. The module is expected to be processed with `wasm-bindgen`, whose glue calls `start`
+//! when the module is instantiated.
+pub mod convert;
+
+use std::cell::{Cell, RefCell};
+use std::ffi::OsString;
+use std::iter;
+
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+use firefly_rt::term::Atom;
+
+use crate::env;
+use crate::erlang::gen::{self, Message};
+use crate::erlang::util::*;
+use crate::halt::{Request, Status};
+use crate::scheduler;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &JsValue, timeout: i32) -> JsValue;
+
+    #[wasm_bindgen(js_name = clearTimeout)]
+    fn clear_timeout(handle: &JsValue);
+}
+
+/// The listeners registered with `onMessage`
+#[thread_local]
+static LISTENERS: RefCell<Vec<Function>> = RefCell::new(Vec::new());
+
+/// The handle of the `setTimeout` which will run the next timeout to expire, if any
+#[thread_local]
+static PENDING_TIMER: Cell<Option<JsValue>> = Cell::new(None);
+
+/// Set once the system has shut down, see `shutdown_if_halted`
+#[thread_local]
+static HALTED: Cell<bool> = Cell::new(false);
+
+/// Initializes the runtime and boots the system, once the module is instantiated
+#[wasm_bindgen(start)]
+pub fn start() {
+    let code = firefly_crt::main_internal();
+    if code != 0 {
+        web_sys::console::error_1(&format!("failed to start the runtime ({})", code).into());
+    }
+}
+
+/// The entry point called by `firefly_crt` once the runtime is initialized
+///
+/// This returns as soon as the system has booted, with its exit code if it halted while booting.
+#[export_name = "firefly_entry"]
+pub unsafe extern "C" fn main() -> i32 {
+    let name = env!("CARGO_PKG_NAME");
+    env::init(iter::once(OsString::from(name))).unwrap();
+
+    scheduler::init();
+    scheduler::with_current(|scheduler| scheduler.spawn_init()).unwrap();
+    // The init process runs until the boot function returns, as it cannot be suspended
+    while scheduler::with_current(|scheduler| scheduler.run_once()) {}
+
+    // The system halts if it failed to boot, as it does with `erlang:halt/1`
+    if scheduler::with_current(|scheduler| scheduler.has_failed()) {
+        crate::halt::request(Request {
+            status: Status::Code(1),
+            flush: true,
+            flush_timeout: None,
+        });
+    }
+    if let Some(code) = shutdown_if_halted() {
+        return code;
+    }
+    run_timers();
+    0
+}
+
+/// Sends `message` to the server registered as `name`, returning false if there is no such server
+///
+/// The message is handled before this returns.
+#[wasm_bindgen]
+pub fn send(name: &str, message: JsValue) -> bool {
+    if HALTED.get() {
+        return false;
+    }
+    let server = Atom::try_from_str_existing(name)
+        .ok()
+        .and_then(|name| gen::whereis(name.into()));
+    let Some(pid) = server else {
+        return false;
+    };
+    let message = with_process(|proc| make_global(convert::from_js(&message, proc)));
+    gen::cast(pid, Message::Info(message));
+    if shutdown_if_halted().is_none() {
+        // Handling the message may have started or cancelled a timeout
+        run_timers();
+    }
+    true
+}
+
+/// Registers `listener` to be called with each message posted by Erlang code via `js:post/1`
+#[wasm_bindgen(js_name = onMessage)]
+pub fn on_message(listener: Function) {
+    LISTENERS.borrow_mut().push(listener);
+}
+
+/// Calls each listener registered with `onMessage` with `message`
+///
+/// Returns the first exception raised by a listener, if any.
+pub(crate) fn post(message: &JsValue) -> Result<(), JsValue> {
+    // Listeners may register other listeners
+    let listeners = LISTENERS.borrow().clone();
+    for listener in listeners.iter() {
+        listener.call1(&JsValue::UNDEFINED, message)?;
+    }
+    Ok(())
+}
+
+/// Handles the timeouts which have expired, and schedules this to run again when the next expires
+fn run_timers() {
+    if let Some(handle) = PENDING_TIMER.take() {
+        clear_timeout(&handle);
+    }
+    if HALTED.get() {
+        return;
+    }
+    let timeout = gen::run_expired_timers();
+    if shutdown_if_halted().is_some() {
+        return;
+    }
+    let Some(timeout) = timeout else {
+        return;
+    };
+    // Round up, so that the next timeout has expired when this runs again
+    let timeout = (timeout.as_micros() + 999) / 1000;
+    let handler = Closure::once_into_js(run_timers);
+    let handle = set_timeout(&handler, timeout.try_into().unwrap_or(i32::MAX));
+    PENDING_TIMER.set(Some(handle));
+}
+
+/// Shuts the system down if a halt has been requested, returning its exit code
+///
+/// This runs the callbacks registered with `crate::halt::on_halt`, see `crate::halt::run`.
+fn shutdown_if_halted() -> Option<i32> {
+    if HALTED.get() || !crate::halt::is_requested() {
+        return None;
+    }
+    HALTED.set(true);
+    if let Some(handle) = PENDING_TIMER.take() {
+        clear_timeout(&handle);
+    }
+    Some(scheduler::with_current(|scheduler| scheduler.shutdown()).to_i32())
+}
@@ -0,0 +1,23 @@
+//! The runtime for server-side WebAssembly, i.e. the `wasm32-wasi` target.
+//!
+//! This is the tiny runtime with an entry point suitable for WASI hosts such as wasmtime: the
+//! module's `_start` initializes the core runtime via `firefly_crt`, which then calls the entry
+//! point defined here. WASI has no threads or signals, so the scheduler runs on the main thread,
+//! and processes are run cooperatively until the system halts, at which point its exit code is
+//! returned to the host. Files and the standard streams are accessed via WASI syscalls, so the host
+//! must preopen any directories the program needs, e.g. `wasmtime --dir=. app.wasm`.
+//!
+//! On any other target this crate is empty, so that it does not break builds of the workspace.
+#![cfg(target_os = "wasi")]
+#![feature(c_unwind)]
+#![feature(process_exitcode_internals)]
+
+extern crate firefly_rt_tiny;
+
+/// The entry point called by `firefly_crt` once the runtime is initialized
+#[export_name = "firefly_entry"]
+pub unsafe extern "C" fn main() -> i32 {
+    use std::process::Termination;
+
+    firefly_rt_tiny::run(std::env::args_os()).report().to_i32()
+}
@@ -0,0 +1,50 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile 2>&1; echo "status $?"
+
+%% CHECK: application: temp_app
+%% CHECK: exited: crashed
+%% CHECK: type: temporary
+%% CHECK: {running, [perm_app, stdlib, kernel]}
+%% CHECK: application: perm_app
+%% CHECK: type: permanent
+%% CHECK: Kernel pid terminated (application_controller) ({application_terminated,perm_app,crashed})
+%% CHECK: status 1
+-module(init).
+
+-behaviour(application).
+-behaviour(gen_server).
+
+-export([boot/1]).
+-export([start/2, stop/1]).
+-export([init/1, handle_call/3, handle_cast/2]).
+
+-import(erlang, [display/1]).
+
+%% The termination of a temporary application is only reported, while that of a permanent one
+%% halts the system once boot returns
+boot(_) ->
+  ok = application:load(spec(temp_app)),
+  ok = application:load(spec(perm_app)),
+  ok = application:start(temp_app, temporary),
+  ok = application:start(perm_app, permanent),
+  ok = gen_server:cast(temp_app, stop),
+  display({running, [Name || {Name, _, _} <- application:which_applications()]}),
+  ok = gen_server:cast(perm_app, stop).
+
+spec(Name) ->
+  Props = [{description, "test"}, {vsn, "1"}, {applications, []}, {mod, {init, Name}}],
+  {application, Name, Props}.
+
+start(normal, Name) ->
+  gen_server:start({local, Name}, init, [], []).
+
+stop(_State) ->
+  ok.
+
+init([]) ->
+  {ok, []}.
+
+handle_call(_Request, _From, State) ->
+  {reply, ok, State}.
+
+handle_cast(stop, State) ->
+  {stop, crashed, State}.
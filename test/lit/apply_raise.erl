@@ -0,0 +1,26 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {caught, error, x}
+%% CHECK: {caught, throw, y}
+-module(init).
+
+-export([boot/1]).
+
+-import(erlang, [display/1]).
+
+%% The funs are called by the runtime, so their exceptions unwind out of generated code into it,
+%% which returns them to the caller as though the funs had returned them
+boot(_) ->
+  try
+    apply(fun() -> error(x) end, [])
+  catch
+    Class:Reason ->
+      display({caught, Class, Reason})
+  end,
+  Y = y,
+  try
+    erlang:apply(fun(Z) -> throw(Z) end, [Y])
+  catch
+    Class2:Reason2 ->
+      display({caught, Class2, Reason2})
+  end.
@@ -0,0 +1,31 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1
+
+%% CHECK: unreachable case clause
+%% CHECK: previous clauses already match this value
+%% CHECK: unreachable case clause
+%% CHECK: previous clauses already match every possible value
+-module(init).
+
+-export([boot/1]).
+
+-spec status() -> ok | error.
+status() ->
+    ok.
+
+boot(Args) ->
+    {pinned(Args), fresh()}.
+
+%% X is bound, so the first clause only matches whatever it is bound to, and the second still
+%% matches ok, unlike the third
+pinned(X) ->
+    case status() of
+        X -> matched;
+        ok -> ok;
+        ok -> again
+    end.
+
+fresh() ->
+    case status() of
+        Status -> Status;
+        error -> error
+    end.
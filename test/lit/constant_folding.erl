@@ -0,0 +1,38 @@
+%% RUN: @firefly compile -O -o @tempfile @file && @tempfile
+
+%% CHECK: {equal, true}
+%% CHECK: {exact, false}
+%% CHECK: {not_exact, true}
+%% CHECK: {element, b}
+%% CHECK: {div_by_zero, badarith}
+%% CHECK: {rem_by_zero, badarith}
+%% CHECK: {element_out_of_range, badarg}
+%% CHECK: {element_zero, badarg}
+-module(init).
+
+-export([boot/1]).
+
+-import(erlang, [display/1]).
+
+%% Every operand is known at -O, so the calls which cannot fail are folded to their results,
+%% while those which would raise are left for the runtime to raise
+boot(_) ->
+  Int = 1,
+  Float = 1.0,
+  Tuple = {a, b},
+  display({equal, Int == Float}),
+  display({exact, Int =:= Float}),
+  display({not_exact, Int =/= Float}),
+  display({element, element(2, Tuple)}),
+  display({div_by_zero, error_reason(fun() -> Int div 0 end)}),
+  display({rem_by_zero, error_reason(fun() -> Int rem 0 end)}),
+  display({element_out_of_range, error_reason(fun() -> element(3, Tuple) end)}),
+  display({element_zero, error_reason(fun() -> element(0, Tuple) end)}).
+
+error_reason(Fun) ->
+  try
+    Fun()
+  catch
+    error:Reason ->
+      Reason
+  end.
@@ -0,0 +1,23 @@
+%% RUN: @firefly compile -o @tempfile --emit=cost @file 2>&1
+
+%% CHECK: potentially quadratic recursion
+%% CHECK: `++` in a recursive function copies its left operand on every iteration
+%% CHECK: potentially quadratic recursion
+%% CHECK: length/1 in a recursive function walks the whole list on every iteration
+-module(init).
+
+-export([boot/1]).
+
+boot(Args) ->
+    {append(Args, []), count(Args, 0)}.
+
+%% The warnings point at the offending expressions, rather than only naming the function
+append([H | T], Acc) ->
+    append(T, Acc ++ [H]);
+append([], Acc) ->
+    Acc.
+
+count(L, N) when length(L) > 0 ->
+    count(tl(L), N + 1);
+count(_, N) ->
+    N.
@@ -0,0 +1,47 @@
+%% RUN: @firefly test --results-dir @tempfile @file
+
+%% CHECK: Failed: 0.  Skipped: 1.  Passed: 3.
+-module(ct_SUITE).
+
+-include_lib("common_test/include/ct.hrl").
+
+-export([all/0, groups/0, init_per_suite/1, end_per_suite/1]).
+-export([init_per_group/2, end_per_group/2]).
+-export([config_case/1, priv_dir_case/1, grouped_case/1, skipped_case/1]).
+
+all() ->
+  [config_case, priv_dir_case, {group, arithmetic}, skipped_case].
+
+groups() ->
+  [{arithmetic, [sequence], [grouped_case]}].
+
+init_per_suite(Config) ->
+  [{answer, 42} | Config].
+
+end_per_suite(_Config) ->
+  ok.
+
+init_per_group(arithmetic, Config) ->
+  [{operand, 2} | Config].
+
+end_per_group(_Group, _Config) ->
+  ok.
+
+%% The configuration returned by init_per_suite is given to every test case
+config_case(Config) ->
+  42 = ?config(answer, Config),
+  ok.
+
+%% Each test case has its own private directory
+priv_dir_case(Config) ->
+  PrivDir = ?config(priv_dir, Config),
+  true = is_list(PrivDir),
+  ok.
+
+grouped_case(Config) ->
+  4 = ?config(operand, Config) * 2,
+  42 = ?config(answer, Config),
+  ok.
+
+skipped_case(_Config) ->
+  {skip, "not supported"}.
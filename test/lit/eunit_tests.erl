@@ -0,0 +1,32 @@
+%% RUN: @firefly test @file
+
+%% CHECK: All 6 tests passed.
+-module(eunit_tests).
+
+-include_lib("eunit/include/eunit.hrl").
+
+-record(counter, {value = 0}).
+
+addition_test() ->
+  ?assertEqual(4, 2 + 2).
+
+match_test() ->
+  ?assertMatch({ok, _}, {ok, 1}).
+
+%% Each test of a generator is counted separately
+arithmetic_test_() ->
+  [?_assertEqual(1, 3 - 2),
+   {"division by zero", ?_assertError(badarith, 1 div zero())}].
+
+%% The setup runs before the tests, which are instantiated with its result
+setup_test_() ->
+  {setup,
+   fun() -> #counter{value = 1} end,
+   fun(_) -> ok end,
+   fun(#counter{value = Value}) ->
+       [?_assert(Value =:= 1),
+        {timeout, 1, ?_assertThrow(done, throw(done))}]
+   end}.
+
+zero() ->
+  0.
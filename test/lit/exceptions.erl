@@ -0,0 +1,50 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {caught, throw, deep}
+%% CHECK: {caught, error, badarith}
+%% CHECK: {caught, exit, normal}
+%% CHECK: cleanup
+%% CHECK: {result, ok}
+%% CHECK: cleanup
+%% CHECK: {caught, throw, inner}
+%% CHECK: {'catch', deep}
+-module(init).
+
+-export([boot/1]).
+
+-import(erlang, [display/1]).
+
+%% Each exception is raised several frames below the handler which catches it, so it unwinds
+%% through frames which do not handle it
+boot(_) ->
+  catch_class(fun() -> nested(10, fun() -> throw(deep) end) end),
+  catch_class(fun() -> nested(10, fun() -> divide(1, 0) end) end),
+  catch_class(fun() -> nested(10, fun() -> exit(normal) end) end),
+  display({result, with_after(fun() -> ok end)}),
+  catch_class(fun() -> with_after(fun() -> nested(10, fun() -> throw(inner) end) end) end),
+  display({'catch', catch nested(10, fun() -> throw(deep) end)}).
+
+catch_class(Fun) ->
+  try
+    Fun()
+  catch
+    Class:Reason ->
+      display({caught, Class, Reason})
+  end.
+
+%% The after block runs whether the body returns or raises
+with_after(Fun) ->
+  try
+    Fun()
+  after
+    display(cleanup)
+  end.
+
+nested(0, Fun) ->
+  Fun();
+nested(N, Fun) ->
+  {ok, Result} = {ok, nested(N - 1, Fun)},
+  Result.
+
+divide(A, B) ->
+  A div B.
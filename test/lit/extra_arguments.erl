@@ -0,0 +1,16 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile -name foo -extra +Mlimit nonsense -name bar
+
+%% CHECK: {name, [<<"foo">>]}
+%% CHECK: [<<"+Mlimit">>, <<"nonsense">>, <<"-name">>, <<"bar">>]
+-module(init).
+
+-export([boot/1]).
+
+-import(erlang, [display/1]).
+
+%% The arguments after -extra are plain arguments, even those which look like flags, and are not
+%% taken by the runtime, which would reject +Mlimit without a size
+boot(_) ->
+  {ok, [Values]} = init:get_argument(name),
+  display({name, [list_to_binary(Value) || Value <- Values]}),
+  display([list_to_binary(Arg) || Arg <- init:get_plain_arguments()]).
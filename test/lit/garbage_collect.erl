@@ -0,0 +1,27 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {kept, [1, 2, 3]}
+-module(init).
+
+-export([boot/1]).
+
+-import(erlang, [display/1]).
+
+%% The garbage is dropped before collecting, but the list is live across the call, so it is only
+%% found, and moved, via the stack map of the call
+boot(_) ->
+  Kept = seq(1, 3),
+  garbage(1000),
+  erlang:garbage_collect(),
+  display({kept, Kept}).
+
+garbage(0) ->
+  ok;
+garbage(N) ->
+  _ = seq(1, 10),
+  garbage(N - 1).
+
+seq(N, N) ->
+  [N];
+seq(M, N) ->
+  [M | seq(M + 1, N)].
@@ -0,0 +1,50 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {collected, true}
+%% CHECK: {unstarted, true}
+%% CHECK: {caught, error, badarg}
+%% CHECK: {kept, [1, 2, 3]}
+-module(init).
+
+-export([boot/1, work/1]).
+
+-import(erlang, [display/1]).
+
+%% The worker is preempted while it makes garbage, and collects its heap on behalf of the caller
+%% the next time it is scheduled, while the list it holds is live
+boot(_) ->
+  Pid = erlang:spawn_opt(init, work, [100000], []),
+  spin(100000),
+  display({collected, erlang:garbage_collect(Pid)}),
+  Unstarted = erlang:spawn_opt(init, work, [0], []),
+  display({unstarted, erlang:garbage_collect(Unstarted, [{type, minor}])}),
+  try
+    erlang:garbage_collect(self(), [{async, request}])
+  catch
+    Class:Reason ->
+      display({caught, Class, Reason})
+  end.
+
+work(N) ->
+  Kept = seq(1, 3),
+  garbage(N),
+  case N of
+    0 -> ok;
+    _ -> display({kept, Kept})
+  end.
+
+spin(0) ->
+  ok;
+spin(N) ->
+  spin(N - 1).
+
+garbage(0) ->
+  ok;
+garbage(N) ->
+  _ = seq(1, 10),
+  garbage(N - 1).
+
+seq(N, N) ->
+  [N];
+seq(M, N) ->
+  [M | seq(M + 1, N)].
@@ -0,0 +1,66 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {call, 0}
+%% CHECK: {cast, 2}
+%% CHECK: {noreply, timeout}
+%% CHECK: {crashed, boom}
+%% CHECK: {stopped, noproc}
+%% CHECK: {tick, 2}
+%% CHECK: {tick, 1}
+%% CHECK: {terminate, done}
+-module(init).
+
+-behaviour(gen_server).
+
+-export([boot/1]).
+-export([init/1, handle_call/3, handle_cast/2, handle_info/2, terminate/2]).
+
+-import(erlang, [display/1]).
+
+%% Servers have their callbacks invoked by whichever process calls them, so the replies and
+%% failures are seen in order, while the timeouts of the ticker are handled once boot returns
+boot(_) ->
+  {ok, Pid} = gen_server:start({local, counter}, init, counter, []),
+  display({call, gen_server:call(counter, get)}),
+  ok = gen_server:cast(counter, {add, 2}),
+  display({cast, gen_server:call(Pid, get)}),
+  display({noreply, exit_reason(fun() -> gen_server:call(counter, ignore) end)}),
+  display({crashed, exit_reason(fun() -> gen_server:call(counter, crash) end)}),
+  display({stopped, exit_reason(fun() -> gen_server:call(counter, get) end)}),
+  {ok, _} = gen_server:start({local, ticker}, init, {ticker, 2}, []).
+
+exit_reason(Fun) ->
+  try
+    Fun()
+  catch
+    exit:{{Reason, _Stacktrace}, {gen_server, call, _}} ->
+      Reason;
+    exit:{Reason, {gen_server, call, _}} ->
+      Reason
+  end.
+
+init(counter) ->
+  {ok, 0};
+init({ticker, N}) ->
+  {ok, N, 10}.
+
+handle_call(get, _From, N) ->
+  {reply, N, N};
+handle_call(ignore, _From, N) ->
+  {noreply, N};
+handle_call(crash, _From, _N) ->
+  error(boom).
+
+handle_cast({add, M}, N) ->
+  {noreply, N + M}.
+
+handle_info(timeout, 0) ->
+  {stop, done, 0};
+handle_info(timeout, N) ->
+  display({tick, N}),
+  {noreply, N - 1, 10}.
+
+terminate(done, _N) ->
+  display({terminate, done});
+terminate(_Reason, _N) ->
+  ok.
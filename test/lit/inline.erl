@@ -0,0 +1,36 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {forced, 7}
+%% CHECK: {nested, 10}
+%% CHECK: {tail, 10}
+%% CHECK: {caught, badarith}
+-module(init).
+
+-export([boot/1]).
+
+-compile({inline, [add/2, scale/2]}).
+
+-import(erlang, [display/1]).
+
+%% The functions named by -compile({inline, ...}) are inlined without -O, including scale/2,
+%% whose tail call to add/2 becomes an ordinary call once it is itself inlined into boot/1
+boot(_) ->
+  display({forced, add(3, 4)}),
+  display({nested, scale(2, 3)}),
+  display({tail, tail(2, 3)}),
+  try
+    add(1, foo)
+  catch
+    error:Reason ->
+      display({caught, Reason})
+  end.
+
+%% A tail call which is inlined copies the body as-is
+tail(X, Y) ->
+  scale(X, Y).
+
+add(X, Y) ->
+  X + Y.
+
+scale(X, Y) ->
+  add(X * Y, 4).
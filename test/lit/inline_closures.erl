@@ -0,0 +1,38 @@
+%% RUN: @firefly compile -O -o @tempfile @file && @tempfile
+
+%% CHECK: {direct, {5, [a, b]}}
+%% CHECK: {map, [11, 12, 13]}
+%% CHECK: {map, []}
+%% CHECK: {not_list, function_clause}
+%% CHECK: {improper, function_clause}
+-module(init).
+
+-export([boot/1]).
+
+-import(erlang, [display/1]).
+
+%% At -O, the body of a closure called directly uses the variables it was built from, and
+%% lists:map/2 with a closure runs a specialization which applies the body of the closure inline
+boot(Args) ->
+  display({direct, direct(3, [a, b])}),
+  display({map, add_all(10, [1, 2, 3])}),
+  display({map, add_all(10, [])}),
+  display({not_list, map_error(Args, foo)}),
+  display({improper, map_error(Args, [1 | 2])}).
+
+direct(N, L) ->
+  F = fun(X) -> {X + N, L} end,
+  F(2).
+
+add_all(N, L) ->
+  lists:map(fun(X) -> X + N end, L).
+
+%% Anything other than a proper list falls back to lists:map/2, so the error is the same as
+%% without the specialization
+map_error(Args, L) ->
+  try
+    lists:map(fun(X) -> {X, Args} end, L)
+  catch
+    error:Reason ->
+      Reason
+  end.
@@ -0,0 +1,28 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {referrer, true}
+%% CHECK: {caught, error, badarg}
+-module(init).
+
+-export([boot/1]).
+
+-import(erlang, [display/1]).
+
+%% The caller holds the constant list it asks about, so is among the processes which refer to it,
+%% while a term which is not a literal has no literal area
+boot(_) ->
+  Referrers = erts_internal:literal_area_referrers([a, b, c]),
+  display({referrer, member(self(), Referrers)}),
+  try
+    erts_internal:literal_area_referrers(self())
+  catch
+    Class:Reason ->
+      display({caught, Class, Reason})
+  end.
+
+member(_, []) ->
+  false;
+member(X, [X | _]) ->
+  true;
+member(X, [_ | Rest]) ->
+  member(X, Rest).
@@ -0,0 +1,19 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1
+
+%% CHECK: no functions are declared as nifs
+%% CHECK: only those declared in a -nifs attribute can be implemented natively
+-module(init).
+
+-export([boot/1]).
+
+-on_load(load/0).
+
+%% Without a -nifs attribute, the loaded library cannot replace native/1
+load() ->
+    erlang:load_nif("./native", 0).
+
+boot(_) ->
+    native(1).
+
+native(X) ->
+    X.
@@ -0,0 +1,23 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: hello
+%% CHECK: {spin, done}
+-module(init).
+
+-export([boot/1, spin/1, hello/0]).
+
+-import(erlang, [display/1]).
+
+%% The spinning process is scheduled first, but never waits for anything, so the second
+%% process only gets to run before it finishes if it is preempted
+boot(_) ->
+  erlang:spawn_opt(init, spin, [10000000], []),
+  erlang:spawn_opt(init, hello, [], []).
+
+spin(0) ->
+  display({spin, done});
+spin(N) ->
+  spin(N - 1).
+
+hello() ->
+  display(hello).
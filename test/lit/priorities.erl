@@ -0,0 +1,26 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {priority, high}
+%% CHECK: hello
+%% CHECK: {spin, done}
+-module(init).
+
+-export([boot/1, spin/1, hello/0]).
+
+-import(erlang, [display/1]).
+
+%% The low priority process is spawned first, but the max priority one runs exclusively as soon
+%% as it is runnable
+boot(_) ->
+  normal = erlang:process_flag(priority, high),
+  display(erlang:process_info(self(), priority)),
+  erlang:spawn_opt(init, spin, [1000000], [{priority, low}]),
+  erlang:spawn_opt(init, hello, [], [{priority, max}]).
+
+spin(0) ->
+  display({spin, done});
+spin(N) ->
+  spin(N - 1).
+
+hello() ->
+  display(hello).
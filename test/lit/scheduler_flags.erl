@@ -0,0 +1,16 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile +scl false +sub true -name foo
+
+%% CHECK: [{name, [<<"foo">>]}]
+%% CHECK: []
+-module(init).
+
+-export([boot/1]).
+
+-import(erlang, [display/1]).
+
+%% There is a single scheduler, so the flags controlling load compaction and migration between
+%% schedulers have no effect, but are accepted, and not passed on to init
+boot(_) ->
+  {ok, [Values]} = init:get_argument(name),
+  display([{name, [list_to_binary(Value) || Value <- Values]}]),
+  display(init:get_plain_arguments()).
@@ -0,0 +1,8 @@
+%% RUN: @firefly shell --records @file -e 'X = lists:reverse([1, 2, 3], []), {X, #point{x = hd(X)}}.'
+
+%% CHECK: {[3,2,1],#point{x = 3, y = 0}}
+-module(shell).
+
+%% The call is evaluated by the runtime, and its result bound in the shell, in which the rest is
+%% evaluated, printing the record using the definition loaded from this file
+-record(point, {x, y = 0}).
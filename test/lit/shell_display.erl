@@ -0,0 +1,8 @@
+%% RUN: @firefly shell -e 'erlang:display(before), lists:seq(1, 3).'
+
+%% CHECK: before
+%% CHECK: [1,2,3]
+-module(shell_display).
+
+%% What the expression evaluated by the runtime prints goes to stdout, like the result the shell
+%% reads back, and is passed through ahead of it
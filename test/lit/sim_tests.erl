@@ -0,0 +1,19 @@
+%% RUN: @firefly test --sim-seed 42 @file
+
+%% CHECK: All 2 tests passed.
+%% CHECK: Simulated with seed 42, run again with --sim-seed 42 to replay
+-module(sim_tests).
+
+-include_lib("eunit/include/eunit.hrl").
+
+%% OS system time is virtual, starting at midnight on the first of January 2020
+system_time_test() ->
+  Seconds = os:system_time(second),
+  ?assert(Seconds >= 1577836800),
+  ?assert(Seconds < 1577836800 + 60).
+
+%% Monotonic time never goes backwards, even though it only advances as reductions are consumed
+monotonic_time_test() ->
+  Before = erlang:monotonic_time(),
+  _ = lists:seq(1, 1000),
+  ?assert(erlang:monotonic_time() >= Before).
@@ -0,0 +1,24 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: [{min_heap_size, 1024}, {heap_size, 1024}]
+%% CHECK: [{min_heap_size, 1024}, {heap_size, 1024}]
+%% CHECK: [{min_heap_size, 512}, {heap_size, 256}]
+-module(init).
+
+-export([boot/1, hello/0]).
+
+-import(erlang, [display/1]).
+
+%% The heap is sized from min_heap_size, or the default of 512 words, and clamped to max_heap_size,
+%% while process_info/2 reports the min_heap_size configured
+boot(_) ->
+  info([{min_heap_size, 1024}]),
+  info([{min_heap_size, 1024}, {max_heap_size, 100000}]),
+  info([{max_heap_size, 256}]).
+
+info(Options) ->
+  Pid = erlang:spawn_opt(init, hello, [], Options),
+  display(erlang:process_info(Pid, [min_heap_size, heap_size])).
+
+hello() ->
+  ok.
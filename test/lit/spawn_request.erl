@@ -0,0 +1,24 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {request, true}
+%% CHECK: {caught, error, notsup}
+%% CHECK: hello
+-module(init).
+
+-export([boot/1, hello/0]).
+
+-import(erlang, [display/1]).
+
+%% Processes have no mailboxes, so only requests which are never replied to are supported
+boot(_) ->
+  ReqId = erlang:spawn_request(init, hello, [], [{reply, no}]),
+  display({request, is_reference(ReqId)}),
+  try
+    erlang:spawn_request(init, hello, [])
+  catch
+    Class:Reason ->
+      display({caught, Class, Reason})
+  end.
+
+hello() ->
+  display(hello).
@@ -0,0 +1,26 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {status, suspended}
+%% CHECK: {suspended, 1}
+%% CHECK: resumed
+%% CHECK: hello
+-module(init).
+
+-export([boot/1, hello/0]).
+
+-import(erlang, [display/1]).
+
+%% The spawned process is suspended before it gets to run, so only runs once resumed
+boot(_) ->
+  Pid = erlang:spawn_opt(init, hello, [], []),
+  true = erlang:suspend_process(Pid),
+  display(erlang:process_info(Pid, status)),
+  false = erlang:suspend_process(Pid, [unless_suspending]),
+  {suspending, [{Pid, Count, 0}]} = erlang:process_info(self(), suspending),
+  display({suspended, Count}),
+  {'EXIT', {badarg, _}} = (catch erlang:suspend_process(self())),
+  true = erlang:resume_process(Pid),
+  display(resumed).
+
+hello() ->
+  display(hello).
@@ -0,0 +1,38 @@
+%% RUN: @firefly compile -o @tempfile @file && @tempfile
+
+%% CHECK: {loop, 10000000}
+%% CHECK: {ping, 10000000}
+%% CHECK: {dynamic, 10000000}
+-module(init).
+
+-export([boot/1, dynamic/3]).
+
+-import(erlang, [display/1]).
+
+-define(ITERATIONS, 10000000).
+
+boot(_) ->
+  display({loop, loop(?ITERATIONS, 0, a, b, c, d, e, f)}),
+  display({ping, ping(?ITERATIONS, 0)}),
+  display({dynamic, dynamic(init, ?ITERATIONS, 0)}).
+
+%% More arguments than are passed in registers, so each call passes some on the stack
+loop(0, Acc, _, _, _, _, _, _) ->
+  Acc;
+loop(N, Acc, A, B, C, D, E, F) ->
+  loop(N - 1, Acc + 1, B, C, D, E, F, A).
+
+%% Mutual recursion between functions of different arities
+ping(0, Acc) ->
+  Acc;
+ping(N, Acc) ->
+  pong(N - 1, Acc + 1, x, y, z, w, v, u).
+
+pong(N, Acc, _, _, _, _, _, _) ->
+  ping(N, Acc).
+
+%% Calls through the dispatch table
+dynamic(_, 0, Acc) ->
+  Acc;
+dynamic(M, N, Acc) ->
+  M:dynamic(M, N - 1, Acc + 1).
@@ -74,6 +74,11 @@ pub struct Config {
     /// The location where the compiler toolchain should be installed
     #[clap(long, env("FIREFLY_INSTALL_DIR"), default_value = "./_build")]
     install_dir: PathBuf,
+    /// Whether to also build and install the runtime for the wasm32-wasi target
+    ///
+    /// This requires the wasm32-wasi target to be installed for the toolchain
+    #[clap(long, env("FIREFLY_BUILD_WASI"))]
+    wasi: bool,
 }
 impl Config {
     pub fn working_directory(&self) -> PathBuf {
@@ -162,6 +167,10 @@ impl Config {
     pub fn toolchain_target_dir(&self) -> PathBuf {
         self.sysroot().join("lib/rustlib").join(self.rust_target())
     }
+
+    pub fn wasi(&self) -> bool {
+        self.wasi
+    }
 }
 
 pub fn run(config: &Config) -> anyhow::Result<()> {
@@ -471,6 +480,16 @@ pub fn run(config: &Config) -> anyhow::Result<()> {
         }
     }
 
+    if config.wasi() {
+        install_wasi_runtime(
+            config,
+            &target_dir,
+            target_subdir,
+            cargo_args.as_slice(),
+            install_dir,
+        )?;
+    }
+
     if config.link_dynamic() {
         match env::var_os("LLVM_LINK_LLVM_DYLIB") {
             Some(val) if val == "ON" => {
@@ -523,6 +542,78 @@ pub fn run(config: &Config) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Builds the runtime for wasm32-wasi, and installs it along with the libraries it links against
+fn install_wasi_runtime(
+    config: &Config,
+    target_dir: &Path,
+    target_subdir: &str,
+    cargo_args: &[&String],
+    install_dir: &Path,
+) -> anyhow::Result<()> {
+    const WASI_TARGET: &str = "wasm32-wasi";
+
+    println!("Building runtime for {}..", WASI_TARGET);
+
+    let mut cargo_cmd = Command::new("rustup");
+    let cargo_cmd = cargo_cmd
+        .arg("run")
+        .arg(config.toolchain())
+        .args(&["cargo", "build"])
+        .args(&["-p", "firefly_rt_wasi"])
+        .arg("--target")
+        .arg(WASI_TARGET)
+        .args(cargo_args);
+    if !config.verbose() {
+        cargo_cmd.stdout(Stdio::null());
+    }
+    let status = cargo_cmd.status()?;
+    if !status.success() {
+        bail!(
+            "command did not execute successfully: {:?}\n\
+            expected success, got: {}",
+            cargo_cmd,
+            status
+        );
+    }
+
+    let install_wasi_lib_dir = install_dir.join(&format!("lib/fireflylib/{}/lib", WASI_TARGET));
+    let install_self_contained_dir = install_wasi_lib_dir.join("self-contained");
+    fs::create_dir_all(&install_self_contained_dir)?;
+
+    let runtime = target_dir
+        .join(WASI_TARGET)
+        .join(target_subdir)
+        .join("libfirefly_rt_wasi.a");
+    fs::copy(&runtime, install_wasi_lib_dir.join("libfirefly_rt_wasi.a"))?;
+
+    // The runtime is built with panic=abort, and links against wasi-libc, which the toolchain ships
+    // along with the startup objects in its self-contained directory
+    let toolchain_lib_dir = config
+        .sysroot()
+        .join("lib/rustlib")
+        .join(WASI_TARGET)
+        .join("lib");
+    let walker = WalkDir::new(&toolchain_lib_dir).max_depth(1).into_iter();
+    for entry in walker.filter_entry(|e| is_dir_or_matching_rlib(e, &["libpanic_abort"])) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            fs::copy(
+                entry.path(),
+                install_wasi_lib_dir.join("libpanic_abort.rlib"),
+            )?;
+        }
+    }
+    for entry in fs::read_dir(toolchain_lib_dir.join("self-contained"))? {
+        let entry = entry?;
+        fs::copy(
+            entry.path(),
+            install_self_contained_dir.join(entry.file_name()),
+        )?;
+    }
+
+    Ok(())
+}
+
 static LLVM_TARGET: OnceLock<String> = OnceLock::new();
 
 fn get_llvm_target(toolchain_name: &str, target: &str) -> &'static str {